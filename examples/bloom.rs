@@ -0,0 +1,206 @@
+//! Bloom on a row of spinning cubes, some of them far brighter than white. Only those glow; the dim ones stay crisp.
+//! The intensity of the bloom swells and fades over time, through the settings of the effect.
+//!
+//! Run with `cargo run --release --example bloom`.
+
+use r3d::{
+    gfx::{
+        Bloom, Camera, CameraClearMode, CameraPerspectiveProjectionAspect, CameraProjection, Color,
+        Fxaa, LitMaterial, Material, MaterialHandle, MeshRenderer, PerInstancePropertyValue,
+        Tonemap, TonemapOperator, BUILT_IN_SHADER_LIT,
+    },
+    math::{Quat, Vec3},
+    object::Object,
+    specs::{prelude::*, Component},
+    transform::Transform,
+    use_context, Engine, EngineConfig, EngineLoopMode, EngineTargetFps,
+};
+
+/// Base colors of the cubes. Without any light in the scene, the lit shader shows them as they are, so the ones
+/// above one are as bright as emissive surfaces.
+const CUBE_COLORS: [[f32; 3]; 7] = [
+    [0.3, 0.3, 0.35],
+    [8.0, 2.0, 0.4],
+    [0.2, 0.5, 0.3],
+    [0.6, 4.0, 9.0],
+    [0.5, 0.2, 0.2],
+    [6.0, 6.0, 6.0],
+    [0.3, 0.3, 0.6],
+];
+
+#[derive(Component)]
+#[storage(VecStorage)]
+struct Spin {
+    speed: f32,
+}
+
+struct SpinSystem {
+    time: f32,
+}
+
+impl<'a> System<'a> for SpinSystem {
+    type SystemData = (
+        ReadStorage<'a, Object>,
+        ReadStorage<'a, Spin>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, (objects, spins, mut transforms): Self::SystemData) {
+        let ctx = use_context();
+        let delta_time = ctx.time_mgr().delta_time();
+        self.time += delta_time;
+
+        let mut object_mgr = ctx.object_mgr_mut();
+        let object_hierarchy = object_mgr.object_hierarchy_mut();
+
+        for (object, spin, transform) in (&objects, &spins, &mut transforms).join() {
+            transform.rotation = Quat::from_axis_angle(
+                Vec3::new(0.3, 1.0, 0.2).normalized(),
+                self.time * spin.speed,
+            );
+            object_hierarchy.set_dirty(object.object_id());
+        }
+
+        if let Some(bloom) = ctx
+            .render_mgr_mut()
+            .post_effects_mut()
+            .effect_mut::<Bloom>()
+        {
+            bloom.settings.intensity = 0.6 + (self.time * 0.8).sin() * 0.5;
+        }
+    }
+}
+
+/// Triangles of a unit cube as `[position, normal, uv]`, facing outwards.
+fn cube_vertices() -> Vec<[f32; 8]> {
+    // Each face as its normal and two axes whose cross product is the normal.
+    let faces = [
+        ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+    ];
+    let corners = [
+        (-1.0, -1.0),
+        (1.0, -1.0),
+        (1.0, 1.0),
+        (-1.0, -1.0),
+        (1.0, 1.0),
+        (-1.0, 1.0),
+    ];
+
+    Vec::from_iter(faces.iter().flat_map(|&(normal, u, v)| {
+        corners.iter().map(move |&(s, t)| {
+            let position = |axis: usize| (normal[axis] + u[axis] * s + v[axis] * t) * 0.5;
+            [
+                position(0),
+                position(1),
+                position(2),
+                normal[0],
+                normal[1],
+                normal[2],
+                (s + 1.0) * 0.5,
+                (t + 1.0) * 0.5,
+            ]
+        })
+    }))
+}
+
+fn main() {
+    let engine = pollster::block_on(Engine::new(EngineConfig {
+        title: "bloom".to_owned(),
+        ..Default::default()
+    }))
+    .unwrap();
+    let ctx = use_context();
+    ctx.world_mut().register::<Spin>();
+
+    let mut material = Material::new(
+        ctx.built_in_shader_mgr()
+            .find_shader(BUILT_IN_SHADER_LIT)
+            .unwrap(),
+        ctx.render_mgr_mut().pipeline_layout_cache(),
+    );
+    LitMaterial::default().bind(&mut material);
+    let material = MaterialHandle::new(material);
+    let vertices = cube_vertices();
+
+    {
+        let mut object_mgr = ctx.object_mgr_mut();
+        let mut world = ctx.world_mut();
+        let mut render_mgr = ctx.render_mgr_mut();
+        let device = &ctx.gfx_ctx().device;
+
+        // Bloom works on the HDR scene, then the tonemapping brings it into range for the anti-aliasing.
+        let post_effects = render_mgr.post_effects_mut();
+        post_effects.add(Bloom::ORDER, Box::new(Bloom::new(device)));
+        post_effects.add(
+            Tonemap::ORDER,
+            Box::new(Tonemap::new(device, TonemapOperator::Aces)),
+        );
+        post_effects.add(Fxaa::ORDER, Box::new(Fxaa::new(device)));
+
+        let camera = Camera::new(
+            u32::MAX,
+            0,
+            CameraClearMode::All {
+                color: Color::from_rgb(0.01, 0.01, 0.02),
+                depth: 1.0,
+                stencil: 0,
+            },
+            CameraProjection::perspective(
+                60f32.to_radians(),
+                CameraPerspectiveProjectionAspect::Screen,
+                0.1,
+                100.0,
+            ),
+            device,
+            render_mgr.bind_group_layout_cache(),
+        );
+        let mut camera_transform = Transform::new();
+        camera_transform.position = Vec3::new(0.0, 0.0, 8.0);
+        let (_, builder) = object_mgr.create_object_builder(
+            &mut world,
+            Some("camera".to_owned()),
+            Some(camera_transform),
+        );
+        builder.with(camera).build();
+
+        for (index, color) in CUBE_COLORS.iter().enumerate() {
+            let mut mesh_renderer = MeshRenderer::new();
+            mesh_renderer.set_material(material.clone());
+            mesh_renderer.set_dynamic_vertices(&vertices, device, render_mgr.uploader_mut());
+            mesh_renderer.set_instance_property(
+                "base_color",
+                PerInstancePropertyValue::Float32x4([color[0], color[1], color[2], 1.0]),
+            );
+
+            let mut transform = Transform::new();
+            transform.position = Vec3::new(
+                (index as f32 - (CUBE_COLORS.len() - 1) as f32 * 0.5) * 1.6,
+                0.0,
+                0.0,
+            );
+            let (_, builder) = object_mgr.create_object_builder(
+                &mut world,
+                Some(format!("cube #{}", index)),
+                Some(transform),
+            );
+            builder
+                .with(mesh_renderer)
+                .with(Spin {
+                    speed: 0.4 + index as f32 * 0.15,
+                })
+                .build();
+        }
+    }
+
+    ctx.system_registry_mut()
+        .register(0, SpinSystem { time: 0.0 });
+
+    engine
+        .run(EngineLoopMode::Poll, EngineTargetFps::Unlimited)
+        .unwrap();
+}
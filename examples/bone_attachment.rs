@@ -0,0 +1,295 @@
+//! A sword held in the hand of a waving arm. The arm is a two-bone skeleton moved by a system; the sword is attached
+//! to the hand bone of the skin of the body, so it follows the wave without being a child of the arm.
+//!
+//! Run with `cargo run --release --example bone_attachment`.
+
+use r3d::{
+    animation::{Bone, BonePose, Skeleton},
+    gfx::{
+        Camera, CameraClearMode, CameraPerspectiveProjectionAspect, CameraProjection, Color,
+        LitMaterial, Material, MaterialHandle, MeshRenderer, MeshSkin, PerInstancePropertyValue,
+        BUILT_IN_SHADER_LIT,
+    },
+    math::{Mat4, Quat, Vec3},
+    object::{Object, ObjectHandle},
+    specs::{prelude::*, Component},
+    transform::Transform,
+    use_context, Engine, EngineConfig, EngineLoopMode, EngineTargetFps,
+};
+
+#[derive(Component)]
+#[storage(HashMapStorage)]
+struct Wave;
+
+struct WaveSystem {
+    time: f32,
+}
+
+impl<'a> System<'a> for WaveSystem {
+    type SystemData = (
+        ReadStorage<'a, Object>,
+        ReadStorage<'a, Wave>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, (objects, waves, mut transforms): Self::SystemData) {
+        let ctx = use_context();
+        self.time += ctx.time_mgr().delta_time();
+
+        let mut object_mgr = ctx.object_mgr_mut();
+        let object_hierarchy = object_mgr.object_hierarchy_mut();
+
+        for (object, _, transform) in (&objects, &waves, &mut transforms).join() {
+            transform.rotation = Quat::from_axis_angle(
+                Vec3::new(0.0, 0.0, 1.0),
+                -0.4 - (self.time * 3.0).sin() * 0.6,
+            );
+            object_hierarchy.set_dirty(object.object_id());
+        }
+    }
+}
+
+/// Triangles of a unit cube as `[position, normal, uv]`, facing outwards.
+fn cube_vertices() -> Vec<[f32; 8]> {
+    // Each face as its normal and two axes whose cross product is the normal.
+    let faces = [
+        ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+    ];
+    let corners = [
+        (-1.0, -1.0),
+        (1.0, -1.0),
+        (1.0, 1.0),
+        (-1.0, -1.0),
+        (1.0, 1.0),
+        (-1.0, 1.0),
+    ];
+
+    Vec::from_iter(faces.iter().flat_map(|&(normal, u, v)| {
+        corners.iter().map(move |&(s, t)| {
+            let position = |axis: usize| (normal[axis] + u[axis] * s + v[axis] * t) * 0.5;
+            [
+                position(0),
+                position(1),
+                position(2),
+                normal[0],
+                normal[1],
+                normal[2],
+                (s + 1.0) * 0.5,
+                (t + 1.0) * 0.5,
+            ]
+        })
+    }))
+}
+
+/// Creates an object under the parent, drawn as a box of the size and color if it has a size.
+fn create_box(
+    name: &str,
+    position: Vec3,
+    size: Option<Vec3>,
+    color: [f32; 3],
+    parent: Option<&ObjectHandle>,
+    material: &MaterialHandle,
+) -> ObjectHandle {
+    let ctx = use_context();
+    let mut object_mgr = ctx.object_mgr_mut();
+    let mut world = ctx.world_mut();
+    let mut render_mgr = ctx.render_mgr_mut();
+    let device = &ctx.gfx_ctx().device;
+
+    let mut transform = Transform::new();
+    transform.position = position;
+    let (handle, builder) =
+        object_mgr.create_object_builder(&mut world, Some(name.to_owned()), Some(transform));
+    builder.build();
+    object_mgr
+        .object_hierarchy_mut()
+        .set_parent(handle.object_id, parent.map(|parent| parent.object_id))
+        .unwrap();
+
+    if let Some(size) = size {
+        // The box is a child, so that its scale does not stretch the children of the object.
+        let mut mesh_renderer = MeshRenderer::new();
+        mesh_renderer.set_material(material.clone());
+        mesh_renderer.set_dynamic_vertices(&cube_vertices(), device, render_mgr.uploader_mut());
+        mesh_renderer.set_instance_property(
+            "base_color",
+            PerInstancePropertyValue::Float32x4([color[0], color[1], color[2], 1.0]),
+        );
+
+        let mut transform = Transform::new();
+        transform.position = Vec3::new(0.0, size.y * 0.5, 0.0);
+        transform.scale = size;
+        let (shape, builder) = object_mgr.create_object_builder(
+            &mut world,
+            Some(format!("{} shape", name)),
+            Some(transform),
+        );
+        builder.with(mesh_renderer).build();
+        object_mgr
+            .object_hierarchy_mut()
+            .set_parent(shape.object_id, Some(handle.object_id))
+            .unwrap();
+    }
+
+    handle
+}
+
+fn main() {
+    let engine = pollster::block_on(Engine::new(EngineConfig {
+        title: "bone attachment".to_owned(),
+        ..Default::default()
+    }))
+    .unwrap();
+    let ctx = use_context();
+    ctx.world_mut().register::<Wave>();
+
+    let mut material = Material::new(
+        ctx.built_in_shader_mgr()
+            .find_shader(BUILT_IN_SHADER_LIT)
+            .unwrap(),
+        ctx.render_mgr_mut().pipeline_layout_cache(),
+    );
+    LitMaterial::default().bind(&mut material);
+    let material = MaterialHandle::new(material);
+    // Rest positions of the bones, each relative to its parent.
+    let shoulder_position = Vec3::new(0.6, 1.2, 0.0);
+    let hand_position = Vec3::new(0.0, 1.0, 0.0);
+
+    {
+        let mut object_mgr = ctx.object_mgr_mut();
+        let mut world = ctx.world_mut();
+        let mut render_mgr = ctx.render_mgr_mut();
+        let device = &ctx.gfx_ctx().device;
+
+        let camera = Camera::new(
+            u32::MAX,
+            0,
+            CameraClearMode::All {
+                color: Color::from_rgb(0.1, 0.1, 0.12),
+                depth: 1.0,
+                stencil: 0,
+            },
+            CameraProjection::perspective(
+                60f32.to_radians(),
+                CameraPerspectiveProjectionAspect::Screen,
+                0.1,
+                100.0,
+            ),
+            device,
+            render_mgr.bind_group_layout_cache(),
+        );
+        let mut camera_transform = Transform::new();
+        camera_transform.position = Vec3::new(0.0, 1.5, 6.0);
+        let (_, builder) = object_mgr.create_object_builder(
+            &mut world,
+            Some("camera".to_owned()),
+            Some(camera_transform),
+        );
+        builder.with(camera).build();
+    }
+
+    let character = create_box(
+        "character",
+        Vec3::new(0.0, -1.0, 0.0),
+        None,
+        [0.0; 3],
+        None,
+        &material,
+    );
+    let body = create_box(
+        "body",
+        Vec3::new(0.0, 0.0, 0.0),
+        Some(Vec3::new(1.0, 1.6, 0.5)),
+        [0.3, 0.4, 0.7],
+        Some(&character),
+        &material,
+    );
+    let shoulder = create_box(
+        "shoulder",
+        shoulder_position,
+        Some(Vec3::new(0.25, 1.0, 0.25)),
+        [0.8, 0.6, 0.5],
+        Some(&character),
+        &material,
+    );
+    let hand = create_box(
+        "hand",
+        hand_position,
+        Some(Vec3::new(0.3, 0.3, 0.3)),
+        [0.8, 0.6, 0.5],
+        Some(&shoulder),
+        &material,
+    );
+    ctx.world()
+        .write_storage::<Wave>()
+        .insert(shoulder.entity, Wave)
+        .unwrap();
+
+    // The body is skinned to the arm, as a model spawned with `spawn_model` would be; the skin names the bones.
+    {
+        let world = ctx.world();
+        let mut mesh_renderers = world.write_storage::<MeshRenderer>();
+        let mut render_mgr = ctx.render_mgr_mut();
+        let skeleton = Skeleton::new(vec![
+            Bone {
+                name: "shoulder".to_owned(),
+                parent: None,
+                rest: BonePose::new(shoulder_position, Quat::IDENTITY),
+            },
+            Bone {
+                name: "hand".to_owned(),
+                parent: Some(0),
+                rest: BonePose::new(hand_position, Quat::IDENTITY),
+            },
+        ])
+        .unwrap();
+        let skin = MeshSkin::new(
+            [
+                (
+                    Some(shoulder.object_id),
+                    Mat4::translation(shoulder_position).inversed(),
+                ),
+                (
+                    Some(hand.object_id),
+                    Mat4::translation(shoulder_position + hand_position).inversed(),
+                ),
+            ],
+            &ctx.gfx_ctx().device,
+            render_mgr.bind_group_layout_cache(),
+        )
+        .with_skeleton(skeleton);
+
+        let mut mesh_renderer = MeshRenderer::new();
+        mesh_renderer.set_skin(Some(skin));
+        mesh_renderers.insert(body.entity, mesh_renderer).unwrap();
+    }
+
+    // The sword is held along the forearm, a little past the hand.
+    let sword = create_box(
+        "sword",
+        Vec3::new(0.0, 0.15, 0.0),
+        Some(Vec3::new(0.08, 1.4, 0.08)),
+        [0.9, 0.9, 1.0],
+        None,
+        &material,
+    );
+    let hand_bone = ctx
+        .world()
+        .read_storage::<MeshRenderer>()
+        .get(body.entity)
+        .and_then(|mesh_renderer| mesh_renderer.bone_handle("hand"))
+        .unwrap();
+    sword.attach_to_bone(&body, hand_bone, false).unwrap();
+
+    ctx.system_registry_mut()
+        .register(0, WaveSystem { time: 0.0 });
+
+    engine
+        .run(EngineLoopMode::Poll, EngineTargetFps::Unlimited)
+        .unwrap();
+}
@@ -0,0 +1,259 @@
+//! Stress test of the light culling: 500 lit cubes under 200 point lights orbiting above them. Every second, prints
+//! the frame time and the time the renderer spent picking the lights of each cube.
+//!
+//! Run with `cargo run --release --example point_light_stress`.
+
+use r3d::{
+    gfx::{
+        Camera, CameraClearMode, CameraPerspectiveProjectionAspect, CameraProjection, Color,
+        LitMaterial, Material, MaterialHandle, MeshRenderer, PerInstancePropertyValue, PointLight,
+        BUILT_IN_SHADER_LIT,
+    },
+    math::{Mat4, Vec3},
+    object::Object,
+    specs::{prelude::*, Component},
+    transform::Transform,
+    use_context, Engine, EngineConfig, EngineLoopMode, EngineTargetFps,
+};
+use std::f32::consts::TAU;
+
+const GRID_WIDTH: usize = 25;
+const GRID_DEPTH: usize = 20;
+const CUBE_SPACING: f32 = 2.0;
+const LIGHT_COUNT: usize = 200;
+
+/// Circle a light moves along, around its center.
+#[derive(Component)]
+#[storage(VecStorage)]
+struct Orbit {
+    center: Vec3,
+    radius: f32,
+    speed: f32,
+    phase: f32,
+}
+
+struct MoveLightsSystem {
+    time: f32,
+    frames: u32,
+    frame_ms: f32,
+    light_culling_ms: f32,
+}
+
+impl<'a> System<'a> for MoveLightsSystem {
+    type SystemData = (
+        ReadStorage<'a, Object>,
+        ReadStorage<'a, Orbit>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, (objects, orbits, mut transforms): Self::SystemData) {
+        let ctx = use_context();
+        let delta_time = ctx.time_mgr().delta_time();
+        self.time += delta_time;
+
+        let mut object_mgr = ctx.object_mgr_mut();
+        let object_hierarchy = object_mgr.object_hierarchy_mut();
+
+        for (object, orbit, transform) in (&objects, &orbits, &mut transforms).join() {
+            let angle = orbit.phase + self.time * orbit.speed;
+            transform.position = Vec3::new(
+                orbit.center.x + angle.cos() * orbit.radius,
+                orbit.center.y,
+                orbit.center.z + angle.sin() * orbit.radius,
+            );
+            object_hierarchy.set_dirty(object.object_id());
+        }
+
+        // The report is of the previous frame.
+        self.frames += 1;
+        self.frame_ms += delta_time * 1000.0;
+        self.light_culling_ms += ctx.render_mgr().frame_report().light_culling_ms;
+
+        if 1000.0 <= self.frame_ms {
+            println!(
+                "{:.2} ms per frame, {:.3} ms of them picking the lights",
+                self.frame_ms / self.frames as f32,
+                self.light_culling_ms / self.frames as f32
+            );
+            self.frames = 0;
+            self.frame_ms = 0.0;
+            self.light_culling_ms = 0.0;
+        }
+    }
+}
+
+/// Deterministic value in `[0, 1)`, so that every run lays the scene out the same.
+fn hash(seed: usize) -> f32 {
+    let mut x = seed as u32 ^ 0x9E37_79B9;
+    x = (x ^ (x >> 16)).wrapping_mul(0x7FEB_352D);
+    x = (x ^ (x >> 15)).wrapping_mul(0x846C_A68B);
+    x ^= x >> 16;
+    (x >> 8) as f32 / (1 << 24) as f32
+}
+
+/// Triangles of a unit cube as `[position, normal, uv]`, facing outwards.
+fn cube_vertices() -> Vec<[f32; 8]> {
+    // Each face as its normal and two axes whose cross product is the normal.
+    let faces = [
+        ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+    ];
+    let corners = [
+        (-1.0, -1.0),
+        (1.0, -1.0),
+        (1.0, 1.0),
+        (-1.0, -1.0),
+        (1.0, 1.0),
+        (-1.0, 1.0),
+    ];
+
+    Vec::from_iter(faces.iter().flat_map(|&(normal, u, v)| {
+        corners.iter().map(move |&(s, t)| {
+            let position = |axis: usize| (normal[axis] + u[axis] * s + v[axis] * t) * 0.5;
+            [
+                position(0),
+                position(1),
+                position(2),
+                normal[0],
+                normal[1],
+                normal[2],
+                (s + 1.0) * 0.5,
+                (t + 1.0) * 0.5,
+            ]
+        })
+    }))
+}
+
+fn main() {
+    let engine = pollster::block_on(Engine::new(EngineConfig {
+        title: "point light stress".to_owned(),
+        ..Default::default()
+    }))
+    .unwrap();
+    let ctx = use_context();
+    ctx.world_mut().register::<Orbit>();
+
+    let mut material = Material::new(
+        ctx.built_in_shader_mgr()
+            .find_shader(BUILT_IN_SHADER_LIT)
+            .unwrap(),
+        ctx.render_mgr_mut().pipeline_layout_cache(),
+    );
+    LitMaterial::default().bind(&mut material);
+    let material = MaterialHandle::new(material);
+
+    let vertices = cube_vertices();
+    let extent = Vec3::new(
+        GRID_WIDTH as f32 * CUBE_SPACING,
+        0.0,
+        GRID_DEPTH as f32 * CUBE_SPACING,
+    );
+
+    {
+        let mut object_mgr = ctx.object_mgr_mut();
+        let mut world = ctx.world_mut();
+        let mut render_mgr = ctx.render_mgr_mut();
+        render_mgr
+            .lights_mut()
+            .set_ambient(Color::from_rgb(0.02, 0.02, 0.02));
+
+        let camera = Camera::new(
+            u32::MAX,
+            0,
+            CameraClearMode::All {
+                color: Color::black(),
+                depth: 1.0,
+                stencil: 0,
+            },
+            CameraProjection::perspective(
+                60f32.to_radians(),
+                CameraPerspectiveProjectionAspect::Screen,
+                0.1,
+                200.0,
+            ),
+            &ctx.gfx_ctx().device,
+            render_mgr.bind_group_layout_cache(),
+        );
+        let (_, builder) = object_mgr.create_object_builder(
+            &mut world,
+            Some("camera".to_owned()),
+            Some(Transform::from_mat4(&Mat4::look_at(
+                Vec3::new(extent.x * 0.5, 30.0, extent.z + 20.0),
+                Vec3::new(extent.x * 0.5, 0.0, extent.z * 0.5),
+                Vec3::new(0.0, 1.0, 0.0),
+            ))),
+        );
+        builder.with(camera).build();
+
+        for index in 0..GRID_WIDTH * GRID_DEPTH {
+            let mut mesh_renderer = MeshRenderer::new();
+            mesh_renderer.set_material(material.clone());
+            mesh_renderer.set_dynamic_vertices(
+                &vertices,
+                &ctx.gfx_ctx().device,
+                render_mgr.uploader_mut(),
+            );
+            mesh_renderer.set_instance_property(
+                "base_color",
+                PerInstancePropertyValue::Float32x4([
+                    0.5 + hash(index * 3) * 0.5,
+                    0.5 + hash(index * 3 + 1) * 0.5,
+                    0.5 + hash(index * 3 + 2) * 0.5,
+                    1.0,
+                ]),
+            );
+
+            let mut transform = Transform::new();
+            transform.position = Vec3::new(
+                (index % GRID_WIDTH) as f32 * CUBE_SPACING + CUBE_SPACING * 0.5,
+                0.5,
+                (index / GRID_WIDTH) as f32 * CUBE_SPACING + CUBE_SPACING * 0.5,
+            );
+            let (_, builder) = object_mgr.create_object_builder(
+                &mut world,
+                Some(format!("cube #{}", index)),
+                Some(transform),
+            );
+            builder.with(mesh_renderer).build();
+        }
+
+        for index in 0..LIGHT_COUNT {
+            let seed = 1000 + index * 7;
+            let light = PointLight::new(
+                Color::from_rgb(hash(seed), hash(seed + 1), hash(seed + 2)),
+                4.0,
+                4.0 + hash(seed + 3) * 4.0,
+            );
+            let orbit = Orbit {
+                center: Vec3::new(hash(seed + 4) * extent.x, 1.5, hash(seed + 5) * extent.z),
+                radius: 1.0 + hash(seed + 6) * 4.0,
+                speed: 0.5 + hash(seed + 7),
+                phase: hash(seed + 8) * TAU,
+            };
+            let (_, builder) = object_mgr.create_object_builder(
+                &mut world,
+                Some(format!("light #{}", index)),
+                None,
+            );
+            builder.with(light).with(orbit).build();
+        }
+    }
+
+    ctx.system_registry_mut().register(
+        0,
+        MoveLightsSystem {
+            time: 0.0,
+            frames: 0,
+            frame_ms: 0.0,
+            light_culling_ms: 0.0,
+        },
+    );
+
+    engine
+        .run(EngineLoopMode::Poll, EngineTargetFps::Unlimited)
+        .unwrap();
+}
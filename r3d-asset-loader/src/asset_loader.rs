@@ -1,5 +1,7 @@
-use crate::AssetDatabase;
-use asset::{AssetKey, TypedAsset};
+use crate::{AssetDatabase, AssetPackError};
+use asset::{AssetKey, AssetSource, GfxBridge, TypedAsset};
+use asset_pipeline::TypedAssetSource;
+use std::collections::HashMap;
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -15,6 +17,10 @@ pub enum AssetLoadError {
     LoadError(#[from] asset::AssetLoadError),
     #[error("io error: {0}")]
     IOError(#[from] std::io::Error),
+    #[error("failed to read asset pack: {0}")]
+    PackError(#[from] AssetPackError),
+    #[error("packed assets can only be loaded by id: {0}")]
+    PathKeyUnsupported(AssetKey),
 }
 
 pub trait AssetLoader {
@@ -24,3 +30,47 @@ pub trait AssetLoader {
         database: &AssetDatabase,
     ) -> Result<TypedAsset, AssetLoadError>;
 }
+
+/// Loads the dependencies of a processed asset through the loader, then the asset itself.
+pub(crate) fn load_processed_asset(
+    loader: &dyn AssetLoader,
+    key: &AssetKey,
+    processed: TypedAssetSource,
+    database: &AssetDatabase,
+    gfx_bridge: &dyn GfxBridge,
+) -> Result<TypedAsset, AssetLoadError> {
+    // Resolve dependencies. NOTE: It can be recursive.
+    let deps = match &processed {
+        TypedAssetSource::Font(source) => source.dependencies(),
+        TypedAssetSource::Material(source) => source.dependencies(),
+        TypedAssetSource::Model(source) => source.dependencies(),
+        TypedAssetSource::Shader(source) => source.dependencies(),
+        TypedAssetSource::Texture(source) => source.dependencies(),
+    };
+    let deps = deps
+        .into_iter()
+        .map(|key| {
+            let asset = loader.load_asset(&key, database)?;
+
+            Ok((key, asset))
+        })
+        .collect::<Result<HashMap<_, _>, AssetLoadError>>()?;
+
+    Ok(match processed {
+        TypedAssetSource::Font(source) => {
+            TypedAsset::Font(source.load(key.clone(), &deps, gfx_bridge)?)
+        }
+        TypedAssetSource::Material(source) => {
+            TypedAsset::Material(source.load(key.clone(), &deps, gfx_bridge)?)
+        }
+        TypedAssetSource::Model(source) => {
+            TypedAsset::Model(source.load(key.clone(), &deps, gfx_bridge)?)
+        }
+        TypedAssetSource::Shader(source) => {
+            TypedAsset::Shader(source.load(key.clone(), &deps, gfx_bridge)?)
+        }
+        TypedAssetSource::Texture(source) => {
+            TypedAsset::Texture(source.load(key.clone(), &deps, gfx_bridge)?)
+        }
+    })
+}
@@ -1,3 +1,5 @@
+mod packed_asset_loader;
 mod runtime_asset_loader;
 
+pub use packed_asset_loader::*;
 pub use runtime_asset_loader::*;
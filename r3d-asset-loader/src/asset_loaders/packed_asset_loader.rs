@@ -0,0 +1,49 @@
+use crate::{
+    load_processed_asset, unpack_source, AssetDatabase, AssetLoadError, AssetLoader,
+    LayeredAssetPack,
+};
+use asset::{AssetKey, GfxBridge, TypedAsset};
+
+/// Loads processed assets from a base pack and its patches, instead of processing the source files.
+/// Assets are looked up by id only; the database is not used.
+pub struct PackedAssetLoader {
+    packs: LayeredAssetPack,
+    gfx_bridge: Box<dyn GfxBridge>,
+}
+
+impl PackedAssetLoader {
+    pub fn new(packs: LayeredAssetPack, gfx_bridge: impl GfxBridge + 'static) -> Self {
+        Self {
+            packs,
+            gfx_bridge: Box::new(gfx_bridge),
+        }
+    }
+
+    pub fn packs(&self) -> &LayeredAssetPack {
+        &self.packs
+    }
+
+    pub fn packs_mut(&mut self) -> &mut LayeredAssetPack {
+        &mut self.packs
+    }
+}
+
+impl AssetLoader for PackedAssetLoader {
+    fn load_asset(
+        &self,
+        key: &AssetKey,
+        database: &AssetDatabase,
+    ) -> Result<TypedAsset, AssetLoadError> {
+        let id = match key {
+            AssetKey::Id(id) => *id,
+            AssetKey::Path(_) => return Err(AssetLoadError::PathKeyUnsupported(key.clone())),
+        };
+        let bytes = self
+            .packs
+            .read(id)?
+            .ok_or_else(|| AssetLoadError::AssetNotFound(id))?;
+        let processed = unpack_source(&bytes)?;
+
+        load_processed_asset(self, key, processed, database, &*self.gfx_bridge)
+    }
+}
@@ -1,9 +1,6 @@
-use crate::{AssetDatabase, AssetLoadError, AssetLoader};
-use asset::{AssetKey, AssetSource, GfxBridge, TypedAsset};
-use asset_pipeline::{
-    deduce_asset_type_from_path, process_asset, PipelineGfxBridge, TypedAssetSource,
-};
-use std::collections::HashMap;
+use crate::{load_processed_asset, AssetDatabase, AssetLoadError, AssetLoader};
+use asset::{AssetKey, GfxBridge, TypedAsset};
+use asset_pipeline::{deduce_asset_type_from_path, process_asset, PipelineGfxBridge};
 
 pub struct RuntimeAssetLoader {
     gfx_bridge: Box<dyn GfxBridge>,
@@ -56,39 +53,6 @@ impl AssetLoader for RuntimeAssetLoader {
             }
         };
 
-        // Resolve dependencies. NOTE: It can be recursive.
-        let deps = match &processed {
-            TypedAssetSource::Font(source) => source.dependencies(),
-            TypedAssetSource::Material(source) => source.dependencies(),
-            TypedAssetSource::Model(source) => source.dependencies(),
-            TypedAssetSource::Shader(source) => source.dependencies(),
-            TypedAssetSource::Texture(source) => source.dependencies(),
-        };
-        let deps = deps
-            .into_iter()
-            .map(|key| {
-                let asset = self.load_asset(&key, database)?;
-
-                Ok((key, asset))
-            })
-            .collect::<Result<HashMap<_, _>, AssetLoadError>>()?;
-
-        Ok(match processed {
-            TypedAssetSource::Font(source) => {
-                TypedAsset::Font(source.load(key.clone(), &deps, &*self.gfx_bridge)?)
-            }
-            TypedAssetSource::Material(source) => {
-                TypedAsset::Material(source.load(key.clone(), &deps, &*self.gfx_bridge)?)
-            }
-            TypedAssetSource::Model(source) => {
-                TypedAsset::Model(source.load(key.clone(), &deps, &*self.gfx_bridge)?)
-            }
-            TypedAssetSource::Shader(source) => {
-                TypedAsset::Shader(source.load(key.clone(), &deps, &*self.gfx_bridge)?)
-            }
-            TypedAssetSource::Texture(source) => {
-                TypedAsset::Texture(source.load(key.clone(), &deps, &*self.gfx_bridge)?)
-            }
-        })
+        load_processed_asset(self, key, processed, database, &*self.gfx_bridge)
     }
 }
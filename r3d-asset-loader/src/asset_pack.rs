@@ -0,0 +1,582 @@
+use asset::{
+    assets::{FontSource, MaterialSource, ModelSource, ShaderSource, TextureSource},
+    migrations, AssetMigrationError, AssetType, VersionedAsset,
+};
+use asset_pipeline::TypedAssetSource;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::Mutex,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum AssetPackError {
+    #[error("io error: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("malformed asset pack: {0}")]
+    MalformedPack(String),
+    #[error("unsupported asset pack format version: {0}")]
+    UnsupportedVersion(u32),
+    #[error("asset {id} does not match its hash: expected {expected}, found {actual}")]
+    HashMismatch {
+        id: Uuid,
+        expected: ContentHash,
+        actual: ContentHash,
+    },
+    #[error("unknown asset type: {0}")]
+    UnknownAssetType(String),
+    #[error("failed to decode asset: {0}")]
+    MigrationError(#[from] AssetMigrationError),
+}
+
+/// Hash of the processed bytes of an asset. Entries with the same hash have the same content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContentHash(pub [u8; 32]);
+
+impl ContentHash {
+    pub fn of(bytes: &[u8]) -> Self {
+        Self(*blake3::hash(bytes).as_bytes())
+    }
+}
+
+impl Display for ContentHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Location and hash of an asset in a pack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetPackEntry {
+    pub hash: ContentHash,
+    /// Offset of the bytes from the start of the pack.
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Index of an asset pack: the entries it contains, and for patches, the assets it removes from the layers below.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AssetPackIndex {
+    entries: BTreeMap<Uuid, AssetPackEntry>,
+    removed: BTreeSet<Uuid>,
+}
+
+impl AssetPackIndex {
+    const ENTRY_SIZE: usize = 16 + 32 + 8 + 8;
+
+    pub fn entry(&self, id: Uuid) -> Option<&AssetPackEntry> {
+        self.entries.get(&id)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (Uuid, &AssetPackEntry)> {
+        self.entries.iter().map(|(id, entry)| (*id, entry))
+    }
+
+    pub fn is_removed(&self, id: Uuid) -> bool {
+        self.removed.contains(&id)
+    }
+
+    pub fn removed(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.removed.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.removed.is_empty()
+    }
+
+    /// Parses the index: entry count (u32 LE) and entries (id, hash, offset and length), then removal count and ids.
+    fn parse(bytes: &[u8]) -> Result<Self, AssetPackError> {
+        let malformed = || AssetPackError::MalformedPack("truncated index".to_owned());
+        let mut cursor = 0;
+        let mut take = |len: usize| {
+            let slice = bytes.get(cursor..cursor + len).ok_or_else(malformed)?;
+            cursor += len;
+            Ok::<_, AssetPackError>(slice)
+        };
+        let mut index = Self::default();
+
+        let entry_count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+
+        for _ in 0..entry_count {
+            let entry = take(Self::ENTRY_SIZE)?;
+            let id = Uuid::from_bytes(entry[..16].try_into().unwrap());
+            let hash = ContentHash(entry[16..48].try_into().unwrap());
+            let offset = u64::from_le_bytes(entry[48..56].try_into().unwrap());
+            let len = u64::from_le_bytes(entry[56..64].try_into().unwrap());
+            index
+                .entries
+                .insert(id, AssetPackEntry { hash, offset, len });
+        }
+
+        let removed_count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+
+        for _ in 0..removed_count {
+            let id = Uuid::from_bytes(take(16)?.try_into().unwrap());
+            index.removed.insert(id);
+        }
+
+        Ok(index)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            4 + self.entries.len() * Self::ENTRY_SIZE + 4 + self.removed.len() * 16,
+        );
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+
+        for (id, entry) in &self.entries {
+            bytes.extend_from_slice(id.as_bytes());
+            bytes.extend_from_slice(&entry.hash.0);
+            bytes.extend_from_slice(&entry.offset.to_le_bytes());
+            bytes.extend_from_slice(&entry.len.to_le_bytes());
+        }
+
+        bytes.extend_from_slice(&(self.removed.len() as u32).to_le_bytes());
+
+        for id in &self.removed {
+            bytes.extend_from_slice(id.as_bytes());
+        }
+
+        bytes
+    }
+}
+
+/// Source of the bytes of an asset pack.
+pub trait AssetPackReader: Read + Seek + Send {}
+
+impl<T> AssetPackReader for T where T: Read + Seek + Send {}
+
+/// An asset pack, read on demand; only the index is kept in memory.
+///
+/// The pack starts with a header: magic, format version (u32 LE) and offset of the index (u64 LE).
+/// The bytes of the entries follow, and the index comes last.
+pub struct AssetPack {
+    index: AssetPackIndex,
+    reader: Mutex<Box<dyn AssetPackReader>>,
+}
+
+impl AssetPack {
+    pub const MAGIC: &'static [u8; 4] = b"R3DP";
+    pub const FORMAT_VERSION: u32 = 1;
+    const HEADER_SIZE: u64 = 4 + 4 + 8;
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AssetPackError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader(mut reader: impl AssetPackReader + 'static) -> Result<Self, AssetPackError> {
+        let mut header = [0u8; Self::HEADER_SIZE as usize];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut header)?;
+
+        if &header[..4] != Self::MAGIC {
+            return Err(AssetPackError::MalformedPack(
+                "missing asset pack magic".to_owned(),
+            ));
+        }
+
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+
+        if Self::FORMAT_VERSION < version {
+            return Err(AssetPackError::UnsupportedVersion(version));
+        }
+
+        let index_offset = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let mut index = Vec::new();
+        reader.seek(SeekFrom::Start(index_offset))?;
+        reader.read_to_end(&mut index)?;
+
+        Ok(Self {
+            index: AssetPackIndex::parse(&index)?,
+            reader: Mutex::new(Box::new(reader)),
+        })
+    }
+
+    pub fn index(&self) -> &AssetPackIndex {
+        &self.index
+    }
+
+    /// Reads the bytes of the asset as stored, without checking them against the hash.
+    pub fn read(&self, id: Uuid) -> Result<Option<Vec<u8>>, AssetPackError> {
+        let entry = match self.index.entry(id) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let mut bytes = vec![0u8; entry.len as usize];
+        let mut reader = self.reader.lock().unwrap();
+        reader.seek(SeekFrom::Start(entry.offset))?;
+        reader.read_exact(&mut bytes)?;
+
+        Ok(Some(bytes))
+    }
+
+    /// Reads the bytes of the asset, failing if they do not match the hash in the index.
+    pub fn read_verified(&self, id: Uuid) -> Result<Option<Vec<u8>>, AssetPackError> {
+        let bytes = match self.read(id)? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        let expected = self.index.entry(id).unwrap().hash;
+        let actual = ContentHash::of(&bytes);
+
+        if actual != expected {
+            return Err(AssetPackError::HashMismatch {
+                id,
+                expected,
+                actual,
+            });
+        }
+
+        Ok(Some(bytes))
+    }
+
+    /// Builds a patch that turns a pack with the old index into this one.
+    /// Only the changed entries are read, so that diffing two large packs stays cheap.
+    pub fn patch_from(&self, old_index: &AssetPackIndex) -> Result<PatchPack, AssetPackError> {
+        let mut builder = AssetPackBuilder::new();
+
+        for (id, entry) in self.index.entries() {
+            if old_index.entry(id).map(|old| old.hash) != Some(entry.hash) {
+                builder.add(id, self.read(id)?.unwrap());
+            }
+        }
+
+        for (id, _) in old_index.entries() {
+            if self.index.entry(id).is_none() {
+                builder.remove(id);
+            }
+        }
+
+        Ok(PatchPack { builder })
+    }
+}
+
+/// Collects assets and writes them as a pack.
+#[derive(Debug, Default, Clone)]
+pub struct AssetPackBuilder {
+    entries: BTreeMap<Uuid, (ContentHash, Vec<u8>)>,
+    removed: BTreeSet<Uuid>,
+}
+
+impl AssetPackBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, id: Uuid, bytes: Vec<u8>) {
+        self.removed.remove(&id);
+        self.entries.insert(id, (ContentHash::of(&bytes), bytes));
+    }
+
+    /// Adds a processed asset in the packed [`VersionedAsset`] format.
+    pub fn add_source(
+        &mut self,
+        id: Uuid,
+        source: &TypedAssetSource,
+    ) -> Result<(), AssetPackError> {
+        self.add(id, pack_source(source)?);
+        Ok(())
+    }
+
+    /// Marks the asset as removed, hiding it in the layers below when the pack is used as a patch.
+    pub fn remove(&mut self, id: Uuid) {
+        self.entries.remove(&id);
+        self.removed.insert(id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.removed.is_empty()
+    }
+
+    /// Writes the pack, returning its index.
+    pub fn build(&self, mut writer: impl Write) -> Result<AssetPackIndex, AssetPackError> {
+        let mut index = AssetPackIndex {
+            entries: BTreeMap::new(),
+            removed: self.removed.clone(),
+        };
+        let mut offset = AssetPack::HEADER_SIZE;
+
+        for (id, (hash, bytes)) in &self.entries {
+            let len = bytes.len() as u64;
+            index.entries.insert(
+                *id,
+                AssetPackEntry {
+                    hash: *hash,
+                    offset,
+                    len,
+                },
+            );
+            offset += len;
+        }
+
+        writer.write_all(AssetPack::MAGIC)?;
+        writer.write_all(&AssetPack::FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&offset.to_le_bytes())?;
+
+        for (_, bytes) in self.entries.values() {
+            writer.write_all(bytes)?;
+        }
+
+        writer.write_all(&index.to_bytes())?;
+        writer.flush()?;
+
+        Ok(index)
+    }
+
+    /// Builds a patch that turns a pack with the old index into a pack of this content:
+    /// the entries whose hash changed or that are new, and the removal of the entries that are gone.
+    pub fn build_patch(&self, old_index: &AssetPackIndex) -> PatchPack {
+        let mut builder = AssetPackBuilder::new();
+
+        for (id, (hash, bytes)) in &self.entries {
+            if old_index.entry(*id).map(|old| old.hash) != Some(*hash) {
+                builder.entries.insert(*id, (*hash, bytes.clone()));
+            }
+        }
+
+        for (id, _) in old_index.entries() {
+            if !self.entries.contains_key(&id) {
+                builder.removed.insert(id);
+            }
+        }
+
+        PatchPack { builder }
+    }
+}
+
+/// The difference between two versions of a pack, layered over the old one by [`LayeredAssetPack`].
+#[derive(Debug, Clone)]
+pub struct PatchPack {
+    builder: AssetPackBuilder,
+}
+
+impl PatchPack {
+    pub fn changed(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.builder.entries.keys().copied()
+    }
+
+    pub fn removed(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.builder.removed.iter().copied()
+    }
+
+    /// Returns `true` if both versions have the same content.
+    pub fn is_empty(&self) -> bool {
+        self.builder.is_empty()
+    }
+
+    pub fn build(&self, writer: impl Write) -> Result<AssetPackIndex, AssetPackError> {
+        self.builder.build(writer)
+    }
+}
+
+/// A base pack with patches over it. Each asset is read from the topmost layer that contains or removes it.
+pub struct LayeredAssetPack {
+    layers: Vec<AssetPack>,
+    verify_hashes: bool,
+}
+
+impl LayeredAssetPack {
+    pub fn new(base: AssetPack) -> Self {
+        Self {
+            layers: vec![base],
+            verify_hashes: false,
+        }
+    }
+
+    /// Layers a patch over the others. Patches must be pushed in the order they were built.
+    pub fn push_patch(&mut self, patch: AssetPack) {
+        self.layers.push(patch);
+    }
+
+    pub fn layers(&self) -> &[AssetPack] {
+        &self.layers
+    }
+
+    pub fn verifies_hashes(&self) -> bool {
+        self.verify_hashes
+    }
+
+    /// Checks the bytes of every asset read against its hash. Off by default, as it costs a hash per read.
+    pub fn set_verify_hashes(&mut self, verify_hashes: bool) {
+        self.verify_hashes = verify_hashes;
+    }
+
+    /// Returns the index of the layer the asset is read from, or `None` if no layer has it.
+    pub fn find(&self, id: Uuid) -> Option<usize> {
+        for (layer_index, layer) in self.layers.iter().enumerate().rev() {
+            if layer.index().entry(id).is_some() {
+                return Some(layer_index);
+            }
+
+            if layer.index().is_removed(id) {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    pub fn contains(&self, id: Uuid) -> bool {
+        self.find(id).is_some()
+    }
+
+    /// Ids of all assets visible through the layers.
+    pub fn ids(&self) -> BTreeSet<Uuid> {
+        let mut ids = BTreeSet::new();
+
+        for layer in &self.layers {
+            for id in layer.index().removed() {
+                ids.remove(&id);
+            }
+
+            ids.extend(layer.index().entries().map(|(id, _)| id));
+        }
+
+        ids
+    }
+
+    pub fn read(&self, id: Uuid) -> Result<Option<Vec<u8>>, AssetPackError> {
+        let layer = match self.find(id) {
+            Some(layer_index) => &self.layers[layer_index],
+            None => return Ok(None),
+        };
+
+        if self.verify_hashes {
+            layer.read_verified(id)
+        } else {
+            layer.read(id)
+        }
+    }
+
+    /// Collects the assets visible through the layers, to write them back as a single pack.
+    pub fn merge(&self) -> Result<AssetPackBuilder, AssetPackError> {
+        let mut builder = AssetPackBuilder::new();
+
+        for id in self.ids() {
+            builder.add(id, self.read(id)?.unwrap());
+        }
+
+        Ok(builder)
+    }
+}
+
+/// Serializes a processed asset in the packed [`VersionedAsset`] format.
+pub fn pack_source(source: &TypedAssetSource) -> Result<Vec<u8>, AssetPackError> {
+    let asset = match source {
+        TypedAssetSource::Font(source) => VersionedAsset::new(AssetType::Font, source)?,
+        TypedAssetSource::Material(source) => VersionedAsset::new(AssetType::Material, source)?,
+        TypedAssetSource::Model(source) => VersionedAsset::new(AssetType::Model, source)?,
+        TypedAssetSource::Shader(source) => VersionedAsset::new(AssetType::Shader, source)?,
+        TypedAssetSource::Texture(source) => VersionedAsset::new(AssetType::Texture, source)?,
+    };
+
+    Ok(asset.to_packed()?)
+}
+
+/// Deserializes a processed asset written by [`pack_source`], migrating it to the current format version.
+pub fn unpack_source(bytes: &[u8]) -> Result<TypedAssetSource, AssetPackError> {
+    let asset = VersionedAsset::from_packed(bytes)?;
+    let migrations = migrations();
+
+    Ok(match asset.asset_type.as_str() {
+        "font" => migrations.load::<FontSource>(asset)?.into(),
+        "material" => migrations.load::<MaterialSource>(asset)?.into(),
+        "model" => migrations.load::<ModelSource>(asset)?.into(),
+        "shader" => migrations.load::<ShaderSource>(asset)?.into(),
+        "texture" => migrations.load::<TextureSource>(asset)?.into(),
+        _ => return Err(AssetPackError::UnknownAssetType(asset.asset_type)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn pack(builder: &AssetPackBuilder) -> (Vec<u8>, AssetPackIndex) {
+        let mut bytes = Vec::new();
+        let index = builder.build(&mut bytes).unwrap();
+        (bytes, index)
+    }
+
+    fn open(bytes: Vec<u8>) -> AssetPack {
+        AssetPack::from_reader(Cursor::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn check_patch_layers_over_base() {
+        let ids = (0..4).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+        let mut v1 = AssetPackBuilder::new();
+        for (index, id) in ids.iter().enumerate() {
+            v1.add(*id, format!("asset {} v1", index).into_bytes());
+        }
+        let (base_bytes, base_index) = pack(&v1);
+
+        let mut v2 = AssetPackBuilder::new();
+        v2.add(ids[0], b"asset 0 v2".to_vec());
+        v2.add(ids[1], b"asset 1 v2".to_vec());
+        v2.add(ids[2], b"asset 2 v1".to_vec());
+        let patch = v2.build_patch(&base_index);
+        assert_eq!(patch.changed().collect::<Vec<_>>(), {
+            let mut changed = vec![ids[0], ids[1]];
+            changed.sort();
+            changed
+        });
+        assert_eq!(patch.removed().collect::<Vec<_>>(), [ids[3]]);
+
+        let mut patch_bytes = Vec::new();
+        let patch_index = patch.build(&mut patch_bytes).unwrap();
+
+        let mut packs = LayeredAssetPack::new(open(base_bytes.clone()));
+        packs.push_patch(open(patch_bytes.clone()));
+        packs.set_verify_hashes(true);
+
+        assert_eq!(packs.find(ids[0]), Some(1));
+        assert_eq!(packs.find(ids[1]), Some(1));
+        assert_eq!(packs.find(ids[2]), Some(0));
+        assert_eq!(packs.find(ids[3]), None);
+        assert_eq!(packs.read(ids[0]).unwrap().unwrap(), b"asset 0 v2");
+        assert_eq!(packs.read(ids[2]).unwrap().unwrap(), b"asset 2 v1");
+        assert_eq!(packs.read(ids[3]).unwrap(), None);
+
+        // Merging the layers gives the same pack as building the new version directly.
+        let (_, merged_index) = pack(&packs.merge().unwrap());
+        assert_eq!(merged_index, pack(&v2).1);
+
+        // Diffing the packs themselves gives the same patch.
+        let v2_pack = open(pack(&v2).0);
+        let (_, diffed_index) = pack(&v2_pack.patch_from(&base_index).unwrap().builder);
+        assert_eq!(diffed_index, patch_index);
+
+        // A corrupted entry is caught by the hash, but only when verifying.
+        let entry = patch_index.entry(ids[1]).unwrap();
+        patch_bytes[entry.offset as usize] ^= 0xff;
+        let mut packs = LayeredAssetPack::new(open(base_bytes));
+        packs.push_patch(open(patch_bytes));
+        assert!(packs.read(ids[1]).unwrap().is_some());
+
+        packs.set_verify_hashes(true);
+        assert!(matches!(
+            packs.read(ids[1]),
+            Err(AssetPackError::HashMismatch { id, .. }) if id == ids[1]
+        ));
+        assert_eq!(packs.read(ids[0]).unwrap().unwrap(), b"asset 0 v2");
+    }
+}
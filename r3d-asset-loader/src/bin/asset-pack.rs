@@ -0,0 +1,67 @@
+//! Builds patches between asset packs, and merges patches back into a single pack.
+//!
+//! Usage:
+//! - `asset-pack diff <old-pack> <new-pack> <patch>`: writes the patch that turns the old pack into the new one.
+//! - `asset-pack apply <base-pack> [<patch>...] <output>`: layers the patches over the base in order and writes the result.
+
+use asset_loader::{AssetPack, AssetPackError, LayeredAssetPack};
+use std::{fs::File, io::BufWriter, process::ExitCode};
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let result = match args.as_slice() {
+        [command, old, new, patch] if command == "diff" => diff(old, new, patch),
+        [command, base, patches @ .., output] if command == "apply" => apply(base, patches, output),
+        _ => {
+            eprintln!("usage: asset-pack diff <old-pack> <new-pack> <patch>");
+            eprintln!("       asset-pack apply <base-pack> [<patch>...] <output>");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("failed to {}: {}", args[0], err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn diff(old: &str, new: &str, patch: &str) -> Result<(), AssetPackError> {
+    let old = AssetPack::open(old)?;
+    let new = AssetPack::open(new)?;
+    let diff = new.patch_from(old.index())?;
+
+    for id in diff.changed() {
+        println!("changed {}", id);
+    }
+
+    for id in diff.removed() {
+        println!("removed {}", id);
+    }
+
+    diff.build(BufWriter::new(File::create(patch)?))?;
+    println!(
+        "{} changed, {} removed",
+        diff.changed().count(),
+        diff.removed().count()
+    );
+
+    Ok(())
+}
+
+fn apply(base: &str, patches: &[String], output: &str) -> Result<(), AssetPackError> {
+    let mut packs = LayeredAssetPack::new(AssetPack::open(base)?);
+    packs.set_verify_hashes(true);
+
+    for patch in patches {
+        packs.push_patch(AssetPack::open(patch)?);
+    }
+
+    let merged = packs.merge()?;
+    merged.build(BufWriter::new(File::create(output)?))?;
+    println!("{} asset(s) written", merged.len());
+
+    Ok(())
+}
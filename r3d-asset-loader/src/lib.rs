@@ -1,6 +1,8 @@
 mod asset_database;
 mod asset_loader;
 pub mod asset_loaders;
+mod asset_pack;
 
 pub use asset_database::*;
 pub use asset_loader::*;
+pub use asset_pack::*;
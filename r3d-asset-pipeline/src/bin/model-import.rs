@@ -0,0 +1,58 @@
+//! Processes a model the way the asset pipeline does and reports what the mesh processing did.
+//!
+//! Usage: `model-import <model> [<metadata>]`
+//!
+//! The metadata is the TOML file next to the model, whose `[mesh]` table configures welding, simplification and LODs.
+
+use asset_pipeline::{pipelines::process_model, Metadata};
+use std::{path::Path, process::ExitCode};
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (model_path, metadata_path) = match args.as_slice() {
+        [model_path] => (model_path, None),
+        [model_path, metadata_path] => (model_path, Some(metadata_path)),
+        _ => {
+            eprintln!("usage: model-import <model> [<metadata>]");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let metadata = match metadata_path
+        .map(|path| std::fs::read_to_string(path).map_err(anyhow::Error::from))
+        .transpose()
+        .and_then(|content| {
+            content
+                .map(|content| Metadata::from_toml(content).map_err(anyhow::Error::from))
+                .transpose()
+        }) {
+        Ok(metadata) => metadata.map(|metadata| metadata.extra).unwrap_or_default(),
+        Err(err) => {
+            eprintln!("failed to load metadata: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let content = match std::fs::read(model_path) {
+        Ok(content) => content,
+        Err(err) => {
+            eprintln!("failed to read {}: {}", model_path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match process_model(Path::new(model_path), &content, &metadata) {
+        Ok((model, reports)) => {
+            for report in &reports {
+                println!("{}", report);
+            }
+
+            println!("{} mesh(es) processed", model.meshes.len());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("failed to process {}: {}", model_path, err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -5,14 +5,18 @@ use asset::{
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+mod mesh_processing;
 mod metadata;
 mod pipeline;
 mod pipeline_gfx_bridge;
 pub mod pipelines;
+mod texture_container;
 
+pub use mesh_processing::*;
 pub use metadata::*;
 pub use pipeline::*;
 pub use pipeline_gfx_bridge::*;
+pub use texture_container::*;
 
 pub enum TypedAssetSource {
     Font(FontSource),
@@ -142,9 +146,8 @@ pub fn deduce_asset_type_from_path(
         "mat" => Ok(AssetType::Material),
         "gltf" | "glb" | "fbx" | "obj" | "3ds" | "blender" => Ok(AssetType::Model),
         "pmx" => Ok(AssetType::Model),
-        "png" | "jpg" | "jpeg" | "gif" | "tif" | "tiff" | "tga" | "bmp" | "webp" => {
-            Ok(AssetType::Texture)
-        }
+        "png" | "jpg" | "jpeg" | "gif" | "tif" | "tiff" | "tga" | "bmp" | "webp" | "dds"
+        | "ktx2" => Ok(AssetType::Texture),
         "wgsl" => Ok(AssetType::Shader),
         _ => Err(AssetTypeDeduceError::UnsupportedExtension(
             path.to_path_buf(),
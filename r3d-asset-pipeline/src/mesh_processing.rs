@@ -0,0 +1,1089 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+    fmt::Display,
+};
+
+/// Indexed triangle mesh with interleaved `f32` vertex attributes, processed at import time.
+/// The position is always the first three floats of a vertex.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshGeometry {
+    /// Number of floats per vertex.
+    pub stride: usize,
+    pub vertices: Vec<f32>,
+    pub indices: Vec<u32>,
+    /// Offset of the normal in floats, if any.
+    pub normal_offset: Option<usize>,
+    /// Offsets of the texture coordinates in floats.
+    pub tex_coord_offsets: Vec<usize>,
+}
+
+impl MeshGeometry {
+    pub fn vertex_count(&self) -> usize {
+        if self.stride == 0 {
+            0
+        } else {
+            self.vertices.len() / self.stride
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn vertex(&self, vertex: u32) -> &[f32] {
+        let offset = vertex as usize * self.stride;
+        &self.vertices[offset..offset + self.stride]
+    }
+
+    pub fn position(&self, vertex: u32) -> [f32; 3] {
+        let vertex = self.vertex(vertex);
+        [vertex[0], vertex[1], vertex[2]]
+    }
+
+    /// Drops the vertices no triangle refers to, keeping the order of the rest.
+    pub fn remove_unused_vertices(&mut self) {
+        let mut remap = vec![None; self.vertex_count()];
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+
+        for index in &mut self.indices {
+            let offset = *index as usize * self.stride;
+            *index = *remap[*index as usize].get_or_insert_with(|| {
+                vertices.extend_from_slice(&self.vertices[offset..offset + self.stride]);
+                (vertices.len() / self.stride - 1) as u32
+            });
+        }
+
+        self.vertices = vertices;
+    }
+
+    fn bounds_diagonal(&self) -> f32 {
+        let (min, max) = self.indices.iter().fold(
+            ([f32::MAX; 3], [f32::MIN; 3]),
+            |(mut min, mut max), &index| {
+                let position = self.position(index);
+
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(position[axis]);
+                    max[axis] = max[axis].max(position[axis]);
+                }
+
+                (min, max)
+            },
+        );
+
+        if self.indices.is_empty() {
+            0.0
+        } else {
+            length(sub(to_f64(max), to_f64(min))) as f32
+        }
+    }
+}
+
+/// Counts of a mesh before and after the import time processing.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MeshProcessingReport {
+    pub mesh_index: u32,
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    pub triangles_before: usize,
+    pub triangles_after: usize,
+    /// Triangle counts of the generated LODs, from the most detailed one.
+    pub lod_triangles: Vec<usize>,
+}
+
+impl Display for MeshProcessingReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "mesh {}: {} -> {} vertices, {} -> {} triangles",
+            self.mesh_index,
+            self.vertices_before,
+            self.vertices_after,
+            self.triangles_before,
+            self.triangles_after
+        )?;
+
+        if !self.lod_triangles.is_empty() {
+            write!(f, ", lod triangles {:?}", self.lod_triangles)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeldOptions {
+    pub position_epsilon: f32,
+    pub normal_epsilon: f32,
+    pub tex_coord_epsilon: f32,
+    /// Recomputes the normals before matching, smoothing across edges whose faces meet at no more than this angle
+    /// in radians. The source normals are discarded, so that flat shaded meshes can be welded into smooth ones.
+    pub smoothing_angle: Option<f32>,
+}
+
+impl Default for WeldOptions {
+    fn default() -> Self {
+        Self {
+            position_epsilon: 1e-5,
+            normal_epsilon: 1e-3,
+            tex_coord_epsilon: 1e-5,
+            smoothing_angle: None,
+        }
+    }
+}
+
+/// Merges the vertices whose positions, normals and texture coordinates are within the epsilons of the options.
+/// Any other attribute, e.g. colors or bone weights, must match exactly. Unused vertices are dropped.
+pub fn weld_vertices(geometry: &MeshGeometry, options: &WeldOptions) -> MeshGeometry {
+    let smoothed = match (options.smoothing_angle, geometry.normal_offset) {
+        (Some(angle), Some(normal_offset)) => Some(smooth_normals(
+            geometry,
+            normal_offset,
+            angle,
+            options.position_epsilon,
+        )),
+        _ => None,
+    };
+    let geometry = smoothed.as_ref().unwrap_or(geometry);
+
+    let mut tolerances = vec![0f32; geometry.stride];
+    tolerances[..3].fill(options.position_epsilon);
+
+    if let Some(normal_offset) = geometry.normal_offset {
+        tolerances[normal_offset..normal_offset + 3].fill(options.normal_epsilon);
+    }
+
+    for &offset in &geometry.tex_coord_offsets {
+        tolerances[offset..offset + 2].fill(options.tex_coord_epsilon);
+    }
+
+    let mut grid = PositionGrid::new(options.position_epsilon);
+    let mut remap = vec![None; geometry.vertex_count()];
+    let mut vertices = Vec::<f32>::with_capacity(geometry.vertices.len());
+    let mut indices = Vec::with_capacity(geometry.indices.len());
+
+    for &index in &geometry.indices {
+        if let Some(welded) = remap[index as usize] {
+            indices.push(welded);
+            continue;
+        }
+
+        let vertex = geometry.vertex(index);
+        let position = geometry.position(index);
+        let existing = grid.candidates(position).find(|&candidate| {
+            let offset = candidate as usize * geometry.stride;
+            vertices[offset..offset + geometry.stride]
+                .iter()
+                .zip(vertex)
+                .zip(&tolerances)
+                .all(|((lhs, rhs), tolerance)| (lhs - rhs).abs() <= *tolerance)
+        });
+        let welded = match existing {
+            Some(welded) => welded,
+            None => {
+                let welded = (vertices.len() / geometry.stride) as u32;
+                vertices.extend_from_slice(vertex);
+                grid.insert(position, welded);
+                welded
+            }
+        };
+
+        remap[index as usize] = Some(welded);
+        indices.push(welded);
+    }
+
+    MeshGeometry {
+        stride: geometry.stride,
+        vertices,
+        indices,
+        normal_offset: geometry.normal_offset,
+        tex_coord_offsets: geometry.tex_coord_offsets.clone(),
+    }
+}
+
+/// Splits every triangle corner into its own vertex, with the normal averaged over the faces around its position
+/// that are within the smoothing angle of the corner's face. Faces are weighted by their angle at the position,
+/// so that the way faces are triangulated doesn't bias the result.
+fn smooth_normals(
+    geometry: &MeshGeometry,
+    normal_offset: usize,
+    smoothing_angle: f32,
+    position_epsilon: f32,
+) -> MeshGeometry {
+    let mut face_normals = Vec::with_capacity(geometry.triangle_count());
+    let mut corner_angles = Vec::with_capacity(geometry.indices.len());
+
+    for triangle in geometry.indices.chunks_exact(3) {
+        let corners = [0, 1, 2].map(|corner| to_f64(geometry.position(triangle[corner])));
+        face_normals.push(normalize(cross(
+            sub(corners[1], corners[0]),
+            sub(corners[2], corners[0]),
+        )));
+
+        for corner in 0..3 {
+            let to_next = normalize(sub(corners[(corner + 1) % 3], corners[corner]));
+            let to_prev = normalize(sub(corners[(corner + 2) % 3], corners[corner]));
+            corner_angles.push(dot(to_next, to_prev).clamp(-1.0, 1.0).acos());
+        }
+    }
+
+    // Groups the corners by position, so that faces touching only by position are smoothed too.
+    let mut grid = PositionGrid::new(position_epsilon);
+    let mut group_positions = Vec::new();
+    let mut group_corners = Vec::<Vec<usize>>::new();
+    let mut corner_groups = Vec::with_capacity(geometry.indices.len());
+
+    for (corner, &index) in geometry.indices.iter().enumerate() {
+        let position = geometry.position(index);
+        let existing = grid.candidates(position).find(|&group| {
+            let existing: [f32; 3] = group_positions[group as usize];
+            (0..3).all(|axis| (existing[axis] - position[axis]).abs() <= position_epsilon)
+        });
+        let group = match existing {
+            Some(group) => group,
+            None => {
+                let group = group_positions.len() as u32;
+                group_positions.push(position);
+                group_corners.push(Vec::new());
+                grid.insert(position, group);
+                group
+            }
+        };
+
+        group_corners[group as usize].push(corner);
+        corner_groups.push(group);
+    }
+
+    let threshold = (smoothing_angle as f64).cos() - 1e-6;
+    let mut vertices = Vec::with_capacity(geometry.indices.len() * geometry.stride);
+
+    for (corner, &index) in geometry.indices.iter().enumerate() {
+        let face_normal = face_normals[corner / 3];
+        let mut normal = [0.0; 3];
+
+        for &other in &group_corners[corner_groups[corner] as usize] {
+            let other_normal = face_normals[other / 3];
+
+            if other / 3 == corner / 3 || threshold <= dot(face_normal, other_normal) {
+                normal = add(
+                    normal,
+                    other_normal.map(|value| value * corner_angles[other]),
+                );
+            }
+        }
+
+        let offset = vertices.len();
+        vertices.extend_from_slice(geometry.vertex(index));
+
+        let normal = normalize(normal);
+        if normal != [0.0; 3] {
+            for axis in 0..3 {
+                vertices[offset + normal_offset + axis] = normal[axis] as f32;
+            }
+        }
+    }
+
+    MeshGeometry {
+        stride: geometry.stride,
+        vertices,
+        indices: Vec::from_iter(0..geometry.indices.len() as u32),
+        normal_offset: geometry.normal_offset,
+        tex_coord_offsets: geometry.tex_coord_offsets.clone(),
+    }
+}
+
+/// Hashes positions into cells no smaller than twice the epsilon, so that matches are always in neighboring cells.
+struct PositionGrid {
+    cell_size: f32,
+    cells: HashMap<[i32; 3], Vec<u32>>,
+}
+
+impl PositionGrid {
+    fn new(epsilon: f32) -> Self {
+        Self {
+            cell_size: (epsilon * 2.0).max(1e-4),
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, position: [f32; 3]) -> [i32; 3] {
+        position.map(|value| (value / self.cell_size).floor() as i32)
+    }
+
+    fn insert(&mut self, position: [f32; 3], item: u32) {
+        let cell = self.cell(position);
+        self.cells.entry(cell).or_default().push(item);
+    }
+
+    fn candidates(&self, position: [f32; 3]) -> impl Iterator<Item = u32> + '_ {
+        let [x, y, z] = self.cell(position);
+
+        (-1..=1)
+            .flat_map(move |dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [dx, dy, dz])))
+            .filter_map(move |[dx, dy, dz]| {
+                self.cells.get(&[
+                    x.saturating_add(dx),
+                    y.saturating_add(dy),
+                    z.saturating_add(dz),
+                ])
+            })
+            .flatten()
+            .copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimplifyOptions {
+    /// Simplification stops once the mesh has no more triangles than this.
+    pub target_triangles: usize,
+    /// Largest error allowed, relative to the diagonal of the mesh bounds.
+    /// Simplification stops before the target if every remaining collapse would exceed it.
+    pub max_error: f32,
+}
+
+/// Result of [`simplify`]. The indices refer to the vertices of the simplified geometry, which are left untouched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimplifiedMesh {
+    pub indices: Vec<u32>,
+    /// Largest error of the applied collapses, relative to the diagonal of the mesh bounds.
+    pub error: f32,
+}
+
+/// Reduces the triangle count by collapsing edges in the order of their quadric error.
+/// Vertices are only ever collapsed into one of their neighbors, so no attribute is interpolated and
+/// the result can share the vertex buffer of the source, e.g. for LODs.
+/// Boundary edges only collapse along the boundary, and vertices on attribute seams such as UV seams only
+/// collapse along the seam, on every side of it at once. Expects the geometry to be welded first.
+pub fn simplify(geometry: &MeshGeometry, options: &SimplifyOptions) -> SimplifiedMesh {
+    let diagonal = geometry.bounds_diagonal();
+
+    if geometry.triangle_count() <= options.target_triangles || diagonal <= 0.0 {
+        return SimplifiedMesh {
+            indices: geometry.indices.clone(),
+            error: 0.0,
+        };
+    }
+
+    let mut simplifier = Simplifier::new(geometry);
+    let max_error = options.max_error as f64 * diagonal as f64;
+    let error = simplifier.run(options.target_triangles, max_error);
+
+    SimplifiedMesh {
+        indices: simplifier.into_indices(),
+        error: (error / diagonal as f64) as f32,
+    }
+}
+
+/// Builds one index buffer per ratio of the source triangle count, all sharing the vertices of the geometry.
+pub fn generate_lods(
+    geometry: &MeshGeometry,
+    ratios: &[f32],
+    max_error: f32,
+) -> Vec<SimplifiedMesh> {
+    Vec::from_iter(ratios.iter().map(|&ratio| {
+        let target_triangles = (geometry.triangle_count() as f32 * ratio.clamp(0.0, 1.0)).ceil();
+        simplify(
+            geometry,
+            &SimplifyOptions {
+                target_triangles: target_triangles as usize,
+                max_error,
+            },
+        )
+    }))
+}
+
+/// Boundary planes are weighted heavily, so that boundaries keep their shape.
+const BOUNDARY_WEIGHT: f64 = 10.0;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric {
+    elements: [f64; 10],
+    weight: f64,
+}
+
+impl Quadric {
+    fn from_plane(normal: [f64; 3], distance: f64, weight: f64) -> Self {
+        let [a, b, c] = normal;
+        let d = distance;
+
+        Self {
+            elements: [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|element| element * weight),
+            weight,
+        }
+    }
+
+    fn add(&mut self, other: &Self) {
+        for index in 0..10 {
+            self.elements[index] += other.elements[index];
+        }
+
+        self.weight += other.weight;
+    }
+
+    /// Returns the weighted root mean square distance of the point to the planes.
+    fn error(&self, point: [f64; 3]) -> f64 {
+        let [x, y, z] = point;
+        let [aa, ab, ac, ad, bb, bc, bd, cc, cd, dd] = self.elements;
+        let squared = aa * x * x
+            + 2.0 * ab * x * y
+            + 2.0 * ac * x * z
+            + 2.0 * ad * x
+            + bb * y * y
+            + 2.0 * bc * y * z
+            + 2.0 * bd * y
+            + cc * z * z
+            + 2.0 * cd * z
+            + dd;
+
+        if self.weight <= 0.0 {
+            0.0
+        } else {
+            (squared.max(0.0) / self.weight).sqrt()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Collapse {
+    error: f64,
+    from: u32,
+    to: u32,
+    version: u32,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, so that the heap pops the cheapest collapse first.
+        other
+            .error
+            .total_cmp(&self.error)
+            .then(other.from.cmp(&self.from))
+            .then(other.to.cmp(&self.to))
+    }
+}
+
+/// Edge collapse simplification over positions; every vertex sharing a position moves together.
+struct Simplifier {
+    indices: Vec<u32>,
+    triangle_alive: Vec<bool>,
+    triangle_count: usize,
+    vertex_positions: Vec<u32>,
+    positions: Vec<[f64; 3]>,
+    position_alive: Vec<bool>,
+    position_triangles: Vec<Vec<u32>>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,
+}
+
+impl Simplifier {
+    fn new(geometry: &MeshGeometry) -> Self {
+        let mut position_ids = HashMap::new();
+        let mut positions = Vec::new();
+        let vertex_positions = Vec::from_iter((0..geometry.vertex_count() as u32).map(|vertex| {
+            // Adding zero turns negative zeros into positive ones, so that both hash the same.
+            let position = geometry.position(vertex).map(|value| value + 0.0);
+            *position_ids
+                .entry(position.map(f32::to_bits))
+                .or_insert_with(|| {
+                    positions.push(to_f64(position));
+                    positions.len() as u32 - 1
+                })
+        }));
+
+        let triangle_count = geometry.triangle_count();
+        let mut position_triangles = vec![Vec::new(); positions.len()];
+        let mut quadrics = vec![Quadric::default(); positions.len()];
+        let mut edge_triangles = HashMap::<(u32, u32), Vec<u32>>::new();
+
+        for (triangle, corners) in geometry.indices.chunks_exact(3).enumerate() {
+            let corners = corners
+                .iter()
+                .map(|&index| vertex_positions[index as usize]);
+            let [p0, p1, p2] = <[u32; 3]>::try_from(Vec::from_iter(corners)).unwrap();
+
+            for position in [p0, p1, p2] {
+                position_triangles[position as usize].push(triangle as u32);
+            }
+
+            for (from, to) in [(p0, p1), (p1, p2), (p2, p0)] {
+                edge_triangles
+                    .entry((from.min(to), from.max(to)))
+                    .or_default()
+                    .push(triangle as u32);
+            }
+
+            let normal = cross(
+                sub(positions[p1 as usize], positions[p0 as usize]),
+                sub(positions[p2 as usize], positions[p0 as usize]),
+            );
+            let area = length(normal) * 0.5;
+
+            if area <= 0.0 {
+                continue;
+            }
+
+            let normal = normalize(normal);
+            let quadric = Quadric::from_plane(normal, -dot(normal, positions[p0 as usize]), area);
+
+            for position in [p0, p1, p2] {
+                quadrics[position as usize].add(&quadric);
+            }
+        }
+
+        // Keeps boundaries in place by adding planes perpendicular to the faces along boundary edges.
+        for ((from, to), triangles) in &edge_triangles {
+            if triangles.len() != 1 {
+                continue;
+            }
+
+            let corners = &geometry.indices[triangles[0] as usize * 3..][..3];
+            let [p0, p1, p2] = [0, 1, 2]
+                .map(|corner| positions[vertex_positions[corners[corner] as usize] as usize]);
+            let face_normal = normalize(cross(sub(p1, p0), sub(p2, p0)));
+            let edge = sub(positions[*to as usize], positions[*from as usize]);
+            let normal = normalize(cross(edge, face_normal));
+
+            if normal == [0.0; 3] {
+                continue;
+            }
+
+            let quadric = Quadric::from_plane(
+                normal,
+                -dot(normal, positions[*from as usize]),
+                dot(edge, edge) * BOUNDARY_WEIGHT,
+            );
+            quadrics[*from as usize].add(&quadric);
+            quadrics[*to as usize].add(&quadric);
+        }
+
+        Self {
+            indices: geometry.indices.clone(),
+            triangle_alive: vec![true; triangle_count],
+            triangle_count,
+            vertex_positions,
+            position_alive: vec![true; positions.len()],
+            versions: vec![0; positions.len()],
+            positions,
+            position_triangles,
+            quadrics,
+        }
+    }
+
+    fn into_indices(self) -> Vec<u32> {
+        Vec::from_iter(
+            self.indices
+                .chunks_exact(3)
+                .zip(&self.triangle_alive)
+                .filter(|(_, alive)| **alive)
+                .flat_map(|(triangle, _)| triangle.iter().copied()),
+        )
+    }
+
+    /// Collapses edges until the target is reached or no collapse stays under the error.
+    /// Returns the largest error of the applied collapses.
+    fn run(&mut self, target_triangles: usize, max_error: f64) -> f64 {
+        let mut error = 0f64;
+
+        // Collapses rejected once may become valid after their neighborhood changes, so passes repeat until stuck.
+        loop {
+            let mut heap = BinaryHeap::new();
+
+            for position in 0..self.positions.len() as u32 {
+                self.push_collapses(&mut heap, position);
+            }
+
+            let mut has_collapsed = false;
+
+            while target_triangles < self.triangle_count {
+                let collapse = match heap.pop() {
+                    Some(collapse) => collapse,
+                    None => break,
+                };
+
+                if max_error < collapse.error {
+                    break;
+                }
+
+                if !self.position_alive[collapse.from as usize]
+                    || !self.position_alive[collapse.to as usize]
+                    || self.version(collapse.from, collapse.to) != collapse.version
+                {
+                    continue;
+                }
+
+                let remap = match self.check_collapse(collapse.from, collapse.to) {
+                    Some(remap) => remap,
+                    None => continue,
+                };
+
+                self.collapse(collapse.from, collapse.to, &remap);
+                self.push_collapses(&mut heap, collapse.to);
+                error = error.max(collapse.error);
+                has_collapsed = true;
+            }
+
+            if !has_collapsed || self.triangle_count <= target_triangles {
+                return error;
+            }
+        }
+    }
+
+    fn push_collapses(&self, heap: &mut BinaryHeap<Collapse>, position: u32) {
+        if !self.position_alive[position as usize] {
+            return;
+        }
+
+        for neighbor in self.neighbors(position) {
+            for (from, to) in [(position, neighbor), (neighbor, position)] {
+                let mut quadric = self.quadrics[from as usize];
+                quadric.add(&self.quadrics[to as usize]);
+
+                heap.push(Collapse {
+                    error: quadric.error(self.positions[to as usize]),
+                    from,
+                    to,
+                    version: self.version(from, to),
+                });
+            }
+        }
+    }
+
+    /// Entries stay valid while neither end changes, so the version of an edge is the sum of both ends.
+    fn version(&self, from: u32, to: u32) -> u32 {
+        self.versions[from as usize].wrapping_add(self.versions[to as usize])
+    }
+
+    fn alive_triangles(&self, position: u32) -> impl Iterator<Item = u32> + '_ {
+        self.position_triangles[position as usize]
+            .iter()
+            .copied()
+            .filter(|&triangle| self.triangle_alive[triangle as usize])
+    }
+
+    fn triangle_positions(&self, triangle: u32) -> [u32; 3] {
+        let corners = &self.indices[triangle as usize * 3..][..3];
+        [0, 1, 2].map(|corner| self.vertex_positions[corners[corner] as usize])
+    }
+
+    fn neighbors(&self, position: u32) -> HashSet<u32> {
+        HashSet::from_iter(
+            self.alive_triangles(position)
+                .flat_map(|triangle| self.triangle_positions(triangle))
+                .filter(|&other| other != position),
+        )
+    }
+
+    fn is_boundary(&self, position: u32) -> bool {
+        let mut edge_counts = HashMap::<u32, usize>::new();
+
+        for triangle in self.alive_triangles(position) {
+            for other in self.triangle_positions(triangle) {
+                if other != position {
+                    *edge_counts.entry(other).or_default() += 1;
+                }
+            }
+        }
+
+        edge_counts.values().any(|&count| count == 1)
+    }
+
+    /// Returns the vertex each vertex at `from` turns into, if collapsing `from` into `to` keeps the mesh intact.
+    fn check_collapse(&self, from: u32, to: u32) -> Option<HashMap<u32, u32>> {
+        let shared = self
+            .alive_triangles(from)
+            .filter(|&triangle| self.triangle_positions(triangle).contains(&to))
+            .count();
+
+        // Non-manifold edges are left alone, and boundaries only collapse along themselves.
+        if shared == 0 || 2 < shared || (self.is_boundary(from) && shared != 1) {
+            return None;
+        }
+
+        // The ends must share exactly the neighbors across the collapsed triangles, or the surface would fold.
+        let common = self
+            .neighbors(from)
+            .intersection(&self.neighbors(to))
+            .count();
+        if common != shared {
+            return None;
+        }
+
+        // Every vertex at `from` must be connected to exactly one vertex at `to`, which keeps seams intact.
+        let mut partners = HashMap::<u32, HashSet<u32>>::new();
+
+        for triangle in self.alive_triangles(from) {
+            let corners = &self.indices[triangle as usize * 3..][..3];
+
+            for &vertex in corners {
+                if self.vertex_positions[vertex as usize] != from {
+                    continue;
+                }
+
+                let partners = partners.entry(vertex).or_default();
+
+                for &other in corners {
+                    if self.vertex_positions[other as usize] == to {
+                        partners.insert(other);
+                    }
+                }
+            }
+        }
+
+        let mut remap = HashMap::with_capacity(partners.len());
+
+        for (vertex, partners) in partners {
+            if partners.len() != 1 {
+                return None;
+            }
+
+            remap.insert(vertex, partners.into_iter().next().unwrap());
+        }
+
+        // Rejects collapses that flip or degenerate the remaining triangles.
+        for triangle in self.alive_triangles(from) {
+            let corners = self.triangle_positions(triangle);
+
+            if corners.contains(&to) {
+                continue;
+            }
+
+            let before = corners.map(|position| self.positions[position as usize]);
+            let after = corners.map(|position| {
+                self.positions[if position == from { to } else { position } as usize]
+            });
+            let before = normalize(cross(sub(before[1], before[0]), sub(before[2], before[0])));
+            let after = normalize(cross(sub(after[1], after[0]), sub(after[2], after[0])));
+
+            if after == [0.0; 3] || dot(before, after) <= 0.0 {
+                return None;
+            }
+        }
+
+        Some(remap)
+    }
+
+    fn collapse(&mut self, from: u32, to: u32, remap: &HashMap<u32, u32>) {
+        let triangles = Vec::from_iter(self.alive_triangles(from));
+
+        for triangle in triangles {
+            if self.triangle_positions(triangle).contains(&to) {
+                self.triangle_alive[triangle as usize] = false;
+                self.triangle_count -= 1;
+                continue;
+            }
+
+            for corner in &mut self.indices[triangle as usize * 3..][..3] {
+                if let Some(&vertex) = remap.get(corner) {
+                    *corner = vertex;
+                }
+            }
+
+            self.position_triangles[to as usize].push(triangle);
+        }
+
+        let quadric = self.quadrics[from as usize];
+        self.quadrics[to as usize].add(&quadric);
+        self.position_alive[from as usize] = false;
+        self.position_triangles[from as usize].clear();
+        self.versions[to as usize] = self.versions[to as usize].wrapping_add(1);
+        self.position_triangles[to as usize]
+            .retain(|&triangle| self.triangle_alive[triangle as usize]);
+    }
+}
+
+fn to_f64(value: [f32; 3]) -> [f64; 3] {
+    value.map(|value| value as f64)
+}
+
+fn add(lhs: [f64; 3], rhs: [f64; 3]) -> [f64; 3] {
+    [lhs[0] + rhs[0], lhs[1] + rhs[1], lhs[2] + rhs[2]]
+}
+
+fn sub(lhs: [f64; 3], rhs: [f64; 3]) -> [f64; 3] {
+    [lhs[0] - rhs[0], lhs[1] - rhs[1], lhs[2] - rhs[2]]
+}
+
+fn dot(lhs: [f64; 3], rhs: [f64; 3]) -> f64 {
+    lhs[0] * rhs[0] + lhs[1] * rhs[1] + lhs[2] * rhs[2]
+}
+
+fn cross(lhs: [f64; 3], rhs: [f64; 3]) -> [f64; 3] {
+    [
+        lhs[1] * rhs[2] - lhs[2] * rhs[1],
+        lhs[2] * rhs[0] - lhs[0] * rhs[2],
+        lhs[0] * rhs[1] - lhs[1] * rhs[0],
+    ]
+}
+
+fn length(value: [f64; 3]) -> f64 {
+    dot(value, value).sqrt()
+}
+
+fn normalize(value: [f64; 3]) -> [f64; 3] {
+    let length = length(value);
+
+    if length <= f64::EPSILON {
+        [0.0; 3]
+    } else {
+        value.map(|value| value / length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FACES: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+        ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+        ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+        ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+        ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+        ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+        ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+    ];
+
+    /// A unit cube centered at the origin with every face split into `subdivisions` squared quads,
+    /// as an unindexed triangle soup of positions and flat normals.
+    fn subdivided_cube(subdivisions: usize) -> MeshGeometry {
+        let mut vertices = Vec::new();
+        let step = 1.0 / subdivisions as f32;
+
+        for (normal, u, v) in FACES {
+            let point = |i: usize, j: usize| {
+                let (s, t) = (i as f32 * step - 0.5, j as f32 * step - 0.5);
+                [0, 1, 2].map(|axis| normal[axis] * 0.5 + u[axis] * s + v[axis] * t)
+            };
+
+            for i in 0..subdivisions {
+                for j in 0..subdivisions {
+                    let quad = [
+                        point(i, j),
+                        point(i + 1, j),
+                        point(i + 1, j + 1),
+                        point(i, j + 1),
+                    ];
+
+                    for corner in [0, 1, 2, 0, 2, 3] {
+                        vertices.extend_from_slice(&quad[corner]);
+                        vertices.extend_from_slice(&normal);
+                    }
+                }
+            }
+        }
+
+        MeshGeometry {
+            stride: 6,
+            indices: Vec::from_iter(0..(vertices.len() / 6) as u32),
+            vertices,
+            normal_offset: Some(3),
+            tex_coord_offsets: vec![],
+        }
+    }
+
+    /// Symmetric Hausdorff distance, sampled at the vertices, edge midpoints and centroids of both meshes.
+    fn hausdorff_distance(lhs: &MeshGeometry, rhs: &MeshGeometry) -> f64 {
+        fn one_sided(from: &MeshGeometry, to: &MeshGeometry) -> f64 {
+            let triangles = |geometry: &MeshGeometry| {
+                Vec::from_iter(geometry.indices.chunks_exact(3).map(|triangle| {
+                    triangle
+                        .iter()
+                        .map(|&index| to_f64(geometry.position(index)))
+                }))
+                .into_iter()
+                .map(|corners| <[[f64; 3]; 3]>::try_from(Vec::from_iter(corners)).unwrap())
+                .collect::<Vec<_>>()
+            };
+            let targets = triangles(to);
+
+            triangles(from)
+                .into_iter()
+                .flat_map(|[a, b, c]| {
+                    let mid = |p: [f64; 3], q: [f64; 3]| add(p, q).map(|value| value * 0.5);
+                    let centroid = add(add(a, b), c).map(|value| value / 3.0);
+                    [a, b, c, mid(a, b), mid(b, c), mid(c, a), centroid]
+                })
+                .map(|point| {
+                    targets
+                        .iter()
+                        .map(|&triangle| point_triangle_distance(point, triangle))
+                        .fold(f64::MAX, f64::min)
+                })
+                .fold(0.0, f64::max)
+        }
+
+        one_sided(lhs, rhs).max(one_sided(rhs, lhs))
+    }
+
+    fn point_triangle_distance(point: [f64; 3], [a, b, c]: [[f64; 3]; 3]) -> f64 {
+        let normal = normalize(cross(sub(b, a), sub(c, a)));
+        let projected = sub(
+            point,
+            normal.map(|value| value * dot(sub(point, a), normal)),
+        );
+        let is_inside = [(a, b), (b, c), (c, a)]
+            .iter()
+            .all(|&(p, q)| 0.0 <= dot(cross(sub(q, p), sub(projected, p)), normal));
+
+        if is_inside {
+            return length(sub(point, projected));
+        }
+
+        [(a, b), (b, c), (c, a)]
+            .iter()
+            .map(|&(p, q)| {
+                let edge = sub(q, p);
+                let t = (dot(sub(point, p), edge) / dot(edge, edge)).clamp(0.0, 1.0);
+                length(sub(point, add(p, edge.map(|value| value * t))))
+            })
+            .fold(f64::MAX, f64::min)
+    }
+
+    #[test]
+    fn check_weld_cube() {
+        let cube = subdivided_cube(1);
+        assert_eq!(cube.vertex_count(), 36);
+
+        let welded = weld_vertices(&cube, &WeldOptions::default());
+        assert_eq!(welded.vertex_count(), 24);
+        assert_eq!(welded.triangle_count(), 12);
+
+        let flat = weld_vertices(
+            &cube,
+            &WeldOptions {
+                smoothing_angle: Some(60f32.to_radians()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(flat.vertex_count(), 24);
+
+        let smooth = weld_vertices(
+            &cube,
+            &WeldOptions {
+                smoothing_angle: Some(180f32.to_radians()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(smooth.vertex_count(), 8);
+
+        let normal = &smooth.vertex(0)[3..6];
+        let expected = 1.0 / 3f32.sqrt();
+        assert!(normal
+            .iter()
+            .all(|value| (value.abs() - expected).abs() < 1e-5));
+    }
+
+    #[test]
+    fn check_weld_subdivided_cube() {
+        let welded = weld_vertices(&subdivided_cube(4), &WeldOptions::default());
+        assert_eq!(welded.vertex_count(), 6 * 5 * 5);
+        assert_eq!(welded.triangle_count(), 6 * 4 * 4 * 2);
+    }
+
+    #[test]
+    fn check_simplify_subdivided_cube() {
+        let source = weld_vertices(&subdivided_cube(4), &WeldOptions::default());
+        let simplified = simplify(
+            &source,
+            &SimplifyOptions {
+                target_triangles: 12,
+                max_error: 0.01,
+            },
+        );
+        let mut result = MeshGeometry {
+            indices: simplified.indices,
+            ..source.clone()
+        };
+        result.remove_unused_vertices();
+
+        assert_eq!(result.triangle_count(), 12);
+        assert_eq!(result.vertex_count(), 24);
+        assert!(simplified.error < 1e-6);
+        assert!(hausdorff_distance(&source, &result) < 1e-4);
+    }
+
+    #[test]
+    fn check_simplify_error_cap_and_lods() {
+        // A curved grid: every collapse moves the surface, so a tiny error cap stops all of them.
+        let size = 8;
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        for i in 0..=size {
+            for j in 0..=size {
+                let (x, y) = (i as f32, j as f32);
+                vertices.extend_from_slice(&[x, y, 0.1 * (x * x + y * y)]);
+            }
+        }
+
+        for i in 0..size as u32 {
+            for j in 0..size as u32 {
+                let index = |i: u32, j: u32| i * (size as u32 + 1) + j;
+                indices.extend_from_slice(&[index(i, j), index(i + 1, j), index(i + 1, j + 1)]);
+                indices.extend_from_slice(&[index(i, j), index(i + 1, j + 1), index(i, j + 1)]);
+            }
+        }
+
+        let grid = MeshGeometry {
+            stride: 3,
+            vertices,
+            indices,
+            normal_offset: None,
+            tex_coord_offsets: vec![],
+        };
+
+        let capped = simplify(
+            &grid,
+            &SimplifyOptions {
+                target_triangles: 2,
+                max_error: 1e-4,
+            },
+        );
+        assert_eq!(capped.indices.len() / 3, grid.triangle_count());
+
+        let lods = generate_lods(&grid, &[1.0, 0.5, 0.25, 0.1], 0.05);
+        let counts = Vec::from_iter(lods.iter().map(|lod| lod.indices.len() / 3));
+        assert_eq!(counts[0], grid.triangle_count());
+        assert!(counts.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(counts[1] <= 64);
+        assert!(counts[3] <= 13);
+
+        // Boundaries stay in place, so the outline of the grid is kept.
+        let diagonal = grid.bounds_diagonal() as f64;
+
+        for lod in &lods {
+            let result = MeshGeometry {
+                indices: lod.indices.clone(),
+                ..grid.clone()
+            };
+            assert!(hausdorff_distance(&grid, &result) < 0.05 * diagonal);
+            assert!(result.bounds_diagonal() == grid.bounds_diagonal());
+        }
+    }
+}
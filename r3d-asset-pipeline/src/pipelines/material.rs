@@ -1,6 +1,6 @@
 use crate::{AssetPipeline, PipelineGfxBridge};
 use anyhow::{anyhow, Context};
-use asset::assets::MaterialSource;
+use asset::{assets::MaterialSource, migrations, AssetType};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
@@ -16,6 +16,11 @@ impl AssetPipeline for MaterialSource {
         _metadata: &Self::Metadata,
         _gfx_bridge: &dyn PipelineGfxBridge,
     ) -> anyhow::Result<Self> {
+        // Versioned materials are migrated to the current format; others are legacy bincode files.
+        if let Some(source) = migrations().load_file(AssetType::Material, &file_content) {
+            return Ok(source?);
+        }
+
         Self::deserialize(&file_content)
             .with_context(|| "failed to deserialize material")
             .map_err(|err| anyhow!(err))
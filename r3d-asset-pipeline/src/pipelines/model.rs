@@ -1,31 +1,110 @@
-use crate::{AssetPipeline, PipelineGfxBridge};
+use crate::{
+    generate_lods, simplify, weld_vertices, AssetPipeline, MeshGeometry, MeshProcessingReport,
+    PipelineGfxBridge, SimplifyOptions, WeldOptions,
+};
 use anyhow::{anyhow, Context};
 use asset::assets::{
-    MeshAABB, MeshSource, ModelSource, NodeSource, NodeTransform, VertexAttribute,
-    VertexAttributeKind, VertexIndexType,
+    AnimationRotationKey, AnimationVectorKey, MeshAABB, MeshLodSource, MeshSource, ModelAnimation,
+    ModelAnimationChannel, ModelBone, ModelSkeleton, ModelSource, NodeSource, NodeTransform,
+    VertexAttribute, VertexAttributeKind, VertexIndexType,
 };
 use byteorder::ByteOrder;
 use pmx::Pmx;
 use russimp::{
     mesh::PrimitiveType,
     scene::{PostProcess, Scene},
-    Color4D, Vector3D,
+    Color4D, Matrix4x4, Vector3D,
 };
 use serde::{Deserialize, Serialize};
-use std::{
-    collections::{hash_map::Entry, HashMap},
-    mem::size_of,
-    path::Path,
-};
-use zerocopy::AsBytes;
+use std::{collections::HashMap, mem::size_of, path::Path};
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct MeshMetadata {
     pub mesh: MeshTable,
 }
 
+/// Import time processing applied to every mesh of the model, in the order of the fields.
 #[derive(Default, Serialize, Deserialize)]
-pub struct MeshTable {}
+pub struct MeshTable {
+    pub weld: Option<WeldTable>,
+    pub simplify: Option<SimplifyTable>,
+    pub lod: Option<LodTable>,
+}
+
+/// Merges vertices that are equal within the epsilons, e.g. ones duplicated by per-face normals.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct WeldTable {
+    pub position_epsilon: f32,
+    pub normal_epsilon: f32,
+    pub tex_coord_epsilon: f32,
+    /// In degrees. If set, normals are recomputed, smoothing across edges sharper than this angle.
+    pub smoothing_angle: Option<f32>,
+}
+
+impl Default for WeldTable {
+    fn default() -> Self {
+        let options = WeldOptions::default();
+
+        Self {
+            position_epsilon: options.position_epsilon,
+            normal_epsilon: options.normal_epsilon,
+            tex_coord_epsilon: options.tex_coord_epsilon,
+            smoothing_angle: None,
+        }
+    }
+}
+
+impl From<&WeldTable> for WeldOptions {
+    fn from(value: &WeldTable) -> Self {
+        Self {
+            position_epsilon: value.position_epsilon,
+            normal_epsilon: value.normal_epsilon,
+            tex_coord_epsilon: value.tex_coord_epsilon,
+            smoothing_angle: value.smoothing_angle.map(f32::to_radians),
+        }
+    }
+}
+
+/// Reduces the triangle count of the meshes themselves.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimplifyTable {
+    /// Ratio of the triangles to keep. Ignored if `target_triangles` is set.
+    pub target_ratio: f32,
+    pub target_triangles: Option<u32>,
+    /// Largest error allowed, relative to the diagonal of the mesh bounds.
+    pub max_error: f32,
+}
+
+impl Default for SimplifyTable {
+    fn default() -> Self {
+        Self {
+            target_ratio: 0.5,
+            target_triangles: None,
+            max_error: 0.01,
+        }
+    }
+}
+
+/// Generates simplified levels of detail, which share the vertices of the mesh.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct LodTable {
+    /// Ratios of the triangles to keep per level, from the most detailed one.
+    pub ratios: Vec<f32>,
+    /// Largest error allowed, relative to the diagonal of the mesh bounds.
+    pub max_error: f32,
+}
+
+impl Default for LodTable {
+    fn default() -> Self {
+        Self {
+            ratios: vec![1.0, 0.5, 0.25, 0.1],
+            max_error: 0.05,
+        }
+    }
+}
 
 impl AssetPipeline for ModelSource {
     type Metadata = MeshMetadata;
@@ -33,25 +112,38 @@ impl AssetPipeline for ModelSource {
     fn process(
         file_path: &Path,
         file_content: Vec<u8>,
-        _metadata: &Self::Metadata,
+        metadata: &Self::Metadata,
         _gfx_bridge: &dyn PipelineGfxBridge,
     ) -> anyhow::Result<Self> {
-        if file_path
-            .extension()
-            .map_or(false, |ext| ext.to_ascii_lowercase() == "pmx")
-        {
-            process_pmx_model(&file_content)
-        } else {
-            process_assimp_model(&file_content)
-        }
+        process_model(file_path, &file_content, metadata).map(|(model, _)| model)
     }
 }
 
-fn process_pmx_model(content: &[u8]) -> anyhow::Result<ModelSource> {
+/// Processes a model file, also returning the vertex and triangle counts of each mesh before and after processing.
+pub fn process_model(
+    file_path: &Path,
+    file_content: &[u8],
+    metadata: &MeshMetadata,
+) -> anyhow::Result<(ModelSource, Vec<MeshProcessingReport>)> {
+    if file_path
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("pmx"))
+    {
+        process_pmx_model(file_content, &metadata.mesh)
+    } else {
+        process_assimp_model(file_content, &metadata.mesh)
+    }
+}
+
+fn process_pmx_model(
+    content: &[u8],
+    table: &MeshTable,
+) -> anyhow::Result<(ModelSource, Vec<MeshProcessingReport>)> {
     let pmx = Pmx::parse(content).with_context(|| "failed to load mesh from file")?;
 
     let mut material_offset = 0;
     let mut meshes = Vec::with_capacity(pmx.materials.len());
+    let mut reports = Vec::with_capacity(pmx.materials.len());
 
     for material_index in 0..pmx.materials.len() {
         let material = &pmx.materials[material_index];
@@ -125,87 +217,43 @@ fn process_pmx_model(content: &[u8]) -> anyhow::Result<ModelSource> {
             });
         }
 
-        let mut vertices = Vec::<u8>::with_capacity(
-            surfaces.len() * 3 * (8 + additional_vec4_count * 4) * size_of::<f32>(),
-        );
-        let mut indices = Vec::with_capacity(surfaces.len() * 3);
-        let mut index_map = HashMap::new();
-
-        for (surface_index, surface) in surfaces.iter().enumerate() {
-            for (surface_sub_index, vertex_index) in surface.vertex_indices.iter().enumerate() {
-                let index = match index_map.entry(surface_index * 3 + surface_sub_index) {
-                    Entry::Occupied(entry) => *entry.get(),
-                    Entry::Vacant(entry) => {
-                        let vertex = &pmx.vertices[vertex_index.get() as usize];
-
-                        vertices.extend_from_slice(
-                            &[vertex.position.x, vertex.position.y, vertex.position.z].as_bytes(),
-                        );
-                        vertices.extend_from_slice(
-                            &[vertex.normal.x, vertex.normal.y, vertex.normal.z].as_bytes(),
-                        );
-                        vertices.extend_from_slice(&[vertex.uv.x, vertex.uv.y].as_bytes());
-
-                        for index in 0..additional_vec4_count {
-                            let vec4 = &vertex.additional_vec4s[index];
-                            vertices
-                                .extend_from_slice(&[vec4.x, vec4.y, vec4.z, vec4.w].as_bytes());
-                        }
+        let mut vertices =
+            Vec::<f32>::with_capacity(surfaces.len() * 3 * (8 + additional_vec4_count * 4));
 
-                        let index = vertices.len() as u32;
-                        entry.insert(index);
-                        index
-                    }
-                };
+        for surface in surfaces {
+            for vertex_index in &surface.vertex_indices {
+                let vertex = &pmx.vertices[vertex_index.get() as usize];
 
-                indices.push(index);
-            }
-        }
-
-        // reduce vertex indices if possible
-        let (index_type, indices) = if index_map.is_empty() {
-            (VertexIndexType::U8, vec![])
-        } else {
-            let max = *index_map.values().max().unwrap();
-
-            if max <= u8::MAX as u32 {
-                let mut raw_indices = Vec::with_capacity(indices.len());
-
-                for index in indices {
-                    raw_indices.push(index as u8);
-                }
-
-                (VertexIndexType::U8, raw_indices)
-            } else if max <= u16::MAX as u32 {
-                let mut raw_indices = Vec::with_capacity(indices.len() * 2);
-
-                for index in indices {
-                    let index = index as u16;
-                    raw_indices.extend_from_slice(&index.to_le_bytes());
-                }
-
-                (VertexIndexType::U16, raw_indices)
-            } else {
-                let mut raw_indices = Vec::with_capacity(indices.len() * 4);
+                vertices.extend_from_slice(&[
+                    vertex.position.x,
+                    vertex.position.y,
+                    vertex.position.z,
+                ]);
+                vertices.extend_from_slice(&[vertex.normal.x, vertex.normal.y, vertex.normal.z]);
+                vertices.extend_from_slice(&[vertex.uv.x, vertex.uv.y]);
 
-                for index in indices {
-                    raw_indices.extend_from_slice(&index.to_le_bytes());
+                for index in 0..additional_vec4_count {
+                    let vec4 = &vertex.additional_vec4s[index];
+                    vertices.extend_from_slice(&[vec4.x, vec4.y, vec4.z, vec4.w]);
                 }
-
-                (VertexIndexType::U32, raw_indices)
             }
-        };
+        }
 
-        meshes.push(MeshSource {
-            index: material_index as u32,
+        let geometry = mesh_geometry(
+            8 + additional_vec4_count * 4,
+            vertices,
+            Vec::from_iter(0..surfaces.len() as u32 * 3),
+            &vertex_attributes,
+        );
+        let (mesh, report) = build_mesh_source(
+            material_index as u32,
             aabb,
-            index_type,
-            index_buffer: indices,
             vertex_attributes,
-            vertex_buffer: vertices,
-            vertex_count: surfaces.len() as u32 * 3,
-            material: None,
-        });
+            geometry,
+            table,
+        );
+        meshes.push(mesh);
+        reports.push(report);
     }
 
     // test: drop all bones and attach all meshes to root node
@@ -225,14 +273,22 @@ fn process_pmx_model(content: &[u8]) -> anyhow::Result<ModelSource> {
         mesh_indices: (0..meshes.len() as u32).collect(),
     }];
 
-    Ok(ModelSource {
-        root_node_index: Some(0),
-        nodes,
-        meshes,
-    })
+    Ok((
+        ModelSource {
+            root_node_index: Some(0),
+            nodes,
+            meshes,
+            skeleton: None,
+            animations: Vec::new(),
+        },
+        reports,
+    ))
 }
 
-fn process_assimp_model(content: &[u8]) -> anyhow::Result<ModelSource> {
+fn process_assimp_model(
+    content: &[u8],
+    table: &MeshTable,
+) -> anyhow::Result<(ModelSource, Vec<MeshProcessingReport>)> {
     let scene = Scene::from_buffer(
         &content,
         vec![
@@ -253,7 +309,11 @@ fn process_assimp_model(content: &[u8]) -> anyhow::Result<ModelSource> {
     )
     .with_context(|| "failed to load mesh from file")
     .map_err(|err| anyhow!(err))?;
-    let mut extractor = SceneExtractor::new();
+    let (skeleton, bone_indices) = match extract_skeleton(&scene) {
+        Some((skeleton, bone_indices)) => (Some(skeleton), bone_indices),
+        None => (None, HashMap::new()),
+    };
+    let mut extractor = SceneExtractor::new(table, &bone_indices);
 
     let root_node_index = scene
         .root
@@ -262,22 +322,35 @@ fn process_assimp_model(content: &[u8]) -> anyhow::Result<ModelSource> {
     let nodes = extractor.nodes;
     let meshes = extractor.meshes;
 
-    Ok(ModelSource {
-        root_node_index,
-        nodes,
-        meshes,
-    })
+    Ok((
+        ModelSource {
+            root_node_index,
+            nodes,
+            meshes,
+            skeleton,
+            animations: extract_animations(&scene),
+        },
+        extractor.reports,
+    ))
 }
 
-#[derive(Default)]
-struct SceneExtractor {
+struct SceneExtractor<'a> {
+    pub table: &'a MeshTable,
+    pub bone_indices: &'a HashMap<String, u32>,
     pub nodes: Vec<NodeSource>,
     pub meshes: Vec<MeshSource>,
+    pub reports: Vec<MeshProcessingReport>,
 }
 
-impl SceneExtractor {
-    pub fn new() -> Self {
-        Default::default()
+impl<'a> SceneExtractor<'a> {
+    pub fn new(table: &'a MeshTable, bone_indices: &'a HashMap<String, u32>) -> Self {
+        Self {
+            table,
+            bone_indices,
+            nodes: vec![],
+            meshes: vec![],
+            reports: vec![],
+        }
     }
 
     pub fn extract_node(
@@ -293,24 +366,7 @@ impl SceneExtractor {
             children_indices: vec![],
             name: node.name.clone(),
             transform: NodeTransform {
-                matrix: [
-                    node.transformation.a1,
-                    node.transformation.b1,
-                    node.transformation.c1,
-                    node.transformation.d1,
-                    node.transformation.a2,
-                    node.transformation.b2,
-                    node.transformation.c2,
-                    node.transformation.d2,
-                    node.transformation.a3,
-                    node.transformation.b3,
-                    node.transformation.c3,
-                    node.transformation.d3,
-                    node.transformation.a4,
-                    node.transformation.b4,
-                    node.transformation.c4,
-                    node.transformation.d4,
-                ],
+                matrix: convert_matrix(&node.transformation),
             },
             mesh_indices: vec![],
         });
@@ -338,13 +394,204 @@ impl SceneExtractor {
 
     fn extract_mesh(&mut self, mesh: &russimp::mesh::Mesh) -> u32 {
         let index = self.meshes.len() as u32;
-        let mesh = convert_mesh(index, mesh);
+        let (mesh, report) = convert_mesh(index, mesh, self.table, self.bone_indices);
         self.meshes.push(mesh);
+        self.reports.push(report);
         index
     }
 }
 
-fn convert_mesh(index: u32, mesh: &russimp::mesh::Mesh) -> MeshSource {
+/// Converts a matrix of assimp, whose translation is in the last column, into the layout of the node transforms.
+fn convert_matrix(matrix: &Matrix4x4) -> [f32; 16] {
+    [
+        matrix.a1, matrix.b1, matrix.c1, matrix.d1, //
+        matrix.a2, matrix.b2, matrix.c2, matrix.d2, //
+        matrix.a3, matrix.b3, matrix.c3, matrix.d3, //
+        matrix.a4, matrix.b4, matrix.c4, matrix.d4, //
+    ]
+}
+
+/// Gathers the bones of all the meshes into one skeleton, ordered as their nodes so that parents come first, along
+/// with the indices of the bones by name. Returns `None` if no mesh has bones.
+fn extract_skeleton(scene: &Scene) -> Option<(ModelSkeleton, HashMap<String, u32>)> {
+    let mut inverse_bind_matrices = HashMap::new();
+
+    for mesh in &scene.meshes {
+        for bone in &mesh.bones {
+            inverse_bind_matrices
+                .entry(bone.name.clone())
+                .or_insert_with(|| convert_matrix(&bone.offset_matrix));
+        }
+    }
+
+    if inverse_bind_matrices.is_empty() {
+        return None;
+    }
+
+    let mut skeleton = ModelSkeleton::default();
+    let mut bone_indices = HashMap::new();
+
+    if let Some(root) = &scene.root {
+        collect_bones(
+            root,
+            &mut 0,
+            None,
+            &mut inverse_bind_matrices,
+            &mut skeleton,
+            &mut bone_indices,
+        );
+    }
+
+    // Bones without a node of their name, as in malformed scenes, stay in their bind pose.
+    let mut orphans = Vec::from_iter(inverse_bind_matrices);
+    orphans.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    for (name, inverse_bind_matrix) in orphans {
+        bone_indices.insert(name.clone(), skeleton.bones.len() as u32);
+        skeleton.bones.push(ModelBone {
+            name,
+            parent_index: None,
+            node_index: None,
+            inverse_bind_matrix,
+        });
+    }
+
+    Some((skeleton, bone_indices))
+}
+
+/// Visits the nodes in the order [`SceneExtractor::extract_node`] indexes them, adding the ones that are bones.
+fn collect_bones(
+    node: &russimp::node::Node,
+    node_index: &mut u32,
+    parent_bone: Option<u32>,
+    inverse_bind_matrices: &mut HashMap<String, [f32; 16]>,
+    skeleton: &mut ModelSkeleton,
+    bone_indices: &mut HashMap<String, u32>,
+) {
+    let index = *node_index;
+    *node_index += 1;
+
+    let parent_bone = match inverse_bind_matrices.remove(&node.name) {
+        Some(inverse_bind_matrix) => {
+            let bone_index = skeleton.bones.len() as u32;
+            bone_indices.insert(node.name.clone(), bone_index);
+            skeleton.bones.push(ModelBone {
+                name: node.name.clone(),
+                parent_index: parent_bone,
+                node_index: Some(index),
+                inverse_bind_matrix,
+            });
+            Some(bone_index)
+        }
+        None => parent_bone,
+    };
+
+    for child in node.children.borrow().iter() {
+        collect_bones(
+            child,
+            node_index,
+            parent_bone,
+            inverse_bind_matrices,
+            skeleton,
+            bone_indices,
+        );
+    }
+}
+
+fn extract_animations(scene: &Scene) -> Vec<ModelAnimation> {
+    Vec::from_iter(
+        scene
+            .animations
+            .iter()
+            .enumerate()
+            .map(|(index, animation)| {
+                // Assimp leaves the rate unset for formats without one, and plays them at 25 ticks per second.
+                let ticks_per_second = if 0.0 < animation.ticks_per_second {
+                    animation.ticks_per_second
+                } else {
+                    25.0
+                };
+                let seconds = |ticks: f64| (ticks / ticks_per_second) as f32;
+                let vector_keys = |keys: &[russimp::animation::VectorKey]| {
+                    Vec::from_iter(keys.iter().map(|key| AnimationVectorKey {
+                        time: seconds(key.time),
+                        value: [key.value.x, key.value.y, key.value.z],
+                    }))
+                };
+
+                ModelAnimation {
+                    name: if animation.name.is_empty() {
+                        index.to_string()
+                    } else {
+                        animation.name.clone()
+                    },
+                    duration: seconds(animation.duration),
+                    channels: Vec::from_iter(animation.channels.iter().map(|channel| {
+                        ModelAnimationChannel {
+                            node_name: channel.name.clone(),
+                            translations: vector_keys(&channel.position_keys),
+                            rotations: Vec::from_iter(channel.rotation_keys.iter().map(|key| {
+                                AnimationRotationKey {
+                                    time: seconds(key.time),
+                                    value: [key.value.x, key.value.y, key.value.z, key.value.w],
+                                }
+                            })),
+                            scales: vector_keys(&channel.scaling_keys),
+                        }
+                    })),
+                }
+            }),
+    )
+}
+
+/// Up to the four heaviest bones of each vertex, as their indices and their weights normalized to sum to 1.
+/// Vertices no bone moves get zero weights.
+fn bone_influences(
+    mesh: &russimp::mesh::Mesh,
+    bone_indices: &HashMap<String, u32>,
+) -> (Vec<[f32; 4]>, Vec<[f32; 4]>) {
+    let mut influences = vec![Vec::<(u32, f32)>::new(); mesh.vertices.len()];
+
+    for bone in &mesh.bones {
+        let bone_index = match bone_indices.get(&bone.name) {
+            Some(&bone_index) => bone_index,
+            None => continue,
+        };
+
+        for weight in &bone.weights {
+            match influences.get_mut(weight.vertex_id as usize) {
+                Some(influences) if 0.0 < weight.weight => {
+                    influences.push((bone_index, weight.weight))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut indices = vec![[0f32; 4]; influences.len()];
+    let mut weights = vec![[0f32; 4]; influences.len()];
+
+    for (vertex, mut influences) in influences.into_iter().enumerate() {
+        influences.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        influences.truncate(4);
+
+        let total = influences.iter().map(|(_, weight)| weight).sum::<f32>();
+
+        for (slot, (bone_index, weight)) in influences.into_iter().enumerate() {
+            indices[vertex][slot] = bone_index as f32;
+            weights[vertex][slot] = weight / total;
+        }
+    }
+
+    (indices, weights)
+}
+
+fn convert_mesh(
+    index: u32,
+    mesh: &russimp::mesh::Mesh,
+    table: &MeshTable,
+    bone_indices: &HashMap<String, u32>,
+) -> (MeshSource, MeshProcessingReport) {
     let mut vertex_attributes = Vec::with_capacity(8);
     let mut offset = 0;
 
@@ -393,22 +640,29 @@ fn convert_mesh(index: u32, mesh: &russimp::mesh::Mesh) -> MeshSource {
         }
     }
 
-    // Tangent
+    // Tangent, with the handedness of the bitangent in w
     if !mesh.tangents.is_empty() {
         vertex_attributes.push(VertexAttribute {
             offset,
             kind: VertexAttributeKind::Tangent,
         });
-        offset += size_of::<[f32; 3]>() as u32;
+        offset += size_of::<[f32; 4]>() as u32;
     }
 
-    // Bitangent
-    if !mesh.bitangents.is_empty() {
+    // Bones
+    let (influence_indices, influence_weights) = bone_influences(mesh, bone_indices);
+
+    if !mesh.bones.is_empty() {
+        vertex_attributes.push(VertexAttribute {
+            offset,
+            kind: VertexAttributeKind::BoneIndices,
+        });
+        offset += size_of::<[f32; 4]>() as u32;
         vertex_attributes.push(VertexAttribute {
             offset,
-            kind: VertexAttributeKind::Bitangent,
+            kind: VertexAttributeKind::BoneWeights,
         });
-        offset += size_of::<[f32; 3]>() as u32;
+        offset += size_of::<[f32; 4]>() as u32;
     }
 
     let stride = (offset / size_of::<f32>() as u32) as usize;
@@ -424,8 +678,13 @@ fn convert_mesh(index: u32, mesh: &russimp::mesh::Mesh) -> MeshSource {
             VertexAttributeKind::TexCoord { index } => VertexDataCopySource::Vector2D(
                 mesh.texture_coords[index as usize].as_ref().unwrap(),
             ),
-            VertexAttributeKind::Tangent => VertexDataCopySource::Vector3D(&mesh.tangents),
-            VertexAttributeKind::Bitangent => VertexDataCopySource::Vector3D(&mesh.bitangents),
+            VertexAttributeKind::Tangent => VertexDataCopySource::Tangent4D {
+                tangents: &mesh.tangents,
+                bitangents: &mesh.bitangents,
+                normals: &mesh.normals,
+            },
+            VertexAttributeKind::BoneIndices => VertexDataCopySource::Vector4D(&influence_indices),
+            VertexAttributeKind::BoneWeights => VertexDataCopySource::Vector4D(&influence_weights),
             _ => unreachable!(),
         };
 
@@ -437,66 +696,158 @@ fn convert_mesh(index: u32, mesh: &russimp::mesh::Mesh) -> MeshSource {
         }
     }
 
-    let mut raw_vertex_buffer = vec![0u8; vertex_buffer.len() * size_of::<f32>()];
-    byteorder::LE::write_f32_into(&vertex_buffer, &mut raw_vertex_buffer);
-    drop(vertex_buffer);
-
-    let vertex_count = mesh.vertices.len();
-    let (index_type, raw_index_buffer) = if vertex_count < u8::MAX as usize {
-        let mut index_buffer = Vec::with_capacity(mesh.faces.len() * 3);
+    let mut indices = Vec::with_capacity(mesh.faces.len() * 3);
 
-        for face in &mesh.faces {
-            debug_assert_eq!(face.0.len(), 3);
-            index_buffer.push(face.0[0] as u8);
-            index_buffer.push(face.0[1] as u8);
-            index_buffer.push(face.0[2] as u8);
-        }
+    for face in &mesh.faces {
+        debug_assert_eq!(face.0.len(), 3);
+        indices.extend_from_slice(&face.0[..3]);
+    }
 
-        (VertexIndexType::U8, index_buffer)
-    } else if vertex_count < u16::MAX as usize {
-        let mut index_buffer = Vec::with_capacity(mesh.faces.len() * 3);
+    let aabb = MeshAABB {
+        min: [mesh.aabb.min.x, mesh.aabb.min.y, mesh.aabb.min.z],
+        max: [mesh.aabb.max.x, mesh.aabb.max.y, mesh.aabb.max.z],
+    };
+    let geometry = mesh_geometry(stride, vertex_buffer, indices, &vertex_attributes);
 
-        for face in &mesh.faces {
-            debug_assert_eq!(face.0.len(), 3);
-            index_buffer.push(face.0[0] as u16);
-            index_buffer.push(face.0[1] as u16);
-            index_buffer.push(face.0[2] as u16);
-        }
+    build_mesh_source(index, aabb, vertex_attributes, geometry, table)
+}
 
-        let mut raw_index_buffer = vec![0u8; index_buffer.len() * size_of::<u16>()];
-        byteorder::LE::write_u16_into(&index_buffer, &mut raw_index_buffer);
+fn mesh_geometry(
+    stride: usize,
+    vertices: Vec<f32>,
+    indices: Vec<u32>,
+    vertex_attributes: &[VertexAttribute],
+) -> MeshGeometry {
+    let offset = |attribute: &VertexAttribute| attribute.offset as usize / size_of::<f32>();
+
+    MeshGeometry {
+        stride,
+        vertices,
+        indices,
+        normal_offset: vertex_attributes
+            .iter()
+            .find(|attribute| attribute.kind == VertexAttributeKind::Normal)
+            .map(offset),
+        tex_coord_offsets: Vec::from_iter(
+            vertex_attributes
+                .iter()
+                .filter(|attribute| matches!(attribute.kind, VertexAttributeKind::TexCoord { .. }))
+                .map(offset),
+        ),
+    }
+}
 
-        (VertexIndexType::U16, raw_index_buffer)
-    } else {
-        let mut index_buffer = Vec::with_capacity(mesh.faces.len() * 3);
+/// Applies the processing of the table to the geometry and encodes the result.
+fn build_mesh_source(
+    index: u32,
+    aabb: MeshAABB,
+    vertex_attributes: Vec<VertexAttribute>,
+    geometry: MeshGeometry,
+    table: &MeshTable,
+) -> (MeshSource, MeshProcessingReport) {
+    let vertices_before = geometry.vertex_count();
+    let triangles_before = geometry.triangle_count();
+
+    let mut geometry = match &table.weld {
+        Some(weld) => weld_vertices(&geometry, &weld.into()),
+        None => geometry,
+    };
 
-        for face in &mesh.faces {
-            debug_assert_eq!(face.0.len(), 3);
-            index_buffer.push(face.0[0] as u32);
-            index_buffer.push(face.0[1] as u32);
-            index_buffer.push(face.0[2] as u32);
-        }
+    if let Some(simplify_table) = &table.simplify {
+        let target_triangles = match simplify_table.target_triangles {
+            Some(target_triangles) => target_triangles as usize,
+            None => {
+                (geometry.triangle_count() as f32 * simplify_table.target_ratio.clamp(0.0, 1.0))
+                    .ceil() as usize
+            }
+        };
+        geometry.indices = simplify(
+            &geometry,
+            &SimplifyOptions {
+                target_triangles,
+                max_error: simplify_table.max_error,
+            },
+        )
+        .indices;
+        geometry.remove_unused_vertices();
+    }
 
-        let mut raw_index_buffer = vec![0u8; index_buffer.len() * size_of::<u32>()];
-        byteorder::LE::write_u32_into(&index_buffer, &mut raw_index_buffer);
+    let vertex_count = geometry.vertex_count();
+    // GPUs cannot draw with 8-bit indices.
+    let index_type = if vertex_count <= u16::MAX as usize {
+        VertexIndexType::U16
+    } else {
+        VertexIndexType::U32
+    };
 
-        (VertexIndexType::U32, raw_index_buffer)
+    let lods = match &table.lod {
+        Some(lod) => Vec::from_iter(
+            lod.ratios
+                .iter()
+                .zip(generate_lods(&geometry, &lod.ratios, lod.max_error))
+                .map(|(&ratio, simplified)| MeshLodSource {
+                    ratio,
+                    error: simplified.error,
+                    index_buffer: encode_indices(index_type, &simplified.indices),
+                }),
+        ),
+        None => vec![],
     };
 
-    let aabb = MeshAABB {
-        min: [mesh.aabb.min.x, mesh.aabb.min.y, mesh.aabb.min.z],
-        max: [mesh.aabb.max.x, mesh.aabb.max.y, mesh.aabb.max.z],
+    let report = MeshProcessingReport {
+        mesh_index: index,
+        vertices_before,
+        vertices_after: vertex_count,
+        triangles_before,
+        triangles_after: geometry.triangle_count(),
+        lod_triangles: Vec::from_iter(
+            lods.iter()
+                .map(|lod| lod.index_buffer.len() / index_size(index_type) / 3),
+        ),
     };
 
-    MeshSource {
+    let mut vertex_buffer = vec![0u8; geometry.vertices.len() * size_of::<f32>()];
+    byteorder::LE::write_f32_into(&geometry.vertices, &mut vertex_buffer);
+
+    let mut mesh = MeshSource {
         index,
         aabb,
         index_type,
-        index_buffer: raw_index_buffer,
+        index_buffer: encode_indices(index_type, &geometry.indices),
         vertex_attributes,
-        vertex_buffer: raw_vertex_buffer,
+        vertex_buffer,
         vertex_count: vertex_count as u32,
         material: None,
+        lods,
+    };
+    // After welding, so that the tangents are averaged over the vertices that were merged.
+    mesh.generate_tangents();
+
+    (mesh, report)
+}
+
+fn index_size(index_type: VertexIndexType) -> usize {
+    match index_type {
+        VertexIndexType::U8 => size_of::<u8>(),
+        VertexIndexType::U16 => size_of::<u16>(),
+        VertexIndexType::U32 => size_of::<u32>(),
+    }
+}
+
+fn encode_indices(index_type: VertexIndexType, indices: &[u32]) -> Vec<u8> {
+    match index_type {
+        VertexIndexType::U8 => Vec::from_iter(indices.iter().map(|&index| index as u8)),
+        VertexIndexType::U16 => {
+            let indices = Vec::from_iter(indices.iter().map(|&index| index as u16));
+            let mut raw_indices = vec![0u8; indices.len() * size_of::<u16>()];
+            byteorder::LE::write_u16_into(&indices, &mut raw_indices);
+            raw_indices
+        }
+        VertexIndexType::U32 => {
+            let mut raw_indices = vec![0u8; indices.len() * size_of::<u32>()];
+            byteorder::LE::write_u32_into(indices, &mut raw_indices);
+            raw_indices
+        }
     }
 }
 
@@ -505,6 +856,13 @@ enum VertexDataCopySource<'a> {
     Vector2D(&'a [Vector3D]),
     Vector3D(&'a [Vector3D]),
     Color4D(&'a [Color4D]),
+    Vector4D(&'a [[f32; 4]]),
+    /// Tangent in xyz, and in w whether the bitangent points along `cross(normal, tangent)` (1) or against it (-1).
+    Tangent4D {
+        tangents: &'a [Vector3D],
+        bitangents: &'a [Vector3D],
+        normals: &'a [Vector3D],
+    },
 }
 
 impl<'a> VertexDataCopySource<'a> {
@@ -528,6 +886,37 @@ impl<'a> VertexDataCopySource<'a> {
                 dst[2] = src.b;
                 dst[3] = src.a;
             }
+            &VertexDataCopySource::Vector4D(src) => {
+                dst[..4].copy_from_slice(&src[index]);
+            }
+            &VertexDataCopySource::Tangent4D {
+                tangents,
+                bitangents,
+                normals,
+            } => {
+                let tangent = tangents[index];
+                dst[0] = tangent.x;
+                dst[1] = tangent.y;
+                dst[2] = tangent.z;
+                dst[3] = match (bitangents.get(index), normals.get(index)) {
+                    (Some(bitangent), Some(normal)) => {
+                        let cross = Vector3D {
+                            x: normal.y * tangent.z - normal.z * tangent.y,
+                            y: normal.z * tangent.x - normal.x * tangent.z,
+                            z: normal.x * tangent.y - normal.y * tangent.x,
+                        };
+
+                        if cross.x * bitangent.x + cross.y * bitangent.y + cross.z * bitangent.z
+                            < 0f32
+                        {
+                            -1f32
+                        } else {
+                            1f32
+                        }
+                    }
+                    _ => 1f32,
+                };
+            }
         }
     }
 }
@@ -1,7 +1,10 @@
-use crate::{AssetPipeline, PipelineGfxBridge};
-use asset::assets::{
-    NinePatchSource, NinePatchTexelRange, SpriteSource, SpriteTexelRange, TextureAddressMode,
-    TextureFilterMode, TextureFormat, TextureSource,
+use crate::{AssetPipeline, PipelineGfxBridge, TextureContainer};
+use asset::{
+    assets::{
+        NinePatchSource, NinePatchTexelRange, SpriteSource, SpriteTexelRange, TextureAddressMode,
+        TextureFilterMode, TextureFormat, TextureSource,
+    },
+    migrations, AssetType,
 };
 use image::io::Reader as ImageReader;
 use serde::{Deserialize, Serialize};
@@ -106,30 +109,48 @@ impl AssetPipeline for TextureSource {
         metadata: &Self::Metadata,
         _gfx_bridge: &dyn PipelineGfxBridge,
     ) -> anyhow::Result<Self> {
-        let image = ImageReader::new(Cursor::new(file_content))
-            .with_guessed_format()?
-            .decode()?;
-        let width = image.width() as u16;
-        let height = image.height() as u16;
-        let texels = {
-            let mut image = {
-                let rgba = image.to_rgba8();
-                drop(image);
-                rgba
-            };
-
-            if metadata.texture.is_srgb {
-                for pixel in image.pixels_mut() {
-                    let (r, g, b) = srgb_to_linear(pixel[0], pixel[1], pixel[2]);
-                    pixel[0] = r;
-                    pixel[1] = g;
-                    pixel[2] = b;
+        // Versioned textures are already processed; they are only migrated to the current format.
+        if let Some(source) = migrations().load_file(AssetType::Texture, &file_content) {
+            return Ok(source?);
+        }
+
+        // Containers hold texels in the format they are sampled in, so `is_srgb` applies to plain images only.
+        let container = match TextureContainer::parse(&file_content) {
+            Some(container) => container?,
+            None => {
+                let image = ImageReader::new(Cursor::new(file_content))
+                    .with_guessed_format()?
+                    .decode()?;
+                let width = image.width() as u16;
+                let height = image.height() as u16;
+                let texels = {
+                    let mut image = {
+                        let rgba = image.to_rgba8();
+                        drop(image);
+                        rgba
+                    };
+
+                    if metadata.texture.is_srgb {
+                        for pixel in image.pixels_mut() {
+                            let (r, g, b) = srgb_to_linear(pixel[0], pixel[1], pixel[2]);
+                            pixel[0] = r;
+                            pixel[1] = g;
+                            pixel[2] = b;
+                        }
+                    }
+
+                    image.into_raw()
+                };
+                TextureContainer {
+                    width,
+                    height,
+                    format: TextureFormat::RGBA8,
+                    levels: vec![texels],
+                    array_layers: 1,
+                    is_cube_map: false,
                 }
             }
-
-            image.into_raw()
         };
-        let format = TextureFormat::RGBA8;
         let filter_mode = metadata.texture.filter_mode.into();
         let address_mode = (
             metadata.texture.address_mode_u.into(),
@@ -199,16 +220,22 @@ impl AssetPipeline for TextureSource {
             }
         }));
 
-        Ok(Self {
-            width,
-            height,
-            format,
+        let mut levels = container.levels.into_iter();
+        let source = Self {
+            width: container.width,
+            height: container.height,
+            format: container.format,
             filter_mode,
             address_mode,
-            texels,
+            texels: levels.next().unwrap_or_default(),
+            mip_levels: levels.collect(),
+            array_layers: container.array_layers,
+            is_cube_map: container.is_cube_map,
             sprites,
             nine_patches,
-        })
+        };
+        source.validate()?;
+        Ok(source)
     }
 }
 
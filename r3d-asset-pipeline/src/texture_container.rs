@@ -0,0 +1,612 @@
+use asset::assets::{mip_level_size, TextureFormat};
+use thiserror::Error;
+
+const DDS_MAGIC: &[u8] = b"DDS ";
+const KTX2_IDENTIFIER: &[u8] = &[
+    0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+];
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TextureContainerError {
+    #[error("{container} file is truncated: needs {expected} bytes, but has {actual}")]
+    Truncated {
+        container: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("{container} file is truncated in mip level {level}: needs {expected} bytes, but has {actual}")]
+    TruncatedMipLevel {
+        container: &'static str,
+        level: u32,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("{container} header is corrupt: {reason}")]
+    CorruptHeader {
+        container: &'static str,
+        reason: String,
+    },
+    #[error("{container} texel format {format} is not supported")]
+    UnsupportedFormat {
+        container: &'static str,
+        format: String,
+    },
+    #[error("{container} texture is not supported: {reason}")]
+    UnsupportedLayout {
+        container: &'static str,
+        reason: String,
+    },
+    #[error("{container} texture of {width}x{height} texels is too large")]
+    TooLarge {
+        container: &'static str,
+        width: u32,
+        height: u32,
+    },
+}
+
+/// Texels of a 2D texture read from a DDS or KTX2 container, as they are stored in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextureContainer {
+    pub width: u16,
+    pub height: u16,
+    pub format: TextureFormat,
+    /// Texels of the mip levels from the base one, each of all the array layers one after another.
+    pub levels: Vec<Vec<u8>>,
+    /// Number of array layers, 6 per cube map.
+    pub array_layers: u32,
+    /// Whether each 6 layers are the faces of a cube map.
+    pub is_cube_map: bool,
+}
+
+/// Size of the texture, mip level count and array layer count read from a header.
+struct Layout {
+    width: u16,
+    height: u16,
+    format: TextureFormat,
+    array_layers: u32,
+}
+
+impl Layout {
+    fn new(
+        container: &'static str,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        level_count: u32,
+        array_layers: u32,
+    ) -> Result<Self, TextureContainerError> {
+        if width == 0 || height == 0 {
+            return Err(corrupt_header(container, "texture has no texels"));
+        }
+
+        let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => {
+                return Err(TextureContainerError::TooLarge {
+                    container,
+                    width,
+                    height,
+                })
+            }
+        };
+
+        let max_level_count = u16::BITS - width.max(height).leading_zeros();
+
+        if max_level_count < level_count {
+            return Err(corrupt_header(
+                container,
+                format!(
+                    "{} mip levels, but a {}x{} texture has at most {}",
+                    level_count, width, height, max_level_count
+                ),
+            ));
+        }
+
+        if array_layers == 0 {
+            return Err(corrupt_header(container, "texture has no array layers"));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            format,
+            array_layers,
+        })
+    }
+
+    /// Size of an image of the mip level, i.e. of a single layer.
+    fn image_size(&self, level: u32) -> usize {
+        let (width, height) = mip_level_size(self.width as u32, self.height as u32, level);
+        self.format.texel_data_size(width, height)
+    }
+
+    /// Size of the mip level of all the layers, or `None` if it overflows.
+    fn level_size(&self, level: u32) -> Option<usize> {
+        self.image_size(level)
+            .checked_mul(self.array_layers as usize)
+    }
+}
+
+impl TextureContainer {
+    /// Reads the file if it is a DDS or KTX2 container, telling them apart by their magic bytes.
+    pub fn parse(content: &[u8]) -> Option<Result<Self, TextureContainerError>> {
+        if content.starts_with(DDS_MAGIC) {
+            Some(Self::parse_dds(content))
+        } else if content.starts_with(KTX2_IDENTIFIER) {
+            Some(Self::parse_ktx2(content))
+        } else {
+            None
+        }
+    }
+
+    fn parse_dds(content: &[u8]) -> Result<Self, TextureContainerError> {
+        const CONTAINER: &str = "DDS";
+        const MIPMAPCOUNT: u32 = 0x20000;
+        const FOURCC: u32 = 0x4;
+        const RGB: u32 = 0x40;
+        const CUBEMAP: u32 = 0x200;
+        const CUBEMAP_ALL_FACES: u32 = 0xfc00;
+        const VOLUME: u32 = 0x200000;
+        const DX10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+        const DX10_MISC_TEXTURECUBE: u32 = 0x4;
+
+        let reader = Reader::new(CONTAINER, content);
+
+        if reader.u32(4)? != 124 {
+            return Err(corrupt_header(CONTAINER, "header size is not 124 bytes"));
+        }
+
+        let flags = reader.u32(8)?;
+        let height = reader.u32(12)?;
+        let width = reader.u32(16)?;
+        let level_count = if flags & MIPMAPCOUNT != 0 {
+            reader.u32(28)?.max(1)
+        } else {
+            1
+        };
+        let pixel_format_flags = reader.u32(80)?;
+        let fourcc = reader.bytes(84, 4)?;
+        let caps2 = reader.u32(112)?;
+
+        let mut is_cube_map = false;
+        let mut array_layers = 1;
+
+        let (format, swizzle_bgra, data_offset) = if pixel_format_flags & FOURCC != 0 {
+            match fourcc {
+                b"DXT1" => (TextureFormat::BC1, false, 128),
+                b"DXT5" => (TextureFormat::BC3, false, 128),
+                b"ATI2" | b"BC5U" => (TextureFormat::BC5, false, 128),
+                b"DX10" => {
+                    let format = match reader.u32(128)? {
+                        28 => TextureFormat::RGBA8,
+                        29 => TextureFormat::RGBA8Srgb,
+                        71 => TextureFormat::BC1,
+                        72 => TextureFormat::BC1Srgb,
+                        77 => TextureFormat::BC3,
+                        78 => TextureFormat::BC3Srgb,
+                        83 => TextureFormat::BC5,
+                        98 => TextureFormat::BC7,
+                        99 => TextureFormat::BC7Srgb,
+                        format => {
+                            return Err(TextureContainerError::UnsupportedFormat {
+                                container: CONTAINER,
+                                format: format!("DXGI format {}", format),
+                            })
+                        }
+                    };
+
+                    if reader.u32(132)? != DX10_RESOURCE_DIMENSION_TEXTURE2D {
+                        return Err(unsupported_layout(
+                            CONTAINER,
+                            "only 2D textures are supported",
+                        ));
+                    }
+
+                    is_cube_map = reader.u32(136)? & DX10_MISC_TEXTURECUBE != 0;
+                    // The array size counts cubes rather than faces.
+                    array_layers = reader.u32(140)?.max(1);
+
+                    if is_cube_map {
+                        array_layers = array_layers.checked_mul(6).ok_or_else(|| {
+                            corrupt_header(CONTAINER, "array size is out of range")
+                        })?;
+                    }
+
+                    (format, false, 148)
+                }
+                fourcc => {
+                    return Err(TextureContainerError::UnsupportedFormat {
+                        container: CONTAINER,
+                        format: format!("FourCC {:?}", String::from_utf8_lossy(fourcc)),
+                    })
+                }
+            }
+        } else if pixel_format_flags & RGB != 0 && reader.u32(88)? == 32 {
+            match (reader.u32(92)?, reader.u32(100)?) {
+                (0x0000_00ff, 0x00ff_0000) => (TextureFormat::RGBA8, false, 128),
+                (0x00ff_0000, 0x0000_00ff) => (TextureFormat::RGBA8, true, 128),
+                _ => {
+                    return Err(TextureContainerError::UnsupportedFormat {
+                        container: CONTAINER,
+                        format: "32-bit RGB with unusual channel masks".to_owned(),
+                    })
+                }
+            }
+        } else {
+            return Err(TextureContainerError::UnsupportedFormat {
+                container: CONTAINER,
+                format: "non-32-bit uncompressed".to_owned(),
+            });
+        };
+
+        if caps2 & VOLUME != 0 {
+            return Err(unsupported_layout(
+                CONTAINER,
+                "volume textures are not supported",
+            ));
+        }
+
+        if fourcc != b"DX10" && caps2 & CUBEMAP != 0 {
+            if caps2 & CUBEMAP_ALL_FACES != CUBEMAP_ALL_FACES {
+                return Err(unsupported_layout(
+                    CONTAINER,
+                    "cube maps must have all 6 faces",
+                ));
+            }
+
+            is_cube_map = true;
+            array_layers = 6;
+        }
+
+        let layout = Layout::new(CONTAINER, format, width, height, level_count, array_layers)?;
+        let mut levels = Vec::from_iter((0..level_count).map(|level| {
+            Vec::with_capacity(
+                layout
+                    .level_size(level)
+                    .unwrap_or_default()
+                    .min(content.len()),
+            )
+        }));
+
+        // DDS stores the mip chain of each layer in turn; the levels are gathered across the layers.
+        let mut offset: usize = data_offset;
+
+        for _ in 0..array_layers {
+            for (level, texels) in levels.iter_mut().enumerate() {
+                let size = layout.image_size(level as u32);
+                let image = offset
+                    .checked_add(size)
+                    .and_then(|end| content.get(offset..end))
+                    .ok_or(TextureContainerError::TruncatedMipLevel {
+                        container: CONTAINER,
+                        level: level as u32,
+                        expected: offset.saturating_add(size),
+                        actual: content.len(),
+                    })?;
+                texels.extend_from_slice(image);
+                offset += size;
+            }
+        }
+
+        if swizzle_bgra {
+            for texel in levels
+                .iter_mut()
+                .flat_map(|texels| texels.chunks_exact_mut(4))
+            {
+                texel.swap(0, 2);
+            }
+        }
+
+        Ok(Self {
+            width: layout.width,
+            height: layout.height,
+            format,
+            levels,
+            array_layers,
+            is_cube_map,
+        })
+    }
+
+    fn parse_ktx2(content: &[u8]) -> Result<Self, TextureContainerError> {
+        const CONTAINER: &str = "KTX2";
+        const LEVEL_INDEX_OFFSET: usize = 80;
+        const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+        let reader = Reader::new(CONTAINER, content);
+        let format = match reader.u32(12)? {
+            37 => TextureFormat::RGBA8,
+            43 => TextureFormat::RGBA8Srgb,
+            131 | 133 => TextureFormat::BC1,
+            132 | 134 => TextureFormat::BC1Srgb,
+            137 => TextureFormat::BC3,
+            138 => TextureFormat::BC3Srgb,
+            141 => TextureFormat::BC5,
+            145 => TextureFormat::BC7,
+            146 => TextureFormat::BC7Srgb,
+            0 => {
+                return Err(TextureContainerError::UnsupportedFormat {
+                    container: CONTAINER,
+                    format: "undefined (Basis Universal)".to_owned(),
+                })
+            }
+            format => {
+                return Err(TextureContainerError::UnsupportedFormat {
+                    container: CONTAINER,
+                    format: format!("Vulkan format {}", format),
+                })
+            }
+        };
+        let width = reader.u32(20)?;
+        let height = reader.u32(24)?;
+        let depth = reader.u32(28)?;
+        let layer_count = reader.u32(32)?;
+        let face_count = reader.u32(36)?;
+        // Zero levels ask for the mip chain to be generated; only the base level is stored then.
+        let level_count = reader.u32(40)?.max(1);
+
+        if reader.u32(44)? != 0 {
+            return Err(TextureContainerError::UnsupportedFormat {
+                container: CONTAINER,
+                format: "supercompressed".to_owned(),
+            });
+        }
+
+        if height == 0 || depth != 0 {
+            return Err(unsupported_layout(
+                CONTAINER,
+                "only 2D textures are supported",
+            ));
+        }
+
+        if face_count != 1 && face_count != 6 {
+            return Err(corrupt_header(
+                CONTAINER,
+                format!("face count is {}, but must be 1 or 6", face_count),
+            ));
+        }
+
+        // Each layer stores its faces in turn, which is the order cube map layers take.
+        let array_layers = layer_count
+            .max(1)
+            .checked_mul(face_count)
+            .ok_or_else(|| corrupt_header(CONTAINER, "layer count is out of range"))?;
+        let layout = Layout::new(CONTAINER, format, width, height, level_count, array_layers)?;
+        let mut levels = Vec::with_capacity(level_count as usize);
+
+        for level in 0..level_count {
+            let entry = LEVEL_INDEX_OFFSET + level as usize * LEVEL_INDEX_ENTRY_SIZE;
+            let offset = reader.u64(entry)?;
+            let length = reader.u64(entry + 8)?;
+            let size = layout
+                .level_size(level)
+                .ok_or_else(|| corrupt_header(CONTAINER, "texture is too large"))?;
+
+            if length != size as u64 {
+                return Err(corrupt_header(
+                    CONTAINER,
+                    format!(
+                        "mip level {} has {} bytes, but {} are needed",
+                        level, length, size
+                    ),
+                ));
+            }
+
+            let texels = usize::try_from(offset)
+                .ok()
+                .and_then(|offset| content.get(offset..offset.checked_add(size)?))
+                .ok_or(TextureContainerError::TruncatedMipLevel {
+                    container: CONTAINER,
+                    level,
+                    expected: offset.saturating_add(length) as usize,
+                    actual: content.len(),
+                })?;
+            levels.push(texels.to_vec());
+        }
+
+        Ok(Self {
+            width: layout.width,
+            height: layout.height,
+            format,
+            levels,
+            array_layers,
+            is_cube_map: face_count == 6,
+        })
+    }
+}
+
+fn corrupt_header(container: &'static str, reason: impl Into<String>) -> TextureContainerError {
+    TextureContainerError::CorruptHeader {
+        container,
+        reason: reason.into(),
+    }
+}
+
+fn unsupported_layout(container: &'static str, reason: impl Into<String>) -> TextureContainerError {
+    TextureContainerError::UnsupportedLayout {
+        container,
+        reason: reason.into(),
+    }
+}
+
+/// Reads little-endian fields at offsets, failing instead of panicking past the end of the content.
+struct Reader<'a> {
+    container: &'static str,
+    content: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(container: &'static str, content: &'a [u8]) -> Self {
+        Self { container, content }
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], TextureContainerError> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.content.get(offset..end))
+            .ok_or(TextureContainerError::Truncated {
+                container: self.container,
+                expected: offset.saturating_add(len),
+                actual: self.content.len(),
+            })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, TextureContainerError> {
+        let bytes = self.bytes(offset, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64, TextureContainerError> {
+        let bytes = self.bytes(offset, 8)?;
+        let mut value = [0; 8];
+        value.copy_from_slice(bytes);
+        Ok(u64::from_le_bytes(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The fixtures hold texels numbered per byte from 0, so that the order of levels and layers shows.
+    const BC1_MIPS_DDS: &[u8] = include_bytes!("../tests/fixtures/bc1_mips.dds");
+    const BC7_SRGB_ARRAY_DDS: &[u8] = include_bytes!("../tests/fixtures/bc7_srgb_array.dds");
+    const BC7_MIPS_KTX2: &[u8] = include_bytes!("../tests/fixtures/bc7_mips.ktx2");
+    const RGBA8_CUBE_KTX2: &[u8] = include_bytes!("../tests/fixtures/rgba8_cube.ktx2");
+    const BGRA8_DDS: &[u8] = include_bytes!("../tests/fixtures/bgra8.dds");
+
+    fn parse(content: &[u8]) -> Result<TextureContainer, TextureContainerError> {
+        TextureContainer::parse(content).unwrap()
+    }
+
+    fn numbered(start: usize, len: usize) -> Vec<u8> {
+        Vec::from_iter((start..start + len).map(|index| index as u8))
+    }
+
+    #[test]
+    fn check_dds_mip_levels_are_read() {
+        let container = parse(BC1_MIPS_DDS).unwrap();
+
+        assert_eq!(container.format, TextureFormat::BC1);
+        assert_eq!((container.width, container.height), (8, 8));
+        assert_eq!((container.array_layers, container.is_cube_map), (1, false));
+        // 4, 1, 1 and 1 blocks of 8 bytes.
+        assert_eq!(
+            container.levels,
+            vec![
+                numbered(0, 32),
+                numbered(32, 8),
+                numbered(40, 8),
+                numbered(48, 8)
+            ]
+        );
+    }
+
+    #[test]
+    fn check_dds_layers_are_gathered_per_level() {
+        let container = parse(BC7_SRGB_ARRAY_DDS).unwrap();
+
+        assert_eq!(container.format, TextureFormat::BC7Srgb);
+        assert_eq!((container.width, container.height), (8, 4));
+        assert_eq!(container.array_layers, 2);
+        // Each layer stores 2 blocks of level 0 and a block of level 1 in turn.
+        assert_eq!(
+            container.levels,
+            vec![
+                [numbered(0, 32), numbered(48, 32)].concat(),
+                [numbered(32, 16), numbered(80, 16)].concat(),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_dds_bgra_texels_are_swizzled() {
+        let container = parse(BGRA8_DDS).unwrap();
+
+        assert_eq!(container.format, TextureFormat::RGBA8);
+        assert_eq!(container.levels, vec![vec![2, 1, 0, 3, 6, 5, 4, 7]]);
+    }
+
+    #[test]
+    fn check_ktx2_mip_levels_are_read() {
+        let container = parse(BC7_MIPS_KTX2).unwrap();
+
+        assert_eq!(container.format, TextureFormat::BC7);
+        assert_eq!((container.width, container.height), (4, 4));
+        assert_eq!(container.levels.len(), 3);
+        assert!(container.levels.iter().all(|texels| texels.len() == 16));
+        // KTX2 stores the smallest level first.
+        assert_eq!(container.levels[2], numbered(0, 16));
+        assert_eq!(container.levels[0], numbered(32, 16));
+    }
+
+    #[test]
+    fn check_ktx2_cube_maps_are_flagged() {
+        let container = parse(RGBA8_CUBE_KTX2).unwrap();
+
+        assert_eq!(container.format, TextureFormat::RGBA8);
+        assert_eq!((container.array_layers, container.is_cube_map), (6, true));
+        assert_eq!(container.levels[0], numbered(24, 2 * 2 * 4 * 6));
+        assert_eq!(container.levels[1], numbered(0, 4 * 6));
+    }
+
+    #[test]
+    fn check_truncated_mip_levels_are_errors() {
+        assert_eq!(
+            parse(&BC1_MIPS_DDS[..BC1_MIPS_DDS.len() - 1]),
+            Err(TextureContainerError::TruncatedMipLevel {
+                container: "DDS",
+                level: 3,
+                expected: BC1_MIPS_DDS.len(),
+                actual: BC1_MIPS_DDS.len() - 1,
+            })
+        );
+        assert!(matches!(
+            parse(&RGBA8_CUBE_KTX2[..RGBA8_CUBE_KTX2.len() - 4]),
+            Err(TextureContainerError::TruncatedMipLevel {
+                container: "KTX2",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn check_corrupt_headers_are_errors() {
+        assert!(matches!(
+            parse(&BC1_MIPS_DDS[..64]),
+            Err(TextureContainerError::Truncated { .. })
+        ));
+
+        let mut content = BC1_MIPS_DDS.to_vec();
+        content[4] = 0;
+        assert!(matches!(
+            parse(&content),
+            Err(TextureContainerError::CorruptHeader { .. })
+        ));
+
+        // 40 mip levels.
+        let mut content = BC1_MIPS_DDS.to_vec();
+        content[28] = 40;
+        assert!(matches!(
+            parse(&content),
+            Err(TextureContainerError::CorruptHeader { .. })
+        ));
+
+        // 5 faces.
+        let mut content = RGBA8_CUBE_KTX2.to_vec();
+        content[36] = 5;
+        assert!(matches!(
+            parse(&content),
+            Err(TextureContainerError::CorruptHeader { .. })
+        ));
+
+        // Unsupported DXGI format.
+        let mut content = BC7_SRGB_ARRAY_DDS.to_vec();
+        content[128] = 95;
+        assert!(matches!(
+            parse(&content),
+            Err(TextureContainerError::UnsupportedFormat { .. })
+        ));
+
+        assert!(TextureContainer::parse(b"\x89PNG\r\n\x1a\n").is_none());
+    }
+}
@@ -0,0 +1,661 @@
+use crate::{
+    assets::{FontSource, MaterialSource, ModelSource, ShaderSource, TextureSource},
+    AssetSource, AssetType,
+};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{OnceLock, RwLock, RwLockReadGuard},
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AssetMigrationError {
+    #[error("io error: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("malformed asset file: {0}")]
+    MalformedFile(String),
+    #[error("unknown asset type: `{0}`")]
+    UnknownAssetType(String),
+    #[error("expected a `{expected}` asset, but found a `{found}` asset")]
+    AssetTypeMismatch { expected: String, found: String },
+    #[error("`{asset_type}` asset has format version {found}, but this engine supports up to version {supported}")]
+    UnsupportedVersion {
+        asset_type: String,
+        found: u32,
+        supported: u32,
+    },
+    #[error("no migration registered for `{asset_type}` asset from format version {from_version}")]
+    MissingMigration {
+        asset_type: String,
+        from_version: u32,
+    },
+    #[error(
+        "failed to migrate `{asset_type}` asset from format version {from_version}: {message}"
+    )]
+    MigrationFailed {
+        asset_type: String,
+        from_version: u32,
+        message: String,
+    },
+    #[error("failed to decode `{asset_type}` asset: {message}")]
+    DecodeError { asset_type: String, message: String },
+}
+
+/// Transforms the data of an asset from one format version to the next.
+pub type AssetMigrationFn = fn(Value) -> Result<Value, String>;
+
+/// Serialized asset tagged with its type and format version.
+/// The data is kept as a self-describing value, so that migrations can restructure it freely.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionedAsset {
+    pub asset_type: String,
+    pub version: u32,
+    pub data: Value,
+}
+
+impl VersionedAsset {
+    /// Magic bytes at the start of the packed format.
+    pub const PACKED_MAGIC: &'static [u8; 4] = b"R3DA";
+
+    pub fn new<T: AssetSource>(
+        asset_type: impl ToString,
+        source: &T,
+    ) -> Result<Self, AssetMigrationError> {
+        let asset_type = asset_type.to_string();
+        let data =
+            serde_json::to_value(source).map_err(|err| AssetMigrationError::DecodeError {
+                asset_type: asset_type.clone(),
+                message: err.to_string(),
+            })?;
+
+        Ok(Self {
+            asset_type,
+            version: T::FORMAT_VERSION,
+            data,
+        })
+    }
+
+    pub fn is_packed(bytes: &[u8]) -> bool {
+        bytes.starts_with(Self::PACKED_MAGIC)
+    }
+
+    /// Parses either format. Returns `None` if the bytes are not a versioned asset at all.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Result<Self, AssetMigrationError>> {
+        if Self::is_packed(bytes) {
+            return Some(Self::from_packed(bytes));
+        }
+
+        std::str::from_utf8(bytes)
+            .ok()
+            .and_then(|source| Self::from_loose(source).ok())
+            .map(Ok)
+    }
+
+    /// Parses the loose format: a JSON object with `asset_type`, `version` and `data` fields.
+    pub fn from_loose(source: &str) -> Result<Self, AssetMigrationError> {
+        let mut value = serde_json::from_str::<Value>(source)
+            .map_err(|err| AssetMigrationError::MalformedFile(err.to_string()))?;
+        let object = value
+            .as_object_mut()
+            .ok_or_else(|| AssetMigrationError::MalformedFile("expected an object".to_owned()))?;
+        let asset_type = object
+            .get("asset_type")
+            .and_then(|asset_type| asset_type.as_str())
+            .ok_or_else(|| AssetMigrationError::MalformedFile("missing `asset_type`".to_owned()))?
+            .to_owned();
+        let version = object
+            .get("version")
+            .and_then(|version| version.as_u64())
+            .ok_or_else(|| AssetMigrationError::MalformedFile("missing `version`".to_owned()))?
+            as u32;
+        let data = object
+            .remove("data")
+            .ok_or_else(|| AssetMigrationError::MalformedFile("missing `data`".to_owned()))?;
+
+        Ok(Self {
+            asset_type,
+            version,
+            data,
+        })
+    }
+
+    pub fn to_loose(&self) -> String {
+        serde_json::to_string_pretty(&serde_json::json!({
+            "asset_type": self.asset_type,
+            "version": self.version,
+            "data": self.data,
+        }))
+        .unwrap()
+    }
+
+    /// Parses the packed format: magic, version (u32 LE), asset type (u8 length and UTF-8), then the data.
+    pub fn from_packed(bytes: &[u8]) -> Result<Self, AssetMigrationError> {
+        let malformed = || AssetMigrationError::MalformedFile("truncated packed asset".to_owned());
+
+        if !Self::is_packed(bytes) {
+            return Err(AssetMigrationError::MalformedFile(
+                "missing packed asset magic".to_owned(),
+            ));
+        }
+
+        let bytes = &bytes[Self::PACKED_MAGIC.len()..];
+        let version = u32::from_le_bytes(bytes.get(..4).ok_or_else(malformed)?.try_into().unwrap());
+        let asset_type_len = *bytes.get(4).ok_or_else(malformed)? as usize;
+        let asset_type = bytes.get(5..5 + asset_type_len).ok_or_else(malformed)?;
+        let asset_type = std::str::from_utf8(asset_type)
+            .map_err(|err| AssetMigrationError::MalformedFile(err.to_string()))?
+            .to_owned();
+        let data = serde_json::from_slice(&bytes[5 + asset_type_len..])
+            .map_err(|err| AssetMigrationError::MalformedFile(err.to_string()))?;
+
+        Ok(Self {
+            asset_type,
+            version,
+            data,
+        })
+    }
+
+    pub fn to_packed(&self) -> Result<Vec<u8>, AssetMigrationError> {
+        let asset_type_len = u8::try_from(self.asset_type.len()).map_err(|_| {
+            AssetMigrationError::MalformedFile(format!(
+                "asset type `{}` is longer than 255 bytes",
+                self.asset_type
+            ))
+        })?;
+        let data = serde_json::to_vec(&self.data).unwrap();
+        let mut bytes = Vec::with_capacity(
+            Self::PACKED_MAGIC.len() + 4 + 1 + self.asset_type.len() + data.len(),
+        );
+        bytes.extend_from_slice(Self::PACKED_MAGIC);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.push(asset_type_len);
+        bytes.extend_from_slice(self.asset_type.as_bytes());
+        bytes.extend_from_slice(&data);
+        Ok(bytes)
+    }
+}
+
+/// Result of [`AssetMigrations::migrate_directory`].
+#[derive(Debug, Default)]
+pub struct DirectoryMigration {
+    /// Files that were rewritten to their format version.
+    pub migrated: Vec<PathBuf>,
+    /// Files that could not be migrated. They are left untouched.
+    pub failed: Vec<(PathBuf, AssetMigrationError)>,
+}
+
+/// Registry of migrations between format versions of each asset type.
+#[derive(Default)]
+pub struct AssetMigrations {
+    format_versions: HashMap<String, u32>,
+    migrations: HashMap<(String, u32), AssetMigrationFn>,
+}
+
+impl AssetMigrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry that knows the format versions of the built-in asset types.
+    pub fn with_built_in_asset_types() -> Self {
+        let mut migrations = Self::new();
+        migrations.register_asset_type(AssetType::Font, FontSource::FORMAT_VERSION);
+        migrations.register_asset_type(AssetType::Material, MaterialSource::FORMAT_VERSION);
+        migrations.register_asset_type(AssetType::Model, ModelSource::FORMAT_VERSION);
+        migrations.register_asset_type(AssetType::Shader, ShaderSource::FORMAT_VERSION);
+        migrations.register_asset_type(AssetType::Texture, TextureSource::FORMAT_VERSION);
+        migrations
+    }
+
+    /// Registers the format version that `asset_type` assets are migrated to.
+    /// It must match the [`AssetSource::FORMAT_VERSION`] of the source the assets are loaded as.
+    pub fn register_asset_type(&mut self, asset_type: impl ToString, format_version: u32) {
+        self.format_versions
+            .insert(asset_type.to_string(), format_version);
+    }
+
+    /// Registers a migration that transforms `asset_type` assets from `from_version` to `from_version + 1`.
+    pub fn register_migration(
+        &mut self,
+        asset_type: impl ToString,
+        from_version: u32,
+        migration: AssetMigrationFn,
+    ) {
+        self.migrations
+            .insert((asset_type.to_string(), from_version), migration);
+    }
+
+    /// Returns the format version that `asset_type` assets are migrated to, if the type is registered.
+    pub fn format_version(&self, asset_type: &str) -> Option<u32> {
+        self.format_versions.get(asset_type).copied()
+    }
+
+    /// Applies the migrations in sequence until the asset reaches the target version.
+    pub fn migrate(
+        &self,
+        mut asset: VersionedAsset,
+        target_version: u32,
+    ) -> Result<VersionedAsset, AssetMigrationError> {
+        if target_version < asset.version {
+            return Err(AssetMigrationError::UnsupportedVersion {
+                asset_type: asset.asset_type,
+                found: asset.version,
+                supported: target_version,
+            });
+        }
+
+        while asset.version < target_version {
+            let migration = self
+                .migrations
+                .get(&(asset.asset_type.clone(), asset.version))
+                .ok_or_else(|| AssetMigrationError::MissingMigration {
+                    asset_type: asset.asset_type.clone(),
+                    from_version: asset.version,
+                })?;
+            asset.data =
+                migration(asset.data).map_err(|message| AssetMigrationError::MigrationFailed {
+                    asset_type: asset.asset_type.clone(),
+                    from_version: asset.version,
+                    message,
+                })?;
+            asset.version += 1;
+        }
+
+        Ok(asset)
+    }
+
+    /// Migrates the asset to the format version of `T` and decodes it.
+    pub fn load<T: AssetSource>(&self, asset: VersionedAsset) -> Result<T, AssetMigrationError> {
+        let asset = self.migrate(asset, T::FORMAT_VERSION)?;
+        serde_json::from_value(asset.data).map_err(|err| AssetMigrationError::DecodeError {
+            asset_type: asset.asset_type,
+            message: err.to_string(),
+        })
+    }
+
+    /// Decodes a `T` from a file in either versioned format, migrating it first.
+    /// Returns `None` if the file is not a versioned asset, so that callers can fall back to other formats.
+    pub fn load_file<T: AssetSource>(
+        &self,
+        asset_type: impl ToString,
+        bytes: &[u8],
+    ) -> Option<Result<T, AssetMigrationError>> {
+        let asset_type = asset_type.to_string();
+        let asset = match VersionedAsset::from_bytes(bytes)? {
+            Ok(asset) => asset,
+            Err(err) => return Some(Err(err)),
+        };
+
+        if asset.asset_type != asset_type {
+            return Some(Err(AssetMigrationError::AssetTypeMismatch {
+                expected: asset_type,
+                found: asset.asset_type,
+            }));
+        }
+
+        Some(self.load(asset))
+    }
+
+    /// Rewrites every versioned asset under the directory to the format version of its type, in place.
+    /// The original file is kept next to it with a `.bak` extension appended.
+    /// Files that fail to migrate are reported and left untouched; only errors reading the directory abort.
+    pub fn migrate_directory(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<DirectoryMigration, AssetMigrationError> {
+        let mut result = DirectoryMigration::default();
+        self.migrate_directory_recursive(path.as_ref(), &mut result)?;
+        Ok(result)
+    }
+
+    fn migrate_directory_recursive(
+        &self,
+        path: &Path,
+        result: &mut DirectoryMigration,
+    ) -> Result<(), AssetMigrationError> {
+        for entry in std::fs::read_dir(path)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                self.migrate_directory_recursive(&path, result)?;
+                continue;
+            }
+
+            if path.extension().and_then(|ext| ext.to_str()) == Some("bak") {
+                continue;
+            }
+
+            match self.migrate_file(&path) {
+                Ok(true) => result.migrated.push(path),
+                Ok(false) => {}
+                Err(err) => result.failed.push((path, err)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the file was rewritten.
+    fn migrate_file(&self, path: &Path) -> Result<bool, AssetMigrationError> {
+        let bytes = std::fs::read(path)?;
+        // Files that are not versioned assets are not ours to touch.
+        let asset = match VersionedAsset::from_bytes(&bytes) {
+            Some(asset) => asset?,
+            None => return Ok(false),
+        };
+        let format_version = self
+            .format_version(&asset.asset_type)
+            .ok_or_else(|| AssetMigrationError::UnknownAssetType(asset.asset_type.clone()))?;
+
+        if asset.version == format_version {
+            return Ok(false);
+        }
+
+        let asset = self.migrate(asset, format_version)?;
+        let migrated = if VersionedAsset::is_packed(&bytes) {
+            asset.to_packed()?
+        } else {
+            asset.to_loose().into_bytes()
+        };
+
+        let mut backup_path = path.to_owned().into_os_string();
+        backup_path.push(".bak");
+        std::fs::copy(path, backup_path)?;
+        std::fs::write(path, migrated)?;
+
+        Ok(true)
+    }
+}
+
+fn global_migrations() -> &'static RwLock<AssetMigrations> {
+    static MIGRATIONS: OnceLock<RwLock<AssetMigrations>> = OnceLock::new();
+    MIGRATIONS.get_or_init(|| RwLock::new(AssetMigrations::with_built_in_asset_types()))
+}
+
+/// Registers the format version of a custom asset type to the global registry.
+pub fn register_asset_type(asset_type: impl ToString, format_version: u32) {
+    global_migrations()
+        .write()
+        .unwrap()
+        .register_asset_type(asset_type, format_version);
+}
+
+/// Registers a migration to the global registry used by the asset pipelines.
+pub fn register_migration(
+    asset_type: impl ToString,
+    from_version: u32,
+    migration: AssetMigrationFn,
+) {
+    global_migrations()
+        .write()
+        .unwrap()
+        .register_migration(asset_type, from_version, migration);
+}
+
+pub fn migrations() -> RwLockReadGuard<'static, AssetMigrations> {
+    global_migrations().read().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::{TextureAddressMode, TextureFilterMode, TextureFormat};
+    use serde_json::json;
+
+    fn texture_migrations() -> AssetMigrations {
+        let mut migrations = AssetMigrations::new();
+
+        // v1 -> v2: `filter` is renamed to `filter_mode`.
+        migrations.register_migration("texture", 1, |mut data| {
+            let object = data.as_object_mut().ok_or("expected an object")?;
+            let filter = object.remove("filter").ok_or("missing `filter`")?;
+            object.insert("filter_mode".to_owned(), filter);
+            Ok(data)
+        });
+        // v2 -> v3: a single `address_mode` becomes a pair, one for each axis.
+        migrations.register_migration("texture", 2, |mut data| {
+            let object = data.as_object_mut().ok_or("expected an object")?;
+            let address_mode = object
+                .remove("address_mode")
+                .ok_or("missing `address_mode`")?;
+            object.insert(
+                "address_mode".to_owned(),
+                json!([address_mode.clone(), address_mode]),
+            );
+            Ok(data)
+        });
+
+        migrations
+    }
+
+    fn texture_v1() -> VersionedAsset {
+        VersionedAsset {
+            asset_type: "texture".to_owned(),
+            version: 1,
+            data: json!({
+                "width": 1,
+                "height": 1,
+                "format": "RGBA8",
+                "filter": "Point",
+                "address_mode": "Clamp",
+                "texels": [255, 255, 255, 255],
+                "sprites": [],
+                "nine_patches": [],
+            }),
+        }
+    }
+
+    #[test]
+    fn check_texture_is_migrated_in_two_steps() {
+        let migrations = texture_migrations();
+
+        let asset = migrations.migrate(texture_v1(), 3).unwrap();
+        assert_eq!(asset.version, 3);
+
+        let texture: TextureSource = serde_json::from_value(asset.data).unwrap();
+        assert_eq!(texture.format, TextureFormat::RGBA8);
+        assert_eq!(texture.filter_mode, TextureFilterMode::Point);
+        assert_eq!(
+            texture.address_mode,
+            (TextureAddressMode::Clamp, TextureAddressMode::Clamp)
+        );
+    }
+
+    #[test]
+    fn check_packed_and_loose_formats_round_trip() {
+        let asset = texture_v1();
+
+        assert_eq!(
+            VersionedAsset::from_packed(&asset.to_packed().unwrap()).unwrap(),
+            asset
+        );
+        assert_eq!(
+            VersionedAsset::from_loose(&asset.to_loose()).unwrap(),
+            asset
+        );
+    }
+
+    #[test]
+    fn check_scene_components_are_restructured() {
+        let mut migrations = AssetMigrations::new();
+
+        // v1 -> v2: components move from a map keyed by type to a list of tagged entries.
+        migrations.register_migration("scene", 1, |mut data| {
+            for object in data["objects"].as_array_mut().ok_or("missing `objects`")? {
+                let components = object["components"]
+                    .as_object()
+                    .ok_or("expected a component map")?
+                    .iter()
+                    .map(|(ty, value)| json!({ "type": ty, "value": value }))
+                    .collect::<Vec<_>>();
+                object["components"] = Value::Array(components);
+            }
+            Ok(data)
+        });
+        // v2 -> v3: transform components store their rotation as a quaternion instead of euler angles.
+        migrations.register_migration("scene", 2, |mut data| {
+            for object in data["objects"].as_array_mut().ok_or("missing `objects`")? {
+                for component in object["components"].as_array_mut().unwrap() {
+                    if component["type"] == "transform" {
+                        let value = &mut component["value"];
+                        let euler = value
+                            .as_object_mut()
+                            .and_then(|value| value.remove("euler"))
+                            .ok_or("missing `euler`")?;
+
+                        if euler != json!([0.0, 0.0, 0.0]) {
+                            return Err("only identity rotations are expected".to_owned());
+                        }
+
+                        value["rotation"] = json!([0.0, 0.0, 0.0, 1.0]);
+                    }
+                }
+            }
+            Ok(data)
+        });
+
+        let scene = VersionedAsset {
+            asset_type: "scene".to_owned(),
+            version: 1,
+            data: json!({
+                "objects": [{
+                    "name": "root",
+                    "components": {
+                        "transform": { "position": [1.0, 2.0, 3.0], "euler": [0.0, 0.0, 0.0] },
+                    },
+                }],
+            }),
+        };
+        let packed = VersionedAsset::from_packed(&scene.to_packed().unwrap()).unwrap();
+        let migrated = migrations.migrate(packed, 3).unwrap();
+
+        assert_eq!(
+            migrated.data,
+            json!({
+                "objects": [{
+                    "name": "root",
+                    "components": [{
+                        "type": "transform",
+                        "value": { "position": [1.0, 2.0, 3.0], "rotation": [0.0, 0.0, 0.0, 1.0] },
+                    }],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn check_newer_version_is_rejected() {
+        let mut asset = texture_v1();
+        asset.version = 4;
+
+        let err = texture_migrations().migrate(asset, 3).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`texture` asset has format version 4, but this engine supports up to version 3"
+        );
+    }
+
+    #[test]
+    fn check_versioned_files_are_loaded_in_either_format() {
+        let texture = texture_migrations().migrate(texture_v1(), 3).unwrap();
+        let texture = VersionedAsset {
+            version: TextureSource::FORMAT_VERSION,
+            ..texture
+        };
+        let migrations = AssetMigrations::with_built_in_asset_types();
+
+        for bytes in [
+            texture.to_packed().unwrap(),
+            texture.to_loose().into_bytes(),
+        ] {
+            let source = migrations
+                .load_file::<TextureSource>(AssetType::Texture, &bytes)
+                .unwrap()
+                .unwrap();
+            assert_eq!(source.filter_mode, TextureFilterMode::Point);
+
+            assert!(matches!(
+                migrations.load_file::<MaterialSource>(AssetType::Material, &bytes),
+                Some(Err(AssetMigrationError::AssetTypeMismatch { .. }))
+            ));
+        }
+
+        assert!(migrations
+            .load_file::<TextureSource>(AssetType::Texture, b"\x89PNG")
+            .is_none());
+    }
+
+    #[test]
+    fn check_directory_failures_are_reported_per_file() {
+        let dir = std::env::temp_dir().join(format!("asset-migration-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+
+        let mut newer = texture_v1();
+        newer.version = 4;
+        let unknown = VersionedAsset {
+            asset_type: "scene".to_owned(),
+            version: 1,
+            data: json!({ "objects": [] }),
+        };
+        std::fs::write(dir.join("old.json"), texture_v1().to_loose()).unwrap();
+        std::fs::write(dir.join("newer.bin"), newer.to_packed().unwrap()).unwrap();
+        std::fs::write(dir.join("nested/unknown.bin"), unknown.to_packed().unwrap()).unwrap();
+        std::fs::write(dir.join("readme.txt"), "not an asset").unwrap();
+
+        let mut migrations = texture_migrations();
+        migrations.register_asset_type(AssetType::Texture, 3);
+        let result = migrations.migrate_directory(&dir).unwrap();
+
+        assert_eq!(result.migrated, [dir.join("old.json")]);
+        let mut failed = result
+            .failed
+            .iter()
+            .map(|(path, err)| (path.strip_prefix(&dir).unwrap().to_owned(), err))
+            .collect::<Vec<_>>();
+        failed.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+        assert_eq!(failed.len(), 2);
+        assert_eq!(failed[0].0, Path::new("nested/unknown.bin"));
+        assert!(matches!(
+            failed[0].1,
+            AssetMigrationError::UnknownAssetType(_)
+        ));
+        assert_eq!(failed[1].0, Path::new("newer.bin"));
+        assert!(matches!(
+            failed[1].1,
+            AssetMigrationError::UnsupportedVersion { found: 4, .. }
+        ));
+
+        let old = std::fs::read_to_string(dir.join("old.json")).unwrap();
+        assert_eq!(VersionedAsset::from_loose(&old).unwrap().version, 3);
+        assert!(dir.join("old.json.bak").exists());
+        assert!(!dir.join("newer.bin.bak").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn check_long_asset_type_is_not_packed() {
+        let mut asset = texture_v1();
+        asset.asset_type = "t".repeat(256);
+
+        assert!(matches!(
+            asset.to_packed(),
+            Err(AssetMigrationError::MalformedFile(_))
+        ));
+    }
+
+    #[test]
+    fn check_missing_migration_is_reported() {
+        let err = AssetMigrations::new().migrate(texture_v1(), 2).unwrap_err();
+
+        assert!(matches!(
+            err,
+            AssetMigrationError::MissingMigration {
+                from_version: 1,
+                ..
+            }
+        ));
+    }
+}
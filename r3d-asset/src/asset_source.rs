@@ -1,4 +1,4 @@
-use crate::{Asset, AssetDepsProvider, AssetKey, AssetType, GfxBridge};
+use crate::{assets::TextureSourceError, Asset, AssetDepsProvider, AssetKey, AssetType, GfxBridge};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use thiserror::Error;
@@ -32,6 +32,8 @@ pub enum AssetLoadError {
         texture_key: AssetKey,
         nine_patch_name: String,
     },
+    #[error("invalid texture: {0}")]
+    InvalidTexture(#[from] TextureSourceError),
     #[error("{0}")]
     Other(String),
 }
@@ -41,6 +43,11 @@ pub trait AssetSource: Serialize + for<'de> Deserialize<'de> {
     /// The asset type that this source can load.
     type Asset: ?Sized + Asset;
 
+    /// The format version of the serialized source. Bump it along with registering a migration
+    /// from the previous version (see [`register_migration`](crate::register_migration)).
+    /// Custom asset types also register it with [`register_asset_type`](crate::register_asset_type).
+    const FORMAT_VERSION: u32 = 1;
+
     /// List all dependencies of the asset.
     fn dependencies(&self) -> Vec<AssetKey>;
 
@@ -382,16 +382,14 @@ impl AssetSource for MaterialSource {
                                 actual_ty: texture.ty(),
                             }
                         })?;
-                        let sprites = texture.sprites();
-                        let sprite_index = sprites
-                            .iter()
-                            .position(|item| item.name.as_str() == sprite.as_str())
-                            .ok_or_else(|| AssetLoadError::InvalidSpriteName {
+                        let sprite = texture.find_sprite(&sprite).ok_or_else(|| {
+                            AssetLoadError::InvalidSpriteName {
                                 texture_key: key,
                                 sprite_name: sprite.clone(),
-                            })?;
+                            }
+                        })?;
                         MaterialBindingValue::Sampler {
-                            sampler: sprites[sprite_index].sampler_handle.clone(),
+                            sampler: sprite.sampler_handle.clone(),
                         }
                     }
                     MaterialBindingValueSource::SamplerNinePatch {
@@ -411,16 +409,14 @@ impl AssetSource for MaterialSource {
                                 actual_ty: texture.ty(),
                             }
                         })?;
-                        let nine_patches = texture.nine_patches();
-                        let nine_patch_index = nine_patches
-                            .iter()
-                            .position(|item| item.name.as_str() == nine_patch.as_str())
-                            .ok_or_else(|| AssetLoadError::InvalidNinePatchName {
+                        let nine_patch = texture.find_nine_patch(&nine_patch).ok_or_else(|| {
+                            AssetLoadError::InvalidNinePatchName {
                                 texture_key: key.clone(),
                                 nine_patch_name: nine_patch.clone(),
-                            })?;
+                            }
+                        })?;
                         MaterialBindingValue::Sampler {
-                            sampler: nine_patches[nine_patch_index].sampler_handle.clone(),
+                            sampler: nine_patch.sampler_handle.clone(),
                         }
                     }
                     _ => {
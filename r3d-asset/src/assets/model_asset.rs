@@ -3,12 +3,13 @@ use crate::{
     TypedAsset,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{mem::size_of, sync::Arc};
 use wgpu::BufferUsages;
 
 /// Index element type of a mesh.
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum VertexIndexType {
+    /// Cannot be drawn by the GPU; only found in assets imported before 16-bit indices became the narrowest.
     U8,
     U16,
     U32,
@@ -25,10 +26,14 @@ pub enum VertexAttributeKind {
     Color { index: u32 },
     /// vec2
     TexCoord { index: u32 },
-    /// vec3
+    /// vec4; the handedness of the bitangent, `cross(normal, tangent)`, is in `w`.
     Tangent,
-    /// vec3
+    /// vec3; only found in models imported before tangents carried their handedness, and folded into them on load.
     Bitangent,
+    /// vec4 of the indices of the bones moving the vertex in the [`ModelSkeleton`], as floats.
+    BoneIndices,
+    /// vec4 of the weights of the bones moving the vertex, summing to 1.
+    BoneWeights,
     /// vec4
     Extra { index: u32 },
 }
@@ -73,6 +78,70 @@ pub struct Mesh {
     pub vertex_buffer: GfxBuffer,
     pub vertex_count: u32,
     pub material: Option<MeshMaterial>,
+    pub lods: Vec<MeshLod>,
+}
+
+/// Simplified level of detail of a mesh. It shares the vertex buffer and the index type of the mesh.
+#[derive(Debug)]
+pub struct MeshLod {
+    /// Ratio of the triangle count of the mesh this level was generated for.
+    pub ratio: f32,
+    /// Simplification error, relative to the diagonal of the mesh bounds.
+    pub error: f32,
+    pub index_buffer: GfxBuffer,
+}
+
+/// Bone of a [`ModelSkeleton`], moved by the node of the same name.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelBone {
+    pub name: String,
+    /// Index of the parent bone, which always comes before its children.
+    pub parent_index: Option<u32>,
+    /// Index of the node moving the bone, if the model has one of its name.
+    pub node_index: Option<u32>,
+    /// Transforms from the space of the meshes to the one of the bone in the bind pose, laid out as the node
+    /// transforms.
+    pub inverse_bind_matrix: [f32; 16],
+}
+
+/// Bones the vertices of the meshes of a model are skinned to, parents first.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ModelSkeleton {
+    pub bones: Vec<ModelBone>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct AnimationVectorKey {
+    /// In seconds.
+    pub time: f32,
+    pub value: [f32; 3],
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct AnimationRotationKey {
+    /// In seconds.
+    pub time: f32,
+    /// Quaternion as `[x, y, z, w]`.
+    pub value: [f32; 4],
+}
+
+/// Keyframes of the local transform of a node, by name. Keys are sorted by time; a channel with no key of a kind
+/// leaves that part of the transform as it is.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelAnimationChannel {
+    pub node_name: String,
+    pub translations: Vec<AnimationVectorKey>,
+    pub rotations: Vec<AnimationRotationKey>,
+    pub scales: Vec<AnimationVectorKey>,
+}
+
+/// Animation clip of a model, moving its nodes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelAnimation {
+    pub name: String,
+    /// In seconds.
+    pub duration: f32,
+    pub channels: Vec<ModelAnimationChannel>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -85,6 +154,8 @@ pub trait ModelAsset: Asset {
     fn root_node_index(&self) -> Option<u32>;
     fn nodes(&self) -> &[Node];
     fn meshes(&self) -> &[Mesh];
+    fn skeleton(&self) -> Option<&ModelSkeleton>;
+    fn animations(&self) -> &[ModelAnimation];
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -99,6 +170,416 @@ pub struct MeshSource {
     pub vertex_buffer: Vec<u8>,
     pub vertex_count: u32,
     pub material: Option<MeshMaterialSource>,
+    /// Levels of detail from the most detailed one, generated at import time.
+    #[serde(default)]
+    pub lods: Vec<MeshLodSource>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MeshLodSource {
+    pub ratio: f32,
+    pub error: f32,
+    /// Little-endian.
+    pub index_buffer: Vec<u8>,
+}
+
+impl MeshSource {
+    /// Gives each triangle its own vertices with the normal of its face, for meshes imported without normals.
+    /// The triangles of the levels of detail get their own vertices too, after the ones of the mesh.
+    /// Does nothing if the mesh has normals already or has no positions.
+    pub fn generate_flat_normals(&mut self) {
+        let has_normals = self
+            .vertex_attributes
+            .iter()
+            .any(|attribute| attribute.kind == VertexAttributeKind::Normal);
+        let position_offset = match self
+            .vertex_attributes
+            .iter()
+            .find(|attribute| attribute.kind == VertexAttributeKind::Position)
+        {
+            Some(attribute) => attribute.offset as usize,
+            None => return,
+        };
+
+        if has_normals || self.vertex_count == 0 {
+            return;
+        }
+
+        let stride = self.vertex_buffer.len() / self.vertex_count as usize;
+
+        if stride < position_offset + size_of::<[f32; 3]>() {
+            return;
+        }
+
+        let lists = Vec::from_iter(
+            std::iter::once(&self.index_buffer)
+                .chain(self.lods.iter().map(|lod| &lod.index_buffer))
+                .map(|index_buffer| decode_indices(index_buffer, self.index_type)),
+        );
+        let vertex_count = lists
+            .iter()
+            .map(|indices| indices.len() / 3 * 3)
+            .sum::<usize>();
+        let index_type = if vertex_count <= u16::MAX as usize {
+            VertexIndexType::U16
+        } else {
+            VertexIndexType::U32
+        };
+        let vertex = |index: u32| {
+            let start = index as usize * stride;
+            self.vertex_buffer.get(start..start + stride)
+        };
+        let position = |vertex: &[u8]| {
+            let mut position = [0f32; 3];
+
+            for (axis, value) in position.iter_mut().enumerate() {
+                let start = position_offset + axis * size_of::<f32>();
+                *value = f32::from_le_bytes(vertex[start..start + 4].try_into().unwrap());
+            }
+
+            position
+        };
+
+        let mut vertex_buffer = Vec::with_capacity(vertex_count * (stride + size_of::<[f32; 3]>()));
+        let mut index_buffers = Vec::with_capacity(lists.len());
+        let mut next_index = 0u32;
+
+        for indices in &lists {
+            let mut index_buffer = Vec::with_capacity(indices.len() * 4);
+
+            for triangle in indices.chunks_exact(3) {
+                let vertices = match (
+                    vertex(triangle[0]),
+                    vertex(triangle[1]),
+                    vertex(triangle[2]),
+                ) {
+                    (Some(v0), Some(v1), Some(v2)) => [v0, v1, v2],
+                    // Out of range, as in a corrupted mesh; the triangle is dropped.
+                    _ => continue,
+                };
+                let normal = face_normal(
+                    position(vertices[0]),
+                    position(vertices[1]),
+                    position(vertices[2]),
+                );
+
+                for vertex in vertices {
+                    vertex_buffer.extend_from_slice(vertex);
+
+                    for value in normal {
+                        vertex_buffer.extend_from_slice(&value.to_le_bytes());
+                    }
+
+                    match index_type {
+                        VertexIndexType::U16 => {
+                            index_buffer.extend_from_slice(&(next_index as u16).to_le_bytes())
+                        }
+                        _ => index_buffer.extend_from_slice(&next_index.to_le_bytes()),
+                    }
+
+                    next_index += 1;
+                }
+            }
+
+            index_buffers.push(index_buffer);
+        }
+
+        let mut index_buffers = index_buffers.into_iter();
+        self.index_buffer = index_buffers.next().unwrap();
+
+        for (lod, index_buffer) in self.lods.iter_mut().zip(index_buffers) {
+            lod.index_buffer = index_buffer;
+        }
+
+        self.index_type = index_type;
+        self.vertex_buffer = vertex_buffer;
+        self.vertex_count = next_index;
+        self.vertex_attributes.push(VertexAttribute {
+            offset: stride as u32,
+            kind: VertexAttributeKind::Normal,
+        });
+    }
+
+    /// Appends a tangent to each vertex, computed from the positions, the normals and the first UV set, for meshes
+    /// imported without tangents. The handedness of the bitangent, `cross(normal, tangent)`, is stored in `w`.
+    /// Does nothing if the mesh has tangents already, or lacks any of the attributes they are computed from.
+    pub fn generate_tangents(&mut self) {
+        let offset_of = |kind: VertexAttributeKind| {
+            self.vertex_attributes
+                .iter()
+                .find(|attribute| attribute.kind == kind)
+                .map(|attribute| attribute.offset as usize)
+        };
+
+        if offset_of(VertexAttributeKind::Tangent).is_some() || self.vertex_count == 0 {
+            return;
+        }
+
+        let (position_offset, normal_offset, uv_offset) = match (
+            offset_of(VertexAttributeKind::Position),
+            offset_of(VertexAttributeKind::Normal),
+            offset_of(VertexAttributeKind::TexCoord { index: 0 }),
+        ) {
+            (Some(position), Some(normal), Some(uv)) => (position, normal, uv),
+            _ => return,
+        };
+
+        let vertex_count = self.vertex_count as usize;
+        let stride = self.vertex_buffer.len() / vertex_count;
+
+        if stride < position_offset.max(normal_offset) + size_of::<[f32; 3]>()
+            || stride < uv_offset + size_of::<[f32; 2]>()
+        {
+            return;
+        }
+
+        let positions =
+            Vec::from_iter((0..vertex_count).map(|index| {
+                read_f32s::<3>(&self.vertex_buffer, index * stride + position_offset)
+            }));
+        let uvs = Vec::from_iter(
+            (0..vertex_count)
+                .map(|index| read_f32s::<2>(&self.vertex_buffer, index * stride + uv_offset)),
+        );
+
+        // Sums of the tangents and bitangents of the triangles around each vertex, weighted by their area.
+        let mut tangents = vec![[0f32; 3]; vertex_count];
+        let mut bitangents = vec![[0f32; 3]; vertex_count];
+
+        for triangle in decode_indices(&self.index_buffer, self.index_type).chunks_exact(3) {
+            let [i0, i1, i2] = [
+                triangle[0] as usize,
+                triangle[1] as usize,
+                triangle[2] as usize,
+            ];
+
+            if vertex_count <= i0.max(i1).max(i2) {
+                continue;
+            }
+
+            let e1 = sub3(positions[i1], positions[i0]);
+            let e2 = sub3(positions[i2], positions[i0]);
+            let (du1, dv1) = (uvs[i1][0] - uvs[i0][0], uvs[i1][1] - uvs[i0][1]);
+            let (du2, dv2) = (uvs[i2][0] - uvs[i0][0], uvs[i2][1] - uvs[i0][1]);
+            let determinant = du1 * dv2 - du2 * dv1;
+
+            // The UVs of the triangle are degenerate; it has no tangent space to contribute.
+            if determinant.abs() <= f32::EPSILON {
+                continue;
+            }
+
+            let r = 1f32 / determinant;
+            let tangent = [
+                (e1[0] * dv2 - e2[0] * dv1) * r,
+                (e1[1] * dv2 - e2[1] * dv1) * r,
+                (e1[2] * dv2 - e2[2] * dv1) * r,
+            ];
+            let bitangent = [
+                (e2[0] * du1 - e1[0] * du2) * r,
+                (e2[1] * du1 - e1[1] * du2) * r,
+                (e2[2] * du1 - e1[2] * du2) * r,
+            ];
+
+            for index in [i0, i1, i2] {
+                tangents[index] = add3(tangents[index], tangent);
+                bitangents[index] = add3(bitangents[index], bitangent);
+            }
+        }
+
+        let mut vertex_buffer = Vec::with_capacity(vertex_count * (stride + size_of::<[f32; 4]>()));
+
+        for (index, vertex) in self.vertex_buffer.chunks_exact(stride).enumerate() {
+            let normal = read_f32s::<3>(vertex, normal_offset);
+            let tangent = orthogonal_tangent(normal, tangents[index], bitangents[index]);
+
+            vertex_buffer.extend_from_slice(vertex);
+
+            for value in tangent {
+                vertex_buffer.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        self.vertex_buffer = vertex_buffer;
+        self.vertex_attributes.push(VertexAttribute {
+            offset: stride as u32,
+            kind: VertexAttributeKind::Tangent,
+        });
+    }
+
+    /// Folds the bitangents of meshes imported before tangents carried their handedness into the `w` of their
+    /// tangents, and drops them. The tangent is dropped too if the bitangent doesn't directly follow it, as its `w`
+    /// would have nowhere to go.
+    pub fn fold_legacy_bitangents(&mut self) {
+        let bitangent_offset = match self
+            .vertex_attributes
+            .iter()
+            .find(|attribute| attribute.kind == VertexAttributeKind::Bitangent)
+        {
+            Some(attribute) => attribute.offset as usize,
+            None => return,
+        };
+        let tangent_offset = self
+            .vertex_attributes
+            .iter()
+            .find(|attribute| attribute.kind == VertexAttributeKind::Tangent)
+            .map(|attribute| attribute.offset as usize);
+        let normal_offset = self
+            .vertex_attributes
+            .iter()
+            .find(|attribute| attribute.kind == VertexAttributeKind::Normal)
+            .map(|attribute| attribute.offset as usize);
+
+        self.vertex_attributes
+            .retain(|attribute| attribute.kind != VertexAttributeKind::Bitangent);
+
+        let (tangent_offset, normal_offset) = match (tangent_offset, normal_offset) {
+            (Some(tangent), Some(normal))
+                if tangent + size_of::<[f32; 3]>() == bitangent_offset =>
+            {
+                (tangent, normal)
+            }
+            _ => {
+                self.vertex_attributes
+                    .retain(|attribute| attribute.kind != VertexAttributeKind::Tangent);
+                return;
+            }
+        };
+
+        if self.vertex_count == 0 {
+            return;
+        }
+
+        let stride = self.vertex_buffer.len() / self.vertex_count as usize;
+
+        if stride < (bitangent_offset.max(normal_offset) + size_of::<[f32; 3]>()) {
+            return;
+        }
+
+        for vertex in self.vertex_buffer.chunks_exact_mut(stride) {
+            let normal = read_f32s::<3>(vertex, normal_offset);
+            let tangent = read_f32s::<3>(vertex, tangent_offset);
+            let bitangent = read_f32s::<3>(vertex, bitangent_offset);
+            let handedness = tangent_handedness(normal, tangent, bitangent);
+
+            vertex[bitangent_offset..bitangent_offset + 4]
+                .copy_from_slice(&handedness.to_le_bytes());
+        }
+    }
+}
+
+/// Decodes little-endian indices of the type.
+fn decode_indices(bytes: &[u8], index_type: VertexIndexType) -> Vec<u32> {
+    match index_type {
+        VertexIndexType::U8 => Vec::from_iter(bytes.iter().map(|&index| index as u32)),
+        VertexIndexType::U16 => Vec::from_iter(
+            bytes
+                .chunks_exact(2)
+                .map(|index| u16::from_le_bytes([index[0], index[1]]) as u32),
+        ),
+        VertexIndexType::U32 => Vec::from_iter(
+            bytes
+                .chunks_exact(4)
+                .map(|index| u32::from_le_bytes([index[0], index[1], index[2], index[3]])),
+        ),
+    }
+}
+
+/// Reads `N` little-endian floats from the bytes, starting at the offset.
+fn read_f32s<const N: usize>(bytes: &[u8], offset: usize) -> [f32; N] {
+    let mut values = [0f32; N];
+
+    for (index, value) in values.iter_mut().enumerate() {
+        let start = offset + index * size_of::<f32>();
+        *value = f32::from_le_bytes(bytes[start..start + 4].try_into().unwrap());
+    }
+
+    values
+}
+
+fn add3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot3(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// `w` of a tangent: `1` if the bitangent points along `cross(normal, tangent)`, `-1` if it is mirrored.
+fn tangent_handedness(normal: [f32; 3], tangent: [f32; 3], bitangent: [f32; 3]) -> f32 {
+    if dot3(cross3(normal, tangent), bitangent) < 0f32 {
+        -1f32
+    } else {
+        1f32
+    }
+}
+
+/// Tangent made orthogonal to the normal with its handedness in `w`. Falls back to any unit vector orthogonal to the
+/// normal if the tangent is zero or parallel to it, as around vertices of degenerate UVs.
+fn orthogonal_tangent(normal: [f32; 3], tangent: [f32; 3], bitangent: [f32; 3]) -> [f32; 4] {
+    let projected = sub3(
+        tangent,
+        [
+            normal[0] * dot3(normal, tangent),
+            normal[1] * dot3(normal, tangent),
+            normal[2] * dot3(normal, tangent),
+        ],
+    );
+    let length = dot3(projected, projected).sqrt();
+
+    if f32::EPSILON < length {
+        let handedness = tangent_handedness(normal, projected, bitangent);
+        return [
+            projected[0] / length,
+            projected[1] / length,
+            projected[2] / length,
+            handedness,
+        ];
+    }
+
+    let axis = if normal[0].abs() < 0.9 {
+        [1f32, 0f32, 0f32]
+    } else {
+        [0f32, 1f32, 0f32]
+    };
+    let fallback = cross3(axis, normal);
+    let length = dot3(fallback, fallback).sqrt().max(f32::EPSILON);
+
+    [
+        fallback[0] / length,
+        fallback[1] / length,
+        fallback[2] / length,
+        1f32,
+    ]
+}
+
+/// Normal of a counter-clockwise triangle, or zero if it is degenerate.
+fn face_normal(p0: [f32; 3], p1: [f32; 3], p2: [f32; 3]) -> [f32; 3] {
+    let a = [p1[0] - p0[0], p1[1] - p0[1], p1[2] - p0[2]];
+    let b = [p2[0] - p0[0], p2[1] - p0[1], p2[2] - p0[2]];
+    let cross = [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ];
+    let length = (cross[0] * cross[0] + cross[1] * cross[1] + cross[2] * cross[2]).sqrt();
+
+    if length <= f32::EPSILON {
+        return [0f32; 3];
+    }
+
+    [cross[0] / length, cross[1] / length, cross[2] / length]
 }
 
 pub type MeshMaterialSource = MeshMaterial;
@@ -109,6 +590,10 @@ pub struct ModelSource {
     pub root_node_index: Option<u32>,
     pub nodes: Vec<NodeSource>,
     pub meshes: Vec<MeshSource>,
+    #[serde(default)]
+    pub skeleton: Option<ModelSkeleton>,
+    #[serde(default)]
+    pub animations: Vec<ModelAnimation>,
 }
 
 impl AssetSource for ModelSource {
@@ -131,6 +616,11 @@ impl AssetSource for ModelSource {
             meshes: self
                 .meshes
                 .into_iter()
+                .map(|mut mesh| {
+                    mesh.fold_legacy_bitangents();
+                    mesh.generate_flat_normals();
+                    mesh
+                })
                 .map(|mesh| Mesh {
                     index: mesh.index,
                     aabb: mesh.aabb,
@@ -142,8 +632,20 @@ impl AssetSource for ModelSource {
                         .upload_vertex_buffer(BufferUsages::VERTEX, &mesh.vertex_buffer),
                     vertex_count: mesh.vertex_count,
                     material: mesh.material,
+                    lods: mesh
+                        .lods
+                        .into_iter()
+                        .map(|lod| MeshLod {
+                            ratio: lod.ratio,
+                            error: lod.error,
+                            index_buffer: gfx_bridge
+                                .upload_vertex_buffer(BufferUsages::INDEX, &lod.index_buffer),
+                        })
+                        .collect(),
                 })
                 .collect(),
+            skeleton: self.skeleton,
+            animations: self.animations,
         }))
     }
 }
@@ -153,6 +655,8 @@ struct Model {
     root_node_index: Option<u32>,
     nodes: Vec<Node>,
     meshes: Vec<Mesh>,
+    skeleton: Option<ModelSkeleton>,
+    animations: Vec<ModelAnimation>,
 }
 
 impl Asset for Model {
@@ -177,4 +681,184 @@ impl ModelAsset for Model {
     fn meshes(&self) -> &[Mesh] {
         &self.meshes
     }
+
+    fn skeleton(&self) -> Option<&ModelSkeleton> {
+        self.skeleton.as_ref()
+    }
+
+    fn animations(&self) -> &[ModelAnimation] {
+        &self.animations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad() -> MeshSource {
+        let positions: [[f32; 3]; 4] = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+        let indices: [u8; 6] = [0, 1, 2, 0, 2, 3];
+
+        MeshSource {
+            index: 0,
+            aabb: MeshAABB {
+                min: [0.0, 0.0, 0.0],
+                max: [1.0, 1.0, 0.0],
+            },
+            index_type: VertexIndexType::U8,
+            index_buffer: indices.to_vec(),
+            vertex_attributes: vec![VertexAttribute {
+                offset: 0,
+                kind: VertexAttributeKind::Position,
+            }],
+            vertex_buffer: Vec::from_iter(
+                positions
+                    .iter()
+                    .flatten()
+                    .flat_map(|value| value.to_le_bytes()),
+            ),
+            vertex_count: 4,
+            material: None,
+            lods: vec![MeshLodSource {
+                ratio: 0.5,
+                error: 0.0,
+                index_buffer: vec![0, 1, 2],
+            }],
+        }
+    }
+
+    #[test]
+    fn check_flat_normals_unweld_the_triangles() {
+        let mut mesh = quad();
+        mesh.generate_flat_normals();
+
+        assert_eq!(mesh.vertex_count, 9);
+        assert_eq!(mesh.index_type, VertexIndexType::U16);
+        assert_eq!(
+            decode_indices(&mesh.index_buffer, mesh.index_type),
+            vec![0, 1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            decode_indices(&mesh.lods[0].index_buffer, mesh.index_type),
+            vec![6, 7, 8]
+        );
+        assert_eq!(mesh.vertex_buffer.len(), 9 * 24);
+
+        let normal = mesh.vertex_attributes[1];
+        assert_eq!(normal.kind, VertexAttributeKind::Normal);
+        assert_eq!(normal.offset, 12);
+
+        for vertex in mesh.vertex_buffer.chunks_exact(24) {
+            let z = f32::from_le_bytes(vertex[20..24].try_into().unwrap());
+            assert_eq!(z, 1.0);
+        }
+    }
+
+    #[test]
+    fn check_meshes_with_normals_are_kept() {
+        let mut mesh = quad();
+        mesh.vertex_attributes.push(VertexAttribute {
+            offset: 0,
+            kind: VertexAttributeKind::Normal,
+        });
+        mesh.generate_flat_normals();
+
+        assert_eq!(mesh.vertex_count, 4);
+        assert_eq!(mesh.index_type, VertexIndexType::U8);
+    }
+
+    /// The quad with a normal along +Z and UVs, mirrored along U if `is_mirrored`.
+    fn textured_quad(is_mirrored: bool) -> MeshSource {
+        let mut mesh = quad();
+        let uvs: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+
+        mesh.vertex_buffer = Vec::from_iter(mesh.vertex_buffer.chunks_exact(12).zip(uvs).flat_map(
+            |(position, uv)| {
+                let u = if is_mirrored { 1.0 - uv[0] } else { uv[0] };
+                let mut vertex = position.to_vec();
+                vertex.extend(
+                    [0f32, 0f32, 1f32, u, uv[1]]
+                        .iter()
+                        .flat_map(|value| value.to_le_bytes()),
+                );
+                vertex
+            },
+        ));
+        mesh.vertex_attributes.extend([
+            VertexAttribute {
+                offset: 12,
+                kind: VertexAttributeKind::Normal,
+            },
+            VertexAttribute {
+                offset: 24,
+                kind: VertexAttributeKind::TexCoord { index: 0 },
+            },
+        ]);
+        mesh
+    }
+
+    #[test]
+    fn check_tangents_follow_the_uvs() {
+        let mut mesh = textured_quad(false);
+        mesh.generate_tangents();
+
+        let tangent = mesh.vertex_attributes[3];
+        assert_eq!(tangent.kind, VertexAttributeKind::Tangent);
+        assert_eq!(tangent.offset, 32);
+        assert_eq!(mesh.vertex_buffer.len(), 4 * 48);
+
+        for vertex in mesh.vertex_buffer.chunks_exact(48) {
+            assert_eq!(read_f32s::<4>(vertex, 32), [1.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn check_mirrored_uvs_flip_the_handedness() {
+        let mut mesh = textured_quad(true);
+        mesh.generate_tangents();
+
+        for vertex in mesh.vertex_buffer.chunks_exact(48) {
+            assert_eq!(read_f32s::<4>(vertex, 32), [-1.0, 0.0, 0.0, -1.0]);
+        }
+    }
+
+    #[test]
+    fn check_legacy_bitangents_are_folded() {
+        let mut mesh = textured_quad(false);
+        mesh.vertex_buffer =
+            Vec::from_iter(mesh.vertex_buffer.chunks_exact(32).flat_map(|vertex| {
+                let mut vertex = vertex.to_vec();
+                vertex.extend(
+                    [1f32, 0f32, 0f32, 0f32, -1f32, 0f32]
+                        .iter()
+                        .flat_map(|value| value.to_le_bytes()),
+                );
+                vertex
+            }));
+        mesh.vertex_attributes.extend([
+            VertexAttribute {
+                offset: 32,
+                kind: VertexAttributeKind::Tangent,
+            },
+            VertexAttribute {
+                offset: 44,
+                kind: VertexAttributeKind::Bitangent,
+            },
+        ]);
+        mesh.fold_legacy_bitangents();
+
+        assert!(mesh
+            .vertex_attributes
+            .iter()
+            .all(|attribute| attribute.kind != VertexAttributeKind::Bitangent));
+
+        for vertex in mesh.vertex_buffer.chunks_exact(56) {
+            assert_eq!(read_f32s::<4>(vertex, 32), [1.0, 0.0, 0.0, -1.0]);
+        }
+    }
 }
@@ -1,13 +1,133 @@
 use crate::{
-    Asset, AssetDepsProvider, AssetKey, AssetLoadError, AssetSource, GfxBridge, GfxSampler,
-    GfxTexture, GfxTextureView, TypedAsset,
+    Asset, AssetDepsProvider, AssetKey, AssetLoadError, AssetSource, BlockCompression, GfxBridge,
+    GfxSampler, GfxTexture, GfxTextureView, TypedAsset,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use thiserror::Error;
 
+/// Layout of the texels of a texture. The block-compressed formats store blocks of 4x4 texels, row by row.
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TextureFormat {
     RGBA8,
+    RGBA8Srgb,
+    /// RGB with 1-bit alpha, 8 bytes per block.
+    BC1,
+    BC1Srgb,
+    /// RGBA, 16 bytes per block.
+    BC3,
+    BC3Srgb,
+    /// Two channels, e.g. for normal maps; 16 bytes per block.
+    BC5,
+    /// High quality RGBA, 16 bytes per block.
+    BC7,
+    BC7Srgb,
+}
+
+impl TextureFormat {
+    /// The block compression of the format, or `None` if it is uncompressed.
+    pub fn block_compression(&self) -> Option<BlockCompression> {
+        match self {
+            Self::RGBA8 | Self::RGBA8Srgb => None,
+            Self::BC1 | Self::BC1Srgb => Some(BlockCompression::BC1),
+            Self::BC3 | Self::BC3Srgb => Some(BlockCompression::BC3),
+            Self::BC5 => Some(BlockCompression::BC5),
+            Self::BC7 | Self::BC7Srgb => Some(BlockCompression::BC7),
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.block_compression().is_some()
+    }
+
+    /// Whether the texels are sRGB-encoded and decoded when sampled.
+    pub fn is_srgb(&self) -> bool {
+        matches!(
+            self,
+            Self::RGBA8Srgb | Self::BC1Srgb | Self::BC3Srgb | Self::BC7Srgb
+        )
+    }
+
+    /// Width and height of a block of texels; 1x1 for uncompressed formats.
+    pub fn block_dimensions(&self) -> (u32, u32) {
+        if self.is_compressed() {
+            (4, 4)
+        } else {
+            (1, 1)
+        }
+    }
+
+    /// Size of a block of texels in bytes.
+    pub fn block_size(&self) -> u32 {
+        match self.block_compression() {
+            Some(compression) => compression.block_size() as u32,
+            None => 4,
+        }
+    }
+
+    /// Size of a row of blocks of a texture of the width, in bytes.
+    pub fn bytes_per_row(&self, width: u32) -> u32 {
+        width.div_ceil(self.block_dimensions().0) * self.block_size()
+    }
+
+    /// Number of rows of blocks of a texture of the height.
+    pub fn rows(&self, height: u32) -> u32 {
+        height.div_ceil(self.block_dimensions().1)
+    }
+
+    /// Size of the texels of a texture of the size, in bytes.
+    pub fn texel_data_size(&self, width: u32, height: u32) -> usize {
+        self.bytes_per_row(width) as usize * self.rows(height) as usize
+    }
+}
+
+/// Size of the mip level of a texture of the size. Each level is half the size of the previous one, rounded down
+/// but at least 1.
+pub fn mip_level_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TextureSourceError {
+    #[error("{format:?} texture of {width}x{height} texels must be a multiple of its block size")]
+    UnalignedSize {
+        format: TextureFormat,
+        width: u16,
+        height: u16,
+    },
+    #[error("mip level {level} of {format:?} texture of {width}x{height} texels needs {expected} bytes, but has {actual}")]
+    TexelSizeMismatch {
+        format: TextureFormat,
+        width: u16,
+        height: u16,
+        level: u32,
+        expected: usize,
+        actual: usize,
+    },
+    #[error(
+        "texture of {width}x{height} texels can have at most {max} mip levels, but has {count}"
+    )]
+    TooManyMipLevels {
+        width: u16,
+        height: u16,
+        count: u32,
+        max: u32,
+    },
+    #[error("texture must have at least one array layer")]
+    NoArrayLayers,
+    #[error("cube map must be square and have 6 layers per cube, but is {width}x{height} texels with {array_layers} layers")]
+    InvalidCubeMap {
+        width: u16,
+        height: u16,
+        array_layers: u32,
+    },
+    #[error("sprite `{name}` is defined more than once")]
+    DuplicateSpriteName { name: String },
+    #[error("nine-patch `{name}` is defined more than once")]
+    DuplicateNinePatchName { name: String },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -47,6 +167,32 @@ pub struct NinePatchTexelRange {
     pub max: u16,
 }
 
+/// How far UVs computed from texel ranges are pulled in from the edges of the ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TexelInset {
+    /// The UVs lie on the outer edges of the texels.
+    None,
+    /// The UVs lie on the centers of the outermost texels, so that neither point nor bilinear sampling picks up
+    /// the neighboring texels of an atlas.
+    HalfTexel,
+}
+
+impl TexelInset {
+    fn offset(self) -> f32 {
+        match self {
+            TexelInset::None => 0.0,
+            TexelInset::HalfTexel => 0.5,
+        }
+    }
+}
+
+/// Normalized UV min/max of the texels from `min` to `max` along an axis of the given size.
+fn uv_range(min: u16, max: u16, size: u16, inset: TexelInset) -> (f32, f32) {
+    let size = size as f32;
+    let offset = inset.offset();
+    ((min as f32 + offset) / size, (max as f32 - offset) / size)
+}
+
 /// Rectangular region of a texture.
 #[derive(Debug)]
 pub struct Sprite {
@@ -57,6 +203,25 @@ pub struct Sprite {
     pub texel_mapping: (SpriteTexelRange, SpriteTexelRange),
 }
 
+impl Sprite {
+    /// UVs of the sprite in a texture of the given size, as `[u_min, v_min, u_max, v_max]`.
+    pub fn uv_rect(&self, texture_width: u16, texture_height: u16, inset: TexelInset) -> [f32; 4] {
+        let (x, y) = self.texel_mapping;
+        let (u_min, u_max) = uv_range(x.min, x.max, texture_width, inset);
+        let (v_min, v_max) = uv_range(y.min, y.max, texture_height, inset);
+        [u_min, v_min, u_max, v_max]
+    }
+}
+
+/// Sizes of the border regions of a nine-patch in texels; the middle region is stretched in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NinePatchBorders {
+    pub left: u16,
+    pub right: u16,
+    pub bottom: u16,
+    pub top: u16,
+}
+
 /// Nine-patch region of a texture, in 3 by 3 grid.
 #[derive(Debug)]
 pub struct NinePatch {
@@ -67,6 +232,46 @@ pub struct NinePatch {
     pub texel_mapping: (NinePatchTexelRange, NinePatchTexelRange),
 }
 
+impl NinePatch {
+    /// UVs of the 9 regions in a texture of the given size, each as `[u_min, v_min, u_max, v_max]`.
+    /// The regions go left to right within a row, and the rows go from the one at `max` y to the one at `min` y,
+    /// the order the UI renderer draws them in. The inset applies to every region on its own.
+    pub fn uv_rects(
+        &self,
+        texture_width: u16,
+        texture_height: u16,
+        inset: TexelInset,
+    ) -> [[f32; 4]; 9] {
+        let (x, y) = self.texel_mapping;
+        let columns = [
+            uv_range(x.min, x.mid_min, texture_width, inset),
+            uv_range(x.mid_min, x.mid_max, texture_width, inset),
+            uv_range(x.mid_max, x.max, texture_width, inset),
+        ];
+        let rows = [
+            uv_range(y.mid_max, y.max, texture_height, inset),
+            uv_range(y.mid_min, y.mid_max, texture_height, inset),
+            uv_range(y.min, y.mid_min, texture_height, inset),
+        ];
+
+        std::array::from_fn(|index| {
+            let (u_min, u_max) = columns[index % 3];
+            let (v_min, v_max) = rows[index / 3];
+            [u_min, v_min, u_max, v_max]
+        })
+    }
+
+    pub fn borders(&self) -> NinePatchBorders {
+        let (x, y) = self.texel_mapping;
+        NinePatchBorders {
+            left: x.mid_min - x.min,
+            right: x.max - x.mid_max,
+            bottom: y.mid_min - y.min,
+            top: y.max - y.mid_max,
+        }
+    }
+}
+
 /// Represents a texture asset. It supplies texture parameters too.
 pub trait TextureAsset: Asset {
     fn handle(&self) -> &GfxTexture;
@@ -75,10 +280,15 @@ pub trait TextureAsset: Asset {
     fn width(&self) -> u16;
     fn height(&self) -> u16;
     fn format(&self) -> TextureFormat;
+    fn mip_level_count(&self) -> u32;
+    fn array_layers(&self) -> u32;
+    fn is_cube_map(&self) -> bool;
     fn filter_mode(&self) -> TextureFilterMode;
     fn address_mode(&self) -> (TextureAddressMode, TextureAddressMode);
     fn sprites(&self) -> &[Sprite];
     fn nine_patches(&self) -> &[NinePatch];
+    fn find_sprite(&self, name: &str) -> Option<&Sprite>;
+    fn find_nine_patch(&self, name: &str) -> Option<&NinePatch>;
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -104,11 +314,113 @@ pub struct TextureSource {
     pub format: TextureFormat,
     pub filter_mode: TextureFilterMode,
     pub address_mode: (TextureAddressMode, TextureAddressMode),
+    /// Texels of the base mip level, of all the array layers one after another.
     pub texels: Vec<u8>,
+    /// Texels of the mip levels below the base one, from the largest, laid out like `texels`.
+    #[serde(default)]
+    pub mip_levels: Vec<Vec<u8>>,
+    /// Number of array layers, 6 per cube map.
+    #[serde(default = "default_array_layers")]
+    pub array_layers: u32,
+    /// Whether each 6 layers are the faces of a cube map, in +X, -X, +Y, -Y, +Z, -Z order.
+    #[serde(default)]
+    pub is_cube_map: bool,
     pub sprites: Vec<SpriteSource>,
     pub nine_patches: Vec<NinePatchSource>,
 }
 
+fn default_array_layers() -> u32 {
+    1
+}
+
+impl TextureSource {
+    /// Number of mip levels, including the base one.
+    pub fn mip_level_count(&self) -> u32 {
+        1 + self.mip_levels.len() as u32
+    }
+
+    /// Texels of the mip levels from the base one.
+    pub fn levels(&self) -> impl Iterator<Item = &[u8]> {
+        std::iter::once(self.texels.as_slice()).chain(self.mip_levels.iter().map(Vec::as_slice))
+    }
+
+    /// Checks that each mip level has as many texels as the format needs for its size and the layers. Block-compressed
+    /// textures must also be a whole number of blocks wide and high, as GPUs do not sample partial blocks.
+    pub fn validate(&self) -> Result<(), TextureSourceError> {
+        let (block_width, block_height) = self.format.block_dimensions();
+
+        if self.width as u32 % block_width != 0 || self.height as u32 % block_height != 0 {
+            return Err(TextureSourceError::UnalignedSize {
+                format: self.format,
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        if self.array_layers == 0 {
+            return Err(TextureSourceError::NoArrayLayers);
+        }
+
+        if self.is_cube_map && (self.width != self.height || self.array_layers % 6 != 0) {
+            return Err(TextureSourceError::InvalidCubeMap {
+                width: self.width,
+                height: self.height,
+                array_layers: self.array_layers,
+            });
+        }
+
+        let max_mip_level_count = u16::BITS - self.width.max(self.height).leading_zeros();
+
+        if max_mip_level_count < self.mip_level_count() {
+            return Err(TextureSourceError::TooManyMipLevels {
+                width: self.width,
+                height: self.height,
+                count: self.mip_level_count(),
+                max: max_mip_level_count,
+            });
+        }
+
+        let mut sprite_names = HashSet::new();
+
+        for sprite in &self.sprites {
+            if !sprite_names.insert(sprite.name.as_str()) {
+                return Err(TextureSourceError::DuplicateSpriteName {
+                    name: sprite.name.clone(),
+                });
+            }
+        }
+
+        let mut nine_patch_names = HashSet::new();
+
+        for nine_patch in &self.nine_patches {
+            if !nine_patch_names.insert(nine_patch.name.as_str()) {
+                return Err(TextureSourceError::DuplicateNinePatchName {
+                    name: nine_patch.name.clone(),
+                });
+            }
+        }
+
+        for (level, texels) in self.levels().enumerate() {
+            let (width, height) =
+                mip_level_size(self.width as u32, self.height as u32, level as u32);
+            let expected = self.format.texel_data_size(width, height) * self.array_layers as usize;
+
+            if texels.len() != expected {
+                return Err(TextureSourceError::TexelSizeMismatch {
+                    format: self.format,
+                    width: self.width,
+                    height: self.height,
+                    level: level as u32,
+                    expected,
+                    actual: texels.len(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl AssetSource for TextureSource {
     type Asset = dyn TextureAsset;
 
@@ -122,10 +434,32 @@ impl AssetSource for TextureSource {
         _deps_provider: &dyn AssetDepsProvider,
         gfx_bridge: &dyn GfxBridge,
     ) -> Result<Arc<Self::Asset>, AssetLoadError> {
-        let handle = gfx_bridge.upload_texture(self.width, self.height, self.format, &self.texels);
-        let view_handle = gfx_bridge.create_texture_view(&handle);
+        self.validate()?;
+
+        let levels = Vec::from_iter(self.levels());
+        let handle = gfx_bridge.upload_texture(
+            self.width,
+            self.height,
+            self.array_layers,
+            self.format,
+            &levels,
+        );
+        let view_handle = gfx_bridge.create_texture_view(&handle, self.is_cube_map);
         let sampler_handle = gfx_bridge.create_sampler(self.filter_mode, self.address_mode);
 
+        let sprite_indices = self
+            .sprites
+            .iter()
+            .enumerate()
+            .map(|(index, sprite)| (sprite.name.clone(), index))
+            .collect();
+        let nine_patch_indices = self
+            .nine_patches
+            .iter()
+            .enumerate()
+            .map(|(index, nine_patch)| (nine_patch.name.clone(), index))
+            .collect();
+
         Ok(Arc::new(Texture {
             key,
             handle,
@@ -134,6 +468,9 @@ impl AssetSource for TextureSource {
             width: self.width,
             height: self.height,
             format: self.format,
+            mip_level_count: self.mip_level_count(),
+            array_layers: self.array_layers,
+            is_cube_map: self.is_cube_map,
             filter_mode: self.filter_mode,
             address_mode: self.address_mode,
             sprites: self
@@ -160,6 +497,8 @@ impl AssetSource for TextureSource {
                     texel_mapping: nine_patch.texel_mapping,
                 })
                 .collect(),
+            sprite_indices,
+            nine_patch_indices,
         }))
     }
 }
@@ -172,10 +511,15 @@ struct Texture {
     width: u16,
     height: u16,
     format: TextureFormat,
+    mip_level_count: u32,
+    array_layers: u32,
+    is_cube_map: bool,
     filter_mode: TextureFilterMode,
     address_mode: (TextureAddressMode, TextureAddressMode),
     sprites: Vec<Sprite>,
     nine_patches: Vec<NinePatch>,
+    sprite_indices: HashMap<String, usize>,
+    nine_patch_indices: HashMap<String, usize>,
 }
 
 impl Asset for Texture {
@@ -213,6 +557,18 @@ impl TextureAsset for Texture {
         self.format
     }
 
+    fn mip_level_count(&self) -> u32 {
+        self.mip_level_count
+    }
+
+    fn array_layers(&self) -> u32 {
+        self.array_layers
+    }
+
+    fn is_cube_map(&self) -> bool {
+        self.is_cube_map
+    }
+
     fn filter_mode(&self) -> TextureFilterMode {
         self.filter_mode
     }
@@ -228,4 +584,137 @@ impl TextureAsset for Texture {
     fn nine_patches(&self) -> &[NinePatch] {
         &self.nine_patches
     }
+
+    fn find_sprite(&self, name: &str) -> Option<&Sprite> {
+        self.sprite_indices
+            .get(name)
+            .map(|&index| &self.sprites[index])
+    }
+
+    fn find_nine_patch(&self, name: &str) -> Option<&NinePatch> {
+        self.nine_patch_indices
+            .get(name)
+            .map(|&index| &self.nine_patches[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(format: TextureFormat, width: u16, height: u16, texels: usize) -> TextureSource {
+        TextureSource {
+            width,
+            height,
+            format,
+            filter_mode: TextureFilterMode::Bilinear,
+            address_mode: (TextureAddressMode::Clamp, TextureAddressMode::Clamp),
+            texels: vec![0; texels],
+            mip_levels: Vec::new(),
+            array_layers: 1,
+            is_cube_map: false,
+            sprites: Vec::new(),
+            nine_patches: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn check_texel_size_is_validated_per_block() {
+        assert_eq!(
+            source(TextureFormat::RGBA8, 3, 5, 3 * 5 * 4).validate(),
+            Ok(())
+        );
+        assert_eq!(source(TextureFormat::BC1, 8, 4, 2 * 8).validate(), Ok(()));
+        assert_eq!(
+            source(TextureFormat::BC7Srgb, 8, 8, 4 * 16).validate(),
+            Ok(())
+        );
+
+        assert_eq!(
+            source(TextureFormat::BC3, 8, 8, 3 * 16).validate(),
+            Err(TextureSourceError::TexelSizeMismatch {
+                format: TextureFormat::BC3,
+                width: 8,
+                height: 8,
+                level: 0,
+                expected: 4 * 16,
+                actual: 3 * 16,
+            })
+        );
+        assert_eq!(
+            source(TextureFormat::BC1, 6, 4, 2 * 8).validate(),
+            Err(TextureSourceError::UnalignedSize {
+                format: TextureFormat::BC1,
+                width: 6,
+                height: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn check_mip_levels_and_layers_are_validated() {
+        // 8x8 BC1 has 4 levels of 4, 1, 1 and 1 blocks, each per layer.
+        let mut texture = source(TextureFormat::BC1, 8, 8, 4 * 8 * 6);
+        texture.array_layers = 6;
+        texture.is_cube_map = true;
+        texture.mip_levels = vec![vec![0; 8 * 6], vec![0; 8 * 6], vec![0; 8 * 6]];
+        assert_eq!(texture.validate(), Ok(()));
+
+        texture.mip_levels[1].pop();
+        assert!(matches!(
+            texture.validate(),
+            Err(TextureSourceError::TexelSizeMismatch { level: 2, .. })
+        ));
+
+        texture.mip_levels[1].push(0);
+        texture.mip_levels.push(vec![0; 8 * 6]);
+        assert_eq!(
+            texture.validate(),
+            Err(TextureSourceError::TooManyMipLevels {
+                width: 8,
+                height: 8,
+                count: 5,
+                max: 4,
+            })
+        );
+
+        let mut texture = source(TextureFormat::RGBA8, 2, 2, 2 * 2 * 4 * 4);
+        texture.array_layers = 4;
+        texture.is_cube_map = true;
+        assert!(matches!(
+            texture.validate(),
+            Err(TextureSourceError::InvalidCubeMap { .. })
+        ));
+    }
+
+    #[test]
+    fn check_uv_ranges_are_inset_by_half_texels() {
+        assert_eq!(uv_range(0, 4, 8, TexelInset::None), (0.0, 0.5));
+        assert_eq!(uv_range(0, 4, 8, TexelInset::HalfTexel), (0.0625, 0.4375));
+        assert_eq!(uv_range(6, 8, 8, TexelInset::HalfTexel), (0.8125, 0.9375));
+    }
+
+    #[test]
+    fn check_duplicate_names_are_rejected() {
+        let sprite = SpriteSource {
+            name: "icon".to_owned(),
+            filter_mode: TextureFilterMode::Point,
+            address_mode: (TextureAddressMode::Clamp, TextureAddressMode::Clamp),
+            texel_mapping: (
+                SpriteTexelRange { min: 0, max: 2 },
+                SpriteTexelRange { min: 0, max: 2 },
+            ),
+        };
+        let mut texture = source(TextureFormat::RGBA8, 4, 4, 4 * 4 * 4);
+        texture.sprites = vec![sprite.clone(), sprite.clone()];
+        assert_eq!(
+            texture.validate(),
+            Err(TextureSourceError::DuplicateSpriteName {
+                name: "icon".to_owned(),
+            })
+        );
+
+        texture.sprites[1].name = "cursor".to_owned();
+        assert_eq!(texture.validate(), Ok(()));
+    }
 }
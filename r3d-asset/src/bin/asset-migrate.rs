@@ -0,0 +1,48 @@
+//! Rewrites versioned assets to the format versions this engine loads.
+//!
+//! Usage: `asset-migrate migrate <dir>`
+//!
+//! Only the built-in asset types and their migrations are known to this tool, so games that register
+//! their own should call [`asset::AssetMigrations::migrate_directory`] from their tools instead.
+
+use asset::AssetMigrations;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let dir = match args.as_slice() {
+        [command, dir] if command == "migrate" => dir,
+        _ => {
+            eprintln!("usage: asset-migrate migrate <dir>");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match AssetMigrations::with_built_in_asset_types().migrate_directory(dir) {
+        Ok(result) => {
+            for path in &result.migrated {
+                println!("migrated {}", path.display());
+            }
+
+            for (path, err) in &result.failed {
+                eprintln!("failed to migrate {}: {}", path.display(), err);
+            }
+
+            println!(
+                "{} asset(s) migrated, {} failed",
+                result.migrated.len(),
+                result.failed.len()
+            );
+
+            if result.failed.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Err(err) => {
+            eprintln!("failed to migrate assets: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
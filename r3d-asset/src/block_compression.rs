@@ -0,0 +1,615 @@
+//! CPU decompression of block-compressed texels, for devices without the feature to sample them.
+
+/// Decompresses BC1, BC3, BC5 or BC7 blocks into RGBA8 texels, `width * height * 4` bytes in rows from the top.
+/// Blocks past the edges of sizes that are not multiples of 4 are cropped. Returns `None` if there are fewer blocks
+/// than the size needs.
+pub fn decompress_blocks(
+    kind: BlockCompression,
+    width: u32,
+    height: u32,
+    blocks: &[u8],
+) -> Option<Vec<u8>> {
+    let blocks_wide = width.div_ceil(4);
+    let blocks_high = height.div_ceil(4);
+    let block_size = kind.block_size();
+
+    if blocks.len() < (blocks_wide * blocks_high) as usize * block_size {
+        return None;
+    }
+
+    let mut texels = vec![0; (width * height * 4) as usize];
+    let mut decoded = [[0u8; 4]; 16];
+
+    for block_y in 0..blocks_high {
+        for block_x in 0..blocks_wide {
+            let offset = (block_y * blocks_wide + block_x) as usize * block_size;
+            let block = &blocks[offset..offset + block_size];
+
+            match kind {
+                BlockCompression::BC1 => decode_bc1(block, &mut decoded),
+                BlockCompression::BC3 => decode_bc3(block, &mut decoded),
+                BlockCompression::BC5 => decode_bc5(block, &mut decoded),
+                BlockCompression::BC7 => decode_bc7(block, &mut decoded),
+            }
+
+            for y in 0..4 {
+                let texel_y = block_y * 4 + y;
+
+                if height <= texel_y {
+                    break;
+                }
+
+                for x in 0..4 {
+                    let texel_x = block_x * 4 + x;
+
+                    if width <= texel_x {
+                        break;
+                    }
+
+                    let index = ((texel_y * width + texel_x) * 4) as usize;
+                    texels[index..index + 4].copy_from_slice(&decoded[(y * 4 + x) as usize]);
+                }
+            }
+        }
+    }
+
+    Some(texels)
+}
+
+/// Block-compressed formats [`decompress_blocks`] decodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockCompression {
+    BC1,
+    BC3,
+    BC5,
+    BC7,
+}
+
+impl BlockCompression {
+    /// Size of a block of 4x4 texels, in bytes.
+    pub fn block_size(self) -> usize {
+        match self {
+            Self::BC1 => 8,
+            Self::BC3 | Self::BC5 | Self::BC7 => 16,
+        }
+    }
+}
+
+fn decode_bc1(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_color_block(block, true, texels);
+}
+
+fn decode_bc3(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_color_block(&block[8..16], false, texels);
+
+    let mut alpha = [0u8; 16];
+    decode_channel_block(&block[0..8], &mut alpha);
+
+    for (texel, alpha) in texels.iter_mut().zip(alpha) {
+        texel[3] = alpha;
+    }
+}
+
+fn decode_bc5(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    let mut red = [0u8; 16];
+    let mut green = [0u8; 16];
+    decode_channel_block(&block[0..8], &mut red);
+    decode_channel_block(&block[8..16], &mut green);
+
+    for (index, texel) in texels.iter_mut().enumerate() {
+        *texel = [red[index], green[index], 0, 255];
+    }
+}
+
+/// Decodes the color part of BC1 to BC3 blocks. Only BC1 blocks switch to 3 colors and transparent black when the
+/// first endpoint is not greater than the second.
+fn decode_color_block(block: &[u8], allow_alpha: bool, texels: &mut [[u8; 4]; 16]) {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    let rgb0 = expand_rgb565(color0);
+    let rgb1 = expand_rgb565(color1);
+    let mix = |a: u8, b: u8, wa: u32, wb: u32| ((a as u32 * wa + b as u32 * wb) / (wa + wb)) as u8;
+
+    let mut palette = [[0u8; 4]; 4];
+    palette[0] = [rgb0[0], rgb0[1], rgb0[2], 255];
+    palette[1] = [rgb1[0], rgb1[1], rgb1[2], 255];
+
+    if color1 < color0 || !allow_alpha {
+        for channel in 0..3 {
+            palette[2][channel] = mix(rgb0[channel], rgb1[channel], 2, 1);
+            palette[3][channel] = mix(rgb0[channel], rgb1[channel], 1, 2);
+        }
+        palette[2][3] = 255;
+        palette[3][3] = 255;
+    } else {
+        for channel in 0..3 {
+            palette[2][channel] = mix(rgb0[channel], rgb1[channel], 1, 1);
+        }
+        palette[2][3] = 255;
+        palette[3] = [0, 0, 0, 0];
+    }
+
+    for (index, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (index * 2)) & 0b11) as usize];
+    }
+}
+
+/// Decodes a single channel block, i.e. the alpha of BC3 blocks or a channel of BC4 and BC5 blocks.
+fn decode_channel_block(block: &[u8], values: &mut [u8; 16]) {
+    let value0 = block[0] as u32;
+    let value1 = block[1] as u32;
+    let indices = block[2..8]
+        .iter()
+        .rev()
+        .fold(0u64, |indices, &byte| (indices << 8) | byte as u64);
+
+    let mut palette = [0u8; 8];
+    palette[0] = value0 as u8;
+    palette[1] = value1 as u8;
+
+    if value1 < value0 {
+        for index in 1..7 {
+            palette[index + 1] = ((value0 * (7 - index as u32) + value1 * index as u32) / 7) as u8;
+        }
+    } else {
+        for index in 1..5 {
+            palette[index + 1] = ((value0 * (5 - index as u32) + value1 * index as u32) / 5) as u8;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+
+    for (index, value) in values.iter_mut().enumerate() {
+        *value = palette[((indices >> (index * 3)) & 0b111) as usize];
+    }
+}
+
+fn expand_rgb565(color: u16) -> [u8; 3] {
+    let r = ((color >> 11) & 0x1f) as u8;
+    let g = ((color >> 5) & 0x3f) as u8;
+    let b = (color & 0x1f) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32,
+}
+
+#[rustfmt::skip]
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode { subsets: 3, partition_bits: 4, rotation_bits: 0, index_selection_bits: 0, color_bits: 4, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 6, alpha_bits: 0, endpoint_pbits: false, shared_pbits: true, index_bits: 3, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 3, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 0, endpoint_pbits: false, shared_pbits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 0, endpoint_pbits: true, shared_pbits: false, index_bits: 2, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 1, color_bits: 5, alpha_bits: 6, endpoint_pbits: false, shared_pbits: false, index_bits: 2, secondary_index_bits: 3 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 2, index_selection_bits: 0, color_bits: 7, alpha_bits: 8, endpoint_pbits: false, shared_pbits: false, index_bits: 2, secondary_index_bits: 2 },
+    Bc7Mode { subsets: 1, partition_bits: 0, rotation_bits: 0, index_selection_bits: 0, color_bits: 7, alpha_bits: 7, endpoint_pbits: true, shared_pbits: false, index_bits: 4, secondary_index_bits: 0 },
+    Bc7Mode { subsets: 2, partition_bits: 6, rotation_bits: 0, index_selection_bits: 0, color_bits: 5, alpha_bits: 5, endpoint_pbits: true, shared_pbits: false, index_bits: 2, secondary_index_bits: 0 },
+];
+
+/// Subset 1 texels of the 2-subset partitions, a bit per texel.
+#[rustfmt::skip]
+const BC7_PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80,
+    0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000,
+    0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c,
+    0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a,
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+#[rustfmt::skip]
+const BC7_PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+    [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+    [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+    [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+    [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+    [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+    [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+    [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+    [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+    [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+    [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+    [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// Anchor texel of subset 1 of the 2-subset partitions. The anchor of subset 0 is always texel 0.
+#[rustfmt::skip]
+const BC7_ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Anchor texels of subsets 1 and 2 of the 3-subset partitions.
+#[rustfmt::skip]
+const BC7_ANCHORS_3: [[u8; 64]; 2] = [
+    [
+        3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3,
+        3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15,
+        8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15,
+        3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+    ],
+    [
+        15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8,
+        15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+        15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8,
+        15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+    ],
+];
+
+const BC7_WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn read(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+
+        for bit in 0..count {
+            let position = self.position + bit;
+            let byte = self.bytes[(position / 8) as usize];
+            value |= (((byte >> (position % 8)) & 1) as u32) << bit;
+        }
+
+        self.position += count;
+        value
+    }
+}
+
+fn decode_bc7(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    let mode_index = match block[0].trailing_zeros() {
+        // Reserved mode; decoders output transparent black for it.
+        8 => {
+            *texels = [[0; 4]; 16];
+            return;
+        }
+        mode_index => mode_index as usize,
+    };
+    let mode = &BC7_MODES[mode_index];
+    let mut reader = BitReader {
+        bytes: block,
+        position: mode_index as u32 + 1,
+    };
+
+    let partition = reader.read(mode.partition_bits) as usize;
+    let rotation = reader.read(mode.rotation_bits);
+    let index_selection = reader.read(mode.index_selection_bits);
+
+    // Endpoints of the subsets, channel by channel.
+    let mut endpoints = [[[0u32; 4]; 2]; 3];
+
+    for channel in 0..3 {
+        for subset in endpoints.iter_mut().take(mode.subsets) {
+            for endpoint in subset.iter_mut() {
+                endpoint[channel] = reader.read(mode.color_bits);
+            }
+        }
+    }
+
+    if mode.alpha_bits != 0 {
+        for subset in endpoints.iter_mut().take(mode.subsets) {
+            for endpoint in subset.iter_mut() {
+                endpoint[3] = reader.read(mode.alpha_bits);
+            }
+        }
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+
+    if mode.endpoint_pbits || mode.shared_pbits {
+        let mut pbits = [[0u32; 2]; 3];
+
+        for subset in pbits.iter_mut().take(mode.subsets) {
+            if mode.shared_pbits {
+                let pbit = reader.read(1);
+                *subset = [pbit, pbit];
+            } else {
+                *subset = [reader.read(1), reader.read(1)];
+            }
+        }
+
+        for (subset, pbits) in endpoints.iter_mut().zip(pbits).take(mode.subsets) {
+            for (endpoint, pbit) in subset.iter_mut().zip(pbits) {
+                for value in endpoint.iter_mut() {
+                    *value = (*value << 1) | pbit;
+                }
+            }
+        }
+
+        color_bits += 1;
+
+        if alpha_bits != 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    for subset in endpoints.iter_mut().take(mode.subsets) {
+        for endpoint in subset.iter_mut() {
+            for value in endpoint.iter_mut().take(3) {
+                *value = unquantize(*value, color_bits);
+            }
+
+            endpoint[3] = if alpha_bits == 0 {
+                255
+            } else {
+                unquantize(endpoint[3], alpha_bits)
+            };
+        }
+    }
+
+    let subset_of = |texel: usize| -> usize {
+        match mode.subsets {
+            2 => ((BC7_PARTITIONS_2[partition] >> texel) & 1) as usize,
+            3 => BC7_PARTITIONS_3[partition][texel] as usize,
+            _ => 0,
+        }
+    };
+    let is_anchor = |texel: usize| -> bool {
+        texel == 0
+            || match mode.subsets {
+                2 => texel == BC7_ANCHORS_2[partition] as usize,
+                3 => {
+                    texel == BC7_ANCHORS_3[0][partition] as usize
+                        || texel == BC7_ANCHORS_3[1][partition] as usize
+                }
+                _ => false,
+            }
+    };
+
+    // Anchor texels have their most significant index bit omitted, as it is always zero.
+    let mut indices = [0u32; 16];
+
+    for (texel, index) in indices.iter_mut().enumerate() {
+        *index = reader.read(mode.index_bits - is_anchor(texel) as u32);
+    }
+
+    let mut secondary_indices = [0u32; 16];
+
+    if mode.secondary_index_bits != 0 {
+        for (texel, index) in secondary_indices.iter_mut().enumerate() {
+            *index = reader.read(mode.secondary_index_bits - (texel == 0) as u32);
+        }
+    }
+
+    for (texel, output) in texels.iter_mut().enumerate() {
+        let [endpoint0, endpoint1] = endpoints[subset_of(texel)];
+        let mut color = [0u32; 4];
+
+        if mode.secondary_index_bits == 0 {
+            let weight = weight(mode.index_bits, indices[texel]);
+
+            for channel in 0..4 {
+                color[channel] = interpolate(endpoint0[channel], endpoint1[channel], weight);
+            }
+        } else {
+            // Modes 4 and 5 interpolate color and alpha with separate indices; the selection bit swaps them.
+            let (color_index, color_index_bits, alpha_index, alpha_index_bits) =
+                if index_selection == 0 {
+                    (
+                        indices[texel],
+                        mode.index_bits,
+                        secondary_indices[texel],
+                        mode.secondary_index_bits,
+                    )
+                } else {
+                    (
+                        secondary_indices[texel],
+                        mode.secondary_index_bits,
+                        indices[texel],
+                        mode.index_bits,
+                    )
+                };
+            let color_weight = weight(color_index_bits, color_index);
+            let alpha_weight = weight(alpha_index_bits, alpha_index);
+
+            for channel in 0..3 {
+                color[channel] = interpolate(endpoint0[channel], endpoint1[channel], color_weight);
+            }
+
+            color[3] = interpolate(endpoint0[3], endpoint1[3], alpha_weight);
+        }
+
+        match rotation {
+            1 => color.swap(0, 3),
+            2 => color.swap(1, 3),
+            3 => color.swap(2, 3),
+            _ => {}
+        }
+
+        *output = color.map(|channel| channel as u8);
+    }
+}
+
+fn unquantize(value: u32, bits: u32) -> u32 {
+    let value = value << (8 - bits);
+    value | (value >> bits)
+}
+
+fn weight(index_bits: u32, index: u32) -> u32 {
+    match index_bits {
+        2 => BC7_WEIGHTS_2[index as usize],
+        3 => BC7_WEIGHTS_3[index as usize],
+        _ => BC7_WEIGHTS_4[index as usize],
+    }
+}
+
+fn interpolate(endpoint0: u32, endpoint1: u32, weight: u32) -> u32 {
+    ((64 - weight) * endpoint0 + weight * endpoint1 + 32) >> 6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_bc1_blocks_decode_to_their_palette() {
+        // Red and blue endpoints; texel 0 takes the first, texel 1 the second, texel 2 the color between them.
+        let block = [0x00, 0xf8, 0x1f, 0x00, 0b0010_0100, 0, 0, 0];
+        let texels = decompress_blocks(BlockCompression::BC1, 4, 4, &block).unwrap();
+
+        assert_eq!(&texels[0..4], &[255, 0, 0, 255]);
+        assert_eq!(&texels[4..8], &[0, 0, 255, 255]);
+        assert_eq!(&texels[8..12], &[170, 0, 85, 255]);
+        assert_eq!(&texels[12..16], &[255, 0, 0, 255]);
+
+        // The endpoints in ascending order select 3 colors and transparent black.
+        let block = [0x1f, 0x00, 0x00, 0xf8, 0b0000_1110, 0, 0, 0];
+        let texels = decompress_blocks(BlockCompression::BC1, 4, 4, &block).unwrap();
+
+        assert_eq!(&texels[0..4], &[127, 0, 127, 255]);
+        assert_eq!(&texels[4..8], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn check_blocks_are_cropped_to_the_size() {
+        // A 5x1 texture takes 2 blocks, of which the second contributes a single texel.
+        let mut blocks = [0u8; 16];
+        blocks[0..4].copy_from_slice(&[0x00, 0xf8, 0x00, 0xf8]);
+        blocks[8..12].copy_from_slice(&[0x1f, 0x00, 0x1f, 0x00]);
+        let texels = decompress_blocks(BlockCompression::BC1, 5, 1, &blocks).unwrap();
+
+        assert_eq!(texels.len(), 5 * 4);
+        assert_eq!(&texels[12..16], &[255, 0, 0, 255]);
+        assert_eq!(&texels[16..20], &[0, 0, 255, 255]);
+        assert!(decompress_blocks(BlockCompression::BC1, 5, 1, &blocks[..8]).is_none());
+    }
+
+    #[test]
+    fn check_bc5_blocks_decode_into_red_and_green() {
+        let mut block = [0u8; 16];
+        block[0..2].copy_from_slice(&[200, 100]);
+        block[8..10].copy_from_slice(&[10, 20]);
+        // Texel 0 of the red channel takes the second endpoint.
+        block[2] = 0b001;
+        let texels = decompress_blocks(BlockCompression::BC5, 4, 4, &block).unwrap();
+
+        assert_eq!(&texels[0..4], &[100, 10, 0, 255]);
+        assert_eq!(&texels[4..8], &[200, 10, 0, 255]);
+    }
+
+    #[test]
+    fn check_bc7_mode_6_blocks_interpolate_endpoints() {
+        // Mode 6: 7-bit RGBA endpoints with a p-bit each, and 4-bit indices.
+        let mut bits = Vec::new();
+        let mut push = |value: u32, count: u32| {
+            for bit in 0..count {
+                bits.push((value >> bit) & 1);
+            }
+        };
+        push(1 << 6, 7);
+        for (value0, value1) in [(0, 127), (0, 0), (127, 0), (127, 127)] {
+            push(value0, 7);
+            push(value1, 7);
+        }
+        push(0, 1);
+        push(1, 1);
+        // Texel 0 is the anchor with 3 bits, and takes endpoint 0; texel 1 takes endpoint 1.
+        push(0, 3);
+        push(15, 4);
+        for _ in 2..16 {
+            push(8, 4);
+        }
+
+        let mut block = [0u8; 16];
+        for (position, bit) in bits.iter().enumerate() {
+            block[position / 8] |= (*bit as u8) << (position % 8);
+        }
+
+        let texels = decompress_blocks(BlockCompression::BC7, 4, 4, &block).unwrap();
+
+        assert_eq!(&texels[0..4], &[0, 0, 254, 254]);
+        assert_eq!(&texels[4..8], &[255, 1, 1, 255]);
+        assert_eq!(&texels[8..12], &[135, 1, 120, 255]);
+    }
+
+    #[test]
+    fn check_bc7_partition_anchors_are_in_their_subsets() {
+        for partition in 0..64 {
+            let anchor = BC7_ANCHORS_2[partition];
+            assert_eq!((BC7_PARTITIONS_2[partition] >> anchor) & 1, 1);
+
+            for (subset, anchors) in BC7_ANCHORS_3.iter().enumerate() {
+                let anchor = anchors[partition] as usize;
+                assert_eq!(BC7_PARTITIONS_3[partition][anchor] as usize, subset + 1);
+            }
+        }
+    }
+}
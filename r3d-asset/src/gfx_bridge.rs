@@ -16,16 +16,17 @@ pub trait GfxBridge {
     /// Compiles a shader and returns a handle to it.
     fn compile_shader(&self, source: ShaderSource) -> GfxShaderModule;
     /// Uploads a texture to the GPU and returns a handle to it.
-    // TODO: add support for mipmaps
+    /// `levels` are the texels of the mip levels from the base one, each of all the array layers one after another.
     fn upload_texture(
         &self,
         width: u16,
         height: u16,
+        array_layers: u32,
         format: TextureFormat,
-        texels: &[u8],
+        levels: &[&[u8]],
     ) -> GfxTexture;
-    /// Creates a texture view from a texture.
-    fn create_texture_view(&self, texture: &wgpu::Texture) -> GfxTextureView;
+    /// Creates a texture view from a texture; a cube view if it is a cube map.
+    fn create_texture_view(&self, texture: &wgpu::Texture, is_cube_map: bool) -> GfxTextureView;
     /// Creates a sampler.
     fn create_sampler(
         &self,
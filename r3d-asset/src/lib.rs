@@ -1,12 +1,16 @@
 mod asset;
 mod asset_deps_provider;
 mod asset_key;
+mod asset_migration;
 mod asset_source;
 pub mod assets;
+mod block_compression;
 mod gfx_bridge;
 
 pub use asset::*;
 pub use asset_deps_provider::*;
 pub use asset_key::*;
+pub use asset_migration::*;
 pub use asset_source::*;
+pub use block_compression::*;
 pub use gfx_bridge::*;
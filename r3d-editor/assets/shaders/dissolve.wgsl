@@ -0,0 +1,81 @@
+// Lit mesh that dissolves through a hashed noise pattern, glowing along the edge.
+
+@group(0) @binding(0) var<uniform> camera_transform: mat4x4<f32>;
+
+struct InstanceInput {
+  @location(0) transform_row_0: vec4<f32>,
+  @location(1) transform_row_1: vec4<f32>,
+  @location(2) transform_row_2: vec4<f32>,
+  @location(3) transform_row_3: vec4<f32>,
+  // 0: intact, 1: fully dissolved.
+  @location(4) dissolve: f32,
+  @location(5) emissive_strength: f32,
+  @location(6) edge_color: vec4<f32>,
+};
+
+struct VertexInput {
+  @location(7) position: vec3<f32>,
+  @location(8) normal: vec3<f32>,
+  @location(9) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) world_normal: vec3<f32>,
+  @location(1) uv: vec2<f32>,
+  @location(2) dissolve: f32,
+  @location(3) emissive_strength: f32,
+  @location(4) edge_color: vec4<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+const EDGE_WIDTH: f32 = 0.05;
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let transform = mat4x4<f32>(instance.transform_row_0, instance.transform_row_1, instance.transform_row_2, instance.transform_row_3);
+  out.position = camera_transform * transform * vec4<f32>(vertex.position, 1.0);
+  out.world_normal = (transform * vec4<f32>(vertex.normal, 0.0)).xyz;
+  out.uv = vertex.uv;
+  out.dissolve = instance.dissolve;
+  out.emissive_strength = instance.emissive_strength;
+  out.edge_color = instance.edge_color;
+  return out;
+}
+
+fn hash(p: vec2<f32>) -> f32 {
+  return fract(sin(dot(p, vec2<f32>(12.9898, 78.233))) * 43758.5453);
+}
+
+// Value noise, smooth enough for the edge to read as a shape.
+fn noise(p: vec2<f32>) -> f32 {
+  let i = floor(p);
+  let f = fract(p);
+  let u = f * f * (3.0 - 2.0 * f);
+  let a = hash(i);
+  let b = hash(i + vec2<f32>(1.0, 0.0));
+  let c = hash(i + vec2<f32>(0.0, 1.0));
+  let d = hash(i + vec2<f32>(1.0, 1.0));
+  return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  let pattern = noise(in.uv * 16.0);
+
+  if pattern < in.dissolve {
+    discard;
+  }
+
+  let light = max(dot(normalize(in.world_normal), normalize(vec3<f32>(0.4, 1.0, 0.6))), 0.0);
+  let albedo = vec3<f32>(0.8, 0.8, 0.8) * (0.2 + 0.8 * light);
+  let edge = 1.0 - smoothstep(0.0, EDGE_WIDTH, pattern - in.dissolve);
+
+  var out: FragmentOutput;
+  out.color = vec4<f32>(albedo + in.edge_color.rgb * edge * in.emissive_strength, 1.0);
+  return out;
+}
@@ -0,0 +1,61 @@
+// Blends four layers of a ground texture array, weighted by the RGBA channels of a splat map.
+// The layer indices are looked up by name on the host, e.g. `ground_layers.layer("grass")`.
+struct Splat {
+  // Array layers blended by the R, G, B and A channels of the splat map.
+  layers: vec4<u32>,
+  // xy: how many times the ground textures repeat over the terrain.
+  tiling: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera_transform: mat4x4<f32>;
+@group(1) @binding(0) var<uniform> splat: Splat;
+@group(1) @binding(1) var splat_map: texture_2d<f32>;
+@group(1) @binding(2) var splat_sampler: sampler;
+@group(2) @binding(0) var ground_layers: texture_2d_array<f32>;
+@group(2) @binding(1) var ground_sampler: sampler;
+
+struct InstanceInput {
+  @location(0) transform_row_0: vec4<f32>,
+  @location(1) transform_row_1: vec4<f32>,
+  @location(2) transform_row_2: vec4<f32>,
+  @location(3) transform_row_3: vec4<f32>,
+};
+
+struct VertexInput {
+  @location(4) position: vec3<f32>,
+  @location(5) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let transform = mat4x4<f32>(instance.transform_row_0, instance.transform_row_1, instance.transform_row_2, instance.transform_row_3);
+  out.position = camera_transform * transform * vec4<f32>(vertex.position, 1.0);
+  out.uv = vertex.uv;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  let weights = textureSample(splat_map, splat_sampler, in.uv);
+  let total = max(weights.r + weights.g + weights.b + weights.a, 0.0001);
+  let uv = in.uv * splat.tiling.xy;
+
+  let color = textureSample(ground_layers, ground_sampler, uv, splat.layers.x) * weights.r
+    + textureSample(ground_layers, ground_sampler, uv, splat.layers.y) * weights.g
+    + textureSample(ground_layers, ground_sampler, uv, splat.layers.z) * weights.b
+    + textureSample(ground_layers, ground_sampler, uv, splat.layers.w) * weights.a;
+
+  out.color = vec4<f32>(color.rgb / total, 1.0);
+  return out;
+}
@@ -0,0 +1,183 @@
+//! A ring of models drawn by three stacked cameras: the scene, a spinning marker layered additively over it as a
+//! stand-in for a UI, and a black overlay whose opacity fades in and out every few seconds.
+//!
+//! Usage: `cargo run -p editor --release --example camera_stacking -- <model with uvs>`
+//!
+//! Each camera draws into its own slot of the stack, so the overlay fades the UI along with the scene. Blooming the UI
+//! alone would add an effect to its slot.
+
+use pollster::FutureExt;
+use r3d::{
+    event::{event_types, EventHandler},
+    gfx::{
+        Camera, CameraBlendMode, CameraClearMode, CameraPerspectiveProjectionAspect,
+        CameraProjection, CameraStackEntry, CameraStackSlot, Color, Material, MaterialHandle, Mesh,
+        MeshHandle, MeshRenderer, PerInstancePropertyValue,
+    },
+    math::{Quat, Vec3},
+    russimp::scene::{PostProcess, Scene},
+    specs::Builder,
+    transform::{Transform, TransformComponent},
+    use_context, Engine, EngineConfig, EngineLoopMode, EngineTargetFps,
+};
+use std::{f32::consts::TAU, time::Instant};
+
+const USAGE: &str = "usage: camera_stacking <model with uvs>";
+const STACK: &str = "main";
+const SCENE_MASK: u32 = 0b01;
+const UI_MASK: u32 = 0b10;
+
+fn main() {
+    let model_path = std::env::args().nth(1).expect(USAGE);
+
+    let config = EngineConfig::from_args_and_env(EngineConfig {
+        title: "camera stacking".to_owned(),
+        resizable: true,
+        width: 1280,
+        height: 720,
+        ..Default::default()
+    })
+    .unwrap();
+    let engine = Engine::new(config).block_on().unwrap();
+    let ctx = engine.context();
+
+    ctx.render_mgr_mut().define_stack(
+        STACK,
+        vec![
+            CameraStackEntry::new(CameraBlendMode::AlphaOver),
+            CameraStackEntry::new(CameraBlendMode::Additive).with_opacity(0.8),
+            CameraStackEntry::new(CameraBlendMode::AlphaOver).with_opacity(0.0),
+        ],
+    );
+
+    let shader = ctx
+        .shader_mgr()
+        .create_shader(
+            ctx.render_mgr_mut().bind_group_layout_cache(),
+            std::fs::read_to_string("r3d-editor/assets/shaders/dissolve.wgsl").unwrap(),
+        )
+        .unwrap();
+    let material = MaterialHandle::new(Material::new(
+        shader,
+        ctx.render_mgr_mut().pipeline_layout_cache(),
+    ));
+    material.write().set_per_instance_property(
+        "edge_color",
+        PerInstancePropertyValue::Float32x4([1.0, 1.0, 1.0, 1.0]),
+    );
+
+    let scene = Scene::from_file(
+        &model_path,
+        vec![
+            PostProcess::Triangulate,
+            PostProcess::GenerateNormals,
+            PostProcess::FlipUVs,
+        ],
+    )
+    .unwrap();
+    let mesh = MeshHandle::new(Mesh::new(
+        scene
+            .meshes
+            .into_iter()
+            .next()
+            .expect("the model has no mesh"),
+    ));
+
+    let create_camera = |mask: u32, depth: u32, clear_mode: CameraClearMode, slot: usize| {
+        let mut camera = Camera::new(
+            mask,
+            depth,
+            clear_mode,
+            CameraProjection::perspective(
+                60.0,
+                CameraPerspectiveProjectionAspect::Screen,
+                0.1,
+                1000.0,
+            ),
+            &ctx.gfx_ctx().device,
+            ctx.render_mgr_mut().bind_group_layout_cache(),
+        );
+        camera.stack = Some(CameraStackSlot::new(STACK, slot));
+        camera
+    };
+    let scene_camera = create_camera(
+        SCENE_MASK,
+        0,
+        CameraClearMode::all(Color::parse_hex("203040").unwrap(), 1.0, 0),
+        0,
+    );
+    // Keeping the color, the first camera of a slot starts from a transparent layer.
+    let ui_camera = create_camera(UI_MASK, 1, CameraClearMode::depth_only(1.0, 0), 1);
+    // Draws nothing: the overlay is its clear color, shown through the opacity of its slot.
+    let fade_camera = create_camera(0, 2, CameraClearMode::all(Color::black(), 1.0, 0), 2);
+
+    let marker_object = {
+        let mut object_mgr = ctx.object_mgr_mut();
+        let mut world = ctx.world_mut();
+
+        // A camera per slot, all seeing from the same place.
+        for (name, camera) in [
+            ("scene camera", scene_camera),
+            ("ui camera", ui_camera),
+            ("fade camera", fade_camera),
+        ] {
+            let mut transform = Transform::new();
+            transform.position = Vec3::new(0.0, 4.0, 24.0);
+            let (_, builder) = object_mgr.create_object_builder(
+                &mut world,
+                Some(name.to_owned()),
+                Some(transform),
+            );
+            builder.with(camera).build();
+        }
+
+        for index in 0..12 {
+            let mut mesh_renderer = MeshRenderer::new();
+            mesh_renderer.set_mask(SCENE_MASK);
+            mesh_renderer.set_material(material.clone());
+            mesh_renderer.set_mesh(mesh.clone(), &ctx.gfx_ctx().device);
+
+            let angle = TAU * index as f32 / 12.0;
+            let mut transform = Transform::new();
+            transform.position = Vec3::new(angle.cos() * 12.0, 0.0, angle.sin() * 12.0);
+            let (_, builder) = object_mgr.create_object_builder(&mut world, None, Some(transform));
+            builder.with(mesh_renderer).build();
+        }
+
+        let mut mesh_renderer = MeshRenderer::new();
+        mesh_renderer.set_mask(UI_MASK);
+        mesh_renderer.set_material(material);
+        mesh_renderer.set_mesh(mesh, &ctx.gfx_ctx().device);
+
+        let mut transform = Transform::new();
+        transform.position = Vec3::new(0.0, 4.0, 16.0);
+        let (marker_object, builder) = object_mgr.create_object_builder(
+            &mut world,
+            Some("marker".to_owned()),
+            Some(transform),
+        );
+        builder.with(mesh_renderer).build();
+        marker_object
+    };
+
+    let start = Instant::now();
+    ctx.event_mgr()
+        .add_handler(EventHandler::<event_types::Update>::new(move |_| {
+            let ctx = use_context();
+            let time = start.elapsed().as_secs_f32();
+
+            marker_object
+                .component::<TransformComponent>()
+                .set_rotation(Quat::from_axis_angle(Vec3::UP, time));
+
+            // Fades to black and back every 6 seconds.
+            let fade = (1.0 - (time * TAU / 6.0).cos()) * 0.5;
+            if let Some(stack) = ctx.render_mgr_mut().camera_stacks_mut().stack_mut(STACK) {
+                stack.entries_mut()[2].opacity = fade;
+            }
+        }));
+
+    engine
+        .run(EngineLoopMode::Poll, EngineTargetFps::Unlimited)
+        .unwrap();
+}
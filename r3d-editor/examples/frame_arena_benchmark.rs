@@ -0,0 +1,277 @@
+//! A sprite or mesh stress scene printing the frame times, the heap allocations per frame and the usage of the frame
+//! arenas every second.
+//!
+//! Usage: `cargo run -p editor --release --example frame_arena_benchmark -- sprites <image> [<count>]`
+//! or `cargo run -p editor --release --example frame_arena_benchmark -- meshes <model with uvs> [<count>]`
+//!
+//! Run it before and after a change to the hot paths of the render system; the worst frame time shows the spikes the
+//! allocator traffic causes. The high water mark is what `EngineConfig::frame_arena_bytes` should reserve.
+
+use pollster::FutureExt;
+use r3d::{
+    event::{event_types, EventHandler},
+    gfx::{
+        Camera, CameraClearMode, CameraPerspectiveProjectionAspect, CameraProjection, Color,
+        Material, MaterialHandle, Mesh, MeshHandle, MeshRenderer, PerInstancePropertyValue, Sprite,
+        SpriteHandle, SpriteTexelMapping, Texture, TextureHandle, UIElementRenderer,
+        UIElementSprite,
+    },
+    math::{Vec2, Vec3},
+    russimp::scene::{PostProcess, Scene},
+    specs::Builder,
+    transform::Transform,
+    ui::{UIAnchor, UIElement, UIMargin, UIScaleMode, UIScaler, UISize},
+    use_context,
+    wgpu::TextureFormat,
+    ContextHandle, Engine, EngineConfig, EngineLoopMode, EngineTargetFps,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+const USAGE: &str =
+    "usage: frame_arena_benchmark <sprites <image> | meshes <model with uvs>> [<count>]";
+
+/// Counts the heap allocations, reallocations included, of the whole process.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let scene = args.next().expect(USAGE);
+    let path = args.next().expect(USAGE);
+    let count = args
+        .next()
+        .map_or(20_000, |count| count.parse::<u32>().expect(USAGE));
+
+    let config = EngineConfig::from_args_and_env(EngineConfig {
+        title: format!("frame arena benchmark ({})", scene),
+        resizable: true,
+        width: 1280,
+        height: 720,
+        vsync: false,
+        ..Default::default()
+    })
+    .unwrap();
+    let engine = Engine::new(config).block_on().unwrap();
+    let ctx = engine.context();
+
+    match scene.as_str() {
+        "sprites" => create_sprites(&ctx, &path, count),
+        "meshes" => create_meshes(&ctx, &path, count),
+        _ => panic!("{}", USAGE),
+    }
+
+    let mut frames = 0u32;
+    let mut worst_frame = Duration::ZERO;
+    let mut last_frame = Instant::now();
+    let mut last_print = Instant::now();
+    let mut last_allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    ctx.event_mgr()
+        .add_handler(EventHandler::<event_types::Update>::new(move |_| {
+            frames += 1;
+            worst_frame = worst_frame.max(last_frame.elapsed());
+            last_frame = Instant::now();
+
+            if last_print.elapsed() < Duration::from_secs(1) {
+                return;
+            }
+
+            let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+            let stats = use_context().frame_arena_stats();
+            println!(
+                "{} {}, {:.2} ms per frame (worst {:.2} ms), {} allocations per frame, arena {} KiB (high water {} KiB, reserved {} KiB)",
+                count,
+                scene,
+                last_print.elapsed().as_secs_f64() * 1000.0 / frames as f64,
+                worst_frame.as_secs_f64() * 1000.0,
+                (allocations - last_allocations) / frames as usize,
+                stats.last_frame_bytes / 1024,
+                stats.high_water_bytes / 1024,
+                stats.reserved_bytes / 1024,
+            );
+
+            frames = 0;
+            worst_frame = Duration::ZERO;
+            last_print = Instant::now();
+            last_allocations = allocations;
+        }));
+
+    engine
+        .run(EngineLoopMode::Poll, EngineTargetFps::Unlimited)
+        .unwrap();
+}
+
+fn create_camera(ctx: &ContextHandle) -> Camera {
+    Camera::new(
+        0xFFFF_FFFF,
+        0,
+        CameraClearMode::All {
+            color: Color::parse_hex("141414").unwrap(),
+            depth: 1.0,
+            stencil: 0,
+        },
+        CameraProjection::perspective(60.0, CameraPerspectiveProjectionAspect::Screen, 0.1, 1000.0),
+        &ctx.gfx_ctx().device,
+        ctx.render_mgr_mut().bind_group_layout_cache(),
+    )
+}
+
+fn create_material(ctx: &ContextHandle, shader_path: &str) -> MaterialHandle {
+    let shader = ctx
+        .shader_mgr()
+        .create_shader(
+            ctx.render_mgr_mut().bind_group_layout_cache(),
+            std::fs::read_to_string(shader_path).unwrap(),
+        )
+        .unwrap();
+    MaterialHandle::new(Material::new(
+        shader,
+        ctx.render_mgr_mut().pipeline_layout_cache(),
+    ))
+}
+
+/// Small sprites tiling the screen, each one drawn on its own.
+fn create_sprites(ctx: &ContextHandle, image_path: &str, count: u32) {
+    let material = create_material(ctx, "r3d-editor/assets/shaders/sprite.wgsl");
+    let texture = TextureHandle::new(Texture::from_image(
+        TextureFormat::Rgba8Unorm,
+        &r3d::image::open(image_path).unwrap().flipv(),
+        &ctx.gfx_ctx().device,
+        &ctx.gfx_ctx().queue,
+    ));
+    let sprite = SpriteHandle::new(Sprite::new(
+        texture.clone(),
+        SpriteTexelMapping::new(0, texture.width, 0, texture.height),
+    ));
+
+    let mut object_mgr = ctx.object_mgr_mut();
+    let mut world = ctx.world_mut();
+
+    let (_, builder) =
+        object_mgr.create_object_builder(&mut world, Some("camera".to_owned()), None);
+    builder.with(create_camera(ctx)).build();
+
+    let (ui_root, builder) =
+        object_mgr.create_object_builder(&mut world, Some("ui-root".to_owned()), None);
+    builder
+        .with(UIScaler {
+            mode: UIScaleMode::Stretch,
+            reference_size: Vec2::new(1280.0, 720.0),
+        })
+        .with(UISize {
+            width: 0.0,
+            height: 0.0,
+        })
+        .build();
+
+    let side = (count as f32).sqrt().ceil() as u32;
+    let cell = 1.0 / side as f32;
+
+    for index in 0..count {
+        let mut ui_element_renderer = UIElementRenderer::new();
+        ui_element_renderer.set_material(material.clone());
+        ui_element_renderer.set_sprite(
+            UIElementSprite::sprite(sprite.clone()),
+            &ctx.gfx_ctx().device,
+            ctx.render_mgr_mut().bind_group_layout_cache(),
+        );
+
+        let min = Vec2::new((index % side) as f32 * cell, (index / side) as f32 * cell);
+        let (element, builder) = object_mgr.create_object_builder(&mut world, None, None);
+        builder
+            .with(UIElement {
+                anchor: UIAnchor::new(min, min + Vec2::ONE * cell),
+                margin: UIMargin::zero(),
+                is_interactable: false,
+            })
+            .with(UISize {
+                width: 0.0,
+                height: 0.0,
+            })
+            .with(ui_element_renderer)
+            .build();
+
+        object_mgr
+            .object_hierarchy_mut()
+            .set_parent(element.object_id, Some(ui_root.object_id))
+            .unwrap();
+    }
+}
+
+/// A square grid of meshes on the ground, batched into instanced draws.
+fn create_meshes(ctx: &ContextHandle, model_path: &str, count: u32) {
+    let material = create_material(ctx, "r3d-editor/assets/shaders/dissolve.wgsl");
+    material.write().set_per_instance_property(
+        "edge_color",
+        PerInstancePropertyValue::Float32x4([1.0, 1.0, 1.0, 1.0]),
+    );
+    let scene = Scene::from_file(
+        model_path,
+        vec![
+            PostProcess::Triangulate,
+            PostProcess::GenerateNormals,
+            PostProcess::FlipUVs,
+        ],
+    )
+    .unwrap();
+    let mesh = MeshHandle::new(Mesh::new(
+        scene
+            .meshes
+            .into_iter()
+            .next()
+            .expect("the model has no mesh"),
+    ));
+
+    let side = (count as f32).sqrt().ceil() as u32;
+    let spacing = 2.0;
+
+    let mut object_mgr = ctx.object_mgr_mut();
+    let mut world = ctx.world_mut();
+
+    let mut camera_transform = Transform::new();
+    camera_transform.position = Vec3::new(0.0, side as f32 * 0.6, side as f32 * 1.2);
+    let (_, builder) = object_mgr.create_object_builder(
+        &mut world,
+        Some("camera".to_owned()),
+        Some(camera_transform),
+    );
+    builder.with(create_camera(ctx)).build();
+
+    for index in 0..count {
+        let mut mesh_renderer = MeshRenderer::new();
+        mesh_renderer.set_material(material.clone());
+        mesh_renderer.set_mesh(mesh.clone(), &ctx.gfx_ctx().device);
+
+        let mut transform = Transform::new();
+        transform.position = Vec3::new(
+            ((index % side) as f32 - side as f32 * 0.5) * spacing,
+            0.0,
+            ((index / side) as f32 - side as f32 * 0.5) * spacing,
+        );
+        let (_, builder) = object_mgr.create_object_builder(&mut world, None, Some(transform));
+        builder.with(mesh_renderer).build();
+    }
+}
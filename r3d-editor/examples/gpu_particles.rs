@@ -0,0 +1,164 @@
+//! A snowstorm of half a million particles simulated in a compute shader, printing the frame report every second.
+//!
+//! Usage: `cargo run -p editor --release --example gpu_particles -- [cpu] [<particle count>]`
+//!
+//! Pass `cpu` to simulate on the CPU instead, for comparison. Devices without compute shaders or
+//! indirect draws fall back to the CPU as well.
+
+use pollster::FutureExt;
+use r3d::{
+    animation::{AnimationCurve, CurveInterpolation, Keyframe},
+    event::{event_types, EventHandler},
+    gfx::{
+        Camera, CameraClearMode, CameraPerspectiveProjectionAspect, CameraProjection, Color,
+        ColorGradient, GpuParticles, Material, MaterialHandle, ParticleEmitter,
+        ParticleSimulationMode, ParticleSystem, BUILT_IN_SHADER_PARTICLE,
+    },
+    math::Vec3,
+    specs::Builder,
+    transform::Transform,
+    use_context, Engine, EngineConfig, EngineLoopMode, EngineTargetFps,
+};
+use std::time::{Duration, Instant};
+
+fn main() {
+    let mut mode = ParticleSimulationMode::Gpu;
+    let mut particle_count = 500_000u32;
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "cpu" => mode = ParticleSimulationMode::Cpu,
+            count => {
+                particle_count = count
+                    .parse()
+                    .expect("usage: gpu_particles [cpu] [<particle count>]")
+            }
+        }
+    }
+
+    let config = EngineConfig::from_args_and_env(EngineConfig {
+        title: "gpu particles".to_owned(),
+        resizable: true,
+        width: 1280,
+        height: 720,
+        vsync: false,
+        ..Default::default()
+    })
+    .unwrap();
+    let engine = Engine::new(config).block_on().unwrap();
+    let ctx = engine.context();
+
+    if mode == ParticleSimulationMode::Gpu && !GpuParticles::is_supported(ctx.gfx_ctx()) {
+        println!("compute shaders or indirect draws are not supported; simulating on the CPU");
+    }
+
+    let material = MaterialHandle::new(Material::new(
+        ctx.built_in_shader_mgr()
+            .find_shader(BUILT_IN_SHADER_PARTICLE)
+            .unwrap(),
+        ctx.render_mgr_mut().pipeline_layout_cache(),
+    ));
+
+    // Flakes live for 20 seconds, so the storm settles at the requested count.
+    let lifetime = 20.0;
+    let emitter = ParticleEmitter {
+        max_particles: particle_count,
+        emission_rate: particle_count as f32 / lifetime,
+        lifetime,
+        lifetime_variance: 0.0,
+        speed: 0.5,
+        speed_variance: 0.5,
+        spread: std::f32::consts::PI,
+        radius: 40.0,
+        gravity: Vec3::new(0.0, -0.6, 0.0),
+        drag: 0.4,
+        noise_strength: 1.5,
+        noise_frequency: 0.15,
+        size: AnimationCurve::new(
+            CurveInterpolation::Linear,
+            vec![
+                Keyframe::new(0.0, 0.0),
+                Keyframe::new(0.05, 0.06),
+                Keyframe::new(1.0, 0.04),
+            ],
+        ),
+        color: ColorGradient::new(vec![
+            (0.0, Color::from_rgba(1.0, 1.0, 1.0, 0.0)),
+            (0.05, Color::from_rgba(1.0, 1.0, 1.0, 0.9)),
+            (0.8, Color::from_rgba(0.9, 0.95, 1.0, 0.9)),
+            (1.0, Color::from_rgba(0.9, 0.95, 1.0, 0.0)),
+        ]),
+    };
+
+    let mut particle_system = ParticleSystem::new(ctx.gfx_ctx().clone(), emitter);
+    particle_system.set_mode(mode);
+    particle_system.set_material(material);
+
+    let camera = Camera::new(
+        0xFFFF_FFFF,
+        0,
+        CameraClearMode::All {
+            color: Color::parse_hex("1b2230").unwrap(),
+            depth: 1.0,
+            stencil: 0,
+        },
+        CameraProjection::perspective(60.0, CameraPerspectiveProjectionAspect::Screen, 0.1, 500.0),
+        &ctx.gfx_ctx().device,
+        ctx.render_mgr_mut().bind_group_layout_cache(),
+    );
+
+    {
+        let mut object_mgr = ctx.object_mgr_mut();
+        let mut world = ctx.world_mut();
+
+        let mut camera_transform = Transform::new();
+        camera_transform.position = Vec3::new(0.0, 0.0, 60.0);
+        let (_, builder) = object_mgr.create_object_builder(
+            &mut world,
+            Some("camera".to_owned()),
+            Some(camera_transform),
+        );
+        builder.with(camera).build();
+
+        let mut emitter_transform = Transform::new();
+        emitter_transform.position = Vec3::new(0.0, 10.0, 0.0);
+        let (_, builder) = object_mgr.create_object_builder(
+            &mut world,
+            Some("snowstorm".to_owned()),
+            Some(emitter_transform),
+        );
+        builder.with(particle_system).build();
+    }
+
+    let mut frames = 0u32;
+    let mut last_print = Instant::now();
+    ctx.event_mgr()
+        .add_handler(EventHandler::<event_types::Update>::new(move |_| {
+            frames += 1;
+
+            if last_print.elapsed() < Duration::from_secs(1) {
+                return;
+            }
+
+            let report = use_context().render_mgr().frame_report();
+            println!(
+                "{:?}: {} particles, {:.1} fps, gpu {}, waited {:.2} ms, {} frames in flight",
+                mode,
+                particle_count,
+                frames as f32 / last_print.elapsed().as_secs_f32(),
+                match report.gpu_ms {
+                    Some(gpu_ms) => format!("{:.2} ms", gpu_ms),
+                    None => "n/a".to_owned(),
+                },
+                report.wait_ms,
+                report.frames_in_flight,
+            );
+
+            frames = 0;
+            last_print = Instant::now();
+        }));
+
+    engine
+        .run(EngineLoopMode::Poll, EngineTargetFps::Unlimited)
+        .unwrap();
+}
@@ -0,0 +1,171 @@
+//! A city of static buildings merged into HLOD proxies, printing the draw calls every second while the camera
+//! flies away from it and back.
+//!
+//! Usage: `cargo run -p editor --release --example hlod_city -- <model with uvs> [<buildings per side>]`
+//!
+//! The draw calls drop from one per building to one per cluster once the camera is past the switch distance.
+
+use pollster::FutureExt;
+use r3d::{
+    event::{event_types, EventHandler},
+    gfx::{
+        Camera, CameraClearMode, CameraPerspectiveProjectionAspect, CameraProjection, Color,
+        HlodBakeSettings, HlodStatic, Material, MaterialHandle, Mesh, MeshHandle, MeshRenderer,
+        PerInstancePropertyValue,
+    },
+    math::Vec3,
+    russimp::scene::{PostProcess, Scene},
+    specs::Builder,
+    transform::{Transform, TransformComponent},
+    use_context, Engine, EngineConfig, EngineLoopMode, EngineTargetFps,
+};
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: hlod_city <model with uvs> [<buildings per side>]";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let model_path = args.next().expect(USAGE);
+    let side = args
+        .next()
+        .map_or(64, |side| side.parse::<u32>().expect(USAGE));
+
+    let config = EngineConfig::from_args_and_env(EngineConfig {
+        title: "hlod city".to_owned(),
+        resizable: true,
+        width: 1280,
+        height: 720,
+        vsync: false,
+        ..Default::default()
+    })
+    .unwrap();
+    let engine = Engine::new(config).block_on().unwrap();
+    let ctx = engine.context();
+
+    let shader = ctx
+        .shader_mgr()
+        .create_shader(
+            ctx.render_mgr_mut().bind_group_layout_cache(),
+            std::fs::read_to_string("r3d-editor/assets/shaders/dissolve.wgsl").unwrap(),
+        )
+        .unwrap();
+    let material = MaterialHandle::new(Material::new(
+        shader,
+        ctx.render_mgr_mut().pipeline_layout_cache(),
+    ));
+    material.write().set_per_instance_property(
+        "edge_color",
+        PerInstancePropertyValue::Float32x4([1.0, 1.0, 1.0, 1.0]),
+    );
+
+    let scene = Scene::from_file(
+        &model_path,
+        vec![
+            PostProcess::Triangulate,
+            PostProcess::GenerateNormals,
+            PostProcess::FlipUVs,
+        ],
+    )
+    .unwrap();
+    let mesh = MeshHandle::new(Mesh::new(
+        scene
+            .meshes
+            .into_iter()
+            .next()
+            .expect("the model has no mesh"),
+    ));
+
+    let camera = Camera::new(
+        0xFFFF_FFFF,
+        0,
+        CameraClearMode::All {
+            color: Color::parse_hex("8fb3d9").unwrap(),
+            depth: 1.0,
+            stencil: 0,
+        },
+        CameraProjection::perspective(
+            60.0,
+            CameraPerspectiveProjectionAspect::Screen,
+            0.1,
+            10000.0,
+        ),
+        &ctx.gfx_ctx().device,
+        ctx.render_mgr_mut().bind_group_layout_cache(),
+    );
+
+    let spacing = 12.0;
+    let extent = side as f32 * spacing;
+
+    let camera_object = {
+        let mut object_mgr = ctx.object_mgr_mut();
+        let mut world = ctx.world_mut();
+
+        let (camera_object, builder) =
+            object_mgr.create_object_builder(&mut world, Some("camera".to_owned()), None);
+        builder.with(camera).build();
+
+        for index in 0..side * side {
+            let mut mesh_renderer = MeshRenderer::new();
+            mesh_renderer.set_material(material.clone());
+            mesh_renderer.set_mesh(mesh.clone(), &ctx.gfx_ctx().device);
+
+            let mut transform = Transform::new();
+            transform.position = Vec3::new(
+                ((index % side) as f32 - side as f32 * 0.5) * spacing,
+                0.0,
+                ((index / side) as f32 - side as f32 * 0.5) * spacing,
+            );
+            transform.scale = Vec3::new(4.0, 4.0 + (index % 11) as f32, 4.0);
+            let shade = 0.4 + (index % 5) as f32 * 0.1;
+            let (_, builder) = object_mgr.create_object_builder(&mut world, None, Some(transform));
+            builder
+                .with(mesh_renderer)
+                .with(HlodStatic::new(Color::from_rgb(shade, shade, shade * 1.1)))
+                .build();
+        }
+
+        camera_object
+    };
+
+    // The bake reads the world matrices of the last frame, which the first update has none of yet.
+    let mut updates = 0u32;
+    let start = Instant::now();
+    let mut last_print = Instant::now();
+    ctx.event_mgr()
+        .add_handler(EventHandler::<event_types::Update>::new(move |_| {
+            let ctx = use_context();
+
+            updates += 1;
+
+            if updates == 2 {
+                ctx.bake_and_apply_hlod(HlodBakeSettings {
+                    cell_size: extent / 8.0,
+                    switch_distance: extent,
+                    ..Default::default()
+                });
+            }
+
+            // From the edge of the city to four times its size away, and back.
+            let phase = (start.elapsed().as_secs_f32() * 0.1).sin() * 0.5 + 0.5;
+            let distance = extent * (0.5 + 3.5 * phase);
+            camera_object
+                .component::<TransformComponent>()
+                .set_position(Vec3::new(0.0, extent * 0.1 + distance * 0.2, distance));
+
+            if last_print.elapsed() < Duration::from_secs(1) {
+                return;
+            }
+
+            let report = ctx.render_mgr().frame_report();
+            println!(
+                "camera {:.0} units away: {} draw call(s), {} of them HLOD proxies",
+                distance, report.draw_calls, report.hlod_proxies_drawn
+            );
+
+            last_print = Instant::now();
+        }));
+
+    engine
+        .run(EngineLoopMode::Poll, EngineTargetFps::Unlimited)
+        .unwrap();
+}
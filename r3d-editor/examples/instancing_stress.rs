@@ -0,0 +1,162 @@
+//! A grid of tens of thousands of meshes, each one drawn on its own, printing the time spent recording the camera pass
+//! every second.
+//!
+//! Usage: `cargo run -p editor --release --example instancing_stress -- <model with uvs> [<encoder threads>] [<mesh count>]`
+//!
+//! Compare the recording time with 1 encoder thread against several; the time of each thread is printed as well.
+
+use pollster::FutureExt;
+use r3d::{
+    event::{event_types, EventHandler},
+    gfx::{
+        Camera, CameraClearMode, CameraPerspectiveProjectionAspect, CameraProjection, Color,
+        Material, MaterialHandle, Mesh, MeshHandle, MeshRenderer, PerInstancePropertyValue,
+    },
+    math::Vec3,
+    russimp::scene::{PostProcess, Scene},
+    specs::Builder,
+    transform::Transform,
+    use_context, Engine, EngineConfig, EngineLoopMode, EngineTargetFps,
+};
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: instancing_stress <model with uvs> [<encoder threads>] [<mesh count>]";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let model_path = args.next().expect(USAGE);
+    let encoder_threads = args
+        .next()
+        .map_or(4, |threads| threads.parse::<usize>().expect(USAGE));
+    let mesh_count = args
+        .next()
+        .map_or(20_000, |count| count.parse::<u32>().expect(USAGE));
+
+    let config = EngineConfig::from_args_and_env(EngineConfig {
+        title: "instancing stress".to_owned(),
+        resizable: true,
+        width: 1280,
+        height: 720,
+        vsync: false,
+        ..Default::default()
+    })
+    .unwrap();
+    let engine = Engine::new(config).block_on().unwrap();
+    let ctx = engine.context();
+
+    ctx.render_mgr_mut().set_encoder_threads(encoder_threads);
+
+    let shader = ctx
+        .shader_mgr()
+        .create_shader(
+            ctx.render_mgr_mut().bind_group_layout_cache(),
+            std::fs::read_to_string("r3d-editor/assets/shaders/dissolve.wgsl").unwrap(),
+        )
+        .unwrap();
+    let material = MaterialHandle::new(Material::new(
+        shader,
+        ctx.render_mgr_mut().pipeline_layout_cache(),
+    ));
+    material.write().set_per_instance_property(
+        "edge_color",
+        PerInstancePropertyValue::Float32x4([1.0, 1.0, 1.0, 1.0]),
+    );
+
+    let scene = Scene::from_file(
+        &model_path,
+        vec![
+            PostProcess::Triangulate,
+            PostProcess::GenerateNormals,
+            PostProcess::FlipUVs,
+        ],
+    )
+    .unwrap();
+    let mesh = MeshHandle::new(Mesh::new(
+        scene
+            .meshes
+            .into_iter()
+            .next()
+            .expect("the model has no mesh"),
+    ));
+
+    let camera = Camera::new(
+        0xFFFF_FFFF,
+        0,
+        CameraClearMode::All {
+            color: Color::parse_hex("141414").unwrap(),
+            depth: 1.0,
+            stencil: 0,
+        },
+        CameraProjection::perspective(60.0, CameraPerspectiveProjectionAspect::Screen, 0.1, 1000.0),
+        &ctx.gfx_ctx().device,
+        ctx.render_mgr_mut().bind_group_layout_cache(),
+    );
+
+    // A square grid on the ground, wide enough to fill the view.
+    let side = (mesh_count as f32).sqrt().ceil() as u32;
+    let spacing = 2.0;
+
+    {
+        let mut object_mgr = ctx.object_mgr_mut();
+        let mut world = ctx.world_mut();
+
+        let mut camera_transform = Transform::new();
+        camera_transform.position = Vec3::new(0.0, side as f32 * 0.6, side as f32 * 1.2);
+        let (_, builder) = object_mgr.create_object_builder(
+            &mut world,
+            Some("camera".to_owned()),
+            Some(camera_transform),
+        );
+        builder.with(camera).build();
+
+        for index in 0..mesh_count {
+            let mut mesh_renderer = MeshRenderer::new();
+            mesh_renderer.set_material(material.clone());
+            mesh_renderer.set_mesh(mesh.clone(), &ctx.gfx_ctx().device);
+
+            let mut transform = Transform::new();
+            transform.position = Vec3::new(
+                ((index % side) as f32 - side as f32 * 0.5) * spacing,
+                0.0,
+                ((index / side) as f32 - side as f32 * 0.5) * spacing,
+            );
+            let (_, builder) = object_mgr.create_object_builder(&mut world, None, Some(transform));
+            builder.with(mesh_renderer).build();
+        }
+    }
+
+    let mut frames = 0u32;
+    let mut last_print = Instant::now();
+    ctx.event_mgr()
+        .add_handler(EventHandler::<event_types::Update>::new(move |_| {
+            frames += 1;
+
+            if last_print.elapsed() < Duration::from_secs(1) {
+                return;
+            }
+
+            let render_mgr = use_context().render_mgr();
+            let report = render_mgr.frame_report();
+            println!(
+                "{} meshes, {:.1} fps, recorded on {} thread(s) in {:.2} ms [{}]",
+                mesh_count,
+                frames as f32 / last_print.elapsed().as_secs_f32(),
+                report.encoder_threads,
+                report.encode_ms,
+                Vec::from_iter(
+                    render_mgr
+                        .encoder_thread_ms()
+                        .iter()
+                        .map(|ms| format!("{:.2}", ms))
+                )
+                .join(", "),
+            );
+
+            frames = 0;
+            last_print = Instant::now();
+        }));
+
+    engine
+        .run(EngineLoopMode::Poll, EngineTargetFps::Unlimited)
+        .unwrap();
+}
@@ -0,0 +1,143 @@
+//! Dissolves a model over and over with a property animation clip, blending in a pulsing glow.
+//!
+//! Usage: `cargo run -p editor --example property_animation -- <model with uvs>`
+
+use asset::AssetKey;
+use pollster::FutureExt;
+use r3d::{
+    animation::{
+        AnimationCurve, CurveInterpolation, Keyframe, PropertyAnimation, PropertyAnimator,
+    },
+    gfx::{
+        Camera, CameraClearMode, CameraPerspectiveProjectionAspect, CameraProjection, Color,
+        Material, MaterialHandle, Mesh, MeshHandle, MeshRenderer, PerInstancePropertyValue,
+    },
+    math::Vec3,
+    russimp::scene::{PostProcess, Scene},
+    specs::Builder,
+    transform::Transform,
+    Engine, EngineConfig, EngineLoopMode, EngineTargetFps,
+};
+
+fn main() {
+    let model_path = std::env::args()
+        .nth(1)
+        .expect("usage: property_animation <model with uvs>");
+
+    let config = EngineConfig::from_args_and_env(EngineConfig {
+        title: "property animation".to_owned(),
+        resizable: true,
+        width: 800,
+        height: 600,
+        ..Default::default()
+    })
+    .unwrap();
+    let engine = Engine::new(config).block_on().unwrap();
+    let ctx = engine.context();
+
+    let dissolve_clip = AssetKey::Path("r3d-editor/assets/animations/dissolve.json".to_owned());
+    let pulse_clip = AssetKey::Path("pulse".to_owned());
+    {
+        let dissolve = serde_json::from_str::<PropertyAnimation>(
+            &std::fs::read_to_string("r3d-editor/assets/animations/dissolve.json").unwrap(),
+        )
+        .unwrap();
+        let pulse = PropertyAnimation::new().with_track(
+            "renderer.instance.emissive_strength",
+            AnimationCurve::new(
+                CurveInterpolation::Cubic,
+                vec![
+                    Keyframe::new(0.0, 1.0),
+                    Keyframe::new(0.5, 6.0),
+                    Keyframe::new(1.0, 1.0),
+                ],
+            ),
+        );
+
+        let mut animation_mgr = ctx.animation_mgr_mut();
+        animation_mgr.insert_property_animation(dissolve_clip.clone(), dissolve);
+        animation_mgr.insert_property_animation(pulse_clip.clone(), pulse);
+    }
+
+    let shader = ctx
+        .shader_mgr()
+        .create_shader(
+            ctx.render_mgr_mut().bind_group_layout_cache(),
+            std::fs::read_to_string("r3d-editor/assets/shaders/dissolve.wgsl").unwrap(),
+        )
+        .unwrap();
+    let material = MaterialHandle::new(Material::new(
+        shader,
+        ctx.render_mgr_mut().pipeline_layout_cache(),
+    ));
+    material.write().set_per_instance_property(
+        "edge_color",
+        PerInstancePropertyValue::Float32x4([1.0, 1.0, 1.0, 1.0]),
+    );
+
+    let scene = Scene::from_file(
+        &model_path,
+        vec![
+            PostProcess::Triangulate,
+            PostProcess::GenerateNormals,
+            PostProcess::FlipUVs,
+        ],
+    )
+    .unwrap();
+    let mesh = MeshHandle::new(Mesh::new(
+        scene
+            .meshes
+            .into_iter()
+            .next()
+            .expect("the model has no mesh"),
+    ));
+
+    let camera = Camera::new(
+        0xFFFF_FFFF,
+        0,
+        CameraClearMode::All {
+            color: Color::parse_hex("141414").unwrap(),
+            depth: 1.0,
+            stencil: 0,
+        },
+        CameraProjection::perspective(
+            60.0,
+            CameraPerspectiveProjectionAspect::Screen,
+            0.01,
+            1000.0,
+        ),
+        &ctx.gfx_ctx().device,
+        ctx.render_mgr_mut().bind_group_layout_cache(),
+    );
+
+    let mut mesh_renderer = MeshRenderer::new();
+    mesh_renderer.set_material(material);
+    mesh_renderer.set_mesh(mesh, &ctx.gfx_ctx().device);
+
+    // Glows while dissolving, with the pulse layered on at half weight.
+    let mut animator = PropertyAnimator::new();
+    animator.play(dissolve_clip, true);
+    animator.blend_with(pulse_clip, true, 0.5);
+
+    {
+        let mut object_mgr = ctx.object_mgr_mut();
+        let mut world = ctx.world_mut();
+
+        let mut camera_transform = Transform::new();
+        camera_transform.position = Vec3::new(0.0, 0.0, 3.0);
+        let (_, builder) = object_mgr.create_object_builder(
+            &mut world,
+            Some("camera".to_owned()),
+            Some(camera_transform),
+        );
+        builder.with(camera).build();
+
+        let (_, builder) =
+            object_mgr.create_object_builder(&mut world, Some("model".to_owned()), None);
+        builder.with(mesh_renderer).with(animator).build();
+    }
+
+    engine
+        .run(EngineLoopMode::Poll, EngineTargetFps::VSync)
+        .unwrap();
+}
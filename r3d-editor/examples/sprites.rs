@@ -0,0 +1,181 @@
+//! A field of hundreds of sprites from one procedural atlas, flipped and tinted, printing the frame rate and the
+//! number of draw calls every second.
+//!
+//! Usage: `cargo run -p editor --release --example sprites -- [<sprite count>]`
+//!
+//! Sprites of one sorting order and one texture are drawn as instances of one command, so each row of sprites should
+//! take one draw call however many sprites it has.
+
+use asset::assets::{TextureAddressMode, TextureFilterMode};
+use pollster::FutureExt;
+use r3d::{
+    event::{event_types, EventHandler},
+    gfx::{
+        Camera, CameraClearMode, CameraProjection, Color, Material, MaterialHandle, Sprite,
+        SpriteHandle, SpriteRenderer, SpriteTexelMapping, TextureCreationParams,
+        BUILT_IN_SHADER_SPRITE,
+    },
+    image::{DynamicImage, Rgba, RgbaImage},
+    math::{Vec2, Vec3},
+    specs::Builder,
+    transform::Transform,
+    use_context, Engine, EngineConfig, EngineLoopMode, EngineTargetFps,
+};
+use std::time::{Duration, Instant};
+
+/// Two 16x16 sprites side by side: a ball and a crate.
+fn atlas() -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(32, 16, |x, y| {
+        let (local_x, local_y) = (x % 16, y);
+
+        if x < 16 {
+            let dx = local_x as f32 - 7.5;
+            let dy = local_y as f32 - 7.5;
+
+            if dx * dx + dy * dy <= 49.0 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        } else if local_x == 0 || local_x == 15 || local_y == 0 || local_y == 15 {
+            Rgba([90, 60, 30, 255])
+        } else {
+            Rgba([180, 130, 70, 255])
+        }
+    }))
+}
+
+fn main() {
+    let sprite_count = std::env::args().nth(1).map_or(400, |count| {
+        count
+            .parse::<u32>()
+            .expect("usage: sprites [<sprite count>]")
+    });
+
+    let config = EngineConfig::from_args_and_env(EngineConfig {
+        title: "sprites".to_owned(),
+        resizable: true,
+        width: 1280,
+        height: 720,
+        vsync: false,
+        ..Default::default()
+    })
+    .unwrap();
+    let engine = Engine::new(config).block_on().unwrap();
+    let ctx = engine.context();
+
+    let texture = ctx.gfx_ctx().create_texture_from_image(
+        &atlas(),
+        TextureCreationParams {
+            srgb: true,
+            filter_mode: TextureFilterMode::Point,
+            address_mode: (TextureAddressMode::Clamp, TextureAddressMode::Clamp),
+        },
+    );
+    let sprites = [
+        SpriteHandle::new(Sprite::new(
+            texture.clone(),
+            SpriteTexelMapping::new(0, 16, 0, 16),
+        )),
+        SpriteHandle::new(Sprite::new(
+            texture.clone(),
+            SpriteTexelMapping::new(16, 32, 0, 16),
+        )),
+    ];
+    let material = MaterialHandle::new(Material::new(
+        ctx.built_in_shader_mgr()
+            .find_shader(BUILT_IN_SHADER_SPRITE)
+            .unwrap(),
+        ctx.render_mgr_mut().pipeline_layout_cache(),
+    ));
+
+    let camera = Camera::new(
+        0xFFFF_FFFF,
+        0,
+        CameraClearMode::All {
+            color: Color::parse_hex("20242c").unwrap(),
+            depth: 1.0,
+            stencil: 0,
+        },
+        CameraProjection::orthographic(48.0, 0.1, 100.0),
+        &ctx.gfx_ctx().device,
+        ctx.render_mgr_mut().bind_group_layout_cache(),
+    );
+
+    {
+        let mut object_mgr = ctx.object_mgr_mut();
+        let mut world = ctx.world_mut();
+        let mut render_mgr = ctx.render_mgr_mut();
+
+        let mut camera_transform = Transform::new();
+        camera_transform.position = Vec3::new(0.0, 0.0, 10.0);
+        let (_, builder) = object_mgr.create_object_builder(
+            &mut world,
+            Some("camera".to_owned()),
+            Some(camera_transform),
+        );
+        builder.with(camera).build();
+
+        let columns = (sprite_count as f32).sqrt().ceil() as u32;
+
+        for index in 0..sprite_count {
+            let (column, row) = (index % columns, index / columns);
+            let mut sprite_renderer = SpriteRenderer::new();
+            sprite_renderer.set_material(material.clone());
+            sprite_renderer.set_sprite(
+                sprites[(index % 2) as usize].clone(),
+                &ctx.gfx_ctx().device,
+                render_mgr.bind_group_layout_cache(),
+            );
+            sprite_renderer.set_pivot(Vec2::new(0.5, 0.0));
+            sprite_renderer.set_flip_x(index % 3 == 0);
+            sprite_renderer.set_color(Color::from_rgba(
+                0.5 + 0.5 * (column as f32 / columns as f32),
+                0.5 + 0.5 * (row as f32 / columns as f32),
+                1.0,
+                1.0,
+            ));
+            // Rows nearer the bottom of the screen are drawn over the ones above them.
+            sprite_renderer.set_sorting_order(-(row as i32));
+
+            let mut transform = Transform::new();
+            transform.position = Vec3::new(
+                (column as f32 - columns as f32 * 0.5) * 1.1,
+                (row as f32 - columns as f32 * 0.5) * 0.6,
+                0.0,
+            );
+            let (_, builder) = object_mgr.create_object_builder(
+                &mut world,
+                Some(format!("sprite #{}", index)),
+                Some(transform),
+            );
+            builder.with(sprite_renderer).build();
+        }
+    }
+
+    let mut frames = 0u32;
+    let mut last_print = Instant::now();
+    ctx.event_mgr()
+        .add_handler(EventHandler::<event_types::Update>::new(move |_| {
+            frames += 1;
+
+            if last_print.elapsed() < Duration::from_secs(1) {
+                return;
+            }
+
+            let report = use_context().render_mgr().frame_report();
+            println!(
+                "{} sprites: {:.1} fps, {} draw calls",
+                sprite_count,
+                frames as f32 / last_print.elapsed().as_secs_f32(),
+                report.draw_calls,
+            );
+
+            frames = 0;
+            last_print = Instant::now();
+        }));
+
+    engine
+        .run(EngineLoopMode::Poll, EngineTargetFps::Unlimited)
+        .unwrap();
+}
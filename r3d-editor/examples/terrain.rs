@@ -0,0 +1,205 @@
+//! A 4k heightmap terrain drawn in geo-mipmapped chunks, printing how many chunks were drawn and culled every second.
+//!
+//! Usage: `cargo run -p editor --release --example terrain -- [<heightmap size>]`
+//!
+//! The heightmap is generated, as are the splat map and its layers.
+
+use pollster::FutureExt;
+use r3d::{
+    event::{event_types, EventHandler},
+    gfx::{
+        bind_terrain_splatting, Camera, CameraClearMode, CameraPerspectiveProjectionAspect,
+        CameraProjection, Color, Heightfield, Material, MaterialHandle, Terrain, Texture,
+        TextureArray, BUILT_IN_SHADER_TERRAIN,
+    },
+    image::{DynamicImage, ImageBuffer, Luma, Rgba},
+    math::{Quat, Vec2, Vec3},
+    specs::Builder,
+    transform::Transform,
+    use_context,
+    wgpu::TextureFormat,
+    Engine, EngineConfig, EngineLoopMode, EngineTargetFps,
+};
+use std::time::{Duration, Instant};
+
+fn main() {
+    let size = match std::env::args().nth(1) {
+        Some(size) => size.parse().expect("usage: terrain [<heightmap size>]"),
+        None => 4096u32,
+    };
+    let world_size = size as f32;
+    let height_scale = world_size / 8.0;
+
+    let config = EngineConfig::from_args_and_env(EngineConfig {
+        title: "terrain".to_owned(),
+        resizable: true,
+        width: 1280,
+        height: 720,
+        vsync: false,
+        ..Default::default()
+    })
+    .unwrap();
+    let engine = Engine::new(config).block_on().unwrap();
+    let ctx = engine.context();
+
+    let heightmap = DynamicImage::ImageLuma16(ImageBuffer::from_fn(size, size, |x, z| {
+        Luma([(height(x as f32 / size as f32, z as f32 / size as f32) * u16::MAX as f32) as u16])
+    }));
+    let heightfield =
+        Heightfield::from_image(&heightmap, Vec2::new(world_size, world_size), height_scale)
+            .unwrap();
+
+    // Grass in the valleys, rock on the slopes and snow on the peaks.
+    let splat_map = DynamicImage::ImageRgba8(ImageBuffer::from_fn(1024, 1024, |x, z| {
+        let height = height(x as f32 / 1024.0, z as f32 / 1024.0);
+        let snow = ((height - 0.6) * 8.0).clamp(0.0, 1.0);
+        let rock = ((height - 0.35) * 8.0).clamp(0.0, 1.0) - snow;
+        let grass = 1.0 - rock - snow;
+        Rgba([
+            (grass * 255.0) as u8,
+            (rock * 255.0) as u8,
+            (snow * 255.0) as u8,
+            0,
+        ])
+    }));
+    let layers = Vec::from_iter(
+        [
+            ("grass", [70, 110, 50]),
+            ("rock", [110, 100, 90]),
+            ("snow", [235, 240, 245]),
+            ("sand", [200, 180, 130]),
+        ]
+        .into_iter()
+        .map(|(name, [r, g, b])| {
+            let layer = ImageBuffer::from_fn(64, 64, |x, y| {
+                // A little grain, so that the tiling is visible.
+                let grain = ((x * 7 + y * 13) % 17) as u8;
+                Rgba([r - grain, g - grain, b - grain, 255])
+            });
+            (name.to_owned(), DynamicImage::ImageRgba8(layer))
+        }),
+    );
+
+    let device = &ctx.gfx_ctx().device;
+    let queue = &ctx.gfx_ctx().queue;
+    let splat_map = Texture::from_image(TextureFormat::Rgba8Unorm, &splat_map, device, queue);
+    let splat_layers =
+        TextureArray::from_images(TextureFormat::Rgba8UnormSrgb, &layers, true, device, queue)
+            .unwrap();
+
+    let material = MaterialHandle::new(Material::new(
+        ctx.built_in_shader_mgr()
+            .find_shader(BUILT_IN_SHADER_TERRAIN)
+            .unwrap(),
+        ctx.render_mgr_mut().pipeline_layout_cache(),
+    ));
+    {
+        let mut material = material.write();
+        assert!(bind_terrain_splatting(
+            &mut material,
+            &splat_map,
+            &splat_layers
+        ));
+        material.update_bind_group(device);
+    }
+
+    let mut terrain = Terrain::new(
+        heightfield,
+        device,
+        queue,
+        ctx.render_mgr_mut().bind_group_layout_cache(),
+    )
+    .unwrap();
+    terrain.set_material(material);
+    terrain.set_texture_tiling(0.25);
+
+    let camera = Camera::new(
+        0xFFFF_FFFF,
+        0,
+        CameraClearMode::All {
+            color: Color::parse_hex("9cc3e6").unwrap(),
+            depth: 1.0,
+            stencil: 0,
+        },
+        CameraProjection::perspective(
+            60.0,
+            CameraPerspectiveProjectionAspect::Screen,
+            1.0,
+            world_size,
+        ),
+        device,
+        ctx.render_mgr_mut().bind_group_layout_cache(),
+    );
+
+    {
+        let mut object_mgr = ctx.object_mgr_mut();
+        let mut world = ctx.world_mut();
+
+        // Looks over the terrain from its near edge, so that the chunks behind and beside the camera are culled.
+        let mut camera_transform = Transform::new();
+        camera_transform.position = Vec3::new(world_size * 0.5, height_scale * 1.2, world_size);
+        camera_transform.rotation = Quat::from_axis_angle(Vec3::RIGHT, -0.35);
+        let (_, builder) = object_mgr.create_object_builder(
+            &mut world,
+            Some("camera".to_owned()),
+            Some(camera_transform),
+        );
+        builder.with(camera).build();
+
+        let (_, builder) =
+            object_mgr.create_object_builder(&mut world, Some("terrain".to_owned()), None);
+        builder.with(terrain).build();
+    }
+
+    let mut frames = 0u32;
+    let mut last_print = Instant::now();
+    ctx.event_mgr()
+        .add_handler(EventHandler::<event_types::Update>::new(move |_| {
+            frames += 1;
+
+            if last_print.elapsed() < Duration::from_secs(1) {
+                return;
+            }
+
+            let report = use_context().render_mgr().frame_report();
+            println!(
+                "{}x{} heightmap: {} chunks drawn, {} culled, {:.1} fps, gpu {}",
+                size,
+                size,
+                report.terrain_chunks_drawn,
+                report.terrain_chunks_culled,
+                frames as f32 / last_print.elapsed().as_secs_f32(),
+                match report.gpu_ms {
+                    Some(gpu_ms) => format!("{:.2} ms", gpu_ms),
+                    None => "n/a".to_owned(),
+                },
+            );
+
+            frames = 0;
+            last_print = Instant::now();
+        }));
+
+    engine
+        .run(EngineLoopMode::Poll, EngineTargetFps::Unlimited)
+        .unwrap();
+}
+
+/// Rolling hills from a few octaves of sines, in `[0, 1]`.
+fn height(x: f32, z: f32) -> f32 {
+    let mut height = 0.0;
+    let mut total = 0.0;
+    let mut amplitude = 0.5;
+    let mut frequency = 3.0;
+
+    for _ in 0..5 {
+        height += amplitude
+            * (0.5
+                + 0.25 * (x * frequency * std::f32::consts::TAU).sin()
+                + 0.25 * (z * frequency * 1.3 * std::f32::consts::TAU).cos());
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.1;
+    }
+
+    height / total
+}
@@ -0,0 +1,191 @@
+//! A forest of pillars in volumetric fog lit by a low sun, with a bank of denser mist in the middle, printing the GPU
+//! time of the fog every second while the camera circles the forest.
+//!
+//! Usage: `cargo run -p editor --release --example volumetric_fog -- <model with uvs> [<low|medium|high|ultra>]`
+//!
+//! The shafts of light between the pillars are brightest when the camera faces the sun. The tier picks the froxel grid;
+//! without compute shaders, the fog falls back to a uniform one and the mist bank disappears.
+
+use pollster::FutureExt;
+use r3d::{
+    event::{event_types, EventHandler},
+    gfx::{
+        Camera, CameraClearMode, CameraPerspectiveProjectionAspect, CameraProjection, Color,
+        FogVolume, Material, MaterialHandle, Mesh, MeshHandle, MeshRenderer,
+        PerInstancePropertyValue, QualitySetting, RenderTier, VolumetricFogSettings,
+    },
+    math::{Quat, Vec3},
+    russimp::scene::{PostProcess, Scene},
+    specs::Builder,
+    transform::{Transform, TransformComponent},
+    use_context, Engine, EngineConfig, EngineLoopMode, EngineTargetFps,
+};
+use std::{
+    f32::consts::FRAC_PI_2,
+    time::{Duration, Instant},
+};
+
+const USAGE: &str = "usage: volumetric_fog <model with uvs> [<low|medium|high|ultra>]";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let model_path = args.next().expect(USAGE);
+    let tier = match args.next().as_deref() {
+        None => None,
+        Some("low") => Some(RenderTier::Low),
+        Some("medium") => Some(RenderTier::Medium),
+        Some("high") => Some(RenderTier::High),
+        Some("ultra") => Some(RenderTier::Ultra),
+        Some(_) => panic!("{}", USAGE),
+    };
+
+    let config = EngineConfig::from_args_and_env(EngineConfig {
+        title: "volumetric fog".to_owned(),
+        resizable: true,
+        width: 1280,
+        height: 720,
+        vsync: false,
+        ..Default::default()
+    })
+    .unwrap();
+    let engine = Engine::new(config).block_on().unwrap();
+    let ctx = engine.context();
+
+    {
+        let mut render_mgr = ctx.render_mgr_mut();
+
+        if let Some(tier) = tier {
+            let mut config = render_mgr.current_config();
+            config.quality = QualitySetting::Tier(tier);
+            render_mgr.apply_config(&config);
+        }
+
+        render_mgr.set_volumetric_fog(Some(VolumetricFogSettings {
+            density: 0.015,
+            albedo: Color::from_rgb(0.9, 0.92, 0.95),
+            anisotropy: 0.7,
+            sun_direction: Vec3::new(-1.0, -0.35, 0.2),
+            sun_color: Color::from_rgb(1.0, 0.85, 0.65),
+            sun_intensity: 6.0,
+            ambient: Color::from_rgb(0.05, 0.06, 0.08),
+            far: 160.0,
+        }));
+    }
+
+    let shader = ctx
+        .shader_mgr()
+        .create_shader(
+            ctx.render_mgr_mut().bind_group_layout_cache(),
+            std::fs::read_to_string("r3d-editor/assets/shaders/dissolve.wgsl").unwrap(),
+        )
+        .unwrap();
+    let material = MaterialHandle::new(Material::new(
+        shader,
+        ctx.render_mgr_mut().pipeline_layout_cache(),
+    ));
+    material.write().set_per_instance_property(
+        "edge_color",
+        PerInstancePropertyValue::Float32x4([1.0, 1.0, 1.0, 1.0]),
+    );
+
+    let scene = Scene::from_file(
+        &model_path,
+        vec![
+            PostProcess::Triangulate,
+            PostProcess::GenerateNormals,
+            PostProcess::FlipUVs,
+        ],
+    )
+    .unwrap();
+    let mesh = MeshHandle::new(Mesh::new(
+        scene
+            .meshes
+            .into_iter()
+            .next()
+            .expect("the model has no mesh"),
+    ));
+
+    let camera = Camera::new(
+        0xFFFF_FFFF,
+        0,
+        CameraClearMode::All {
+            color: Color::parse_hex("c9d4de").unwrap(),
+            depth: 1.0,
+            stencil: 0,
+        },
+        CameraProjection::perspective(60.0, CameraPerspectiveProjectionAspect::Screen, 0.1, 1000.0),
+        &ctx.gfx_ctx().device,
+        ctx.render_mgr_mut().bind_group_layout_cache(),
+    );
+
+    let camera_object = {
+        let mut object_mgr = ctx.object_mgr_mut();
+        let mut world = ctx.world_mut();
+
+        let (camera_object, builder) =
+            object_mgr.create_object_builder(&mut world, Some("camera".to_owned()), None);
+        builder.with(camera).build();
+
+        for index in 0..24 * 24 {
+            let mut mesh_renderer = MeshRenderer::new();
+            mesh_renderer.set_material(material.clone());
+            mesh_renderer.set_mesh(mesh.clone(), &ctx.gfx_ctx().device);
+
+            // A jittered grid, so that the gaps between the pillars don't line up.
+            let jitter = ((index * 7919) % 101) as f32 / 101.0 - 0.5;
+            let mut transform = Transform::new();
+            transform.position = Vec3::new(
+                ((index % 24) as f32 - 12.0 + jitter) * 6.0,
+                0.0,
+                ((index / 24) as f32 - 12.0 - jitter) * 6.0,
+            );
+            transform.scale = Vec3::new(1.0, 12.0 + jitter * 4.0, 1.0);
+            let (_, builder) = object_mgr.create_object_builder(&mut world, None, Some(transform));
+            builder.with(mesh_renderer).build();
+        }
+
+        let mut transform = Transform::new();
+        transform.position = Vec3::new(0.0, 2.0, 0.0);
+        transform.scale = Vec3::new(60.0, 6.0, 40.0);
+        transform.rotation = Quat::from_eular(0.0, 0.4, 0.0);
+        let (_, builder) =
+            object_mgr.create_object_builder(&mut world, Some("mist".to_owned()), Some(transform));
+        builder.with(FogVolume::new(0.25, 0.4)).build();
+
+        camera_object
+    };
+
+    let start = Instant::now();
+    let mut last_print = Instant::now();
+    ctx.event_mgr()
+        .add_handler(EventHandler::<event_types::Update>::new(move |_| {
+            let ctx = use_context();
+
+            let angle = start.elapsed().as_secs_f32() * 0.1;
+            let position = Vec3::new(angle.cos() * 90.0, 6.0, angle.sin() * 90.0);
+            // Facing the middle of the forest.
+            let camera = camera_object.component::<TransformComponent>();
+            camera.set_position(position);
+            camera.set_rotation(Quat::from_axis_angle(Vec3::UP, FRAC_PI_2 - angle));
+
+            if last_print.elapsed() < Duration::from_secs(1) {
+                return;
+            }
+
+            let report = ctx.render_mgr().frame_report();
+            match report.fog_gpu_ms {
+                Some(fog_ms) => println!(
+                    "fog: {:.2} ms of {:.2} ms",
+                    fog_ms,
+                    report.gpu_ms.unwrap_or_default()
+                ),
+                None => println!("fog: no timestamp queries on this device"),
+            }
+
+            last_print = Instant::now();
+        }));
+
+    engine
+        .run(EngineLoopMode::Poll, EngineTargetFps::Unlimited)
+        .unwrap();
+}
@@ -20,11 +20,16 @@ lazy_static! {
 }
 
 fn create_shader(path: impl AsRef<Path>) -> ShaderHandle {
+    let path = path.as_ref();
     let source = std::fs::read_to_string(path).unwrap();
     let ctx = use_context();
     ctx.shader_mgr()
-        .create_shader(ctx.render_mgr_mut().bind_group_layout_cache(), source)
-        .unwrap()
+        .create_named_shader(
+            ctx.render_mgr_mut().bind_group_layout_cache(),
+            &path.display().to_string(),
+            source,
+        )
+        .unwrap_or_else(|err| panic!("{}", err))
 }
 
 fn create_font(path: impl AsRef<Path>) -> FontHandle {
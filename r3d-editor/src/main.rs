@@ -1,12 +1,13 @@
 use assets::{FONT, MATERIAL_GLYPH, MATERIAL_SPRITE};
 use pollster::FutureExt;
 use r3d::{
+    console::ConsoleConfig,
     event::{event_types, EventHandler},
     fontdue::layout::{HorizontalAlign, VerticalAlign},
     gfx::{
         Camera, CameraClearMode, CameraPerspectiveProjectionAspect, CameraProjection, Color,
-        NinePatch, NinePatchHandle, NinePatchTexelMapping, Texture, TextureHandle,
-        UIElementRenderer, UIElementSprite, UITextRenderer,
+        NinePatch, NinePatchHandle, NinePatchTexelMapping, ShaderCacheConfig, Texture,
+        TextureHandle, UIElementRenderer, UIElementSprite, UITextRenderer,
     },
     math::{Quat, Vec2, Vec3},
     object::{Object, ObjectHandle},
@@ -16,10 +17,10 @@ use r3d::{
     ui::{UIAnchor, UIElement, UIMargin, UIScaleMode, UIScaler, UISize},
     use_context,
     wgpu::TextureFormat,
-    ContextHandle, Engine, EngineConfig, EngineExecError, EngineInitError, EngineLoopMode,
-    EngineTargetFps,
+    ContextHandle, Engine, EngineConfig, EngineConfigError, EngineExecError, EngineInitError,
+    EngineLoopMode, EngineTargetFps,
 };
-use std::mem::MaybeUninit;
+use std::{mem::MaybeUninit, num::NonZeroU32};
 use thiserror::Error;
 
 mod assets;
@@ -39,6 +40,8 @@ pub fn use_app() -> &'static Application {
 
 #[derive(Error, Debug)]
 pub enum Error {
+    #[error("engine config error: {0}")]
+    EngineConfigError(#[from] EngineConfigError),
     #[error("engine init error: {0}")]
     EngineInitError(#[from] EngineInitError),
     #[error("engine exec error: {0}")]
@@ -46,13 +49,17 @@ pub enum Error {
 }
 
 fn main() -> Result<(), Error> {
-    let engine = Engine::new(EngineConfig {
+    let config = EngineConfig::from_args_and_env(EngineConfig {
         title: format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
         resizable: true,
         width: 800,
         height: 600,
-    })
-    .block_on()?;
+        shader_cache: Some(ShaderCacheConfig::new("cache/shaders")),
+        console: Some(ConsoleConfig::default()),
+        throttle_when_unfocused: NonZeroU32::new(10_000),
+        ..Default::default()
+    })?;
+    let engine = Engine::new(config).block_on()?;
 
     init(engine.context());
 
@@ -145,7 +152,8 @@ fn init(ctx: ContextHandle) {
 
     object_mgr
         .object_hierarchy_mut()
-        .set_parent(ui_root_under.object_id, Some(ui_root.object_id));
+        .set_parent(ui_root_under.object_id, Some(ui_root.object_id))
+        .unwrap();
 
     let mut ui_text_renderer = UITextRenderer::new();
     ui_text_renderer.with_config(|config| {
@@ -156,6 +164,7 @@ fn init(ctx: ContextHandle) {
     ui_text_renderer.set_font_size_with_recommended_values(36.0);
     ui_text_renderer.set_material(MATERIAL_GLYPH.clone());
     ui_text_renderer.set_font(FONT.clone());
+    ctx.console_mgr_mut().set_font(Some(FONT.clone()));
     ui_text_renderer.set_text("iiiiWowVAAV\nHi!".to_owned());
 
     let (ui_text, builder) =
@@ -175,7 +184,8 @@ fn init(ctx: ContextHandle) {
 
     object_mgr
         .object_hierarchy_mut()
-        .set_parent(ui_text.object_id, Some(ui_root_under.object_id));
+        .set_parent(ui_text.object_id, Some(ui_root_under.object_id))
+        .unwrap();
 
     ctx.event_mgr()
         .add_handler(EventHandler::<event_types::Update>::new(|_| update()));
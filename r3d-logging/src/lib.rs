@@ -5,6 +5,10 @@ use uuid::Uuid;
 
 pub mod transports;
 
+mod ring_buffer;
+
+pub use ring_buffer::*;
+
 pub trait LogLevel
 where
     Self: 'static + Clone + PartialEq + Eq + Display,
@@ -12,7 +16,7 @@ where
     fn color(&self) -> Color;
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum StandardLogLevel {
     Debug,
     Info,
@@ -48,6 +52,8 @@ impl Display for StandardLogLevel {
 #[derive(Debug, Clone)]
 pub struct Log<L: LogLevel> {
     pub level: L,
+    /// The part of the program the log comes from, e.g. `"console"`.
+    pub target: Option<String>,
     pub message: String,
     pub timestamp: DateTime<Utc>,
 }
@@ -80,9 +86,18 @@ impl<L: LogLevel> Logger<L> {
     }
 
     pub fn log(&self, level: L, message: impl Into<String>) {
+        self.forward(level, None, message.into());
+    }
+
+    pub fn log_target(&self, level: L, target: impl Into<String>, message: impl Into<String>) {
+        self.forward(level, Some(target.into()), message.into());
+    }
+
+    fn forward(&self, level: L, target: Option<String>, message: String) {
         let log = Log {
             level,
-            message: message.into(),
+            target,
+            message,
             timestamp: Utc::now(),
         };
 
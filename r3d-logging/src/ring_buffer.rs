@@ -0,0 +1,260 @@
+use crate::{transports::format_timestamp, Log, LogLevel, Transport};
+use chrono::{DateTime, Utc};
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+};
+use uuid::Uuid;
+
+/// A log kept by a [`LogRingBuffer`].
+#[derive(Debug, Clone)]
+pub struct LogRecord<L: LogLevel> {
+    /// Position of the record among every record the buffer has received, starting from 0.
+    pub sequence: u64,
+    pub level: L,
+    pub target: Option<String>,
+    pub message: String,
+    pub timestamp: DateTime<Utc>,
+    /// The frame number set by [`LogRingBuffer::set_frame`] when the log was sent.
+    pub frame: u64,
+}
+
+/// Keeps the latest logs, dropping the oldest ones once full.
+///
+/// Logs arrive through [`RingBufferTransport`]s, which only send them over a channel,
+/// so they can be used from any thread; the buffer owner takes them in with [`drain`](Self::drain).
+pub struct LogRingBuffer<L: LogLevel> {
+    capacity: usize,
+    records: VecDeque<LogRecord<L>>,
+    sender: Sender<(Log<L>, u64)>,
+    receiver: Receiver<(Log<L>, u64)>,
+    frame: Arc<AtomicU64>,
+    received: u64,
+}
+
+impl<L: LogLevel> LogRingBuffer<L> {
+    /// Creates a buffer keeping at most `capacity` records. The capacity is at least 1.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, receiver) = channel();
+
+        Self {
+            capacity: capacity.max(1),
+            records: VecDeque::with_capacity(capacity.max(1)),
+            sender,
+            receiver,
+            frame: Arc::new(AtomicU64::new(0)),
+            received: 0,
+        }
+    }
+
+    /// Creates a transport sending logs to this buffer.
+    pub fn transport(&self) -> RingBufferTransport<L> {
+        RingBufferTransport {
+            id: Uuid::new_v4(),
+            sender: self.sender.clone(),
+            frame: self.frame.clone(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Number of records received so far, including the dropped ones.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Number of records dropped to make room for newer ones.
+    pub fn dropped(&self) -> u64 {
+        self.received - self.records.len() as u64
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame.load(Ordering::Relaxed)
+    }
+
+    /// Sets the frame number stamped on the logs sent from now on.
+    pub fn set_frame(&self, frame: u64) {
+        self.frame.store(frame, Ordering::Relaxed);
+    }
+
+    /// Takes in the logs sent since the last call. Returns the number of new records.
+    pub fn drain(&mut self) -> usize {
+        let mut count = 0;
+
+        while let Ok((log, frame)) = self.receiver.try_recv() {
+            if self.records.len() == self.capacity {
+                self.records.pop_front();
+            }
+
+            self.records.push_back(LogRecord {
+                sequence: self.received,
+                level: log.level,
+                target: log.target,
+                message: log.message,
+                timestamp: log.timestamp,
+                frame,
+            });
+            self.received += 1;
+            count += 1;
+        }
+
+        count
+    }
+
+    /// The kept records, from the oldest to the newest.
+    pub fn records(&self) -> impl DoubleEndedIterator<Item = &LogRecord<L>> + ExactSizeIterator {
+        self.records.iter()
+    }
+
+    /// Writes the kept records as text, from the oldest to the newest, e.g. into a crash report.
+    pub fn write_report(&self, writer: &mut impl Write) -> io::Result<()> {
+        if self.dropped() != 0 {
+            writeln!(writer, "({} older record(s) dropped)", self.dropped())?;
+        }
+
+        for record in &self.records {
+            let message = record.message.split('\n').collect::<Vec<_>>().join("\n\t");
+
+            match &record.target {
+                Some(target) => writeln!(
+                    writer,
+                    "[{}] #{} {} {}: {}",
+                    format_timestamp(record.timestamp),
+                    record.frame,
+                    record.level,
+                    target,
+                    message
+                )?,
+                None => writeln!(
+                    writer,
+                    "[{}] #{} {} {}",
+                    format_timestamp(record.timestamp),
+                    record.frame,
+                    record.level,
+                    message
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Sends logs to a [`LogRingBuffer`]. It can be cloned and moved to other threads.
+pub struct RingBufferTransport<L: LogLevel> {
+    id: Uuid,
+    sender: Sender<(Log<L>, u64)>,
+    frame: Arc<AtomicU64>,
+}
+
+impl<L: LogLevel> RingBufferTransport<L> {
+    /// Sends a log without a [`Logger`](crate::Logger), e.g. from a worker thread.
+    pub fn log(&self, level: L, target: Option<&str>, message: impl Into<String>) {
+        self.forward(&Log {
+            level,
+            target: target.map(|target| target.to_owned()),
+            message: message.into(),
+            timestamp: Utc::now(),
+        });
+    }
+}
+
+impl<L: LogLevel> Clone for RingBufferTransport<L> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            sender: self.sender.clone(),
+            frame: self.frame.clone(),
+        }
+    }
+}
+
+impl<L: LogLevel> Transport<L> for RingBufferTransport<L> {
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn forward(&self, log: &Log<L>) {
+        // The buffer may have been dropped; the log is simply lost then.
+        let _ = self
+            .sender
+            .send((log.clone(), self.frame.load(Ordering::Relaxed)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Logger, StandardLogLevel};
+
+    #[test]
+    fn it_should_drop_oldest_records_on_overflow() {
+        let mut buffer = LogRingBuffer::new(3);
+        let mut logger = Logger::new();
+        logger.wire(Arc::new(buffer.transport()));
+
+        for index in 0..5 {
+            buffer.set_frame(index * 10);
+            logger.log(StandardLogLevel::Info, format!("message {}", index));
+        }
+
+        // Nothing is kept until the buffer takes the logs in.
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.drain(), 5);
+
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.received(), 5);
+        assert_eq!(buffer.dropped(), 2);
+        assert_eq!(
+            buffer
+                .records()
+                .map(|record| (record.sequence, record.frame, record.message.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (2, 20, "message 2"),
+                (3, 30, "message 3"),
+                (4, 40, "message 4")
+            ]
+        );
+
+        let mut report = Vec::new();
+        buffer.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.starts_with("(2 older record(s) dropped)\n"));
+        assert_eq!(report.lines().count(), 4);
+    }
+
+    #[test]
+    fn it_should_receive_logs_from_other_threads() {
+        let mut buffer = LogRingBuffer::new(16);
+        let transport = buffer.transport();
+        buffer.set_frame(7);
+
+        std::thread::spawn(move || {
+            transport.log(StandardLogLevel::Warning, Some("worker"), "from a worker");
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(buffer.drain(), 1);
+        let record = buffer.records().next().unwrap();
+        assert_eq!(record.level, StandardLogLevel::Warning);
+        assert_eq!(record.target.as_deref(), Some("worker"));
+        assert_eq!(record.frame, 7);
+    }
+}
@@ -8,7 +8,7 @@ pub use console_transport::*;
 pub use file_transport::*;
 pub use filter_transport::*;
 
-fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+pub(crate) fn format_timestamp(timestamp: DateTime<Utc>) -> String {
     let local = timestamp.with_timezone(&Local);
 
     let year = local.year();
@@ -29,7 +29,10 @@ impl<L: LogLevel> Transport<L> for ConsoleTransport {
             .join("\n\t")
             .color(log.level.color());
 
-        println!("[{}] {} {}", timestamp, level, message);
+        match &log.target {
+            Some(target) => println!("[{}] {} {}: {}", timestamp, level, target, message),
+            None => println!("[{}] {} {}", timestamp, level, message),
+        }
     }
 }
 
@@ -45,7 +45,10 @@ impl<L: LogLevel> Transport<L> for FileTransport {
     fn forward(&self, log: &Log<L>) {
         let timestamp = format_timestamp(log.timestamp);
         let message = log.message.split('\n').collect::<Vec<_>>().join("\n\t");
-        let lines = format!("[{}] {} {}\n", timestamp, log.level, message);
+        let lines = match &log.target {
+            Some(target) => format!("[{}] {} {}: {}\n", timestamp, log.level, target, message),
+            None => format!("[{}] {} {}\n", timestamp, log.level, message),
+        };
 
         let mut file = self.file.lock();
         file.write_all(lines.as_bytes()).ok();
@@ -0,0 +1,227 @@
+use crate::event::event_types::AnimationEvent;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Debug, sync::Arc};
+
+/// Loops walked in a single advance at most, so that a hitch on a short looping clip cannot flood the event bus.
+const MAX_LOOPS: usize = 8;
+
+/// Extra data carried by an [`AnimationEventMarker`]. Written as a plain string or number in the clip.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum AnimationEventPayload {
+    Float(f32),
+    String(String),
+}
+
+/// A named event placed on a clip. Playback crossing it publishes an [`AnimationEvent`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AnimationEventMarker {
+    pub name: String,
+    /// Normalized time in range [0, 1]. On a looping clip, 0 and 1 are the same instant.
+    pub time: f32,
+    #[serde(default)]
+    pub payload: Option<AnimationEventPayload>,
+    /// The bone the event happens at, e.g. the foot of a footstep, so that handlers can place effects on it.
+    #[serde(default)]
+    pub bone: Option<String>,
+}
+
+impl AnimationEventMarker {
+    pub fn new(name: impl Into<String>, time: f32) -> Self {
+        Self {
+            name: name.into(),
+            time,
+            payload: None,
+            bone: None,
+        }
+    }
+
+    pub fn with_payload(mut self, payload: AnimationEventPayload) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+
+    pub fn with_bone(mut self, bone: impl Into<String>) -> Self {
+        self.bone = Some(bone.into());
+        self
+    }
+}
+
+/// Which clips of a cross-fade fire their events while both are playing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimationEventBlendPolicy {
+    /// Both clips fire their events.
+    All,
+    /// Only the clip weighing more fires its events; the current one on a tie.
+    Dominant,
+    /// Clips weighing at least this much fire their events.
+    MinWeight(f32),
+}
+
+impl AnimationEventBlendPolicy {
+    /// Returns whether the current clip and the blended one fire their events at the blend weight.
+    pub fn fires(self, blend_weight: f32) -> (bool, bool) {
+        match self {
+            Self::All => (true, true),
+            Self::Dominant => (blend_weight <= 0.5, 0.5 < blend_weight),
+            Self::MinWeight(min) => (min <= 1.0 - blend_weight, min <= blend_weight),
+        }
+    }
+}
+
+impl Default for AnimationEventBlendPolicy {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+pub type AnimationEventCallback = Arc<dyn Fn(&AnimationEvent) + Send + Sync>;
+
+/// Callbacks of an animator by event name.
+#[derive(Clone, Default)]
+pub struct AnimationEventCallbacks {
+    callbacks: Vec<(String, AnimationEventCallback)>,
+}
+
+impl AnimationEventCallbacks {
+    pub fn add(&mut self, name: impl Into<String>, callback: AnimationEventCallback) {
+        self.callbacks.push((name.into(), callback));
+    }
+
+    /// Removes the callbacks of the event.
+    pub fn remove(&mut self, name: &str) {
+        self.callbacks.retain(|(event, _)| event != name);
+    }
+
+    pub fn of<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a AnimationEventCallback> + 'a {
+        self.callbacks
+            .iter()
+            .filter(move |(event, _)| event == name)
+            .map(|(_, callback)| callback)
+    }
+}
+
+impl Debug for AnimationEventCallbacks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.callbacks.iter().map(|(event, _)| event))
+            .finish()
+    }
+}
+
+/// Indices of the events crossed by a playback moving by `delta` seconds from `from`, in the order they are crossed.
+/// A negative delta plays backwards.
+///
+/// The event at `from` itself is only crossed if `include_from` is set, e.g. on the first advance of a playback,
+/// so that an event is never fired twice when playback stops on it. Looping clips wrap around, firing an event once
+/// per loop passed, even at the loop boundary; the others stop at their ends.
+pub fn crossed_events(
+    events: &[AnimationEventMarker],
+    from: f32,
+    delta: f32,
+    duration: f32,
+    looping: bool,
+    include_from: bool,
+) -> Vec<usize> {
+    if duration <= 0.0 || delta == 0.0 || events.is_empty() {
+        return Vec::new();
+    }
+
+    let max_delta = duration * MAX_LOOPS as f32;
+    let to = if looping {
+        from + delta.clamp(-max_delta, max_delta)
+    } else {
+        (from + delta).clamp(0.0, duration)
+    };
+    let (low, high) = if from <= to { (from, to) } else { (to, from) };
+    let loops = if looping {
+        (low / duration).floor() as i32..=(high / duration).floor() as i32
+    } else {
+        0..=0
+    };
+
+    let mut crossed = Vec::new();
+
+    for index in loops {
+        let offset = index as f32 * duration;
+
+        for (event, marker) in events.iter().enumerate() {
+            let time = if looping {
+                marker.time.rem_euclid(1.0) * duration + offset
+            } else {
+                marker.time.clamp(0.0, 1.0) * duration
+            };
+
+            if low <= time && time <= high && (include_from || time != from) {
+                crossed.push((event, time));
+            }
+        }
+    }
+
+    if from <= to {
+        crossed.sort_by(|a, b| a.1.total_cmp(&b.1));
+    } else {
+        crossed.sort_by(|a, b| b.1.total_cmp(&a.1));
+    }
+
+    crossed.into_iter().map(|(event, _)| event).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walk() -> Vec<AnimationEventMarker> {
+        vec![
+            AnimationEventMarker::new("left", 0.0),
+            AnimationEventMarker::new("right", 0.5),
+            AnimationEventMarker::new("end", 1.0),
+        ]
+    }
+
+    #[test]
+    fn check_events_are_crossed_in_order() {
+        let events = walk();
+
+        // The first advance fires the event at the start; the later ones don't fire it again.
+        assert_eq!(crossed_events(&events, 0.0, 0.5, 2.0, false, true), [0]);
+        assert_eq!(crossed_events(&events, 0.5, 0.5, 2.0, false, false), [1]);
+        assert_eq!(crossed_events(&events, 1.0, 5.0, 2.0, false, false), [2]);
+        // Held at the end, nothing is crossed anymore.
+        assert!(crossed_events(&events, 2.0, 1.0, 2.0, false, false).is_empty());
+        // Backwards from the end.
+        assert_eq!(
+            crossed_events(&events, 2.0, -2.0, 2.0, false, false),
+            [1, 0]
+        );
+    }
+
+    #[test]
+    fn check_loop_boundary_fires_once_per_loop() {
+        let events = vec![AnimationEventMarker::new("step", 1.0)];
+        let mut time = 0.0;
+        let mut fired = 0;
+
+        for step in 0..12 {
+            fired += crossed_events(&events, time, 0.25, 1.0, true, step == 0).len();
+            time = (time + 0.25f32).rem_euclid(1.0);
+        }
+
+        // Once at the start, then once per loop completed.
+        assert_eq!(fired, 4);
+
+        // A delta larger than the clip passes every event of every loop, in order.
+        let events = walk();
+        assert_eq!(
+            crossed_events(&events, 0.25, 2.5, 1.0, true, false),
+            [1, 0, 2, 1, 0, 2, 1]
+        );
+        assert_eq!(
+            crossed_events(&events, 0.25, -1.0, 1.0, true, false),
+            [0, 2, 1]
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use super::SkeletalAnimation;
+use crate::object::ObjectId;
+use specs::{prelude::*, Component};
+use std::{collections::HashMap, sync::Arc};
+
+/// Plays skeletal animation clips on the objects under its own, e.g. the nodes of a model spawned by
+/// [`spawn_model`](crate::object::spawn_model). The tracks of a clip move the descendants named after their bones;
+/// the object of the player itself included.
+///
+/// The transforms are written before the object matrices are updated, so that skinned meshes and objects attached
+/// to the bones follow them on the same frame.
+#[derive(Component, Debug, Clone)]
+#[storage(HashMapStorage)]
+pub struct AnimationPlayer {
+    /// A negative speed plays backwards.
+    pub speed: f32,
+    /// Restarts the clip once it reaches its end, instead of stopping at its last pose.
+    pub looping: bool,
+    clips: HashMap<String, Arc<SkeletalAnimation>>,
+    current: Option<String>,
+    time: f32,
+    is_playing: bool,
+    targets: Option<Vec<Option<ObjectId>>>,
+}
+
+impl AnimationPlayer {
+    pub fn new() -> Self {
+        Self {
+            speed: 1.0,
+            looping: true,
+            clips: HashMap::new(),
+            current: None,
+            time: 0.0,
+            is_playing: false,
+            targets: None,
+        }
+    }
+
+    pub fn with_clip(mut self, name: impl Into<String>, clip: SkeletalAnimation) -> Self {
+        self.add_clip(name, clip);
+        self
+    }
+
+    /// Adds or replaces a clip. Replacing the current clip keeps playing the new one from the same time.
+    pub fn add_clip(&mut self, name: impl Into<String>, clip: SkeletalAnimation) {
+        let name = name.into();
+
+        if self.current.as_ref() == Some(&name) {
+            self.targets = None;
+        }
+
+        self.clips.insert(name, Arc::new(clip));
+    }
+
+    pub fn clip(&self, name: &str) -> Option<&SkeletalAnimation> {
+        self.clips.get(name).map(Arc::as_ref)
+    }
+
+    pub fn clip_names(&self) -> impl Iterator<Item = &str> {
+        self.clips.keys().map(String::as_str)
+    }
+
+    /// Plays the clip from its start; its end when playing backwards. Returns `false` if there is no such clip.
+    pub fn play(&mut self, name: &str) -> bool {
+        let clip = match self.clips.get(name) {
+            Some(clip) => clip,
+            None => return false,
+        };
+
+        self.time = if self.speed < 0.0 {
+            clip.duration().max(0.0)
+        } else {
+            0.0
+        };
+        self.current = Some(name.to_owned());
+        self.is_playing = true;
+        self.targets = None;
+        true
+    }
+
+    /// Stops playing, leaving the objects at their current pose.
+    pub fn stop(&mut self) {
+        self.is_playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.is_playing
+    }
+
+    /// Name of the clip played last, even if stopped.
+    pub fn current_clip(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Seeks the current clip. Out of range times are wrapped or clamped on the next advance.
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    /// Advances the current clip by `delta` seconds scaled by the speed, and returns the clip and the time to sample
+    /// it at, or `None` if nothing is playing.
+    ///
+    /// Looping clips wrap around, however many times a frame crosses them, e.g. clips shorter than a frame. Other
+    /// clips stop at their end, which is still returned so that their last pose is applied. A clip without duration,
+    /// as with single keyframes only, holds its pose.
+    pub fn advance(&mut self, delta: f32) -> Option<(Arc<SkeletalAnimation>, f32)> {
+        if !self.is_playing {
+            return None;
+        }
+
+        let clip = match self.current.as_ref().and_then(|name| self.clips.get(name)) {
+            Some(clip) => clip.clone(),
+            None => {
+                self.is_playing = false;
+                return None;
+            }
+        };
+        let duration = clip.duration();
+
+        self.time += delta * self.speed;
+
+        if duration <= 0.0 {
+            self.time = 0.0;
+            self.is_playing = self.looping;
+        } else if self.looping {
+            // `rem_euclid` may round tiny negative times up to the duration itself.
+            self.time = self.time.rem_euclid(duration).min(duration);
+        } else {
+            let is_finished = if self.speed < 0.0 {
+                self.time <= 0.0
+            } else {
+                duration <= self.time
+            };
+            self.time = self.time.clamp(0.0, duration);
+            self.is_playing = !is_finished;
+        }
+
+        Some((clip, self.time))
+    }
+
+    /// Objects moved by the tracks of the current clip, in the order of the tracks. Resolved by name with `resolve`
+    /// once per clip, and again when `is_stale` reports one of them, e.g. removed.
+    pub(crate) fn targets(
+        &mut self,
+        mut resolve: impl FnMut(&str) -> Option<ObjectId>,
+        mut is_stale: impl FnMut(ObjectId) -> bool,
+    ) -> &[Option<ObjectId>] {
+        if let Some(targets) = &self.targets {
+            if targets.iter().flatten().any(|&target| is_stale(target)) {
+                self.targets = None;
+            }
+        }
+
+        let clip = self.current.as_ref().and_then(|name| self.clips.get(name));
+        self.targets.get_or_insert_with(|| match clip {
+            Some(clip) => Vec::from_iter(clip.tracks.iter().map(|track| resolve(&track.bone))),
+            None => Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{AnimationCurve, BoneTrack};
+
+    fn clip(duration: f32) -> SkeletalAnimation {
+        SkeletalAnimation {
+            tracks: vec![BoneTrack {
+                bone: "bone".to_owned(),
+                rotation: None,
+                position: Some([
+                    AnimationCurve::linear(0.0, 1.0, duration),
+                    AnimationCurve::constant(0.0),
+                    AnimationCurve::constant(0.0),
+                ]),
+                scale: None,
+            }],
+            events: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn check_looping_clips_wrap_around() {
+        let mut player = AnimationPlayer::new().with_clip("walk", clip(1.0));
+
+        assert!(player.advance(0.5).is_none());
+        assert!(!player.play("run"));
+        assert!(player.play("walk"));
+
+        assert_eq!(player.advance(0.25).map(|(_, time)| time), Some(0.25));
+        assert_eq!(player.advance(1.0).map(|(_, time)| time), Some(0.25));
+        assert!(player.is_playing());
+
+        player.speed = -1.0;
+        assert_eq!(player.advance(0.5).map(|(_, time)| time), Some(0.75));
+    }
+
+    #[test]
+    fn check_other_clips_stop_at_their_end() {
+        let mut player = AnimationPlayer::new().with_clip("jump", clip(1.0));
+        player.looping = false;
+        player.play("jump");
+
+        assert_eq!(player.advance(0.5).map(|(_, time)| time), Some(0.5));
+        assert!(player.is_playing());
+        assert_eq!(player.advance(2.0).map(|(_, time)| time), Some(1.0));
+        assert!(!player.is_playing());
+        assert!(player.advance(0.5).is_none());
+
+        player.speed = -1.0;
+        player.play("jump");
+        assert_eq!(player.time(), 1.0);
+        assert_eq!(player.advance(2.0).map(|(_, time)| time), Some(0.0));
+        assert!(!player.is_playing());
+    }
+
+    #[test]
+    fn check_clips_shorter_than_a_frame() {
+        let mut player = AnimationPlayer::new()
+            .with_clip("blink", clip(0.01))
+            .with_clip("pose", clip(0.0));
+
+        player.play("blink");
+        let time = player.advance(1.0 / 60.0).unwrap().1;
+        assert!((0.0..=0.01).contains(&time));
+        assert!(player.is_playing());
+
+        player.looping = false;
+        player.play("pose");
+        assert_eq!(player.advance(1.0 / 60.0).map(|(_, time)| time), Some(0.0));
+        assert!(!player.is_playing());
+    }
+}
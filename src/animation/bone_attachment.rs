@@ -0,0 +1,255 @@
+use super::Skeleton;
+use crate::{
+    math::Mat4,
+    object::{ObjectHierarchy, ObjectId},
+    transform::Transform,
+};
+use specs::{prelude::*, Component};
+
+/// A bone of the skin of a [`MeshRenderer`](crate::gfx::MeshRenderer), found by its name with
+/// [`bone_handle`](crate::gfx::MeshRenderer::bone_handle).
+///
+/// The handle keeps the name, so that it still finds the bone after the skin is replaced, e.g. when the model is
+/// reloaded; the index only saves the lookup while the skeleton stays the same.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BoneHandle {
+    name: String,
+    index: usize,
+}
+
+impl BoneHandle {
+    pub(crate) fn new(name: impl Into<String>, index: usize) -> Self {
+        Self {
+            name: name.into(),
+            index,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the index of the bone in the skeleton, if it still has a bone of the name.
+    pub fn resolve(&self, skeleton: &Skeleton) -> Option<usize> {
+        match skeleton.bones().get(self.index) {
+            Some(bone) if bone.name == self.name => Some(self.index),
+            _ => skeleton.bone_index(&self.name),
+        }
+    }
+}
+
+/// Keeps an object attached to a bone of a skinned mesh renderer, as set by
+/// [`attach_to_bone`](crate::object::ObjectHandle::attach_to_bone).
+///
+/// The object is a child of the object of the renderer, and its attachment matrix is the current pose of the bone
+/// in the space of the renderer, so that its matrix is its transform in the space of the bone. Its children follow
+/// it as usual. If the bone goes missing, e.g. renamed by a reload of the model, the object keeps the last pose of
+/// the bone instead of snapping to the renderer.
+#[derive(Component, Debug, Clone)]
+#[storage(HashMapStorage)]
+pub struct BoneAttachment {
+    renderer: ObjectId,
+    bone: BoneHandle,
+    previous_parent: Option<ObjectId>,
+    is_bone_missing: bool,
+}
+
+impl BoneAttachment {
+    pub(crate) fn new(
+        renderer: ObjectId,
+        bone: BoneHandle,
+        previous_parent: Option<ObjectId>,
+    ) -> Self {
+        Self {
+            renderer,
+            bone,
+            previous_parent,
+            is_bone_missing: false,
+        }
+    }
+
+    /// The object of the skinned mesh renderer.
+    pub fn renderer(&self) -> ObjectId {
+        self.renderer
+    }
+
+    pub fn bone(&self) -> &BoneHandle {
+        &self.bone
+    }
+
+    /// The parent the object gets back once detached.
+    pub fn previous_parent(&self) -> Option<ObjectId> {
+        self.previous_parent
+    }
+
+    pub fn is_bone_missing(&self) -> bool {
+        self.is_bone_missing
+    }
+
+    /// Moves the attachment matrix of `object` to the current pose of the bone, given as the object moving it,
+    /// from the transforms rather than from the matrices of the last update. Without a bone, the last matrix stays.
+    /// Returns `true` if the bone went missing on this update, so that it is reported once.
+    pub fn update<'a>(
+        &mut self,
+        hierarchy: &mut ObjectHierarchy,
+        object: ObjectId,
+        bone_object: Option<ObjectId>,
+        transforms: impl Fn(Entity) -> Option<&'a Transform>,
+    ) -> bool {
+        let bone_object = match bone_object.filter(|&bone| hierarchy.contains(bone)) {
+            Some(bone_object) => bone_object,
+            None => {
+                let went_missing = !self.is_bone_missing;
+                self.is_bone_missing = true;
+                return went_missing;
+            }
+        };
+
+        self.is_bone_missing = false;
+        let matrix = bone_matrix(hierarchy, self.renderer, bone_object, transforms);
+        hierarchy.set_attachment(object, Some(matrix));
+        false
+    }
+}
+
+/// Matrix of the bone in the space of the renderer, computed from the current transforms.
+pub(crate) fn bone_matrix<'a>(
+    hierarchy: &ObjectHierarchy,
+    renderer: ObjectId,
+    bone_object: ObjectId,
+    transforms: impl Fn(Entity) -> Option<&'a Transform>,
+) -> Mat4 {
+    let renderer_matrix = hierarchy.compute_matrix(renderer, &transforms);
+    hierarchy.compute_matrix(bone_object, &transforms) * &renderer_matrix.inversed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        animation::{Bone, BonePose},
+        math::{Quat, Vec3},
+    };
+    use std::collections::HashMap;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn skeleton() -> Skeleton {
+        Skeleton::new(vec![
+            Bone {
+                name: "shoulder".to_owned(),
+                parent: None,
+                rest: BonePose::default(),
+            },
+            Bone {
+                name: "hand".to_owned(),
+                parent: Some(0),
+                rest: BonePose::default(),
+            },
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn check_bone_handle_survives_reordering() {
+        let bone = BoneHandle::new("hand", 1);
+        assert_eq!(bone.resolve(&skeleton()), Some(1));
+
+        let mut bones = skeleton().bones().to_vec();
+        bones.insert(
+            0,
+            Bone {
+                name: "root".to_owned(),
+                parent: None,
+                rest: BonePose::default(),
+            },
+        );
+        bones[1].parent = Some(0);
+        bones[2].parent = Some(1);
+        assert_eq!(bone.resolve(&Skeleton::new(bones).unwrap()), Some(2));
+
+        let mut bones = skeleton().bones().to_vec();
+        bones[1].name = "palm".to_owned();
+        assert_eq!(bone.resolve(&Skeleton::new(bones).unwrap()), None);
+    }
+
+    #[test]
+    fn check_attached_object_tracks_the_hand() {
+        let mut world = World::new();
+        let mut hierarchy = ObjectHierarchy::new();
+        let [root, shoulder, hand, renderer, prop] = [0, 1, 2, 3, 4].map(ObjectId::from_u32);
+
+        for object in [root, shoulder, hand, renderer, prop] {
+            hierarchy.add(object, world.create_entity().build());
+        }
+
+        // The character is moved by its root; the arm and the mesh are siblings under it, as spawned models are.
+        hierarchy.set_parent(shoulder, Some(root)).unwrap();
+        hierarchy.set_parent(hand, Some(shoulder)).unwrap();
+        hierarchy.set_parent(renderer, Some(root)).unwrap();
+        hierarchy.set_parent(prop, Some(renderer)).unwrap();
+
+        let transform = |position: Vec3| {
+            let mut transform = Transform::new();
+            transform.position = position;
+            transform
+        };
+        let mut transforms = HashMap::new();
+        transforms.insert(hierarchy.entity(root), transform(Vec3::new(5.0, 0.0, 0.0)));
+        transforms.insert(
+            hierarchy.entity(shoulder),
+            transform(Vec3::new(0.0, 1.5, 0.0)),
+        );
+        transforms.insert(hierarchy.entity(hand), transform(Vec3::new(0.0, 0.6, 0.0)));
+        transforms.insert(
+            hierarchy.entity(renderer),
+            transform(Vec3::new(0.0, 0.0, 1.0)),
+        );
+        // The prop is held a little past the hand, along the arm.
+        transforms.insert(hierarchy.entity(prop), transform(Vec3::new(0.0, 0.2, 0.0)));
+
+        let mut attachment = BoneAttachment::new(renderer, BoneHandle::new("hand", 1), None);
+
+        for frame in 0..8 {
+            // The arm waves about the Z axis of the shoulder.
+            let angle = (frame as f32 * 0.7).sin() * 1.2;
+            transforms
+                .get_mut(&hierarchy.entity(shoulder))
+                .unwrap()
+                .rotation = Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), angle);
+            hierarchy.set_dirty(shoulder);
+
+            // The pose is sampled before the matrices are updated, so it is read from the transforms.
+            assert!(
+                !attachment.update(&mut hierarchy, prop, Some(hand), |entity| {
+                    transforms.get(&entity)
+                })
+            );
+            hierarchy.update_object_matrices(|entity| transforms.get(&entity));
+
+            let hand_matrix = hierarchy.matrix(hand).clone();
+            let expected = Mat4::translation(Vec3::new(0.0, 0.2, 0.0)) * &hand_matrix;
+            let (expected, _, _) = expected.split();
+            let (position, _, _) = hierarchy.matrix(prop).split();
+            assert!(
+                (position - expected).len() < EPSILON,
+                "frame {}: {:?} is not {:?}",
+                frame,
+                position,
+                expected
+            );
+        }
+
+        // The hand goes missing: the prop keeps the last pose, relative to the renderer, instead of snapping to it.
+        let last = hierarchy.matrix(prop).clone();
+        assert!(attachment.update(&mut hierarchy, prop, None, |entity| transforms.get(&entity)));
+        assert!(!attachment.update(&mut hierarchy, prop, None, |entity| transforms.get(&entity)));
+        assert!(attachment.is_bone_missing());
+        hierarchy.set_dirty(prop);
+        hierarchy.update_object_matrices(|entity| transforms.get(&entity));
+
+        let (position, _, _) = hierarchy.matrix(prop).split();
+        let (last, _, _) = last.split();
+        assert!((position - last).len() < EPSILON);
+    }
+}
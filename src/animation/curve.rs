@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CurveInterpolation {
+    /// Holds the value of a keyframe until the next one.
+    Step,
+    Linear,
+    /// Cubic Hermite, shaped by the tangents of the keyframes.
+    Cubic,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    pub time: f32,
+    pub value: f32,
+    /// Slope of the curve arriving at the keyframe, in value per second. Used by cubic interpolation only.
+    #[serde(default)]
+    pub in_tangent: f32,
+    /// Slope of the curve leaving the keyframe, in value per second. Used by cubic interpolation only.
+    #[serde(default)]
+    pub out_tangent: f32,
+}
+
+impl Keyframe {
+    pub fn new(time: f32, value: f32) -> Self {
+        Self {
+            time,
+            value,
+            in_tangent: 0.0,
+            out_tangent: 0.0,
+        }
+    }
+
+    pub fn with_tangents(mut self, in_tangent: f32, out_tangent: f32) -> Self {
+        self.in_tangent = in_tangent;
+        self.out_tangent = out_tangent;
+        self
+    }
+}
+
+/// A scalar value over time. Before the first keyframe and after the last one, the curve holds their values.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AnimationCurve {
+    pub interpolation: CurveInterpolation,
+    /// Sorted by time.
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl AnimationCurve {
+    pub fn new(interpolation: CurveInterpolation, mut keyframes: Vec<Keyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self {
+            interpolation,
+            keyframes,
+        }
+    }
+
+    pub fn constant(value: f32) -> Self {
+        Self::new(CurveInterpolation::Step, vec![Keyframe::new(0.0, value)])
+    }
+
+    pub fn linear(from: f32, to: f32, duration: f32) -> Self {
+        Self::new(
+            CurveInterpolation::Linear,
+            vec![Keyframe::new(0.0, from), Keyframe::new(duration, to)],
+        )
+    }
+
+    /// The time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    pub fn evaluate(&self, time: f32) -> f32 {
+        let (first, last) = match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return 0.0,
+        };
+
+        if time <= first.time {
+            return first.value;
+        }
+
+        if last.time <= time {
+            return last.value;
+        }
+
+        // The first keyframe after the time; the range checks above keep it in [1, len - 1].
+        let index = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+        let from = &self.keyframes[index - 1];
+        let to = &self.keyframes[index];
+        let span = to.time - from.time;
+
+        if span <= 0.0 {
+            return to.value;
+        }
+
+        let t = (time - from.time) / span;
+
+        match self.interpolation {
+            CurveInterpolation::Step => from.value,
+            CurveInterpolation::Linear => from.value + (to.value - from.value) * t,
+            CurveInterpolation::Cubic => {
+                let t2 = t * t;
+                let t3 = t2 * t;
+                let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+                let h10 = t3 - 2.0 * t2 + t;
+                let h01 = -2.0 * t3 + 3.0 * t2;
+                let h11 = t3 - t2;
+
+                h00 * from.value
+                    + h10 * span * from.out_tangent
+                    + h01 * to.value
+                    + h11 * span * to.in_tangent
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn equals_float(a: f32, b: f32) -> bool {
+        (a - b).abs() <= 1e-5
+    }
+
+    #[test]
+    fn check_curve_evaluation() {
+        let keyframes = vec![
+            Keyframe::new(1.0, 2.0),
+            Keyframe::new(0.0, 0.0),
+            Keyframe::new(2.0, 2.0),
+        ];
+
+        let step = AnimationCurve::new(CurveInterpolation::Step, keyframes.clone());
+        assert_eq!(step.duration(), 2.0);
+        assert_eq!(step.evaluate(-1.0), 0.0);
+        assert_eq!(step.evaluate(0.5), 0.0);
+        assert_eq!(step.evaluate(1.5), 2.0);
+        assert_eq!(step.evaluate(3.0), 2.0);
+
+        let linear = AnimationCurve::new(CurveInterpolation::Linear, keyframes.clone());
+        assert!(equals_float(linear.evaluate(0.25), 0.5));
+        assert!(equals_float(linear.evaluate(1.0), 2.0));
+        assert!(equals_float(linear.evaluate(1.5), 2.0));
+
+        // Flat tangents ease in and out; the midpoint of a segment is its average.
+        let cubic = AnimationCurve::new(CurveInterpolation::Cubic, keyframes);
+        assert!(equals_float(cubic.evaluate(0.5), 1.0));
+        assert!(cubic.evaluate(0.1) < linear.evaluate(0.1));
+
+        // Tangents matching the slope of a line reproduce the line.
+        let line = AnimationCurve::new(
+            CurveInterpolation::Cubic,
+            vec![
+                Keyframe::new(0.0, 0.0).with_tangents(2.0, 2.0),
+                Keyframe::new(1.0, 2.0).with_tangents(2.0, 2.0),
+            ],
+        );
+        assert!(equals_float(line.evaluate(0.3), 0.6));
+
+        assert_eq!(
+            AnimationCurve::new(CurveInterpolation::Linear, vec![]).evaluate(1.0),
+            0.0
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use super::Skeleton;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Canonical bones of a humanoid skeleton, which clips are transferred through when retargeted.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum HumanoidBone {
+    Hips,
+    Spine,
+    Chest,
+    Neck,
+    Head,
+    LeftUpperLeg,
+    LeftLowerLeg,
+    LeftFoot,
+    RightUpperLeg,
+    RightLowerLeg,
+    RightFoot,
+    LeftShoulder,
+    LeftUpperArm,
+    LeftLowerArm,
+    LeftHand,
+    RightShoulder,
+    RightUpperArm,
+    RightLowerArm,
+    RightHand,
+}
+
+impl HumanoidBone {
+    pub const ALL: [HumanoidBone; 19] = [
+        Self::Hips,
+        Self::Spine,
+        Self::Chest,
+        Self::Neck,
+        Self::Head,
+        Self::LeftUpperLeg,
+        Self::LeftLowerLeg,
+        Self::LeftFoot,
+        Self::RightUpperLeg,
+        Self::RightLowerLeg,
+        Self::RightFoot,
+        Self::LeftShoulder,
+        Self::LeftUpperArm,
+        Self::LeftLowerArm,
+        Self::LeftHand,
+        Self::RightShoulder,
+        Self::RightUpperArm,
+        Self::RightLowerArm,
+        Self::RightHand,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// Maps the canonical bones onto the bones of a skeleton, by name. Canonical bones may be left unmapped;
+/// retargeting skips them.
+///
+/// Authored as a table of canonical bone to bone name, e.g. in the import metadata of a model:
+///
+/// ```toml
+/// [humanoid]
+/// hips = "mixamorig:Hips"
+/// left_upper_leg = "mixamorig:LeftUpLeg"
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(transparent)]
+pub struct HumanoidRig {
+    bones: BTreeMap<HumanoidBone, String>,
+}
+
+impl HumanoidRig {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Guesses the mapping from common bone naming conventions, e.g. `LeftUpLeg`, `thigh_l` or `Bip01 L Thigh`.
+    /// Canonical bones matching no bone are left unmapped.
+    pub fn guess(skeleton: &Skeleton) -> Self {
+        let mut rig = Self::new();
+        let mut spines = Vec::new();
+
+        for bone in skeleton.bones() {
+            let (side, name) = split_side(&bone.name);
+            let name = name.as_str();
+            let canonical = match side {
+                None if matches!(name, "hips" | "pelvis" | "hip") => Some(HumanoidBone::Hips),
+                None if name == "chest" || name == "upperchest" => Some(HumanoidBone::Chest),
+                None if name.starts_with("spine") => {
+                    spines.push(bone.name.clone());
+                    None
+                }
+                None if name.starts_with("neck") => Some(HumanoidBone::Neck),
+                None if name == "head" => Some(HumanoidBone::Head),
+                Some(side) => {
+                    let (upper_leg, lower_leg, foot, shoulder, upper_arm, lower_arm, hand) =
+                        match side {
+                            Side::Left => (
+                                HumanoidBone::LeftUpperLeg,
+                                HumanoidBone::LeftLowerLeg,
+                                HumanoidBone::LeftFoot,
+                                HumanoidBone::LeftShoulder,
+                                HumanoidBone::LeftUpperArm,
+                                HumanoidBone::LeftLowerArm,
+                                HumanoidBone::LeftHand,
+                            ),
+                            Side::Right => (
+                                HumanoidBone::RightUpperLeg,
+                                HumanoidBone::RightLowerLeg,
+                                HumanoidBone::RightFoot,
+                                HumanoidBone::RightShoulder,
+                                HumanoidBone::RightUpperArm,
+                                HumanoidBone::RightLowerArm,
+                                HumanoidBone::RightHand,
+                            ),
+                        };
+
+                    match name {
+                        "upleg" | "upperleg" | "thigh" | "hip" => Some(upper_leg),
+                        "leg" | "lowerleg" | "calf" | "shin" | "knee" => Some(lower_leg),
+                        "foot" | "ankle" => Some(foot),
+                        "shoulder" | "clavicle" | "collar" => Some(shoulder),
+                        "arm" | "upperarm" => Some(upper_arm),
+                        "forearm" | "lowerarm" | "elbow" => Some(lower_arm),
+                        "hand" | "wrist" => Some(hand),
+                        _ => None,
+                    }
+                }
+                None => None,
+            };
+
+            if let Some(canonical) = canonical {
+                // The first match wins, as parents come before their children.
+                rig.bones
+                    .entry(canonical)
+                    .or_insert_with(|| bone.name.clone());
+            }
+        }
+
+        // The lowest spine bone is the spine, the next one the chest unless a bone is named so.
+        let mut spines = spines.into_iter();
+
+        if let Some(spine) = spines.next() {
+            rig.bones.entry(HumanoidBone::Spine).or_insert(spine);
+        }
+
+        if let Some(chest) = spines.next() {
+            rig.bones.entry(HumanoidBone::Chest).or_insert(chest);
+        }
+
+        rig
+    }
+
+    /// Guesses the mapping, then applies the manually mapped bones of `overrides` over it.
+    pub fn guess_with_overrides(skeleton: &Skeleton, overrides: &HumanoidRig) -> Self {
+        let mut rig = Self::guess(skeleton);
+        rig.bones.extend(
+            overrides
+                .bones
+                .iter()
+                .map(|(bone, name)| (*bone, name.clone())),
+        );
+        rig
+    }
+
+    pub fn with_bone(mut self, bone: HumanoidBone, name: impl Into<String>) -> Self {
+        self.set_bone(bone, name);
+        self
+    }
+
+    pub fn set_bone(&mut self, bone: HumanoidBone, name: impl Into<String>) {
+        self.bones.insert(bone, name.into());
+    }
+
+    pub fn remove_bone(&mut self, bone: HumanoidBone) -> Option<String> {
+        self.bones.remove(&bone)
+    }
+
+    pub fn bone_name(&self, bone: HumanoidBone) -> Option<&str> {
+        self.bones.get(&bone).map(String::as_str)
+    }
+
+    /// Index of the bone of `skeleton` the canonical bone maps onto, if it is mapped and the skeleton has it.
+    pub fn bone_index(&self, skeleton: &Skeleton, bone: HumanoidBone) -> Option<usize> {
+        skeleton.bone_index(self.bone_name(bone)?)
+    }
+}
+
+/// Splits the side off a bone name, and normalizes the rest: lowercase, without any namespace prefix
+/// such as `mixamorig:`, separators or digits.
+fn split_side(name: &str) -> (Option<Side>, String) {
+    let name = name.rsplit(':').next().unwrap_or(name).to_lowercase();
+    let mut side = None;
+    let mut rest = String::new();
+
+    for token in name.split(|c: char| !c.is_ascii_alphanumeric()) {
+        match token {
+            "l" | "left" if side.is_none() => side = Some(Side::Left),
+            "r" | "right" if side.is_none() => side = Some(Side::Right),
+            "bip01" | "bip" => {}
+            token => rest.push_str(token),
+        }
+    }
+
+    // Camel case names keep the side within the token, e.g. `LeftUpLeg`.
+    if side.is_none() {
+        for (prefix, prefix_side) in [("left", Side::Left), ("right", Side::Right)] {
+            if let Some(stripped) = rest.strip_prefix(prefix) {
+                side = Some(prefix_side);
+                rest = stripped.to_owned();
+                break;
+            }
+        }
+    }
+
+    rest.retain(|c| !c.is_ascii_digit());
+    (side, rest)
+}
@@ -0,0 +1,141 @@
+use crate::math::{Quat, Vec3};
+use specs::{prelude::*, Component};
+
+/// Rotations bending a two-bone chain, e.g. a leg or an arm, so that its end reaches a target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TwoBoneIkSolution {
+    /// Rotation to apply to the root bone, in the space the positions are given in.
+    pub root: Quat,
+    /// Rotation to apply to the mid bone after [`root`](Self::root), in the same space.
+    pub mid: Quat,
+}
+
+/// Solves a two-bone chain from the positions of its joints, all in the same space. The chain bends towards `pole`,
+/// e.g. a point in front of the knee. Targets out of reach are approached as far as the chain stretches.
+pub fn solve_two_bone_ik(
+    root: Vec3,
+    mid: Vec3,
+    end: Vec3,
+    target: Vec3,
+    pole: Vec3,
+) -> TwoBoneIkSolution {
+    let upper_len = Vec3::distance(root, mid);
+    let lower_len = Vec3::distance(mid, end);
+    let to_target = target - root;
+
+    if upper_len <= f32::EPSILON || lower_len <= f32::EPSILON || to_target.len() <= f32::EPSILON {
+        return TwoBoneIkSolution {
+            root: Quat::IDENTITY,
+            mid: Quat::IDENTITY,
+        };
+    }
+
+    // Never fully stretched nor folded, which would leave the bending plane undefined.
+    let epsilon = 1e-4 * (upper_len + lower_len);
+    let distance = to_target.len().clamp(
+        (upper_len - lower_len).abs() + epsilon,
+        upper_len + lower_len - epsilon,
+    );
+    let direction = to_target.normalized();
+
+    // Direction within the bending plane, perpendicular to the target direction and towards the pole.
+    let mut bend = Vec3::cross(Vec3::cross(direction, pole - root), direction);
+
+    if bend.len_square() <= 1e-12 {
+        // The pole lies on the line to the target; keep the current bend instead.
+        bend = Vec3::cross(Vec3::cross(direction, mid - root), direction);
+    }
+
+    if bend.len_square() <= 1e-12 {
+        // The chain is straight along the line as well; any perpendicular will do.
+        bend = Vec3::cross(direction, Vec3::RIGHT);
+
+        if bend.len_square() <= 1e-12 {
+            bend = Vec3::cross(direction, Vec3::UP);
+        }
+    }
+
+    let bend = bend.normalized();
+    let cos = ((upper_len * upper_len + distance * distance - lower_len * lower_len)
+        / (2.0 * upper_len * distance))
+        .clamp(-1.0, 1.0);
+    let sin = (1.0 - cos * cos).sqrt();
+    let new_mid = root + upper_len * (cos * direction + sin * bend);
+    let new_end = root + distance * direction;
+
+    let root_rotation = Quat::from_rotation_arc(mid - root, new_mid - root);
+    let mid_rotation = Quat::from_rotation_arc(root_rotation * (end - mid), new_end - new_mid);
+
+    TwoBoneIkSolution {
+        root: root_rotation,
+        mid: mid_rotation,
+    }
+}
+
+/// Bends the object's parent and grandparent so that the object reaches the target, as the end of a two-bone chain.
+/// The object itself keeps its local transform.
+///
+/// The target and the pole are in world space. The chain is expected to be uniformly scaled.
+#[derive(Component, Debug, Clone)]
+#[storage(HashMapStorage)]
+pub struct IkConstraint {
+    pub target: Vec3,
+    /// A point the chain bends towards, e.g. in front of the knee or behind the elbow.
+    pub pole: Vec3,
+    /// Blends between the animated pose at zero and the solved pose at one.
+    pub weight: f32,
+}
+
+impl IkConstraint {
+    pub fn new(target: Vec3, pole: Vec3) -> Self {
+        Self {
+            target,
+            pole,
+            weight: 1.0,
+        }
+    }
+
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_two_bone_ik_reaches_target() {
+        let root = Vec3::new(0.0, 1.0, 0.0);
+        let mid = Vec3::new(0.0, 0.5, 0.0);
+        let end = Vec3::new(0.0, 0.0, 0.0);
+        let target = Vec3::new(0.3, 0.3, 0.2);
+        let pole = Vec3::new(0.0, 0.5, 1.0);
+
+        let solution = solve_two_bone_ik(root, mid, end, target, pole);
+        let new_mid = root + solution.root * (mid - root);
+        let new_end = new_mid + solution.mid * (solution.root * (end - mid));
+
+        assert!(Vec3::distance(new_end, target) < 1e-3);
+        assert!((Vec3::distance(root, new_mid) - 0.5).abs() < 1e-4);
+        assert!((Vec3::distance(new_mid, new_end) - 0.5).abs() < 1e-4);
+        // Bent towards the pole.
+        assert!(0.0 < new_mid.z);
+    }
+
+    #[test]
+    fn check_two_bone_ik_stretches_towards_unreachable_target() {
+        let root = Vec3::new(0.0, 1.0, 0.0);
+        let mid = Vec3::new(0.0, 0.5, 0.1);
+        let end = Vec3::new(0.0, 0.0, 0.0);
+        let target = Vec3::new(0.0, -2.0, 0.0);
+
+        let solution = solve_two_bone_ik(root, mid, end, target, Vec3::new(0.0, 0.5, 1.0));
+        let new_mid = root + solution.root * (mid - root);
+        let new_end = new_mid + solution.mid * (solution.root * (end - mid));
+        let reach = Vec3::distance(root, mid) + Vec3::distance(mid, end);
+
+        assert!(new_end.y < 1.0 - reach * 0.99);
+    }
+}
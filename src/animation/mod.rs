@@ -0,0 +1,72 @@
+mod animation_event;
+mod animation_player;
+mod bone_attachment;
+mod curve;
+mod humanoid_rig;
+mod ik;
+mod path_follower;
+mod property_animation;
+mod property_animator;
+mod retarget;
+mod skeletal_animation;
+mod skeleton;
+
+pub use animation_event::*;
+pub use animation_player::*;
+pub use bone_attachment::*;
+pub use curve::*;
+pub use humanoid_rig::*;
+pub use ik::*;
+pub use path_follower::*;
+pub use property_animation::*;
+pub use property_animator::*;
+pub use retarget::*;
+pub use skeletal_animation::*;
+pub use skeleton::*;
+
+use crate::math::Spline;
+use asset::AssetKey;
+use std::collections::HashMap;
+
+/// Holds the animation clips and the shared paths by their asset keys.
+#[derive(Debug, Default)]
+pub struct AnimationManager {
+    property_animations: HashMap<AssetKey, PropertyAnimation>,
+    splines: HashMap<AssetKey, Spline>,
+}
+
+impl AnimationManager {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn property_animation(&self, key: &AssetKey) -> Option<&PropertyAnimation> {
+        self.property_animations.get(key)
+    }
+
+    /// Adds or replaces a clip. Animators playing it pick up the new one on the next frame.
+    pub fn insert_property_animation(
+        &mut self,
+        key: AssetKey,
+        animation: PropertyAnimation,
+    ) -> Option<PropertyAnimation> {
+        self.property_animations.insert(key, animation)
+    }
+
+    pub fn remove_property_animation(&mut self, key: &AssetKey) -> Option<PropertyAnimation> {
+        self.property_animations.remove(key)
+    }
+
+    pub fn spline(&self, key: &AssetKey) -> Option<&Spline> {
+        self.splines.get(key)
+    }
+
+    /// Adds or replaces a path. Followers on it move along the new one from the same distance on the next frame.
+    pub fn insert_spline(&mut self, key: AssetKey, spline: Spline) -> Option<Spline> {
+        self.splines.insert(key, spline)
+    }
+
+    pub fn remove_spline(&mut self, key: &AssetKey) -> Option<Spline> {
+        self.splines.remove(key)
+    }
+}
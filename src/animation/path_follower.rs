@@ -0,0 +1,341 @@
+use crate::math::{Mat4, Quat, Spline, Vec3};
+use asset::AssetKey;
+use specs::{prelude::*, Component};
+use std::{f32::consts::FRAC_PI_2, sync::Arc};
+
+/// The spline a [`PathFollower`] moves along.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSource {
+    Inline(Arc<Spline>),
+    /// A spline shared through the [`AnimationManager`](super::AnimationManager).
+    Asset(AssetKey),
+}
+
+impl From<Spline> for PathSource {
+    fn from(spline: Spline) -> Self {
+        Self::Inline(Arc::new(spline))
+    }
+}
+
+impl From<AssetKey> for PathSource {
+    fn from(key: AssetKey) -> Self {
+        Self::Asset(key)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PathFollowMode {
+    /// Stops at the end of the path.
+    Once,
+    /// Starts over from the beginning at the end of the path.
+    Loop,
+    /// Turns around at both ends of the path.
+    PingPong,
+}
+
+/// Turns a [`PathFollower`] to face its direction of travel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathAlignment {
+    /// Preferred up direction; the follower only tilts away from it as much as the path slopes.
+    pub up: Vec3,
+    /// Roll into the turns: the turn rate, in radians per unit of distance, times this factor gives the roll in radians,
+    /// up to a quarter turn. Zero keeps the follower level.
+    pub banking: f32,
+}
+
+impl Default for PathAlignment {
+    fn default() -> Self {
+        Self {
+            up: Vec3::UP,
+            banking: 0.0,
+        }
+    }
+}
+
+/// A named place along a path. Reaching it publishes a [`PathMarkerReached`](crate::event::event_types::PathMarkerReached).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathMarker {
+    pub name: String,
+    /// Distance from the start of the path.
+    pub distance: f32,
+}
+
+/// Moves the object along a spline at a constant speed, scaled by the time scale.
+///
+/// The spline is in the space of the object's parent; the follower writes the position of the object,
+/// and its rotation too if [`alignment`](Self::alignment) is set.
+#[derive(Component, Debug, Clone)]
+#[storage(HashMapStorage)]
+pub struct PathFollower {
+    pub path: PathSource,
+    /// Distance per second. A negative speed moves backwards.
+    pub speed: f32,
+    pub mode: PathFollowMode,
+    pub alignment: Option<PathAlignment>,
+    markers: Vec<PathMarker>,
+    distance: f32,
+    is_reversed: bool,
+    is_finished: bool,
+    warned_missing_path: bool,
+}
+
+impl PathFollower {
+    /// Legs walked in a single advance at most, so that a huge step along a tiny path cannot spin forever.
+    const MAX_LEGS: usize = 8;
+
+    pub fn new(path: impl Into<PathSource>, speed: f32, mode: PathFollowMode) -> Self {
+        Self {
+            path: path.into(),
+            speed,
+            mode,
+            alignment: None,
+            markers: Vec::new(),
+            distance: 0.0,
+            is_reversed: false,
+            is_finished: false,
+            warned_missing_path: false,
+        }
+    }
+
+    pub fn with_alignment(mut self, alignment: PathAlignment) -> Self {
+        self.alignment = Some(alignment);
+        self
+    }
+
+    pub fn with_marker(mut self, name: impl Into<String>, distance: f32) -> Self {
+        self.add_marker(name, distance);
+        self
+    }
+
+    /// Distance travelled from the start of the path.
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    /// Moves the follower to the distance without publishing the markers in between, and resumes it if it finished.
+    pub fn set_distance(&mut self, distance: f32) {
+        self.distance = distance;
+        self.is_finished = false;
+    }
+
+    /// Returns `true` while the follower is heading back to the start in [`PathFollowMode::PingPong`].
+    pub fn is_reversed(&self) -> bool {
+        self.is_reversed
+    }
+
+    /// Returns `true` once the follower stopped at an end in [`PathFollowMode::Once`].
+    pub fn is_finished(&self) -> bool {
+        self.is_finished
+    }
+
+    pub fn markers(&self) -> &[PathMarker] {
+        &self.markers
+    }
+
+    pub fn add_marker(&mut self, name: impl Into<String>, distance: f32) {
+        self.markers.push(PathMarker {
+            name: name.into(),
+            distance,
+        });
+    }
+
+    /// Removes the markers with the name.
+    pub fn remove_markers(&mut self, name: &str) {
+        self.markers.retain(|marker| marker.name != name);
+    }
+
+    pub fn resolve<'a>(
+        &'a self,
+        splines: impl Fn(&AssetKey) -> Option<&'a Spline>,
+    ) -> Option<&'a Spline> {
+        match &self.path {
+            PathSource::Inline(spline) => Some(spline.as_ref()),
+            PathSource::Asset(key) => splines(key),
+        }
+    }
+
+    /// Moves along a path of the length, returning the indices of the markers passed in order.
+    pub fn advance(&mut self, delta: f32, length: f32) -> Vec<usize> {
+        let mut reached = Vec::new();
+
+        if self.is_finished || length <= 0.0 {
+            return reached;
+        }
+
+        self.distance = self.distance.clamp(0.0, length);
+
+        let mut remaining = (self.speed * delta).abs();
+        let mut is_forward = (0.0 <= self.speed) != self.is_reversed;
+        let mut include_start = false;
+
+        for _ in 0..Self::MAX_LEGS {
+            if remaining <= 0.0 {
+                break;
+            }
+
+            let end = if is_forward { length } else { 0.0 };
+            let room = (end - self.distance).abs();
+            let next = if remaining < room {
+                self.distance + if is_forward { remaining } else { -remaining }
+            } else {
+                end
+            };
+
+            self.collect_markers(self.distance, next, include_start, length, &mut reached);
+            remaining -= room.min(remaining);
+            self.distance = next;
+
+            if next != end {
+                break;
+            }
+
+            match self.mode {
+                PathFollowMode::Once => {
+                    self.is_finished = true;
+                    break;
+                }
+                PathFollowMode::Loop => {
+                    // The markers at the start are passed again once the follower comes around.
+                    self.distance = length - end;
+                    include_start = true;
+                }
+                PathFollowMode::PingPong => {
+                    self.is_reversed = !self.is_reversed;
+                    is_forward = !is_forward;
+                    include_start = false;
+                }
+            }
+        }
+
+        reached
+    }
+
+    /// Position and, if aligned, rotation of the follower on the spline.
+    pub fn pose(&self, spline: &Spline) -> (Vec3, Option<Quat>) {
+        let position = spline.point_at_distance(self.distance);
+        let alignment = match &self.alignment {
+            Some(alignment) => alignment,
+            None => return (position, None),
+        };
+
+        let is_forward = (0.0 <= self.speed) != self.is_reversed;
+        let direction = if is_forward { 1.0 } else { -1.0 };
+        let forward = spline.tangent_at(self.distance) * direction;
+
+        if forward.len_square() <= f32::EPSILON {
+            return (position, None);
+        }
+
+        // An up vector along the path cannot tell the sides apart; any other axis can.
+        let up = if Vec3::cross(alignment.up, forward).len_square() <= 1e-6 {
+            if forward.y.abs() < 0.9 {
+                Vec3::UP
+            } else {
+                Vec3::BACKWARD
+            }
+        } else {
+            alignment.up
+        };
+        let mut rotation = Quat::from_mat4(&Mat4::look_at(Vec3::ZERO, forward, up));
+
+        if alignment.banking != 0.0 {
+            let step =
+                spline.length() / (spline.segment_count() * spline.samples_per_segment()) as f32;
+            let ahead = spline.tangent_at(self.distance + step * direction) * direction;
+
+            if 0.0 < step && f32::EPSILON < ahead.len_square() {
+                let turn = Vec3::angle_signed(forward, ahead, up) / step;
+                let bank = (turn * alignment.banking).clamp(-FRAC_PI_2, FRAC_PI_2);
+                // Rolling about the forward axis by a negative angle lowers the left side, into a left turn.
+                rotation *= Quat::from_axis_angle(Vec3::FORWARD, -bank);
+            }
+        }
+
+        (position, Some(rotation))
+    }
+
+    /// Positions of the markers on the spline, e.g. for drawing them along [`Spline::polyline`].
+    pub fn marker_positions<'a>(
+        &'a self,
+        spline: &'a Spline,
+    ) -> impl Iterator<Item = (&'a PathMarker, Vec3)> + 'a {
+        self.markers
+            .iter()
+            .map(|marker| (marker, spline.point_at_distance(marker.distance)))
+    }
+
+    /// Returns `true` only the first time the path cannot be found, so that it is warned once.
+    pub fn should_warn_missing_path(&mut self) -> bool {
+        !std::mem::replace(&mut self.warned_missing_path, true)
+    }
+
+    fn collect_markers(
+        &self,
+        from: f32,
+        to: f32,
+        include_from: bool,
+        length: f32,
+        reached: &mut Vec<usize>,
+    ) {
+        let (low, high) = if from <= to { (from, to) } else { (to, from) };
+        let mut passed = self
+            .markers
+            .iter()
+            .enumerate()
+            .map(|(index, marker)| (index, marker.distance.clamp(0.0, length)))
+            .filter(|&(_, distance)| {
+                low <= distance && distance <= high && (include_from || distance != from)
+            })
+            .collect::<Vec<_>>();
+
+        if from <= to {
+            passed.sort_by(|a, b| a.1.total_cmp(&b.1));
+        } else {
+            passed.sort_by(|a, b| b.1.total_cmp(&a.1));
+        }
+
+        reached.extend(passed.into_iter().map(|(index, _)| index));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn follower(speed: f32, mode: PathFollowMode) -> PathFollower {
+        let spline = Spline::catmull_rom(vec![Vec3::ZERO, Vec3::RIGHT * 10.0], false).unwrap();
+        PathFollower::new(spline, speed, mode)
+            .with_marker("start", 0.0)
+            .with_marker("middle", 5.0)
+            .with_marker("end", 10.0)
+    }
+
+    #[test]
+    fn check_markers_are_reached_in_order() {
+        let mut once = follower(4.0, PathFollowMode::Once);
+        assert_eq!(once.advance(1.0, 10.0), Vec::<usize>::new());
+        assert_eq!(once.advance(1.0, 10.0), [1]);
+        assert_eq!(once.advance(1.0, 10.0), [2]);
+        assert!(once.is_finished());
+        assert_eq!(once.distance(), 10.0);
+        assert_eq!(once.advance(1.0, 10.0), Vec::<usize>::new());
+
+        let mut looping = follower(4.0, PathFollowMode::Loop);
+        looping.set_distance(8.0);
+        assert_eq!(looping.advance(1.0, 10.0), [2, 0]);
+        assert_eq!(looping.distance(), 2.0);
+
+        let mut ping_pong = follower(4.0, PathFollowMode::PingPong);
+        ping_pong.set_distance(8.0);
+        assert_eq!(ping_pong.advance(2.0, 10.0), [2, 1]);
+        assert!(ping_pong.is_reversed());
+        assert_eq!(ping_pong.distance(), 4.0);
+        assert_eq!(ping_pong.advance(1.0, 10.0), [0]);
+        assert!(!ping_pong.is_reversed());
+
+        // A zero-length path holds the follower in place.
+        let mut empty = follower(4.0, PathFollowMode::Loop);
+        assert_eq!(empty.advance(1.0, 0.0), Vec::<usize>::new());
+        assert_eq!(empty.distance(), 0.0);
+    }
+}
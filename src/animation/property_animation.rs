@@ -0,0 +1,252 @@
+use super::{AnimationCurve, AnimationEventMarker};
+use crate::gfx::PerInstancePropertyValue;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+use thiserror::Error;
+use wgpu::VertexFormat;
+
+/// A clip animating material and renderer properties. Referenced by its asset key.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PropertyAnimation {
+    pub tracks: Vec<PropertyTrack>,
+    /// Events fired by the animators playing the clip, see [`crossed_events`](super::crossed_events).
+    #[serde(default)]
+    pub events: Vec<AnimationEventMarker>,
+}
+
+impl PropertyAnimation {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn with_track(mut self, target: impl Into<String>, curve: AnimationCurve) -> Self {
+        self.tracks.push(PropertyTrack {
+            target: target.into(),
+            curve,
+        });
+        self
+    }
+
+    pub fn with_event(mut self, event: AnimationEventMarker) -> Self {
+        self.events.push(event);
+        self
+    }
+
+    /// The time of the last keyframe among the tracks.
+    pub fn duration(&self) -> f32 {
+        self.tracks
+            .iter()
+            .map(|track| track.curve.duration())
+            .fold(0.0, f32::max)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PropertyTrack {
+    /// Path of the animated property, see [`PropertyTarget`].
+    pub target: String,
+    pub curve: AnimationCurve,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PropertyTargetError {
+    #[error("unknown property scope in `{0}`; expected `material.` or `renderer.instance.`")]
+    UnknownScope(String),
+    #[error("missing property name in `{0}`")]
+    MissingName(String),
+    #[error(
+        "unknown component `{component}` in `{path}`; expected one of x, y, z, w or r, g, b, a"
+    )]
+    UnknownComponent { path: String, component: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PropertyScope {
+    /// The per-instance property of the material itself, shared by every renderer using it.
+    Material,
+    /// An override of the material's per-instance property for a single renderer.
+    RendererInstance,
+}
+
+/// A parsed track target, such as `material.emissive_strength` or `renderer.instance.tint.a`.
+/// Without a component, every component of a vector property is set to the value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PropertyTarget {
+    pub scope: PropertyScope,
+    pub name: String,
+    pub component: Option<usize>,
+}
+
+impl PropertyTarget {
+    /// Writes the animated value into the targeted component(s) of a property of the given format, keeping the other
+    /// components of the current value. Returns `None` if the format isn't a float one or lacks the component.
+    pub fn write(
+        &self,
+        current: Option<&PerInstancePropertyValue>,
+        format: VertexFormat,
+        value: f32,
+    ) -> Option<PerInstancePropertyValue> {
+        let mut components = match (format, current) {
+            (VertexFormat::Float32, Some(PerInstancePropertyValue::Float32(current))) => {
+                current.to_vec()
+            }
+            (VertexFormat::Float32x2, Some(PerInstancePropertyValue::Float32x2(current))) => {
+                current.to_vec()
+            }
+            (VertexFormat::Float32x3, Some(PerInstancePropertyValue::Float32x3(current))) => {
+                current.to_vec()
+            }
+            (VertexFormat::Float32x4, Some(PerInstancePropertyValue::Float32x4(current))) => {
+                current.to_vec()
+            }
+            (VertexFormat::Float32, _) => vec![0.0; 1],
+            (VertexFormat::Float32x2, _) => vec![0.0; 2],
+            (VertexFormat::Float32x3, _) => vec![0.0; 3],
+            (VertexFormat::Float32x4, _) => vec![0.0; 4],
+            _ => return None,
+        };
+
+        match self.component {
+            Some(component) => *components.get_mut(component)? = value,
+            None => components.fill(value),
+        }
+
+        Some(match components.len() {
+            1 => PerInstancePropertyValue::Float32([components[0]]),
+            2 => PerInstancePropertyValue::Float32x2([components[0], components[1]]),
+            3 => PerInstancePropertyValue::Float32x3([components[0], components[1], components[2]]),
+            _ => PerInstancePropertyValue::Float32x4([
+                components[0],
+                components[1],
+                components[2],
+                components[3],
+            ]),
+        })
+    }
+}
+
+impl FromStr for PropertyTarget {
+    type Err = PropertyTargetError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let (scope, rest) = if let Some(rest) = path.strip_prefix("material.") {
+            (PropertyScope::Material, rest)
+        } else if let Some(rest) = path.strip_prefix("renderer.instance.") {
+            (PropertyScope::RendererInstance, rest)
+        } else {
+            return Err(PropertyTargetError::UnknownScope(path.to_owned()));
+        };
+
+        let (name, component) = match rest.split_once('.') {
+            Some((name, component)) => {
+                let component = match component {
+                    "x" | "r" => 0,
+                    "y" | "g" => 1,
+                    "z" | "b" => 2,
+                    "w" | "a" => 3,
+                    _ => {
+                        return Err(PropertyTargetError::UnknownComponent {
+                            path: path.to_owned(),
+                            component: component.to_owned(),
+                        })
+                    }
+                };
+                (name, Some(component))
+            }
+            None => (rest, None),
+        };
+
+        if name.is_empty() {
+            return Err(PropertyTargetError::MissingName(path.to_owned()));
+        }
+
+        Ok(Self {
+            scope,
+            name: name.to_owned(),
+            component,
+        })
+    }
+}
+
+impl Display for PropertyTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.scope {
+            PropertyScope::Material => write!(f, "material.{}", self.name)?,
+            PropertyScope::RendererInstance => write!(f, "renderer.instance.{}", self.name)?,
+        }
+
+        match self.component {
+            Some(component) => write!(f, ".{}", ["x", "y", "z", "w"][component]),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_property_target_parsing() {
+        assert_eq!(
+            "material.emissive_strength".parse(),
+            Ok(PropertyTarget {
+                scope: PropertyScope::Material,
+                name: "emissive_strength".to_owned(),
+                component: None,
+            })
+        );
+        assert_eq!(
+            "renderer.instance.tint.a".parse(),
+            Ok(PropertyTarget {
+                scope: PropertyScope::RendererInstance,
+                name: "tint".to_owned(),
+                component: Some(3),
+            })
+        );
+        assert_eq!(
+            "renderer.instance.tint.a"
+                .parse::<PropertyTarget>()
+                .unwrap()
+                .to_string(),
+            "renderer.instance.tint.w"
+        );
+        assert!(matches!(
+            "transform.position".parse::<PropertyTarget>(),
+            Err(PropertyTargetError::UnknownScope(_))
+        ));
+        assert!(matches!(
+            "material.".parse::<PropertyTarget>(),
+            Err(PropertyTargetError::MissingName(_))
+        ));
+        assert!(matches!(
+            "material.tint.q".parse::<PropertyTarget>(),
+            Err(PropertyTargetError::UnknownComponent { .. })
+        ));
+    }
+
+    #[test]
+    fn check_property_target_write() {
+        let alpha: PropertyTarget = "renderer.instance.tint.a".parse().unwrap();
+        assert_eq!(
+            alpha.write(
+                Some(&PerInstancePropertyValue::Float32x4([1.0, 0.5, 0.25, 1.0])),
+                VertexFormat::Float32x4,
+                0.5
+            ),
+            Some(PerInstancePropertyValue::Float32x4([1.0, 0.5, 0.25, 0.5]))
+        );
+        assert_eq!(alpha.write(None, VertexFormat::Float32x2, 0.5), None);
+        assert_eq!(alpha.write(None, VertexFormat::Uint32x4, 0.5), None);
+
+        let dissolve: PropertyTarget = "material.dissolve".parse().unwrap();
+        assert_eq!(
+            dissolve.write(None, VertexFormat::Float32, 0.75),
+            Some(PerInstancePropertyValue::Float32([0.75]))
+        );
+        assert_eq!(
+            dissolve.write(None, VertexFormat::Float32x3, 2.0),
+            Some(PerInstancePropertyValue::Float32x3([2.0, 2.0, 2.0]))
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use super::{
+    crossed_events, AnimationEventBlendPolicy, AnimationEventCallback, AnimationEventCallbacks,
+    AnimationEventMarker, PropertyAnimation,
+};
+use crate::event::event_types::AnimationEvent;
+use asset::AssetKey;
+use specs::{prelude::*, Component};
+use std::{collections::HashSet, sync::Arc};
+
+/// A clip being played by a [`PropertyAnimator`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyPlayback {
+    pub clip: AssetKey,
+    pub time: f32,
+    pub looping: bool,
+    has_advanced: bool,
+}
+
+impl PropertyPlayback {
+    pub fn new(clip: AssetKey, looping: bool) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            looping,
+            has_advanced: false,
+        }
+    }
+
+    /// Returns the indices of the events crossed, the one at the start included on the first advance.
+    fn advance(
+        &mut self,
+        delta: f32,
+        duration: f32,
+        events: &[AnimationEventMarker],
+    ) -> Vec<usize> {
+        let crossed = crossed_events(
+            events,
+            self.time,
+            delta,
+            duration,
+            self.looping,
+            !self.has_advanced,
+        );
+
+        if delta != 0.0 {
+            self.has_advanced = true;
+        }
+
+        self.time += delta;
+
+        if duration <= 0.0 {
+            self.time = 0.0;
+        } else if self.looping {
+            self.time = self.time.rem_euclid(duration);
+        } else {
+            self.time = self.time.clamp(0.0, duration);
+        }
+
+        crossed
+    }
+}
+
+/// Plays property animation clips on the object's renderer, optionally blending a second clip over the first one.
+///
+/// The events of the clips crossed while playing are published as [`AnimationEvent`]s, and passed to the callbacks
+/// registered with [`on_event`](Self::on_event).
+#[derive(Component, Debug, Clone)]
+#[storage(HashMapStorage)]
+pub struct PropertyAnimator {
+    /// A negative speed plays backwards.
+    pub speed: f32,
+    /// Which clips fire their events while blending.
+    pub event_policy: AnimationEventBlendPolicy,
+    playback: Option<PropertyPlayback>,
+    blend: Option<PropertyPlayback>,
+    blend_weight: f32,
+    callbacks: AnimationEventCallbacks,
+    warned_targets: HashSet<String>,
+}
+
+impl PropertyAnimator {
+    pub fn new() -> Self {
+        Self {
+            speed: 1.0,
+            event_policy: AnimationEventBlendPolicy::default(),
+            playback: None,
+            blend: None,
+            blend_weight: 0.0,
+            callbacks: AnimationEventCallbacks::default(),
+            warned_targets: HashSet::new(),
+        }
+    }
+
+    pub fn playback(&self) -> Option<&PropertyPlayback> {
+        self.playback.as_ref()
+    }
+
+    pub fn blend(&self) -> Option<&PropertyPlayback> {
+        self.blend.as_ref()
+    }
+
+    pub fn blend_weight(&self) -> f32 {
+        self.blend_weight
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    /// Returns `true` if nothing is playing or every clip has reached its end without looping; the start when playing
+    /// backwards.
+    /// Unregistered clips count as finished.
+    pub fn is_finished<'a>(
+        &self,
+        clips: impl Fn(&AssetKey) -> Option<&'a PropertyAnimation>,
+    ) -> bool {
+        [&self.playback, &self.blend]
+            .into_iter()
+            .flatten()
+            .all(|playback| match clips(&playback.clip) {
+                Some(_) if playback.looping => false,
+                Some(_) if self.speed < 0.0 => playback.time <= 0.0,
+                Some(clip) => clip.duration() <= playback.time,
+                None => true,
+            })
+    }
+
+    /// Plays the clip from the start, dropping the blended clip if any.
+    pub fn play(&mut self, clip: AssetKey, looping: bool) {
+        self.playback = Some(PropertyPlayback::new(clip, looping));
+        self.blend = None;
+        self.blend_weight = 0.0;
+    }
+
+    /// Plays a second clip from the start alongside the current one. The weight is in range [0, 1];
+    /// at 0 only the current clip shows, at 1 only the blended one.
+    pub fn blend_with(&mut self, clip: AssetKey, looping: bool, weight: f32) {
+        self.blend = Some(PropertyPlayback::new(clip, looping));
+        self.set_blend_weight(weight);
+    }
+
+    pub fn set_blend_weight(&mut self, weight: f32) {
+        self.blend_weight = weight.clamp(0.0, 1.0);
+    }
+
+    pub fn stop(&mut self) {
+        self.playback = None;
+        self.blend = None;
+        self.blend_weight = 0.0;
+    }
+
+    /// Calls the callback whenever an event of the name fires, after it is published.
+    pub fn on_event(
+        &mut self,
+        name: impl Into<String>,
+        callback: impl Fn(&AnimationEvent) + Send + Sync + 'static,
+    ) {
+        self.callbacks.add(name, Arc::new(callback));
+    }
+
+    /// Removes the callbacks of the event.
+    pub fn remove_event_callbacks(&mut self, name: &str) {
+        self.callbacks.remove(name);
+    }
+
+    pub fn event_callbacks<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a AnimationEventCallback> + 'a {
+        self.callbacks.of(name)
+    }
+
+    /// Advances the clips by the delta time in seconds, scaled by the speed, returning the events crossed with their
+    /// clips in order; the events of the current clip come first. While blending, the events fire according to
+    /// the [`event_policy`](Self::event_policy). Clips that are not registered don't move.
+    pub fn advance<'a>(
+        &mut self,
+        delta: f32,
+        clips: impl Fn(&AssetKey) -> Option<&'a PropertyAnimation>,
+    ) -> Vec<(AssetKey, &'a AnimationEventMarker)> {
+        let delta = delta * self.speed;
+        let (playback_fires, blend_fires) = match self.blend {
+            Some(_) => self.event_policy.fires(self.blend_weight),
+            None => (true, false),
+        };
+        let mut fired = Vec::new();
+
+        for (playback, fires) in [
+            (&mut self.playback, playback_fires),
+            (&mut self.blend, blend_fires),
+        ] {
+            let playback = match playback {
+                Some(playback) => playback,
+                None => continue,
+            };
+
+            if let Some(clip) = clips(&playback.clip) {
+                let crossed = playback.advance(delta, clip.duration(), &clip.events);
+
+                if fires {
+                    fired.extend(
+                        crossed
+                            .into_iter()
+                            .map(|index| (playback.clip.clone(), &clip.events[index])),
+                    );
+                }
+            }
+        }
+
+        fired
+    }
+
+    /// Evaluates the tracks of the clips at their current times, returning the target paths and their values.
+    /// Targets animated by both clips are blended by the weight; the others take the value of the clip animating them.
+    pub fn sample<'a>(
+        &self,
+        clips: impl Fn(&AssetKey) -> Option<&'a PropertyAnimation>,
+    ) -> Vec<(&'a str, f32)> {
+        let evaluate = |playback: Option<&PropertyPlayback>| {
+            playback
+                .and_then(|playback| clips(&playback.clip).map(|clip| (clip, playback.time)))
+                .map(|(clip, time)| {
+                    Vec::from_iter(
+                        clip.tracks
+                            .iter()
+                            .map(|track| (track.target.as_str(), track.curve.evaluate(time))),
+                    )
+                })
+                .unwrap_or_default()
+        };
+
+        let mut values = evaluate(self.playback.as_ref());
+        let blended = evaluate(self.blend.as_ref());
+
+        for (target, blended_value) in blended {
+            match values.iter_mut().find(|(existing, _)| *existing == target) {
+                Some((_, value)) => *value += (blended_value - *value) * self.blend_weight,
+                None => values.push((target, blended_value)),
+            }
+        }
+
+        values
+    }
+
+    /// Returns `true` only the first time it is called for the target, so that unresolvable targets are reported once.
+    pub fn should_warn(&mut self, target: &str) -> bool {
+        if self.warned_targets.contains(target) {
+            return false;
+        }
+
+        self.warned_targets.insert(target.to_owned());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::AnimationCurve;
+    use std::collections::HashMap;
+
+    fn equals_float(a: f32, b: f32) -> bool {
+        (a - b).abs() <= 1e-5
+    }
+
+    fn key(name: &str) -> AssetKey {
+        AssetKey::Path(name.to_owned())
+    }
+
+    #[test]
+    fn check_animator_playback_and_blending() {
+        let mut clips = HashMap::new();
+        clips.insert(
+            key("dissolve"),
+            PropertyAnimation::new().with_track(
+                "renderer.instance.dissolve",
+                AnimationCurve::linear(0.0, 1.0, 2.0),
+            ),
+        );
+        clips.insert(
+            key("pulse"),
+            PropertyAnimation::new()
+                .with_track("renderer.instance.dissolve", AnimationCurve::constant(0.0))
+                .with_track(
+                    "material.emissive_strength",
+                    AnimationCurve::linear(1.0, 3.0, 1.0),
+                ),
+        );
+
+        let mut animator = PropertyAnimator::new();
+        animator.play(key("dissolve"), false);
+        animator.advance(0.5, |key| clips.get(key));
+        assert_eq!(
+            animator.sample(|key| clips.get(key)),
+            vec![("renderer.instance.dissolve", 0.25)]
+        );
+
+        // Without looping, the clip holds at its end.
+        assert!(!animator.is_finished(|key| clips.get(key)));
+        animator.speed = 2.0;
+        animator.advance(1.0, |key| clips.get(key));
+        assert_eq!(animator.playback().unwrap().time, 2.0);
+        assert!(animator.is_finished(|key| clips.get(key)));
+
+        animator.play(key("dissolve"), true);
+        animator.advance(2.5, |key| clips.get(key));
+        assert!(equals_float(animator.playback().unwrap().time, 1.0));
+
+        animator.blend_with(key("pulse"), false, 0.25);
+        animator.advance(0.25, |key| clips.get(key));
+        let values = animator.sample(|key| clips.get(key));
+        assert_eq!(values.len(), 2);
+        // The dissolve clip is at 1.5s (0.75) and the pulse clip at 0.5s.
+        assert_eq!(values[0].0, "renderer.instance.dissolve");
+        assert!(equals_float(values[0].1, 0.75 * 0.75));
+        assert_eq!(values[1].0, "material.emissive_strength");
+        assert!(equals_float(values[1].1, 2.0));
+
+        // Unregistered clips are skipped.
+        animator.play(key("missing"), false);
+        animator.advance(1.0, |key| clips.get(key));
+        assert!(animator.sample(|key| clips.get(key)).is_empty());
+
+        assert!(animator.should_warn("material.missing"));
+        assert!(!animator.should_warn("material.missing"));
+    }
+
+    #[test]
+    fn check_animator_fires_events() {
+        let mut clips = HashMap::new();
+        clips.insert(
+            key("walk"),
+            PropertyAnimation::new()
+                .with_track("material.dissolve", AnimationCurve::linear(0.0, 1.0, 1.0))
+                .with_event(AnimationEventMarker::new("footstep", 0.0).with_bone("foot.l"))
+                .with_event(AnimationEventMarker::new("footstep", 0.5).with_bone("foot.r")),
+        );
+        clips.insert(
+            key("swing"),
+            PropertyAnimation::new()
+                .with_track("material.dissolve", AnimationCurve::linear(0.0, 1.0, 2.0))
+                .with_event(AnimationEventMarker::new("hit", 0.5)),
+        );
+
+        fn names(fired: Vec<(AssetKey, &AnimationEventMarker)>) -> Vec<String> {
+            fired
+                .into_iter()
+                .map(|(_, marker)| format!("{}@{}", marker.name, marker.time))
+                .collect()
+        }
+
+        let mut animator = PropertyAnimator::new();
+        animator.play(key("walk"), true);
+        assert_eq!(
+            names(animator.advance(0.25, |key| clips.get(key))),
+            ["footstep@0"]
+        );
+        assert_eq!(
+            names(animator.advance(0.25, |key| clips.get(key))),
+            ["footstep@0.5"]
+        );
+        assert!(names(animator.advance(0.25, |key| clips.get(key))).is_empty());
+        // The step at the loop boundary fires once, at the end of the loop.
+        assert_eq!(
+            names(animator.advance(0.25, |key| clips.get(key))),
+            ["footstep@0"]
+        );
+        assert!(names(animator.advance(0.25, |key| clips.get(key))).is_empty());
+
+        // A delta longer than the clip, sped up, passes both steps of every loop.
+        animator.speed = 2.0;
+        assert_eq!(
+            names(animator.advance(1.25, |key| clips.get(key))),
+            [
+                "footstep@0.5",
+                "footstep@0",
+                "footstep@0.5",
+                "footstep@0",
+                "footstep@0.5"
+            ]
+        );
+        assert!(equals_float(animator.playback().unwrap().time, 0.75));
+
+        // Backwards.
+        animator.speed = -1.0;
+        assert_eq!(
+            names(animator.advance(0.5, |key| clips.get(key))),
+            ["footstep@0.5"]
+        );
+        assert_eq!(
+            names(animator.advance(0.5, |key| clips.get(key))),
+            ["footstep@0"]
+        );
+
+        // While cross-fading, the policy picks the clips firing their events.
+        animator.speed = 1.0;
+        animator.play(key("walk"), true);
+        animator.advance(0.25, |key| clips.get(key));
+        animator.blend_with(key("swing"), false, 0.25);
+        let fired = animator.advance(1.0, |key| clips.get(key));
+        assert_eq!(
+            fired
+                .iter()
+                .map(|(clip, _)| clip.clone())
+                .collect::<Vec<_>>(),
+            [key("walk"), key("walk"), key("swing")]
+        );
+
+        animator.play(key("walk"), true);
+        animator.advance(0.25, |key| clips.get(key));
+        animator.blend_with(key("swing"), false, 0.25);
+        animator.event_policy = AnimationEventBlendPolicy::Dominant;
+        assert_eq!(
+            names(animator.advance(1.0, |key| clips.get(key))),
+            ["footstep@0.5", "footstep@0"]
+        );
+
+        animator.play(key("walk"), true);
+        animator.advance(0.25, |key| clips.get(key));
+        animator.blend_with(key("swing"), false, 0.75);
+        animator.event_policy = AnimationEventBlendPolicy::MinWeight(0.5);
+        assert_eq!(
+            names(animator.advance(1.0, |key| clips.get(key))),
+            ["hit@0.5"]
+        );
+    }
+}
@@ -0,0 +1,580 @@
+use super::{solve_two_bone_ik, BonePose, HumanoidBone, HumanoidRig, SkeletalAnimation, Skeleton};
+use crate::math::Vec3;
+
+/// A leg of the target skeleton that can be bent to place its foot, and the source foot it follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RetargetLeg {
+    source_foot: usize,
+    upper: usize,
+    lower: usize,
+    foot: usize,
+}
+
+/// Transfers poses between two humanoid skeletons of different proportions or naming, through their
+/// [`HumanoidRig`]s.
+///
+/// Rotations are transferred in model space, relative to the rest poses, so the target keeps its own bone lengths
+/// and rest pose; both skeletons are expected to share the model space orientation, e.g. Y up and facing the same way.
+/// The hips translation is scaled by the ratio of the leg lengths so that the stride fits the target.
+///
+/// Canonical bones missing on either side are skipped, leaving the target bones at the pose they are given.
+#[derive(Debug, Clone)]
+pub struct Retargeter {
+    source: Skeleton,
+    target: Skeleton,
+    /// Source bone of each target bone, for the mapped ones.
+    source_bones: Vec<Option<usize>>,
+    source_rest: Vec<BonePose>,
+    target_rest: Vec<BonePose>,
+    /// Source and target hips.
+    hips: Option<(usize, usize)>,
+    legs: Vec<RetargetLeg>,
+    leg_ratio: f32,
+    missing_bones: Vec<HumanoidBone>,
+    foot_locking: bool,
+}
+
+impl Retargeter {
+    pub fn new(
+        source: &Skeleton,
+        source_rig: &HumanoidRig,
+        target: &Skeleton,
+        target_rig: &HumanoidRig,
+    ) -> Self {
+        let source_rest = source.model_poses(&source.rest_pose());
+        let target_rest = target.model_poses(&target.rest_pose());
+        let mut source_bones = vec![None; target.bones().len()];
+        let mut missing_bones = Vec::new();
+
+        for bone in HumanoidBone::ALL {
+            match (
+                source_rig.bone_index(source, bone),
+                target_rig.bone_index(target, bone),
+            ) {
+                (Some(source_index), Some(target_index)) => {
+                    source_bones[target_index] = Some(source_index)
+                }
+                _ => missing_bones.push(bone),
+            }
+        }
+
+        let pair = |bone| {
+            Some((
+                source_rig.bone_index(source, bone)?,
+                target_rig.bone_index(target, bone)?,
+            ))
+        };
+        let hips = pair(HumanoidBone::Hips);
+
+        let mut legs = Vec::new();
+        let mut source_leg_len = 0.0;
+        let mut target_leg_len = 0.0;
+
+        for (upper, lower, foot) in [
+            (
+                HumanoidBone::LeftUpperLeg,
+                HumanoidBone::LeftLowerLeg,
+                HumanoidBone::LeftFoot,
+            ),
+            (
+                HumanoidBone::RightUpperLeg,
+                HumanoidBone::RightLowerLeg,
+                HumanoidBone::RightFoot,
+            ),
+        ] {
+            let (upper, lower, foot) = match (pair(upper), pair(lower), pair(foot)) {
+                (Some(upper), Some(lower), Some(foot)) => (upper, lower, foot),
+                _ => continue,
+            };
+            let leg_len = |rest: &[BonePose], upper: usize, lower: usize, foot: usize| {
+                Vec3::distance(rest[upper].position, rest[lower].position)
+                    + Vec3::distance(rest[lower].position, rest[foot].position)
+            };
+
+            source_leg_len += leg_len(&source_rest, upper.0, lower.0, foot.0);
+            target_leg_len += leg_len(&target_rest, upper.1, lower.1, foot.1);
+
+            // The solver bends the chain only if the bones are directly parented.
+            if target.bones()[lower.1].parent == Some(upper.1)
+                && target.bones()[foot.1].parent == Some(lower.1)
+            {
+                legs.push(RetargetLeg {
+                    source_foot: foot.0,
+                    upper: upper.1,
+                    lower: lower.1,
+                    foot: foot.1,
+                });
+            }
+        }
+
+        let leg_ratio = if f32::EPSILON < source_leg_len && f32::EPSILON < target_leg_len {
+            target_leg_len / source_leg_len
+        } else {
+            // No legs to measure; the height of the hips is the next best guess.
+            match hips {
+                Some((source_hips, target_hips))
+                    if f32::EPSILON < source_rest[source_hips].position.y
+                        && f32::EPSILON < target_rest[target_hips].position.y =>
+                {
+                    target_rest[target_hips].position.y / source_rest[source_hips].position.y
+                }
+                _ => 1.0,
+            }
+        };
+
+        Self {
+            source: source.clone(),
+            target: target.clone(),
+            source_bones,
+            source_rest,
+            target_rest,
+            hips,
+            legs,
+            leg_ratio,
+            missing_bones,
+            foot_locking: false,
+        }
+    }
+
+    /// Bends the legs of the target so that its feet land where the source feet do, scaled by the leg ratio.
+    /// Keeps planted feet from sliding when the target's legs are proportioned differently.
+    pub fn with_foot_locking(mut self, foot_locking: bool) -> Self {
+        self.foot_locking = foot_locking;
+        self
+    }
+
+    pub fn source(&self) -> &Skeleton {
+        &self.source
+    }
+
+    pub fn target(&self) -> &Skeleton {
+        &self.target
+    }
+
+    /// Length of the target's legs over the source's, which scales the hips translation.
+    pub fn leg_ratio(&self) -> f32 {
+        self.leg_ratio
+    }
+
+    /// Canonical bones not transferred, as either rig lacks them.
+    pub fn missing_bones(&self) -> &[HumanoidBone] {
+        &self.missing_bones
+    }
+
+    /// Transfers `source_pose`, a local pose of the source skeleton, onto `target_pose`, a local pose of the target
+    /// skeleton. Unmapped target bones keep their pose, so pass the rest pose to start from scratch.
+    pub fn retarget(&self, source_pose: &[BonePose], target_pose: &mut [BonePose]) {
+        let source_model = self.source.model_poses(source_pose);
+        let mut target_model: Vec<BonePose> = Vec::with_capacity(target_pose.len());
+
+        for (index, bone) in self.target.bones().iter().enumerate() {
+            let parent = bone.parent.map(|parent| target_model[parent]);
+
+            if let Some(source_index) = self.source_bones[index] {
+                let rotation = source_model[source_index].rotation
+                    * self.source_rest[source_index].rotation.inverted()
+                    * self.target_rest[index].rotation;
+
+                target_pose[index] = match self.hips {
+                    Some((source_hips, target_hips)) if target_hips == index => {
+                        let displacement = source_model[source_hips].position
+                            - self.source_rest[source_hips].position;
+                        let model = BonePose::new(
+                            self.target_rest[index].position + displacement * self.leg_ratio,
+                            rotation,
+                        );
+
+                        match &parent {
+                            Some(parent) => parent.relative(&model),
+                            None => model,
+                        }
+                    }
+                    _ => BonePose::new(
+                        bone.rest.position,
+                        match &parent {
+                            Some(parent) => (parent.rotation.inverted() * rotation).normalized(),
+                            None => rotation.normalized(),
+                        },
+                    ),
+                };
+            }
+
+            target_model.push(match &parent {
+                Some(parent) => parent.then(&target_pose[index]),
+                None => target_pose[index],
+            });
+        }
+
+        if self.foot_locking {
+            for leg in &self.legs {
+                self.lock_foot(leg, &source_model, &target_model, target_pose);
+            }
+        }
+    }
+
+    /// Samples `animation`, authored against the source skeleton, and transfers the pose onto `target_pose`.
+    pub fn sample(&self, animation: &SkeletalAnimation, time: f32, target_pose: &mut [BonePose]) {
+        let mut source_pose = self.source.rest_pose();
+        animation.sample(&self.source, time, &mut source_pose);
+        self.retarget(&source_pose, target_pose);
+    }
+
+    fn lock_foot(
+        &self,
+        leg: &RetargetLeg,
+        source_model: &[BonePose],
+        target_model: &[BonePose],
+        target_pose: &mut [BonePose],
+    ) {
+        let desired = self.target_rest[leg.foot].position
+            + (source_model[leg.source_foot].position - self.source_rest[leg.source_foot].position)
+                * self.leg_ratio;
+        let upper = target_model[leg.upper];
+        let lower = target_model[leg.lower];
+        let foot = target_model[leg.foot];
+
+        // Bending towards the knee keeps the knee on the side the transferred rotations put it.
+        let solution = solve_two_bone_ik(
+            upper.position,
+            lower.position,
+            foot.position,
+            desired,
+            lower.position,
+        );
+        let upper_rotation = (solution.root * upper.rotation).normalized();
+        let lower_rotation = (solution.mid * solution.root * lower.rotation).normalized();
+        let parent_rotation = self.target.bones()[leg.upper]
+            .parent
+            .map(|parent| target_model[parent].rotation);
+
+        target_pose[leg.upper].rotation = match parent_rotation {
+            Some(parent_rotation) => (parent_rotation.inverted() * upper_rotation).normalized(),
+            None => upper_rotation,
+        };
+        target_pose[leg.lower].rotation = (upper_rotation.inverted() * lower_rotation).normalized();
+        // The foot keeps its orientation in the model.
+        target_pose[leg.foot].rotation = (lower_rotation.inverted() * foot.rotation).normalized();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        animation::{
+            AnimationCurve, Bone, BoneTrack, CurveInterpolation, Keyframe, RotationKeyframe,
+            RotationTrack,
+        },
+        math::Quat,
+    };
+    use std::f32::consts::PI;
+
+    const SOURCE_NAMES: [&str; 19] = [
+        "mixamorig:Hips",
+        "mixamorig:Spine",
+        "mixamorig:Spine1",
+        "mixamorig:Neck",
+        "mixamorig:Head",
+        "mixamorig:LeftUpLeg",
+        "mixamorig:LeftLeg",
+        "mixamorig:LeftFoot",
+        "mixamorig:RightUpLeg",
+        "mixamorig:RightLeg",
+        "mixamorig:RightFoot",
+        "mixamorig:LeftShoulder",
+        "mixamorig:LeftArm",
+        "mixamorig:LeftForeArm",
+        "mixamorig:LeftHand",
+        "mixamorig:RightShoulder",
+        "mixamorig:RightArm",
+        "mixamorig:RightForeArm",
+        "mixamorig:RightHand",
+    ];
+
+    const TARGET_NAMES: [&str; 19] = [
+        "pelvis",
+        "spine_01",
+        "spine_02",
+        "neck_01",
+        "head",
+        "thigh_l",
+        "calf_l",
+        "foot_l",
+        "thigh_r",
+        "calf_r",
+        "foot_r",
+        "clavicle_l",
+        "upperarm_l",
+        "lowerarm_l",
+        "hand_l",
+        "clavicle_r",
+        "upperarm_r",
+        "lowerarm_r",
+        "hand_r",
+    ];
+
+    struct Proportions {
+        upper_leg: f32,
+        lower_leg: f32,
+        torso: f32,
+        arm: f32,
+    }
+
+    const SOURCE_PROPORTIONS: Proportions = Proportions {
+        upper_leg: 0.45,
+        lower_leg: 0.45,
+        torso: 1.0,
+        arm: 1.0,
+    };
+
+    // 20% longer legs, with the thighs taking most of it, a 20% longer torso and 20% shorter arms.
+    const TARGET_PROPORTIONS: Proportions = Proportions {
+        upper_leg: 0.45 * 1.3,
+        lower_leg: 0.45 * 1.1,
+        torso: 1.2,
+        arm: 0.8,
+    };
+
+    /// A T-posed humanoid, bones in the order of [`HumanoidBone::ALL`], left along +X and facing +Z.
+    fn humanoid(names: &[&str; 19], proportions: &Proportions) -> Skeleton {
+        let Proportions {
+            upper_leg,
+            lower_leg,
+            torso,
+            arm,
+        } = *proportions;
+        let bones = [
+            (None, Vec3::new(0.0, upper_leg + lower_leg, 0.0)),
+            (Some(0), Vec3::new(0.0, 0.1 * torso, 0.0)),
+            (Some(1), Vec3::new(0.0, 0.15 * torso, 0.0)),
+            (Some(2), Vec3::new(0.0, 0.2 * torso, 0.0)),
+            (Some(3), Vec3::new(0.0, 0.1 * torso, 0.0)),
+            (Some(0), Vec3::new(0.1, 0.0, 0.0)),
+            (Some(5), Vec3::new(0.0, -upper_leg, 0.0)),
+            (Some(6), Vec3::new(0.0, -lower_leg, 0.0)),
+            (Some(0), Vec3::new(-0.1, 0.0, 0.0)),
+            (Some(8), Vec3::new(0.0, -upper_leg, 0.0)),
+            (Some(9), Vec3::new(0.0, -lower_leg, 0.0)),
+            (Some(2), Vec3::new(0.05, 0.15 * torso, 0.0)),
+            (Some(11), Vec3::new(0.1 * arm, 0.0, 0.0)),
+            (Some(12), Vec3::new(0.25 * arm, 0.0, 0.0)),
+            (Some(13), Vec3::new(0.25 * arm, 0.0, 0.0)),
+            (Some(2), Vec3::new(-0.05, 0.15 * torso, 0.0)),
+            (Some(15), Vec3::new(-0.1 * arm, 0.0, 0.0)),
+            (Some(16), Vec3::new(-0.25 * arm, 0.0, 0.0)),
+            (Some(17), Vec3::new(-0.25 * arm, 0.0, 0.0)),
+        ];
+
+        Skeleton::new(Vec::from_iter(bones.into_iter().zip(names).map(
+            |((parent, position), name)| Bone {
+                name: name.to_string(),
+                parent,
+                rest: BonePose::new(position, Quat::IDENTITY),
+            },
+        )))
+        .unwrap()
+    }
+
+    const FRAME_RATE: f32 = 30.0;
+    const FRAME_COUNT: usize = 60;
+    const WALK_SPEED: f32 = 0.4;
+
+    fn hips_height(time: f32) -> f32 {
+        0.84 + 0.02 * (4.0 * PI * time).cos()
+    }
+
+    /// Where a foot is at `time`, for a foot planted from `plant_time` for half a second of every second and swinging
+    /// over to its next step in the other half.
+    fn foot_position(x: f32, plant_time: f32, plant_z: f32, time: f32) -> Vec3 {
+        let cycle = (time - plant_time).div_euclid(1.0);
+        let phase = (time - plant_time).rem_euclid(1.0);
+        let step = WALK_SPEED;
+        let z = plant_z + step * cycle;
+
+        if phase <= 0.5 {
+            Vec3::new(x, 0.0, z)
+        } else {
+            let swing = (phase - 0.5) / 0.5;
+            Vec3::new(x, 0.1 * (PI * swing).sin(), z + step * swing)
+        }
+    }
+
+    fn is_planted(plant_time: f32, time: f32) -> bool {
+        // Away from the ends of the stance, where the foot lifts off and lands.
+        let phase = (time - plant_time).rem_euclid(1.0);
+        0.05 <= phase && phase <= 0.45
+    }
+
+    /// A walk for the source skeleton, its legs solved so that the stance feet stay exactly planted.
+    fn walk(skeleton: &Skeleton) -> SkeletalAnimation {
+        let rest = skeleton.model_poses(&skeleton.rest_pose());
+        let mut hips = [Vec::new(), Vec::new(), Vec::new()];
+        let mut legs = [Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+
+        for frame in 0..=FRAME_COUNT {
+            let time = frame as f32 / FRAME_RATE;
+            let hips_position = Vec3::new(0.0, hips_height(time), WALK_SPEED * time);
+            hips[0].push(Keyframe::new(time, hips_position.x));
+            hips[1].push(Keyframe::new(time, hips_position.y));
+            hips[2].push(Keyframe::new(time, hips_position.z));
+
+            let feet = [
+                (5, foot_position(0.1, 0.0, 0.1, time)),
+                (8, foot_position(-0.1, 0.5, 0.3, time)),
+            ];
+
+            for (leg, (upper, target)) in feet.into_iter().enumerate() {
+                let offset = hips_position - rest[0].position;
+                let root = rest[upper].position + offset;
+                let mid = rest[upper + 1].position + offset;
+                let end = rest[upper + 2].position + offset;
+                let solution =
+                    solve_two_bone_ik(root, mid, end, target, mid + Vec3::new(0.0, 0.0, 1.0));
+
+                legs[leg * 2].push(RotationKeyframe {
+                    time,
+                    rotation: solution.root,
+                });
+                legs[leg * 2 + 1].push(RotationKeyframe {
+                    time,
+                    rotation: solution.root.inverted() * solution.mid * solution.root,
+                });
+            }
+        }
+
+        let [hips_x, hips_y, hips_z] = hips;
+        let mut tracks = vec![BoneTrack {
+            bone: SOURCE_NAMES[0].to_owned(),
+            rotation: None,
+            position: Some([
+                AnimationCurve::new(CurveInterpolation::Linear, hips_x),
+                AnimationCurve::new(CurveInterpolation::Linear, hips_y),
+                AnimationCurve::new(CurveInterpolation::Linear, hips_z),
+            ]),
+            scale: None,
+        }];
+
+        for (keyframes, bone) in legs.into_iter().zip([5, 6, 8, 9]) {
+            tracks.push(BoneTrack {
+                bone: SOURCE_NAMES[bone].to_owned(),
+                rotation: Some(RotationTrack::new(keyframes)),
+                position: None,
+                scale: None,
+            });
+        }
+
+        // Arms hanging down.
+        for (bone, direction) in [(12, Vec3::RIGHT), (16, -1.0 * Vec3::RIGHT)] {
+            tracks.push(BoneTrack {
+                bone: SOURCE_NAMES[bone].to_owned(),
+                rotation: Some(RotationTrack::new(vec![RotationKeyframe {
+                    time: 0.0,
+                    rotation: Quat::from_rotation_arc(direction, -1.0 * Vec3::UP),
+                }])),
+                position: None,
+                scale: None,
+            });
+        }
+
+        SkeletalAnimation {
+            tracks,
+            events: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn check_guessed_rigs_map_every_bone() {
+        for names in [&SOURCE_NAMES, &TARGET_NAMES] {
+            let skeleton = humanoid(names, &SOURCE_PROPORTIONS);
+            let rig = HumanoidRig::guess(&skeleton);
+
+            for (bone, name) in HumanoidBone::ALL.into_iter().zip(names) {
+                assert_eq!(rig.bone_name(bone), Some(*name), "{:?}", bone);
+            }
+        }
+    }
+
+    #[test]
+    fn check_retargeted_walk_keeps_feet_planted() {
+        let source = humanoid(&SOURCE_NAMES, &SOURCE_PROPORTIONS);
+        let target = humanoid(&TARGET_NAMES, &TARGET_PROPORTIONS);
+        let retargeter = Retargeter::new(
+            &source,
+            &HumanoidRig::guess(&source),
+            &target,
+            &HumanoidRig::guess(&target),
+        )
+        .with_foot_locking(true);
+        let animation = walk(&source);
+
+        assert!(retargeter.missing_bones().is_empty());
+        assert!((retargeter.leg_ratio() - 1.2).abs() <= 1e-4);
+
+        let mut planted_feet: [Option<Vec3>; 2] = [None, None];
+
+        for frame in 0..=FRAME_COUNT {
+            let time = frame as f32 / FRAME_RATE;
+            let mut pose = target.rest_pose();
+            retargeter.sample(&animation, time, &mut pose);
+            let model = target.model_poses(&pose);
+
+            for (leg, (foot, plant_time)) in [(7, 0.0), (10, 0.5)].into_iter().enumerate() {
+                if !is_planted(plant_time, time) {
+                    planted_feet[leg] = None;
+                    continue;
+                }
+
+                let position = model[foot].position;
+                let planted = *planted_feet[leg].get_or_insert(position);
+                assert!(
+                    Vec3::distance(position, planted) <= 0.005,
+                    "foot {} slid to {} from {} at {}",
+                    foot,
+                    position,
+                    planted,
+                    time
+                );
+                assert!(position.y.abs() <= 0.005);
+            }
+
+            // The hands hang below the shoulders, at the target's own arm length.
+            let hips_y = model[0].position.y;
+            let expected_hand_y = hips_y + (0.1 + 0.15 + 0.15) * TARGET_PROPORTIONS.torso
+                - (0.25 + 0.25) * TARGET_PROPORTIONS.arm;
+            let expected_hips_y = TARGET_PROPORTIONS.upper_leg
+                + TARGET_PROPORTIONS.lower_leg
+                + (hips_height(time) - 0.9) * 1.2;
+
+            assert!((hips_y - expected_hips_y).abs() <= 1e-3);
+
+            for hand in [14, 18] {
+                assert!(
+                    (model[hand].position.y - expected_hand_y).abs() <= 1e-3,
+                    "hand {} at {} instead of {}",
+                    hand,
+                    model[hand].position.y,
+                    expected_hand_y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn check_retarget_skips_missing_bones() {
+        let source = humanoid(&SOURCE_NAMES, &SOURCE_PROPORTIONS);
+        let target = humanoid(&TARGET_NAMES, &TARGET_PROPORTIONS);
+        let mut target_rig = HumanoidRig::guess(&target);
+        target_rig.remove_bone(HumanoidBone::LeftUpperArm);
+
+        let retargeter =
+            Retargeter::new(&source, &HumanoidRig::guess(&source), &target, &target_rig);
+        assert_eq!(retargeter.missing_bones(), &[HumanoidBone::LeftUpperArm]);
+
+        let mut pose = target.rest_pose();
+        retargeter.sample(&walk(&source), 0.5, &mut pose);
+
+        // The left arm stays in its rest pose, while the right one is animated.
+        assert_eq!(pose[12], target.bones()[12].rest);
+        assert_ne!(pose[16], target.bones()[16].rest);
+    }
+}
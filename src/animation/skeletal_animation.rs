@@ -0,0 +1,224 @@
+use super::{
+    AnimationCurve, AnimationEventMarker, BonePose, CurveInterpolation, Keyframe, Skeleton,
+};
+use crate::math::{Quat, Vec3};
+use asset::assets::{AnimationVectorKey, ModelAnimation};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RotationKeyframe {
+    pub time: f32,
+    pub rotation: Quat,
+}
+
+/// Local rotations of a bone over time, interpolated spherically. Before the first keyframe and after the last one,
+/// the track holds their rotations.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RotationTrack {
+    /// Sorted by time.
+    pub keyframes: Vec<RotationKeyframe>,
+}
+
+impl RotationTrack {
+    pub fn new(mut keyframes: Vec<RotationKeyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { keyframes }
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    pub fn evaluate(&self, time: f32) -> Option<Quat> {
+        let (first, last) = match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return None,
+        };
+
+        if time <= first.time {
+            return Some(first.rotation);
+        }
+
+        if last.time <= time {
+            return Some(last.rotation);
+        }
+
+        let index = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+        let from = &self.keyframes[index - 1];
+        let to = &self.keyframes[index];
+        let span = to.time - from.time;
+
+        if span <= 0.0 {
+            return Some(to.rotation);
+        }
+
+        Some(Quat::slerp(
+            from.rotation,
+            to.rotation,
+            (time - from.time) / span,
+        ))
+    }
+}
+
+/// Animation of a single bone, by name. Channels that are `None` leave the bone at its pose.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoneTrack {
+    pub bone: String,
+    pub rotation: Option<RotationTrack>,
+    /// Local position, per axis.
+    pub position: Option<[AnimationCurve; 3]>,
+    /// Local scale, per axis. Bone poses have no scale, so only an [`AnimationPlayer`](super::AnimationPlayer)
+    /// moving objects applies it.
+    pub scale: Option<[AnimationCurve; 3]>,
+}
+
+/// A clip animating the bones of a skeleton. Tracks are bound to bones by name, so that a clip plays on any skeleton
+/// having the bones; tracks of missing bones are ignored. See [`Retargeter`](super::Retargeter) for skeletons
+/// of other proportions or naming.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SkeletalAnimation {
+    pub tracks: Vec<BoneTrack>,
+    /// Events of the clip, usually placed on bones, e.g. footsteps on the feet.
+    /// See [`crossed_events`](super::crossed_events).
+    pub events: Vec<AnimationEventMarker>,
+}
+
+impl SkeletalAnimation {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Converts a clip of a model, with a track per channel bound to the node of the same name. Positions and scales
+    /// are interpolated linearly, rotations spherically; channels without keys are left out.
+    pub fn from_model_animation(animation: &ModelAnimation) -> Self {
+        fn curves(keys: &[AnimationVectorKey]) -> Option<[AnimationCurve; 3]> {
+            if keys.is_empty() {
+                return None;
+            }
+
+            Some([0, 1, 2].map(|axis| {
+                AnimationCurve::new(
+                    CurveInterpolation::Linear,
+                    Vec::from_iter(
+                        keys.iter()
+                            .map(|key| Keyframe::new(key.time, key.value[axis])),
+                    ),
+                )
+            }))
+        }
+
+        let tracks = Vec::from_iter(animation.channels.iter().map(|channel| BoneTrack {
+            bone: channel.node_name.clone(),
+            rotation: if channel.rotations.is_empty() {
+                None
+            } else {
+                Some(RotationTrack::new(Vec::from_iter(
+                    channel.rotations.iter().map(|key| {
+                        let [x, y, z, w] = key.value;
+                        RotationKeyframe {
+                            time: key.time,
+                            rotation: Quat { x, y, z, w }.normalized(),
+                        }
+                    }),
+                )))
+            },
+            position: curves(&channel.translations),
+            scale: curves(&channel.scales),
+        }));
+
+        Self {
+            tracks,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn with_event(mut self, event: AnimationEventMarker) -> Self {
+        self.events.push(event);
+        self
+    }
+
+    /// The time of the last keyframe among the tracks.
+    pub fn duration(&self) -> f32 {
+        self.tracks
+            .iter()
+            .flat_map(|track| {
+                let position = track
+                    .position
+                    .iter()
+                    .chain(&track.scale)
+                    .flatten()
+                    .map(AnimationCurve::duration);
+                track
+                    .rotation
+                    .iter()
+                    .map(RotationTrack::duration)
+                    .chain(position)
+            })
+            .fold(0.0, f32::max)
+    }
+
+    /// Writes the animated channels at `time` into `pose`, a local pose of `skeleton`.
+    pub fn sample(&self, skeleton: &Skeleton, time: f32, pose: &mut [BonePose]) {
+        for track in &self.tracks {
+            let index = match skeleton.bone_index(&track.bone) {
+                Some(index) => index,
+                None => continue,
+            };
+
+            if let Some(rotation) = track
+                .rotation
+                .as_ref()
+                .and_then(|rotation| rotation.evaluate(time))
+            {
+                pose[index].rotation = rotation;
+            }
+
+            if let Some([x, y, z]) = &track.position {
+                pose[index].position =
+                    Vec3::new(x.evaluate(time), y.evaluate(time), z.evaluate(time));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asset::assets::{AnimationRotationKey, ModelAnimationChannel};
+
+    #[test]
+    fn check_model_animation_conversion() {
+        let key = |time, value| AnimationVectorKey { time, value };
+        let animation = ModelAnimation {
+            name: "wave".to_owned(),
+            duration: 1.0,
+            channels: vec![ModelAnimationChannel {
+                node_name: "arm".to_owned(),
+                translations: vec![key(0.0, [0.0, 0.0, 0.0]), key(1.0, [2.0, 4.0, 0.0])],
+                rotations: vec![AnimationRotationKey {
+                    time: 0.5,
+                    value: [0.0, 0.0, 0.0, 2.0],
+                }],
+                scales: Vec::new(),
+            }],
+        };
+
+        let clip = SkeletalAnimation::from_model_animation(&animation);
+        let track = &clip.tracks[0];
+        assert_eq!(track.bone, "arm");
+        assert_eq!(clip.duration(), 1.0);
+        assert!(track.scale.is_none());
+
+        let [x, y, z] = track.position.as_ref().unwrap();
+        assert_eq!(
+            [x.evaluate(0.5), y.evaluate(0.5), z.evaluate(0.5)],
+            [1.0, 2.0, 0.0]
+        );
+
+        // A single key holds its rotation over the whole clip.
+        let rotation = track.rotation.as_ref().unwrap();
+        assert_eq!(rotation.evaluate(0.0), Some(Quat::IDENTITY));
+        assert_eq!(rotation.evaluate(1.0), Some(Quat::IDENTITY));
+    }
+}
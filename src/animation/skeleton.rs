@@ -0,0 +1,130 @@
+use crate::math::{Mat4, Quat, Vec3};
+use asset::assets::ModelSkeleton;
+use thiserror::Error;
+
+/// Rotation and translation of a bone relative to its parent, or to the model for root bones.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BonePose {
+    pub position: Vec3,
+    pub rotation: Quat,
+}
+
+impl BonePose {
+    pub fn new(position: Vec3, rotation: Quat) -> Self {
+        Self { position, rotation }
+    }
+
+    /// Places a pose given relative to this one.
+    pub fn then(&self, child: &BonePose) -> BonePose {
+        BonePose {
+            position: self.position + self.rotation * child.position,
+            rotation: (self.rotation * child.rotation).normalized(),
+        }
+    }
+
+    /// The pose that places `this` when given relative to this one; the inverse of [`then`](Self::then).
+    pub fn relative(&self, this: &BonePose) -> BonePose {
+        let inverse = self.rotation.inverted();
+        BonePose {
+            position: inverse * (this.position - self.position),
+            rotation: (inverse * this.rotation).normalized(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bone {
+    pub name: String,
+    /// Index of the parent bone, which always comes before its children.
+    pub parent: Option<usize>,
+    /// Local pose of the bone when nothing animates it.
+    pub rest: BonePose,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SkeletonError {
+    #[error("bone `{name}` must come after its parent, bone {parent}")]
+    ParentAfterChild { name: String, parent: usize },
+    #[error("bone `{0}` is defined more than once")]
+    DuplicateName(String),
+}
+
+/// Bones of a model, parents first. Poses of a skeleton are slices of local poses, one per bone in the same order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skeleton {
+    bones: Vec<Bone>,
+}
+
+impl Skeleton {
+    pub fn new(bones: Vec<Bone>) -> Result<Self, SkeletonError> {
+        for (index, bone) in bones.iter().enumerate() {
+            if let Some(parent) = bone.parent {
+                if index <= parent {
+                    return Err(SkeletonError::ParentAfterChild {
+                        name: bone.name.clone(),
+                        parent,
+                    });
+                }
+            }
+
+            if bones[..index].iter().any(|other| other.name == bone.name) {
+                return Err(SkeletonError::DuplicateName(bone.name.clone()));
+            }
+        }
+
+        Ok(Self { bones })
+    }
+
+    /// Creates the skeleton of the bones of a model, in the same order. The rest poses are the bind poses.
+    pub fn from_model_skeleton(skeleton: &ModelSkeleton) -> Result<Self, SkeletonError> {
+        let bind_matrices = Vec::from_iter(
+            skeleton
+                .bones
+                .iter()
+                .map(|bone| Mat4::new(bone.inverse_bind_matrix).inversed()),
+        );
+
+        Self::new(Vec::from_iter(skeleton.bones.iter().enumerate().map(
+            |(index, bone)| {
+                let parent = bone.parent_index.map(|parent| parent as usize);
+                let local_matrix = match parent.and_then(|parent| bind_matrices.get(parent)) {
+                    Some(parent_matrix) => bind_matrices[index].clone() * &parent_matrix.inversed(),
+                    None => bind_matrices[index].clone(),
+                };
+                let (position, rotation, _) = local_matrix.split();
+
+                Bone {
+                    name: bone.name.clone(),
+                    parent,
+                    rest: BonePose::new(position, rotation),
+                }
+            },
+        )))
+    }
+
+    pub fn bones(&self) -> &[Bone] {
+        &self.bones
+    }
+
+    pub fn bone_index(&self, name: &str) -> Option<usize> {
+        self.bones.iter().position(|bone| bone.name == name)
+    }
+
+    pub fn rest_pose(&self) -> Vec<BonePose> {
+        Vec::from_iter(self.bones.iter().map(|bone| bone.rest))
+    }
+
+    /// Converts local poses into poses relative to the model.
+    pub fn model_poses(&self, local_poses: &[BonePose]) -> Vec<BonePose> {
+        let mut model_poses: Vec<BonePose> = Vec::with_capacity(self.bones.len());
+
+        for (bone, local_pose) in self.bones.iter().zip(local_poses) {
+            model_poses.push(match bone.parent {
+                Some(parent) => model_poses[parent].then(local_pose),
+                None => *local_pose,
+            });
+        }
+
+        model_poses
+    }
+}
@@ -1,12 +1,15 @@
-use crate::ContextHandle;
+use crate::{gfx::texture_sampler_descriptor, ContextHandle};
 use asset::{
-    assets::{TextureAddressMode, TextureFilterMode, TextureFormat},
-    GfxBridge, GfxBuffer, GfxSampler, GfxShaderModule, GfxTexture, GfxTextureView,
+    assets::{mip_level_size, TextureAddressMode, TextureFilterMode, TextureFormat},
+    decompress_blocks, GfxBridge, GfxBuffer, GfxSampler, GfxShaderModule, GfxTexture,
+    GfxTextureView,
 };
+use std::borrow::Cow;
 use wgpu::{
-    BufferAddress, BufferDescriptor, BufferUsages, Extent3d, FilterMode, ImageCopyTexture,
-    ImageDataLayout, Origin3d, SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, Texture,
-    TextureAspect, TextureDescriptor, TextureDimension, TextureUsages,
+    BufferAddress, BufferDescriptor, BufferUsages, Extent3d, Features, ImageCopyTexture,
+    ImageDataLayout, Origin3d, ShaderModuleDescriptor, ShaderSource, Texture, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureUsages, TextureViewDescriptor,
+    TextureViewDimension,
 };
 
 pub struct GfxBridgeImpl {
@@ -53,57 +56,105 @@ impl GfxBridge for GfxBridgeImpl {
         &self,
         width: u16,
         height: u16,
+        array_layers: u32,
         format: TextureFormat,
-        texels: &[u8],
+        levels: &[&[u8]],
     ) -> asset::GfxTexture {
-        let format = match format {
-            TextureFormat::RGBA8 => wgpu::TextureFormat::Rgba8Unorm,
-        };
-        let texture = self
-            .context
-            .gfx_ctx
-            .device
-            .create_texture(&TextureDescriptor {
-                label: None,
-                size: wgpu::Extent3d {
-                    width: width as u32,
-                    height: height as u32,
-                    depth_or_array_layers: 1,
-                },
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: TextureDimension::D2,
+        let device = &self.context.gfx_ctx.device;
+
+        // Without the feature, block-compressed texels are decompressed on the CPU and uploaded as RGBA8. This
+        // takes 4 to 8 times the memory, but keeps the textures working on devices such as mobile GPUs.
+        let (format, levels) = match format.block_compression() {
+            Some(compression) if !device.features().contains(Features::TEXTURE_COMPRESSION_BC) => {
+                let levels = Vec::from_iter(levels.iter().enumerate().map(|(level, texels)| {
+                    let (width, height) = mip_level_size(width as u32, height as u32, level as u32);
+                    let layer_size = format.texel_data_size(width, height);
+                    let texels =
+                        Vec::from_iter(texels.chunks_exact(layer_size).flat_map(|layer| {
+                            decompress_blocks(compression, width, height, layer)
+                                .expect("texture sources are validated before they are uploaded")
+                        }));
+                    Cow::Owned(texels)
+                }));
+                let format = if format.is_srgb() {
+                    TextureFormat::RGBA8Srgb
+                } else {
+                    TextureFormat::RGBA8
+                };
+                (format, levels)
+            }
+            _ => (
                 format,
-                usage: TextureUsages::COPY_DST
-                    | TextureUsages::TEXTURE_BINDING
-                    | TextureUsages::RENDER_ATTACHMENT,
-                view_formats: &[format],
-            });
-        self.context.gfx_ctx.queue.write_texture(
-            ImageCopyTexture {
-                texture: &texture,
-                mip_level: 0,
-                origin: Origin3d::ZERO,
-                aspect: TextureAspect::All,
-            },
-            texels,
-            ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * width as u32),
-                rows_per_image: Some(height as u32),
-            },
-            Extent3d {
+                Vec::from_iter(levels.iter().map(|texels| Cow::Borrowed(*texels))),
+            ),
+        };
+        let wgpu_format = convert_texture_format(format);
+        // Compressed textures cannot be rendered into.
+        let usage = if format.is_compressed() {
+            TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING
+        } else {
+            TextureUsages::COPY_DST
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::RENDER_ATTACHMENT
+        };
+        let texture = device.create_texture(&TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
                 width: width as u32,
                 height: height as u32,
-                depth_or_array_layers: 1,
+                depth_or_array_layers: array_layers,
             },
-        );
+            mip_level_count: levels.len() as u32,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: wgpu_format,
+            usage,
+            view_formats: &[wgpu_format],
+        });
+        let (block_width, block_height) = format.block_dimensions();
+
+        for (level, texels) in levels.iter().enumerate() {
+            let (width, height) = mip_level_size(width as u32, height as u32, level as u32);
+            self.context.gfx_ctx.queue.write_texture(
+                ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: level as u32,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                texels,
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(format.bytes_per_row(width)),
+                    rows_per_image: Some(format.rows(height)),
+                },
+                // Mip levels smaller than a block are copied as whole blocks.
+                Extent3d {
+                    width: width.next_multiple_of(block_width),
+                    height: height.next_multiple_of(block_height),
+                    depth_or_array_layers: array_layers,
+                },
+            );
+        }
 
         GfxTexture::new(texture)
     }
 
-    fn create_texture_view(&self, texture: &Texture) -> GfxTextureView {
-        GfxTextureView::new(texture.create_view(&Default::default()))
+    fn create_texture_view(&self, texture: &Texture, is_cube_map: bool) -> GfxTextureView {
+        let dimension = if is_cube_map {
+            if texture.depth_or_array_layers() == 6 {
+                Some(TextureViewDimension::Cube)
+            } else {
+                Some(TextureViewDimension::CubeArray)
+            }
+        } else {
+            None
+        };
+
+        GfxTextureView::new(texture.create_view(&TextureViewDescriptor {
+            dimension,
+            ..Default::default()
+        }))
     }
 
     fn create_sampler(
@@ -111,40 +162,26 @@ impl GfxBridge for GfxBridgeImpl {
         filter_mode: TextureFilterMode,
         address_mode: (TextureAddressMode, TextureAddressMode),
     ) -> GfxSampler {
-        let (texel_filter_mode, mipmap_filter_mode) = match filter_mode {
-            TextureFilterMode::Point => (FilterMode::Nearest, FilterMode::Nearest),
-            TextureFilterMode::Bilinear => (FilterMode::Linear, FilterMode::Nearest),
-            TextureFilterMode::Trilinear => (FilterMode::Linear, FilterMode::Linear),
-        };
-        let (address_mode_u, address_mode_v) = address_mode;
-        let address_mode_u = convert_address_mode(address_mode_u);
-        let address_mode_v = convert_address_mode(address_mode_v);
         let sampler = self
             .context
             .gfx_ctx
             .device
-            .create_sampler(&SamplerDescriptor {
-                label: None,
-                address_mode_u,
-                address_mode_v,
-                address_mode_w: wgpu::AddressMode::Repeat,
-                mag_filter: texel_filter_mode,
-                min_filter: texel_filter_mode,
-                mipmap_filter: mipmap_filter_mode,
-                lod_min_clamp: 0.0,
-                lod_max_clamp: 32.0,
-                compare: None,
-                anisotropy_clamp: 1,
-                border_color: None,
-            });
+            .create_sampler(&texture_sampler_descriptor(filter_mode, address_mode));
 
         GfxSampler::new(sampler)
     }
 }
 
-fn convert_address_mode(mode: TextureAddressMode) -> wgpu::AddressMode {
-    match mode {
-        TextureAddressMode::Repeat => wgpu::AddressMode::Repeat,
-        TextureAddressMode::Clamp => wgpu::AddressMode::ClampToEdge,
+fn convert_texture_format(format: TextureFormat) -> wgpu::TextureFormat {
+    match format {
+        TextureFormat::RGBA8 => wgpu::TextureFormat::Rgba8Unorm,
+        TextureFormat::RGBA8Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+        TextureFormat::BC1 => wgpu::TextureFormat::Bc1RgbaUnorm,
+        TextureFormat::BC1Srgb => wgpu::TextureFormat::Bc1RgbaUnormSrgb,
+        TextureFormat::BC3 => wgpu::TextureFormat::Bc3RgbaUnorm,
+        TextureFormat::BC3Srgb => wgpu::TextureFormat::Bc3RgbaUnormSrgb,
+        TextureFormat::BC5 => wgpu::TextureFormat::Bc5RgUnorm,
+        TextureFormat::BC7 => wgpu::TextureFormat::Bc7RgbaUnorm,
+        TextureFormat::BC7Srgb => wgpu::TextureFormat::Bc7RgbaUnormSrgb,
     }
 }
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Audio settings chosen by the user, kept across runs. The engine loads them from `audio.ron` next to the
+/// executable on start, and saves them there on exit if they changed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct AudioSettings {
+    /// Linear volume of the mixer groups, by name. Groups the mixer does not have are ignored.
+    pub group_volumes: BTreeMap<String, f32>,
+}
+
+impl AudioSettings {
+    /// `audio.ron` placed next to the executable.
+    pub fn path_next_to_executable() -> Option<PathBuf> {
+        let exe = std::env::current_exe().ok()?;
+        Some(exe.parent()?.join("audio.ron"))
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, AudioSettingsError> {
+        ron::from_str(source).map_err(|err| AudioSettingsError::ParseError(err.to_string()))
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, Default::default()).unwrap()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AudioSettingsError> {
+        Self::from_ron(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), AudioSettingsError> {
+        Ok(std::fs::write(path, self.to_ron())?)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AudioSettingsError {
+    #[error("failed to access audio settings: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("failed to parse audio settings: {0}")]
+    ParseError(String),
+}
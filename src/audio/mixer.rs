@@ -0,0 +1,886 @@
+use crossbeam_queue::ArrayQueue;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MixerGroupId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SoundHandle(u64);
+
+/// User-facing parameters of a mixer group.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MixerGroupParams {
+    /// Linear gain; `1.0` is unity.
+    pub volume: f32,
+    pub pitch: f32,
+    /// Cutoff frequency of the one-pole low-pass filter in Hz. `None` disables the filter.
+    pub low_pass: Option<f32>,
+}
+
+impl Default for MixerGroupParams {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            pitch: 1.0,
+            low_pass: None,
+        }
+    }
+}
+
+/// Per-sound parameters computed by the spatializer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoiceSpatial {
+    /// Linear distance attenuation.
+    pub gain: f32,
+    /// Doppler pitch multiplier.
+    pub pitch: f32,
+    /// Attenuation in dB applied while occluded; `0.0` if not occluded.
+    pub occlusion_db: f32,
+    /// Low-pass cutoff in Hz applied while occluded. `None` if not occluded.
+    pub occlusion_cutoff: Option<f32>,
+}
+
+impl Default for VoiceSpatial {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            pitch: 1.0,
+            occlusion_db: 0.0,
+            occlusion_cutoff: None,
+        }
+    }
+}
+
+/// Ducks `target` while any sound plays in `trigger` (or its descendants).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DuckingRule {
+    pub target: MixerGroupId,
+    pub trigger: MixerGroupId,
+    /// Attenuation applied at full duck, in dB; e.g. `-8.0`.
+    pub amount_db: f32,
+    pub attack_secs: f32,
+    pub release_secs: f32,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MixerError {
+    #[error("mixer group `{0}` does not exist")]
+    GroupNotFound(String),
+    #[error("mixer group `{0}` already exists")]
+    GroupAlreadyExists(String),
+    #[error("mixer snapshot `{0}` does not exist")]
+    SnapshotNotFound(String),
+    #[error("the mixer has no room for more than {0} groups")]
+    TooManyGroups(usize),
+    #[error("the mixer has no room for more than {0} ducking rules")]
+    TooManyDuckingRules(usize),
+}
+
+pub fn linear_to_db(linear: f32) -> f32 {
+    if linear <= 0.0 {
+        MIN_DB
+    } else {
+        (20.0 * linear.log10()).max(MIN_DB)
+    }
+}
+
+pub fn db_to_linear(db: f32) -> f32 {
+    if db <= MIN_DB {
+        0.0
+    } else {
+        10f32.powf(db / 20.0)
+    }
+}
+
+/// Anything quieter than this is treated as silence.
+const MIN_DB: f32 = -80.0;
+/// Cutoff used when the low-pass filter is disabled; high enough to be transparent.
+const LOW_PASS_OPEN: f32 = 22_000.0;
+/// Spatial gain and pitch are interpolated over this time, as they are only updated once per frame.
+const SPATIAL_RAMP_SECS: f32 = 0.02;
+/// Occlusion changes are smoothed over this time to avoid clicks.
+const OCCLUSION_RAMP_SECS: f32 = 0.1;
+/// Longest block the ducking envelopes are advanced by at once; longer buffers are rendered in blocks of this time.
+const DUCKING_BLOCK_SECS: f32 = 0.005;
+/// Commands the audio thread has not received yet that fit in the queue. More wait on the main thread.
+const COMMAND_CAPACITY: usize = 1024;
+/// Sounds playing at once. Sounds played beyond this are dropped.
+const MAX_VOICES: usize = 256;
+const MAX_GROUPS: usize = 64;
+const MAX_DUCKING_RULES: usize = 64;
+
+enum MixerCommand {
+    AddGroup {
+        parent: Option<MixerGroupId>,
+    },
+    SetGroupParams {
+        group: MixerGroupId,
+        params: MixerGroupParams,
+        duration_secs: f32,
+    },
+    AddDuckingRule(DuckingRule),
+    Play {
+        handle: SoundHandle,
+        samples: Arc<[f32]>,
+        group: MixerGroupId,
+        looping: bool,
+        spatial: VoiceSpatial,
+    },
+    Stop(SoundHandle),
+    SetVoiceSpatial {
+        handle: SoundHandle,
+        spatial: VoiceSpatial,
+    },
+}
+
+/// Main-thread side of the mixer. Every change is sent to the [`MixerRenderer`] through a preallocated lock-free
+/// queue, so the audio thread never waits on a lock nor allocates. The samples of the finished sounds come back
+/// the same way, so that they are freed on the main thread by [`update`](Self::update).
+pub struct Mixer {
+    groups: Vec<(String, Option<MixerGroupId>, MixerGroupParams)>,
+    group_names: HashMap<String, MixerGroupId>,
+    snapshots: HashMap<String, Vec<(MixerGroupId, MixerGroupParams)>>,
+    ducking_rule_count: usize,
+    next_sound: u64,
+    commands: Arc<ArrayQueue<MixerCommand>>,
+    /// Commands that did not fit in the queue, sent first on the next change or update.
+    pending: VecDeque<MixerCommand>,
+    retired: Arc<ArrayQueue<Arc<[f32]>>>,
+}
+
+impl Mixer {
+    pub const MASTER: MixerGroupId = MixerGroupId(0);
+
+    /// Creates a mixer with a `master` group, and the renderer that runs on the audio thread.
+    pub fn new(sample_rate: u32) -> (Self, MixerRenderer) {
+        let commands = Arc::new(ArrayQueue::new(COMMAND_CAPACITY));
+        let retired = Arc::new(ArrayQueue::new(MAX_VOICES));
+        let mut mixer = Self {
+            groups: Vec::new(),
+            group_names: HashMap::new(),
+            snapshots: HashMap::new(),
+            ducking_rule_count: 0,
+            next_sound: 0,
+            commands: commands.clone(),
+            pending: VecDeque::new(),
+            retired: retired.clone(),
+        };
+        let renderer = MixerRenderer::new(sample_rate, commands, retired);
+
+        mixer.push_group("master", None);
+
+        (mixer, renderer)
+    }
+
+    /// Creates a mixer with the standard `music`, `sfx` and `voice` groups under `master`.
+    pub fn with_default_groups(sample_rate: u32) -> (Self, MixerRenderer) {
+        let (mut mixer, renderer) = Self::new(sample_rate);
+
+        for name in ["music", "sfx", "voice"] {
+            mixer.add_group(name, Self::MASTER).unwrap();
+        }
+
+        (mixer, renderer)
+    }
+
+    fn push_group(&mut self, name: &str, parent: Option<MixerGroupId>) -> MixerGroupId {
+        let id = MixerGroupId(self.groups.len());
+        self.groups
+            .push((name.to_owned(), parent, MixerGroupParams::default()));
+        self.group_names.insert(name.to_owned(), id);
+        self.send(MixerCommand::AddGroup { parent });
+        id
+    }
+
+    /// Sends a command to the audio thread, after the ones waiting for room in the queue.
+    fn send(&mut self, command: MixerCommand) {
+        self.flush();
+
+        if !self.pending.is_empty() {
+            self.pending.push_back(command);
+        } else if let Err(command) = self.commands.push(command) {
+            self.pending.push_back(command);
+        }
+    }
+
+    fn flush(&mut self) {
+        while let Some(command) = self.pending.pop_front() {
+            if let Err(command) = self.commands.push(command) {
+                self.pending.push_front(command);
+                break;
+            }
+        }
+    }
+
+    /// Sends the commands that did not fit in the queue, and frees the samples of the sounds that finished.
+    /// Called once per frame by the [`AudioManager`](super::AudioManager).
+    pub fn update(&mut self) {
+        self.flush();
+        while self.retired.pop().is_some() {}
+    }
+
+    pub fn add_group(
+        &mut self,
+        name: &str,
+        parent: MixerGroupId,
+    ) -> Result<MixerGroupId, MixerError> {
+        if self.group_names.contains_key(name) {
+            return Err(MixerError::GroupAlreadyExists(name.to_owned()));
+        }
+
+        if MAX_GROUPS <= self.groups.len() {
+            return Err(MixerError::TooManyGroups(MAX_GROUPS));
+        }
+
+        Ok(self.push_group(name, Some(parent)))
+    }
+
+    pub fn find_group(&self, name: &str) -> Result<MixerGroupId, MixerError> {
+        self.group_names
+            .get(name)
+            .copied()
+            .ok_or_else(|| MixerError::GroupNotFound(name.to_owned()))
+    }
+
+    pub fn group_params(&self, group: MixerGroupId) -> MixerGroupParams {
+        self.groups[group.0].2
+    }
+
+    pub fn set_group_params(
+        &mut self,
+        group: MixerGroupId,
+        params: MixerGroupParams,
+        duration_secs: f32,
+    ) {
+        self.groups[group.0].2 = params;
+        self.send(MixerCommand::SetGroupParams {
+            group,
+            params,
+            duration_secs,
+        });
+    }
+
+    /// Sets the linear volume of a group. It is applied as dB on the audio thread.
+    pub fn set_group_volume(&mut self, name: &str, volume: f32) -> Result<(), MixerError> {
+        let group = self.find_group(name)?;
+        let params = MixerGroupParams {
+            volume,
+            ..self.group_params(group)
+        };
+        self.set_group_params(group, params, 0.0);
+        Ok(())
+    }
+
+    /// Captures the current parameters of the given groups under a name.
+    pub fn save_snapshot(&mut self, name: &str, groups: &[MixerGroupId]) {
+        let values = groups
+            .iter()
+            .map(|&group| (group, self.group_params(group)))
+            .collect();
+        self.snapshots.insert(name.to_owned(), values);
+    }
+
+    pub fn add_snapshot(&mut self, name: &str, values: Vec<(MixerGroupId, MixerGroupParams)>) {
+        self.snapshots.insert(name.to_owned(), values);
+    }
+
+    /// Interpolates the groups of the snapshot to its values over `duration_secs`, on the audio thread.
+    pub fn transition_to_snapshot(
+        &mut self,
+        name: &str,
+        duration_secs: f32,
+    ) -> Result<(), MixerError> {
+        let values = self
+            .snapshots
+            .get(name)
+            .cloned()
+            .ok_or_else(|| MixerError::SnapshotNotFound(name.to_owned()))?;
+
+        for (group, params) in values {
+            self.set_group_params(group, params, duration_secs);
+        }
+
+        Ok(())
+    }
+
+    pub fn add_ducking_rule(&mut self, rule: DuckingRule) -> Result<(), MixerError> {
+        if MAX_DUCKING_RULES <= self.ducking_rule_count {
+            return Err(MixerError::TooManyDuckingRules(MAX_DUCKING_RULES));
+        }
+
+        self.ducking_rule_count += 1;
+        self.send(MixerCommand::AddDuckingRule(rule));
+        Ok(())
+    }
+
+    /// Plays mono samples in the given group.
+    pub fn play(&mut self, samples: Arc<[f32]>, group: MixerGroupId, looping: bool) -> SoundHandle {
+        self.play_spatial(samples, group, looping, VoiceSpatial::default())
+    }
+
+    /// Plays mono samples in the given group, starting with the given spatial parameters.
+    pub fn play_spatial(
+        &mut self,
+        samples: Arc<[f32]>,
+        group: MixerGroupId,
+        looping: bool,
+        spatial: VoiceSpatial,
+    ) -> SoundHandle {
+        let handle = SoundHandle(self.next_sound);
+        self.next_sound += 1;
+        self.send(MixerCommand::Play {
+            handle,
+            samples,
+            group,
+            looping,
+            spatial,
+        });
+        handle
+    }
+
+    pub fn stop(&mut self, handle: SoundHandle) {
+        self.send(MixerCommand::Stop(handle));
+    }
+
+    /// Sets the spatial parameters of a playing sound. Changes are smoothed on the audio thread.
+    pub fn set_voice_spatial(&mut self, handle: SoundHandle, spatial: VoiceSpatial) {
+        self.send(MixerCommand::SetVoiceSpatial { handle, spatial });
+    }
+
+    /// Returns the user-facing volume of every group, for persisting in settings.
+    pub fn group_volumes(&self) -> HashMap<String, f32> {
+        self.groups
+            .iter()
+            .map(|(name, _, params)| (name.clone(), params.volume))
+            .collect()
+    }
+
+    /// Restores volumes saved by [`group_volumes`](Self::group_volumes). Unknown groups are ignored.
+    pub fn set_group_volumes(&mut self, volumes: &HashMap<String, f32>) {
+        for (name, &volume) in volumes {
+            let _ = self.set_group_volume(name, volume);
+        }
+    }
+}
+
+/// A parameter ramping linearly towards a target over a number of samples.
+#[derive(Debug, Clone, Copy)]
+struct Ramp {
+    current: f32,
+    target: f32,
+    step: f32,
+    remaining: u32,
+}
+
+impl Ramp {
+    fn new(value: f32) -> Self {
+        Self {
+            current: value,
+            target: value,
+            step: 0.0,
+            remaining: 0,
+        }
+    }
+
+    fn set(&mut self, target: f32, samples: u32) {
+        self.target = target;
+
+        if samples == 0 {
+            self.current = target;
+            self.remaining = 0;
+        } else {
+            self.step = (target - self.current) / samples as f32;
+            self.remaining = samples;
+        }
+    }
+
+    fn advance(&mut self) -> f32 {
+        if self.remaining != 0 {
+            self.remaining -= 1;
+            self.current = if self.remaining == 0 {
+                self.target
+            } else {
+                self.current + self.step
+            };
+        }
+
+        self.current
+    }
+}
+
+struct GroupState {
+    parent: Option<usize>,
+    /// Volume in dB, so that transitions are perceptually linear.
+    volume_db: Ramp,
+    pitch: Ramp,
+    low_pass: Ramp,
+    /// Sum of the ducking applied to the group, ramped across each block.
+    duck_db: Ramp,
+    /// Sum of the ducking at the end of the current block, summed up over the rules.
+    duck_target_db: f32,
+    // Per-sample values resolved through the hierarchy.
+    gain: f32,
+    total_pitch: f32,
+    cutoff: f32,
+    active: bool,
+}
+
+struct Voice {
+    handle: SoundHandle,
+    samples: Arc<[f32]>,
+    group: usize,
+    position: f64,
+    looping: bool,
+    filter_state: f32,
+    spatial_gain: Ramp,
+    spatial_pitch: Ramp,
+    occlusion_db: Ramp,
+    occlusion_cutoff: Ramp,
+}
+
+struct DuckingState {
+    rule: DuckingRule,
+    envelope: f32,
+}
+
+/// Audio-thread side of the mixer. Call [`render`](Self::render) from the audio callback,
+/// or offline for tests. Its storage is allocated up front, so rendering never allocates.
+pub struct MixerRenderer {
+    sample_rate: u32,
+    commands: Arc<ArrayQueue<MixerCommand>>,
+    retired: Arc<ArrayQueue<Arc<[f32]>>>,
+    groups: Vec<GroupState>,
+    voices: Vec<Voice>,
+    duckings: Vec<DuckingState>,
+}
+
+impl MixerRenderer {
+    fn new(
+        sample_rate: u32,
+        commands: Arc<ArrayQueue<MixerCommand>>,
+        retired: Arc<ArrayQueue<Arc<[f32]>>>,
+    ) -> Self {
+        Self {
+            sample_rate,
+            commands,
+            retired,
+            groups: Vec::with_capacity(MAX_GROUPS),
+            voices: Vec::with_capacity(MAX_VOICES),
+            duckings: Vec::with_capacity(MAX_DUCKING_RULES),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn voice_count(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Hands the samples of a voice back to the main thread, so that they are not freed on the audio thread.
+    fn retire(&self, voice: Voice) {
+        // Only dropped here if the main thread has not collected the previous ones for a long time.
+        let _ = self.retired.push(voice.samples);
+    }
+
+    fn handle_commands(&mut self) {
+        while let Some(command) = self.commands.pop() {
+            match command {
+                MixerCommand::AddGroup { parent } => self.groups.push(GroupState {
+                    parent: parent.map(|parent| parent.0),
+                    volume_db: Ramp::new(0.0),
+                    pitch: Ramp::new(1.0),
+                    low_pass: Ramp::new(LOW_PASS_OPEN),
+                    duck_db: Ramp::new(0.0),
+                    duck_target_db: 0.0,
+                    gain: 1.0,
+                    total_pitch: 1.0,
+                    cutoff: LOW_PASS_OPEN,
+                    active: false,
+                }),
+                MixerCommand::SetGroupParams {
+                    group,
+                    params,
+                    duration_secs,
+                } => {
+                    let samples = (duration_secs.max(0.0) * self.sample_rate as f32) as u32;
+                    let group = &mut self.groups[group.0];
+                    group.volume_db.set(linear_to_db(params.volume), samples);
+                    group.pitch.set(params.pitch, samples);
+                    group
+                        .low_pass
+                        .set(params.low_pass.unwrap_or(LOW_PASS_OPEN), samples);
+                }
+                MixerCommand::AddDuckingRule(rule) => self.duckings.push(DuckingState {
+                    rule,
+                    envelope: 0.0,
+                }),
+                MixerCommand::Play { samples, .. } if self.voices.len() == MAX_VOICES => {
+                    let _ = self.retired.push(samples);
+                }
+                MixerCommand::Play {
+                    handle,
+                    samples,
+                    group,
+                    looping,
+                    spatial,
+                } => self.voices.push(Voice {
+                    handle,
+                    samples,
+                    group: group.0,
+                    position: 0.0,
+                    looping,
+                    filter_state: 0.0,
+                    spatial_gain: Ramp::new(spatial.gain),
+                    spatial_pitch: Ramp::new(spatial.pitch),
+                    occlusion_db: Ramp::new(spatial.occlusion_db.min(0.0)),
+                    occlusion_cutoff: Ramp::new(spatial.occlusion_cutoff.unwrap_or(LOW_PASS_OPEN)),
+                }),
+                MixerCommand::Stop(handle) => {
+                    if let Some(index) = self.voices.iter().position(|voice| voice.handle == handle)
+                    {
+                        let voice = self.voices.swap_remove(index);
+                        self.retire(voice);
+                    }
+                }
+                MixerCommand::SetVoiceSpatial { handle, spatial } => {
+                    let sample_rate = self.sample_rate as f32;
+                    let spatial_samples = (SPATIAL_RAMP_SECS * sample_rate) as u32;
+                    let occlusion_samples = (OCCLUSION_RAMP_SECS * sample_rate) as u32;
+
+                    if let Some(voice) = self.voices.iter_mut().find(|voice| voice.handle == handle)
+                    {
+                        voice.spatial_gain.set(spatial.gain, spatial_samples);
+                        voice.spatial_pitch.set(spatial.pitch, spatial_samples);
+                        voice
+                            .occlusion_db
+                            .set(spatial.occlusion_db.min(0.0), occlusion_samples);
+                        voice.occlusion_cutoff.set(
+                            spatial.occlusion_cutoff.unwrap_or(LOW_PASS_OPEN),
+                            occlusion_samples,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn is_descendant_of(&self, mut group: usize, ancestor: usize) -> bool {
+        loop {
+            if group == ancestor {
+                return true;
+            }
+
+            match self.groups[group].parent {
+                Some(parent) => group = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Advances parameters by one sample and resolves them through the hierarchy.
+    /// Parents always precede their children, so one forward pass is enough.
+    fn update_groups(&mut self) {
+        for index in 0..self.groups.len() {
+            let volume_db = self.groups[index].volume_db.advance();
+            let pitch = self.groups[index].pitch.advance();
+            let cutoff = self.groups[index].low_pass.advance();
+            let duck_db = self.groups[index].duck_db.advance();
+            let (parent_gain, parent_pitch, parent_cutoff) = match self.groups[index].parent {
+                Some(parent) => {
+                    let parent = &self.groups[parent];
+                    (parent.gain, parent.total_pitch, parent.cutoff)
+                }
+                None => (1.0, 1.0, LOW_PASS_OPEN),
+            };
+
+            let group = &mut self.groups[index];
+            group.gain = parent_gain * db_to_linear(volume_db + duck_db);
+            group.total_pitch = parent_pitch * pitch;
+            group.cutoff = parent_cutoff.min(cutoff);
+        }
+    }
+
+    /// Advances the ducking envelopes over a block of `samples`, and ramps the ducking of the groups towards
+    /// their values at the end of the block. The voices playing at the start of the block trigger the rules.
+    fn update_duckings(&mut self, samples: usize) {
+        for group in &mut self.groups {
+            group.active = false;
+            group.duck_target_db = 0.0;
+        }
+
+        for voice in &self.voices {
+            self.groups[voice.group].active = true;
+        }
+
+        let sample_rate = self.sample_rate as f32;
+
+        for index in 0..self.duckings.len() {
+            let rule = self.duckings[index].rule;
+            let triggered = (0..self.groups.len()).any(|group| {
+                self.groups[group].active && self.is_descendant_of(group, rule.trigger.0)
+            });
+            let (target, time) = if triggered {
+                (1.0, rule.attack_secs)
+            } else {
+                (0.0, rule.release_secs)
+            };
+            let ducking = &mut self.duckings[index];
+
+            if time <= 0.0 {
+                ducking.envelope = target;
+            } else {
+                let step = samples as f32 / (time * sample_rate);
+                ducking.envelope = if ducking.envelope < target {
+                    (ducking.envelope + step).min(target)
+                } else {
+                    (ducking.envelope - step).max(target)
+                };
+            }
+
+            self.groups[rule.target.0].duck_target_db += rule.amount_db * ducking.envelope;
+        }
+
+        for group in &mut self.groups {
+            group.duck_db.set(group.duck_target_db, samples as u32);
+        }
+    }
+
+    /// Mixes all playing voices into `output` (mono), overwriting its content.
+    pub fn render(&mut self, output: &mut [f32]) {
+        self.handle_commands();
+
+        let block_len = ((DUCKING_BLOCK_SECS * self.sample_rate as f32) as usize).max(1);
+
+        for block in output.chunks_mut(block_len) {
+            self.update_duckings(block.len());
+            self.render_block(block);
+        }
+    }
+
+    fn render_block(&mut self, output: &mut [f32]) {
+        let sample_rate = self.sample_rate as f32;
+
+        for sample in output.iter_mut() {
+            self.update_groups();
+
+            let mut mixed = 0.0;
+
+            for voice in &mut self.voices {
+                let group = &self.groups[voice.group];
+                let index = voice.position as usize;
+
+                if voice.samples.len() <= index {
+                    continue;
+                }
+
+                let spatial_gain = voice.spatial_gain.advance();
+                let spatial_pitch = voice.spatial_pitch.advance();
+                let occlusion_db = voice.occlusion_db.advance();
+                let cutoff = group.cutoff.min(voice.occlusion_cutoff.advance());
+
+                // One-pole low-pass filter.
+                let alpha = 1.0
+                    - (-2.0 * std::f32::consts::PI * cutoff / sample_rate)
+                        .exp()
+                        .min(1.0);
+                voice.filter_state += alpha * (voice.samples[index] - voice.filter_state);
+                mixed +=
+                    voice.filter_state * group.gain * spatial_gain * db_to_linear(occlusion_db);
+
+                voice.position += (group.total_pitch * spatial_pitch).max(0.0) as f64;
+
+                if voice.looping && voice.samples.len() <= voice.position as usize {
+                    voice.position -= voice.samples.len() as f64;
+                }
+            }
+
+            let mut index = 0;
+
+            while index < self.voices.len() {
+                if (self.voices[index].position as usize) < self.voices[index].samples.len() {
+                    index += 1;
+                } else {
+                    let voice = self.voices.swap_remove(index);
+                    self.retire(voice);
+                }
+            }
+
+            *sample = mixed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    fn constant(len: usize) -> Arc<[f32]> {
+        vec![1.0; len].into()
+    }
+
+    #[test]
+    fn check_group_volume_is_applied() {
+        let (mut mixer, mut renderer) = Mixer::with_default_groups(1000);
+        let music = mixer.find_group("music").unwrap();
+        mixer.set_group_volume("music", 0.5).unwrap();
+        mixer.play(constant(1000), music, true);
+
+        let mut output = vec![0.0; 1000];
+        renderer.render(&mut output);
+
+        assert!((rms(&output[500..]) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn check_music_is_ducked_while_voice_plays() {
+        let (mut mixer, mut renderer) = Mixer::with_default_groups(1000);
+        let music = mixer.find_group("music").unwrap();
+        let voice = mixer.find_group("voice").unwrap();
+        mixer
+            .add_ducking_rule(DuckingRule {
+                target: music,
+                trigger: voice,
+                amount_db: -8.0,
+                attack_secs: 0.05,
+                release_secs: 0.3,
+            })
+            .unwrap();
+        mixer.play(constant(10_000), music, true);
+        mixer.play(vec![0.0; 500].into(), voice, false);
+
+        let mut output = vec![0.0; 1000];
+        renderer.render(&mut output);
+
+        // Fully ducked after the attack, while the voice still plays.
+        assert!((rms(&output[100..500]) - db_to_linear(-8.0)).abs() < 1e-3);
+        // Fully released 300 ms after the voice stopped.
+        assert!((rms(&output[850..]) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn check_voice_spatial_is_smoothed() {
+        let (mut mixer, mut renderer) = Mixer::with_default_groups(1000);
+        let sfx = mixer.find_group("sfx").unwrap();
+        let handle = mixer.play(constant(10_000), sfx, true);
+        let mut output = vec![0.0; 100];
+        renderer.render(&mut output);
+
+        mixer.set_voice_spatial(
+            handle,
+            VoiceSpatial {
+                gain: 0.5,
+                pitch: 1.0,
+                occlusion_db: -12.0,
+                occlusion_cutoff: None,
+            },
+        );
+
+        let mut output = vec![0.0; 500];
+        renderer.render(&mut output);
+
+        // The occlusion fades in over 100 ms instead of jumping.
+        assert!(output[0] > output[50] && output[50] > output[99]);
+        assert!((rms(&output[200..]) - 0.5 * db_to_linear(-12.0)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn check_voice_pitch_scales_playback_rate() {
+        let (mut mixer, mut renderer) = Mixer::with_default_groups(1000);
+        let sfx = mixer.find_group("sfx").unwrap();
+        let handle = mixer.play(constant(1000), sfx, false);
+        mixer.set_voice_spatial(
+            handle,
+            VoiceSpatial {
+                pitch: 2.0,
+                ..Default::default()
+            },
+        );
+
+        // Played at twice the rate after a 20 ms ramp, the sound ends after roughly 505 samples.
+        let mut output = vec![0.0; 1000];
+        renderer.render(&mut output);
+
+        assert_eq!(renderer.voice_count(), 0);
+        assert!(output[500] != 0.0 && output[520] == 0.0);
+    }
+
+    #[test]
+    fn check_commands_beyond_the_queue_are_kept() {
+        let (mut mixer, mut renderer) = Mixer::with_default_groups(1000);
+        let music = mixer.find_group("music").unwrap();
+
+        for step in 0..=COMMAND_CAPACITY * 2 {
+            mixer
+                .set_group_volume("music", step as f32 / (COMMAND_CAPACITY * 2) as f32 * 0.5)
+                .unwrap();
+        }
+
+        mixer.play(constant(1000), music, true);
+
+        // The renderer sees the first part, then the rest once the main thread updates.
+        let mut output = vec![0.0; 100];
+        renderer.render(&mut output);
+        assert_eq!(renderer.voice_count(), 0);
+
+        mixer.update();
+        renderer.render(&mut output);
+        mixer.update();
+        renderer.render(&mut output);
+
+        assert_eq!(renderer.voice_count(), 1);
+        assert!((rms(&output) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn check_finished_samples_are_freed_on_the_main_thread() {
+        let (mut mixer, mut renderer) = Mixer::with_default_groups(1000);
+        let sfx = mixer.find_group("sfx").unwrap();
+        let samples = constant(100);
+        mixer.play(samples.clone(), sfx, false);
+        let stopped = mixer.play(samples.clone(), sfx, true);
+        mixer.stop(stopped);
+
+        let mut output = vec![0.0; 200];
+        renderer.render(&mut output);
+
+        assert_eq!(renderer.voice_count(), 0);
+        assert_eq!(Arc::strong_count(&samples), 3);
+
+        mixer.update();
+        assert_eq!(Arc::strong_count(&samples), 1);
+    }
+
+    #[test]
+    fn check_snapshot_transition_interpolates() {
+        let (mut mixer, mut renderer) = Mixer::with_default_groups(1000);
+        let music = mixer.find_group("music").unwrap();
+        mixer.add_snapshot(
+            "paused",
+            vec![(
+                music,
+                MixerGroupParams {
+                    volume: db_to_linear(-20.0),
+                    ..Default::default()
+                },
+            )],
+        );
+        mixer.play(constant(10_000), music, true);
+        mixer.transition_to_snapshot("paused", 0.3).unwrap();
+
+        let mut output = vec![0.0; 1000];
+        renderer.render(&mut output);
+
+        assert!(output[0] > output[150] && output[150] > output[299]);
+        assert!((rms(&output[300..]) - db_to_linear(-20.0)).abs() < 1e-3);
+    }
+}
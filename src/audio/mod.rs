@@ -0,0 +1,193 @@
+mod audio_settings;
+mod mixer;
+mod spatial;
+
+pub use audio_settings::*;
+pub use mixer::*;
+pub use spatial::*;
+
+use crate::math::Vec3;
+use std::{io::ErrorKind, path::Path, sync::Arc};
+
+/// Owns the mixer of the engine. There is no output device backend yet; whoever drives the
+/// audio device takes the [`MixerRenderer`] once and calls [`MixerRenderer::render`] from its callback.
+pub struct AudioManager {
+    mixer: Mixer,
+    renderer: Option<MixerRenderer>,
+    spatializer: Spatializer,
+    /// Settings as last loaded or saved, so that unchanged settings are not written again.
+    persisted_settings: AudioSettings,
+}
+
+impl AudioManager {
+    pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+    pub fn new() -> Self {
+        let (mixer, renderer) = Mixer::with_default_groups(Self::DEFAULT_SAMPLE_RATE);
+        let mut audio_mgr = Self {
+            mixer,
+            renderer: Some(renderer),
+            spatializer: Spatializer::new(),
+            persisted_settings: AudioSettings::default(),
+        };
+        audio_mgr.persisted_settings = audio_mgr.settings();
+        audio_mgr
+    }
+
+    pub fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+
+    pub fn mixer_mut(&mut self) -> &mut Mixer {
+        &mut self.mixer
+    }
+
+    /// Takes the audio-thread side of the mixer. Returns `None` if it has already been taken.
+    pub fn take_renderer(&mut self) -> Option<MixerRenderer> {
+        self.renderer.take()
+    }
+
+    pub fn set_group_volume(&mut self, name: &str, volume: f32) -> Result<(), MixerError> {
+        self.mixer.set_group_volume(name, volume)
+    }
+
+    /// The user-facing settings of the mixer, i.e. the volumes of its groups.
+    pub fn settings(&self) -> AudioSettings {
+        AudioSettings {
+            group_volumes: self.mixer.group_volumes().into_iter().collect(),
+        }
+    }
+
+    /// Applies settings, e.g. loaded ones. Groups the mixer does not have are ignored.
+    pub fn apply_settings(&mut self, settings: &AudioSettings) {
+        for (name, &volume) in &settings.group_volumes {
+            let _ = self.mixer.set_group_volume(name, volume);
+        }
+    }
+
+    /// Loads the settings from a file and applies them. A missing file leaves the settings as they are.
+    pub fn load_settings(&mut self, path: impl AsRef<Path>) -> Result<(), AudioSettingsError> {
+        let settings = match AudioSettings::load(path) {
+            Ok(settings) => settings,
+            Err(AudioSettingsError::IoError(err)) if err.kind() == ErrorKind::NotFound => {
+                return Ok(())
+            }
+            Err(err) => return Err(err),
+        };
+
+        self.apply_settings(&settings);
+        self.persisted_settings = self.settings();
+        Ok(())
+    }
+
+    /// Saves the settings to a file, unless they are the same as last loaded or saved.
+    pub fn save_settings(&mut self, path: impl AsRef<Path>) -> Result<(), AudioSettingsError> {
+        let settings = self.settings();
+
+        if settings == self.persisted_settings {
+            return Ok(());
+        }
+
+        settings.save(path)?;
+        self.persisted_settings = settings;
+        Ok(())
+    }
+
+    pub fn transition_to_snapshot(
+        &mut self,
+        name: &str,
+        duration_secs: f32,
+    ) -> Result<(), MixerError> {
+        self.mixer.transition_to_snapshot(name, duration_secs)
+    }
+
+    pub fn play(&mut self, samples: Arc<[f32]>, group: &str) -> Result<SoundHandle, MixerError> {
+        let group = self.mixer.find_group(group)?;
+        Ok(self.mixer.play(samples, group, false))
+    }
+
+    pub fn stop(&mut self, handle: SoundHandle) {
+        self.mixer.stop(handle);
+        self.spatializer.remove(handle);
+    }
+
+    pub fn spatializer(&self) -> &Spatializer {
+        &self.spatializer
+    }
+
+    pub fn spatializer_mut(&mut self) -> &mut Spatializer {
+        &mut self.spatializer
+    }
+
+    /// Plays a sound positioned in the world. Its parameters can be changed through the
+    /// [`spatializer`](Self::spatializer_mut) while it plays; stop it to release them.
+    pub fn play_spatial(
+        &mut self,
+        samples: Arc<[f32]>,
+        group: &str,
+        looping: bool,
+        params: SpatialParams,
+    ) -> Result<SoundHandle, MixerError> {
+        let group = self.mixer.find_group(group)?;
+        // Evaluated up front, so that the sound doesn't start unattenuated.
+        let spatial = self.spatializer.evaluate(&params);
+        let handle = self.mixer.play_spatial(samples, group, looping, spatial);
+        self.spatializer.set_params(handle, params);
+        Ok(handle)
+    }
+
+    pub fn set_listener(&mut self, position: Vec3, velocity: Option<Vec3>) {
+        self.spatializer.set_listener(position, velocity);
+    }
+
+    /// Sends the spatial parameters of the sounds to the audio thread, and updates the mixer.
+    /// Called once per frame by the engine.
+    pub fn update_spatial(&mut self, delta_secs: f32) {
+        self.spatializer.update(delta_secs, &mut self.mixer);
+        self.mixer.update();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn check_settings_are_saved_only_when_changed() {
+        let path = std::env::temp_dir().join(format!(
+            "r3d-audio-settings-test-{}.ron",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        // Nothing to load, and the defaults are not written.
+        let mut audio_mgr = AudioManager::new();
+        audio_mgr.load_settings(&path).unwrap();
+        audio_mgr.save_settings(&path).unwrap();
+        assert!(!path.exists());
+
+        audio_mgr.set_group_volume("music", 0.25).unwrap();
+        audio_mgr.save_settings(&path).unwrap();
+
+        let mut audio_mgr = AudioManager::new();
+        audio_mgr.load_settings(&path).unwrap();
+        assert_eq!(audio_mgr.settings().group_volumes["music"], 0.25);
+        assert_eq!(audio_mgr.settings().group_volumes["sfx"], 1.0);
+
+        // Unknown groups are ignored, e.g. ones a newer version of the game has.
+        let mut settings = audio_mgr.settings();
+        settings.group_volumes.insert("ambience".to_owned(), 0.5);
+        fs::write(&path, settings.to_ron()).unwrap();
+        audio_mgr.load_settings(&path).unwrap();
+        assert!(!audio_mgr.settings().group_volumes.contains_key("ambience"));
+
+        fs::write(&path, "(group_volumes: 3)").unwrap();
+        assert!(matches!(
+            audio_mgr.load_settings(&path),
+            Err(AudioSettingsError::ParseError(_))
+        ));
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,397 @@
+use super::{Mixer, SoundHandle, VoiceSpatial};
+use crate::math::Vec3;
+use std::{collections::HashMap, sync::Arc};
+
+/// Speed of sound in air, in world units (meters) per second.
+pub const SPEED_OF_SOUND: f32 = 343.0;
+
+/// How the volume of a sound decreases between its min and max distance.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttenuationCurve {
+    /// Falls linearly to silence at the max distance.
+    Linear,
+    /// Falls by the same number of decibels for every doubling of the distance, reaching silence at the max distance.
+    Logarithmic,
+    /// `min_distance / distance`, the physically based rolloff. Stays constant beyond the max distance.
+    Inverse,
+    /// Piecewise linear gain over the distance normalized to `[0, 1]` between min and max distance,
+    /// given as `(normalized distance, gain)` points sorted by distance.
+    Custom(Arc<[(f32, f32)]>),
+}
+
+impl AttenuationCurve {
+    /// Returns the linear gain at the given distance.
+    pub fn gain(&self, distance: f32, min_distance: f32, max_distance: f32) -> f32 {
+        let min_distance = min_distance.max(f32::EPSILON);
+        let max_distance = max_distance.max(min_distance);
+        let distance = distance.clamp(min_distance, max_distance);
+
+        if max_distance <= min_distance {
+            return 1.0;
+        }
+
+        let t = (distance - min_distance) / (max_distance - min_distance);
+
+        match self {
+            AttenuationCurve::Linear => 1.0 - t,
+            AttenuationCurve::Logarithmic => {
+                1.0 - (distance / min_distance).ln() / (max_distance / min_distance).ln()
+            }
+            AttenuationCurve::Inverse => min_distance / distance,
+            AttenuationCurve::Custom(points) => sample_points(points, t),
+        }
+    }
+}
+
+impl Default for AttenuationCurve {
+    fn default() -> Self {
+        Self::Inverse
+    }
+}
+
+fn sample_points(points: &[(f32, f32)], t: f32) -> f32 {
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return 1.0,
+    };
+
+    if t <= first.0 {
+        return first.1;
+    }
+
+    for pair in points.windows(2) {
+        let ((t0, gain0), (t1, gain1)) = (pair[0], pair[1]);
+
+        if t <= t1 {
+            let ratio = if t1 <= t0 { 1.0 } else { (t - t0) / (t1 - t0) };
+            return gain0 + (gain1 - gain0) * ratio;
+        }
+    }
+
+    last.1
+}
+
+/// Computes the pitch multiplier caused by the relative motion of a source and a listener.
+/// Velocities are clamped below the speed of sound so that the result stays finite.
+pub fn doppler_pitch(
+    listener_position: Vec3,
+    listener_velocity: Vec3,
+    source_position: Vec3,
+    source_velocity: Vec3,
+    speed_of_sound: f32,
+    doppler_scale: f32,
+) -> f32 {
+    let to_listener = listener_position - source_position;
+    let distance = to_listener.len();
+
+    if distance <= f32::EPSILON || doppler_scale <= 0.0 {
+        return 1.0;
+    }
+
+    let direction = to_listener / distance;
+    let max_speed = speed_of_sound / doppler_scale * 0.99;
+    // Positive when moving away from the source.
+    let listener_speed = Vec3::dot(listener_velocity, direction).min(max_speed);
+    // Positive when moving towards the listener.
+    let source_speed = Vec3::dot(source_velocity, direction).min(max_speed);
+
+    (speed_of_sound - doppler_scale * listener_speed)
+        / (speed_of_sound - doppler_scale * source_speed)
+}
+
+/// Spatialization parameters of a sound.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpatialParams {
+    pub position: Vec3,
+    /// Explicit velocity. If `None`, it is derived from the position changes between updates.
+    pub velocity: Option<Vec3>,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub curve: AttenuationCurve,
+    /// Scales the doppler effect; `0.0` disables it.
+    pub doppler_scale: f32,
+    /// Clamps the doppler pitch multiplier.
+    pub min_pitch: f32,
+    pub max_pitch: f32,
+    /// Attenuation in dB while the path to the listener is blocked.
+    pub occlusion_db: f32,
+    /// Low-pass cutoff in Hz while the path to the listener is blocked.
+    pub occlusion_cutoff: f32,
+}
+
+impl Default for SpatialParams {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            velocity: None,
+            min_distance: 1.0,
+            max_distance: 100.0,
+            curve: AttenuationCurve::default(),
+            doppler_scale: 1.0,
+            min_pitch: 0.5,
+            max_pitch: 2.0,
+            occlusion_db: -12.0,
+            occlusion_cutoff: 1_500.0,
+        }
+    }
+}
+
+/// Decides whether the path from the listener to a source is blocked, e.g. by raycasting the scene.
+pub type OcclusionQuery = Box<dyn Fn(Vec3, Vec3) -> bool>;
+
+struct SpatialSource {
+    params: SpatialParams,
+    previous_position: Option<Vec3>,
+    velocity: Vec3,
+}
+
+/// Turns the positions of the listener and the spatial sounds into per-sound gain, pitch and occlusion,
+/// which are sent to the mixer once per update.
+pub struct Spatializer {
+    listener_position: Vec3,
+    listener_velocity: Option<Vec3>,
+    previous_listener_position: Option<Vec3>,
+    derived_listener_velocity: Vec3,
+    speed_of_sound: f32,
+    sources: HashMap<SoundHandle, SpatialSource>,
+    occlusion_query: Option<OcclusionQuery>,
+}
+
+impl Spatializer {
+    pub fn new() -> Self {
+        Self {
+            listener_position: Vec3::ZERO,
+            listener_velocity: None,
+            previous_listener_position: None,
+            derived_listener_velocity: Vec3::ZERO,
+            speed_of_sound: SPEED_OF_SOUND,
+            sources: HashMap::new(),
+            occlusion_query: None,
+        }
+    }
+
+    /// Moves the listener. If `velocity` is `None`, it is derived from the position changes between updates.
+    pub fn set_listener(&mut self, position: Vec3, velocity: Option<Vec3>) {
+        self.listener_position = position;
+        self.listener_velocity = velocity;
+    }
+
+    pub fn set_speed_of_sound(&mut self, speed_of_sound: f32) {
+        self.speed_of_sound = speed_of_sound.max(f32::EPSILON);
+    }
+
+    pub fn set_occlusion_query(&mut self, query: Option<OcclusionQuery>) {
+        self.occlusion_query = query;
+    }
+
+    pub fn params(&self, handle: SoundHandle) -> Option<&SpatialParams> {
+        self.sources.get(&handle).map(|source| &source.params)
+    }
+
+    /// Makes the sound spatial, or updates its parameters.
+    pub fn set_params(&mut self, handle: SoundHandle, params: SpatialParams) {
+        match self.sources.get_mut(&handle) {
+            Some(source) => source.params = params,
+            None => {
+                self.sources.insert(
+                    handle,
+                    SpatialSource {
+                        params,
+                        previous_position: None,
+                        velocity: Vec3::ZERO,
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn remove(&mut self, handle: SoundHandle) {
+        self.sources.remove(&handle);
+    }
+
+    /// Computes the parameters of a new sound, so that it can start with them.
+    pub fn evaluate(&self, params: &SpatialParams) -> VoiceSpatial {
+        let listener_velocity = self
+            .listener_velocity
+            .unwrap_or(self.derived_listener_velocity);
+        self.evaluate_params(
+            params,
+            params.velocity.unwrap_or(Vec3::ZERO),
+            listener_velocity,
+        )
+    }
+
+    /// Updates the derived velocities and sends the resulting parameters of every spatial sound to the mixer.
+    pub fn update(&mut self, delta_secs: f32, mixer: &mut Mixer) {
+        self.derived_listener_velocity = derive_velocity(
+            &mut self.previous_listener_position,
+            self.listener_position,
+            delta_secs,
+        )
+        .unwrap_or(self.derived_listener_velocity);
+        let listener_velocity = self
+            .listener_velocity
+            .unwrap_or(self.derived_listener_velocity);
+
+        for source in self.sources.values_mut() {
+            source.velocity = derive_velocity(
+                &mut source.previous_position,
+                source.params.position,
+                delta_secs,
+            )
+            .unwrap_or(source.velocity);
+        }
+
+        for (&handle, source) in &self.sources {
+            let velocity = source.params.velocity.unwrap_or(source.velocity);
+            mixer.set_voice_spatial(
+                handle,
+                self.evaluate_params(&source.params, velocity, listener_velocity),
+            );
+        }
+    }
+
+    fn evaluate_params(
+        &self,
+        params: &SpatialParams,
+        velocity: Vec3,
+        listener_velocity: Vec3,
+    ) -> VoiceSpatial {
+        let distance = Vec3::distance(self.listener_position, params.position);
+        let gain = params
+            .curve
+            .gain(distance, params.min_distance, params.max_distance);
+        let pitch = doppler_pitch(
+            self.listener_position,
+            listener_velocity,
+            params.position,
+            velocity,
+            self.speed_of_sound,
+            params.doppler_scale,
+        )
+        .clamp(params.min_pitch, params.max_pitch);
+        let occluded = match &self.occlusion_query {
+            Some(query) => query(self.listener_position, params.position),
+            None => false,
+        };
+
+        VoiceSpatial {
+            gain,
+            pitch,
+            occlusion_db: if occluded { params.occlusion_db } else { 0.0 },
+            occlusion_cutoff: occluded.then_some(params.occlusion_cutoff),
+        }
+    }
+}
+
+impl Default for Spatializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn derive_velocity(previous: &mut Option<Vec3>, position: Vec3, delta_secs: f32) -> Option<Vec3> {
+    let velocity = match *previous {
+        Some(previous) if f32::EPSILON < delta_secs => Some((position - previous) / delta_secs),
+        _ => None,
+    };
+    *previous = Some(position);
+    velocity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doppler_pitch_at_known_velocities() {
+        let listener = Vec3::ZERO;
+        let source = Vec3::new(100.0, 0.0, 0.0);
+
+        // A source approaching at a tenth of the speed of sound: f' = c / (c - v).
+        let approaching = doppler_pitch(
+            listener,
+            Vec3::ZERO,
+            source,
+            Vec3::new(-34.3, 0.0, 0.0),
+            SPEED_OF_SOUND,
+            1.0,
+        );
+        assert!((approaching - 1.0 / 0.9).abs() < 1e-4);
+
+        // Receding: f' = c / (c + v).
+        let receding = doppler_pitch(
+            listener,
+            Vec3::ZERO,
+            source,
+            Vec3::new(34.3, 0.0, 0.0),
+            SPEED_OF_SOUND,
+            1.0,
+        );
+        assert!((receding - 1.0 / 1.1).abs() < 1e-4);
+
+        // A listener moving towards the source: f' = (c + v) / c.
+        let moving_listener = doppler_pitch(
+            listener,
+            Vec3::new(34.3, 0.0, 0.0),
+            source,
+            Vec3::ZERO,
+            SPEED_OF_SOUND,
+            1.0,
+        );
+        assert!((moving_listener - 1.1).abs() < 1e-4);
+
+        // Perpendicular motion and a disabled effect don't shift the pitch.
+        let perpendicular = doppler_pitch(
+            listener,
+            Vec3::ZERO,
+            source,
+            Vec3::new(0.0, 50.0, 0.0),
+            SPEED_OF_SOUND,
+            1.0,
+        );
+        assert!((perpendicular - 1.0).abs() < 1e-6);
+        assert_eq!(
+            doppler_pitch(
+                listener,
+                Vec3::ZERO,
+                source,
+                Vec3::new(-34.3, 0.0, 0.0),
+                SPEED_OF_SOUND,
+                0.0
+            ),
+            1.0
+        );
+
+        // Supersonic sources stay finite.
+        let supersonic = doppler_pitch(
+            listener,
+            Vec3::ZERO,
+            source,
+            Vec3::new(-1000.0, 0.0, 0.0),
+            SPEED_OF_SOUND,
+            1.0,
+        );
+        assert!(supersonic.is_finite() && 1.0 < supersonic);
+    }
+
+    #[test]
+    fn attenuation_curves() {
+        let custom = AttenuationCurve::Custom(vec![(0.0, 1.0), (0.5, 0.2), (1.0, 0.0)].into());
+
+        for curve in [
+            AttenuationCurve::Linear,
+            AttenuationCurve::Logarithmic,
+            AttenuationCurve::Inverse,
+            custom.clone(),
+        ] {
+            assert_eq!(curve.gain(0.5, 1.0, 11.0), 1.0);
+            assert!(curve.gain(6.0, 1.0, 11.0) < 1.0);
+        }
+
+        assert!((AttenuationCurve::Linear.gain(6.0, 1.0, 11.0) - 0.5).abs() < 1e-6);
+        assert!(AttenuationCurve::Logarithmic.gain(11.0, 1.0, 11.0).abs() < 1e-6);
+        assert!((AttenuationCurve::Inverse.gain(4.0, 1.0, 11.0) - 0.25).abs() < 1e-6);
+        assert!((custom.gain(3.5, 1.0, 11.0) - 0.6).abs() < 1e-6);
+    }
+}
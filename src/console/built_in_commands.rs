@@ -0,0 +1,293 @@
+use super::{ConsoleCommand, ConsoleCommandRegistry};
+use crate::{
+    gfx::{image_difference, DebugViewMode, FrameCapture, FrameReplayer, HlodBakeSettings},
+    use_context,
+};
+use logging::StandardLogLevel;
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Registers the commands every console has: `help`, `fps`, `stats`, `set_time_scale`,
+/// `screenshot`, `capture_frame`, `replay_capture`, `bake_hlod`, `debug_view`, `log_level` and `quit`.
+pub fn register_built_in_commands(registry: &mut ConsoleCommandRegistry) {
+    registry.register(ConsoleCommand::new("help", "- lists the commands", |_| {
+        let console_mgr = use_context().console_mgr();
+        let lines = console_mgr
+            .commands()
+            .commands()
+            .map(|command| format!("{} {}", command.name(), command.help()))
+            .collect::<Vec<_>>();
+        Ok(Some(lines.join("\n")))
+    }));
+    registry.register(ConsoleCommand::new(
+        "fps",
+        "- prints the frame rate of the last frame",
+        |_| {
+            let delta_time = use_context().time_mgr().unscaled_delta_time();
+
+            if delta_time <= 0.0 {
+                return Ok(Some("no frame yet".to_owned()));
+            }
+
+            Ok(Some(format!(
+                "{:.1} fps ({:.2} ms)",
+                1.0 / delta_time,
+                delta_time * 1000.0
+            )))
+        },
+    ));
+    registry.register(ConsoleCommand::new(
+        "stats",
+        "- prints the frame report and engine counters",
+        |_| {
+            let ctx = use_context();
+            let report = ctx.render_mgr().frame_report();
+            let objects = ctx.object_mgr().object_hierarchy().objects().len();
+            let frame = ctx.time_mgr().frame_index();
+            let time_scale = ctx.time_mgr().time_scale();
+            let log = ctx.console_mgr().log().map(|log| {
+                format!(
+                    "{}/{} log record(s) kept, {} dropped",
+                    log.len(),
+                    log.capacity(),
+                    log.dropped()
+                )
+            });
+            let optional_ms = |ms: Option<f32>| match ms {
+                Some(ms) => format!("{:.2} ms", ms),
+                None => "n/a".to_owned(),
+            };
+
+            let mut lines = vec![
+                format!("frame {}, time scale {}", frame, time_scale),
+                format!(
+                    "{} frame(s) in flight, waited {:.2} ms, gpu {}, input latency {}",
+                    report.frames_in_flight,
+                    report.wait_ms,
+                    optional_ms(report.gpu_ms),
+                    optional_ms(report.input_latency_ms)
+                ),
+                format!(
+                    "{} draw call(s), {} of them HLOD proxies, {} material batch(es)",
+                    report.draw_calls, report.hlod_proxies_drawn, report.material_batches
+                ),
+                format!(
+                    "{} terrain chunk(s) drawn, {} culled",
+                    report.terrain_chunks_drawn, report.terrain_chunks_culled
+                ),
+                format!(
+                    "{:.2} ms picking the local lights of the meshes",
+                    report.light_culling_ms
+                ),
+                format!(
+                    "{} upload(s) batched ({} bytes), {} direct, {} staging chunk(s) in flight",
+                    report.uploads.writes_coalesced,
+                    report.uploads.staging_bytes,
+                    report.uploads.direct_writes,
+                    report.uploads.chunks_in_flight
+                ),
+                format!(
+                    "{} bytes of per-instance and generated vertex data uploaded",
+                    report.frame_buffer_bytes
+                ),
+                format!(
+                    "frame buffers: {} of {} bytes used over {} page(s), {} bytes waiting for release",
+                    report.frame_buffers.device.used_bytes,
+                    report.frame_buffers.device.reserved_bytes,
+                    report.frame_buffers.device.page_count,
+                    report.frame_buffers.device.retired_bytes
+                ),
+                format!(
+                    "camera passes recorded on {} thread(s) in {:.2} ms",
+                    report.encoder_threads, report.encode_ms
+                ),
+                format!("{} object(s)", objects),
+            ];
+            lines.extend(log);
+            Ok(Some(lines.join("\n")))
+        },
+    ));
+    registry.register(ConsoleCommand::new(
+        "set_time_scale",
+        "<scale> - sets the time scale, e.g. 0.5 for half speed",
+        |args| {
+            let time_scale = match args {
+                [time_scale] => time_scale
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|time_scale| time_scale.is_finite() && 0.0 <= *time_scale),
+                _ => None,
+            };
+            let time_scale = time_scale
+                .ok_or_else(|| "usage: set_time_scale <non-negative number>".to_owned())?;
+
+            use_context().time_mgr_mut().set_time_scale(time_scale);
+            Ok(Some(format!("time scale set to {}", time_scale)))
+        },
+    ));
+    registry.register(ConsoleCommand::new(
+        "screenshot",
+        "[path] - saves the next frame as a PNG image",
+        |args| {
+            let path = match args {
+                [] => {
+                    let seconds = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |duration| duration.as_secs());
+                    format!("screenshot-{}.png", seconds)
+                }
+                [path] => path.clone(),
+                _ => return Err("usage: screenshot [path]".to_owned()),
+            };
+
+            use_context()
+                .render_mgr_mut()
+                .request_screenshot(&path)
+                .map_err(|err| err.to_string())?;
+            Ok(Some(format!("saving screenshot to {}", path)))
+        },
+    ));
+    registry.register(ConsoleCommand::new(
+        "capture_frame",
+        "[path] - records the next frame for replaying it elsewhere",
+        |args| {
+            let ctx = use_context();
+            let path = match args {
+                [] => ctx.capture_next_frame(),
+                [path] => {
+                    ctx.render_mgr_mut().capture_next_frame(path);
+                    PathBuf::from(path)
+                }
+                _ => return Err("usage: capture_frame [path]".to_owned()),
+            };
+
+            Ok(Some(format!(
+                "capturing the next frame into {}",
+                path.display()
+            )))
+        },
+    ));
+    registry.register(ConsoleCommand::new(
+        "replay_capture",
+        "<path> [commands] - renders a frame capture, or its first commands, into a PNG image next to it",
+        |args| {
+            let (path, command_limit) = match args {
+                [path] => (path, None),
+                [path, commands] => (
+                    path,
+                    Some(
+                        commands
+                            .parse::<usize>()
+                            .map_err(|_| format!("invalid command count: {}", commands))?,
+                    ),
+                ),
+                _ => return Err("usage: replay_capture <path> [commands]".to_owned()),
+            };
+            let capture = FrameCapture::load(path).map_err(|err| err.to_string())?;
+            let gfx_ctx = use_context().gfx_ctx();
+            let image = FrameReplayer::new(&gfx_ctx.device, &gfx_ctx.queue)
+                .render(&capture, command_limit)
+                .map_err(|err| err.to_string())?;
+            let output = match command_limit {
+                Some(command_limit) => format!("{}-{}.png", path, command_limit),
+                None => format!("{}.png", path),
+            };
+            image.save(&output).map_err(|err| err.to_string())?;
+
+            let mut message = format!(
+                "replayed {} of {} commands into {}",
+                command_limit.map_or(capture.command_count(), |command_limit| {
+                    command_limit.min(capture.command_count())
+                }),
+                capture.command_count(),
+                output
+            );
+
+            if let Some(difference) = capture
+                .reference_image()
+                .filter(|_| command_limit.is_none())
+                .and_then(|reference| image_difference(&reference, &image))
+            {
+                message += &format!(
+                    " ({:.2}% off the captured frame)",
+                    difference * 100.0
+                );
+            }
+
+            Ok(Some(message))
+        },
+    ));
+    registry.register(ConsoleCommand::new(
+        "bake_hlod",
+        "[<cell size>] [<switch distance>] - merges the static objects into HLOD proxies in the background",
+        |args| {
+            let parse = |arg: &String| {
+                arg.parse::<f32>()
+                    .ok()
+                    .filter(|value| value.is_finite() && 0.0 < *value)
+                    .ok_or_else(|| "usage: bake_hlod [<cell size>] [<switch distance>]".to_owned())
+            };
+            let mut settings = HlodBakeSettings::default();
+
+            match args {
+                [] => {}
+                [cell_size] => settings.cell_size = parse(cell_size)?,
+                [cell_size, switch_distance] => {
+                    settings.cell_size = parse(cell_size)?;
+                    settings.switch_distance = parse(switch_distance)?;
+                }
+                _ => return Err("usage: bake_hlod [<cell size>] [<switch distance>]".to_owned()),
+            }
+
+            use_context().bake_and_apply_hlod(settings);
+            Ok(Some(format!(
+                "baking HLOD proxies with {} unit cells, switching at {} units",
+                settings.cell_size, settings.switch_distance
+            )))
+        },
+    ));
+    registry.register(ConsoleCommand::new(
+        "debug_view",
+        "<none|wireframe|normals|overdraw|depth> - shows the scene as the mode",
+        |args| {
+            let debug_view = match args {
+                [name] => DebugViewMode::from_name(name),
+                _ => None,
+            };
+            let debug_view = debug_view.ok_or_else(|| {
+                "usage: debug_view <none|wireframe|normals|overdraw|depth>".to_owned()
+            })?;
+
+            use_context().render_mgr_mut().set_debug_view(debug_view);
+            Ok(Some(format!("showing the scene as {}", debug_view.name())))
+        },
+    ));
+    registry.register(ConsoleCommand::new(
+        "log_level",
+        "<debug|info|warning|error|fatal> - hides the logs below the level",
+        |args| {
+            let level = match args {
+                [level] => match level.to_ascii_lowercase().as_str() {
+                    "debug" => Some(StandardLogLevel::Debug),
+                    "info" => Some(StandardLogLevel::Info),
+                    "warning" => Some(StandardLogLevel::Warning),
+                    "error" => Some(StandardLogLevel::Error),
+                    "fatal" => Some(StandardLogLevel::Fatal),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let level = level
+                .ok_or_else(|| "usage: log_level <debug|info|warning|error|fatal>".to_owned())?;
+
+            use_context().console_mgr_mut().set_level_filter(level);
+            Ok(None)
+        },
+    ));
+    registry.register(ConsoleCommand::new("quit", "- exits the engine", |_| {
+        use_context().request_exit();
+        Ok(None)
+    }));
+}
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Output of a console command: the text to print, if any, or an error message.
+pub type ConsoleCommandResult = Result<Option<String>, String>;
+
+/// A command typed into the console. It is executed on the main thread with the arguments after its name.
+pub struct ConsoleCommand {
+    name: String,
+    help: String,
+    callback: Box<dyn FnMut(&[String]) -> ConsoleCommandResult>,
+}
+
+impl ConsoleCommand {
+    /// `help` describes the arguments, e.g. `"<scale> - sets the time scale"`.
+    pub fn new(
+        name: impl Into<String>,
+        help: impl Into<String>,
+        callback: impl FnMut(&[String]) -> ConsoleCommandResult + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            help: help.into(),
+            callback: Box::new(callback),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn help(&self) -> &str {
+        &self.help
+    }
+
+    pub fn execute(&mut self, args: &[String]) -> ConsoleCommandResult {
+        (self.callback)(args)
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleCommandError {
+    #[error("{0}")]
+    Parse(#[from] ConsoleParseError),
+    #[error("unknown command `{0}`; type `help` for the list of commands")]
+    UnknownCommand(String),
+    #[error("{0}")]
+    Failed(String),
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleParseError {
+    #[error("unterminated quote")]
+    UnterminatedQuote,
+    #[error("trailing backslash")]
+    TrailingBackslash,
+}
+
+/// Commands of the console, by name.
+#[derive(Default)]
+pub struct ConsoleCommandRegistry {
+    commands: BTreeMap<String, ConsoleCommand>,
+}
+
+impl ConsoleCommandRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers a command, returning the one it replaces.
+    pub fn register(&mut self, command: ConsoleCommand) -> Option<ConsoleCommand> {
+        self.commands.insert(command.name.clone(), command)
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<ConsoleCommand> {
+        self.commands.remove(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.commands.contains_key(name)
+    }
+
+    /// The commands in alphabetical order.
+    pub fn commands(&self) -> impl Iterator<Item = &ConsoleCommand> {
+        self.commands.values()
+    }
+
+    /// Takes a command out to execute it without borrowing the registry;
+    /// see [`restore`](Self::restore).
+    pub fn take(&mut self, name: &str) -> Option<ConsoleCommand> {
+        self.commands.remove(name)
+    }
+
+    /// Puts back a command taken by [`take`](Self::take), unless it has been registered again meanwhile.
+    pub fn restore(&mut self, command: ConsoleCommand) {
+        self.commands.entry(command.name.clone()).or_insert(command);
+    }
+}
+
+/// Splits a command line into words separated by whitespace.
+///
+/// Single quotes keep everything up to the closing quote as it is. Double quotes keep whitespace,
+/// and a backslash inside them or outside quotes escapes the next character.
+pub fn parse_command_line(line: &str) -> Result<Vec<String>, ConsoleParseError> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(ConsoleParseError::UnterminatedQuote),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => word.push(c),
+                            None => return Err(ConsoleParseError::UnterminatedQuote),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(ConsoleParseError::UnterminatedQuote),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+
+                match chars.next() {
+                    Some(c) => word.push(c),
+                    None => return Err(ConsoleParseError::TrailingBackslash),
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+
+    if in_word {
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Vec<String>, ConsoleParseError> {
+        parse_command_line(line)
+    }
+
+    #[test]
+    fn check_command_line_parsing() {
+        assert_eq!(parse("").unwrap(), Vec::<String>::new());
+        assert_eq!(parse("   ").unwrap(), Vec::<String>::new());
+        assert_eq!(
+            parse("  set_time_scale   0.5 ").unwrap(),
+            ["set_time_scale", "0.5"]
+        );
+        assert_eq!(
+            parse(r#"screenshot "my shots/a b.png""#).unwrap(),
+            ["screenshot", "my shots/a b.png"]
+        );
+        assert_eq!(
+            parse(r#"say 'a\b' "a \"quote\"" a\ b"#).unwrap(),
+            ["say", "a\\b", "a \"quote\"", "a b"]
+        );
+        // Quotes join with the surrounding characters, and empty quotes are an empty word.
+        assert_eq!(parse(r#"a"b c"d '' x"#).unwrap(), ["ab cd", "", "x"]);
+
+        assert_eq!(
+            parse("say 'oops"),
+            Err(ConsoleParseError::UnterminatedQuote)
+        );
+        assert_eq!(
+            parse("say \"oops\\"),
+            Err(ConsoleParseError::UnterminatedQuote)
+        );
+        assert_eq!(
+            parse("say oops\\"),
+            Err(ConsoleParseError::TrailingBackslash)
+        );
+    }
+
+    #[test]
+    fn check_registry_take_and_restore() {
+        let mut registry = ConsoleCommandRegistry::new();
+        registry.register(ConsoleCommand::new("echo", "<text>...", |args| {
+            Ok(Some(args.join(" ")))
+        }));
+
+        let mut command = registry.take("echo").unwrap();
+        assert!(!registry.contains("echo"));
+        assert_eq!(
+            command.execute(&["a".to_owned(), "b".to_owned()]),
+            Ok(Some("a b".to_owned()))
+        );
+
+        // A command registered again while the old one was running wins.
+        registry.register(ConsoleCommand::new("echo", "", |_| Ok(None)));
+        registry.restore(command);
+        assert_eq!(registry.take("echo").unwrap().help(), "");
+    }
+}
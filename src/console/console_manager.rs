@@ -0,0 +1,422 @@
+use super::{ConsoleCommandRegistry, ConsoleDrawList};
+use crate::gfx::{FontHandle, OverlayId};
+use logging::{LogRecord, LogRingBuffer, RingBufferTransport, StandardLogLevel};
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+
+/// Log capture and the in-engine console. See [`ConsoleManager`].
+#[derive(Debug, Clone)]
+pub struct ConsoleConfig {
+    /// Number of log records kept for the console and the crash report.
+    pub capacity: usize,
+    /// Key opening and closing the console. The console can only be opened from code if `None`.
+    pub toggle_key: Option<VirtualKeyCode>,
+    /// Font size of the console text, in logical pixels.
+    pub font_size: f32,
+    /// File the kept records are written into if the main thread panics. No report is written if `None`.
+    pub crash_report_path: Option<PathBuf>,
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            toggle_key: Some(VirtualKeyCode::Grave),
+            font_size: 16.0,
+            crash_report_path: Some(PathBuf::from("crash_report.log")),
+        }
+    }
+}
+
+/// Captures the engine logs into a ring buffer and shows them in a console drawn over everything else.
+///
+/// The console has an input line dispatching the commands of [`commands_mut`](Self::commands_mut).
+/// Submitted lines are executed by the engine on the main thread at the start of the next frame.
+/// Without a [`ConsoleConfig`], nothing is captured and the console cannot be opened.
+pub struct ConsoleManager {
+    log: Option<LogRingBuffer<StandardLogLevel>>,
+    commands: ConsoleCommandRegistry,
+    toggle_key: Option<VirtualKeyCode>,
+    font: Option<FontHandle>,
+    font_size: f32,
+    is_open: bool,
+    level_filter: StandardLogLevel,
+    scroll: usize,
+    anchor: Option<u64>,
+    input: String,
+    history: Vec<String>,
+    history_index: Option<usize>,
+    submitted: VecDeque<String>,
+    suppress_character: bool,
+    overlay: Option<(OverlayId, bool)>,
+    draw_list: Arc<Mutex<ConsoleDrawList>>,
+}
+
+impl ConsoleManager {
+    /// Lines scrolled by the page keys.
+    pub const PAGE_LINES: usize = 10;
+
+    pub fn new(config: Option<&ConsoleConfig>) -> Self {
+        Self {
+            log: config.map(|config| LogRingBuffer::new(config.capacity)),
+            commands: ConsoleCommandRegistry::new(),
+            toggle_key: config.and_then(|config| config.toggle_key),
+            font: None,
+            font_size: config.map_or(16.0, |config| config.font_size),
+            is_open: false,
+            level_filter: StandardLogLevel::Debug,
+            scroll: 0,
+            anchor: None,
+            input: String::new(),
+            history: Vec::new(),
+            history_index: None,
+            submitted: VecDeque::new(),
+            suppress_character: false,
+            overlay: None,
+            draw_list: Default::default(),
+        }
+    }
+
+    /// Returns `true` if logs are captured and the console can be opened.
+    pub fn is_enabled(&self) -> bool {
+        self.log.is_some()
+    }
+
+    /// The captured logs.
+    pub fn log(&self) -> Option<&LogRingBuffer<StandardLogLevel>> {
+        self.log.as_ref()
+    }
+
+    /// A transport into the captured logs, e.g. for worker threads that cannot reach the engine logger.
+    pub fn log_transport(&self) -> Option<RingBufferTransport<StandardLogLevel>> {
+        self.log.as_ref().map(|log| log.transport())
+    }
+
+    pub fn commands(&self) -> &ConsoleCommandRegistry {
+        &self.commands
+    }
+
+    pub fn commands_mut(&mut self) -> &mut ConsoleCommandRegistry {
+        &mut self.commands
+    }
+
+    pub fn font(&self) -> Option<&FontHandle> {
+        self.font.as_ref()
+    }
+
+    /// Sets the font of the console text. The engine has no font of its own,
+    /// so the console shows an empty panel until one is set.
+    pub fn set_font(&mut self, font: Option<FontHandle>) {
+        self.font = font;
+    }
+
+    pub fn font_size(&self) -> f32 {
+        self.font_size
+    }
+
+    pub fn set_font_size(&mut self, font_size: f32) {
+        self.font_size = font_size;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    /// Opens the console. Does nothing if the console is not enabled.
+    pub fn open(&mut self) {
+        self.is_open = self.is_enabled();
+    }
+
+    pub fn close(&mut self) {
+        self.is_open = false;
+    }
+
+    pub fn toggle(&mut self) {
+        if self.is_open {
+            self.close();
+        } else {
+            self.open();
+        }
+    }
+
+    /// The lowest level shown in the console.
+    pub fn level_filter(&self) -> StandardLogLevel {
+        self.level_filter
+    }
+
+    pub fn set_level_filter(&mut self, level: StandardLogLevel) {
+        self.level_filter = level;
+        self.scroll_to_tail();
+    }
+
+    /// Returns `true` while the view is scrolled up; new records do not move it until it is back at the tail.
+    pub fn is_paused(&self) -> bool {
+        self.anchor.is_some()
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        let log = match &self.log {
+            Some(log) => log,
+            None => return,
+        };
+
+        if self.anchor.is_none() {
+            self.anchor = match log.records().next_back() {
+                Some(record) => Some(record.sequence),
+                None => return,
+            };
+        }
+
+        let max_scroll = self.shown_records().count().saturating_sub(1);
+        self.scroll = (self.scroll + lines).min(max_scroll);
+
+        if self.scroll == 0 {
+            self.anchor = None;
+        }
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+
+        if self.scroll == 0 {
+            self.anchor = None;
+        }
+    }
+
+    /// Resumes following the newest records.
+    pub fn scroll_to_tail(&mut self) {
+        self.scroll = 0;
+        self.anchor = None;
+    }
+
+    /// The text of the input line.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Queues a command line for execution, as if it had been typed in.
+    pub fn submit(&mut self, line: impl Into<String>) {
+        let line = line.into();
+
+        if line.trim().is_empty() {
+            return;
+        }
+
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+
+        self.submitted.push_back(line);
+    }
+
+    /// Takes the next submitted command line.
+    pub fn take_submitted(&mut self) -> Option<String> {
+        self.submitted.pop_front()
+    }
+
+    /// Handles a key, returning `true` if the console consumed it.
+    /// Every key press is consumed while the console is open.
+    pub fn handle_keyboard_input(&mut self, input: &KeyboardInput) -> bool {
+        let is_toggle = self.toggle_key.is_some() && input.virtual_keycode == self.toggle_key;
+
+        if is_toggle && self.is_enabled() {
+            if input.state == ElementState::Pressed {
+                self.toggle();
+                // The character of the toggle key must not end up in the input line.
+                self.suppress_character = true;
+            }
+
+            return true;
+        }
+
+        if !self.is_open {
+            return false;
+        }
+
+        // Releases go through, so that keys held when the console opened do not get stuck.
+        if input.state != ElementState::Pressed {
+            return false;
+        }
+
+        match input.virtual_keycode {
+            Some(VirtualKeyCode::Return) | Some(VirtualKeyCode::NumpadEnter) => {
+                let line = std::mem::take(&mut self.input);
+                self.history_index = None;
+                self.submit(line);
+                self.scroll_to_tail();
+            }
+            Some(VirtualKeyCode::Back) => {
+                self.input.pop();
+            }
+            Some(VirtualKeyCode::Escape) => {
+                if self.input.is_empty() {
+                    self.close();
+                } else {
+                    self.input.clear();
+                    self.history_index = None;
+                }
+            }
+            Some(VirtualKeyCode::Up) => {
+                let index = match self.history_index {
+                    Some(index) => index.saturating_sub(1),
+                    None => match self.history.len().checked_sub(1) {
+                        Some(index) => index,
+                        None => return true,
+                    },
+                };
+                self.history_index = Some(index);
+                self.input = self.history[index].clone();
+            }
+            Some(VirtualKeyCode::Down) => {
+                if let Some(index) = self.history_index {
+                    if index + 1 < self.history.len() {
+                        self.history_index = Some(index + 1);
+                        self.input = self.history[index + 1].clone();
+                    } else {
+                        self.history_index = None;
+                        self.input.clear();
+                    }
+                }
+            }
+            Some(VirtualKeyCode::PageUp) => self.scroll_up(Self::PAGE_LINES),
+            Some(VirtualKeyCode::PageDown) => self.scroll_down(Self::PAGE_LINES),
+            Some(VirtualKeyCode::End) => self.scroll_to_tail(),
+            _ => {}
+        }
+
+        true
+    }
+
+    /// Handles a typed character, returning `true` if the console consumed it.
+    pub fn handle_received_character(&mut self, character: char) -> bool {
+        if std::mem::take(&mut self.suppress_character) {
+            return true;
+        }
+
+        if !self.is_open {
+            return false;
+        }
+
+        if !character.is_control() {
+            self.input.push(character);
+        }
+
+        true
+    }
+
+    /// Takes in the logs sent since the last frame, stamping the next ones with `frame`.
+    pub fn update(&mut self, frame: u64) {
+        self.suppress_character = false;
+
+        if let Some(log) = &mut self.log {
+            log.drain();
+            log.set_frame(frame);
+        }
+    }
+
+    /// The records to show in `rows` lines, from the oldest to the newest,
+    /// following the level filter and the scroll position.
+    pub fn visible_records(&self, rows: usize) -> Vec<&LogRecord<StandardLogLevel>> {
+        let mut records = self
+            .shown_records()
+            .rev()
+            .skip(self.scroll)
+            .take(rows)
+            .collect::<Vec<_>>();
+        records.reverse();
+        records
+    }
+
+    /// Writes `header` and the captured records into `path`, taking in the pending logs first.
+    pub fn write_crash_report(&mut self, path: &Path, header: &str) -> io::Result<()> {
+        let log = match &mut self.log {
+            Some(log) => log,
+            None => return Ok(()),
+        };
+
+        log.drain();
+
+        let mut file = File::create(path)?;
+        writeln!(file, "{}", header)?;
+        log.write_report(&mut file)
+    }
+
+    /// The overlay drawing the console, and whether it has been faded in.
+    pub(crate) fn overlay(&self) -> Option<(OverlayId, bool)> {
+        self.overlay
+    }
+
+    pub(crate) fn set_overlay(&mut self, overlay: OverlayId, is_shown: bool) {
+        self.overlay = Some((overlay, is_shown));
+    }
+
+    pub(crate) fn draw_list(&self) -> &Arc<Mutex<ConsoleDrawList>> {
+        &self.draw_list
+    }
+
+    fn shown_records(&self) -> impl DoubleEndedIterator<Item = &LogRecord<StandardLogLevel>> + '_ {
+        let level_filter = self.level_filter;
+        let anchor = self.anchor.unwrap_or(u64::MAX);
+        self.log
+            .iter()
+            .flat_map(|log| log.records())
+            .filter(move |record| level_filter <= record.level && record.sequence <= anchor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(console: &ConsoleManager, rows: usize) -> Vec<String> {
+        console
+            .visible_records(rows)
+            .iter()
+            .map(|record| record.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn check_scrolling_pauses_the_view() {
+        let mut console = ConsoleManager::new(Some(&ConsoleConfig::default()));
+        let transport = console.log_transport().unwrap();
+
+        for index in 0..6 {
+            let level = if index % 2 == 0 {
+                StandardLogLevel::Info
+            } else {
+                StandardLogLevel::Debug
+            };
+            transport.log(level, None, format!("{}", index));
+        }
+
+        console.update(1);
+        assert_eq!(messages(&console, 2), ["4", "5"]);
+
+        console.set_level_filter(StandardLogLevel::Info);
+        assert_eq!(messages(&console, 2), ["2", "4"]);
+
+        console.scroll_up(1);
+        assert!(console.is_paused());
+        assert_eq!(messages(&console, 2), ["0", "2"]);
+
+        // New records do not move a paused view.
+        transport.log(StandardLogLevel::Info, None, "6");
+        console.update(2);
+        assert_eq!(messages(&console, 2), ["0", "2"]);
+
+        // Scrolling is clamped to the oldest record, and scrolling back down resumes at the tail.
+        console.scroll_up(100);
+        assert_eq!(messages(&console, 2), ["0"]);
+        console.scroll_down(100);
+        assert!(!console.is_paused());
+        assert_eq!(messages(&console, 2), ["4", "6"]);
+    }
+}
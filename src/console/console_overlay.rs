@@ -0,0 +1,502 @@
+use super::ConsoleManager;
+use crate::{
+    gfx::{
+        compute_glyph_layout, BindGroupLayoutCache, CachedBindGroupLayout, Color, GfxContextHandle,
+        GlyphLayoutConfig, GlyphManager, GlyphSprite, OverlayShader,
+    },
+    ui::UISize,
+};
+use fontdue::layout::{HorizontalAlign, VerticalAlign};
+use logging::StandardLogLevel;
+use parking_lot::Mutex;
+use std::{borrow::Cow, mem::size_of, ops::Range, sync::Arc};
+use wgpu::{
+    BindGroup, BindGroupLayoutEntry, BindingType, BlendState, Buffer, BufferAddress,
+    BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites, FragmentState, MultisampleState,
+    PipelineLayout, PipelineLayoutDescriptor, PrimitiveState, Queue, RenderPass, RenderPipeline,
+    RenderPipelineDescriptor, SamplerBindingType, ShaderModule, ShaderModuleDescriptor,
+    ShaderSource, ShaderStages, TextureSampleType, TextureViewDimension, VertexAttribute,
+    VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
+};
+use zerocopy::AsBytes;
+
+/// Priority of the console in the overlay stack, above everything else.
+pub const CONSOLE_OVERLAY_PRIORITY: i32 = i32::MAX;
+
+#[repr(C)]
+#[derive(AsBytes, Debug, Clone, Copy)]
+struct ConsoleVertex {
+    /// In clip space.
+    position: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+struct ConsoleTextBatch {
+    texture_bind_group: Arc<BindGroup>,
+    sampler_bind_group: Arc<BindGroup>,
+    vertices: Vec<ConsoleVertex>,
+}
+
+/// Triangles of the console for the next frame, built on the main thread and drawn by [`ConsoleOverlay`].
+#[derive(Default)]
+pub struct ConsoleDrawList {
+    panel: Vec<ConsoleVertex>,
+    text: Vec<ConsoleTextBatch>,
+}
+
+impl ConsoleDrawList {
+    /// Lays the console out over a surface of `width` by `height` pixels.
+    pub fn build(
+        console: &ConsoleManager,
+        width: f32,
+        height: f32,
+        scale_factor: f32,
+        glyph_mgr: &mut GlyphManager,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) -> Self {
+        let mut builder = ConsoleDrawListBuilder {
+            list: Default::default(),
+            width,
+            height,
+        };
+
+        let font_size = console.font_size() * scale_factor;
+        let line_height = (font_size * 1.25).ceil();
+        let padding = (font_size * 0.5).ceil();
+        let bottom = (height * 0.5).floor();
+        let input_top = bottom + line_height + padding;
+        let rows = (((height - input_top - padding) / line_height).floor() as usize).max(1);
+
+        builder.rect(
+            0.0,
+            bottom,
+            width,
+            height,
+            Color::from_rgba(0.04, 0.05, 0.07, 0.85),
+        );
+        builder.rect(
+            0.0,
+            bottom,
+            width,
+            input_top,
+            Color::from_rgba(0.1, 0.12, 0.16, 0.9),
+        );
+
+        let font = match console.font() {
+            Some(font) => font,
+            None => return builder.list,
+        };
+        let mut text = |text: &str, x: f32, y: f32, color: Color| {
+            let chars = text.chars().collect::<Vec<_>>();
+            let elements = compute_glyph_layout(
+                font,
+                font_size,
+                UISize {
+                    width,
+                    height: font_size,
+                },
+                &GlyphLayoutConfig {
+                    horizontal_align: HorizontalAlign::Left,
+                    vertical_align: VerticalAlign::Bottom,
+                    ..Default::default()
+                },
+                chars.iter().copied(),
+            );
+
+            for (c, element) in chars.iter().zip(elements) {
+                let left = x + element.offset.x;
+
+                if width < left {
+                    break;
+                }
+
+                if c.is_whitespace() {
+                    continue;
+                }
+
+                let sprite = glyph_mgr.glyph(bind_group_layout_cache, font, element.key);
+                builder.glyph(
+                    &sprite,
+                    [left, y + element.offset.y],
+                    [element.size.x, element.size.y],
+                    color,
+                );
+            }
+        };
+
+        let baseline = bottom + padding + (line_height - font_size) * 0.5;
+        let prompt = if console.is_paused() {
+            "[paused] > "
+        } else {
+            "> "
+        };
+        text(
+            &format!("{}{}_", prompt, console.input()),
+            padding,
+            baseline,
+            Color::white(),
+        );
+
+        // Newest records at the bottom; multi-line records take several rows.
+        let mut lines = Vec::with_capacity(rows);
+        'records: for record in console.visible_records(rows).into_iter().rev() {
+            let header = match &record.target {
+                Some(target) => format!("#{} {} {}: ", record.frame, record.level, target),
+                None => format!("#{} {} ", record.frame, record.level),
+            };
+            let mut record_lines = record
+                .message
+                .split('\n')
+                .enumerate()
+                .map(|(index, line)| {
+                    if index == 0 {
+                        format!("{}{}", header, line)
+                    } else {
+                        format!("    {}", line)
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            while let Some(line) = record_lines.pop() {
+                if lines.len() == rows {
+                    break 'records;
+                }
+
+                lines.push((line, level_color(record.level)));
+            }
+        }
+
+        for (index, (line, color)) in lines.iter().enumerate() {
+            text(
+                line,
+                padding,
+                input_top + padding + line_height * index as f32,
+                *color,
+            );
+        }
+
+        builder.list
+    }
+}
+
+struct ConsoleDrawListBuilder {
+    list: ConsoleDrawList,
+    width: f32,
+    height: f32,
+}
+
+impl ConsoleDrawListBuilder {
+    fn to_clip(&self, x: f32, y: f32) -> [f32; 2] {
+        [x / self.width * 2.0 - 1.0, y / self.height * 2.0 - 1.0]
+    }
+
+    fn rect(&mut self, left: f32, bottom: f32, right: f32, top: f32, color: Color) {
+        let min = self.to_clip(left, bottom);
+        let max = self.to_clip(right, top);
+        push_quad(&mut self.list.panel, min, max, [0.0; 2], [0.0; 2], color);
+    }
+
+    fn glyph(&mut self, sprite: &GlyphSprite, position: [f32; 2], size: [f32; 2], color: Color) {
+        let min = self.to_clip(position[0], position[1]);
+        let max = self.to_clip(position[0] + size[0], position[1] + size[1]);
+        let texture_width = sprite.texture().width as f32;
+        let texture_height = sprite.texture().height as f32;
+        let texel_width_half = 0.5 / texture_width;
+        let texel_height_half = 0.5 / texture_height;
+        let mapping = sprite.mapping();
+        let uv_min = [
+            mapping.x_min as f32 / texture_width + texel_width_half,
+            mapping.y_min as f32 / texture_height + texel_height_half,
+        ];
+        let uv_max = [
+            mapping.x_max as f32 / texture_width - texel_width_half,
+            mapping.y_max as f32 / texture_height - texel_height_half,
+        ];
+        let texture_bind_group = sprite.texture_bind_group();
+
+        let batch = match self
+            .list
+            .text
+            .iter_mut()
+            .position(|batch| Arc::ptr_eq(&batch.texture_bind_group, texture_bind_group))
+        {
+            Some(index) => &mut self.list.text[index],
+            None => {
+                self.list.text.push(ConsoleTextBatch {
+                    texture_bind_group: texture_bind_group.clone(),
+                    sampler_bind_group: sprite.sampler_bind_group().clone(),
+                    vertices: Vec::new(),
+                });
+                self.list.text.last_mut().unwrap()
+            }
+        };
+        push_quad(&mut batch.vertices, min, max, uv_min, uv_max, color);
+    }
+}
+
+fn push_quad(
+    vertices: &mut Vec<ConsoleVertex>,
+    min: [f32; 2],
+    max: [f32; 2],
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+    color: Color,
+) {
+    let color = [color.r, color.g, color.b, color.a];
+    // Same winding and uv orientation as the standard ui quad.
+    for (x, y, u, v) in [
+        (min[0], min[1], uv_min[0], uv_min[1]),
+        (max[0], min[1], uv_max[0], uv_min[1]),
+        (max[0], max[1], uv_max[0], uv_max[1]),
+        (min[0], min[1], uv_min[0], uv_min[1]),
+        (max[0], max[1], uv_max[0], uv_max[1]),
+        (min[0], max[1], uv_min[0], uv_max[1]),
+    ] {
+        vertices.push(ConsoleVertex {
+            position: [x, y],
+            uv: [u, v],
+            color,
+        });
+    }
+}
+
+fn level_color(level: StandardLogLevel) -> Color {
+    match level {
+        StandardLogLevel::Debug => Color::from_rgba(0.6, 0.6, 0.6, 1.0),
+        StandardLogLevel::Info => Color::white(),
+        StandardLogLevel::Warning => Color::from_rgba(1.0, 0.85, 0.3, 1.0),
+        StandardLogLevel::Error => Color::from_rgba(1.0, 0.4, 0.4, 1.0),
+        StandardLogLevel::Fatal => Color::from_rgba(1.0, 0.2, 0.6, 1.0),
+    }
+}
+
+/// Draws the [`ConsoleDrawList`] shared with the [`ConsoleManager`] in the overlay pass.
+pub struct ConsoleOverlay {
+    gfx_ctx: GfxContextHandle,
+    draw_list: Arc<Mutex<ConsoleDrawList>>,
+    // Kept alive for the text pipeline; the cache only holds weak references.
+    _texture_bind_group_layout: CachedBindGroupLayout,
+    _sampler_bind_group_layout: CachedBindGroupLayout,
+    panel_pipeline: RenderPipeline,
+    text_pipeline: RenderPipeline,
+    vertex_buffer: Option<Buffer>,
+    panel: Range<u32>,
+    text: Vec<(Arc<BindGroup>, Arc<BindGroup>, Range<u32>)>,
+}
+
+impl ConsoleOverlay {
+    const VERTEX_SIZE: usize = size_of::<ConsoleVertex>();
+
+    pub fn new(
+        gfx_ctx: GfxContextHandle,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+        draw_list: Arc<Mutex<ConsoleDrawList>>,
+    ) -> Self {
+        let device = &gfx_ctx.device;
+        // The same layouts as the glyph textures, so that their bind groups can be used as they are.
+        let texture_bind_group_layout =
+            bind_group_layout_cache.create_layout(vec![BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }]);
+        let sampler_bind_group_layout =
+            bind_group_layout_cache.create_layout(vec![BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            }]);
+
+        let panel_shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("console panel shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "../gfx/built_in_shaders/overlay_color.wgsl"
+            ))),
+        });
+        let text_shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("console text shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "../gfx/built_in_shaders/console_text.wgsl"
+            ))),
+        });
+        let panel_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("console panel pipeline layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let text_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("console text pipeline layout"),
+            bind_group_layouts: &[
+                texture_bind_group_layout.as_ref(),
+                sampler_bind_group_layout.as_ref(),
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |label: &str,
+                               layout: &PipelineLayout,
+                               shader: &ShaderModule,
+                               attributes: &[VertexAttribute]| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(layout),
+                vertex: VertexState {
+                    module: shader,
+                    entry_point: "vs_main",
+                    buffers: &[VertexBufferLayout {
+                        array_stride: Self::VERTEX_SIZE as BufferAddress,
+                        step_mode: VertexStepMode::Vertex,
+                        attributes,
+                    }],
+                },
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                fragment: Some(FragmentState {
+                    module: shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(ColorTargetState {
+                        format: gfx_ctx.plain_surface_format(),
+                        blend: Some(BlendState::ALPHA_BLENDING),
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            })
+        };
+        let position = VertexAttribute {
+            format: VertexFormat::Float32x2,
+            offset: 0,
+            shader_location: 0,
+        };
+        let uv_offset = size_of::<[f32; 2]>() as BufferAddress;
+        let color_offset = size_of::<[f32; 4]>() as BufferAddress;
+        let panel_pipeline = create_pipeline(
+            "console panel pipeline",
+            &panel_pipeline_layout,
+            &panel_shader,
+            &[
+                position,
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: color_offset,
+                    shader_location: 1,
+                },
+            ],
+        );
+        let text_pipeline = create_pipeline(
+            "console text pipeline",
+            &text_pipeline_layout,
+            &text_shader,
+            &[
+                position,
+                VertexAttribute {
+                    format: VertexFormat::Float32x2,
+                    offset: uv_offset,
+                    shader_location: 1,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: color_offset,
+                    shader_location: 2,
+                },
+            ],
+        );
+
+        Self {
+            gfx_ctx,
+            draw_list,
+            _texture_bind_group_layout: texture_bind_group_layout,
+            _sampler_bind_group_layout: sampler_bind_group_layout,
+            panel_pipeline,
+            text_pipeline,
+            vertex_buffer: None,
+            panel: 0..0,
+            text: Vec::new(),
+        }
+    }
+}
+
+impl OverlayShader for ConsoleOverlay {
+    fn prepare(&mut self, queue: &Queue, opacity: f32) {
+        let draw_list = self.draw_list.lock();
+        let mut vertices = Vec::with_capacity(
+            draw_list.panel.len()
+                + draw_list
+                    .text
+                    .iter()
+                    .map(|batch| batch.vertices.len())
+                    .sum::<usize>(),
+        );
+        let mut push = |batch: &[ConsoleVertex]| {
+            let start = vertices.len() as u32;
+            vertices.extend(batch.iter().map(|vertex| {
+                let mut vertex = *vertex;
+                vertex.color[3] *= opacity;
+                vertex
+            }));
+            start..vertices.len() as u32
+        };
+
+        self.panel = push(&draw_list.panel);
+        self.text = Vec::from_iter(draw_list.text.iter().map(|batch| {
+            (
+                batch.texture_bind_group.clone(),
+                batch.sampler_bind_group.clone(),
+                push(&batch.vertices),
+            )
+        }));
+
+        if vertices.is_empty() {
+            return;
+        }
+
+        let size = (Self::VERTEX_SIZE * vertices.len()) as BufferAddress;
+        let is_too_small = match &self.vertex_buffer {
+            Some(buffer) => buffer.size() < size,
+            None => true,
+        };
+
+        if is_too_small {
+            self.vertex_buffer = Some(self.gfx_ctx.device.create_buffer(&BufferDescriptor {
+                label: Some("console vertex buffer"),
+                size: size.next_power_of_two(),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+
+        queue.write_buffer(self.vertex_buffer.as_ref().unwrap(), 0, vertices.as_bytes());
+    }
+
+    fn draw<'r>(&'r self, render_pass: &mut RenderPass<'r>) {
+        let vertex_buffer = match &self.vertex_buffer {
+            Some(vertex_buffer) => vertex_buffer,
+            None => return,
+        };
+
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+
+        if !self.panel.is_empty() {
+            render_pass.set_pipeline(&self.panel_pipeline);
+            render_pass.draw(self.panel.clone(), 0..1);
+        }
+
+        if !self.text.is_empty() {
+            render_pass.set_pipeline(&self.text_pipeline);
+
+            for (texture_bind_group, sampler_bind_group, range) in &self.text {
+                render_pass.set_bind_group(0, texture_bind_group, &[]);
+                render_pass.set_bind_group(1, sampler_bind_group, &[]);
+                render_pass.draw(range.clone(), 0..1);
+            }
+        }
+    }
+}
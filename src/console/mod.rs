@@ -0,0 +1,9 @@
+mod built_in_commands;
+mod console_command;
+mod console_manager;
+mod console_overlay;
+
+pub use built_in_commands::*;
+pub use console_command::*;
+pub use console_manager::*;
+pub use console_overlay::*;
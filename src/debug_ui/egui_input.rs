@@ -0,0 +1,153 @@
+use winit::{
+    event::{ModifiersState, MouseButton, VirtualKeyCode},
+    window::CursorIcon,
+};
+
+/// Points scrolled per line of a mouse wheel.
+pub const EGUI_POINTS_PER_SCROLL_LINE: f32 = 50.0;
+
+pub fn egui_modifiers(modifiers: ModifiersState) -> egui::Modifiers {
+    egui::Modifiers {
+        alt: modifiers.alt(),
+        ctrl: modifiers.ctrl(),
+        shift: modifiers.shift(),
+        mac_cmd: cfg!(target_os = "macos") && modifiers.logo(),
+        command: if cfg!(target_os = "macos") {
+            modifiers.logo()
+        } else {
+            modifiers.ctrl()
+        },
+    }
+}
+
+pub fn egui_pointer_button(button: MouseButton) -> Option<egui::PointerButton> {
+    match button {
+        MouseButton::Left => Some(egui::PointerButton::Primary),
+        MouseButton::Right => Some(egui::PointerButton::Secondary),
+        MouseButton::Middle => Some(egui::PointerButton::Middle),
+        MouseButton::Other(0) => Some(egui::PointerButton::Extra1),
+        MouseButton::Other(1) => Some(egui::PointerButton::Extra2),
+        MouseButton::Other(_) => None,
+    }
+}
+
+pub fn egui_key(key: VirtualKeyCode) -> Option<egui::Key> {
+    use egui::Key;
+
+    Some(match key {
+        VirtualKeyCode::Down => Key::ArrowDown,
+        VirtualKeyCode::Left => Key::ArrowLeft,
+        VirtualKeyCode::Right => Key::ArrowRight,
+        VirtualKeyCode::Up => Key::ArrowUp,
+        VirtualKeyCode::Escape => Key::Escape,
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => Key::Enter,
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::Insert => Key::Insert,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::End => Key::End,
+        VirtualKeyCode::PageUp => Key::PageUp,
+        VirtualKeyCode::PageDown => Key::PageDown,
+        VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => Key::Minus,
+        VirtualKeyCode::Equals | VirtualKeyCode::Plus | VirtualKeyCode::NumpadAdd => {
+            Key::PlusEquals
+        }
+        VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => Key::Num0,
+        VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1 => Key::Num1,
+        VirtualKeyCode::Key2 | VirtualKeyCode::Numpad2 => Key::Num2,
+        VirtualKeyCode::Key3 | VirtualKeyCode::Numpad3 => Key::Num3,
+        VirtualKeyCode::Key4 | VirtualKeyCode::Numpad4 => Key::Num4,
+        VirtualKeyCode::Key5 | VirtualKeyCode::Numpad5 => Key::Num5,
+        VirtualKeyCode::Key6 | VirtualKeyCode::Numpad6 => Key::Num6,
+        VirtualKeyCode::Key7 | VirtualKeyCode::Numpad7 => Key::Num7,
+        VirtualKeyCode::Key8 | VirtualKeyCode::Numpad8 => Key::Num8,
+        VirtualKeyCode::Key9 | VirtualKeyCode::Numpad9 => Key::Num9,
+        VirtualKeyCode::A => Key::A,
+        VirtualKeyCode::B => Key::B,
+        VirtualKeyCode::C => Key::C,
+        VirtualKeyCode::D => Key::D,
+        VirtualKeyCode::E => Key::E,
+        VirtualKeyCode::F => Key::F,
+        VirtualKeyCode::G => Key::G,
+        VirtualKeyCode::H => Key::H,
+        VirtualKeyCode::I => Key::I,
+        VirtualKeyCode::J => Key::J,
+        VirtualKeyCode::K => Key::K,
+        VirtualKeyCode::L => Key::L,
+        VirtualKeyCode::M => Key::M,
+        VirtualKeyCode::N => Key::N,
+        VirtualKeyCode::O => Key::O,
+        VirtualKeyCode::P => Key::P,
+        VirtualKeyCode::Q => Key::Q,
+        VirtualKeyCode::R => Key::R,
+        VirtualKeyCode::S => Key::S,
+        VirtualKeyCode::T => Key::T,
+        VirtualKeyCode::U => Key::U,
+        VirtualKeyCode::V => Key::V,
+        VirtualKeyCode::W => Key::W,
+        VirtualKeyCode::X => Key::X,
+        VirtualKeyCode::Y => Key::Y,
+        VirtualKeyCode::Z => Key::Z,
+        VirtualKeyCode::F1 => Key::F1,
+        VirtualKeyCode::F2 => Key::F2,
+        VirtualKeyCode::F3 => Key::F3,
+        VirtualKeyCode::F4 => Key::F4,
+        VirtualKeyCode::F5 => Key::F5,
+        VirtualKeyCode::F6 => Key::F6,
+        VirtualKeyCode::F7 => Key::F7,
+        VirtualKeyCode::F8 => Key::F8,
+        VirtualKeyCode::F9 => Key::F9,
+        VirtualKeyCode::F10 => Key::F10,
+        VirtualKeyCode::F11 => Key::F11,
+        VirtualKeyCode::F12 => Key::F12,
+        VirtualKeyCode::F13 => Key::F13,
+        VirtualKeyCode::F14 => Key::F14,
+        VirtualKeyCode::F15 => Key::F15,
+        VirtualKeyCode::F16 => Key::F16,
+        VirtualKeyCode::F17 => Key::F17,
+        VirtualKeyCode::F18 => Key::F18,
+        VirtualKeyCode::F19 => Key::F19,
+        VirtualKeyCode::F20 => Key::F20,
+        _ => return None,
+    })
+}
+
+/// The window cursor for an egui cursor, or `None` to hide the cursor.
+pub fn winit_cursor_icon(cursor_icon: egui::CursorIcon) -> Option<CursorIcon> {
+    Some(match cursor_icon {
+        egui::CursorIcon::None => return None,
+        egui::CursorIcon::PointingHand => CursorIcon::Hand,
+        egui::CursorIcon::Text | egui::CursorIcon::VerticalText => CursorIcon::Text,
+        egui::CursorIcon::Grab => CursorIcon::Grab,
+        egui::CursorIcon::Grabbing => CursorIcon::Grabbing,
+        egui::CursorIcon::Move | egui::CursorIcon::AllScroll => CursorIcon::Move,
+        egui::CursorIcon::ResizeHorizontal
+        | egui::CursorIcon::ResizeEast
+        | egui::CursorIcon::ResizeWest
+        | egui::CursorIcon::ResizeColumn => CursorIcon::EwResize,
+        egui::CursorIcon::ResizeVertical
+        | egui::CursorIcon::ResizeNorth
+        | egui::CursorIcon::ResizeSouth
+        | egui::CursorIcon::ResizeRow => CursorIcon::NsResize,
+        egui::CursorIcon::ResizeNeSw
+        | egui::CursorIcon::ResizeNorthEast
+        | egui::CursorIcon::ResizeSouthWest => CursorIcon::NeswResize,
+        egui::CursorIcon::ResizeNwSe
+        | egui::CursorIcon::ResizeNorthWest
+        | egui::CursorIcon::ResizeSouthEast => CursorIcon::NwseResize,
+        egui::CursorIcon::NotAllowed | egui::CursorIcon::NoDrop => CursorIcon::NotAllowed,
+        egui::CursorIcon::Wait => CursorIcon::Wait,
+        egui::CursorIcon::Progress => CursorIcon::Progress,
+        egui::CursorIcon::Crosshair => CursorIcon::Crosshair,
+        egui::CursorIcon::Help => CursorIcon::Help,
+        egui::CursorIcon::ContextMenu => CursorIcon::ContextMenu,
+        egui::CursorIcon::Cell => CursorIcon::Cell,
+        egui::CursorIcon::Alias => CursorIcon::Alias,
+        egui::CursorIcon::Copy => CursorIcon::Copy,
+        egui::CursorIcon::ZoomIn => CursorIcon::ZoomIn,
+        egui::CursorIcon::ZoomOut => CursorIcon::ZoomOut,
+        _ => CursorIcon::Default,
+    })
+}
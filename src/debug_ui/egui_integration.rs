@@ -0,0 +1,415 @@
+use super::{
+    egui_key, egui_modifiers, egui_pointer_button, EguiRenderer, EGUI_POINTS_PER_SCROLL_LINE,
+};
+use crate::gfx::{GfxContextHandle, TextureHandle};
+use std::time::{Duration, Instant};
+use wgpu::{CommandEncoder, TextureView};
+use winit::event::{ElementState, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+/// Identifies a UI closure registered into an [`EguiIntegration`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EguiUiId(u64);
+
+type EguiUi = (EguiUiId, Box<dyn FnMut(&egui::Context)>);
+
+/// Runs egui once per frame with the window input, and draws it over everything else.
+///
+/// Window events go through [`handle_window_event`](Self::handle_window_event) first; the ones egui
+/// consumes, e.g. clicks on a window or typing into a text field, are not seen by the game input.
+/// A headless integration runs the UI closures but draws nothing, which is enough for logic tests.
+pub struct EguiIntegration {
+    egui_ctx: egui::Context,
+    renderer: Option<EguiRenderer>,
+    uis: Vec<EguiUi>,
+    next_ui_id: u64,
+    removed_uis: Vec<EguiUiId>,
+    events: Vec<egui::Event>,
+    modifiers: egui::Modifiers,
+    pointer_position: Option<egui::Pos2>,
+    pixels_per_point: f32,
+    is_focused: bool,
+    is_paste_requested: bool,
+    needs_repaint: bool,
+    start_time: Instant,
+    output: Option<egui::FullOutput>,
+    cursor_icon: egui::CursorIcon,
+}
+
+impl EguiIntegration {
+    pub fn new(gfx_ctx: GfxContextHandle) -> Self {
+        Self::with_renderer(Some(EguiRenderer::new(gfx_ctx)))
+    }
+
+    /// An integration that runs the UI closures without drawing them.
+    pub fn headless() -> Self {
+        Self::with_renderer(None)
+    }
+
+    fn with_renderer(renderer: Option<EguiRenderer>) -> Self {
+        Self {
+            egui_ctx: egui::Context::default(),
+            renderer,
+            uis: Vec::new(),
+            next_ui_id: 0,
+            removed_uis: Vec::new(),
+            events: Vec::new(),
+            modifiers: egui::Modifiers::default(),
+            pointer_position: None,
+            pixels_per_point: 1.0,
+            is_focused: true,
+            is_paste_requested: false,
+            needs_repaint: true,
+            start_time: Instant::now(),
+            output: None,
+            cursor_icon: egui::CursorIcon::Default,
+        }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.renderer.is_none()
+    }
+
+    pub fn egui_ctx(&self) -> &egui::Context {
+        &self.egui_ctx
+    }
+
+    /// Registers a closure building the UI every frame.
+    pub fn add_ui(&mut self, ui: impl FnMut(&egui::Context) + 'static) -> EguiUiId {
+        let id = EguiUiId(self.next_ui_id);
+        self.next_ui_id += 1;
+        self.uis.push((id, Box::new(ui)));
+        self.needs_repaint = true;
+        id
+    }
+
+    pub fn remove_ui(&mut self, id: EguiUiId) {
+        let count = self.uis.len();
+        self.uis.retain(|(ui_id, _)| *ui_id != id);
+
+        // The closure may be running right now; it is dropped when it is put back.
+        if self.uis.len() == count {
+            self.removed_uis.push(id);
+        }
+
+        self.needs_repaint = true;
+    }
+
+    /// Returns `true` if egui used the pointer during the last frame, e.g. it hovers or drags a window.
+    pub fn wants_pointer_input(&self) -> bool {
+        self.egui_ctx.wants_pointer_input()
+    }
+
+    /// Returns `true` if egui used the keyboard during the last frame, e.g. a text field has the focus.
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.egui_ctx.wants_keyboard_input()
+    }
+
+    /// Returns `true` if egui has to run again, e.g. to animate or to react to new input.
+    pub fn needs_repaint(&self) -> bool {
+        self.needs_repaint
+    }
+
+    /// Returns `true` if a paste shortcut has been pressed since the last frame;
+    /// the clipboard text is passed to [`begin_frame`](Self::begin_frame).
+    pub fn is_paste_requested(&self) -> bool {
+        self.is_paste_requested
+    }
+
+    /// Queues an input event for the next frame, e.g. to simulate input in tests.
+    pub fn push_event(&mut self, event: egui::Event) {
+        self.events.push(event);
+        self.needs_repaint = true;
+    }
+
+    /// Translates the window event into egui input, returning `true` if egui consumed it.
+    pub fn handle_window_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = egui::pos2(
+                    position.x as f32 / self.pixels_per_point,
+                    position.y as f32 / self.pixels_per_point,
+                );
+                self.pointer_position = Some(position);
+                self.push_event(egui::Event::PointerMoved(position));
+                self.wants_pointer_input()
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.pointer_position = None;
+                self.push_event(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let (pos, button) = match (self.pointer_position, egui_pointer_button(*button)) {
+                    (Some(pos), Some(button)) => (pos, button),
+                    _ => return false,
+                };
+                self.push_event(egui::Event::PointerButton {
+                    pos,
+                    button,
+                    pressed: *state == ElementState::Pressed,
+                    modifiers: self.modifiers,
+                });
+                self.wants_pointer_input()
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => {
+                        egui::vec2(*x, *y) * EGUI_POINTS_PER_SCROLL_LINE
+                    }
+                    MouseScrollDelta::PixelDelta(delta) => {
+                        egui::vec2(delta.x as f32, delta.y as f32) / self.pixels_per_point
+                    }
+                };
+
+                if self.modifiers.command {
+                    self.push_event(egui::Event::Zoom((delta.y / 200.0).exp()));
+                } else {
+                    self.push_event(egui::Event::Scroll(delta));
+                }
+
+                self.wants_pointer_input()
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = egui_modifiers(*modifiers);
+                false
+            }
+            WindowEvent::KeyboardInput { input, .. } => {
+                let keycode = match input.virtual_keycode {
+                    Some(keycode) => keycode,
+                    None => return self.wants_keyboard_input(),
+                };
+                let pressed = input.state == ElementState::Pressed;
+
+                if pressed && self.modifiers.command {
+                    match keycode {
+                        VirtualKeyCode::C => self.push_event(egui::Event::Copy),
+                        VirtualKeyCode::X => self.push_event(egui::Event::Cut),
+                        VirtualKeyCode::V => self.is_paste_requested = true,
+                        _ => {}
+                    }
+                }
+
+                if let Some(key) = egui_key(keycode) {
+                    self.push_event(egui::Event::Key {
+                        key,
+                        pressed,
+                        repeat: false,
+                        modifiers: self.modifiers,
+                    });
+                }
+
+                self.wants_keyboard_input()
+            }
+            WindowEvent::ReceivedCharacter(character) => {
+                // Shortcuts also send characters, which must not be typed in.
+                let is_shortcut = self.modifiers.ctrl || self.modifiers.mac_cmd;
+
+                if !character.is_control() && !is_shortcut {
+                    self.push_event(egui::Event::Text(character.to_string()));
+                }
+
+                self.wants_keyboard_input()
+            }
+            WindowEvent::Focused(is_focused) => {
+                self.is_focused = *is_focused;
+                self.needs_repaint = true;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs the UI closures for a surface of the physical size, keeping the output for [`encode`](Self::encode).
+    /// Returns what egui asks of the platform, e.g. the cursor icon or the text to copy.
+    ///
+    /// The integration is not borrowed by the closures, so they can be run through
+    /// [`begin_frame`](Self::begin_frame) and [`end_frame`](Self::end_frame) instead while the integration
+    /// sits in a `RefCell`.
+    pub fn run(
+        &mut self,
+        physical_width: u32,
+        physical_height: u32,
+        scale_factor: f64,
+        clipboard_text: Option<String>,
+    ) -> egui::PlatformOutput {
+        let frame = self.begin_frame(
+            physical_width,
+            physical_height,
+            scale_factor,
+            clipboard_text,
+        );
+        let output = frame.run();
+        self.end_frame(output)
+    }
+
+    /// Takes the input and the UI closures out for running them; see [`EguiFrame::run`].
+    pub fn begin_frame(
+        &mut self,
+        physical_width: u32,
+        physical_height: u32,
+        scale_factor: f64,
+        clipboard_text: Option<String>,
+    ) -> EguiFrame {
+        self.pixels_per_point = scale_factor as f32;
+
+        if std::mem::take(&mut self.is_paste_requested) {
+            if let Some(text) = clipboard_text {
+                self.events.push(egui::Event::Paste(text));
+            }
+        }
+
+        let raw_input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(physical_width as f32, physical_height as f32) / self.pixels_per_point,
+            )),
+            pixels_per_point: Some(self.pixels_per_point),
+            time: Some(self.start_time.elapsed().as_secs_f64()),
+            modifiers: self.modifiers,
+            events: std::mem::take(&mut self.events),
+            focused: self.is_focused,
+            ..Default::default()
+        };
+
+        EguiFrame {
+            egui_ctx: self.egui_ctx.clone(),
+            raw_input,
+            uis: std::mem::take(&mut self.uis),
+            output: None,
+        }
+    }
+
+    /// Puts the UI closures back and keeps the output of the frame for drawing.
+    pub fn end_frame(&mut self, mut frame: EguiFrame) -> egui::PlatformOutput {
+        // Closures registered while the frame ran come after the ones that ran.
+        frame.uis.append(&mut self.uis);
+        frame.uis.retain(|(id, _)| !self.removed_uis.contains(id));
+        self.uis = frame.uis;
+        self.removed_uis.clear();
+
+        let mut output = match frame.output {
+            Some(output) => output,
+            None => return Default::default(),
+        };
+        let platform_output = std::mem::take(&mut output.platform_output);
+        self.needs_repaint = output.repaint_after == Duration::ZERO;
+
+        if self.renderer.is_some() {
+            // Texture updates must not be lost when a frame is not drawn.
+            if let Some(previous) = self.output.take() {
+                let mut textures_delta = previous.textures_delta;
+                textures_delta.append(std::mem::take(&mut output.textures_delta));
+                output.textures_delta = textures_delta;
+            }
+
+            self.output = Some(output);
+        }
+
+        platform_output
+    }
+
+    /// Draws the output of the last frame over `target`.
+    pub fn encode(&mut self, encoder: &mut CommandEncoder, target: &TextureView) {
+        let (renderer, output) = match (&mut self.renderer, self.output.take()) {
+            (Some(renderer), Some(output)) => (renderer, output),
+            _ => return,
+        };
+
+        let primitives = self.egui_ctx.tessellate(output.shapes);
+        renderer.encode(
+            encoder,
+            target,
+            &output.textures_delta,
+            &primitives,
+            self.pixels_per_point,
+        );
+    }
+
+    /// Returns the cursor icon if it differs from the last one, so that the window cursor is only set on changes.
+    pub fn update_cursor_icon(
+        &mut self,
+        cursor_icon: egui::CursorIcon,
+    ) -> Option<egui::CursorIcon> {
+        if std::mem::replace(&mut self.cursor_icon, cursor_icon) == cursor_icon {
+            None
+        } else {
+            Some(cursor_icon)
+        }
+    }
+
+    /// Makes an engine texture usable in egui, e.g. with `egui::Image`. Returns `None` if headless.
+    pub fn register_texture(&mut self, texture: &TextureHandle) -> Option<egui::TextureId> {
+        self.renderer
+            .as_mut()
+            .map(|renderer| renderer.register_texture(texture))
+    }
+
+    pub fn unregister_texture(&mut self, id: egui::TextureId) {
+        if let Some(renderer) = &mut self.renderer {
+            renderer.unregister_texture(id);
+        }
+    }
+}
+
+/// A frame of egui taken out of an [`EguiIntegration`].
+pub struct EguiFrame {
+    egui_ctx: egui::Context,
+    raw_input: egui::RawInput,
+    uis: Vec<EguiUi>,
+    output: Option<egui::FullOutput>,
+}
+
+impl EguiFrame {
+    /// Runs the UI closures.
+    pub fn run(mut self) -> Self {
+        let raw_input = std::mem::take(&mut self.raw_input);
+        let uis = &mut self.uis;
+        let output = self.egui_ctx.run(raw_input, |egui_ctx| {
+            for (_, ui) in uis.iter_mut() {
+                ui(egui_ctx);
+            }
+        });
+        self.output = Some(output);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+
+    #[test]
+    fn check_headless_integration_runs_the_ui() {
+        let mut integration = EguiIntegration::headless();
+        let clicks = Rc::new(Cell::new(0));
+        let ui_clicks = clicks.clone();
+        integration.add_ui(move |egui_ctx| {
+            egui::CentralPanel::default().show(egui_ctx, |ui| {
+                if ui.button("click").clicked() {
+                    ui_clicks.set(ui_clicks.get() + 1);
+                }
+            });
+        });
+
+        integration.run(800, 600, 2.0, None);
+        assert_eq!(clicks.get(), 0);
+
+        // Clicks at a point inside the button; the central panel puts it at its top left.
+        let pos = egui::pos2(16.0, 14.0);
+        integration.push_event(egui::Event::PointerMoved(pos));
+
+        for pressed in [true, false] {
+            integration.push_event(egui::Event::PointerButton {
+                pos,
+                button: egui::PointerButton::Primary,
+                pressed,
+                modifiers: Default::default(),
+            });
+            integration.run(800, 600, 2.0, None);
+        }
+
+        assert_eq!(clicks.get(), 1);
+        assert!(integration.is_headless());
+    }
+}
@@ -0,0 +1,506 @@
+use crate::gfx::{GfxContextHandle, TextureHandle};
+use std::{borrow::Cow, collections::HashMap, mem::size_of, ops::Range};
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent,
+    BlendFactor, BlendOperation, BlendState, Buffer, BufferAddress, BufferBindingType,
+    BufferDescriptor, BufferSize, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder,
+    Extent3d, FilterMode, FragmentState, ImageCopyTexture, ImageDataLayout, IndexFormat, LoadOp,
+    MultisampleState, Operations, Origin3d, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    Sampler, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsages, TextureView, TextureViewDimension, VertexAttribute,
+    VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
+};
+use zerocopy::AsBytes;
+
+#[repr(C)]
+#[derive(AsBytes, Debug, Clone, Copy)]
+struct EguiVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    /// Premultiplied sRGB.
+    color: [u8; 4],
+}
+
+struct EguiTexture {
+    /// `None` for the engine textures, which are not owned by egui.
+    texture: Option<wgpu::Texture>,
+    bind_group: BindGroup,
+}
+
+struct EguiDraw {
+    texture_id: egui::TextureId,
+    indices: Range<u32>,
+    base_vertex: i32,
+    /// `x, y, width, height` in physical pixels.
+    scissor: [u32; 4],
+}
+
+/// Draws the tessellated egui meshes in a pass of their own.
+pub struct EguiRenderer {
+    gfx_ctx: GfxContextHandle,
+    format: TextureFormat,
+    pipeline: RenderPipeline,
+    uniform_buffer: Buffer,
+    uniform_bind_group_layout: BindGroupLayout,
+    uniform_bind_group: BindGroup,
+    texture_bind_group_layout: BindGroupLayout,
+    textures: HashMap<egui::TextureId, EguiTexture>,
+    next_user_texture_id: u64,
+    vertex_buffer: Option<Buffer>,
+    index_buffer: Option<Buffer>,
+}
+
+impl EguiRenderer {
+    pub fn new(gfx_ctx: GfxContextHandle) -> Self {
+        let device = &gfx_ctx.device;
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("egui uniform bind group layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<[f32; 4]>() as u64),
+                    },
+                    count: None,
+                }],
+            });
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("egui texture bind group layout"),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: true },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("egui uniform buffer"),
+            size: size_of::<[f32; 4]>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("egui uniform bind group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let format = gfx_ctx.plain_surface_format();
+        let pipeline = create_pipeline(
+            &gfx_ctx,
+            format,
+            &uniform_bind_group_layout,
+            &texture_bind_group_layout,
+        );
+
+        Self {
+            gfx_ctx,
+            format,
+            pipeline,
+            uniform_buffer,
+            uniform_bind_group_layout,
+            uniform_bind_group,
+            texture_bind_group_layout,
+            textures: HashMap::new(),
+            next_user_texture_id: 0,
+            vertex_buffer: None,
+            index_buffer: None,
+        }
+    }
+
+    /// Binds an engine texture for egui. sRGB textures show their true colors.
+    pub fn register_texture(&mut self, texture: &TextureHandle) -> egui::TextureId {
+        let id = egui::TextureId::User(self.next_user_texture_id);
+        self.next_user_texture_id += 1;
+
+        let bind_group = self.create_texture_bind_group(&texture.view, &texture.sampler);
+        self.textures.insert(
+            id,
+            EguiTexture {
+                texture: None,
+                bind_group,
+            },
+        );
+        id
+    }
+
+    pub fn unregister_texture(&mut self, id: egui::TextureId) {
+        if let egui::TextureId::User(_) = id {
+            self.textures.remove(&id);
+        }
+    }
+
+    /// Applies the texture changes and draws the primitives over `target`.
+    /// The surface may have been resized or reconfigured since the last frame; the scissor rectangles
+    /// are clamped to its current size, and the pipeline follows its format.
+    pub fn encode(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        textures_delta: &egui::TexturesDelta,
+        primitives: &[egui::ClippedPrimitive],
+        pixels_per_point: f32,
+    ) {
+        for (id, delta) in &textures_delta.set {
+            self.update_texture(*id, delta);
+        }
+
+        let (width, height) = {
+            let surface_config = self.gfx_ctx.surface_config.read();
+            (surface_config.width, surface_config.height)
+        };
+        let format = self.gfx_ctx.plain_surface_format();
+
+        if format != self.format {
+            self.recreate_pipeline(format);
+        }
+
+        let draws = self.upload(primitives, width, height, pixels_per_point);
+
+        if !draws.is_empty() {
+            let screen_size = [
+                width as f32 / pixels_per_point,
+                height as f32 / pixels_per_point,
+                0.0,
+                0.0,
+            ];
+            self.gfx_ctx
+                .queue
+                .write_buffer(&self.uniform_buffer, 0, screen_size.as_bytes());
+
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("egui pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.as_ref().unwrap().slice(..));
+            render_pass.set_index_buffer(
+                self.index_buffer.as_ref().unwrap().slice(..),
+                IndexFormat::Uint32,
+            );
+
+            for draw in &draws {
+                let texture = match self.textures.get(&draw.texture_id) {
+                    Some(texture) => texture,
+                    None => continue,
+                };
+                let [x, y, width, height] = draw.scissor;
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.set_bind_group(1, &texture.bind_group, &[]);
+                render_pass.draw_indexed(draw.indices.clone(), draw.base_vertex, 0..1);
+            }
+        }
+
+        // Freed after drawing, since this frame may still have used them.
+        for id in &textures_delta.free {
+            self.textures.remove(id);
+        }
+    }
+
+    /// Writes the meshes into the vertex and index buffers, returning what to draw.
+    fn upload(
+        &mut self,
+        primitives: &[egui::ClippedPrimitive],
+        width: u32,
+        height: u32,
+        pixels_per_point: f32,
+    ) -> Vec<EguiDraw> {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut draws = Vec::new();
+
+        for primitive in primitives {
+            let mesh = match &primitive.primitive {
+                egui::epaint::Primitive::Mesh(mesh) => mesh,
+                // Paint callbacks need a backend of their own.
+                egui::epaint::Primitive::Callback(_) => continue,
+            };
+
+            // The clip rectangle is in points; the scissor rectangle in pixels, inside the target.
+            let clip = primitive.clip_rect;
+            let min_x = (clip.min.x * pixels_per_point)
+                .round()
+                .clamp(0.0, width as f32) as u32;
+            let min_y = (clip.min.y * pixels_per_point)
+                .round()
+                .clamp(0.0, height as f32) as u32;
+            let max_x = (clip.max.x * pixels_per_point)
+                .round()
+                .clamp(0.0, width as f32) as u32;
+            let max_y = (clip.max.y * pixels_per_point)
+                .round()
+                .clamp(0.0, height as f32) as u32;
+
+            if mesh.indices.is_empty() || max_x <= min_x || max_y <= min_y {
+                continue;
+            }
+
+            let first_index = indices.len() as u32;
+            draws.push(EguiDraw {
+                texture_id: mesh.texture_id,
+                indices: first_index..first_index + mesh.indices.len() as u32,
+                base_vertex: vertices.len() as i32,
+                scissor: [min_x, min_y, max_x - min_x, max_y - min_y],
+            });
+            indices.extend_from_slice(&mesh.indices);
+            vertices.extend(mesh.vertices.iter().map(|vertex| EguiVertex {
+                position: [vertex.pos.x, vertex.pos.y],
+                uv: [vertex.uv.x, vertex.uv.y],
+                color: vertex.color.to_array(),
+            }));
+        }
+
+        if draws.is_empty() {
+            return draws;
+        }
+
+        let device = &self.gfx_ctx.device;
+        let queue = &self.gfx_ctx.queue;
+        let vertex_bytes = vertices.as_bytes();
+        let index_bytes = indices.as_bytes();
+
+        if self.vertex_buffer.as_ref().map_or(true, |buffer| {
+            buffer.size() < vertex_bytes.len() as BufferAddress
+        }) {
+            self.vertex_buffer = Some(device.create_buffer(&BufferDescriptor {
+                label: Some("egui vertex buffer"),
+                size: (vertex_bytes.len() as BufferAddress).next_power_of_two(),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+
+        if self.index_buffer.as_ref().map_or(true, |buffer| {
+            buffer.size() < index_bytes.len() as BufferAddress
+        }) {
+            self.index_buffer = Some(device.create_buffer(&BufferDescriptor {
+                label: Some("egui index buffer"),
+                size: (index_bytes.len() as BufferAddress).next_power_of_two(),
+                usage: BufferUsages::INDEX | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+
+        queue.write_buffer(self.vertex_buffer.as_ref().unwrap(), 0, vertex_bytes);
+        queue.write_buffer(self.index_buffer.as_ref().unwrap(), 0, index_bytes);
+        draws
+    }
+
+    fn update_texture(&mut self, id: egui::TextureId, delta: &egui::epaint::ImageDelta) {
+        let [width, height] = delta.image.size();
+        let pixels = match &delta.image {
+            egui::ImageData::Color(image) => image
+                .pixels
+                .iter()
+                .flat_map(|pixel| pixel.to_array())
+                .collect::<Vec<_>>(),
+            egui::ImageData::Font(image) => image
+                .srgba_pixels(None)
+                .flat_map(|pixel| pixel.to_array())
+                .collect::<Vec<_>>(),
+        };
+        let size = Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        };
+
+        let origin = match (delta.pos, self.textures.get(&id)) {
+            // A partial update of a texture created earlier, e.g. new glyphs in the font atlas.
+            (Some([x, y]), Some(texture)) if texture.texture.is_some() => Origin3d {
+                x: x as u32,
+                y: y as u32,
+                z: 0,
+            },
+            (Some(_), _) => return,
+            (None, _) => {
+                let texture = self.gfx_ctx.device.create_texture(&TextureDescriptor {
+                    label: Some("egui texture"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: TextureFormat::Rgba8UnormSrgb,
+                    usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                    view_formats: &[],
+                });
+                let view = texture.create_view(&Default::default());
+                let filter = |filter: egui::TextureFilter| match filter {
+                    egui::TextureFilter::Nearest => FilterMode::Nearest,
+                    egui::TextureFilter::Linear => FilterMode::Linear,
+                };
+                let sampler = self.gfx_ctx.device.create_sampler(&SamplerDescriptor {
+                    label: Some("egui sampler"),
+                    address_mode_u: AddressMode::ClampToEdge,
+                    address_mode_v: AddressMode::ClampToEdge,
+                    address_mode_w: AddressMode::ClampToEdge,
+                    mag_filter: filter(delta.options.magnification),
+                    min_filter: filter(delta.options.minification),
+                    ..Default::default()
+                });
+                let bind_group = self.create_texture_bind_group(&view, &sampler);
+                self.textures.insert(
+                    id,
+                    EguiTexture {
+                        texture: Some(texture),
+                        bind_group,
+                    },
+                );
+                Origin3d::ZERO
+            }
+        };
+
+        self.gfx_ctx.queue.write_texture(
+            ImageCopyTexture {
+                texture: self.textures[&id].texture.as_ref().unwrap(),
+                mip_level: 0,
+                origin,
+                aspect: TextureAspect::All,
+            },
+            &pixels,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width as u32),
+                rows_per_image: Some(height as u32),
+            },
+            size,
+        );
+    }
+
+    fn create_texture_bind_group(&self, view: &TextureView, sampler: &Sampler) -> BindGroup {
+        self.gfx_ctx.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("egui texture bind group"),
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    fn recreate_pipeline(&mut self, format: TextureFormat) {
+        self.pipeline = create_pipeline(
+            &self.gfx_ctx,
+            format,
+            &self.uniform_bind_group_layout,
+            &self.texture_bind_group_layout,
+        );
+        self.format = format;
+    }
+}
+
+fn create_pipeline(
+    gfx_ctx: &GfxContextHandle,
+    format: TextureFormat,
+    uniform_bind_group_layout: &BindGroupLayout,
+    texture_bind_group_layout: &BindGroupLayout,
+) -> RenderPipeline {
+    let device = &gfx_ctx.device;
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some("egui shader"),
+        source: ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+            "../gfx/built_in_shaders/egui.wgsl"
+        ))),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("egui pipeline layout"),
+        bind_group_layouts: &[uniform_bind_group_layout, texture_bind_group_layout],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("egui pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[VertexBufferLayout {
+                array_stride: size_of::<EguiVertex>() as BufferAddress,
+                step_mode: VertexStepMode::Vertex,
+                attributes: &[
+                    VertexAttribute {
+                        format: VertexFormat::Float32x2,
+                        offset: 0,
+                        shader_location: 0,
+                    },
+                    VertexAttribute {
+                        format: VertexFormat::Float32x2,
+                        offset: size_of::<[f32; 2]>() as BufferAddress,
+                        shader_location: 1,
+                    },
+                    VertexAttribute {
+                        format: VertexFormat::Unorm8x4,
+                        offset: size_of::<[f32; 4]>() as BufferAddress,
+                        shader_location: 2,
+                    },
+                ],
+            }],
+        },
+        primitive: PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        fragment: Some(FragmentState {
+            module: &shader,
+            // egui blends in gamma space; a linear target needs the colors converted first.
+            entry_point: if format.is_srgb() {
+                "fs_main_linear"
+            } else {
+                "fs_main_gamma"
+            },
+            targets: &[Some(ColorTargetState {
+                format,
+                // egui colors are premultiplied.
+                blend: Some(BlendState {
+                    color: BlendComponent {
+                        src_factor: BlendFactor::One,
+                        dst_factor: BlendFactor::OneMinusSrcAlpha,
+                        operation: BlendOperation::Add,
+                    },
+                    alpha: BlendComponent {
+                        src_factor: BlendFactor::OneMinusDstAlpha,
+                        dst_factor: BlendFactor::One,
+                        operation: BlendOperation::Add,
+                    },
+                }),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        multiview: None,
+    })
+}
@@ -0,0 +1,9 @@
+//! Immediate-mode debug UI built on egui. Only available with the `egui` feature.
+
+mod egui_input;
+mod egui_integration;
+mod egui_renderer;
+
+pub use egui_input::*;
+pub use egui_integration::*;
+pub use egui_renderer::*;
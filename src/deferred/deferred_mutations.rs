@@ -0,0 +1,374 @@
+use super::FramePhase;
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Display,
+    mem::take,
+};
+
+/// The kind of a structural change, kept for the logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MutationKind {
+    /// Swapping the shader of a material, which rebuilds all of its bind groups.
+    MaterialShader,
+    /// Rebinding a resource of a material, which rebuilds one of its bind groups.
+    MaterialBinding,
+    /// Replacing an asset, e.g. after a hot reload.
+    AssetReplacement,
+    /// Removing an object and its children.
+    ObjectRemoval,
+}
+
+impl Display for MutationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MaterialShader => write!(f, "material shader swap"),
+            Self::MaterialBinding => write!(f, "material rebind"),
+            Self::AssetReplacement => write!(f, "asset replacement"),
+            Self::ObjectRemoval => write!(f, "object removal"),
+        }
+    }
+}
+
+struct DeferredMutation {
+    kind: MutationKind,
+    apply: Box<dyn FnOnce()>,
+}
+
+/// Queues the structural changes requested during a restricted [`FramePhase`], until they are applied at the start
+/// of the next frame. See the [module](super) for which changes are structural.
+#[derive(Default)]
+pub struct DeferredMutations {
+    phase: Cell<FramePhase>,
+    queue: RefCell<Vec<DeferredMutation>>,
+}
+
+impl DeferredMutations {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn phase(&self) -> FramePhase {
+        self.phase.get()
+    }
+
+    /// Marks the start of a phase, returning the previous one.
+    pub fn enter_phase(&self, phase: FramePhase) -> FramePhase {
+        self.phase.replace(phase)
+    }
+
+    /// Returns `true` if structural changes requested now are deferred.
+    pub fn is_restricted(&self) -> bool {
+        self.phase.get().is_restricted()
+    }
+
+    /// Returns the number of changes waiting for the next frame.
+    pub fn len(&self) -> usize {
+        self.queue.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.borrow().is_empty()
+    }
+
+    /// Returns the kinds of the changes waiting for the next frame, in the order they will be applied.
+    pub fn pending_kinds(&self) -> Vec<MutationKind> {
+        Vec::from_iter(self.queue.borrow().iter().map(|mutation| mutation.kind))
+    }
+
+    /// Queues a change for the next frame, whatever the current phase is.
+    pub fn defer(&self, kind: MutationKind, mutation: impl FnOnce() + 'static) {
+        self.queue.borrow_mut().push(DeferredMutation {
+            kind,
+            apply: Box::new(mutation),
+        });
+    }
+
+    /// Applies a change at once outside of the restricted phases. Otherwise queues it and returns `false`.
+    pub fn run_or_defer(&self, kind: MutationKind, mutation: impl FnOnce() + 'static) -> bool {
+        if self.is_restricted() {
+            self.defer(kind, mutation);
+            return false;
+        }
+
+        mutation();
+        true
+    }
+
+    /// Applies the queued changes in the order they have been requested, returning how many were applied.
+    /// The engine calls it at the start of every frame; tests may call it to apply the changes on the spot.
+    /// Changes queued while flushing wait for the next flush.
+    pub fn flush_now(&self) -> usize {
+        // Taken out, so that the changes may queue new ones.
+        let queue = take(&mut *self.queue.borrow_mut());
+        let count = queue.len();
+        let phase = self.enter_phase(FramePhase::Idle);
+
+        for mutation in queue {
+            (mutation.apply)();
+        }
+
+        self.enter_phase(phase);
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        create_test_context,
+        event::{event_types, EventHandler},
+        gfx::{
+            BindGroupEntryResource, BindingPropKey, Material, MaterialHandle, BUILT_IN_SHADER_LIT,
+            BUILT_IN_SHADER_STANDARD_PBR, PBR_SAMPLER_NAME,
+        },
+        prefab::{Prefab, PrefabError, PrefabInstanceNode, PrefabObject},
+        ContextHandle,
+    };
+    use asset::AssetKey;
+    use specs::Builder;
+    use std::{rc::Rc, sync::Arc};
+    use wgpu::{Sampler, SamplerDescriptor};
+
+    #[test]
+    fn check_deferred_mutations_run_at_once_outside_restricted_phases() {
+        let mutations = DeferredMutations::new();
+        let applied = Rc::new(Cell::new(0));
+
+        for phase in [FramePhase::Idle, FramePhase::Input] {
+            mutations.enter_phase(phase);
+            let applied = applied.clone();
+            assert!(
+                mutations.run_or_defer(MutationKind::ObjectRemoval, move || {
+                    applied.set(applied.get() + 1)
+                })
+            );
+        }
+
+        assert_eq!(applied.get(), 2);
+        assert!(mutations.is_empty());
+    }
+
+    #[test]
+    fn check_deferred_mutations_apply_in_request_order() {
+        let mutations = DeferredMutations::new();
+        let order = Rc::new(RefCell::new(Vec::new()));
+        mutations.enter_phase(FramePhase::Update);
+
+        for (index, kind) in [MutationKind::MaterialShader, MutationKind::ObjectRemoval]
+            .into_iter()
+            .enumerate()
+        {
+            let order = order.clone();
+            assert!(!mutations.run_or_defer(kind, move || order.borrow_mut().push(index)));
+        }
+
+        assert_eq!(
+            mutations.pending_kinds(),
+            vec![MutationKind::MaterialShader, MutationKind::ObjectRemoval]
+        );
+        assert!(order.borrow().is_empty());
+
+        assert_eq!(mutations.flush_now(), 2);
+        assert_eq!(*order.borrow(), vec![0, 1]);
+        assert_eq!(mutations.phase(), FramePhase::Update);
+    }
+
+    #[test]
+    fn check_deferred_mutations_queued_while_flushing_wait_for_next_flush() {
+        let mutations = Rc::new(DeferredMutations::new());
+        let applied = Rc::new(Cell::new(false));
+
+        {
+            let inner = mutations.clone();
+            let applied = applied.clone();
+            mutations.defer(MutationKind::AssetReplacement, move || {
+                inner.defer(MutationKind::ObjectRemoval, move || applied.set(true));
+            });
+        }
+
+        assert_eq!(mutations.flush_now(), 1);
+        assert!(!applied.get());
+        assert_eq!(mutations.flush_now(), 1);
+        assert!(applied.get());
+    }
+
+    fn pbr_material(ctx: &ContextHandle) -> MaterialHandle {
+        let shader = ctx
+            .built_in_shader_mgr()
+            .find_shader(BUILT_IN_SHADER_STANDARD_PBR)
+            .unwrap();
+        MaterialHandle::new(Material::new(
+            shader,
+            ctx.render_mgr_mut().pipeline_layout_cache(),
+        ))
+    }
+
+    fn bound_sampler(material: &MaterialHandle, key: &BindingPropKey) -> Option<Arc<Sampler>> {
+        let material = material.read();
+        let index = material.bind_properties[key];
+        match &material.bind_group_holders[index.group_index].entries[index.entry_index].resource {
+            Some(BindGroupEntryResource::Sampler { sampler }) => Some(sampler.clone()),
+            _ => None,
+        }
+    }
+
+    fn enemy(children: &[&str]) -> Prefab {
+        let mut root = PrefabObject::new("enemy".to_owned());
+
+        for child in children {
+            root = root.with_child(PrefabObject::new((*child).to_owned()));
+        }
+
+        Prefab { root: root.into() }
+    }
+
+    #[test]
+    fn check_material_rebind_during_render_applies_next_frame() {
+        let ctx = match create_test_context() {
+            Some(ctx) => ctx,
+            None => return,
+        };
+        let material = pbr_material(&ctx);
+        let key = BindingPropKey::StringKey(PBR_SAMPLER_NAME.to_owned());
+        let sampler = Arc::new(
+            ctx.gfx_ctx()
+                .device
+                .create_sampler(&SamplerDescriptor::default()),
+        );
+
+        ctx.deferred_mutations().enter_phase(FramePhase::Render);
+        ctx.set_material_bind_property(
+            &material,
+            key.clone(),
+            BindGroupEntryResource::Sampler {
+                sampler: sampler.clone(),
+            },
+        );
+        assert!(bound_sampler(&material, &key).is_none());
+        assert_eq!(
+            ctx.deferred_mutations().pending_kinds(),
+            vec![MutationKind::MaterialBinding]
+        );
+
+        ctx.apply_deferred_mutations();
+        assert!(Arc::ptr_eq(
+            &bound_sampler(&material, &key).unwrap(),
+            &sampler
+        ));
+    }
+
+    #[test]
+    fn check_material_shader_swap_during_render_applies_next_frame() {
+        let ctx = match create_test_context() {
+            Some(ctx) => ctx,
+            None => return,
+        };
+        let material = pbr_material(&ctx);
+        let pbr = material.read().shader.clone();
+        let lit = ctx
+            .built_in_shader_mgr()
+            .find_shader(BUILT_IN_SHADER_LIT)
+            .unwrap();
+
+        ctx.deferred_mutations().enter_phase(FramePhase::Render);
+        ctx.set_material_shader(&material, lit.clone());
+        assert!(material.read().shader == pbr);
+        assert_eq!(
+            ctx.deferred_mutations().pending_kinds(),
+            vec![MutationKind::MaterialShader]
+        );
+
+        ctx.apply_deferred_mutations();
+        assert!(material.read().shader == lit);
+    }
+
+    #[test]
+    fn check_object_removal_during_dispatch_applies_next_frame() {
+        let ctx = match create_test_context() {
+            Some(ctx) => ctx,
+            None => return,
+        };
+        let object = {
+            let mut world = ctx.world_mut();
+            let (object, builder) =
+                ctx.object_mgr_mut()
+                    .create_object_builder(&mut world, "doomed".to_owned(), None);
+            builder.build();
+            object
+        };
+
+        {
+            let object = object.clone();
+            ctx.event_mgr()
+                .add_handler(EventHandler::new(move |_: &event_types::Update| {
+                    object.remove().unwrap();
+                }));
+        }
+
+        ctx.deferred_mutations().enter_phase(FramePhase::Update);
+        ctx.event_mgr().dispatch(&event_types::Update);
+        assert!(object.is_alive());
+        assert_eq!(
+            ctx.deferred_mutations().pending_kinds(),
+            vec![MutationKind::ObjectRemoval]
+        );
+
+        ctx.apply_deferred_mutations();
+        assert!(!object.is_alive());
+    }
+
+    #[test]
+    fn check_asset_replacement_during_render_applies_next_frame() {
+        let ctx = match create_test_context() {
+            Some(ctx) => ctx,
+            None => return,
+        };
+        let key = AssetKey::Path("enemy".to_owned());
+        ctx.replace_prefab(key.clone(), enemy(&["body"])).unwrap();
+        let instance = ctx.prefab_mgr_mut().instantiate(key.clone(), None).unwrap();
+
+        ctx.deferred_mutations().enter_phase(FramePhase::Render);
+        ctx.replace_prefab(key.clone(), enemy(&["body", "shadow"]))
+            .unwrap();
+        // A prefab containing itself is refused on the spot, not when the frame ends.
+        let cycle = Prefab {
+            root: PrefabObject::new("enemy".to_owned())
+                .with_child(PrefabInstanceNode::new(key.clone()))
+                .into(),
+        };
+        assert!(matches!(
+            ctx.replace_prefab(key.clone(), cycle),
+            Err(PrefabError::Cycle(_))
+        ));
+
+        assert_eq!(
+            ctx.prefab_mgr()
+                .registry()
+                .resolve(&key)
+                .unwrap()
+                .children
+                .len(),
+            1
+        );
+        assert!(instance.is_alive());
+        assert_eq!(
+            ctx.deferred_mutations().pending_kinds(),
+            vec![MutationKind::AssetReplacement]
+        );
+
+        ctx.apply_deferred_mutations();
+        assert_eq!(
+            ctx.prefab_mgr()
+                .registry()
+                .resolve(&key)
+                .unwrap()
+                .children
+                .len(),
+            2
+        );
+        assert!(!instance.is_alive());
+        let root = ctx.prefab_mgr().instances()[0].root.clone();
+        assert_eq!(root.children().len(), 2);
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt::Display;
+
+/// The part of the frame the engine is running.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FramePhase {
+    /// Between frames, including the start of a frame where deferred mutations are applied.
+    #[default]
+    Idle,
+    /// Polling the input.
+    Input,
+    /// Dispatching the update events and running the update systems.
+    Update,
+    /// Recording and submitting the rendering commands.
+    Render,
+}
+
+impl FramePhase {
+    /// Returns `true` if structural changes must be deferred to the next frame during the phase.
+    pub fn is_restricted(self) -> bool {
+        match self {
+            Self::Idle | Self::Input => false,
+            Self::Update | Self::Render => true,
+        }
+    }
+}
+
+impl Display for FramePhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Idle => write!(f, "idle"),
+            Self::Input => write!(f, "input"),
+            Self::Update => write!(f, "update"),
+            Self::Render => write!(f, "render"),
+        }
+    }
+}
@@ -0,0 +1,17 @@
+//! Structural changes requested while the frame still uses the state they change.
+//!
+//! Changes are split into two kinds:
+//!
+//! - value changes, such as uniform values, transform fields and per-instance properties, are applied at once;
+//! - structural changes, such as swapping the shader of a material, rebinding its textures, replacing an asset
+//!   from a hot reload and removing objects, are applied at once only between frames.
+//!
+//! While the frame is in a restricted [`FramePhase`], e.g. dispatching events or rendering, structural changes are
+//! queued into the [`DeferredMutations`] of the context instead, and applied at the start of the next frame,
+//! before input is polled. Debug builds log every change deferred this way; release builds defer silently.
+
+mod deferred_mutations;
+mod frame_phase;
+
+pub use deferred_mutations::*;
+pub use frame_phase::*;
@@ -1,6 +1,18 @@
 pub mod make_ui_scaler_dirty;
 pub mod render;
+pub mod system_registry;
+pub mod update_animation_players;
+pub mod update_bone_attachments;
+pub mod update_buoyancy;
 pub mod update_camera_transform_buffer;
+pub mod update_cloth_meshes;
+pub mod update_cloths;
+pub mod update_ik_constraints;
+pub mod update_lights;
+pub mod update_nav_agents;
+pub mod update_path_followers;
+pub mod update_property_animators;
+pub mod update_skinned_meshes;
 pub mod update_ui_element;
 pub mod update_ui_raycast_grid;
 pub mod update_ui_scaler;
@@ -1,26 +1,51 @@
 use crate::{
     gfx::{
-        BindGroupLayoutCache, Camera, MeshRenderer, Renderer, UIElementRenderer, UITextRenderer,
+        batch_sprites, build_instanced_rendering_command, group_batches, mirrored_frustum,
+        mirrored_view_projection, record_in_parallel, reflection_matrix, skybox_matrix,
+        sort_render_queue, surface_plane, view_depth, BindGroupLayoutCache, BundleTargetFormat,
+        Camera, CameraClearMode, CapturePassTarget, Color, FogView, FogVolume, FrameGraph,
+        FrameGraphDiagnostic, GfxContextHandle, GpuCulling, GpuParticles, HlodProxy, Layers,
+        LineRenderer, MaterialHandle, MeshRenderer, MeshSubRenderer, NinePatchRenderer,
+        ParticleSystem, PlanarReflection, PlanarReflectionCandidate, QueuedItem, QueuedSprite,
+        RenderManager, RenderQueue, Renderer, RenderingCommand, ResourceDeclaration, ShaderManager,
+        SpriteRenderer, Terrain, TextRenderer, UIElementRenderer, UITextRenderer, WaterSurface,
+        MIN_COMMANDS_PER_RECORDING_THREAD,
     },
-    object::Object,
+    math::{Mat4, Vec3, Vec4},
+    object::{Object, ObjectHierarchy, ObjectId},
+    time::FrameRenderCounters,
     ui::UISize,
     use_context,
 };
 use image::EncodableLayout;
+use logging::StandardLogLevel;
 use specs::prelude::*;
-use std::mem::size_of;
+use std::{
+    collections::{HashMap, HashSet},
+    mem::size_of,
+    time::Instant,
+};
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupLayoutEntry, BindingType, Buffer, BufferAddress,
-    BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, Device, ShaderStages,
+    BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, CommandEncoder, ShaderStages,
+    SurfaceError, TextureViewDescriptor,
 };
 
 pub struct RenderSystem {
     screen_size_buffer: Buffer,
     screen_size_bind_group: BindGroup,
+    gpu_culling: Option<GpuCulling>,
+    gpu_particles: Option<GpuParticles>,
+    last_diagnostics: Vec<FrameGraphDiagnostic>,
+    surface_error: Option<SurfaceError>,
 }
 
 impl RenderSystem {
-    pub fn new(device: &Device, bind_group_layout_cache: &mut BindGroupLayoutCache) -> Self {
+    pub fn new(
+        gfx_ctx: &GfxContextHandle,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) -> Self {
+        let device = &gfx_ctx.device;
         let screen_size_buffer = device.create_buffer(&BufferDescriptor {
             label: None,
             size: size_of::<[f32; 4]>() as u64 as BufferAddress,
@@ -50,15 +75,436 @@ impl RenderSystem {
         Self {
             screen_size_buffer,
             screen_size_bind_group,
+            gpu_culling: GpuCulling::new(gfx_ctx),
+            gpu_particles: GpuParticles::new(gfx_ctx),
+            last_diagnostics: Vec::new(),
+            surface_error: None,
+        }
+    }
+
+    /// Takes the error of the last frame that failed to acquire the surface texture, and was skipped.
+    pub fn take_surface_error(&mut self) -> Option<SurfaceError> {
+        self.surface_error.take()
+    }
+
+    /// Validates the resource declarations of the frame, reporting the diagnostics only when they change.
+    fn validate_frame_graph(
+        &mut self,
+        frame_graph: &FrameGraph,
+        order: &[usize],
+        cycle: Option<FrameGraphDiagnostic>,
+    ) {
+        let mut diagnostics = frame_graph.validate(order);
+        diagnostics.extend(cycle);
+
+        if diagnostics == self.last_diagnostics {
+            return;
+        }
+
+        if !diagnostics.is_empty() {
+            let mut message = String::from("invalid frame graph:\n");
+
+            for diagnostic in &diagnostics {
+                message += &format!("  - {}\n", diagnostic);
+            }
+
+            message += &frame_graph.dump(order);
+            use_context().logger().log(StandardLogLevel::Error, message);
+        }
+
+        self.last_diagnostics = diagnostics;
+    }
+
+    /// Renders the main camera mirrored about the surface into the reflection texture.
+    /// Only meshes are reflected; the surface itself is skipped.
+    #[allow(clippy::too_many_arguments)]
+    fn render_planar_reflection(
+        &self,
+        encoder: &mut CommandEncoder,
+        render_mgr: &mut RenderManager,
+        shader_mgr: &ShaderManager,
+        object_hierarchy: &ObjectHierarchy,
+        objects: &ReadStorage<Object>,
+        layers: &ReadStorage<Layers>,
+        mesh_renderers: &mut WriteStorage<MeshRenderer>,
+        surface: &ReflectiveSurface,
+        target_index: usize,
+        culling_slot: usize,
+        (camera_transform, camera, projection): (&Mat4, &Camera, &Mat4),
+    ) {
+        let view_projection = mirrored_view_projection(camera_transform, projection, surface.plane);
+        let frustum = mirrored_frustum(&view_projection);
+        let mask = camera.mask & surface.reflection.mask;
+        let frustum_culling = render_mgr.is_frustum_culling_enabled();
+
+        let target = &render_mgr.planar_reflections().targets()[target_index];
+        target.update(
+            use_context().gfx_ctx(),
+            &view_projection,
+            Vec3::from(camera_transform.row(3)),
+            surface.reflection.distortion,
+        );
+        let camera_bind_group = target.camera_bind_group.clone();
+        let lights_bind_group = render_mgr.lights().bind_group().clone();
+        let local_lights = render_mgr.lights().local_lights().clone();
+        let color_view = target.color.view.clone();
+        let color_size = (target.color.width as u32, target.color.height as u32);
+        let color_format = target.color.texture.format();
+        let depth_view = target.depth.as_ref().map(|depth| depth.view.clone());
+
+        let (_, pipeline_cache) = render_mgr.split_caches();
+        let frame_alloc = use_context().frame_alloc();
+        let mut mesh_sub_renderers = frame_alloc.alloc_vec(1024);
+        let mut instanced_mesh_sub_renderers = frame_alloc.alloc_vec(0);
+
+        for (object, mesh_renderer, object_layers) in
+            (objects, &mut *mesh_renderers, layers.maybe()).join()
+        {
+            let object_id = object.object_id();
+
+            if object_id == surface.object_id || !object_hierarchy.is_active(object_id) {
+                continue;
+            }
+
+            if mesh_renderer.mask() & mask == 0
+                || !Layers::of(object_layers).intersects(camera.culling_mask)
+            {
+                continue;
+            }
+
+            if frustum_culling
+                && !mesh_renderer.is_in_frustum(object_hierarchy.matrix(object_id), &frustum)
+            {
+                continue;
+            }
+
+            let renderer =
+                if let Some(renderer) = mesh_renderer.sub_renderer(shader_mgr, pipeline_cache) {
+                    renderer
+                } else {
+                    continue;
+                };
+
+            if let Some(instanced_group) = mesh_renderer.instanced_group_mut() {
+                let draw = match &self.gpu_culling {
+                    Some(gpu_culling) => instanced_group.cull_gpu(
+                        encoder,
+                        gpu_culling,
+                        culling_slot,
+                        &frustum,
+                        renderer
+                            .index_buffer()
+                            .map_or(renderer.vertex_count(), |(_, _, index_count)| index_count),
+                    ),
+                    None => instanced_group.cull_cpu(culling_slot, &frustum),
+                };
+                instanced_mesh_sub_renderers.push((object_id, renderer, draw));
+                continue;
+            }
+
+            mesh_sub_renderers.push((object_id, renderer));
+        }
+
+        for (object_id, renderer) in mesh_sub_renderers.iter_mut() {
+            renderer.pick_lights(object_hierarchy.matrix(*object_id), &local_lights);
+        }
+
+        // Sorted as seen from the mirrored camera.
+        let mirrored_camera_transform = camera_transform * reflection_matrix(surface.plane);
+        let mut queued_commands =
+            frame_alloc.alloc_vec(mesh_sub_renderers.len() + instanced_mesh_sub_renderers.len());
+
+        for batch in batch_mesh_sub_renderers(&mesh_sub_renderers) {
+            let command = render_mgr.build_batched_rendering_command(&batch, object_hierarchy);
+            queued_commands.extend(command.map(|command| {
+                queue_command(
+                    command,
+                    batch.iter().map(|(object_id, _)| *object_id),
+                    object_hierarchy,
+                    &mirrored_camera_transform,
+                )
+            }));
+        }
+
+        for (object_id, renderer, draw) in &instanced_mesh_sub_renderers {
+            let command = build_instanced_rendering_command(renderer, draw.clone());
+            queued_commands.extend(command.map(|command| {
+                queue_command(
+                    command,
+                    [*object_id],
+                    object_hierarchy,
+                    &mirrored_camera_transform,
+                )
+            }));
+        }
+
+        sort_render_queue(
+            &mut queued_commands,
+            render_mgr.is_opaque_front_to_back(),
+            &frame_alloc,
+        );
+        let mut commands = frame_alloc.alloc_vec(queued_commands.len());
+        commands.extend(queued_commands.into_iter().map(|queued| queued.item));
+
+        // The texture always starts from scratch, even if the main camera keeps the previous frame.
+        let clear_mode = match &camera.clear_mode {
+            CameraClearMode::All { color, .. } => CameraClearMode::all(*color, 1.0, 0),
+            _ => CameraClearMode::all(Color::black(), 1.0, 0),
+        };
+        let clear_mode = render_mgr.color_space().clear_mode(&clear_mode);
+
+        if render_mgr.is_capturing_frame() {
+            render_mgr.record_capture_pass(
+                format!("planar reflection #{}", target_index),
+                CapturePassTarget::Texture {
+                    view: &color_view,
+                    width: color_size.0,
+                    height: color_size.1,
+                    format: color_format,
+                },
+                &clear_mode,
+                &view_projection,
+                &commands,
+                shader_mgr,
+            );
+        }
+
+        let mut render_pass = RenderManager::begin_render_target_pass(
+            encoder,
+            &color_view,
+            depth_view.as_deref(),
+            &clear_mode,
+        );
+
+        for cmd in &commands {
+            cmd.render_in_pass(
+                &mut render_pass,
+                &camera_bind_group,
+                &self.screen_size_bind_group,
+                &lights_bind_group,
+            );
+        }
+    }
+}
+
+/// Returns the stencil reference of the commands if they all share the same one.
+fn shared_stencil_reference(commands: &[RenderingCommand]) -> Option<u32> {
+    let reference = commands
+        .first()
+        .map_or(0, RenderingCommand::stencil_reference);
+
+    commands
+        .iter()
+        .all(|cmd| cmd.stencil_reference() == reference)
+        .then_some(reference)
+}
+
+/// Places a command of the objects in the queue of its material, at the depth of the nearest object as seen from
+/// the camera.
+fn queue_command<'r>(
+    command: RenderingCommand<'r>,
+    object_ids: impl IntoIterator<Item = ObjectId>,
+    object_hierarchy: &ObjectHierarchy,
+    camera_transform: &Mat4,
+) -> QueuedItem<RenderingCommand<'r>> {
+    let depth = object_ids
+        .into_iter()
+        .map(|object_id| {
+            view_depth(
+                camera_transform,
+                Vec3::from(object_hierarchy.matrix(object_id).row(3)),
+            )
+        })
+        .fold(f32::INFINITY, f32::min);
+
+    QueuedItem {
+        queue: command.material.render_queue,
+        order: command.material.render_order,
+        depth,
+        item: command,
+    }
+}
+
+/// Groups the meshes that can be drawn as instances of one command. Transparent meshes are drawn alone, since their
+/// queue is sorted object by object.
+fn batch_mesh_sub_renderers(
+    mesh_sub_renderers: &[(ObjectId, MeshSubRenderer)],
+) -> Vec<Vec<(ObjectId, &dyn Renderer)>> {
+    group_batches(mesh_sub_renderers.iter().map(|(object_id, renderer)| {
+        let key = renderer
+            .batch_key()
+            .filter(|_| renderer.material().render_queue == RenderQueue::Opaque);
+        (key, (*object_id, renderer as &dyn Renderer))
+    }))
+}
+
+/// A reflective surface found this frame.
+struct ReflectiveSurface {
+    object_id: ObjectId,
+    reflection: PlanarReflection,
+    plane: Vec4,
+    material: Option<MaterialHandle>,
+}
+
+/// Surfaces are either meshes or water; the material of either samples the reflection.
+fn collect_reflective_surfaces(
+    object_hierarchy: &ObjectHierarchy,
+    objects: &ReadStorage<Object>,
+    planar_reflections: &ReadStorage<PlanarReflection>,
+    mesh_renderers: &WriteStorage<MeshRenderer>,
+    water_surfaces: &WriteStorage<WaterSurface>,
+) -> Vec<ReflectiveSurface> {
+    let create_surface = |object: &Object, reflection: &PlanarReflection, material| {
+        let object_id = object.object_id();
+        ReflectiveSurface {
+            object_id,
+            reflection: reflection.clone(),
+            plane: surface_plane(object_hierarchy.matrix(object_id), reflection.clip_offset),
+            material,
+        }
+    };
+    let meshes = (objects, planar_reflections, mesh_renderers)
+        .join()
+        .filter(|(object, _, _)| object_hierarchy.is_active(object.object_id()))
+        .map(|(object, reflection, mesh_renderer)| {
+            create_surface(object, reflection, mesh_renderer.drawn_material().cloned())
+        });
+    let waters = (objects, planar_reflections, water_surfaces)
+        .join()
+        .filter(|(object, _, _)| object_hierarchy.is_active(object.object_id()))
+        .map(|(object, reflection, water_surface)| {
+            create_surface(object, reflection, water_surface.material().cloned())
+        });
+
+    meshes.chain(waters).collect()
+}
+
+/// Invalidates the proxies whose members moved or were removed since the bake, and switches the others
+/// by the distance of the main camera, so that every camera draws the same members.
+/// Returns the members to suppress, as their proxy is drawn instead.
+fn update_hlod_proxies(
+    object_hierarchy: &ObjectHierarchy,
+    objects: &ReadStorage<Object>,
+    mesh_renderers: &WriteStorage<MeshRenderer>,
+    hlod_proxies: &mut WriteStorage<HlodProxy>,
+    camera_position: Option<Vec3>,
+) -> HashSet<ObjectId> {
+    let mut proxies = Vec::new();
+    let mut member_proxies = HashMap::new();
+
+    for (object, hlod_proxy) in (objects, hlod_proxies).join() {
+        let object_id = object.object_id();
+
+        if !object_hierarchy.is_active(object_id) {
+            continue;
+        }
+
+        for member in hlod_proxy.members() {
+            member_proxies.insert(member, proxies.len());
+        }
+
+        proxies.push((object_id, hlod_proxy, 0));
+    }
+
+    if proxies.is_empty() {
+        return HashSet::new();
+    }
+
+    let invalidate = |proxy_id: ObjectId, hlod_proxy: &mut HlodProxy, reason: &str| {
+        if hlod_proxy.invalidate() {
+            use_context().logger().log(
+                StandardLogLevel::Warning,
+                format!(
+                    "the HLOD bake of {:?} is out of date as {}; bake again to use its proxy",
+                    proxy_id, reason
+                ),
+            );
+        }
+    };
+
+    for (object, _) in (objects, mesh_renderers).join() {
+        let object_id = object.object_id();
+        let (proxy_id, hlod_proxy, found) = match member_proxies.get(&object_id) {
+            Some(&index) => &mut proxies[index],
+            None => continue,
+        };
+        *found += 1;
+
+        if object_hierarchy.is_current_frame_dirty(object_id)
+            && hlod_proxy.baked_matrix(object_id) != Some(object_hierarchy.matrix(object_id))
+        {
+            invalidate(*proxy_id, hlod_proxy, "a member has moved");
+        }
+    }
+
+    let mut suppressed = HashSet::new();
+
+    for (proxy_id, hlod_proxy, found) in proxies {
+        if found < hlod_proxy.member_count() {
+            invalidate(proxy_id, hlod_proxy, "a member has been removed");
+        }
+
+        if let Some(camera_position) = camera_position {
+            hlod_proxy.update_switch(camera_position);
+        }
+
+        if hlod_proxy.is_using_proxy() {
+            suppressed.extend(hlod_proxy.members());
         }
     }
+
+    suppressed
+}
+
+/// Returns `true` if a custom pass must run before one of the cameras, i.e. between camera passes.
+/// Such frames are recorded on a single thread.
+fn has_interleaved_custom_pass(
+    order: &[usize],
+    camera_pass_indices: &[usize],
+    custom_pass_indices: &[usize],
+) -> bool {
+    let last_camera = order
+        .iter()
+        .rposition(|index| camera_pass_indices.contains(index));
+
+    match last_camera {
+        Some(last_camera) => order[..last_camera]
+            .iter()
+            .any(|index| custom_pass_indices.contains(index)),
+        None => false,
+    }
+}
+
+/// Depth is cleared as a new version, since cameras stacked over a scene (e.g. UI) clear the depth of the scene on
+/// purpose; clearing the surface over an unread one is still reported.
+fn declare_camera_resources(clear_mode: &CameraClearMode) -> ResourceDeclaration {
+    let mut declaration = ResourceDeclaration::new();
+
+    match clear_mode {
+        CameraClearMode::Keep => declaration.load("surface").load("depth"),
+        CameraClearMode::All { .. } => declaration.write("surface").clear("depth"),
+        CameraClearMode::DepthOnly { .. } => declaration.load("surface").clear("depth"),
+    };
+
+    declaration
 }
 
 impl<'a> System<'a> for RenderSystem {
     type SystemData = (
         ReadStorage<'a, Object>,
         ReadStorage<'a, Camera>,
+        ReadStorage<'a, PlanarReflection>,
+        ReadStorage<'a, FogVolume>,
+        ReadStorage<'a, Layers>,
         WriteStorage<'a, MeshRenderer>,
+        WriteStorage<'a, ParticleSystem>,
+        WriteStorage<'a, Terrain>,
+        WriteStorage<'a, WaterSurface>,
+        WriteStorage<'a, HlodProxy>,
+        WriteStorage<'a, LineRenderer>,
+        WriteStorage<'a, SpriteRenderer>,
+        WriteStorage<'a, NinePatchRenderer>,
+        WriteStorage<'a, TextRenderer>,
         WriteStorage<'a, UIElementRenderer>,
         WriteStorage<'a, UITextRenderer>,
         ReadStorage<'a, UISize>,
@@ -69,61 +515,346 @@ impl<'a> System<'a> for RenderSystem {
         (
             objects,
             cameras,
+            planar_reflections,
+            fog_volumes,
+            layers,
             mut mesh_renderers,
+            mut particle_systems,
+            mut terrains,
+            mut water_surfaces,
+            mut hlod_proxies,
+            mut line_renderers,
+            mut sprite_renderers,
+            mut nine_patch_renderers,
+            mut text_renderers,
             mut ui_element_renderers,
             mut ui_text_renderers,
             ui_sizes,
         ): Self::SystemData,
     ) {
         let context = use_context();
+        // Temporary lists of the frame live in the arena, instead of being allocated every frame.
+        let frame_alloc = context.frame_alloc();
         let mut glyph_mgr = context.glyph_mgr_mut();
         let mut render_mgr = context.render_mgr_mut();
+        let lights_bind_group = render_mgr.lights().bind_group().clone();
+        let local_lights = render_mgr.lights().local_lights().clone();
+        let mut debug_draw_mgr = context.debug_draw_mgr_mut();
         let shader_mgr = context.shader_mgr();
         let world_mgr = context.object_mgr();
         let object_hierarchy = world_mgr.object_hierarchy();
 
-        context
-            .gfx_ctx()
-            .queue
-            .write_buffer(&self.screen_size_buffer, 0, {
-                let screen_mgr = context.screen_mgr();
-                [
-                    screen_mgr.width() as f32,
-                    screen_mgr.height() as f32,
-                    0.0f32,
-                    0.0f32,
-                ]
-                .as_bytes()
-            });
+        let screen_size = {
+            let screen_mgr = context.screen_mgr();
+            render_mgr.set_render_scale(screen_mgr.render_scale());
+            [
+                screen_mgr.width() as f32,
+                screen_mgr.height() as f32,
+                0.0f32,
+                0.0f32,
+            ]
+        };
+
+        render_mgr.begin_frame();
+
+        let surface_texture = match render_mgr.acquire_frame_target() {
+            Ok(surface_texture) => surface_texture,
+            Err(err) => {
+                render_mgr.skip_frame();
+                debug_draw_mgr.clear();
+                self.surface_error = Some(err);
+                return;
+            }
+        };
+
+        render_mgr
+            .uploader_mut()
+            .write(&self.screen_size_buffer, 0, screen_size.as_bytes());
+        render_mgr.begin_frame_capture(screen_size);
+        render_mgr.begin_camera_stacks();
 
-        let surface_texture = context.gfx_ctx().surface.get_current_texture().unwrap();
-        let surface_texture_view = surface_texture.texture.create_view(&Default::default());
+        // The scene is written through the view of its color space, and the rest draws colors as picked.
+        let surface_texture_view = surface_texture
+            .texture()
+            .create_view(&TextureViewDescriptor {
+                format: Some(context.gfx_ctx().plain_surface_format()),
+                ..Default::default()
+            });
+        let output_texture_view = surface_texture
+            .texture()
+            .create_view(&TextureViewDescriptor {
+                format: Some(render_mgr.surface_output().format),
+                ..Default::default()
+            });
+        // With post-processing, the cameras draw into an intermediate target instead of the surface.
+        let post_process_view = render_mgr.prepare_post_processing();
+        let scene_texture_view = post_process_view.as_deref().unwrap_or(&output_texture_view);
         let mut encoder = render_mgr.create_encoder();
 
-        let mut camera_objects = (&objects, &cameras).join().collect::<Vec<_>>();
-        camera_objects.sort_unstable_by_key(|&(_, camera)| camera.depth);
+        // Particles advance once per frame, however many cameras draw them.
+        {
+            let delta_time = context.time_mgr().delta_time();
+            let particle_budget = render_mgr.quality_preset().particle_budget;
+
+            for (object, particle_system) in (&objects, &mut particle_systems).join() {
+                if !object_hierarchy.is_active(object.object_id()) {
+                    continue;
+                }
+
+                particle_system.simulate(
+                    &mut encoder,
+                    render_mgr.uploader_mut(),
+                    self.gpu_particles.as_ref(),
+                    object_hierarchy.matrix(object.object_id()),
+                    delta_time,
+                    particle_budget,
+                );
+            }
+        }
+
+        for terrain in (&mut terrains).join() {
+            terrain.upload_heights(&context.gfx_ctx().queue);
+        }
+
+        let color_space = render_mgr.color_space();
+
+        // Gameplay samples the waves at the same time through `WaterSurface::height_at`.
+        {
+            let time = context.time_mgr().time().as_secs_f32();
+
+            for water_surface in (&water_surfaces).join() {
+                water_surface.upload(render_mgr.uploader_mut(), time, color_space);
+            }
+        }
+
+        // Segments are submitted every frame, and drawn by every camera.
+        for line_renderer in (&mut line_renderers).join() {
+            line_renderer.upload(
+                render_mgr.frame_buffer_allocator_mut(),
+                &frame_alloc,
+                color_space,
+            );
+        }
+
+        debug_draw_mgr.upload(
+            render_mgr.frame_buffer_allocator_mut(),
+            &frame_alloc,
+            color_space,
+        );
+
+        let mut camera_objects = frame_alloc.alloc_vec(0);
+        camera_objects.extend((&objects, &cameras).join());
+        // Cameras drawing into offscreen targets go first, so that the others can sample them.
+        camera_objects.sort_unstable_by_key(|&(_, camera)| (camera.target.is_none(), camera.depth));
+
+        // Reflections and fog follow the first active camera drawing into the surface, which is usually the one
+        // drawing the world.
+        let main_camera_id = camera_objects
+            .iter()
+            .find(|(object, camera)| {
+                camera.target.is_none() && object_hierarchy.is_active(object.object_id())
+            })
+            .map(|(object, _)| object.object_id());
+        let main_camera = camera_objects
+            .iter()
+            .find(|(object, _)| Some(object.object_id()) == main_camera_id)
+            .map(|&(object, camera)| (object_hierarchy.matrix(object.object_id()), camera));
+        let hlod_suppressed = update_hlod_proxies(
+            object_hierarchy,
+            &objects,
+            &mesh_renderers,
+            &mut hlod_proxies,
+            main_camera.map(|(camera_transform, _)| Vec3::from(camera_transform.row(3))),
+        );
+        let surfaces = match main_camera {
+            Some(_) => collect_reflective_surfaces(
+                object_hierarchy,
+                &objects,
+                &planar_reflections,
+                &mesh_renderers,
+                &water_surfaces,
+            ),
+            None => Vec::new(),
+        };
+        let due_reflections = match main_camera {
+            Some((camera_transform, _)) => {
+                let screen_mgr = context.screen_mgr();
+                let camera_position = Vec3::from(camera_transform.row(3));
+                let candidates = Vec::from_iter(surfaces.iter().map(|surface| {
+                    let (width, height) = surface
+                        .reflection
+                        .texture_size(screen_mgr.width() as u32, screen_mgr.height() as u32);
+                    PlanarReflectionCandidate {
+                        object_id: surface.object_id,
+                        distance: Vec3::distance(
+                            camera_position,
+                            Vec3::from(object_hierarchy.matrix(surface.object_id).row(3)),
+                        ),
+                        width,
+                        height,
+                        update_interval: surface.reflection.update_interval,
+                    }
+                }));
+                let (pool, bind_group_layout_cache) = render_mgr.split_planar_reflections();
+                pool.update_fallback(camera_position);
+                pool.assign(&candidates, bind_group_layout_cache)
+            }
+            None => Vec::new(),
+        };
+
+        let mut frame_graph = FrameGraph::new();
+        let mut camera_pass_indices = Vec::with_capacity(camera_objects.len());
+        let mut custom_pass_indices = Vec::with_capacity(render_mgr.custom_passes().len());
+
+        for &(surface_index, target_index) in &due_reflections {
+            let mut declaration = ResourceDeclaration::new();
+            declaration.write(format!("planar reflection #{}", target_index));
+            frame_graph.add_pass(
+                format!(
+                    "planar reflection of {:?}",
+                    surfaces[surface_index].object_id
+                ),
+                declaration,
+            );
+        }
+
+        for (index, (_, camera)) in camera_objects.iter().enumerate() {
+            let mut declaration = declare_camera_resources(&camera.clear_mode);
+
+            for &(_, target_index) in &due_reflections {
+                declaration.sample(format!("planar reflection #{}", target_index));
+            }
 
-        for (object, camera) in camera_objects {
+            camera_pass_indices
+                .push(frame_graph.add_pass(format!("camera #{}", index), declaration));
+        }
+
+        for pass in render_mgr.custom_passes() {
+            let mut declaration = ResourceDeclaration::new();
+            pass.declare_resources(&mut declaration);
+            custom_pass_indices.push(frame_graph.add_pass(pass.name(), declaration));
+        }
+
+        let (order, cycle) = frame_graph.resolve_order();
+
+        if cfg!(debug_assertions) {
+            self.validate_frame_graph(&frame_graph, &order, cycle);
+        }
+
+        let encoder_threads =
+            if has_interleaved_custom_pass(&order, &camera_pass_indices, &custom_pass_indices) {
+                1
+            } else {
+                render_mgr.encoder_threads()
+            };
+
+        if let Some((camera_transform, camera)) = main_camera {
+            let projection = camera.projection_matrix(&context.screen_mgr());
+
+            for &(surface_index, target_index) in &due_reflections {
+                self.render_planar_reflection(
+                    &mut encoder,
+                    &mut render_mgr,
+                    shader_mgr,
+                    object_hierarchy,
+                    &objects,
+                    &layers,
+                    &mut mesh_renderers,
+                    &surfaces[surface_index],
+                    target_index,
+                    // Culling slots after the cameras' ones.
+                    camera_objects.len() + target_index,
+                    (camera_transform, camera, &projection),
+                );
+            }
+        }
+
+        let visible_fog_volumes = if render_mgr.volumetric_fog().is_some() {
+            Vec::from_iter(
+                (&objects, &fog_volumes)
+                    .join()
+                    .filter(|(object, _)| object_hierarchy.is_active(object.object_id()))
+                    .map(|(object, fog_volume)| {
+                        (
+                            object_hierarchy.matrix(object.object_id()).clone(),
+                            *fog_volume,
+                        )
+                    }),
+            )
+        } else {
+            Vec::new()
+        };
+
+        for surface in &surfaces {
+            if let Some(material) = &surface.material {
+                render_mgr
+                    .planar_reflections_mut()
+                    .bind_material(surface.object_id, material);
+            }
+        }
+
+        let scene_size = render_mgr.scene_size();
+
+        for (camera_index, (object, camera)) in camera_objects.into_iter().enumerate() {
             let standard_ui_vertex_buffer = render_mgr.standard_ui_vertex_buffer().clone();
+            let frustum_culling = render_mgr.is_frustum_culling_enabled();
             let (bind_group_layout_cache, pipeline_cache) = render_mgr.split_caches();
 
             if !object_hierarchy.is_active(object.object_id()) {
                 continue;
             }
 
-            let mut mesh_sub_renderers = Vec::with_capacity(1024);
+            let render_target = camera.target.as_ref().map(|target| target.read());
+            let (target_width, target_height) = match &render_target {
+                Some(target) => (target.width() as u32, target.height() as u32),
+                // The scene is drawn at the render scale of the surface.
+                None => (scene_size.width, scene_size.height),
+            };
+            // Cameras whose viewport covers nothing, e.g. while the window is minimized, draw nothing.
+            let viewport = match camera.viewport.to_pixels(target_width, target_height) {
+                Some(viewport) if viewport.covers(target_width, target_height) => None,
+                Some(viewport) => Some(viewport),
+                None => continue,
+            };
+
+            let frustum = camera.frustum(
+                &context.screen_mgr(),
+                object_hierarchy.matrix(object.object_id()),
+            );
+            let camera_position = Vec3::from(object_hierarchy.matrix(object.object_id()).row(3));
+            let mut meshes_culled = 0;
+            let mut mesh_sub_renderers = frame_alloc.alloc_vec(1024);
+            let mut instanced_mesh_sub_renderers = frame_alloc.alloc_vec(0);
+            let mut hlod_proxy_sub_renderers = frame_alloc.alloc_vec(0);
+            let mut terrain_sub_renderers = frame_alloc.alloc_vec(0);
+            let mut terrain_chunks = (0, 0);
+            let mut water_sub_renderers = frame_alloc.alloc_vec(0);
+            let mut line_sub_renderers = frame_alloc.alloc_vec(0);
+            let mut particle_sub_renderers = frame_alloc.alloc_vec(0);
+            let mut sprite_sub_renderers = frame_alloc.alloc_vec(1024);
 
-            let mut ui_element_sub_renderers = Vec::with_capacity(1024);
-            let mut ui_text_sub_renderers = Vec::with_capacity(1024);
+            let mut ui_element_sub_renderers = frame_alloc.alloc_vec(1024);
+            let mut ui_text_sub_renderers = frame_alloc.alloc_vec(1024);
 
-            for (object, mesh_renderer) in (&objects, &mut mesh_renderers).join() {
+            for (object, mesh_renderer, object_layers) in
+                (&objects, &mut mesh_renderers, layers.maybe()).join()
+            {
                 let object_id = object.object_id();
 
-                if !object_hierarchy.is_active(object.object_id()) {
+                if !object_hierarchy.is_active(object.object_id())
+                    || !Layers::of(object_layers).intersects(camera.culling_mask)
+                {
                     continue;
                 }
 
-                if mesh_renderer.mask() & camera.mask == 0 {
+                if mesh_renderer.mask() & camera.mask == 0 || hlod_suppressed.contains(&object_id) {
+                    continue;
+                }
+
+                if frustum_culling
+                    && !mesh_renderer.is_in_frustum(object_hierarchy.matrix(object_id), &frustum)
+                {
+                    meshes_culled += 1;
                     continue;
                 }
 
@@ -135,15 +866,242 @@ impl<'a> System<'a> for RenderSystem {
                     continue;
                 };
 
+                if let Some(instanced_group) = mesh_renderer.instanced_group_mut() {
+                    let draw = match &self.gpu_culling {
+                        Some(gpu_culling) => instanced_group.cull_gpu(
+                            &mut encoder,
+                            gpu_culling,
+                            camera_index,
+                            &frustum,
+                            renderer
+                                .index_buffer()
+                                .map_or(renderer.vertex_count(), |(_, _, index_count)| index_count),
+                        ),
+                        None => instanced_group.cull_cpu(camera_index, &frustum),
+                    };
+                    instanced_mesh_sub_renderers.push((object_id, renderer, draw));
+                    continue;
+                }
+
                 mesh_sub_renderers.push((object_id, renderer));
             }
 
-            for (object, ui_element_renderer, ui_size) in
-                (&objects, &mut ui_element_renderers, &ui_sizes).join()
+            for (object, hlod_proxy, object_layers) in
+                (&objects, &mut hlod_proxies, layers.maybe()).join()
             {
                 let object_id = object.object_id();
 
-                if !object_hierarchy.is_active(object.object_id()) {
+                if !object_hierarchy.is_active(object_id)
+                    || !hlod_proxy.is_using_proxy()
+                    || !Layers::of(object_layers).intersects(camera.culling_mask)
+                {
+                    continue;
+                }
+
+                if hlod_proxy.mask() & camera.mask == 0 {
+                    continue;
+                }
+
+                let (center, radius) = hlod_proxy.bounding_sphere();
+
+                if !frustum.intersects_sphere(center, radius) {
+                    continue;
+                }
+
+                if let Some(renderer) = hlod_proxy.sub_renderer(shader_mgr, pipeline_cache) {
+                    hlod_proxy_sub_renderers.push((object_id, renderer));
+                }
+            }
+
+            for (object, terrain, object_layers) in (&objects, &mut terrains, layers.maybe()).join()
+            {
+                let object_id = object.object_id();
+
+                if !object_hierarchy.is_active(object_id)
+                    || !Layers::of(object_layers).intersects(camera.culling_mask)
+                {
+                    continue;
+                }
+
+                if terrain.mask() & camera.mask == 0 {
+                    continue;
+                }
+
+                let renderers = terrain.sub_renderers(
+                    object_hierarchy.matrix(object_id),
+                    &frustum,
+                    camera_position,
+                    shader_mgr,
+                    pipeline_cache,
+                );
+                let drawn = renderers
+                    .iter()
+                    .map(|renderer| renderer.instance_count())
+                    .sum::<u32>();
+                terrain_chunks.0 += drawn;
+                terrain_chunks.1 += terrain.chunk_count() as u32 - drawn;
+                terrain_sub_renderers
+                    .extend(renderers.into_iter().map(|renderer| (object_id, renderer)));
+            }
+
+            for (object, water_surface, object_layers) in
+                (&objects, &mut water_surfaces, layers.maybe()).join()
+            {
+                let object_id = object.object_id();
+
+                if !object_hierarchy.is_active(object_id)
+                    || !Layers::of(object_layers).intersects(camera.culling_mask)
+                {
+                    continue;
+                }
+
+                if water_surface.mask() & camera.mask == 0 {
+                    continue;
+                }
+
+                if let Some(renderer) = water_surface.sub_renderer(shader_mgr, pipeline_cache) {
+                    water_sub_renderers.push((object_id, renderer));
+                }
+            }
+
+            for (object, line_renderer, object_layers) in
+                (&objects, &mut line_renderers, layers.maybe()).join()
+            {
+                let object_id = object.object_id();
+
+                if !object_hierarchy.is_active(object_id)
+                    || !Layers::of(object_layers).intersects(camera.culling_mask)
+                {
+                    continue;
+                }
+
+                if line_renderer.mask() & camera.mask == 0 {
+                    continue;
+                }
+
+                if let Some(renderer) = line_renderer.sub_renderer(shader_mgr, pipeline_cache) {
+                    line_sub_renderers.push((object_id, renderer));
+                }
+            }
+
+            for (object, particle_system, object_layers) in
+                (&objects, &mut particle_systems, layers.maybe()).join()
+            {
+                if !object_hierarchy.is_active(object.object_id())
+                    || !Layers::of(object_layers).intersects(camera.culling_mask)
+                {
+                    continue;
+                }
+
+                if particle_system.mask() & camera.mask == 0 {
+                    continue;
+                }
+
+                let draw = if let Some(draw) = particle_system.draw() {
+                    draw.clone()
+                } else {
+                    continue;
+                };
+
+                if let Some(renderer) = particle_system.sub_renderer(
+                    &standard_ui_vertex_buffer,
+                    shader_mgr,
+                    pipeline_cache,
+                ) {
+                    particle_sub_renderers.push((object.object_id(), renderer, draw));
+                }
+            }
+
+            for (object, sprite_renderer, object_layers) in
+                (&objects, &mut sprite_renderers, layers.maybe()).join()
+            {
+                let object_id = object.object_id();
+
+                if !object_hierarchy.is_active(object_id)
+                    || !Layers::of(object_layers).intersects(camera.culling_mask)
+                {
+                    continue;
+                }
+
+                if sprite_renderer.mask() & camera.mask == 0 {
+                    continue;
+                }
+
+                if let Some(renderer) = sprite_renderer.sub_renderer(
+                    &standard_ui_vertex_buffer,
+                    shader_mgr,
+                    pipeline_cache,
+                ) {
+                    sprite_sub_renderers.push((object_id, renderer));
+                }
+            }
+
+            // Nine-patches are drawn as sprites, and batched with them.
+            for (object, nine_patch_renderer, object_layers) in
+                (&objects, &mut nine_patch_renderers, layers.maybe()).join()
+            {
+                let object_id = object.object_id();
+
+                if !object_hierarchy.is_active(object_id)
+                    || !Layers::of(object_layers).intersects(camera.culling_mask)
+                {
+                    continue;
+                }
+
+                if nine_patch_renderer.mask() & camera.mask == 0 {
+                    continue;
+                }
+
+                if let Some(renderer) = nine_patch_renderer.sub_renderer(
+                    &standard_ui_vertex_buffer,
+                    shader_mgr,
+                    pipeline_cache,
+                ) {
+                    sprite_sub_renderers.push((object_id, renderer));
+                }
+            }
+
+            // Texts are drawn as sprites of their glyph textures, one sub renderer per texture.
+            for (object, text_renderer, object_layers) in
+                (&objects, &mut text_renderers, layers.maybe()).join()
+            {
+                let object_id = object.object_id();
+
+                if !object_hierarchy.is_active(object_id)
+                    || !Layers::of(object_layers).intersects(camera.culling_mask)
+                {
+                    continue;
+                }
+
+                if text_renderer.mask() & camera.mask == 0 {
+                    continue;
+                }
+
+                if let Some(renderers) = text_renderer.sub_renderers(
+                    &standard_ui_vertex_buffer,
+                    shader_mgr,
+                    &mut glyph_mgr,
+                    pipeline_cache,
+                    bind_group_layout_cache,
+                ) {
+                    sprite_sub_renderers
+                        .extend(renderers.into_iter().map(|renderer| (object_id, renderer)));
+                }
+            }
+
+            for (object, ui_element_renderer, ui_size, object_layers) in (
+                &objects,
+                &mut ui_element_renderers,
+                &ui_sizes,
+                layers.maybe(),
+            )
+                .join()
+            {
+                let object_id = object.object_id();
+
+                if !object_hierarchy.is_active(object.object_id())
+                    || !Layers::of(object_layers).intersects(camera.culling_mask)
+                {
                     continue;
                 }
 
@@ -169,12 +1127,14 @@ impl<'a> System<'a> for RenderSystem {
                 ));
             }
 
-            for (object, ui_text_renderer, ui_size) in
-                (&objects, &mut ui_text_renderers, &ui_sizes).join()
+            for (object, ui_text_renderer, ui_size, object_layers) in
+                (&objects, &mut ui_text_renderers, &ui_sizes, layers.maybe()).join()
             {
                 let object_id = object.object_id();
 
-                if !object_hierarchy.is_active(object.object_id()) {
+                if !object_hierarchy.is_active(object.object_id())
+                    || !Layers::of(object_layers).intersects(camera.culling_mask)
+                {
                     continue;
                 }
 
@@ -206,7 +1166,7 @@ impl<'a> System<'a> for RenderSystem {
             }
 
             let mut ui_sub_renderers =
-                Vec::with_capacity(ui_element_sub_renderers.len() + ui_text_sub_renderers.len());
+                frame_alloc.alloc_vec(ui_element_sub_renderers.len() + ui_text_sub_renderers.len());
 
             for (index, object_id, renderer) in &ui_element_sub_renderers {
                 ui_sub_renderers.push((*index, *object_id, renderer as &dyn Renderer));
@@ -218,39 +1178,417 @@ impl<'a> System<'a> for RenderSystem {
 
             ui_sub_renderers.sort_unstable_by_key(|&(index, _, _)| index);
 
-            let mut commands =
-                Vec::with_capacity(mesh_sub_renderers.len() + ui_sub_renderers.len());
+            let light_culling_start = Instant::now();
+
+            for (object_id, renderer) in mesh_sub_renderers.iter_mut() {
+                renderer.pick_lights(object_hierarchy.matrix(*object_id), &local_lights);
+            }
+
+            render_mgr.record_light_culling(light_culling_start.elapsed().as_secs_f32() * 1000.0);
+            render_mgr.record_culled_meshes(meshes_culled);
+            render_mgr.record_material_batches(
+                mesh_sub_renderers
+                    .iter()
+                    .map(|(_, renderer)| renderer.material_handle())
+                    .chain(
+                        instanced_mesh_sub_renderers
+                            .iter()
+                            .map(|(_, renderer, _)| renderer.material_handle()),
+                    )
+                    .collect::<HashSet<_>>()
+                    .len() as u32,
+            );
+            render_mgr.record_terrain_chunks(terrain_chunks.0, terrain_chunks.1);
+
+            let camera_transform = object_hierarchy.matrix(object.object_id());
+            // Meshes sharing a mesh and a material are drawn as instances of one command.
+            let mut world_batches = batch_mesh_sub_renderers(&mesh_sub_renderers);
+
+            for (object_id, renderer) in &hlod_proxy_sub_renderers {
+                world_batches.push(vec![(*object_id, renderer as &dyn Renderer)]);
+            }
+
+            for (object_id, renderer) in &terrain_sub_renderers {
+                world_batches.push(vec![(*object_id, renderer as &dyn Renderer)]);
+            }
+
+            for (object_id, renderer) in &water_sub_renderers {
+                world_batches.push(vec![(*object_id, renderer as &dyn Renderer)]);
+            }
+
+            for (object_id, renderer) in &line_sub_renderers {
+                world_batches.push(vec![(*object_id, renderer as &dyn Renderer)]);
+            }
+
+            // Sprites of a texture drawn one after another are drawn as instances of one command.
+            let sprite_batches = batch_sprites(Vec::from_iter(sprite_sub_renderers.iter().map(
+                |(object_id, renderer)| QueuedSprite {
+                    sorting_order: renderer.sorting_order(),
+                    depth: view_depth(
+                        camera_transform,
+                        Vec3::from(object_hierarchy.matrix(*object_id).row(3)),
+                    ),
+                    batch_key: renderer.batch_key(),
+                    item: (*object_id, renderer as &dyn Renderer),
+                },
+            )));
+
+            // Cameras keeping what the previous ones drew, e.g. overlays, must not cover it with the sky.
+            let skybox_sub_renderer = if matches!(camera.clear_mode, CameraClearMode::All { .. }) {
+                render_mgr.skybox_sub_renderer(context.built_in_shader_mgr(), shader_mgr)
+            } else {
+                None
+            };
+
+            let mut queued_commands = frame_alloc.alloc_vec(
+                world_batches.len()
+                    + instanced_mesh_sub_renderers.len()
+                    + particle_sub_renderers.len()
+                    + sprite_batches.len()
+                    + 1,
+            );
+
+            for batch in world_batches {
+                let command = render_mgr.build_batched_rendering_command(&batch, object_hierarchy);
+                queued_commands.extend(command.map(|command| {
+                    queue_command(
+                        command,
+                        batch.iter().map(|(object_id, _)| *object_id),
+                        object_hierarchy,
+                        camera_transform,
+                    )
+                }));
+            }
+
+            for (object_id, renderer, draw) in &instanced_mesh_sub_renderers {
+                let command = build_instanced_rendering_command(renderer, draw.clone());
+                queued_commands.extend(command.map(|command| {
+                    queue_command(command, [*object_id], object_hierarchy, camera_transform)
+                }));
+            }
+
+            // Blended, so always in the transparent queue.
+            for (object_id, renderer, draw) in &particle_sub_renderers {
+                let command = build_instanced_rendering_command(renderer, draw.clone());
+                queued_commands.extend(command.map(|command| QueuedItem {
+                    queue: RenderQueue::Transparent,
+                    ..queue_command(command, [*object_id], object_hierarchy, camera_transform)
+                }));
+            }
+
+            for batch in sprite_batches {
+                let command =
+                    render_mgr.build_batched_rendering_command(&batch.item, object_hierarchy);
+                queued_commands.extend(command.map(|command| QueuedItem {
+                    queue: batch.queue,
+                    order: batch.order,
+                    depth: batch.depth,
+                    item: command,
+                }));
+            }
+
+            // The material orders it after the other opaque commands, at the far plane.
+            if let Some(renderer) = &skybox_sub_renderer {
+                let matrix = skybox_matrix(
+                    camera_transform,
+                    &camera.projection_matrix(&context.screen_mgr()),
+                );
+                let command = render_mgr.build_detached_rendering_command(&matrix, renderer);
+                queued_commands.extend(command.map(|command| QueuedItem {
+                    queue: command.material.render_queue,
+                    order: command.material.render_order,
+                    depth: f32::INFINITY,
+                    item: command,
+                }));
+            }
+
+            sort_render_queue(
+                &mut queued_commands,
+                render_mgr.is_opaque_front_to_back(),
+                &frame_alloc,
+            );
+
+            // Gizmos are drawn over the scene of the main camera only.
+            let mut debug_draw_sub_renderers = frame_alloc.alloc_vec(0);
+
+            if Some(object.object_id()) == main_camera_id {
+                debug_draw_sub_renderers
+                    .extend(debug_draw_mgr.sub_renderers(shader_mgr, render_mgr.pipeline_cache()));
+            }
+
+            let mut commands = frame_alloc.alloc_vec(
+                queued_commands.len() + debug_draw_sub_renderers.len() + ui_sub_renderers.len(),
+            );
+            commands.extend(queued_commands.into_iter().map(|queued| queued.item));
 
-            for (object_id, renderer) in &mesh_sub_renderers {
+            for renderer in &debug_draw_sub_renderers {
                 let command =
-                    render_mgr.build_rendering_command(*object_id, object_hierarchy, renderer);
-                commands.push(command);
+                    render_mgr.build_detached_rendering_command(&Mat4::identity(), renderer);
+                commands.extend(command);
             }
 
+            // The UI is drawn over the world, in the order of the hierarchy.
             for (_, object_id, renderer) in &ui_sub_renderers {
                 let command =
                     render_mgr.build_rendering_command(*object_id, object_hierarchy, *renderer);
-                commands.push(command);
+                commands.extend(command);
             }
 
-            let mut render_pass = render_mgr
-                .begin_frame_buffer_render_pass(
-                    &mut encoder,
-                    &surface_texture_view,
-                    &camera.clear_mode,
-                )
-                .unwrap();
+            render_mgr
+                .record_draw_calls(commands.len() as u32, hlod_proxy_sub_renderers.len() as u32);
 
-            for cmd in &commands {
-                cmd.render(
-                    &mut render_pass,
+            // Cameras of a stack draw into the layer of their slot, to be composited after every camera.
+            // Cameras with an offscreen target are not stacked.
+            let stack_layer = match (&render_target, &camera.stack) {
+                (None, Some(slot)) => render_mgr.acquire_stack_layer(slot),
+                _ => None,
+            };
+            let clear_mode = match &stack_layer {
+                Some(layer) => layer.clear_mode(&camera.clear_mode),
+                None => camera.clear_mode.clone(),
+            };
+            let clear_mode = render_mgr.color_space().clear_mode(&clear_mode);
+            // Color and depth views of an offscreen pass, or `None` to draw into the surface.
+            let offscreen_views = match (&render_target, &stack_layer) {
+                (Some(target), _) => Some((target.color_view(), target.depth_view())),
+                (None, Some(layer)) => Some((&layer.color, layer.depth.as_ref())),
+                (None, None) => None,
+            };
+            let (color_format, bundle_target_format) = match &render_target {
+                Some(target) => (
+                    target.format(),
+                    BundleTargetFormat {
+                        color: target.format(),
+                        depth_stencil: target.depth_format(),
+                    },
+                ),
+                None => (
+                    render_mgr.scene_color_format(),
+                    render_mgr.bundle_target_format(),
+                ),
+            };
+
+            if render_mgr.is_capturing_frame() {
+                let view_projection = camera.view_projection_matrix(
+                    &context.screen_mgr(),
+                    object_hierarchy.matrix(object.object_id()),
+                );
+                let target = match offscreen_views {
+                    Some((color_view, _)) => CapturePassTarget::Texture {
+                        view: color_view,
+                        width: target_width,
+                        height: target_height,
+                        format: color_format,
+                    },
+                    None => CapturePassTarget::Surface,
+                };
+                render_mgr.record_capture_pass(
+                    format!("camera #{}", camera_index),
+                    target,
+                    &clear_mode,
+                    &view_projection,
+                    &commands,
+                    shader_mgr,
+                );
+            }
+
+            // Bundles draw with the stencil reference of the pass, so every command must share it.
+            let parallel_stencil_reference =
+                if 1 < encoder_threads && MIN_COMMANDS_PER_RECORDING_THREAD * 2 <= commands.len() {
+                    shared_stencil_reference(&commands)
+                } else {
+                    None
+                };
+
+            if let Some(stencil_reference) = parallel_stencil_reference {
+                let recording = record_in_parallel(
+                    &context.gfx_ctx().device,
+                    bundle_target_format,
+                    &commands,
+                    encoder_threads,
                     &camera.bind_group,
                     &self.screen_size_bind_group,
+                    &lights_bind_group,
+                );
+                render_mgr.record_encoder_timings(&recording.thread_ms);
+
+                let mut render_pass = match offscreen_views {
+                    Some((color_view, depth_view)) => render_mgr.begin_viewport_render_pass(
+                        &mut encoder,
+                        color_view,
+                        depth_view.map(|view| view.as_ref()),
+                        &clear_mode,
+                        viewport,
+                    ),
+                    None => render_mgr
+                        .begin_frame_buffer_render_pass(
+                            &mut encoder,
+                            scene_texture_view,
+                            &clear_mode,
+                            viewport,
+                        )
+                        .unwrap(),
+                };
+                render_pass.set_stencil_reference(stencil_reference);
+                render_pass.execute_bundles(recording.bundles.iter());
+            } else {
+                let start = Instant::now();
+                let mut render_pass = match offscreen_views {
+                    Some((color_view, depth_view)) => render_mgr.begin_viewport_render_pass(
+                        &mut encoder,
+                        color_view,
+                        depth_view.map(|view| view.as_ref()),
+                        &clear_mode,
+                        viewport,
+                    ),
+                    None => render_mgr
+                        .begin_frame_buffer_render_pass(
+                            &mut encoder,
+                            scene_texture_view,
+                            &clear_mode,
+                            viewport,
+                        )
+                        .unwrap(),
+                };
+
+                for cmd in &commands {
+                    cmd.render_in_pass(
+                        &mut render_pass,
+                        &camera.bind_group,
+                        &self.screen_size_bind_group,
+                        &lights_bind_group,
+                    );
+                }
+
+                drop(render_pass);
+                render_mgr.record_encoder_timings(&[start.elapsed().as_secs_f32() * 1000.0]);
+            }
+
+            // Fogs the world of the main camera before the cameras drawn over it, e.g. for the UI.
+            // The froxels span the whole surface, so a main camera drawing into a part of it is not fogged.
+            // Stacked cameras do not draw into the depth the fog reads either.
+            if Some(object.object_id()) == main_camera_id
+                && viewport.is_none()
+                && offscreen_views.is_none()
+            {
+                let camera_transform = object_hierarchy.matrix(object.object_id());
+                render_mgr.encode_volumetric_fog(
+                    &mut encoder,
+                    scene_texture_view,
+                    &FogView {
+                        view_projection: camera
+                            .view_projection_matrix(&context.screen_mgr(), camera_transform),
+                        position: Vec3::from(camera_transform.row(3)),
+                        near: camera.projection().near(),
+                    },
+                    &visible_fog_volumes,
                 );
             }
         }
 
+        render_mgr.compose_camera_stacks(&mut encoder, scene_texture_view);
+        render_mgr.encode_post_processing(&mut encoder, &output_texture_view);
+
+        // Custom passes, overlays and the debug UI are not part of captures.
+        render_mgr.encode_frame_capture(&mut encoder, surface_texture.texture());
+
+        for index in order {
+            if let Some(position) = custom_pass_indices.iter().position(|&i| i == index) {
+                render_mgr.custom_passes_mut()[position]
+                    .execute(&mut encoder, &surface_texture_view);
+            }
+        }
+
+        render_mgr.encode_overlays(&mut encoder, &surface_texture_view);
+        #[cfg(feature = "egui")]
+        context
+            .egui_integration_mut()
+            .encode(&mut encoder, &surface_texture_view);
+        render_mgr.encode_screenshots(&mut encoder, surface_texture.texture());
         render_mgr.finish_frame(vec![encoder.finish()]);
-        surface_texture.present();
+        render_mgr.present(surface_texture);
+
+        let frame_report = render_mgr.frame_report();
+        context
+            .time_mgr_mut()
+            .report_render_counters(FrameRenderCounters {
+                draw_calls: frame_report.draw_calls,
+                pipeline_cache_hits: frame_report.pipeline_cache.hits,
+                pipeline_cache_misses: frame_report.pipeline_cache.misses,
+                frame_buffer_bytes: frame_report.frame_buffer_bytes,
+                gpu_ms: frame_report.gpu_ms,
+            });
+
+        for (path, result) in render_mgr.collect_screenshots() {
+            match result {
+                Ok(()) => context.logger().log(
+                    StandardLogLevel::Info,
+                    format!("screenshot saved to {}", path.display()),
+                ),
+                Err(err) => context.logger().log(
+                    StandardLogLevel::Error,
+                    format!("failed to save screenshot {}: {}", path.display(), err),
+                ),
+            }
+        }
+
+        if let Some((path, result)) = render_mgr.collect_frame_capture() {
+            match result {
+                Ok(()) => context.logger().log(
+                    StandardLogLevel::Info,
+                    format!("frame capture saved to {}", path.display()),
+                ),
+                Err(err) => context.logger().log(
+                    StandardLogLevel::Error,
+                    format!("failed to save frame capture {}: {}", path.display(), err),
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate_cameras(clear_modes: &[CameraClearMode]) -> Vec<FrameGraphDiagnostic> {
+        let mut frame_graph = FrameGraph::new();
+
+        for (index, clear_mode) in clear_modes.iter().enumerate() {
+            frame_graph.add_pass(
+                format!("camera #{}", index),
+                declare_camera_resources(clear_mode),
+            );
+        }
+
+        let (order, cycle) = frame_graph.resolve_order();
+        assert_eq!(cycle, None);
+        frame_graph.validate(&order)
+    }
+
+    #[test]
+    fn check_depth_only_overlay_camera_is_valid() {
+        let scene = CameraClearMode::All {
+            color: Color::from_rgb(0.1, 0.1, 0.1),
+            depth: 1.0,
+            stencil: 0,
+        };
+        let overlay = CameraClearMode::DepthOnly {
+            depth: 1.0,
+            stencil: 0,
+        };
+
+        assert!(validate_cameras(&[scene.clone(), overlay.clone(), overlay]).is_empty());
+        assert!(validate_cameras(&[scene.clone(), CameraClearMode::Keep]).is_empty());
+
+        // A second camera clearing the whole surface still discards the scene.
+        assert_eq!(
+            validate_cameras(&[scene.clone(), scene]),
+            vec![FrameGraphDiagnostic::DoubleWrite {
+                resource: "surface".to_owned(),
+                first: "camera #0".to_owned(),
+                second: "camera #1".to_owned(),
+            }]
+        );
     }
 }
@@ -0,0 +1,171 @@
+use specs::{RunNow, World};
+use std::collections::HashSet;
+
+/// Identifies a system registered to a [`SystemRegistry`], to remove it later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SystemId(u64);
+
+struct RegisteredSystem {
+    id: SystemId,
+    priority: i32,
+    system: Box<dyn for<'a> RunNow<'a>>,
+}
+
+/// User systems run every frame after the `Update` event, before the object matrices are updated and the frame is rendered.
+/// Systems run in ascending order of priority, and in registration order within the same priority.
+///
+/// Changes take effect on the next frame, so that systems may register and remove systems while running.
+pub struct SystemRegistry {
+    next_id: u64,
+    systems: Vec<RegisteredSystem>,
+    active: HashSet<SystemId>,
+    added: Vec<RegisteredSystem>,
+    removed: Vec<SystemId>,
+}
+
+impl SystemRegistry {
+    pub fn new() -> Self {
+        Self {
+            next_id: 0,
+            systems: Vec::new(),
+            active: HashSet::new(),
+            added: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+
+    /// Number of systems that run on the next frame.
+    pub fn system_count(&self) -> usize {
+        self.active.len() + self.added.len()
+    }
+
+    pub fn register(
+        &mut self,
+        priority: i32,
+        system: impl for<'a> RunNow<'a> + 'static,
+    ) -> SystemId {
+        let id = SystemId(self.next_id);
+        self.next_id += 1;
+        self.added.push(RegisteredSystem {
+            id,
+            priority,
+            system: Box::new(system),
+        });
+        id
+    }
+
+    /// Removes the system. Returns `false` if it was not registered or already removed.
+    pub fn remove(&mut self, id: SystemId) -> bool {
+        if let Some(index) = self.added.iter().position(|system| system.id == id) {
+            self.added.remove(index);
+            return true;
+        }
+
+        if !self.active.remove(&id) {
+            return false;
+        }
+
+        self.removed.push(id);
+        true
+    }
+
+    /// Applies the changes made since the last frame and takes the systems out to run them.
+    /// Newly registered systems are set up against the world first.
+    /// They must be given back by [`end_frame`](Self::end_frame).
+    pub fn begin_frame(&mut self, world: &mut World) -> SystemFrame {
+        let removed = std::mem::take(&mut self.removed);
+        self.systems.retain(|system| !removed.contains(&system.id));
+
+        for mut system in self.added.drain(..) {
+            system.system.setup(world);
+            self.active.insert(system.id);
+            self.systems.push(system);
+        }
+
+        // Stable, so that registration order breaks ties.
+        self.systems.sort_by_key(|system| system.priority);
+
+        SystemFrame {
+            systems: std::mem::take(&mut self.systems),
+        }
+    }
+
+    pub fn end_frame(&mut self, frame: SystemFrame) {
+        self.systems = frame.systems;
+    }
+}
+
+/// Systems taken out of a [`SystemRegistry`] for a frame.
+pub struct SystemFrame {
+    systems: Vec<RegisteredSystem>,
+}
+
+impl SystemFrame {
+    pub fn run(&mut self, world: &World) {
+        for system in &mut self.systems {
+            system.system.run_now(world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::{System, WorldExt, Write};
+
+    #[derive(Default)]
+    struct Trace(Vec<&'static str>);
+
+    struct Push(&'static str);
+
+    impl<'a> System<'a> for Push {
+        type SystemData = Write<'a, Trace>;
+
+        fn run(&mut self, mut trace: Self::SystemData) {
+            trace.0.push(self.0);
+        }
+    }
+
+    fn run_frame(registry: &mut SystemRegistry, world: &mut World) -> Vec<&'static str> {
+        let mut frame = registry.begin_frame(world);
+        frame.run(world);
+        registry.end_frame(frame);
+        std::mem::take(&mut world.write_resource::<Trace>().0)
+    }
+
+    #[test]
+    fn check_systems_run_by_priority() {
+        let mut world = World::new();
+        let mut registry = SystemRegistry::new();
+        registry.register(10, Push("late"));
+        registry.register(-5, Push("early"));
+        registry.register(10, Push("later"));
+
+        assert_eq!(
+            run_frame(&mut registry, &mut world),
+            vec!["early", "late", "later"]
+        );
+    }
+
+    #[test]
+    fn check_changes_apply_on_next_frame() {
+        let mut world = World::new();
+        let mut registry = SystemRegistry::new();
+        let first = registry.register(0, Push("first"));
+
+        let mut frame = registry.begin_frame(&mut world);
+        let second = registry.register(1, Push("second"));
+        assert!(registry.remove(first));
+        frame.run(&world);
+        registry.end_frame(frame);
+
+        assert_eq!(world.write_resource::<Trace>().0, vec!["first"]);
+        world.write_resource::<Trace>().0.clear();
+
+        assert_eq!(run_frame(&mut registry, &mut world), vec!["second"]);
+        assert!(!registry.remove(first));
+        assert!(registry.remove(second));
+        assert!(run_frame(&mut registry, &mut world).is_empty());
+        assert_eq!(registry.system_count(), 0);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    animation::{AnimationPlayer, BoneTrack},
+    math::Vec3,
+    object::Object,
+    transform::Transform,
+    world_ext::write_if_changed,
+    ContextHandle,
+};
+use specs::prelude::*;
+
+/// Advances the animation players and writes the sampled channels into the transforms of the objects named after
+/// the bones. Runs before the object matrices are updated, so that the skins and the objects attached to the bones
+/// follow on the same frame.
+pub struct UpdateAnimationPlayersSystem {
+    ctx: ContextHandle,
+    is_animating: bool,
+}
+
+impl UpdateAnimationPlayersSystem {
+    pub fn new(ctx: ContextHandle) -> Self {
+        Self {
+            ctx,
+            is_animating: false,
+        }
+    }
+
+    /// Returns `true` if any active player was still playing after the last run.
+    pub fn is_animating(&self) -> bool {
+        self.is_animating
+    }
+}
+
+impl<'a> System<'a> for UpdateAnimationPlayersSystem {
+    type SystemData = (
+        ReadStorage<'a, Object>,
+        WriteStorage<'a, AnimationPlayer>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, (objects, mut players, mut transforms): Self::SystemData) {
+        let mut object_mgr = self.ctx.object_mgr_mut();
+        let delta_time = self.ctx.time_mgr().delta_time();
+        let mut moved = Vec::new();
+        self.is_animating = false;
+
+        {
+            let object_name_registry = object_mgr.object_name_registry();
+            let object_hierarchy = object_mgr.object_hierarchy();
+
+            for (object, player) in (&objects, &mut players).join() {
+                let object_id = object.object_id();
+
+                if !object_hierarchy.is_active(object_id) {
+                    continue;
+                }
+
+                let (clip, time) = match player.advance(delta_time) {
+                    Some(sample) => sample,
+                    None => continue,
+                };
+
+                if player.is_playing() {
+                    self.is_animating = true;
+                }
+
+                let descendants = object_hierarchy.object_and_children(object_id);
+                let targets = player.targets(
+                    |bone| {
+                        descendants.iter().copied().find(|&descendant| {
+                            object_name_registry.name(descendant).map(String::as_str) == Some(bone)
+                        })
+                    },
+                    |target| !object_hierarchy.contains(target),
+                );
+
+                for (track, &target) in clip.tracks.iter().zip(targets) {
+                    let target = match target {
+                        Some(target) => target,
+                        None => continue,
+                    };
+
+                    // Tracks holding their last key leave the transform as it is.
+                    if write_if_changed(
+                        &mut transforms,
+                        object_hierarchy.entity(target),
+                        |transform| apply(track, time, transform),
+                    ) {
+                        moved.push(target);
+                    }
+                }
+            }
+        }
+
+        let object_hierarchy = object_mgr.object_hierarchy_mut();
+
+        for object_id in moved {
+            object_hierarchy.set_dirty(object_id);
+        }
+    }
+}
+
+/// Writes the animated channels of the track at `time` into the transform, leaving the others.
+fn apply(track: &BoneTrack, time: f32, transform: &mut Transform) {
+    if let Some(rotation) = track
+        .rotation
+        .as_ref()
+        .and_then(|rotation| rotation.evaluate(time))
+    {
+        transform.rotation = rotation;
+    }
+
+    if let Some([x, y, z]) = &track.position {
+        transform.position = Vec3::new(x.evaluate(time), y.evaluate(time), z.evaluate(time));
+    }
+
+    if let Some([x, y, z]) = &track.scale {
+        transform.scale = Vec3::new(x.evaluate(time), y.evaluate(time), z.evaluate(time));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::{
+    animation::BoneAttachment, gfx::MeshRenderer, object::Object, transform::Transform,
+    ContextHandle,
+};
+use logging::StandardLogLevel;
+use specs::prelude::*;
+
+/// Moves the objects attached to bones along with the poses of the bones. Runs after the animation players and
+/// before the object matrices are updated, so that the attached objects and their children follow on the same frame.
+pub struct UpdateBoneAttachmentsSystem {
+    ctx: ContextHandle,
+}
+
+impl UpdateBoneAttachmentsSystem {
+    pub fn new(ctx: ContextHandle) -> Self {
+        Self { ctx }
+    }
+}
+
+impl<'a> System<'a> for UpdateBoneAttachmentsSystem {
+    type SystemData = (
+        ReadStorage<'a, Object>,
+        WriteStorage<'a, BoneAttachment>,
+        ReadStorage<'a, MeshRenderer>,
+        ReadStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, (objects, mut attachments, mesh_renderers, transforms): Self::SystemData) {
+        let mut object_mgr = self.ctx.object_mgr_mut();
+        let object_hierarchy = object_mgr.object_hierarchy_mut();
+
+        for (object, attachment) in (&objects, &mut attachments).join() {
+            let object_id = object.object_id();
+
+            if !object_hierarchy.is_active(object_id) {
+                continue;
+            }
+
+            let renderer = attachment.renderer();
+            let bone_object = Some(renderer)
+                .filter(|&renderer| object_hierarchy.contains(renderer))
+                .and_then(|renderer| mesh_renderers.get(object_hierarchy.entity(renderer)))
+                .and_then(|mesh_renderer| mesh_renderer.skin())
+                .and_then(|skin| skin.bone_object(attachment.bone()));
+
+            if attachment.update(object_hierarchy, object_id, bone_object, |entity| {
+                transforms.get(entity)
+            }) {
+                self.ctx.logger().log(
+                    StandardLogLevel::Warning,
+                    format!(
+                        "bone attachment kept its last pose: no bone `{}` in the skin of its renderer",
+                        attachment.bone().name()
+                    ),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{
+    gfx::{sample_waves_at, Buoyancy, WaterSurface},
+    math::Vec2,
+    object::Object,
+    transform::Transform,
+    world_ext::write_if_changed,
+    ContextHandle,
+};
+use specs::prelude::*;
+
+/// Floats the objects with a [`Buoyancy`] on their water surfaces and writes their poses into their transforms.
+pub struct UpdateBuoyancySystem {
+    ctx: ContextHandle,
+    is_animating: bool,
+}
+
+impl UpdateBuoyancySystem {
+    pub fn new(ctx: ContextHandle) -> Self {
+        Self {
+            ctx,
+            is_animating: false,
+        }
+    }
+
+    /// Returns `true` if any water surface had waves during the last run.
+    pub fn is_animating(&self) -> bool {
+        self.is_animating
+    }
+}
+
+impl<'a> System<'a> for UpdateBuoyancySystem {
+    type SystemData = (
+        ReadStorage<'a, Object>,
+        ReadStorage<'a, WaterSurface>,
+        WriteStorage<'a, Buoyancy>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, (objects, water_surfaces, mut buoyancies, mut transforms): Self::SystemData) {
+        let mut object_mgr = self.ctx.object_mgr_mut();
+        let object_hierarchy = object_mgr.object_hierarchy_mut();
+        let (time, delta_time) = {
+            let time_mgr = self.ctx.time_mgr();
+            (time_mgr.time().as_secs_f32(), time_mgr.delta_time())
+        };
+        self.is_animating = water_surfaces
+            .join()
+            .any(|water_surface| !water_surface.waves().is_empty());
+
+        for (object, buoyancy) in (&objects, &mut buoyancies).join() {
+            let object_id = object.object_id();
+
+            if !object_hierarchy.is_active(object_id) || !object_hierarchy.is_active(buoyancy.water)
+            {
+                continue;
+            }
+
+            let water_surface = if let Some(water_surface) =
+                water_surfaces.get(object_hierarchy.entity(buoyancy.water))
+            {
+                water_surface
+            } else {
+                continue;
+            };
+
+            let position = match transforms.get(object.entity()) {
+                Some(transform) => transform.position,
+                None => continue,
+            };
+            let rest_height = object_hierarchy.matrix(buoyancy.water).row(3).y;
+            let sample = sample_waves_at(
+                water_surface.waves(),
+                Vec2::new(position.x, position.z),
+                time,
+            );
+            // A body at rest on still water keeps its transform, so that it is not reported as modified.
+            let is_moved = write_if_changed(&mut transforms, object.entity(), |transform| {
+                buoyancy.step(
+                    transform,
+                    rest_height + sample.displacement.y,
+                    sample.normal,
+                    delta_time,
+                );
+            });
+
+            if is_moved {
+                object_hierarchy.set_dirty(object_id);
+            }
+        }
+    }
+}
@@ -12,14 +12,15 @@ impl UpdateCameraTransformBufferSystem {
 }
 
 impl<'a> System<'a> for UpdateCameraTransformBufferSystem {
-    type SystemData = (ReadStorage<'a, Object>, ReadStorage<'a, Camera>);
+    type SystemData = (ReadStorage<'a, Object>, WriteStorage<'a, Camera>);
 
-    fn run(&mut self, (objects, cameras): Self::SystemData) {
+    fn run(&mut self, (objects, mut cameras): Self::SystemData) {
         let world_mgr = self.ctx.object_mgr();
         let screen_mgr = self.ctx.screen_mgr();
+        let mut render_mgr = self.ctx.render_mgr_mut();
         let object_hierarchy = world_mgr.object_hierarchy();
 
-        for (object, camera) in (&objects, &cameras).join() {
+        for (object, camera) in (&objects, &mut cameras).join() {
             if !object_hierarchy.is_active(object.object_id()) {
                 continue;
             }
@@ -27,7 +28,7 @@ impl<'a> System<'a> for UpdateCameraTransformBufferSystem {
             let object_id = object.object_id();
             let matrix = object_hierarchy.matrix(object_id);
 
-            camera.update_buffer(&screen_mgr, &self.ctx.gfx_ctx.queue, matrix);
+            camera.update_buffer(&screen_mgr, render_mgr.uploader_mut(), matrix);
         }
     }
 }
@@ -0,0 +1,48 @@
+use crate::{
+    gfx::{Cloth, MeshRenderer},
+    object::Object,
+    ContextHandle,
+};
+use specs::prelude::*;
+
+/// Writes the simulated [`Cloth`]es into the [`MeshRenderer`]s of their objects. Runs once per frame, after the
+/// object matrices are updated.
+pub struct UpdateClothMeshesSystem {
+    ctx: ContextHandle,
+}
+
+impl UpdateClothMeshesSystem {
+    pub fn new(ctx: ContextHandle) -> Self {
+        Self { ctx }
+    }
+}
+
+impl<'a> System<'a> for UpdateClothMeshesSystem {
+    type SystemData = (
+        ReadStorage<'a, Object>,
+        WriteStorage<'a, Cloth>,
+        WriteStorage<'a, MeshRenderer>,
+    );
+
+    fn run(&mut self, (objects, mut cloths, mut mesh_renderers): Self::SystemData) {
+        let object_mgr = self.ctx.object_mgr();
+        let object_hierarchy = object_mgr.object_hierarchy();
+        let mut render_mgr = self.ctx.render_mgr_mut();
+
+        for (object, cloth, mesh_renderer) in (&objects, &mut cloths, &mut mesh_renderers).join() {
+            let object_id = object.object_id();
+
+            if !cloth.is_mesh_dirty() || !object_hierarchy.is_active(object_id) {
+                continue;
+            }
+
+            let vertices = cloth.vertices(&object_hierarchy.matrix(object_id).inversed());
+            mesh_renderer.set_dynamic_vertices(
+                &vertices,
+                &self.ctx.gfx_ctx().device,
+                render_mgr.uploader_mut(),
+            );
+            cloth.clear_mesh_dirty();
+        }
+    }
+}
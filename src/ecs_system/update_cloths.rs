@@ -0,0 +1,102 @@
+use crate::{
+    gfx::{Camera, Cloth, ClothCollider},
+    object::{Object, ObjectId},
+    ContextHandle,
+};
+use specs::prelude::*;
+
+/// Simulates the [`Cloth`]es by one fixed update. Runs once per fixed update, after the fixed systems.
+pub struct UpdateClothsSystem {
+    ctx: ContextHandle,
+    is_animating: bool,
+}
+
+impl UpdateClothsSystem {
+    pub fn new(ctx: ContextHandle) -> Self {
+        Self {
+            ctx,
+            is_animating: false,
+        }
+    }
+
+    /// Returns `true` if any active cloth was simulated during the last run.
+    pub fn is_animating(&self) -> bool {
+        self.is_animating
+    }
+}
+
+impl<'a> System<'a> for UpdateClothsSystem {
+    type SystemData = (
+        ReadStorage<'a, Object>,
+        ReadStorage<'a, Camera>,
+        ReadStorage<'a, ClothCollider>,
+        WriteStorage<'a, Cloth>,
+    );
+
+    fn run(&mut self, (objects, cameras, colliders, mut cloths): Self::SystemData) {
+        let object_mgr = self.ctx.object_mgr();
+        let object_hierarchy = object_mgr.object_hierarchy();
+        let delta_time = self.ctx.time_mgr().fixed_delta_time().as_secs_f32();
+        let global_wind = self.ctx.global_wind();
+        let is_active = |object_id: ObjectId| {
+            object_mgr.is_alive(object_id) && object_hierarchy.is_active(object_id)
+        };
+        let frustums = {
+            let screen_mgr = self.ctx.screen_mgr();
+            Vec::from_iter(
+                (&objects, &cameras)
+                    .join()
+                    .filter(|(object, _)| is_active(object.object_id()))
+                    .map(|(object, camera)| {
+                        camera.frustum(&screen_mgr, object_hierarchy.matrix(object.object_id()))
+                    }),
+            )
+        };
+        self.is_animating = false;
+
+        for (object, cloth) in (&objects, &mut cloths).join() {
+            let object_id = object.object_id();
+
+            if !is_active(object_id) {
+                continue;
+            }
+
+            // Without any camera, there is nothing to tell on-screen cloth from off-screen cloth.
+            let is_visible = frustums.is_empty()
+                || cloth.bounds().map_or(true, |(min, max)| {
+                    frustums
+                        .iter()
+                        .any(|frustum| frustum.intersects_aabb(min, max))
+                });
+            let iterations = match cloth.settings.offscreen_iterations {
+                Some(iterations) if !is_visible => iterations,
+                _ => cloth.settings.iterations,
+            };
+
+            if iterations == 0 {
+                continue;
+            }
+
+            let collider_shapes =
+                Vec::from_iter(cloth.colliders().iter().filter_map(|&collider| {
+                    if !is_active(collider) {
+                        return None;
+                    }
+
+                    colliders
+                        .get(object_hierarchy.entity(collider))
+                        .map(|shape| shape.to_world(object_hierarchy.matrix(collider)))
+                }));
+
+            cloth.step(
+                object_hierarchy.matrix(object_id),
+                |attached| is_active(attached).then(|| object_hierarchy.matrix(attached).clone()),
+                &collider_shapes,
+                global_wind,
+                delta_time,
+                iterations,
+            );
+            self.is_animating = true;
+        }
+    }
+}
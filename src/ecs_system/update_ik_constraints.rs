@@ -0,0 +1,107 @@
+use crate::{
+    animation::{solve_two_bone_ik, IkConstraint},
+    math::{Mat4, Quat},
+    object::Object,
+    transform::Transform,
+    world_ext::write_if_changed,
+    ContextHandle,
+};
+use specs::prelude::*;
+
+/// Bends the chains ending at the objects with an [`IkConstraint`] and writes the rotations into the transforms
+/// of their parents and grandparents. Runs after the animations, so that it corrects the animated pose.
+///
+/// Chains with attachment matrices are solved as if they had none.
+pub struct UpdateIkConstraintsSystem {
+    ctx: ContextHandle,
+}
+
+impl UpdateIkConstraintsSystem {
+    pub fn new(ctx: ContextHandle) -> Self {
+        Self { ctx }
+    }
+}
+
+impl<'a> System<'a> for UpdateIkConstraintsSystem {
+    type SystemData = (
+        ReadStorage<'a, Object>,
+        ReadStorage<'a, IkConstraint>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, (objects, constraints, mut transforms): Self::SystemData) {
+        let mut object_mgr = self.ctx.object_mgr_mut();
+        let object_hierarchy = object_mgr.object_hierarchy_mut();
+
+        for (object, constraint) in (&objects, &constraints).join() {
+            let end = object.object_id();
+
+            if !object_hierarchy.is_active(end) || constraint.weight <= 0.0 {
+                continue;
+            }
+
+            let (mid, root) = match object_hierarchy
+                .parent(end)
+                .and_then(|mid| Some((mid, object_hierarchy.parent(mid)?)))
+            {
+                Some(chain) => chain,
+                None => continue,
+            };
+
+            // The matrices of the chain are a frame old at this point; the parent of the root only moves the chain.
+            let root_parent = object_hierarchy
+                .parent(root)
+                .map_or_else(Mat4::identity, |parent| {
+                    object_hierarchy.matrix(parent).clone()
+                });
+            let local_matrix = |object| {
+                transforms
+                    .get(object_hierarchy.entity(object))
+                    .map(Transform::matrix)
+            };
+            let (root_local, mid_local, end_local) =
+                match (local_matrix(root), local_matrix(mid), local_matrix(end)) {
+                    (Some(root), Some(mid), Some(end)) => (root, mid, end),
+                    _ => continue,
+                };
+
+            let root_world = root_local * &root_parent;
+            let mid_world = mid_local * &root_world;
+            let end_world = end_local * &mid_world;
+            let (_, parent_rotation, _) = root_parent.split();
+            let (root_position, root_rotation, _) = root_world.split();
+            let (mid_position, mid_rotation, _) = mid_world.split();
+            let (end_position, _, _) = end_world.split();
+
+            let solution = solve_two_bone_ik(
+                root_position,
+                mid_position,
+                end_position,
+                constraint.target,
+                constraint.pole,
+            );
+            let weight = constraint.weight.min(1.0);
+            let root_delta = Quat::slerp(Quat::IDENTITY, solution.root, weight);
+            let mid_delta = Quat::slerp(Quat::IDENTITY, solution.mid, weight);
+            let root_rotation = root_delta * root_rotation;
+            let mid_rotation = mid_delta * root_delta * mid_rotation;
+
+            // A solved chain whose target stays keeps its transforms, so that it is not reported as modified.
+            let is_root_moved = write_if_changed(
+                &mut transforms,
+                object_hierarchy.entity(root),
+                |transform| {
+                    transform.rotation = (parent_rotation.inverted() * root_rotation).normalized();
+                },
+            );
+            let is_mid_moved =
+                write_if_changed(&mut transforms, object_hierarchy.entity(mid), |transform| {
+                    transform.rotation = (root_rotation.inverted() * mid_rotation).normalized();
+                });
+
+            if is_root_moved || is_mid_moved {
+                object_hierarchy.set_dirty(root);
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use crate::{
+    gfx::{DirectionalLight, LightsUniform, PointLight, SpotLight},
+    math::Vec3,
+    object::Object,
+    ContextHandle,
+};
+use specs::prelude::*;
+
+/// Writes the lights of the active objects into the `lights` uniform of the scene. Runs once per frame, after the
+/// object matrices are updated.
+pub struct UpdateLightsSystem {
+    ctx: ContextHandle,
+}
+
+impl UpdateLightsSystem {
+    pub fn new(ctx: ContextHandle) -> Self {
+        Self { ctx }
+    }
+}
+
+impl<'a> System<'a> for UpdateLightsSystem {
+    type SystemData = (
+        ReadStorage<'a, Object>,
+        ReadStorage<'a, DirectionalLight>,
+        ReadStorage<'a, PointLight>,
+        ReadStorage<'a, SpotLight>,
+    );
+
+    fn run(&mut self, (objects, directional_lights, point_lights, spot_lights): Self::SystemData) {
+        let object_mgr = self.ctx.object_mgr();
+        let object_hierarchy = object_mgr.object_hierarchy();
+        let mut render_mgr = self.ctx.render_mgr_mut();
+
+        let directional = (&objects, &directional_lights)
+            .join()
+            .filter(|(object, _)| object_hierarchy.is_active(object.object_id()))
+            .map(|(object, light)| {
+                let (_, rotation, _) = object_hierarchy.matrix(object.object_id()).split();
+                (rotation * Vec3::FORWARD, light)
+            });
+        let point = (&objects, &point_lights)
+            .join()
+            .filter(|(object, _)| object_hierarchy.is_active(object.object_id()))
+            .map(|(object, light)| {
+                let (position, _, _) = object_hierarchy.matrix(object.object_id()).split();
+                light.data(position)
+            });
+        let spot = (&objects, &spot_lights)
+            .join()
+            .filter(|(object, _)| object_hierarchy.is_active(object.object_id()))
+            .map(|(object, light)| {
+                let (position, rotation, _) = object_hierarchy.matrix(object.object_id()).split();
+                light.data(position, rotation * Vec3::FORWARD)
+            });
+        let uniform = LightsUniform::new(
+            *render_mgr.lights().ambient(),
+            directional,
+            point.chain(spot),
+        );
+
+        let (lights, uploader) = render_mgr.split_lights();
+        lights.upload(&uniform, uploader);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{
+    math::Vec3,
+    navigation::{NavAgent, NavMesh, NavNeighbor},
+    object::Object,
+    transform::Transform,
+    ContextHandle,
+};
+use specs::prelude::*;
+use std::sync::Arc;
+
+/// Searches the paths of the navigation agents, steers them and writes their positions into their transforms.
+pub struct UpdateNavAgentsSystem {
+    ctx: ContextHandle,
+    navmesh: Option<Arc<NavMesh>>,
+    neighbors: Vec<(Entity, NavNeighbor)>,
+    is_animating: bool,
+}
+
+impl UpdateNavAgentsSystem {
+    pub fn new(ctx: ContextHandle) -> Self {
+        Self {
+            ctx,
+            navmesh: None,
+            neighbors: Vec::new(),
+            is_animating: false,
+        }
+    }
+
+    /// Returns `true` if any active agent was still moving during the last run.
+    pub fn is_animating(&self) -> bool {
+        self.is_animating
+    }
+}
+
+impl<'a> System<'a> for UpdateNavAgentsSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Object>,
+        WriteStorage<'a, NavAgent>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, (entities, objects, mut agents, mut transforms): Self::SystemData) {
+        let navmesh = self.ctx.navigation_mgr().navmesh().cloned();
+        let mut object_mgr = self.ctx.object_mgr_mut();
+        let object_hierarchy = object_mgr.object_hierarchy_mut();
+        let delta_time = self.ctx.time_mgr().delta_time();
+        self.is_animating = false;
+
+        let navmesh_changed = match (&self.navmesh, &navmesh) {
+            (Some(old), Some(new)) => !Arc::ptr_eq(old, new),
+            (None, None) => false,
+            _ => true,
+        };
+        self.navmesh = navmesh;
+
+        let navmesh = match &self.navmesh {
+            Some(navmesh) => navmesh,
+            None => return,
+        };
+
+        // Snapshot the agents first, so that each one avoids where the others were at the start of the frame.
+        self.neighbors.clear();
+
+        for (entity, object, agent, transform) in (&entities, &objects, &agents, &transforms).join()
+        {
+            if !object_hierarchy.is_active(object.object_id()) {
+                continue;
+            }
+
+            self.neighbors.push((
+                entity,
+                NavNeighbor {
+                    position: transform.position,
+                    velocity: agent.velocity(),
+                    radius: agent.radius,
+                },
+            ));
+        }
+
+        for (entity, object, agent) in (&entities, &objects, &mut agents).join() {
+            let object_id = object.object_id();
+
+            if !object_hierarchy.is_active(object_id) {
+                continue;
+            }
+
+            // The transform is only taken mutably once the agent moves, so that resting agents are not reported
+            // as modified.
+            let current = match transforms.get(entity) {
+                Some(transform) => transform.position,
+                None => continue,
+            };
+
+            if navmesh_changed {
+                agent.set_path(None);
+            }
+
+            if agent.needs_path() {
+                if let Some(destination) = agent.destination() {
+                    agent.set_path(navmesh.find_path(current, destination));
+                }
+            }
+
+            let mut desired_velocity = agent.desired_velocity(current);
+
+            if agent.avoids_agents && desired_velocity != Vec3::ZERO {
+                let neighbors = self
+                    .neighbors
+                    .iter()
+                    .filter(|(neighbor, _)| *neighbor != entity)
+                    .map(|(_, neighbor)| *neighbor)
+                    .collect::<Vec<_>>();
+                desired_velocity += agent.avoidance(current, &neighbors);
+            }
+
+            let step = agent.steer(desired_velocity, delta_time);
+
+            if step == Vec3::ZERO {
+                continue;
+            }
+
+            let target = current + step;
+
+            // Avoidance may push the agent off the mesh; pull it back and keep only the velocity along the mesh.
+            let position = match navmesh.closest_point_on_navmesh(target) {
+                Some(point) => point.position,
+                None => target,
+            };
+
+            if 0.0 < delta_time {
+                agent.set_velocity((position - current) / delta_time);
+            }
+
+            if position == current {
+                continue;
+            }
+
+            transforms.get_mut(entity).unwrap().position = position;
+            object_hierarchy.set_dirty(object_id);
+            self.is_animating = true;
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{
+    animation::{PathFollower, PathSource},
+    event::event_types::PathMarkerReached,
+    object::Object,
+    transform::Transform,
+    world_ext::write_if_changed,
+    ContextHandle,
+};
+use logging::StandardLogLevel;
+use specs::prelude::*;
+
+/// Advances the path followers and writes their poses into their transforms.
+pub struct UpdatePathFollowersSystem {
+    ctx: ContextHandle,
+    reached_markers: Vec<PathMarkerReached>,
+    is_animating: bool,
+}
+
+impl UpdatePathFollowersSystem {
+    pub fn new(ctx: ContextHandle) -> Self {
+        Self {
+            ctx,
+            reached_markers: Vec::new(),
+            is_animating: false,
+        }
+    }
+
+    /// Returns `true` if any active follower was still moving during the last run.
+    pub fn is_animating(&self) -> bool {
+        self.is_animating
+    }
+
+    /// Dispatches the markers passed during the last run. Must be called after the system ran,
+    /// so that the handlers can access the world.
+    pub fn dispatch_reached_markers(&mut self) {
+        for event in self.reached_markers.drain(..) {
+            self.ctx.event_mgr().dispatch(&event);
+        }
+    }
+}
+
+impl<'a> System<'a> for UpdatePathFollowersSystem {
+    type SystemData = (
+        ReadStorage<'a, Object>,
+        WriteStorage<'a, PathFollower>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, (objects, mut followers, mut transforms): Self::SystemData) {
+        let mut object_mgr = self.ctx.object_mgr_mut();
+        let object_hierarchy = object_mgr.object_hierarchy_mut();
+        let animation_mgr = self.ctx.animation_mgr();
+        let delta_time = self.ctx.time_mgr().delta_time();
+        self.is_animating = false;
+
+        for (object, follower) in (&objects, &mut followers).join() {
+            let object_id = object.object_id();
+
+            if !object_hierarchy.is_active(object_id) {
+                continue;
+            }
+
+            let length = match follower.resolve(|key| animation_mgr.spline(key)) {
+                Some(spline) => spline.length(),
+                None => {
+                    let should_warn = follower.should_warn_missing_path();
+
+                    if let (PathSource::Asset(key), true) = (&follower.path, should_warn) {
+                        self.ctx.logger().log(
+                            StandardLogLevel::Warning,
+                            format!("path follower skipped: no path registered as `{}`", key),
+                        );
+                    }
+                    continue;
+                }
+            };
+
+            for index in follower.advance(delta_time, length) {
+                let marker = &follower.markers()[index];
+                self.reached_markers.push(PathMarkerReached {
+                    object_id,
+                    marker: marker.name.clone(),
+                    distance: marker.distance,
+                });
+            }
+
+            if let Some(spline) = follower.resolve(|key| animation_mgr.spline(key)) {
+                let (position, rotation) = follower.pose(spline);
+                let is_moved = write_if_changed(&mut transforms, object.entity(), |transform| {
+                    transform.position = position;
+
+                    if let Some(rotation) = rotation {
+                        transform.rotation = rotation;
+                    }
+                });
+
+                if is_moved {
+                    object_hierarchy.set_dirty(object_id);
+                }
+            }
+
+            if !follower.is_finished() && follower.speed != 0.0 {
+                self.is_animating = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{
+    animation::{AnimationEventCallback, PropertyAnimator, PropertyScope, PropertyTarget},
+    event::event_types::AnimationEvent,
+    gfx::MeshRenderer,
+    object::Object,
+    ContextHandle,
+};
+use logging::StandardLogLevel;
+use specs::prelude::*;
+
+/// Advances the property animators and writes the animated values into their renderers.
+pub struct UpdatePropertyAnimatorsSystem {
+    ctx: ContextHandle,
+    fired_events: Vec<(AnimationEvent, Vec<AnimationEventCallback>)>,
+    is_animating: bool,
+}
+
+impl UpdatePropertyAnimatorsSystem {
+    pub fn new(ctx: ContextHandle) -> Self {
+        Self {
+            ctx,
+            fired_events: Vec::new(),
+            is_animating: false,
+        }
+    }
+
+    /// Returns `true` if any active animator had a clip still in progress during the last run.
+    pub fn is_animating(&self) -> bool {
+        self.is_animating
+    }
+
+    /// Dispatches the animation events fired during the last run, then calls the callbacks of their animators.
+    /// Must be called after the system ran, so that the handlers can access the world.
+    pub fn dispatch_fired_events(&mut self) {
+        for (event, callbacks) in self.fired_events.drain(..) {
+            self.ctx.event_mgr().dispatch(&event);
+
+            for callback in callbacks {
+                callback(&event);
+            }
+        }
+    }
+}
+
+impl<'a> System<'a> for UpdatePropertyAnimatorsSystem {
+    type SystemData = (
+        ReadStorage<'a, Object>,
+        WriteStorage<'a, PropertyAnimator>,
+        WriteStorage<'a, MeshRenderer>,
+    );
+
+    fn run(&mut self, (objects, mut animators, mut mesh_renderers): Self::SystemData) {
+        let object_mgr = self.ctx.object_mgr();
+        let object_hierarchy = object_mgr.object_hierarchy();
+        let animation_mgr = self.ctx.animation_mgr();
+        let delta_time = self.ctx.time_mgr().delta_time();
+        self.is_animating = false;
+
+        for (object, animator, mut mesh_renderer) in
+            (&objects, &mut animators, (&mut mesh_renderers).maybe()).join()
+        {
+            if !object_hierarchy.is_active(object.object_id()) || !animator.is_playing() {
+                continue;
+            }
+
+            let object_id = object.object_id();
+
+            for (clip, marker) in
+                animator.advance(delta_time, |key| animation_mgr.property_animation(key))
+            {
+                let callbacks = Vec::from_iter(animator.event_callbacks(&marker.name).cloned());
+                self.fired_events.push((
+                    AnimationEvent {
+                        object_id,
+                        clip,
+                        name: marker.name.clone(),
+                        payload: marker.payload.clone(),
+                        bone: marker.bone.clone(),
+                    },
+                    callbacks,
+                ));
+            }
+
+            if !animator.is_finished(|key| animation_mgr.property_animation(key)) {
+                self.is_animating = true;
+            }
+
+            for (path, value) in animator.sample(|key| animation_mgr.property_animation(key)) {
+                let result = path
+                    .parse::<PropertyTarget>()
+                    .map_err(|err| err.to_string())
+                    .and_then(|target| apply(&target, mesh_renderer.as_deref_mut(), value));
+
+                if let Err(err) = result {
+                    if animator.should_warn(path) {
+                        self.ctx.logger().log(
+                            StandardLogLevel::Warning,
+                            format!("property animation target `{}` skipped: {}", path, err),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Writes the value into the material shared by the renderer, or into the renderer's own override.
+fn apply(
+    target: &PropertyTarget,
+    mesh_renderer: Option<&mut MeshRenderer>,
+    value: f32,
+) -> Result<(), String> {
+    let mesh_renderer = mesh_renderer.ok_or("the object has no mesh renderer")?;
+    let material = mesh_renderer
+        .material()
+        .cloned()
+        .ok_or("the mesh renderer has no material")?;
+    let missing_property = || {
+        format!(
+            "the material has no per-instance property named `{}`",
+            target.name
+        )
+    };
+    let unsupported_format = || "the property is not a float one with such component".to_owned();
+
+    match target.scope {
+        PropertyScope::Material => {
+            // The shared material is locked for writing only if the value changes, e.g. not while a clip holds it.
+            let value = {
+                let material = material.read();
+                let property = material
+                    .instance_properties
+                    .get(&target.name)
+                    .ok_or_else(missing_property)?;
+                let value = target
+                    .write(property.value.as_ref(), property.format, value)
+                    .ok_or_else(unsupported_format)?;
+
+                if property.value.as_ref() == Some(&value) {
+                    return Ok(());
+                }
+
+                value
+            };
+            material
+                .write()
+                .set_per_instance_property(&target.name, value);
+        }
+        PropertyScope::RendererInstance => {
+            let material = material.read();
+            let property = material
+                .instance_properties
+                .get(&target.name)
+                .ok_or_else(missing_property)?;
+            let current = mesh_renderer
+                .instance_property(&target.name)
+                .or(property.value.as_ref());
+            let value = target
+                .write(current, property.format, value)
+                .ok_or_else(unsupported_format)?;
+
+            if current != Some(&value) {
+                mesh_renderer.set_instance_property(target.name.clone(), value);
+            }
+        }
+    }
+
+    Ok(())
+}
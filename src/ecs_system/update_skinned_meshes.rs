@@ -0,0 +1,35 @@
+use crate::{gfx::MeshRenderer, object::Object, ContextHandle};
+use specs::prelude::*;
+
+/// Uploads the skinning matrices of the [`MeshRenderer`]s with a [`MeshSkin`](crate::gfx::MeshSkin), and moves their
+/// bounds to the current pose. Runs once per frame, after the object matrices are updated, so that the skins follow
+/// the bones moved on the same frame.
+pub struct UpdateSkinnedMeshesSystem {
+    ctx: ContextHandle,
+}
+
+impl UpdateSkinnedMeshesSystem {
+    pub fn new(ctx: ContextHandle) -> Self {
+        Self { ctx }
+    }
+}
+
+impl<'a> System<'a> for UpdateSkinnedMeshesSystem {
+    type SystemData = (ReadStorage<'a, Object>, WriteStorage<'a, MeshRenderer>);
+
+    fn run(&mut self, (objects, mut mesh_renderers): Self::SystemData) {
+        let object_mgr = self.ctx.object_mgr();
+        let object_hierarchy = object_mgr.object_hierarchy();
+        let mut render_mgr = self.ctx.render_mgr_mut();
+
+        for (object, mesh_renderer) in (&objects, &mut mesh_renderers).join() {
+            let object_id = object.object_id();
+
+            if mesh_renderer.skin().is_none() || !object_hierarchy.is_active(object_id) {
+                continue;
+            }
+
+            mesh_renderer.update_skin(object_hierarchy, object_id, render_mgr.uploader_mut());
+        }
+    }
+}
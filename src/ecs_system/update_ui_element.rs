@@ -104,13 +104,17 @@ fn compute_pair(
     let width = margin_right - margin_left - element.margin.left - element.margin.right;
     let height = margin_top - margin_bottom - element.margin.bottom - element.margin.top;
 
-    let transform = transforms.get_mut(pair.child).unwrap();
-    transform.position = Vec3::new(
+    let position = Vec3::new(
         margin_left + element.margin.left,
         margin_bottom + element.margin.bottom,
         0.0,
     );
 
+    // Transforms are flagged, so they are taken mutably only when they actually change.
+    if transforms.get(pair.child).unwrap().position != position {
+        transforms.get_mut(pair.child).unwrap().position = position;
+    }
+
     let size = sizes.get_mut(pair.child).unwrap();
     size.width = width;
     size.height = height;
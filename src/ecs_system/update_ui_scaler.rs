@@ -131,8 +131,12 @@ fn compute_pair(
         }
     };
 
-    let transform = transforms.get_mut(pair.child).unwrap();
-    transform.position = Vec3::new(width * -0.5f32, height * -0.5f32, 0.0f32);
+    let position = Vec3::new(width * -0.5f32, height * -0.5f32, 0.0f32);
+
+    // Transforms are flagged, so they are taken mutably only when they actually change.
+    if transforms.get(pair.child).unwrap().position != position {
+        transforms.get_mut(pair.child).unwrap().position = position;
+    }
 
     let size = sizes.get_mut(pair.child).unwrap();
     size.width = width;
@@ -0,0 +1,547 @@
+use crate::{
+    console::ConsoleConfig,
+    gfx::{GfxContextConfig, ShaderCacheConfig},
+    input::raw_input_name_into_virtual_keycode,
+    util::DEFAULT_FRAME_ARENA_BYTES,
+};
+use std::{fmt::Display, num::NonZeroU32, time::Duration};
+use thiserror::Error;
+use wgpu::{Backends, PowerPreference};
+use winit::event::VirtualKeyCode;
+
+pub struct EngineConfig {
+    pub title: String,
+    pub resizable: bool,
+    pub width: u32,
+    pub height: u32,
+    pub vsync: bool,
+    /// Scale of the internal render resolution relative to the surface size.
+    pub render_scale: f32,
+    /// Adapter selection, features and limits of the device.
+    pub gfx: GfxContextConfig,
+    /// Persists shader reflection results between runs. Shaders are reflected on every start if `None`.
+    pub shader_cache: Option<ShaderCacheConfig>,
+    /// Captures the logs and enables the in-engine console. Logs only go to the standard output if `None`.
+    pub console: Option<ConsoleConfig>,
+    /// How long the hidden benchmark detecting the render tier runs at startup.
+    /// The tier is estimated from the adapter alone if `None`.
+    pub render_tier_benchmark: Option<Duration>,
+    /// Upper bound of the scaled delta time. See [`TimeManager::max_delta_time`](crate::time::TimeManager::max_delta_time).
+    pub max_delta_time: Duration,
+    /// Fixed updates per second of scaled time. See [`TimeManager::fixed_delta_time`](crate::time::TimeManager::fixed_delta_time).
+    pub fixed_update_rate: f64,
+    /// Target frame rate in millihertz while the window is unfocused, e.g. 10000 for 10 frames per second.
+    /// The original rate is restored when the focus comes back. Unfocused windows run at full rate if `None`.
+    pub throttle_when_unfocused: Option<NonZeroU32>,
+    /// Captures the next frame into the working directory when pressed, for replaying it elsewhere. Disabled if `None`.
+    pub frame_capture_key: Option<VirtualKeyCode>,
+    /// Bytes each per-frame scratch arena reserves up front. See [`Context::frame_alloc`](crate::Context::frame_alloc).
+    pub frame_arena_bytes: usize,
+    /// Fields changed by [`from_args_and_env`](Self::from_args_and_env), for diagnostics.
+    pub overrides: Vec<EngineConfigOverride>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            title: "r3d".to_owned(),
+            resizable: true,
+            width: 800,
+            height: 600,
+            vsync: true,
+            render_scale: 1.0,
+            gfx: GfxContextConfig::default(),
+            shader_cache: None,
+            console: None,
+            render_tier_benchmark: Some(Duration::from_millis(100)),
+            max_delta_time: Duration::from_millis(250),
+            fixed_update_rate: 50.0,
+            throttle_when_unfocused: None,
+            frame_capture_key: None,
+            frame_arena_bytes: DEFAULT_FRAME_ARENA_BYTES,
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Options accepted by [`from_args_and_env`](Self::from_args_and_env).
+    ///
+    /// Each option can be given as `--<name>=<value>` or `--<name> <value>` on the command line,
+    /// or as the environment variable `ENTER_<NAME>` (uppercase, `-` replaced by `_`).
+    /// Command-line arguments take precedence over environment variables, which take precedence over the code.
+    /// Arguments that are not listed here are left for the application.
+    pub const OPTIONS: &'static [EngineConfigOption] = &[
+        EngineConfigOption {
+            name: "title",
+            expected: "any string",
+        },
+        EngineConfigOption {
+            name: "resizable",
+            expected: "on, off, true, false, 1 or 0",
+        },
+        EngineConfigOption {
+            name: "window-size",
+            expected: "<width>x<height> with positive integers, e.g. 1280x720",
+        },
+        EngineConfigOption {
+            name: "vsync",
+            expected: "on, off, true, false, 1 or 0",
+        },
+        EngineConfigOption {
+            name: "render-scale",
+            expected: "a number in (0, 4], e.g. 0.8",
+        },
+        EngineConfigOption {
+            name: "gfx-backend",
+            expected: "vulkan, metal, dx12, dx11, gl, webgpu, primary or all",
+        },
+        EngineConfigOption {
+            name: "power-preference",
+            expected: "high-performance, low-power or none",
+        },
+        EngineConfigOption {
+            name: "adapter",
+            expected: "a part of the adapter name, e.g. \"NVIDIA\"",
+        },
+        EngineConfigOption {
+            name: "shader-cache",
+            expected: "a directory path, or off",
+        },
+        EngineConfigOption {
+            name: "console",
+            expected: "on, off, true, false, 1 or 0",
+        },
+        EngineConfigOption {
+            name: "render-tier-benchmark",
+            expected: "a positive number of milliseconds, e.g. 100, or off",
+        },
+        EngineConfigOption {
+            name: "max-delta-time",
+            expected: "a positive number of milliseconds, e.g. 250",
+        },
+        EngineConfigOption {
+            name: "fixed-update-rate",
+            expected: "a positive number of updates per second, e.g. 60",
+        },
+        EngineConfigOption {
+            name: "throttle-when-unfocused",
+            expected: "a positive number of frames per second, e.g. 10, or off",
+        },
+        EngineConfigOption {
+            name: "frame-capture-key",
+            expected: "a key name of the keyboard input, e.g. f12, or off",
+        },
+        EngineConfigOption {
+            name: "frame-arena-bytes",
+            expected: "a number of bytes, e.g. 1048576",
+        },
+    ];
+
+    /// Applies the overrides given by the process arguments and environment variables over `base`.
+    pub fn from_args_and_env(base: EngineConfig) -> Result<Self, EngineConfigError> {
+        let args = std::env::args().skip(1).collect::<Vec<_>>();
+        base.with_overrides(&args, std::env::vars())
+    }
+
+    /// Applies the overrides given by `args` (without the program name) and `env` over this config.
+    pub fn with_overrides(
+        mut self,
+        args: &[impl AsRef<str>],
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, EngineConfigError> {
+        let mut env = env.into_iter().collect::<Vec<_>>();
+        env.sort();
+
+        for (key, value) in env {
+            let option = match Self::OPTIONS.iter().find(|option| option.env_name() == key) {
+                Some(option) => option,
+                None => continue,
+            };
+            self.apply(option, &value, EngineConfigSource::Env)?;
+        }
+
+        let mut args = args.iter().map(|arg| arg.as_ref());
+
+        while let Some(arg) = args.next() {
+            let arg = match arg.strip_prefix("--") {
+                Some(arg) => arg,
+                None => continue,
+            };
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg, None),
+            };
+            let option = match Self::OPTIONS.iter().find(|option| option.name == name) {
+                Some(option) => option,
+                None => continue,
+            };
+            let value = match value.or_else(|| args.next()) {
+                Some(value) => value,
+                None => {
+                    return Err(EngineConfigError::MissingValue {
+                        option: format!("--{}", option.name),
+                        expected: option.expected,
+                    })
+                }
+            };
+            self.apply(option, value, EngineConfigSource::Args)?;
+        }
+
+        Ok(self)
+    }
+
+    fn apply(
+        &mut self,
+        option: &EngineConfigOption,
+        value: &str,
+        source: EngineConfigSource,
+    ) -> Result<(), EngineConfigError> {
+        let invalid = || EngineConfigError::InvalidValue {
+            option: match source {
+                EngineConfigSource::Args => format!("--{}", option.name),
+                EngineConfigSource::Env => option.env_name(),
+            },
+            value: value.to_owned(),
+            expected: option.expected,
+        };
+
+        match option.name {
+            "title" => self.title = value.to_owned(),
+            "resizable" => self.resizable = parse_bool(value).ok_or_else(invalid)?,
+            "window-size" => {
+                let (width, height) = parse_size(value).ok_or_else(invalid)?;
+                self.width = width;
+                self.height = height;
+            }
+            "vsync" => self.vsync = parse_bool(value).ok_or_else(invalid)?,
+            "render-scale" => {
+                self.render_scale = value
+                    .trim()
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|scale| 0.0 < *scale && *scale <= 4.0)
+                    .ok_or_else(invalid)?
+            }
+            "gfx-backend" => self.gfx.backends = parse_backends(value).ok_or_else(invalid)?,
+            "power-preference" => {
+                self.gfx.power_preference = parse_power_preference(value).ok_or_else(invalid)?
+            }
+            "adapter" => {
+                let adapter = value.trim();
+                if adapter.is_empty() {
+                    return Err(invalid());
+                }
+                self.gfx.adapter_name = Some(adapter.to_owned());
+            }
+            "shader-cache" => {
+                self.shader_cache = match value.trim() {
+                    "" => return Err(invalid()),
+                    "off" => None,
+                    directory => Some(ShaderCacheConfig::new(directory)),
+                }
+            }
+            "console" => {
+                // Turning the console on keeps the config given by the code, if any.
+                self.console = match parse_bool(value).ok_or_else(invalid)? {
+                    true => Some(self.console.take().unwrap_or_default()),
+                    false => None,
+                }
+            }
+            "render-tier-benchmark" => {
+                self.render_tier_benchmark = match value.trim() {
+                    "off" => None,
+                    value => Some(parse_millis(value).ok_or_else(invalid)?),
+                }
+            }
+            "max-delta-time" => self.max_delta_time = parse_millis(value).ok_or_else(invalid)?,
+            "fixed-update-rate" => {
+                self.fixed_update_rate = parse_positive(value).ok_or_else(invalid)?
+            }
+            "throttle-when-unfocused" => {
+                self.throttle_when_unfocused = match value.trim() {
+                    "off" => None,
+                    value => {
+                        let millihertz = parse_positive(value).ok_or_else(invalid)? * 1000.0;
+                        Some(NonZeroU32::new(millihertz.round() as u32).ok_or_else(invalid)?)
+                    }
+                }
+            }
+            "frame-capture-key" => {
+                self.frame_capture_key = match value.trim().to_ascii_lowercase().as_str() {
+                    "off" => None,
+                    key => Some(raw_input_name_into_virtual_keycode(key).ok_or_else(invalid)?),
+                }
+            }
+            "frame-arena-bytes" => {
+                self.frame_arena_bytes = value.trim().parse::<usize>().ok().ok_or_else(invalid)?
+            }
+            _ => unreachable!(),
+        }
+
+        // A later source replaces the record of an earlier one.
+        self.overrides
+            .retain(|override_| override_.option != option.name);
+        self.overrides.push(EngineConfigOverride {
+            option: option.name,
+            value: value.to_owned(),
+            source,
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EngineConfigOption {
+    pub name: &'static str,
+    /// Human readable description of the accepted values.
+    pub expected: &'static str,
+}
+
+impl EngineConfigOption {
+    pub fn env_name(&self) -> String {
+        format!("ENTER_{}", self.name.to_uppercase().replace('-', "_"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EngineConfigSource {
+    Env,
+    Args,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EngineConfigOverride {
+    pub option: &'static str,
+    pub value: String,
+    pub source: EngineConfigSource,
+}
+
+impl Display for EngineConfigOverride {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.source {
+            EngineConfigSource::Env => write!(f, "{}={} (environment)", self.option, self.value),
+            EngineConfigSource::Args => write!(f, "{}={} (command line)", self.option, self.value),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum EngineConfigError {
+    #[error("invalid value `{value}` for {option}; expected {expected}")]
+    InvalidValue {
+        option: String,
+        value: String,
+        expected: &'static str,
+    },
+    #[error("missing value for {option}; expected {expected}")]
+    MissingValue {
+        option: String,
+        expected: &'static str,
+    },
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "on" | "true" | "1" => Some(true),
+        "off" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_size(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.trim().split_once(['x', 'X'])?;
+    let width = width.parse::<u32>().ok().filter(|width| *width != 0)?;
+    let height = height.parse::<u32>().ok().filter(|height| *height != 0)?;
+    Some((width, height))
+}
+
+/// Parses a positive, finite number.
+fn parse_positive(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite() && 0.0 < *value)
+}
+
+fn parse_millis(value: &str) -> Option<Duration> {
+    parse_positive(value).map(|millis| Duration::from_secs_f64(millis / 1000.0))
+}
+
+fn parse_power_preference(value: &str) -> Option<PowerPreference> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "high-performance" => Some(PowerPreference::HighPerformance),
+        "low-power" => Some(PowerPreference::LowPower),
+        "none" => Some(PowerPreference::None),
+        _ => None,
+    }
+}
+
+fn parse_backends(value: &str) -> Option<Backends> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "vulkan" => Some(Backends::VULKAN),
+        "metal" => Some(Backends::METAL),
+        "dx12" => Some(Backends::DX12),
+        "dx11" => Some(Backends::DX11),
+        "gl" => Some(Backends::GL),
+        "webgpu" => Some(Backends::BROWSER_WEBGPU),
+        "primary" => Some(Backends::PRIMARY),
+        "all" => Some(Backends::all()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn check_override_precedence() {
+        let config = EngineConfig::default()
+            .with_overrides(
+                &["--window-size=1280x720", "--vsync", "off", "--unknown"],
+                env(&[
+                    ("ENTER_WINDOW_SIZE", "640x480"),
+                    ("ENTER_RENDER_SCALE", "0.8"),
+                    ("ENTER_GFX_BACKEND", "vulkan"),
+                    ("HOME", "/home/user"),
+                ]),
+            )
+            .unwrap();
+
+        assert_eq!((config.width, config.height), (1280, 720));
+        assert!(!config.vsync);
+        assert_eq!(config.render_scale, 0.8);
+        assert_eq!(config.gfx.backends, Backends::VULKAN);
+        assert_eq!(config.title, "r3d");
+
+        let overrides = config
+            .overrides
+            .iter()
+            .map(|override_| (override_.option, override_.source))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            overrides,
+            [
+                ("gfx-backend", EngineConfigSource::Env),
+                ("render-scale", EngineConfigSource::Env),
+                ("window-size", EngineConfigSource::Args),
+                ("vsync", EngineConfigSource::Args),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_runtime_options() {
+        let config = EngineConfig::default()
+            .with_overrides(
+                &[
+                    "--console=on",
+                    "--render-tier-benchmark=off",
+                    "--max-delta-time",
+                    "100",
+                    "--fixed-update-rate=60",
+                    "--throttle-when-unfocused=7.5",
+                    "--frame-capture-key=F12",
+                    "--frame-arena-bytes=4096",
+                ],
+                env(&[("ENTER_POWER_PREFERENCE", "low-power")]),
+            )
+            .unwrap();
+
+        assert!(config.console.is_some());
+        assert_eq!(config.render_tier_benchmark, None);
+        assert_eq!(config.max_delta_time, Duration::from_millis(100));
+        assert_eq!(config.fixed_update_rate, 60.0);
+        assert_eq!(config.throttle_when_unfocused, NonZeroU32::new(7500));
+        assert_eq!(config.frame_capture_key, Some(VirtualKeyCode::F12));
+        assert_eq!(config.frame_arena_bytes, 4096);
+        assert_eq!(config.gfx.power_preference, PowerPreference::LowPower);
+
+        // Turning the console on keeps the config of the code; the options given as off turn the features off.
+        let base = EngineConfig {
+            console: Some(ConsoleConfig {
+                capacity: 16,
+                ..Default::default()
+            }),
+            throttle_when_unfocused: NonZeroU32::new(10000),
+            frame_capture_key: Some(VirtualKeyCode::F12),
+            ..Default::default()
+        };
+        let config = base
+            .with_overrides(
+                &[
+                    "--console=1",
+                    "--render-tier-benchmark=250",
+                    "--throttle-when-unfocused=off",
+                    "--frame-capture-key=off",
+                ],
+                env(&[]),
+            )
+            .unwrap();
+
+        assert_eq!(config.console.map(|console| console.capacity), Some(16));
+        assert_eq!(
+            config.render_tier_benchmark,
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(config.throttle_when_unfocused, None);
+        assert_eq!(config.frame_capture_key, None);
+
+        let config = EngineConfig::default()
+            .with_overrides(&["--console=off"], env(&[]))
+            .unwrap();
+        assert!(config.console.is_none());
+    }
+
+    #[test]
+    fn check_malformed_values() {
+        for (args, option) in [
+            (["--window-size=1280"], "--window-size"),
+            (["--window-size=0x720"], "--window-size"),
+            (["--render-scale=-1"], "--render-scale"),
+            (["--vsync=maybe"], "--vsync"),
+            (["--gfx-backend=glide"], "--gfx-backend"),
+            (["--power-preference=fast"], "--power-preference"),
+            (["--console=maybe"], "--console"),
+            (["--render-tier-benchmark=0"], "--render-tier-benchmark"),
+            (["--max-delta-time=-5"], "--max-delta-time"),
+            (["--fixed-update-rate=inf"], "--fixed-update-rate"),
+            (
+                ["--throttle-when-unfocused=0.0001"],
+                "--throttle-when-unfocused",
+            ),
+            (["--frame-capture-key=hyper"], "--frame-capture-key"),
+            (["--frame-arena-bytes=1MB"], "--frame-arena-bytes"),
+        ] {
+            match EngineConfig::default().with_overrides(&args, env(&[])) {
+                Err(EngineConfigError::InvalidValue {
+                    option: actual,
+                    expected,
+                    ..
+                }) => {
+                    assert_eq!(actual, option);
+                    assert!(!expected.is_empty());
+                }
+                _ => panic!("{:?} must be rejected", args),
+            }
+        }
+
+        assert!(matches!(
+            EngineConfig::default().with_overrides(&["--adapter"], env(&[])),
+            Err(EngineConfigError::MissingValue { .. })
+        ));
+        assert!(matches!(
+            EngineConfig::default().with_overrides(&[] as &[&str], env(&[("ENTER_MSAA", "4")])),
+            Ok(_)
+        ));
+    }
+}
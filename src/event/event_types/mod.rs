@@ -1,5 +1,65 @@
+use crate::{
+    animation::AnimationEventPayload,
+    gfx::{DisplaySettings, QualityPreset, RenderTier},
+    object::ObjectId,
+};
+use asset::AssetKey;
+
+/// Dispatched on each fixed update, before `Update`. See [`TimeManager::fixed_steps`](crate::time::TimeManager::fixed_steps).
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FixedUpdate;
+
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Update;
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct LateUpdate;
+
+/// Dispatched right before display settings are applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplaySettingsChanging {
+    pub from: DisplaySettings,
+    pub to: DisplaySettings,
+}
+
+/// Dispatched once after display settings have been applied, so UI can re-layout exactly once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplaySettingsChanged {
+    pub from: DisplaySettings,
+    pub to: DisplaySettings,
+}
+
+/// Dispatched when the window gains or loses the keyboard focus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FocusChanged {
+    pub is_focused: bool,
+}
+
+/// Dispatched when a [`PathFollower`](crate::animation::PathFollower) passes one of its markers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathMarkerReached {
+    pub object_id: ObjectId,
+    pub marker: String,
+    /// Distance of the marker along the path.
+    pub distance: f32,
+}
+
+/// Dispatched when a [`PropertyAnimator`](crate::animation::PropertyAnimator) crosses an event of a clip it plays.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationEvent {
+    pub object_id: ObjectId,
+    pub clip: AssetKey,
+    pub name: String,
+    pub payload: Option<AnimationEventPayload>,
+    /// The bone the event happens at, if placed on one.
+    pub bone: Option<String>,
+}
+
+/// Dispatched when a quality preset is applied: once at startup, then whenever the render config changes it,
+/// so that game code can scale its own load, e.g. crowd density.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityPresetApplied {
+    /// Tier of the preset, or `None` if it is a custom one.
+    pub tier: Option<RenderTier>,
+    pub preset: QualityPreset,
+}
@@ -0,0 +1,2037 @@
+use super::{create_placeholder_texture, framing_transform, GfxContextHandle};
+use crate::{
+    math::{Mat4, Vec3},
+    object::transform_aabb,
+    task::{Task, TaskContext, TaskStatus},
+};
+use asset::{
+    assets::{
+        FontAsset, MaterialBindingKey, MaterialBindingValue, MaterialInstancePropKey,
+        MaterialInstancePropValue, MaterialPreset, ModelAsset, Node, SemanticShaderBindingKey,
+        SemanticShaderInputKey, ShaderGlobalItemKind, ShaderInput, ShaderReflection,
+        VertexAttributeKind, VertexIndexType,
+    },
+    AssetKey, GfxShaderModule, TypedAsset,
+};
+use asset_loader::ContentHash;
+use image::RgbaImage;
+use parking_lot::Mutex;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    future::Future,
+    mem::size_of,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::{mpsc::channel, Arc},
+    task::{Poll, RawWaker, RawWakerVTable, Waker},
+    time::Instant,
+};
+use thiserror::Error;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferAddress, BufferBinding,
+    BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, Color, ColorTargetState,
+    ColorWrites, CommandEncoder, CommandEncoderDescriptor, CompareFunction, DepthStencilState,
+    Device, ErrorFilter, Extent3d, FilterMode, FragmentState, ImageCopyBuffer, ImageCopyTexture,
+    ImageDataLayout, IndexFormat, LoadOp, Maintain, MapMode, MultisampleState, Operations,
+    Origin3d, PipelineLayoutDescriptor, PrimitiveState, Queue, RenderPass,
+    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
+    ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages, Texture, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDescriptor, TextureViewDimension, VertexAttribute, VertexBufferLayout,
+    VertexFormat, VertexState, VertexStepMode, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+use zerocopy::AsBytes;
+
+/// Largest preview size, in pixels per side.
+pub const ASSET_PREVIEW_MAX_SIZE: u32 = 1024;
+
+/// Bumped whenever previews change their look, so that previews cached by older versions are rendered again.
+const PREVIEW_VERSION: u32 = 1;
+const PREVIEW_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+const PREVIEW_DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+/// Clear color of model and material previews; matches `NEUTRAL` of the shader.
+const PREVIEW_BACKDROP: Color = Color {
+    r: 0.22,
+    g: 0.22,
+    b: 0.24,
+    a: 1.0,
+};
+/// Vertical field of view of model and material previews, in radians.
+const PREVIEW_FOV: f32 = 0.6;
+/// Three-quarter view from above.
+const PREVIEW_VIEW_DIRECTION: Vec3 = Vec3 {
+    x: -1.0,
+    y: -0.7,
+    z: -1.0,
+};
+const MODEL_COLOR: [f32; 4] = [0.75, 0.75, 0.72, 1.0];
+const ERROR_COLOR: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+/// Text drawn by font previews.
+const FONT_SAMPLE_TEXT: &str = "Aa";
+/// Part of the preview images are fitted into; the rest is margin.
+const TEXTURE_EXTENT: f32 = 1.0;
+const FONT_EXTENT: f32 = 0.8;
+const CHECKERBOARD_CELLS: f32 = 8.0;
+const SPHERE_RINGS: u32 = 24;
+const SPHERE_SEGMENTS: u32 = 48;
+
+const KEY_CAMERA_TRANSFORM: u32 = 1;
+const KEY_POSITION: u32 = 1;
+const KEY_NORMAL: u32 = 2;
+const KEY_UV: u32 = 3;
+const KEY_TRANSFORM_ROW_0: u32 = 101;
+
+#[derive(Error, Debug)]
+pub enum AssetPreviewError {
+    #[error("the preview size must be in 1..={max}, but is {size}")]
+    InvalidSize { size: u32, max: u32 },
+    #[error("failed to read the preview back")]
+    ReadBackFailed,
+    #[error("failed to read the cached preview: {0}")]
+    Cache(#[from] image::ImageError),
+}
+
+/// A preview to render. See [`PreviewRenderer`] for how each type of asset is previewed.
+#[derive(Clone)]
+pub struct AssetPreviewRequest {
+    pub asset: TypedAsset,
+    /// Hash of the content of the asset, e.g. from its asset pack entry.
+    /// The preview is cached on disk under it, so that unchanged assets are not rendered again across sessions.
+    pub content_hash: Option<ContentHash>,
+    /// Width and height of the preview, in pixels.
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AssetPreviewSource {
+    Rendered,
+    /// The asset failed to render, e.g. because of a broken shader; the error material has been rendered instead.
+    Fallback,
+    /// Loaded from the disk cache.
+    Cached,
+}
+
+#[derive(Debug, Clone)]
+pub struct AssetPreview {
+    pub key: AssetKey,
+    pub image: RgbaImage,
+    pub source: AssetPreviewSource,
+}
+
+struct PreviewTarget {
+    texture: Texture,
+    view: TextureView,
+    depth_view: TextureView,
+    read_back_buffer: Buffer,
+    padded_bytes_per_row: u32,
+}
+
+struct MaterialPipeline {
+    /// Kept, so that a reloaded shader is never mistaken for the one the pipeline has been created from.
+    module: GfxShaderModule,
+    pipeline: RenderPipeline,
+    /// Layout and entries of every group up to the last one the shader uses.
+    groups: Vec<(BindGroupLayout, Vec<BindGroupLayoutEntry>)>,
+}
+
+/// UV sphere of radius 1 that materials are previewed on.
+struct PreviewSphere {
+    positions: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+    position_buffer: Buffer,
+    index_buffer: Buffer,
+}
+
+/// Renders standardized previews of assets into small images, e.g. for the thumbnails of an asset browser:
+///
+/// - textures are fitted into the preview over an alpha checkerboard;
+/// - fonts draw a sample text on a neutral backdrop;
+/// - models are framed as a whole on a neutral backdrop, flat-shaded under a fixed light;
+/// - materials, and shaders with their defaults, are applied to a sphere on a neutral backdrop.
+///
+/// An asset that fails to render, e.g. a material whose shader does not match its reflection,
+/// is previewed as a sphere of the error material instead.
+///
+/// Pipelines and render targets are kept across previews, so rendering many previews in a row is cheap.
+/// The renderer must always be used with the device it has been created with.
+pub struct PreviewRenderer {
+    cache_dir: Option<PathBuf>,
+    shader: ShaderModule,
+    image_bind_group_layout: BindGroupLayout,
+    image_pipeline: RenderPipeline,
+    model_bind_group_layout: BindGroupLayout,
+    /// Model pipelines by vertex stride.
+    model_pipelines: HashMap<BufferAddress, RenderPipeline>,
+    material_pipelines: HashMap<AssetKey, Arc<MaterialPipeline>>,
+    placeholder_textures: HashMap<BindingType, Arc<TextureView>>,
+    sampler: Arc<Sampler>,
+    comparison_sampler: Arc<Sampler>,
+    sphere: PreviewSphere,
+    targets: HashMap<u32, PreviewTarget>,
+}
+
+impl PreviewRenderer {
+    /// `ModelParams` of the shader: two matrices, the eye position and the color.
+    const MODEL_PARAMS_SIZE: BufferAddress = size_of::<[f32; 16 + 16 + 4 + 4]>() as BufferAddress;
+    const PLACEHOLDER_SIZE: u32 = 8;
+
+    /// Previews are cached in `cache_dir` if given.
+    pub fn new(device: &Device, cache_dir: Option<PathBuf>) -> Self {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("asset preview shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "./built_in_shaders/asset_preview.wgsl"
+            ))),
+        });
+        let image_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("asset preview image bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<[f32; 8]>() as u64),
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let image_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("asset preview image pipeline layout"),
+            bind_group_layouts: &[&image_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let image_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("asset preview image pipeline"),
+            layout: Some(&image_pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_image",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_image",
+                targets: &[Some(ColorTargetState {
+                    format: PREVIEW_FORMAT,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+        let model_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("asset preview model bind group layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 3,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: BufferSize::new(Self::MODEL_PARAMS_SIZE),
+                },
+                count: None,
+            }],
+        });
+
+        Self {
+            cache_dir,
+            shader,
+            image_bind_group_layout,
+            image_pipeline,
+            model_bind_group_layout,
+            model_pipelines: HashMap::new(),
+            material_pipelines: HashMap::new(),
+            placeholder_textures: HashMap::new(),
+            sampler: Arc::new(device.create_sampler(&SamplerDescriptor {
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            })),
+            comparison_sampler: Arc::new(device.create_sampler(&SamplerDescriptor {
+                compare: Some(CompareFunction::LessEqual),
+                ..Default::default()
+            })),
+            sphere: PreviewSphere::new(device),
+            targets: HashMap::new(),
+        }
+    }
+
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.cache_dir.as_deref()
+    }
+
+    /// Returns the cached preview of the request, or renders it and caches it.
+    /// Failing to write the cache does not fail the preview; it is rendered again next time.
+    pub fn render_request(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        request: &AssetPreviewRequest,
+    ) -> Result<AssetPreview, AssetPreviewError> {
+        let cache_path = self.cache_path(request);
+
+        if let Some(path) = &cache_path {
+            if path.is_file() {
+                let image = image::open(path)?.into_rgba8();
+
+                if image.dimensions() == (request.size, request.size) {
+                    return Ok(AssetPreview {
+                        key: asset_key(&request.asset).clone(),
+                        image,
+                        source: AssetPreviewSource::Cached,
+                    });
+                }
+            }
+        }
+
+        let preview = self.render(device, queue, &request.asset, request.size)?;
+
+        if let Some(path) = &cache_path {
+            if std::fs::create_dir_all(path.parent().unwrap()).is_ok() {
+                let _ = preview.image.save(path);
+            }
+        }
+
+        Ok(preview)
+    }
+
+    /// Renders the preview of the asset, without the disk cache.
+    pub fn render(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        asset: &TypedAsset,
+        size: u32,
+    ) -> Result<AssetPreview, AssetPreviewError> {
+        if size == 0 || ASSET_PREVIEW_MAX_SIZE < size {
+            return Err(AssetPreviewError::InvalidSize {
+                size,
+                max: ASSET_PREVIEW_MAX_SIZE,
+            });
+        }
+
+        self.targets
+            .entry(size)
+            .or_insert_with(|| PreviewTarget::new(device, size));
+
+        // Any validation error of the preview, e.g. from a shader not matching its reflection, makes it fall back.
+        device.push_error_scope(ErrorFilter::Validation);
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("asset preview encoder"),
+        });
+        self.encode(device, queue, &mut encoder, asset, size);
+        let commands = encoder.finish();
+
+        let source = if pop_error_scope(device).is_none() {
+            queue.submit(std::iter::once(commands));
+            AssetPreviewSource::Rendered
+        } else {
+            drop(commands);
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("asset preview fallback encoder"),
+            });
+            self.encode_sphere_with_error_material(device, &mut encoder, size);
+            queue.submit(std::iter::once(encoder.finish()));
+            AssetPreviewSource::Fallback
+        };
+
+        Ok(AssetPreview {
+            key: asset_key(asset).clone(),
+            image: self.targets[&size].read_back(device, queue, size)?,
+            source,
+        })
+    }
+
+    fn cache_path(&self, request: &AssetPreviewRequest) -> Option<PathBuf> {
+        let cache_dir = self.cache_dir.as_ref()?;
+        let content_hash = request.content_hash?;
+        Some(cache_dir.join(format!(
+            "{}-{}-v{}.png",
+            content_hash, request.size, PREVIEW_VERSION
+        )))
+    }
+
+    fn encode(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        asset: &TypedAsset,
+        size: u32,
+    ) {
+        match asset {
+            TypedAsset::Texture(texture) => {
+                let target = &self.targets[&size];
+                self.encode_image(
+                    device,
+                    encoder,
+                    target,
+                    texture.view_handle(),
+                    texture.sampler_handle(),
+                    (texture.width() as u32, texture.height() as u32),
+                    TEXTURE_EXTENT,
+                    true,
+                );
+            }
+            TypedAsset::Font(font) => {
+                let image = rasterize_sample_text(font.as_ref(), FONT_SAMPLE_TEXT);
+                let view = upload_image(device, queue, &image);
+                let target = &self.targets[&size];
+                self.encode_image(
+                    device,
+                    encoder,
+                    target,
+                    &view,
+                    &self.sampler,
+                    image.dimensions(),
+                    FONT_EXTENT,
+                    false,
+                );
+            }
+            TypedAsset::Model(model) => {
+                self.encode_model(device, encoder, model.as_ref(), size);
+            }
+            TypedAsset::Material(material) => {
+                self.encode_material(device, queue, encoder, material.preset(), size);
+            }
+            TypedAsset::Shader(shader) => {
+                let preset = MaterialPreset {
+                    shader: shader.clone(),
+                    binding_props: Vec::new(),
+                    instance_props: Vec::new(),
+                };
+                self.encode_material(device, queue, encoder, &preset, size);
+            }
+        }
+    }
+
+    /// Fits the image into `extent` of the preview, keeping its aspect ratio.
+    #[allow(clippy::too_many_arguments)]
+    fn encode_image(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        target: &PreviewTarget,
+        view: &TextureView,
+        sampler: &Sampler,
+        (width, height): (u32, u32),
+        extent: f32,
+        checkerboard: bool,
+    ) {
+        let aspect = width.max(1) as f32 / height.max(1) as f32;
+        let (half_width, half_height) = if 1.0 <= aspect {
+            (extent * 0.5, extent * 0.5 / aspect)
+        } else {
+            (extent * 0.5 * aspect, extent * 0.5)
+        };
+        let params = [
+            0.5 - half_width,
+            0.5 - half_height,
+            0.5 + half_width,
+            0.5 + half_height,
+            if checkerboard { 1.0 } else { 0.0 },
+            CHECKERBOARD_CELLS,
+            0.0,
+            0.0,
+        ];
+        let params_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("asset preview image params"),
+            contents: params.as_bytes(),
+            usage: BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("asset preview image bind group"),
+            layout: &self.image_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("asset preview image pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &target.view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(PREVIEW_BACKDROP),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.image_pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Draws every mesh of the model with its node transform, framing the whole model.
+    /// Meshes with 8-bit indices are left out, as they cannot be drawn.
+    fn encode_model(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        model: &dyn ModelAsset,
+        size: u32,
+    ) {
+        let node_matrices = node_matrices(model);
+        let mut draws = Vec::new();
+        let mut bounds: Option<(Vec3, Vec3)> = None;
+
+        for (node, matrix) in model.nodes().iter().zip(&node_matrices) {
+            for &mesh_index in &node.mesh_indices {
+                let mesh = match model.meshes().get(mesh_index as usize) {
+                    Some(mesh) => mesh,
+                    None => continue,
+                };
+                let index_format = match mesh.index_type {
+                    VertexIndexType::U8 => continue,
+                    VertexIndexType::U16 => IndexFormat::Uint16,
+                    VertexIndexType::U32 => IndexFormat::Uint32,
+                };
+                let position_offset = match mesh
+                    .vertex_attributes
+                    .iter()
+                    .find(|attribute| attribute.kind == VertexAttributeKind::Position)
+                {
+                    Some(attribute) => attribute.offset as BufferAddress,
+                    None => continue,
+                };
+
+                if mesh.vertex_count == 0 {
+                    continue;
+                }
+
+                let stride = mesh.vertex_buffer.size() / mesh.vertex_count as BufferAddress;
+                let index_size = match index_format {
+                    IndexFormat::Uint16 => size_of::<u16>(),
+                    IndexFormat::Uint32 => size_of::<u32>(),
+                } as BufferAddress;
+                let (min, max) = transform_aabb(
+                    Vec3::new(mesh.aabb.min[0], mesh.aabb.min[1], mesh.aabb.min[2]),
+                    Vec3::new(mesh.aabb.max[0], mesh.aabb.max[1], mesh.aabb.max[2]),
+                    matrix,
+                );
+                bounds = Some(match bounds {
+                    Some((bounds_min, bounds_max)) => {
+                        (Vec3::min(bounds_min, min), Vec3::max(bounds_max, max))
+                    }
+                    None => (min, max),
+                });
+                draws.push((
+                    mesh,
+                    matrix.clone(),
+                    index_format,
+                    (mesh.index_buffer.size() / index_size) as u32,
+                    position_offset,
+                    stride,
+                ));
+            }
+        }
+
+        for &(_, _, _, _, _, stride) in &draws {
+            if !self.model_pipelines.contains_key(&stride) {
+                let pipeline = self.create_model_pipeline(device, stride);
+                self.model_pipelines.insert(stride, pipeline);
+            }
+        }
+
+        let (min, max) = bounds.unwrap_or((Vec3::ZERO, Vec3::ZERO));
+        let (world_to_clip, eye) = framing_camera(min, max);
+        let params_buffer = self.create_model_params(
+            device,
+            &world_to_clip,
+            eye,
+            draws
+                .iter()
+                .map(|(_, matrix, ..)| (matrix.clone(), MODEL_COLOR)),
+        );
+        let bind_group = self.create_model_bind_group(device, &params_buffer);
+        let target = &self.targets[&size];
+        let mut render_pass = begin_depth_pass(encoder, target, "asset preview model pass");
+
+        for (index, (mesh, _, index_format, index_count, position_offset, stride)) in
+            draws.iter().enumerate()
+        {
+            render_pass.set_pipeline(&self.model_pipelines[stride]);
+            render_pass.set_bind_group(
+                0,
+                &bind_group,
+                &[model_params_offset(device, index) as u32],
+            );
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(position_offset..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), *index_format);
+            render_pass.draw_indexed(0..*index_count, 0, 0..1);
+        }
+    }
+
+    /// Applies the material to the preview sphere. Bindings the material does not provide are bound to placeholders.
+    fn encode_material(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        preset: &MaterialPreset,
+        size: u32,
+    ) {
+        let shader_key = preset.shader.key().clone();
+        let is_stale = match self.material_pipelines.get(&shader_key) {
+            Some(pipeline) => !Arc::ptr_eq(&pipeline.module, preset.shader.handle()),
+            None => true,
+        };
+
+        if is_stale {
+            let pipeline = self.create_material_pipeline(device, &preset.shader);
+            self.material_pipelines
+                .insert(shader_key.clone(), Arc::new(pipeline));
+        }
+
+        let pipeline = self.material_pipelines[&shader_key].clone();
+        let reflection = preset.shader.reflection();
+        let (world_to_clip, _) = framing_camera(Vec3::new(-1.0, -1.0, -1.0), Vec3::ONE);
+        let camera_buffer = Arc::new(device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("asset preview camera transform"),
+            contents: world_to_clip.as_bytes(),
+            usage: BufferUsages::UNIFORM,
+        }));
+        let bind_groups = self.create_material_bind_groups(
+            device,
+            queue,
+            &pipeline,
+            reflection,
+            preset,
+            &camera_buffer,
+        );
+        let vertex_buffers = Vec::from_iter(
+            [&reflection.vertex_input, &reflection.instance_input]
+                .into_iter()
+                .filter(|input| !input.fields.is_empty())
+                .map(|input| {
+                    device.create_buffer_init(&BufferInitDescriptor {
+                        label: Some("asset preview material vertices"),
+                        contents: &self.sphere.input_data(input, preset),
+                        usage: BufferUsages::VERTEX,
+                    })
+                }),
+        );
+
+        let target = &self.targets[&size];
+        let mut render_pass = begin_depth_pass(encoder, target, "asset preview material pass");
+        render_pass.set_pipeline(&pipeline.pipeline);
+
+        for (group, bind_group) in bind_groups.iter().enumerate() {
+            render_pass.set_bind_group(group as u32, bind_group, &[]);
+        }
+
+        for (slot, buffer) in vertex_buffers.iter().enumerate() {
+            render_pass.set_vertex_buffer(slot as u32, buffer.slice(..));
+        }
+
+        render_pass.set_index_buffer(self.sphere.index_buffer.slice(..), IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.sphere.indices.len() as u32, 0, 0..1);
+    }
+
+    fn encode_sphere_with_error_material(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        size: u32,
+    ) {
+        let stride = size_of::<[f32; 3]>() as BufferAddress;
+
+        if !self.model_pipelines.contains_key(&stride) {
+            let pipeline = self.create_model_pipeline(device, stride);
+            self.model_pipelines.insert(stride, pipeline);
+        }
+
+        let (world_to_clip, eye) = framing_camera(Vec3::new(-1.0, -1.0, -1.0), Vec3::ONE);
+        let params_buffer = self.create_model_params(
+            device,
+            &world_to_clip,
+            eye,
+            std::iter::once((Mat4::identity(), ERROR_COLOR)),
+        );
+        let bind_group = self.create_model_bind_group(device, &params_buffer);
+        let target = &self.targets[&size];
+        let mut render_pass = begin_depth_pass(encoder, target, "asset preview fallback pass");
+        render_pass.set_pipeline(&self.model_pipelines[&stride]);
+        render_pass.set_bind_group(0, &bind_group, &[0]);
+        render_pass.set_vertex_buffer(0, self.sphere.position_buffer.slice(..));
+        render_pass.set_index_buffer(self.sphere.index_buffer.slice(..), IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.sphere.indices.len() as u32, 0, 0..1);
+    }
+
+    fn create_model_pipeline(&self, device: &Device, stride: BufferAddress) -> RenderPipeline {
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("asset preview model pipeline layout"),
+            bind_group_layouts: &[&self.model_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("asset preview model pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module: &self.shader,
+                entry_point: "vs_model",
+                buffers: &[VertexBufferLayout {
+                    array_stride: stride,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: &[VertexAttribute {
+                        format: VertexFormat::Float32x3,
+                        offset: 0,
+                        shader_location: 0,
+                    }],
+                }],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: Some(depth_stencil_state()),
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &self.shader,
+                entry_point: "fs_model",
+                targets: &[Some(ColorTargetState {
+                    format: PREVIEW_FORMAT,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        })
+    }
+
+    /// Writes the parameters of each draw at its own dynamic offset.
+    fn create_model_params(
+        &self,
+        device: &Device,
+        world_to_clip: &Mat4,
+        eye: Vec3,
+        draws: impl Iterator<Item = (Mat4, [f32; 4])>,
+    ) -> Buffer {
+        let mut contents = Vec::new();
+
+        for (index, (local_to_world, color)) in draws.enumerate() {
+            contents.resize(model_params_offset(device, index) as usize, 0);
+            contents.extend_from_slice(world_to_clip.as_bytes());
+            contents.extend_from_slice(local_to_world.as_bytes());
+            contents.extend_from_slice([eye.x, eye.y, eye.z, 1.0].as_bytes());
+            contents.extend_from_slice(color.as_bytes());
+        }
+
+        // Bound even if nothing is drawn.
+        contents.resize(contents.len().max(Self::MODEL_PARAMS_SIZE as usize), 0);
+        device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("asset preview model params"),
+            contents: &contents,
+            usage: BufferUsages::UNIFORM,
+        })
+    }
+
+    fn create_model_bind_group(&self, device: &Device, params_buffer: &Buffer) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("asset preview model bind group"),
+            layout: &self.model_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 3,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: params_buffer,
+                    offset: 0,
+                    size: BufferSize::new(Self::MODEL_PARAMS_SIZE),
+                }),
+            }],
+        })
+    }
+
+    /// Creates the pipeline as the shader reflection describes it. Must be called within an error scope,
+    /// as the reflection of a broken shader may not match the shader.
+    fn create_material_pipeline(
+        &self,
+        device: &Device,
+        shader: &asset::assets::Shader,
+    ) -> MaterialPipeline {
+        let reflection = shader.reflection();
+        let group_count = reflection
+            .globals
+            .iter()
+            .map(|global| global.group + 1)
+            .max()
+            .unwrap_or(0);
+        let groups = Vec::from_iter((0..group_count).map(|group| {
+            let entries = Vec::from_iter(
+                reflection
+                    .globals
+                    .iter()
+                    .filter(|global| global.group == group)
+                    .map(|global| BindGroupLayoutEntry {
+                        binding: global.binding,
+                        visibility: ShaderStages::VERTEX_FRAGMENT,
+                        ty: match &global.kind {
+                            ShaderGlobalItemKind::Buffer { size } => BindingType::Buffer {
+                                ty: BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(*size),
+                            },
+                            ShaderGlobalItemKind::Texture {
+                                sample_type,
+                                view_dimension,
+                                multisampled,
+                                ..
+                            } => BindingType::Texture {
+                                sample_type: *sample_type,
+                                view_dimension: *view_dimension,
+                                multisampled: *multisampled,
+                            },
+                            ShaderGlobalItemKind::Sampler { binding_type } => {
+                                BindingType::Sampler(*binding_type)
+                            }
+                        },
+                        count: match &global.kind {
+                            ShaderGlobalItemKind::Texture { array_size, .. } => *array_size,
+                            _ => None,
+                        },
+                    }),
+            );
+            let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("asset preview material bind group layout"),
+                entries: &entries,
+            });
+            (layout, entries)
+        }));
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("asset preview material pipeline layout"),
+            bind_group_layouts: &Vec::from_iter(groups.iter().map(|(layout, _)| layout)),
+            push_constant_ranges: &[],
+        });
+        let attributes = Vec::from_iter(
+            [&reflection.vertex_input, &reflection.instance_input]
+                .into_iter()
+                .filter(|input| !input.fields.is_empty())
+                .map(|input| {
+                    (
+                        input,
+                        Vec::from_iter(input.fields.iter().map(|field| field.attribute)),
+                    )
+                }),
+        );
+        let buffers =
+            Vec::from_iter(
+                attributes
+                    .iter()
+                    .map(|(input, attributes)| VertexBufferLayout {
+                        array_stride: input.stride,
+                        step_mode: input.step_mode,
+                        attributes,
+                    }),
+            );
+        let module = shader.handle();
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("asset preview material pipeline"),
+            layout: Some(&layout),
+            vertex: VertexState {
+                module,
+                entry_point: &reflection.vertex_entry_point,
+                buffers: &buffers,
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: Some(depth_stencil_state()),
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module,
+                entry_point: &reflection.fragment_entry_point,
+                targets: &[Some(ColorTargetState {
+                    format: PREVIEW_FORMAT,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        MaterialPipeline {
+            module: module.clone(),
+            pipeline,
+            groups,
+        }
+    }
+
+    /// Binds the camera transform, then the binding properties of the material, and placeholders to the rest.
+    fn create_material_bind_groups(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        pipeline: &MaterialPipeline,
+        reflection: &ShaderReflection,
+        preset: &MaterialPreset,
+        camera_buffer: &Arc<Buffer>,
+    ) -> Vec<BindGroup> {
+        let camera_key =
+            SemanticShaderBindingKey::new(NonZeroU32::new(KEY_CAMERA_TRANSFORM).unwrap());
+        let mut bind_groups = Vec::with_capacity(pipeline.groups.len());
+
+        for (group, (layout, entries)) in pipeline.groups.iter().enumerate() {
+            let bound = Vec::from_iter(entries.iter().map(|entry| {
+                let global = reflection
+                    .globals
+                    .iter()
+                    .find(|global| global.group == group as u32 && global.binding == entry.binding)
+                    .unwrap();
+                let value = preset
+                    .binding_props
+                    .iter()
+                    .find(|prop| match &prop.key {
+                        MaterialBindingKey::Semantic(key) => global.sematic_key == Some(*key),
+                        MaterialBindingKey::Named(name) => &global.name == name,
+                    })
+                    .map(|prop| &prop.value);
+                let bound = match value {
+                    _ if global.sematic_key == Some(camera_key) => {
+                        Bound::Buffer(camera_buffer.clone(), 0, None)
+                    }
+                    Some(MaterialBindingValue::Buffer {
+                        buffer,
+                        offset,
+                        size,
+                    }) => Bound::Buffer(buffer.clone(), *offset, *size),
+                    Some(MaterialBindingValue::TextureView { view }) => {
+                        Bound::Texture(view.clone())
+                    }
+                    Some(MaterialBindingValue::TextureViewArray { views }) => {
+                        Bound::Textures(views.clone())
+                    }
+                    Some(MaterialBindingValue::Sampler { sampler }) => {
+                        Bound::Sampler(sampler.clone())
+                    }
+                    None => self.placeholder(device, queue, entry),
+                };
+                (entry.binding, bound)
+            }));
+            let texture_arrays = Vec::from_iter(bound.iter().map(|(_, bound)| match bound {
+                Bound::Textures(views) => Vec::from_iter(views.iter().map(|view| view.as_ref())),
+                _ => Vec::new(),
+            }));
+            let bind_group_entries = Vec::from_iter(bound.iter().zip(&texture_arrays).map(
+                |((binding, bound), texture_array)| BindGroupEntry {
+                    binding: *binding,
+                    resource: match bound {
+                        Bound::Buffer(buffer, offset, size) => {
+                            BindingResource::Buffer(BufferBinding {
+                                buffer,
+                                offset: *offset,
+                                size: *size,
+                            })
+                        }
+                        Bound::Texture(view) => BindingResource::TextureView(view),
+                        Bound::Textures(_) => BindingResource::TextureViewArray(texture_array),
+                        Bound::Sampler(sampler) => BindingResource::Sampler(sampler),
+                    },
+                },
+            ));
+
+            bind_groups.push(device.create_bind_group(&BindGroupDescriptor {
+                label: Some("asset preview material bind group"),
+                layout,
+                entries: &bind_group_entries,
+            }));
+        }
+
+        bind_groups
+    }
+
+    fn placeholder(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        entry: &BindGroupLayoutEntry,
+    ) -> Bound {
+        match entry.ty {
+            BindingType::Buffer {
+                min_binding_size, ..
+            } => Bound::Buffer(
+                Arc::new(device.create_buffer(&BufferDescriptor {
+                    label: Some("asset preview placeholder buffer"),
+                    size: min_binding_size.map_or(256, |size| size.get()),
+                    usage: BufferUsages::UNIFORM | BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                })),
+                0,
+                None,
+            ),
+            BindingType::Sampler(SamplerBindingType::Comparison) => {
+                Bound::Sampler(self.comparison_sampler.clone())
+            }
+            BindingType::Sampler(_) => Bound::Sampler(self.sampler.clone()),
+            BindingType::Texture { .. } | BindingType::StorageTexture { .. } => {
+                let view = self
+                    .placeholder_textures
+                    .entry(entry.ty)
+                    .or_insert_with(|| {
+                        Arc::new(create_placeholder_texture(
+                            device,
+                            queue,
+                            entry.ty,
+                            Self::PLACEHOLDER_SIZE,
+                        ))
+                    })
+                    .clone();
+                match entry.count {
+                    Some(count) => Bound::Textures(vec![view; count.get() as usize]),
+                    None => Bound::Texture(view),
+                }
+            }
+        }
+    }
+}
+
+/// Resource resolved for a binding of a material.
+enum Bound {
+    Buffer(Arc<Buffer>, BufferAddress, Option<BufferSize>),
+    Texture(Arc<TextureView>),
+    Textures(Vec<Arc<TextureView>>),
+    Sampler(Arc<Sampler>),
+}
+
+impl PreviewTarget {
+    fn new(device: &Device, size: u32) -> Self {
+        let extent = Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("asset preview target"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: PREVIEW_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let depth_view = device
+            .create_texture(&TextureDescriptor {
+                label: Some("asset preview depth"),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: PREVIEW_DEPTH_FORMAT,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+            .create_view(&TextureViewDescriptor::default());
+        let padded_bytes_per_row = (size * 4 + COPY_BYTES_PER_ROW_ALIGNMENT - 1)
+            / COPY_BYTES_PER_ROW_ALIGNMENT
+            * COPY_BYTES_PER_ROW_ALIGNMENT;
+        let read_back_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("asset preview read back buffer"),
+            size: (padded_bytes_per_row * size) as BufferAddress,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            view: texture.create_view(&TextureViewDescriptor::default()),
+            texture,
+            depth_view,
+            read_back_buffer,
+            padded_bytes_per_row,
+        }
+    }
+
+    /// Copies the target out, waiting for the device. Must be called after the preview has been submitted.
+    fn read_back(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        size: u32,
+    ) -> Result<RgbaImage, AssetPreviewError> {
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("asset preview read back encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            ImageCopyBuffer {
+                buffer: &self.read_back_buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(size),
+                },
+            },
+            Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = channel();
+        self.read_back_buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        device.poll(Maintain::Wait);
+
+        if !matches!(receiver.try_recv(), Ok(Ok(()))) {
+            return Err(AssetPreviewError::ReadBackFailed);
+        }
+
+        let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+
+        {
+            let data = self.read_back_buffer.slice(..).get_mapped_range();
+
+            for row in data.chunks_exact(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..size as usize * 4]);
+            }
+        }
+
+        self.read_back_buffer.unmap();
+        RgbaImage::from_raw(size, size, pixels).ok_or(AssetPreviewError::ReadBackFailed)
+    }
+}
+
+impl PreviewSphere {
+    fn new(device: &Device) -> Self {
+        let mut positions =
+            Vec::with_capacity(((SPHERE_RINGS + 1) * (SPHERE_SEGMENTS + 1)) as usize);
+        let mut uvs = Vec::with_capacity(positions.capacity());
+
+        for ring in 0..=SPHERE_RINGS {
+            let theta = std::f32::consts::PI * ring as f32 / SPHERE_RINGS as f32;
+
+            for segment in 0..=SPHERE_SEGMENTS {
+                let phi = std::f32::consts::TAU * segment as f32 / SPHERE_SEGMENTS as f32;
+                positions.push([
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                ]);
+                uvs.push([
+                    segment as f32 / SPHERE_SEGMENTS as f32,
+                    ring as f32 / SPHERE_RINGS as f32,
+                ]);
+            }
+        }
+
+        let mut indices = Vec::with_capacity((SPHERE_RINGS * SPHERE_SEGMENTS * 6) as usize);
+
+        for ring in 0..SPHERE_RINGS {
+            for segment in 0..SPHERE_SEGMENTS {
+                let top = ring * (SPHERE_SEGMENTS + 1) + segment;
+                let bottom = top + SPHERE_SEGMENTS + 1;
+                indices.extend_from_slice(&[top, bottom, top + 1, top + 1, bottom, bottom + 1]);
+            }
+        }
+
+        let position_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("asset preview sphere positions"),
+            contents: positions.as_bytes(),
+            usage: BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("asset preview sphere indices"),
+            contents: indices.as_bytes(),
+            usage: BufferUsages::INDEX,
+        });
+
+        Self {
+            positions,
+            uvs,
+            indices,
+            position_buffer,
+            index_buffer,
+        }
+    }
+
+    /// Lays the sphere out as the shader input describes it. Positions, normals, UVs and transform rows are filled
+    /// by their semantic keys, other fields by the named instance properties of the material; the rest is zeroed.
+    fn input_data(&self, input: &ShaderInput, preset: &MaterialPreset) -> Vec<u8> {
+        let count = match input.step_mode {
+            VertexStepMode::Vertex => self.positions.len(),
+            VertexStepMode::Instance => 1,
+        };
+        let mut data = vec![0u8; input.stride as usize * count];
+        let identity = Mat4::identity();
+
+        for index in 0..count {
+            for field in &input.fields {
+                let semantic = field.semantic_key;
+                let row = (KEY_TRANSFORM_ROW_0..KEY_TRANSFORM_ROW_0 + 4)
+                    .position(|key| semantic == Some(input_key(key)));
+                let values: Option<&[u8]> = if semantic == Some(input_key(KEY_POSITION))
+                    || semantic == Some(input_key(KEY_NORMAL))
+                {
+                    // The normal of a unit sphere is its position.
+                    Some(self.positions[index].as_bytes())
+                } else if semantic == Some(input_key(KEY_UV)) {
+                    Some(self.uvs[index].as_bytes())
+                } else if let Some(row) = row {
+                    Some(identity.elements[row * 4..row * 4 + 4].as_bytes())
+                } else {
+                    // Semantic instance properties are keyed by binding keys, which do not identify inputs.
+                    preset
+                        .instance_props
+                        .iter()
+                        .find(|prop| match &prop.key {
+                            MaterialInstancePropKey::Semantic(_) => false,
+                            MaterialInstancePropKey::Named(name) => &field.name == name,
+                        })
+                        .map(|prop| instance_prop_bytes(&prop.value))
+                };
+
+                if let Some(values) = values {
+                    let offset = index * input.stride as usize + field.attribute.offset as usize;
+                    let len = values.len().min(field.attribute.format.size() as usize);
+                    data[offset..offset + len].copy_from_slice(&values[..len]);
+                }
+            }
+        }
+
+        data
+    }
+}
+
+/// Renders a batch of previews on the main thread through the task scheduler, as many per step as the budget allows,
+/// so that generating many thumbnails never hitches the frame. Every request yields a result; a failing request
+/// does not stop the batch.
+pub struct AssetPreviewTask {
+    gfx_ctx: GfxContextHandle,
+    renderer: Arc<Mutex<PreviewRenderer>>,
+    requests: VecDeque<AssetPreviewRequest>,
+    request_count: usize,
+    previews: Vec<(AssetKey, Result<AssetPreview, AssetPreviewError>)>,
+}
+
+impl AssetPreviewTask {
+    pub fn new(
+        gfx_ctx: GfxContextHandle,
+        renderer: Arc<Mutex<PreviewRenderer>>,
+        requests: impl IntoIterator<Item = AssetPreviewRequest>,
+    ) -> Self {
+        let requests = VecDeque::from_iter(requests);
+
+        Self {
+            gfx_ctx,
+            renderer,
+            request_count: requests.len(),
+            previews: Vec::with_capacity(requests.len()),
+            requests,
+        }
+    }
+}
+
+impl Task for AssetPreviewTask {
+    /// Previews in the order of the requests.
+    type Output = Vec<(AssetKey, Result<AssetPreview, AssetPreviewError>)>;
+
+    fn step(&mut self, _ctx: &mut TaskContext, deadline: Instant) -> TaskStatus<Self::Output> {
+        let mut renderer = self.renderer.lock();
+
+        // At least one preview per step, so that the batch progresses even without any budget left.
+        while let Some(request) = self.requests.pop_front() {
+            let preview =
+                renderer.render_request(&self.gfx_ctx.device, &self.gfx_ctx.queue, &request);
+            self.previews
+                .push((asset_key(&request.asset).clone(), preview));
+
+            if deadline <= Instant::now() {
+                break;
+            }
+        }
+
+        if self.requests.is_empty() {
+            TaskStatus::Done(std::mem::take(&mut self.previews))
+        } else {
+            TaskStatus::InProgress(self.previews.len() as f32 / self.request_count as f32)
+        }
+    }
+}
+
+fn asset_key(asset: &TypedAsset) -> &AssetKey {
+    match asset {
+        TypedAsset::Font(font) => font.key(),
+        TypedAsset::Material(material) => material.key(),
+        TypedAsset::Model(model) => model.key(),
+        TypedAsset::Shader(shader) => shader.key(),
+        TypedAsset::Texture(texture) => texture.key(),
+    }
+}
+
+fn input_key(key: u32) -> SemanticShaderInputKey {
+    SemanticShaderInputKey::new(NonZeroU32::new(key).unwrap())
+}
+
+fn instance_prop_bytes(value: &MaterialInstancePropValue) -> &[u8] {
+    match value {
+        MaterialInstancePropValue::Uint8x2(value) => value.as_bytes(),
+        MaterialInstancePropValue::Uint8x4(value) => value.as_bytes(),
+        MaterialInstancePropValue::Sint8x2(value) => value.as_bytes(),
+        MaterialInstancePropValue::Sint8x4(value) => value.as_bytes(),
+        MaterialInstancePropValue::Unorm8x2(value) => value.as_bytes(),
+        MaterialInstancePropValue::Unorm8x4(value) => value.as_bytes(),
+        MaterialInstancePropValue::Snorm8x2(value) => value.as_bytes(),
+        MaterialInstancePropValue::Snorm8x4(value) => value.as_bytes(),
+        MaterialInstancePropValue::Uint16x2(value) => value.as_bytes(),
+        MaterialInstancePropValue::Uint16x4(value) => value.as_bytes(),
+        MaterialInstancePropValue::Sint16x2(value) => value.as_bytes(),
+        MaterialInstancePropValue::Sint16x4(value) => value.as_bytes(),
+        MaterialInstancePropValue::Unorm16x2(value) => value.as_bytes(),
+        MaterialInstancePropValue::Unorm16x4(value) => value.as_bytes(),
+        MaterialInstancePropValue::Snorm16x2(value) => value.as_bytes(),
+        MaterialInstancePropValue::Snorm16x4(value) => value.as_bytes(),
+        MaterialInstancePropValue::Float32(value) => value.as_bytes(),
+        MaterialInstancePropValue::Float32x2(value) => value.as_bytes(),
+        MaterialInstancePropValue::Float32x3(value) => value.as_bytes(),
+        MaterialInstancePropValue::Float32x4(value) => value.as_bytes(),
+        MaterialInstancePropValue::Uint32(value) => value.as_bytes(),
+        MaterialInstancePropValue::Uint32x2(value) => value.as_bytes(),
+        MaterialInstancePropValue::Uint32x3(value) => value.as_bytes(),
+        MaterialInstancePropValue::Uint32x4(value) => value.as_bytes(),
+        MaterialInstancePropValue::Sint32(value) => value.as_bytes(),
+        MaterialInstancePropValue::Sint32x2(value) => value.as_bytes(),
+        MaterialInstancePropValue::Sint32x3(value) => value.as_bytes(),
+        MaterialInstancePropValue::Sint32x4(value) => value.as_bytes(),
+        MaterialInstancePropValue::Float64(value) => value.as_bytes(),
+        MaterialInstancePropValue::Float64x2(value) => value.as_bytes(),
+        MaterialInstancePropValue::Float64x3(value) => value.as_bytes(),
+        MaterialInstancePropValue::Float64x4(value) => value.as_bytes(),
+    }
+}
+
+/// World matrices of the nodes, in the order of the nodes.
+fn node_matrices(model: &dyn ModelAsset) -> Vec<Mat4> {
+    let nodes = model.nodes();
+    let mut matrices = vec![None; nodes.len()];
+
+    for index in 0..nodes.len() {
+        node_matrix(nodes, index, &mut matrices, 0);
+    }
+
+    Vec::from_iter(matrices.into_iter().map(|matrix| matrix.unwrap()))
+}
+
+fn node_matrix(nodes: &[Node], index: usize, matrices: &mut [Option<Mat4>], depth: usize) -> Mat4 {
+    if let Some(matrix) = &matrices[index] {
+        return matrix.clone();
+    }
+
+    let local = Mat4::new(nodes[index].transform.matrix);
+    let matrix = match nodes[index].parent_index {
+        // The depth guards against cycles in malformed models.
+        Some(parent) if (parent as usize) < nodes.len() && depth < nodes.len() => {
+            local * node_matrix(nodes, parent as usize, matrices, depth + 1)
+        }
+        _ => local,
+    };
+    matrices[index] = Some(matrix.clone());
+    matrix
+}
+
+/// Frames the box from the preview direction. Returns the world-to-clip matrix and the eye position.
+fn framing_camera(min: Vec3, max: Vec3) -> (Mat4, Vec3) {
+    let transform = framing_transform(min, max, PREVIEW_VIEW_DIRECTION, PREVIEW_FOV, 1.0);
+    let eye = Vec3::from_vec4(transform.row(3));
+    let radius = ((max - min) * 0.5).len().max(f32::EPSILON);
+    let distance = Vec3::distance(eye, (min + max) * 0.5);
+    let near = (distance - radius).max(distance * 0.01);
+    let far = distance + radius;
+
+    (
+        transform.inversed() * Mat4::perspective(PREVIEW_FOV, 1.0, near, far),
+        eye,
+    )
+}
+
+fn begin_depth_pass<'a>(
+    encoder: &'a mut CommandEncoder,
+    target: &'a PreviewTarget,
+    label: &'a str,
+) -> RenderPass<'a> {
+    encoder.begin_render_pass(&RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: &target.view,
+            resolve_target: None,
+            ops: Operations {
+                load: LoadOp::Clear(PREVIEW_BACKDROP),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+            view: &target.depth_view,
+            depth_ops: Some(Operations {
+                load: LoadOp::Clear(1.0),
+                store: false,
+            }),
+            stencil_ops: None,
+        }),
+    })
+}
+
+fn depth_stencil_state() -> DepthStencilState {
+    DepthStencilState {
+        format: PREVIEW_DEPTH_FORMAT,
+        depth_write_enabled: true,
+        depth_compare: CompareFunction::Less,
+        stencil: Default::default(),
+        bias: Default::default(),
+    }
+}
+
+/// Offset of the parameters of the `index`th draw in the model params buffer.
+fn model_params_offset(device: &Device, index: usize) -> BufferAddress {
+    let alignment = device.limits().min_uniform_buffer_offset_alignment as BufferAddress;
+    let stride = (PreviewRenderer::MODEL_PARAMS_SIZE + alignment - 1) / alignment * alignment;
+    stride * index as BufferAddress
+}
+
+/// Pops the error scope without blocking. Native backends resolve the scope immediately;
+/// an unresolved scope is taken as no error.
+pub(crate) fn pop_error_scope(device: &Device) -> Option<wgpu::Error> {
+    fn noop_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            noop_raw_waker()
+        }
+        fn noop(_: *const ()) {}
+
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+
+    // The vtable never touches the data pointer.
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    let mut future = std::pin::pin!(device.pop_error_scope());
+
+    match future
+        .as_mut()
+        .poll(&mut std::task::Context::from_waker(&waker))
+    {
+        Poll::Ready(error) => error,
+        Poll::Pending => None,
+    }
+}
+
+/// Draws the text in light gray, with the coverage of the glyphs as alpha.
+fn rasterize_sample_text(font: &dyn FontAsset, text: &str) -> RgbaImage {
+    let mut glyphs = Vec::new();
+    let mut pen = 0.0f32;
+
+    for character in text.chars() {
+        let glyph_id = match font.glyph_id(character) {
+            Some(glyph_id) => glyph_id,
+            None => continue,
+        };
+        let metrics = font.glyph_metrics(glyph_id.glyph_index);
+        let coverage = font.rasterize(glyph_id.glyph_index);
+        let x = pen.round() as i32 + metrics.xmin;
+        pen += metrics.advance_width;
+        glyphs.push((x, metrics, coverage));
+    }
+
+    let left = glyphs.iter().map(|(x, ..)| *x).min().unwrap_or(0);
+    let right = glyphs
+        .iter()
+        .map(|(x, metrics, _)| x + metrics.width as i32)
+        .max()
+        .unwrap_or(0);
+    let bottom = glyphs
+        .iter()
+        .map(|(_, metrics, _)| metrics.ymin)
+        .min()
+        .unwrap_or(0);
+    let top = glyphs
+        .iter()
+        .map(|(_, metrics, _)| metrics.ymin + metrics.height as i32)
+        .max()
+        .unwrap_or(0);
+    let mut image = RgbaImage::new((right - left).max(1) as u32, (top - bottom).max(1) as u32);
+
+    for (x, metrics, coverage) in &glyphs {
+        let origin_x = (x - left) as u32;
+        let origin_y = (top - metrics.ymin - metrics.height as i32) as u32;
+
+        for row in 0..metrics.height {
+            for column in 0..metrics.width {
+                let alpha = coverage[row * metrics.width + column];
+                let pixel = image.get_pixel_mut(origin_x + column as u32, origin_y + row as u32);
+                pixel.0 = [235, 235, 235, pixel.0[3].max(alpha)];
+            }
+        }
+    }
+
+    image
+}
+
+fn upload_image(device: &Device, queue: &Queue, image: &RgbaImage) -> TextureView {
+    device
+        .create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("asset preview image"),
+                size: Extent3d {
+                    width: image.width(),
+                    height: image.height(),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8Unorm,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            image.as_raw(),
+        )
+        .create_view(&TextureViewDescriptor::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::{create_test_device, HeadlessDevice};
+    use asset::{
+        assets::{
+            FontSource, MaterialBindingKeySource, MaterialBindingPropSource,
+            MaterialBindingValueSource, MaterialSource, MeshAABB, MeshSource, ModelSource,
+            NodeTransform, SemanticShaderOutputKey, ShaderGlobalItem, ShaderInputField,
+            ShaderOutputItem, TextureAddressMode, TextureFilterMode, TextureSource,
+        },
+        AssetSource, GfxBridge, GfxBuffer, GfxSampler, GfxTexture, GfxTextureView,
+    };
+    use wgpu::AddressMode;
+
+    const SIZE: u32 = 64;
+
+    /// Loads assets straight onto the test device.
+    struct TestGfxBridge<'a> {
+        device: &'a HeadlessDevice,
+    }
+
+    impl GfxBridge for TestGfxBridge<'_> {
+        fn upload_vertex_buffer(&self, usage: BufferUsages, content: &[u8]) -> GfxBuffer {
+            GfxBuffer::new(
+                self.device
+                    .device
+                    .create_buffer_init(&BufferInitDescriptor {
+                        label: None,
+                        contents: content,
+                        usage,
+                    }),
+            )
+        }
+
+        fn compile_shader(&self, source: wgpu::ShaderSource) -> GfxShaderModule {
+            GfxShaderModule::new(
+                self.device
+                    .device
+                    .create_shader_module(ShaderModuleDescriptor {
+                        label: None,
+                        source,
+                    }),
+            )
+        }
+
+        fn upload_texture(
+            &self,
+            width: u16,
+            height: u16,
+            _array_layers: u32,
+            _format: asset::assets::TextureFormat,
+            levels: &[&[u8]],
+        ) -> GfxTexture {
+            GfxTexture::new(self.device.device.create_texture_with_data(
+                &self.device.queue,
+                &TextureDescriptor {
+                    label: None,
+                    size: Extent3d {
+                        width: width as u32,
+                        height: height as u32,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: TextureFormat::Rgba8Unorm,
+                    usage: TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                },
+                levels[0],
+            ))
+        }
+
+        fn create_texture_view(&self, texture: &Texture, _is_cube_map: bool) -> GfxTextureView {
+            GfxTextureView::new(texture.create_view(&TextureViewDescriptor::default()))
+        }
+
+        fn create_sampler(
+            &self,
+            _filter_mode: TextureFilterMode,
+            _address_mode: (TextureAddressMode, TextureAddressMode),
+        ) -> GfxSampler {
+            GfxSampler::new(self.device.device.create_sampler(&SamplerDescriptor {
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                ..Default::default()
+            }))
+        }
+    }
+
+    fn load<S: AssetSource>(
+        device: &HeadlessDevice,
+        source: S,
+        key: &str,
+        deps: &HashMap<AssetKey, TypedAsset>,
+    ) -> Arc<S::Asset> {
+        source
+            .load(
+                AssetKey::Path(key.to_owned()),
+                deps,
+                &TestGfxBridge { device },
+            )
+            .unwrap()
+    }
+
+    /// A 4x2 texture, opaque red on the left and transparent on the right.
+    fn create_texture(device: &HeadlessDevice) -> TypedAsset {
+        let texels = Vec::from_iter((0..8).flat_map(|index| {
+            if index % 4 < 2 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 0, 0]
+            }
+        }));
+        TypedAsset::Texture(load(
+            device,
+            TextureSource {
+                width: 4,
+                height: 2,
+                format: asset::assets::TextureFormat::RGBA8,
+                filter_mode: TextureFilterMode::Point,
+                address_mode: (TextureAddressMode::Clamp, TextureAddressMode::Clamp),
+                texels,
+                mip_levels: Vec::new(),
+                array_layers: 1,
+                is_cube_map: false,
+                sprites: Vec::new(),
+                nine_patches: Vec::new(),
+            },
+            "texture",
+            &HashMap::new(),
+        ))
+    }
+
+    /// A cube from -1 to 1, with 16-bit indices.
+    fn create_model(device: &HeadlessDevice) -> TypedAsset {
+        let positions = Vec::from_iter((0..8).map(|index| {
+            [
+                if index & 1 == 0 { -1.0f32 } else { 1.0 },
+                if index & 2 == 0 { -1.0 } else { 1.0 },
+                if index & 4 == 0 { -1.0 } else { 1.0 },
+            ]
+        }));
+        let indices: [u16; 36] = [
+            0, 1, 3, 0, 3, 2, 4, 6, 7, 4, 7, 5, 0, 4, 5, 0, 5, 1, 2, 3, 7, 2, 7, 6, 0, 2, 6, 0, 6,
+            4, 1, 5, 7, 1, 7, 3,
+        ];
+        TypedAsset::Model(load(
+            device,
+            ModelSource {
+                root_node_index: Some(0),
+                nodes: vec![Node {
+                    index: 0,
+                    parent_index: None,
+                    children_indices: Vec::new(),
+                    name: "cube".to_owned(),
+                    transform: NodeTransform {
+                        matrix: Mat4::identity().elements,
+                    },
+                    mesh_indices: vec![0],
+                }],
+                meshes: vec![MeshSource {
+                    index: 0,
+                    aabb: MeshAABB {
+                        min: [-1.0; 3],
+                        max: [1.0; 3],
+                    },
+                    index_type: VertexIndexType::U16,
+                    index_buffer: indices.as_bytes().to_vec(),
+                    vertex_attributes: vec![asset::assets::VertexAttribute {
+                        offset: 0,
+                        kind: VertexAttributeKind::Position,
+                    }],
+                    vertex_buffer: positions.as_bytes().to_vec(),
+                    vertex_count: positions.len() as u32,
+                    material: None,
+                    lods: Vec::new(),
+                }],
+                skeleton: None,
+                animations: Vec::new(),
+            },
+            "model",
+            &HashMap::new(),
+        ))
+    }
+
+    /// A material sampling a 1x1 green texture over the sphere.
+    /// With `broken`, the reflection names a fragment entry point the shader does not have.
+    fn create_material(device: &HeadlessDevice, broken: bool) -> TypedAsset {
+        let source = r#"
+            @group(0) @binding(0) var<uniform> camera_transform: mat4x4<f32>;
+            @group(1) @binding(0) var albedo: texture_2d<f32>;
+            @group(1) @binding(1) var albedo_sampler: sampler;
+
+            struct InstanceInput {
+              @location(0) transform_row_0: vec4<f32>,
+              @location(1) transform_row_1: vec4<f32>,
+              @location(2) transform_row_2: vec4<f32>,
+              @location(3) transform_row_3: vec4<f32>,
+            };
+
+            struct VertexInput {
+              @location(4) position: vec3<f32>,
+              @location(5) uv: vec2<f32>,
+            };
+
+            struct VertexOutput {
+              @builtin(position) position: vec4<f32>,
+              @location(0) uv: vec2<f32>,
+            };
+
+            @vertex
+            fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+              var out: VertexOutput;
+              let transform = mat4x4<f32>(
+                instance.transform_row_0,
+                instance.transform_row_1,
+                instance.transform_row_2,
+                instance.transform_row_3,
+              );
+              out.position = camera_transform * transform * vec4<f32>(vertex.position, 1.0);
+              out.uv = vertex.uv;
+              return out;
+            }
+
+            @fragment
+            fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+              return textureSample(albedo, albedo_sampler, in.uv);
+            }
+        "#;
+        let field =
+            |key: u32, name: &str, format: VertexFormat, offset, location| ShaderInputField {
+                semantic_key: Some(input_key(key)),
+                name: name.to_owned(),
+                attribute: VertexAttribute {
+                    format,
+                    offset,
+                    shader_location: location,
+                },
+            };
+        let reflection = ShaderReflection {
+            vertex_entry_point: "vs_main".to_owned(),
+            fragment_entry_point: if broken { "fs_missing" } else { "fs_main" }.to_owned(),
+            globals: vec![
+                ShaderGlobalItem {
+                    sematic_key: Some(SemanticShaderBindingKey::new(
+                        NonZeroU32::new(KEY_CAMERA_TRANSFORM).unwrap(),
+                    )),
+                    name: "camera_transform".to_owned(),
+                    group: 0,
+                    binding: 0,
+                    kind: ShaderGlobalItemKind::Buffer {
+                        size: std::num::NonZeroU64::new(64).unwrap(),
+                    },
+                },
+                ShaderGlobalItem {
+                    sematic_key: None,
+                    name: "albedo".to_owned(),
+                    group: 1,
+                    binding: 0,
+                    kind: ShaderGlobalItemKind::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                        array_size: None,
+                    },
+                },
+                ShaderGlobalItem {
+                    sematic_key: None,
+                    name: "albedo_sampler".to_owned(),
+                    group: 1,
+                    binding: 1,
+                    kind: ShaderGlobalItemKind::Sampler {
+                        binding_type: SamplerBindingType::Filtering,
+                    },
+                },
+            ],
+            vertex_input: ShaderInput {
+                step_mode: VertexStepMode::Vertex,
+                stride: 20,
+                fields: vec![
+                    field(KEY_POSITION, "position", VertexFormat::Float32x3, 0, 4),
+                    field(KEY_UV, "uv", VertexFormat::Float32x2, 12, 5),
+                ],
+            },
+            instance_input: ShaderInput {
+                step_mode: VertexStepMode::Instance,
+                stride: 64,
+                fields: Vec::from_iter((0..4).map(|row| {
+                    field(
+                        KEY_TRANSFORM_ROW_0 + row,
+                        &format!("transform_row_{}", row),
+                        VertexFormat::Float32x4,
+                        row as BufferAddress * 16,
+                        row,
+                    )
+                })),
+            },
+            outputs: vec![ShaderOutputItem {
+                semantic_key: Some(SemanticShaderOutputKey::new(NonZeroU32::new(1).unwrap())),
+                name: "color".to_owned(),
+                location: 0,
+            }],
+        };
+
+        let mut deps = HashMap::new();
+        let shader_key = AssetKey::Path("shader".to_owned());
+        let texture_key = AssetKey::Path("green".to_owned());
+        deps.insert(
+            shader_key.clone(),
+            TypedAsset::Shader(load(
+                device,
+                asset::assets::ShaderSource {
+                    source: source.to_owned(),
+                    reflection,
+                },
+                "shader",
+                &HashMap::new(),
+            )),
+        );
+        deps.insert(
+            texture_key.clone(),
+            TypedAsset::Texture(load(
+                device,
+                TextureSource {
+                    width: 1,
+                    height: 1,
+                    format: asset::assets::TextureFormat::RGBA8,
+                    filter_mode: TextureFilterMode::Point,
+                    address_mode: (TextureAddressMode::Clamp, TextureAddressMode::Clamp),
+                    texels: vec![0, 255, 0, 255],
+                    mip_levels: Vec::new(),
+                    array_layers: 1,
+                    is_cube_map: false,
+                    sprites: Vec::new(),
+                    nine_patches: Vec::new(),
+                },
+                "green",
+                &HashMap::new(),
+            )),
+        );
+
+        TypedAsset::Material(load(
+            device,
+            MaterialSource {
+                shader: shader_key,
+                binding_props: vec![
+                    MaterialBindingPropSource {
+                        key: MaterialBindingKeySource::Named("albedo".to_owned()),
+                        value: MaterialBindingValueSource::TextureView {
+                            texture: texture_key.clone(),
+                        },
+                    },
+                    MaterialBindingPropSource {
+                        key: MaterialBindingKeySource::Named("albedo_sampler".to_owned()),
+                        value: MaterialBindingValueSource::SamplerTexture {
+                            texture: texture_key,
+                        },
+                    },
+                ],
+                instance_props: Vec::new(),
+            },
+            "material",
+            &deps,
+        ))
+    }
+
+    fn create_font(device: &HeadlessDevice) -> TypedAsset {
+        TypedAsset::Font(load(
+            device,
+            FontSource {
+                font_file: include_bytes!("../../r3d-editor/assets/fonts/NotoSans-Regular.ttf")
+                    .to_vec(),
+                sdf_font_size: 32.0,
+                sdf_inset: 4,
+                sdf_radius: 4,
+                sdf_cutoff: 0.25,
+            },
+            "font",
+            &HashMap::new(),
+        ))
+    }
+
+    fn render(
+        renderer: &mut PreviewRenderer,
+        device: &HeadlessDevice,
+        asset: &TypedAsset,
+    ) -> AssetPreview {
+        renderer
+            .render(&device.device, &device.queue, asset, SIZE)
+            .unwrap()
+    }
+
+    fn center(preview: &AssetPreview) -> [u8; 4] {
+        preview.image.get_pixel(SIZE / 2, SIZE / 2).0
+    }
+
+    #[test]
+    fn check_previews_of_each_asset_type() {
+        let device = if let Some(device) = create_test_device() {
+            device
+        } else {
+            return;
+        };
+        let mut renderer = PreviewRenderer::new(&device.device, None);
+
+        // The opaque half as is, and the transparent half and the margins over the checkerboard.
+        let texture = render(&mut renderer, &device, &create_texture(&device));
+        assert_eq!(texture.source, AssetPreviewSource::Rendered);
+        assert_eq!(texture.image.get_pixel(8, SIZE / 2).0, [255, 0, 0, 255]);
+        assert!([[153, 153, 153, 255], [204, 204, 204, 255]]
+            .contains(&texture.image.get_pixel(SIZE - 8, SIZE / 2).0));
+        assert!(
+            [[153, 153, 153, 255], [204, 204, 204, 255]].contains(&texture.image.get_pixel(4, 4).0)
+        );
+
+        let font = render(&mut renderer, &device, &create_font(&device));
+        assert_eq!(font.source, AssetPreviewSource::Rendered);
+        assert!(font.image.pixels().any(|pixel| 150 < pixel.0[0]));
+
+        // The cube covers the center, and the backdrop the corners.
+        let model = render(&mut renderer, &device, &create_model(&device));
+        assert_eq!(model.source, AssetPreviewSource::Rendered);
+        assert_ne!(center(&model), model.image.get_pixel(0, 0).0);
+
+        let [r, g, b, _] = center(&render(
+            &mut renderer,
+            &device,
+            &create_material(&device, false),
+        ));
+        assert!(r < g && b < g);
+    }
+
+    #[test]
+    fn check_broken_shader_falls_back() {
+        let device = if let Some(device) = create_test_device() {
+            device
+        } else {
+            return;
+        };
+        let mut renderer = PreviewRenderer::new(&device.device, None);
+
+        let broken = render(&mut renderer, &device, &create_material(&device, true));
+        assert_eq!(broken.source, AssetPreviewSource::Fallback);
+        let [r, g, b, _] = center(&broken);
+        assert!(g < r && g < b);
+
+        // The batch goes on.
+        let texture = render(&mut renderer, &device, &create_texture(&device));
+        assert_eq!(texture.source, AssetPreviewSource::Rendered);
+    }
+
+    #[test]
+    fn check_previews_are_deterministic() {
+        let device = if let Some(device) = create_test_device() {
+            device
+        } else {
+            return;
+        };
+        let assets = [
+            create_texture(&device),
+            create_font(&device),
+            create_model(&device),
+            create_material(&device, false),
+        ];
+        let hashes =
+            || {
+                let mut renderer = PreviewRenderer::new(&device.device, None);
+                Vec::from_iter(assets.iter().map(|asset| {
+                    ContentHash::of(render(&mut renderer, &device, asset).image.as_raw())
+                }))
+            };
+
+        assert_eq!(hashes(), hashes());
+    }
+
+    #[test]
+    fn check_previews_are_cached_on_disk() {
+        let device = if let Some(device) = create_test_device() {
+            device
+        } else {
+            return;
+        };
+        let cache_dir =
+            std::env::temp_dir().join(format!("r3d-asset-preview-test-{}", std::process::id()));
+        let request = AssetPreviewRequest {
+            asset: create_texture(&device),
+            content_hash: Some(ContentHash::of(b"texture")),
+            size: SIZE,
+        };
+
+        let rendered = PreviewRenderer::new(&device.device, Some(cache_dir.clone()))
+            .render_request(&device.device, &device.queue, &request)
+            .unwrap();
+        let cached = PreviewRenderer::new(&device.device, Some(cache_dir.clone()))
+            .render_request(&device.device, &device.queue, &request)
+            .unwrap();
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+
+        assert_eq!(rendered.source, AssetPreviewSource::Rendered);
+        assert_eq!(cached.source, AssetPreviewSource::Cached);
+        assert_eq!(cached.image, rendered.image);
+    }
+}
@@ -0,0 +1,263 @@
+use super::{
+    FullscreenPass, FullscreenPassOptions, PostEffect, PostEffectContext, Texture,
+    POST_PROCESS_FORMAT,
+};
+use std::{any::Any, mem::size_of};
+use wgpu::{
+    BlendComponent, BlendFactor, BlendOperation, BlendState, CommandEncoder, Device, TextureView,
+};
+use zerocopy::AsBytes;
+
+/// Look of the [`Bloom`] effect, adjustable at any time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomSettings {
+    /// Brightness above which pixels bloom, usually above one so that only HDR highlights do.
+    pub threshold: f32,
+    /// Part of the threshold below it over which the bloom fades in, in `[0, 1]`. Zero cuts it off sharply.
+    pub soft_knee: f32,
+    /// Scale of the bloom added over the scene.
+    pub intensity: f32,
+    /// Number of half-resolution targets the bloom spreads through. More makes it wider.
+    pub max_mips: u32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            soft_knee: 0.5,
+            intensity: 0.8,
+            max_mips: 6,
+        }
+    }
+}
+
+/// Part of a pixel of the given color that blooms. Mirrors the prefilter of the bloom shader: zero below the knee,
+/// a quadratic fade over it, then whatever exceeds the threshold.
+pub fn bloom_contribution(color: [f32; 3], threshold: f32, soft_knee: f32) -> f32 {
+    let brightness = color[0].max(color[1]).max(color[2]);
+    let knee = threshold * soft_knee.clamp(0.0, 1.0);
+    let soft = (brightness - threshold + knee).clamp(0.0, 2.0 * knee);
+    let soft = soft * soft / (4.0 * knee + 1e-5);
+
+    soft.max(brightness - threshold) / brightness.max(1e-5)
+}
+
+/// Sizes of the targets of the downsample chain for a screen of the given size: halving from half the screen down
+/// to at most `max_mips` targets, and never below 1×1.
+pub fn bloom_mip_sizes(width: u32, height: u32, max_mips: u32) -> Vec<(u32, u32)> {
+    let mut sizes = Vec::new();
+    let mut size = ((width / 2).max(1), (height / 2).max(1));
+
+    while sizes.len() < max_mips.max(1) as usize {
+        sizes.push(size);
+
+        if size == (1, 1) {
+            break;
+        }
+
+        size = ((size.0 / 2).max(1), (size.1 / 2).max(1));
+    }
+
+    sizes
+}
+
+/// Built-in effect making the bright parts of the scene glow.
+///
+/// The pixels above the threshold are extracted into a half-resolution target, then downsampled through a chain of
+/// targets, each half the size of the previous one. Going back up, every target is blurred into the larger one
+/// additively, and the largest is added over the scene. Runs on the HDR scene, so before the tonemapping.
+pub struct Bloom {
+    pub settings: BloomSettings,
+    prefilter: FullscreenPass,
+    downsample: FullscreenPass,
+    upsample: FullscreenPass,
+    composite: FullscreenPass,
+    mips: Vec<Texture>,
+}
+
+impl Bloom {
+    pub const NAME: &'static str = "bloom";
+    pub const ORDER: i32 = 500;
+
+    pub fn new(device: &Device) -> Self {
+        let prefilter = FullscreenPass::new(
+            device,
+            "bloom prefilter pass",
+            include_str!("./built_in_shaders/post_bloom_prefilter.wgsl"),
+            Some(size_of::<[f32; 4]>() as u64),
+            POST_PROCESS_FORMAT,
+        );
+        let downsample = FullscreenPass::new(
+            device,
+            "bloom downsample pass",
+            include_str!("./built_in_shaders/post_bloom_downsample.wgsl"),
+            None,
+            POST_PROCESS_FORMAT,
+        );
+        let upsample = FullscreenPass::with_options(
+            device,
+            "bloom upsample pass",
+            include_str!("./built_in_shaders/post_bloom_upsample.wgsl"),
+            POST_PROCESS_FORMAT,
+            FullscreenPassOptions {
+                blend: Some(BlendState {
+                    color: BlendComponent {
+                        src_factor: BlendFactor::One,
+                        dst_factor: BlendFactor::One,
+                        operation: BlendOperation::Add,
+                    },
+                    alpha: BlendComponent {
+                        src_factor: BlendFactor::Zero,
+                        dst_factor: BlendFactor::One,
+                        operation: BlendOperation::Add,
+                    },
+                }),
+                ..Default::default()
+            },
+        );
+        let composite = FullscreenPass::with_options(
+            device,
+            "bloom composite pass",
+            include_str!("./built_in_shaders/post_bloom_composite.wgsl"),
+            POST_PROCESS_FORMAT,
+            FullscreenPassOptions {
+                uniform_size: Some(size_of::<[f32; 4]>() as u64),
+                extra_textures: 1,
+                ..Default::default()
+            },
+        );
+
+        Self {
+            settings: BloomSettings::default(),
+            prefilter,
+            downsample,
+            upsample,
+            composite,
+            mips: Vec::new(),
+        }
+    }
+
+    /// Number of targets of the downsample chain, once the first frame has been drawn.
+    pub fn mip_count(&self) -> usize {
+        self.mips.len()
+    }
+
+    /// Reallocates the chain if the screen or `max_mips` changed.
+    fn allocate_mips(&mut self, device: &Device, width: u32, height: u32) {
+        let sizes = bloom_mip_sizes(width, height, self.settings.max_mips);
+
+        if self.mips.len() == sizes.len()
+            && self.mips.iter().zip(&sizes).all(|(mip, &(width, height))| {
+                mip.width as u32 == width && mip.height as u32 == height
+            })
+        {
+            return;
+        }
+
+        self.mips = Vec::from_iter(sizes.into_iter().map(|(width, height)| {
+            Texture::create_render_target(
+                width.min(u16::MAX as u32) as u16,
+                height.min(u16::MAX as u32) as u16,
+                POST_PROCESS_FORMAT,
+                device,
+            )
+        }));
+    }
+}
+
+impl PostEffect for Bloom {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn resize(&mut self, device: &Device, width: u32, height: u32) {
+        self.allocate_mips(device, width, height);
+    }
+
+    fn encode(
+        &mut self,
+        ctx: &PostEffectContext,
+        encoder: &mut CommandEncoder,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        // `max_mips` may have changed since the last resize.
+        self.allocate_mips(ctx.device, ctx.width, ctx.height);
+
+        let settings = &self.settings;
+        self.prefilter.write_uniform(
+            ctx.queue,
+            [
+                settings.threshold.max(0.0),
+                settings.soft_knee.clamp(0.0, 1.0),
+                0.0,
+                0.0,
+            ]
+            .as_bytes(),
+        );
+        self.composite.write_uniform(
+            ctx.queue,
+            [settings.intensity.max(0.0), 0.0, 0.0, 0.0].as_bytes(),
+        );
+
+        self.prefilter
+            .encode(ctx.device, encoder, input, &self.mips[0].view);
+
+        for pair in self.mips.windows(2) {
+            self.downsample
+                .encode(ctx.device, encoder, &pair[0].view, &pair[1].view);
+        }
+
+        for pair in self.mips.windows(2).rev() {
+            self.upsample
+                .encode(ctx.device, encoder, &pair[1].view, &pair[0].view);
+        }
+
+        self.composite.encode_with_textures(
+            ctx.device,
+            encoder,
+            input,
+            &[&self.mips[0].view],
+            output,
+        );
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_mip_chain_stops_at_one_pixel() {
+        assert_eq!(
+            bloom_mip_sizes(1920, 1080, 4),
+            vec![(960, 540), (480, 270), (240, 135), (120, 67)]
+        );
+        assert_eq!(bloom_mip_sizes(8, 3, 16), vec![(4, 1), (2, 1), (1, 1)]);
+        assert_eq!(bloom_mip_sizes(1, 1, 16), vec![(1, 1)]);
+        assert_eq!(bloom_mip_sizes(0, 0, 0), vec![(1, 1)]);
+    }
+
+    #[test]
+    fn check_soft_knee_fades_in_below_the_threshold() {
+        // Nothing below the knee, everything above the threshold blooms by its excess.
+        assert_eq!(bloom_contribution([0.4, 0.2, 0.1], 1.0, 0.5), 0.0);
+        assert!((bloom_contribution([4.0, 0.0, 0.0], 1.0, 0.5) - 0.75).abs() < 1e-4);
+
+        let mut previous = 0.0;
+
+        for step in 0..=40 {
+            let contribution = bloom_contribution([step as f32 * 0.05, 0.0, 0.0], 1.0, 0.5);
+            assert!(previous <= contribution + 1e-6);
+            previous = contribution;
+        }
+
+        // Without a knee, the cut is sharp.
+        assert_eq!(bloom_contribution([0.99, 0.0, 0.0], 1.0, 0.0), 0.0);
+    }
+}
@@ -14,6 +14,53 @@ pub const BUILT_IN_SHADER_UI_ELEMENT_NORMAL: BuiltInShaderKey =
     BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(1) });
 pub const BUILT_IN_SHADER_UI_TEXT_NORMAL: BuiltInShaderKey =
     BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(11) });
+/// Water and mirror shader sampling a [`PlanarReflection`](super::PlanarReflection).
+/// The `environment_texture` cubemap and `environment_sampler` must be set on the material.
+pub const BUILT_IN_SHADER_PLANAR_REFLECTION: BuiltInShaderKey =
+    BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(21) });
+/// Metallic-roughness PBR shader. Its bindings are set by [`PbrMaterial::bind`](super::PbrMaterial::bind)
+/// and [`PbrLighting::bind`](super::PbrLighting::bind).
+pub const BUILT_IN_SHADER_STANDARD_PBR: BuiltInShaderKey =
+    BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(31) });
+/// Soft round particles of a [`ParticleSystem`](super::ParticleSystem), tinted by their color.
+pub const BUILT_IN_SHADER_PARTICLE: BuiltInShaderKey =
+    BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(41) });
+/// Heightmap shader of a [`Terrain`](super::Terrain), blending the layers of a [`TextureArray`](super::TextureArray)
+/// by the weights of a splat map. Its material bindings are set by [`bind_terrain_splatting`](super::bind_terrain_splatting).
+pub const BUILT_IN_SHADER_TERRAIN: BuiltInShaderKey =
+    BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(51) });
+/// Gerstner wave shader of a [`WaterSurface`](super::WaterSurface), sampling the reflection of a
+/// [`PlanarReflection`](super::PlanarReflection) on the same object. As for the planar reflection shader,
+/// the `environment_texture` cubemap and `environment_sampler` must be set on the material.
+pub const BUILT_IN_SHADER_WATER: BuiltInShaderKey =
+    BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(61) });
+/// Vertex-colored shader of an [`HlodProxy`](super::HlodProxy), lit like the terrain. It has no material bindings.
+pub const BUILT_IN_SHADER_HLOD_PROXY: BuiltInShaderKey =
+    BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(71) });
+/// Unlit vertex-colored shader of a [`LineRenderer`](super::LineRenderer). It has no material bindings.
+pub const BUILT_IN_SHADER_LINE: BuiltInShaderKey =
+    BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(81) });
+/// Textured quads of a [`SpriteRenderer`](super::SpriteRenderer), tinted by their color. It has no material bindings.
+pub const BUILT_IN_SHADER_SPRITE: BuiltInShaderKey =
+    BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(91) });
+/// Signed distance field glyphs of a [`TextRenderer`](super::TextRenderer), tinted by their color. It has no material
+/// bindings.
+pub const BUILT_IN_SHADER_TEXT: BuiltInShaderKey =
+    BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(101) });
+/// The standard PBR shader for meshes deformed by a [`MeshSkin`](super::MeshSkin), reading their `bone_indices` and
+/// `bone_weights`. Its bindings are the same as the standard one.
+pub const BUILT_IN_SHADER_STANDARD_PBR_SKINNED: BuiltInShaderKey =
+    BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(111) });
+/// Blinn-Phong shader lit by the [`DirectionalLight`](super::DirectionalLight)s of the scene, the
+/// [`PointLight`](super::PointLight)s and [`SpotLight`](super::SpotLight)s picked for each object, and its ambient
+/// color. Its per-instance properties are set by [`LitMaterial::bind`](super::LitMaterial::bind).
+pub const BUILT_IN_SHADER_LIT: BuiltInShaderKey =
+    BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(121) });
+
+/// Sky drawn behind the scene by [`RenderManager::set_skybox`](super::RenderManager::set_skybox). Its
+/// `skybox_texture` cubemap and `skybox_sampler` are bound by the [`Skybox`](super::Skybox).
+pub const BUILT_IN_SHADER_SKYBOX: BuiltInShaderKey =
+    BuiltInShaderKey::new(unsafe { NonZeroU64::new_unchecked(131) });
 
 pub struct BuiltInShaderManager {
     shaders: HashMap<BuiltInShaderKey, ShaderHandle>,
@@ -35,14 +82,109 @@ impl BuiltInShaderManager {
             shader_mgr,
             bind_group_layout_cache,
             BUILT_IN_SHADER_UI_ELEMENT_NORMAL,
+            "ui_element.normal.wgsl",
             include_str!("./built_in_shaders/ui_element.normal.wgsl"),
         );
         self.add_shader(
             shader_mgr,
             bind_group_layout_cache,
             BUILT_IN_SHADER_UI_TEXT_NORMAL,
+            "ui_text.normal.wgsl",
             include_str!("./built_in_shaders/ui_text.normal.wgsl"),
         );
+        self.add_shader(
+            shader_mgr,
+            bind_group_layout_cache,
+            BUILT_IN_SHADER_PLANAR_REFLECTION,
+            "planar_reflection.wgsl",
+            include_str!("./built_in_shaders/planar_reflection.wgsl"),
+        );
+        self.add_shader(
+            shader_mgr,
+            bind_group_layout_cache,
+            BUILT_IN_SHADER_STANDARD_PBR,
+            "standard_pbr.wgsl",
+            concat!(
+                include_str!("./built_in_shaders/standard_pbr_surface.wgsl"),
+                include_str!("./built_in_shaders/standard_pbr.wgsl"),
+            ),
+        );
+        self.add_shader(
+            shader_mgr,
+            bind_group_layout_cache,
+            BUILT_IN_SHADER_STANDARD_PBR_SKINNED,
+            "standard_pbr_skinned.wgsl",
+            concat!(
+                include_str!("./built_in_shaders/standard_pbr_surface.wgsl"),
+                include_str!("./built_in_shaders/standard_pbr_skinned.wgsl"),
+            ),
+        );
+        self.add_shader(
+            shader_mgr,
+            bind_group_layout_cache,
+            BUILT_IN_SHADER_PARTICLE,
+            "particle.wgsl",
+            include_str!("./built_in_shaders/particle.wgsl"),
+        );
+        self.add_shader(
+            shader_mgr,
+            bind_group_layout_cache,
+            BUILT_IN_SHADER_TERRAIN,
+            "terrain.wgsl",
+            include_str!("./built_in_shaders/terrain.wgsl"),
+        );
+        self.add_shader(
+            shader_mgr,
+            bind_group_layout_cache,
+            BUILT_IN_SHADER_WATER,
+            "water.wgsl",
+            concat!(
+                include_str!("./built_in_shaders/water_waves.wgsl"),
+                include_str!("./built_in_shaders/water.wgsl"),
+            ),
+        );
+        self.add_shader(
+            shader_mgr,
+            bind_group_layout_cache,
+            BUILT_IN_SHADER_HLOD_PROXY,
+            "hlod_proxy.wgsl",
+            include_str!("./built_in_shaders/hlod_proxy.wgsl"),
+        );
+        self.add_shader(
+            shader_mgr,
+            bind_group_layout_cache,
+            BUILT_IN_SHADER_LINE,
+            "line.wgsl",
+            include_str!("./built_in_shaders/line.wgsl"),
+        );
+        self.add_shader(
+            shader_mgr,
+            bind_group_layout_cache,
+            BUILT_IN_SHADER_SPRITE,
+            "sprite.wgsl",
+            include_str!("./built_in_shaders/sprite.wgsl"),
+        );
+        self.add_shader(
+            shader_mgr,
+            bind_group_layout_cache,
+            BUILT_IN_SHADER_TEXT,
+            "text.wgsl",
+            include_str!("./built_in_shaders/text.wgsl"),
+        );
+        self.add_shader(
+            shader_mgr,
+            bind_group_layout_cache,
+            BUILT_IN_SHADER_LIT,
+            "lit.wgsl",
+            include_str!("./built_in_shaders/lit.wgsl"),
+        );
+        self.add_shader(
+            shader_mgr,
+            bind_group_layout_cache,
+            BUILT_IN_SHADER_SKYBOX,
+            "skybox.wgsl",
+            include_str!("./built_in_shaders/skybox.wgsl"),
+        );
     }
 
     fn add_shader(
@@ -50,11 +192,12 @@ impl BuiltInShaderManager {
         shader_mgr: &ShaderManager,
         bind_group_layout_cache: &mut BindGroupLayoutCache,
         key: BuiltInShaderKey,
+        name: &str,
         source: &str,
     ) {
         let shader = shader_mgr
-            .create_shader(bind_group_layout_cache, source)
-            .unwrap();
+            .create_named_shader(bind_group_layout_cache, name, source)
+            .unwrap_or_else(|err| panic!("failed to create a built-in shader: {}", err));
         self.shaders.insert(key, shader);
     }
 
@@ -0,0 +1,99 @@
+// Previews of assets for editor thumbnails.
+// Images (textures and fonts) are drawn by a full-screen triangle fitting the image into `rect`,
+// over an alpha checkerboard or a neutral backdrop. Models and the error material are drawn flat-shaded
+// under a fixed light, with normals derived from the screen-space derivatives of the positions.
+
+struct ImageParams {
+  // xy: top-left corner, zw: bottom-right corner of the image, in `[0, 1]` of the preview.
+  rect: vec4<f32>,
+  // x: 1 for the checkerboard backdrop, y: number of checkerboard cells across the preview.
+  backdrop: vec4<f32>,
+};
+
+struct ModelParams {
+  world_to_clip: mat4x4<f32>,
+  local_to_world: mat4x4<f32>,
+  eye: vec4<f32>,
+  color: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> image_params: ImageParams;
+@group(0) @binding(1) var image_texture: texture_2d<f32>;
+@group(0) @binding(2) var image_sampler: sampler;
+
+// Separate from the image bindings, as both are declared in the same module.
+@group(0) @binding(3) var<uniform> model_params: ModelParams;
+
+// Direction towards the light.
+const LIGHT_DIRECTION: vec3<f32> = vec3<f32>(0.4, 0.8, 0.6);
+const AMBIENT: f32 = 0.35;
+const NEUTRAL: vec3<f32> = vec3<f32>(0.22, 0.22, 0.24);
+const CHECKER_LIGHT: vec3<f32> = vec3<f32>(0.8, 0.8, 0.8);
+const CHECKER_DARK: vec3<f32> = vec3<f32>(0.6, 0.6, 0.6);
+
+struct ImageVertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+};
+
+struct ModelVertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) world_position: vec3<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_image(@builtin(vertex_index) index: u32) -> ImageVertexOutput {
+  var out: ImageVertexOutput;
+  let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+  out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+  out.uv = uv;
+  return out;
+}
+
+@fragment
+fn fs_image(in: ImageVertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  var backdrop = NEUTRAL;
+
+  if (0.5 < image_params.backdrop.x) {
+    let cell = vec2<i32>(floor(in.uv * image_params.backdrop.y));
+    backdrop = select(CHECKER_DARK, CHECKER_LIGHT, (cell.x + cell.y) % 2 == 0);
+  }
+
+  let uv = (in.uv - image_params.rect.xy) / (image_params.rect.zw - image_params.rect.xy);
+  // Sampled outside of the branch, as it needs uniform control flow.
+  let color = textureSample(image_texture, image_sampler, clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)));
+  let inside = all(vec2<f32>(0.0) <= uv) && all(uv <= vec2<f32>(1.0));
+  let alpha = select(0.0, color.a, inside);
+
+  out.color = vec4<f32>(mix(backdrop, color.rgb, alpha), 1.0);
+  return out;
+}
+
+@vertex
+fn vs_model(@location(0) position: vec3<f32>) -> ModelVertexOutput {
+  var out: ModelVertexOutput;
+  let world_position = model_params.local_to_world * vec4<f32>(position, 1.0);
+  out.position = model_params.world_to_clip * world_position;
+  out.world_position = world_position.xyz;
+  return out;
+}
+
+@fragment
+fn fs_model(in: ModelVertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  var normal = normalize(cross(dpdx(in.world_position), dpdy(in.world_position)));
+
+  // Faces the camera whatever the winding of the mesh is.
+  if (dot(normal, model_params.eye.xyz - in.world_position) < 0.0) {
+    normal = -normal;
+  }
+
+  let diffuse = clamp(dot(normal, normalize(LIGHT_DIRECTION)), 0.0, 1.0);
+  out.color = vec4<f32>(model_params.color.rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse), 1.0);
+  return out;
+}
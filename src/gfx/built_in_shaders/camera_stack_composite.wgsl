@@ -0,0 +1,39 @@
+// Composites the layer of a camera stack slot over the output of the stack with a full-screen triangle.
+// The blend state of the pipeline selects the blend mode; the opacity scales the alpha, or the whole color of
+// premultiplied layers.
+
+struct Params {
+  // x: opacity, y: 1 if the layer is premultiplied.
+  opacity: vec4<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+};
+
+@group(0) @binding(0) var layer: texture_2d<f32>;
+@group(0) @binding(1) var layer_sampler: sampler;
+@group(0) @binding(2) var<uniform> params: Params;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+  let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+  var out: VertexOutput;
+  out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+  // Textures are sampled from the top left corner.
+  out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = textureSample(layer, layer_sampler, in.uv);
+  let opacity = params.opacity.x;
+
+  if (0.5 < params.opacity.y) {
+    return color * opacity;
+  }
+
+  return vec4<f32>(color.rgb, color.a * opacity);
+}
@@ -0,0 +1,40 @@
+// Glyphs of the console overlay, positioned in clip space and sampled from the SDF glyph textures.
+
+@group(0) @binding(0) var glyph_texture: texture_2d<f32>;
+@group(1) @binding(0) var glyph_sampler: sampler;
+
+struct VertexInput {
+  @location(0) position: vec2<f32>,
+  @location(1) uv: vec2<f32>,
+  @location(2) color: vec4<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+  @location(1) color: vec4<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  out.position = vec4<f32>(vertex.position, 0.0, 1.0);
+  out.uv = vertex.uv;
+  out.color = vertex.color;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  let distance = textureSample(glyph_texture, glyph_sampler, in.uv).r;
+  // The edge is kept about a pixel wide whatever the font size.
+  let width = max(fwidth(distance), 0.0001);
+  let alpha = smoothstep(0.5 - width, 0.5 + width, distance);
+  out.color = vec4<f32>(in.color.rgb, in.color.a * alpha);
+  return out;
+}
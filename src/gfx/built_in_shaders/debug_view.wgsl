@@ -0,0 +1,79 @@
+// Drawn in place of the shaders of the materials by the debug view modes, see `debug_view.rs`. The binding of
+// `camera_transform` is declared ahead of this source, at the group and binding of the material it replaces.
+
+// Distance along the view over which the depth view fades out, by 1/e.
+const DEPTH_FALLOFF: f32 = 16.0;
+const WIRE_COLOR: vec3<f32> = vec3<f32>(1.0, 1.0, 1.0);
+
+struct InstanceInput {
+  @location(2) transform_row_0: vec4<f32>,
+  @location(3) transform_row_1: vec4<f32>,
+  @location(4) transform_row_2: vec4<f32>,
+  @location(5) transform_row_3: vec4<f32>,
+};
+
+struct VertexInput {
+  @builtin(vertex_index) index: u32,
+  @location(0) position: vec3<f32>,
+  @location(1) normal: vec3<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) world_normal: vec3<f32>,
+  // x: w of the clip position, y: depth after the division.
+  @location(1) depth: vec2<f32>,
+  @location(2) barycentric: vec3<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let transform = mat4x4<f32>(instance.transform_row_0, instance.transform_row_1, instance.transform_row_2, instance.transform_row_3);
+  let position = camera_transform * transform * vec4<f32>(vertex.position, 1.0);
+  // Each corner of a triangle of a list, which unindexed vertices are in order.
+  let corner = vertex.index % 3u;
+
+  out.position = position;
+  out.world_normal = (transform * vec4<f32>(vertex.normal, 0.0)).xyz;
+  out.depth = vec2<f32>(position.w, position.z / position.w);
+  out.barycentric = vec3<f32>(f32(corner == 0u), f32(corner == 1u), f32(corner == 2u));
+  return out;
+}
+
+@fragment
+fn fs_normals(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  out.color = vec4<f32>(normalize(in.world_normal) * 0.5 + 0.5, 1.0);
+  return out;
+}
+
+@fragment
+fn fs_depth(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  // A perspective projection puts the distance along the view in w; an orthographic one keeps w at one, with the
+  // depth linear already.
+  let is_perspective = any(transpose(camera_transform)[3].xyz != vec3<f32>(0.0));
+  let brightness = select(1.0 - in.depth.y, exp(-in.depth.x / DEPTH_FALLOFF), is_perspective);
+  out.color = vec4<f32>(vec3<f32>(brightness), 1.0);
+  return out;
+}
+
+@fragment
+fn fs_wireframe(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  // About a pixel wide, whatever the size of the triangle on the screen.
+  let edges = smoothstep(vec3<f32>(0.0), fwidth(in.barycentric) * 1.5, in.barycentric);
+  let coverage = 1.0 - min(min(edges.x, edges.y), edges.z);
+
+  if coverage <= 0.0 {
+    discard;
+  }
+
+  out.color = vec4<f32>(WIRE_COLOR, coverage);
+  return out;
+}
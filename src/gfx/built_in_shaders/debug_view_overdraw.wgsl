@@ -0,0 +1,8 @@
+// Fragment stage drawn after the vertex stage of the materials by the overdraw debug view, see `debug_view.rs`.
+// Every fragment adds the same amount, so the brightness counts the surfaces covering the pixel.
+
+@fragment
+fn fs_overdraw() -> @location(0) vec4<f32> {
+  // Saturates after ten layers.
+  return vec4<f32>(0.1, 0.04, 0.02, 1.0);
+}
@@ -0,0 +1,64 @@
+struct Screen {
+    // Screen size in points.
+    size: vec2<f32>,
+    _padding: vec2<f32>,
+};
+
+@group(0) @binding(0) var<uniform> screen: Screen;
+@group(1) @binding(0) var egui_texture: texture_2d<f32>;
+@group(1) @binding(1) var egui_sampler: sampler;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    // Premultiplied sRGB.
+    @location(2) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+fn linear_from_gamma(srgb: vec3<f32>) -> vec3<f32> {
+    let cutoff = srgb < vec3<f32>(0.04045);
+    let lower = srgb / vec3<f32>(12.92);
+    let higher = pow((srgb + vec3<f32>(0.055)) / vec3<f32>(1.055), vec3<f32>(2.4));
+    return select(higher, lower, cutoff);
+}
+
+fn gamma_from_linear(rgb: vec3<f32>) -> vec3<f32> {
+    let cutoff = rgb < vec3<f32>(0.0031308);
+    let lower = rgb * vec3<f32>(12.92);
+    let higher = vec3<f32>(1.055) * pow(rgb, vec3<f32>(1.0 / 2.4)) - vec3<f32>(0.055);
+    return select(higher, lower, cutoff);
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(
+        2.0 * in.position.x / screen.size.x - 1.0,
+        1.0 - 2.0 * in.position.y / screen.size.y,
+        0.0,
+        1.0
+    );
+    out.uv = in.uv;
+    out.color = in.color;
+    return out;
+}
+
+// For targets storing linear values, e.g. `Bgra8UnormSrgb`.
+@fragment
+fn fs_main_linear(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = vec4<f32>(linear_from_gamma(in.color.rgb), in.color.a);
+    return color * textureSample(egui_texture, egui_sampler, in.uv);
+}
+
+// For targets storing gamma values, e.g. `Bgra8Unorm`.
+@fragment
+fn fs_main_gamma(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texture = textureSample(egui_texture, egui_sampler, in.uv);
+    return in.color * vec4<f32>(gamma_from_linear(texture.rgb), texture.a);
+}
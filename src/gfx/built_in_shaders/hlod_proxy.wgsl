@@ -0,0 +1,50 @@
+// Merged mesh of an HLOD cluster. The vertices are in world space already, colored by the members they come from.
+
+@group(0) @binding(0) var<uniform> camera_transform: mat4x4<f32>;
+
+// Direction towards the light, as for the terrain.
+const LIGHT_DIRECTION: vec3<f32> = vec3<f32>(0.3, 0.9, 0.3);
+const AMBIENT: f32 = 0.3;
+
+struct InstanceInput {
+  @location(0) transform_row_0: vec4<f32>,
+  @location(1) transform_row_1: vec4<f32>,
+  @location(2) transform_row_2: vec4<f32>,
+  @location(3) transform_row_3: vec4<f32>,
+};
+
+struct VertexInput {
+  @location(4) position: vec3<f32>,
+  @location(5) normal: vec3<f32>,
+  @location(6) vertex_color: vec4<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) world_normal: vec3<f32>,
+  @location(1) color: vec4<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let transform = mat4x4<f32>(instance.transform_row_0, instance.transform_row_1, instance.transform_row_2, instance.transform_row_3);
+
+  out.position = camera_transform * transform * vec4<f32>(vertex.position, 1.0);
+  out.world_normal = (transform * vec4<f32>(vertex.normal, 0.0)).xyz;
+  out.color = vertex.vertex_color;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+
+  let diffuse = clamp(dot(normalize(in.world_normal), normalize(LIGHT_DIRECTION)), 0.0, 1.0);
+  out.color = vec4<f32>(in.color.rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse), in.color.a);
+  return out;
+}
@@ -0,0 +1,57 @@
+struct Instance {
+    row_0: vec4<f32>,
+    row_1: vec4<f32>,
+    row_2: vec4<f32>,
+    row_3: vec4<f32>,
+    // xyz: world-space center, w: radius
+    bounds: vec4<f32>,
+}
+
+struct VisibleInstance {
+    row_0: vec4<f32>,
+    row_1: vec4<f32>,
+    row_2: vec4<f32>,
+    row_3: vec4<f32>,
+}
+
+struct Params {
+    planes: array<vec4<f32>, 6>,
+    instance_count: u32,
+}
+
+// The indexed arguments share the layout up to the instance count.
+struct DrawArgs {
+    vertex_count: u32,
+    instance_count: atomic<u32>,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> instances: array<Instance>;
+@group(0) @binding(2) var<storage, read_write> visible_instances: array<VisibleInstance>;
+@group(0) @binding(3) var<storage, read_write> draw_args: DrawArgs;
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+
+    if (index >= params.instance_count) {
+        return;
+    }
+
+    let instance = instances[index];
+    let center = instance.bounds.xyz;
+    let radius = instance.bounds.w;
+
+    for (var plane_index = 0u; plane_index < 6u; plane_index += 1u) {
+        let plane = params.planes[plane_index];
+
+        if (dot(plane.xyz, center) + plane.w < -radius) {
+            return;
+        }
+    }
+
+    let slot = atomicAdd(&draw_args.instance_count, 1u);
+    visible_instances[slot] = VisibleInstance(instance.row_0, instance.row_1, instance.row_2, instance.row_3);
+}
@@ -0,0 +1,165 @@
+// Blinn-Phong surface lit by the lights of the scene on top of its ambient color, see `light.rs`.
+// Without any light, surfaces are shown at their full color rather than black.
+
+const MAX_DIRECTIONAL_LIGHTS: u32 = 4u;
+const MAX_LOCAL_LIGHTS: u32 = 240u;
+const MAX_LIGHTS_PER_OBJECT: u32 = 4u;
+
+struct DirectionalLight {
+  // xyz: normalized direction the light travels in.
+  direction: vec4<f32>,
+  // rgb: color premultiplied by the intensity.
+  color: vec4<f32>,
+};
+
+struct LocalLight {
+  // xyz: position, w: range.
+  position: vec4<f32>,
+  // rgb: color premultiplied by the intensity.
+  color: vec4<f32>,
+  // xyz: normalized direction the cone points towards, w: cosine of its outer angle.
+  direction: vec4<f32>,
+  // x: cosine of the inner angle of the cone.
+  cone: vec4<f32>,
+};
+
+struct Lights {
+  ambient: vec4<f32>,
+  // x: number of directional lights, y: number of local lights.
+  counts: vec4<u32>,
+  directional: array<DirectionalLight, 4>,
+  local: array<LocalLight, 240>,
+};
+
+@group(0) @binding(0) var<uniform> camera_transform: mat4x4<f32>;
+@group(1) @binding(0) var<uniform> lights: Lights;
+
+struct InstanceInput {
+  @location(0) transform_row_0: vec4<f32>,
+  @location(1) transform_row_1: vec4<f32>,
+  @location(2) transform_row_2: vec4<f32>,
+  @location(3) transform_row_3: vec4<f32>,
+  @location(4) base_color: vec4<f32>,
+  // rgb: specular color, w: shininess.
+  @location(5) specular: vec4<f32>,
+  // Indices into `lights.local`, -1 for none.
+  @location(6) light_indices: vec4<f32>,
+};
+
+struct VertexInput {
+  @location(7) position: vec3<f32>,
+  @location(8) normal: vec3<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) world_normal: vec3<f32>,
+  @location(1) to_camera: vec3<f32>,
+  @location(2) base_color: vec4<f32>,
+  @location(3) specular: vec4<f32>,
+  @location(4) world_position: vec3<f32>,
+  @location(5) @interpolate(flat) light_indices: vec4<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+// The camera is where the clip-space x, y and w all vanish. An orthographic projection has no such point; its view
+// direction is the one along which x and y stay constant, towards the nearer depths.
+fn to_camera(world_position: vec3<f32>) -> vec3<f32> {
+  let rows = transpose(camera_transform);
+  let x = rows[0];
+  let y = rows[1];
+  let w = rows[3];
+  let x_cross_y = cross(x.xyz, y.xyz);
+  let determinant = dot(w.xyz, x_cross_y);
+
+  if abs(determinant) < 1e-6 {
+    return -x_cross_y * sign(dot(rows[2].xyz, x_cross_y));
+  }
+
+  let camera_position = -(x.w * cross(y.xyz, w.xyz) + y.w * cross(w.xyz, x.xyz) + w.w * x_cross_y) / determinant;
+  return camera_position - world_position;
+}
+
+// Mirrors `light_attenuation` of `light.rs`: inverse square, reaching exactly zero at the range.
+fn attenuation(distance: f32, range: f32) -> f32 {
+  if range <= distance {
+    return 0.0;
+  }
+
+  let ratio = distance / range;
+  let window = 1.0 - ratio * ratio * ratio * ratio;
+  return window * window / (distance * distance + 1.0);
+}
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let transform = mat4x4<f32>(instance.transform_row_0, instance.transform_row_1, instance.transform_row_2, instance.transform_row_3);
+  let world_position = transform * vec4<f32>(vertex.position, 1.0);
+
+  out.position = camera_transform * world_position;
+  out.world_normal = (transform * vec4<f32>(vertex.normal, 0.0)).xyz;
+  out.to_camera = to_camera(world_position.xyz);
+  out.base_color = instance.base_color;
+  out.specular = instance.specular;
+  out.world_position = world_position.xyz;
+  out.light_indices = instance.light_indices;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+
+  let directional_count = min(lights.counts.x, MAX_DIRECTIONAL_LIGHTS);
+  let local_count = min(lights.counts.y, MAX_LOCAL_LIGHTS);
+  if directional_count == 0u && local_count == 0u {
+    out.color = in.base_color;
+    return out;
+  }
+
+  let normal = normalize(in.world_normal);
+  let view = normalize(in.to_camera);
+  var diffuse = lights.ambient.rgb;
+  var specular = vec3<f32>(0.0);
+
+  for (var index = 0u; index < directional_count; index += 1u) {
+    let light = lights.directional[index];
+    let to_light = -light.direction.xyz;
+    let n_dot_l = max(dot(normal, to_light), 0.0);
+    diffuse += light.color.rgb * n_dot_l;
+
+    if 0.0 < n_dot_l {
+      let half_vector = normalize(to_light + view);
+      specular += light.color.rgb * pow(max(dot(normal, half_vector), 0.0), in.specular.w);
+    }
+  }
+
+  // The picked lights fill the slots from the first one.
+  for (var slot = 0u; slot < MAX_LIGHTS_PER_OBJECT; slot += 1u) {
+    let picked = in.light_indices[slot];
+    if picked < 0.0 || local_count <= u32(picked) {
+      break;
+    }
+
+    let light = lights.local[u32(picked)];
+    let offset = light.position.xyz - in.world_position;
+    let distance = length(offset);
+    let to_light = offset / max(distance, 1e-4);
+    let cone = smoothstep(light.direction.w, light.cone.x, dot(light.direction.xyz, -to_light));
+    let radiance = light.color.rgb * attenuation(distance, light.position.w) * cone;
+    let n_dot_l = max(dot(normal, to_light), 0.0);
+    diffuse += radiance * n_dot_l;
+
+    if 0.0 < n_dot_l {
+      let half_vector = normalize(to_light + view);
+      specular += radiance * pow(max(dot(normal, half_vector), 0.0), in.specular.w);
+    }
+  }
+
+  out.color = vec4<f32>(in.base_color.rgb * diffuse + in.specular.rgb * specular, in.base_color.a);
+  return out;
+}
@@ -0,0 +1,30 @@
+// Full-screen triangles carrying the merged color of consecutive color overlays.
+
+struct VertexInput {
+  @location(0) position: vec2<f32>,
+  @location(1) color: vec4<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) color: vec4<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  out.position = vec4<f32>(vertex.position, 0.0, 1.0);
+  out.color = vertex.color;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  out.color = in.color;
+  return out;
+}
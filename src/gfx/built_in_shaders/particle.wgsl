@@ -0,0 +1,54 @@
+// Camera-facing quads of a ParticleSystem. The per-instance rows carry a particle instead of a transform:
+// row 0 is the world position and the size, and row 1 is the color.
+
+@group(0) @binding(0) var<uniform> camera_transform: mat4x4<f32>;
+
+struct InstanceInput {
+  @location(0) transform_row_0: vec4<f32>,
+  @location(1) transform_row_1: vec4<f32>,
+  @location(2) transform_row_2: vec4<f32>,
+  @location(3) transform_row_3: vec4<f32>,
+};
+
+struct VertexInput {
+  @location(4) position: vec3<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) color: vec4<f32>,
+  @location(1) uv: vec2<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let center = camera_transform * vec4<f32>(instance.transform_row_0.xyz, 1.0);
+  // Clip-space length of a world unit along the camera's right and up axes.
+  let scale = vec2<f32>(
+    length(vec3<f32>(camera_transform[0].x, camera_transform[1].x, camera_transform[2].x)),
+    length(vec3<f32>(camera_transform[0].y, camera_transform[1].y, camera_transform[2].y))
+  );
+  let corner = vertex.position.xy - vec2<f32>(0.5);
+  out.position = center + vec4<f32>(corner * instance.transform_row_0.w * scale, 0.0, 0.0);
+  out.color = instance.transform_row_1;
+  out.uv = vertex.position.xy;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  let falloff = 1.0 - smoothstep(0.25, 0.5, length(in.uv - vec2<f32>(0.5)));
+
+  if (in.color.a * falloff <= 0.001) {
+    discard;
+  }
+
+  out.color = vec4<f32>(in.color.rgb, in.color.a * falloff);
+  return out;
+}
@@ -0,0 +1,216 @@
+// Advances the particles of a ParticleSystem by one frame. It mirrors `simulate_particles` in particle_simulation.rs.
+// The survivors of the source buffer and the spawned particles are compacted into the destination buffer,
+// whose draw arguments receive the number of alive particles.
+
+struct Particle {
+    // xyz: world position, w: size
+    position_size: vec4<f32>,
+    color: vec4<f32>,
+    // xyz: velocity, w: age
+    velocity_age: vec4<f32>,
+    // x: lifetime
+    lifetime: vec4<f32>,
+}
+
+struct Params {
+    emitter: array<vec4<f32>, 4>,
+    // xyz: gravity, w: drag
+    gravity_drag: vec4<f32>,
+    // x: noise strength, y: noise frequency, z: time, w: delta time
+    noise_time: vec4<f32>,
+    // x: speed, y: spread, z: lifetime, w: radius
+    emission: vec4<f32>,
+    // x: speed variance, y: lifetime variance
+    variance: vec4<f32>,
+    // x: spawn count, y: capacity, z: source side, w: seed
+    counts: vec4<u32>,
+    size_curve: array<vec4<f32>, 8>,
+    color_curve: array<vec4<f32>, 32>,
+}
+
+struct SourceDrawArgs {
+    vertex_count: u32,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+struct DrawArgs {
+    vertex_count: u32,
+    instance_count: atomic<u32>,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> source_particles: array<Particle>;
+@group(0) @binding(2) var<storage, read> source_draw_args: SourceDrawArgs;
+@group(0) @binding(3) var<storage, read_write> destination_particles: array<Particle>;
+@group(0) @binding(4) var<storage, read_write> destination_draw_args: DrawArgs;
+
+const CURVE_SAMPLES: u32 = 32u;
+const TAU: f32 = 6.283185307179586;
+
+fn pcg_hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn to_unit(hash: u32) -> f32 {
+    return f32(hash >> 8u) / 16777216.0;
+}
+
+fn random(index: u32, channel: u32) -> f32 {
+    return to_unit(pcg_hash(params.counts.w ^ pcg_hash(index ^ pcg_hash(channel))));
+}
+
+fn lattice(cell: vec3<u32>, channel: u32) -> f32 {
+    return to_unit(pcg_hash(cell.x ^ pcg_hash(cell.y ^ pcg_hash(cell.z ^ channel))));
+}
+
+fn value_noise_gradient(position: vec3<f32>, channel: u32) -> vec3<f32> {
+    let cell_f = floor(position);
+    let f = position - cell_f;
+    let u = f * f * (3.0 - 2.0 * f);
+    let du = 6.0 * f * (1.0 - f);
+    let cell = bitcast<vec3<u32>>(vec3<i32>(cell_f));
+
+    let a = lattice(cell + vec3<u32>(0u, 0u, 0u), channel);
+    let b = lattice(cell + vec3<u32>(1u, 0u, 0u), channel);
+    let c = lattice(cell + vec3<u32>(0u, 1u, 0u), channel);
+    let d = lattice(cell + vec3<u32>(1u, 1u, 0u), channel);
+    let e = lattice(cell + vec3<u32>(0u, 0u, 1u), channel);
+    let g = lattice(cell + vec3<u32>(1u, 0u, 1u), channel);
+    let h = lattice(cell + vec3<u32>(0u, 1u, 1u), channel);
+    let k = lattice(cell + vec3<u32>(1u, 1u, 1u), channel);
+
+    let k1 = b - a;
+    let k2 = c - a;
+    let k3 = e - a;
+    let k4 = a - b - c + d;
+    let k5 = a - c - e + h;
+    let k6 = a - b - e + g;
+    let k7 = -a + b + c - d + e - g - h + k;
+
+    return du * vec3<f32>(
+        k1 + k4 * u.y + k6 * u.z + k7 * u.y * u.z,
+        k2 + k5 * u.z + k4 * u.x + k7 * u.z * u.x,
+        k3 + k6 * u.x + k5 * u.y + k7 * u.x * u.y
+    );
+}
+
+fn curl_noise(position: vec3<f32>) -> vec3<f32> {
+    let x = value_noise_gradient(position, 0u);
+    let y = value_noise_gradient(position, 1u);
+    let z = value_noise_gradient(position, 2u);
+    return vec3<f32>(z.y - y.z, x.z - z.x, y.x - x.y);
+}
+
+fn curve_position(age_ratio: f32) -> vec2<f32> {
+    let position = clamp(age_ratio, 0.0, 1.0) * f32(CURVE_SAMPLES - 1u);
+    let index = min(floor(position), f32(CURVE_SAMPLES - 2u));
+    return vec2<f32>(index, position - index);
+}
+
+fn size_sample(index: u32) -> f32 {
+    return params.size_curve[index / 4u][index % 4u];
+}
+
+fn size(age_ratio: f32) -> f32 {
+    let position = curve_position(age_ratio);
+    let index = u32(position.x);
+    return mix(size_sample(index), size_sample(index + 1u), position.y);
+}
+
+fn color(age_ratio: f32) -> vec4<f32> {
+    let position = curve_position(age_ratio);
+    let index = u32(position.x);
+    return mix(params.color_curve[index], params.color_curve[index + 1u], position.y);
+}
+
+fn emitter_point(local: vec3<f32>) -> vec3<f32> {
+    return local.x * params.emitter[0].xyz + local.y * params.emitter[1].xyz + local.z * params.emitter[2].xyz + params.emitter[3].xyz;
+}
+
+fn emitter_direction(local: vec3<f32>) -> vec3<f32> {
+    let direction = local.x * params.emitter[0].xyz + local.y * params.emitter[1].xyz + local.z * params.emitter[2].xyz;
+
+    if (dot(direction, direction) == 0.0) {
+        return vec3<f32>(0.0);
+    }
+
+    return normalize(direction);
+}
+
+fn update(particle: Particle) {
+    let delta_time = params.noise_time.w;
+    let lifetime = particle.lifetime.x;
+    let age = particle.velocity_age.w + delta_time;
+
+    if (lifetime <= age) {
+        return;
+    }
+
+    let position = particle.position_size.xyz;
+    let turbulence = curl_noise(position * params.noise_time.y + vec3<f32>(0.0, params.noise_time.z * 0.1, 0.0)) * params.noise_time.x;
+    var velocity = particle.velocity_age.xyz;
+    velocity += (params.gravity_drag.xyz + turbulence) * delta_time;
+    velocity *= 1.0 / (1.0 + params.gravity_drag.w * delta_time);
+
+    let age_ratio = age / lifetime;
+    let slot = atomicAdd(&destination_draw_args.instance_count, 1u);
+    destination_particles[slot] = Particle(
+        vec4<f32>(position + velocity * delta_time, size(age_ratio)),
+        color(age_ratio),
+        vec4<f32>(velocity, age),
+        particle.lifetime
+    );
+}
+
+fn spawn(index: u32) {
+    let speed = params.emission.x;
+    let spread = params.emission.y;
+    let lifetime = params.emission.z;
+    let radius = params.emission.w;
+
+    // Uniform in the cone around +Y.
+    let cos_theta = 1.0 - random(index, 0u) * (1.0 - cos(spread));
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = random(index, 1u) * TAU;
+    let direction = emitter_direction(vec3<f32>(sin_theta * cos(phi), cos_theta, sin_theta * sin(phi)));
+
+    // Uniform in the sphere of the radius.
+    var offset = vec3<f32>(random(index, 2u), random(index, 3u), random(index, 4u)) * 2.0 - 1.0;
+
+    if (1.0 < dot(offset, offset)) {
+        offset = normalize(offset) * pow(random(index, 5u), 1.0 / 3.0);
+    }
+
+    let velocity = direction * speed * (1.0 - params.variance.x * random(index, 6u));
+    let particle_lifetime = lifetime * (1.0 - params.variance.y * random(index, 7u));
+
+    let slot = atomicAdd(&destination_draw_args.instance_count, 1u);
+    destination_particles[slot] = Particle(
+        vec4<f32>(emitter_point(offset * radius), size(0.0)),
+        color(0.0),
+        vec4<f32>(velocity, 0.0),
+        vec4<f32>(particle_lifetime, 0.0, 0.0, 0.0)
+    );
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    let alive_count = source_draw_args.instance_count;
+    let capacity = params.counts.y;
+
+    if (index < alive_count) {
+        update(source_particles[index]);
+    }
+
+    // Spawning only into the room left by the previous frame keeps the survivors from being pushed out.
+    if (index < min(params.counts.x, capacity - min(alive_count, capacity))) {
+        spawn(index);
+    }
+}
@@ -0,0 +1,65 @@
+struct Reflection {
+  view_projection: mat4x4<f32>,
+  camera_position: vec4<f32>,
+  // x: distortion strength, y: 1 if the reflection texture is available, 0 to use the environment map instead.
+  params: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> camera_transform: mat4x4<f32>;
+@group(1) @binding(0) var<uniform> reflection: Reflection;
+@group(1) @binding(1) var reflection_texture: texture_2d<f32>;
+@group(1) @binding(2) var reflection_sampler: sampler;
+@group(2) @binding(0) var environment_texture: texture_cube<f32>;
+@group(2) @binding(1) var environment_sampler: sampler;
+
+struct InstanceInput {
+  @location(0) transform_row_0: vec4<f32>,
+  @location(1) transform_row_1: vec4<f32>,
+  @location(2) transform_row_2: vec4<f32>,
+  @location(3) transform_row_3: vec4<f32>,
+};
+
+struct VertexInput {
+  @location(4) position: vec3<f32>,
+  @location(5) normal: vec3<f32>,
+  @location(6) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) world_position: vec3<f32>,
+  @location(1) world_normal: vec3<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let transform = mat4x4<f32>(instance.transform_row_0, instance.transform_row_1, instance.transform_row_2, instance.transform_row_3);
+  let world_position = transform * vec4<f32>(vertex.position, 1.0);
+  out.position = camera_transform * world_position;
+  out.world_position = world_position.xyz;
+  out.world_normal = (transform * vec4<f32>(vertex.normal, 0.0)).xyz;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  let normal = normalize(in.world_normal);
+
+  // Project the surface with the mirrored camera to find where it sees this point.
+  let clip = reflection.view_projection * vec4<f32>(in.world_position, 1.0);
+  let ndc = clip.xy / clip.w;
+  let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5) + normal.xz * reflection.params.x;
+  let reflected = textureSample(reflection_texture, reflection_sampler, clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)));
+
+  let view_direction = normalize(in.world_position - reflection.camera_position.xyz);
+  let environment = textureSample(environment_texture, environment_sampler, reflect(view_direction, normal));
+
+  out.color = select(environment, reflected, 0.5 < reflection.params.y);
+  return out;
+}
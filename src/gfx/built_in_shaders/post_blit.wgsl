@@ -0,0 +1,40 @@
+// Copies the output of the post-processing stack into the surface, filtered if the scene is drawn at another size
+// than the surface. A surface lacking a view of the color space of the scene gets the colors converted into its own
+// on the way.
+
+struct Params {
+  // x: 0 copies, 1 encodes linear colors into sRGB, 2 decodes sRGB colors into linear values.
+  conversion: vec4<f32>,
+};
+
+@group(0) @binding(2) var<uniform> blit: Params;
+
+fn encode_srgb(value: vec3<f32>) -> vec3<f32> {
+  let color = max(value, vec3<f32>(0.0));
+  let low = color * 12.92;
+  let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+  return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn decode_srgb(value: vec3<f32>) -> vec3<f32> {
+  let color = max(value, vec3<f32>(0.0));
+  let low = color / 12.92;
+  let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+  return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  // At the same size, the pixel centers fall onto the texel centers and the texels are copied as they are.
+  let texel = textureSample(input_texture, input_sampler, in.uv);
+
+  if (blit.conversion.x == 1.0) {
+    return vec4<f32>(encode_srgb(texel.rgb), texel.a);
+  }
+
+  if (blit.conversion.x == 2.0) {
+    return vec4<f32>(decode_srgb(texel.rgb), texel.a);
+  }
+
+  return texel;
+}
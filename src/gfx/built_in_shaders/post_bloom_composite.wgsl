@@ -0,0 +1,16 @@
+// Adds the first target of the bloom chain, which holds the whole chain once upsampled, over the scene.
+
+struct Params {
+  // x: intensity.
+  params: vec4<f32>,
+};
+
+@group(0) @binding(2) var<uniform> bloom: Params;
+@group(0) @binding(3) var bloom_texture: texture_2d<f32>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let scene = textureSample(input_texture, input_sampler, in.uv);
+  let glow = textureSample(bloom_texture, input_sampler, in.uv).rgb;
+  return vec4<f32>(scene.rgb + glow * bloom.params.x, scene.a);
+}
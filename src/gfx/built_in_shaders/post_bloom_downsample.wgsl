@@ -0,0 +1,12 @@
+// Halves the previous target of the bloom chain.
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let texel = 1.0 / vec2<f32>(textureDimensions(input_texture));
+  // Four bilinear taps average the 4×4 pixels under the target pixel.
+  let sum = textureSample(input_texture, input_sampler, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb
+    + textureSample(input_texture, input_sampler, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb
+    + textureSample(input_texture, input_sampler, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb
+    + textureSample(input_texture, input_sampler, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb;
+  return vec4<f32>(sum * 0.25, 1.0);
+}
@@ -0,0 +1,29 @@
+// Extracts the pixels that bloom into the first target of the chain, at half the resolution of the scene.
+// Mirrors `bloom_contribution` of `bloom.rs`.
+
+struct Params {
+  // x: threshold, y: soft knee.
+  params: vec4<f32>,
+};
+
+@group(0) @binding(2) var<uniform> bloom: Params;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let texel = 1.0 / vec2<f32>(textureDimensions(input_texture));
+  // Four bilinear taps average the 4×4 pixels under the target pixel.
+  let sum = textureSample(input_texture, input_sampler, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb
+    + textureSample(input_texture, input_sampler, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb
+    + textureSample(input_texture, input_sampler, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb
+    + textureSample(input_texture, input_sampler, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb;
+  // Keeps a single overflowing pixel from flooding the whole chain.
+  let color = min(sum * 0.25, vec3<f32>(65000.0));
+
+  let threshold = bloom.params.x;
+  let knee = threshold * bloom.params.y;
+  let brightness = max(color.r, max(color.g, color.b));
+  var soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+  soft = soft * soft / (4.0 * knee + 1e-5);
+  let contribution = max(soft, brightness - threshold) / max(brightness, 1e-5);
+  return vec4<f32>(color * contribution, 1.0);
+}
@@ -0,0 +1,17 @@
+// Blurs a target of the bloom chain into the next larger one with a 3×3 tent filter. The pipeline adds the result
+// to what the downsampling left there.
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let texel = 1.0 / vec2<f32>(textureDimensions(input_texture));
+  var color = textureSample(input_texture, input_sampler, in.uv).rgb * 4.0;
+  color += textureSample(input_texture, input_sampler, in.uv + vec2<f32>(-1.0, 0.0) * texel).rgb * 2.0;
+  color += textureSample(input_texture, input_sampler, in.uv + vec2<f32>(1.0, 0.0) * texel).rgb * 2.0;
+  color += textureSample(input_texture, input_sampler, in.uv + vec2<f32>(0.0, -1.0) * texel).rgb * 2.0;
+  color += textureSample(input_texture, input_sampler, in.uv + vec2<f32>(0.0, 1.0) * texel).rgb * 2.0;
+  color += textureSample(input_texture, input_sampler, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb;
+  color += textureSample(input_texture, input_sampler, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb;
+  color += textureSample(input_texture, input_sampler, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb;
+  color += textureSample(input_texture, input_sampler, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb;
+  return vec4<f32>(color / 16.0, 1.0);
+}
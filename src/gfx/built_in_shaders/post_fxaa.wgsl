@@ -0,0 +1,54 @@
+// Fast approximate anti-aliasing: finds edges by the contrast of the luma around each pixel, and blurs along them.
+
+struct Params {
+  // x: contrast threshold, y: threshold relative to the brightest neighbour, z: subpixel blending.
+  params: vec4<f32>,
+};
+
+@group(0) @binding(2) var<uniform> fxaa: Params;
+
+fn luma(color: vec3<f32>) -> f32 {
+  return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+// Explicit level, as the early return makes the control flow non-uniform.
+fn fetch(uv: vec2<f32>) -> vec4<f32> {
+  return textureSampleLevel(input_texture, input_sampler, uv, 0.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let texel = 1.0 / vec2<f32>(textureDimensions(input_texture));
+  let center = fetch(in.uv);
+  let luma_m = luma(center.rgb);
+  let luma_nw = luma(fetch(in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb);
+  let luma_ne = luma(fetch(in.uv + vec2<f32>(1.0, -1.0) * texel).rgb);
+  let luma_sw = luma(fetch(in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb);
+  let luma_se = luma(fetch(in.uv + vec2<f32>(1.0, 1.0) * texel).rgb);
+  let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+  let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+  if luma_max - luma_min < max(fxaa.params.x, luma_max * fxaa.params.y) {
+    return center;
+  }
+
+  // Perpendicular to the gradient of the luma, i.e. along the edge.
+  var direction = vec2<f32>(
+    (luma_sw + luma_se) - (luma_nw + luma_ne),
+    (luma_nw + luma_sw) - (luma_ne + luma_se),
+  );
+  let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * fxaa.params.z, 1.0 / 128.0);
+  let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+  direction = clamp(direction * scale, vec2<f32>(-8.0), vec2<f32>(8.0)) * texel;
+
+  let inner = 0.5 * (fetch(in.uv - direction / 6.0).rgb + fetch(in.uv + direction / 6.0).rgb);
+  let outer = inner * 0.5 + 0.25 * (fetch(in.uv - direction * 0.5).rgb + fetch(in.uv + direction * 0.5).rgb);
+  let luma_outer = luma(outer);
+
+  // The wider blur crossed another edge.
+  if luma_outer < luma_min || luma_max < luma_outer {
+    return vec4<f32>(inner, center.a);
+  }
+
+  return vec4<f32>(outer, center.a);
+}
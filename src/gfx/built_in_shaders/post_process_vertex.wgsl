@@ -0,0 +1,20 @@
+// Vertex stage shared by the post-processing passes, see `FullscreenPass` in `post_process.rs`. The fragment stage of
+// the pass is appended to it, and reads the previous output through `input_texture`.
+
+@group(0) @binding(0) var input_texture: texture_2d<f32>;
+@group(0) @binding(1) var input_sampler: sampler;
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+  let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+  var out: VertexOutput;
+  out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+  // Textures are sampled from the top left corner.
+  out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+  return out;
+}
@@ -0,0 +1,25 @@
+// Maps the HDR scene into the displayable range. Mirrors `tonemap` of `post_process.rs`.
+
+struct Params {
+  // x: 0 for Reinhard, 1 for ACES, y: exposure.
+  params: vec4<f32>,
+};
+
+@group(0) @binding(2) var<uniform> tonemap: Params;
+
+// Narkowicz's fit of the ACES filmic curve.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+  return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let texel = textureSample(input_texture, input_sampler, in.uv);
+  let color = max(texel.rgb * tonemap.params.y, vec3<f32>(0.0));
+
+  if tonemap.params.x < 0.5 {
+    return vec4<f32>(color / (1.0 + color), texel.a);
+  }
+
+  return vec4<f32>(aces(color), texel.a);
+}
@@ -0,0 +1,20 @@
+// Darkens the corners of the screen towards the vignette color, round whatever the aspect ratio.
+
+struct Params {
+  color: vec4<f32>,
+  // x: intensity, y: smoothness.
+  params: vec4<f32>,
+};
+
+@group(0) @binding(2) var<uniform> vignette: Params;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let texel = textureSample(input_texture, input_sampler, in.uv);
+  let size = vec2<f32>(textureDimensions(input_texture));
+  let aspect = vec2<f32>(size.x / size.y, 1.0);
+  // 0 at the center, 1 at the corners.
+  let radius = length((in.uv - 0.5) * aspect) / length(0.5 * aspect);
+  let amount = smoothstep(1.0 - vignette.params.y, 1.0, radius) * vignette.params.x * vignette.color.a;
+  return vec4<f32>(mix(texel.rgb, vignette.color.rgb, amount), texel.a);
+}
@@ -0,0 +1,27 @@
+// Standardized workload of the render tier benchmark: full-screen layers of arithmetic-heavy fragments.
+// Changing it invalidates the thresholds in `quality.rs`.
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, @builtin(instance_index) instance_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var output: VertexOutput;
+    output.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    output.uv = uv + f32(instance_index) * 0.01;
+    return output;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    var value = vec3<f32>(input.uv, 0.5);
+
+    for (var i = 0; i < 32; i += 1) {
+        value = fract(sin(value * 12.9898 + value.yzx * 78.233) * 43758.547);
+    }
+
+    return vec4<f32>(value, 0.1);
+}
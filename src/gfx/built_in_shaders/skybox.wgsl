@@ -0,0 +1,46 @@
+// Sky of the scene, see `skybox.rs`. A single triangle given in clip space covers the screen at the far plane, so that
+// it only shows where nothing has been drawn. The transform maps clip space back to the view directions of the camera.
+
+@group(0) @binding(0) var skybox_texture: texture_cube<f32>;
+@group(0) @binding(1) var skybox_sampler: sampler;
+
+struct InstanceInput {
+  @location(0) transform_row_0: vec4<f32>,
+  @location(1) transform_row_1: vec4<f32>,
+  @location(2) transform_row_2: vec4<f32>,
+  @location(3) transform_row_3: vec4<f32>,
+};
+
+struct VertexInput {
+  @location(4) position: vec3<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) near: vec4<f32>,
+  @location(1) far: vec4<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let inverse_view_projection = mat4x4<f32>(instance.transform_row_0, instance.transform_row_1, instance.transform_row_2, instance.transform_row_3);
+  let clip = vertex.position.xy;
+
+  out.position = vec4<f32>(clip, 1.0, 1.0);
+  out.near = inverse_view_projection * vec4<f32>(clip, 0.0, 1.0);
+  out.far = inverse_view_projection * vec4<f32>(clip, 1.0, 1.0);
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  let direction = in.far.xyz / in.far.w - in.near.xyz / in.near.w;
+  out.color = vec4<f32>(textureSample(skybox_texture, skybox_sampler, direction).rgb, 1.0);
+  return out;
+}
@@ -0,0 +1,20 @@
+
+// Vertex stage of the standard PBR shader; the rest is in `standard_pbr_surface.wgsl`, appended above.
+
+struct VertexInput {
+  @location(4) position: vec3<f32>,
+  @location(5) normal: vec3<f32>,
+  @location(6) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let transform = mat4x4<f32>(instance.transform_row_0, instance.transform_row_1, instance.transform_row_2, instance.transform_row_3);
+  let world_position = transform * vec4<f32>(vertex.position, 1.0);
+  out.position = camera_transform * world_position;
+  out.world_position = world_position.xyz;
+  out.world_normal = (transform * vec4<f32>(vertex.normal, 0.0)).xyz;
+  out.uv = vertex.uv;
+  return out;
+}
@@ -0,0 +1,43 @@
+
+// Vertex stage of the skinned PBR shader; the rest is in `standard_pbr_surface.wgsl`, appended above.
+// The vertices are moved by up to 4 of the skinning matrices of a `MeshSkin`, which take them from the bind pose to
+// the current one in the space of the object.
+
+const MAX_BONES: u32 = 256u;
+
+@group(3) @binding(0) var<uniform> bone_matrices: array<mat4x4<f32>, 256>;
+
+struct VertexInput {
+  @location(4) position: vec3<f32>,
+  @location(5) normal: vec3<f32>,
+  @location(6) uv: vec2<f32>,
+  @location(7) bone_indices: vec4<f32>,
+  @location(8) bone_weights: vec4<f32>,
+};
+
+fn bone_matrix(index: f32) -> mat4x4<f32> {
+  return bone_matrices[min(u32(index + 0.5), MAX_BONES - 1u)];
+}
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let transform = mat4x4<f32>(instance.transform_row_0, instance.transform_row_1, instance.transform_row_2, instance.transform_row_3);
+
+  // Vertices without weights, as with meshes skinned in part, stay in the bind pose.
+  var skin = mat4x4<f32>(vec4<f32>(1.0, 0.0, 0.0, 0.0), vec4<f32>(0.0, 1.0, 0.0, 0.0), vec4<f32>(0.0, 0.0, 1.0, 0.0), vec4<f32>(0.0, 0.0, 0.0, 1.0));
+  let weights = vertex.bone_weights;
+  if 0.0 < weights.x + weights.y + weights.z + weights.w {
+    skin = bone_matrix(vertex.bone_indices.x) * weights.x
+      + bone_matrix(vertex.bone_indices.y) * weights.y
+      + bone_matrix(vertex.bone_indices.z) * weights.z
+      + bone_matrix(vertex.bone_indices.w) * weights.w;
+  }
+
+  let world_position = transform * skin * vec4<f32>(vertex.position, 1.0);
+  out.position = camera_transform * world_position;
+  out.world_position = world_position.xyz;
+  out.world_normal = (transform * skin * vec4<f32>(vertex.normal, 0.0)).xyz;
+  out.uv = vertex.uv;
+  return out;
+}
@@ -0,0 +1,206 @@
+// Metallic-roughness PBR following the glTF 2.0 conventions.
+// Specular: Cook-Torrance with the GGX distribution, height-correlated Smith visibility and Schlick Fresnel.
+// Diffuse: Lambert, scaled by what the specular lobe doesn't reflect.
+// Everything but the vertex input, shared by `standard_pbr.wgsl` and `standard_pbr_skinned.wgsl`, appended below.
+
+struct PbrMaterial {
+  base_color: vec4<f32>,
+  // xyz: emissive factor, w: occlusion strength.
+  emissive: vec4<f32>,
+  // x: metallic, y: roughness, z: normal scale, w: alpha cutoff.
+  params: vec4<f32>,
+  // x: texture flags, y: alpha mode (0: opaque, 1: mask, 2: blend).
+  flags: vec4<u32>,
+};
+
+struct PbrLighting {
+  // Direction the light travels in.
+  light_direction: vec4<f32>,
+  // rgb: color premultiplied by the intensity.
+  light_color: vec4<f32>,
+  // rgb: constant ambient used without an environment map.
+  ambient_color: vec4<f32>,
+  // x: 1 if the environment map is available, y: its highest mip level.
+  params: vec4<f32>,
+};
+
+const TEXTURE_BASE_COLOR: u32 = 1u;
+const TEXTURE_METALLIC_ROUGHNESS: u32 = 2u;
+const TEXTURE_NORMAL: u32 = 4u;
+const TEXTURE_OCCLUSION: u32 = 8u;
+const TEXTURE_EMISSIVE: u32 = 16u;
+
+const ALPHA_MODE_MASK: u32 = 1u;
+const ALPHA_MODE_BLEND: u32 = 2u;
+
+const PI: f32 = 3.14159265359;
+const MIN_ROUGHNESS: f32 = 0.045;
+
+@group(0) @binding(0) var<uniform> camera_transform: mat4x4<f32>;
+@group(1) @binding(0) var<uniform> pbr_material: PbrMaterial;
+@group(1) @binding(1) var base_color_texture: texture_2d<f32>;
+@group(1) @binding(2) var metallic_roughness_texture: texture_2d<f32>;
+@group(1) @binding(3) var normal_texture: texture_2d<f32>;
+@group(1) @binding(4) var occlusion_texture: texture_2d<f32>;
+@group(1) @binding(5) var emissive_texture: texture_2d<f32>;
+@group(1) @binding(6) var pbr_sampler: sampler;
+@group(2) @binding(0) var<uniform> pbr_lighting: PbrLighting;
+@group(2) @binding(1) var environment_texture: texture_cube<f32>;
+@group(2) @binding(2) var environment_sampler: sampler;
+
+struct InstanceInput {
+  @location(0) transform_row_0: vec4<f32>,
+  @location(1) transform_row_1: vec4<f32>,
+  @location(2) transform_row_2: vec4<f32>,
+  @location(3) transform_row_3: vec4<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) world_position: vec3<f32>,
+  @location(1) world_normal: vec3<f32>,
+  @location(2) uv: vec2<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+fn has_texture(flag: u32) -> bool {
+  return (pbr_material.flags.x & flag) != 0u;
+}
+
+// Perturbs the normal without vertex tangents, using the screen-space derivatives of the position and uv.
+// The derivatives are taken by the caller: helper functions end up in the vertex stage too on some backends, e.g. GL,
+// where derivatives do not exist.
+fn perturb_normal(normal: vec3<f32>, dp1: vec3<f32>, dp2: vec3<f32>, duv1: vec2<f32>, duv2: vec2<f32>, tangent_normal: vec3<f32>) -> vec3<f32> {
+  let dp2perp = cross(dp2, normal);
+  let dp1perp = cross(normal, dp1);
+  let t = dp2perp * duv1.x + dp1perp * duv2.x;
+  let b = dp2perp * duv1.y + dp1perp * duv2.y;
+  let inv_max = inverseSqrt(max(dot(t, t), dot(b, b)));
+  return normalize(mat3x3<f32>(t * inv_max, b * inv_max, normal) * tangent_normal);
+}
+
+// The camera is where the clip-space x, y and w all vanish, as in `lit.wgsl`. An orthographic projection has no such
+// point; its view direction is the one along which x and y stay constant, towards the nearer depths.
+fn to_camera(world_position: vec3<f32>) -> vec3<f32> {
+  let rows = transpose(camera_transform);
+  let x = rows[0];
+  let y = rows[1];
+  let w = rows[3];
+  let x_cross_y = cross(x.xyz, y.xyz);
+  let determinant = dot(w.xyz, x_cross_y);
+
+  if abs(determinant) < 1e-6 {
+    return -x_cross_y * sign(dot(rows[2].xyz, x_cross_y));
+  }
+
+  let camera_position = -(x.w * cross(y.xyz, w.xyz) + y.w * cross(w.xyz, x.xyz) + w.w * x_cross_y) / determinant;
+  return camera_position - world_position;
+}
+
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+  let alpha2 = alpha * alpha;
+  let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+  return alpha2 / (PI * d * d);
+}
+
+fn visibility_smith_ggx_correlated(n_dot_v: f32, n_dot_l: f32, alpha: f32) -> f32 {
+  let alpha2 = alpha * alpha;
+  let ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2);
+  let ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2);
+  return 0.5 / max(ggx_v + ggx_l, 0.00001);
+}
+
+fn fresnel_schlick(f0: vec3<f32>, v_dot_h: f32) -> vec3<f32> {
+  return f0 + (vec3<f32>(1.0) - f0) * pow(1.0 - v_dot_h, 5.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+
+  // Sampling and derivatives need uniform control flow, so every slot is sampled up front, before any discard, and
+  // the texture flags pick what is used. Slots without a texture are bound to a placeholder.
+  let base_color_sample = textureSample(base_color_texture, pbr_sampler, in.uv);
+  let metallic_roughness_sample = textureSample(metallic_roughness_texture, pbr_sampler, in.uv);
+  let normal_sample = textureSample(normal_texture, pbr_sampler, in.uv).xyz * 2.0 - 1.0;
+  let occlusion_sample = textureSample(occlusion_texture, pbr_sampler, in.uv).r;
+  let emissive_sample = textureSample(emissive_texture, pbr_sampler, in.uv).rgb;
+  let geometric_normal = normalize(in.world_normal);
+  let tangent_normal = normalize(vec3<f32>(normal_sample.xy * pbr_material.params.z, normal_sample.z));
+  let perturbed_normal = perturb_normal(geometric_normal, dpdx(in.world_position), dpdy(in.world_position), dpdx(in.uv), dpdy(in.uv), tangent_normal);
+
+  var base_color = pbr_material.base_color;
+  if has_texture(TEXTURE_BASE_COLOR) {
+    base_color *= base_color_sample;
+  }
+
+  let alpha_mode = pbr_material.flags.y;
+  if alpha_mode == ALPHA_MODE_MASK && base_color.a < pbr_material.params.w {
+    discard;
+  }
+
+  var metallic = pbr_material.params.x;
+  var roughness = pbr_material.params.y;
+  if has_texture(TEXTURE_METALLIC_ROUGHNESS) {
+    // glTF: G is roughness, B is metallic.
+    roughness *= metallic_roughness_sample.g;
+    metallic *= metallic_roughness_sample.b;
+  }
+  metallic = clamp(metallic, 0.0, 1.0);
+  roughness = clamp(roughness, MIN_ROUGHNESS, 1.0);
+
+  var normal = geometric_normal;
+  if has_texture(TEXTURE_NORMAL) {
+    normal = perturbed_normal;
+  }
+
+  var occlusion = 1.0;
+  if has_texture(TEXTURE_OCCLUSION) {
+    occlusion = 1.0 + pbr_material.emissive.w * (occlusion_sample - 1.0);
+  }
+
+  var emissive = pbr_material.emissive.rgb;
+  if has_texture(TEXTURE_EMISSIVE) {
+    emissive *= emissive_sample;
+  }
+
+  let view = normalize(to_camera(in.world_position));
+  let light = normalize(-pbr_lighting.light_direction.xyz);
+  let half_vector = normalize(view + light);
+  let n_dot_v = max(dot(normal, view), 0.0001);
+  let n_dot_l = clamp(dot(normal, light), 0.0, 1.0);
+  let n_dot_h = clamp(dot(normal, half_vector), 0.0, 1.0);
+  let v_dot_h = clamp(dot(view, half_vector), 0.0, 1.0);
+
+  let alpha = roughness * roughness;
+  let f0 = mix(vec3<f32>(0.04), base_color.rgb, metallic);
+  let diffuse_color = base_color.rgb * (1.0 - metallic);
+
+  let fresnel = fresnel_schlick(f0, v_dot_h);
+  let specular = fresnel * distribution_ggx(n_dot_h, alpha) * visibility_smith_ggx_correlated(n_dot_v, n_dot_l, alpha);
+  let diffuse = (vec3<f32>(1.0) - fresnel) * diffuse_color / PI;
+  let direct = (diffuse + specular) * pbr_lighting.light_color.rgb * n_dot_l;
+
+  // Image-based lighting from the environment's mips, or a constant ambient term without one.
+  let ambient_fresnel = fresnel_schlick(f0, n_dot_v);
+  let environment = textureSampleLevel(environment_texture, environment_sampler, normal, pbr_lighting.params.y);
+  let reflected = textureSampleLevel(environment_texture, environment_sampler, reflect(-view, normal), roughness * pbr_lighting.params.y);
+  var ambient_diffuse = pbr_lighting.ambient_color.rgb;
+  var ambient_specular = pbr_lighting.ambient_color.rgb;
+  if 0.5 < pbr_lighting.params.x {
+    ambient_diffuse = environment.rgb;
+    ambient_specular = reflected.rgb;
+  }
+  let ambient = ((vec3<f32>(1.0) - ambient_fresnel) * diffuse_color * ambient_diffuse + ambient_fresnel * ambient_specular) * occlusion;
+
+  var output_alpha = 1.0;
+  if alpha_mode == ALPHA_MODE_BLEND {
+    output_alpha = base_color.a;
+  }
+
+  out.color = vec4<f32>(direct + ambient + emissive, output_alpha);
+  return out;
+}
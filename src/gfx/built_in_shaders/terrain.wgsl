@@ -0,0 +1,88 @@
+// Heightmap terrain, one chunk per instance. Heights are read in the vertex shader,
+// so all chunks drawn at a level of detail share the same grid.
+// Up to four layers of `splat_layers` are blended by the rgba weights of `splat_map`.
+
+@group(0) @binding(0) var<uniform> camera_transform: mat4x4<f32>;
+@group(1) @binding(0) var terrain_heights: texture_2d<f32>;
+@group(2) @binding(0) var splat_map: texture_2d<f32>;
+@group(2) @binding(1) var splat_map_sampler: sampler;
+@group(2) @binding(2) var splat_layers: texture_2d_array<f32>;
+@group(2) @binding(3) var splat_layers_sampler: sampler;
+
+// Direction towards the light.
+const LIGHT_DIRECTION: vec3<f32> = vec3<f32>(0.3, 0.9, 0.3);
+const AMBIENT: f32 = 0.3;
+
+struct InstanceInput {
+  @location(0) transform_row_0: vec4<f32>,
+  @location(1) transform_row_1: vec4<f32>,
+  @location(2) transform_row_2: vec4<f32>,
+  @location(3) transform_row_3: vec4<f32>,
+  // xy: first sample of the chunk, z: skirt depth.
+  @location(4) terrain_chunk: vec4<f32>,
+  // xy: distance between samples, z: height scale, w: layer tiling per unit.
+  @location(5) terrain_params: vec4<f32>,
+};
+
+struct VertexInput {
+  // xz: sample offset in the chunk, y: 1 at the bottom of the skirts.
+  @location(6) position: vec3<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) world_normal: vec3<f32>,
+  @location(1) splat_uv: vec2<f32>,
+  @location(2) layer_uv: vec2<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+fn load_height(coord: vec2<i32>) -> f32 {
+  let size = vec2<i32>(textureDimensions(terrain_heights));
+  let texel = textureLoad(terrain_heights, clamp(coord, vec2<i32>(0), size - 1), 0);
+  // 16-bit heights are split into the high and low bytes.
+  return (texel.r * 65280.0 + texel.g * 255.0) / 65535.0;
+}
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let transform = mat4x4<f32>(instance.transform_row_0, instance.transform_row_1, instance.transform_row_2, instance.transform_row_3);
+  let size = vec2<i32>(textureDimensions(terrain_heights));
+  // Chunks on the far edges are cut short by collapsing their vertices onto the last samples.
+  let coord = min(vec2<i32>(instance.terrain_chunk.xy + vertex.position.xz), size - 1);
+  let spacing = instance.terrain_params.xy;
+  let height_scale = instance.terrain_params.z;
+
+  let height = load_height(coord) * height_scale - vertex.position.y * instance.terrain_chunk.z;
+  let local_position = vec3<f32>(f32(coord.x) * spacing.x, height, f32(coord.y) * spacing.y);
+
+  let slope_x = (load_height(coord + vec2<i32>(1, 0)) - load_height(coord - vec2<i32>(1, 0))) * height_scale / (2.0 * spacing.x);
+  let slope_z = (load_height(coord + vec2<i32>(0, 1)) - load_height(coord - vec2<i32>(0, 1))) * height_scale / (2.0 * spacing.y);
+  let normal = normalize(vec3<f32>(-slope_x, 1.0, -slope_z));
+
+  out.position = camera_transform * transform * vec4<f32>(local_position, 1.0);
+  out.world_normal = (transform * vec4<f32>(normal, 0.0)).xyz;
+  out.splat_uv = vec2<f32>(coord) / vec2<f32>(size - 1);
+  out.layer_uv = local_position.xz * instance.terrain_params.w;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+
+  let weights = textureSample(splat_map, splat_map_sampler, in.splat_uv);
+  var color = textureSample(splat_layers, splat_layers_sampler, in.layer_uv, 0) * weights.r;
+  color += textureSample(splat_layers, splat_layers_sampler, in.layer_uv, 1) * weights.g;
+  color += textureSample(splat_layers, splat_layers_sampler, in.layer_uv, 2) * weights.b;
+  color += textureSample(splat_layers, splat_layers_sampler, in.layer_uv, 3) * weights.a;
+  color /= max(dot(weights, vec4<f32>(1.0)), 0.0001);
+
+  let diffuse = clamp(dot(normalize(in.world_normal), normalize(LIGHT_DIRECTION)), 0.0, 1.0);
+  out.color = vec4<f32>(color.rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse), 1.0);
+  return out;
+}
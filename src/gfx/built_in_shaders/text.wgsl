@@ -0,0 +1,66 @@
+// Glyph quads of a TextRenderer in the XY plane of the object, laid out as sprite quads. The glyph textures hold
+// signed distance fields; the outlines are antialiased by the screen-space rate of change of the distance, so that
+// text stays sharp at any distance from the camera.
+
+@group(0) @binding(0) var<uniform> camera_transform: mat4x4<f32>;
+@group(1) @binding(0) var sprite_texture: texture_2d<f32>;
+@group(2) @binding(0) var sprite_sampler: sampler;
+
+struct InstanceInput {
+  @location(0) transform_row_0: vec4<f32>,
+  @location(1) transform_row_1: vec4<f32>,
+  @location(2) transform_row_2: vec4<f32>,
+  @location(3) transform_row_3: vec4<f32>,
+  @location(4) sprite_size: vec2<f32>,
+  @location(5) sprite_offset: vec2<f32>,
+  @location(6) sprite_uv_min: vec2<f32>,
+  @location(7) sprite_uv_max: vec2<f32>,
+  @location(8) sprite_color: vec4<f32>,
+  @location(9) glyph_thickness: f32,
+  @location(10) glyph_smoothness: f32,
+};
+
+struct VertexInput {
+  @location(11) position: vec3<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) color: vec4<f32>,
+  @location(1) uv: vec2<f32>,
+  @location(2) thickness: f32,
+  @location(3) smoothness: f32,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let transform = mat4x4<f32>(instance.transform_row_0, instance.transform_row_1, instance.transform_row_2, instance.transform_row_3);
+  let position = instance.sprite_offset + instance.sprite_size * vertex.position.xy;
+
+  out.position = camera_transform * transform * vec4<f32>(position, 0.0, 1.0);
+  out.color = instance.sprite_color;
+  out.uv = instance.sprite_uv_min + (instance.sprite_uv_max - instance.sprite_uv_min) * vertex.position.xy;
+  out.thickness = instance.glyph_thickness;
+  out.smoothness = instance.glyph_smoothness;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  let distance = textureSample(sprite_texture, sprite_sampler, in.uv).r;
+  let width = fwidth(distance) * 0.5 + in.smoothness * 0.5;
+  let alpha = smoothstep(1.0 - in.thickness - width, 1.0 - in.thickness + width, distance);
+
+  if (in.color.a * alpha <= 0.001) {
+    discard;
+  }
+
+  out.color = vec4<f32>(in.color.rgb, in.color.a * alpha);
+  return out;
+}
@@ -0,0 +1,163 @@
+// Scatters the light of the sun into a grid of froxels over the view, blends them with the previous frame and
+// integrates them from the camera outwards. The slices, the volume density, the phase function and the integration
+// mirror the functions of `volumetric_fog.rs`; both must be changed together.
+
+struct Params {
+  inverse_view_projection: mat4x4<f32>,
+  previous_view_projection: mat4x4<f32>,
+  camera_position: vec4<f32>,
+  previous_camera_position: vec4<f32>,
+  // xyz: direction the sunlight travels in, w: anisotropy of the phase function.
+  sun_direction: vec4<f32>,
+  // a: intensity.
+  sun_color: vec4<f32>,
+  ambient: vec4<f32>,
+  // a: global density.
+  albedo: vec4<f32>,
+  // xyz: froxels across, down and deep, w: volume count.
+  grid: vec4<u32>,
+  // x: near, y: far, z: jitter of the samples within the slices, w: weight of the history.
+  depth: vec4<f32>,
+  // x: samples per froxel.
+  samples: vec4<u32>,
+};
+
+struct FogVolume {
+  world_to_local: mat4x4<f32>,
+  // x: density, y: edge falloff.
+  params: vec4<f32>,
+};
+
+const PI: f32 = 3.14159265358979;
+const MIN_EXTINCTION: f32 = 1e-5;
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> volumes: array<FogVolume>;
+@group(0) @binding(2) var history: texture_3d<f32>;
+@group(0) @binding(3) var history_sampler: sampler;
+@group(0) @binding(4) var scattering_out: texture_storage_3d<rgba16float, write>;
+@group(0) @binding(5) var scattering: texture_3d<f32>;
+@group(0) @binding(6) var integrated_out: texture_storage_3d<rgba16float, write>;
+
+fn slice_depth(slice: f32) -> f32 {
+  let near = params.depth.x;
+  let far = params.depth.y;
+  return near * pow(far / near, slice / f32(params.grid.z));
+}
+
+fn depth_slice(depth: f32) -> f32 {
+  let near = params.depth.x;
+  let far = params.depth.y;
+  return log(depth / near) / log(far / near) * f32(params.grid.z);
+}
+
+fn henyey_greenstein(cos_theta: f32, anisotropy: f32) -> f32 {
+  let g2 = anisotropy * anisotropy;
+  let denominator = max(1.0 + g2 - 2.0 * anisotropy * cos_theta, 1e-4);
+  return (1.0 - g2) / (4.0 * PI * pow(denominator, 1.5));
+}
+
+// Direction from the camera through the middle of the froxel column.
+fn view_ray(column: vec2<u32>) -> vec3<f32> {
+  let uv = (vec2<f32>(column) + 0.5) / vec2<f32>(params.grid.xy);
+  let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 1.0, 1.0);
+  let position = params.inverse_view_projection * ndc;
+  return normalize(position.xyz / position.w - params.camera_position.xyz);
+}
+
+fn density_at(position: vec3<f32>) -> f32 {
+  var density = params.albedo.a;
+
+  for (var index = 0u; index < params.grid.w; index += 1u) {
+    let volume = volumes[index];
+    let local = abs((volume.world_to_local * vec4<f32>(position, 1.0)).xyz);
+    let edge = max(max(local.x, local.y), local.z) * 2.0;
+
+    if (1.0 < edge) {
+      continue;
+    }
+
+    var fade = 1.0;
+
+    if (0.0 < volume.params.y) {
+      fade = clamp((1.0 - edge) / volume.params.y, 0.0, 1.0);
+    }
+
+    density += volume.params.x * fade;
+  }
+
+  return density;
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn cs_scatter(@builtin(global_invocation_id) id: vec3<u32>) {
+  if (any(id >= params.grid.xyz)) {
+    return;
+  }
+
+  let ray = view_ray(id.xy);
+  let camera_position = params.camera_position.xyz;
+  let samples = max(params.samples.x, 1u);
+  var extinction = 0.0;
+
+  // The samples are spread within the slice, and jittered along it from frame to frame.
+  for (var index = 0u; index < samples; index += 1u) {
+    let offset = fract(params.depth.z + f32(index) / f32(samples));
+    extinction += density_at(camera_position + ray * slice_depth(f32(id.z) + offset));
+  }
+
+  extinction /= f32(samples);
+
+  let cos_theta = dot(params.sun_direction.xyz, -ray);
+  let sun = params.sun_color.rgb * params.sun_color.a * henyey_greenstein(cos_theta, params.sun_direction.w);
+  let light = params.ambient.rgb + sun;
+  var froxel = vec4<f32>(params.albedo.rgb * extinction * light, extinction);
+
+  // Blends in the froxel of the previous frame that saw the same point.
+  let center = camera_position + ray * slice_depth(f32(id.z) + 0.5);
+  let previous_clip = params.previous_view_projection * vec4<f32>(center, 1.0);
+
+  if (0.0 < params.depth.w && 0.0 < previous_clip.w) {
+    let previous_ndc = previous_clip.xy / previous_clip.w;
+    let previous_depth = length(center - params.previous_camera_position.xyz);
+    let previous_uvw = vec3<f32>(
+      previous_ndc.x * 0.5 + 0.5,
+      0.5 - previous_ndc.y * 0.5,
+      depth_slice(previous_depth) / f32(params.grid.z),
+    );
+
+    if (all(vec3<f32>(0.0) <= previous_uvw) && all(previous_uvw <= vec3<f32>(1.0))) {
+      let previous = textureSampleLevel(history, history_sampler, previous_uvw, 0.0);
+      froxel = mix(froxel, previous, params.depth.w);
+    }
+  }
+
+  textureStore(scattering_out, vec3<i32>(id), froxel);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_integrate(@builtin(global_invocation_id) id: vec3<u32>) {
+  if (any(id.xy >= params.grid.xy)) {
+    return;
+  }
+
+  var scattered = vec3<f32>(0.0);
+  var transmittance = 1.0;
+
+  for (var slice = 0u; slice < params.grid.z; slice += 1u) {
+    let coords = vec3<i32>(i32(id.x), i32(id.y), i32(slice));
+    let froxel = textureLoad(scattering, coords, 0);
+    let thickness = slice_depth(f32(slice + 1u)) - slice_depth(f32(slice));
+    let slice_transmittance = exp(-froxel.a * thickness);
+    // Integrated within the slice, so that dense froxels never add more light than they let through.
+    var integral = froxel.rgb * thickness;
+
+    if (MIN_EXTINCTION < froxel.a) {
+      integral = (froxel.rgb - froxel.rgb * slice_transmittance) / froxel.a;
+    }
+
+    scattered += transmittance * integral;
+    transmittance *= slice_transmittance;
+    textureStore(integrated_out, coords, vec4<f32>(scattered, transmittance));
+  }
+}
@@ -0,0 +1,85 @@
+// Composites the volumetric fog over a camera pass: the color becomes color * transmittance + scattered light.
+// `fs_main` samples the froxels integrated by `volumetric_fog.wgsl`; `fs_analytic` is the fallback without compute
+// shaders, a uniform fog of the global density. `Params` and the slices mirror `volumetric_fog.wgsl`.
+
+struct Params {
+  inverse_view_projection: mat4x4<f32>,
+  previous_view_projection: mat4x4<f32>,
+  camera_position: vec4<f32>,
+  previous_camera_position: vec4<f32>,
+  sun_direction: vec4<f32>,
+  sun_color: vec4<f32>,
+  ambient: vec4<f32>,
+  albedo: vec4<f32>,
+  grid: vec4<u32>,
+  depth: vec4<f32>,
+  samples: vec4<u32>,
+};
+
+struct Surface {
+  uv: vec2<f32>,
+  ray: vec3<f32>,
+  distance: f32,
+};
+
+const PI: f32 = 3.14159265358979;
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var integrated: texture_3d<f32>;
+@group(0) @binding(2) var integrated_sampler: sampler;
+@group(0) @binding(3) var depth_texture: texture_depth_2d;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+  let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+  return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn slice_depth(slice: f32) -> f32 {
+  let near = params.depth.x;
+  let far = params.depth.y;
+  return near * pow(far / near, slice / f32(params.grid.z));
+}
+
+fn depth_slice(depth: f32) -> f32 {
+  let near = params.depth.x;
+  let far = params.depth.y;
+  return log(depth / near) / log(far / near) * f32(params.grid.z);
+}
+
+fn henyey_greenstein(cos_theta: f32, anisotropy: f32) -> f32 {
+  let g2 = anisotropy * anisotropy;
+  let denominator = max(1.0 + g2 - 2.0 * anisotropy * cos_theta, 1e-4);
+  return (1.0 - g2) / (4.0 * PI * pow(denominator, 1.5));
+}
+
+// The surface drawn at the pixel, up to the end of the fog.
+fn surface_at(position: vec4<f32>) -> Surface {
+  let uv = position.xy / vec2<f32>(textureDimensions(depth_texture));
+  let depth = textureLoad(depth_texture, vec2<i32>(position.xy), 0);
+  let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+  let world = params.inverse_view_projection * ndc;
+  let offset = world.xyz / world.w - params.camera_position.xyz;
+  return Surface(uv, normalize(offset), min(length(offset), params.depth.y));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+  let surface = surface_at(position);
+  // Texel `i` of the integrated grid holds the fog up to the end of slice `i`.
+  let w = (depth_slice(max(surface.distance, params.depth.x)) - 0.5) / f32(params.grid.z);
+  let fog = textureSampleLevel(integrated, integrated_sampler, vec3<f32>(surface.uv, w), 0.0);
+  // Before the end of the first slice, its fog fades in from the camera.
+  let first = slice_depth(1.0);
+  return mix(vec4<f32>(0.0, 0.0, 0.0, 1.0), fog, clamp(surface.distance / first, 0.0, 1.0));
+}
+
+@fragment
+fn fs_analytic(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+  let surface = surface_at(position);
+  let cos_theta = dot(params.sun_direction.xyz, -surface.ray);
+  let sun = params.sun_color.rgb * params.sun_color.a * henyey_greenstein(cos_theta, params.sun_direction.w);
+  let light = params.ambient.rgb + sun;
+  let transmittance = exp(-params.albedo.a * surface.distance);
+  return vec4<f32>(params.albedo.rgb * light * (1.0 - transmittance), transmittance);
+}
@@ -0,0 +1,85 @@
+
+// Water surface of a `WaterSurface`. The grid is displaced by the waves of `water_waves.wgsl`, appended above.
+// The reflection bindings are the ones of `planar_reflection.wgsl`; the environment cubemap is sampled instead
+// while the surface has no reflection texture.
+// There is no scene depth to measure the water against, so the light is absorbed along the view ray
+// down to a flat floor `params.w` below the rest plane.
+
+@group(0) @binding(0) var<uniform> camera_transform: mat4x4<f32>;
+@group(1) @binding(0) var<uniform> water: Water;
+@group(2) @binding(0) var<uniform> reflection: Reflection;
+@group(2) @binding(1) var reflection_texture: texture_2d<f32>;
+@group(2) @binding(2) var reflection_sampler: sampler;
+@group(3) @binding(0) var environment_texture: texture_cube<f32>;
+@group(3) @binding(1) var environment_sampler: sampler;
+
+struct Reflection {
+  view_projection: mat4x4<f32>,
+  camera_position: vec4<f32>,
+  // x: distortion strength, y: 1 if the reflection texture is available, 0 to use the environment map instead.
+  params: vec4<f32>,
+};
+
+struct InstanceInput {
+  @location(0) transform_row_0: vec4<f32>,
+  @location(1) transform_row_1: vec4<f32>,
+  @location(2) transform_row_2: vec4<f32>,
+  @location(3) transform_row_3: vec4<f32>,
+};
+
+struct VertexInput {
+  @location(4) position: vec3<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) world_position: vec3<f32>,
+  // Position on the rest plane the waves are evaluated at, and its height.
+  @location(1) rest_position: vec3<f32>,
+};
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(instance: InstanceInput, vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  let transform = mat4x4<f32>(instance.transform_row_0, instance.transform_row_1, instance.transform_row_2, instance.transform_row_3);
+  let rest_position = (transform * vec4<f32>(vertex.position, 1.0)).xyz;
+  let world_position = rest_position + evaluate_waves(rest_position.xz).displacement;
+  out.position = camera_transform * vec4<f32>(world_position, 1.0);
+  out.world_position = world_position;
+  out.rest_position = rest_position;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  // Evaluated per fragment, so that the normals keep the detail of the short waves between vertices.
+  let waves = evaluate_waves(in.rest_position.xz);
+  let normal = waves.normal;
+  let view_direction = normalize(in.world_position - reflection.camera_position.xyz);
+
+  let clip = reflection.view_projection * vec4<f32>(in.world_position, 1.0);
+  let ndc = clip.xy / clip.w;
+  let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5) + normal.xz * reflection.params.x;
+  let reflected = textureSample(reflection_texture, reflection_sampler, clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)));
+  let environment = textureSample(environment_texture, environment_sampler, reflect(view_direction, normal));
+  let mirrored = select(environment, reflected, 0.5 < reflection.params.y).rgb;
+
+  // Distance the view ray travels under the surface before reaching the floor.
+  let depth = max(water.params.w + waves.displacement.y, 0.0);
+  let path_length = depth / max(-view_direction.y, 0.05);
+  let transmittance = exp(-water.deep_color.a * path_length);
+  let body = mix(water.deep_color.rgb, water.shallow_color.rgb, transmittance);
+
+  // Schlick's approximation for water.
+  let facing = clamp(dot(-view_direction, normal), 0.0, 1.0);
+  let fresnel = 0.02 + 0.98 * pow(1.0 - facing, 5.0);
+  let foam = 1.0 - smoothstep(water.params.z - 0.25, water.params.z, waves.jacobian);
+
+  out.color = vec4<f32>(mix(mix(body, mirrored, fresnel), vec3<f32>(1.0), foam), 1.0);
+  return out;
+}
@@ -0,0 +1,71 @@
+// Gerstner waves of a water surface, shared by the water shader and the tests comparing them with
+// `evaluate_waves` in `water.rs`; both must be changed together.
+// The including shader declares the `water` uniform the waves are read from.
+
+const WATER_MAX_WAVES: u32 = 8u;
+const WATER_PI: f32 = 3.14159265358979;
+
+struct Water {
+  // x: wave count, y: time in seconds, z: foam threshold, w: depth of the floor below the rest plane.
+  params: vec4<f32>,
+  shallow_color: vec4<f32>,
+  // a: absorption per unit of distance travelled through the water.
+  deep_color: vec4<f32>,
+  // Two per wave; xy: direction, z: amplitude, w: wavelength, then x: speed, y: steepness.
+  waves: array<vec4<f32>, 16>,
+};
+
+struct WaveSample {
+  displacement: vec3<f32>,
+  normal: vec3<f32>,
+  // Area of the surface relative to the rest plane; crests pinch it below 1.
+  jacobian: f32,
+};
+
+// Sums the waves at the position of the rest plane.
+fn evaluate_waves(position: vec2<f32>) -> WaveSample {
+  let count = min(u32(water.params.x), WATER_MAX_WAVES);
+  let time = water.params.y;
+  var displacement = vec3<f32>(0.0);
+  var tangent_x = vec3<f32>(1.0, 0.0, 0.0);
+  var tangent_z = vec3<f32>(0.0, 0.0, 1.0);
+
+  for (var index = 0u; index < count; index += 1u) {
+    let shape = water.waves[index * 2u];
+    let motion = water.waves[index * 2u + 1u];
+    let amplitude = shape.z;
+    let wavelength = shape.w;
+
+    // Flat waves are skipped.
+    if (wavelength <= 0.0 || dot(shape.xy, shape.xy) == 0.0) {
+      continue;
+    }
+
+    let direction = normalize(shape.xy);
+    let k = 2.0 * WATER_PI / wavelength;
+    var q = 0.0;
+
+    if (k * amplitude != 0.0) {
+      q = clamp(motion.y, 0.0, 1.0) / (k * amplitude * f32(count));
+    }
+
+    let theta = k * (dot(direction, position) - motion.x * time);
+    let s = sin(theta);
+    let c = cos(theta);
+    let qa = q * amplitude;
+    let qak = qa * k;
+    let ak = amplitude * k;
+    let dx = direction.x;
+    let dz = direction.y;
+
+    displacement += vec3<f32>(qa * dx * c, amplitude * s, qa * dz * c);
+    tangent_x += vec3<f32>(-qak * dx * dx * s, ak * dx * c, -qak * dx * dz * s);
+    tangent_z += vec3<f32>(-qak * dx * dz * s, ak * dz * c, -qak * dz * dz * s);
+  }
+
+  var result: WaveSample;
+  result.displacement = displacement;
+  result.normal = normalize(cross(tangent_z, tangent_x));
+  result.jacobian = tangent_x.x * tangent_z.z - tangent_x.z * tangent_z.x;
+  return result;
+}
@@ -1,11 +1,14 @@
-use super::{BindGroupLayoutCache, Color, ScreenManager};
-use crate::math::Mat4;
+use super::{
+    BindGroupLayoutCache, CameraStackSlot, Color, ProjectionProvider, RenderTargetHandle,
+    ScreenManager, Uploader,
+};
+use crate::math::{Frustum, Mat4, Vec2, Vec3};
 use specs::{prelude::*, Component};
 use std::{mem::size_of, sync::Arc};
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutEntry, BindingResource,
     BindingType, Buffer, BufferAddress, BufferBinding, BufferBindingType, BufferDescriptor,
-    BufferSize, BufferUsages, Device, Queue, ShaderStages,
+    BufferSize, BufferUsages, Device, ShaderStages,
 };
 use zerocopy::AsBytes;
 
@@ -45,6 +48,7 @@ impl CameraClearMode {
 pub enum CameraProjection {
     Orthographic(CamereOrthographicProjection),
     Perspective(CameraPerspectiveProjection),
+    Custom(CameraCustomProjection),
 }
 
 impl CameraProjection {
@@ -66,12 +70,46 @@ impl CameraProjection {
         })
     }
 
-    pub fn as_matrix(&self, screen_mgr: &ScreenManager) -> Mat4 {
+    pub fn custom(provider: impl ProjectionProvider + 'static, near: f32, far: f32) -> Self {
+        Self::Custom(CameraCustomProjection {
+            provider: Arc::new(provider),
+            near,
+            far,
+        })
+    }
+
+    pub fn provider(&self) -> &dyn ProjectionProvider {
         match self {
-            Self::Orthographic(projection) => projection.as_matrix(screen_mgr),
-            Self::Perspective(projection) => projection.as_matrix(screen_mgr),
+            Self::Orthographic(projection) => projection,
+            Self::Perspective(projection) => projection,
+            Self::Custom(projection) => projection.provider.as_ref(),
         }
     }
+
+    pub fn near(&self) -> f32 {
+        match self {
+            Self::Orthographic(projection) => projection.near,
+            Self::Perspective(projection) => projection.near,
+            Self::Custom(projection) => projection.near,
+        }
+    }
+
+    pub fn far(&self) -> f32 {
+        match self {
+            Self::Orthographic(projection) => projection.far,
+            Self::Perspective(projection) => projection.far,
+            Self::Custom(projection) => projection.far,
+        }
+    }
+
+    pub fn as_matrix(&self, screen_mgr: &ScreenManager) -> Mat4 {
+        self.provider()
+            .projection_matrix(viewport_size(screen_mgr), self.near(), self.far())
+    }
+}
+
+fn viewport_size(screen_mgr: &ScreenManager) -> Vec2 {
+    Vec2::new(screen_mgr.width() as f32, screen_mgr.height() as f32)
 }
 
 #[derive(Debug, Clone)]
@@ -83,14 +121,20 @@ pub struct CamereOrthographicProjection {
 
 impl CamereOrthographicProjection {
     pub fn as_matrix(&self, screen_mgr: &ScreenManager) -> Mat4 {
-        let aspect = screen_mgr.width() as f32 / screen_mgr.height() as f32;
+        self.projection_matrix(viewport_size(screen_mgr), self.near, self.far)
+    }
+}
+
+impl ProjectionProvider for CamereOrthographicProjection {
+    fn projection_matrix(&self, viewport_size: Vec2, near: f32, far: f32) -> Mat4 {
+        let aspect = viewport_size.x / viewport_size.y;
         Mat4::orthographic(
             self.width * -0.5,
             self.width * 0.5,
             self.width * aspect * -0.5,
             self.width * aspect * 0.5,
-            self.near,
-            self.far,
+            near,
+            far,
         )
     }
 }
@@ -105,33 +149,136 @@ pub struct CameraPerspectiveProjection {
 
 impl CameraPerspectiveProjection {
     pub fn as_matrix(&self, screen_mgr: &ScreenManager) -> Mat4 {
+        self.projection_matrix(viewport_size(screen_mgr), self.near, self.far)
+    }
+}
+
+impl ProjectionProvider for CameraPerspectiveProjection {
+    fn projection_matrix(&self, viewport_size: Vec2, near: f32, far: f32) -> Mat4 {
         Mat4::perspective(
             self.fov,
             match self.aspect {
-                CameraPerspectiveProjectionAspect::Screen => {
-                    screen_mgr.width() as f32 / screen_mgr.height() as f32
-                }
+                CameraPerspectiveProjectionAspect::Screen => viewport_size.x / viewport_size.y,
                 CameraPerspectiveProjectionAspect::Fixed(aspect) => aspect,
             },
-            self.near,
-            self.far,
+            near,
+            far,
         )
     }
 }
 
+/// User supplied projection, e.g. an [`OffAxisProjection`](super::OffAxisProjection) or an oblique projection.
+#[derive(Debug, Clone)]
+pub struct CameraCustomProjection {
+    pub provider: Arc<dyn ProjectionProvider>,
+    pub near: f32,
+    pub far: f32,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum CameraPerspectiveProjectionAspect {
     Screen,
     Fixed(f32),
 }
 
+/// Part of the screen a camera draws into, in fractions of its size from the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraViewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl CameraViewport {
+    pub const FULL: Self = Self {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns the pixels of a target of the given size the viewport covers, clipped to the target.
+    /// Returns `None` if it covers none, e.g. because the window has been minimized.
+    pub fn to_pixels(&self, target_width: u32, target_height: u32) -> Option<ViewportRect> {
+        let to_pixel = |fraction: f32, size: u32| {
+            if fraction.is_finite() {
+                (fraction.clamp(0.0, 1.0) * size as f32).round() as u32
+            } else {
+                0
+            }
+        };
+        let left = to_pixel(self.x, target_width);
+        let right = to_pixel(self.x + self.width, target_width);
+        let top = to_pixel(self.y, target_height);
+        let bottom = to_pixel(self.y + self.height, target_height);
+
+        if right <= left || bottom <= top {
+            return None;
+        }
+
+        Some(ViewportRect {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        })
+    }
+}
+
+impl Default for CameraViewport {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+/// A viewport in pixels, from the top left corner of the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ViewportRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ViewportRect {
+    /// Returns `true` if the viewport is the whole target of the given size.
+    pub fn covers(&self, target_width: u32, target_height: u32) -> bool {
+        self.x == 0 && self.y == 0 && self.width == target_width && self.height == target_height
+    }
+}
+
 #[derive(Debug, Clone, Component)]
 #[storage(HashMapStorage)]
 pub struct Camera {
+    /// Bits the masks of the renderers must share to be drawn, e.g. [`MeshRenderer::mask`](super::MeshRenderer::mask).
     pub mask: u32,
+    /// Bits the [`Layers`](super::Layers) of the objects must share to be drawn. Defaults to every layer.
+    pub culling_mask: u32,
+    /// Cameras are rendered in increasing depth, so that later ones draw over the earlier ones.
     pub depth: u32,
     pub clear_mode: CameraClearMode,
-    pub projection: CameraProjection,
+    /// Part of the screen the camera draws into. The aspect of the projection follows its size.
+    /// Only the viewport is cleared, so a camera drawing in a corner of the screen leaves the rest as it is.
+    pub viewport: CameraViewport,
+    /// The slot of a camera stack the camera draws into, to be composited with the other slots of the stack.
+    /// Draws into the surface if `None` or if the stack is not defined.
+    pub stack: Option<CameraStackSlot>,
+    /// The offscreen texture the camera draws into instead of the surface. Such cameras are rendered before the
+    /// others, so that the texture can be sampled in the same frame.
+    pub target: Option<RenderTargetHandle>,
+    projection: CameraProjection,
+    projection_generation: u64,
+    uploaded_state: Option<CameraUploadedState>,
     pub buffer: Arc<Buffer>,
     pub bind_group: Arc<BindGroup>,
 }
@@ -145,60 +292,221 @@ impl Camera {
         device: &Device,
         bind_group_layout_cache: &mut BindGroupLayoutCache,
     ) -> Self {
-        let buffer = Arc::new(device.create_buffer(&BufferDescriptor {
-            label: Some("camera transform buffer"),
-            size: size_of::<[f32; 4 * 4]>() as BufferAddress,
-            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
-            mapped_at_creation: false,
-        }));
-        let bind_group = Arc::new(
-            device.create_bind_group(&BindGroupDescriptor {
-                label: Some("camera transform bind group"),
-                layout: bind_group_layout_cache
-                    .create_layout(vec![BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::VERTEX_FRAGMENT,
-                        ty: BindingType::Buffer {
-                            ty: BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: Some(
-                                BufferSize::new(size_of::<[f32; 4 * 4]>() as u64).unwrap(),
-                            ),
-                        },
-                        count: None,
-                    }])
-                    .as_ref(),
-                entries: &[BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::Buffer(BufferBinding {
-                        buffer: &buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                }],
-            }),
-        );
+        let (buffer, bind_group) = create_camera_buffer(device, bind_group_layout_cache);
 
         Self {
             mask,
+            culling_mask: u32::MAX,
             depth,
             clear_mode,
+            viewport: CameraViewport::FULL,
+            stack: None,
+            target: None,
             projection,
+            projection_generation: 0,
+            uploaded_state: None,
             buffer,
             bind_group,
         }
     }
 
-    pub fn update_buffer(
+    pub fn projection(&self) -> &CameraProjection {
+        &self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: CameraProjection) {
+        self.projection = projection;
+        self.projection_generation += 1;
+    }
+
+    /// Size of the viewport in logical pixels, zero if it covers none.
+    pub fn viewport_size(&self, screen_mgr: &ScreenManager) -> Vec2 {
+        match self.viewport.to_pixels(
+            screen_mgr.width().round() as u32,
+            screen_mgr.height().round() as u32,
+        ) {
+            Some(rect) => Vec2::new(rect.width as f32, rect.height as f32),
+            None => Vec2::ZERO,
+        }
+    }
+
+    pub fn projection_matrix(&self, screen_mgr: &ScreenManager) -> Mat4 {
+        self.projection.provider().projection_matrix(
+            self.viewport_size(screen_mgr),
+            self.projection.near(),
+            self.projection.far(),
+        )
+    }
+
+    pub fn view_projection_matrix(
         &self,
         screen_mgr: &ScreenManager,
-        queue: &Queue,
         transform_matrix: &Mat4,
-    ) {
-        queue.write_buffer(
-            &self.buffer,
-            0,
-            (transform_matrix.inversed() * self.projection.as_matrix(screen_mgr)).as_bytes(),
+    ) -> Mat4 {
+        transform_matrix.inversed() * self.projection_matrix(screen_mgr)
+    }
+
+    pub fn frustum(&self, screen_mgr: &ScreenManager, transform_matrix: &Mat4) -> Frustum {
+        self.projection
+            .provider()
+            .frustum(&self.view_projection_matrix(screen_mgr, transform_matrix))
+    }
+
+    /// Uploads the view-projection matrix if the transform, the viewport or the projection changed since the last upload.
+    /// Returns `true` if the buffer has been written. A non-finite matrix, e.g. from a degenerate transform or a zero-sized
+    /// viewport, is never uploaded; the buffer keeps the last valid one.
+    pub fn update_buffer(
+        &mut self,
+        screen_mgr: &ScreenManager,
+        uploader: &mut Uploader,
+        transform_matrix: &Mat4,
+    ) -> bool {
+        let state = CameraUploadedState {
+            transform_matrix: transform_matrix.clone(),
+            viewport_size: self.viewport_size(screen_mgr),
+            projection_generation: self.projection_generation,
+            provider_generation: self.projection.provider().generation(),
+        };
+
+        if self.uploaded_state.as_ref() == Some(&state) {
+            return false;
+        }
+
+        let view_projection = self.view_projection_matrix(screen_mgr, transform_matrix);
+
+        if !view_projection.is_finite() {
+            return false;
+        }
+
+        uploader.write(&self.buffer, 0, view_projection.as_bytes());
+        self.uploaded_state = Some(state);
+        true
+    }
+}
+
+/// Returns the transform of a perspective camera looking along `direction` at the bounding box, from as close as it can
+/// while the whole box stays in view. `fov` is the vertical field of view in radians; `aspect` is width over height.
+pub fn framing_transform(min: Vec3, max: Vec3, direction: Vec3, fov: f32, aspect: f32) -> Mat4 {
+    let center = (min + max) * 0.5;
+    // The bounding sphere, so that the box fits whatever the direction is.
+    let radius = ((max - min) * 0.5).len().max(f32::EPSILON);
+    let half_fov_y = fov * 0.5;
+    let half_fov_x = (half_fov_y.tan() * aspect).atan();
+    let distance = radius / half_fov_y.min(half_fov_x).sin();
+    let eye = center - direction.normalized() * distance;
+    let up = if Vec3::cross(direction, Vec3::UP).len_square() < f32::EPSILON {
+        Vec3::BACKWARD
+    } else {
+        Vec3::UP
+    };
+
+    Mat4::look_at(eye, center, up)
+}
+
+/// Creates the uniform buffer holding a view-projection matrix, along with the bind group shaders read it from.
+pub(crate) fn create_camera_buffer(
+    device: &Device,
+    bind_group_layout_cache: &mut BindGroupLayoutCache,
+) -> (Arc<Buffer>, Arc<BindGroup>) {
+    let buffer = Arc::new(device.create_buffer(&BufferDescriptor {
+        label: Some("camera transform buffer"),
+        size: size_of::<[f32; 4 * 4]>() as BufferAddress,
+        usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+        mapped_at_creation: false,
+    }));
+    let bind_group = Arc::new(
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("camera transform bind group"),
+            layout: bind_group_layout_cache
+                .create_layout(vec![BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(
+                            BufferSize::new(size_of::<[f32; 4 * 4]>() as u64).unwrap(),
+                        ),
+                    },
+                    count: None,
+                }])
+                .as_ref(),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Buffer(BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            }],
+        }),
+    );
+
+    (buffer, bind_group)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CameraUploadedState {
+    transform_matrix: Mat4,
+    viewport_size: Vec2,
+    projection_generation: u64,
+    provider_generation: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_viewport_to_pixels() {
+        assert_eq!(
+            CameraViewport::FULL.to_pixels(1280, 720),
+            Some(ViewportRect {
+                x: 0,
+                y: 0,
+                width: 1280,
+                height: 720
+            })
+        );
+        assert!(CameraViewport::FULL
+            .to_pixels(1280, 720)
+            .unwrap()
+            .covers(1280, 720));
+        assert_eq!(
+            CameraViewport::new(0.75, 0.0, 0.25, 0.25).to_pixels(1280, 720),
+            Some(ViewportRect {
+                x: 960,
+                y: 0,
+                width: 320,
+                height: 180
+            })
+        );
+    }
+
+    #[test]
+    fn check_viewport_clipped_to_the_target() {
+        assert_eq!(
+            CameraViewport::new(0.5, -0.5, 1.0, 1.0).to_pixels(100, 100),
+            Some(ViewportRect {
+                x: 50,
+                y: 0,
+                width: 50,
+                height: 50
+            })
+        );
+    }
+
+    #[test]
+    fn check_empty_viewport_is_skipped() {
+        assert_eq!(CameraViewport::FULL.to_pixels(0, 0), None);
+        assert_eq!(CameraViewport::FULL.to_pixels(1280, 0), None);
+        assert_eq!(
+            CameraViewport::new(0.5, 0.5, 0.001, 0.25).to_pixels(100, 100),
+            None
+        );
+        assert_eq!(
+            CameraViewport::new(0.0, 0.0, f32::NAN, 1.0).to_pixels(100, 100),
+            None
         );
     }
 }
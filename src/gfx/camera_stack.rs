@@ -0,0 +1,744 @@
+use super::{CameraClearMode, Color, CustomPass, GfxContextHandle, Texture};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    mem::size_of,
+    sync::Arc,
+};
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent,
+    BlendFactor, BlendOperation, BlendState, Buffer, BufferAddress, BufferBindingType,
+    BufferDescriptor, BufferSize, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder,
+    Device, Extent3d, FilterMode, FragmentState, LoadOp, MultisampleState, Operations,
+    PipelineLayout, PipelineLayoutDescriptor, PrimitiveState, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDimension, VertexState,
+};
+use zerocopy::AsBytes;
+
+/// How the output of a camera of a stack is blended over the slots below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CameraBlendMode {
+    /// The colors are blended by their alpha, as drawn by regular alpha-blended materials.
+    AlphaOver,
+    /// The colors are added, weighted by their alpha, e.g. for glows.
+    Additive,
+    /// The colors have already been multiplied by their alpha.
+    Premultiplied,
+}
+
+impl CameraBlendMode {
+    pub const ALL: [Self; 3] = [Self::AlphaOver, Self::Additive, Self::Premultiplied];
+
+    fn index(self) -> usize {
+        match self {
+            Self::AlphaOver => 0,
+            Self::Additive => 1,
+            Self::Premultiplied => 2,
+        }
+    }
+
+    fn is_premultiplied(self) -> bool {
+        self == Self::Premultiplied
+    }
+
+    /// Blend state of the composition. The shader multiplies the alpha of the layer by its opacity,
+    /// or the whole color if the layer is premultiplied.
+    fn blend_state(self) -> BlendState {
+        let component = |src_factor, dst_factor| BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: BlendOperation::Add,
+        };
+
+        match self {
+            Self::AlphaOver => BlendState {
+                color: component(BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha),
+                alpha: component(BlendFactor::One, BlendFactor::OneMinusSrcAlpha),
+            },
+            Self::Additive => BlendState {
+                color: component(BlendFactor::SrcAlpha, BlendFactor::One),
+                alpha: component(BlendFactor::Zero, BlendFactor::One),
+            },
+            Self::Premultiplied => BlendState {
+                color: component(BlendFactor::One, BlendFactor::OneMinusSrcAlpha),
+                alpha: component(BlendFactor::One, BlendFactor::OneMinusSrcAlpha),
+            },
+        }
+    }
+
+    /// Blends `src` over `dst` as the composition does on the GPU.
+    pub fn blend(self, dst: Color, src: Color, opacity: f32) -> Color {
+        let opacity = opacity.clamp(0.0, 1.0);
+        let alpha = src.a * opacity;
+
+        match self {
+            Self::AlphaOver => Color::from_rgba(
+                src.r * alpha + dst.r * (1.0 - alpha),
+                src.g * alpha + dst.g * (1.0 - alpha),
+                src.b * alpha + dst.b * (1.0 - alpha),
+                alpha + dst.a * (1.0 - alpha),
+            ),
+            Self::Additive => Color::from_rgba(
+                dst.r + src.r * alpha,
+                dst.g + src.g * alpha,
+                dst.b + src.b * alpha,
+                dst.a,
+            ),
+            Self::Premultiplied => Color::from_rgba(
+                src.r * opacity + dst.r * (1.0 - alpha),
+                src.g * opacity + dst.g * (1.0 - alpha),
+                src.b * opacity + dst.b * (1.0 - alpha),
+                alpha + dst.a * (1.0 - alpha),
+            ),
+        }
+    }
+}
+
+/// Composes the layers of a stack over `background` in order, as [`CameraStacks::compose`] does.
+pub fn compose_layers(background: Color, layers: &[(Color, CameraBlendMode, f32)]) -> Color {
+    layers
+        .iter()
+        .fold(background, |dst, &(src, blend_mode, opacity)| {
+            blend_mode.blend(dst, src, opacity)
+        })
+}
+
+/// A slot of a camera stack.
+pub struct CameraStackEntry {
+    pub blend_mode: CameraBlendMode,
+    /// Multiplies the alpha of the slot when it is composited, in `0..=1`.
+    pub opacity: f32,
+    /// Passes run on the output of the slot before it is composited, e.g. a bloom of the UI alone.
+    /// The surface texture view they are given is the intermediate target of the slot.
+    pub effects: Vec<Box<dyn CustomPass>>,
+}
+
+impl CameraStackEntry {
+    pub fn new(blend_mode: CameraBlendMode) -> Self {
+        Self {
+            blend_mode,
+            opacity: 1.0,
+            effects: Vec::new(),
+        }
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
+
+    pub fn with_effect(mut self, effect: Box<dyn CustomPass>) -> Self {
+        self.effects.push(effect);
+        self
+    }
+}
+
+/// Where a camera stack composites its slots.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CameraStackOutput {
+    Surface,
+    /// The slot of another stack, which composites the result along with its own slots.
+    Stack {
+        name: String,
+        slot: usize,
+    },
+}
+
+/// The slot of a camera stack a camera draws into, see [`Camera::stack`](super::Camera::stack).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CameraStackSlot {
+    pub stack: String,
+    pub slot: usize,
+}
+
+impl CameraStackSlot {
+    pub fn new(stack: impl Into<String>, slot: usize) -> Self {
+        Self {
+            stack: stack.into(),
+            slot,
+        }
+    }
+}
+
+pub struct CameraStack {
+    entries: Vec<CameraStackEntry>,
+    output: CameraStackOutput,
+}
+
+impl CameraStack {
+    pub fn entries(&self) -> &[CameraStackEntry] {
+        &self.entries
+    }
+
+    pub fn entries_mut(&mut self) -> &mut [CameraStackEntry] {
+        &mut self.entries
+    }
+
+    pub fn output(&self) -> &CameraStackOutput {
+        &self.output
+    }
+
+    /// Returns `true` if the only slot may draw into the surface directly, as if there was no stack.
+    pub fn is_passthrough(&self) -> bool {
+        match self.entries.as_slice() {
+            [entry] => {
+                self.output == CameraStackOutput::Surface
+                    && entry.effects.is_empty()
+                    && entry.blend_mode == CameraBlendMode::AlphaOver
+                    && 1.0 <= entry.opacity
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Color and depth views of the intermediate target of a slot.
+#[derive(Clone)]
+pub struct CameraStackLayer {
+    pub color: Arc<TextureView>,
+    pub depth: Option<Arc<TextureView>>,
+    /// `false` if another camera of the slot has already drawn into it this frame.
+    pub is_first: bool,
+}
+
+impl CameraStackLayer {
+    /// The clear mode of a camera drawing into the layer. The first camera of the slot starts from a transparent
+    /// layer unless it clears the color itself, so that the slots below show through what it leaves untouched.
+    pub fn clear_mode(&self, camera_clear_mode: &CameraClearMode) -> CameraClearMode {
+        match camera_clear_mode {
+            CameraClearMode::Keep | CameraClearMode::DepthOnly { .. } if self.is_first => {
+                CameraClearMode::all(Color::transparent(), 1.0, 0)
+            }
+            clear_mode => clear_mode.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TargetKey {
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+}
+
+struct StackTarget {
+    key: TargetKey,
+    color: Texture,
+    depth: Option<Arc<TextureView>>,
+    /// `[opacity, 1 if premultiplied, 0, 0]`.
+    uniform_buffer: Buffer,
+    bind_group: BindGroup,
+    is_used: bool,
+}
+
+/// Camera stacks and the pooled intermediate targets of their slots.
+///
+/// Each camera of a stack draws into the target of its slot instead of the surface. Once every camera has been drawn,
+/// the slots are composited in order into the output of the stack, with the blend mode and opacity of each slot.
+/// Stacks composited into another stack go first. Targets are pooled by size and format; the ones left unused
+/// for a frame, e.g. of the size before a resize, are dropped.
+pub struct CameraStacks {
+    gfx_ctx: GfxContextHandle,
+    color_format: TextureFormat,
+    depth_format: Option<TextureFormat>,
+    stacks: HashMap<String, CameraStack>,
+    targets: Vec<StackTarget>,
+    slot_targets: HashMap<(String, usize), usize>,
+    drawn_slots: HashSet<(String, usize)>,
+    bind_group_layout: BindGroupLayout,
+    sampler: Sampler,
+    shader: ShaderModule,
+    pipeline_layout: PipelineLayout,
+    pipelines: Vec<RenderPipeline>,
+}
+
+impl CameraStacks {
+    pub fn new(gfx_ctx: GfxContextHandle, depth_format: Option<TextureFormat>) -> Self {
+        let device = &gfx_ctx.device;
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("camera stack composition shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "./built_in_shaders/camera_stack_composite.wgsl"
+            ))),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("camera stack composition bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<[f32; 4]>() as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("camera stack composition pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let color_format = gfx_ctx.surface_config.read().format;
+        let pipelines = create_pipelines(device, &shader, &pipeline_layout, color_format);
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("camera stack composition sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            gfx_ctx,
+            color_format,
+            depth_format,
+            stacks: HashMap::new(),
+            targets: Vec::new(),
+            slot_targets: HashMap::new(),
+            drawn_slots: HashSet::new(),
+            bind_group_layout,
+            sampler,
+            shader,
+            pipeline_layout,
+            pipelines,
+        }
+    }
+
+    /// Follows the color format the cameras draw in, dropping the pooled targets.
+    pub fn set_color_format(&mut self, color_format: TextureFormat) {
+        if color_format == self.color_format {
+            return;
+        }
+
+        self.color_format = color_format;
+        self.pipelines = create_pipelines(
+            &self.gfx_ctx.device,
+            &self.shader,
+            &self.pipeline_layout,
+            color_format,
+        );
+        self.targets.clear();
+        self.slot_targets.clear();
+    }
+
+    /// Follows the depth format of the surface passes, dropping the pooled targets.
+    pub fn set_depth_format(&mut self, depth_format: Option<TextureFormat>) {
+        self.depth_format = depth_format;
+        self.targets.clear();
+        self.slot_targets.clear();
+    }
+
+    /// Defines the stack of the given name, replacing any previous one. It is composited into the surface.
+    pub fn define_stack(&mut self, name: impl Into<String>, entries: Vec<CameraStackEntry>) {
+        self.stacks.insert(
+            name.into(),
+            CameraStack {
+                entries,
+                output: CameraStackOutput::Surface,
+            },
+        );
+    }
+
+    /// Returns `false` if there's no stack of the given name.
+    pub fn set_output(&mut self, name: &str, output: CameraStackOutput) -> bool {
+        match self.stacks.get_mut(name) {
+            Some(stack) => {
+                stack.output = output;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove_stack(&mut self, name: &str) -> Option<CameraStack> {
+        self.stacks.remove(name)
+    }
+
+    pub fn stack(&self, name: &str) -> Option<&CameraStack> {
+        self.stacks.get(name)
+    }
+
+    pub fn stack_mut(&mut self, name: &str) -> Option<&mut CameraStack> {
+        self.stacks.get_mut(name)
+    }
+
+    /// Assigns the targets of the slots for a frame of the given size.
+    /// Stacks that can draw into the surface directly get none.
+    pub fn begin_frame(&mut self, width: u32, height: u32) {
+        self.targets.retain(|target| target.is_used);
+
+        for target in &mut self.targets {
+            target.is_used = false;
+        }
+
+        self.slot_targets.clear();
+        self.drawn_slots.clear();
+
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let key = TargetKey {
+            width,
+            height,
+            format: self.color_format,
+        };
+        let mut slots = Vec::new();
+
+        for (name, stack) in &self.stacks {
+            if stack.is_passthrough() {
+                continue;
+            }
+
+            slots.extend((0..stack.entries.len()).map(|slot| (name.clone(), slot)));
+        }
+
+        for slot in slots {
+            let target = self.acquire_target(key);
+            self.slot_targets.insert(slot, target);
+        }
+    }
+
+    /// Returns the target the camera of the slot draws into this frame.
+    /// Returns `None` if it draws into the surface, i.e. the stack is a passthrough or is not defined.
+    pub fn acquire_layer(&mut self, slot: &CameraStackSlot) -> Option<CameraStackLayer> {
+        let key = (slot.stack.clone(), slot.slot);
+        let target = &self.targets[*self.slot_targets.get(&key)?];
+        let is_first = self.drawn_slots.insert(key);
+
+        Some(CameraStackLayer {
+            color: target.color.view.clone(),
+            depth: target.depth.clone(),
+            is_first,
+        })
+    }
+
+    /// Runs the effects of the slots and composites the stacks, the ones composited into another stack first.
+    pub fn compose(&mut self, encoder: &mut CommandEncoder, surface_texture_view: &TextureView) {
+        let mut names = Vec::from_iter(
+            self.stacks
+                .iter()
+                .filter(|(_, stack)| !stack.is_passthrough())
+                .map(|(name, _)| (self.nesting_depth(name), name.clone())),
+        );
+        // The deepest first; by name among stacks of the same depth, so the order is stable.
+        names.sort_unstable_by(|lhs, rhs| rhs.0.cmp(&lhs.0).then_with(|| lhs.1.cmp(&rhs.1)));
+
+        for (_, name) in names {
+            self.compose_stack(encoder, surface_texture_view, &name);
+        }
+    }
+
+    fn compose_stack(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        surface_texture_view: &TextureView,
+        name: &str,
+    ) {
+        let stack = self.stacks.get_mut(name).unwrap();
+        let mut layers = Vec::with_capacity(stack.entries.len());
+
+        for (slot, entry) in stack.entries.iter_mut().enumerate() {
+            let target = match self.slot_targets.get(&(name.to_owned(), slot)) {
+                Some(&target) => target,
+                None => continue,
+            };
+
+            // Slots nothing has drawn into stay transparent.
+            if !self.drawn_slots.contains(&(name.to_owned(), slot)) {
+                continue;
+            }
+
+            for effect in &mut entry.effects {
+                effect.execute(encoder, &self.targets[target].color.view);
+            }
+
+            self.gfx_ctx.queue.write_buffer(
+                &self.targets[target].uniform_buffer,
+                0,
+                [
+                    entry.opacity.clamp(0.0, 1.0),
+                    if entry.blend_mode.is_premultiplied() {
+                        1.0
+                    } else {
+                        0.0
+                    },
+                    0.0,
+                    0.0,
+                ]
+                .as_bytes(),
+            );
+            layers.push((target, entry.blend_mode));
+        }
+
+        // A stack composited into a slot of another one counts as drawing into it.
+        let output_view = match &stack.output {
+            CameraStackOutput::Surface => surface_texture_view,
+            CameraStackOutput::Stack { name, slot } => {
+                match self.slot_targets.get(&(name.clone(), *slot)) {
+                    Some(&target) => {
+                        if self.drawn_slots.insert((name.clone(), *slot)) {
+                            clear_target(encoder, &self.targets[target].color.view);
+                        }
+
+                        &self.targets[target].color.view
+                    }
+                    None => surface_texture_view,
+                }
+            }
+        };
+
+        if layers.is_empty() {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("camera stack composition pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: output_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        for (target, blend_mode) in layers {
+            render_pass.set_pipeline(&self.pipelines[blend_mode.index()]);
+            render_pass.set_bind_group(0, &self.targets[target].bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    /// The number of stacks the output of the stack goes through before the surface.
+    /// Cycles are cut at the number of stacks.
+    fn nesting_depth(&self, name: &str) -> usize {
+        let mut depth = 0;
+        let mut current = name;
+
+        while let Some(CameraStackOutput::Stack { name, .. }) =
+            self.stacks.get(current).map(|stack| &stack.output)
+        {
+            if self.stacks.len() <= depth {
+                break;
+            }
+
+            depth += 1;
+            current = name;
+        }
+
+        depth
+    }
+
+    fn acquire_target(&mut self, key: TargetKey) -> usize {
+        if let Some(index) = self
+            .targets
+            .iter()
+            .position(|target| !target.is_used && target.key == key)
+        {
+            self.targets[index].is_used = true;
+            return index;
+        }
+
+        let target = self.create_target(key);
+        self.targets.push(target);
+        self.targets.len() - 1
+    }
+
+    fn create_target(&self, key: TargetKey) -> StackTarget {
+        let device = &self.gfx_ctx.device;
+        let color =
+            Texture::create_render_target(key.width as u16, key.height as u16, key.format, device);
+        let depth = self.depth_format.map(|format| {
+            let texture = device.create_texture(&TextureDescriptor {
+                label: Some("camera stack depth texture"),
+                size: Extent3d {
+                    width: key.width,
+                    height: key.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[format],
+            });
+            Arc::new(texture.create_view(&Default::default()))
+        });
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("camera stack composition uniform buffer"),
+            size: size_of::<[f32; 4]>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("camera stack composition bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&color.view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        StackTarget {
+            key,
+            color,
+            depth,
+            uniform_buffer,
+            bind_group,
+            is_used: true,
+        }
+    }
+}
+
+fn clear_target(encoder: &mut CommandEncoder, view: &TextureView) {
+    encoder.begin_render_pass(&RenderPassDescriptor {
+        label: Some("camera stack clear pass"),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: Operations {
+                load: LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+}
+
+/// One composition pipeline per blend mode, indexed as [`CameraBlendMode::ALL`].
+fn create_pipelines(
+    device: &Device,
+    shader: &ShaderModule,
+    pipeline_layout: &PipelineLayout,
+    color_format: TextureFormat,
+) -> Vec<RenderPipeline> {
+    Vec::from_iter(CameraBlendMode::ALL.map(|blend_mode| {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("camera stack composition pipeline"),
+            layout: Some(pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: color_format,
+                    blend: Some(blend_mode.blend_state()),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_color_eq(lhs: Color, rhs: Color) {
+        for (lhs, rhs) in [
+            (lhs.r, rhs.r),
+            (lhs.g, rhs.g),
+            (lhs.b, rhs.b),
+            (lhs.a, rhs.a),
+        ] {
+            assert!((lhs - rhs).abs() < 1e-5, "{:?} != {:?}", lhs, rhs);
+        }
+    }
+
+    #[test]
+    fn check_two_layer_stack_composition() {
+        let scene = Color::from_rgba(0.2, 0.4, 0.6, 1.0);
+        let ui = Color::from_rgba(1.0, 1.0, 1.0, 0.5);
+
+        // The UI at half opacity covers a quarter of the scene.
+        assert_color_eq(
+            compose_layers(
+                Color::transparent(),
+                &[
+                    (scene, CameraBlendMode::AlphaOver, 1.0),
+                    (ui, CameraBlendMode::AlphaOver, 0.5),
+                ],
+            ),
+            Color::from_rgba(0.4, 0.55, 0.7, 1.0),
+        );
+        assert_color_eq(
+            compose_layers(
+                Color::transparent(),
+                &[
+                    (scene, CameraBlendMode::AlphaOver, 1.0),
+                    (ui, CameraBlendMode::Additive, 1.0),
+                ],
+            ),
+            Color::from_rgba(0.7, 0.9, 1.1, 1.0),
+        );
+    }
+
+    #[test]
+    fn check_premultiplied_matches_alpha_over() {
+        let dst = Color::from_rgba(0.1, 0.2, 0.3, 1.0);
+        let src = Color::from_rgba(0.8, 0.6, 0.4, 0.25);
+        let premultiplied = Color::from_rgba(0.2, 0.15, 0.1, 0.25);
+
+        assert_color_eq(
+            CameraBlendMode::Premultiplied.blend(dst, premultiplied, 0.5),
+            CameraBlendMode::AlphaOver.blend(dst, src, 0.5),
+        );
+    }
+
+    #[test]
+    fn check_transparent_or_hidden_layers_change_nothing() {
+        let dst = Color::from_rgba(0.3, 0.3, 0.3, 1.0);
+
+        for blend_mode in CameraBlendMode::ALL {
+            assert_color_eq(blend_mode.blend(dst, Color::white(), 0.0), dst);
+            assert_color_eq(blend_mode.blend(dst, Color::transparent(), 1.0), dst);
+        }
+    }
+}
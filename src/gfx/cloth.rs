@@ -0,0 +1,806 @@
+use crate::{
+    math::{Mat4, Vec3, Vec4},
+    object::ObjectId,
+};
+use specs::{prelude::*, Component};
+
+/// How a [`ClothConstraint`] holds two particles together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClothConstraintKind {
+    /// Between neighbors along a row or a column; keeps the cloth from stretching.
+    Structural,
+    /// Between diagonal neighbors; keeps the quads from shearing.
+    Shear,
+    /// Between particles two apart along a row or a column; keeps the cloth from folding sharply.
+    Bend,
+}
+
+/// Keeps two particles of a [`Cloth`] at their rest distance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClothConstraint {
+    pub kind: ClothConstraintKind,
+    pub a: u32,
+    pub b: u32,
+    pub rest_length: f32,
+}
+
+/// Simulation parameters of a [`Cloth`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClothSettings {
+    /// Constraint iterations per fixed update. More iterations stretch less.
+    pub iterations: u32,
+    /// Constraint iterations per fixed update while the cloth is outside of every camera's frustum. 0 pauses it.
+    /// `None` simulates it the same wherever the cameras look, which rollback requires.
+    pub offscreen_iterations: Option<u32>,
+    /// Acceleration in world space.
+    pub gravity: Vec3,
+    /// Fraction of the velocity lost per fixed update.
+    pub damping: f32,
+    /// Wind of this cloth in world space, added to the global wind of the [`Context`](crate::Context).
+    pub wind: Vec3,
+    /// How much the wind gusts, from 0 for a steady wind to 1 for gusts stopping it entirely.
+    pub turbulence: f32,
+    /// Stiffness of the shear constraints, from 0 to 1.
+    pub shear_stiffness: f32,
+    /// Stiffness of the bend constraints, from 0 to 1.
+    pub bend_stiffness: f32,
+}
+
+impl Default for ClothSettings {
+    fn default() -> Self {
+        Self {
+            iterations: 8,
+            offscreen_iterations: Some(0),
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            damping: 0.01,
+            wind: Vec3::ZERO,
+            turbulence: 0.5,
+            shear_stiffness: 0.8,
+            bend_stiffness: 0.2,
+        }
+    }
+}
+
+/// Where a pinned particle of a [`Cloth`] is held.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClothPin {
+    /// At its rest position on the cloth's object.
+    Rest { particle: u32 },
+    /// At an offset in the space of another object, e.g. a shoulder bone.
+    Attached {
+        particle: u32,
+        object: ObjectId,
+        offset: Vec3,
+    },
+}
+
+impl ClothPin {
+    fn particle(&self) -> u32 {
+        match *self {
+            Self::Rest { particle } | Self::Attached { particle, .. } => particle,
+        }
+    }
+}
+
+/// A collision shape in world space, resolved from a [`ClothCollider`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClothColliderShape {
+    Sphere { center: Vec3, radius: f32 },
+    Capsule { start: Vec3, end: Vec3, radius: f32 },
+}
+
+impl ClothColliderShape {
+    /// Moves the point onto the surface of the shape if it is inside.
+    pub fn push_out(&self, point: Vec3) -> Vec3 {
+        let (closest, radius) = match *self {
+            Self::Sphere { center, radius } => (center, radius),
+            Self::Capsule { start, end, radius } => {
+                let axis = end - start;
+                let len_square = axis.len_square();
+                let t = if len_square <= f32::EPSILON {
+                    0.0
+                } else {
+                    (Vec3::dot(point - start, axis) / len_square).clamp(0.0, 1.0)
+                };
+                (start + axis * t, radius)
+            }
+        };
+        let offset = point - closest;
+        let distance = offset.len();
+
+        if radius <= distance || distance <= f32::EPSILON {
+            return point;
+        }
+
+        closest + offset * (radius / distance)
+    }
+}
+
+/// A sphere or a capsule pushing the particles of the [`Cloth`]es that list the object out, e.g. a character's body
+/// under its cape. The shape is in the object's space, but the radius is in world units.
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+#[storage(HashMapStorage)]
+pub enum ClothCollider {
+    Sphere { center: Vec3, radius: f32 },
+    Capsule { start: Vec3, end: Vec3, radius: f32 },
+}
+
+impl ClothCollider {
+    /// Places the shape in world space by the object's matrix.
+    pub fn to_world(&self, matrix: &Mat4) -> ClothColliderShape {
+        match *self {
+            Self::Sphere { center, radius } => ClothColliderShape::Sphere {
+                center: transform_point(center, matrix),
+                radius,
+            },
+            Self::Capsule { start, end, radius } => ClothColliderShape::Capsule {
+                start: transform_point(start, matrix),
+                end: transform_point(end, matrix),
+                radius,
+            },
+        }
+    }
+}
+
+/// A rectangular grid of particles simulated as cloth in the fixed update, e.g. a flag, a cape or a banner.
+///
+/// The grid hangs from its top edge in the object's xy plane, facing +Z: the particle of column `c` and row `r`
+/// rests at `(c * width / (columns - 1), -r * height / (rows - 1), 0)`. The particles are integrated with Verlet
+/// in world space, so the cloth trails behind its object, and the distance constraints are relaxed a configurable
+/// number of times per fixed update. Pinned particles follow the object, or another object they are attached to.
+///
+/// The simulated cloth is written into the [`MeshRenderer`](super::MeshRenderer) of the same object every frame,
+/// as a double-sided mesh with recomputed normals.
+///
+/// The state is plain data, so the component may be registered for rollback as long as
+/// [`ClothSettings::offscreen_iterations`] is `None`.
+#[derive(Debug, Clone, Component)]
+#[storage(HashMapStorage)]
+pub struct Cloth {
+    pub settings: ClothSettings,
+    columns: u32,
+    rows: u32,
+    width: f32,
+    height: f32,
+    rest_positions: Vec<Vec3>,
+    positions: Vec<Vec3>,
+    previous_positions: Vec<Vec3>,
+    inverse_masses: Vec<f32>,
+    constraints: Vec<ClothConstraint>,
+    pins: Vec<ClothPin>,
+    /// Objects whose nearby particles get attached to them once the cloth is placed, with the radius.
+    pending_attachments: Vec<(ObjectId, f32)>,
+    colliders: Vec<ObjectId>,
+    time: f32,
+    is_placed: bool,
+    is_mesh_dirty: bool,
+}
+
+impl Cloth {
+    /// Creates a grid `width` by `height` units of `columns` by `rows` particles. At least 2 by 2 are created.
+    pub fn new(width: f32, height: f32, columns: u32, rows: u32) -> Self {
+        let columns = columns.max(2);
+        let rows = rows.max(2);
+        let rest_positions = Vec::from_iter((0..rows).flat_map(|row| {
+            (0..columns).map(move |column| {
+                Vec3::new(
+                    column as f32 * width / (columns - 1) as f32,
+                    -(row as f32) * height / (rows - 1) as f32,
+                    0.0,
+                )
+            })
+        }));
+        let particle_count = rest_positions.len();
+
+        let mut cloth = Self {
+            settings: ClothSettings::default(),
+            columns,
+            rows,
+            width,
+            height,
+            rest_positions,
+            positions: vec![Vec3::ZERO; particle_count],
+            previous_positions: vec![Vec3::ZERO; particle_count],
+            inverse_masses: vec![1.0; particle_count],
+            constraints: Vec::new(),
+            pins: Vec::new(),
+            pending_attachments: Vec::new(),
+            colliders: Vec::new(),
+            time: 0.0,
+            is_placed: false,
+            is_mesh_dirty: true,
+        };
+        cloth.constraints = cloth.build_constraints();
+        cloth
+    }
+
+    /// Creates a grid spanning the bounding box of the mesh on the xy plane, keeping the mesh's top-left corner.
+    /// Returns `None` if the mesh has no vertices.
+    pub fn from_mesh(mesh: &super::Mesh, columns: u32, rows: u32) -> Option<Self> {
+        let (min, max) = mesh.bounds()?;
+        let mut cloth = Self::new(max.x - min.x, max.y - min.y, columns, rows);
+        let corner = Vec3::new(min.x, max.y, 0.5 * (min.z + max.z));
+
+        for position in &mut cloth.rest_positions {
+            *position += corner;
+        }
+
+        Some(cloth)
+    }
+
+    pub fn columns(&self) -> u32 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    pub fn particle_index(&self, column: u32, row: u32) -> usize {
+        (row * self.columns + column) as usize
+    }
+
+    /// Positions of the particles in world space, row by row. Empty until the first fixed update.
+    pub fn positions(&self) -> &[Vec3] {
+        if self.is_placed {
+            &self.positions
+        } else {
+            &[]
+        }
+    }
+
+    /// Rest positions of the particles in the object's space, row by row.
+    pub fn rest_positions(&self) -> &[Vec3] {
+        &self.rest_positions
+    }
+
+    pub fn constraints(&self) -> &[ClothConstraint] {
+        &self.constraints
+    }
+
+    /// Holds the particle at its rest position on the object.
+    pub fn pin(&mut self, column: u32, row: u32) {
+        let particle = self.particle_index(column, row) as u32;
+        self.unpin_particle(particle);
+        self.pins.push(ClothPin::Rest { particle });
+        self.inverse_masses[particle as usize] = 0.0;
+    }
+
+    /// Pins every particle of the row, e.g. the top row of a banner.
+    pub fn pin_row(&mut self, row: u32) {
+        for column in 0..self.columns {
+            self.pin(column, row);
+        }
+    }
+
+    /// Pins every particle of the column, e.g. the one along the pole of a flag.
+    pub fn pin_column(&mut self, column: u32) {
+        for row in 0..self.rows {
+            self.pin(column, row);
+        }
+    }
+
+    pub fn unpin(&mut self, column: u32, row: u32) {
+        self.unpin_particle(self.particle_index(column, row) as u32);
+    }
+
+    pub fn is_pinned(&self, column: u32, row: u32) -> bool {
+        let particle = self.particle_index(column, row) as u32;
+        self.pins.iter().any(|pin| pin.particle() == particle)
+    }
+
+    /// Attaches the particles within `radius` of the object's origin to it, e.g. a cape to the shoulder bones.
+    /// The particles are picked at their rest positions when the cloth is first simulated, or on the next fixed
+    /// update after a [`reset`](Self::reset).
+    pub fn attach_to(&mut self, object: ObjectId, radius: f32) {
+        self.pending_attachments.push((object, radius));
+    }
+
+    /// Objects with a [`ClothCollider`] pushing the particles out.
+    pub fn colliders(&self) -> &[ObjectId] {
+        &self.colliders
+    }
+
+    pub fn add_collider(&mut self, object: ObjectId) {
+        if !self.colliders.contains(&object) {
+            self.colliders.push(object);
+        }
+    }
+
+    pub fn remove_collider(&mut self, object: ObjectId) {
+        self.colliders.retain(|&collider| collider != object);
+    }
+
+    /// Places the cloth back at rest on the next fixed update, dropping its velocity.
+    pub fn reset(&mut self) {
+        self.is_placed = false;
+    }
+
+    /// Returns `true` if the particles moved since the mesh was last written.
+    pub fn is_mesh_dirty(&self) -> bool {
+        self.is_mesh_dirty
+    }
+
+    /// Largest stretch of a structural constraint relative to its rest length, e.g. `0.05` for 5% longer.
+    pub fn max_stretch(&self) -> f32 {
+        self.constraints
+            .iter()
+            .filter(|constraint| constraint.kind == ClothConstraintKind::Structural)
+            .map(|constraint| {
+                let length = Vec3::distance(
+                    self.positions[constraint.a as usize],
+                    self.positions[constraint.b as usize],
+                );
+                (length - constraint.rest_length) / constraint.rest_length
+            })
+            .fold(0.0, f32::max)
+    }
+
+    /// Bounding box of the particles in world space, as `(min, max)`. `None` until the first fixed update.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        if !self.is_placed {
+            return None;
+        }
+
+        Some(self.positions.iter().fold(
+            (
+                Vec3::new(f32::MAX, f32::MAX, f32::MAX),
+                Vec3::new(f32::MIN, f32::MIN, f32::MIN),
+            ),
+            |(min, max), &position| (Vec3::min(min, position), Vec3::max(max, position)),
+        ))
+    }
+
+    /// Advances the cloth by one fixed update of `delta_time` seconds, relaxing the constraints `iterations` times.
+    /// `matrix` places the cloth's object, and `object_matrix` places the objects the cloth is attached to.
+    pub fn step(
+        &mut self,
+        matrix: &Mat4,
+        object_matrix: impl Fn(ObjectId) -> Option<Mat4>,
+        colliders: &[ClothColliderShape],
+        global_wind: Vec3,
+        delta_time: f32,
+        iterations: u32,
+    ) {
+        if !self.is_placed {
+            self.place(matrix, &object_matrix);
+        }
+
+        self.time += delta_time;
+        self.is_mesh_dirty = true;
+
+        let wind = global_wind + self.settings.wind;
+        let turbulence = self.settings.turbulence.clamp(0.0, 1.0);
+        let retained = 1.0 - self.settings.damping.clamp(0.0, 1.0);
+        let delta_time_square = delta_time * delta_time;
+
+        for index in 0..self.positions.len() {
+            if self.inverse_masses[index] == 0.0 {
+                continue;
+            }
+
+            let position = self.positions[index];
+            let gust = 1.0 - turbulence * 0.5 * (1.0 - wind_noise(position, self.time));
+            let acceleration = self.settings.gravity + wind * gust;
+            let velocity = (position - self.previous_positions[index]) * retained;
+
+            self.previous_positions[index] = position;
+            self.positions[index] = position + velocity + acceleration * delta_time_square;
+        }
+
+        self.move_pins(matrix, &object_matrix);
+
+        for _ in 0..iterations {
+            for constraint in &self.constraints {
+                let stiffness = match constraint.kind {
+                    ClothConstraintKind::Structural => 1.0,
+                    ClothConstraintKind::Shear => self.settings.shear_stiffness,
+                    ClothConstraintKind::Bend => self.settings.bend_stiffness,
+                };
+                let (a, b) = (constraint.a as usize, constraint.b as usize);
+                let (weight_a, weight_b) = (self.inverse_masses[a], self.inverse_masses[b]);
+                let weight_sum = weight_a + weight_b;
+
+                if weight_sum == 0.0 {
+                    continue;
+                }
+
+                let delta = self.positions[b] - self.positions[a];
+                let length = delta.len();
+
+                if length <= f32::EPSILON {
+                    continue;
+                }
+
+                let correction = delta * (stiffness * (length - constraint.rest_length) / length);
+                self.positions[a] += correction * (weight_a / weight_sum);
+                self.positions[b] -= correction * (weight_b / weight_sum);
+            }
+
+            for (position, &inverse_mass) in self.positions.iter_mut().zip(&self.inverse_masses) {
+                if inverse_mass == 0.0 {
+                    continue;
+                }
+
+                for collider in colliders {
+                    *position = collider.push_out(*position);
+                }
+            }
+        }
+    }
+
+    /// Builds the vertices of the double-sided mesh in the space of `inverse_matrix`, as triangles of
+    /// `[position, normal, uv]`. The front faces +Z at rest.
+    pub fn vertices(&self, inverse_matrix: &Mat4) -> Vec<[f32; 8]> {
+        let positions = if self.is_placed {
+            Vec::from_iter(
+                self.positions
+                    .iter()
+                    .map(|&position| transform_point(position, inverse_matrix)),
+            )
+        } else {
+            self.rest_positions.clone()
+        };
+        let mut normals = vec![Vec3::ZERO; positions.len()];
+        let quads = Vec::from_iter((0..self.rows - 1).flat_map(|row| {
+            (0..self.columns - 1).map(move |column| {
+                let top_left = (row * self.columns + column) as usize;
+                let bottom_left = top_left + self.columns as usize;
+                [top_left, bottom_left, bottom_left + 1, top_left + 1]
+            })
+        }));
+        let triangles = Vec::from_iter(
+            quads
+                .iter()
+                .flat_map(|&[tl, bl, br, tr]| [[tl, bl, br], [tl, br, tr]]),
+        );
+
+        // Area weighted, so that thin triangles don't skew the normals.
+        for &[a, b, c] in &triangles {
+            let normal = Vec3::cross(positions[b] - positions[a], positions[c] - positions[a]);
+
+            for index in [a, b, c] {
+                normals[index] += normal;
+            }
+        }
+
+        for normal in &mut normals {
+            *normal = if normal.len_square() <= f32::EPSILON {
+                Vec3::new(0.0, 0.0, 1.0)
+            } else {
+                normal.normalized()
+            };
+        }
+
+        let vertex = |index: usize, flip: bool| {
+            let position = positions[index];
+            let normal = if flip {
+                -normals[index]
+            } else {
+                normals[index]
+            };
+            let (column, row) = (index as u32 % self.columns, index as u32 / self.columns);
+            [
+                position.x,
+                position.y,
+                position.z,
+                normal.x,
+                normal.y,
+                normal.z,
+                column as f32 / (self.columns - 1) as f32,
+                row as f32 / (self.rows - 1) as f32,
+            ]
+        };
+        let mut vertices = Vec::with_capacity(triangles.len() * 6);
+
+        for &[a, b, c] in &triangles {
+            vertices.extend([vertex(a, false), vertex(b, false), vertex(c, false)]);
+        }
+
+        for &[a, b, c] in &triangles {
+            vertices.extend([vertex(a, true), vertex(c, true), vertex(b, true)]);
+        }
+
+        vertices
+    }
+
+    /// Marks the mesh as written.
+    pub fn clear_mesh_dirty(&mut self) {
+        self.is_mesh_dirty = false;
+    }
+
+    fn build_constraints(&self) -> Vec<ClothConstraint> {
+        let mut constraints = Vec::new();
+        let mut add = |kind, (column_a, row_a): (u32, u32), (column_b, row_b): (u32, u32)| {
+            if self.columns <= column_b || self.rows <= row_b {
+                return;
+            }
+
+            let a = self.particle_index(column_a, row_a);
+            let b = self.particle_index(column_b, row_b);
+            constraints.push(ClothConstraint {
+                kind,
+                a: a as u32,
+                b: b as u32,
+                rest_length: Vec3::distance(self.rest_positions[a], self.rest_positions[b]),
+            });
+        };
+
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                add(
+                    ClothConstraintKind::Structural,
+                    (column, row),
+                    (column + 1, row),
+                );
+                add(
+                    ClothConstraintKind::Structural,
+                    (column, row),
+                    (column, row + 1),
+                );
+                add(
+                    ClothConstraintKind::Shear,
+                    (column, row),
+                    (column + 1, row + 1),
+                );
+
+                if 0 < column {
+                    add(
+                        ClothConstraintKind::Shear,
+                        (column, row),
+                        (column - 1, row + 1),
+                    );
+                }
+
+                add(ClothConstraintKind::Bend, (column, row), (column + 2, row));
+                add(ClothConstraintKind::Bend, (column, row), (column, row + 2));
+            }
+        }
+
+        constraints
+    }
+
+    /// Puts the particles at rest where the object is, and attaches the pending particles.
+    fn place(&mut self, matrix: &Mat4, object_matrix: &impl Fn(ObjectId) -> Option<Mat4>) {
+        for (index, &rest) in self.rest_positions.iter().enumerate() {
+            let position = transform_point(rest, matrix);
+            self.positions[index] = position;
+            self.previous_positions[index] = position;
+        }
+
+        for (object, radius) in std::mem::take(&mut self.pending_attachments) {
+            let object_matrix = if let Some(object_matrix) = object_matrix(object) {
+                object_matrix
+            } else {
+                // Tried again once the object shows up.
+                self.pending_attachments.push((object, radius));
+                continue;
+            };
+            let origin = Vec3::from(object_matrix.row(3));
+            let inverse = object_matrix.inversed();
+
+            for particle in 0..self.positions.len() as u32 {
+                let position = self.positions[particle as usize];
+
+                if radius < Vec3::distance(position, origin) {
+                    continue;
+                }
+
+                self.unpin_particle(particle);
+                self.pins.push(ClothPin::Attached {
+                    particle,
+                    object,
+                    offset: transform_point(position, &inverse),
+                });
+                self.inverse_masses[particle as usize] = 0.0;
+            }
+        }
+
+        self.is_placed = true;
+    }
+
+    fn move_pins(&mut self, matrix: &Mat4, object_matrix: &impl Fn(ObjectId) -> Option<Mat4>) {
+        for pin in &self.pins {
+            let (particle, target) = match *pin {
+                ClothPin::Rest { particle } => (
+                    particle as usize,
+                    transform_point(self.rest_positions[particle as usize], matrix),
+                ),
+                ClothPin::Attached {
+                    particle,
+                    object,
+                    offset,
+                } => match object_matrix(object) {
+                    Some(object_matrix) => {
+                        (particle as usize, transform_point(offset, &object_matrix))
+                    }
+                    // Stays where it was while the object is gone.
+                    None => continue,
+                },
+            };
+
+            self.previous_positions[particle] = self.positions[particle];
+            self.positions[particle] = target;
+        }
+    }
+
+    fn unpin_particle(&mut self, particle: u32) {
+        self.pins.retain(|pin| pin.particle() != particle);
+        self.inverse_masses[particle as usize] = 1.0;
+    }
+}
+
+/// Smooth noise in range [-1, 1] varying over space and time, giving the wind its gusts.
+pub fn wind_noise(position: Vec3, time: f32) -> f32 {
+    let noise = (position.x * 0.7 + time * 1.3).sin()
+        + 0.5 * (position.y * 1.1 - time * 1.7).sin()
+        + 0.25 * (position.z * 0.9 + time * 2.3).sin();
+    noise / 1.75
+}
+
+fn transform_point(point: Vec3, matrix: &Mat4) -> Vec3 {
+    Vec3::from_vec4(Vec4::from_vec3(point, 1.0) * matrix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA_TIME: f32 = 0.02;
+
+    fn settle(cloth: &mut Cloth, matrix: &Mat4, steps: usize) {
+        for _ in 0..steps {
+            cloth.step(matrix, |_| None, &[], Vec3::ZERO, DELTA_TIME, 16);
+        }
+    }
+
+    #[test]
+    fn check_constraint_counts() {
+        let cloth = Cloth::new(1.0, 1.0, 4, 3);
+        let count = |kind| {
+            cloth
+                .constraints()
+                .iter()
+                .filter(|constraint| constraint.kind == kind)
+                .count()
+        };
+
+        assert_eq!(count(ClothConstraintKind::Structural), 3 * 3 + 4 * 2);
+        assert_eq!(count(ClothConstraintKind::Shear), 3 * 2 * 2);
+        assert_eq!(count(ClothConstraintKind::Bend), 2 * 3 + 4);
+    }
+
+    #[test]
+    fn check_hanging_cloth_stays_within_stretch_threshold() {
+        let mut cloth = Cloth::new(2.0, 2.0, 12, 12);
+        cloth.pin_row(0);
+        settle(&mut cloth, &Mat4::identity(), 300);
+
+        assert!(
+            cloth.max_stretch() < 0.05,
+            "stretched by {}",
+            cloth.max_stretch()
+        );
+        // Hanging straight down under gravity.
+        let bottom = cloth.positions()[cloth.particle_index(6, 11)];
+        assert!((bottom.y + 2.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn check_pinned_particles_follow_the_object() {
+        let mut cloth = Cloth::new(1.0, 1.0, 6, 6);
+        cloth.pin_column(0);
+        cloth.settings.wind = Vec3::new(5.0, 0.0, 3.0);
+        let matrix = Mat4::translation(Vec3::new(3.0, 4.0, -1.0));
+        settle(&mut cloth, &matrix, 100);
+
+        for row in 0..cloth.rows() {
+            let index = cloth.particle_index(0, row);
+            let expected = transform_point(cloth.rest_positions()[index], &matrix);
+            assert!(Vec3::distance(cloth.positions()[index], expected) < 1e-5);
+        }
+
+        assert!(cloth.is_pinned(0, 3));
+        assert!(!cloth.is_pinned(1, 3));
+    }
+
+    #[test]
+    fn check_attached_particles_follow_their_object() {
+        let mut cloth = Cloth::new(1.0, 1.0, 5, 5);
+        let shoulder = ObjectId::from_u32(7);
+        cloth.attach_to(shoulder, 0.1);
+
+        let mut shoulder_matrix = Mat4::identity();
+        for step in 0..50 {
+            shoulder_matrix = Mat4::translation(Vec3::new(step as f32 * 0.01, 0.0, 0.0));
+            let shoulder_matrix = shoulder_matrix.clone();
+            cloth.step(
+                &Mat4::identity(),
+                move |object| (object == shoulder).then(|| shoulder_matrix.clone()),
+                &[],
+                Vec3::ZERO,
+                DELTA_TIME,
+                8,
+            );
+        }
+
+        // Only the top-left particle is within reach of the shoulder.
+        assert!(Vec3::distance(cloth.positions()[0], Vec3::from(shoulder_matrix.row(3))) < 1e-5);
+        assert!(!cloth.is_pinned(1, 0));
+    }
+
+    #[test]
+    fn check_colliders_push_particles_out() {
+        let mut cloth = Cloth::new(2.0, 2.0, 10, 10);
+        cloth.pin_row(0);
+        let sphere = ClothColliderShape::Sphere {
+            center: Vec3::new(1.0, -1.5, 0.2),
+            radius: 0.5,
+        };
+
+        for _ in 0..200 {
+            cloth.step(
+                &Mat4::identity(),
+                |_| None,
+                &[sphere],
+                Vec3::ZERO,
+                DELTA_TIME,
+                8,
+            );
+        }
+
+        assert!(cloth
+            .positions()
+            .iter()
+            .all(|&position| 0.5 - 1e-3 <= Vec3::distance(position, Vec3::new(1.0, -1.5, 0.2))));
+    }
+
+    #[test]
+    fn check_capsule_push_out() {
+        let capsule = ClothColliderShape::Capsule {
+            start: Vec3::new(0.0, 0.0, 0.0),
+            end: Vec3::new(0.0, 2.0, 0.0),
+            radius: 1.0,
+        };
+
+        assert_eq!(
+            capsule.push_out(Vec3::new(0.5, 1.0, 0.0)),
+            Vec3::new(1.0, 1.0, 0.0)
+        );
+        assert_eq!(
+            capsule.push_out(Vec3::new(2.0, 1.0, 0.0)),
+            Vec3::new(2.0, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn check_simulation_is_deterministic() {
+        let simulate = || {
+            let mut cloth = Cloth::new(1.5, 1.0, 8, 6);
+            cloth.pin_column(0);
+            cloth.settings.wind = Vec3::new(6.0, 0.0, 1.0);
+            settle(&mut cloth, &Mat4::identity(), 120);
+            cloth.positions().to_vec()
+        };
+
+        assert_eq!(simulate(), simulate());
+    }
+
+    #[test]
+    fn check_vertices_are_double_sided() {
+        let cloth = Cloth::new(1.0, 1.0, 3, 3);
+        let vertices = cloth.vertices(&Mat4::identity());
+
+        assert_eq!(vertices.len(), 2 * 2 * 2 * 3 * 2);
+        assert_eq!(&vertices[0][3..6], &[0.0, 0.0, 1.0]);
+        assert_eq!(&vertices[vertices.len() / 2][3..6], &[0.0, 0.0, -1.0]);
+    }
+}
@@ -22,6 +22,8 @@ pub enum ColorParseHexError {
     IncorrectLengthError,
 }
 
+/// A color as picked, e.g. `#808080`: sRGB-encoded, with a linear alpha. Where the scene is shaded in linear space,
+/// it is decoded on the way to the shaders, see [`ColorSpaceMode`](super::ColorSpaceMode).
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
     pub r: f32,
@@ -105,6 +107,26 @@ impl Color {
         }
     }
 
+    /// Decodes the sRGB components into linear values. Alpha is left as is.
+    pub fn to_linear(&self) -> Self {
+        Self {
+            r: srgb_to_linear(self.r),
+            g: srgb_to_linear(self.g),
+            b: srgb_to_linear(self.b),
+            a: self.a,
+        }
+    }
+
+    /// Encodes linear components into sRGB; the inverse of [`to_linear`](Self::to_linear).
+    pub fn to_srgb(&self) -> Self {
+        Self {
+            r: linear_to_srgb(self.r),
+            g: linear_to_srgb(self.g),
+            b: linear_to_srgb(self.b),
+            a: self.a,
+        }
+    }
+
     pub fn transparent() -> Self {
         Self {
             r: 0f32,
@@ -187,6 +209,22 @@ impl Color {
     }
 }
 
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
 impl Mul for Color {
     type Output = Self;
 
@@ -0,0 +1,375 @@
+use super::{CameraClearMode, Color};
+use serde::{Deserialize, Serialize};
+use wgpu::TextureFormat;
+
+/// Space the scene is shaded and blended in, see
+/// [`RenderManager::set_color_space`](super::RenderManager::set_color_space).
+///
+/// Either way, a color picked as `#808080` is shown as `#808080`; only the math in between differs.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpaceMode {
+    /// Colors are decoded into linear values before they reach the shaders, and the surface encodes the output back
+    /// into sRGB: through an sRGB view of it, or a gamma pass where the surface has none. Lighting and blending are
+    /// physically correct.
+    Linear,
+    /// Colors reach the shaders as picked, and the surface stores what the shaders output. Lighting and blending
+    /// happen on sRGB values, as they always did.
+    #[default]
+    Gamma,
+}
+
+impl ColorSpaceMode {
+    /// The color as the shaders of the scene expect it in this space. [`Color`]s are sRGB, as picked.
+    pub fn shader_color(self, color: Color) -> Color {
+        match self {
+            Self::Linear => color.to_linear(),
+            Self::Gamma => color,
+        }
+    }
+
+    /// The clear mode with its color as the targets of the scene expect it in this space.
+    pub fn clear_mode(self, clear_mode: &CameraClearMode) -> CameraClearMode {
+        match clear_mode {
+            CameraClearMode::All {
+                color,
+                depth,
+                stencil,
+            } => CameraClearMode::all(self.shader_color(*color), *depth, *stencil),
+            clear_mode => clear_mode.clone(),
+        }
+    }
+}
+
+/// Conversion the post-processing blit applies while writing the scene into the surface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SurfaceConversion {
+    None,
+    /// Linear colors into sRGB, for a linear scene on a surface without an sRGB view.
+    EncodeSrgb,
+    /// sRGB colors into linear values, for a gamma scene on a surface that can only be viewed as sRGB.
+    DecodeSrgb,
+}
+
+/// How the scene reaches the surface, see [`surface_output`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SurfaceOutput {
+    /// Format of the view of the surface the scene is written through.
+    pub format: TextureFormat,
+    pub conversion: SurfaceConversion,
+}
+
+/// The format the surface is configured with: an sRGB one if the surface offers one and it can also be viewed
+/// without sRGB encoding, for the passes drawing colors as picked, e.g. the overlays. Otherwise a plain one.
+/// Only 8-bit formats are picked while there are some, as frame captures and screenshots expect them.
+pub fn select_surface_format(formats: &[TextureFormat], srgb_views: bool) -> TextureFormat {
+    let is_8bit = |format: &TextureFormat| {
+        matches!(
+            format.remove_srgb_suffix(),
+            TextureFormat::Bgra8Unorm | TextureFormat::Rgba8Unorm
+        )
+    };
+    let candidates = || formats.iter().copied().filter(is_8bit);
+
+    candidates()
+        .find(|format| srgb_views && format.is_srgb())
+        .or_else(|| candidates().find(|format| !format.is_srgb()))
+        .or_else(|| formats.first().copied())
+        .unwrap_or(TextureFormat::Bgra8Unorm)
+}
+
+/// Formats the surface may be viewed in: its own, and its sRGB or plain twin if the views allow it.
+pub fn surface_view_formats(format: TextureFormat, srgb_views: bool) -> Vec<TextureFormat> {
+    let twin = if format.is_srgb() {
+        format.remove_srgb_suffix()
+    } else {
+        format.add_srgb_suffix()
+    };
+
+    if srgb_views && twin != format {
+        vec![format, twin]
+    } else {
+        vec![format]
+    }
+}
+
+/// The view the scene of the color space is written into the surface through. The view encodes linear colors by
+/// itself if it is sRGB; the conversion makes up for a surface lacking the view the space calls for.
+pub fn surface_output(
+    color_space: ColorSpaceMode,
+    format: TextureFormat,
+    view_formats: &[TextureFormat],
+) -> SurfaceOutput {
+    let is_viewable =
+        |view_format: TextureFormat| view_format == format || view_formats.contains(&view_format);
+    let (view_format, conversion) = match color_space {
+        ColorSpaceMode::Linear => (format.add_srgb_suffix(), SurfaceConversion::EncodeSrgb),
+        ColorSpaceMode::Gamma => (format.remove_srgb_suffix(), SurfaceConversion::DecodeSrgb),
+    };
+
+    if is_viewable(view_format) {
+        SurfaceOutput {
+            format: view_format,
+            conversion: SurfaceConversion::None,
+        }
+    } else {
+        SurfaceOutput { format, conversion }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::{
+        create_test_device, image_difference, CapturedBinding, CapturedBufferLayout, CapturedClear,
+        CapturedCommand, CapturedPass, CapturedPassTarget, CapturedPipeline, CapturedResource,
+        CapturedShader, CapturedVertexBuffer, FrameCapture, FrameReplayer,
+    };
+    use wgpu::{
+        BindGroupLayoutEntry, BindingType, BufferBindingType, ColorTargetState, ColorWrites,
+        PrimitiveState, ShaderStages, VertexAttribute, VertexFormat, VertexStepMode,
+    };
+    use zerocopy::AsBytes;
+
+    const SHADER: &str = r#"
+struct Tint {
+    color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> tint: Tint;
+
+@vertex
+fn vs_main(@location(0) position: vec2<f32>) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(position, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return tint.color;
+}
+"#;
+
+    /// A frame of the color space on an sRGB surface, cleared to `clear` with a quad of `tint` over its left half,
+    /// as the recorder would capture it.
+    fn create_capture(color_space: ColorSpaceMode, clear: Color, tint: Color) -> FrameCapture {
+        let surface_format = TextureFormat::Bgra8UnormSrgb;
+        let output = surface_output(
+            color_space,
+            surface_format,
+            &surface_view_formats(surface_format, true),
+        );
+        assert_eq!(output.conversion, SurfaceConversion::None);
+
+        let quad = [
+            -1.0f32, -1.0, 0.0, -1.0, 0.0, 1.0, //
+            -1.0, -1.0, 0.0, 1.0, -1.0, 1.0,
+        ];
+        let clear = color_space.shader_color(clear);
+        let tint = color_space.shader_color(tint);
+
+        FrameCapture {
+            width: 64,
+            height: 32,
+            surface_format: output.format,
+            screen_size: 0,
+            shaders: vec![CapturedShader {
+                source: SHADER.to_owned(),
+            }],
+            pipelines: vec![CapturedPipeline {
+                shader: 0,
+                vertex_entry_point: "vs_main".to_owned(),
+                fragment_entry_point: "fs_main".to_owned(),
+                bind_group_layouts: vec![vec![BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }]],
+                buffer_layouts: vec![CapturedBufferLayout {
+                    array_stride: 8,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: vec![VertexAttribute {
+                        format: VertexFormat::Float32x2,
+                        offset: 0,
+                        shader_location: 0,
+                    }],
+                }],
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                targets: vec![Some(ColorTargetState {
+                    format: output.format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }],
+            textures: Vec::new(),
+            passes: vec![CapturedPass {
+                name: "camera #0".to_owned(),
+                target: CapturedPassTarget::Surface,
+                clear: CapturedClear {
+                    color: Some([
+                        clear.r as f64,
+                        clear.g as f64,
+                        clear.b as f64,
+                        clear.a as f64,
+                    ]),
+                    depth: None,
+                    stencil: None,
+                },
+                camera_transform: 1,
+                commands: vec![CapturedCommand {
+                    pipeline: 0,
+                    vertex_count: 6,
+                    instance_count: 1,
+                    bindings: vec![CapturedBinding {
+                        group: 0,
+                        binding: 0,
+                        resource: CapturedResource::Buffer { buffer: 3 },
+                    }],
+                    vertex_buffers: vec![CapturedVertexBuffer { slot: 0, buffer: 2 }],
+                    index_buffer: None,
+                    indirect_buffer: None,
+                    instance_properties: Vec::new(),
+                }],
+            }],
+            reference_image: None,
+            buffers: vec![
+                [64.0f32, 32.0, 0.0, 0.0].as_bytes().to_vec(),
+                crate::math::Mat4::identity().as_bytes().to_vec(),
+                quad.as_bytes().to_vec(),
+                [tint.r, tint.g, tint.b, tint.a].as_bytes().to_vec(),
+            ],
+        }
+    }
+
+    #[test]
+    fn check_surface_prefers_srgb_formats_it_can_view_plainly() {
+        let formats = [
+            TextureFormat::Rgba16Float,
+            TextureFormat::Bgra8Unorm,
+            TextureFormat::Bgra8UnormSrgb,
+        ];
+        assert_eq!(
+            select_surface_format(&formats, true),
+            TextureFormat::Bgra8UnormSrgb
+        );
+        assert_eq!(
+            select_surface_format(&formats, false),
+            TextureFormat::Bgra8Unorm
+        );
+        assert_eq!(
+            select_surface_format(&[TextureFormat::Rgba8UnormSrgb], false),
+            TextureFormat::Rgba8UnormSrgb
+        );
+        assert_eq!(select_surface_format(&[], true), TextureFormat::Bgra8Unorm);
+
+        assert_eq!(
+            surface_view_formats(TextureFormat::Bgra8UnormSrgb, true),
+            vec![TextureFormat::Bgra8UnormSrgb, TextureFormat::Bgra8Unorm]
+        );
+        assert_eq!(
+            surface_view_formats(TextureFormat::Bgra8Unorm, false),
+            vec![TextureFormat::Bgra8Unorm]
+        );
+    }
+
+    #[test]
+    fn check_surface_output_converts_without_a_matching_view() {
+        let srgb_surface = surface_view_formats(TextureFormat::Bgra8UnormSrgb, true);
+        assert_eq!(
+            surface_output(
+                ColorSpaceMode::Linear,
+                TextureFormat::Bgra8UnormSrgb,
+                &srgb_surface
+            ),
+            SurfaceOutput {
+                format: TextureFormat::Bgra8UnormSrgb,
+                conversion: SurfaceConversion::None,
+            }
+        );
+        assert_eq!(
+            surface_output(
+                ColorSpaceMode::Gamma,
+                TextureFormat::Bgra8UnormSrgb,
+                &srgb_surface
+            ),
+            SurfaceOutput {
+                format: TextureFormat::Bgra8Unorm,
+                conversion: SurfaceConversion::None,
+            }
+        );
+
+        // Without views of the other kind, the gamma pass encodes or decodes.
+        assert_eq!(
+            surface_output(
+                ColorSpaceMode::Linear,
+                TextureFormat::Bgra8Unorm,
+                &[TextureFormat::Bgra8Unorm]
+            ),
+            SurfaceOutput {
+                format: TextureFormat::Bgra8Unorm,
+                conversion: SurfaceConversion::EncodeSrgb,
+            }
+        );
+        assert_eq!(
+            surface_output(
+                ColorSpaceMode::Gamma,
+                TextureFormat::Rgba8UnormSrgb,
+                &[TextureFormat::Rgba8UnormSrgb]
+            ),
+            SurfaceOutput {
+                format: TextureFormat::Rgba8UnormSrgb,
+                conversion: SurfaceConversion::DecodeSrgb,
+            }
+        );
+    }
+
+    #[test]
+    fn check_shader_colors_decode_only_in_linear() {
+        let gray = Color::parse_hex("#808080").unwrap();
+        assert_eq!(ColorSpaceMode::Gamma.shader_color(gray), gray);
+
+        let linear = ColorSpaceMode::Linear.shader_color(gray);
+        assert!((linear.r - 0.2158).abs() < 1e-3);
+        assert_eq!(linear.a, 1.0);
+        assert!((linear.to_srgb().g - gray.g).abs() < 1e-5);
+
+        let clear_mode = ColorSpaceMode::Linear.clear_mode(&CameraClearMode::all(gray, 1.0, 0));
+        assert!(matches!(clear_mode, CameraClearMode::All { color, .. } if color == linear));
+    }
+
+    #[test]
+    fn check_gray_looks_the_same_in_both_spaces() {
+        let device = match create_test_device() {
+            Some(device) => device,
+            None => return,
+        };
+        let gray = Color::parse_hex("#808080").unwrap();
+        let orange = Color::parse_hex("#ff8000").unwrap();
+        let render = |color_space| {
+            FrameReplayer::new(&device.device, &device.queue)
+                .render(&create_capture(color_space, gray, orange), None)
+                .unwrap()
+        };
+        let gamma = render(ColorSpaceMode::Gamma);
+        let linear = render(ColorSpaceMode::Linear);
+
+        assert_eq!(gamma.get_pixel(56, 16).0, [0x80, 0x80, 0x80, 0xff]);
+        assert_eq!(gamma.get_pixel(8, 16).0, [0xff, 0x80, 0x00, 0xff]);
+
+        // The linear space rounds differently on the way, by a step at most.
+        for (x, y) in [(56, 16), (8, 16)] {
+            let (gamma, linear) = (gamma.get_pixel(x, y).0, linear.get_pixel(x, y).0);
+
+            for channel in 0..4 {
+                assert!(gamma[channel].abs_diff(linear[channel]) <= 1);
+            }
+        }
+
+        assert!(image_difference(&gamma, &linear).unwrap() < 0.01);
+    }
+}
@@ -0,0 +1,453 @@
+use crate::math::Vec3;
+use codegen::Handle;
+use image::{ColorType, DynamicImage, GenericImageView, Rgba32FImage};
+use std::{f32::consts::PI, sync::Arc};
+use thiserror::Error;
+use wgpu::{
+    AddressMode, Device, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout, Origin3d, Queue,
+    Sampler, SamplerDescriptor, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CubemapError {
+    #[error("cubemap faces must not be empty")]
+    Empty,
+    #[error("cubemap face {face:?} is {width}x{height}; faces must be square")]
+    NotSquare {
+        face: CubemapFace,
+        width: u32,
+        height: u32,
+    },
+    #[error(
+        "cubemap face {face:?} is {width}x{width}, but the first face is {expected}x{expected}"
+    )]
+    SizeMismatch {
+        face: CubemapFace,
+        width: u32,
+        expected: u32,
+    },
+    #[error(
+        "a vertical cross must be 3 faces wide and 4 faces high, but the image is {width}x{height}"
+    )]
+    NotVerticalCross { width: u32, height: u32 },
+}
+
+/// Faces of a cubemap, in the order of its array layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CubemapFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubemapFace {
+    pub const ALL: [CubemapFace; 6] = [
+        CubemapFace::PositiveX,
+        CubemapFace::NegativeX,
+        CubemapFace::PositiveY,
+        CubemapFace::NegativeY,
+        CubemapFace::PositiveZ,
+        CubemapFace::NegativeZ,
+    ];
+
+    /// Unnormalized direction the texel at `(u, v)` of the face is sampled from, `v` going down the image.
+    pub fn direction(self, u: f32, v: f32) -> Vec3 {
+        let s = u * 2.0 - 1.0;
+        let t = v * 2.0 - 1.0;
+
+        match self {
+            CubemapFace::PositiveX => Vec3::new(1.0, -t, -s),
+            CubemapFace::NegativeX => Vec3::new(-1.0, -t, s),
+            CubemapFace::PositiveY => Vec3::new(s, 1.0, t),
+            CubemapFace::NegativeY => Vec3::new(s, -1.0, -t),
+            CubemapFace::PositiveZ => Vec3::new(s, -t, 1.0),
+            CubemapFace::NegativeZ => Vec3::new(-s, -t, -1.0),
+        }
+    }
+}
+
+/// Cuts a vertical cross into its faces, in the order of [`CubemapFace::ALL`]. The cross is laid out as
+///
+/// ```text
+///     +Y
+/// -X  +Z  +X
+///     -Y
+///     -Z
+/// ```
+///
+/// with -Z upside down, so that every face meets its neighbours along shared edges.
+pub fn split_vertical_cross(image: &DynamicImage) -> Result<[DynamicImage; 6], CubemapError> {
+    let (width, height) = image.dimensions();
+    let size = width / 3;
+
+    if size == 0 || width != size * 3 || height != size * 4 {
+        return Err(CubemapError::NotVerticalCross { width, height });
+    }
+
+    let face = |column: u32, row: u32| image.crop_imm(column * size, row * size, size, size);
+
+    Ok([
+        face(2, 1),
+        face(0, 1),
+        face(1, 0),
+        face(1, 2),
+        face(1, 1),
+        face(1, 3).rotate180(),
+    ])
+}
+
+/// Samples an equirectangular panorama in the given direction, bilinearly. The center of the panorama faces
+/// [`Vec3::FORWARD`] and its top row is straight up; it wraps around horizontally.
+pub fn sample_equirectangular(panorama: &Rgba32FImage, direction: Vec3) -> [f32; 4] {
+    let direction = direction.normalized();
+    let (width, height) = panorama.dimensions();
+    let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+    let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let xs = [
+        (x0 as i64).rem_euclid(width as i64) as u32,
+        (x0 as i64 + 1).rem_euclid(width as i64) as u32,
+    ];
+    let ys = [y0 as u32, (y0 as u32 + 1).min(height - 1)];
+
+    let mut texel = [0.0; 4];
+    for (y, wy) in ys.into_iter().zip([1.0 - ty, ty]) {
+        for (x, wx) in xs.into_iter().zip([1.0 - tx, tx]) {
+            let sample = panorama.get_pixel(x, y).0;
+            for channel in 0..4 {
+                texel[channel] += sample[channel] * wx * wy;
+            }
+        }
+    }
+
+    texel
+}
+
+/// Renders the faces of a cubemap `face_size` texels wide out of an equirectangular panorama, on the CPU.
+pub fn equirectangular_to_faces(panorama: &Rgba32FImage, face_size: u32) -> [Rgba32FImage; 6] {
+    CubemapFace::ALL.map(|face| {
+        Rgba32FImage::from_fn(face_size, face_size, |x, y| {
+            let u = (x as f32 + 0.5) / face_size as f32;
+            let v = (y as f32 + 0.5) / face_size as f32;
+            image::Rgba(sample_equirectangular(panorama, face.direction(u, v)))
+        })
+    })
+}
+
+/// Converts to the bits of a half float, rounding to nearest. Out of range values become infinities.
+pub fn f32_to_f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x007F_FFFF;
+
+    if exponent == 0xFF {
+        // NaNs stay NaNs.
+        return sign | 0x7C00 | if mantissa != 0 { 0x0200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+
+    if 31 <= exponent {
+        return sign | 0x7C00;
+    }
+
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - exponent) as u32;
+        return sign | ((mantissa + (1 << (shift - 1))) >> shift) as u16;
+    }
+
+    // A carry out of the mantissa correctly bumps the exponent, up to infinity.
+    sign | (((exponent as u32) << 10) + ((mantissa + 0x1000) >> 13)) as u16
+}
+
+/// Texture whose six faces are sampled by direction, e.g. the sky of a [`RenderManager::set_skybox`](super::RenderManager::set_skybox).
+/// Faces of 8-bit images are uploaded as sRGB; faces of floating point images keep their range as half floats.
+#[derive(Handle)]
+pub struct Cubemap {
+    pub texture: Arc<wgpu::Texture>,
+    pub view: Arc<TextureView>,
+    pub sampler: Arc<Sampler>,
+    pub size: u32,
+    pub format: TextureFormat,
+}
+
+impl Cubemap {
+    /// Uploads the faces given in the order of [`CubemapFace::ALL`]. They must be square and of the same size.
+    pub fn from_faces(
+        faces: &[DynamicImage; 6],
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<Self, CubemapError> {
+        let size = validate_faces(faces)?;
+        let is_hdr = faces
+            .iter()
+            .any(|face| matches!(face.color(), ColorType::Rgb32F | ColorType::Rgba32F));
+        let (format, texel_size) = if is_hdr {
+            (TextureFormat::Rgba16Float, 8)
+        } else {
+            (TextureFormat::Rgba8UnormSrgb, 4)
+        };
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("cubemap"),
+            size: Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[format],
+        });
+
+        for (layer, face) in faces.iter().enumerate() {
+            let texels = if is_hdr {
+                Vec::from_iter(
+                    face.to_rgba32f()
+                        .into_raw()
+                        .into_iter()
+                        .flat_map(|value| f32_to_f16_bits(value).to_le_bytes()),
+                )
+            } else {
+                face.to_rgba8().into_raw()
+            };
+
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                &texels,
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(texel_size * size),
+                    rows_per_image: Some(size),
+                },
+                Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: None,
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 32.0,
+            compare: None,
+            anisotropy_clamp: 1,
+            border_color: None,
+        });
+
+        Ok(Self {
+            texture: texture.into(),
+            view: view.into(),
+            sampler: sampler.into(),
+            size,
+            format,
+        })
+    }
+
+    /// Uploads a single image holding the faces as a vertical cross, see [`split_vertical_cross`].
+    pub fn from_vertical_cross(
+        image: &DynamicImage,
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<Self, CubemapError> {
+        Self::from_faces(&split_vertical_cross(image)?, device, queue)
+    }
+
+    /// Converts an equirectangular panorama, e.g. an HDR environment, into faces `face_size` texels wide.
+    /// The conversion runs on the CPU, so it is meant for loading rather than every frame.
+    pub fn from_equirectangular(
+        panorama: &DynamicImage,
+        face_size: u32,
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<Self, CubemapError> {
+        if face_size == 0 || panorama.width() == 0 || panorama.height() == 0 {
+            return Err(CubemapError::Empty);
+        }
+
+        let faces = equirectangular_to_faces(&panorama.to_rgba32f(), face_size)
+            .map(DynamicImage::ImageRgba32F);
+        Self::from_faces(&faces, device, queue)
+    }
+}
+
+/// Checks that the faces are square and of one size, returning the size.
+fn validate_faces(faces: &[DynamicImage; 6]) -> Result<u32, CubemapError> {
+    let expected = faces[0].width();
+
+    if expected == 0 {
+        return Err(CubemapError::Empty);
+    }
+
+    for (face, image) in CubemapFace::ALL.into_iter().zip(faces) {
+        let (width, height) = image.dimensions();
+
+        if width != height {
+            return Err(CubemapError::NotSquare {
+                face,
+                width,
+                height,
+            });
+        }
+
+        if width != expected {
+            return Err(CubemapError::SizeMismatch {
+                face,
+                width,
+                expected,
+            });
+        }
+    }
+
+    Ok(expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn assert_near(lhs: Vec3, rhs: Vec3) {
+        assert!(
+            Vec3::distance(lhs, rhs) < 1e-5,
+            "{:?} is not {:?}",
+            lhs,
+            rhs
+        );
+    }
+
+    #[test]
+    fn check_face_directions_meet_at_edges() {
+        assert_near(
+            CubemapFace::PositiveX.direction(0.5, 0.5),
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert_near(
+            CubemapFace::NegativeZ.direction(0.5, 0.5),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        assert_near(
+            CubemapFace::PositiveY.direction(0.5, 0.5),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+
+        // Right edge of +Z is the left edge of +X, and the top of +Z is the bottom of +Y.
+        assert_near(
+            CubemapFace::PositiveZ.direction(1.0, 0.25),
+            CubemapFace::PositiveX.direction(0.0, 0.25),
+        );
+        assert_near(
+            CubemapFace::PositiveZ.direction(0.25, 0.0),
+            CubemapFace::PositiveY.direction(0.25, 1.0),
+        );
+        assert_near(
+            CubemapFace::NegativeY.direction(0.25, 1.0),
+            CubemapFace::NegativeZ.direction(0.75, 1.0),
+        );
+    }
+
+    #[test]
+    fn check_vertical_cross_split() {
+        let mut cross = RgbaImage::new(6, 8);
+        for (x, y, texel) in cross.enumerate_pixels_mut() {
+            *texel = Rgba([(x / 2) as u8, (y / 2) as u8, (x % 2 + y % 2 * 2) as u8, 255]);
+        }
+
+        let faces = split_vertical_cross(&DynamicImage::ImageRgba8(cross)).unwrap();
+        let cells = faces.map(|face| face.to_rgba8().get_pixel(0, 0).0);
+
+        assert_eq!(cells[0][..3], [2, 1, 0]);
+        assert_eq!(cells[1][..3], [0, 1, 0]);
+        assert_eq!(cells[2][..3], [1, 0, 0]);
+        assert_eq!(cells[3][..3], [1, 2, 0]);
+        assert_eq!(cells[4][..3], [1, 1, 0]);
+        // Upside down, so its first texel is the last of its cell.
+        assert_eq!(cells[5][..3], [1, 3, 3]);
+
+        assert_eq!(
+            split_vertical_cross(&DynamicImage::ImageRgba8(RgbaImage::new(8, 8))).unwrap_err(),
+            CubemapError::NotVerticalCross {
+                width: 8,
+                height: 8
+            }
+        );
+    }
+
+    #[test]
+    fn check_equirectangular_sampling() {
+        // Upper half bright, lower half dark, brighter towards the center column.
+        let panorama = Rgba32FImage::from_fn(8, 4, |x, y| {
+            let column = 4.0 - (x as f32 + 0.5 - 4.0).abs();
+            Rgba([if y < 2 { 1.0 } else { 0.0 }, column, 0.0, 1.0])
+        });
+
+        let up = sample_equirectangular(&panorama, Vec3::UP);
+        let down = sample_equirectangular(&panorama, Vec3::DOWN);
+        let forward = sample_equirectangular(&panorama, Vec3::FORWARD);
+        let backward = sample_equirectangular(&panorama, Vec3::BACKWARD);
+
+        assert!((up[0] - 1.0).abs() < 1e-6);
+        assert_eq!(down[0], 0.0);
+        assert!(backward[1] < forward[1]);
+        assert!((forward[3] - 1.0).abs() < 1e-6);
+
+        let faces = equirectangular_to_faces(&panorama, 4);
+        assert!((faces[2].get_pixel(1, 1).0[0] - 1.0).abs() < 1e-6);
+        assert_eq!(faces[3].get_pixel(1, 1).0[0], 0.0);
+    }
+
+    #[test]
+    fn check_f16_conversion() {
+        assert_eq!(f32_to_f16_bits(0.0), 0x0000);
+        assert_eq!(f32_to_f16_bits(-0.0), 0x8000);
+        assert_eq!(f32_to_f16_bits(1.0), 0x3C00);
+        assert_eq!(f32_to_f16_bits(-2.0), 0xC000);
+        assert_eq!(f32_to_f16_bits(0.5), 0x3800);
+        assert_eq!(f32_to_f16_bits(65504.0), 0x7BFF);
+        assert_eq!(f32_to_f16_bits(1.0e6), 0x7C00);
+        assert_eq!(f32_to_f16_bits(f32::INFINITY), 0x7C00);
+        assert_eq!(f32_to_f16_bits(f32::NAN) & 0x7C00, 0x7C00);
+        assert_ne!(f32_to_f16_bits(f32::NAN) & 0x03FF, 0);
+        // Smallest subnormal.
+        assert_eq!(f32_to_f16_bits(5.960_464_5e-8), 0x0001);
+        assert_eq!(f32_to_f16_bits(1.0e-10), 0x0000);
+    }
+}
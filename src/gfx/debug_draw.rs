@@ -0,0 +1,235 @@
+use super::{
+    Color, ColorSpaceMode, FrameBufferAllocator, LineRenderer, LineSegment, LineSubRenderer,
+    MaterialHandle, PipelineCache, ShaderManager,
+};
+use crate::{
+    math::{Mat4, Vec3},
+    util::FrameArena,
+};
+use std::f32::consts::TAU;
+
+/// Immediate-mode gizmos in world space, for debugging transforms, bounds and camera frustums.
+///
+/// Gizmos accumulate during the frame, e.g. in update systems, and are drawn over the scene of the main camera
+/// before the UI, in at most two draw calls: one for the depth-tested gizmos and one for the others.
+/// They are reset once the frame has been drawn.
+pub struct DebugDrawManager {
+    is_enabled: bool,
+    depth_tested: LineRenderer,
+    overlaid: LineRenderer,
+}
+
+impl DebugDrawManager {
+    /// The number of segments of each circle of a wire sphere.
+    pub const SPHERE_SEGMENTS: usize = 32;
+
+    /// `material` should use the built-in [`BUILT_IN_SHADER_LINE`](super::BUILT_IN_SHADER_LINE).
+    pub fn new(material: MaterialHandle) -> Self {
+        let mut depth_tested = LineRenderer::new();
+        depth_tested.set_material(material.clone());
+
+        let mut overlaid = LineRenderer::new();
+        overlaid.set_material(material);
+        overlaid.set_depth_test(false);
+
+        Self {
+            is_enabled: true,
+            depth_tested,
+            overlaid,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    /// Disabled, the gizmos are discarded instead of being drawn.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.is_enabled = enabled;
+    }
+
+    /// The number of segments accumulated for the current frame.
+    pub fn segment_count(&self) -> usize {
+        self.depth_tested.segments().len() + self.overlaid.segments().len()
+    }
+
+    pub fn draw_line(&mut self, a: Vec3, b: Vec3, color: Color, depth_test: bool) {
+        self.lines(depth_test).push_segment(a, b, color);
+    }
+
+    /// Draws the edges of an axis-aligned box. `extents` are the half sizes along each axis.
+    pub fn draw_wire_cube(&mut self, center: Vec3, extents: Vec3, color: Color, depth_test: bool) {
+        self.lines(depth_test)
+            .extend_segments(wire_cube_segments(center, extents, color));
+    }
+
+    /// Draws a circle around each axis.
+    pub fn draw_wire_sphere(&mut self, center: Vec3, radius: f32, color: Color, depth_test: bool) {
+        self.lines(depth_test)
+            .extend_segments(wire_sphere_segments(center, radius, color));
+    }
+
+    /// Draws the axes of the matrix from its origin, `size` units long: X in red, Y in green and Z in blue.
+    pub fn draw_axes(&mut self, matrix: &Mat4, size: f32, depth_test: bool) {
+        self.lines(depth_test)
+            .extend_segments(axes_segments(matrix, size));
+    }
+
+    /// Discards the gizmos of the current frame.
+    pub fn clear(&mut self) {
+        self.depth_tested.clear_segments();
+        self.overlaid.clear_segments();
+    }
+
+    /// Uploads the gizmos of the frame, their colors in the given space, and resets them. Called once per frame by the
+    /// render system.
+    pub fn upload(
+        &mut self,
+        frame_buffer_allocator: &mut FrameBufferAllocator,
+        frame_alloc: &FrameArena,
+        color_space: ColorSpaceMode,
+    ) {
+        if !self.is_enabled {
+            self.clear();
+        }
+
+        self.depth_tested
+            .upload(frame_buffer_allocator, frame_alloc, color_space);
+        self.overlaid
+            .upload(frame_buffer_allocator, frame_alloc, color_space);
+    }
+
+    /// Renderers of the uploaded gizmos, the depth-tested ones first.
+    pub fn sub_renderers(
+        &mut self,
+        shader_mgr: &ShaderManager,
+        pipeline_cache: &mut PipelineCache,
+    ) -> impl Iterator<Item = LineSubRenderer> {
+        [
+            self.depth_tested.sub_renderer(shader_mgr, pipeline_cache),
+            self.overlaid.sub_renderer(shader_mgr, pipeline_cache),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    fn lines(&mut self, depth_test: bool) -> &mut LineRenderer {
+        if depth_test {
+            &mut self.depth_tested
+        } else {
+            &mut self.overlaid
+        }
+    }
+}
+
+fn wire_cube_segments(center: Vec3, extents: Vec3, color: Color) -> Vec<LineSegment> {
+    let corner = |index: usize| {
+        center
+            + Vec3::new(
+                if index & 1 == 0 {
+                    -extents.x
+                } else {
+                    extents.x
+                },
+                if index & 2 == 0 {
+                    -extents.y
+                } else {
+                    extents.y
+                },
+                if index & 4 == 0 {
+                    -extents.z
+                } else {
+                    extents.z
+                },
+            )
+    };
+
+    // Each edge joins two corners differing by a single axis bit.
+    Vec::from_iter((0..8).flat_map(|from| {
+        [1, 2, 4]
+            .into_iter()
+            .filter(move |bit| from & bit == 0)
+            .map(move |bit| LineSegment {
+                start: corner(from),
+                end: corner(from | bit),
+                color,
+            })
+    }))
+}
+
+fn wire_sphere_segments(center: Vec3, radius: f32, color: Color) -> Vec<LineSegment> {
+    let circles = [
+        (Vec3::RIGHT, Vec3::UP),
+        (Vec3::UP, Vec3::FORWARD),
+        (Vec3::FORWARD, Vec3::RIGHT),
+    ];
+    let point = |(u, v): (Vec3, Vec3), index: usize| {
+        let angle = TAU * index as f32 / DebugDrawManager::SPHERE_SEGMENTS as f32;
+        center + (u * angle.cos() + v * angle.sin()) * radius
+    };
+
+    Vec::from_iter(circles.into_iter().flat_map(|axes| {
+        (0..DebugDrawManager::SPHERE_SEGMENTS).map(move |index| LineSegment {
+            start: point(axes, index),
+            end: point(axes, index + 1),
+            color,
+        })
+    }))
+}
+
+fn axes_segments(matrix: &Mat4, size: f32) -> Vec<LineSegment> {
+    let origin = Vec3::from(matrix.row(3));
+    let colors = [Color::red(), Color::green(), Color::blue()];
+
+    Vec::from_iter(
+        colors
+            .into_iter()
+            .enumerate()
+            .map(|(axis, color)| LineSegment {
+                start: origin,
+                end: origin + Vec3::from(matrix.row(axis)).normalized() * size,
+                color,
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_wire_cube_has_12_edges_of_its_size() {
+        let segments = wire_cube_segments(Vec3::ZERO, Vec3::new(1.0, 2.0, 3.0), Color::white());
+
+        assert_eq!(segments.len(), 12);
+
+        for segment in &segments {
+            let length = Vec3::distance(segment.start, segment.end);
+            assert!([2.0, 4.0, 6.0].contains(&length));
+        }
+    }
+
+    #[test]
+    fn check_wire_sphere_points_lie_on_the_sphere() {
+        let center = Vec3::new(1.0, 1.0, 1.0);
+        let segments = wire_sphere_segments(center, 2.0, Color::white());
+
+        assert_eq!(segments.len(), 3 * DebugDrawManager::SPHERE_SEGMENTS);
+
+        for segment in &segments {
+            assert!((Vec3::distance(center, segment.start) - 2.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn check_axes_start_at_the_origin_of_the_matrix() {
+        let matrix = Mat4::translation(Vec3::new(5.0, 6.0, 7.0));
+        let segments = axes_segments(&matrix, 0.5);
+
+        assert_eq!(segments.len(), 3);
+        assert!(segments
+            .iter()
+            .all(|segment| segment.start == Vec3::new(5.0, 6.0, 7.0)));
+        assert_eq!(segments[0].end, Vec3::new(5.5, 6.0, 7.0));
+    }
+}
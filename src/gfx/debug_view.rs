@@ -0,0 +1,561 @@
+use super::{
+    semantic_bindings, semantic_inputs, BufferLayout, PipelineKey, ReflectedShader,
+    SemanticShaderInputKey,
+};
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+use wgpu::{
+    BlendComponent, BlendFactor, BlendOperation, BlendState, ColorTargetState, ColorWrites,
+    CompareFunction, Device, FragmentState, PrimitiveTopology, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, VertexAttribute,
+    VertexState,
+};
+
+/// What the scene is shown as, for diagnosing why something does not show up, or where the time goes. Set on the
+/// whole scene by [`RenderManager::set_debug_view`](super::RenderManager::set_debug_view); the materials are left
+/// untouched.
+///
+/// Normals, depth and the wireframe without [`Features::POLYGON_MODE_LINE`](wgpu::Features::POLYGON_MODE_LINE) are
+/// drawn by a built-in shader, which only stands in for shaders whose vertices it can place the same way, see
+/// [`DebugViewInputs`]. Renderers of other shaders are drawn as usual.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DebugViewMode {
+    #[default]
+    None,
+    /// Edges of the triangles, as lines in the colors of the materials if the device rasterizes polygons as lines,
+    /// in white over nothing otherwise. The fallback is exact for unindexed vertices only; indexed meshes miss the
+    /// edges between vertices that do not happen to be distinct corners.
+    Wireframe,
+    /// World-space normals, mapped from `[-1, 1]` to `[0, 1]`.
+    Normals,
+    /// Every surface added up regardless of the depth, so that brighter pixels are drawn more times over.
+    Overdraw,
+    /// Bright near the camera, fading out with the distance along the view.
+    Depth,
+}
+
+impl DebugViewMode {
+    pub const ALL: [Self; 5] = [
+        Self::None,
+        Self::Wireframe,
+        Self::Normals,
+        Self::Overdraw,
+        Self::Depth,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Wireframe => "wireframe",
+            Self::Normals => "normals",
+            Self::Overdraw => "overdraw",
+            Self::Depth => "depth",
+        }
+    }
+
+    /// The mode of the [`name`](Self::name), case-insensitively.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// How a pipeline of the [`PipelineCache`](super::PipelineCache) draws under the debug view mode. It is part of the
+/// key of the pipeline, so that each mode gets pipelines of its own, while the renderers it does not apply to share
+/// the ones of the materials.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DebugViewVariant {
+    /// The shader of the material, as without a debug view.
+    Material,
+    /// The shader of the material, its triangles rasterized as lines.
+    PolygonLine,
+    /// The vertex stage of the material followed by the overdraw fragment stage, added up without depth test.
+    Overdraw,
+    /// The built-in shader of the mode in place of the shader of the material.
+    Builtin(DebugViewMode),
+}
+
+/// The variant drawing vertices of the topology under the mode. `builtin` tells whether the built-in shader can
+/// stand in for the shader of the material, see [`DebugViewInputs`], and `polygon_line` whether the device
+/// rasterizes polygons as lines.
+pub fn debug_view_variant(
+    mode: DebugViewMode,
+    topology: PrimitiveTopology,
+    builtin: bool,
+    polygon_line: bool,
+) -> DebugViewVariant {
+    let is_triangles = matches!(
+        topology,
+        PrimitiveTopology::TriangleList | PrimitiveTopology::TriangleStrip
+    );
+
+    match mode {
+        DebugViewMode::Wireframe if is_triangles && polygon_line => DebugViewVariant::PolygonLine,
+        // The fallback tells the corners apart by their index, which only lists keep in order.
+        DebugViewMode::Wireframe if topology == PrimitiveTopology::TriangleList && builtin => {
+            DebugViewVariant::Builtin(mode)
+        }
+        DebugViewMode::Normals | DebugViewMode::Depth if builtin => DebugViewVariant::Builtin(mode),
+        DebugViewMode::Overdraw => DebugViewVariant::Overdraw,
+        _ => DebugViewVariant::Material,
+    }
+}
+
+/// Where the built-in shader of the debug view modes finds its inputs, at its own locations.
+const BUILTIN_LOCATIONS: [(SemanticShaderInputKey, u32); 6] = [
+    (semantic_inputs::KEY_POSITION, 0),
+    (semantic_inputs::KEY_NORMAL, 1),
+    (semantic_inputs::KEY_TRANSFORM_ROW_0, 2),
+    (semantic_inputs::KEY_TRANSFORM_ROW_1, 3),
+    (semantic_inputs::KEY_TRANSFORM_ROW_2, 4),
+    (semantic_inputs::KEY_TRANSFORM_ROW_3, 5),
+];
+
+/// Inputs of a shader the built-in shader of the debug view modes reads in its place: the position and the normal
+/// of the vertices, the transform of the instances and the camera transform.
+///
+/// It places the vertices by the transforms alone, so it does not stand in for shaders bound to anything else that
+/// may move them, e.g. bones, terrain heights or waves. Shaders of quads sized per instance, such as the ones of
+/// sprites, read no normal and are left out as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugViewInputs {
+    /// Group and binding of the camera transform.
+    pub camera_transform: (u32, u32),
+    /// Locations of the inputs in the shader, paired with the ones of the built-in shader.
+    pub locations: Vec<(u32, u32)>,
+}
+
+impl DebugViewInputs {
+    /// The inputs of the shader, or `None` if the built-in shader cannot stand in for it.
+    pub fn of_shader(shader: &ReflectedShader) -> Option<Self> {
+        let mut camera_transform = None;
+
+        for binding in &shader.bindings {
+            match binding.semantic_binding {
+                Some(semantic_bindings::KEY_CAMERA_TRANSFORM) => {
+                    camera_transform = Some((binding.group, binding.binding));
+                }
+                Some(semantic_bindings::KEY_SCREEN_SIZE) | Some(semantic_bindings::KEY_LIGHTS) => {}
+                Some(_) => return None,
+                None => {}
+            }
+        }
+
+        let inputs = Vec::from_iter(
+            shader
+                .per_vertex_input
+                .elements
+                .iter()
+                .chain(&shader.per_instance_input.elements),
+        );
+        let locations = BUILTIN_LOCATIONS
+            .iter()
+            .map(|&(key, location)| {
+                inputs
+                    .iter()
+                    .find(|input| input.semantic_input == Some(key))
+                    .map(|input| (input.attribute.shader_location, location))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self {
+            camera_transform: camera_transform?,
+            locations,
+        })
+    }
+
+    /// Moves the attributes of the buffer layouts of the shader to the locations of the built-in shader, dropping the
+    /// ones it does not read. The buffers keep their slots and strides, so the ones bound for the shader fit.
+    pub fn buffer_layouts(&self, buffer_layouts: &[BufferLayout]) -> Vec<BufferLayout> {
+        Vec::from_iter(buffer_layouts.iter().map(|layout| BufferLayout {
+            array_stride: layout.array_stride,
+            step_mode: layout.step_mode,
+            attributes: Vec::from_iter(layout.attributes.iter().filter_map(|attribute| {
+                let (_, location) = self
+                    .locations
+                    .iter()
+                    .find(|(location, _)| *location == attribute.shader_location)?;
+
+                Some(VertexAttribute {
+                    shader_location: *location,
+                    ..*attribute
+                })
+            })),
+        }))
+    }
+}
+
+/// Modules of the shaders of the debug view modes, created as pipelines first need them.
+#[derive(Default)]
+pub struct DebugViewShaders {
+    /// Built-in shaders by the group and binding of their camera transform.
+    builtin: HashMap<(u32, u32), Arc<ShaderModule>>,
+    overdraw: Option<Arc<ShaderModule>>,
+}
+
+impl DebugViewShaders {
+    /// Creates the pipeline of the key, of the [`Overdraw`](DebugViewVariant::Overdraw) or
+    /// [`Builtin`](DebugViewVariant::Builtin) variant. Returns `None` for the other ones, which the shader of the
+    /// material draws.
+    pub fn create_pipeline(
+        &mut self,
+        device: &Device,
+        key: &PipelineKey,
+    ) -> Option<RenderPipeline> {
+        match key.variant {
+            DebugViewVariant::Material | DebugViewVariant::PolygonLine => None,
+            DebugViewVariant::Overdraw => {
+                let module = self.overdraw.get_or_insert_with(|| {
+                    Arc::new(create_module(
+                        device,
+                        "debug view overdraw shader",
+                        include_str!("./built_in_shaders/debug_view_overdraw.wgsl").into(),
+                    ))
+                });
+
+                Some(create_overdraw_pipeline(device, key, module))
+            }
+            DebugViewVariant::Builtin(mode) => {
+                let inputs = DebugViewInputs::of_shader(&key.shader.reflected_shader)?;
+                let module = self
+                    .builtin
+                    .entry(inputs.camera_transform)
+                    .or_insert_with(|| {
+                        let (group, binding) = inputs.camera_transform;
+                        let source = format!(
+                            "@group({}) @binding({}) var<uniform> camera_transform: mat4x4<f32>;\n{}",
+                            group,
+                            binding,
+                            include_str!("./built_in_shaders/debug_view.wgsl")
+                        );
+                        Arc::new(create_module(device, "debug view shader", source.into()))
+                    });
+
+                Some(create_builtin_pipeline(device, key, &inputs, mode, module))
+            }
+        }
+    }
+}
+
+fn create_module(device: &Device, label: &str, source: Cow<str>) -> ShaderModule {
+    device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(label),
+        source: ShaderSource::Wgsl(source),
+    })
+}
+
+/// Both kinds of pipelines keep the layout of the material, so that the bind groups set for it fit.
+fn create_overdraw_pipeline(
+    device: &Device,
+    key: &PipelineKey,
+    module: &ShaderModule,
+) -> RenderPipeline {
+    let buffers = Vec::from_iter(
+        key.buffer_layouts
+            .iter()
+            .map(|buffer| buffer.vertex_buffer_layout()),
+    );
+    // Hidden surfaces count as much as visible ones.
+    let depth_stencil = key.depth_stencil.clone().map(|mut depth_stencil| {
+        depth_stencil.depth_write_enabled = false;
+        depth_stencil.depth_compare = CompareFunction::Always;
+        depth_stencil.stencil = Default::default();
+        depth_stencil
+    });
+    let additive = BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::One,
+        operation: BlendOperation::Add,
+    };
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("debug view overdraw pipeline"),
+        layout: Some(key.layout.as_ref()),
+        vertex: VertexState {
+            module: &key.shader.shader_module,
+            entry_point: &key.shader.reflected_shader.vertex_entry_point_name,
+            buffers: &buffers,
+        },
+        primitive: key.primitive,
+        depth_stencil,
+        multisample: Default::default(),
+        fragment: Some(FragmentState {
+            module,
+            entry_point: "fs_overdraw",
+            targets: &[Some(ColorTargetState {
+                format: key.color_format,
+                blend: Some(BlendState {
+                    color: additive,
+                    alpha: additive,
+                }),
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        multiview: None,
+    })
+}
+
+fn create_builtin_pipeline(
+    device: &Device,
+    key: &PipelineKey,
+    inputs: &DebugViewInputs,
+    mode: DebugViewMode,
+    module: &ShaderModule,
+) -> RenderPipeline {
+    let buffer_layouts = inputs.buffer_layouts(&key.buffer_layouts);
+    let buffers = Vec::from_iter(
+        buffer_layouts
+            .iter()
+            .map(|buffer| buffer.vertex_buffer_layout()),
+    );
+    let (entry_point, blend) = match mode {
+        DebugViewMode::Wireframe => ("fs_wireframe", Some(BlendState::ALPHA_BLENDING)),
+        DebugViewMode::Depth => ("fs_depth", None),
+        _ => ("fs_normals", None),
+    };
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("debug view pipeline"),
+        layout: Some(key.layout.as_ref()),
+        vertex: VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &buffers,
+        },
+        primitive: key.primitive,
+        depth_stencil: key.depth_stencil.clone(),
+        multisample: Default::default(),
+        fragment: Some(FragmentState {
+            module,
+            entry_point,
+            targets: &[Some(ColorTargetState {
+                format: key.color_format,
+                blend,
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        multiview: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::{
+        ReflectedShaderBindingElement, ReflectedShaderBindingElementKind, ReflectedShaderInput,
+        ReflectedShaderInputElement,
+    };
+    use std::num::NonZeroU64;
+    use wgpu::{VertexFormat, VertexStepMode};
+
+    fn input(
+        key: SemanticShaderInputKey,
+        format: VertexFormat,
+        location: u32,
+    ) -> ReflectedShaderInputElement {
+        ReflectedShaderInputElement {
+            semantic_input: Some(key),
+            name: String::new(),
+            attribute: VertexAttribute {
+                format,
+                offset: 0,
+                shader_location: location,
+            },
+        }
+    }
+
+    /// A shader laid out as the built-in lit one, with the given vertex inputs.
+    fn lit_shader(per_vertex: Vec<ReflectedShaderInputElement>) -> ReflectedShader {
+        ReflectedShader {
+            vertex_entry_point_name: "vs_main".to_owned(),
+            fragment_entry_point_name: "fs_main".to_owned(),
+            bindings: vec![ReflectedShaderBindingElement {
+                semantic_binding: Some(semantic_bindings::KEY_CAMERA_TRANSFORM),
+                name: "camera_transform".to_owned(),
+                group: 0,
+                binding: 0,
+                kind: ReflectedShaderBindingElementKind::Buffer {
+                    size: NonZeroU64::new(64).unwrap(),
+                },
+                members: Vec::new(),
+            }],
+            per_instance_input: ReflectedShaderInput {
+                step_mode: VertexStepMode::Instance,
+                stride: 80,
+                elements: vec![
+                    input(
+                        semantic_inputs::KEY_TRANSFORM_ROW_0,
+                        VertexFormat::Float32x4,
+                        0,
+                    ),
+                    input(
+                        semantic_inputs::KEY_TRANSFORM_ROW_1,
+                        VertexFormat::Float32x4,
+                        1,
+                    ),
+                    input(
+                        semantic_inputs::KEY_TRANSFORM_ROW_2,
+                        VertexFormat::Float32x4,
+                        2,
+                    ),
+                    input(
+                        semantic_inputs::KEY_TRANSFORM_ROW_3,
+                        VertexFormat::Float32x4,
+                        3,
+                    ),
+                ],
+            },
+            per_vertex_input: ReflectedShaderInput {
+                step_mode: VertexStepMode::Vertex,
+                stride: 0,
+                elements: per_vertex,
+            },
+            outputs: Vec::new(),
+        }
+    }
+
+    fn position_and_normal() -> Vec<ReflectedShaderInputElement> {
+        vec![
+            input(semantic_inputs::KEY_POSITION, VertexFormat::Float32x3, 7),
+            input(semantic_inputs::KEY_NORMAL, VertexFormat::Float32x3, 8),
+        ]
+    }
+
+    #[test]
+    fn check_modes_fall_back_to_the_material() {
+        let list = PrimitiveTopology::TriangleList;
+        let strip = PrimitiveTopology::TriangleStrip;
+        let lines = PrimitiveTopology::LineList;
+
+        for topology in [list, strip, lines] {
+            assert_eq!(
+                debug_view_variant(DebugViewMode::None, topology, true, true),
+                DebugViewVariant::Material
+            );
+            assert_eq!(
+                debug_view_variant(DebugViewMode::Overdraw, topology, false, false),
+                DebugViewVariant::Overdraw
+            );
+        }
+
+        assert_eq!(
+            debug_view_variant(DebugViewMode::Wireframe, strip, false, true),
+            DebugViewVariant::PolygonLine
+        );
+        assert_eq!(
+            debug_view_variant(DebugViewMode::Wireframe, list, true, false),
+            DebugViewVariant::Builtin(DebugViewMode::Wireframe)
+        );
+        // Neither strips nor lines have corners the fallback can tell apart.
+        assert_eq!(
+            debug_view_variant(DebugViewMode::Wireframe, strip, true, false),
+            DebugViewVariant::Material
+        );
+        assert_eq!(
+            debug_view_variant(DebugViewMode::Wireframe, lines, true, true),
+            DebugViewVariant::Material
+        );
+        assert_eq!(
+            debug_view_variant(DebugViewMode::Depth, lines, true, false),
+            DebugViewVariant::Builtin(DebugViewMode::Depth)
+        );
+        assert_eq!(
+            debug_view_variant(DebugViewMode::Normals, list, false, true),
+            DebugViewVariant::Material
+        );
+    }
+
+    #[test]
+    fn check_builtin_shader_stands_in_for_transformed_meshes_only() {
+        let inputs = DebugViewInputs::of_shader(&lit_shader(position_and_normal())).unwrap();
+        assert_eq!(inputs.camera_transform, (0, 0));
+        assert_eq!(
+            inputs.locations,
+            vec![(7, 0), (8, 1), (0, 2), (1, 3), (2, 4), (3, 5)]
+        );
+
+        // Sprites read no normal.
+        let sprite = lit_shader(vec![input(
+            semantic_inputs::KEY_POSITION,
+            VertexFormat::Float32x3,
+            9,
+        )]);
+        assert_eq!(DebugViewInputs::of_shader(&sprite), None);
+
+        // Bones move the vertices.
+        let mut skinned = lit_shader(position_and_normal());
+        skinned.bindings.push(ReflectedShaderBindingElement {
+            semantic_binding: Some(semantic_bindings::KEY_BONE_MATRICES),
+            group: 2,
+            ..skinned.bindings[0].clone()
+        });
+        assert_eq!(DebugViewInputs::of_shader(&skinned), None);
+    }
+
+    #[test]
+    fn check_buffer_layouts_keep_their_slots() {
+        let inputs = DebugViewInputs::of_shader(&lit_shader(position_and_normal())).unwrap();
+        let attribute = |format, offset, shader_location| VertexAttribute {
+            format,
+            offset,
+            shader_location,
+        };
+        let buffer_layouts = [
+            BufferLayout {
+                array_stride: 32,
+                step_mode: VertexStepMode::Vertex,
+                attributes: vec![
+                    attribute(VertexFormat::Float32x3, 0, 7),
+                    attribute(VertexFormat::Float32x3, 12, 8),
+                ],
+            },
+            // UVs the built-in shader does not read.
+            BufferLayout {
+                array_stride: 8,
+                step_mode: VertexStepMode::Vertex,
+                attributes: vec![attribute(VertexFormat::Float32x2, 0, 9)],
+            },
+            BufferLayout {
+                array_stride: 80,
+                step_mode: VertexStepMode::Instance,
+                attributes: vec![
+                    attribute(VertexFormat::Float32x4, 0, 0),
+                    attribute(VertexFormat::Float32x4, 16, 1),
+                    attribute(VertexFormat::Float32x4, 32, 2),
+                    attribute(VertexFormat::Float32x4, 48, 3),
+                    attribute(VertexFormat::Float32x4, 64, 4),
+                ],
+            },
+        ];
+        let remapped = inputs.buffer_layouts(&buffer_layouts);
+
+        assert_eq!(remapped.len(), 3);
+        assert_eq!(
+            remapped[0].attributes,
+            vec![
+                attribute(VertexFormat::Float32x3, 0, 0),
+                attribute(VertexFormat::Float32x3, 12, 1),
+            ]
+        );
+        assert!(remapped[1].attributes.is_empty());
+        assert_eq!(remapped[1].array_stride, 8);
+        assert_eq!(remapped[2].array_stride, 80);
+        assert_eq!(
+            Vec::from_iter(remapped[2].attributes.iter().map(|a| a.shader_location)),
+            vec![2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn check_mode_names_round_trip() {
+        for mode in DebugViewMode::ALL {
+            assert_eq!(DebugViewMode::from_name(mode.name()), Some(mode));
+        }
+
+        assert_eq!(
+            DebugViewMode::from_name("Wireframe"),
+            Some(DebugViewMode::Wireframe)
+        );
+        assert_eq!(DebugViewMode::from_name("albedo"), None);
+    }
+}
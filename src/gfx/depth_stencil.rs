@@ -1,15 +1,18 @@
 use super::GfxContextHandle;
+use serde::{Deserialize, Serialize};
 use wgpu::{
-    Device, Extent3d, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
-    TextureView,
+    Device, Extent3d, Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureView, TextureViewDescriptor,
 };
 use winit::dpi::PhysicalSize;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DepthStencilMode {
     None,
     DepthOnly,
     DepthStencil,
+    /// A stencil buffer without depth testing. The attachment still has a depth aspect, which is left unused.
+    StencilOnly,
 }
 
 impl DepthStencilMode {
@@ -18,14 +21,25 @@ impl DepthStencilMode {
             Self::None => "",
             Self::DepthOnly => "depth texture",
             Self::DepthStencil => "depth and stencil texture",
+            Self::StencilOnly => "stencil texture",
         }
     }
 
+    pub fn has_depth(self) -> bool {
+        matches!(self, Self::DepthOnly | Self::DepthStencil)
+    }
+
+    pub fn has_stencil(self) -> bool {
+        matches!(self, Self::DepthStencil | Self::StencilOnly)
+    }
+
     pub fn as_texture_format(self) -> Option<TextureFormat> {
         match self {
             DepthStencilMode::None => None,
             DepthStencilMode::DepthOnly => Some(TextureFormat::Depth32Float),
-            DepthStencilMode::DepthStencil => Some(TextureFormat::Depth24PlusStencil8),
+            DepthStencilMode::DepthStencil | DepthStencilMode::StencilOnly => {
+                Some(TextureFormat::Depth24PlusStencil8)
+            }
         }
     }
 }
@@ -35,6 +49,7 @@ pub struct DepthStencil {
     mode: DepthStencilMode,
     texture: Option<Texture>,
     texture_view: Option<TextureView>,
+    depth_view: Option<TextureView>,
 }
 
 impl DepthStencil {
@@ -47,12 +62,14 @@ impl DepthStencil {
             return None;
         }
 
-        let (texture, texture_view) = create_texture_and_view(&gfx_ctx.device, mode, size);
+        let (texture, texture_view, depth_view) =
+            create_texture_and_views(&gfx_ctx.device, mode, size);
         Some(Self {
             gfx_ctx,
             mode,
             texture,
             texture_view,
+            depth_view,
         })
     }
 
@@ -68,30 +85,40 @@ impl DepthStencil {
         self.texture_view.as_ref()
     }
 
+    /// View of the depth aspect alone, for passes sampling the depth after the camera passes.
+    pub fn depth_view(&self) -> Option<&TextureView> {
+        self.depth_view.as_ref()
+    }
+
     pub fn resize(&mut self, size: PhysicalSize<u32>) {
         if size.width == 0 || size.height == 0 {
             return;
         }
 
-        let (texture, texture_view) =
-            create_texture_and_view(&self.gfx_ctx.device, self.mode, size);
+        let (texture, texture_view, depth_view) =
+            create_texture_and_views(&self.gfx_ctx.device, self.mode, size);
         self.texture = texture;
         self.texture_view = texture_view;
+        self.depth_view = depth_view;
     }
 }
 
-fn create_texture_and_view(
+fn create_texture_and_views(
     device: &Device,
     mode: DepthStencilMode,
     size: PhysicalSize<u32>,
-) -> (Option<Texture>, Option<TextureView>) {
+) -> (Option<Texture>, Option<TextureView>, Option<TextureView>) {
     match mode.as_texture_format() {
         Some(format) => {
             let texture = create_texture(device, mode, size, format);
             let texture_view = texture.create_view(&Default::default());
-            (Some(texture), Some(texture_view))
+            let depth_view = texture.create_view(&TextureViewDescriptor {
+                aspect: TextureAspect::DepthOnly,
+                ..Default::default()
+            });
+            (Some(texture), Some(texture_view), Some(depth_view))
         }
-        None => (None, None),
+        None => (None, None, None),
     }
 }
 
@@ -112,7 +139,7 @@ fn create_texture(
         sample_count: 1,
         dimension: TextureDimension::D2,
         format,
-        usage: TextureUsages::RENDER_ATTACHMENT,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
         view_formats: &[format],
     })
 }
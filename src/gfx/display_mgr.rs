@@ -0,0 +1,144 @@
+use std::time::{Duration, Instant};
+use winit::dpi::{LogicalSize, PhysicalSize};
+
+/// Display mode of the window, applied atomically at the next frame boundary.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplaySettings {
+    /// Logical size of the window. Ignored if `fullscreen` is `true`.
+    pub size: LogicalSize<u32>,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// Scale of the internal render resolution relative to the surface size.
+    pub render_scale: f32,
+}
+
+/// Tracks the current display settings and the pending change, if any.
+#[derive(Debug)]
+pub struct DisplayManager {
+    current: DisplaySettings,
+    pending: Option<DisplaySettings>,
+    revert: Option<(DisplaySettings, Instant)>,
+    expected_size: Option<PhysicalSize<u32>>,
+}
+
+impl DisplayManager {
+    pub fn new(current: DisplaySettings) -> Self {
+        Self {
+            current,
+            pending: None,
+            revert: None,
+            expected_size: None,
+        }
+    }
+
+    pub fn current(&self) -> &DisplaySettings {
+        &self.current
+    }
+
+    pub fn pending(&self) -> Option<&DisplaySettings> {
+        self.pending.as_ref()
+    }
+
+    /// Returns `true` if the last change is awaiting confirmation.
+    pub fn is_awaiting_confirmation(&self) -> bool {
+        self.revert.is_some()
+    }
+
+    /// Requests a display change. It is applied at the start of the next frame; that frame is not rendered.
+    /// Requesting again before the change is applied overrides the previous request.
+    pub fn apply(&mut self, settings: DisplaySettings) {
+        self.pending = Some(settings);
+    }
+
+    /// Requests a change of the vsync alone, on top of the pending change if any. Does nothing if the vsync is
+    /// already so.
+    pub fn request_vsync(&mut self, vsync: bool) {
+        let settings = self.pending.unwrap_or(self.current);
+
+        if settings.vsync != vsync {
+            self.pending = Some(DisplaySettings { vsync, ..settings });
+        }
+    }
+
+    /// Reverts to the settings that were active before the last change, unless [`confirm`](Self::confirm)
+    /// is called within the given seconds. Call this right after [`apply`](Self::apply).
+    pub fn revert_after(&mut self, seconds: f64) {
+        self.revert = Some((
+            self.current,
+            Instant::now() + Duration::from_secs_f64(seconds.max(0.0)),
+        ));
+    }
+
+    /// Keeps the current settings, cancelling the pending revert.
+    pub fn confirm(&mut self) {
+        self.revert = None;
+    }
+
+    /// Returns the settings to be applied in this frame, if any. A due revert is turned into a pending change.
+    pub(crate) fn take_pending(&mut self, now: Instant) -> Option<DisplaySettings> {
+        if let Some((settings, deadline)) = self.revert {
+            if deadline <= now {
+                self.revert = None;
+                self.pending = Some(settings);
+            }
+        }
+
+        self.pending.take()
+    }
+
+    pub(crate) fn mark_applied(
+        &mut self,
+        settings: DisplaySettings,
+        physical_size: PhysicalSize<u32>,
+    ) {
+        self.current = settings;
+        self.expected_size = Some(physical_size);
+    }
+
+    /// Returns `true` if the resize is the echo of a change that has already been applied.
+    /// Such resize events must not go through the generic resize path again.
+    pub(crate) fn consume_expected_size(&mut self, size: PhysicalSize<u32>) -> bool {
+        match self.expected_size {
+            Some(expected) if expected == size => {
+                self.expected_size = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> DisplaySettings {
+        DisplaySettings {
+            size: LogicalSize::new(800, 600),
+            fullscreen: false,
+            vsync: true,
+            render_scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn check_vsync_request_keeps_the_pending_change() {
+        let mut display_mgr = DisplayManager::new(settings());
+        display_mgr.request_vsync(true);
+        assert_eq!(display_mgr.pending(), None);
+
+        display_mgr.apply(DisplaySettings {
+            fullscreen: true,
+            ..settings()
+        });
+        display_mgr.request_vsync(false);
+        assert_eq!(
+            display_mgr.take_pending(Instant::now()),
+            Some(DisplaySettings {
+                fullscreen: true,
+                vsync: false,
+                ..settings()
+            })
+        );
+    }
+}
@@ -0,0 +1,1100 @@
+use super::{
+    semantic_bindings, BindGroupEntryResource, CachedPipeline, CameraClearMode,
+    GenericBufferAllocation, GfxContextHandle, RenderingCommand, ShaderHandle, ShaderManager,
+    VertexBuffer,
+};
+use crate::math::Mat4;
+use asset::AssetKey;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    mem::size_of,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver},
+        Arc, Weak,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use wgpu::{
+    BindGroupLayoutEntry, Buffer, BufferAddress, BufferAsyncError, BufferDescriptor, BufferSize,
+    BufferUsages, ColorTargetState, CommandEncoder, DepthStencilState, Extent3d, ImageCopyBuffer,
+    ImageCopyTexture, ImageDataLayout, IndexFormat, Maintain, MapMode, Origin3d, PrimitiveState,
+    Texture, TextureAspect, TextureFormat, TextureUsages, TextureView, VertexAttribute,
+    VertexFormat, VertexStepMode, COPY_BUFFER_ALIGNMENT, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+use zerocopy::AsBytes;
+
+/// Extension of the files written by [`FrameCapture::save`].
+pub const FRAME_CAPTURE_EXTENSION: &str = "encapture";
+
+/// Returns a path in the working directory named after the current time, e.g. `frame-1700000000.encapture`.
+pub fn timestamped_capture_path() -> PathBuf {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    PathBuf::from(format!("frame-{}.{}", seconds, FRAME_CAPTURE_EXTENSION))
+}
+
+#[derive(Error, Debug)]
+pub enum FrameCaptureError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed capture description: {0}")]
+    Description(#[from] serde_json::Error),
+    #[error("not a frame capture")]
+    InvalidMagic,
+    #[error("unsupported frame capture version {0}")]
+    UnsupportedVersion(u32),
+    #[error("the capture refers to {kind} #{index}, which does not exist")]
+    InvalidReference { kind: &'static str, index: usize },
+    #[error("failed to read the frame back")]
+    ReadBackFailed,
+}
+
+/// Command stream of one frame, recorded above wgpu so that it can be replayed without the game's content.
+///
+/// Buffers are embedded by value; textures are only identified, by asset key where known.
+/// Binary data lives in [`buffers`](Self::buffers) and is referred to by index everywhere else.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FrameCapture {
+    pub width: u32,
+    pub height: u32,
+    /// Format the surface passes are drawn in. Under post-processing, it is the one of the intermediate target,
+    /// which replays cannot read back.
+    pub surface_format: TextureFormat,
+    /// Buffer holding the screen size uniform.
+    pub screen_size: usize,
+    pub shaders: Vec<CapturedShader>,
+    pub pipelines: Vec<CapturedPipeline>,
+    pub textures: Vec<CapturedTexture>,
+    pub passes: Vec<CapturedPass>,
+    /// Buffer holding the surface after the captured passes as tightly packed RGBA8 pixels, if the surface could be copied from.
+    pub reference_image: Option<usize>,
+    #[serde(skip)]
+    pub buffers: Vec<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CapturedShader {
+    pub source: String,
+}
+
+/// Everything the pipeline key of a [`CachedPipeline`] describes, in a serializable form.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CapturedPipeline {
+    pub shader: usize,
+    pub vertex_entry_point: String,
+    pub fragment_entry_point: String,
+    /// Layout entries of each bind group, indexed by group.
+    pub bind_group_layouts: Vec<Vec<BindGroupLayoutEntry>>,
+    pub buffer_layouts: Vec<CapturedBufferLayout>,
+    pub primitive: PrimitiveState,
+    pub depth_stencil: Option<DepthStencilState>,
+    pub targets: Vec<Option<ColorTargetState>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CapturedBufferLayout {
+    pub array_stride: BufferAddress,
+    pub step_mode: VertexStepMode,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CapturedTexture {
+    /// A texture labelled through [`FrameCaptureRecorder::label_texture`].
+    Asset {
+        key: AssetKey,
+        width: u32,
+        height: u32,
+    },
+    /// The color target of an earlier pass of the frame.
+    RenderTarget { pass: usize },
+    /// A texture the engine knows nothing about, e.g. a glyph atlas or a previous frame's render target.
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CapturedPass {
+    pub name: String,
+    pub target: CapturedPassTarget,
+    pub clear: CapturedClear,
+    /// Buffer holding the view-projection matrix of the pass.
+    pub camera_transform: usize,
+    pub commands: Vec<CapturedCommand>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CapturedPassTarget {
+    Surface,
+    Texture {
+        texture: usize,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+    },
+}
+
+/// Values the targets are cleared with; `None` loads the previous contents.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CapturedClear {
+    pub color: Option<[f64; 4]>,
+    pub depth: Option<f32>,
+    pub stencil: Option<u32>,
+}
+
+impl From<&CameraClearMode> for CapturedClear {
+    fn from(clear_mode: &CameraClearMode) -> Self {
+        match clear_mode {
+            CameraClearMode::Keep => Self {
+                color: None,
+                depth: None,
+                stencil: None,
+            },
+            CameraClearMode::All {
+                color,
+                depth,
+                stencil,
+            } => Self {
+                color: Some([
+                    color.r as f64,
+                    color.g as f64,
+                    color.b as f64,
+                    color.a as f64,
+                ]),
+                depth: Some(*depth),
+                stencil: Some(*stencil),
+            },
+            CameraClearMode::DepthOnly { depth, stencil } => Self {
+                color: None,
+                depth: Some(*depth),
+                stencil: Some(*stencil),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CapturedCommand {
+    pub pipeline: usize,
+    pub vertex_count: u32,
+    pub instance_count: u32,
+    pub bindings: Vec<CapturedBinding>,
+    pub vertex_buffers: Vec<CapturedVertexBuffer>,
+    /// Indices drawn by `draw_indexed` instead of `vertex_count` vertices, if the vertices are indexed.
+    pub index_buffer: Option<CapturedIndexBuffer>,
+    /// Buffer holding the arguments of `draw_indirect`, or `draw_indexed_indirect` if the vertices are indexed,
+    /// if the instance count is decided on the GPU.
+    pub indirect_buffer: Option<usize>,
+    /// Per-instance property values of the material, for inspection; they are already encoded in the instance buffer.
+    pub instance_properties: Vec<CapturedInstanceProperty>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapturedIndexBuffer {
+    pub buffer: usize,
+    pub format: IndexFormat,
+    pub index_count: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CapturedBinding {
+    pub group: u32,
+    pub binding: u32,
+    pub resource: CapturedResource,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CapturedResource {
+    CameraTransform,
+    ScreenSize,
+    Buffer {
+        buffer: usize,
+    },
+    Texture {
+        texture: usize,
+    },
+    TextureArray {
+        textures: Vec<usize>,
+    },
+    Sampler,
+    /// Bound by the renderer rather than the material, so its contents are unknown.
+    Missing,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapturedVertexBuffer {
+    pub slot: u32,
+    pub buffer: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CapturedInstanceProperty {
+    pub name: String,
+    pub format: VertexFormat,
+    pub value: Option<Vec<u8>>,
+}
+
+impl FrameCapture {
+    const MAGIC: &'static [u8; 8] = b"ENCAPTUR";
+    const VERSION: u32 = 2;
+
+    pub fn command_count(&self) -> usize {
+        self.passes.iter().map(|pass| pass.commands.len()).sum()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), FrameCaptureError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, FrameCaptureError> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the magic and the version, the description as length-prefixed JSON, then the length-prefixed buffers.
+    pub fn write(&self, writer: &mut impl Write) -> Result<(), FrameCaptureError> {
+        let description = serde_json::to_vec(self)?;
+
+        writer.write_all(Self::MAGIC)?;
+        writer.write_all(&Self::VERSION.to_le_bytes())?;
+        writer.write_all(&(description.len() as u64).to_le_bytes())?;
+        writer.write_all(&description)?;
+        writer.write_all(&(self.buffers.len() as u64).to_le_bytes())?;
+
+        for buffer in &self.buffers {
+            writer.write_all(&(buffer.len() as u64).to_le_bytes())?;
+            writer.write_all(buffer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a capture written by [`write`](Self::write), checking that every reference in it is valid.
+    pub fn read(reader: &mut impl Read) -> Result<Self, FrameCaptureError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != Self::MAGIC {
+            return Err(FrameCaptureError::InvalidMagic);
+        }
+
+        let mut version = [0u8; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+
+        if version != Self::VERSION {
+            return Err(FrameCaptureError::UnsupportedVersion(version));
+        }
+
+        let description = read_chunk(reader)?;
+        let mut capture = serde_json::from_slice::<Self>(&description)?;
+        let buffer_count = read_u64(reader)?;
+
+        for _ in 0..buffer_count {
+            capture.buffers.push(read_chunk(reader)?);
+        }
+
+        capture.validate()?;
+        Ok(capture)
+    }
+
+    /// Returns the surface as the captured frame left it, if the surface could be copied from.
+    pub fn reference_image(&self) -> Option<image::RgbaImage> {
+        let pixels = self.buffers.get(self.reference_image?)?.clone();
+        image::RgbaImage::from_raw(self.width, self.height, pixels)
+    }
+
+    fn validate(&self) -> Result<(), FrameCaptureError> {
+        let check = |kind: &'static str, index: usize, len: usize| {
+            if index < len {
+                Ok(())
+            } else {
+                Err(FrameCaptureError::InvalidReference { kind, index })
+            }
+        };
+        let buffers = self.buffers.len();
+
+        check("buffer", self.screen_size, buffers)?;
+
+        if let Some(reference_image) = self.reference_image {
+            check("buffer", reference_image, buffers)?;
+        }
+
+        for pipeline in &self.pipelines {
+            check("shader", pipeline.shader, self.shaders.len())?;
+        }
+
+        for texture in &self.textures {
+            if let CapturedTexture::RenderTarget { pass } = texture {
+                check("pass", *pass, self.passes.len())?;
+            }
+        }
+
+        for pass in &self.passes {
+            check("buffer", pass.camera_transform, buffers)?;
+
+            if let CapturedPassTarget::Texture { texture, .. } = pass.target {
+                check("texture", texture, self.textures.len())?;
+            }
+
+            for command in &pass.commands {
+                check("pipeline", command.pipeline, self.pipelines.len())?;
+
+                for vertex_buffer in &command.vertex_buffers {
+                    check("buffer", vertex_buffer.buffer, buffers)?;
+                }
+
+                if let Some(index_buffer) = command.index_buffer {
+                    check("buffer", index_buffer.buffer, buffers)?;
+                }
+
+                if let Some(indirect_buffer) = command.indirect_buffer {
+                    check("buffer", indirect_buffer, buffers)?;
+                }
+
+                for binding in &command.bindings {
+                    match &binding.resource {
+                        CapturedResource::Buffer { buffer } => check("buffer", *buffer, buffers)?,
+                        CapturedResource::Texture { texture } => {
+                            check("texture", *texture, self.textures.len())?
+                        }
+                        CapturedResource::TextureArray { textures } => {
+                            for texture in textures {
+                                check("texture", *texture, self.textures.len())?;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, FrameCaptureError> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_chunk(reader: &mut impl Read) -> Result<Vec<u8>, FrameCaptureError> {
+    let len = read_u64(reader)?;
+    let mut chunk = Vec::new();
+
+    // Read through `take`, so that a corrupted length fails at the end of the file rather than allocating it upfront.
+    if reader.take(len).read_to_end(&mut chunk)? as u64 != len {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+
+    Ok(chunk)
+}
+
+/// Target of a pass passed to [`FrameCaptureRecorder::record_pass`].
+pub enum CapturePassTarget<'a> {
+    Surface,
+    Texture {
+        view: &'a Arc<TextureView>,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+    },
+}
+
+struct TextureLabel {
+    view: Weak<TextureView>,
+    key: AssetKey,
+    width: u32,
+    height: u32,
+}
+
+/// Part of a GPU buffer to be copied into the capture, widened to the copy alignment.
+struct BufferCopy {
+    buffer: Arc<Buffer>,
+    offset: BufferAddress,
+    size: BufferAddress,
+    /// Range of the requested bytes within the copy.
+    skip: usize,
+    len: usize,
+    index: usize,
+}
+
+struct ActiveCapture {
+    path: PathBuf,
+    capture: FrameCapture,
+    pipelines: HashMap<CachedPipeline, usize>,
+    shaders: HashMap<usize, usize>,
+    textures: HashMap<usize, usize>,
+    buffers: HashMap<(usize, BufferAddress, usize), usize>,
+    copies: Vec<BufferCopy>,
+}
+
+impl ActiveCapture {
+    fn push_buffer(&mut self, contents: Vec<u8>) -> usize {
+        self.capture.buffers.push(contents);
+        self.capture.buffers.len() - 1
+    }
+
+    /// Schedules a copy of the buffer range. Buffers that cannot be copied from are captured as zeros.
+    fn buffer_index(&mut self, buffer: &Arc<Buffer>, offset: BufferAddress, len: usize) -> usize {
+        let id = (Arc::as_ptr(buffer) as usize, offset, len);
+
+        if let Some(&index) = self.buffers.get(&id) {
+            return index;
+        }
+
+        let index = self.push_buffer(vec![0; len]);
+        self.buffers.insert(id, index);
+
+        if !buffer.usage().contains(BufferUsages::COPY_SRC) {
+            return index;
+        }
+
+        let copy_offset = offset / COPY_BUFFER_ALIGNMENT * COPY_BUFFER_ALIGNMENT;
+        let copy_end = ((offset + len as BufferAddress + COPY_BUFFER_ALIGNMENT - 1)
+            / COPY_BUFFER_ALIGNMENT
+            * COPY_BUFFER_ALIGNMENT)
+            .min(buffer.size());
+        let skip = (offset - copy_offset) as usize;
+
+        if copy_end <= copy_offset {
+            return index;
+        }
+
+        self.copies.push(BufferCopy {
+            buffer: buffer.clone(),
+            offset: copy_offset,
+            size: copy_end - copy_offset,
+            skip,
+            len: len.min((copy_end - copy_offset) as usize - skip),
+            index,
+        });
+        index
+    }
+
+    fn allocation_index(&mut self, allocation: &GenericBufferAllocation<Buffer>) -> usize {
+        // Empty allocations are marked by the maximum size.
+        if allocation.size() == BufferSize::MAX {
+            return self.push_buffer(Vec::new());
+        }
+
+        self.buffer_index(
+            allocation.buffer(),
+            allocation.offset(),
+            allocation.size().get() as usize,
+        )
+    }
+
+    fn shader_index(&mut self, shader: &ShaderHandle) -> usize {
+        let id = shader.as_ptr() as usize;
+
+        if let Some(&index) = self.shaders.get(&id) {
+            return index;
+        }
+
+        self.capture.shaders.push(CapturedShader {
+            source: shader.source.clone(),
+        });
+        let index = self.capture.shaders.len() - 1;
+        self.shaders.insert(id, index);
+        index
+    }
+
+    fn pipeline_index(&mut self, pipeline: &CachedPipeline, shader_mgr: &ShaderManager) -> usize {
+        if let Some(&index) = self.pipelines.get(pipeline) {
+            return index;
+        }
+
+        let key = pipeline.key();
+        let group_count = key
+            .shader
+            .bind_group_layouts
+            .keys()
+            .max()
+            .map_or(0, |group| group + 1);
+        let captured = CapturedPipeline {
+            shader: self.shader_index(&key.shader),
+            vertex_entry_point: key.shader.reflected_shader.vertex_entry_point_name.clone(),
+            fragment_entry_point: key
+                .shader
+                .reflected_shader
+                .fragment_entry_point_name
+                .clone(),
+            bind_group_layouts: Vec::from_iter((0..group_count).map(|group| {
+                key.shader
+                    .bind_group_layouts
+                    .get(&group)
+                    .map_or_else(Vec::new, |layout| layout.key().entries.clone())
+            })),
+            buffer_layouts: Vec::from_iter(key.buffer_layouts.iter().map(|layout| {
+                CapturedBufferLayout {
+                    array_stride: layout.array_stride,
+                    step_mode: layout.step_mode,
+                    attributes: layout.attributes.clone(),
+                }
+            })),
+            primitive: key.primitive,
+            depth_stencil: key.depth_stencil.clone(),
+            targets: key.color_targets(shader_mgr),
+        };
+
+        self.capture.pipelines.push(captured);
+        let index = self.capture.pipelines.len() - 1;
+        self.pipelines.insert(pipeline.clone(), index);
+        index
+    }
+
+    fn texture_index(
+        &mut self,
+        view: &Arc<TextureView>,
+        labels: &HashMap<usize, TextureLabel>,
+    ) -> usize {
+        let id = Arc::as_ptr(view) as usize;
+
+        if let Some(&index) = self.textures.get(&id) {
+            return index;
+        }
+
+        let texture = match labels
+            .get(&id)
+            .filter(|label| label.view.strong_count() != 0)
+        {
+            Some(label) => CapturedTexture::Asset {
+                key: label.key.clone(),
+                width: label.width,
+                height: label.height,
+            },
+            None => CapturedTexture::Unknown,
+        };
+
+        self.capture.textures.push(texture);
+        let index = self.capture.textures.len() - 1;
+        self.textures.insert(id, index);
+        index
+    }
+
+    fn capture_resource(
+        &mut self,
+        resource: &BindGroupEntryResource,
+        labels: &HashMap<usize, TextureLabel>,
+    ) -> CapturedResource {
+        match resource {
+            BindGroupEntryResource::Buffer {
+                buffer,
+                offset,
+                size,
+            } => {
+                let len = size.map_or(buffer.size().saturating_sub(*offset), |size| size.get());
+                CapturedResource::Buffer {
+                    buffer: self.buffer_index(buffer, *offset, len as usize),
+                }
+            }
+            BindGroupEntryResource::Sampler { .. } => CapturedResource::Sampler,
+            BindGroupEntryResource::TextureView { texture_view } => CapturedResource::Texture {
+                texture: self.texture_index(texture_view, labels),
+            },
+            BindGroupEntryResource::TextureViewArray { texture_views } => {
+                CapturedResource::TextureArray {
+                    textures: Vec::from_iter(
+                        texture_views
+                            .iter()
+                            .map(|texture_view| self.texture_index(texture_view, labels)),
+                    ),
+                }
+            }
+        }
+    }
+
+    fn record_command(
+        &mut self,
+        command: &RenderingCommand,
+        shader_mgr: &ShaderManager,
+        labels: &HashMap<usize, TextureLabel>,
+    ) -> CapturedCommand {
+        let material = &command.material;
+        let reflected_shader = &material.shader.reflected_shader;
+        let mut bindings = Vec::new();
+        let mut material_groups = Vec::new();
+
+        // Bind groups of the material are set last when rendering, so they take precedence over the semantic ones.
+        for bind_group_holder in &material.bind_group_holders {
+            if bind_group_holder.bind_group.is_none() {
+                continue;
+            }
+
+            material_groups.push(bind_group_holder.group);
+
+            for entry in &bind_group_holder.entries {
+                bindings.push(CapturedBinding {
+                    group: bind_group_holder.group,
+                    binding: entry.binding,
+                    resource: match &entry.resource {
+                        Some(resource) => self.capture_resource(resource, labels),
+                        None => CapturedResource::Missing,
+                    },
+                });
+            }
+        }
+
+        for binding in &reflected_shader.bindings {
+            if material_groups.contains(&binding.group) {
+                continue;
+            }
+
+            let resource = match binding.semantic_binding {
+                Some(semantic_bindings::KEY_CAMERA_TRANSFORM) => CapturedResource::CameraTransform,
+                Some(semantic_bindings::KEY_SCREEN_SIZE) => CapturedResource::ScreenSize,
+                // Bind groups provided by the renderers are opaque.
+                _ => CapturedResource::Missing,
+            };
+            bindings.push(CapturedBinding {
+                group: binding.group,
+                binding: binding.binding,
+                resource,
+            });
+        }
+
+        let mut vertex_buffers = Vec::<CapturedVertexBuffer>::new();
+
+        for input in &reflected_shader.per_vertex_input.elements {
+            let key = if let Some(key) = input.semantic_input {
+                key
+            } else {
+                continue;
+            };
+
+            if let Some(VertexBuffer { slot, buffer }) =
+                command.vertex_buffer_provider.vertex_buffer(key)
+            {
+                if vertex_buffers
+                    .iter()
+                    .any(|vertex_buffer| vertex_buffer.slot == slot)
+                {
+                    continue;
+                }
+
+                vertex_buffers.push(CapturedVertexBuffer {
+                    slot,
+                    buffer: self.allocation_index(buffer),
+                });
+            }
+        }
+
+        if !reflected_shader.per_instance_input.elements.is_empty() {
+            if let Some(instance_buffer) = &command.instance_buffer {
+                vertex_buffers.push(CapturedVertexBuffer {
+                    slot: command.vertex_buffer_provider.vertex_buffer_count(),
+                    buffer: self.allocation_index(instance_buffer),
+                });
+            }
+        }
+
+        let index_buffer = command
+            .index_buffer
+            .as_ref()
+            .map(|(buffer, format, index_count)| CapturedIndexBuffer {
+                buffer: self.allocation_index(buffer),
+                format: *format,
+                index_count: *index_count,
+            });
+
+        // The arguments of indexed draws have one more field.
+        let indirect_args_size = match command.index_buffer {
+            Some(_) => size_of::<[u32; 5]>(),
+            None => size_of::<[u32; 4]>(),
+        };
+        let indirect_buffer = command
+            .indirect_buffer
+            .as_ref()
+            .map(|buffer| self.buffer_index(buffer, 0, indirect_args_size));
+
+        let mut instance_properties =
+            Vec::from_iter(material.instance_properties.iter().map(|(name, property)| {
+                CapturedInstanceProperty {
+                    name: name.clone(),
+                    format: property.format,
+                    value: property
+                        .value
+                        .as_ref()
+                        .map(|value| value.as_bytes().to_vec()),
+                }
+            }));
+        instance_properties.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        CapturedCommand {
+            pipeline: self.pipeline_index(&command.pipeline, shader_mgr),
+            vertex_count: command.vertex_count,
+            instance_count: command.instance_count,
+            bindings,
+            vertex_buffers,
+            index_buffer,
+            indirect_buffer,
+            instance_properties,
+        }
+    }
+}
+
+struct SurfaceCopy {
+    buffer: Buffer,
+    format: TextureFormat,
+    padded_bytes_per_row: u32,
+}
+
+struct PendingCapture {
+    path: PathBuf,
+    capture: FrameCapture,
+    staging_buffer: Option<Buffer>,
+    /// Offset of each copy in the staging buffer.
+    copies: Vec<(BufferCopy, BufferAddress)>,
+    surface: Option<SurfaceCopy>,
+    mapped: Option<Receiver<Result<(), BufferAsyncError>>>,
+    mapping_count: usize,
+}
+
+/// Records the command stream of a requested frame into a [`FrameCapture`] file.
+///
+/// The render system feeds every pass through [`record_pass`](Self::record_pass) while a capture is active.
+/// Buffer contents are copied on the GPU at the end of the frame and read back when the frame is collected,
+/// which waits for the GPU; the capture is a debugging aid, so the stall is acceptable.
+/// Custom passes, overlays and the debug UI are not captured.
+pub struct FrameCaptureRecorder {
+    gfx_ctx: GfxContextHandle,
+    request: Option<PathBuf>,
+    active: Option<ActiveCapture>,
+    pending: Option<PendingCapture>,
+    labels: HashMap<usize, TextureLabel>,
+}
+
+impl FrameCaptureRecorder {
+    pub fn new(gfx_ctx: GfxContextHandle) -> Self {
+        Self {
+            gfx_ctx,
+            request: None,
+            active: None,
+            pending: None,
+            labels: HashMap::new(),
+        }
+    }
+
+    /// Captures the next frame into `path`. A later request replaces an earlier one that has not started yet.
+    pub fn request(&mut self, path: impl Into<PathBuf>) {
+        self.request = Some(path.into());
+    }
+
+    /// Returns `true` if a frame is being recorded.
+    pub fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Identifies the texture by its asset in the captures, so that a replay can load the asset instead of a placeholder.
+    pub fn label_texture(
+        &mut self,
+        view: &Arc<TextureView>,
+        key: AssetKey,
+        width: u32,
+        height: u32,
+    ) {
+        self.labels
+            .retain(|_, label| label.view.strong_count() != 0);
+        self.labels.insert(
+            Arc::as_ptr(view) as usize,
+            TextureLabel {
+                view: Arc::downgrade(view),
+                key,
+                width,
+                height,
+            },
+        );
+    }
+
+    /// Starts recording the frame if one has been requested and the previous capture has been collected.
+    pub fn begin(
+        &mut self,
+        width: u32,
+        height: u32,
+        surface_format: TextureFormat,
+        screen_size: [f32; 4],
+    ) -> bool {
+        if self.pending.is_some() {
+            return false;
+        }
+
+        let path = if let Some(path) = self.request.take() {
+            path
+        } else {
+            return false;
+        };
+
+        let mut active = ActiveCapture {
+            path,
+            capture: FrameCapture {
+                width,
+                height,
+                surface_format,
+                screen_size: 0,
+                shaders: Vec::new(),
+                pipelines: Vec::new(),
+                textures: Vec::new(),
+                passes: Vec::new(),
+                reference_image: None,
+                buffers: Vec::new(),
+            },
+            pipelines: HashMap::new(),
+            shaders: HashMap::new(),
+            textures: HashMap::new(),
+            buffers: HashMap::new(),
+            copies: Vec::new(),
+        };
+        active.capture.screen_size = active.push_buffer(screen_size.as_bytes().to_vec());
+        self.active = Some(active);
+        true
+    }
+
+    /// Records a pass about to draw the commands. Does nothing unless a capture is active.
+    pub fn record_pass(
+        &mut self,
+        name: impl Into<String>,
+        target: CapturePassTarget,
+        clear_mode: &CameraClearMode,
+        view_projection: &Mat4,
+        commands: &[RenderingCommand],
+        shader_mgr: &ShaderManager,
+    ) {
+        let active = if let Some(active) = &mut self.active {
+            active
+        } else {
+            return;
+        };
+        let pass = active.capture.passes.len();
+        let target = match target {
+            CapturePassTarget::Surface => CapturedPassTarget::Surface,
+            CapturePassTarget::Texture {
+                view,
+                width,
+                height,
+                format,
+            } => {
+                active
+                    .capture
+                    .textures
+                    .push(CapturedTexture::RenderTarget { pass });
+                let texture = active.capture.textures.len() - 1;
+                active.textures.insert(Arc::as_ptr(view) as usize, texture);
+                CapturedPassTarget::Texture {
+                    texture,
+                    width,
+                    height,
+                    format,
+                }
+            }
+        };
+        let camera_transform = active.push_buffer(view_projection.as_bytes().to_vec());
+        let commands = Vec::from_iter(
+            commands
+                .iter()
+                .map(|command| active.record_command(command, shader_mgr, &self.labels)),
+        );
+
+        active.capture.passes.push(CapturedPass {
+            name: name.into(),
+            target,
+            clear: clear_mode.into(),
+            camera_transform,
+            commands,
+        });
+    }
+
+    /// Copies the buffers and the surface of the recorded passes. Must be called after the last captured pass.
+    pub fn encode(&mut self, encoder: &mut CommandEncoder, surface_texture: &Texture) {
+        let active = if let Some(active) = self.active.take() {
+            active
+        } else {
+            return;
+        };
+        let device = &self.gfx_ctx.device;
+
+        let mut staging_size = 0;
+        let copies = Vec::from_iter(active.copies.into_iter().map(|copy| {
+            let offset = staging_size;
+            staging_size += copy.size;
+            (copy, offset)
+        }));
+        let staging_buffer = (staging_size != 0).then(|| {
+            device.create_buffer(&BufferDescriptor {
+                label: Some("frame capture buffer"),
+                size: staging_size,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        });
+
+        if let Some(staging_buffer) = &staging_buffer {
+            for (copy, offset) in &copies {
+                encoder.copy_buffer_to_buffer(
+                    &copy.buffer,
+                    copy.offset,
+                    staging_buffer,
+                    *offset,
+                    copy.size,
+                );
+            }
+        }
+
+        let is_surface_copyable = surface_texture.usage().contains(TextureUsages::COPY_SRC)
+            && matches!(
+                surface_texture.format(),
+                TextureFormat::Bgra8Unorm
+                    | TextureFormat::Bgra8UnormSrgb
+                    | TextureFormat::Rgba8Unorm
+                    | TextureFormat::Rgba8UnormSrgb
+            );
+        let surface = is_surface_copyable.then(|| {
+            let width = surface_texture.width();
+            let height = surface_texture.height();
+            let padded_bytes_per_row = (width * 4 + COPY_BYTES_PER_ROW_ALIGNMENT - 1)
+                / COPY_BYTES_PER_ROW_ALIGNMENT
+                * COPY_BYTES_PER_ROW_ALIGNMENT;
+            let buffer = device.create_buffer(&BufferDescriptor {
+                label: Some("frame capture surface buffer"),
+                size: (padded_bytes_per_row * height) as BufferAddress,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            encoder.copy_texture_to_buffer(
+                ImageCopyTexture {
+                    texture: surface_texture,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                ImageCopyBuffer {
+                    buffer: &buffer,
+                    layout: ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(padded_bytes_per_row),
+                        rows_per_image: Some(height),
+                    },
+                },
+                Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+            SurfaceCopy {
+                buffer,
+                format: surface_texture.format(),
+                padded_bytes_per_row,
+            }
+        });
+
+        self.pending = Some(PendingCapture {
+            path: active.path,
+            capture: active.capture,
+            staging_buffer,
+            copies,
+            surface,
+            mapped: None,
+            mapping_count: 0,
+        });
+    }
+
+    /// Starts reading back the copies. Must be called after the frame has been submitted.
+    pub fn read_back(&mut self) {
+        let pending = match &mut self.pending {
+            Some(pending) if pending.mapped.is_none() => pending,
+            _ => return,
+        };
+        let (sender, receiver) = channel();
+
+        for buffer in pending
+            .staging_buffer
+            .iter()
+            .chain(pending.surface.as_ref().map(|surface| &surface.buffer))
+        {
+            let sender = sender.clone();
+            buffer.slice(..).map_async(MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+            pending.mapping_count += 1;
+        }
+
+        pending.mapped = Some(receiver);
+    }
+
+    /// Waits for the captured frame to be read back and saves it, returning where it went.
+    pub fn collect(&mut self) -> Option<(PathBuf, Result<(), FrameCaptureError>)> {
+        if self.pending.as_ref()?.mapped.is_none() {
+            return None;
+        }
+
+        let mut pending = self.pending.take()?;
+        self.gfx_ctx.device.poll(Maintain::Wait);
+
+        let is_mapped = pending.mapped.as_ref().map_or(false, |mapped| {
+            (0..pending.mapping_count).all(|_| matches!(mapped.try_recv(), Ok(Ok(()))))
+        });
+        let result = if is_mapped {
+            fill_buffers(&mut pending);
+            pending.capture.save(&pending.path)
+        } else {
+            Err(FrameCaptureError::ReadBackFailed)
+        };
+
+        Some((pending.path, result))
+    }
+}
+
+fn fill_buffers(pending: &mut PendingCapture) {
+    if let Some(staging_buffer) = &pending.staging_buffer {
+        {
+            let data = staging_buffer.slice(..).get_mapped_range();
+
+            for (copy, offset) in &pending.copies {
+                let start = *offset as usize + copy.skip;
+                pending.capture.buffers[copy.index][..copy.len]
+                    .copy_from_slice(&data[start..start + copy.len]);
+            }
+        }
+
+        staging_buffer.unmap();
+    }
+
+    if let Some(surface) = &pending.surface {
+        let width = pending.capture.width as usize;
+        let is_bgra = matches!(
+            surface.format,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+        );
+        let mut pixels = Vec::with_capacity(width * pending.capture.height as usize * 4);
+
+        {
+            let data = surface.buffer.slice(..).get_mapped_range();
+
+            for row in data
+                .chunks_exact(surface.padded_bytes_per_row as usize)
+                .take(pending.capture.height as usize)
+            {
+                for pixel in row[..width * 4].chunks_exact(4) {
+                    if is_bgra {
+                        pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                    } else {
+                        pixels.extend_from_slice(pixel);
+                    }
+                }
+            }
+        }
+
+        surface.buffer.unmap();
+        pending.capture.buffers.push(pixels);
+        pending.capture.reference_image = Some(pending.capture.buffers.len() - 1);
+    }
+}
@@ -0,0 +1,462 @@
+use std::fmt::{Display, Write};
+use wgpu::{CommandEncoder, TextureView};
+
+/// How a pass uses a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResourceAccess {
+    /// Bound as a texture or buffer to be read by shaders.
+    Sample,
+    /// Bound as an attachment with its previous content loaded; reads and writes it.
+    Load,
+    /// Bound as an attachment and overwritten entirely; does not read it.
+    Write,
+    /// Bound as an attachment and cleared, starting a new version of the resource; does not read it.
+    /// Unlike [`Write`](Self::Write), discarding an unread version this way is intended, e.g. a camera clearing
+    /// the depth left by the camera before it.
+    Clear,
+}
+
+impl ResourceAccess {
+    pub fn reads(self) -> bool {
+        matches!(self, Self::Sample | Self::Load)
+    }
+
+    pub fn writes(self) -> bool {
+        matches!(self, Self::Load | Self::Write | Self::Clear)
+    }
+}
+
+impl Display for ResourceAccess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sample => write!(f, "sample"),
+            Self::Load => write!(f, "load"),
+            Self::Write => write!(f, "write"),
+            Self::Clear => write!(f, "clear"),
+        }
+    }
+}
+
+/// Resources a pass declares to use in a frame.
+#[derive(Debug, Default, Clone)]
+pub struct ResourceDeclaration {
+    accesses: Vec<(String, ResourceAccess)>,
+    imports: Vec<String>,
+}
+
+impl ResourceDeclaration {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn accesses(&self) -> &[(String, ResourceAccess)] {
+        &self.accesses
+    }
+
+    pub fn sample(&mut self, resource: impl Into<String>) -> &mut Self {
+        self.accesses
+            .push((resource.into(), ResourceAccess::Sample));
+        self
+    }
+
+    pub fn load(&mut self, resource: impl Into<String>) -> &mut Self {
+        self.accesses.push((resource.into(), ResourceAccess::Load));
+        self
+    }
+
+    pub fn write(&mut self, resource: impl Into<String>) -> &mut Self {
+        self.accesses.push((resource.into(), ResourceAccess::Write));
+        self
+    }
+
+    pub fn clear(&mut self, resource: impl Into<String>) -> &mut Self {
+        self.accesses.push((resource.into(), ResourceAccess::Clear));
+        self
+    }
+
+    /// Marks a resource as initialized outside of the frame (e.g. a persistent texture),
+    /// so reading it before any write in the frame is valid.
+    pub fn import(&mut self, resource: impl Into<String>) -> &mut Self {
+        self.imports.push(resource.into());
+        self
+    }
+}
+
+/// A user-defined render pass, scheduled after the built-in camera passes.
+pub trait CustomPass {
+    fn name(&self) -> &str;
+
+    fn declare_resources(&self, declaration: &mut ResourceDeclaration);
+
+    /// Draws into the view of the surface that stores colors as they are written, of the format of
+    /// [`GfxContext::plain_surface_format`](super::GfxContext::plain_surface_format).
+    fn execute(&mut self, encoder: &mut CommandEncoder, surface_texture_view: &TextureView);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameGraphDiagnostic {
+    /// The resource is written twice without anything reading the first write.
+    DoubleWrite {
+        resource: String,
+        first: String,
+        second: String,
+    },
+    /// The resource is read before any pass of the frame writes it.
+    UninitializedRead { resource: String, pass: String },
+    /// The pass samples a resource that it also binds as an attachment.
+    AttachmentAndSample { resource: String, pass: String },
+    /// The passes depend on each other, so no order satisfies them; registration order is used.
+    Cycle { passes: Vec<String> },
+}
+
+impl Display for FrameGraphDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DoubleWrite {
+                resource,
+                first,
+                second,
+            } => write!(
+                f,
+                "`{}` writes `{}` written by `{}` without any read between",
+                second, resource, first
+            ),
+            Self::UninitializedRead { resource, pass } => write!(
+                f,
+                "`{}` reads `{}` before any pass writes it",
+                pass, resource
+            ),
+            Self::AttachmentAndSample { resource, pass } => write!(
+                f,
+                "`{}` samples `{}` while binding it as an attachment",
+                pass, resource
+            ),
+            Self::Cycle { passes } => {
+                write!(f, "passes depend on each other: {}", passes.join(", "))
+            }
+        }
+    }
+}
+
+struct FrameGraphPass {
+    name: String,
+    declaration: ResourceDeclaration,
+}
+
+/// Collects the resource declarations of the passes of a frame, to order and validate them.
+#[derive(Default)]
+pub struct FrameGraph {
+    passes: Vec<FrameGraphPass>,
+}
+
+impl FrameGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_pass(&mut self, name: impl Into<String>, declaration: ResourceDeclaration) -> usize {
+        self.passes.push(FrameGraphPass {
+            name: name.into(),
+            declaration,
+        });
+        self.passes.len() - 1
+    }
+
+    /// Orders the passes by registration, except that a pass sampling a resource is moved after
+    /// every pass writing it. Returns the indices of the passes and a diagnostic if they form a cycle.
+    pub fn resolve_order(&self) -> (Vec<usize>, Option<FrameGraphDiagnostic>) {
+        let count = self.passes.len();
+        let mut dependencies = vec![Vec::new(); count];
+
+        for (reader, pass) in self.passes.iter().enumerate() {
+            for (resource, access) in pass.declaration.accesses() {
+                if *access != ResourceAccess::Sample {
+                    continue;
+                }
+
+                for (writer, other) in self.passes.iter().enumerate() {
+                    if writer != reader && other.writes(resource) {
+                        dependencies[reader].push(writer);
+                    }
+                }
+            }
+
+            // Passes writing the same resource keep their registration order.
+            for (writer, other) in self.passes[..reader].iter().enumerate() {
+                if pass
+                    .declaration
+                    .accesses()
+                    .iter()
+                    .any(|(resource, access)| access.writes() && other.writes(resource))
+                {
+                    dependencies[reader].push(writer);
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(count);
+        let mut scheduled = vec![false; count];
+
+        while order.len() < count {
+            let next = (0..count).find(|&index| {
+                !scheduled[index]
+                    && dependencies[index]
+                        .iter()
+                        .all(|&dependency| scheduled[dependency])
+            });
+
+            match next {
+                Some(index) => {
+                    scheduled[index] = true;
+                    order.push(index);
+                }
+                None => {
+                    let passes = (0..count)
+                        .filter(|&index| !scheduled[index])
+                        .map(|index| self.passes[index].name.clone())
+                        .collect();
+                    return (
+                        (0..count).collect(),
+                        Some(FrameGraphDiagnostic::Cycle { passes }),
+                    );
+                }
+            }
+        }
+
+        (order, None)
+    }
+
+    /// Checks the declarations of the passes executed in the given order.
+    pub fn validate(&self, order: &[usize]) -> Vec<FrameGraphDiagnostic> {
+        let mut diagnostics = Vec::new();
+        // The last pass written each resource, and whether it has been read since.
+        let mut states: Vec<(&str, Option<usize>, bool)> = Vec::new();
+
+        for pass in self.passes.iter() {
+            for import in &pass.declaration.imports {
+                if !states.iter().any(|(name, ..)| name == import) {
+                    states.push((import, None, true));
+                }
+            }
+        }
+
+        for &index in order {
+            let pass = &self.passes[index];
+
+            for (resource, access) in pass.declaration.accesses() {
+                if *access == ResourceAccess::Sample && pass.writes(resource) {
+                    let diagnostic = FrameGraphDiagnostic::AttachmentAndSample {
+                        resource: resource.clone(),
+                        pass: pass.name.clone(),
+                    };
+
+                    if !diagnostics.contains(&diagnostic) {
+                        diagnostics.push(diagnostic);
+                    }
+                }
+
+                let state = match states.iter().position(|(name, ..)| name == resource) {
+                    Some(position) => &mut states[position],
+                    None => {
+                        states.push((resource, None, false));
+                        states.last_mut().unwrap()
+                    }
+                };
+
+                if access.reads() {
+                    if state.1.is_none() && !state.2 {
+                        diagnostics.push(FrameGraphDiagnostic::UninitializedRead {
+                            resource: resource.clone(),
+                            pass: pass.name.clone(),
+                        });
+                    }
+
+                    state.2 = true;
+                }
+
+                if *access == ResourceAccess::Write {
+                    if let (Some(writer), false) = (state.1, state.2) {
+                        diagnostics.push(FrameGraphDiagnostic::DoubleWrite {
+                            resource: resource.clone(),
+                            first: self.passes[writer].name.clone(),
+                            second: pass.name.clone(),
+                        });
+                    }
+                }
+
+                if access.writes() {
+                    state.1 = Some(index);
+                    state.2 = false;
+                }
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Renders the passes in the given order along with their accesses, for error reports.
+    pub fn dump(&self, order: &[usize]) -> String {
+        let mut dump = String::new();
+
+        for (position, &index) in order.iter().enumerate() {
+            let pass = &self.passes[index];
+            writeln!(dump, "#{} {}", position, pass.name).unwrap();
+
+            for (resource, access) in pass.declaration.accesses() {
+                writeln!(dump, "    {:<6} {}", access.to_string(), resource).unwrap();
+            }
+        }
+
+        dump
+    }
+}
+
+impl FrameGraphPass {
+    fn writes(&self, resource: &str) -> bool {
+        self.declaration
+            .accesses()
+            .iter()
+            .any(|(name, access)| name == resource && access.writes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(passes: &[(&str, ResourceDeclaration)]) -> FrameGraph {
+        let mut graph = FrameGraph::new();
+
+        for (name, declaration) in passes {
+            graph.add_pass(*name, declaration.clone());
+        }
+
+        graph
+    }
+
+    fn declare(f: impl FnOnce(&mut ResourceDeclaration)) -> ResourceDeclaration {
+        let mut declaration = ResourceDeclaration::new();
+        f(&mut declaration);
+        declaration
+    }
+
+    #[test]
+    fn check_valid_frame_has_no_diagnostics() {
+        let graph = graph(&[
+            ("scene", declare(|d| _ = d.write("color").write("depth"))),
+            ("outline", declare(|d| _ = d.sample("depth").load("color"))),
+        ]);
+        let (order, cycle) = graph.resolve_order();
+
+        assert_eq!(cycle, None);
+        assert!(graph.validate(&order).is_empty());
+    }
+
+    #[test]
+    fn check_double_write_is_reported() {
+        let graph = graph(&[
+            ("scene", declare(|d| _ = d.write("color"))),
+            ("clear", declare(|d| _ = d.write("color"))),
+        ]);
+
+        assert_eq!(
+            graph.validate(&[0, 1]),
+            vec![FrameGraphDiagnostic::DoubleWrite {
+                resource: "color".to_owned(),
+                first: "scene".to_owned(),
+                second: "clear".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn check_clear_starts_a_new_version() {
+        let graph = graph(&[
+            ("scene", declare(|d| _ = d.write("color").clear("depth"))),
+            ("overlay", declare(|d| _ = d.load("color").clear("depth"))),
+            ("decal", declare(|d| _ = d.write("depth"))),
+        ]);
+
+        assert_eq!(
+            graph.validate(&[0, 1, 2]),
+            vec![FrameGraphDiagnostic::DoubleWrite {
+                resource: "depth".to_owned(),
+                first: "overlay".to_owned(),
+                second: "decal".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn check_uninitialized_read_is_reported() {
+        let graph = graph(&[(
+            "blur",
+            declare(|d| {
+                _ = d
+                    .sample("bloom")
+                    .import("history")
+                    .sample("history")
+                    .write("color")
+            }),
+        )]);
+
+        assert_eq!(
+            graph.validate(&[0]),
+            vec![FrameGraphDiagnostic::UninitializedRead {
+                resource: "bloom".to_owned(),
+                pass: "blur".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn check_attachment_and_sample_is_reported() {
+        let graph = graph(&[
+            ("scene", declare(|d| _ = d.write("color"))),
+            ("feedback", declare(|d| _ = d.sample("color").load("color"))),
+        ]);
+        let (order, _) = graph.resolve_order();
+
+        assert_eq!(
+            graph.validate(&order),
+            vec![FrameGraphDiagnostic::AttachmentAndSample {
+                resource: "color".to_owned(),
+                pass: "feedback".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn check_readers_are_moved_after_writers() {
+        let graph = graph(&[
+            (
+                "composite",
+                declare(|d| _ = d.sample("ssao").sample("color").write("final")),
+            ),
+            ("scene", declare(|d| _ = d.write("color").write("depth"))),
+            ("ssao", declare(|d| _ = d.sample("depth").write("ssao"))),
+        ]);
+        let (order, cycle) = graph.resolve_order();
+
+        assert_eq!(cycle, None);
+        assert_eq!(order, vec![1, 2, 0]);
+        assert!(graph.validate(&order).is_empty());
+    }
+
+    #[test]
+    fn check_cycle_is_reported() {
+        let graph = graph(&[
+            ("a", declare(|d| _ = d.sample("y").write("x"))),
+            ("b", declare(|d| _ = d.sample("x").write("y"))),
+        ]);
+        let (order, cycle) = graph.resolve_order();
+
+        assert_eq!(order, vec![0, 1]);
+        assert_eq!(
+            cycle,
+            Some(FrameGraphDiagnostic::Cycle {
+                passes: vec!["a".to_owned(), "b".to_owned()]
+            })
+        );
+    }
+}
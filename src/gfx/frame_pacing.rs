@@ -0,0 +1,252 @@
+use super::{FrameBufferAllocatorStats, PipelineCacheStats, UploaderStats};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+pub const MIN_FRAMES_IN_FLIGHT: u32 = 1;
+pub const MAX_FRAMES_IN_FLIGHT: u32 = 3;
+
+/// Signaled once the GPU has finished the work submitted for a frame.
+#[derive(Debug, Default, Clone)]
+pub struct FrameFence {
+    signaled: Arc<AtomicBool>,
+}
+
+impl FrameFence {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn signal(&self) {
+        self.signaled.store(true, Ordering::Release);
+    }
+
+    pub fn is_signaled(&self) -> bool {
+        self.signaled.load(Ordering::Acquire)
+    }
+}
+
+/// Fences of the frames submitted but not finished yet, oldest first.
+/// `T` identifies the submission of each frame, so that the oldest one can be waited on.
+#[derive(Debug)]
+pub struct FrameFenceRing<T> {
+    max_frames_in_flight: u32,
+    fences: VecDeque<(FrameFence, T)>,
+    peak_frames_in_flight: u32,
+}
+
+impl<T> FrameFenceRing<T> {
+    pub fn new(max_frames_in_flight: u32) -> Self {
+        Self {
+            max_frames_in_flight: max_frames_in_flight
+                .clamp(MIN_FRAMES_IN_FLIGHT, MAX_FRAMES_IN_FLIGHT),
+            fences: VecDeque::with_capacity(MAX_FRAMES_IN_FLIGHT as usize),
+            peak_frames_in_flight: 0,
+        }
+    }
+
+    pub fn max_frames_in_flight(&self) -> u32 {
+        self.max_frames_in_flight
+    }
+
+    /// Sets the limit, clamped to `1..=3`. Takes effect when the next frame begins.
+    pub fn set_max_frames_in_flight(&mut self, max_frames_in_flight: u32) {
+        self.max_frames_in_flight =
+            max_frames_in_flight.clamp(MIN_FRAMES_IN_FLIGHT, MAX_FRAMES_IN_FLIGHT);
+    }
+
+    /// Number of frames submitted and not finished, forgetting the finished ones.
+    pub fn frames_in_flight(&mut self) -> u32 {
+        while let Some((fence, _)) = self.fences.front() {
+            if !fence.is_signaled() {
+                break;
+            }
+
+            self.fences.pop_front();
+        }
+
+        self.fences.len() as u32
+    }
+
+    /// The highest number of frames in flight seen when a frame was registered.
+    pub fn peak_frames_in_flight(&self) -> u32 {
+        self.peak_frames_in_flight
+    }
+
+    /// Blocks until fewer than `limit` frames are in flight. `wait` is given the oldest submission
+    /// and must block until it progresses, e.g. by polling the device for it.
+    pub fn wait_until_below(&mut self, limit: u32, mut wait: impl FnMut(&T)) {
+        while limit <= self.frames_in_flight() {
+            let (_, submission) = self.fences.front().unwrap();
+            wait(submission);
+        }
+    }
+
+    /// Blocks until a new frame may start under the limit.
+    pub fn wait_for_slot(&mut self, wait: impl FnMut(&T)) {
+        self.wait_until_below(self.max_frames_in_flight, wait);
+    }
+
+    /// Registers a submitted frame. The returned fence must be signaled once its work is done.
+    pub fn push(&mut self, submission: T) -> FrameFence {
+        let fence = FrameFence::new();
+        self.fences.push_back((fence.clone(), submission));
+        self.peak_frames_in_flight = self.peak_frames_in_flight.max(self.fences.len() as u32);
+        fence
+    }
+}
+
+/// Timings and counters of the last presented frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FrameReport {
+    /// Frames submitted and not finished by the GPU when this frame was presented, including itself.
+    pub frames_in_flight: u32,
+    /// Time spent blocked before encoding, waiting for a frame in flight to finish.
+    pub wait_ms: f32,
+    /// Time from the newest input handled by this frame to its present call.
+    /// It doesn't include the compositor and the display, so it is approximate but consistent between frames.
+    /// `None` if no input arrived since the previous frame.
+    pub input_latency_ms: Option<f32>,
+    /// GPU time of a recent frame, from its first command to its last one. The timestamps are read back
+    /// asynchronously, so it lags a few frames behind. `None` if the device doesn't support timestamp queries.
+    pub gpu_ms: Option<f32>,
+    /// GPU time of the volumetric fog of a recent frame, lagging like [`gpu_ms`](Self::gpu_ms).
+    /// `None` if the fog is off or the device doesn't support timestamp queries.
+    pub fog_gpu_ms: Option<f32>,
+    /// Mesh renderers skipped for being outside of the frustum, summed over the cameras.
+    pub meshes_culled: u32,
+    /// Time spent picking the local lights shading each mesh renderer, summed over the cameras.
+    pub light_culling_ms: f32,
+    /// Terrain chunks drawn, summed over the cameras.
+    pub terrain_chunks_drawn: u32,
+    /// Terrain chunks skipped for being outside of the frustum, summed over the cameras.
+    pub terrain_chunks_culled: u32,
+    /// Draw commands recorded by the camera passes, summed over the cameras.
+    pub draw_calls: u32,
+    /// [`HlodProxy`](super::HlodProxy) draws among the draw calls, each standing in for a cluster of objects.
+    pub hlod_proxies_drawn: u32,
+    /// Distinct materials the mesh renderers were drawn with, summed over the cameras. Renderers sharing a
+    /// material can be batched; a [`MaterialInstance`](super::MaterialInstance) with its own bind groups adds one.
+    pub material_batches: u32,
+    /// Pipelines requested from the [`PipelineCache`](super::PipelineCache) this frame.
+    pub pipeline_cache: PipelineCacheStats,
+    /// Bytes allocated from the [`FrameBufferAllocator`](super::FrameBufferAllocator) this frame: the per-instance
+    /// data and the vertices renderers generate every frame. Mesh buffers are uploaded once and shared, so static
+    /// meshes only add their per-instance data.
+    pub frame_buffer_bytes: u64,
+    /// Memory the [`FrameBufferAllocator`](super::FrameBufferAllocator) holds after this frame.
+    pub frame_buffers: FrameBufferAllocatorStats,
+    /// Buffer writes batched by the [`Uploader`](super::Uploader).
+    pub uploads: UploaderStats,
+    /// Most threads a camera pass was recorded on, see
+    /// [`RenderManager::set_encoder_threads`](super::RenderManager::set_encoder_threads).
+    pub encoder_threads: u32,
+    /// Time spent recording the camera passes, summed over the cameras, by the slowest thread.
+    pub encode_ms: f32,
+}
+
+/// Carries the time of the newest input from the event loop to the frame that handles it.
+#[derive(Debug, Default, Clone)]
+pub struct InputLatencyTracker {
+    pending: Option<Instant>,
+    current: Option<Instant>,
+}
+
+impl InputLatencyTracker {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn record_input(&mut self, time: Instant) {
+        self.pending = Some(match self.pending {
+            Some(pending) => pending.max(time),
+            None => time,
+        });
+    }
+
+    /// Hands the inputs received so far to the frame being started.
+    pub fn begin_frame(&mut self) {
+        self.current = self.pending.take();
+    }
+
+    /// Latency of the current frame in milliseconds, measured at `presented`.
+    pub fn end_frame(&mut self, presented: Instant) -> Option<f32> {
+        self.current
+            .take()
+            .map(|input| presented.saturating_duration_since(input).as_secs_f32() * 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::mpsc, thread, time::Duration};
+
+    #[test]
+    fn check_frames_in_flight_never_exceed_limit() {
+        for limit in MIN_FRAMES_IN_FLIGHT..=MAX_FRAMES_IN_FLIGHT {
+            // A fake GPU finishing the submitted frames in order, at an uneven pace.
+            let (submit, submitted) = mpsc::channel::<FrameFence>();
+            let gpu = thread::spawn(move || {
+                for (index, fence) in submitted.into_iter().enumerate() {
+                    thread::sleep(Duration::from_micros(50 * (index % 7) as u64));
+                    fence.signal();
+                }
+            });
+
+            let mut ring = FrameFenceRing::<u32>::new(limit);
+            for frame in 0..300 {
+                ring.wait_for_slot(|_| thread::yield_now());
+                assert!(ring.frames_in_flight() < limit);
+
+                let fence = ring.push(frame);
+                assert!(ring.frames_in_flight() <= limit);
+                submit.send(fence).unwrap();
+            }
+
+            drop(submit);
+            gpu.join().unwrap();
+
+            assert!(1 <= ring.peak_frames_in_flight());
+            assert!(ring.peak_frames_in_flight() <= limit);
+            assert_eq!(ring.frames_in_flight(), 0);
+        }
+    }
+
+    #[test]
+    fn check_frames_in_flight_limit_is_clamped() {
+        let mut ring = FrameFenceRing::<()>::new(0);
+        assert_eq!(ring.max_frames_in_flight(), 1);
+        ring.set_max_frames_in_flight(8);
+        assert_eq!(ring.max_frames_in_flight(), 3);
+    }
+
+    #[test]
+    fn check_input_latency_uses_newest_input() {
+        let start = Instant::now();
+        let mut tracker = InputLatencyTracker::new();
+
+        tracker.record_input(start + Duration::from_millis(5));
+        tracker.record_input(start);
+        tracker.begin_frame();
+        // Inputs arriving during the frame belong to the next one.
+        tracker.record_input(start + Duration::from_millis(9));
+
+        let latency = tracker
+            .end_frame(start + Duration::from_millis(21))
+            .unwrap();
+        assert!((latency - 16.0).abs() < 0.01);
+
+        tracker.begin_frame();
+        assert!(tracker
+            .end_frame(start + Duration::from_millis(30))
+            .is_some());
+        tracker.begin_frame();
+        assert_eq!(tracker.end_frame(start + Duration::from_millis(40)), None);
+    }
+}
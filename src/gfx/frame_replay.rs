@@ -0,0 +1,984 @@
+use super::{
+    CapturedClear, CapturedCommand, CapturedPassTarget, CapturedResource, CapturedTexture,
+    FrameCapture, FrameCaptureError,
+};
+use asset::AssetKey;
+use image::RgbaImage;
+use std::{
+    collections::HashMap,
+    sync::{mpsc::channel, Arc},
+};
+use thiserror::Error;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferAddress, BufferDescriptor,
+    BufferUsages, Color, CommandEncoderDescriptor, CompareFunction, Device, DeviceDescriptor,
+    Extent3d, Features, FragmentState, ImageCopyBuffer, ImageCopyTexture, ImageDataLayout,
+    Instance, InstanceDescriptor, LoadOp, Maintain, MapMode, Operations, Origin3d,
+    PipelineLayoutDescriptor, Queue, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, RequestAdapterOptions,
+    RequestDeviceError, Sampler, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor,
+    ShaderSource, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+    VertexBufferLayout, VertexState, VertexStepMode, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+#[derive(Error, Debug)]
+pub enum FrameReplayError {
+    #[error("failed to load the capture: {0}")]
+    Capture(#[from] FrameCaptureError),
+    #[error("no adapter found")]
+    AdapterNotFound,
+    #[error("failed to obtain device")]
+    RequestDeviceError(#[from] RequestDeviceError),
+    #[error("the surface format {0:?} cannot be read back")]
+    UnsupportedSurfaceFormat(TextureFormat),
+    #[error("failed to read the replayed frame back")]
+    ReadBackFailed,
+}
+
+/// Device without a surface, for replaying captures without a window, e.g. in tests and tools.
+pub struct HeadlessDevice {
+    pub device: Device,
+    pub queue: Queue,
+}
+
+impl HeadlessDevice {
+    pub async fn new(backends: wgpu::Backends) -> Result<Self, FrameReplayError> {
+        let instance = Instance::new(InstanceDescriptor {
+            backends,
+            ..Default::default()
+        });
+        let adapter = instance
+            .request_adapter(&RequestAdapterOptions {
+                compatible_surface: None,
+                ..Default::default()
+            })
+            .await
+            .ok_or(FrameReplayError::AdapterNotFound)?;
+        let (device, queue) = adapter
+            .request_device(
+                &DeviceDescriptor {
+                    label: None,
+                    features: adapter.features() & Features::CLEAR_TEXTURE,
+                    limits: adapter.limits(),
+                },
+                None,
+            )
+            .await?;
+
+        Ok(Self { device, queue })
+    }
+}
+
+struct ReplayPipeline {
+    pipeline: RenderPipeline,
+    bind_group_layouts: Vec<BindGroupLayout>,
+    entries: Vec<Vec<BindGroupLayoutEntry>>,
+    vertex_buffer_sizes: Vec<(BufferAddress, VertexStepMode)>,
+    depth_format: Option<TextureFormat>,
+}
+
+/// Resource resolved for a binding of a replayed command.
+enum Bound {
+    Buffer(Arc<Buffer>),
+    Texture(Arc<TextureView>),
+    Textures(Vec<Arc<TextureView>>),
+    Sampler(Arc<Sampler>),
+}
+
+/// Re-renders [`FrameCapture`]s on any device, headless or not.
+///
+/// Buffers are recreated exactly from the embedded copies. Textures of assets inserted through
+/// [`insert_asset_texture`](Self::insert_asset_texture) and render targets of earlier passes are used as they are;
+/// every other texture, as well as anything the capture could not record, is replaced by a placeholder.
+pub struct FrameReplayer<'d> {
+    device: &'d Device,
+    queue: &'d Queue,
+    asset_textures: HashMap<AssetKey, Arc<TextureView>>,
+    placeholder_textures: HashMap<BindingType, Arc<TextureView>>,
+    placeholder_buffers: HashMap<BufferAddress, Arc<Buffer>>,
+    sampler: Arc<Sampler>,
+    comparison_sampler: Arc<Sampler>,
+}
+
+impl<'d> FrameReplayer<'d> {
+    const PLACEHOLDER_SIZE: u32 = 8;
+
+    pub fn new(device: &'d Device, queue: &'d Queue) -> Self {
+        let sampler = Arc::new(device.create_sampler(&SamplerDescriptor::default()));
+        let comparison_sampler = Arc::new(device.create_sampler(&SamplerDescriptor {
+            compare: Some(CompareFunction::LessEqual),
+            ..Default::default()
+        }));
+
+        Self {
+            device,
+            queue,
+            asset_textures: HashMap::new(),
+            placeholder_textures: HashMap::new(),
+            placeholder_buffers: HashMap::new(),
+            sampler,
+            comparison_sampler,
+        }
+    }
+
+    /// Provides the contents of an asset texture, which would be a placeholder otherwise.
+    pub fn insert_asset_texture(&mut self, key: AssetKey, image: &RgbaImage) {
+        let size = Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&TextureDescriptor {
+            label: Some("replayed asset texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        self.queue.write_texture(
+            texture.as_image_copy(),
+            image.as_raw(),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(image.width() * 4),
+                rows_per_image: Some(image.height()),
+            },
+            size,
+        );
+        self.asset_textures.insert(
+            key,
+            Arc::new(texture.create_view(&TextureViewDescriptor::default())),
+        );
+    }
+
+    /// Renders the frame, returning the surface as RGBA8 pixels.
+    /// If `command_limit` is given, only that many commands are drawn; later passes still clear their targets,
+    /// so the frame can be stepped through draw by draw.
+    pub fn render(
+        &mut self,
+        capture: &FrameCapture,
+        command_limit: Option<usize>,
+    ) -> Result<RgbaImage, FrameReplayError> {
+        let is_bgra = match capture.surface_format {
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+            format => return Err(FrameReplayError::UnsupportedSurfaceFormat(format)),
+        };
+
+        let pipelines = self.create_pipelines(capture);
+        let buffers = Vec::from_iter(capture.buffers.iter().map(|contents| {
+            // Padded, so that even empty buffers can be bound.
+            let mut contents = contents.clone();
+            contents.resize(((contents.len() + 15) / 16 * 16).max(16), 0);
+            Arc::new(self.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("replayed buffer"),
+                contents: &contents,
+                usage: BufferUsages::VERTEX
+                    | BufferUsages::INDEX
+                    | BufferUsages::UNIFORM
+                    | BufferUsages::STORAGE
+                    | BufferUsages::INDIRECT,
+            }))
+        }));
+
+        let surface_size = Extent3d {
+            width: capture.width.max(1),
+            height: capture.height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let surface = self.device.create_texture(&TextureDescriptor {
+            label: Some("replayed surface"),
+            size: surface_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: capture.surface_format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let surface_view = Arc::new(surface.create_view(&TextureViewDescriptor::default()));
+        let mut render_targets = HashMap::<usize, Arc<TextureView>>::new();
+        let mut remaining = command_limit.unwrap_or(usize::MAX);
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: None });
+
+        for pass in &capture.passes {
+            let commands = &pass.commands[..pass.commands.len().min(remaining)];
+            remaining -= commands.len();
+
+            let (color_view, size, target_texture) = match pass.target {
+                CapturedPassTarget::Surface => (surface_view.clone(), surface_size, None),
+                CapturedPassTarget::Texture {
+                    texture,
+                    width,
+                    height,
+                    format,
+                } => {
+                    let size = Extent3d {
+                        width: width.max(1),
+                        height: height.max(1),
+                        depth_or_array_layers: 1,
+                    };
+                    let target = self.device.create_texture(&TextureDescriptor {
+                        label: Some("replayed render target"),
+                        size,
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: TextureDimension::D2,
+                        format,
+                        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                        view_formats: &[],
+                    });
+                    let view = Arc::new(target.create_view(&TextureViewDescriptor::default()));
+                    (view, size, Some(texture))
+                }
+            };
+            let depth_format = commands
+                .iter()
+                .find_map(|command| pipelines[command.pipeline].depth_format);
+            let depth_view = depth_format.map(|format| {
+                self.device
+                    .create_texture(&TextureDescriptor {
+                        label: Some("replayed depth stencil"),
+                        size,
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: TextureDimension::D2,
+                        format,
+                        usage: TextureUsages::RENDER_ATTACHMENT,
+                        view_formats: &[],
+                    })
+                    .create_view(&TextureViewDescriptor::default())
+            });
+
+            // Everything the pass refers to must outlive it.
+            let bind_groups = Vec::from_iter(commands.iter().map(|command| {
+                self.create_bind_groups(
+                    capture,
+                    command,
+                    &pipelines[command.pipeline],
+                    &buffers,
+                    &buffers[pass.camera_transform],
+                    &render_targets,
+                )
+            }));
+            let vertex_buffers = Vec::from_iter(commands.iter().map(|command| {
+                self.collect_vertex_buffers(command, &pipelines[command.pipeline], &buffers)
+            }));
+
+            {
+                let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: Some(&pass.name),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: &color_view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: color_load_op(&pass.clear),
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: depth_view.as_ref().zip(depth_format).map(
+                        |(view, format)| RenderPassDepthStencilAttachment {
+                            view,
+                            depth_ops: format.has_depth_aspect().then(|| Operations {
+                                load: pass.clear.depth.map_or(LoadOp::Load, LoadOp::Clear),
+                                store: true,
+                            }),
+                            stencil_ops: format.has_stencil_aspect().then(|| Operations {
+                                load: pass.clear.stencil.map_or(LoadOp::Load, LoadOp::Clear),
+                                store: true,
+                            }),
+                        },
+                    ),
+                });
+
+                for ((command, bind_groups), vertex_buffers) in
+                    commands.iter().zip(&bind_groups).zip(&vertex_buffers)
+                {
+                    render_pass.set_pipeline(&pipelines[command.pipeline].pipeline);
+
+                    for (group, (bind_group, dynamic_offset_count)) in
+                        bind_groups.iter().enumerate()
+                    {
+                        render_pass.set_bind_group(
+                            group as u32,
+                            bind_group,
+                            &vec![0; *dynamic_offset_count],
+                        );
+                    }
+
+                    for (slot, buffer) in vertex_buffers {
+                        render_pass.set_vertex_buffer(*slot, buffer.slice(..));
+                    }
+
+                    if let Some(index_buffer) = &command.index_buffer {
+                        render_pass.set_index_buffer(
+                            buffers[index_buffer.buffer].slice(..),
+                            index_buffer.format,
+                        );
+                    }
+
+                    match (command.indirect_buffer, &command.index_buffer) {
+                        (Some(indirect_buffer), Some(_)) => {
+                            render_pass.draw_indexed_indirect(&buffers[indirect_buffer], 0)
+                        }
+                        (Some(indirect_buffer), None) => {
+                            render_pass.draw_indirect(&buffers[indirect_buffer], 0)
+                        }
+                        (None, Some(index_buffer)) => render_pass.draw_indexed(
+                            0..index_buffer.index_count,
+                            0,
+                            0..command.instance_count,
+                        ),
+                        (None, None) => {
+                            render_pass.draw(0..command.vertex_count, 0..command.instance_count)
+                        }
+                    }
+                }
+            }
+
+            if let Some(texture) = target_texture {
+                render_targets.insert(texture, color_view);
+            }
+        }
+
+        let padded_bytes_per_row = (surface_size.width * 4 + COPY_BYTES_PER_ROW_ALIGNMENT - 1)
+            / COPY_BYTES_PER_ROW_ALIGNMENT
+            * COPY_BYTES_PER_ROW_ALIGNMENT;
+        let read_back_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("replayed surface buffer"),
+            size: (padded_bytes_per_row * surface_size.height) as BufferAddress,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                texture: &surface,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            ImageCopyBuffer {
+                buffer: &read_back_buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(surface_size.height),
+                },
+            },
+            surface_size,
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = channel();
+        read_back_buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.device.poll(Maintain::Wait);
+
+        if !matches!(receiver.try_recv(), Ok(Ok(()))) {
+            return Err(FrameReplayError::ReadBackFailed);
+        }
+
+        let mut pixels =
+            Vec::with_capacity((surface_size.width * surface_size.height * 4) as usize);
+
+        {
+            let data = read_back_buffer.slice(..).get_mapped_range();
+
+            for row in data.chunks_exact(padded_bytes_per_row as usize) {
+                for pixel in row[..surface_size.width as usize * 4].chunks_exact(4) {
+                    if is_bgra {
+                        pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                    } else {
+                        pixels.extend_from_slice(pixel);
+                    }
+                }
+            }
+        }
+
+        read_back_buffer.unmap();
+        RgbaImage::from_raw(surface_size.width, surface_size.height, pixels)
+            .ok_or(FrameReplayError::ReadBackFailed)
+    }
+
+    fn create_pipelines(&self, capture: &FrameCapture) -> Vec<ReplayPipeline> {
+        let shader_modules = Vec::from_iter(capture.shaders.iter().map(|shader| {
+            self.device.create_shader_module(ShaderModuleDescriptor {
+                label: Some("replayed shader"),
+                source: ShaderSource::Wgsl(shader.source.as_str().into()),
+            })
+        }));
+
+        Vec::from_iter(capture.pipelines.iter().map(|pipeline| {
+            let bind_group_layouts =
+                Vec::from_iter(pipeline.bind_group_layouts.iter().map(|entries| {
+                    self.device
+                        .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                            label: None,
+                            entries,
+                        })
+                }));
+            let layout = self
+                .device
+                .create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &Vec::from_iter(bind_group_layouts.iter()),
+                    push_constant_ranges: &[],
+                });
+            let buffers =
+                Vec::from_iter(
+                    pipeline
+                        .buffer_layouts
+                        .iter()
+                        .map(|buffer| VertexBufferLayout {
+                            array_stride: buffer.array_stride,
+                            step_mode: buffer.step_mode,
+                            attributes: &buffer.attributes,
+                        }),
+                );
+            let module = &shader_modules[pipeline.shader];
+            let render_pipeline = self
+                .device
+                .create_render_pipeline(&RenderPipelineDescriptor {
+                    label: Some("replayed pipeline"),
+                    layout: Some(&layout),
+                    vertex: VertexState {
+                        module,
+                        entry_point: &pipeline.vertex_entry_point,
+                        buffers: &buffers,
+                    },
+                    primitive: pipeline.primitive,
+                    depth_stencil: pipeline.depth_stencil.clone(),
+                    multisample: Default::default(),
+                    fragment: Some(FragmentState {
+                        module,
+                        entry_point: &pipeline.fragment_entry_point,
+                        targets: &pipeline.targets,
+                    }),
+                    multiview: None,
+                });
+
+            ReplayPipeline {
+                pipeline: render_pipeline,
+                bind_group_layouts,
+                entries: pipeline.bind_group_layouts.clone(),
+                vertex_buffer_sizes: Vec::from_iter(
+                    pipeline
+                        .buffer_layouts
+                        .iter()
+                        .map(|buffer| (buffer.array_stride, buffer.step_mode)),
+                ),
+                depth_format: pipeline
+                    .depth_stencil
+                    .as_ref()
+                    .map(|depth_stencil| depth_stencil.format),
+            }
+        }))
+    }
+
+    /// Returns the bind groups of every group of the pipeline, with the number of dynamic offsets each one takes.
+    fn create_bind_groups(
+        &mut self,
+        capture: &FrameCapture,
+        command: &CapturedCommand,
+        pipeline: &ReplayPipeline,
+        buffers: &[Arc<Buffer>],
+        camera_transform: &Arc<Buffer>,
+        render_targets: &HashMap<usize, Arc<TextureView>>,
+    ) -> Vec<(BindGroup, usize)> {
+        let mut bind_groups = Vec::with_capacity(pipeline.bind_group_layouts.len());
+
+        for (group, (layout, entries)) in pipeline
+            .bind_group_layouts
+            .iter()
+            .zip(&pipeline.entries)
+            .enumerate()
+        {
+            let bound = Vec::from_iter(entries.iter().map(|entry| {
+                let resource = command
+                    .bindings
+                    .iter()
+                    .find(|binding| {
+                        binding.group == group as u32 && binding.binding == entry.binding
+                    })
+                    .map_or(&CapturedResource::Missing, |binding| &binding.resource);
+                let bound = match resource {
+                    CapturedResource::CameraTransform => Bound::Buffer(camera_transform.clone()),
+                    CapturedResource::ScreenSize => {
+                        Bound::Buffer(buffers[capture.screen_size].clone())
+                    }
+                    CapturedResource::Buffer { buffer } => Bound::Buffer(buffers[*buffer].clone()),
+                    _ => self.resolve(capture, resource, entry, render_targets),
+                };
+                (entry.binding, bound)
+            }));
+            let texture_arrays = Vec::from_iter(bound.iter().map(|(_, bound)| match bound {
+                Bound::Textures(views) => Vec::from_iter(views.iter().map(|view| view.as_ref())),
+                _ => Vec::new(),
+            }));
+            let bind_group_entries = Vec::from_iter(bound.iter().zip(&texture_arrays).map(
+                |((binding, bound), texture_array)| BindGroupEntry {
+                    binding: *binding,
+                    resource: match bound {
+                        Bound::Buffer(buffer) => buffer.as_entire_binding(),
+                        Bound::Texture(view) => BindingResource::TextureView(view),
+                        Bound::Textures(_) => BindingResource::TextureViewArray(texture_array),
+                        Bound::Sampler(sampler) => BindingResource::Sampler(sampler),
+                    },
+                },
+            ));
+            let dynamic_offset_count = entries
+                .iter()
+                .filter(|entry| {
+                    matches!(
+                        entry.ty,
+                        BindingType::Buffer {
+                            has_dynamic_offset: true,
+                            ..
+                        }
+                    )
+                })
+                .count();
+
+            bind_groups.push((
+                self.device.create_bind_group(&BindGroupDescriptor {
+                    label: None,
+                    layout,
+                    entries: &bind_group_entries,
+                }),
+                dynamic_offset_count,
+            ));
+        }
+
+        bind_groups
+    }
+
+    /// Resolves a resource that is not one of the captured buffers.
+    fn resolve(
+        &mut self,
+        capture: &FrameCapture,
+        resource: &CapturedResource,
+        entry: &BindGroupLayoutEntry,
+        render_targets: &HashMap<usize, Arc<TextureView>>,
+    ) -> Bound {
+        match entry.ty {
+            BindingType::Buffer {
+                min_binding_size, ..
+            } => Bound::Buffer(
+                self.placeholder_buffer(min_binding_size.map_or(256, |size| size.get().max(256))),
+            ),
+            BindingType::Sampler(SamplerBindingType::Comparison) => {
+                Bound::Sampler(self.comparison_sampler.clone())
+            }
+            BindingType::Sampler(_) => Bound::Sampler(self.sampler.clone()),
+            BindingType::Texture { .. } | BindingType::StorageTexture { .. } => {
+                let textures = match resource {
+                    CapturedResource::Texture { texture } => vec![*texture],
+                    CapturedResource::TextureArray { textures } => textures.clone(),
+                    _ => Vec::new(),
+                };
+                let mut resolve = |index: usize| {
+                    let view = textures
+                        .get(index)
+                        .and_then(|&texture| self.texture(capture, texture, entry, render_targets));
+                    view.unwrap_or_else(|| self.placeholder_texture(entry.ty))
+                };
+
+                match entry.count {
+                    Some(count) => {
+                        Bound::Textures(Vec::from_iter((0..count.get() as usize).map(resolve)))
+                    }
+                    None => Bound::Texture(resolve(0)),
+                }
+            }
+        }
+    }
+
+    /// Returns the texture if it is known and fits the binding.
+    fn texture(
+        &self,
+        capture: &FrameCapture,
+        texture: usize,
+        entry: &BindGroupLayoutEntry,
+        render_targets: &HashMap<usize, Arc<TextureView>>,
+    ) -> Option<Arc<TextureView>> {
+        let is_compatible = matches!(
+            entry.ty,
+            BindingType::Texture {
+                sample_type: TextureSampleType::Float { .. },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            }
+        );
+
+        if !is_compatible {
+            return None;
+        }
+
+        match &capture.textures[texture] {
+            CapturedTexture::Asset { key, .. } => self.asset_textures.get(key).cloned(),
+            // Only rendered if an earlier pass drew into it.
+            CapturedTexture::RenderTarget { .. } => render_targets.get(&texture).cloned(),
+            CapturedTexture::Unknown => None,
+        }
+    }
+
+    fn placeholder_buffer(&mut self, size: BufferAddress) -> Arc<Buffer> {
+        let device = self.device;
+
+        self.placeholder_buffers
+            .entry(size)
+            .or_insert_with(|| {
+                Arc::new(device.create_buffer(&BufferDescriptor {
+                    label: Some("placeholder buffer"),
+                    size,
+                    usage: BufferUsages::VERTEX | BufferUsages::UNIFORM | BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                }))
+            })
+            .clone()
+    }
+
+    /// Creates a magenta checkerboard matching the binding, or an empty texture where a checkerboard cannot be written.
+    fn placeholder_texture(&mut self, ty: BindingType) -> Arc<TextureView> {
+        if let Some(view) = self.placeholder_textures.get(&ty) {
+            return view.clone();
+        }
+
+        let view = Arc::new(create_placeholder_texture(
+            self.device,
+            self.queue,
+            ty,
+            Self::PLACEHOLDER_SIZE,
+        ));
+        self.placeholder_textures.insert(ty, view.clone());
+        view
+    }
+
+    /// Returns the vertex buffers of the command, with zeroed buffers in the slots it did not record.
+    fn collect_vertex_buffers(
+        &mut self,
+        command: &CapturedCommand,
+        pipeline: &ReplayPipeline,
+        buffers: &[Arc<Buffer>],
+    ) -> Vec<(u32, Arc<Buffer>)> {
+        Vec::from_iter(pipeline.vertex_buffer_sizes.iter().enumerate().map(
+            |(slot, &(array_stride, step_mode))| {
+                let slot = slot as u32;
+                let buffer = command
+                    .vertex_buffers
+                    .iter()
+                    .find(|vertex_buffer| vertex_buffer.slot == slot)
+                    .map(|vertex_buffer| buffers[vertex_buffer.buffer].clone())
+                    .unwrap_or_else(|| {
+                        let count = match step_mode {
+                            VertexStepMode::Vertex => command.vertex_count,
+                            VertexStepMode::Instance => command.instance_count,
+                        };
+                        self.placeholder_buffer((array_stride * count as u64).max(256))
+                    });
+                (slot, buffer)
+            },
+        ))
+    }
+}
+
+fn color_load_op(clear: &CapturedClear) -> LoadOp<Color> {
+    match clear.color {
+        Some([r, g, b, a]) => LoadOp::Clear(Color { r, g, b, a }),
+        None => LoadOp::Load,
+    }
+}
+
+/// Creates a texture matching a texture binding, e.g. for bindings whose resource is unknown:
+/// a magenta checkerboard of `size` texels, or an empty texture where a checkerboard cannot be written.
+pub(crate) fn create_placeholder_texture(
+    device: &Device,
+    queue: &Queue,
+    ty: BindingType,
+    size: u32,
+) -> TextureView {
+    let (format, view_dimension, sample_count, usage) = match ty {
+        BindingType::Texture {
+            sample_type,
+            view_dimension,
+            multisampled,
+        } => (
+            match sample_type {
+                TextureSampleType::Float { .. } => TextureFormat::Rgba8Unorm,
+                TextureSampleType::Sint => TextureFormat::Rgba8Sint,
+                TextureSampleType::Uint => TextureFormat::Rgba8Uint,
+                TextureSampleType::Depth => TextureFormat::Depth32Float,
+            },
+            view_dimension,
+            if multisampled { 4 } else { 1 },
+            if multisampled {
+                TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT
+            } else {
+                TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST
+            },
+        ),
+        BindingType::StorageTexture {
+            format,
+            view_dimension,
+            ..
+        } => (format, view_dimension, 1, TextureUsages::STORAGE_BINDING),
+        _ => unreachable!("placeholder textures are only created for texture bindings"),
+    };
+    let (dimension, layers) = match view_dimension {
+        TextureViewDimension::D1 => (TextureDimension::D1, 1),
+        TextureViewDimension::D2 | TextureViewDimension::D2Array => (TextureDimension::D2, 1),
+        TextureViewDimension::Cube | TextureViewDimension::CubeArray => (TextureDimension::D2, 6),
+        TextureViewDimension::D3 => (TextureDimension::D3, 1),
+    };
+    let size = Extent3d {
+        width: size,
+        height: if dimension == TextureDimension::D1 {
+            1
+        } else {
+            size
+        },
+        depth_or_array_layers: layers,
+    };
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("placeholder texture"),
+        size,
+        mip_level_count: 1,
+        sample_count,
+        dimension,
+        format,
+        usage,
+        view_formats: &[],
+    });
+
+    if usage.contains(TextureUsages::COPY_DST) && format != TextureFormat::Depth32Float {
+        let pixels = Vec::from_iter((0..size.width * size.height * layers).flat_map(|index| {
+            let (x, y) = (index % size.width, index / size.width % size.height);
+
+            if (x / 2 + y / 2) % 2 == 0 {
+                [255, 0, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            }
+        }));
+        queue.write_texture(
+            texture.as_image_copy(),
+            &pixels,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.width * 4),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+    }
+
+    texture.create_view(&TextureViewDescriptor {
+        dimension: Some(view_dimension),
+        ..Default::default()
+    })
+}
+
+/// Mean absolute difference of the channels, from 0 for identical images to 1.
+/// Returns `None` if the sizes differ.
+pub fn image_difference(a: &RgbaImage, b: &RgbaImage) -> Option<f32> {
+    if a.dimensions() != b.dimensions() {
+        return None;
+    }
+
+    if a.as_raw().is_empty() {
+        return Some(0.0);
+    }
+
+    let sum = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&a, &b)| a.abs_diff(b) as u64)
+        .sum::<u64>();
+    Some(sum as f32 / (a.as_raw().len() as f32 * 255.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::{
+        create_test_device, CapturedBinding, CapturedBufferLayout, CapturedPass, CapturedPipeline,
+        CapturedShader, CapturedVertexBuffer,
+    };
+    use wgpu::{
+        BufferBindingType, ColorTargetState, ColorWrites, PrimitiveState, ShaderStages,
+        VertexAttribute, VertexFormat,
+    };
+    use zerocopy::AsBytes;
+
+    const SHADER: &str = r#"
+struct Tint {
+    color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> tint: Tint;
+
+@vertex
+fn vs_main(@location(0) position: vec2<f32>) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(position, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return tint.color;
+}
+"#;
+
+    /// A frame drawing a quad over the left half of the surface, as the recorder would write it.
+    fn create_capture() -> FrameCapture {
+        let quad = [
+            -1.0f32, -1.0, 0.0, -1.0, 0.0, 1.0, //
+            -1.0, -1.0, 0.0, 1.0, -1.0, 1.0,
+        ];
+        let tint = [0.0f32, 1.0, 0.0, 1.0];
+
+        FrameCapture {
+            width: 64,
+            height: 32,
+            surface_format: TextureFormat::Bgra8Unorm,
+            screen_size: 0,
+            shaders: vec![CapturedShader {
+                source: SHADER.to_owned(),
+            }],
+            pipelines: vec![CapturedPipeline {
+                shader: 0,
+                vertex_entry_point: "vs_main".to_owned(),
+                fragment_entry_point: "fs_main".to_owned(),
+                bind_group_layouts: vec![vec![BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }]],
+                buffer_layouts: vec![CapturedBufferLayout {
+                    array_stride: 8,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: vec![VertexAttribute {
+                        format: VertexFormat::Float32x2,
+                        offset: 0,
+                        shader_location: 0,
+                    }],
+                }],
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                targets: vec![Some(ColorTargetState {
+                    format: TextureFormat::Bgra8Unorm,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }],
+            textures: Vec::new(),
+            passes: vec![CapturedPass {
+                name: "camera #0".to_owned(),
+                target: CapturedPassTarget::Surface,
+                clear: CapturedClear {
+                    color: Some([0.0, 0.0, 1.0, 1.0]),
+                    depth: None,
+                    stencil: None,
+                },
+                camera_transform: 1,
+                commands: vec![CapturedCommand {
+                    pipeline: 0,
+                    vertex_count: 6,
+                    instance_count: 1,
+                    bindings: vec![CapturedBinding {
+                        group: 0,
+                        binding: 0,
+                        resource: CapturedResource::Buffer { buffer: 3 },
+                    }],
+                    vertex_buffers: vec![CapturedVertexBuffer { slot: 0, buffer: 2 }],
+                    index_buffer: None,
+                    indirect_buffer: None,
+                    instance_properties: Vec::new(),
+                }],
+            }],
+            reference_image: None,
+            buffers: vec![
+                [64.0f32, 32.0, 0.0, 0.0].as_bytes().to_vec(),
+                crate::math::Mat4::identity().as_bytes().to_vec(),
+                quad.as_bytes().to_vec(),
+                tint.as_bytes().to_vec(),
+            ],
+        }
+    }
+
+    #[test]
+    fn check_capture_round_trip() {
+        let mut capture = create_capture();
+        capture.buffers.push(vec![7; 64 * 32 * 4]);
+        capture.reference_image = Some(capture.buffers.len() - 1);
+
+        let mut bytes = Vec::new();
+        capture.write(&mut bytes).unwrap();
+        let loaded = FrameCapture::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded, capture);
+        assert_eq!(loaded.reference_image().unwrap().get_pixel(3, 4).0, [7; 4]);
+
+        bytes[0] = b'X';
+        assert!(matches!(
+            FrameCapture::read(&mut bytes.as_slice()),
+            Err(FrameCaptureError::InvalidMagic)
+        ));
+
+        capture.passes[0].commands[0].pipeline = 1;
+        let mut bytes = Vec::new();
+        capture.write(&mut bytes).unwrap();
+        assert!(matches!(
+            FrameCapture::read(&mut bytes.as_slice()),
+            Err(FrameCaptureError::InvalidReference {
+                kind: "pipeline",
+                index: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn check_replay_matches_original() {
+        let device = if let Some(device) = create_test_device() {
+            device
+        } else {
+            return;
+        };
+        let mut replayer = FrameReplayer::new(&device.device, &device.queue);
+
+        // The original render; the loaded capture must replay into the same image.
+        let capture = create_capture();
+        let original = replayer.render(&capture, None).unwrap();
+        assert_eq!(original.get_pixel(8, 16).0, [0, 255, 0, 255]);
+        assert_eq!(original.get_pixel(56, 16).0, [0, 0, 255, 255]);
+
+        let mut bytes = Vec::new();
+        capture.write(&mut bytes).unwrap();
+        let loaded = FrameCapture::read(&mut bytes.as_slice()).unwrap();
+        let replayed = FrameReplayer::new(&device.device, &device.queue)
+            .render(&loaded, None)
+            .unwrap();
+        assert!(image_difference(&original, &replayed).unwrap() < 0.01);
+
+        // Stepping to before the first command leaves only the clear color.
+        let stepped = replayer.render(&loaded, Some(0)).unwrap();
+        assert_eq!(stepped.get_pixel(8, 16).0, [0, 0, 255, 255]);
+        assert!(0.1 < image_difference(&original, &stepped).unwrap());
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+use wgpu::{
+    Device, Extent3d, SurfaceConfiguration, SurfaceTexture, Texture, TextureDescriptor,
+    TextureDimension,
+};
+
+/// The texture a frame is rendered into: the surface texture, or the offscreen texture of a headless context.
+pub enum FrameTarget {
+    Surface(SurfaceTexture),
+    Offscreen(Arc<Texture>),
+}
+
+impl FrameTarget {
+    pub fn texture(&self) -> &Texture {
+        match self {
+            FrameTarget::Surface(surface_texture) => &surface_texture.texture,
+            FrameTarget::Offscreen(texture) => texture,
+        }
+    }
+
+    /// Shows the surface texture. Offscreen frames stay where they are, to be read back.
+    pub fn present(self) {
+        if let FrameTarget::Surface(surface_texture) = self {
+            surface_texture.present();
+        }
+    }
+}
+
+/// Creates the texture standing in for the surface of a headless context, configured alike.
+pub fn create_offscreen_frame_texture(
+    surface_config: &SurfaceConfiguration,
+    device: &Device,
+) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("offscreen frame texture"),
+        size: Extent3d {
+            width: surface_config.width.max(1),
+            height: surface_config.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: surface_config.format,
+        usage: surface_config.usage,
+        view_formats: &surface_config.view_formats,
+    })
+}
@@ -0,0 +1,145 @@
+use std::{fmt::Debug, sync::Arc};
+use wgpu::{AdapterInfo, Backend, Backends, DeviceType, Features, Limits, PowerPreference};
+
+/// Decides whether an adapter may be picked, given its info.
+pub type AdapterFilter = Arc<dyn Fn(&AdapterInfo) -> bool + Send + Sync>;
+
+/// How a [`GfxContext`](super::GfxContext) picks its adapter and creates its device.
+#[derive(Clone)]
+pub struct GfxContextConfig {
+    /// Graphics backends the adapter may be chosen from.
+    pub backends: Backends,
+    /// Ranks discrete and integrated GPUs. Software adapters always come last.
+    pub power_preference: PowerPreference,
+    /// Restricts the adapter to ones whose name contains this string, case-insensitively.
+    pub adapter_name: Option<String>,
+    /// Restricts the adapter to ones it accepts, on top of `adapter_name`.
+    pub adapter_filter: Option<AdapterFilter>,
+    /// Features the device is created with, e.g. [`Features::POLYGON_MODE_LINE`]. Adapters lacking any of them
+    /// are skipped, and creation fails if no adapter has them all.
+    pub required_features: Features,
+    /// Limits the device is created with. Creation fails if the adapter does not support them.
+    pub required_limits: Limits,
+    /// Limits the device is created with instead, if the adapter supports them.
+    pub preferred_limits: Option<Limits>,
+}
+
+impl GfxContextConfig {
+    /// The limits of a device created on an adapter of `adapter_limits`.
+    pub fn device_limits(&self, adapter_limits: &Limits) -> Limits {
+        match &self.preferred_limits {
+            Some(preferred_limits) if preferred_limits.check_limits(adapter_limits) => {
+                preferred_limits.clone()
+            }
+            _ => self.required_limits.clone(),
+        }
+    }
+
+    /// Ranks the adapter; the highest score is picked.
+    pub fn score_adapter(&self, info: &AdapterInfo) -> i32 {
+        let (discrete_score, integrated_score) = match self.power_preference {
+            PowerPreference::LowPower => (10, 20),
+            _ => (20, 10),
+        };
+        let device_score = match info.device_type {
+            DeviceType::IntegratedGpu => integrated_score,
+            DeviceType::DiscreteGpu => discrete_score,
+            DeviceType::Cpu => -10,
+            _ => 0,
+        };
+        let backend_score = match info.backend {
+            // The Vulkan is available with other backends simultaneously on some platforms.
+            // Because the dedicated backends are preferred over the Vulkan, we set the score of the Vulkan slightly lower than others.
+            Backend::Metal => 2,
+            Backend::Dx12 => 2,
+            Backend::Vulkan => 1,
+            _ => 0,
+        };
+
+        device_score + backend_score
+    }
+}
+
+impl Default for GfxContextConfig {
+    fn default() -> Self {
+        Self {
+            backends: Backends::all(),
+            power_preference: PowerPreference::HighPerformance,
+            adapter_name: None,
+            adapter_filter: None,
+            required_features: Features::empty(),
+            required_limits: if cfg!(target_arch = "wasm32") {
+                Limits::downlevel_webgl2_defaults()
+            } else {
+                Limits::default()
+            },
+            preferred_limits: None,
+        }
+    }
+}
+
+impl Debug for GfxContextConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GfxContextConfig")
+            .field("backends", &self.backends)
+            .field("power_preference", &self.power_preference)
+            .field("adapter_name", &self.adapter_name)
+            .field("adapter_filter", &self.adapter_filter.is_some())
+            .field("required_features", &self.required_features)
+            .field("required_limits", &self.required_limits)
+            .field("preferred_limits", &self.preferred_limits)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapter_info(device_type: DeviceType, backend: Backend) -> AdapterInfo {
+        AdapterInfo {
+            name: String::new(),
+            vendor: 0,
+            device: 0,
+            device_type,
+            driver: String::new(),
+            driver_info: String::new(),
+            backend,
+        }
+    }
+
+    #[test]
+    fn check_power_preference_ranks_gpus() {
+        let discrete = adapter_info(DeviceType::DiscreteGpu, Backend::Vulkan);
+        let integrated = adapter_info(DeviceType::IntegratedGpu, Backend::Vulkan);
+        let cpu = adapter_info(DeviceType::Cpu, Backend::Vulkan);
+
+        let high_performance = GfxContextConfig::default();
+        assert!(
+            high_performance.score_adapter(&integrated) < high_performance.score_adapter(&discrete)
+        );
+
+        let low_power = GfxContextConfig {
+            power_preference: PowerPreference::LowPower,
+            ..Default::default()
+        };
+        assert!(low_power.score_adapter(&discrete) < low_power.score_adapter(&integrated));
+        assert!(low_power.score_adapter(&cpu) < low_power.score_adapter(&discrete));
+    }
+
+    #[test]
+    fn check_preferred_limits_fall_back_to_required() {
+        let preferred_limits = Limits {
+            max_texture_dimension_2d: 16384,
+            ..Limits::default()
+        };
+        let config = GfxContextConfig {
+            required_limits: Limits::default(),
+            preferred_limits: Some(preferred_limits.clone()),
+            ..Default::default()
+        };
+
+        assert_eq!(config.device_limits(&preferred_limits), preferred_limits);
+        assert_eq!(config.device_limits(&Limits::default()), Limits::default());
+    }
+}
@@ -6,6 +6,8 @@ pub struct GlyphLayoutElement {
     pub size: Vec2,
     pub offset: Vec2,
     pub key: GlyphRasterConfig,
+    /// Whether the glyph has no texels, e.g. a space. It still takes its place in the line.
+    pub is_blank: bool,
 }
 
 // TODO: Add vertical align: baseline.
@@ -21,13 +23,7 @@ pub fn compute_glyph_layout(
 
     let mut lines = Vec::with_capacity(4);
 
-    loop {
-        let line = compute_glyph_layout_line(font, font_size, inset, &mut chars);
-
-        if line.elements.is_empty() {
-            break;
-        }
-
+    while let Some(line) = compute_glyph_layout_line(font, font_size, inset, &mut chars) {
         lines.push(line);
     }
 
@@ -58,17 +54,37 @@ pub fn compute_glyph_layout(
     lines.into_iter().flat_map(|line| line.elements).collect()
 }
 
+/// Size of the text as laid out by [`compute_glyph_layout`], without rasterizing any glyph: the width of its widest
+/// line by the height of its lines.
+pub fn measure_glyph_layout(
+    font: &Font,
+    font_size: f32,
+    mut chars: impl Iterator<Item = char>,
+) -> UISize {
+    let mut size = UISize::new();
+
+    while let Some(line) = compute_glyph_layout_line(font, font_size, 0f32, &mut chars) {
+        size.width = size.width.max(line.width);
+        size.height += font_size;
+    }
+
+    size
+}
+
 struct GlyphLineLayout {
     pub width: f32,
     pub elements: Vec<GlyphLayoutElement>,
 }
 
+/// Lays out the characters up to the next line break, or returns `None` if there are no characters left.
+/// An empty line between two line breaks is still a line.
 fn compute_glyph_layout_line(
     font: &Font,
     font_size: f32,
     inset: f32,
     chars: &mut impl Iterator<Item = char>,
-) -> GlyphLineLayout {
+) -> Option<GlyphLineLayout> {
+    let mut is_empty = true;
     let mut prev = None;
     let mut acc_width = 0.0f32;
     // let mut acc_height_min = 0.0f32;
@@ -77,6 +93,8 @@ fn compute_glyph_layout_line(
     let mut elements = Vec::new();
 
     for c in chars {
+        is_empty = false;
+
         if c == '\n' {
             break;
         }
@@ -97,11 +115,13 @@ fn compute_glyph_layout_line(
         elements.push(GlyphLayoutElement {
             size,
             offset,
+            // Glyphs are rasterized at the SDF font size whatever size they are drawn at, so they share one key.
             key: GlyphRasterConfig {
                 glyph_index: font.data.lookup_glyph_index(c),
-                px: font_size,
+                px: font.sdf_font_size,
                 font_hash: font.data.file_hash(),
             },
+            is_blank: metrics.width == 0 || metrics.height == 0,
         });
 
         acc_width += kern + metrics.advance_width;
@@ -112,8 +132,77 @@ fn compute_glyph_layout_line(
         prev = Some(c);
     }
 
-    GlyphLineLayout {
+    if is_empty {
+        return None;
+    }
+
+    Some(GlyphLineLayout {
         width: acc_width,
         elements,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fontdue::FontSettings;
+
+    fn font() -> Font {
+        Font::with_default(
+            fontdue::Font::from_bytes(
+                include_bytes!("../../../r3d-editor/assets/fonts/NotoSans-Regular.ttf") as &[u8],
+                FontSettings::default(),
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn check_empty_lines_are_measured() {
+        let font = font();
+
+        assert_eq!(measure_glyph_layout(&font, 16f32, "".chars()).height, 0f32);
+        assert_eq!(
+            measure_glyph_layout(&font, 16f32, "a\n".chars()).height,
+            16f32
+        );
+        assert_eq!(
+            measure_glyph_layout(&font, 16f32, "a\n\nb".chars()).height,
+            48f32
+        );
+    }
+
+    #[test]
+    fn check_measure_takes_the_widest_line() {
+        let font = font();
+        let short = measure_glyph_layout(&font, 16f32, "ab".chars());
+        let long = measure_glyph_layout(&font, 16f32, "abcd".chars());
+        let both = measure_glyph_layout(&font, 16f32, "ab\nabcd\nab".chars());
+
+        assert!(short.width < long.width);
+        assert_eq!(both.width, long.width);
+    }
+
+    #[test]
+    fn check_glyphs_share_a_key_across_font_sizes() {
+        let font = font();
+        let small = compute_glyph_layout(
+            &font,
+            16f32,
+            UISize::new(),
+            &GlyphLayoutConfig::default(),
+            "a ".chars(),
+        );
+        let large = compute_glyph_layout(
+            &font,
+            32f32,
+            UISize::new(),
+            &GlyphLayoutConfig::default(),
+            "a ".chars(),
+        );
+
+        assert_eq!(small[0].key, large[0].key);
+        assert!(!small[0].is_blank);
+        assert!(small[1].is_blank);
     }
 }
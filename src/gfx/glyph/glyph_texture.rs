@@ -1,5 +1,5 @@
 use crate::gfx::{BindGroupLayoutCache, FontHandle, SpriteTexelMapping, Texture, TextureHandle};
-use std::{cmp::max, sync::Arc};
+use std::sync::Arc;
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutEntry, BindingResource,
     BindingType, Device, Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, Queue,
@@ -7,14 +7,76 @@ use wgpu::{
     TextureViewDimension,
 };
 
+/// Width and height of a glyph texture, in texels.
+const GLYPH_TEXTURE_SIZE: u16 = 2048;
+
+/// A row of glyphs no taller than it, filled from the left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct GlyphShelf {
+    y: u16,
+    height: u16,
+    offset_x: u16,
+}
+
+/// Shelf packing of the glyphs of a texture. A glyph goes to the shelf that wastes the least height for it, or to a
+/// new shelf below the last one; once neither fits, the texture is full.
+#[derive(Debug, Clone)]
+struct GlyphShelfPacker {
+    size: u16,
+    shelves: Vec<GlyphShelf>,
+    bottom: u16,
+}
+
+impl GlyphShelfPacker {
+    fn new(size: u16) -> Self {
+        Self {
+            size,
+            shelves: Vec::with_capacity(32),
+            bottom: 0,
+        }
+    }
+
+    /// Returns the top left texel of the glyph, if it fits.
+    fn allocate(&mut self, width: u16, height: u16) -> Option<(u16, u16)> {
+        if self.size < width || self.size < height {
+            return None;
+        }
+
+        let shelf = self
+            .shelves
+            .iter_mut()
+            .filter(|shelf| height <= shelf.height && width <= self.size - shelf.offset_x)
+            .min_by_key(|shelf| shelf.height - height);
+
+        if let Some(shelf) = shelf {
+            let offset = (shelf.offset_x, shelf.y);
+            shelf.offset_x += width;
+            return Some(offset);
+        }
+
+        if self.size - self.bottom < height {
+            return None;
+        }
+
+        self.shelves.push(GlyphShelf {
+            y: self.bottom,
+            height,
+            offset_x: width,
+        });
+        let offset = (0, self.bottom);
+        self.bottom += height;
+        Some(offset)
+    }
+}
+
+/// A page of the glyph atlas of a font. The glyphs are rasterized into it as they are needed; once it is full, the
+/// [`GlyphManager`](super::GlyphManager) adds another page.
 pub struct GlyphTexture {
     texture_bind_group: Arc<BindGroup>,
     sampler_bind_group: Arc<BindGroup>,
     texture: TextureHandle,
     font: FontHandle,
-    offset_x: u16,
-    offset_y: u16,
-    line_height: u16,
+    packer: GlyphShelfPacker,
 }
 
 impl GlyphTexture {
@@ -23,7 +85,12 @@ impl GlyphTexture {
         bind_group_layout_cache: &mut BindGroupLayoutCache,
         font: FontHandle,
     ) -> Self {
-        let texture = Texture::create_empty(2048u16, 2048u16, TextureFormat::R8Unorm, device);
+        let texture = Texture::create_empty(
+            GLYPH_TEXTURE_SIZE,
+            GLYPH_TEXTURE_SIZE,
+            TextureFormat::R8Unorm,
+            device,
+        );
         let texture_bind_group_layout =
             bind_group_layout_cache.create_layout(vec![BindGroupLayoutEntry {
                 binding: 0,
@@ -68,9 +135,7 @@ impl GlyphTexture {
             sampler_bind_group,
             texture: TextureHandle::new(texture),
             font,
-            offset_x: 0,
-            offset_y: 0,
-            line_height: 0,
+            packer: GlyphShelfPacker::new(GLYPH_TEXTURE_SIZE),
         }
     }
 
@@ -97,33 +162,20 @@ impl GlyphTexture {
         sdf_height: u16,
         sdf: &[u8],
     ) -> Option<SpriteTexelMapping> {
-        if 2048 < self.offset_y + sdf_height {
-            return None;
-        }
-
-        if 2048 < self.offset_x + sdf_width {
-            self.offset_x = 0;
-            self.offset_y += self.line_height;
-            self.line_height = sdf_height;
-
-            if 2048 < self.offset_y + sdf_height {
-                return None;
-            }
-        }
-
+        let (offset_x, offset_y) = self.packer.allocate(sdf_width, sdf_height)?;
         let mapping = SpriteTexelMapping::new(
-            self.offset_x as _,
-            (self.offset_x + sdf_width) as _,
-            self.offset_y as _,
-            (self.offset_y + sdf_height) as _,
+            offset_x as _,
+            (offset_x + sdf_width) as _,
+            offset_y as _,
+            (offset_y + sdf_height) as _,
         );
         queue.write_texture(
             ImageCopyTexture {
                 texture: &self.texture.texture,
                 mip_level: 0,
                 origin: Origin3d {
-                    x: self.offset_x as u32,
-                    y: self.offset_y as u32,
+                    x: offset_x as u32,
+                    y: offset_y as u32,
                     z: 0,
                 },
                 aspect: TextureAspect::All,
@@ -141,9 +193,37 @@ impl GlyphTexture {
             },
         );
 
-        self.offset_x += sdf_width;
-        self.line_height = max(self.line_height, sdf_height);
-
         Some(mapping)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_glyphs_go_to_the_tightest_shelf() {
+        let mut packer = GlyphShelfPacker::new(64);
+
+        assert_eq!(packer.allocate(16, 20), Some((0, 0)));
+        assert_eq!(packer.allocate(16, 10), Some((16, 0)));
+        assert_eq!(packer.allocate(16, 12), Some((32, 0)));
+        // Too wide for the first shelf, so it opens a second one.
+        assert_eq!(packer.allocate(40, 8), Some((0, 20)));
+        // Fits both shelves, but the second one wastes no height.
+        assert_eq!(packer.allocate(16, 8), Some((40, 20)));
+        assert_eq!(packer.allocate(16, 20), Some((48, 0)));
+    }
+
+    #[test]
+    fn check_a_full_texture_rejects_glyphs() {
+        let mut packer = GlyphShelfPacker::new(32);
+
+        assert_eq!(packer.allocate(64, 8), None);
+        assert_eq!(packer.allocate(32, 24), Some((0, 0)));
+        assert_eq!(packer.allocate(8, 16), None);
+        assert_eq!(packer.allocate(8, 8), Some((0, 24)));
+        assert_eq!(packer.allocate(24, 8), Some((8, 24)));
+        assert_eq!(packer.allocate(1, 1), None);
+    }
+}
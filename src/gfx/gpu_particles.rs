@@ -0,0 +1,93 @@
+use super::{GfxContextHandle, GpuCulling, ParticleParams};
+use std::{borrow::Cow, mem::size_of};
+use wgpu::{
+    BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType,
+    BufferBindingType, BufferSize, ComputePipeline, ComputePipelineDescriptor,
+    PipelineLayoutDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages,
+};
+
+/// Compute pipeline that simulates [`ParticleSystem`](super::ParticleSystem)s on the GPU.
+/// The alive particles are compacted into a buffer consumed by `draw_indirect`, so nothing is read back.
+pub struct GpuParticles {
+    bind_group_layout: BindGroupLayout,
+    pipeline: ComputePipeline,
+}
+
+impl GpuParticles {
+    pub const WORKGROUP_SIZE: u32 = 64;
+    /// The most particles a system can have on the GPU, limited by the number of workgroups of a dispatch.
+    pub const MAX_PARTICLES: u32 = 65535 * Self::WORKGROUP_SIZE;
+
+    /// Returns `true` if the device can run compute shaders and issue indirect draws.
+    pub fn is_supported(gfx_ctx: &GfxContextHandle) -> bool {
+        GpuCulling::is_supported(gfx_ctx)
+    }
+
+    /// Creates the simulation pipeline. Returns `None` if the device does not support it; see [`is_supported`](Self::is_supported).
+    pub fn new(gfx_ctx: &GfxContextHandle) -> Option<Self> {
+        if !Self::is_supported(gfx_ctx) {
+            return None;
+        }
+
+        let device = &gfx_ctx.device;
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("particle simulation shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "./built_in_shaders/particle_simulation.wgsl"
+            ))),
+        });
+        let storage_entry = |binding: u32, read_only: bool| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("particle simulation bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(size_of::<ParticleParams>() as u64),
+                    },
+                    count: None,
+                },
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, false),
+                storage_entry(4, false),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("particle simulation pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("particle simulation pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+        });
+
+        Some(Self {
+            bind_group_layout,
+            pipeline,
+        })
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn pipeline(&self) -> &ComputePipeline {
+        &self.pipeline
+    }
+}
@@ -0,0 +1,178 @@
+use super::{GfxContextHandle, MAX_FRAMES_IN_FLIGHT};
+use std::{
+    mem::size_of,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+};
+use wgpu::{
+    Buffer, BufferAddress, BufferDescriptor, BufferUsages, CommandEncoder, Features, MapMode,
+    QuerySet, QuerySetDescriptor, QueryType,
+};
+
+const READBACK_FREE: u8 = 0;
+const READBACK_PENDING: u8 = 1;
+const READBACK_MAPPED: u8 = 2;
+
+struct TimerReadback {
+    buffer: Arc<Buffer>,
+    state: Arc<AtomicU8>,
+    frame: u64,
+}
+
+/// Measures the GPU time of whole frames, or of a part of them, with timestamp queries.
+/// The timestamps are read back asynchronously, so a timing arrives a few frames after its frame.
+pub struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readbacks: Vec<TimerReadback>,
+    period_ns: f32,
+    frame: u64,
+    last_frame: u64,
+    last_ms: Option<f32>,
+}
+
+impl GpuTimer {
+    const SIZE: BufferAddress = size_of::<[u64; 2]>() as BufferAddress;
+
+    /// Returns `true` if the device has been created with timestamp queries.
+    pub fn is_supported(gfx_ctx: &GfxContextHandle) -> bool {
+        gfx_ctx
+            .device
+            .features()
+            .contains(Features::TIMESTAMP_QUERY)
+    }
+
+    /// Creates the timer. Returns `None` if the device does not support it; see [`is_supported`](Self::is_supported).
+    pub fn new(gfx_ctx: &GfxContextHandle) -> Option<Self> {
+        Self::with_label(gfx_ctx, "frame timer")
+    }
+
+    /// Creates a timer whose GPU resources are labelled after `label`, to tell it apart from the frame timer.
+    pub fn with_label(gfx_ctx: &GfxContextHandle, label: &str) -> Option<Self> {
+        if !Self::is_supported(gfx_ctx) {
+            return None;
+        }
+
+        let device = &gfx_ctx.device;
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: Some(&format!("{} query set", label)),
+            ty: QueryType::Timestamp,
+            count: 2,
+        });
+        let resolve_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some(&format!("{} resolve buffer", label)),
+            size: Self::SIZE,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        // One more than the frames in flight, so that a free one is usually there when a frame begins.
+        let readbacks = Vec::from_iter((0..=MAX_FRAMES_IN_FLIGHT).map(|_| TimerReadback {
+            buffer: Arc::new(device.create_buffer(&BufferDescriptor {
+                label: Some(&format!("{} readback buffer", label)),
+                size: Self::SIZE,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })),
+            state: Arc::new(AtomicU8::new(READBACK_FREE)),
+            frame: 0,
+        }));
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readbacks,
+            period_ns: gfx_ctx.queue.get_timestamp_period(),
+            frame: 0,
+            last_frame: 0,
+            last_ms: None,
+        })
+    }
+
+    /// GPU time of the newest frame whose timestamps have been read back, in milliseconds.
+    pub fn last_ms(&self) -> Option<f32> {
+        self.last_ms
+    }
+
+    /// Records the start of a frame. Returns the readback slot to pass to [`end`](Self::end),
+    /// or `None` if every slot is still waiting for an earlier frame, in which case this frame is not timed.
+    pub fn begin(&mut self, encoder: &mut CommandEncoder) -> Option<usize> {
+        let slot = self
+            .readbacks
+            .iter()
+            .position(|readback| readback.state.load(Ordering::Acquire) == READBACK_FREE)?;
+        encoder.write_timestamp(&self.query_set, 0);
+        Some(slot)
+    }
+
+    /// Records the end of the frame started with [`begin`](Self::begin) and copies the timestamps into the slot.
+    pub fn end(&mut self, encoder: &mut CommandEncoder, slot: usize) {
+        encoder.write_timestamp(&self.query_set, 1);
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readbacks[slot].buffer,
+            0,
+            Self::SIZE,
+        );
+    }
+
+    /// Starts reading the slot back. Must be called after the frame has been submitted.
+    pub fn read_back(&mut self, slot: usize) {
+        self.frame += 1;
+
+        let readback = &mut self.readbacks[slot];
+        readback.frame = self.frame;
+        readback.state.store(READBACK_PENDING, Ordering::Release);
+
+        let state = readback.state.clone();
+        readback
+            .buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                // A failed mapping is dropped; the slot is reused as if it had been read.
+                state.store(
+                    if result.is_ok() {
+                        READBACK_MAPPED
+                    } else {
+                        READBACK_FREE
+                    },
+                    Ordering::Release,
+                );
+            });
+    }
+
+    /// Collects the timings that have arrived since the last call.
+    pub fn collect(&mut self) {
+        for readback in &mut self.readbacks {
+            if readback.state.load(Ordering::Acquire) != READBACK_MAPPED {
+                continue;
+            }
+
+            let timestamps = {
+                let view = readback.buffer.slice(..).get_mapped_range();
+                let mut timestamps = [0u64; 2];
+
+                for (index, timestamp) in timestamps.iter_mut().enumerate() {
+                    let bytes = &view[index * size_of::<u64>()..(index + 1) * size_of::<u64>()];
+                    *timestamp = u64::from_le_bytes(bytes.try_into().unwrap());
+                }
+
+                timestamps
+            };
+            readback.buffer.unmap();
+            readback.state.store(READBACK_FREE, Ordering::Release);
+
+            if readback.frame < self.last_frame {
+                continue;
+            }
+
+            self.last_frame = readback.frame;
+            self.last_ms = Some(
+                timestamps[1].saturating_sub(timestamps[0]) as f32 * self.period_ns / 1_000_000.0,
+            );
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use super::{
+    BuiltInShaderManager, DepthStencilMode, GfxContext, GfxContextConfig, GfxContextCreationError,
+    GfxContextHandle, RenderManager, ScreenshotError, ShaderManager,
+};
+use image::RgbaImage;
+use wgpu::{CommandEncoder, Maintain, TextureView, TextureViewDescriptor};
+use winit::dpi::PhysicalSize;
+
+/// The gfx stack without a window or an engine loop, rendering into an offscreen texture,
+/// e.g. for golden-image tests on CI.
+pub struct HeadlessGfx {
+    gfx_ctx: GfxContextHandle,
+    render_mgr: RenderManager,
+    shader_mgr: ShaderManager,
+    built_in_shader_mgr: BuiltInShaderManager,
+}
+
+impl HeadlessGfx {
+    pub async fn new(
+        config: &GfxContextConfig,
+        width: u32,
+        height: u32,
+        depth_stencil_mode: DepthStencilMode,
+    ) -> Result<Self, GfxContextCreationError> {
+        let size = PhysicalSize::new(width, height);
+        let gfx_ctx = GfxContextHandle::new(GfxContext::new_headless(config, size).await?);
+        let mut render_mgr = RenderManager::new(gfx_ctx.clone(), size, depth_stencil_mode);
+        let shader_mgr = ShaderManager::new(gfx_ctx.clone(), None);
+        let mut built_in_shader_mgr = BuiltInShaderManager::new();
+        built_in_shader_mgr.init(&shader_mgr, render_mgr.bind_group_layout_cache());
+
+        Ok(Self {
+            gfx_ctx,
+            render_mgr,
+            shader_mgr,
+            built_in_shader_mgr,
+        })
+    }
+
+    pub fn gfx_ctx(&self) -> &GfxContextHandle {
+        &self.gfx_ctx
+    }
+
+    pub fn render_mgr(&self) -> &RenderManager {
+        &self.render_mgr
+    }
+
+    pub fn render_mgr_mut(&mut self) -> &mut RenderManager {
+        &mut self.render_mgr
+    }
+
+    pub fn shader_mgr(&self) -> &ShaderManager {
+        &self.shader_mgr
+    }
+
+    pub fn built_in_shader_mgr(&self) -> &BuiltInShaderManager {
+        &self.built_in_shader_mgr
+    }
+
+    /// The render manager along with the shader managers, e.g. to obtain the pipelines of renderers.
+    pub fn split_mut(&mut self) -> (&mut RenderManager, &ShaderManager, &BuiltInShaderManager) {
+        (
+            &mut self.render_mgr,
+            &self.shader_mgr,
+            &self.built_in_shader_mgr,
+        )
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let size = PhysicalSize::new(width, height);
+        self.gfx_ctx.resize(size);
+        self.render_mgr.resize(size);
+    }
+
+    /// Renders a frame with `encode`, given the view the scene is drawn into: the offscreen texture in the format of
+    /// [`RenderManager::surface_output`], or the target of the post-processing stack while it is active. Blocks until
+    /// the frame has been read back.
+    pub fn render_frame(
+        &mut self,
+        encode: impl FnOnce(&mut RenderManager, &mut CommandEncoder, &TextureView),
+    ) -> Result<RgbaImage, ScreenshotError> {
+        self.render_mgr.begin_frame();
+
+        // The offscreen texture is always there to be acquired.
+        let frame_target = self.render_mgr.acquire_frame_target().unwrap();
+        let frame_target_view = frame_target.texture().create_view(&TextureViewDescriptor {
+            format: Some(self.render_mgr.surface_output().format),
+            ..Default::default()
+        });
+        let post_process_view = self.render_mgr.prepare_post_processing();
+        let mut encoder = self.render_mgr.create_encoder();
+        encode(
+            &mut self.render_mgr,
+            &mut encoder,
+            post_process_view.as_deref().unwrap_or(&frame_target_view),
+        );
+        self.render_mgr
+            .encode_post_processing(&mut encoder, &frame_target_view);
+
+        let image = self.render_mgr.capture_frame();
+        self.render_mgr
+            .encode_screenshots(&mut encoder, frame_target.texture());
+        self.render_mgr.finish_frame(vec![encoder.finish()]);
+        self.render_mgr.present(frame_target);
+
+        let device = &self.gfx_ctx.device;
+        let render_mgr = &mut self.render_mgr;
+        image.block_on(|| {
+            device.poll(Maintain::Wait);
+            render_mgr.readbacks_mut().collect();
+        })
+    }
+}
+
+/// Creates a [`HeadlessDevice`](super::HeadlessDevice) for tests, or `None` if there is nothing to run on, e.g. on a
+/// CI machine without any GPU or software rasterizer.
+#[cfg(test)]
+pub(crate) fn create_test_device() -> Option<super::HeadlessDevice> {
+    match pollster::block_on(super::HeadlessDevice::new(wgpu::Backends::all())) {
+        Ok(device) => Some(device),
+        Err(super::FrameReplayError::AdapterNotFound) => None,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+/// Creates a [`HeadlessGfx`] of the size for tests, or `None` if there is nothing to render on.
+#[cfg(test)]
+pub(crate) fn create_test_gfx(
+    width: u32,
+    height: u32,
+    depth_stencil_mode: DepthStencilMode,
+) -> Option<HeadlessGfx> {
+    match pollster::block_on(HeadlessGfx::new(
+        &GfxContextConfig::default(),
+        width,
+        height,
+        depth_stencil_mode,
+    )) {
+        Ok(gfx) => Some(gfx),
+        Err(GfxContextCreationError::AdapterNotFound) => None,
+        Err(err) => panic!("{}", err),
+    }
+}
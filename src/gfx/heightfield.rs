@@ -0,0 +1,531 @@
+use crate::{
+    math::{Frustum, Mat4, Vec2, Vec3},
+    object::transform_aabb,
+};
+use image::DynamicImage;
+use thiserror::Error;
+
+/// Quads along each side of a terrain chunk at the finest level of detail.
+pub const TERRAIN_CHUNK_QUADS: u32 = 64;
+/// Coarsest level of detail, at which a chunk is a single quad.
+pub const TERRAIN_MAX_LOD: u32 = TERRAIN_CHUNK_QUADS.trailing_zeros();
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum HeightfieldError {
+    #[error("heightfield must have at least 2x2 samples, but has {width}x{depth}")]
+    TooSmall { width: u32, depth: u32 },
+    #[error("heightfield of {width}x{depth} samples exceeds the texture size limit of {limit}")]
+    TooLarge { width: u32, depth: u32, limit: u32 },
+    #[error("{expected} heights are expected, but {actual} are given")]
+    SizeMismatch { expected: usize, actual: usize },
+    #[error("region {region:?} is outside of the {width}x{depth} heightfield")]
+    OutOfBounds {
+        region: HeightfieldRegion,
+        width: u32,
+        depth: u32,
+    },
+}
+
+/// Rectangle of samples, `width` along x and `depth` along z.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HeightfieldRegion {
+    pub x: u32,
+    pub z: u32,
+    pub width: u32,
+    pub depth: u32,
+}
+
+impl HeightfieldRegion {
+    pub fn new(x: u32, z: u32, width: u32, depth: u32) -> Self {
+        Self { x, z, width, depth }
+    }
+}
+
+/// Chunk of a heightfield that passed the frustum test, with the level of detail to draw it at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VisibleTerrainChunk {
+    pub index: usize,
+    pub lod: u32,
+}
+
+/// 16-bit heights sampled on a regular grid spanning `size` on the xz plane, from the origin.
+/// A height of `u16::MAX` is `height_scale` units high.
+///
+/// The grid is split into chunks of [`TERRAIN_CHUNK_QUADS`] quads per side, whose height ranges are kept up to date
+/// so that their bounding boxes can be culled without looking at the samples.
+#[derive(Debug, Clone)]
+pub struct Heightfield {
+    width: u32,
+    depth: u32,
+    heights: Vec<u16>,
+    size: Vec2,
+    height_scale: f32,
+    chunk_ranges: Vec<(u16, u16)>,
+}
+
+impl Heightfield {
+    /// Creates a heightfield from `width * depth` heights in rows of increasing z.
+    pub fn new(
+        width: u32,
+        depth: u32,
+        heights: Vec<u16>,
+        size: Vec2,
+        height_scale: f32,
+    ) -> Result<Self, HeightfieldError> {
+        if width < 2 || depth < 2 {
+            return Err(HeightfieldError::TooSmall { width, depth });
+        }
+
+        let expected = width as usize * depth as usize;
+
+        if heights.len() != expected {
+            return Err(HeightfieldError::SizeMismatch {
+                expected,
+                actual: heights.len(),
+            });
+        }
+
+        let mut this = Self {
+            width,
+            depth,
+            heights,
+            size,
+            height_scale,
+            chunk_ranges: Vec::new(),
+        };
+        let (chunks_x, chunks_z) = this.chunk_counts();
+        this.chunk_ranges = vec![(0, 0); (chunks_x * chunks_z) as usize];
+
+        for index in 0..this.chunk_ranges.len() {
+            this.update_chunk_range(index);
+        }
+
+        Ok(this)
+    }
+
+    /// Creates a heightfield from a grayscale image, one sample per pixel.
+    /// 8-bit images are widened, so that their brightest value is also `height_scale` high.
+    pub fn from_image(
+        image: &DynamicImage,
+        size: Vec2,
+        height_scale: f32,
+    ) -> Result<Self, HeightfieldError> {
+        let image = image.to_luma16();
+        let (width, depth) = image.dimensions();
+        Self::new(width, depth, image.into_raw(), size, height_scale)
+    }
+
+    /// Number of samples along x.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Number of samples along z.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn heights(&self) -> &[u16] {
+        &self.heights
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.size
+    }
+
+    pub fn height_scale(&self) -> f32 {
+        self.height_scale
+    }
+
+    /// Distance between two neighbouring samples along x and z.
+    pub fn spacing(&self) -> Vec2 {
+        Vec2::new(
+            self.size.x / (self.width - 1) as f32,
+            self.size.y / (self.depth - 1) as f32,
+        )
+    }
+
+    /// Height of the sample, clamping the coordinates to the grid.
+    pub fn sample(&self, x: u32, z: u32) -> f32 {
+        let x = x.min(self.width - 1);
+        let z = z.min(self.depth - 1);
+        let height = self.heights[(z * self.width + x) as usize];
+        height as f32 / u16::MAX as f32 * self.height_scale
+    }
+
+    /// Height at the position on the xz plane, interpolated bilinearly between the samples around it.
+    /// `None` if the position is outside of the heightfield.
+    pub fn height_at(&self, position: Vec2) -> Option<f32> {
+        if !(0.0..=self.size.x).contains(&position.x) || !(0.0..=self.size.y).contains(&position.y)
+        {
+            return None;
+        }
+
+        let spacing = self.spacing();
+        let x = (position.x / spacing.x).min((self.width - 1) as f32);
+        let z = (position.y / spacing.y).min((self.depth - 1) as f32);
+
+        let x0 = (x.floor() as u32).min(self.width - 2);
+        let z0 = (z.floor() as u32).min(self.depth - 2);
+        let tx = x - x0 as f32;
+        let tz = z - z0 as f32;
+
+        let near = lerp(self.sample(x0, z0), self.sample(x0 + 1, z0), tx);
+        let far = lerp(self.sample(x0, z0 + 1), self.sample(x0 + 1, z0 + 1), tx);
+        Some(lerp(near, far, tz))
+    }
+
+    /// Upward surface normal at the position on the xz plane, from the slope between the heights a sample away.
+    /// `None` if the position is outside of the heightfield.
+    pub fn normal_at(&self, position: Vec2) -> Option<Vec3> {
+        self.height_at(position)?;
+
+        let spacing = self.spacing();
+        let left = (position.x - spacing.x).max(0.0);
+        let right = (position.x + spacing.x).min(self.size.x);
+        let back = (position.y - spacing.y).max(0.0);
+        let front = (position.y + spacing.y).min(self.size.y);
+
+        let slope_x = (self.height_at(Vec2::new(right, position.y))?
+            - self.height_at(Vec2::new(left, position.y))?)
+            / (right - left);
+        let slope_z = (self.height_at(Vec2::new(position.x, front))?
+            - self.height_at(Vec2::new(position.x, back))?)
+            / (front - back);
+
+        Some(Vec3::new(-slope_x, 1.0, -slope_z).normalized())
+    }
+
+    /// Replaces the heights of the region with `data`, given in rows of increasing z in the same units as
+    /// [`height_at`](Self::height_at). Only the chunks overlapping the region are updated.
+    pub fn set_heights(
+        &mut self,
+        region: HeightfieldRegion,
+        data: &[f32],
+    ) -> Result<(), HeightfieldError> {
+        if self.width < region.x.saturating_add(region.width)
+            || self.depth < region.z.saturating_add(region.depth)
+        {
+            return Err(HeightfieldError::OutOfBounds {
+                region,
+                width: self.width,
+                depth: self.depth,
+            });
+        }
+
+        let expected = region.width as usize * region.depth as usize;
+
+        if data.len() != expected {
+            return Err(HeightfieldError::SizeMismatch {
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        if expected == 0 {
+            return Ok(());
+        }
+
+        for (row, heights) in data.chunks_exact(region.width as usize).enumerate() {
+            let start = ((region.z + row as u32) * self.width + region.x) as usize;
+
+            for (target, &height) in self.heights[start..start + heights.len()]
+                .iter_mut()
+                .zip(heights)
+            {
+                *target = (height / self.height_scale * u16::MAX as f32)
+                    .round()
+                    .clamp(0.0, u16::MAX as f32) as u16;
+            }
+        }
+
+        for index in self.chunks_in_region(region) {
+            self.update_chunk_range(index);
+        }
+
+        Ok(())
+    }
+
+    /// Number of chunks along x and z. Chunks on the far edges may be smaller than the others.
+    pub fn chunk_counts(&self) -> (u32, u32) {
+        (
+            (self.width - 1 + TERRAIN_CHUNK_QUADS - 1) / TERRAIN_CHUNK_QUADS,
+            (self.depth - 1 + TERRAIN_CHUNK_QUADS - 1) / TERRAIN_CHUNK_QUADS,
+        )
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_ranges.len()
+    }
+
+    /// First sample of the chunk, as `(x, z)`.
+    pub fn chunk_origin(&self, index: usize) -> (u32, u32) {
+        let (chunks_x, _) = self.chunk_counts();
+        (
+            index as u32 % chunks_x * TERRAIN_CHUNK_QUADS,
+            index as u32 / chunks_x * TERRAIN_CHUNK_QUADS,
+        )
+    }
+
+    /// How far the skirts of the chunk hang below its edges. It covers the height range of the chunk, which bounds
+    /// the gap to a neighbour drawn at another level of detail, plus a sample spacing to hide rounding gaps.
+    pub fn skirt_depth(&self, index: usize) -> f32 {
+        let (min, max) = self.chunk_ranges[index];
+        let spacing = self.spacing();
+        (max - min) as f32 / u16::MAX as f32 * self.height_scale + spacing.x.min(spacing.y)
+    }
+
+    /// Bounding box of the chunk and its skirts in local space, as `(min, max)`.
+    pub fn chunk_bounds(&self, index: usize) -> (Vec3, Vec3) {
+        let (x, z) = self.chunk_origin(index);
+        let (min, max) = self.chunk_ranges[index];
+        let spacing = self.spacing();
+        let end_x = (x + TERRAIN_CHUNK_QUADS).min(self.width - 1);
+        let end_z = (z + TERRAIN_CHUNK_QUADS).min(self.depth - 1);
+
+        (
+            Vec3::new(
+                x as f32 * spacing.x,
+                min as f32 / u16::MAX as f32 * self.height_scale - self.skirt_depth(index),
+                z as f32 * spacing.y,
+            ),
+            Vec3::new(
+                end_x as f32 * spacing.x,
+                max as f32 / u16::MAX as f32 * self.height_scale,
+                end_z as f32 * spacing.y,
+            ),
+        )
+    }
+
+    /// Culls the chunks against the frustum and picks a level of detail for the rest by their distance to the camera.
+    /// `matrix` places the heightfield in the world, where the frustum and the camera position are given.
+    pub fn visible_chunks(
+        &self,
+        matrix: &Mat4,
+        frustum: &Frustum,
+        camera_position: Vec3,
+        lod_distance: f32,
+    ) -> Vec<VisibleTerrainChunk> {
+        Vec::from_iter((0..self.chunk_count()).filter_map(|index| {
+            let (min, max) = self.chunk_bounds(index);
+            let (min, max) = transform_aabb(min, max, matrix);
+
+            if !frustum.intersects_aabb(min, max) {
+                return None;
+            }
+
+            let closest = Vec3::max(min, Vec3::min(camera_position, max));
+            Some(VisibleTerrainChunk {
+                index,
+                lod: terrain_chunk_lod(Vec3::distance(camera_position, closest), lod_distance),
+            })
+        }))
+    }
+
+    /// Indices of the chunks containing any sample of the region. Samples on a chunk border belong to both sides.
+    fn chunks_in_region(&self, region: HeightfieldRegion) -> impl Iterator<Item = usize> {
+        let (chunks_x, chunks_z) = self.chunk_counts();
+        let first_x = region.x.saturating_sub(1) / TERRAIN_CHUNK_QUADS;
+        let first_z = region.z.saturating_sub(1) / TERRAIN_CHUNK_QUADS;
+        let last_x = ((region.x + region.width - 1) / TERRAIN_CHUNK_QUADS).min(chunks_x - 1);
+        let last_z = ((region.z + region.depth - 1) / TERRAIN_CHUNK_QUADS).min(chunks_z - 1);
+
+        (first_z..=last_z)
+            .flat_map(move |z| (first_x..=last_x).map(move |x| (z * chunks_x + x) as usize))
+    }
+
+    fn update_chunk_range(&mut self, index: usize) {
+        let (x, z) = self.chunk_origin(index);
+        let end_x = (x + TERRAIN_CHUNK_QUADS).min(self.width - 1);
+        let end_z = (z + TERRAIN_CHUNK_QUADS).min(self.depth - 1);
+        let mut range = (u16::MAX, u16::MIN);
+
+        for row in z..=end_z {
+            let start = (row * self.width + x) as usize;
+
+            for &height in &self.heights[start..=start + (end_x - x) as usize] {
+                range = (range.0.min(height), range.1.max(height));
+            }
+        }
+
+        self.chunk_ranges[index] = range;
+    }
+}
+
+/// Level of detail of a chunk at the distance. Each level halves the resolution and starts twice as far as the
+/// previous one, the first coarser level starting at `lod_distance`.
+pub fn terrain_chunk_lod(distance: f32, lod_distance: f32) -> u32 {
+    if lod_distance <= 0.0 || distance < lod_distance {
+        return 0;
+    }
+
+    ((distance / lod_distance).log2().floor() as u32 + 1).min(TERRAIN_MAX_LOD)
+}
+
+fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vec4;
+
+    fn assert_close(actual: Option<f32>, expected: Option<f32>) {
+        match (actual, expected) {
+            (Some(actual), Some(expected)) => assert!(
+                (actual - expected).abs() < 1e-3,
+                "{} is not close to {}",
+                actual,
+                expected
+            ),
+            (actual, expected) => assert_eq!(actual, expected),
+        }
+    }
+
+    fn ramp(width: u32, depth: u32) -> Heightfield {
+        // Rises along x by one unit per sample.
+        let heights = Vec::from_iter((0..depth).flat_map(|_| (0..width).map(|x| x as u16)));
+        Heightfield::new(
+            width,
+            depth,
+            heights,
+            Vec2::new((width - 1) as f32, (depth - 1) as f32),
+            u16::MAX as f32,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn check_height_at_interpolates_bilinearly() {
+        let heightfield = Heightfield::new(
+            2,
+            2,
+            vec![0, 100, 200, 300],
+            Vec2::new(10.0, 10.0),
+            u16::MAX as f32,
+        )
+        .unwrap();
+
+        assert_close(heightfield.height_at(Vec2::new(0.0, 0.0)), Some(0.0));
+        assert_close(heightfield.height_at(Vec2::new(10.0, 10.0)), Some(300.0));
+        assert_close(heightfield.height_at(Vec2::new(5.0, 5.0)), Some(150.0));
+        assert_close(heightfield.height_at(Vec2::new(2.5, 0.0)), Some(25.0));
+        assert_eq!(heightfield.height_at(Vec2::new(10.5, 0.0)), None);
+        assert_eq!(heightfield.height_at(Vec2::new(0.0, -0.5)), None);
+    }
+
+    #[test]
+    fn check_normal_at_follows_slope() {
+        let heightfield = ramp(8, 8);
+        let normal = heightfield.normal_at(Vec2::new(3.5, 3.5)).unwrap();
+        let expected = Vec3::new(-1.0, 1.0, 0.0).normalized();
+
+        assert!(Vec3::distance(normal, expected) < 1e-5);
+        assert_eq!(heightfield.normal_at(Vec2::new(-1.0, 0.0)), None);
+    }
+
+    #[test]
+    fn check_chunks_cover_heightfield() {
+        let heightfield = ramp(TERRAIN_CHUNK_QUADS * 2 + 2, TERRAIN_CHUNK_QUADS + 1);
+
+        // The last sample along x needs a chunk of its own.
+        assert_eq!(heightfield.chunk_counts(), (3, 1));
+        assert_eq!(heightfield.chunk_origin(2), (TERRAIN_CHUNK_QUADS * 2, 0));
+
+        let (min, max) = heightfield.chunk_bounds(1);
+        assert_eq!(min.x, TERRAIN_CHUNK_QUADS as f32);
+        assert_eq!(max.x, (TERRAIN_CHUNK_QUADS * 2) as f32);
+        assert_close(Some(max.y), Some((TERRAIN_CHUNK_QUADS * 2) as f32));
+        assert!(min.y < TERRAIN_CHUNK_QUADS as f32);
+    }
+
+    #[test]
+    fn check_set_heights_updates_overlapping_chunks() {
+        let mut heightfield = Heightfield::new(
+            TERRAIN_CHUNK_QUADS * 2 + 1,
+            TERRAIN_CHUNK_QUADS * 2 + 1,
+            vec![0; (TERRAIN_CHUNK_QUADS as usize * 2 + 1).pow(2)],
+            Vec2::new(128.0, 128.0),
+            u16::MAX as f32,
+        )
+        .unwrap();
+
+        // A sample on the border between chunks belongs to both of them.
+        heightfield
+            .set_heights(
+                HeightfieldRegion::new(TERRAIN_CHUNK_QUADS, 10, 1, 1),
+                &[500.0],
+            )
+            .unwrap();
+
+        assert_close(
+            Some(heightfield.sample(TERRAIN_CHUNK_QUADS, 10)),
+            Some(500.0),
+        );
+        assert_close(Some(heightfield.chunk_bounds(0).1.y), Some(500.0));
+        assert_close(Some(heightfield.chunk_bounds(1).1.y), Some(500.0));
+        assert_eq!(heightfield.chunk_bounds(2).1.y, 0.0);
+        assert_eq!(heightfield.chunk_bounds(3).1.y, 0.0);
+
+        assert_eq!(
+            heightfield.set_heights(HeightfieldRegion::new(128, 0, 2, 1), &[0.0, 0.0]),
+            Err(HeightfieldError::OutOfBounds {
+                region: HeightfieldRegion::new(128, 0, 2, 1),
+                width: 129,
+                depth: 129,
+            })
+        );
+        assert_eq!(
+            heightfield.set_heights(HeightfieldRegion::new(0, 0, 2, 2), &[0.0]),
+            Err(HeightfieldError::SizeMismatch {
+                expected: 4,
+                actual: 1
+            })
+        );
+    }
+
+    #[test]
+    fn check_visible_chunks_are_culled_and_lodded() {
+        let heightfield = ramp(TERRAIN_CHUNK_QUADS * 4 + 1, TERRAIN_CHUNK_QUADS + 1);
+        let everywhere = Vec4::new(0.0, 0.0, 0.0, 1.0);
+        // Keeps x <= 150, cutting off the last two chunks.
+        let frustum = Frustum {
+            planes: [
+                Vec4::new(-1.0, 0.0, 0.0, 150.0),
+                everywhere,
+                everywhere,
+                everywhere,
+                everywhere,
+                everywhere,
+            ],
+        };
+
+        let chunks = heightfield.visible_chunks(
+            &Mat4::identity(),
+            &frustum,
+            Vec3::new(0.0, 1000.0, 0.0),
+            500.0,
+        );
+
+        assert_eq!(
+            chunks,
+            vec![
+                VisibleTerrainChunk { index: 0, lod: 1 },
+                VisibleTerrainChunk { index: 1, lod: 1 },
+                VisibleTerrainChunk { index: 2, lod: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn check_terrain_chunk_lod() {
+        assert_eq!(terrain_chunk_lod(0.0, 100.0), 0);
+        assert_eq!(terrain_chunk_lod(99.0, 100.0), 0);
+        assert_eq!(terrain_chunk_lod(100.0, 100.0), 1);
+        assert_eq!(terrain_chunk_lod(399.0, 100.0), 2);
+        assert_eq!(terrain_chunk_lod(400.0, 100.0), 3);
+        assert_eq!(terrain_chunk_lod(1e9, 100.0), TERRAIN_MAX_LOD);
+        assert_eq!(terrain_chunk_lod(1e9, 0.0), 0);
+    }
+}
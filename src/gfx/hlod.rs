@@ -0,0 +1,568 @@
+use super::{Color, Mesh, MeshRenderer};
+use crate::{
+    math::{Mat4, Vec3, Vec4},
+    object::{Object, ObjectId},
+    task::{Task, TaskContext, TaskStatus},
+    Context,
+};
+use specs::{prelude::*, Component};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::Instant,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum HlodBakeError {
+    #[error("no static objects to bake")]
+    EmptyScene,
+    #[error("invalid bake settings: {0}")]
+    InvalidSettings(&'static str),
+}
+
+/// Parameters of an HLOD bake. Distances are in world units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HlodBakeSettings {
+    /// Size of the grid cells on the horizontal plane. Objects are clustered by the cell their origin is in.
+    pub cell_size: f32,
+    /// Distance from the camera to the center of a cluster beyond which the proxy is drawn instead of the members.
+    pub switch_distance: f32,
+    /// Fraction of the switch distance the camera must move past it before switching again,
+    /// so that a camera standing at the switch distance doesn't flip between the proxy and the members every frame.
+    pub hysteresis: f32,
+    /// Cells with fewer objects are left alone, as their proxy would save little.
+    pub min_members: usize,
+}
+
+impl HlodBakeSettings {
+    fn validate(&self) -> Result<(), HlodBakeError> {
+        if !(0.0 < self.cell_size && 0.0 < self.switch_distance) {
+            return Err(HlodBakeError::InvalidSettings(
+                "cell size and switch distance must be positive",
+            ));
+        }
+
+        if !(0.0..1.0).contains(&self.hysteresis) {
+            return Err(HlodBakeError::InvalidSettings(
+                "hysteresis must be in [0, 1)",
+            ));
+        }
+
+        if self.min_members == 0 {
+            return Err(HlodBakeError::InvalidSettings(
+                "clusters need at least one member",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for HlodBakeSettings {
+    fn default() -> Self {
+        Self {
+            cell_size: 64.0,
+            switch_distance: 200.0,
+            hysteresis: 0.1,
+            min_members: 2,
+        }
+    }
+}
+
+/// Marks an object with a [`MeshRenderer`] as static, so that HLOD bakes may merge it with its neighbours.
+/// The proxy approximates the material of the object by a single color.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct HlodStatic {
+    pub color: Color,
+}
+
+impl HlodStatic {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+/// Triangles of a mesh, three vertices each, in the local space of the objects using it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HlodMeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+}
+
+impl HlodMeshData {
+    /// Flattens the faces of the mesh. Meshes without normals get the normals of their faces.
+    pub fn from_mesh(mesh: &Mesh) -> Self {
+        let data = &mesh.data;
+        let mut positions = Vec::with_capacity(data.faces.len() * 3);
+        let mut normals = Vec::with_capacity(data.faces.len() * 3);
+
+        for face in &data.faces {
+            if face.0.len() != 3 {
+                continue;
+            }
+
+            let corners = [0, 1, 2].map(|corner| {
+                let vertex = &data.vertices[face.0[corner] as usize];
+                Vec3::new(vertex.x, vertex.y, vertex.z)
+            });
+            let face_normal =
+                Vec3::cross(corners[1] - corners[0], corners[2] - corners[0]).normalized();
+
+            for (corner, position) in corners.into_iter().enumerate() {
+                positions.push(position);
+                normals.push(match data.normals.get(face.0[corner] as usize) {
+                    Some(normal) => Vec3::new(normal.x, normal.y, normal.z),
+                    None => face_normal,
+                });
+            }
+        }
+
+        Self { positions, normals }
+    }
+}
+
+/// A static object to merge, with its world matrix at the time of collection.
+#[derive(Debug, Clone)]
+pub struct HlodSource {
+    pub object_id: ObjectId,
+    pub matrix: Mat4,
+    pub mesh: Arc<HlodMeshData>,
+    pub color: Color,
+}
+
+/// Gathers the active objects with a [`HlodStatic`] and a mesh, using the world matrices of the last frame.
+/// Instanced meshes are left out; they are drawn in one call already.
+pub fn collect_hlod_sources(ctx: &Context) -> Vec<HlodSource> {
+    let world = ctx.world();
+    let objects = world.read_storage::<Object>();
+    let statics = world.read_storage::<HlodStatic>();
+    let mesh_renderers = world.read_storage::<MeshRenderer>();
+    let object_mgr = ctx.object_mgr();
+    let object_hierarchy = object_mgr.object_hierarchy();
+    // Meshes are usually shared by many objects; they are flattened once.
+    let mut meshes = HashMap::new();
+    let mut sources = Vec::new();
+
+    for (object, hlod_static, mesh_renderer) in (&objects, &statics, &mesh_renderers).join() {
+        let object_id = object.object_id();
+
+        if !object_hierarchy.is_active(object_id) || mesh_renderer.instanced_group().is_some() {
+            continue;
+        }
+
+        let mesh = match mesh_renderer.mesh() {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        let mesh = meshes
+            .entry(mesh.as_ptr())
+            .or_insert_with(|| Arc::new(HlodMeshData::from_mesh(mesh)))
+            .clone();
+
+        sources.push(HlodSource {
+            object_id,
+            matrix: object_hierarchy.matrix(object_id).clone(),
+            mesh,
+            color: hlod_static.color,
+        });
+    }
+
+    sources
+}
+
+/// Objects merged into one proxy mesh.
+#[derive(Debug, Clone)]
+pub struct HlodCluster {
+    /// The members with the world matrices they have been baked with.
+    pub members: Vec<(ObjectId, Mat4)>,
+    /// Position, normal and color of each vertex of the proxy, in world space. Three vertices per triangle.
+    pub vertices: Vec<[f32; 10]>,
+    /// Bounding sphere of the proxy.
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl HlodCluster {
+    fn merge(sources: &[HlodSource], members: &[usize]) -> Self {
+        let vertex_count = members
+            .iter()
+            .map(|&index| sources[index].mesh.positions.len())
+            .sum();
+        let mut vertices = Vec::with_capacity(vertex_count);
+        let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
+
+        for &index in members {
+            let source = &sources[index];
+            let color = source.color;
+
+            for (&position, &normal) in source.mesh.positions.iter().zip(&source.mesh.normals) {
+                let position = Vec3::from(Vec4::from_vec3(position, 1.0) * &source.matrix);
+                // Non-uniform scales skew the normals slightly, which is hardly visible at the distance proxies are drawn at.
+                let normal = Vec3::from(Vec4::from_vec3(normal, 0.0) * &source.matrix).normalized();
+                min = Vec3::min(min, position);
+                max = Vec3::max(max, position);
+                vertices.push([
+                    position.x, position.y, position.z, normal.x, normal.y, normal.z, color.r,
+                    color.g, color.b, color.a,
+                ]);
+            }
+        }
+
+        let (center, radius) = if vertices.is_empty() {
+            (Vec3::from(sources[members[0]].matrix.row(3)), 0.0)
+        } else {
+            let center = (min + max) * 0.5;
+            (center, Vec3::distance(center, max))
+        };
+
+        Self {
+            members: members
+                .iter()
+                .map(|&index| (sources[index].object_id, sources[index].matrix.clone()))
+                .collect(),
+            vertices,
+            center,
+            radius,
+        }
+    }
+}
+
+/// Result of an HLOD bake. Spawn the proxies with [`Context::apply_hlod_bake`](crate::Context::apply_hlod_bake).
+#[derive(Debug, Clone)]
+pub struct HlodBake {
+    pub settings: HlodBakeSettings,
+    pub clusters: Vec<HlodCluster>,
+}
+
+/// Bakes the clusters at once.
+/// Use [`HlodBakeTask`] to bake over several frames or on the background thread.
+pub fn bake_hlod(
+    sources: Vec<HlodSource>,
+    settings: &HlodBakeSettings,
+) -> Result<HlodBake, HlodBakeError> {
+    let mut task = HlodBakeTask::new(Arc::new(sources), *settings);
+    Ok(task.bake_step(None)?.unwrap())
+}
+
+/// Bakes HLOD proxies in steps: the objects are clustered on a grid, then the clusters are merged one at a time.
+/// It only uses the CPU, so it can run on the background thread of the task scheduler.
+pub struct HlodBakeTask {
+    sources: Arc<Vec<HlodSource>>,
+    settings: HlodBakeSettings,
+    cells: Option<Vec<Vec<usize>>>,
+    clusters: Vec<HlodCluster>,
+}
+
+impl HlodBakeTask {
+    pub fn new(sources: Arc<Vec<HlodSource>>, settings: HlodBakeSettings) -> Self {
+        Self {
+            sources,
+            settings,
+            cells: None,
+            clusters: Vec::new(),
+        }
+    }
+
+    fn progress(&self) -> f32 {
+        match &self.cells {
+            Some(cells) if !cells.is_empty() => self.clusters.len() as f32 / cells.len() as f32,
+            _ => 0.0,
+        }
+    }
+
+    /// Merges clusters until the deadline passes. Returns the bake once every cluster is merged.
+    fn bake_step(&mut self, deadline: Option<Instant>) -> Result<Option<HlodBake>, HlodBakeError> {
+        if self.cells.is_none() {
+            self.settings.validate()?;
+
+            if self.sources.is_empty() {
+                return Err(HlodBakeError::EmptyScene);
+            }
+
+            // Ordered, so that bakes of the same scene are identical.
+            let mut cells = BTreeMap::<(i32, i32), Vec<usize>>::new();
+
+            for (index, source) in self.sources.iter().enumerate() {
+                let origin = source.matrix.row(3);
+                let cell = (
+                    (origin.x / self.settings.cell_size).floor() as i32,
+                    (origin.z / self.settings.cell_size).floor() as i32,
+                );
+                cells.entry(cell).or_default().push(index);
+            }
+
+            self.cells = Some(
+                cells
+                    .into_values()
+                    .filter(|members| self.settings.min_members <= members.len())
+                    .collect(),
+            );
+        }
+
+        let cells = self.cells.as_ref().unwrap();
+
+        while self.clusters.len() < cells.len() {
+            let members = &cells[self.clusters.len()];
+            self.clusters
+                .push(HlodCluster::merge(&self.sources, members));
+
+            if deadline.map_or(false, |deadline| deadline <= Instant::now()) {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(HlodBake {
+            settings: self.settings,
+            clusters: std::mem::take(&mut self.clusters),
+        }))
+    }
+}
+
+impl Task for HlodBakeTask {
+    type Output = HlodBake;
+
+    fn step(&mut self, _ctx: &mut TaskContext, deadline: Instant) -> TaskStatus<Self::Output> {
+        match self.bake_step(Some(deadline)) {
+            Ok(Some(bake)) => TaskStatus::Done(bake),
+            Ok(None) => TaskStatus::InProgress(self.progress()),
+            Err(err) => TaskStatus::Failed(err.to_string()),
+        }
+    }
+}
+
+/// Decides between a cluster's proxy and its members by the distance of the camera to the cluster.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HlodSwitch {
+    center: Vec3,
+    switch_distance: f32,
+    hysteresis: f32,
+    is_using_proxy: bool,
+}
+
+impl HlodSwitch {
+    pub fn new(center: Vec3, switch_distance: f32, hysteresis: f32) -> Self {
+        Self {
+            center,
+            switch_distance,
+            hysteresis,
+            is_using_proxy: false,
+        }
+    }
+
+    pub fn is_using_proxy(&self) -> bool {
+        self.is_using_proxy
+    }
+
+    /// Switches to the proxy once the camera is past the switch distance by the hysteresis,
+    /// and back to the members once it is as much inside of it. Returns `true` if it switched.
+    pub fn update(&mut self, camera_position: Vec3) -> bool {
+        let distance = Vec3::distance(camera_position, self.center);
+        let is_using_proxy = if self.is_using_proxy {
+            self.switch_distance * (1.0 - self.hysteresis) < distance
+        } else {
+            self.switch_distance * (1.0 + self.hysteresis) < distance
+        };
+        let switched = is_using_proxy != self.is_using_proxy;
+        self.is_using_proxy = is_using_proxy;
+        switched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Quat;
+
+    /// A unit box, as two triangles per side.
+    fn unit_box() -> Arc<HlodMeshData> {
+        let mut mesh = HlodMeshData::default();
+
+        for axis in 0..3 {
+            for sign in [-1.0f32, 1.0] {
+                let along = |a: f32, b: f32| {
+                    let mut v = [0.0; 3];
+                    v[axis] = sign * 0.5;
+                    v[(axis + 1) % 3] = a;
+                    v[(axis + 2) % 3] = b;
+                    Vec3::new(v[0], v[1], v[2])
+                };
+                let mut normal = [0.0; 3];
+                normal[axis] = sign;
+
+                for (a, b) in [
+                    (-0.5, -0.5),
+                    (0.5, -0.5),
+                    (0.5, 0.5),
+                    (-0.5, -0.5),
+                    (0.5, 0.5),
+                    (-0.5, 0.5),
+                ] {
+                    mesh.positions.push(along(a, b));
+                    mesh.normals
+                        .push(Vec3::new(normal[0], normal[1], normal[2]));
+                }
+            }
+        }
+
+        Arc::new(mesh)
+    }
+
+    /// `side` x `side` buildings, `spacing` apart, centered on the origin.
+    fn city(side: u32, spacing: f32) -> Vec<HlodSource> {
+        let mesh = unit_box();
+        let half = side as f32 * spacing * 0.5;
+
+        (0..side * side)
+            .map(|index| HlodSource {
+                object_id: ObjectId::from_u32(index),
+                matrix: Mat4::srt(
+                    Vec3::new(
+                        (index % side) as f32 * spacing - half,
+                        2.0,
+                        (index / side) as f32 * spacing - half,
+                    ),
+                    Quat::IDENTITY,
+                    Vec3::new(4.0, 4.0 + (index % 7) as f32, 4.0),
+                ),
+                mesh: mesh.clone(),
+                color: Color::from_rgb(0.5, 0.5, 0.5),
+            })
+            .collect()
+    }
+
+    /// Draw calls of the city for a camera: one per proxy in use, one per member otherwise.
+    fn draw_calls(bake: &HlodBake, switches: &mut [HlodSwitch], camera_position: Vec3) -> usize {
+        bake.clusters
+            .iter()
+            .zip(switches)
+            .map(|(cluster, switch)| {
+                switch.update(camera_position);
+
+                if switch.is_using_proxy() {
+                    1
+                } else {
+                    cluster.members.len()
+                }
+            })
+            .sum()
+    }
+
+    #[test]
+    fn check_city_is_clustered_on_the_grid() {
+        let sources = city(32, 10.0);
+        let bake = bake_hlod(
+            sources.clone(),
+            &HlodBakeSettings {
+                cell_size: 80.0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(bake.clusters.len(), 16);
+        assert_eq!(
+            bake.clusters
+                .iter()
+                .map(|cluster| cluster.members.len())
+                .sum::<usize>(),
+            sources.len()
+        );
+
+        for cluster in &bake.clusters {
+            assert_eq!(cluster.vertices.len(), cluster.members.len() * 36);
+
+            for vertex in &cluster.vertices {
+                let position = Vec3::new(vertex[0], vertex[1], vertex[2]);
+                assert!(Vec3::distance(position, cluster.center) <= cluster.radius + 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn check_an_order_of_magnitude_less_is_drawn_at_distance() {
+        let bake = bake_hlod(
+            city(32, 10.0),
+            &HlodBakeSettings {
+                cell_size: 80.0,
+                switch_distance: 400.0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut switches = Vec::from_iter(bake.clusters.iter().map(|cluster| {
+            HlodSwitch::new(
+                cluster.center,
+                bake.settings.switch_distance,
+                bake.settings.hysteresis,
+            )
+        }));
+
+        let near = draw_calls(&bake, &mut switches, Vec3::new(0.0, 20.0, 0.0));
+        let far = draw_calls(&bake, &mut switches, Vec3::new(0.0, 300.0, 1500.0));
+
+        assert_eq!(near, 1024);
+        assert_eq!(far, 16);
+        assert!(far * 10 <= near);
+    }
+
+    #[test]
+    fn check_switch_hysteresis() {
+        let mut switch = HlodSwitch::new(Vec3::ZERO, 100.0, 0.1);
+
+        assert!(!switch.update(Vec3::new(105.0, 0.0, 0.0)));
+        assert!(switch.update(Vec3::new(111.0, 0.0, 0.0)));
+        assert!(switch.is_using_proxy());
+        assert!(!switch.update(Vec3::new(95.0, 0.0, 0.0)));
+        assert!(switch.update(Vec3::new(89.0, 0.0, 0.0)));
+        assert!(!switch.is_using_proxy());
+    }
+
+    #[test]
+    fn check_sparse_cells_are_skipped() {
+        let mut sources = city(2, 10.0);
+        let positions = [
+            Vec3::new(10.0, 0.0, 10.0),
+            Vec3::new(20.0, 0.0, 10.0),
+            Vec3::new(10.0, 0.0, 20.0),
+            Vec3::new(500.0, 0.0, 500.0),
+        ];
+
+        for (source, position) in sources.iter_mut().zip(positions) {
+            source.matrix = Mat4::translation(position);
+        }
+
+        let bake = bake_hlod(
+            sources,
+            &HlodBakeSettings {
+                cell_size: 80.0,
+                min_members: 2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(bake.clusters.len(), 1);
+        assert_eq!(bake.clusters[0].members.len(), 3);
+    }
+
+    #[test]
+    fn check_invalid_settings_are_rejected() {
+        assert_eq!(
+            bake_hlod(Vec::new(), &Default::default()).unwrap_err(),
+            HlodBakeError::EmptyScene
+        );
+        assert!(matches!(
+            bake_hlod(
+                city(2, 10.0),
+                &HlodBakeSettings {
+                    hysteresis: 1.0,
+                    ..Default::default()
+                }
+            ),
+            Err(HlodBakeError::InvalidSettings(_))
+        ));
+    }
+}
@@ -0,0 +1,379 @@
+use super::{GenericBufferAllocation, GfxContextHandle, GpuCulling};
+use crate::{
+    handles::{GenIndex, HandleStore, StaleHandle},
+    math::{Frustum, Mat4, Vec3, Vec4},
+};
+use std::{mem::size_of, ops::Range, sync::Arc};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, Buffer, BufferAddress, BufferDescriptor,
+    BufferSize, BufferUsages, CommandEncoder, ComputePassDescriptor,
+};
+use zerocopy::AsBytes;
+
+/// Per-instance data of an [`InstancedGroup`]. The transform rows are fed into the
+/// `TRANSFORM_ROW_*` semantic inputs, so materials used with instanced groups must not have other per-instance inputs.
+#[repr(C)]
+#[derive(AsBytes, Debug, Clone, Copy, PartialEq)]
+pub struct InstanceData {
+    pub transform: [Vec4; 4],
+    /// World-space bounding sphere; xyz is the center and w is the radius.
+    pub bounds: Vec4,
+}
+
+impl InstanceData {
+    /// Creates an instance from its world matrix and the radius of the mesh's local bounding sphere.
+    pub fn new(matrix: &Mat4, local_radius: f32) -> Self {
+        let scale = Vec3::from_vec4(matrix.row(0))
+            .len()
+            .max(Vec3::from_vec4(matrix.row(1)).len())
+            .max(Vec3::from_vec4(matrix.row(2)).len());
+        let center = matrix.row(3);
+
+        Self {
+            transform: [matrix.row(0), matrix.row(1), matrix.row(2), matrix.row(3)],
+            bounds: Vec4::new(center.x, center.y, center.z, local_radius * scale),
+        }
+    }
+}
+
+/// Identifies an instance of an [`InstancedGroup`]. It stays valid while other instances are removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InstanceId(GenIndex);
+
+/// The result of culling an [`InstancedGroup`] for a camera.
+#[derive(Clone)]
+pub struct InstancedDraw {
+    pub instance_buffer: GenericBufferAllocation<Buffer>,
+    /// Set if the instance count has been written by the GPU; the draw must be issued with `draw_indirect`.
+    pub indirect_buffer: Option<Arc<Buffer>>,
+    /// The number of visible instances. It is only meaningful if `indirect_buffer` is `None`.
+    pub instance_count: u32,
+}
+
+/// Per-camera buffers of an instanced group.
+struct CullingTarget {
+    params_buffer: Buffer,
+    visible_buffer: Arc<Buffer>,
+    indirect_buffer: Arc<Buffer>,
+    bind_group: Option<BindGroup>,
+    capacity: u32,
+}
+
+/// A large set of instances of the same mesh, living in persistent GPU buffers.
+/// Instances are uploaded incrementally and culled per camera, on the GPU when supported or on the CPU otherwise.
+pub struct InstancedGroup {
+    gfx_ctx: GfxContextHandle,
+    instances: Vec<InstanceData>,
+    /// Ids of the instances, in the order of `instances`.
+    instance_ids: Vec<InstanceId>,
+    /// Positions of the instances in `instances`, by id.
+    instance_indices: HandleStore<u32>,
+    dirty: Option<Range<usize>>,
+    instance_buffer: Buffer,
+    capacity: u32,
+    targets: Vec<CullingTarget>,
+    last_visible_count: u32,
+}
+
+impl InstancedGroup {
+    const VISIBLE_INSTANCE_SIZE: BufferAddress = size_of::<[Vec4; 4]>() as BufferAddress;
+    const PARAMS_SIZE: BufferAddress =
+        (size_of::<[Vec4; 6]>() + size_of::<[u32; 4]>()) as BufferAddress;
+
+    pub fn new(gfx_ctx: GfxContextHandle) -> Self {
+        let instance_buffer = create_instance_buffer(&gfx_ctx, 1);
+
+        Self {
+            gfx_ctx,
+            instances: Vec::new(),
+            instance_ids: Vec::new(),
+            instance_indices: HandleStore::new(),
+            dirty: None,
+            instance_buffer,
+            capacity: 1,
+            targets: Vec::new(),
+            last_visible_count: 0,
+        }
+    }
+
+    pub fn len(&self) -> u32 {
+        self.instances.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub fn is_alive(&self, id: InstanceId) -> bool {
+        self.instance_indices.is_alive(id.0)
+    }
+
+    pub fn instance(&self, id: InstanceId) -> Option<&InstanceData> {
+        let index = *self.instance_indices.get(id.0)?;
+        self.instances.get(index as usize)
+    }
+
+    /// Returns the number of instances that survived the last culling.
+    /// It is only tracked for the CPU path, since the GPU path never reads back.
+    pub fn last_visible_count(&self) -> u32 {
+        self.last_visible_count
+    }
+
+    pub fn add_instance(&mut self, instance: InstanceData) -> InstanceId {
+        let index = self.instances.len();
+        let id = InstanceId(self.instance_indices.insert(index as u32));
+        self.instances.push(instance);
+        self.instance_ids.push(id);
+        self.mark_dirty(index..index + 1);
+        id
+    }
+
+    pub fn add_instances(&mut self, instances: &[InstanceData]) -> Vec<InstanceId> {
+        instances
+            .iter()
+            .map(|&instance| self.add_instance(instance))
+            .collect()
+    }
+
+    pub fn update_instance(
+        &mut self,
+        id: InstanceId,
+        data: InstanceData,
+    ) -> Result<(), StaleHandle> {
+        let index = *self
+            .instance_indices
+            .get(id.0)
+            .ok_or_else(|| StaleHandle::new("instance"))? as usize;
+        self.instances[index] = data;
+        self.mark_dirty(index..index + 1);
+        Ok(())
+    }
+
+    /// Removes an instance by moving the last instance into its place. The ids of the other instances stay valid.
+    pub fn remove(&mut self, id: InstanceId) -> Result<InstanceData, StaleHandle> {
+        let index = self
+            .instance_indices
+            .remove(id.0)
+            .ok_or_else(|| StaleHandle::new("instance"))? as usize;
+        let instance = self.instances.swap_remove(index);
+        self.instance_ids.swap_remove(index);
+
+        if let Some(&moved) = self.instance_ids.get(index) {
+            *self.instance_indices.get_mut(moved.0).unwrap() = index as u32;
+            self.mark_dirty(index..index + 1);
+        }
+
+        Ok(instance)
+    }
+
+    fn mark_dirty(&mut self, range: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(range.start)..dirty.end.max(range.end),
+            None => range,
+        });
+    }
+
+    /// Uploads modified instances. Only the dirty range is written unless the buffer has to grow.
+    fn upload(&mut self) {
+        let dirty = match self.dirty.take() {
+            Some(dirty) => dirty,
+            None => return,
+        };
+
+        if self.capacity < self.len() {
+            self.capacity = self.len().next_power_of_two();
+            self.instance_buffer = create_instance_buffer(&self.gfx_ctx, self.capacity);
+            self.gfx_ctx
+                .queue
+                .write_buffer(&self.instance_buffer, 0, self.instances.as_bytes());
+            return;
+        }
+
+        let dirty = dirty.start..dirty.end.min(self.instances.len());
+
+        if dirty.is_empty() {
+            return;
+        }
+
+        self.gfx_ctx.queue.write_buffer(
+            &self.instance_buffer,
+            (dirty.start * size_of::<InstanceData>()) as BufferAddress,
+            self.instances[dirty].as_bytes(),
+        );
+    }
+
+    /// Culls the instances against the frustum on the GPU, recording a compute pass into the encoder.
+    /// `slot` identifies the camera, so that multiple cameras can cull the same group in one frame.
+    /// `draw_count` is the number of vertices to draw, or of indices if they are indexed.
+    pub fn cull_gpu(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        culling: &GpuCulling,
+        slot: usize,
+        frustum: &Frustum,
+        draw_count: u32,
+    ) -> InstancedDraw {
+        self.upload();
+
+        let instance_count = self.len();
+        let gfx_ctx = &self.gfx_ctx;
+        let instance_buffer = &self.instance_buffer;
+        let capacity = self.capacity;
+        let target = obtain_culling_target(&mut self.targets, gfx_ctx, slot, capacity);
+
+        let mut params = Vec::with_capacity(Self::PARAMS_SIZE as usize);
+        params.extend_from_slice(frustum.planes.as_bytes());
+        params.extend_from_slice([instance_count, 0, 0, 0].as_bytes());
+        gfx_ctx
+            .queue
+            .write_buffer(&target.params_buffer, 0, &params);
+        gfx_ctx.queue.write_buffer(
+            &target.indirect_buffer,
+            0,
+            [draw_count, 0, 0, 0, 0].as_bytes(),
+        );
+
+        // The bind group refers to the instance buffer, which may be reallocated; recreate it every time.
+        target.bind_group = Some(gfx_ctx.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("instance culling bind group"),
+            layout: culling.bind_group_layout(),
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: target.params_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: instance_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: target.visible_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: target.indirect_buffer.as_entire_binding(),
+                },
+            ],
+        }));
+
+        if instance_count != 0 {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("instance culling pass"),
+            });
+            compute_pass.set_pipeline(culling.pipeline());
+            compute_pass.set_bind_group(0, target.bind_group.as_ref().unwrap(), &[]);
+            compute_pass.dispatch_workgroups(
+                (instance_count + GpuCulling::WORKGROUP_SIZE - 1) / GpuCulling::WORKGROUP_SIZE,
+                1,
+                1,
+            );
+        }
+
+        InstancedDraw {
+            instance_buffer: visible_allocation(&target.visible_buffer, capacity),
+            indirect_buffer: Some(target.indirect_buffer.clone()),
+            instance_count: 0,
+        }
+    }
+
+    /// Culls the instances against the frustum on the CPU. This is the fallback for devices without
+    /// compute shaders or indirect draws.
+    pub fn cull_cpu(&mut self, slot: usize, frustum: &Frustum) -> InstancedDraw {
+        let visible = self
+            .instances
+            .iter()
+            .filter(|instance| {
+                frustum.intersects_sphere(Vec3::from_vec4(instance.bounds), instance.bounds.w)
+            })
+            .map(|instance| instance.transform)
+            .collect::<Vec<_>>();
+        let visible_count = visible.len() as u32;
+        self.last_visible_count = visible_count;
+
+        // Uploading keeps the capacity in sync with the instance count, so that the visible buffer can hold every instance.
+        self.upload();
+
+        let gfx_ctx = &self.gfx_ctx;
+        let target = obtain_culling_target(&mut self.targets, gfx_ctx, slot, self.capacity);
+
+        if !visible.is_empty() {
+            gfx_ctx
+                .queue
+                .write_buffer(&target.visible_buffer, 0, visible.as_bytes());
+        }
+
+        InstancedDraw {
+            instance_buffer: visible_allocation(&target.visible_buffer, target.capacity),
+            indirect_buffer: None,
+            instance_count: visible_count,
+        }
+    }
+}
+
+fn obtain_culling_target<'a>(
+    targets: &'a mut Vec<CullingTarget>,
+    gfx_ctx: &GfxContextHandle,
+    slot: usize,
+    capacity: u32,
+) -> &'a mut CullingTarget {
+    while targets.len() <= slot {
+        targets.push(create_culling_target(gfx_ctx, capacity));
+    }
+
+    if targets[slot].capacity < capacity {
+        targets[slot] = create_culling_target(gfx_ctx, capacity);
+    }
+
+    &mut targets[slot]
+}
+
+fn visible_allocation(buffer: &Arc<Buffer>, capacity: u32) -> GenericBufferAllocation<Buffer> {
+    GenericBufferAllocation::from_shared(
+        buffer.clone(),
+        0,
+        BufferSize::new(InstancedGroup::VISIBLE_INSTANCE_SIZE * capacity.max(1) as BufferAddress)
+            .unwrap(),
+    )
+}
+
+fn create_instance_buffer(gfx_ctx: &GfxContextHandle, capacity: u32) -> Buffer {
+    gfx_ctx.device.create_buffer(&BufferDescriptor {
+        label: Some("instanced group instance buffer"),
+        size: size_of::<InstanceData>() as BufferAddress * capacity.max(1) as BufferAddress,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_culling_target(gfx_ctx: &GfxContextHandle, capacity: u32) -> CullingTarget {
+    CullingTarget {
+        params_buffer: gfx_ctx.device.create_buffer(&BufferDescriptor {
+            label: Some("instance culling params buffer"),
+            size: InstancedGroup::PARAMS_SIZE,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }),
+        visible_buffer: Arc::new(gfx_ctx.device.create_buffer(&BufferDescriptor {
+            label: Some("instance culling visible buffer"),
+            size: InstancedGroup::VISIBLE_INSTANCE_SIZE * capacity.max(1) as BufferAddress,
+            usage: BufferUsages::STORAGE
+                | BufferUsages::VERTEX
+                | BufferUsages::COPY_DST
+                | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })),
+        indirect_buffer: Arc::new(gfx_ctx.device.create_buffer(&BufferDescriptor {
+            label: Some("instance culling indirect buffer"),
+            // Holds the arguments of either `draw_indirect` or `draw_indexed_indirect`.
+            size: size_of::<[u32; 5]>() as BufferAddress,
+            usage: BufferUsages::STORAGE
+                | BufferUsages::INDIRECT
+                | BufferUsages::COPY_DST
+                | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })),
+        bind_group: None,
+        capacity: capacity.max(1),
+    }
+}
@@ -0,0 +1,44 @@
+use specs::{prelude::*, Component};
+
+/// Layers an object belongs to, as a bitmask. Cameras draw the objects sharing a bit with their
+/// [`culling_mask`](super::Camera::culling_mask).
+///
+/// Objects without this component are on [`Layers::DEFAULT`].
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[storage(DenseVecStorage)]
+pub struct Layers(pub u32);
+
+impl Layers {
+    /// The first layer alone.
+    pub const DEFAULT: Self = Self(1);
+
+    /// Returns `true` if the layers share a bit with the mask.
+    pub fn intersects(self, mask: u32) -> bool {
+        self.0 & mask != 0
+    }
+
+    /// Layers of an object, given its optional component, e.g. from a `maybe()` join.
+    pub fn of(layers: Option<&Layers>) -> Self {
+        layers.copied().unwrap_or_default()
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_objects_default_to_the_first_layer() {
+        assert_eq!(Layers::of(None), Layers(1));
+        assert!(Layers::of(None).intersects(u32::MAX));
+        assert!(!Layers::of(None).intersects(0b10));
+        assert!(Layers::of(Some(&Layers(0b110))).intersects(0b10));
+        assert!(!Layers(0).intersects(u32::MAX));
+    }
+}
@@ -0,0 +1,499 @@
+use super::{BindGroupLayoutCache, Color, Material, PerInstancePropertyValue, Uploader};
+use crate::math::Vec3;
+use specs::{prelude::*, Component};
+use std::{f32::consts::PI, mem::size_of, sync::Arc};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutEntry, BindingType, Buffer,
+    BufferBindingType, BufferSize, BufferUsages, Device, ShaderStages,
+};
+use zerocopy::AsBytes;
+
+/// Most directional lights lighting the scene at once. Past it, the brightest ones are kept.
+pub const MAX_DIRECTIONAL_LIGHTS: usize = 4;
+
+/// Most point and spot lights lighting the scene at once, so that the `lights` uniform fits in 16 KiB. Past it, the
+/// ones reaching the farthest are kept.
+pub const MAX_LOCAL_LIGHTS: usize = 240;
+
+/// Most local lights shading a single object, picked by [`pick_local_lights`].
+pub const MAX_LIGHTS_PER_OBJECT: usize = 4;
+
+/// Light coming from infinitely far away in a single direction, e.g. the sun. It travels along the forward axis of
+/// its object, so only the rotation of the object matters.
+///
+/// Lights the materials of the built-in lit shader, see [`BUILT_IN_SHADER_LIT`](super::BUILT_IN_SHADER_LIT).
+#[derive(Component, Debug, Clone, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct DirectionalLight {
+    pub color: Color,
+    pub intensity: f32,
+}
+
+impl DirectionalLight {
+    pub fn new(color: Color, intensity: f32) -> Self {
+        Self { color, intensity }
+    }
+
+    /// The color premultiplied by the intensity, as the shaders receive it.
+    pub fn radiance(&self) -> [f32; 3] {
+        radiance(self.color, self.intensity)
+    }
+}
+
+/// Light shining in every direction from its object, up to `range`.
+///
+/// Each object is shaded by the few point and spot lights influencing it most, see [`pick_local_lights`].
+#[derive(Component, Debug, Clone, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct PointLight {
+    pub color: Color,
+    pub intensity: f32,
+    /// Distance at which the light fades out completely.
+    pub range: f32,
+}
+
+impl PointLight {
+    pub fn new(color: Color, intensity: f32, range: f32) -> Self {
+        Self {
+            color,
+            intensity,
+            range,
+        }
+    }
+
+    pub fn radiance(&self) -> [f32; 3] {
+        radiance(self.color, self.intensity)
+    }
+
+    /// Packs the light placed at `position`.
+    pub fn data(&self, position: Vec3) -> LocalLightData {
+        let [r, g, b] = self.radiance();
+
+        LocalLightData {
+            position: [position.x, position.y, position.z, self.range],
+            color: [r, g, b, 0.0],
+            // A cone wider than every direction.
+            direction: [0.0, 0.0, 0.0, -2.0],
+            cone: [-1.0, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+/// Light shining in a cone along the forward axis of its object, up to `range`.
+///
+/// Each object is shaded by the few point and spot lights influencing it most, see [`pick_local_lights`].
+#[derive(Component, Debug, Clone, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct SpotLight {
+    pub color: Color,
+    pub intensity: f32,
+    /// Distance at which the light fades out completely.
+    pub range: f32,
+    /// Angle from the axis within which the light is at full intensity, in radians.
+    pub inner_angle: f32,
+    /// Angle from the axis past which the light has faded out, in radians.
+    pub outer_angle: f32,
+}
+
+impl SpotLight {
+    pub fn new(
+        color: Color,
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            color,
+            intensity,
+            range,
+            inner_angle,
+            outer_angle,
+        }
+    }
+
+    pub fn radiance(&self) -> [f32; 3] {
+        radiance(self.color, self.intensity)
+    }
+
+    /// Packs the light placed at `position` and pointing towards `direction`. An inner angle past the outer one is
+    /// clamped to it.
+    pub fn data(&self, position: Vec3, direction: Vec3) -> LocalLightData {
+        let [r, g, b] = self.radiance();
+        let direction = direction.normalized();
+        let outer_angle = self.outer_angle.clamp(0.0, PI);
+        let cos_outer = outer_angle.cos();
+        // Kept apart, so that the shader never divides by zero between them.
+        let cos_inner = self
+            .inner_angle
+            .clamp(0.0, outer_angle)
+            .cos()
+            .max(cos_outer + 1e-4);
+
+        LocalLightData {
+            position: [position.x, position.y, position.z, self.range],
+            color: [r, g, b, 0.0],
+            direction: [direction.x, direction.y, direction.z, cos_outer],
+            cone: [cos_inner, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+fn radiance(color: Color, intensity: f32) -> [f32; 3] {
+    [
+        color.r * intensity,
+        color.g * intensity,
+        color.b * intensity,
+    ]
+}
+
+fn brightness([r, g, b]: [f32; 3]) -> f32 {
+    r.max(g).max(b)
+}
+
+/// Falloff of a local light at `distance` from it: inverse square, windowed to reach exactly zero at `range` with a
+/// zero slope, so that lights fade out instead of popping as they stop being picked. Mirrored by the lit shader.
+pub fn light_attenuation(distance: f32, range: f32) -> f32 {
+    if range <= distance {
+        return 0.0;
+    }
+
+    let ratio = distance / range;
+    let window = 1.0 - ratio * ratio * ratio * ratio;
+    window * window / (distance * distance + 1.0)
+}
+
+/// Picks the local lights reaching the box from `min` to `max`, the most influential first: the brightest at the
+/// point of the box nearest to them. Spot lights are tested by their range alone. Returns their indices into
+/// `lights` as floats, the way the `light_indices` shader input reads them, `-1` for the slots left empty.
+pub fn pick_local_lights(
+    min: Vec3,
+    max: Vec3,
+    lights: &[LocalLightData],
+) -> [f32; MAX_LIGHTS_PER_OBJECT] {
+    let mut picked = [(0.0, -1.0); MAX_LIGHTS_PER_OBJECT];
+
+    for (index, light) in lights.iter().enumerate() {
+        let [x, y, z, range] = light.position;
+        let dx = x - x.max(min.x).min(max.x);
+        let dy = y - y.max(min.y).min(max.y);
+        let dz = z - z.max(min.z).min(max.z);
+        let distance = (dx * dx + dy * dy + dz * dz).sqrt();
+        let influence = light_attenuation(distance, range)
+            * brightness([light.color[0], light.color[1], light.color[2]]);
+
+        if influence <= 0.0 {
+            continue;
+        }
+
+        // Earlier lights win ties, so that the picks stay stable from frame to frame.
+        let slot = picked.partition_point(|&(picked_influence, _)| influence <= picked_influence);
+
+        if slot == MAX_LIGHTS_PER_OBJECT {
+            continue;
+        }
+
+        picked.copy_within(slot..MAX_LIGHTS_PER_OBJECT - 1, slot + 1);
+        picked[slot] = (influence, index as f32);
+    }
+
+    picked.map(|(_, index)| index)
+}
+
+#[repr(C)]
+#[derive(AsBytes, Debug, Default, Clone, Copy, PartialEq)]
+pub struct DirectionalLightData {
+    /// xyz: normalized direction the light travels in.
+    pub direction: [f32; 4],
+    /// rgb: color premultiplied by the intensity.
+    pub color: [f32; 4],
+}
+
+/// A point or spot light as the shaders receive it, see [`PointLight::data`] and [`SpotLight::data`].
+#[repr(C)]
+#[derive(AsBytes, Debug, Default, Clone, Copy, PartialEq)]
+pub struct LocalLightData {
+    /// xyz: position, w: range.
+    pub position: [f32; 4],
+    /// rgb: color premultiplied by the intensity.
+    pub color: [f32; 4],
+    /// xyz: normalized direction the cone points towards, w: cosine of its outer angle.
+    pub direction: [f32; 4],
+    /// x: cosine of the inner angle of the cone.
+    pub cone: [f32; 4],
+}
+
+/// Layout of the `lights` uniform read by the lit shaders.
+#[repr(C)]
+#[derive(AsBytes, Debug, Clone, Copy, PartialEq)]
+pub struct LightsUniform {
+    /// rgb: ambient color, lighting every surface evenly.
+    pub ambient: [f32; 4],
+    /// x: number of directional lights, y: number of local lights.
+    pub counts: [u32; 4],
+    pub directional: [DirectionalLightData; MAX_DIRECTIONAL_LIGHTS],
+    pub local: [LocalLightData; MAX_LOCAL_LIGHTS],
+}
+
+impl LightsUniform {
+    /// Packs the directional lights, given as their directions and lights, keeping the brightest ones past
+    /// [`MAX_DIRECTIONAL_LIGHTS`], and the local lights, keeping the ones reaching the farthest past
+    /// [`MAX_LOCAL_LIGHTS`]. Lights without a direction, a range or any radiance are skipped.
+    pub fn new<'a>(
+        ambient: Color,
+        directional_lights: impl IntoIterator<Item = (Vec3, &'a DirectionalLight)>,
+        local_lights: impl IntoIterator<Item = LocalLightData>,
+    ) -> Self {
+        let mut lights = Vec::from_iter(directional_lights.into_iter().filter_map(
+            |(direction, light)| {
+                let radiance = light.radiance();
+                let brightness = brightness(radiance);
+
+                if direction.len() <= f32::EPSILON || brightness <= 0.0 {
+                    return None;
+                }
+
+                Some((brightness, direction.normalized(), radiance))
+            },
+        ));
+        // Stable, so that lights as bright keep their order from frame to frame.
+        lights.sort_by(|a, b| b.0.total_cmp(&a.0));
+        lights.truncate(MAX_DIRECTIONAL_LIGHTS);
+
+        let mut directional = [DirectionalLightData::default(); MAX_DIRECTIONAL_LIGHTS];
+
+        for (data, (_, direction, [r, g, b])) in directional.iter_mut().zip(&lights) {
+            *data = DirectionalLightData {
+                direction: [direction.x, direction.y, direction.z, 0.0],
+                color: [*r, *g, *b, 0.0],
+            };
+        }
+
+        let mut local_lights = Vec::from_iter(local_lights.into_iter().filter_map(|light| {
+            let reach =
+                light.position[3] * brightness([light.color[0], light.color[1], light.color[2]]);
+            (0.0 < reach).then_some((reach, light))
+        }));
+        local_lights.sort_by(|a, b| b.0.total_cmp(&a.0));
+        local_lights.truncate(MAX_LOCAL_LIGHTS);
+
+        let mut local = [LocalLightData::default(); MAX_LOCAL_LIGHTS];
+
+        for (data, (_, light)) in local.iter_mut().zip(&local_lights) {
+            *data = *light;
+        }
+
+        Self {
+            ambient: [ambient.r, ambient.g, ambient.b, 0.0],
+            counts: [lights.len() as u32, local_lights.len() as u32, 0, 0],
+            directional,
+            local,
+        }
+    }
+
+    /// The local lights packed, in the order their indices refer to.
+    pub fn local_lights(&self) -> &[LocalLightData] {
+        &self.local[..self.counts[1] as usize]
+    }
+}
+
+/// Lights of the scene, bound as the `lights` uniform of the shaders reading it. Updated every frame from the light
+/// components before rendering.
+pub struct SceneLights {
+    ambient: Color,
+    local_lights: Arc<[LocalLightData]>,
+    uniform_buffer: Buffer,
+    bind_group: Arc<BindGroup>,
+}
+
+impl SceneLights {
+    pub fn new(device: &Device, bind_group_layout_cache: &mut BindGroupLayoutCache) -> Self {
+        let ambient = Color::from_rgb(0.2, 0.2, 0.2);
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("scene lights"),
+            contents: LightsUniform::new(ambient, [], []).as_bytes(),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = bind_group_layout_cache.create_layout(vec![BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX_FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(size_of::<LightsUniform>() as u64),
+            },
+            count: None,
+        }]);
+        let bind_group = Arc::new(device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: bind_group_layout.as_ref(),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        }));
+
+        Self {
+            ambient,
+            local_lights: Arc::from([]),
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    /// Color lighting every surface evenly, on top of the lights. Without any light, lit surfaces are shown at their
+    /// full color instead.
+    pub fn ambient(&self) -> &Color {
+        &self.ambient
+    }
+
+    pub fn set_ambient(&mut self, ambient: Color) {
+        self.ambient = ambient;
+    }
+
+    /// Local lights of the last uniform uploaded, to pick the ones shading each object from.
+    pub fn local_lights(&self) -> &Arc<[LocalLightData]> {
+        &self.local_lights
+    }
+
+    pub fn upload(&mut self, uniform: &LightsUniform, uploader: &mut Uploader) {
+        self.local_lights = Arc::from(uniform.local_lights());
+        uploader.write(&self.uniform_buffer, 0, uniform.as_bytes());
+    }
+
+    pub fn bind_group(&self) -> &Arc<BindGroup> {
+        &self.bind_group
+    }
+}
+
+/// Blinn-Phong surface of the built-in lit shader, set as per-instance properties so that renderers can override
+/// them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LitMaterial {
+    pub base_color: Color,
+    pub specular_color: Color,
+    /// Exponent of the specular highlight; the higher, the smaller and sharper.
+    pub shininess: f32,
+}
+
+impl Default for LitMaterial {
+    fn default() -> Self {
+        Self {
+            base_color: Color::white(),
+            specular_color: Color::from_rgb(0.5, 0.5, 0.5),
+            shininess: 32.0,
+        }
+    }
+}
+
+impl LitMaterial {
+    /// Sets the properties on a material of the built-in lit shader.
+    pub fn bind(&self, material: &mut Material) {
+        let Color { r, g, b, a } = self.base_color;
+        material.set_per_instance_property(
+            "base_color",
+            PerInstancePropertyValue::Float32x4([r, g, b, a]),
+        );
+
+        let Color { r, g, b, .. } = self.specular_color;
+        material.set_per_instance_property(
+            "specular",
+            PerInstancePropertyValue::Float32x4([r, g, b, self.shininess]),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_no_light_packs_nothing() {
+        let uniform = LightsUniform::new(Color::from_rgb(0.1, 0.2, 0.3), [], []);
+
+        assert_eq!(uniform.ambient, [0.1, 0.2, 0.3, 0.0]);
+        assert_eq!(uniform.counts[0], 0);
+        assert_eq!(uniform.counts[1], 0);
+        assert_eq!(
+            size_of::<LightsUniform>(),
+            16 * 2 + 32 * MAX_DIRECTIONAL_LIGHTS + 64 * MAX_LOCAL_LIGHTS
+        );
+        assert!(size_of::<LightsUniform>() <= 16 * 1024);
+    }
+
+    #[test]
+    fn check_brightest_lights_are_kept() {
+        let lights = Vec::from_iter(
+            (1..=6).map(|intensity| DirectionalLight::new(Color::white(), intensity as f32)),
+        );
+        let dark = DirectionalLight::new(Color::black(), 10.0);
+        let uniform = LightsUniform::new(
+            Color::black(),
+            lights
+                .iter()
+                .map(|light| (Vec3::new(0.0, -2.0, 0.0), light))
+                .chain([(Vec3::new(0.0, -1.0, 0.0), &dark)]),
+            [],
+        );
+
+        assert_eq!(uniform.counts[0], MAX_DIRECTIONAL_LIGHTS as u32);
+        assert_eq!(
+            Vec::from_iter(uniform.directional.iter().map(|light| light.color[0])),
+            vec![6.0, 5.0, 4.0, 3.0]
+        );
+        assert_eq!(uniform.directional[0].direction, [0.0, -1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn check_attenuation_fades_out_at_range() {
+        assert_eq!(light_attenuation(0.0, 10.0), 1.0);
+        assert_eq!(light_attenuation(10.0, 10.0), 0.0);
+        assert_eq!(light_attenuation(12.0, 10.0), 0.0);
+        assert!(light_attenuation(9.99, 10.0) < 1e-5);
+
+        let samples =
+            Vec::from_iter((0..=100).map(|step| light_attenuation(step as f32 * 0.1, 10.0)));
+        assert!(samples
+            .windows(2)
+            .all(|pair| pair[1] < pair[0] || pair[1] == 0.0));
+    }
+
+    #[test]
+    fn check_most_influential_lights_are_picked() {
+        let light = |x: f32, intensity: f32, range: f32| {
+            PointLight::new(Color::white(), intensity, range).data(Vec3::new(x, 0.0, 0.0))
+        };
+        let lights = [
+            // Out of range.
+            light(20.0, 100.0, 5.0),
+            light(3.0, 1.0, 10.0),
+            // Inside the box.
+            light(0.5, 1.0, 1.0),
+            light(6.0, 1.0, 10.0),
+            light(2.0, 1.0, 10.0),
+            light(3.0, 1.0, 10.0),
+            light(9.0, 1.0, 10.0),
+        ];
+
+        let picked = pick_local_lights(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), &lights);
+        assert_eq!(picked, [2.0, 4.0, 1.0, 5.0]);
+
+        let picked = pick_local_lights(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            &lights[..2],
+        );
+        assert_eq!(picked, [1.0, -1.0, -1.0, -1.0]);
+    }
+
+    #[test]
+    fn check_spot_light_cone_is_kept_apart() {
+        let light = SpotLight::new(Color::white(), 1.0, 10.0, 1.0, 0.5);
+        let data = light.data(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, -2.0, 0.0));
+
+        assert_eq!(&data.direction[..3], &[0.0, -1.0, 0.0]);
+        assert_eq!(data.direction[3], 0.5f32.cos());
+        assert!(data.direction[3] < data.cone[0]);
+    }
+}
@@ -0,0 +1,386 @@
+use super::{
+    BindGroupEntryResource, BindingPropKey, Material, MaterialHandle, PerInstancePropertyValue,
+};
+use crate::gfx::TextureArray;
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
+use wgpu::{Device, VertexFormat};
+
+/// A handle of a material shared by renderers, which a [`MaterialInstance`] copies on write. Implemented by
+/// [`MaterialHandle`]; handles compare equal if they share the material.
+pub trait SharedMaterial: Clone + Eq + Hash {
+    type Material;
+
+    /// Copies the material behind a new handle. The copy shares the shader, the pipelines and the bind groups
+    /// of the material until they are written.
+    fn copy_material(&self) -> Self;
+
+    fn write_material<R>(&self, write: impl FnOnce(&mut Self::Material) -> R) -> R;
+
+    /// Returns the format of the per-instance property of the given name, and its value in the material.
+    fn per_instance_property(
+        &self,
+        name: &str,
+    ) -> Option<(VertexFormat, Option<PerInstancePropertyValue>)>;
+}
+
+impl SharedMaterial for MaterialHandle {
+    type Material = Material;
+
+    fn copy_material(&self) -> Self {
+        MaterialHandle::new(self.read().clone())
+    }
+
+    fn write_material<R>(&self, write: impl FnOnce(&mut Material) -> R) -> R {
+        write(&mut self.write())
+    }
+
+    fn per_instance_property(
+        &self,
+        name: &str,
+    ) -> Option<(VertexFormat, Option<PerInstancePropertyValue>)> {
+        self.read()
+            .instance_properties
+            .get(name)
+            .map(|property| (property.format, property.value.clone()))
+    }
+}
+
+/// The material of one renderer, shared with the other renderers until it is written.
+///
+/// Reads pass through to the shared material. Per-instance properties written through the instance are kept in
+/// the instance and encoded into the per-instance data of the renderer, so the renderer still draws with the
+/// shared material and batches with the others. Writing a bind group, e.g. binding another texture, copies the
+/// material first; the copy shares the shader, the pipelines and the untouched bind groups, but is a batch of its
+/// own. [`reset`](Self::reset) drops both kinds of overrides.
+///
+/// Writing the shared material itself, e.g. through [`MeshRenderer::material`](crate::gfx::MeshRenderer::material),
+/// still changes every renderer using it.
+#[derive(Debug, Clone)]
+pub struct MaterialInstance<M = MaterialHandle> {
+    shared: Option<M>,
+    copy: Option<M>,
+    properties: HashMap<String, PerInstancePropertyValue>,
+}
+
+impl<M: SharedMaterial> MaterialInstance<M> {
+    pub fn new(shared: Option<M>) -> Self {
+        Self {
+            shared,
+            copy: None,
+            properties: HashMap::new(),
+        }
+    }
+
+    /// Returns the material shared with the other renderers.
+    pub fn shared(&self) -> Option<&M> {
+        self.shared.as_ref()
+    }
+
+    /// Replaces the shared material, dropping the copy of the previous one. The per-instance properties are kept.
+    pub fn set_shared(&mut self, shared: Option<M>) {
+        self.shared = shared;
+        self.copy = None;
+    }
+
+    /// Returns the material the renderer draws with: its own copy if any, the shared material otherwise.
+    pub fn material(&self) -> Option<&M> {
+        self.copy.as_ref().or(self.shared.as_ref())
+    }
+
+    /// Returns `true` if the instance overrides anything of the shared material.
+    pub fn is_instanced(&self) -> bool {
+        self.copy.is_some() || !self.properties.is_empty()
+    }
+
+    /// Returns `true` if the instance has copied the material to write its bind groups, so that it no longer
+    /// batches with the renderers sharing the material.
+    pub fn is_copied(&self) -> bool {
+        self.copy.is_some()
+    }
+
+    /// Returns the value of the per-instance property for this renderer: its override if any, the material's
+    /// otherwise.
+    pub fn per_instance_property(&self, name: &str) -> Option<PerInstancePropertyValue> {
+        self.properties
+            .get(name)
+            .cloned()
+            .or_else(|| self.material()?.per_instance_property(name)?.1)
+    }
+
+    /// Returns the per-instance properties the instance overrides.
+    pub fn property_overrides(&self) -> &HashMap<String, PerInstancePropertyValue> {
+        &self.properties
+    }
+
+    /// Overrides the per-instance property for this renderer only. Fails if the material has no such property
+    /// or it has another format. Without a material, the value is kept and checked when rendering.
+    pub fn set_per_instance_property(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<PerInstancePropertyValue>,
+    ) -> bool {
+        let name = name.into();
+        let value = value.into();
+
+        if let Some(material) = self.material() {
+            match material.per_instance_property(&name) {
+                Some((format, _)) if format == value.to_vertex_format() => {}
+                _ => return false,
+            }
+        }
+
+        self.properties.insert(name, value);
+        true
+    }
+
+    /// Overrides the per-instance property without checking it; values of another format are ignored when
+    /// rendering.
+    pub fn set_property_override(&mut self, name: String, value: PerInstancePropertyValue) {
+        self.properties.insert(name, value);
+    }
+
+    pub fn remove_property_override(&mut self, name: &str) -> Option<PerInstancePropertyValue> {
+        self.properties.remove(name)
+    }
+
+    /// Writes the renderer's own copy of the material, copying the shared one first. A failed write, i.e.
+    /// `write` returning `false`, does not leave a fresh copy behind, so the renderer keeps batching.
+    pub fn write_copy(&mut self, write: impl FnOnce(&mut M::Material) -> bool) -> bool {
+        let is_fresh = self.copy.is_none();
+        let copy = match (&self.copy, &self.shared) {
+            (Some(copy), _) => copy.clone(),
+            (None, Some(shared)) => shared.copy_material(),
+            (None, None) => return false,
+        };
+
+        if !copy.write_material(write) {
+            return false;
+        }
+
+        if is_fresh {
+            self.copy = Some(copy);
+        }
+
+        true
+    }
+
+    /// Drops the overrides, going back to the shared material.
+    pub fn reset(&mut self) {
+        self.copy = None;
+        self.properties.clear();
+    }
+}
+
+impl MaterialInstance {
+    /// Binds a resource for this renderer only, e.g. another texture, copying the material.
+    /// Call [`update_bind_group`](Self::update_bind_group) afterwards, as for a shared material.
+    pub fn set_bind_property(
+        &mut self,
+        key: &BindingPropKey,
+        resource: impl Into<BindGroupEntryResource>,
+    ) -> bool {
+        let resource = resource.into();
+        self.write_copy(|material| material.set_bind_property(key, resource))
+    }
+
+    /// Binds the array to the `texture_2d_array` binding of the given name for this renderer only.
+    pub fn set_texture_array(
+        &mut self,
+        name: impl AsRef<str>,
+        texture_array: &TextureArray,
+    ) -> bool {
+        self.write_copy(|material| material.set_texture_array(name, texture_array))
+    }
+
+    /// Rebuilds the bind groups written since, if the material has been copied. The shared material is left alone.
+    pub fn update_bind_group(&self, device: &Device) {
+        if let Some(copy) = &self.copy {
+            copy.write().update_bind_group(device);
+        }
+    }
+}
+
+impl<M: SharedMaterial> Default for MaterialInstance<M> {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+/// Returns how many batches the renderers of the given materials form: renderers drawing with the same material
+/// share a batch, whatever per-instance properties they override.
+pub fn count_material_batches<'a, M: SharedMaterial + 'a>(
+    instances: impl IntoIterator<Item = &'a MaterialInstance<M>>,
+) -> usize {
+    instances
+        .into_iter()
+        .filter_map(|instance| instance.material())
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::RwLock;
+    use std::{hash::Hasher, sync::Arc};
+
+    /// Stands in for a material: a texture bound to it, and a color per instance.
+    #[derive(Debug, Clone)]
+    struct FakeMaterial {
+        texture: Arc<String>,
+        color: Option<PerInstancePropertyValue>,
+    }
+
+    #[derive(Debug, Clone)]
+    struct FakeHandle(Arc<RwLock<FakeMaterial>>);
+
+    impl FakeHandle {
+        fn new(texture: &'static str) -> Self {
+            Self(Arc::new(RwLock::new(FakeMaterial {
+                texture: Arc::new(texture.to_owned()),
+                color: Some(PerInstancePropertyValue::Float32x4([1.0; 4])),
+            })))
+        }
+    }
+
+    impl PartialEq for FakeHandle {
+        fn eq(&self, other: &Self) -> bool {
+            Arc::ptr_eq(&self.0, &other.0)
+        }
+    }
+
+    impl Eq for FakeHandle {}
+
+    impl Hash for FakeHandle {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            Arc::as_ptr(&self.0).hash(state);
+        }
+    }
+
+    impl SharedMaterial for FakeHandle {
+        type Material = FakeMaterial;
+
+        fn copy_material(&self) -> Self {
+            Self(Arc::new(RwLock::new(self.0.read().clone())))
+        }
+
+        fn write_material<R>(&self, write: impl FnOnce(&mut FakeMaterial) -> R) -> R {
+            write(&mut self.0.write())
+        }
+
+        fn per_instance_property(
+            &self,
+            name: &str,
+        ) -> Option<(VertexFormat, Option<PerInstancePropertyValue>)> {
+            (name == "color").then(|| (VertexFormat::Float32x4, self.0.read().color.clone()))
+        }
+    }
+
+    fn renderers(shared: &FakeHandle) -> Vec<MaterialInstance<FakeHandle>> {
+        Vec::from_iter((0..100).map(|_| MaterialInstance::new(Some(shared.clone()))))
+    }
+
+    fn texture_of(instance: &MaterialInstance<FakeHandle>) -> String {
+        instance.material().unwrap().0.read().texture.to_string()
+    }
+
+    #[test]
+    fn check_writing_one_of_100_renderers_changes_exactly_one() {
+        let shared = FakeHandle::new("bricks");
+        let mut renderers = renderers(&shared);
+
+        assert!(renderers[42].write_copy(|material| {
+            material.texture = Arc::new("moss".to_owned());
+            true
+        }));
+        let red = PerInstancePropertyValue::Float32x4([1.0, 0.0, 0.0, 1.0]);
+        assert!(renderers[7].set_per_instance_property("color", red.clone()));
+
+        let mossy = renderers.iter().filter(|r| texture_of(r) == "moss").count();
+        let reds = renderers
+            .iter()
+            .filter(|r| r.per_instance_property("color") == Some(red.clone()))
+            .count();
+        assert_eq!(mossy, 1);
+        assert_eq!(reds, 1);
+        assert_eq!(*shared.0.read().texture, "bricks");
+        assert_eq!(
+            renderers.iter().filter(|r| r.is_instanced()).count(),
+            2,
+            "only the written renderers are instanced"
+        );
+    }
+
+    #[test]
+    fn check_batches_degrade_only_for_bind_group_overrides() {
+        let shared = FakeHandle::new("bricks");
+        let mut renderers = renderers(&shared);
+        assert_eq!(count_material_batches(&renderers), 1);
+
+        // Per-instance data: still one batch.
+        for (index, renderer) in renderers.iter_mut().enumerate() {
+            let value = PerInstancePropertyValue::Float32x4([index as f32, 0.0, 0.0, 1.0]);
+            assert!(renderer.set_per_instance_property("color", value));
+        }
+        assert_eq!(count_material_batches(&renderers), 1);
+        assert!(renderers.iter().all(|renderer| !renderer.is_copied()));
+
+        // Another texture: a batch of its own, and only one.
+        for _ in 0..3 {
+            renderers[3].write_copy(|material| {
+                material.texture = Arc::new("moss".to_owned());
+                true
+            });
+        }
+        assert_eq!(count_material_batches(&renderers), 2);
+
+        renderers[3].reset();
+        assert_eq!(count_material_batches(&renderers), 1);
+        assert!(!renderers[3].is_instanced());
+        assert_eq!(texture_of(&renderers[3]), "bricks");
+    }
+
+    #[test]
+    fn check_failed_writes_do_not_copy() {
+        let shared = FakeHandle::new("bricks");
+        let mut instance = MaterialInstance::new(Some(shared));
+
+        assert!(!instance.write_copy(|_| false));
+        assert!(!instance.is_copied());
+        assert!(
+            !instance.set_per_instance_property("color", PerInstancePropertyValue::Float32([0.0]))
+        );
+        assert!(!instance
+            .set_per_instance_property("missing", PerInstancePropertyValue::Float32([0.0])));
+        assert!(!instance.is_instanced());
+    }
+
+    #[test]
+    fn check_copies_share_untouched_resources_and_drop_cleanly() {
+        let shared = FakeHandle::new("bricks");
+        let texture = shared.0.read().texture.clone();
+        let mut instance = MaterialInstance::new(Some(shared.clone()));
+
+        instance.write_copy(|material| {
+            material.color = None;
+            true
+        });
+        let copy = instance.material().cloned();
+        instance.write_copy(|material| {
+            material.color = Some(PerInstancePropertyValue::Float32x4([0.0; 4]));
+            true
+        });
+        // Copied once, sharing the texture with the shared material.
+        assert_eq!(instance.material().cloned(), copy);
+        assert_ne!(copy.as_ref(), Some(&shared));
+        drop(copy);
+        assert_eq!(Arc::strong_count(&texture), 3);
+
+        drop(instance);
+        assert_eq!(Arc::strong_count(&texture), 2);
+        assert_eq!(Arc::strong_count(&shared.0), 1);
+    }
+}
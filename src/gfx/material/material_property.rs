@@ -0,0 +1,449 @@
+use super::{
+    BindGroupEntryResource, BindingPropKey, Material, MaterialUniform, PerInstancePropertyValue,
+    UniformType, MATERIAL_UNIFORM_NAME,
+};
+use crate::{
+    gfx::{asset_preview::pop_error_scope, Color, ColorSpaceMode, TextureHandle},
+    math::{Mat4, Vec2, Vec3, Vec4},
+};
+use std::{num::NonZeroU32, sync::Arc};
+use thiserror::Error;
+use wgpu::{
+    BindingType, Device, ErrorFilter, Queue, Sampler, SamplerBindingType, TextureSampleType,
+    TextureViewDimension, VertexFormat,
+};
+use zerocopy::AsBytes;
+
+/// The type a material property is declared with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MaterialPropertyType {
+    /// A per-instance input of the format.
+    PerInstance(VertexFormat),
+    /// A member of the material uniform, see [`MATERIAL_UNIFORM_NAME`](super::MATERIAL_UNIFORM_NAME).
+    Uniform(UniformType),
+    /// A binding of the type.
+    Binding(BindingType),
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MaterialPropertyError {
+    #[error("the material has no property `{name}`")]
+    UnknownProperty { name: String },
+    #[error("property `{name}` is declared as {expected:?}, but {provided:?} was given")]
+    TypeMismatch {
+        name: String,
+        expected: MaterialPropertyType,
+        provided: MaterialPropertyType,
+    },
+    #[error("failed to rebuild bind group {group}: {message}")]
+    GroupRebuild { group: u32, message: String },
+}
+
+/// The type of the textures [`Material::set_texture`] binds.
+const TEXTURE_2D_BINDING_TYPE: BindingType = BindingType::Texture {
+    sample_type: TextureSampleType::Float { filterable: true },
+    view_dimension: TextureViewDimension::D2,
+    multisampled: false,
+};
+
+/// The type of the samplers [`Material::set_sampler`] binds.
+const SAMPLER_BINDING_TYPE: BindingType = BindingType::Sampler(SamplerBindingType::Filtering);
+
+impl Material {
+    /// The properties that can be set on the material: its per-instance inputs, the members of its uniform, and
+    /// its bindings other than the semantic ones the renderers bind and the uniform. Sorted by name.
+    pub fn properties(&self) -> Vec<(&str, MaterialPropertyType)> {
+        let uniform_members = self
+            .uniform
+            .iter()
+            .flat_map(|uniform| uniform.members())
+            .map(|member| {
+                (
+                    member.name.as_str(),
+                    MaterialPropertyType::Uniform(member.ty),
+                )
+            });
+        let mut properties = Vec::from_iter(
+            self.instance_properties
+                .iter()
+                .map(|(name, property)| {
+                    (
+                        name.as_str(),
+                        MaterialPropertyType::PerInstance(property.format),
+                    )
+                })
+                .chain(uniform_members)
+                .chain(self.bind_properties.iter().filter_map(|(key, index)| {
+                    let name = match key {
+                        BindingPropKey::StringKey(name)
+                            if self.uniform.is_some() && name == MATERIAL_UNIFORM_NAME =>
+                        {
+                            return None
+                        }
+                        BindingPropKey::StringKey(name) => name.as_str(),
+                        BindingPropKey::SemanticKey(_) => return None,
+                    };
+                    let entry_holder =
+                        &self.bind_group_holders[index.group_index].entries[index.entry_index];
+                    Some((name, MaterialPropertyType::Binding(entry_holder.binding_ty)))
+                })),
+        );
+        properties.sort_unstable_by_key(|&(name, _)| name);
+        properties
+    }
+
+    /// The type the property is declared with, if the material has it.
+    pub fn property_type(&self, name: &str) -> Option<MaterialPropertyType> {
+        if let Some(property) = self.instance_properties.get(name) {
+            return Some(MaterialPropertyType::PerInstance(property.format));
+        }
+
+        if let Some(member) = self
+            .uniform
+            .as_ref()
+            .and_then(|uniform| uniform.member(name))
+        {
+            return Some(MaterialPropertyType::Uniform(member.ty));
+        }
+
+        let index = self
+            .bind_properties
+            .get(&BindingPropKey::StringKey(name.to_owned()))?;
+        let entry_holder = &self.bind_group_holders[index.group_index].entries[index.entry_index];
+        Some(MaterialPropertyType::Binding(entry_holder.binding_ty))
+    }
+
+    pub fn set_f32(&mut self, name: &str, value: f32) -> Result<(), MaterialPropertyError> {
+        self.set_value(name, PerInstancePropertyValue::Float32([value]))
+    }
+
+    pub fn set_vec2(&mut self, name: &str, value: Vec2) -> Result<(), MaterialPropertyError> {
+        self.set_value(
+            name,
+            PerInstancePropertyValue::Float32x2([value.x, value.y]),
+        )
+    }
+
+    pub fn set_vec3(&mut self, name: &str, value: Vec3) -> Result<(), MaterialPropertyError> {
+        self.set_value(
+            name,
+            PerInstancePropertyValue::Float32x3([value.x, value.y, value.z]),
+        )
+    }
+
+    pub fn set_vec4(&mut self, name: &str, value: Vec4) -> Result<(), MaterialPropertyError> {
+        self.set_value(
+            name,
+            PerInstancePropertyValue::Float32x4([value.x, value.y, value.z, value.w]),
+        )
+    }
+
+    /// Sets a `vec4<f32>` color as the shaders of the color space expect it, see
+    /// [`ColorSpaceMode::shader_color`]. Pass [`RenderManager::color_space`](crate::gfx::RenderManager::color_space)
+    /// so that the color looks as picked whatever the space.
+    pub fn set_color(
+        &mut self,
+        name: &str,
+        color: Color,
+        color_space: ColorSpaceMode,
+    ) -> Result<(), MaterialPropertyError> {
+        let color = color_space.shader_color(color);
+        self.set_vec4(name, Vec4::new(color.r, color.g, color.b, color.a))
+    }
+
+    /// Sets a `mat4x4<f32>` member of the material uniform, or else a matrix passed per instance as four
+    /// `vec4<f32>` rows named `{name}_row_0` to `{name}_row_3`, the way the transform of a renderer is.
+    /// Nothing is set unless the material has all of the rows.
+    pub fn set_mat4(&mut self, name: &str, value: &Mat4) -> Result<(), MaterialPropertyError> {
+        if let Some(result) = self.write_uniform(name, UniformType::Mat4x4, value.as_bytes()) {
+            return result;
+        }
+
+        let row_names = [0, 1, 2, 3].map(|row| format!("{}_row_{}", name, row));
+
+        for row_name in &row_names {
+            self.check_value_type(row_name, VertexFormat::Float32x4)?;
+        }
+
+        for (row, row_name) in row_names.iter().enumerate() {
+            let row = value.row(row);
+            self.set_value(
+                row_name,
+                PerInstancePropertyValue::Float32x4([row.x, row.y, row.z, row.w]),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Binds the texture to the `texture_2d<f32>` binding of the name. The bind group is rebuilt before the
+    /// next draw, or by [`update_bind_group`](Self::update_bind_group).
+    pub fn set_texture(
+        &mut self,
+        name: &str,
+        texture: &TextureHandle,
+    ) -> Result<(), MaterialPropertyError> {
+        self.set_binding(
+            name,
+            TEXTURE_2D_BINDING_TYPE,
+            BindGroupEntryResource::TextureView {
+                texture_view: texture.view.clone(),
+            },
+        )
+    }
+
+    /// Binds the sampler to the `sampler` binding of the name. The bind group is rebuilt before the next draw,
+    /// or by [`update_bind_group`](Self::update_bind_group).
+    pub fn set_sampler(
+        &mut self,
+        name: &str,
+        sampler: Arc<Sampler>,
+    ) -> Result<(), MaterialPropertyError> {
+        self.set_binding(
+            name,
+            SAMPLER_BINDING_TYPE,
+            BindGroupEntryResource::Sampler { sampler },
+        )
+    }
+
+    /// Whether bindings have been set since the bind groups were last rebuilt.
+    pub fn is_bind_group_dirty(&self) -> bool {
+        self.bind_group_holders
+            .iter()
+            .any(|bind_group_holder| bind_group_holder.is_dirty)
+    }
+
+    /// Whether the material must be [prepared](Self::prepare) before it is drawn.
+    pub fn needs_prepare(&self) -> bool {
+        self.is_bind_group_dirty()
+            || self
+                .uniform
+                .as_ref()
+                .map_or(false, |uniform| uniform.is_dirty())
+    }
+
+    /// Uploads the uniform if it has been written, binding its buffer the first time, and rebuilds the bind groups
+    /// whose bindings have been set since. Renderers do this before drawing with the material; see
+    /// [`ShaderManager::prepare_material`](super::ShaderManager::prepare_material).
+    pub fn prepare(&mut self, device: &Device, queue: &Queue) -> Result<(), MaterialPropertyError> {
+        let buffer = self
+            .uniform
+            .as_mut()
+            .and_then(|uniform| uniform.upload(device, queue));
+
+        if let Some(buffer) = buffer {
+            self.set_bind_property(
+                &MaterialUniform::binding_key(),
+                BindGroupEntryResource::Buffer {
+                    buffer,
+                    offset: 0,
+                    size: None,
+                },
+            );
+        }
+
+        self.try_update_bind_group(device)
+    }
+
+    /// Same as [`update_bind_group`](Self::update_bind_group), returning an error instead of raising a device error
+    /// if the device rejects the rebuilt bind groups, which are then left unset.
+    pub fn try_update_bind_group(&mut self, device: &Device) -> Result<(), MaterialPropertyError> {
+        let dirty_groups = Vec::from_iter(
+            self.bind_group_holders
+                .iter()
+                .filter(|bind_group_holder| bind_group_holder.is_dirty)
+                .map(|bind_group_holder| bind_group_holder.group),
+        );
+
+        device.push_error_scope(ErrorFilter::Validation);
+        self.update_bind_group(device);
+
+        let err = match pop_error_scope(device) {
+            Some(err) => err,
+            None => return Ok(()),
+        };
+
+        // The scope does not tell which group failed; none of the rebuilt ones can be trusted.
+        for bind_group_holder in &mut self.bind_group_holders {
+            if dirty_groups.contains(&bind_group_holder.group) {
+                bind_group_holder.bind_group = None;
+            }
+        }
+
+        Err(MaterialPropertyError::GroupRebuild {
+            group: dirty_groups.first().copied().unwrap_or_default(),
+            message: err.to_string(),
+        })
+    }
+
+    fn check_value_type(
+        &self,
+        name: &str,
+        format: VertexFormat,
+    ) -> Result<(), MaterialPropertyError> {
+        let property = match self.instance_properties.get(name) {
+            Some(property) => property,
+            None => {
+                return Err(match self.property_type(name) {
+                    Some(expected) => MaterialPropertyError::TypeMismatch {
+                        name: name.to_owned(),
+                        expected,
+                        provided: MaterialPropertyType::PerInstance(format),
+                    },
+                    None => MaterialPropertyError::UnknownProperty {
+                        name: name.to_owned(),
+                    },
+                })
+            }
+        };
+
+        if property.format != format {
+            return Err(MaterialPropertyError::TypeMismatch {
+                name: name.to_owned(),
+                expected: MaterialPropertyType::PerInstance(property.format),
+                provided: MaterialPropertyType::PerInstance(format),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn write_uniform(
+        &mut self,
+        name: &str,
+        ty: UniformType,
+        bytes: &[u8],
+    ) -> Option<Result<(), MaterialPropertyError>> {
+        let result = self.uniform.as_mut()?.write(name, ty, bytes)?;
+        Some(
+            result.map_err(|expected| MaterialPropertyError::TypeMismatch {
+                name: name.to_owned(),
+                expected: MaterialPropertyType::Uniform(expected),
+                provided: MaterialPropertyType::Uniform(ty),
+            }),
+        )
+    }
+
+    fn set_value(
+        &mut self,
+        name: &str,
+        value: PerInstancePropertyValue,
+    ) -> Result<(), MaterialPropertyError> {
+        let format = value.to_vertex_format();
+
+        if !self.instance_properties.contains_key(name) {
+            if let Some(ty) = UniformType::from_vertex_format(format) {
+                if let Some(result) = self.write_uniform(name, ty, value.as_bytes()) {
+                    return result;
+                }
+            }
+        }
+
+        self.check_value_type(name, format)?;
+        self.set_per_instance_property(name, value);
+        Ok(())
+    }
+
+    fn set_binding(
+        &mut self,
+        name: &str,
+        provided: BindingType,
+        resource: BindGroupEntryResource,
+    ) -> Result<(), MaterialPropertyError> {
+        let key = BindingPropKey::StringKey(name.to_owned());
+        let index = match self.bind_properties.get(&key) {
+            Some(index) => *index,
+            None => {
+                return Err(match self.property_type(name) {
+                    Some(expected) => MaterialPropertyError::TypeMismatch {
+                        name: name.to_owned(),
+                        expected,
+                        provided: MaterialPropertyType::Binding(provided),
+                    },
+                    None => MaterialPropertyError::UnknownProperty {
+                        name: name.to_owned(),
+                    },
+                })
+            }
+        };
+        let entry_holder = &self.bind_group_holders[index.group_index].entries[index.entry_index];
+
+        if !is_binding_compatible(entry_holder.binding_ty, entry_holder.count, provided) {
+            return Err(MaterialPropertyError::TypeMismatch {
+                name: name.to_owned(),
+                expected: MaterialPropertyType::Binding(entry_holder.binding_ty),
+                provided: MaterialPropertyType::Binding(provided),
+            });
+        }
+
+        self.set_bind_property(&key, resource);
+        Ok(())
+    }
+}
+
+/// Whether a single resource of the `provided` type can be bound to a binding of the `expected` type.
+/// Textures of any sample type and samplers of any kind are accepted, as the resource is not inspected further.
+fn is_binding_compatible(
+    expected: BindingType,
+    count: Option<NonZeroU32>,
+    provided: BindingType,
+) -> bool {
+    if count.is_some() {
+        return false;
+    }
+
+    match (expected, provided) {
+        (
+            BindingType::Texture {
+                view_dimension,
+                multisampled,
+                ..
+            },
+            BindingType::Texture {
+                view_dimension: provided_view_dimension,
+                multisampled: provided_multisampled,
+                ..
+            },
+        ) => view_dimension == provided_view_dimension && multisampled == provided_multisampled,
+        (BindingType::Sampler(_), BindingType::Sampler(_)) => true,
+        (BindingType::Buffer { .. }, BindingType::Buffer { .. }) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_textures_match_dimension_but_not_arrays() {
+        let cube = BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: true },
+            view_dimension: TextureViewDimension::Cube,
+            multisampled: false,
+        };
+        let depth = BindingType::Texture {
+            sample_type: TextureSampleType::Depth,
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        };
+
+        assert!(is_binding_compatible(depth, None, TEXTURE_2D_BINDING_TYPE));
+        assert!(!is_binding_compatible(cube, None, TEXTURE_2D_BINDING_TYPE));
+        assert!(!is_binding_compatible(
+            TEXTURE_2D_BINDING_TYPE,
+            NonZeroU32::new(4),
+            TEXTURE_2D_BINDING_TYPE
+        ));
+        assert!(is_binding_compatible(
+            BindingType::Sampler(SamplerBindingType::Comparison),
+            None,
+            SAMPLER_BINDING_TYPE
+        ));
+        assert!(!is_binding_compatible(
+            SAMPLER_BINDING_TYPE,
+            None,
+            TEXTURE_2D_BINDING_TYPE
+        ));
+    }
+}
@@ -0,0 +1,64 @@
+use wgpu::{CompareFunction, StencilFaceState, StencilOperation, StencilState};
+
+/// Stencil test and operations of a material, applied to both faces.
+///
+/// The state is part of the pipeline, while the reference is set on the render pass before each command, so
+/// materials differing only by their reference share pipelines. It only applies under a
+/// [`DepthStencilMode`](crate::gfx::DepthStencilMode) with a stencil buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialStencil {
+    pub compare: CompareFunction,
+    pub reference: u32,
+    /// Applied when both the stencil and the depth tests pass.
+    pub pass_op: StencilOperation,
+    pub fail_op: StencilOperation,
+    /// Applied when the stencil test passes but the depth test fails.
+    pub depth_fail_op: StencilOperation,
+    pub read_mask: u32,
+    pub write_mask: u32,
+}
+
+impl MaterialStencil {
+    /// Writes the reference wherever the material is drawn, e.g. for a mask shape.
+    pub fn write(reference: u32) -> Self {
+        Self {
+            compare: CompareFunction::Always,
+            reference,
+            pass_op: StencilOperation::Replace,
+            fail_op: StencilOperation::Keep,
+            depth_fail_op: StencilOperation::Keep,
+            read_mask: 0xFF,
+            write_mask: 0xFF,
+        }
+    }
+
+    /// Draws only where the stencil compares with the reference, leaving it untouched,
+    /// e.g. [`CompareFunction::Equal`] for content inside a mask.
+    pub fn test(compare: CompareFunction, reference: u32) -> Self {
+        Self {
+            compare,
+            reference,
+            pass_op: StencilOperation::Keep,
+            fail_op: StencilOperation::Keep,
+            depth_fail_op: StencilOperation::Keep,
+            read_mask: 0xFF,
+            write_mask: 0,
+        }
+    }
+
+    pub fn stencil_state(&self) -> StencilState {
+        let face = StencilFaceState {
+            compare: self.compare,
+            fail_op: self.fail_op,
+            depth_fail_op: self.depth_fail_op,
+            pass_op: self.pass_op,
+        };
+
+        StencilState {
+            front: face,
+            back: face,
+            read_mask: self.read_mask,
+            write_mask: self.write_mask,
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use super::{
+    BindingPropKey, ReflectedShaderBindingElementKind, ReflectedUniformMember, ShaderHandle,
+    UniformType,
+};
+use std::sync::Arc;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    Buffer, BufferUsages, Device, Queue,
+};
+
+/// Name of the uniform struct binding a material owns the buffer of, e.g. `var<uniform> material: Material;`.
+/// Its members are set like per-instance properties; see [`Material::set_f32`](super::Material::set_f32).
+pub const MATERIAL_UNIFORM_NAME: &str = "material";
+
+/// The `material` uniform of a material, written on the CPU and uploaded before the material is next drawn.
+///
+/// A clone gets a buffer of its own on its next upload, so that writing it does not change the original.
+#[derive(Debug)]
+pub struct MaterialUniform {
+    members: Vec<ReflectedUniformMember>,
+    data: Vec<u8>,
+    buffer: Option<Arc<Buffer>>,
+    is_dirty: bool,
+}
+
+impl MaterialUniform {
+    pub fn new(size: u64, members: Vec<ReflectedUniformMember>) -> Self {
+        Self {
+            members,
+            data: vec![0; size as usize],
+            buffer: None,
+            is_dirty: true,
+        }
+    }
+
+    /// The uniform of materials of the shader, if it declares one.
+    pub fn from_shader(shader: &ShaderHandle) -> Option<Self> {
+        let binding = shader.reflected_shader.bindings.iter().find(|binding| {
+            binding.semantic_binding.is_none() && binding.name == MATERIAL_UNIFORM_NAME
+        })?;
+
+        match &binding.kind {
+            ReflectedShaderBindingElementKind::Buffer { size } => {
+                Some(Self::new(size.get(), binding.members.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn binding_key() -> BindingPropKey {
+        BindingPropKey::StringKey(MATERIAL_UNIFORM_NAME.to_owned())
+    }
+
+    pub fn members(&self) -> &[ReflectedUniformMember] {
+        &self.members
+    }
+
+    pub fn member(&self, name: &str) -> Option<&ReflectedUniformMember> {
+        self.members.iter().find(|member| member.name == name)
+    }
+
+    /// The bytes of the member, if there is one of the name.
+    pub fn value(&self, name: &str) -> Option<&[u8]> {
+        let member = self.member(name)?;
+        let offset = member.offset as usize;
+        self.data.get(offset..offset + member.ty.size() as usize)
+    }
+
+    /// Writes the bytes of a value of the type to the member of the name. Returns the type of the member
+    /// instead if it is of another one, or `None` if there is no such member.
+    pub fn write(
+        &mut self,
+        name: &str,
+        ty: UniformType,
+        bytes: &[u8],
+    ) -> Option<Result<(), UniformType>> {
+        let member = self.member(name)?;
+
+        if member.ty != ty {
+            return Some(Err(member.ty));
+        }
+
+        debug_assert_eq!(bytes.len() as u64, ty.size());
+
+        let offset = member.offset as usize;
+        self.data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self.is_dirty = true;
+        Some(Ok(()))
+    }
+
+    /// Copies the values of the members the other uniform has with the same names and types.
+    pub fn copy_values_from(&mut self, other: &MaterialUniform) {
+        for member in &other.members {
+            if let Some(value) = other.value(&member.name) {
+                self.write(&member.name, member.ty, value);
+            }
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.is_dirty
+    }
+
+    /// Uploads the values if they have been written since the last upload. Returns the buffer if it has just been
+    /// created, in which case it must be bound to the material.
+    pub fn upload(&mut self, device: &Device, queue: &Queue) -> Option<Arc<Buffer>> {
+        if !self.is_dirty {
+            return None;
+        }
+
+        self.is_dirty = false;
+
+        match &self.buffer {
+            Some(buffer) => {
+                queue.write_buffer(buffer, 0, &self.data);
+                None
+            }
+            None => {
+                let buffer = Arc::new(device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("material uniform buffer"),
+                    contents: &self.data,
+                    usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+                }));
+                self.buffer = Some(buffer.clone());
+                Some(buffer)
+            }
+        }
+    }
+}
+
+impl Clone for MaterialUniform {
+    fn clone(&self) -> Self {
+        Self {
+            members: self.members.clone(),
+            data: self.data.clone(),
+            buffer: None,
+            is_dirty: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zerocopy::AsBytes;
+
+    fn uniform() -> MaterialUniform {
+        MaterialUniform::new(
+            32,
+            vec![
+                ReflectedUniformMember {
+                    name: "tint".to_owned(),
+                    offset: 0,
+                    ty: UniformType::Float32x3,
+                },
+                ReflectedUniformMember {
+                    name: "roughness".to_owned(),
+                    offset: 12,
+                    ty: UniformType::Float32,
+                },
+            ],
+        )
+    }
+
+    #[test]
+    fn check_members_are_written_at_their_offsets() {
+        let mut uniform = uniform();
+        let tint = [1.0f32, 0.5, 0.25];
+
+        assert_eq!(
+            uniform.write("roughness", UniformType::Float32, 0.75f32.as_bytes()),
+            Some(Ok(()))
+        );
+        assert_eq!(
+            uniform.write("tint", UniformType::Float32x3, tint.as_bytes()),
+            Some(Ok(()))
+        );
+        assert_eq!(&uniform.data[0..12], tint.as_bytes());
+        assert_eq!(&uniform.data[12..16], 0.75f32.as_bytes());
+
+        assert_eq!(
+            uniform.write("tint", UniformType::Float32x4, [0.0f32; 4].as_bytes()),
+            Some(Err(UniformType::Float32x3))
+        );
+        assert_eq!(
+            uniform.write("missing", UniformType::Float32, &[0; 4]),
+            None
+        );
+    }
+
+    #[test]
+    fn check_values_carry_over_by_name_and_type() {
+        let mut old = uniform();
+        old.write(
+            "tint",
+            UniformType::Float32x3,
+            [1.0f32, 0.0, 0.0].as_bytes(),
+        );
+        old.write("roughness", UniformType::Float32, 0.5f32.as_bytes());
+
+        let mut new = MaterialUniform::new(
+            16,
+            vec![ReflectedUniformMember {
+                name: "roughness".to_owned(),
+                offset: 4,
+                ty: UniformType::Float32,
+            }],
+        );
+        new.copy_values_from(&old);
+
+        assert_eq!(new.value("roughness"), Some(0.5f32.as_bytes()));
+        assert!(new.value("tint").is_none());
+    }
+}
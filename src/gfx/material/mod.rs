@@ -1,25 +1,49 @@
+use super::{Cubemap, RenderQueue, TextureArray};
 use codegen::HandleMut;
 use std::{collections::HashMap, num::NonZeroU32, sync::Arc};
+use thiserror::Error;
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource, BindingType, Buffer,
-    BufferAddress, BufferBinding, BufferSize, Device, Sampler, TextureView, VertexFormat,
-    VertexStepMode,
+    BufferAddress, BufferBinding, BufferSize, Device, Sampler, TextureView, TextureViewDimension,
+    VertexFormat, VertexStepMode,
 };
 use zerocopy::AsBytes;
 
 mod bind_group_layout_cache;
+mod material_instance;
+mod material_property;
+mod material_stencil;
+mod material_uniform;
 mod pipeline_cache;
 mod pipeline_layout_cache;
 mod shader;
+mod shader_cache;
+mod shader_error;
 mod shader_reflection;
+mod shader_watcher;
 
 pub use bind_group_layout_cache::*;
+pub use material_instance::*;
+pub use material_property::*;
+pub use material_stencil::*;
+pub use material_uniform::*;
 pub use pipeline_cache::*;
 pub use pipeline_layout_cache::*;
 pub use shader::*;
+pub use shader_cache::*;
+pub use shader_error::*;
 pub use shader_reflection::*;
+pub use shader_watcher::*;
 
-#[derive(HandleMut)]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MaterialCreationError {
+    #[error("the shader declares no property named `{property}`")]
+    MissingProperty { property: String },
+}
+
+/// Cloning a material shares its shader, pipeline layout and bind groups, but not its uniform buffer; see
+/// [`MaterialInstance`] for copying one on write.
+#[derive(HandleMut, Clone)]
 pub struct Material {
     pub shader: ShaderHandle,
     pub pipeline_layout: CachedPipelineLayout,
@@ -27,10 +51,77 @@ pub struct Material {
     pub bind_properties: HashMap<BindingPropKey, BindGroupIndex>,
     pub bind_group_holders: Vec<BindGroupHolder>,
     pub instance_properties: HashMap<String, InstanceProperty>,
+    /// Values of the [`MATERIAL_UNIFORM_NAME`] uniform, if the shader declares it.
+    pub uniform: Option<MaterialUniform>,
+    /// The queue the material is drawn in, see [`RenderQueue`].
+    pub render_queue: RenderQueue,
+    /// Sub-order within the queue; lower orders are drawn first.
+    pub render_order: i32,
+    /// Stencil test and operations, or `None` to ignore the stencil buffer.
+    pub stencil: Option<MaterialStencil>,
 }
 
 impl Material {
     pub fn new(shader: ShaderHandle, pipeline_layout_cache: &mut PipelineLayoutCache) -> Self {
+        let pipeline_layout = Self::pipeline_layout_of(&shader, pipeline_layout_cache);
+        Self::with_pipeline_layout(shader, pipeline_layout)
+    }
+
+    /// Creates a material of the shader, failing on the first of the properties it does not declare.
+    pub fn with_properties(
+        shader: ShaderHandle,
+        pipeline_layout_cache: &mut PipelineLayoutCache,
+        properties: &[&str],
+    ) -> Result<Self, MaterialCreationError> {
+        if let Some(property) = properties
+            .iter()
+            .find(|property| !Self::declares_property(&shader, property))
+        {
+            return Err(MaterialCreationError::MissingProperty {
+                property: property.to_string(),
+            });
+        }
+
+        Ok(Self::new(shader, pipeline_layout_cache))
+    }
+
+    /// Whether the shader has a binding, a per-instance input or a member of the material uniform of the name.
+    pub fn declares_property(shader: &ShaderHandle, name: &str) -> bool {
+        let reflected_shader = &shader.reflected_shader;
+        reflected_shader.bindings.iter().any(|binding| {
+            binding.name == name
+                || (binding.name == MATERIAL_UNIFORM_NAME
+                    && binding.members.iter().any(|member| member.name == name))
+        }) || reflected_shader
+            .per_instance_input
+            .elements
+            .iter()
+            .any(|input| input.name == name)
+    }
+
+    /// The pipeline layout of materials of the shader: its bind group layouts, ordered by group.
+    pub fn pipeline_layout_of(
+        shader: &ShaderHandle,
+        pipeline_layout_cache: &mut PipelineLayoutCache,
+    ) -> CachedPipelineLayout {
+        let mut bind_group_layouts = Vec::from_iter(
+            shader
+                .bind_group_layouts
+                .iter()
+                .map(|(group, layout)| (*group, layout.clone())),
+        );
+        bind_group_layouts.sort_unstable_by_key(|(group, _)| *group);
+
+        let bind_group_layouts =
+            Vec::from_iter(bind_group_layouts.into_iter().map(|(_, layout)| layout));
+        pipeline_layout_cache.create_layout(bind_group_layouts)
+    }
+
+    /// Creates a material of the shader with the layout [`pipeline_layout_of`](Self::pipeline_layout_of) gave.
+    pub fn with_pipeline_layout(
+        shader: ShaderHandle,
+        pipeline_layout: CachedPipelineLayout,
+    ) -> Self {
         let semantic_inputs = HashMap::from_iter(
             shader
                 .reflected_shader
@@ -142,17 +233,7 @@ impl Material {
                 }),
         );
 
-        let mut bind_group_layouts = Vec::from_iter(
-            shader
-                .bind_group_layouts
-                .iter()
-                .map(|(group, layout)| (*group, layout.clone())),
-        );
-        bind_group_layouts.sort_unstable_by_key(|(group, _)| *group);
-
-        let bind_group_layouts =
-            Vec::from_iter(bind_group_layouts.into_iter().map(|(_, layout)| layout));
-        let pipeline_layout = pipeline_layout_cache.create_layout(bind_group_layouts);
+        let uniform = MaterialUniform::from_shader(&shader);
 
         Self {
             shader,
@@ -161,9 +242,62 @@ impl Material {
             bind_properties,
             bind_group_holders,
             instance_properties: per_instance_properties,
+            uniform,
+            render_queue: RenderQueue::Opaque,
+            render_order: 0,
+            stencil: None,
         }
     }
 
+    /// Creates a material of another shader, carrying over the render queue, and the bound resources and
+    /// per-instance properties the new shader still declares with the same types.
+    pub fn with_shader(
+        &self,
+        shader: ShaderHandle,
+        pipeline_layout_cache: &mut PipelineLayoutCache,
+    ) -> Self {
+        let pipeline_layout = Self::pipeline_layout_of(&shader, pipeline_layout_cache);
+        self.with_shader_and_layout(shader, pipeline_layout)
+    }
+
+    /// Same as [`with_shader`](Self::with_shader), with the layout of the shader already created.
+    pub fn with_shader_and_layout(
+        &self,
+        shader: ShaderHandle,
+        pipeline_layout: CachedPipelineLayout,
+    ) -> Self {
+        let mut material = Self::with_pipeline_layout(shader, pipeline_layout);
+        material.render_queue = self.render_queue;
+        material.render_order = self.render_order;
+        material.stencil = self.stencil;
+
+        for (key, index) in &self.bind_properties {
+            // The new material binds a buffer of its own.
+            if self.uniform.is_some() && *key == MaterialUniform::binding_key() {
+                continue;
+            }
+
+            let entry_holder =
+                &self.bind_group_holders[index.group_index].entries[index.entry_index];
+
+            if let Some(resource) = &entry_holder.resource {
+                material.set_bind_property(key, resource.clone());
+            }
+        }
+
+        for (name, property) in &self.instance_properties {
+            if let Some(value) = &property.value {
+                material.set_per_instance_property(name, value.clone());
+            }
+        }
+
+        if let (Some(uniform), Some(previous)) = (&mut material.uniform, &self.uniform) {
+            uniform.copy_values_from(previous);
+        }
+
+        material
+    }
+
     pub fn set_bind_property(
         &mut self,
         key: &BindingPropKey,
@@ -187,6 +321,68 @@ impl Material {
         true
     }
 
+    /// Binds the array to the `texture_2d_array` binding of the given name.
+    /// Fails if there is no such binding or it is declared as another kind of texture.
+    pub fn set_texture_array(
+        &mut self,
+        name: impl AsRef<str>,
+        texture_array: &TextureArray,
+    ) -> bool {
+        let key = BindingPropKey::StringKey(name.as_ref().to_owned());
+        let index = if let Some(index) = self.bind_properties.get(&key) {
+            *index
+        } else {
+            return false;
+        };
+        let entry_holder = &self.bind_group_holders[index.group_index].entries[index.entry_index];
+
+        if !matches!(
+            entry_holder.binding_ty,
+            BindingType::Texture {
+                view_dimension: TextureViewDimension::D2Array,
+                ..
+            }
+        ) {
+            return false;
+        }
+
+        self.set_bind_property(
+            &key,
+            BindGroupEntryResource::TextureView {
+                texture_view: texture_array.view.clone(),
+            },
+        )
+    }
+
+    /// Binds the cubemap to the `texture_cube` binding of the given name.
+    /// Fails if there is no such binding or it is declared as another kind of texture.
+    pub fn set_cubemap(&mut self, name: impl AsRef<str>, cubemap: &Cubemap) -> bool {
+        let key = BindingPropKey::StringKey(name.as_ref().to_owned());
+        let index = if let Some(index) = self.bind_properties.get(&key) {
+            *index
+        } else {
+            return false;
+        };
+        let entry_holder = &self.bind_group_holders[index.group_index].entries[index.entry_index];
+
+        if !matches!(
+            entry_holder.binding_ty,
+            BindingType::Texture {
+                view_dimension: TextureViewDimension::Cube,
+                ..
+            }
+        ) {
+            return false;
+        }
+
+        self.set_bind_property(
+            &key,
+            BindGroupEntryResource::TextureView {
+                texture_view: cubemap.view.clone(),
+            },
+        )
+    }
+
     pub fn set_per_instance_property(
         &mut self,
         name: impl AsRef<str>,
@@ -247,11 +443,12 @@ impl Material {
                 },
             ));
 
-            bind_group_holder.bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
-                label: None,
-                layout: layout.as_ref(),
-                entries: &entries,
-            }));
+            bind_group_holder.bind_group =
+                Some(Arc::new(device.create_bind_group(&BindGroupDescriptor {
+                    label: None,
+                    layout: layout.as_ref(),
+                    entries: &entries,
+                })));
         }
     }
 }
@@ -276,11 +473,12 @@ pub struct BindGroupIndex {
     pub entry_index: usize,
 }
 
-#[derive(Debug)]
+/// Shared by the clones of the material until it is rebuilt.
+#[derive(Debug, Clone)]
 pub struct BindGroupHolder {
     pub is_dirty: bool,
     pub group: u32,
-    pub bind_group: Option<BindGroup>,
+    pub bind_group: Option<Arc<BindGroup>>,
     pub entries: Vec<BindGroupEntryHolder>,
 }
 
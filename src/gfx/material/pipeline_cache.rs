@@ -1,13 +1,19 @@
 use super::{CachedPipelineLayout, ShaderHandle, ShaderManager};
-use crate::gfx::GfxContextHandle;
+use crate::gfx::{
+    debug_view_variant, ColorSpaceMode, DebugViewInputs, DebugViewMode, DebugViewShaders,
+    DebugViewVariant, DepthStencilMode, GfxContextHandle,
+};
 use std::{
     collections::HashMap,
+    fmt::Debug,
     hash::Hash,
     sync::{Arc, Weak},
 };
 use wgpu::{
-    BufferAddress, DepthStencilState, Device, FragmentState, PrimitiveState, RenderPipeline,
-    RenderPipelineDescriptor, VertexAttribute, VertexBufferLayout, VertexState, VertexStepMode,
+    BufferAddress, ColorTargetState, CompareFunction, DepthStencilState, Device, Features,
+    FragmentState, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipeline,
+    RenderPipelineDescriptor, TextureFormat, VertexAttribute, VertexBufferLayout, VertexState,
+    VertexStepMode,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -17,22 +23,37 @@ pub struct BufferLayout {
     pub attributes: Vec<VertexAttribute>,
 }
 
+impl BufferLayout {
+    pub fn vertex_buffer_layout(&self) -> VertexBufferLayout {
+        VertexBufferLayout {
+            array_stride: self.array_stride,
+            step_mode: self.step_mode,
+            attributes: &self.attributes,
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct PipelineKey {
     pub layout: CachedPipelineLayout,
     pub shader: ShaderHandle,
     pub buffer_layouts: Vec<BufferLayout>,
+    /// Its topology is part of the key, so the same shader can be drawn as triangles and as lines.
     pub primitive: PrimitiveState,
     pub depth_stencil: Option<DepthStencilState>,
+    /// Format of the color targets, which follows the one the scene is drawn in.
+    pub color_format: TextureFormat,
+    /// How the pipeline draws under the debug view mode it has been created in.
+    pub variant: DebugViewVariant,
 }
 
 impl PipelineKey {
-    pub fn create_pipeline(&self, device: &Device, shader_mgr: &ShaderManager) -> RenderPipeline {
-        let buffers = Vec::from_iter(self.buffer_layouts.iter().map(|buffer| VertexBufferLayout {
-            array_stride: buffer.array_stride,
-            step_mode: buffer.step_mode,
-            attributes: &buffer.attributes,
-        }));
+    pub fn topology(&self) -> PrimitiveTopology {
+        self.primitive.topology
+    }
+
+    /// Color targets of the shader outputs, indexed by location, in the color format of the key.
+    pub fn color_targets(&self, shader_mgr: &ShaderManager) -> Vec<Option<ColorTargetState>> {
         let max_target_location = self
             .shader
             .reflected_shader
@@ -47,11 +68,25 @@ impl PipelineKey {
             let target = output.semantic_output.and_then(|key| {
                 shader_mgr
                     .get_semantic_output(key)
-                    .map(|output| output.target.clone())
+                    .map(|output| ColorTargetState {
+                        format: self.color_format,
+                        ..output.target.clone()
+                    })
             });
             targets[output.location as usize] = target;
         }
 
+        targets
+    }
+
+    pub fn create_pipeline(&self, device: &Device, shader_mgr: &ShaderManager) -> RenderPipeline {
+        let buffers = Vec::from_iter(
+            self.buffer_layouts
+                .iter()
+                .map(|buffer| buffer.vertex_buffer_layout()),
+        );
+        let targets = self.color_targets(shader_mgr);
+
         device.create_render_pipeline(&RenderPipelineDescriptor {
             label: None,
             layout: Some(self.layout.as_ref()),
@@ -73,14 +108,28 @@ impl PipelineKey {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct CachedPipeline {
+    key: Arc<PipelineKey>,
     pipeline: Arc<RenderPipeline>,
 }
 
 impl CachedPipeline {
-    pub fn new(pipeline: Arc<RenderPipeline>) -> Self {
-        Self { pipeline }
+    pub fn new(key: Arc<PipelineKey>, pipeline: Arc<RenderPipeline>) -> Self {
+        Self { key, pipeline }
+    }
+
+    /// The states the pipeline has been created from.
+    pub fn key(&self) -> &PipelineKey {
+        &self.key
+    }
+}
+
+impl Debug for CachedPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CachedPipeline")
+            .field("pipeline", &self.pipeline)
+            .finish_non_exhaustive()
     }
 }
 
@@ -104,17 +153,143 @@ impl Hash for CachedPipeline {
     }
 }
 
+/// Pipeline requests served since the stats were last taken.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineCacheStats {
+    pub hits: u32,
+    /// Requests that created a pipeline.
+    pub misses: u32,
+}
+
 pub struct PipelineCache {
     gfx_ctx: GfxContextHandle,
-    caches: HashMap<PipelineKey, Weak<RenderPipeline>>,
+    depth_stencil_mode: DepthStencilMode,
+    color_format: TextureFormat,
+    color_space: ColorSpaceMode,
+    debug_view: DebugViewMode,
+    debug_view_shaders: DebugViewShaders,
+    caches: HashMap<Arc<PipelineKey>, Weak<RenderPipeline>>,
+    stats: PipelineCacheStats,
 }
 
 impl PipelineCache {
-    pub fn new(gfx_ctx: GfxContextHandle) -> Self {
+    pub fn new(gfx_ctx: GfxContextHandle, depth_stencil_mode: DepthStencilMode) -> Self {
+        let color_format = gfx_ctx.surface_config.read().format;
+
         Self {
             gfx_ctx,
+            depth_stencil_mode,
+            color_format,
+            color_space: ColorSpaceMode::default(),
+            debug_view: DebugViewMode::default(),
+            debug_view_shaders: DebugViewShaders::default(),
             caches: HashMap::new(),
+            stats: PipelineCacheStats::default(),
+        }
+    }
+
+    /// Returns the stats gathered since the last call, and starts over.
+    pub fn take_stats(&mut self) -> PipelineCacheStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    pub fn depth_stencil_mode(&self) -> DepthStencilMode {
+        self.depth_stencil_mode
+    }
+
+    /// Pipelines created afterwards target a depth stencil attachment of this mode, and the cached ones are dropped.
+    /// Renderers pick their new pipelines up as they obtain them.
+    pub fn set_depth_stencil_mode(&mut self, depth_stencil_mode: DepthStencilMode) {
+        if depth_stencil_mode != self.depth_stencil_mode {
+            self.invalidate_all();
         }
+
+        self.depth_stencil_mode = depth_stencil_mode;
+    }
+
+    /// Number of pipelines cached, including the ones no renderer holds anymore.
+    pub fn len(&self) -> usize {
+        self.caches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.caches.is_empty()
+    }
+
+    pub fn color_format(&self) -> TextureFormat {
+        self.color_format
+    }
+
+    /// Pipelines created afterwards draw into color targets of this format.
+    /// Renderers pick their new pipelines up as they obtain them.
+    pub fn set_color_format(&mut self, color_format: TextureFormat) {
+        self.color_format = color_format;
+    }
+
+    /// Space the renderers pass their colors to the shaders in, through
+    /// [`ColorSpaceMode::shader_color`]. It follows the one of the render manager.
+    pub fn color_space(&self) -> ColorSpaceMode {
+        self.color_space
+    }
+
+    pub fn set_color_space(&mut self, color_space: ColorSpaceMode) {
+        self.color_space = color_space;
+    }
+
+    pub fn debug_view(&self) -> DebugViewMode {
+        self.debug_view
+    }
+
+    /// Pipelines created afterwards draw as the mode shows the scene. Renderers pick their new pipelines up as they
+    /// obtain them, and the ones of the previous mode are dropped once no renderer holds them anymore.
+    pub fn set_debug_view(&mut self, debug_view: DebugViewMode) {
+        self.debug_view = debug_view;
+    }
+
+    /// The variant the pipelines of the shader drawing vertices of the topology are created in under the current
+    /// debug view mode.
+    pub fn debug_view_variant(
+        &self,
+        shader: &ShaderHandle,
+        topology: PrimitiveTopology,
+    ) -> DebugViewVariant {
+        if self.debug_view == DebugViewMode::None {
+            return DebugViewVariant::Material;
+        }
+
+        debug_view_variant(
+            self.debug_view,
+            topology,
+            DebugViewInputs::of_shader(&shader.reflected_shader).is_some(),
+            self.gfx_ctx
+                .device
+                .features()
+                .contains(Features::POLYGON_MODE_LINE),
+        )
+    }
+
+    /// Number of pipelines kept, counting the ones no renderer holds anymore until the next creation drops them.
+    pub fn pipeline_count(&self) -> usize {
+        self.caches.len()
+    }
+
+    /// The depth stencil state a renderer asking for `depth_stencil` is drawn with under the current mode.
+    pub fn depth_stencil_state(
+        &self,
+        depth_stencil: Option<DepthStencilState>,
+    ) -> Option<DepthStencilState> {
+        adapt_depth_stencil(depth_stencil, self.depth_stencil_mode)
+    }
+
+    /// Forgets the pipelines of the shader, e.g. once it has been reloaded. Renderers still holding them keep them
+    /// until they obtain new ones.
+    pub fn invalidate_shader(&mut self, shader: &ShaderHandle) {
+        self.caches.retain(|key, _| &key.shader != shader);
+    }
+
+    /// Drops every cached pipeline. Renderers holding one keep drawing with it until they obtain a new one.
+    pub fn invalidate_all(&mut self) {
+        self.caches.clear();
     }
 
     pub fn create_pipeline(
@@ -124,23 +299,160 @@ impl PipelineCache {
         shader: ShaderHandle,
         buffer_layouts: Vec<BufferLayout>,
         primitive: PrimitiveState,
+        topology: PrimitiveTopology,
         depth_stencil: Option<DepthStencilState>,
     ) -> CachedPipeline {
+        let variant = self.debug_view_variant(&shader, topology);
+        let mut primitive = with_topology(primitive, topology);
+
+        if variant == DebugViewVariant::PolygonLine {
+            primitive.polygon_mode = PolygonMode::Line;
+        }
+
         let key = PipelineKey {
             layout,
             shader,
             buffer_layouts,
             primitive,
-            depth_stencil,
+            depth_stencil: self.depth_stencil_state(depth_stencil),
+            color_format: self.color_format,
+            variant,
         };
 
-        if let Some(pipeline) = self.caches.get(&key).and_then(|weak| weak.upgrade()) {
-            return CachedPipeline::new(pipeline);
+        if let Some((key, pipeline)) = self
+            .caches
+            .get_key_value(&key)
+            .and_then(|(key, weak)| Some((key, weak.upgrade()?)))
+        {
+            self.stats.hits += 1;
+            return CachedPipeline::new(key.clone(), pipeline);
         }
 
-        let pipeline = Arc::new(key.create_pipeline(&self.gfx_ctx.device, shader_mgr));
-        self.caches.insert(key, Arc::downgrade(&pipeline));
+        self.stats.misses += 1;
+        // Pipelines no renderer holds anymore, e.g. the ones of a debug view mode left since, go along.
+        self.caches
+            .retain(|_, pipeline| pipeline.strong_count() != 0);
+
+        let key = Arc::new(key);
+        let device = &self.gfx_ctx.device;
+        let pipeline = match self.debug_view_shaders.create_pipeline(device, &key) {
+            Some(pipeline) => pipeline,
+            None => key.create_pipeline(device, shader_mgr),
+        };
+        let pipeline = Arc::new(pipeline);
+        self.caches.insert(key.clone(), Arc::downgrade(&pipeline));
+
+        CachedPipeline::new(key, pipeline)
+    }
+}
+
+/// Replaces the topology of the primitive state. The strip index format only applies to strips.
+pub fn with_topology(primitive: PrimitiveState, topology: PrimitiveTopology) -> PrimitiveState {
+    let strip_index_format = if topology.is_strip() {
+        primitive.strip_index_format
+    } else {
+        None
+    };
+
+    PrimitiveState {
+        topology,
+        strip_index_format,
+        ..primitive
+    }
+}
+
+/// Fits the state of a renderer to the attachment of the mode. Renderers declare their states against a `Depth32Float`
+/// attachment; the mode decides the actual format, and which of the depth and stencil tests apply.
+pub fn adapt_depth_stencil(
+    depth_stencil: Option<DepthStencilState>,
+    mode: DepthStencilMode,
+) -> Option<DepthStencilState> {
+    let mut depth_stencil = depth_stencil?;
+    depth_stencil.format = mode.as_texture_format()?;
+
+    if !mode.has_depth() {
+        depth_stencil.depth_write_enabled = false;
+        depth_stencil.depth_compare = CompareFunction::Always;
+        depth_stencil.bias = Default::default();
+    }
+
+    if !mode.has_stencil() {
+        depth_stencil.stencil = Default::default();
+    }
+
+    Some(depth_stencil)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::{IndexFormat, StencilState, TextureFormat};
+
+    fn depth_stencil_state() -> DepthStencilState {
+        DepthStencilState {
+            format: TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: CompareFunction::Less,
+            stencil: StencilState {
+                read_mask: 0xFF,
+                write_mask: 0xFF,
+                ..Default::default()
+            },
+            bias: Default::default(),
+        }
+    }
+
+    #[test]
+    fn check_topology_distinguishes_primitive_states() {
+        let triangles = with_topology(Default::default(), PrimitiveTopology::TriangleList);
+        let lines = with_topology(Default::default(), PrimitiveTopology::LineList);
+
+        assert_ne!(triangles, lines);
+        assert_eq!(lines.topology, PrimitiveTopology::LineList);
+    }
+
+    #[test]
+    fn check_strip_index_format_is_dropped_for_lists() {
+        let strip = PrimitiveState {
+            strip_index_format: Some(IndexFormat::Uint16),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            with_topology(strip, PrimitiveTopology::LineStrip).strip_index_format,
+            Some(IndexFormat::Uint16)
+        );
+        assert_eq!(
+            with_topology(strip, PrimitiveTopology::PointList).strip_index_format,
+            None
+        );
+    }
+
+    #[test]
+    fn check_depth_stencil_follows_the_mode() {
+        assert_eq!(
+            adapt_depth_stencil(Some(depth_stencil_state()), DepthStencilMode::None),
+            None
+        );
+
+        let depth_only =
+            adapt_depth_stencil(Some(depth_stencil_state()), DepthStencilMode::DepthOnly).unwrap();
+        assert_eq!(depth_only.format, TextureFormat::Depth32Float);
+        assert_eq!(depth_only.stencil, StencilState::default());
+
+        let depth_stencil =
+            adapt_depth_stencil(Some(depth_stencil_state()), DepthStencilMode::DepthStencil)
+                .unwrap();
+        assert_eq!(depth_stencil.format, TextureFormat::Depth24PlusStencil8);
+        assert_eq!(depth_stencil.depth_compare, CompareFunction::Less);
+        assert_eq!(depth_stencil.stencil, depth_stencil_state().stencil);
 
-        CachedPipeline::new(pipeline)
+        let stencil_only =
+            adapt_depth_stencil(Some(depth_stencil_state()), DepthStencilMode::StencilOnly)
+                .unwrap();
+        assert_eq!(stencil_only.format, TextureFormat::Depth24PlusStencil8);
+        assert_eq!(stencil_only.depth_compare, CompareFunction::Always);
+        assert!(!stencil_only.depth_write_enabled);
+        assert_eq!(stencil_only.stencil, depth_stencil_state().stencil);
     }
 }
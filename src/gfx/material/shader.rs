@@ -1,18 +1,24 @@
-use super::{inspect_shader, BindGroupLayoutCache, CachedBindGroupLayout, ShaderInspectionError};
-use crate::gfx::{GfxContextHandle, ReflectedShader};
+use super::{
+    inspect_shader, BindGroupLayoutCache, CachedBindGroupLayout, CachedPipelineLayout, Material,
+    MaterialPropertyError, PipelineLayoutCache, ShaderCache, ShaderCacheConfig,
+    ShaderCacheKeyHasher, ShaderCacheStats, ShaderCompileError, ShaderCompileErrorKind,
+};
+use crate::gfx::{asset_preview::pop_error_scope, GfxContextHandle, ReflectedShader};
 use codegen::Handle;
+use parking_lot::Mutex;
 use std::{
     borrow::Cow,
     collections::{hash_map::Entry, HashMap},
     num::NonZeroU32,
 };
 use wgpu::{
-    BindGroupLayoutEntry, BindingType, ColorTargetState, ShaderModule, ShaderModuleDescriptor,
-    ShaderSource, VertexFormat, VertexStepMode,
+    BindGroupLayoutEntry, BindingType, ColorTargetState, ErrorFilter, ShaderModule,
+    ShaderModuleDescriptor, ShaderSource, VertexFormat, VertexStepMode,
 };
 
 pub mod semantic_bindings {
     use super::{SemanticShaderBinding, SemanticShaderBindingKey};
+    use crate::gfx::{LightsUniform, MESH_SKIN_MAX_BONES};
     use std::{mem::size_of, num::NonZeroU64};
     use wgpu::{
         BindingType, BufferBindingType, SamplerBindingType, TextureSampleType, TextureViewDimension,
@@ -44,6 +50,19 @@ pub mod semantic_bindings {
         },
         count: None,
     };
+    pub const KEY_LIGHTS: SemanticShaderBindingKey = SemanticShaderBindingKey::new(3);
+    pub const LIGHTS: SemanticShaderBinding = SemanticShaderBinding {
+        key: KEY_LIGHTS,
+        name: "lights",
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: Some(unsafe {
+                NonZeroU64::new_unchecked(size_of::<LightsUniform>() as u64)
+            }),
+        },
+        count: None,
+    };
 
     pub const KEY_SPRITE_TEXTURE: SemanticShaderBindingKey = SemanticShaderBindingKey::new(101);
     pub const SPRITE_TEXTURE: SemanticShaderBinding = SemanticShaderBinding {
@@ -63,6 +82,46 @@ pub mod semantic_bindings {
         ty: BindingType::Sampler(SamplerBindingType::Filtering),
         count: None,
     };
+
+    pub const KEY_TERRAIN_HEIGHTS: SemanticShaderBindingKey = SemanticShaderBindingKey::new(201);
+    pub const TERRAIN_HEIGHTS: SemanticShaderBinding = SemanticShaderBinding {
+        key: KEY_TERRAIN_HEIGHTS,
+        name: "terrain_heights",
+        ty: BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: true },
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+
+    pub const KEY_WATER: SemanticShaderBindingKey = SemanticShaderBindingKey::new(301);
+    pub const WATER: SemanticShaderBinding = SemanticShaderBinding {
+        key: KEY_WATER,
+        name: "water",
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: Some(unsafe {
+                NonZeroU64::new_unchecked(size_of::<[f32; 4 * (3 + 16)]>() as u64)
+            }),
+        },
+        count: None,
+    };
+
+    pub const KEY_BONE_MATRICES: SemanticShaderBindingKey = SemanticShaderBindingKey::new(401);
+    pub const BONE_MATRICES: SemanticShaderBinding = SemanticShaderBinding {
+        key: KEY_BONE_MATRICES,
+        name: "bone_matrices",
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: Some(unsafe {
+                NonZeroU64::new_unchecked(size_of::<[[f32; 4 * 4]; MESH_SKIN_MAX_BONES]>() as u64)
+            }),
+        },
+        count: None,
+    };
 }
 
 pub mod semantic_inputs {
@@ -83,13 +142,53 @@ pub mod semantic_inputs {
         format: VertexFormat::Float32x3,
         step_mode: VertexStepMode::Vertex,
     };
+    /// First UV set, also found as `uv0` in shaders.
     pub const KEY_UV: SemanticShaderInputKey = SemanticShaderInputKey::new(3);
+    pub const KEY_UV0: SemanticShaderInputKey = KEY_UV;
     pub const UV: SemanticShaderInput = SemanticShaderInput {
         key: KEY_UV,
         name: "uv",
         format: VertexFormat::Float32x2,
         step_mode: VertexStepMode::Vertex,
     };
+    pub const KEY_VERTEX_COLOR: SemanticShaderInputKey = SemanticShaderInputKey::new(4);
+    pub const VERTEX_COLOR: SemanticShaderInput = SemanticShaderInput {
+        key: KEY_VERTEX_COLOR,
+        name: "vertex_color",
+        format: VertexFormat::Float32x4,
+        step_mode: VertexStepMode::Vertex,
+    };
+    /// Tangent in xyz, with the handedness of the bitangent, `cross(normal, tangent)`, in w.
+    pub const KEY_TANGENT: SemanticShaderInputKey = SemanticShaderInputKey::new(5);
+    pub const TANGENT: SemanticShaderInput = SemanticShaderInput {
+        key: KEY_TANGENT,
+        name: "tangent",
+        format: VertexFormat::Float32x4,
+        step_mode: VertexStepMode::Vertex,
+    };
+    /// Second UV set, as for lightmaps.
+    pub const KEY_UV1: SemanticShaderInputKey = SemanticShaderInputKey::new(6);
+    pub const UV1: SemanticShaderInput = SemanticShaderInput {
+        key: KEY_UV1,
+        name: "uv1",
+        format: VertexFormat::Float32x2,
+        step_mode: VertexStepMode::Vertex,
+    };
+    /// Indices of the four bones moving the vertex into `bone_matrices`, as floats.
+    pub const KEY_BONE_INDICES: SemanticShaderInputKey = SemanticShaderInputKey::new(7);
+    pub const BONE_INDICES: SemanticShaderInput = SemanticShaderInput {
+        key: KEY_BONE_INDICES,
+        name: "bone_indices",
+        format: VertexFormat::Float32x4,
+        step_mode: VertexStepMode::Vertex,
+    };
+    pub const KEY_BONE_WEIGHTS: SemanticShaderInputKey = SemanticShaderInputKey::new(8);
+    pub const BONE_WEIGHTS: SemanticShaderInput = SemanticShaderInput {
+        key: KEY_BONE_WEIGHTS,
+        name: "bone_weights",
+        format: VertexFormat::Float32x4,
+        step_mode: VertexStepMode::Vertex,
+    };
 
     pub const KEY_TRANSFORM_ROW_0: SemanticShaderInputKey = SemanticShaderInputKey::new(101);
     pub const TRANSFORM_ROW_0: SemanticShaderInput = SemanticShaderInput {
@@ -170,6 +269,30 @@ pub mod semantic_inputs {
         format: VertexFormat::Float32,
         step_mode: VertexStepMode::Instance,
     };
+
+    pub const KEY_TERRAIN_CHUNK: SemanticShaderInputKey = SemanticShaderInputKey::new(401);
+    pub const TERRAIN_CHUNK: SemanticShaderInput = SemanticShaderInput {
+        key: KEY_TERRAIN_CHUNK,
+        name: "terrain_chunk",
+        format: VertexFormat::Float32x4,
+        step_mode: VertexStepMode::Instance,
+    };
+    pub const KEY_TERRAIN_PARAMS: SemanticShaderInputKey = SemanticShaderInputKey::new(402);
+    pub const TERRAIN_PARAMS: SemanticShaderInput = SemanticShaderInput {
+        key: KEY_TERRAIN_PARAMS,
+        name: "terrain_params",
+        format: VertexFormat::Float32x4,
+        step_mode: VertexStepMode::Instance,
+    };
+
+    /// Indices of the local lights shading the instance into the `lights` uniform, as floats, `-1` for none.
+    pub const KEY_LIGHT_INDICES: SemanticShaderInputKey = SemanticShaderInputKey::new(501);
+    pub const LIGHT_INDICES: SemanticShaderInput = SemanticShaderInput {
+        key: KEY_LIGHT_INDICES,
+        name: "light_indices",
+        format: VertexFormat::Float32x4,
+        step_mode: VertexStepMode::Instance,
+    };
 }
 
 pub mod semantic_outputs {
@@ -254,11 +377,20 @@ pub struct SemanticShaderOutput {
 
 #[derive(Handle)]
 pub struct Shader {
+    /// WGSL source the module has been compiled from, kept for frame captures.
+    pub source: String,
     pub shader_module: ShaderModule,
     pub bind_group_layouts: HashMap<u32, CachedBindGroupLayout>,
     pub reflected_shader: ReflectedShader,
 }
 
+/// The shader a reloaded one has been replaced with, and the pipeline layout of its materials.
+#[derive(Clone)]
+struct ShaderReload {
+    shader: ShaderHandle,
+    pipeline_layout: CachedPipelineLayout,
+}
+
 pub struct ShaderManager {
     gfx_ctx: GfxContextHandle,
     binding_names: HashMap<&'static str, SemanticShaderBindingKey>,
@@ -267,10 +399,14 @@ pub struct ShaderManager {
     bindings: HashMap<SemanticShaderBindingKey, SemanticShaderBinding>,
     inputs: HashMap<SemanticShaderInputKey, SemanticShaderInput>,
     outputs: HashMap<SemanticShaderOutputKey, SemanticShaderOutput>,
+    cache: Mutex<ShaderCache>,
+    /// Keyed by the replaced shaders, which are kept alive so that their addresses are not reused.
+    reloads: Mutex<HashMap<ShaderHandle, ShaderReload>>,
 }
 
 impl ShaderManager {
-    pub fn new(gfx_ctx: GfxContextHandle) -> Self {
+    /// Creates a shader manager. Reflection results are cached on disk if a cache config is given.
+    pub fn new(gfx_ctx: GfxContextHandle, cache_config: Option<ShaderCacheConfig>) -> Self {
         let mut this = Self {
             gfx_ctx,
             binding_names: HashMap::new(),
@@ -279,16 +415,27 @@ impl ShaderManager {
             bindings: HashMap::new(),
             inputs: HashMap::new(),
             outputs: HashMap::new(),
+            cache: Mutex::new(ShaderCache::new(cache_config)),
+            reloads: Mutex::new(HashMap::new()),
         };
 
         this.register_binding(semantic_bindings::CAMERA_TRANSFORM);
         this.register_binding(semantic_bindings::SCREEN_SIZE);
+        this.register_binding(semantic_bindings::LIGHTS);
         this.register_binding(semantic_bindings::SPRITE_TEXTURE);
         this.register_binding(semantic_bindings::SPRITE_SAMPLER);
+        this.register_binding(semantic_bindings::TERRAIN_HEIGHTS);
+        this.register_binding(semantic_bindings::WATER);
+        this.register_binding(semantic_bindings::BONE_MATRICES);
 
         this.register_input(semantic_inputs::POSITION);
         this.register_input(semantic_inputs::NORMAL);
         this.register_input(semantic_inputs::UV);
+        this.register_input(semantic_inputs::VERTEX_COLOR);
+        this.register_input(semantic_inputs::TANGENT);
+        this.register_input(semantic_inputs::UV1);
+        this.register_input(semantic_inputs::BONE_INDICES);
+        this.register_input(semantic_inputs::BONE_WEIGHTS);
         this.register_input(semantic_inputs::TRANSFORM_ROW_0);
         this.register_input(semantic_inputs::TRANSFORM_ROW_1);
         this.register_input(semantic_inputs::TRANSFORM_ROW_2);
@@ -300,6 +447,12 @@ impl ShaderManager {
         this.register_input(semantic_inputs::SPRITE_COLOR);
         this.register_input(semantic_inputs::GLYPH_THICKNESS);
         this.register_input(semantic_inputs::GLYPH_SMOOTHNESS);
+        this.register_input(semantic_inputs::TERRAIN_CHUNK);
+        this.register_input(semantic_inputs::TERRAIN_PARAMS);
+        this.register_input(semantic_inputs::LIGHT_INDICES);
+
+        // Shaders reading both UV sets may name the first one after the second.
+        this.input_names.insert("uv0", semantic_inputs::KEY_UV0);
 
         this.register_output(semantic_outputs::COLOR);
 
@@ -351,29 +504,188 @@ impl ShaderManager {
         self.outputs.get(&key)
     }
 
+    /// Hits, misses and bytes transferred by the shader reflection cache since startup.
+    pub fn cache_stats(&self) -> ShaderCacheStats {
+        self.cache.lock().stats()
+    }
+
+    /// Reflection depends on the registered semantics as well, so they are part of the key.
+    fn shader_cache_key(&self, source: &str) -> u64 {
+        let mut hasher = ShaderCacheKeyHasher::new();
+        let mut semantics = Vec::with_capacity(self.bindings.len() + self.inputs.len());
+        semantics.extend(self.bindings.values().map(|binding| {
+            format!(
+                "binding {} {} {:?} {:?}",
+                binding.key.get(),
+                binding.name,
+                binding.ty,
+                binding.count
+            )
+        }));
+        semantics.extend(self.inputs.values().map(|input| {
+            format!(
+                "input {} {} {:?} {:?}",
+                input.key.get(),
+                input.name,
+                input.format,
+                input.step_mode
+            )
+        }));
+        semantics.extend(self.outputs.values().map(|output| {
+            format!(
+                "output {} {} {:?} {}",
+                output.key.get(),
+                output.name,
+                output.target,
+                output.location
+            )
+        }));
+        semantics.sort_unstable();
+
+        for semantic in &semantics {
+            hasher.write_str(semantic);
+        }
+
+        hasher.write_str(source);
+        hasher.finish()
+    }
+
     pub fn create_shader(
         &self,
         bind_group_layout_cache: &mut BindGroupLayoutCache,
         source: impl AsRef<str>,
-    ) -> Result<ShaderHandle, ShaderInspectionError> {
-        let (reflected_shader, shader_module) = self.compile_shader(source)?;
+    ) -> Result<ShaderHandle, ShaderCompileError> {
+        let source = source.as_ref();
+        let (reflected_shader, shader_module) = self.compile_shader(None, source)?;
 
-        Ok(self.build_shader(bind_group_layout_cache, shader_module, reflected_shader))
+        Ok(self.build_shader(
+            bind_group_layout_cache,
+            source,
+            shader_module,
+            reflected_shader,
+        ))
     }
 
-    fn compile_shader(
+    /// Same as [`create_shader`](Self::create_shader), naming the shader in its errors and its shader module,
+    /// e.g. by the file it has been read from.
+    pub fn create_named_shader(
         &self,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+        name: &str,
         source: impl AsRef<str>,
-    ) -> Result<(ReflectedShader, ShaderModule), ShaderInspectionError> {
+    ) -> Result<ShaderHandle, ShaderCompileError> {
         let source = source.as_ref();
-        let reflected_shader = inspect_shader(self, source)?;
-        let shader_module = self
-            .gfx_ctx
-            .device
-            .create_shader_module(ShaderModuleDescriptor {
-                label: None,
-                source: ShaderSource::Wgsl(Cow::Borrowed(source)),
-            });
+        let (reflected_shader, shader_module) = self.compile_shader(Some(name), source)?;
+
+        Ok(self.build_shader(
+            bind_group_layout_cache,
+            source,
+            shader_module,
+            reflected_shader,
+        ))
+    }
+
+    /// Compiles `source` in place of the shader, e.g. after its file has been edited. Materials of the shader move
+    /// to the new one, carrying their properties over, as their renderers next obtain a pipeline; see
+    /// [`refresh_material`](Self::refresh_material). Returns the new shader, or an error if the source does not
+    /// compile, in which case the old shader stays in use.
+    pub fn reload_shader(
+        &self,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+        pipeline_layout_cache: &mut PipelineLayoutCache,
+        shader: &ShaderHandle,
+        source: impl AsRef<str>,
+    ) -> Result<ShaderHandle, ShaderCompileError> {
+        let source = source.as_ref();
+        let (reflected_shader, shader_module) = self.compile_shader(None, source)?;
+
+        let reloaded = self.build_shader(
+            bind_group_layout_cache,
+            source,
+            shader_module,
+            reflected_shader,
+        );
+        let reload = ShaderReload {
+            pipeline_layout: Material::pipeline_layout_of(&reloaded, pipeline_layout_cache),
+            shader: reloaded.clone(),
+        };
+
+        // Materials still on an earlier version skip the intermediate ones.
+        let mut reloads = self.reloads.lock();
+        for earlier in reloads.values_mut() {
+            if &earlier.shader == shader {
+                *earlier = reload.clone();
+            }
+        }
+        reloads.insert(shader.clone(), reload);
+
+        Ok(reloaded)
+    }
+
+    /// The newest version of the shader, if it has been reloaded.
+    pub fn reloaded_shader(&self, shader: &ShaderHandle) -> Option<ShaderHandle> {
+        let reloads = self.reloads.lock();
+
+        if reloads.is_empty() {
+            return None;
+        }
+
+        reloads.get(shader).map(|reload| reload.shader.clone())
+    }
+
+    /// Moves the material to the newest version of its shader, rebuilding its bind groups.
+    /// Returns `false` if its shader has not been reloaded.
+    pub fn refresh_material(&self, material: &mut Material) -> bool {
+        let reload = match self.reloads.lock().get(&material.shader) {
+            Some(reload) => reload.clone(),
+            None => return false,
+        };
+
+        *material = material.with_shader_and_layout(reload.shader, reload.pipeline_layout);
+        material.update_bind_group(&self.gfx_ctx.device);
+        true
+    }
+
+    /// Uploads the uniform of the material and rebuilds the bind groups whose bindings have been set since, e.g. by
+    /// [`Material::set_texture`]. Renderers call this before drawing with the material.
+    pub fn prepare_material(&self, material: &mut Material) -> Result<(), MaterialPropertyError> {
+        material.prepare(&self.gfx_ctx.device, &self.gfx_ctx.queue)
+    }
+
+    fn compile_shader(
+        &self,
+        name: Option<&str>,
+        source: &str,
+    ) -> Result<(ReflectedShader, ShaderModule), ShaderCompileError> {
+        let with_name = |err: ShaderCompileError| match name {
+            Some(name) => err.with_shader_name(name),
+            None => err,
+        };
+        let key = self.shader_cache_key(source);
+        let cached = self.cache.lock().get(key);
+        let reflected_shader = match cached {
+            Some(reflected_shader) => reflected_shader,
+            None => {
+                let reflected_shader = inspect_shader(self, source).map_err(with_name)?;
+                self.cache.lock().insert(key, &reflected_shader);
+                reflected_shader
+            }
+        };
+
+        // Reflection only parses the source; the device validates it.
+        let device = &self.gfx_ctx.device;
+        device.push_error_scope(ErrorFilter::Validation);
+        let shader_module = device.create_shader_module(ShaderModuleDescriptor {
+            label: name,
+            source: ShaderSource::Wgsl(Cow::Borrowed(source)),
+        });
+
+        if let Some(err) = pop_error_scope(device) {
+            return Err(with_name(ShaderCompileError::new(
+                ShaderCompileErrorKind::Validation,
+                err.to_string(),
+            )));
+        }
 
         Ok((reflected_shader, shader_module))
     }
@@ -381,6 +693,7 @@ impl ShaderManager {
     fn build_shader(
         &self,
         bind_group_layout_cache: &mut BindGroupLayoutCache,
+        source: &str,
         shader_module: ShaderModule,
         reflected_shader: ReflectedShader,
     ) -> ShaderHandle {
@@ -399,8 +712,12 @@ impl ShaderManager {
             }
         }
 
-        let max_group = bind_group_layout_entries.keys().max().copied().unwrap_or(0);
-        let bind_group_layouts = (0..=max_group)
+        // A shader without bindings has no groups; an empty one would still need a bind group.
+        let group_count = bind_group_layout_entries
+            .keys()
+            .max()
+            .map_or(0, |max_group| max_group + 1);
+        let bind_group_layouts = (0..group_count)
             .map(|group| {
                 let entries = bind_group_layout_entries
                     .remove(&group)
@@ -432,6 +749,7 @@ impl ShaderManager {
         }
 
         ShaderHandle::new(Shader {
+            source: source.to_owned(),
             shader_module,
             reflected_shader,
             bind_group_layouts,
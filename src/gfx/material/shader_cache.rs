@@ -0,0 +1,716 @@
+use super::{
+    ReflectedShader, ReflectedShaderBindingElement, ReflectedShaderBindingElementKind,
+    ReflectedShaderInput, ReflectedShaderInputElement, ReflectedShaderOutputElement,
+    ReflectedUniformMember, SemanticShaderBindingKey, SemanticShaderInputKey,
+    SemanticShaderOutputKey, UniformType,
+};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Write,
+    num::{NonZeroU32, NonZeroU64},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use wgpu::{
+    SamplerBindingType, TextureSampleType, TextureViewDimension, VertexAttribute, VertexFormat,
+    VertexStepMode,
+};
+
+/// Bump this whenever the reflection output or its encoding changes; it is part of every cache key,
+/// so stale bundles are simply never looked up again.
+pub const SHADER_CACHE_FORMAT_VERSION: u32 = 2;
+
+const BUNDLE_MAGIC: &[u8; 4] = b"R3DS";
+const BUNDLE_EXTENSION: &str = "shader";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderCacheConfig {
+    /// Directory the bundles are stored in. Created on demand.
+    pub directory: PathBuf,
+    /// Least recently used bundles are evicted once the directory grows past this size.
+    pub max_bytes: u64,
+}
+
+impl ShaderCacheConfig {
+    pub const DEFAULT_MAX_BYTES: u64 = 32 * 1024 * 1024;
+
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            max_bytes: Self::DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShaderCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Bytes of bundles read from or written to the disk cache.
+    pub bytes: u64,
+}
+
+/// Caches shader reflection results keyed by a hash of the shader source, in memory and optionally on disk.
+/// Any failure to read or write the disk cache is treated as a miss.
+#[derive(Debug, Default)]
+pub struct ShaderCache {
+    config: Option<ShaderCacheConfig>,
+    memory: HashMap<u64, ReflectedShader>,
+    stats: ShaderCacheStats,
+}
+
+impl ShaderCache {
+    pub fn new(config: Option<ShaderCacheConfig>) -> Self {
+        Self {
+            config,
+            memory: HashMap::new(),
+            stats: ShaderCacheStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> ShaderCacheStats {
+        self.stats
+    }
+
+    pub fn get(&mut self, key: u64) -> Option<ReflectedShader> {
+        if let Some(reflected_shader) = self.memory.get(&key) {
+            self.stats.hits += 1;
+            return Some(reflected_shader.clone());
+        }
+
+        let reflected_shader = self.config.as_ref().and_then(|config| {
+            let path = bundle_path(&config.directory, key);
+            let bytes = fs::read(&path).ok()?;
+
+            match decode_bundle(key, &bytes) {
+                Some(reflected_shader) => {
+                    self.stats.bytes += bytes.len() as u64;
+                    // Refresh the access time for the eviction order.
+                    if let Ok(file) = File::options().write(true).open(&path) {
+                        let _ = file.set_modified(SystemTime::now());
+                    }
+                    Some(reflected_shader)
+                }
+                None => {
+                    let _ = fs::remove_file(&path);
+                    None
+                }
+            }
+        });
+
+        match reflected_shader {
+            Some(reflected_shader) => {
+                self.stats.hits += 1;
+                self.memory.insert(key, reflected_shader.clone());
+                Some(reflected_shader)
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: u64, reflected_shader: &ReflectedShader) {
+        self.memory.insert(key, reflected_shader.clone());
+
+        let config = if let Some(config) = &self.config {
+            config
+        } else {
+            return;
+        };
+        let bytes = encode_bundle(key, reflected_shader);
+
+        if write_atomically(
+            &config.directory,
+            &bundle_path(&config.directory, key),
+            &bytes,
+        )
+        .is_ok()
+        {
+            self.stats.bytes += bytes.len() as u64;
+            let _ = evict_least_recently_used(&config.directory, config.max_bytes);
+        }
+    }
+}
+
+/// Stable 64-bit FNV-1a hash; `std`'s hashers are not guaranteed to be stable across builds.
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderCacheKeyHasher(u64);
+
+impl ShaderCacheKeyHasher {
+    pub fn new() -> Self {
+        let mut hasher = Self(0xcbf2_9ce4_8422_2325);
+        hasher.write(&SHADER_CACHE_FORMAT_VERSION.to_le_bytes());
+        hasher
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub fn write_str(&mut self, str: &str) {
+        self.write(&(str.len() as u64).to_le_bytes());
+        self.write(str.as_bytes());
+    }
+
+    pub fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for ShaderCacheKeyHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn bundle_path(directory: &Path, key: u64) -> PathBuf {
+    directory.join(format!("{:016x}.{}", key, BUNDLE_EXTENSION))
+}
+
+/// Writes into a temporary file first, so that readers never observe a partially written bundle.
+fn write_atomically(directory: &Path, path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    fs::create_dir_all(directory)?;
+
+    let temp_path = path.with_extension(format!("{}.{}.tmp", BUNDLE_EXTENSION, std::process::id()));
+    let result = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+/// Removes the least recently used bundles until the directory fits in `max_bytes`.
+fn evict_least_recently_used(directory: &Path, max_bytes: u64) -> std::io::Result<()> {
+    let mut bundles = Vec::new();
+    let mut total_bytes = 0;
+
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if path.extension().and_then(|extension| extension.to_str()) != Some(BUNDLE_EXTENSION) {
+            continue;
+        }
+
+        let metadata = entry.metadata()?;
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        total_bytes += metadata.len();
+        bundles.push((modified, metadata.len(), path));
+    }
+
+    bundles.sort_unstable_by_key(|&(modified, _, _)| modified);
+
+    for (_, len, path) in bundles {
+        if total_bytes <= max_bytes {
+            break;
+        }
+
+        if fs::remove_file(&path).is_ok() {
+            total_bytes -= len;
+        }
+    }
+
+    Ok(())
+}
+
+fn encode_bundle(key: u64, reflected_shader: &ReflectedShader) -> Vec<u8> {
+    let mut writer = BundleWriter::default();
+    writer.bytes.extend_from_slice(BUNDLE_MAGIC);
+    writer.u32(SHADER_CACHE_FORMAT_VERSION);
+    writer.u64(key);
+
+    writer.str(&reflected_shader.vertex_entry_point_name);
+    writer.str(&reflected_shader.fragment_entry_point_name);
+
+    writer.u32(reflected_shader.bindings.len() as u32);
+    for binding in &reflected_shader.bindings {
+        writer.u32(binding.semantic_binding.map_or(0, |key| key.get().get()));
+        writer.str(&binding.name);
+        writer.u32(binding.group);
+        writer.u32(binding.binding);
+
+        match &binding.kind {
+            ReflectedShaderBindingElementKind::Buffer { size } => {
+                writer.u8(0);
+                writer.u64(size.get());
+            }
+            ReflectedShaderBindingElementKind::Texture {
+                sample_type,
+                view_dimension,
+                multisampled,
+                array_size,
+            } => {
+                writer.u8(1);
+                writer.u8(match sample_type {
+                    TextureSampleType::Float { filterable: true } => 0,
+                    TextureSampleType::Float { filterable: false } => 1,
+                    TextureSampleType::Depth => 2,
+                    TextureSampleType::Sint => 3,
+                    TextureSampleType::Uint => 4,
+                });
+                writer.u8(match view_dimension {
+                    TextureViewDimension::D1 => 0,
+                    TextureViewDimension::D2 => 1,
+                    TextureViewDimension::D2Array => 2,
+                    TextureViewDimension::Cube => 3,
+                    TextureViewDimension::CubeArray => 4,
+                    TextureViewDimension::D3 => 5,
+                });
+                writer.u8(*multisampled as u8);
+                writer.u32(array_size.map_or(0, |size| size.get()));
+            }
+            ReflectedShaderBindingElementKind::Sampler { binding_type } => {
+                writer.u8(2);
+                writer.u8(match binding_type {
+                    SamplerBindingType::Filtering => 0,
+                    SamplerBindingType::NonFiltering => 1,
+                    SamplerBindingType::Comparison => 2,
+                });
+            }
+        }
+
+        writer.u32(binding.members.len() as u32);
+        for member in &binding.members {
+            writer.str(&member.name);
+            writer.u64(member.offset);
+            writer.u8(UNIFORM_TYPES
+                .iter()
+                .position(|ty| *ty == member.ty)
+                .map_or(u8::MAX, |index| index as u8));
+        }
+    }
+
+    for input in [
+        &reflected_shader.per_instance_input,
+        &reflected_shader.per_vertex_input,
+    ] {
+        writer.u8(match input.step_mode {
+            VertexStepMode::Vertex => 0,
+            VertexStepMode::Instance => 1,
+        });
+        writer.u64(input.stride);
+        writer.u32(input.elements.len() as u32);
+
+        for element in &input.elements {
+            writer.u32(element.semantic_input.map_or(0, |key| key.get().get()));
+            writer.str(&element.name);
+            writer.u8(VERTEX_FORMATS
+                .iter()
+                .position(|format| *format == element.attribute.format)
+                .map_or(u8::MAX, |index| index as u8));
+            writer.u64(element.attribute.offset);
+            writer.u32(element.attribute.shader_location);
+        }
+    }
+
+    writer.u32(reflected_shader.outputs.len() as u32);
+    for output in &reflected_shader.outputs {
+        writer.u32(output.semantic_output.map_or(0, |key| key.get().get()));
+        writer.str(&output.name);
+        writer.u32(output.location);
+    }
+
+    writer.bytes
+}
+
+/// Returns `None` for anything that does not look like a complete bundle of the given key.
+fn decode_bundle(key: u64, bytes: &[u8]) -> Option<ReflectedShader> {
+    let mut reader = BundleReader { bytes, position: 0 };
+
+    if reader.take(4)? != BUNDLE_MAGIC
+        || reader.u32()? != SHADER_CACHE_FORMAT_VERSION
+        || reader.u64()? != key
+    {
+        return None;
+    }
+
+    let vertex_entry_point_name = reader.str()?;
+    let fragment_entry_point_name = reader.str()?;
+
+    let binding_count = reader.u32()?;
+    let mut bindings = Vec::new();
+    for _ in 0..binding_count {
+        let semantic_binding =
+            NonZeroU32::new(reader.u32()?).map(|key| SemanticShaderBindingKey::new(key.get()));
+        let name = reader.str()?;
+        let group = reader.u32()?;
+        let binding = reader.u32()?;
+        let kind = match reader.u8()? {
+            0 => ReflectedShaderBindingElementKind::Buffer {
+                size: NonZeroU64::new(reader.u64()?)?,
+            },
+            1 => ReflectedShaderBindingElementKind::Texture {
+                sample_type: match reader.u8()? {
+                    0 => TextureSampleType::Float { filterable: true },
+                    1 => TextureSampleType::Float { filterable: false },
+                    2 => TextureSampleType::Depth,
+                    3 => TextureSampleType::Sint,
+                    4 => TextureSampleType::Uint,
+                    _ => return None,
+                },
+                view_dimension: match reader.u8()? {
+                    0 => TextureViewDimension::D1,
+                    1 => TextureViewDimension::D2,
+                    2 => TextureViewDimension::D2Array,
+                    3 => TextureViewDimension::Cube,
+                    4 => TextureViewDimension::CubeArray,
+                    5 => TextureViewDimension::D3,
+                    _ => return None,
+                },
+                multisampled: reader.u8()? != 0,
+                array_size: NonZeroU32::new(reader.u32()?),
+            },
+            2 => ReflectedShaderBindingElementKind::Sampler {
+                binding_type: match reader.u8()? {
+                    0 => SamplerBindingType::Filtering,
+                    1 => SamplerBindingType::NonFiltering,
+                    2 => SamplerBindingType::Comparison,
+                    _ => return None,
+                },
+            },
+            _ => return None,
+        };
+
+        let member_count = reader.u32()?;
+        let mut members = Vec::new();
+        for _ in 0..member_count {
+            members.push(ReflectedUniformMember {
+                name: reader.str()?,
+                offset: reader.u64()?,
+                ty: *UNIFORM_TYPES.get(reader.u8()? as usize)?,
+            });
+        }
+
+        bindings.push(ReflectedShaderBindingElement {
+            semantic_binding,
+            name,
+            group,
+            binding,
+            kind,
+            members,
+        });
+    }
+
+    let mut inputs = Vec::with_capacity(2);
+    for _ in 0..2 {
+        let step_mode = match reader.u8()? {
+            0 => VertexStepMode::Vertex,
+            1 => VertexStepMode::Instance,
+            _ => return None,
+        };
+        let stride = reader.u64()?;
+        let element_count = reader.u32()?;
+        let mut elements = Vec::new();
+
+        for _ in 0..element_count {
+            let semantic_input =
+                NonZeroU32::new(reader.u32()?).map(|key| SemanticShaderInputKey::new(key.get()));
+            let name = reader.str()?;
+            let format = *VERTEX_FORMATS.get(reader.u8()? as usize)?;
+            let offset = reader.u64()?;
+            let shader_location = reader.u32()?;
+
+            elements.push(ReflectedShaderInputElement {
+                semantic_input,
+                name,
+                attribute: VertexAttribute {
+                    format,
+                    offset,
+                    shader_location,
+                },
+            });
+        }
+
+        inputs.push(ReflectedShaderInput {
+            step_mode,
+            stride,
+            elements,
+        });
+    }
+
+    let output_count = reader.u32()?;
+    let mut outputs = Vec::new();
+    for _ in 0..output_count {
+        let semantic_output =
+            NonZeroU32::new(reader.u32()?).map(|key| SemanticShaderOutputKey::new(key.get()));
+        let name = reader.str()?;
+        let location = reader.u32()?;
+
+        outputs.push(ReflectedShaderOutputElement {
+            semantic_output,
+            name,
+            location,
+        });
+    }
+
+    if reader.position != bytes.len() {
+        return None;
+    }
+
+    let per_vertex_input = inputs.pop()?;
+    let per_instance_input = inputs.pop()?;
+
+    Some(ReflectedShader {
+        vertex_entry_point_name,
+        fragment_entry_point_name,
+        bindings,
+        per_instance_input,
+        per_vertex_input,
+        outputs,
+    })
+}
+
+const UNIFORM_TYPES: [UniformType; 8] = [
+    UniformType::Float32,
+    UniformType::Float32x2,
+    UniformType::Float32x3,
+    UniformType::Float32x4,
+    UniformType::Sint32,
+    UniformType::Uint32,
+    UniformType::Mat3x3,
+    UniformType::Mat4x4,
+];
+
+const VERTEX_FORMATS: [VertexFormat; 34] = [
+    VertexFormat::Uint8x2,
+    VertexFormat::Uint8x4,
+    VertexFormat::Sint8x2,
+    VertexFormat::Sint8x4,
+    VertexFormat::Unorm8x2,
+    VertexFormat::Unorm8x4,
+    VertexFormat::Snorm8x2,
+    VertexFormat::Snorm8x4,
+    VertexFormat::Uint16x2,
+    VertexFormat::Uint16x4,
+    VertexFormat::Sint16x2,
+    VertexFormat::Sint16x4,
+    VertexFormat::Unorm16x2,
+    VertexFormat::Unorm16x4,
+    VertexFormat::Snorm16x2,
+    VertexFormat::Snorm16x4,
+    VertexFormat::Float16x2,
+    VertexFormat::Float16x4,
+    VertexFormat::Float32,
+    VertexFormat::Float32x2,
+    VertexFormat::Float32x3,
+    VertexFormat::Float32x4,
+    VertexFormat::Uint32,
+    VertexFormat::Uint32x2,
+    VertexFormat::Uint32x3,
+    VertexFormat::Uint32x4,
+    VertexFormat::Sint32,
+    VertexFormat::Sint32x2,
+    VertexFormat::Sint32x3,
+    VertexFormat::Sint32x4,
+    VertexFormat::Float64,
+    VertexFormat::Float64x2,
+    VertexFormat::Float64x3,
+    VertexFormat::Float64x4,
+];
+
+#[derive(Default)]
+struct BundleWriter {
+    bytes: Vec<u8>,
+}
+
+impl BundleWriter {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+}
+
+struct BundleReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BundleReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reflected_shader() -> ReflectedShader {
+        ReflectedShader {
+            vertex_entry_point_name: "vs_main".to_owned(),
+            fragment_entry_point_name: "fs_main".to_owned(),
+            bindings: vec![
+                ReflectedShaderBindingElement {
+                    semantic_binding: Some(SemanticShaderBindingKey::new(1)),
+                    name: "camera_transform".to_owned(),
+                    group: 0,
+                    binding: 0,
+                    kind: ReflectedShaderBindingElementKind::Buffer {
+                        size: NonZeroU64::new(64).unwrap(),
+                    },
+                    members: Vec::new(),
+                },
+                ReflectedShaderBindingElement {
+                    semantic_binding: None,
+                    name: "material".to_owned(),
+                    group: 2,
+                    binding: 0,
+                    kind: ReflectedShaderBindingElementKind::Buffer {
+                        size: NonZeroU64::new(32).unwrap(),
+                    },
+                    members: vec![
+                        ReflectedUniformMember {
+                            name: "tint".to_owned(),
+                            offset: 0,
+                            ty: UniformType::Float32x3,
+                        },
+                        ReflectedUniformMember {
+                            name: "roughness".to_owned(),
+                            offset: 12,
+                            ty: UniformType::Float32,
+                        },
+                    ],
+                },
+                ReflectedShaderBindingElement {
+                    semantic_binding: None,
+                    name: "environment_texture".to_owned(),
+                    group: 1,
+                    binding: 0,
+                    kind: ReflectedShaderBindingElementKind::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::Cube,
+                        multisampled: false,
+                        array_size: None,
+                    },
+                    members: Vec::new(),
+                },
+                ReflectedShaderBindingElement {
+                    semantic_binding: None,
+                    name: "environment_sampler".to_owned(),
+                    group: 1,
+                    binding: 1,
+                    kind: ReflectedShaderBindingElementKind::Sampler {
+                        binding_type: SamplerBindingType::Filtering,
+                    },
+                    members: Vec::new(),
+                },
+            ],
+            per_instance_input: ReflectedShaderInput {
+                step_mode: VertexStepMode::Instance,
+                stride: 16,
+                elements: vec![ReflectedShaderInputElement {
+                    semantic_input: Some(SemanticShaderInputKey::new(101)),
+                    name: "transform_row_0".to_owned(),
+                    attribute: VertexAttribute {
+                        format: VertexFormat::Float32x4,
+                        offset: 0,
+                        shader_location: 0,
+                    },
+                }],
+            },
+            per_vertex_input: ReflectedShaderInput::empty(VertexStepMode::Vertex),
+            outputs: vec![ReflectedShaderOutputElement {
+                semantic_output: Some(SemanticShaderOutputKey::new(1)),
+                name: "color".to_owned(),
+                location: 0,
+            }],
+        }
+    }
+
+    #[test]
+    fn check_bundle_round_trip() {
+        let bytes = encode_bundle(42, &reflected_shader());
+        let decoded = decode_bundle(42, &bytes).unwrap();
+
+        // The types don't implement `PartialEq`; encoding again must give the same bytes.
+        assert_eq!(encode_bundle(42, &decoded), bytes);
+        assert!(decode_bundle(43, &bytes).is_none());
+    }
+
+    #[test]
+    fn check_corrupted_bundle_is_rejected() {
+        let bytes = encode_bundle(42, &reflected_shader());
+
+        for len in 0..bytes.len() {
+            assert!(decode_bundle(42, &bytes[..len]).is_none());
+        }
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(decode_bundle(42, &trailing).is_none());
+    }
+
+    #[test]
+    fn check_disk_cache() {
+        let directory =
+            std::env::temp_dir().join(format!("r3d-shader-cache-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        let mut cache = ShaderCache::new(Some(ShaderCacheConfig::new(&directory)));
+        assert!(cache.get(1).is_none());
+        cache.insert(1, &reflected_shader());
+
+        // A fresh cache, as on the next start, finds the bundle on disk.
+        let mut cache = ShaderCache::new(Some(ShaderCacheConfig::new(&directory)));
+        assert!(cache.get(1).is_some());
+        assert_eq!(cache.stats().hits, 1);
+
+        // A corrupted bundle is a silent miss, and gets removed.
+        fs::write(bundle_path(&directory, 2), b"R3DS garbage").unwrap();
+        assert!(cache.get(2).is_none());
+        assert!(!bundle_path(&directory, 2).exists());
+        assert_eq!(cache.stats().misses, 1);
+
+        // Shrinking the limit evicts bundles.
+        evict_least_recently_used(&directory, 0).unwrap();
+        assert!(!bundle_path(&directory, 1).exists());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,195 @@
+use naga::ShaderStage;
+use std::{
+    fmt::{Display, Formatter},
+    ops::Range,
+};
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderCompileErrorKind {
+    /// The source is not valid WGSL.
+    Parse,
+    /// The source parses, but the device rejects it, e.g. for a type error.
+    Validation,
+    /// The shader lacks a vertex or a fragment entry point.
+    MissingEntryPoint,
+    /// A per-vertex input matches no semantic input, so no renderer would provide its vertex buffer.
+    MissingSemanticInput,
+    /// Two bindings share the same group and binding index.
+    BindingConflict,
+}
+
+impl Display for ShaderCompileErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Parse => "parse error",
+            Self::Validation => "validation error",
+            Self::MissingEntryPoint => "missing entry point",
+            Self::MissingSemanticInput => "missing semantic input",
+            Self::BindingConflict => "binding conflict",
+        })
+    }
+}
+
+/// The part of the source an error points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderSourceSpan {
+    /// 1-based line of the start of the span.
+    pub line: u32,
+    /// 1-based column of the start of the span, in characters.
+    pub column: u32,
+    /// The line of the span with a gutter, and carets under the span on the next line.
+    pub excerpt: String,
+}
+
+impl ShaderSourceSpan {
+    /// The span of the byte range of the source, or `None` if the range is out of it.
+    pub fn from_range(source: &str, range: Range<usize>) -> Option<Self> {
+        if source.len() < range.start || !source.is_char_boundary(range.start) {
+            return None;
+        }
+
+        let line_start = source[..range.start]
+            .rfind('\n')
+            .map_or(0, |index| index + 1);
+        let line_end = source[range.start..]
+            .find('\n')
+            .map_or(source.len(), |index| range.start + index);
+        let line_text = source[line_start..line_end].trim_end_matches('\r');
+        let line = source[..line_start].matches('\n').count() as u32 + 1;
+        let column = source[line_start..range.start].chars().count() as u32 + 1;
+
+        // A span running over several lines is underlined up to the end of its first line.
+        let span_end = range.end.clamp(range.start, line_start + line_text.len());
+        let span_len = source
+            .get(range.start..span_end)
+            .map_or(0, |text| text.chars().count())
+            .max(1);
+
+        let line_number = line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let excerpt = format!(
+            "{gutter} |\n{line_number} | {line_text}\n{gutter} | {}{}",
+            " ".repeat(column as usize - 1),
+            "^".repeat(span_len)
+        );
+
+        Some(Self {
+            line,
+            column,
+            excerpt,
+        })
+    }
+}
+
+/// Why a shader could not be created, with the part of the source at fault if known.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub struct ShaderCompileError {
+    /// The name the shader has been created with, e.g. its file.
+    pub shader_name: Option<String>,
+    /// The stage the error is in, if it concerns a single one.
+    pub stage: Option<ShaderStage>,
+    pub kind: ShaderCompileErrorKind,
+    pub message: String,
+    pub span: Option<ShaderSourceSpan>,
+}
+
+impl ShaderCompileError {
+    pub fn new(kind: ShaderCompileErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            shader_name: None,
+            stage: None,
+            kind,
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    pub fn with_shader_name(mut self, shader_name: impl Into<String>) -> Self {
+        self.shader_name = Some(shader_name.into());
+        self
+    }
+
+    pub fn with_stage(mut self, stage: ShaderStage) -> Self {
+        self.stage = Some(stage);
+        self
+    }
+
+    /// Points the error at the byte range of the source. Ranges out of the source are ignored.
+    pub fn with_span(mut self, source: &str, range: Option<Range<usize>>) -> Self {
+        self.span = range.and_then(|range| ShaderSourceSpan::from_range(source, range));
+        self
+    }
+}
+
+impl Display for ShaderCompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(shader_name) = &self.shader_name {
+            write!(f, "{}: ", shader_name)?;
+        }
+
+        write!(f, "{}", self.kind)?;
+
+        if let Some(stage) = self.stage {
+            let stage = match stage {
+                ShaderStage::Vertex => "vertex",
+                ShaderStage::Fragment => "fragment",
+                ShaderStage::Compute => "compute",
+            };
+            write!(f, " in the {} stage", stage)?;
+        }
+
+        if let Some(span) = &self.span {
+            write!(f, " at {}:{}", span.line, span.column)?;
+        }
+
+        write!(f, ": {}", self.message)?;
+
+        if let Some(span) = &self.span {
+            write!(f, "\n{}", span.excerpt)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_excerpt_underlines_the_span() {
+        let source = "fn main() {\n    let x = foo;\n}\n";
+        let start = source.find("foo").unwrap();
+        let span = ShaderSourceSpan::from_range(source, start..start + 3).unwrap();
+
+        assert_eq!(span.line, 2);
+        assert_eq!(span.column, 13);
+        assert_eq!(
+            span.excerpt,
+            "  |\n2 |     let x = foo;\n  |             ^^^"
+        );
+
+        let multiline = ShaderSourceSpan::from_range(source, 0..source.len()).unwrap();
+        assert_eq!(multiline.excerpt, "  |\n1 | fn main() {\n  | ^^^^^^^^^^^");
+        assert!(ShaderSourceSpan::from_range(source, 100..101).is_none());
+    }
+
+    #[test]
+    fn check_message_names_the_shader_and_stage() {
+        let source = "@vertex\nfn vs_main() {}\n";
+        let error = ShaderCompileError::new(
+            ShaderCompileErrorKind::MissingSemanticInput,
+            "input `tint` matches no semantic input",
+        )
+        .with_shader_name("tinted.wgsl")
+        .with_stage(ShaderStage::Vertex)
+        .with_span(source, Some(11..18));
+
+        assert_eq!(
+            error.to_string(),
+            "tinted.wgsl: missing semantic input in the vertex stage at 2:4: \
+             input `tint` matches no semantic input\n  |\n2 | fn vs_main() {}\n  |    ^^^^^^^"
+        );
+    }
+}
@@ -1,30 +1,18 @@
 use super::{
     shader::{SemanticShaderInputKey, ShaderManager},
-    SemanticShaderBindingKey, SemanticShaderOutputKey,
+    SemanticShaderBindingKey, SemanticShaderOutputKey, ShaderCompileError, ShaderCompileErrorKind,
 };
 use naga::{
-    front::wgsl::{parse_str, ParseError},
-    AddressSpace, ArraySize, Binding, Function, ImageClass, ImageDimension, Module, ScalarKind,
-    ShaderStage, StructMember, Type, TypeInner, VectorSize,
+    front::wgsl::parse_str, AddressSpace, ArraySize, Binding, Function, ImageClass, ImageDimension,
+    Module, ScalarKind, ShaderStage, StructMember, Type, TypeInner, VectorSize,
 };
 use std::num::{NonZeroU32, NonZeroU64};
-use thiserror::Error;
 use wgpu::{
     BindGroupLayoutEntry, BindingType, BufferAddress, BufferBindingType, SamplerBindingType,
     ShaderStages, TextureSampleType, TextureViewDimension, VertexAttribute, VertexBufferLayout,
     VertexFormat, VertexStepMode,
 };
 
-#[derive(Error, Debug)]
-pub enum ShaderInspectionError {
-    #[error("failed to parse shader source: {0}")]
-    ParseError(#[from] ParseError),
-    #[error("no vertex entry point found")]
-    NoVertexEntryPoint,
-    #[error("no fragment entry point found")]
-    NoFragmentEntryPoint,
-}
-
 #[derive(Debug, Clone)]
 pub struct ReflectedShader {
     pub vertex_entry_point_name: String,
@@ -42,6 +30,56 @@ pub struct ReflectedShaderBindingElement {
     pub group: u32,
     pub binding: u32,
     pub kind: ReflectedShaderBindingElementKind,
+    /// Members of a uniform struct of the types in [`UniformType`], empty for other bindings.
+    pub members: Vec<ReflectedUniformMember>,
+}
+
+/// Types of the uniform struct members materials can set. Others are not reflected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UniformType {
+    Float32,
+    Float32x2,
+    Float32x3,
+    Float32x4,
+    Sint32,
+    Uint32,
+    Mat3x3,
+    Mat4x4,
+}
+
+impl UniformType {
+    /// Size in the buffer; columns of a `mat3x3<f32>` are padded to 16 bytes each.
+    pub fn size(self) -> u64 {
+        match self {
+            Self::Float32 | Self::Sint32 | Self::Uint32 => 4,
+            Self::Float32x2 => 8,
+            Self::Float32x3 => 12,
+            Self::Float32x4 => 16,
+            Self::Mat3x3 => 48,
+            Self::Mat4x4 => 64,
+        }
+    }
+
+    /// The type of a per-instance input of the format, if a uniform can be of it.
+    pub fn from_vertex_format(format: VertexFormat) -> Option<Self> {
+        match format {
+            VertexFormat::Float32 => Some(Self::Float32),
+            VertexFormat::Float32x2 => Some(Self::Float32x2),
+            VertexFormat::Float32x3 => Some(Self::Float32x3),
+            VertexFormat::Float32x4 => Some(Self::Float32x4),
+            VertexFormat::Sint32 => Some(Self::Sint32),
+            VertexFormat::Uint32 => Some(Self::Uint32),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflectedUniformMember {
+    pub name: String,
+    /// Offset in the struct, as laid out by the WGSL alignment rules.
+    pub offset: u64,
+    pub ty: UniformType,
 }
 
 impl From<&ReflectedShaderBindingElement> for BindGroupLayoutEntry {
@@ -154,9 +192,15 @@ pub struct ReflectedShaderOutputElement {
 pub fn inspect_shader(
     shader_mgr: &ShaderManager,
     source: impl AsRef<str>,
-) -> Result<ReflectedShader, ShaderInspectionError> {
-    let module = parse_str(source.as_ref())?;
-    let bindings = reflect_globals(shader_mgr, &module);
+) -> Result<ReflectedShader, ShaderCompileError> {
+    let source = source.as_ref();
+    let module = parse_str(source).map_err(|err| {
+        ShaderCompileError::new(ShaderCompileErrorKind::Parse, err.message()).with_span(
+            source,
+            err.labels().next().and_then(|(span, _)| span.to_range()),
+        )
+    })?;
+    let bindings = reflect_globals(shader_mgr, &module, source)?;
 
     let mut vertex_entry_point_name = None;
     let mut fragment_entry_point_name = None;
@@ -170,7 +214,7 @@ pub fn inspect_shader(
                 vertex_entry_point_name = Some(entry_point.name.clone());
 
                 for vertex_input in
-                    reflect_vertex_entry_point(shader_mgr, &module, &entry_point.function)
+                    reflect_vertex_entry_point(shader_mgr, &module, &entry_point.function, source)?
                 {
                     match vertex_input.step_mode {
                         VertexStepMode::Vertex => {
@@ -195,11 +239,24 @@ pub fn inspect_shader(
         }
     }
 
+    let vertex_entry_point_name = vertex_entry_point_name.ok_or_else(|| {
+        ShaderCompileError::new(
+            ShaderCompileErrorKind::MissingEntryPoint,
+            "no `@vertex` entry point found",
+        )
+        .with_stage(ShaderStage::Vertex)
+    })?;
+    let fragment_entry_point_name = fragment_entry_point_name.ok_or_else(|| {
+        ShaderCompileError::new(
+            ShaderCompileErrorKind::MissingEntryPoint,
+            "no `@fragment` entry point found",
+        )
+        .with_stage(ShaderStage::Fragment)
+    })?;
+
     Ok(ReflectedShader {
-        vertex_entry_point_name: vertex_entry_point_name
-            .ok_or(ShaderInspectionError::NoVertexEntryPoint)?,
-        fragment_entry_point_name: fragment_entry_point_name
-            .ok_or(ShaderInspectionError::NoFragmentEntryPoint)?,
+        vertex_entry_point_name,
+        fragment_entry_point_name,
         bindings,
         per_instance_input: per_instance_input
             .unwrap_or_else(|| ReflectedShaderInput::empty(VertexStepMode::Instance)),
@@ -212,10 +269,11 @@ pub fn inspect_shader(
 fn reflect_globals(
     shader_mgr: &ShaderManager,
     module: &Module,
-) -> Vec<ReflectedShaderBindingElement> {
-    let mut bindings = Vec::new();
+    source: &str,
+) -> Result<Vec<ReflectedShaderBindingElement>, ShaderCompileError> {
+    let mut bindings = Vec::<ReflectedShaderBindingElement>::new();
 
-    for (_, global) in module.global_variables.iter() {
+    for (handle, global) in module.global_variables.iter() {
         let name = if let Some(name) = &global.name {
             name
         } else {
@@ -226,6 +284,21 @@ fn reflect_globals(
         } else {
             continue;
         };
+
+        if let Some(other) = bindings
+            .iter()
+            .find(|other| other.group == group && other.binding == binding.binding)
+        {
+            return Err(ShaderCompileError::new(
+                ShaderCompileErrorKind::BindingConflict,
+                format!(
+                    "`{}` uses @group({}) @binding({}), already used by `{}`",
+                    name, group, binding.binding, other.name
+                ),
+            )
+            .with_span(source, module.global_variables.get_span(handle).to_range()));
+        }
+
         let element_kind = match global.space {
             AddressSpace::Uniform | AddressSpace::Handle => {
                 shader_ty_to_binding_element_kind(&module, &module.types[global.ty])
@@ -274,12 +347,18 @@ fn reflect_globals(
             Some(key)
         });
 
+        let members = match global.space {
+            AddressSpace::Uniform => reflect_uniform_members(module, &module.types[global.ty]),
+            _ => Vec::new(),
+        };
+
         bindings.push(ReflectedShaderBindingElement {
             semantic_binding,
             name: name.clone(),
             group,
             binding: binding.binding,
             kind: element_kind,
+            members,
         });
     }
 
@@ -301,14 +380,15 @@ fn reflect_globals(
         }
     }
 
-    bindings
+    Ok(bindings)
 }
 
 fn reflect_vertex_entry_point(
     shader_mgr: &ShaderManager,
     module: &Module,
     function: &Function,
-) -> Vec<ReflectedShaderInput> {
+    source: &str,
+) -> Result<Vec<ReflectedShaderInput>, ShaderCompileError> {
     let mut inputs = vec![];
 
     for argument in &function.arguments {
@@ -329,12 +409,31 @@ fn reflect_vertex_entry_point(
             continue;
         };
 
-        inputs.push(reflect_shader_input(
-            shader_mgr, module, step_mode, span, members,
-        ));
+        let input = reflect_shader_input(shader_mgr, module, step_mode, span, members);
+
+        // Renderers only provide the vertex buffers of semantic inputs.
+        if step_mode == VertexStepMode::Vertex {
+            if let Some(element) = input
+                .elements
+                .iter()
+                .find(|element| element.semantic_input.is_none())
+            {
+                return Err(ShaderCompileError::new(
+                    ShaderCompileErrorKind::MissingSemanticInput,
+                    format!(
+                        "`{}` of `{}` matches no semantic input by name, step mode and format",
+                        element.name, name
+                    ),
+                )
+                .with_stage(ShaderStage::Vertex)
+                .with_span(source, module.types.get_span(argument.ty).to_range()));
+            }
+        }
+
+        inputs.push(input);
     }
 
-    inputs
+    Ok(inputs)
 }
 
 fn reflect_shader_input(
@@ -354,7 +453,7 @@ fn reflect_shader_input(
         };
         let location = if let Some(binding) = member.binding.as_ref() {
             match binding {
-                Binding::BuiltIn(_) => todo!(),
+                Binding::BuiltIn(_) => continue,
                 Binding::Location { location, .. } => *location,
             }
         } else {
@@ -443,7 +542,7 @@ fn reflect_shader_output_elements(
         };
         let location = if let Some(binding) = member.binding.as_ref() {
             match binding {
-                Binding::BuiltIn(_) => todo!(),
+                Binding::BuiltIn(_) => continue,
                 Binding::Location { location, .. } => *location,
             }
         } else {
@@ -527,7 +626,11 @@ fn shader_ty_to_binding_element_kind(
         TypeInner::Struct { span, .. } => Some(ReflectedShaderBindingElementKind::Buffer {
             size: unsafe { NonZeroU64::new_unchecked(*span as u64) },
         }),
-        TypeInner::Image { dim, class, .. } => {
+        TypeInner::Image {
+            dim,
+            arrayed,
+            class,
+        } => {
             let (sample_type, multisampled) = match *class {
                 ImageClass::Sampled { kind, multi } => {
                     let sample_type = match kind {
@@ -548,11 +651,17 @@ fn shader_ty_to_binding_element_kind(
 
             Some(ReflectedShaderBindingElementKind::Texture {
                 sample_type,
-                view_dimension: match *dim {
-                    ImageDimension::D1 => TextureViewDimension::D1,
-                    ImageDimension::D2 => TextureViewDimension::D2,
-                    ImageDimension::D3 => TextureViewDimension::D3,
-                    ImageDimension::Cube => TextureViewDimension::Cube,
+                view_dimension: match (*dim, *arrayed) {
+                    (ImageDimension::D1, false) => TextureViewDimension::D1,
+                    (ImageDimension::D2, false) => TextureViewDimension::D2,
+                    (ImageDimension::D2, true) => TextureViewDimension::D2Array,
+                    (ImageDimension::D3, false) => TextureViewDimension::D3,
+                    (ImageDimension::Cube, false) => TextureViewDimension::Cube,
+                    (ImageDimension::Cube, true) => TextureViewDimension::CubeArray,
+                    // WGSL has no arrays of 1D or 3D textures.
+                    (ImageDimension::D1, true) | (ImageDimension::D3, true) => {
+                        return None;
+                    }
                 },
                 multisampled,
                 array_size: None,
@@ -605,6 +714,45 @@ fn shader_ty_to_binding_element_kind(
     }
 }
 
+/// The members of a uniform struct that materials can set, at the offsets the WGSL front end has laid them out at.
+fn reflect_uniform_members(module: &Module, ty: &Type) -> Vec<ReflectedUniformMember> {
+    let members = if let TypeInner::Struct { members, .. } = &ty.inner {
+        members
+    } else {
+        return Vec::new();
+    };
+
+    members
+        .iter()
+        .filter_map(|member| {
+            let name = member.name.as_ref()?;
+            let ty = shader_ty_to_uniform_type(&module.types[member.ty])?;
+
+            Some(ReflectedUniformMember {
+                name: name.clone(),
+                offset: member.offset as u64,
+                ty,
+            })
+        })
+        .collect()
+}
+
+fn shader_ty_to_uniform_type(ty: &Type) -> Option<UniformType> {
+    match &ty.inner {
+        TypeInner::Matrix {
+            columns: VectorSize::Tri,
+            rows: VectorSize::Tri,
+            width: 4,
+        } => Some(UniformType::Mat3x3),
+        TypeInner::Matrix {
+            columns: VectorSize::Quad,
+            rows: VectorSize::Quad,
+            width: 4,
+        } => Some(UniformType::Mat4x4),
+        _ => UniformType::from_vertex_format(shader_ty_to_vertex_format(ty)?),
+    }
+}
+
 fn shader_ty_to_vertex_format(ty: &Type) -> Option<VertexFormat> {
     match &ty.inner {
         TypeInner::Scalar { kind, width } => match (*kind, *width) {
@@ -632,3 +780,60 @@ fn shader_ty_to_vertex_format(ty: &Type) -> Option<VertexFormat> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uniform_members(source: &str) -> Vec<(String, u64, UniformType)> {
+        let module = parse_str(source).unwrap();
+        let (_, global) = module.global_variables.iter().next().unwrap();
+
+        reflect_uniform_members(&module, &module.types[global.ty])
+            .into_iter()
+            .map(|member| (member.name, member.offset, member.ty))
+            .collect()
+    }
+
+    #[test]
+    fn check_vec3_members_are_aligned_to_16_bytes() {
+        let members = uniform_members(
+            "struct Material { a: f32, b: vec3<f32>, c: f32, d: vec2<f32>, e: vec3<f32> }
+             @group(0) @binding(0) var<uniform> material: Material;",
+        );
+
+        assert_eq!(
+            members,
+            vec![
+                ("a".to_owned(), 0, UniformType::Float32),
+                ("b".to_owned(), 16, UniformType::Float32x3),
+                // A scalar fits in the padding after a vec3.
+                ("c".to_owned(), 28, UniformType::Float32),
+                ("d".to_owned(), 32, UniformType::Float32x2),
+                ("e".to_owned(), 48, UniformType::Float32x3),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_mat3_members_take_padded_columns() {
+        let members = uniform_members(
+            "struct Material { a: f32, b: mat3x3<f32>, c: f32, d: mat4x4<f32> }
+             @group(0) @binding(0) var<uniform> material: Material;",
+        );
+
+        assert_eq!(
+            members,
+            vec![
+                ("a".to_owned(), 0, UniformType::Float32),
+                ("b".to_owned(), 16, UniformType::Mat3x3),
+                (
+                    "c".to_owned(),
+                    16 + UniformType::Mat3x3.size(),
+                    UniformType::Float32
+                ),
+                ("d".to_owned(), 80, UniformType::Mat4x4),
+            ]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use super::{
+    BindGroupLayoutCache, PipelineLayoutCache, ShaderCompileError, ShaderHandle, ShaderManager,
+};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ShaderFileError {
+    #[error("failed to read the shader: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to reload the shader: {0}")]
+    Reload(#[from] ShaderCompileError),
+}
+
+struct WatchedShader {
+    path: PathBuf,
+    shader: ShaderHandle,
+    last_modified: Option<SystemTime>,
+}
+
+/// Reloads shaders from their WGSL files when the files change, to iterate on them while the game runs.
+/// See [`Context::watch_shader`](crate::Context::watch_shader).
+pub struct ShaderFileWatcher {
+    shaders: Vec<WatchedShader>,
+    last_checked: Option<Instant>,
+}
+
+impl ShaderFileWatcher {
+    const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new() -> Self {
+        Self {
+            shaders: Vec::new(),
+            last_checked: None,
+        }
+    }
+
+    /// Watches the file the shader has been created from. Changes made before this call are not picked up.
+    pub fn watch(&mut self, path: impl Into<PathBuf>, shader: ShaderHandle) {
+        let path = path.into();
+        let last_modified = modified_time(&path);
+        self.shaders.push(WatchedShader {
+            path,
+            shader,
+            last_modified,
+        });
+    }
+
+    /// Stops watching the file.
+    pub fn unwatch(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        self.shaders.retain(|shader| shader.path != path);
+    }
+
+    /// The newest version of the shader created from the file, if it is watched.
+    pub fn shader(&self, path: impl AsRef<Path>) -> Option<&ShaderHandle> {
+        let path = path.as_ref();
+        self.shaders
+            .iter()
+            .find(|shader| shader.path == path)
+            .map(|shader| &shader.shader)
+    }
+
+    /// Reloads the shaders whose files have been modified since the last call, returning the outcome per file.
+    /// The file system is checked at most once per second.
+    pub fn poll(
+        &mut self,
+        shader_mgr: &ShaderManager,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+        pipeline_layout_cache: &mut PipelineLayoutCache,
+    ) -> Vec<(PathBuf, Result<(), ShaderFileError>)> {
+        let now = Instant::now();
+
+        if let Some(last_checked) = self.last_checked {
+            if now - last_checked < Self::CHECK_INTERVAL {
+                return Vec::new();
+            }
+        }
+
+        self.last_checked = Some(now);
+
+        let mut results = Vec::new();
+
+        for watched in &mut self.shaders {
+            let modified = modified_time(&watched.path);
+
+            if modified.is_none() || watched.last_modified == modified {
+                continue;
+            }
+
+            watched.last_modified = modified;
+
+            let result = std::fs::read_to_string(&watched.path)
+                .map_err(ShaderFileError::from)
+                .and_then(|source| {
+                    Ok(shader_mgr.reload_shader(
+                        bind_group_layout_cache,
+                        pipeline_layout_cache,
+                        &watched.shader,
+                        source,
+                    )?)
+                })
+                .map(|shader| watched.shader = shader);
+            results.push((watched.path.clone(), result));
+        }
+
+        results
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
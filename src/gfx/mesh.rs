@@ -1,7 +1,148 @@
+use super::GenericBufferAllocation;
+use crate::math::Vec3;
 use codegen::Handle;
 use russimp::mesh::Mesh as RussimpMesh;
+use std::{mem::size_of, sync::OnceLock};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    Buffer, BufferSize, BufferUsages, Device, IndexFormat,
+};
+use zerocopy::AsBytes;
 
 #[derive(Handle)]
 pub struct Mesh {
     pub data: RussimpMesh,
+    buffers: OnceLock<MeshBuffers>,
+}
+
+/// Vertices of a mesh on the GPU as `[position, normal, uv]`, with the indices of its faces.
+#[derive(Clone)]
+pub struct MeshBuffers {
+    pub vertex_buffer: GenericBufferAllocation<Buffer>,
+    pub vertex_count: u32,
+    pub index_buffer: GenericBufferAllocation<Buffer>,
+    pub index_format: IndexFormat,
+    pub index_count: u32,
+}
+
+impl Mesh {
+    pub fn new(data: RussimpMesh) -> Self {
+        Self {
+            data,
+            buffers: OnceLock::new(),
+        }
+    }
+
+    /// Bounding box of the vertices in object space, as `(min, max)`. `None` if the mesh has no vertices.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        if self.data.vertices.is_empty() {
+            return None;
+        }
+
+        Some(self.data.vertices.iter().fold(
+            (
+                Vec3::new(f32::MAX, f32::MAX, f32::MAX),
+                Vec3::new(f32::MIN, f32::MIN, f32::MIN),
+            ),
+            |(min, max), vertex| {
+                let vertex = Vec3::new(vertex.x, vertex.y, vertex.z);
+                (Vec3::min(min, vertex), Vec3::max(max, vertex))
+            },
+        ))
+    }
+
+    /// Returns the buffers of the mesh, uploading them on first use; every renderer of the mesh shares them.
+    /// `None` if the mesh has no faces.
+    pub fn buffers(&self, device: &Device) -> Option<&MeshBuffers> {
+        if self.data.vertices.is_empty() || self.data.faces.is_empty() {
+            return None;
+        }
+
+        Some(self.buffers.get_or_init(|| upload(&self.data, device)))
+    }
+}
+
+fn upload(data: &RussimpMesh, device: &Device) -> MeshBuffers {
+    let mut vertices = Vec::with_capacity(data.vertices.len() * (3 + 3 + 2));
+    let uvs = data.texture_coords[0].as_ref().unwrap();
+
+    for ((vertex, normal), uv) in data.vertices.iter().zip(&data.normals).zip(uvs) {
+        vertices.extend_from_slice(&[vertex.x, vertex.y, vertex.z]);
+        vertices.extend_from_slice(&[normal.x, normal.y, normal.z]);
+        vertices.extend_from_slice(&[uv.x, uv.y]);
+    }
+
+    let indices = Vec::from_iter(data.faces.iter().flat_map(|face| face.0.iter().copied()));
+    let index_format = index_format_for(data.vertices.len());
+    let index_bytes = encode_indices(&indices, index_format);
+
+    MeshBuffers {
+        vertex_buffer: GenericBufferAllocation::new(
+            device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("mesh vertices"),
+                contents: vertices.as_bytes(),
+                // Frame captures copy the vertices out.
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_SRC,
+            }),
+            0,
+            BufferSize::new((size_of::<f32>() * vertices.len()) as u64).unwrap(),
+        ),
+        vertex_count: data.vertices.len() as u32,
+        index_buffer: GenericBufferAllocation::new(
+            device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("mesh indices"),
+                contents: &index_bytes,
+                usage: BufferUsages::INDEX | BufferUsages::COPY_SRC,
+            }),
+            0,
+            BufferSize::new(index_bytes.len() as u64).unwrap(),
+        ),
+        index_format,
+        index_count: indices.len() as u32,
+    }
+}
+
+/// Returns the narrowest index format that can address `vertex_count` vertices.
+pub fn index_format_for(vertex_count: usize) -> IndexFormat {
+    if vertex_count <= u16::MAX as usize {
+        IndexFormat::Uint16
+    } else {
+        IndexFormat::Uint32
+    }
+}
+
+/// Encodes the indices in the format. They must fit in it, see [`index_format_for`].
+pub fn encode_indices(indices: &[u32], format: IndexFormat) -> Vec<u8> {
+    match format {
+        IndexFormat::Uint16 => Vec::from_iter(indices.iter().map(|&index| index as u16))
+            .as_bytes()
+            .to_vec(),
+        IndexFormat::Uint32 => indices.as_bytes().to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_index_format_is_chosen_by_vertex_count() {
+        assert_eq!(index_format_for(3), IndexFormat::Uint16);
+        assert_eq!(index_format_for(u16::MAX as usize), IndexFormat::Uint16);
+        assert_eq!(index_format_for(u16::MAX as usize + 1), IndexFormat::Uint32);
+        assert_eq!(index_format_for(1_000_000), IndexFormat::Uint32);
+    }
+
+    #[test]
+    fn check_indices_are_encoded_in_their_format() {
+        let indices = [0, 1, 2, 2, 1, 65534];
+
+        let bytes = encode_indices(&indices, IndexFormat::Uint16);
+        assert_eq!(bytes.len(), indices.len() * size_of::<u16>());
+        assert_eq!(&bytes[10..], &65534u16.to_ne_bytes());
+
+        let bytes = encode_indices(&indices, IndexFormat::Uint32);
+        assert_eq!(bytes.len(), indices.len() * size_of::<u32>());
+        assert_eq!(&bytes[4..8], &1u32.to_ne_bytes());
+    }
 }
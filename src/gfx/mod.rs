@@ -1,43 +1,130 @@
 use codegen::Handle;
-use itertools::Itertools;
-use std::cell::RefCell;
+use image::DynamicImage;
+use parking_lot::RwLock;
 use thiserror::Error;
 use wgpu::{
-    Adapter, Backend, Backends, CompositeAlphaMode, CreateSurfaceError, Device, DeviceDescriptor,
-    DeviceType, Features, Instance, InstanceDescriptor, PresentMode, Queue, RequestDeviceError,
-    Surface, SurfaceConfiguration, TextureFormat, TextureUsages,
+    Adapter, AdapterInfo, Backends, CompositeAlphaMode, CreateSurfaceError, Device,
+    DeviceDescriptor, DownlevelCapabilities, DownlevelFlags, Features, Instance,
+    InstanceDescriptor, PresentMode, Queue, RequestDeviceError, Surface, SurfaceConfiguration,
+    TextureFormat, TextureUsages,
 };
 use winit::{dpi::PhysicalSize, window::Window};
 
+mod asset_preview;
+mod bloom;
 mod built_in_shader_manager;
 mod camera;
+mod camera_stack;
+mod cloth;
 mod color;
+mod color_space;
+mod cubemap;
+mod debug_draw;
+mod debug_view;
 mod depth_stencil;
+mod display_mgr;
 mod font;
+mod frame_capture;
+mod frame_graph;
+mod frame_pacing;
+mod frame_replay;
+mod frame_target;
+mod gfx_context_config;
 mod glyph;
+mod gpu_culling;
+mod gpu_particles;
+mod gpu_timer;
+mod headless;
+mod heightfield;
+mod hlod;
+mod instanced_group;
+mod layers;
+mod light;
 mod material;
 mod mesh;
 mod nine_patch;
+mod overlay;
+mod particle_simulation;
+mod pbr;
+mod planar_reflection;
+mod post_process;
+mod projection;
+mod quality;
+mod readback;
+mod render_config;
 mod render_mgr;
+mod render_queue;
+mod render_target;
 mod renderer;
 mod screen_mgr;
+mod screenshot;
+mod skybox;
 mod sprite;
+mod surface_recovery;
 mod texture;
+mod texture_array;
+mod uploader;
+mod viewport_clear;
+mod volumetric_fog;
+mod water;
 
+pub use asset_preview::*;
+pub use bloom::*;
 pub use built_in_shader_manager::*;
 pub use camera::*;
+pub use camera_stack::*;
+pub use cloth::*;
 pub use color::*;
+pub use color_space::*;
+pub use cubemap::*;
+pub use debug_draw::*;
+pub use debug_view::*;
 pub use depth_stencil::*;
+pub use display_mgr::*;
 pub use font::*;
+pub use frame_capture::*;
+pub use frame_graph::*;
+pub use frame_pacing::*;
+pub use frame_replay::*;
+pub use frame_target::*;
+pub use gfx_context_config::*;
 pub use glyph::*;
+pub use gpu_culling::*;
+pub use gpu_particles::*;
+pub use gpu_timer::*;
+pub use headless::*;
+pub use heightfield::*;
+pub use hlod::*;
+pub use instanced_group::*;
+pub use layers::*;
+pub use light::*;
 pub use material::*;
 pub use mesh::*;
 pub use nine_patch::*;
+pub use overlay::*;
+pub use particle_simulation::*;
+pub use pbr::*;
+pub use planar_reflection::*;
+pub use post_process::*;
+pub use projection::*;
+pub use quality::*;
+pub use readback::*;
+pub use render_config::*;
 pub use render_mgr::*;
+pub use render_queue::*;
+pub use render_target::*;
 pub use renderer::*;
 pub use screen_mgr::*;
+pub use screenshot::*;
+pub use skybox::*;
 pub use sprite::*;
+pub use surface_recovery::*;
 pub use texture::*;
+pub use texture_array::*;
+pub use uploader::*;
+pub use viewport_clear::*;
+pub use volumetric_fog::*;
+pub use water::*;
 
 #[derive(Error, Debug)]
 pub enum GfxContextCreationError {
@@ -45,111 +132,332 @@ pub enum GfxContextCreationError {
     CreateSurfaceError(#[from] CreateSurfaceError),
     #[error("no adapter found")]
     AdapterNotFound,
+    #[error("no adapter matched the adapter filter")]
+    NoAdapterMatchedFilter,
+    #[error("adapter {adapter} lacks the required features {missing:?}")]
+    MissingFeatures { adapter: String, missing: Features },
     #[error("failed to obtain device")]
     RequestDeviceError(#[from] RequestDeviceError),
 }
 
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("present mode {0:?} is not supported by the surface")]
+pub struct PresentModeUnsupportedError(pub PresentMode);
+
 #[derive(Handle)]
 pub struct GfxContext {
     pub instance: Instance,
     pub device: Device,
     pub queue: Queue,
-    pub surface: Surface,
-    pub surface_config: RefCell<SurfaceConfiguration>,
+    /// `None` for a headless context; frames are then rendered into an offscreen texture of the configured size.
+    pub surface: Option<Surface>,
+    pub surface_config: RwLock<SurfaceConfiguration>,
+    pub downlevel_capabilities: DownlevelCapabilities,
+    pub adapter_info: AdapterInfo,
+    /// Features the adapter supports, including the ones the device has not been created with.
+    pub adapter_features: Features,
+    /// Present modes the surface supports on the adapter. Empty for a headless context.
+    pub supported_present_modes: Vec<PresentMode>,
 }
 
 impl GfxContext {
-    pub async fn new(window: &Window) -> Result<Self, GfxContextCreationError> {
-        let instance = Instance::new(InstanceDescriptor::default());
+    /// Creates a context on the best adapter the config allows.
+    pub async fn new(
+        window: &Window,
+        config: &GfxContextConfig,
+    ) -> Result<Self, GfxContextCreationError> {
+        let instance = Instance::new(InstanceDescriptor {
+            backends: config.backends,
+            ..Default::default()
+        });
         let surface = unsafe { instance.create_surface(window) }?;
         let adapters = instance
-            .enumerate_adapters(Backends::all())
+            .enumerate_adapters(config.backends)
             .collect::<Vec<_>>();
-        let adapter = if let Some(adapter_index) = select_adapter(&surface, &adapters) {
-            &adapters[adapter_index]
-        } else {
-            return Err(GfxContextCreationError::AdapterNotFound);
-        };
+        let adapter = &adapters[select_adapter(Some(&surface), &adapters, config)?];
 
-        let (device, queue) = adapter
-            .request_device(
-                &DeviceDescriptor {
-                    label: None,
-                    features: Features::CLEAR_TEXTURE,
-                    limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::default()
-                    },
-                },
-                None,
-            )
-            .await?;
+        let downlevel_capabilities = adapter.get_downlevel_capabilities();
+        let adapter_info = adapter.get_info();
+        let adapter_features = adapter.features();
+        let (device, queue) = request_device(adapter, config).await?;
 
         let window_inner_size = window.inner_size();
-        let surface_config = RefCell::new(SurfaceConfiguration {
-            usage: TextureUsages::RENDER_ATTACHMENT,
-            format: TextureFormat::Bgra8Unorm,
+        // Copying out of the surface is optional; it only enables screenshots.
+        let surface_capabilities = surface.get_capabilities(adapter);
+        let surface_usage = TextureUsages::RENDER_ATTACHMENT
+            | (surface_capabilities.usages & TextureUsages::COPY_SRC);
+        let srgb_views = downlevel_capabilities
+            .flags
+            .contains(DownlevelFlags::SURFACE_VIEW_FORMATS);
+        let surface_format = select_surface_format(&surface_capabilities.formats, srgb_views);
+        let surface_config = RwLock::new(SurfaceConfiguration {
+            usage: surface_usage,
+            format: surface_format,
             width: window_inner_size.width,
             height: window_inner_size.height,
             present_mode: PresentMode::Fifo,
             alpha_mode: CompositeAlphaMode::Auto,
-            view_formats: vec![TextureFormat::Bgra8Unorm],
+            view_formats: surface_view_formats(surface_format, srgb_views),
         });
-        surface.configure(&device, &surface_config.borrow());
+        surface.configure(&device, &surface_config.read());
 
         Ok(GfxContext {
             instance,
             device,
             queue,
-            surface,
+            surface: Some(surface),
             surface_config,
+            downlevel_capabilities,
+            adapter_info,
+            adapter_features,
+            supported_present_modes: surface_capabilities.present_modes,
         })
     }
 
+    /// Creates a context without a window, for tests and offline rendering, e.g. on CI. Falls back through the
+    /// other backends, down to software adapters, if none of the configured ones has a suitable adapter.
+    pub async fn new_headless(
+        config: &GfxContextConfig,
+        size: PhysicalSize<u32>,
+    ) -> Result<Self, GfxContextCreationError> {
+        let mut last_err = GfxContextCreationError::AdapterNotFound;
+
+        for backends in [config.backends, Backends::PRIMARY, Backends::all()] {
+            let instance = Instance::new(InstanceDescriptor {
+                backends,
+                ..Default::default()
+            });
+            let adapters = instance.enumerate_adapters(backends).collect::<Vec<_>>();
+            let adapter = match select_adapter(None, &adapters, config) {
+                Ok(adapter_index) => &adapters[adapter_index],
+                Err(err) => {
+                    last_err = err;
+                    continue;
+                }
+            };
+
+            let downlevel_capabilities = adapter.get_downlevel_capabilities();
+            let adapter_info = adapter.get_info();
+            let adapter_features = adapter.features();
+            let (device, queue) = request_device(adapter, config).await?;
+
+            // The offscreen target stands in for the surface, so it is configured alike and can always be copied from.
+            let srgb_views = downlevel_capabilities
+                .flags
+                .contains(DownlevelFlags::VIEW_FORMATS);
+            let surface_format = select_surface_format(
+                &[TextureFormat::Bgra8UnormSrgb, TextureFormat::Bgra8Unorm],
+                srgb_views,
+            );
+            let surface_config = RwLock::new(SurfaceConfiguration {
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+                format: surface_format,
+                width: size.width,
+                height: size.height,
+                present_mode: PresentMode::Fifo,
+                alpha_mode: CompositeAlphaMode::Auto,
+                view_formats: surface_view_formats(surface_format, srgb_views),
+            });
+
+            return Ok(GfxContext {
+                instance,
+                device,
+                queue,
+                surface: None,
+                surface_config,
+                downlevel_capabilities,
+                adapter_info,
+                adapter_features,
+                supported_present_modes: Vec::new(),
+            });
+        }
+
+        Err(last_err)
+    }
+
+    /// Name, vendor and backend of the adapter, e.g. to show the GPU in diagnostics.
+    pub fn adapter_info(&self) -> &AdapterInfo {
+        &self.adapter_info
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.surface.is_none()
+    }
+
+    /// Format the surface is configured with; an sRGB one where the surface allows it.
+    pub fn surface_format(&self) -> TextureFormat {
+        self.surface_config.read().format
+    }
+
+    /// Format of the view of the surface that stores colors as they are written, for the passes drawing colors as
+    /// picked over the scene, e.g. the overlays and the debug UI. It is the sRGB surface format only if the surface
+    /// has no other view.
+    pub fn plain_surface_format(&self) -> TextureFormat {
+        let surface_config = self.surface_config.read();
+        let format = surface_config.format.remove_srgb_suffix();
+
+        if surface_config.view_formats.contains(&format) {
+            format
+        } else {
+            surface_config.format
+        }
+    }
+
     pub fn resize(&self, size: PhysicalSize<u32>) {
-        let mut surface_config = self.surface_config.borrow_mut();
+        let mut surface_config = self.surface_config.write();
         surface_config.width = size.width;
         surface_config.height = size.height;
-        self.surface.configure(&self.device, &surface_config);
+        self.configure_surface(&surface_config);
     }
-}
 
-fn select_adapter(surface: &Surface, adapters: impl AsRef<[Adapter]>) -> Option<usize> {
-    let adapters = adapters
-        .as_ref()
-        .iter()
-        .filter(|adapter| !surface.get_capabilities(adapter).formats.is_empty())
-        .collect::<Vec<_>>();
+    pub fn is_vsync(&self) -> bool {
+        self.surface_config.read().present_mode == PresentMode::Fifo
+    }
 
-    if adapters.is_empty() {
-        return None;
+    pub fn set_vsync(&self, vsync: bool) {
+        let mut surface_config = self.surface_config.write();
+        surface_config.present_mode = if vsync {
+            PresentMode::Fifo
+        } else {
+            PresentMode::AutoNoVsync
+        };
+        self.configure_surface(&surface_config);
     }
 
-    let mut scores = adapters.iter().map(|_| 0).collect::<Vec<_>>();
+    pub fn present_mode(&self) -> PresentMode {
+        self.surface_config.read().present_mode
+    }
 
-    for (index, adapter) in adapters.iter().enumerate() {
-        if surface.get_capabilities(adapter).formats.is_empty() {
+    /// Reconfigures the surface to present in the given mode from the next frame on, e.g. `Mailbox` for vsync
+    /// without blocking. The automatic modes fall back by themselves and are always supported.
+    pub fn set_present_mode(
+        &self,
+        present_mode: PresentMode,
+    ) -> Result<(), PresentModeUnsupportedError> {
+        if !self.is_present_mode_supported(present_mode) {
+            return Err(PresentModeUnsupportedError(present_mode));
+        }
+
+        let mut surface_config = self.surface_config.write();
+        surface_config.present_mode = present_mode;
+        self.configure_surface(&surface_config);
+        Ok(())
+    }
+
+    pub fn is_present_mode_supported(&self, present_mode: PresentMode) -> bool {
+        match present_mode {
+            PresentMode::AutoVsync | PresentMode::AutoNoVsync => true,
+            // Nothing is presented without a surface.
+            _ if self.is_headless() => true,
+            _ => self.supported_present_modes.contains(&present_mode),
+        }
+    }
+
+    /// Uploads the image as a texture that can be bound to materials, e.g. by
+    /// [`Material::set_texture`](crate::gfx::Material::set_texture).
+    pub fn create_texture_from_image(
+        &self,
+        image: &DynamicImage,
+        params: TextureCreationParams,
+    ) -> TextureHandle {
+        TextureHandle::new(Texture::from_image_with_params(
+            image,
+            params,
+            &self.device,
+            &self.queue,
+        ))
+    }
+
+    fn configure_surface(&self, surface_config: &SurfaceConfiguration) {
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, surface_config);
+        }
+    }
+}
+
+async fn request_device(
+    adapter: &Adapter,
+    config: &GfxContextConfig,
+) -> Result<(Device, Queue), RequestDeviceError> {
+    adapter
+        .request_device(
+            &DeviceDescriptor {
+                label: None,
+                // Timestamp queries are optional; they only feed the frame report. Without BC compression,
+                // compressed textures are decompressed on upload, and without line polygons, the wireframe debug
+                // view falls back to a shader.
+                features: config.required_features
+                    | Features::CLEAR_TEXTURE
+                    | (adapter.features()
+                        & (Features::TIMESTAMP_QUERY
+                            | Features::TEXTURE_COMPRESSION_BC
+                            | Features::POLYGON_MODE_LINE)),
+                limits: config.device_limits(&adapter.limits()),
+            },
+            None,
+        )
+        .await
+}
+
+/// Picks the best scoring adapter the config allows, skipping the ones that cannot present to the surface if there
+/// is one. Adapters lacking required features are only reported if no other adapter is left.
+fn select_adapter(
+    surface: Option<&Surface>,
+    adapters: impl AsRef<[Adapter]>,
+    config: &GfxContextConfig,
+) -> Result<usize, GfxContextCreationError> {
+    let adapter_name = config.adapter_name.as_ref().map(|name| name.to_lowercase());
+    let mut is_filtered_out = false;
+    let mut selected: Option<(usize, i32)> = None;
+    let mut lacking: Option<(AdapterInfo, Features, i32)> = None;
+
+    for (index, adapter) in adapters.as_ref().iter().enumerate() {
+        if surface.map_or(false, |surface| {
+            surface.get_capabilities(adapter).formats.is_empty()
+        }) {
             continue;
         }
 
         let info = adapter.get_info();
-        let device_score = match info.device_type {
-            DeviceType::IntegratedGpu => 10,
-            DeviceType::DiscreteGpu => 20,
-            DeviceType::Cpu => -10,
-            _ => 0,
-        };
-        let backend_score = match info.backend {
-            // The Vulkan is available with other backends simultaneously on some platforms.
-            // Because the dedicated backends are preferred over the Vulkan, we set the score of the Vulkan slightly lower than others.
-            Backend::Metal => 2,
-            Backend::Dx12 => 2,
-            Backend::Vulkan => 1,
-            _ => 0,
-        };
-        scores[index] += device_score + backend_score;
+
+        if let Some(adapter_name) = &adapter_name {
+            if !info.name.to_lowercase().contains(adapter_name) {
+                continue;
+            }
+        }
+
+        if let Some(adapter_filter) = &config.adapter_filter {
+            if !adapter_filter(&info) {
+                is_filtered_out = true;
+                continue;
+            }
+        }
+
+        let score = config.score_adapter(&info);
+        let missing = config.required_features - adapter.features();
+
+        if !missing.is_empty() {
+            if lacking
+                .as_ref()
+                .map_or(true, |(_, _, lacking_score)| *lacking_score <= score)
+            {
+                lacking = Some((info, missing, score));
+            }
+            continue;
+        }
+
+        if selected.map_or(true, |(_, selected_score)| selected_score <= score) {
+            selected = Some((index, score));
+        }
     }
 
-    scores.iter().position_max()
+    match (selected, lacking) {
+        (Some((index, _)), _) => Ok(index),
+        (None, Some((info, missing, _))) => Err(GfxContextCreationError::MissingFeatures {
+            adapter: info.name,
+            missing,
+        }),
+        (None, None) if is_filtered_out => Err(GfxContextCreationError::NoAdapterMatchedFilter),
+        (None, None) => Err(GfxContextCreationError::AdapterNotFound),
+    }
 }
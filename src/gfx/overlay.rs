@@ -0,0 +1,551 @@
+use super::{Color, GfxContextHandle};
+use crate::handles::{GenIndex, HandleStore};
+use std::{borrow::Cow, mem::size_of, time::Duration};
+use wgpu::{
+    BlendState, Buffer, BufferAddress, BufferDescriptor, BufferUsages, ColorTargetState,
+    ColorWrites, CommandEncoder, FragmentState, LoadOp, MultisampleState, Operations,
+    PipelineLayoutDescriptor, PrimitiveState, Queue, RenderPass, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor,
+    ShaderSource, TextureView, VertexAttribute, VertexBufferLayout, VertexFormat, VertexState,
+    VertexStepMode,
+};
+use zerocopy::AsBytes;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OverlayId(GenIndex);
+
+/// An overlay drawn by its own pipeline, e.g. a vignette.
+pub trait OverlayShader {
+    /// Called before the overlay pass with the current opacity of the overlay, in `0..=1`.
+    fn prepare(&mut self, queue: &Queue, opacity: f32);
+
+    /// Draws over the whole surface. The pipeline must target the format of
+    /// [`GfxContext::plain_surface_format`](super::GfxContext::plain_surface_format), without depth and stencil.
+    fn draw<'r>(&'r self, render_pass: &mut RenderPass<'r>);
+}
+
+pub enum OverlayContent {
+    /// A color blended over the surface. Its alpha is multiplied by the opacity of the overlay.
+    Color(Color),
+    Shader(Box<dyn OverlayShader>),
+}
+
+/// A draw of the overlay pass, in compositing order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverlayDraw {
+    /// Consecutive color overlays merged into one color.
+    Color(Color),
+    Shader(OverlayId),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Fade {
+    from: f32,
+    to: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+struct Overlay {
+    priority: i32,
+    content: OverlayContent,
+    opacity: f32,
+    fade: Option<Fade>,
+    removing: bool,
+}
+
+impl Overlay {
+    fn fade_to(&mut self, to: f32, duration: Duration) {
+        let duration = duration.as_secs_f32();
+
+        if duration <= 0.0 {
+            self.opacity = to;
+            self.fade = None;
+            return;
+        }
+
+        self.fade = Some(Fade {
+            from: self.opacity,
+            to,
+            duration,
+            elapsed: 0.0,
+        });
+    }
+
+    fn is_fading_out(&self) -> bool {
+        self.fade.map_or(self.opacity == 0.0, |fade| fade.to == 0.0)
+    }
+}
+
+/// Full-screen effects drawn over everything else, composited in a single pass at the end of the frame.
+///
+/// Overlays with a lower priority are drawn first; overlays of the same priority are drawn in insertion order.
+/// They cover the surface regardless of its size and of the cameras, and their fades are driven by unscaled time.
+#[derive(Default)]
+pub struct OverlayStack {
+    overlays: HandleStore<Overlay>,
+    /// Ids in compositing order.
+    order: Vec<OverlayId>,
+}
+
+impl OverlayStack {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.overlays.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overlays.is_empty()
+    }
+
+    /// Returns `false` once the overlay has been removed, even if its slot has been reused since.
+    pub fn contains(&self, id: OverlayId) -> bool {
+        self.overlays.is_alive(id.0)
+    }
+
+    /// Adds a fully visible overlay.
+    pub fn add(&mut self, priority: i32, content: OverlayContent) -> OverlayId {
+        let id = OverlayId(self.overlays.insert(Overlay {
+            priority,
+            content,
+            opacity: 1.0,
+            fade: None,
+            removing: false,
+        }));
+        let overlays = &self.overlays;
+        let index = self
+            .order
+            .partition_point(|other| overlays.get(other.0).unwrap().priority <= priority);
+        self.order.insert(index, id);
+        id
+    }
+
+    /// Adds an overlay fading in from transparent.
+    pub fn add_faded_in(
+        &mut self,
+        priority: i32,
+        content: OverlayContent,
+        duration: Duration,
+    ) -> OverlayId {
+        let id = self.add(priority, content);
+        let overlay = self.get_mut(id).unwrap();
+        overlay.opacity = 0.0;
+        overlay.fade_to(1.0, duration);
+        id
+    }
+
+    /// Shows a color over the screen at once and fades it out over `duration`, e.g. when taking damage.
+    pub fn flash(&mut self, priority: i32, color: Color, duration: Duration) -> OverlayId {
+        let id = self.add(priority, OverlayContent::Color(color));
+        self.remove(id, duration);
+        id
+    }
+
+    /// Current opacity of the overlay, in `0..=1`.
+    pub fn opacity(&self, id: OverlayId) -> Option<f32> {
+        self.get(id).map(|overlay| overlay.opacity)
+    }
+
+    pub fn content_mut(&mut self, id: OverlayId) -> Option<&mut OverlayContent> {
+        self.get_mut(id).map(|overlay| &mut overlay.content)
+    }
+
+    /// Fades the overlay from its current opacity to fully visible. Cancels a pending removal.
+    pub fn fade_in(&mut self, id: OverlayId, duration: Duration) -> bool {
+        match self.get_mut(id) {
+            Some(overlay) => {
+                overlay.removing = false;
+                overlay.fade_to(1.0, duration);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Fades the overlay from its current opacity to transparent, keeping it in the stack.
+    pub fn fade_out(&mut self, id: OverlayId, duration: Duration) -> bool {
+        match self.get_mut(id) {
+            Some(overlay) => {
+                overlay.fade_to(0.0, duration);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the overlay once it has faded out. A fade-out in progress is completed as it is;
+    /// otherwise the overlay fades out from its current opacity over `duration`.
+    pub fn remove(&mut self, id: OverlayId, duration: Duration) -> bool {
+        let overlay = match self.get_mut(id) {
+            Some(overlay) => overlay,
+            None => return false,
+        };
+
+        if !overlay.is_fading_out() {
+            overlay.fade_to(0.0, duration);
+        }
+
+        overlay.removing = true;
+
+        if overlay.fade.is_none() {
+            self.overlays.remove(id.0);
+            self.order.retain(|&other| other != id);
+        }
+
+        true
+    }
+
+    /// Returns `true` while any overlay is fading.
+    pub fn is_animating(&self) -> bool {
+        self.overlays
+            .iter()
+            .any(|(_, overlay)| overlay.fade.is_some())
+    }
+
+    /// Advances the fades by the unscaled delta time, dropping the removed overlays that finished fading out.
+    pub fn update(&mut self, unscaled_delta_time: Duration) {
+        let delta_time = unscaled_delta_time.as_secs_f32();
+
+        for &id in &self.order {
+            let overlay = self.overlays.get_mut(id.0).unwrap();
+            let fade = match &mut overlay.fade {
+                Some(fade) => fade,
+                None => continue,
+            };
+
+            fade.elapsed += delta_time;
+
+            if fade.duration <= fade.elapsed {
+                overlay.opacity = fade.to;
+                overlay.fade = None;
+            } else {
+                let t = fade.elapsed / fade.duration;
+                overlay.opacity = fade.from + (fade.to - fade.from) * t;
+            }
+        }
+
+        self.overlays
+            .retain(|_, overlay| !(overlay.removing && overlay.fade.is_none()));
+
+        let overlays = &self.overlays;
+        self.order.retain(|&id| overlays.is_alive(id.0));
+    }
+
+    /// Returns the draws of the overlay pass in compositing order.
+    /// Consecutive color overlays are merged into one draw, and invisible overlays are skipped.
+    /// Nothing is drawn, and no pass is added, if the result is empty.
+    pub fn plan(&self) -> Vec<OverlayDraw> {
+        let mut draws = Vec::new();
+
+        for &id in &self.order {
+            let overlay = self.overlays.get(id.0).unwrap();
+
+            if overlay.opacity <= 0.0 {
+                continue;
+            }
+
+            match &overlay.content {
+                OverlayContent::Color(color) => {
+                    let color =
+                        Color::from_rgba(color.r, color.g, color.b, color.a * overlay.opacity);
+
+                    if color.a <= 0.0 {
+                        continue;
+                    }
+
+                    match draws.last_mut() {
+                        Some(OverlayDraw::Color(below)) => *below = merge_over(*below, color),
+                        _ => draws.push(OverlayDraw::Color(color)),
+                    }
+                }
+                OverlayContent::Shader(_) => draws.push(OverlayDraw::Shader(id)),
+            }
+        }
+
+        draws
+    }
+
+    fn prepare_shaders(&mut self, queue: &Queue) {
+        for &id in &self.order {
+            let overlay = self.overlays.get_mut(id.0).unwrap();
+
+            if let OverlayContent::Shader(shader) = &mut overlay.content {
+                if 0.0 < overlay.opacity {
+                    shader.prepare(queue, overlay.opacity);
+                }
+            }
+        }
+    }
+
+    fn get(&self, id: OverlayId) -> Option<&Overlay> {
+        self.overlays.get(id.0)
+    }
+
+    fn get_mut(&mut self, id: OverlayId) -> Option<&mut Overlay> {
+        self.overlays.get_mut(id.0)
+    }
+}
+
+/// Returns the color that, alpha blended once, gives the same result as blending `below` and then `above`.
+fn merge_over(below: Color, above: Color) -> Color {
+    let alpha = 1.0 - (1.0 - below.a) * (1.0 - above.a);
+
+    if alpha <= 0.0 {
+        return Color::transparent();
+    }
+
+    let below_weight = below.a * (1.0 - above.a) / alpha;
+    let above_weight = above.a / alpha;
+    Color::from_rgba(
+        below.r * below_weight + above.r * above_weight,
+        below.g * below_weight + above.g * above_weight,
+        below.b * below_weight + above.b * above_weight,
+        alpha,
+    )
+}
+
+/// Encodes the overlay pass of an [`OverlayStack`].
+pub struct OverlayRenderer {
+    gfx_ctx: GfxContextHandle,
+    pipeline: RenderPipeline,
+    vertex_buffer: Option<Buffer>,
+}
+
+impl OverlayRenderer {
+    /// `position: vec2<f32>` followed by `color: vec4<f32>`.
+    const VERTEX_SIZE: usize = size_of::<[f32; 6]>();
+
+    pub fn new(gfx_ctx: GfxContextHandle) -> Self {
+        let device = &gfx_ctx.device;
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("overlay color shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "./built_in_shaders/overlay_color.wgsl"
+            ))),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("overlay color pipeline layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let format = gfx_ctx.plain_surface_format();
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("overlay color pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[VertexBufferLayout {
+                    array_stride: Self::VERTEX_SIZE as BufferAddress,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: &[
+                        VertexAttribute {
+                            format: VertexFormat::Float32x2,
+                            offset: 0,
+                            shader_location: 0,
+                        },
+                        VertexAttribute {
+                            format: VertexFormat::Float32x4,
+                            offset: size_of::<[f32; 2]>() as BufferAddress,
+                            shader_location: 1,
+                        },
+                    ],
+                }],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self {
+            gfx_ctx,
+            pipeline,
+            vertex_buffer: None,
+        }
+    }
+
+    /// Composites the overlays over `target` in one pass.
+    /// Returns `false` without adding a pass if there is nothing to draw.
+    pub fn encode(
+        &mut self,
+        stack: &mut OverlayStack,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+    ) -> bool {
+        let draws = stack.plan();
+
+        if draws.is_empty() {
+            return false;
+        }
+
+        // One full-screen triangle per merged color.
+        let mut vertices = Vec::new();
+        for draw in &draws {
+            if let OverlayDraw::Color(color) = draw {
+                for [x, y] in [[-1.0f32, -1.0], [3.0, -1.0], [-1.0, 3.0]] {
+                    vertices.extend_from_slice(&[x, y, color.r, color.g, color.b, color.a]);
+                }
+            }
+        }
+
+        if !vertices.is_empty() {
+            let size = (size_of::<f32>() * vertices.len()) as BufferAddress;
+
+            let is_too_small = match &self.vertex_buffer {
+                Some(buffer) => buffer.size() < size,
+                None => true,
+            };
+
+            if is_too_small {
+                self.vertex_buffer = Some(self.gfx_ctx.device.create_buffer(&BufferDescriptor {
+                    label: Some("overlay vertex buffer"),
+                    size: size.next_power_of_two(),
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }));
+            }
+
+            self.gfx_ctx.queue.write_buffer(
+                self.vertex_buffer.as_ref().unwrap(),
+                0,
+                vertices.as_bytes(),
+            );
+        }
+
+        stack.prepare_shaders(&self.gfx_ctx.queue);
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("overlay pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        let mut color_index = 0;
+        for draw in &draws {
+            match draw {
+                OverlayDraw::Color(_) => {
+                    render_pass.set_pipeline(&self.pipeline);
+                    render_pass
+                        .set_vertex_buffer(0, self.vertex_buffer.as_ref().unwrap().slice(..));
+                    render_pass.draw(color_index * 3..color_index * 3 + 3, 0..1);
+                    color_index += 1;
+                }
+                OverlayDraw::Shader(id) => {
+                    if let Some(OverlayContent::Shader(shader)) =
+                        stack.get(*id).map(|overlay| &overlay.content)
+                    {
+                        shader.draw(&mut render_pass);
+                    }
+                }
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoopShader;
+
+    impl OverlayShader for NoopShader {
+        fn prepare(&mut self, _queue: &Queue, _opacity: f32) {}
+
+        fn draw<'r>(&'r self, _render_pass: &mut RenderPass<'r>) {}
+    }
+
+    fn color(r: f32, a: f32) -> OverlayContent {
+        OverlayContent::Color(Color::from_rgba(r, 0.0, 0.0, a))
+    }
+
+    #[test]
+    fn check_overlay_compositing_order() {
+        let mut stack = OverlayStack::new();
+        assert_eq!(stack.plan(), []);
+
+        let loading = stack.add(100, OverlayContent::Shader(Box::new(NoopShader)));
+        stack.add(10, color(1.0, 0.5));
+        let vignette = stack.add(-5, OverlayContent::Shader(Box::new(NoopShader)));
+        stack.add(10, color(0.0, 0.5));
+
+        // The two colors of priority 10 are adjacent and merged, the later one on top.
+        let draws = stack.plan();
+        assert_eq!(draws.len(), 3);
+        assert_eq!(draws[0], OverlayDraw::Shader(vignette));
+        assert_eq!(draws[2], OverlayDraw::Shader(loading));
+        match draws[1] {
+            OverlayDraw::Color(merged) => {
+                assert!((merged.a - 0.75).abs() < 1e-6);
+                // Over black: 1 * 0.5 * (1 - 0.5) + 0 * 0.5.
+                assert!((merged.r * merged.a - 0.25).abs() < 1e-6);
+            }
+            _ => panic!("expected a merged color"),
+        }
+    }
+
+    #[test]
+    fn check_removal_completes_fade_out() {
+        let mut stack = OverlayStack::new();
+        assert!(!stack.is_animating());
+
+        let id = stack.add_faded_in(0, color(1.0, 1.0), Duration::from_millis(100));
+        // Invisible overlays draw nothing, so no pass is added.
+        assert_eq!(stack.plan(), []);
+
+        stack.update(Duration::from_millis(50));
+        assert!((stack.opacity(id).unwrap() - 0.5).abs() < 1e-6);
+
+        // Removed mid-fade: fades out from the current opacity instead of popping.
+        assert!(stack.remove(id, Duration::from_millis(100)));
+        stack.update(Duration::from_millis(50));
+        assert!((stack.opacity(id).unwrap() - 0.25).abs() < 1e-6);
+        assert_eq!(stack.plan().len(), 1);
+
+        stack.update(Duration::from_millis(50));
+        assert!(!stack.contains(id));
+        assert!(stack.is_empty());
+        assert_eq!(stack.plan(), []);
+
+        let flash = stack.flash(0, Color::white(), Duration::from_millis(200));
+        assert_eq!(stack.opacity(flash), Some(1.0));
+        stack.update(Duration::from_millis(250));
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn check_removed_ids_stay_removed() {
+        let mut stack = OverlayStack::new();
+        let removed = stack.add(0, color(1.0, 1.0));
+        assert!(stack.remove(removed, Duration::ZERO));
+
+        // The new overlay takes the slot of the removed one, but not its id.
+        let added = stack.add(0, color(0.0, 1.0));
+        assert!(!stack.contains(removed));
+        assert_eq!(stack.opacity(removed), None);
+        assert!(!stack.fade_out(removed, Duration::ZERO));
+        assert!(!stack.remove(removed, Duration::ZERO));
+        assert_eq!(stack.opacity(added), Some(1.0));
+    }
+}
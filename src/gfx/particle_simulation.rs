@@ -0,0 +1,553 @@
+use super::Color;
+use crate::{
+    animation::AnimationCurve,
+    math::{Mat4, Vec3, Vec4},
+};
+use zerocopy::AsBytes;
+
+/// Number of samples the size and color curves are baked into.
+pub const PARTICLE_CURVE_SAMPLES: usize = 32;
+
+/// State of a single particle. The layout matches the `TRANSFORM_ROW_*` semantic inputs, so that
+/// the particle buffer can be bound as the instance buffer as is.
+#[repr(C)]
+#[derive(AsBytes, Debug, Clone, Copy, PartialEq)]
+pub struct Particle {
+    /// xyz is the world position and w is the size.
+    pub position_size: Vec4,
+    pub color: Vec4,
+    /// xyz is the velocity and w is the age in seconds.
+    pub velocity_age: Vec4,
+    /// x is the lifetime in seconds; the rest is unused.
+    pub lifetime: Vec4,
+}
+
+/// Color over the normalized age of a particle. Before the first key and after the last one, the gradient holds their colors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorGradient {
+    /// Sorted by time.
+    pub keys: Vec<(f32, Color)>,
+}
+
+impl ColorGradient {
+    pub fn new(mut keys: Vec<(f32, Color)>) -> Self {
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { keys }
+    }
+
+    pub fn constant(color: Color) -> Self {
+        Self::new(vec![(0.0, color)])
+    }
+
+    pub fn evaluate(&self, time: f32) -> Color {
+        let (first, last) = match (self.keys.first(), self.keys.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Color::white(),
+        };
+
+        if time <= first.0 {
+            return first.1;
+        }
+
+        if last.0 <= time {
+            return last.1;
+        }
+
+        let index = self.keys.partition_point(|key| key.0 <= time);
+        let (from_time, from) = self.keys[index - 1];
+        let (to_time, to) = self.keys[index];
+        let t = (time - from_time) / (to_time - from_time);
+
+        Color::from_rgba(
+            from.r + (to.r - from.r) * t,
+            from.g + (to.g - from.g) * t,
+            from.b + (to.b - from.b) * t,
+            from.a + (to.a - from.a) * t,
+        )
+    }
+}
+
+/// How a [`ParticleSystem`](super::ParticleSystem) emits and moves its particles.
+/// Particles are simulated in world space, so moving the emitter leaves the emitted ones behind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParticleEmitter {
+    /// Upper bound of the alive particles; emission stops while it is reached.
+    pub max_particles: u32,
+    /// Particles emitted per second.
+    pub emission_rate: f32,
+    /// Lifetime in seconds.
+    pub lifetime: f32,
+    /// Fraction of the lifetime randomly taken off each particle, in `[0, 1]`.
+    pub lifetime_variance: f32,
+    pub speed: f32,
+    /// Fraction of the speed randomly taken off each particle, in `[0, 1]`.
+    pub speed_variance: f32,
+    /// Half angle of the emission cone around the emitter's +Y axis, in radians.
+    pub spread: f32,
+    /// Radius of the sphere around the emitter that particles are spawned in.
+    pub radius: f32,
+    /// Acceleration applied to every particle, in world space.
+    pub gravity: Vec3,
+    /// Fraction of the velocity lost per second.
+    pub drag: f32,
+    /// Acceleration of the turbulence.
+    pub noise_strength: f32,
+    /// Spatial frequency of the turbulence; higher values make smaller swirls.
+    pub noise_frequency: f32,
+    /// Size over the normalized age.
+    pub size: AnimationCurve,
+    /// Color over the normalized age.
+    pub color: ColorGradient,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            max_particles: 1000,
+            emission_rate: 100.0,
+            lifetime: 2.0,
+            lifetime_variance: 0.0,
+            speed: 1.0,
+            speed_variance: 0.0,
+            spread: 0.0,
+            radius: 0.0,
+            gravity: Vec3::new(0.0, -9.8, 0.0),
+            drag: 0.0,
+            noise_strength: 0.0,
+            noise_frequency: 1.0,
+            size: AnimationCurve::constant(0.1),
+            color: ColorGradient::constant(Color::white()),
+        }
+    }
+}
+
+/// Per-frame simulation parameters, shared by the CPU path and the `particle_simulation.wgsl` compute shader.
+#[repr(C)]
+#[derive(AsBytes, Debug, Clone, Copy, PartialEq)]
+pub struct ParticleParams {
+    /// Rows of the emitter's world matrix.
+    pub emitter: [Vec4; 4],
+    /// xyz is the gravity and w is the drag.
+    pub gravity_drag: Vec4,
+    /// Noise strength, noise frequency, elapsed time and delta time.
+    pub noise_time: Vec4,
+    /// Speed, spread, lifetime and radius.
+    pub emission: Vec4,
+    /// Speed variance and lifetime variance; the rest is unused.
+    pub variance: Vec4,
+    /// Particles to spawn, capacity, the side of the ping-pong pair being read and the random seed of the frame.
+    pub counts: [u32; 4],
+    /// [`PARTICLE_CURVE_SAMPLES`] sizes, packed four per element.
+    pub size_curve: [Vec4; PARTICLE_CURVE_SAMPLES / 4],
+    pub color_curve: [Vec4; PARTICLE_CURVE_SAMPLES],
+}
+
+/// Curves of an emitter sampled at regular intervals of the normalized age.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BakedParticleCurves {
+    pub size: [Vec4; PARTICLE_CURVE_SAMPLES / 4],
+    pub color: [Vec4; PARTICLE_CURVE_SAMPLES],
+}
+
+impl BakedParticleCurves {
+    pub fn bake(emitter: &ParticleEmitter) -> Self {
+        let mut size = [Vec4::ZERO; PARTICLE_CURVE_SAMPLES / 4];
+        let mut color = [Vec4::ZERO; PARTICLE_CURVE_SAMPLES];
+
+        for index in 0..PARTICLE_CURVE_SAMPLES {
+            let time = index as f32 / (PARTICLE_CURVE_SAMPLES - 1) as f32;
+            let value = emitter.size.evaluate(time);
+
+            match index % 4 {
+                0 => size[index / 4].x = value,
+                1 => size[index / 4].y = value,
+                2 => size[index / 4].z = value,
+                _ => size[index / 4].w = value,
+            }
+
+            let value = emitter.color.evaluate(time);
+            color[index] = Vec4::new(value.r, value.g, value.b, value.a);
+        }
+
+        Self { size, color }
+    }
+}
+
+impl ParticleParams {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        emitter: &ParticleEmitter,
+        curves: &BakedParticleCurves,
+        matrix: &Mat4,
+        time: f32,
+        delta_time: f32,
+        spawn_count: u32,
+        capacity: u32,
+        source: u32,
+        seed: u32,
+    ) -> Self {
+        Self {
+            emitter: [matrix.row(0), matrix.row(1), matrix.row(2), matrix.row(3)],
+            gravity_drag: Vec4::new(
+                emitter.gravity.x,
+                emitter.gravity.y,
+                emitter.gravity.z,
+                emitter.drag,
+            ),
+            noise_time: Vec4::new(
+                emitter.noise_strength,
+                emitter.noise_frequency,
+                time,
+                delta_time,
+            ),
+            emission: Vec4::new(
+                emitter.speed,
+                emitter.spread,
+                emitter.lifetime,
+                emitter.radius,
+            ),
+            variance: Vec4::new(
+                emitter.speed_variance.clamp(0.0, 1.0),
+                emitter.lifetime_variance.clamp(0.0, 1.0),
+                0.0,
+                0.0,
+            ),
+            counts: [spawn_count, capacity, source, seed],
+            size_curve: curves.size,
+            color_curve: curves.color,
+        }
+    }
+
+    fn delta_time(&self) -> f32 {
+        self.noise_time.w
+    }
+
+    fn size(&self, age_ratio: f32) -> f32 {
+        let (index, t) = curve_position(age_ratio);
+        let sample = |index: usize| {
+            let packed = self.size_curve[index / 4];
+            match index % 4 {
+                0 => packed.x,
+                1 => packed.y,
+                2 => packed.z,
+                _ => packed.w,
+            }
+        };
+        let from = sample(index);
+        from + (sample(index + 1) - from) * t
+    }
+
+    fn color(&self, age_ratio: f32) -> Vec4 {
+        let (index, t) = curve_position(age_ratio);
+        let from = self.color_curve[index];
+        from + (self.color_curve[index + 1] - from) * t
+    }
+}
+
+/// Converts fractional emission into whole particles, carrying the remainder over to the next frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct EmissionCounter {
+    remainder: f32,
+}
+
+impl EmissionCounter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the number of particles to spawn this frame.
+    pub fn advance(&mut self, rate: f32, delta_time: f32) -> u32 {
+        let emitted = self.remainder + rate.max(0.0) * delta_time.max(0.0);
+        let count = emitted.floor();
+        self.remainder = emitted - count;
+        count as u32
+    }
+
+    pub fn reset(&mut self) {
+        self.remainder = 0.0;
+    }
+}
+
+/// Advances the particles by one frame on the CPU: moves and ages the alive ones, removes the expired ones
+/// and spawns new ones while there is room. It mirrors `particle_simulation.wgsl`, except that the order of
+/// the particles differs since the GPU compacts the survivors in no particular order.
+pub fn simulate_particles(particles: &mut Vec<Particle>, params: &ParticleParams) {
+    // Spawning only into the room left by the previous frame, like the GPU does.
+    let [spawn_count, capacity, _, _] = params.counts;
+    let room = capacity.saturating_sub(particles.len() as u32);
+    let mut index = 0;
+
+    while index < particles.len() {
+        match update_particle(particles[index], params) {
+            Some(particle) => {
+                particles[index] = particle;
+                index += 1;
+            }
+            None => {
+                particles.swap_remove(index);
+            }
+        }
+    }
+
+    for index in 0..spawn_count.min(room) {
+        particles.push(spawn_particle(index, params));
+    }
+}
+
+/// Moves and ages a particle, returning `None` if it expired.
+pub fn update_particle(mut particle: Particle, params: &ParticleParams) -> Option<Particle> {
+    let delta_time = params.delta_time();
+    let lifetime = particle.lifetime.x;
+    let age = particle.velocity_age.w + delta_time;
+
+    if lifetime <= age {
+        return None;
+    }
+
+    let position = Vec3::from_vec4(particle.position_size);
+    let mut velocity = Vec3::from_vec4(particle.velocity_age);
+    let gravity = Vec3::from_vec4(params.gravity_drag);
+    let turbulence =
+        curl_noise(position * params.noise_time.y + Vec3::new(0.0, params.noise_time.z * 0.1, 0.0))
+            * params.noise_time.x;
+
+    velocity += (gravity + turbulence) * delta_time;
+    velocity *= 1.0 / (1.0 + params.gravity_drag.w * delta_time);
+
+    let position = position + velocity * delta_time;
+    let age_ratio = age / lifetime;
+
+    particle.position_size = Vec4::new(position.x, position.y, position.z, params.size(age_ratio));
+    particle.color = params.color(age_ratio);
+    particle.velocity_age = Vec4::new(velocity.x, velocity.y, velocity.z, age);
+    Some(particle)
+}
+
+/// Spawns the `index`th particle of the frame.
+pub fn spawn_particle(index: u32, params: &ParticleParams) -> Particle {
+    let seed = params.counts[3];
+    let [speed, spread, lifetime, radius] = [
+        params.emission.x,
+        params.emission.y,
+        params.emission.z,
+        params.emission.w,
+    ];
+
+    // Uniform in the cone around +Y.
+    let cos_theta = 1.0 - random(seed, index, 0) * (1.0 - spread.cos());
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = random(seed, index, 1) * std::f32::consts::TAU;
+    let direction = Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+
+    // Uniform in the sphere of the radius.
+    let offset = Vec3::new(
+        random(seed, index, 2) * 2.0 - 1.0,
+        random(seed, index, 3) * 2.0 - 1.0,
+        random(seed, index, 4) * 2.0 - 1.0,
+    );
+    let offset = if 1.0 < offset.len_square() {
+        offset.normalized() * random(seed, index, 5).cbrt()
+    } else {
+        offset
+    } * radius;
+
+    let row = |index: usize| Vec3::from_vec4(params.emitter[index]);
+    let position = row(0) * offset.x + row(1) * offset.y + row(2) * offset.z + row(3);
+    let direction = row(0) * direction.x + row(1) * direction.y + row(2) * direction.z;
+    let direction = if direction.len_square() == 0.0 {
+        Vec3::ZERO
+    } else {
+        direction.normalized()
+    };
+
+    let speed = speed * (1.0 - params.variance.x * random(seed, index, 6));
+    let lifetime = lifetime * (1.0 - params.variance.y * random(seed, index, 7));
+    let velocity = direction * speed;
+
+    Particle {
+        position_size: Vec4::new(position.x, position.y, position.z, params.size(0.0)),
+        color: params.color(0.0),
+        velocity_age: Vec4::new(velocity.x, velocity.y, velocity.z, 0.0),
+        lifetime: Vec4::new(lifetime, 0.0, 0.0, 0.0),
+    }
+}
+
+fn curve_position(age_ratio: f32) -> (usize, f32) {
+    let position = age_ratio.clamp(0.0, 1.0) * (PARTICLE_CURVE_SAMPLES - 1) as f32;
+    let index = (position.floor() as usize).min(PARTICLE_CURVE_SAMPLES - 2);
+    (index, position - index as f32)
+}
+
+fn pcg_hash(value: u32) -> u32 {
+    let state = value.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+/// Random number in `[0, 1)` of the given particle of the frame and channel.
+pub fn random(seed: u32, index: u32, channel: u32) -> f32 {
+    let hash = pcg_hash(seed ^ pcg_hash(index ^ pcg_hash(channel)));
+    (hash >> 8) as f32 / 16777216.0
+}
+
+/// Gradient of a value noise in `[0, 1)` built from the hash of the lattice points.
+fn value_noise_gradient(position: Vec3, channel: u32) -> Vec3 {
+    let cell = Vec3::floor(position);
+    let f = position - cell;
+    let u = f * f * (Vec3::new(3.0, 3.0, 3.0) - f * 2.0);
+    let du = f * (Vec3::new(1.0, 1.0, 1.0) - f) * 6.0;
+    let (x, y, z) = (
+        cell.x as i32 as u32,
+        cell.y as i32 as u32,
+        cell.z as i32 as u32,
+    );
+    let lattice = |dx: u32, dy: u32, dz: u32| {
+        let hash = pcg_hash(
+            x.wrapping_add(dx)
+                ^ pcg_hash(y.wrapping_add(dy) ^ pcg_hash(z.wrapping_add(dz) ^ channel)),
+        );
+        (hash >> 8) as f32 / 16777216.0
+    };
+
+    let a = lattice(0, 0, 0);
+    let b = lattice(1, 0, 0);
+    let c = lattice(0, 1, 0);
+    let d = lattice(1, 1, 0);
+    let e = lattice(0, 0, 1);
+    let f = lattice(1, 0, 1);
+    let g = lattice(0, 1, 1);
+    let h = lattice(1, 1, 1);
+
+    let k1 = b - a;
+    let k2 = c - a;
+    let k3 = e - a;
+    let k4 = a - b - c + d;
+    let k5 = a - c - e + g;
+    let k6 = a - b - e + f;
+    let k7 = -a + b + c - d + e - f - g + h;
+
+    Vec3::new(
+        du.x * (k1 + k4 * u.y + k6 * u.z + k7 * u.y * u.z),
+        du.y * (k2 + k5 * u.z + k4 * u.x + k7 * u.z * u.x),
+        du.z * (k3 + k6 * u.x + k5 * u.y + k7 * u.x * u.y),
+    )
+}
+
+/// Curl of a vector potential made of three value noises. Being a curl, the field is divergence-free,
+/// which makes the particles swirl around instead of bunching up.
+pub fn curl_noise(position: Vec3) -> Vec3 {
+    let x = value_noise_gradient(position, 0);
+    let y = value_noise_gradient(position, 1);
+    let z = value_noise_gradient(position, 2);
+
+    Vec3::new(z.y - y.z, x.z - z.x, y.x - x.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(emitter: &ParticleEmitter, delta_time: f32, spawn_count: u32) -> ParticleParams {
+        ParticleParams::new(
+            emitter,
+            &BakedParticleCurves::bake(emitter),
+            &Mat4::identity(),
+            0.0,
+            delta_time,
+            spawn_count,
+            emitter.max_particles,
+            0,
+            1,
+        )
+    }
+
+    #[test]
+    fn check_emission_carries_remainder() {
+        let mut counter = EmissionCounter::new();
+        let total = (0..60)
+            .map(|_| counter.advance(100.0, 1.0 / 60.0))
+            .sum::<u32>();
+
+        assert!((99..=100).contains(&total));
+        assert_eq!(counter.advance(0.0, 1.0), 0);
+    }
+
+    #[test]
+    fn check_particles_expire_and_respect_capacity() {
+        let emitter = ParticleEmitter {
+            max_particles: 10,
+            lifetime: 1.0,
+            ..Default::default()
+        };
+        let mut particles = Vec::new();
+
+        simulate_particles(&mut particles, &params(&emitter, 0.1, 25));
+        assert_eq!(particles.len(), 10);
+
+        for _ in 0..9 {
+            simulate_particles(&mut particles, &params(&emitter, 0.1, 0));
+        }
+        assert_eq!(particles.len(), 10);
+
+        simulate_particles(&mut particles, &params(&emitter, 0.1, 0));
+        assert!(particles.is_empty());
+    }
+
+    #[test]
+    fn check_spawned_particles_follow_emitter() {
+        let emitter = ParticleEmitter {
+            speed: 2.0,
+            spread: 0.5,
+            radius: 0.25,
+            size: AnimationCurve::linear(1.0, 0.0, 1.0),
+            ..Default::default()
+        };
+        let mut params = params(&emitter, 0.0, 0);
+        params.emitter[3] = Vec4::new(5.0, 0.0, 0.0, 1.0);
+
+        for index in 0..100 {
+            let particle = spawn_particle(index, &params);
+            let position = Vec3::from_vec4(particle.position_size);
+            let velocity = Vec3::from_vec4(particle.velocity_age);
+
+            assert!(Vec3::distance(position, Vec3::new(5.0, 0.0, 0.0)) <= 0.25 + 1e-5);
+            assert!((velocity.len() - 2.0).abs() < 1e-4);
+            assert!(Vec3::angle(Vec3::UP, velocity) <= 0.5 + 1e-3);
+            assert_eq!(particle.position_size.w, 1.0);
+        }
+
+        let particle = spawn_particle(0, &params);
+        let half = update_particle(
+            particle,
+            &ParticleParams {
+                noise_time: Vec4::new(0.0, 1.0, 0.0, 1.0),
+                gravity_drag: Vec4::ZERO,
+                ..params
+            },
+        )
+        .unwrap();
+        assert!((half.position_size.w - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn check_curl_noise_is_divergence_free() {
+        let epsilon = 1e-3;
+
+        for index in 0..20 {
+            let position = Vec3::new(
+                random(7, index, 0) * 10.0,
+                random(7, index, 1) * 10.0,
+                random(7, index, 2) * 10.0,
+            );
+            let derivative = |axis: Vec3| {
+                (curl_noise(position + axis * epsilon) - curl_noise(position - axis * epsilon))
+                    / (2.0 * epsilon)
+            };
+            let divergence = derivative(Vec3::new(1.0, 0.0, 0.0)).x
+                + derivative(Vec3::new(0.0, 1.0, 0.0)).y
+                + derivative(Vec3::new(0.0, 0.0, 1.0)).z;
+
+            assert!(divergence.abs() < 1e-2, "divergence {}", divergence);
+        }
+    }
+}
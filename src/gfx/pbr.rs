@@ -0,0 +1,647 @@
+use super::{
+    BindGroupEntryResource, BindingPropKey, Color, Material, PipelineLayoutCache, RenderQueue,
+    ShaderHandle, Texture, TextureHandle,
+};
+use crate::math::Vec3;
+use std::sync::Arc;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    AddressMode, BlendState, Buffer, BufferUsages, Device, Extent3d, FilterMode, Sampler,
+    SamplerDescriptor, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    TextureView, TextureViewDescriptor, TextureViewDimension,
+};
+use zerocopy::AsBytes;
+
+/// Names of the material bindings of the built-in standard PBR shader.
+/// See `built_in_shaders/standard_pbr_surface.wgsl` for the expected layout.
+pub const PBR_MATERIAL_UNIFORM_NAME: &str = "pbr_material";
+pub const PBR_BASE_COLOR_TEXTURE_NAME: &str = "base_color_texture";
+pub const PBR_METALLIC_ROUGHNESS_TEXTURE_NAME: &str = "metallic_roughness_texture";
+pub const PBR_NORMAL_TEXTURE_NAME: &str = "normal_texture";
+pub const PBR_OCCLUSION_TEXTURE_NAME: &str = "occlusion_texture";
+pub const PBR_EMISSIVE_TEXTURE_NAME: &str = "emissive_texture";
+pub const PBR_SAMPLER_NAME: &str = "pbr_sampler";
+pub const PBR_LIGHTING_UNIFORM_NAME: &str = "pbr_lighting";
+pub const PBR_ENVIRONMENT_TEXTURE_NAME: &str = "environment_texture";
+pub const PBR_ENVIRONMENT_SAMPLER_NAME: &str = "environment_sampler";
+
+/// Bits of the texture flags telling the shader which texture slots to sample.
+pub const PBR_TEXTURE_BASE_COLOR: u32 = 1 << 0;
+pub const PBR_TEXTURE_METALLIC_ROUGHNESS: u32 = 1 << 1;
+pub const PBR_TEXTURE_NORMAL: u32 = 1 << 2;
+pub const PBR_TEXTURE_OCCLUSION: u32 = 1 << 3;
+pub const PBR_TEXTURE_EMISSIVE: u32 = 1 << 4;
+
+/// How the alpha of the base color is interpreted, as in glTF.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PbrAlphaMode {
+    Opaque,
+    /// Fragments whose alpha is below the cutoff are discarded.
+    Mask {
+        cutoff: f32,
+    },
+    Blend,
+}
+
+impl PbrAlphaMode {
+    /// Blend state of the color target of the render pipeline.
+    pub fn blend_state(self) -> Option<BlendState> {
+        match self {
+            PbrAlphaMode::Opaque | PbrAlphaMode::Mask { .. } => None,
+            PbrAlphaMode::Blend => Some(BlendState::ALPHA_BLENDING),
+        }
+    }
+
+    /// Blended surfaces are drawn after the opaque ones and must not occlude each other.
+    pub fn depth_write_enabled(self) -> bool {
+        !matches!(self, PbrAlphaMode::Blend)
+    }
+
+    /// Masked surfaces write depth like opaque ones, so only blended ones are sorted back to front.
+    pub fn render_queue(self) -> RenderQueue {
+        match self {
+            PbrAlphaMode::Opaque | PbrAlphaMode::Mask { .. } => RenderQueue::Opaque,
+            PbrAlphaMode::Blend => RenderQueue::Transparent,
+        }
+    }
+
+    fn as_index(self) -> u32 {
+        match self {
+            PbrAlphaMode::Opaque => 0,
+            PbrAlphaMode::Mask { .. } => 1,
+            PbrAlphaMode::Blend => 2,
+        }
+    }
+
+    fn cutoff(self) -> f32 {
+        match self {
+            PbrAlphaMode::Mask { cutoff } => cutoff,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Metallic-roughness material properties, with the glTF defaults.
+/// The metallic-roughness texture follows glTF: G is roughness and B is metallic.
+#[derive(Clone)]
+pub struct PbrMaterial {
+    pub base_color_factor: Color,
+    pub base_color_texture: Option<TextureHandle>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub metallic_roughness_texture: Option<TextureHandle>,
+    pub normal_texture: Option<TextureHandle>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<TextureHandle>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    pub emissive_texture: Option<TextureHandle>,
+    pub alpha_mode: PbrAlphaMode,
+}
+
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        Self {
+            base_color_factor: Color::white(),
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: [0.0, 0.0, 0.0],
+            emissive_texture: None,
+            alpha_mode: PbrAlphaMode::Opaque,
+        }
+    }
+}
+
+impl PbrMaterial {
+    pub fn texture_flags(&self) -> u32 {
+        [
+            (&self.base_color_texture, PBR_TEXTURE_BASE_COLOR),
+            (
+                &self.metallic_roughness_texture,
+                PBR_TEXTURE_METALLIC_ROUGHNESS,
+            ),
+            (&self.normal_texture, PBR_TEXTURE_NORMAL),
+            (&self.occlusion_texture, PBR_TEXTURE_OCCLUSION),
+            (&self.emissive_texture, PBR_TEXTURE_EMISSIVE),
+        ]
+        .into_iter()
+        .filter(|(texture, _)| texture.is_some())
+        .fold(0, |flags, (_, flag)| flags | flag)
+    }
+
+    fn uniform(&self) -> PbrMaterialUniform {
+        let color = self.base_color_factor;
+        let [emissive_r, emissive_g, emissive_b] = self.emissive_factor;
+        PbrMaterialUniform {
+            base_color: [color.r, color.g, color.b, color.a],
+            emissive: [emissive_r, emissive_g, emissive_b, self.occlusion_strength],
+            params: [
+                self.metallic_factor,
+                self.roughness_factor,
+                self.normal_scale,
+                self.alpha_mode.cutoff(),
+            ],
+            flags: [self.texture_flags(), self.alpha_mode.as_index(), 0, 0],
+        }
+    }
+
+    /// Binds the properties to a material of the built-in standard PBR shader.
+    /// The slots without a texture are bound to `fallback`, which is sampled but left unused.
+    /// The sampler of the base color texture is used for every texture, or the one of `fallback` without it.
+    /// The material is moved to the render queue of the alpha mode.
+    pub fn bind(&self, material: &mut Material, fallback: &Texture, device: &Device) {
+        material.render_queue = self.alpha_mode.render_queue();
+        material.set_bind_property(
+            &BindingPropKey::StringKey(PBR_MATERIAL_UNIFORM_NAME.to_owned()),
+            BindGroupEntryResource::Buffer {
+                buffer: create_uniform_buffer(device, self.uniform().as_bytes()),
+                offset: 0,
+                size: None,
+            },
+        );
+
+        for (name, texture) in [
+            (PBR_BASE_COLOR_TEXTURE_NAME, &self.base_color_texture),
+            (
+                PBR_METALLIC_ROUGHNESS_TEXTURE_NAME,
+                &self.metallic_roughness_texture,
+            ),
+            (PBR_NORMAL_TEXTURE_NAME, &self.normal_texture),
+            (PBR_OCCLUSION_TEXTURE_NAME, &self.occlusion_texture),
+            (PBR_EMISSIVE_TEXTURE_NAME, &self.emissive_texture),
+        ] {
+            material.set_bind_property(
+                &BindingPropKey::StringKey(name.to_owned()),
+                BindGroupEntryResource::TextureView {
+                    texture_view: match texture {
+                        Some(texture) => texture.view.clone(),
+                        None => fallback.view.clone(),
+                    },
+                },
+            );
+        }
+
+        material.set_bind_property(
+            &BindingPropKey::StringKey(PBR_SAMPLER_NAME.to_owned()),
+            BindGroupEntryResource::Sampler {
+                sampler: match &self.base_color_texture {
+                    Some(texture) => texture.sampler.clone(),
+                    None => fallback.sampler.clone(),
+                },
+            },
+        );
+    }
+
+    /// Creates a material of `shader`, the built-in standard PBR shader or its skinned variant, with the properties
+    /// and the lighting bound.
+    pub fn create_material(
+        &self,
+        shader: ShaderHandle,
+        lighting: &PbrLighting,
+        fallbacks: &PbrFallbacks,
+        device: &Device,
+        pipeline_layout_cache: &mut PipelineLayoutCache,
+    ) -> Material {
+        let mut material = Material::new(shader, pipeline_layout_cache);
+        self.bind(&mut material, &fallbacks.texture, device);
+        lighting.bind(&mut material, &fallbacks.environment, device);
+        material.update_bind_group(device);
+        material
+    }
+}
+
+/// Placeholders bound to the slots of the standard PBR materials that have nothing to sample.
+#[derive(Clone)]
+pub struct PbrFallbacks {
+    pub texture: TextureHandle,
+    pub environment: PbrEnvironment,
+}
+
+impl PbrFallbacks {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            texture: TextureHandle::new(Texture::create_empty(
+                1,
+                1,
+                TextureFormat::Rgba8Unorm,
+                device,
+            )),
+            environment: PbrEnvironment::fallback(device),
+        }
+    }
+}
+
+/// Prefiltered environment cubemap used for image-based lighting. Rougher surfaces sample higher mips.
+#[derive(Clone)]
+pub struct PbrEnvironment {
+    pub view: Arc<TextureView>,
+    pub sampler: Arc<Sampler>,
+    pub mip_level_count: u32,
+}
+
+impl PbrEnvironment {
+    /// A black 1x1 cubemap, bound when there is no environment so that the bind group is complete.
+    pub fn fallback(device: &Device) -> Self {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("fallback environment texture"),
+            size: Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING,
+            view_formats: &[TextureFormat::Rgba8Unorm],
+        });
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: None,
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 32.0,
+            compare: None,
+            anisotropy_clamp: 1,
+            border_color: None,
+        });
+
+        Self {
+            view: view.into(),
+            sampler: sampler.into(),
+            mip_level_count: 1,
+        }
+    }
+}
+
+/// A directional light and the ambient term shading the standard PBR materials. The view direction is taken from
+/// the camera drawing them.
+/// Without an environment, the constant ambient color is used for both diffuse and specular ambient light.
+#[derive(Clone)]
+pub struct PbrLighting {
+    /// Direction the light travels in.
+    pub light_direction: Vec3,
+    pub light_color: Color,
+    pub light_intensity: f32,
+    pub ambient_color: Color,
+    pub environment: Option<PbrEnvironment>,
+}
+
+impl Default for PbrLighting {
+    /// A white light from above and in front, over a dim ambient term; the lighting of the materials given to the
+    /// models by [`spawn_model`](crate::object::spawn_model).
+    fn default() -> Self {
+        Self {
+            light_direction: Vec3::new(-0.4, -1.0, -0.6).normalized(),
+            light_color: Color::white(),
+            light_intensity: 3.0,
+            ambient_color: Color::from_rgb(0.2, 0.2, 0.2),
+            environment: None,
+        }
+    }
+}
+
+impl PbrLighting {
+    fn uniform(&self) -> PbrLightingUniform {
+        let light = self.light_color;
+        let intensity = self.light_intensity;
+        let ambient = self.ambient_color;
+        let (is_available, max_mip) = match &self.environment {
+            Some(environment) => (1.0, environment.mip_level_count.saturating_sub(1) as f32),
+            None => (0.0, 0.0),
+        };
+        PbrLightingUniform {
+            light_direction: [
+                self.light_direction.x,
+                self.light_direction.y,
+                self.light_direction.z,
+                0.0,
+            ],
+            light_color: [
+                light.r * intensity,
+                light.g * intensity,
+                light.b * intensity,
+                1.0,
+            ],
+            ambient_color: [ambient.r, ambient.g, ambient.b, 1.0],
+            params: [is_available, max_mip, 0.0, 0.0],
+        }
+    }
+
+    /// Binds the lighting to a material of the built-in standard PBR shader.
+    /// `fallback` is bound in place of a missing environment; see [`PbrEnvironment::fallback`].
+    pub fn bind(&self, material: &mut Material, fallback: &PbrEnvironment, device: &Device) {
+        let environment = self.environment.as_ref().unwrap_or(fallback);
+
+        material.set_bind_property(
+            &BindingPropKey::StringKey(PBR_LIGHTING_UNIFORM_NAME.to_owned()),
+            BindGroupEntryResource::Buffer {
+                buffer: create_uniform_buffer(device, self.uniform().as_bytes()),
+                offset: 0,
+                size: None,
+            },
+        );
+        material.set_bind_property(
+            &BindingPropKey::StringKey(PBR_ENVIRONMENT_TEXTURE_NAME.to_owned()),
+            BindGroupEntryResource::TextureView {
+                texture_view: environment.view.clone(),
+            },
+        );
+        material.set_bind_property(
+            &BindingPropKey::StringKey(PBR_ENVIRONMENT_SAMPLER_NAME.to_owned()),
+            BindGroupEntryResource::Sampler {
+                sampler: environment.sampler.clone(),
+            },
+        );
+    }
+}
+
+/// Layout of the `pbr_material` uniform.
+#[repr(C)]
+#[derive(AsBytes, Debug, Clone, PartialEq)]
+struct PbrMaterialUniform {
+    base_color: [f32; 4],
+    /// `[emissive r, g, b, occlusion strength]`.
+    emissive: [f32; 4],
+    /// `[metallic, roughness, normal scale, alpha cutoff]`.
+    params: [f32; 4],
+    /// `[texture flags, alpha mode, 0, 0]`.
+    flags: [u32; 4],
+}
+
+/// Layout of the `pbr_lighting` uniform.
+#[repr(C)]
+#[derive(AsBytes, Debug, Clone, PartialEq)]
+struct PbrLightingUniform {
+    light_direction: [f32; 4],
+    light_color: [f32; 4],
+    ambient_color: [f32; 4],
+    /// `[is environment available, highest environment mip, 0, 0]`.
+    params: [f32; 4],
+}
+
+fn create_uniform_buffer(device: &Device, contents: &[u8]) -> Arc<Buffer> {
+    Arc::new(device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("pbr buffer"),
+        contents,
+        usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC | BufferUsages::UNIFORM,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gfx::{
+            create_test_gfx, Camera, CameraClearMode, CameraPerspectiveProjectionAspect,
+            CameraProjection, DepthStencilMode, HeadlessGfx, MaterialHandle, MeshRenderer,
+            BUILT_IN_SHADER_STANDARD_PBR,
+        },
+        math::{Mat4, Vec2},
+    };
+    use image::RgbaImage;
+    use std::{f32::consts::PI, mem::size_of};
+
+    const GOLDEN_SIZE: u32 = 128;
+    const GOLDEN_SPHERE_GRID: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/gfx/golden/standard_pbr_sphere_grid.png"
+    );
+
+    /// Triangles of a unit sphere as `[position, normal, uv]`, facing outwards.
+    fn sphere_vertices(rings: usize, segments: usize) -> Vec<[f32; 8]> {
+        let vertex = |ring: usize, segment: usize| {
+            let (u, v) = (segment as f32 / segments as f32, ring as f32 / rings as f32);
+            let (theta, phi) = (u * 2.0 * PI, v * PI);
+            let normal = Vec3::new(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin());
+            [
+                normal.x, normal.y, normal.z, normal.x, normal.y, normal.z, u, v,
+            ]
+        };
+        let mut vertices = Vec::with_capacity(rings * segments * 6);
+
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let corners = [
+                    vertex(ring, segment),
+                    vertex(ring + 1, segment),
+                    vertex(ring + 1, segment + 1),
+                    vertex(ring, segment + 1),
+                ];
+
+                for [a, b, c] in [[0, 1, 2], [0, 2, 3]].map(|triangle| triangle.map(|i| corners[i]))
+                {
+                    let position = |v: [f32; 8]| Vec3::new(v[0], v[1], v[2]);
+                    let normal = Vec3::cross(position(b) - position(a), position(c) - position(a));
+
+                    // Triangles collapsed at the poles have no area to draw.
+                    if normal.len() < 1e-6 {
+                        continue;
+                    }
+
+                    if Vec3::dot(normal, position(a)) < 0.0 {
+                        vertices.extend([a, c, b]);
+                    } else {
+                        vertices.extend([a, b, c]);
+                    }
+                }
+            }
+        }
+
+        vertices
+    }
+
+    /// Renders a grid of spheres, metallic increasing downwards and roughness to the right.
+    fn render_sphere_grid(gfx: &mut HeadlessGfx) -> RgbaImage {
+        const GRID: usize = 4;
+
+        let gfx_ctx = gfx.gfx_ctx().clone();
+        let device = &gfx_ctx.device;
+        let (render_mgr, shader_mgr, built_in_shader_mgr) = gfx.split_mut();
+        let shader = built_in_shader_mgr
+            .find_shader(BUILT_IN_SHADER_STANDARD_PBR)
+            .unwrap();
+        let fallbacks = PbrFallbacks::new(device);
+        let lighting = PbrLighting {
+            light_direction: Vec3::new(-0.5, -0.6, -1.0).normalized(),
+            ..Default::default()
+        };
+
+        // The camera looks down the Z axis at the grid, which spans 4 units.
+        let projection = CameraProjection::perspective(
+            30f32.to_radians(),
+            CameraPerspectiveProjectionAspect::Screen,
+            0.1,
+            100.0,
+        );
+        let camera = Camera::new(
+            u32::MAX,
+            0,
+            CameraClearMode::All {
+                color: Color::black(),
+                depth: 1.0,
+                stencil: 0,
+            },
+            projection.clone(),
+            device,
+            render_mgr.bind_group_layout_cache(),
+        );
+        let view_projection = Mat4::translation(Vec3::new(0.0, 0.0, 8.5)).inversed()
+            * projection.provider().projection_matrix(
+                Vec2::new(GOLDEN_SIZE as f32, GOLDEN_SIZE as f32),
+                projection.near(),
+                projection.far(),
+            );
+        gfx_ctx
+            .queue
+            .write_buffer(&camera.buffer, 0, view_projection.as_bytes());
+
+        let vertices = sphere_vertices(24, 48);
+        let mut spheres = Vec::with_capacity(GRID * GRID);
+
+        for row in 0..GRID {
+            for column in 0..GRID {
+                let properties = PbrMaterial {
+                    base_color_factor: Color::from_rgb(1.0, 0.6, 0.3),
+                    metallic_factor: row as f32 / (GRID - 1) as f32,
+                    roughness_factor: (column as f32 + 0.5) / GRID as f32,
+                    ..Default::default()
+                };
+                let material = properties.create_material(
+                    shader.clone(),
+                    &lighting,
+                    &fallbacks,
+                    device,
+                    render_mgr.pipeline_layout_cache(),
+                );
+                let mut mesh_renderer = MeshRenderer::new();
+                mesh_renderer.set_material(MaterialHandle::new(material));
+                mesh_renderer.set_dynamic_vertices(&vertices, device, render_mgr.uploader_mut());
+
+                let offset = (GRID - 1) as f32 * 0.5;
+                let matrix = Mat4::scale(Vec3::new(0.45, 0.45, 0.45))
+                    * Mat4::translation(Vec3::new(
+                        column as f32 - offset,
+                        offset - row as f32,
+                        0.0,
+                    ));
+                spheres.push((mesh_renderer, matrix));
+            }
+        }
+
+        let sub_renderers = Vec::from_iter(spheres.iter_mut().map(|(mesh_renderer, matrix)| {
+            let sub_renderer = mesh_renderer
+                .sub_renderer(shader_mgr, render_mgr.pipeline_cache())
+                .unwrap();
+            (sub_renderer, matrix.clone())
+        }));
+        let lights_bind_group = render_mgr.lights().bind_group().clone();
+
+        gfx.render_frame(|render_mgr, encoder, scene_view| {
+            let commands =
+                Vec::from_iter(sub_renderers.iter().filter_map(|(sub_renderer, matrix)| {
+                    render_mgr.build_detached_rendering_command(matrix, sub_renderer)
+                }));
+            let mut render_pass = render_mgr
+                .begin_frame_buffer_render_pass(encoder, scene_view, &camera.clear_mode, None)
+                .unwrap();
+
+            for command in &commands {
+                // The shader reads no screen size; any bind group fills the slot.
+                command.render_in_pass(
+                    &mut render_pass,
+                    &camera.bind_group,
+                    &camera.bind_group,
+                    &lights_bind_group,
+                );
+            }
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn check_sphere_grid_matches_reference() {
+        let mut gfx = match create_test_gfx(GOLDEN_SIZE, GOLDEN_SIZE, DepthStencilMode::DepthOnly) {
+            Some(gfx) => gfx,
+            None => return,
+        };
+        let image = render_sphere_grid(&mut gfx);
+
+        // Regenerate with `ENTER_UPDATE_GOLDEN=1` after an intended change of the shading, and check the image by eye.
+        if std::env::var_os("ENTER_UPDATE_GOLDEN").is_some() {
+            image.save(GOLDEN_SPHERE_GRID).unwrap();
+            return;
+        }
+
+        let reference = image::open(GOLDEN_SPHERE_GRID).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), reference.dimensions());
+
+        // Rasterizers may differ along the silhouettes and in the last bits of the shading, not anywhere else.
+        let differences = Vec::from_iter(image.pixels().zip(reference.pixels()).map(
+            |(pixel, reference)| {
+                (0..3)
+                    .map(|channel| pixel[channel].abs_diff(reference[channel]))
+                    .max()
+                    .unwrap()
+            },
+        ));
+        let mean = differences.iter().map(|&d| d as f32).sum::<f32>() / differences.len() as f32;
+        let outliers = differences.iter().filter(|&&d| 24 < d).count();
+        assert!(mean < 1.5, "mean difference {} to the reference", mean);
+        assert!(
+            outliers <= differences.len() / 100,
+            "{} pixels differ from the reference",
+            outliers
+        );
+    }
+
+    #[test]
+    fn check_pbr_uniform_layout() {
+        // Must match the WGSL structs, which are made of 16-byte vectors only.
+        assert_eq!(size_of::<PbrMaterialUniform>(), 64);
+        assert_eq!(size_of::<PbrLightingUniform>(), 64);
+
+        let uniform = PbrMaterial {
+            emissive_factor: [0.5, 0.25, 0.0],
+            occlusion_strength: 0.8,
+            alpha_mode: PbrAlphaMode::Mask { cutoff: 0.3 },
+            ..Default::default()
+        }
+        .uniform();
+        assert_eq!(uniform.base_color, [1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(uniform.emissive, [0.5, 0.25, 0.0, 0.8]);
+        assert_eq!(uniform.params, [1.0, 1.0, 1.0, 0.3]);
+        assert_eq!(uniform.flags, [0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn check_pbr_alpha_modes() {
+        assert!(PbrAlphaMode::Opaque.blend_state().is_none());
+        assert!(PbrAlphaMode::Mask { cutoff: 0.5 }.depth_write_enabled());
+        assert_eq!(
+            PbrAlphaMode::Blend.blend_state(),
+            Some(BlendState::ALPHA_BLENDING)
+        );
+        assert!(!PbrAlphaMode::Blend.depth_write_enabled());
+        assert_eq!(
+            PbrAlphaMode::Mask { cutoff: 0.5 }.render_queue(),
+            RenderQueue::Opaque
+        );
+        assert_eq!(PbrAlphaMode::Blend.render_queue(), RenderQueue::Transparent);
+    }
+}
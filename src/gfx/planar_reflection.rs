@@ -0,0 +1,498 @@
+use super::{
+    create_camera_buffer, oblique_near_plane, tighten_near_plane, BindGroupEntryResource,
+    BindGroupLayoutCache, BindingPropKey, GfxContextHandle, MaterialHandle, Texture,
+};
+use crate::{
+    math::{Frustum, Mat4, Vec3, Vec4},
+    object::ObjectId,
+};
+use specs::{prelude::*, Component};
+use std::{collections::HashMap, mem::size_of, sync::Arc};
+use wgpu::{BindGroup, Buffer, BufferAddress, BufferDescriptor, BufferUsages, TextureFormat};
+use zerocopy::AsBytes;
+
+/// Maximum number of surfaces rendering a reflection at the same time, unless changed.
+pub const DEFAULT_PLANAR_REFLECTION_BUDGET: usize = 2;
+
+/// Names of the material bindings the reflection is exposed through.
+/// See `built_in_shaders/planar_reflection.wgsl` for the expected layout.
+pub const PLANAR_REFLECTION_UNIFORM_NAME: &str = "reflection";
+pub const PLANAR_REFLECTION_TEXTURE_NAME: &str = "reflection_texture";
+pub const PLANAR_REFLECTION_SAMPLER_NAME: &str = "reflection_sampler";
+
+/// Makes the object a reflective surface, mirroring the scene about its local XZ plane (facing +Y).
+/// The object must have a [`MeshRenderer`](super::MeshRenderer) whose material samples the reflection,
+/// e.g. one using the built-in planar reflection shader.
+#[derive(Debug, Clone, Component)]
+#[storage(HashMapStorage)]
+pub struct PlanarReflection {
+    /// Objects are reflected only if their mask overlaps both this and the main camera's mask.
+    pub mask: u32,
+    /// Size of the reflection texture relative to the screen.
+    pub resolution_scale: f32,
+    /// The reflection is rendered once every `update_interval` frames.
+    pub update_interval: u32,
+    /// Raises the clip plane along the normal, hiding seams where geometry intersects the surface.
+    pub clip_offset: f32,
+    /// Strength of the normal based distortion applied when sampling the reflection.
+    pub distortion: f32,
+}
+
+impl PlanarReflection {
+    pub fn new() -> Self {
+        Self {
+            mask: 0xFFFF_FFFF,
+            resolution_scale: 0.5,
+            update_interval: 1,
+            clip_offset: 0.05,
+            distortion: 0.02,
+        }
+    }
+
+    /// Size of the reflection texture for the given screen size, never smaller than a pixel.
+    pub fn texture_size(&self, screen_width: u32, screen_height: u32) -> (u16, u16) {
+        let scale = self.resolution_scale.clamp(0.0, 1.0);
+        (
+            ((screen_width as f32 * scale) as u32).clamp(1, u16::MAX as u32) as u16,
+            ((screen_height as f32 * scale) as u32).clamp(1, u16::MAX as u32) as u16,
+        )
+    }
+}
+
+impl Default for PlanarReflection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// World space plane of a surface with the given transform, packed as `(normal, distance)`.
+pub fn surface_plane(transform_matrix: &Mat4, clip_offset: f32) -> Vec4 {
+    let normal = Vec3::from(transform_matrix.row(1)).normalized();
+    let position = Vec3::from(transform_matrix.row(3)) + normal * clip_offset;
+    Vec4::new(normal.x, normal.y, normal.z, -Vec3::dot(normal, position))
+}
+
+/// Mirrors positions about the plane: `mirrored = position * reflection_matrix(plane)`.
+pub fn reflection_matrix(plane: Vec4) -> Mat4 {
+    let Vec4 { x, y, z, w } = plane;
+
+    Mat4::new([
+        1.0 - 2.0 * x * x,
+        -2.0 * x * y,
+        -2.0 * x * z,
+        0.0, //
+        -2.0 * x * y,
+        1.0 - 2.0 * y * y,
+        -2.0 * y * z,
+        0.0, //
+        -2.0 * x * z,
+        -2.0 * y * z,
+        1.0 - 2.0 * z * z,
+        0.0, //
+        -2.0 * w * x,
+        -2.0 * w * y,
+        -2.0 * w * z,
+        1.0, //
+    ])
+}
+
+/// View-projection matrix of the camera mirrored about the plane. The near plane is replaced by the
+/// surface so nothing below it ends up in the reflection, and the X axis is flipped to restore the
+/// triangle winding inverted by the mirroring. Sampling with the same matrix undoes the flip.
+pub fn mirrored_view_projection(camera_transform: &Mat4, projection: &Mat4, plane: Vec4) -> Mat4 {
+    // The mirrored view is `reflection * camera_transform⁻¹`; the reflection is its own inverse.
+    let inversed_view = camera_transform * reflection_matrix(plane);
+    let view_plane = &inversed_view * plane;
+    let projection = oblique_near_plane(projection, view_plane);
+    inversed_view.inversed() * projection * Mat4::scale(Vec3::new(-1.0, 1.0, 1.0))
+}
+
+/// Culling frustum of a mirrored view-projection matrix, clipped at the surface.
+pub fn mirrored_frustum(view_projection: &Mat4) -> Frustum {
+    let mut frustum = Frustum::from_view_projection(view_projection);
+    tighten_near_plane(&mut frustum, view_projection);
+    frustum
+}
+
+/// Returns the indices of the `budget` nearest candidates. Ties keep their original order.
+pub fn select_nearest(distances: &[f32], budget: usize) -> Vec<usize> {
+    let mut indices = (0..distances.len()).collect::<Vec<_>>();
+    indices.sort_by(|&lhs, &rhs| distances[lhs].total_cmp(&distances[rhs]));
+    indices.truncate(budget);
+    indices
+}
+
+/// Whether a reflection updated every `interval` frames is due. `stagger` spreads surfaces sharing the
+/// same interval over different frames.
+pub fn is_update_due(frame_index: u64, interval: u32, stagger: usize) -> bool {
+    interval <= 1 || (frame_index + stagger as u64) % interval as u64 == 0
+}
+
+/// Layout of the `reflection` uniform read by the surface material.
+#[repr(C)]
+#[derive(AsBytes, Debug, Clone)]
+struct PlanarReflectionUniform {
+    view_projection: Mat4,
+    camera_position: [f32; 4],
+    /// `[distortion, is_available, 0, 0]`.
+    params: [f32; 4],
+}
+
+/// A reflective surface competing for a reflection texture this frame.
+#[derive(Debug, Clone)]
+pub struct PlanarReflectionCandidate {
+    pub object_id: ObjectId,
+    pub distance: f32,
+    pub width: u16,
+    pub height: u16,
+    pub update_interval: u32,
+}
+
+pub struct PlanarReflectionTarget {
+    pub object_id: Option<ObjectId>,
+    pub color: Texture,
+    /// `None` if the pipelines are built without a depth stencil attachment.
+    pub depth: Option<Texture>,
+    pub camera_buffer: Arc<Buffer>,
+    pub camera_bind_group: Arc<BindGroup>,
+    pub uniform_buffer: Arc<Buffer>,
+    generation: u64,
+    is_rendered: bool,
+}
+
+impl PlanarReflectionTarget {
+    /// Uploads the matrices for this frame's render and for the surface material.
+    pub fn update(
+        &self,
+        gfx_ctx: &GfxContextHandle,
+        view_projection: &Mat4,
+        camera_position: Vec3,
+        distortion: f32,
+    ) {
+        gfx_ctx
+            .queue
+            .write_buffer(&self.camera_buffer, 0, view_projection.as_bytes());
+        gfx_ctx.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            PlanarReflectionUniform {
+                view_projection: view_projection.clone(),
+                camera_position: [camera_position.x, camera_position.y, camera_position.z, 1.0],
+                params: [distortion, 1.0, 0.0, 0.0],
+            }
+            .as_bytes(),
+        );
+    }
+}
+
+/// Owns the reflection textures shared by all reflective surfaces. Only the nearest surfaces within the
+/// budget get a texture; the others are told to fall back to their environment map.
+pub struct PlanarReflectionPool {
+    gfx_ctx: GfxContextHandle,
+    budget: usize,
+    frame_index: u64,
+    next_generation: u64,
+    color_format: TextureFormat,
+    depth_format: Option<TextureFormat>,
+    targets: Vec<PlanarReflectionTarget>,
+    fallback_texture: Texture,
+    fallback_uniform_buffer: Arc<Buffer>,
+    bound_targets: HashMap<ObjectId, Option<(usize, u64)>>,
+}
+
+impl PlanarReflectionPool {
+    pub fn new(gfx_ctx: GfxContextHandle, depth_format: Option<TextureFormat>) -> Self {
+        // Reflections are rendered with the same pipelines as the screen, hence the same color format.
+        let color_format = gfx_ctx.surface_config.read().format;
+        let fallback_texture = Texture::create_empty(1, 1, color_format, &gfx_ctx.device);
+        let fallback_uniform_buffer = create_uniform_buffer(&gfx_ctx);
+
+        Self {
+            gfx_ctx,
+            budget: DEFAULT_PLANAR_REFLECTION_BUDGET,
+            frame_index: 0,
+            next_generation: 0,
+            color_format,
+            depth_format,
+            targets: Vec::new(),
+            fallback_texture,
+            fallback_uniform_buffer,
+            bound_targets: HashMap::new(),
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Sets how many surfaces may render a reflection in the same frame. Zero disables reflections.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.targets.truncate(budget);
+        self.bound_targets.clear();
+    }
+
+    /// Follows the color format the cameras draw in, dropping the targets.
+    pub fn set_color_format(&mut self, color_format: TextureFormat) {
+        if color_format == self.color_format {
+            return;
+        }
+
+        self.color_format = color_format;
+        self.targets.clear();
+        self.bound_targets.clear();
+    }
+
+    /// Follows the depth stencil attachment the pipelines are built against, dropping the targets.
+    pub fn set_depth_format(&mut self, depth_format: Option<TextureFormat>) {
+        self.depth_format = depth_format;
+        self.targets.clear();
+        self.bound_targets.clear();
+    }
+
+    pub fn targets(&self) -> &[PlanarReflectionTarget] {
+        &self.targets
+    }
+
+    /// Keeps the camera position of the fallback up to date, so that environment map lookups stay correct.
+    pub fn update_fallback(&self, camera_position: Vec3) {
+        self.gfx_ctx.queue.write_buffer(
+            &self.fallback_uniform_buffer,
+            0,
+            PlanarReflectionUniform {
+                view_projection: Mat4::identity(),
+                camera_position: [camera_position.x, camera_position.y, camera_position.z, 1.0],
+                params: [0.0; 4],
+            }
+            .as_bytes(),
+        );
+    }
+
+    /// Distributes the textures among the nearest candidates, recreating them on size changes.
+    /// Returns `(candidate index, target index)` pairs for the reflections to render this frame.
+    pub fn assign(
+        &mut self,
+        candidates: &[PlanarReflectionCandidate],
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) -> Vec<(usize, usize)> {
+        self.frame_index += 1;
+
+        let distances = Vec::from_iter(candidates.iter().map(|candidate| candidate.distance));
+        let selected = select_nearest(&distances, self.budget);
+
+        // Release the targets of surfaces that fell out of the budget.
+        for target in &mut self.targets {
+            if let Some(object_id) = target.object_id {
+                if !selected
+                    .iter()
+                    .any(|&index| candidates[index].object_id == object_id)
+                {
+                    target.object_id = None;
+                }
+            }
+        }
+
+        let mut due = Vec::with_capacity(selected.len());
+
+        for candidate_index in selected {
+            let candidate = &candidates[candidate_index];
+            let target_index = match self
+                .targets
+                .iter()
+                .position(|target| target.object_id == Some(candidate.object_id))
+            {
+                Some(index) => index,
+                None => {
+                    let index = match self
+                        .targets
+                        .iter()
+                        .position(|target| target.object_id.is_none())
+                    {
+                        Some(index) => index,
+                        None => {
+                            let target = self.create_target(
+                                candidate.width,
+                                candidate.height,
+                                bind_group_layout_cache,
+                            );
+                            self.targets.push(target);
+                            self.targets.len() - 1
+                        }
+                    };
+                    // The previous content belongs to another surface.
+                    self.targets[index].object_id = Some(candidate.object_id);
+                    self.targets[index].is_rendered = false;
+                    index
+                }
+            };
+
+            let target = &self.targets[target_index];
+
+            if target.color.width != candidate.width || target.color.height != candidate.height {
+                let mut target =
+                    self.create_target(candidate.width, candidate.height, bind_group_layout_cache);
+                target.object_id = Some(candidate.object_id);
+                self.targets[target_index] = target;
+            }
+
+            let target = &mut self.targets[target_index];
+
+            if !target.is_rendered
+                || is_update_due(self.frame_index, candidate.update_interval, target_index)
+            {
+                target.is_rendered = true;
+                due.push((candidate_index, target_index));
+            }
+        }
+
+        self.bound_targets.retain(|object_id, _| {
+            candidates
+                .iter()
+                .any(|candidate| candidate.object_id == *object_id)
+        });
+
+        due
+    }
+
+    /// Points the surface material to its reflection texture, or to the fallback if it has none.
+    /// Materials are only touched when the assignment changes.
+    pub fn bind_material(&mut self, object_id: ObjectId, material: &MaterialHandle) {
+        let target_index = self
+            .targets
+            .iter()
+            .position(|target| target.object_id == Some(object_id));
+        let binding = target_index.map(|index| (index, self.targets[index].generation));
+
+        if self.bound_targets.get(&object_id) == Some(&binding) {
+            return;
+        }
+
+        let (texture, uniform_buffer) = match target_index {
+            Some(index) => (
+                &self.targets[index].color,
+                &self.targets[index].uniform_buffer,
+            ),
+            None => (&self.fallback_texture, &self.fallback_uniform_buffer),
+        };
+
+        let mut material = material.write();
+        material.set_bind_property(
+            &BindingPropKey::StringKey(PLANAR_REFLECTION_UNIFORM_NAME.to_owned()),
+            BindGroupEntryResource::Buffer {
+                buffer: uniform_buffer.clone(),
+                offset: 0,
+                size: None,
+            },
+        );
+        material.set_bind_property(
+            &BindingPropKey::StringKey(PLANAR_REFLECTION_TEXTURE_NAME.to_owned()),
+            BindGroupEntryResource::TextureView {
+                texture_view: texture.view.clone(),
+            },
+        );
+        material.set_bind_property(
+            &BindingPropKey::StringKey(PLANAR_REFLECTION_SAMPLER_NAME.to_owned()),
+            BindGroupEntryResource::Sampler {
+                sampler: texture.sampler.clone(),
+            },
+        );
+        material.update_bind_group(&self.gfx_ctx.device);
+
+        self.bound_targets.insert(object_id, binding);
+    }
+
+    fn create_target(
+        &mut self,
+        width: u16,
+        height: u16,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) -> PlanarReflectionTarget {
+        let device = &self.gfx_ctx.device;
+        let (camera_buffer, camera_bind_group) =
+            create_camera_buffer(device, bind_group_layout_cache);
+        self.next_generation += 1;
+
+        PlanarReflectionTarget {
+            object_id: None,
+            color: Texture::create_render_target(width, height, self.color_format, device),
+            // Reflections are rendered with the same pipelines as the screen, hence the same depth format.
+            depth: self
+                .depth_format
+                .map(|format| Texture::create_render_target(width, height, format, device)),
+            camera_buffer,
+            camera_bind_group,
+            uniform_buffer: create_uniform_buffer(&self.gfx_ctx),
+            generation: self.next_generation,
+            is_rendered: false,
+        }
+    }
+}
+
+fn create_uniform_buffer(gfx_ctx: &GfxContextHandle) -> Arc<Buffer> {
+    Arc::new(gfx_ctx.device.create_buffer(&BufferDescriptor {
+        label: Some("planar reflection buffer"),
+        size: size_of::<PlanarReflectionUniform>() as BufferAddress,
+        usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC | BufferUsages::UNIFORM,
+        mapped_at_creation: false,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(matrix: &Mat4, point: Vec3) -> Vec3 {
+        let point = Vec4::new(point.x, point.y, point.z, 1.0) * matrix;
+        Vec3::new(point.x / point.w, point.y / point.w, point.z / point.w)
+    }
+
+    #[test]
+    fn check_reflection_matrix() {
+        let plane = surface_plane(&Mat4::translation(Vec3::new(0.0, 2.0, 0.0)), 0.0);
+        let matrix = reflection_matrix(plane);
+
+        let mirrored = transform(&matrix, Vec3::new(1.0, 5.0, -3.0));
+        assert!((mirrored - Vec3::new(1.0, -1.0, -3.0)).len() < 1e-5);
+
+        // Mirroring twice is the identity.
+        let twice = transform(&(matrix.clone() * &matrix), Vec3::new(1.0, 5.0, -3.0));
+        assert!((twice - Vec3::new(1.0, 5.0, -3.0)).len() < 1e-5);
+    }
+
+    #[test]
+    fn check_mirrored_view_projection() {
+        // Camera 3 units above a water plane at y = 0, looking down -Z.
+        let camera_transform = Mat4::translation(Vec3::new(0.0, 3.0, 0.0));
+        let projection = Mat4::perspective(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let plane = surface_plane(&Mat4::identity(), 0.0);
+        let view_projection = mirrored_view_projection(&camera_transform, &projection, plane);
+
+        // Points on the surface land on the near plane, points above it are visible.
+        assert!(
+            transform(&view_projection, Vec3::new(0.0, 0.0, -10.0))
+                .z
+                .abs()
+                < 1e-4
+        );
+        let above = transform(&view_projection, Vec3::new(2.0, 1.0, -10.0));
+        assert!(0.0 < above.z && above.z <= 1.0);
+        // The X axis is flipped.
+        assert!(above.x < 0.0);
+
+        let frustum = mirrored_frustum(&view_projection);
+        assert!(frustum.contains_point(Vec3::new(0.0, 1.0, -10.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, -1.0, -10.0)));
+    }
+
+    #[test]
+    fn check_budget_selection() {
+        assert_eq!(select_nearest(&[5.0, 1.0, 3.0, 1.0], 2), vec![1, 3]);
+        assert_eq!(select_nearest(&[5.0, 1.0], 4), vec![1, 0]);
+        assert!(select_nearest(&[5.0, 1.0], 0).is_empty());
+
+        assert!(is_update_due(7, 1, 3));
+        assert!(is_update_due(6, 3, 0));
+        assert!(!is_update_due(6, 3, 1));
+        assert!(is_update_due(6, 3, 3));
+    }
+}
@@ -0,0 +1,776 @@
+use super::{Color, ColorSpaceMode, GfxContextHandle, SurfaceConversion, SurfaceOutput, Texture};
+use std::{any::Any, borrow::Cow, mem::size_of, sync::Arc};
+use wgpu::{
+    AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferAddress,
+    BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, ColorTargetState, ColorWrites,
+    CommandEncoder, Device, FilterMode, FragmentState, LoadOp, MultisampleState, Operations,
+    PipelineLayoutDescriptor, PrimitiveState, Queue, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, TextureFormat,
+    TextureSampleType, TextureView, TextureViewDimension, VertexState,
+};
+use zerocopy::AsBytes;
+
+/// Format the scene is drawn in while the post-processing stack has an enabled effect, and the one of the targets
+/// the effects read and write. Colors above one survive until the tonemapping.
+pub const POST_PROCESS_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// Vertex stage shared by the fullscreen passes. It declares `input_texture` and `input_sampler` at the bindings 0
+/// and 1 of the group 0, and the `VertexOutput` the `fs_main` of the pass receives.
+pub const POST_PROCESS_VERTEX_SHADER: &str =
+    include_str!("./built_in_shaders/post_process_vertex.wgsl");
+
+/// What an effect may use while encoding its pass.
+pub struct PostEffectContext<'a> {
+    pub device: &'a Device,
+    pub queue: &'a Queue,
+    pub width: u32,
+    pub height: u32,
+    /// Space the scene is in, for the colors the effect blends into it.
+    pub color_space: ColorSpaceMode,
+}
+
+/// Fullscreen pass of the post-processing stack, see [`PostEffectStack`].
+pub trait PostEffect: Any {
+    fn name(&self) -> &str;
+
+    /// Disabled effects are skipped, and the stack skips itself once none is enabled, unless the scene must be
+    /// converted on its way to the surface.
+    fn is_enabled(&self) -> bool {
+        true
+    }
+
+    /// Called before the first frame of the effect and whenever the size of the scene changes.
+    fn resize(&mut self, _device: &Device, _width: u32, _height: u32) {}
+
+    /// Reads `input` and overwrites all of `output`, both of the size of the scene in [`POST_PROCESS_FORMAT`].
+    fn encode(
+        &mut self,
+        ctx: &PostEffectContext,
+        encoder: &mut CommandEncoder,
+        input: &TextureView,
+        output: &TextureView,
+    );
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// Options of [`FullscreenPass::with_options`] beyond a single input and a uniform.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FullscreenPassOptions {
+    /// Size of the uniform buffer bound at the binding 2 of the group 0, if any.
+    pub uniform_size: Option<u64>,
+    /// Textures bound after the input at the bindings 3 and up, sampled with `input_sampler`.
+    pub extra_textures: u32,
+    /// Blends the output over the target instead of overwriting it.
+    pub blend: Option<BlendState>,
+}
+
+/// Draws a triangle covering the target with the given fragment stage, sampling the input texture. Most effects are
+/// one of these and a uniform.
+pub struct FullscreenPass {
+    label: String,
+    bind_group_layout: BindGroupLayout,
+    pipeline: RenderPipeline,
+    sampler: Sampler,
+    uniform_buffer: Option<Buffer>,
+    extra_textures: u32,
+    blend: Option<BlendState>,
+}
+
+impl FullscreenPass {
+    /// `fragment_source` is WGSL with an `fs_main` entry point, appended to [`POST_PROCESS_VERTEX_SHADER`].
+    /// With a `uniform_size`, a uniform buffer of that size is bound at the binding 2 of the group 0.
+    pub fn new(
+        device: &Device,
+        label: &str,
+        fragment_source: &str,
+        uniform_size: Option<u64>,
+        format: TextureFormat,
+    ) -> Self {
+        Self::with_options(
+            device,
+            label,
+            fragment_source,
+            format,
+            FullscreenPassOptions {
+                uniform_size,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn with_options(
+        device: &Device,
+        label: &str,
+        fragment_source: &str,
+        format: TextureFormat,
+        options: FullscreenPassOptions,
+    ) -> Self {
+        let FullscreenPassOptions {
+            uniform_size,
+            extra_textures,
+            blend,
+        } = options;
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some(label),
+            source: ShaderSource::Wgsl(Cow::Owned(format!(
+                "{}\n{}",
+                POST_PROCESS_VERTEX_SHADER, fragment_source
+            ))),
+        });
+        let mut entries = vec![
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            },
+        ];
+
+        if let Some(uniform_size) = uniform_size {
+            entries.push(BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: BufferSize::new(uniform_size),
+                },
+                count: None,
+            });
+        }
+
+        let input_entry = entries[0];
+        entries.extend((0..extra_textures).map(|index| BindGroupLayoutEntry {
+            binding: 3 + index,
+            ..input_entry
+        }));
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &entries,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_buffer = uniform_size.map(|size| {
+            device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: size as BufferAddress,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        });
+
+        Self {
+            label: label.to_owned(),
+            bind_group_layout,
+            pipeline,
+            sampler,
+            uniform_buffer,
+            extra_textures,
+            blend,
+        }
+    }
+
+    /// Does nothing if the pass has no uniform.
+    pub fn write_uniform(&self, queue: &Queue, data: &[u8]) {
+        if let Some(uniform_buffer) = &self.uniform_buffer {
+            queue.write_buffer(uniform_buffer, 0, data);
+        }
+    }
+
+    pub fn encode(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        self.encode_with_textures(device, encoder, input, &[], output);
+    }
+
+    /// Binds `extra_textures` after the input, as many as the pass has been created with.
+    pub fn encode_with_textures(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        input: &TextureView,
+        extra_textures: &[&TextureView],
+        output: &TextureView,
+    ) {
+        debug_assert_eq!(extra_textures.len(), self.extra_textures as usize);
+
+        let mut entries = vec![
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(input),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(&self.sampler),
+            },
+        ];
+
+        if let Some(uniform_buffer) = &self.uniform_buffer {
+            entries.push(BindGroupEntry {
+                binding: 2,
+                resource: uniform_buffer.as_entire_binding(),
+            });
+        }
+
+        entries.extend(
+            extra_textures
+                .iter()
+                .enumerate()
+                .map(|(index, texture)| BindGroupEntry {
+                    binding: 3 + index as u32,
+                    resource: BindingResource::TextureView(texture),
+                }),
+        );
+
+        // The input changes with the order of the enabled effects, so the bind group is made per frame.
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some(self.label.as_str()),
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        });
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(self.label.as_str()),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: Operations {
+                    // Blended passes add to what is already there.
+                    load: match self.blend {
+                        Some(_) => LoadOp::Load,
+                        None => LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    },
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TonemapOperator {
+    /// `c / (1 + c)`: never clips, but flattens the highlights.
+    Reinhard,
+    /// Fit of the ACES filmic curve: more contrast, with highlights rolling off to white.
+    Aces,
+}
+
+/// Maps the colors of the HDR scene into the displayable range with the operator, after scaling them by the
+/// exposure. Mirrors the built-in tonemap shader.
+pub fn tonemap(operator: TonemapOperator, color: [f32; 3], exposure: f32) -> [f32; 3] {
+    color.map(|channel| {
+        let c = (channel * exposure).max(0.0);
+
+        match operator {
+            TonemapOperator::Reinhard => c / (1.0 + c),
+            TonemapOperator::Aces => {
+                ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+        }
+    })
+}
+
+/// Built-in effect mapping the HDR scene into the displayable range, see [`tonemap`].
+pub struct Tonemap {
+    pub operator: TonemapOperator,
+    pub exposure: f32,
+    pass: FullscreenPass,
+}
+
+impl Tonemap {
+    pub const NAME: &'static str = "tonemap";
+    /// Suggested order: after the effects working on HDR colors, before the ones expecting displayable ones.
+    pub const ORDER: i32 = 1000;
+
+    pub fn new(device: &Device, operator: TonemapOperator) -> Self {
+        Self {
+            operator,
+            exposure: 1.0,
+            pass: FullscreenPass::new(
+                device,
+                "tonemap pass",
+                include_str!("./built_in_shaders/post_tonemap.wgsl"),
+                Some(size_of::<[f32; 4]>() as u64),
+                POST_PROCESS_FORMAT,
+            ),
+        }
+    }
+}
+
+impl PostEffect for Tonemap {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn encode(
+        &mut self,
+        ctx: &PostEffectContext,
+        encoder: &mut CommandEncoder,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        let operator = match self.operator {
+            TonemapOperator::Reinhard => 0.0,
+            TonemapOperator::Aces => 1.0,
+        };
+        self.pass
+            .write_uniform(ctx.queue, [operator, self.exposure, 0.0, 0.0].as_bytes());
+        self.pass.encode(ctx.device, encoder, input, output);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Built-in fast approximate anti-aliasing, blurring along the edges found in the luma of the image. Goes after the
+/// tonemapping, as the edge thresholds are meant for displayable colors.
+pub struct Fxaa {
+    /// Contrast below which nothing is an edge, which spares the dark areas.
+    pub contrast_threshold: f32,
+    /// Contrast, relative to the brightest neighbour, below which nothing is an edge.
+    pub relative_threshold: f32,
+    /// How much the blur spreads across edges thinner than a pixel.
+    pub subpixel_blending: f32,
+    pass: FullscreenPass,
+}
+
+impl Fxaa {
+    pub const NAME: &'static str = "fxaa";
+    pub const ORDER: i32 = 2000;
+
+    pub fn new(device: &Device) -> Self {
+        Self {
+            contrast_threshold: 0.0312,
+            relative_threshold: 0.125,
+            subpixel_blending: 0.125,
+            pass: FullscreenPass::new(
+                device,
+                "fxaa pass",
+                include_str!("./built_in_shaders/post_fxaa.wgsl"),
+                Some(size_of::<[f32; 4]>() as u64),
+                POST_PROCESS_FORMAT,
+            ),
+        }
+    }
+}
+
+impl PostEffect for Fxaa {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn encode(
+        &mut self,
+        ctx: &PostEffectContext,
+        encoder: &mut CommandEncoder,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        self.pass.write_uniform(
+            ctx.queue,
+            [
+                self.contrast_threshold,
+                self.relative_threshold,
+                self.subpixel_blending,
+                0.0,
+            ]
+            .as_bytes(),
+        );
+        self.pass.encode(ctx.device, encoder, input, output);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Built-in effect darkening the corners of the screen towards a color.
+pub struct Vignette {
+    /// Opacity of the color at the corners.
+    pub intensity: f32,
+    /// Part of the distance from the corners to the center the vignette fades over, in `(0, 1]`.
+    pub smoothness: f32,
+    pub color: Color,
+    pass: FullscreenPass,
+}
+
+impl Vignette {
+    pub const NAME: &'static str = "vignette";
+    pub const ORDER: i32 = 3000;
+
+    pub fn new(device: &Device) -> Self {
+        Self {
+            intensity: 0.3,
+            smoothness: 0.5,
+            color: Color::black(),
+            pass: FullscreenPass::new(
+                device,
+                "vignette pass",
+                include_str!("./built_in_shaders/post_vignette.wgsl"),
+                Some(size_of::<[f32; 8]>() as u64),
+                POST_PROCESS_FORMAT,
+            ),
+        }
+    }
+}
+
+impl PostEffect for Vignette {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn encode(
+        &mut self,
+        ctx: &PostEffectContext,
+        encoder: &mut CommandEncoder,
+        input: &TextureView,
+        output: &TextureView,
+    ) {
+        let color = ctx.color_space.shader_color(self.color);
+        self.pass.write_uniform(
+            ctx.queue,
+            [
+                color.r,
+                color.g,
+                color.b,
+                color.a,
+                self.intensity.clamp(0.0, 1.0),
+                self.smoothness.clamp(0.01, 1.0),
+                0.0,
+                0.0,
+            ]
+            .as_bytes(),
+        );
+        self.pass.encode(ctx.device, encoder, input, output);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Index to insert an effect of the given order at, after the effects of the same order.
+fn insertion_index(orders: impl Iterator<Item = i32>, order: i32) -> usize {
+    orders.take_while(|&other| other <= order).count()
+}
+
+struct PostEffectEntry {
+    order: i32,
+    effect: Box<dyn PostEffect>,
+    /// Size the effect has last been resized to.
+    size: Option<(u32, u32)>,
+}
+
+struct PostProcessTargets {
+    width: u32,
+    height: u32,
+    scene: Texture,
+    ping: Texture,
+    pong: Texture,
+}
+
+/// Effects applied to the scene, in ascending order, before it reaches the surface.
+///
+/// While any effect is enabled, the cameras drawing into the surface draw into an intermediate target in
+/// [`POST_PROCESS_FORMAT`] instead. Each effect reads the output of the previous one, and the last output is copied
+/// into the surface, converted into its color space if it has no view of the one of the scene, and scaled to its size
+/// under a render scale. Without enabled effects, such a conversion or a render scale, the cameras draw into the
+/// surface directly and the targets are dropped.
+pub struct PostEffectStack {
+    gfx_ctx: GfxContextHandle,
+    entries: Vec<PostEffectEntry>,
+    targets: Option<PostProcessTargets>,
+    color_space: ColorSpaceMode,
+    output: SurfaceOutput,
+    is_scaled: bool,
+    blit: FullscreenPass,
+}
+
+impl PostEffectStack {
+    pub fn new(gfx_ctx: GfxContextHandle) -> Self {
+        let output = SurfaceOutput {
+            format: gfx_ctx.surface_format(),
+            conversion: SurfaceConversion::None,
+        };
+        let blit = create_blit(&gfx_ctx, output);
+
+        Self {
+            gfx_ctx,
+            entries: Vec::new(),
+            targets: None,
+            color_space: ColorSpaceMode::default(),
+            output,
+            is_scaled: false,
+            blit,
+        }
+    }
+
+    /// Adds the effect, applied after the effects of a lower or the same order. The built-in effects suggest theirs,
+    /// e.g. [`Tonemap::ORDER`].
+    pub fn add(&mut self, order: i32, effect: Box<dyn PostEffect>) {
+        let index = insertion_index(self.entries.iter().map(|entry| entry.order), order);
+        self.entries.insert(
+            index,
+            PostEffectEntry {
+                order,
+                effect,
+                size: None,
+            },
+        );
+    }
+
+    /// Removes the first effect of the given name.
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn PostEffect>> {
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.effect.name() == name)?;
+        Some(self.entries.remove(index).effect)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut dyn PostEffect> {
+        for entry in &mut self.entries {
+            if entry.effect.name() == name {
+                return Some(entry.effect.as_mut());
+            }
+        }
+
+        None
+    }
+
+    /// The first effect of the given type, e.g. to tune the built-in ones.
+    pub fn effect_mut<T: PostEffect>(&mut self) -> Option<&mut T> {
+        self.entries
+            .iter_mut()
+            .find_map(|entry| entry.effect.as_any_mut().downcast_mut::<T>())
+    }
+
+    /// Names and orders of the effects, in the order they are applied.
+    pub fn effects(&self) -> impl Iterator<Item = (&str, i32)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.effect.name(), entry.order))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns `true` if any effect is enabled or the scene must be converted or scaled on its way to the surface,
+    /// i.e. the scene goes through the stack.
+    pub fn is_active(&self) -> bool {
+        self.output.conversion != SurfaceConversion::None
+            || self.is_scaled
+            || self.entries.iter().any(|entry| entry.effect.is_enabled())
+    }
+
+    /// Sets the space of the scene and how it reaches the surface, see [`surface_output`](super::surface_output).
+    pub fn set_output(&mut self, color_space: ColorSpaceMode, output: SurfaceOutput) {
+        self.color_space = color_space;
+
+        if output != self.output {
+            self.blit = create_blit(&self.gfx_ctx, output);
+            self.output = output;
+        }
+    }
+
+    /// Keeps the stack active while the scene is drawn at another size than the surface, for the last copy to scale
+    /// it into the surface.
+    pub fn set_scaled(&mut self, is_scaled: bool) {
+        self.is_scaled = is_scaled;
+    }
+
+    /// Makes the targets of the frame ready at the size of the scene, and returns the one the scene is drawn into.
+    /// Returns `None` if the stack is inactive, after dropping the targets.
+    pub fn prepare(&mut self, width: u32, height: u32) -> Option<Arc<TextureView>> {
+        if !self.is_active() {
+            self.targets = None;
+            return None;
+        }
+
+        let width = width.clamp(1, u16::MAX as u32);
+        let height = height.clamp(1, u16::MAX as u32);
+
+        if !matches!(&self.targets, Some(targets) if targets.width == width && targets.height == height)
+        {
+            let device = &self.gfx_ctx.device;
+            let create_target = || {
+                Texture::create_render_target(
+                    width as u16,
+                    height as u16,
+                    POST_PROCESS_FORMAT,
+                    device,
+                )
+            };
+            self.targets = Some(PostProcessTargets {
+                width,
+                height,
+                scene: create_target(),
+                ping: create_target(),
+                pong: create_target(),
+            });
+        }
+
+        for entry in &mut self.entries {
+            if entry.effect.is_enabled() && entry.size != Some((width, height)) {
+                entry.effect.resize(&self.gfx_ctx.device, width, height);
+                entry.size = Some((width, height));
+            }
+        }
+
+        self.targets
+            .as_ref()
+            .map(|targets| targets.scene.view.clone())
+    }
+
+    /// Applies the enabled effects to the scene drawn since [`prepare`](Self::prepare), and copies the result into
+    /// `surface_view`, a view in the format of the output. Does nothing if the stack was inactive then.
+    pub fn encode(&mut self, encoder: &mut CommandEncoder, surface_view: &TextureView) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        let ctx = PostEffectContext {
+            device: &self.gfx_ctx.device,
+            queue: &self.gfx_ctx.queue,
+            width: targets.width,
+            height: targets.height,
+            color_space: self.color_space,
+        };
+        let mut input = &targets.scene.view;
+        let mut outputs = [&targets.ping.view, &targets.pong.view].into_iter().cycle();
+
+        for entry in &mut self.entries {
+            if !entry.effect.is_enabled() {
+                continue;
+            }
+
+            let output = outputs.next().unwrap();
+            entry.effect.encode(&ctx, encoder, input, output);
+            input = output;
+        }
+
+        self.blit
+            .encode(&self.gfx_ctx.device, encoder, input, surface_view);
+    }
+}
+
+/// Copies the last output into the surface, converting it as the output requires.
+fn create_blit(gfx_ctx: &GfxContextHandle, output: SurfaceOutput) -> FullscreenPass {
+    let blit = FullscreenPass::new(
+        &gfx_ctx.device,
+        "post-processing blit",
+        include_str!("./built_in_shaders/post_blit.wgsl"),
+        Some(size_of::<[f32; 4]>() as u64),
+        output.format,
+    );
+    let conversion = match output.conversion {
+        SurfaceConversion::None => 0.0f32,
+        SurfaceConversion::EncodeSrgb => 1.0,
+        SurfaceConversion::DecodeSrgb => 2.0,
+    };
+    blit.write_uniform(&gfx_ctx.queue, [conversion, 0.0, 0.0, 0.0].as_bytes());
+    blit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_effects_of_the_same_order_keep_insertion_order() {
+        let mut orders = Vec::new();
+
+        for order in [Fxaa::ORDER, Tonemap::ORDER, Fxaa::ORDER, 0, Vignette::ORDER] {
+            let index = insertion_index(orders.iter().map(|&(order, _)| order), order);
+            orders.insert(index, (order, orders.len()));
+        }
+
+        assert_eq!(
+            orders,
+            vec![
+                (0, 3),
+                (Tonemap::ORDER, 1),
+                (Fxaa::ORDER, 0),
+                (Fxaa::ORDER, 2),
+                (Vignette::ORDER, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn check_tonemap_operators_stay_in_range() {
+        for operator in [TonemapOperator::Reinhard, TonemapOperator::Aces] {
+            assert_eq!(tonemap(operator, [0.0, -1.0, 0.0], 1.0), [0.0; 3]);
+
+            let mut previous = 0.0;
+
+            for step in 1..100 {
+                let [value, _, _] = tonemap(operator, [step as f32 * 0.25, 0.0, 0.0], 1.0);
+                assert!(previous <= value && value <= 1.0);
+                previous = value;
+            }
+        }
+
+        assert_eq!(tonemap(TonemapOperator::Reinhard, [1.0; 3], 1.0), [0.5; 3]);
+        assert_eq!(
+            tonemap(TonemapOperator::Reinhard, [0.5; 3], 2.0),
+            tonemap(TonemapOperator::Reinhard, [1.0; 3], 1.0)
+        );
+    }
+}
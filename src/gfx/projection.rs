@@ -0,0 +1,209 @@
+use crate::math::{Frustum, Mat4, Vec2, Vec3, Vec4};
+use std::fmt::Debug;
+
+/// Produces the projection matrix of a camera.
+///
+/// Matrices follow the engine conventions: they are applied to row vectors (`clip = view_position * projection`)
+/// and target the wgpu clip space, where `x` and `y` are in `[-1, 1]` (y up) and depth is in `[0, 1]`,
+/// `0` being the near plane. Perspective projections look down the -Z axis of the view space.
+pub trait ProjectionProvider: Debug + Send + Sync {
+    /// Builds the projection matrix for a viewport of the given size in physical pixels.
+    fn projection_matrix(&self, viewport_size: Vec2, near: f32, far: f32) -> Mat4;
+
+    /// Builds the culling frustum from the final view-projection matrix.
+    fn frustum(&self, view_projection: &Mat4) -> Frustum {
+        Frustum::from_view_projection(view_projection)
+    }
+
+    /// Changes whenever the provider's parameters change, so that cached matrices can be reused until then.
+    /// Providers without interior mutability can keep the default.
+    fn generation(&self) -> u64 {
+        0
+    }
+}
+
+pub trait ProjectionExt: ProjectionProvider + Sized {
+    /// Replaces the near plane with the given view space plane, e.g. a water surface for reflections.
+    /// The plane is `(normal, distance)` packed into a [`Vec4`], with the normal pointing into the visible side.
+    fn with_oblique_near_plane(self, plane: Vec4) -> ObliqueProjection<Self> {
+        ObliqueProjection {
+            projection: self,
+            plane,
+        }
+    }
+}
+
+impl<T: ProjectionProvider> ProjectionExt for T {}
+
+/// Perspective projection whose near plane is not centered on the view axis.
+/// Useful for head-tracked displays, tiled rendering and mirrors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OffAxisProjection {
+    /// Extents of the near plane in view space, given for a near distance of `1`.
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+}
+
+impl OffAxisProjection {
+    pub fn new(left: f32, right: f32, bottom: f32, top: f32) -> Self {
+        Self {
+            left,
+            right,
+            bottom,
+            top,
+        }
+    }
+}
+
+impl ProjectionProvider for OffAxisProjection {
+    fn projection_matrix(&self, _viewport_size: Vec2, near: f32, far: f32) -> Mat4 {
+        Mat4::perspective_off_center(
+            self.left * near,
+            self.right * near,
+            self.bottom * near,
+            self.top * near,
+            near,
+            far,
+        )
+    }
+}
+
+/// Projection whose near plane is replaced by an arbitrary view space plane.
+/// The depth range stays `[0, 1]`; the far plane is tilted to keep as much depth precision as possible.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObliqueProjection<P> {
+    pub projection: P,
+    pub plane: Vec4,
+}
+
+impl<P: ProjectionProvider> ProjectionProvider for ObliqueProjection<P> {
+    fn projection_matrix(&self, viewport_size: Vec2, near: f32, far: f32) -> Mat4 {
+        let matrix = self.projection.projection_matrix(viewport_size, near, far);
+        oblique_near_plane(&matrix, self.plane)
+    }
+
+    fn frustum(&self, view_projection: &Mat4) -> Frustum {
+        let mut frustum = self.projection.frustum(view_projection);
+        tighten_near_plane(&mut frustum, view_projection);
+        frustum
+    }
+
+    fn generation(&self) -> u64 {
+        self.projection.generation()
+    }
+}
+
+/// Replaces the near plane of the frustum with the exact `0 <= z` plane of the view-projection matrix.
+/// The generic extraction treats the near plane as `-w <= z`, which is too loose once the plane is tilted.
+pub(crate) fn tighten_near_plane(frustum: &mut Frustum, view_projection: &Mat4) {
+    let near = view_projection.column(2);
+    let len = Vec3::new(near.x, near.y, near.z).len();
+
+    if f32::EPSILON < len {
+        frustum.planes[4] = near / len;
+    }
+}
+
+/// Rewrites the depth column of the projection so that `z = 0` lies on the given view space plane.
+pub fn oblique_near_plane(matrix: &Mat4, plane: Vec4) -> Mat4 {
+    let inverse = matrix.inversed();
+    // Since `clip = view * matrix`, planes are transformed with the inverse applied from the other side.
+    let clip_plane = &inverse * plane;
+    let corner = Vec4::new(clip_plane.x.signum(), clip_plane.y.signum(), 1.0, 1.0) * &inverse;
+    let scale = Vec4::dot(corner, matrix.column(3)) / Vec4::dot(corner, plane);
+    let depth = plane * scale;
+
+    let mut elements = matrix.elements;
+    elements[2] = depth.x;
+    elements[6] = depth.y;
+    elements[10] = depth.z;
+    elements[14] = depth.w;
+    Mat4::new(elements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matrix_eq(lhs: &Mat4, rhs: &Mat4) {
+        for (index, (l, r)) in lhs.elements.iter().zip(rhs.elements.iter()).enumerate() {
+            assert!((l - r).abs() < 1e-4, "element {}: {} != {}", index, l, r);
+        }
+    }
+
+    fn project(matrix: &Mat4, point: Vec3) -> Vec3 {
+        let clip = Vec4::new(point.x, point.y, point.z, 1.0) * matrix;
+        Vec3::new(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w)
+    }
+
+    #[test]
+    fn check_off_axis_projection() {
+        let projection = OffAxisProjection::new(-0.5, 1.5, -1.0, 0.5);
+        let matrix = projection.projection_matrix(Vec2::new(800.0, 600.0), 1.0, 11.0);
+
+        // Computed by hand: 2n/(r-l) = 1, 2n/(t-b) = 4/3, (r+l)/(r-l) = 0.5, (t+b)/(t-b) = -1/3,
+        // f/(n-f) = -1.1 and nf/(n-f) = -1.1.
+        assert_matrix_eq(
+            &matrix,
+            &Mat4::new([
+                1.0,
+                0.0,
+                0.0,
+                0.0, //
+                0.0,
+                4.0 / 3.0,
+                0.0,
+                0.0, //
+                0.5,
+                -1.0 / 3.0,
+                -1.1,
+                -1.0, //
+                0.0,
+                0.0,
+                -1.1,
+                0.0, //
+            ]),
+        );
+
+        let near_corner = project(&matrix, Vec3::new(1.5, 0.5, -1.0));
+        assert!((near_corner.x - 1.0).abs() < 1e-4);
+        assert!((near_corner.y - 1.0).abs() < 1e-4);
+        assert!(near_corner.z.abs() < 1e-4);
+
+        let far_corner = project(&matrix, Vec3::new(-5.5, -11.0, -11.0));
+        assert!((far_corner.x + 1.0).abs() < 1e-4);
+        assert!((far_corner.y + 1.0).abs() < 1e-4);
+        assert!((far_corner.z - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn check_oblique_near_plane() {
+        let projection = OffAxisProjection::new(-1.0, 1.0, -1.0, 1.0);
+        // Keep everything below y = 1, tilted so the plane is not parallel to any axis.
+        let plane = Vec4::new(-0.6, -0.8, 0.0, 0.8);
+        let oblique = projection.with_oblique_near_plane(plane);
+        let matrix = oblique.projection_matrix(Vec2::new(1.0, 1.0), 0.1, 100.0);
+        let original = projection.projection_matrix(Vec2::new(1.0, 1.0), 0.1, 100.0);
+
+        // Only the depth column changes.
+        for column in [0, 1, 3] {
+            assert_eq!(matrix.column(column), original.column(column));
+        }
+
+        // Points on the plane land exactly on the near plane of the depth range.
+        for point in [Vec3::new(0.0, 1.0, -2.0), Vec3::new(-4.0, 4.0, -10.0)] {
+            assert!((Vec4::dot(Vec4::new(point.x, point.y, point.z, 1.0), plane)).abs() < 1e-4);
+            assert!(project(&matrix, point).z.abs() < 1e-4);
+        }
+
+        // Points on the visible side stay inside the depth range.
+        let visible = project(&matrix, Vec3::new(0.0, -1.0, -5.0));
+        assert!(0.0 < visible.z && visible.z <= 1.0);
+
+        let frustum = oblique.frustum(&matrix);
+        assert!(frustum.contains_point(Vec3::new(0.0, -1.0, -5.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 1.5, -5.0)));
+    }
+}
@@ -0,0 +1,622 @@
+use super::GfxContextHandle;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    mem::size_of,
+    time::{Duration, Instant},
+};
+use wgpu::{
+    Backend, BlendState, BufferAddress, BufferDescriptor, BufferUsages, Color, ColorTargetState,
+    ColorWrites, CommandEncoderDescriptor, DeviceType, DownlevelFlags, Extent3d, Features,
+    FragmentState, LoadOp, Maintain, MapMode, MultisampleState, Operations,
+    PipelineLayoutDescriptor, PrimitiveState, QuerySetDescriptor, QueryType,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipelineDescriptor,
+    ShaderModuleDescriptor, ShaderSource, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, VertexState,
+};
+
+/// Coarse class of the rendering power of the machine, from which the default [`QualityPreset`] is chosen.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderTier {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+impl RenderTier {
+    pub const ALL: [RenderTier; 4] = [Self::Low, Self::Medium, Self::High, Self::Ultra];
+
+    /// Upper bounds of the benchmark time, in milliseconds, for the tiers above [`Low`](Self::Low).
+    /// They are calibrated for the workload of [`RenderBenchmark`]; changing one requires changing the other.
+    const BENCHMARK_THRESHOLDS_MS: [(RenderTier, f32); 3] =
+        [(Self::Ultra, 2.0), (Self::High, 6.0), (Self::Medium, 20.0)];
+
+    /// Tier of a machine that renders the benchmark workload in `frame_ms`.
+    pub fn from_benchmark_ms(frame_ms: f32) -> Self {
+        Self::BENCHMARK_THRESHOLDS_MS
+            .iter()
+            .find(|(_, threshold)| frame_ms <= *threshold)
+            .map_or(Self::Low, |(tier, _)| *tier)
+    }
+}
+
+impl Display for RenderTier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+            Self::Ultra => "ultra",
+        })
+    }
+}
+
+/// Bundle of the quality settings chosen together for a [`RenderTier`].
+///
+/// The engine applies the render scale bounds, the particle budget and the fog settings; the other settings are read
+/// by the renderers and passes that support them, through [`RenderManager::quality_preset`](super::RenderManager::quality_preset).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct QualityPreset {
+    /// Size of a side of a shadow map, in texels.
+    pub shadow_resolution: u32,
+    pub shadow_cascades: u32,
+    pub msaa_samples: u32,
+    /// Maximum anisotropy of texture filtering, in `1..=16`.
+    pub anisotropy: u16,
+    pub post_effects: bool,
+    /// Bounds of the render scale the dynamic resolution may choose from.
+    pub min_render_scale: f32,
+    pub max_render_scale: f32,
+    /// Most particles a particle system may have alive.
+    pub particle_budget: u32,
+    /// Froxels of the volumetric fog across the width, the height and the depth of the view.
+    pub fog_grid: [u32; 3],
+    /// Samples of the fog density per froxel and frame.
+    pub fog_samples: u32,
+    /// Weight of the previous frames in the fog, in `0..1`. Higher values hide more noise and smear more
+    /// behind moving volumes; 0 disables the temporal blend.
+    pub fog_temporal_blend: f32,
+}
+
+impl QualityPreset {
+    pub fn for_tier(tier: RenderTier) -> Self {
+        match tier {
+            RenderTier::Low => Self {
+                shadow_resolution: 1024,
+                shadow_cascades: 1,
+                msaa_samples: 1,
+                anisotropy: 1,
+                post_effects: false,
+                min_render_scale: 0.5,
+                max_render_scale: 0.75,
+                particle_budget: 1_000,
+                fog_grid: [80, 45, 32],
+                fog_samples: 1,
+                fog_temporal_blend: 0.95,
+            },
+            RenderTier::Medium => Self {
+                shadow_resolution: 2048,
+                shadow_cascades: 2,
+                msaa_samples: 1,
+                anisotropy: 4,
+                post_effects: true,
+                min_render_scale: 0.67,
+                max_render_scale: 1.0,
+                particle_budget: 10_000,
+                fog_grid: [120, 68, 48],
+                fog_samples: 1,
+                fog_temporal_blend: 0.9,
+            },
+            RenderTier::High => Self {
+                shadow_resolution: 2048,
+                shadow_cascades: 3,
+                msaa_samples: 4,
+                anisotropy: 8,
+                post_effects: true,
+                min_render_scale: 0.75,
+                max_render_scale: 1.0,
+                particle_budget: 50_000,
+                fog_grid: [160, 90, 64],
+                fog_samples: 1,
+                fog_temporal_blend: 0.9,
+            },
+            RenderTier::Ultra => Self {
+                shadow_resolution: 4096,
+                shadow_cascades: 4,
+                msaa_samples: 4,
+                anisotropy: 16,
+                post_effects: true,
+                min_render_scale: 0.85,
+                max_render_scale: 1.0,
+                particle_budget: 200_000,
+                fog_grid: [160, 90, 128],
+                fog_samples: 2,
+                fog_temporal_blend: 0.9,
+            },
+        }
+    }
+
+    /// Clamps the render scale into the bounds of this preset.
+    pub fn clamp_render_scale(&self, render_scale: f32) -> f32 {
+        render_scale.clamp(
+            self.min_render_scale,
+            self.max_render_scale.max(self.min_render_scale),
+        )
+    }
+}
+
+impl Default for QualityPreset {
+    fn default() -> Self {
+        Self::for_tier(RenderTier::Medium)
+    }
+}
+
+/// How the render config chooses the [`QualityPreset`].
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QualitySetting {
+    /// The preset of the detected tier, or of [`RenderTier::Medium`] if detection did not run.
+    #[default]
+    Auto,
+    /// The preset of the given tier, whatever the detected one is.
+    Tier(RenderTier),
+    /// A hand-tuned preset.
+    Custom(QualityPreset),
+}
+
+impl QualitySetting {
+    /// Returns the preset to use and the tier it belongs to; a custom preset has no tier.
+    pub fn resolve(
+        &self,
+        detected_tier: Option<RenderTier>,
+    ) -> (Option<RenderTier>, QualityPreset) {
+        match self {
+            Self::Auto => {
+                let tier = detected_tier.unwrap_or(RenderTier::Medium);
+                (Some(tier), QualityPreset::for_tier(tier))
+            }
+            Self::Tier(tier) => (Some(*tier), QualityPreset::for_tier(*tier)),
+            Self::Custom(preset) => (None, *preset),
+        }
+    }
+}
+
+/// What the adapter reports about itself, as far as the tier detection is concerned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderCapabilities {
+    pub adapter_name: String,
+    pub device_type: DeviceType,
+    pub backend: Backend,
+    pub max_texture_dimension_2d: u32,
+    pub compute_shaders: bool,
+    pub indirect_draw: bool,
+    pub bc_textures: bool,
+    pub timestamp_queries: bool,
+}
+
+impl RenderCapabilities {
+    pub fn from_gfx_ctx(gfx_ctx: &GfxContextHandle) -> Self {
+        let downlevel_flags = gfx_ctx.downlevel_capabilities.flags;
+
+        Self {
+            adapter_name: gfx_ctx.adapter_info.name.clone(),
+            device_type: gfx_ctx.adapter_info.device_type,
+            backend: gfx_ctx.adapter_info.backend,
+            max_texture_dimension_2d: gfx_ctx.device.limits().max_texture_dimension_2d,
+            compute_shaders: downlevel_flags.contains(DownlevelFlags::COMPUTE_SHADERS),
+            indirect_draw: downlevel_flags.contains(DownlevelFlags::INDIRECT_EXECUTION),
+            bc_textures: gfx_ctx
+                .adapter_features
+                .contains(Features::TEXTURE_COMPRESSION_BC),
+            timestamp_queries: gfx_ctx
+                .device
+                .features()
+                .contains(Features::TIMESTAMP_QUERY),
+        }
+    }
+
+    /// Highest tier the machine may reach, however fast it runs the benchmark.
+    /// Software renderers and downlevel devices stay low, mobile-class devices without BC textures
+    /// or large textures stay at medium, and integrated GPUs share their memory bandwidth, so they top out at high.
+    pub fn max_tier(&self) -> RenderTier {
+        if self.device_type == DeviceType::Cpu || !self.compute_shaders || !self.indirect_draw {
+            RenderTier::Low
+        } else if self.max_texture_dimension_2d < 8192 || !self.bc_textures {
+            RenderTier::Medium
+        } else if self.device_type == DeviceType::IntegratedGpu {
+            RenderTier::High
+        } else {
+            RenderTier::Ultra
+        }
+    }
+
+    /// Tier guessed from the device type alone, used when the benchmark did not run.
+    pub fn estimated_tier(&self) -> RenderTier {
+        match self.device_type {
+            DeviceType::DiscreteGpu => RenderTier::High,
+            DeviceType::IntegratedGpu | DeviceType::VirtualGpu | DeviceType::Other => {
+                RenderTier::Medium
+            }
+            DeviceType::Cpu => RenderTier::Low,
+        }
+    }
+}
+
+/// Result of rendering the standardized hidden workload of the tier detection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderBenchmark {
+    /// Median time of an iteration, in milliseconds.
+    pub frame_ms: f32,
+    pub iterations: u32,
+    /// Whether the times were measured with timestamp queries. Otherwise they are wall-clock times
+    /// from the submission to the completion, which are a little pessimistic.
+    pub gpu_timed: bool,
+}
+
+impl RenderBenchmark {
+    /// Size of a side of the hidden target.
+    const TARGET_SIZE: u32 = 1024;
+    /// Full-screen layers drawn per iteration.
+    const LAYERS: u32 = 16;
+    const MAX_ITERATIONS: u32 = 64;
+
+    /// Renders the workload into a hidden target for about `duration`, waiting for the GPU after every iteration.
+    /// Blocks the calling thread, so it belongs in the loading phase.
+    pub fn run(gfx_ctx: &GfxContextHandle, duration: Duration) -> Self {
+        let device = &gfx_ctx.device;
+        let format = TextureFormat::Rgba8Unorm;
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("render benchmark shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "./built_in_shaders/render_benchmark.wgsl"
+            ))),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("render benchmark pipeline layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("render benchmark pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+        let target = device.create_texture(&TextureDescriptor {
+            label: Some("render benchmark target"),
+            size: Extent3d {
+                width: Self::TARGET_SIZE,
+                height: Self::TARGET_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let target_view = target.create_view(&Default::default());
+
+        let timestamps_size = size_of::<[u64; 2]>() as BufferAddress;
+        let timestamps = device
+            .features()
+            .contains(Features::TIMESTAMP_QUERY)
+            .then(|| {
+                let query_set = device.create_query_set(&QuerySetDescriptor {
+                    label: Some("render benchmark query set"),
+                    ty: QueryType::Timestamp,
+                    count: 2,
+                });
+                let resolve_buffer = device.create_buffer(&BufferDescriptor {
+                    label: Some("render benchmark resolve buffer"),
+                    size: timestamps_size,
+                    usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                });
+                let readback_buffer = device.create_buffer(&BufferDescriptor {
+                    label: Some("render benchmark readback buffer"),
+                    size: timestamps_size,
+                    usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                (query_set, resolve_buffer, readback_buffer)
+            });
+        let period_ns = gfx_ctx.queue.get_timestamp_period();
+
+        let start = Instant::now();
+        let mut samples = Vec::new();
+
+        // The first iteration is a warm-up that pays for the pipeline creation in the driver.
+        for iteration in 0..=Self::MAX_ITERATIONS {
+            if 1 < iteration && duration <= start.elapsed() {
+                break;
+            }
+
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("render benchmark encoder"),
+            });
+
+            if let Some((query_set, _, _)) = &timestamps {
+                encoder.write_timestamp(query_set, 0);
+            }
+
+            {
+                let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: Some("render benchmark pass"),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: &target_view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Clear(Color::BLACK),
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+                render_pass.set_pipeline(&pipeline);
+                render_pass.draw(0..3, 0..Self::LAYERS);
+            }
+
+            if let Some((query_set, resolve_buffer, readback_buffer)) = &timestamps {
+                encoder.write_timestamp(query_set, 1);
+                encoder.resolve_query_set(query_set, 0..2, resolve_buffer, 0);
+                encoder.copy_buffer_to_buffer(
+                    resolve_buffer,
+                    0,
+                    readback_buffer,
+                    0,
+                    timestamps_size,
+                );
+            }
+
+            let submitted = Instant::now();
+            gfx_ctx.queue.submit([encoder.finish()]);
+            device.poll(Maintain::Wait);
+            let mut frame_ms = submitted.elapsed().as_secs_f32() * 1000.0;
+
+            if let Some((_, _, readback_buffer)) = &timestamps {
+                let slice = readback_buffer.slice(..);
+                slice.map_async(MapMode::Read, |_| {});
+                device.poll(Maintain::Wait);
+
+                {
+                    let view = slice.get_mapped_range();
+                    let begin = u64::from_le_bytes(view[0..8].try_into().unwrap());
+                    let end = u64::from_le_bytes(view[8..16].try_into().unwrap());
+                    frame_ms = end.saturating_sub(begin) as f32 * period_ns / 1_000_000.0;
+                }
+
+                readback_buffer.unmap();
+            }
+
+            if iteration != 0 {
+                samples.push(frame_ms);
+            }
+        }
+
+        samples.sort_by(f32::total_cmp);
+
+        Self {
+            frame_ms: samples[samples.len() / 2],
+            iterations: samples.len() as u32,
+            gpu_timed: timestamps.is_some(),
+        }
+    }
+}
+
+/// Combines the capabilities and the benchmark into a tier: the benchmark decides, capped by the capabilities.
+pub fn classify_render_tier(
+    capabilities: &RenderCapabilities,
+    benchmark: Option<&RenderBenchmark>,
+) -> RenderTier {
+    let tier = match benchmark {
+        Some(benchmark) => RenderTier::from_benchmark_ms(benchmark.frame_ms),
+        None => capabilities.estimated_tier(),
+    };
+    tier.min(capabilities.max_tier())
+}
+
+/// The detected tier with what it was detected from, for the diagnostics and the crash report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderTierReport {
+    pub tier: RenderTier,
+    pub capabilities: RenderCapabilities,
+    pub benchmark: Option<RenderBenchmark>,
+}
+
+impl RenderTierReport {
+    /// Detects the tier of the device. The benchmark runs for `benchmark_duration` if given;
+    /// otherwise the tier is estimated from the capabilities alone.
+    pub fn detect(gfx_ctx: &GfxContextHandle, benchmark_duration: Option<Duration>) -> Self {
+        let capabilities = RenderCapabilities::from_gfx_ctx(gfx_ctx);
+        let benchmark = benchmark_duration.map(|duration| RenderBenchmark::run(gfx_ctx, duration));
+
+        Self {
+            tier: classify_render_tier(&capabilities, benchmark.as_ref()),
+            capabilities,
+            benchmark,
+        }
+    }
+}
+
+impl Display for RenderTierReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+        let capabilities = &self.capabilities;
+
+        write!(
+            f,
+            "render tier {} on \"{}\" ({:?}, {:?}); compute shaders: {}, indirect draw: {}, BC textures: {}, timestamp queries: {}, max texture size: {}; ",
+            self.tier,
+            capabilities.adapter_name,
+            capabilities.device_type,
+            capabilities.backend,
+            yes_no(capabilities.compute_shaders),
+            yes_no(capabilities.indirect_draw),
+            yes_no(capabilities.bc_textures),
+            yes_no(capabilities.timestamp_queries),
+            capabilities.max_texture_dimension_2d,
+        )?;
+
+        match &self.benchmark {
+            Some(benchmark) => write!(
+                f,
+                "benchmark: {:.2} ms ({}) over {} iterations",
+                benchmark.frame_ms,
+                if benchmark.gpu_timed {
+                    "GPU time"
+                } else {
+                    "wall-clock time"
+                },
+                benchmark.iterations
+            ),
+            None => write!(f, "benchmark: skipped"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(device_type: DeviceType) -> RenderCapabilities {
+        RenderCapabilities {
+            adapter_name: "mock".to_owned(),
+            device_type,
+            backend: Backend::Vulkan,
+            max_texture_dimension_2d: 16384,
+            compute_shaders: true,
+            indirect_draw: true,
+            bc_textures: true,
+            timestamp_queries: true,
+        }
+    }
+
+    fn benchmark(frame_ms: f32) -> RenderBenchmark {
+        RenderBenchmark {
+            frame_ms,
+            iterations: 32,
+            gpu_timed: true,
+        }
+    }
+
+    #[test]
+    fn check_tier_mapping() {
+        let discrete = capabilities(DeviceType::DiscreteGpu);
+        let integrated = capabilities(DeviceType::IntegratedGpu);
+
+        assert_eq!(
+            classify_render_tier(&discrete, Some(&benchmark(1.0))),
+            RenderTier::Ultra
+        );
+        assert_eq!(
+            classify_render_tier(&discrete, Some(&benchmark(4.0))),
+            RenderTier::High
+        );
+        assert_eq!(
+            classify_render_tier(&discrete, Some(&benchmark(50.0))),
+            RenderTier::Low
+        );
+        assert_eq!(classify_render_tier(&discrete, None), RenderTier::High);
+
+        // Integrated GPUs top out at high, however fast they run the benchmark.
+        assert_eq!(
+            classify_render_tier(&integrated, Some(&benchmark(1.0))),
+            RenderTier::High
+        );
+        assert_eq!(classify_render_tier(&integrated, None), RenderTier::Medium);
+
+        let software = capabilities(DeviceType::Cpu);
+        assert_eq!(
+            classify_render_tier(&software, Some(&benchmark(1.0))),
+            RenderTier::Low
+        );
+
+        let downlevel = RenderCapabilities {
+            compute_shaders: false,
+            ..discrete.clone()
+        };
+        assert_eq!(
+            classify_render_tier(&downlevel, Some(&benchmark(1.0))),
+            RenderTier::Low
+        );
+
+        let mobile = RenderCapabilities {
+            bc_textures: false,
+            ..discrete.clone()
+        };
+        assert_eq!(
+            classify_render_tier(&mobile, Some(&benchmark(1.0))),
+            RenderTier::Medium
+        );
+    }
+
+    #[test]
+    fn check_quality_setting_resolution() {
+        assert_eq!(
+            QualitySetting::Auto.resolve(Some(RenderTier::Ultra)),
+            (
+                Some(RenderTier::Ultra),
+                QualityPreset::for_tier(RenderTier::Ultra)
+            )
+        );
+        assert_eq!(
+            QualitySetting::Auto.resolve(None),
+            (
+                Some(RenderTier::Medium),
+                QualityPreset::for_tier(RenderTier::Medium)
+            )
+        );
+        assert_eq!(
+            QualitySetting::Tier(RenderTier::Low).resolve(Some(RenderTier::Ultra)),
+            (
+                Some(RenderTier::Low),
+                QualityPreset::for_tier(RenderTier::Low)
+            )
+        );
+
+        let custom = QualityPreset {
+            particle_budget: 123,
+            ..Default::default()
+        };
+        assert_eq!(
+            QualitySetting::Custom(custom).resolve(Some(RenderTier::Ultra)),
+            (None, custom)
+        );
+
+        // Higher tiers never lower a setting.
+        for pair in RenderTier::ALL.windows(2) {
+            let lower = QualityPreset::for_tier(pair[0]);
+            let higher = QualityPreset::for_tier(pair[1]);
+            assert!(lower.shadow_resolution <= higher.shadow_resolution);
+            assert!(lower.shadow_cascades <= higher.shadow_cascades);
+            assert!(lower.msaa_samples <= higher.msaa_samples);
+            assert!(lower.anisotropy <= higher.anisotropy);
+            assert!(lower.min_render_scale <= higher.min_render_scale);
+            assert!(lower.particle_budget <= higher.particle_budget);
+            assert!((0..3).all(|axis| lower.fog_grid[axis] <= higher.fog_grid[axis]));
+            assert!(lower.fog_samples <= higher.fog_samples);
+        }
+    }
+}
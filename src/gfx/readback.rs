@@ -0,0 +1,752 @@
+use super::{FrameFence, GfxContextHandle};
+use parking_lot::Mutex;
+use std::{
+    mem::replace,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Arc,
+    },
+};
+use thiserror::Error;
+use wgpu::{
+    Buffer, BufferAddress, BufferDescriptor, BufferUsages, CommandEncoder, Extent3d,
+    ImageCopyBuffer, ImageCopyTexture, ImageDataLayout, MapMode, Origin3d, Texture, TextureAspect,
+    TextureFormat, COPY_BUFFER_ALIGNMENT, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+/// Default cap on the memory of the readbacks in flight.
+pub const DEFAULT_MAX_READBACK_BYTES: BufferAddress = 256 * 1024 * 1024;
+
+const READBACK_ENCODED: u8 = 0;
+const READBACK_MAPPING: u8 = 1;
+const READBACK_MAPPED: u8 = 2;
+const READBACK_FAILED: u8 = 3;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ReadbackError {
+    #[error("{requested} bytes requested with {available} bytes of the readback quota left")]
+    QuotaExceeded {
+        requested: BufferAddress,
+        available: BufferAddress,
+    },
+    #[error("the texture format {0:?} cannot be read back")]
+    UnsupportedFormat(TextureFormat),
+    #[error("failed to map the readback buffer")]
+    MapFailed,
+}
+
+/// Pixels of a texture read back, as RGBA8 rows from the top without padding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadbackImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+type ReadbackCallback<T> = Box<dyn FnOnce(Result<T, ReadbackError>) + Send>;
+type ReadbackCompletion = Box<dyn FnOnce(Result<&[u8], ReadbackError>)>;
+
+enum ReadbackSlot<T> {
+    Pending,
+    Ready(Result<T, ReadbackError>),
+    Callback(ReadbackCallback<T>),
+    Taken,
+}
+
+/// Receives the result of a readback once the GPU is done with it.
+/// Dropping the handle cancels the readback, unless a callback has been set by [`on_complete`](Self::on_complete).
+pub struct ReadbackHandle<T> {
+    slot: Arc<Mutex<ReadbackSlot<T>>>,
+    is_canceled: Arc<AtomicBool>,
+    is_detached: bool,
+}
+
+impl<T> ReadbackHandle<T> {
+    /// Takes the result if the readback has completed. It is returned only once.
+    pub fn poll(&mut self) -> Option<Result<T, ReadbackError>> {
+        let mut slot = self.slot.lock();
+
+        match replace(&mut *slot, ReadbackSlot::Taken) {
+            ReadbackSlot::Ready(result) => Some(result),
+            other => {
+                *slot = other;
+                None
+            }
+        }
+    }
+
+    /// Blocks until the readback completes. `wait` is called between polls and must make progress,
+    /// e.g. by polling the device and collecting the readbacks.
+    pub fn block_on(mut self, mut wait: impl FnMut()) -> Result<T, ReadbackError> {
+        loop {
+            if let Some(result) = self.poll() {
+                return result;
+            }
+
+            wait();
+        }
+    }
+
+    /// Cancels the readback. Its buffer is released as soon as the GPU is done with it.
+    pub fn cancel(self) {}
+
+    /// Hands the result to `callback` instead, when the readbacks are collected after the frame is presented.
+    /// Runs it right away if the readback has already completed.
+    pub fn on_complete(mut self, callback: impl FnOnce(Result<T, ReadbackError>) + Send + 'static) {
+        self.is_detached = true;
+
+        let mut slot = self.slot.lock();
+
+        match replace(&mut *slot, ReadbackSlot::Taken) {
+            ReadbackSlot::Ready(result) => {
+                drop(slot);
+                callback(result);
+            }
+            _ => {
+                *slot = ReadbackSlot::Callback(Box::new(callback));
+            }
+        }
+    }
+}
+
+impl<T> Drop for ReadbackHandle<T> {
+    fn drop(&mut self) {
+        if !self.is_detached {
+            self.is_canceled.store(true, Ordering::Release);
+        }
+    }
+}
+
+fn deliver<T>(slot: &Mutex<ReadbackSlot<T>>, result: Result<T, ReadbackError>) {
+    let mut slot = slot.lock();
+
+    match replace(&mut *slot, ReadbackSlot::Taken) {
+        ReadbackSlot::Callback(callback) => {
+            drop(slot);
+            callback(result);
+        }
+        _ => {
+            *slot = ReadbackSlot::Ready(result);
+        }
+    }
+}
+
+/// A buffer the copies are read back from, mapped once the GPU is done with it.
+trait ReadbackBuffer {
+    /// Starts mapping the buffer; `done` is called with whether it succeeded.
+    fn map(&self, done: Box<dyn FnOnce(bool) + Send>);
+    fn read<R>(&self, read: impl FnOnce(&[u8]) -> R) -> R;
+    fn unmap(&self);
+}
+
+impl ReadbackBuffer for Buffer {
+    fn map(&self, done: Box<dyn FnOnce(bool) + Send>) {
+        self.slice(..)
+            .map_async(MapMode::Read, move |result| done(result.is_ok()));
+    }
+
+    fn read<R>(&self, read: impl FnOnce(&[u8]) -> R) -> R {
+        read(&self.slice(..).get_mapped_range())
+    }
+
+    fn unmap(&self) {
+        Buffer::unmap(self);
+    }
+}
+
+struct PendingReadback<B> {
+    buffer: B,
+    size: BufferAddress,
+    fence: Option<FrameFence>,
+    state: Arc<AtomicU8>,
+    is_canceled: Arc<AtomicBool>,
+    complete: ReadbackCompletion,
+}
+
+/// Readbacks in flight, oldest first, and the memory they hold.
+struct ReadbackQueue<B> {
+    pending: Vec<PendingReadback<B>>,
+    bytes_in_flight: BufferAddress,
+    max_bytes_in_flight: BufferAddress,
+}
+
+impl<B> ReadbackQueue<B>
+where
+    B: ReadbackBuffer,
+{
+    fn new(max_bytes_in_flight: BufferAddress) -> Self {
+        Self {
+            pending: Vec::new(),
+            bytes_in_flight: 0,
+            max_bytes_in_flight,
+        }
+    }
+
+    fn reserve(&self, size: BufferAddress) -> Result<(), ReadbackError> {
+        let available = self
+            .max_bytes_in_flight
+            .saturating_sub(self.bytes_in_flight);
+
+        if available < size {
+            return Err(ReadbackError::QuotaExceeded {
+                requested: size,
+                available,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Tracks a buffer whose copy has been encoded. `decode` turns its content into the result.
+    fn push<T>(
+        &mut self,
+        buffer: B,
+        size: BufferAddress,
+        decode: impl FnOnce(&[u8]) -> T + 'static,
+    ) -> ReadbackHandle<T>
+    where
+        T: 'static,
+    {
+        let slot = Arc::new(Mutex::new(ReadbackSlot::Pending));
+        let is_canceled = Arc::new(AtomicBool::new(false));
+        let complete = {
+            let slot = slot.clone();
+            move |data: Result<&[u8], ReadbackError>| deliver(&slot, data.map(decode))
+        };
+
+        self.bytes_in_flight += size;
+        self.pending.push(PendingReadback {
+            buffer,
+            size,
+            fence: None,
+            state: Arc::new(AtomicU8::new(READBACK_ENCODED)),
+            is_canceled: is_canceled.clone(),
+            complete: Box::new(complete),
+        });
+
+        ReadbackHandle {
+            slot,
+            is_canceled,
+            is_detached: false,
+        }
+    }
+
+    /// Ties the readbacks encoded so far to the frame just submitted.
+    fn submitted(&mut self, fence: &FrameFence) {
+        for readback in &mut self.pending {
+            if readback.fence.is_none() {
+                readback.fence = Some(fence.clone());
+            }
+        }
+    }
+
+    /// Maps the buffers of the finished frames and delivers the mapped ones. Never blocks.
+    fn collect(&mut self) {
+        let mut index = 0;
+
+        while index < self.pending.len() {
+            let readback = &self.pending[index];
+            let state = readback.state.load(Ordering::Acquire);
+            let is_canceled = readback.is_canceled.load(Ordering::Acquire);
+
+            let is_done = match state {
+                READBACK_ENCODED if is_canceled => true,
+                READBACK_ENCODED => {
+                    if readback.fence.as_ref().is_some_and(FrameFence::is_signaled) {
+                        let state = readback.state.clone();
+                        readback.state.store(READBACK_MAPPING, Ordering::Release);
+                        readback.buffer.map(Box::new(move |is_mapped| {
+                            state.store(
+                                if is_mapped {
+                                    READBACK_MAPPED
+                                } else {
+                                    READBACK_FAILED
+                                },
+                                Ordering::Release,
+                            );
+                        }));
+                    }
+
+                    false
+                }
+                // A buffer being mapped cannot be released until the mapping ends.
+                READBACK_MAPPING => false,
+                _ => true,
+            };
+
+            if !is_done {
+                index += 1;
+                continue;
+            }
+
+            let readback = self.pending.swap_remove(index);
+            self.bytes_in_flight -= readback.size;
+
+            match state {
+                READBACK_MAPPED => {
+                    if !is_canceled {
+                        let complete = readback.complete;
+                        readback.buffer.read(|data| complete(Ok(data)));
+                    }
+
+                    readback.buffer.unmap();
+                }
+                READBACK_FAILED if !is_canceled => {
+                    (readback.complete)(Err(ReadbackError::MapFailed));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Copies buffers and textures out of the GPU without stalling it. The copies are encoded into the frame,
+/// mapped once the GPU has finished the frame and handed over through [`ReadbackHandle`]s, a few frames later.
+/// The memory held by the readbacks in flight is capped; requests over the cap fail right away.
+pub struct ReadbackManager {
+    gfx_ctx: GfxContextHandle,
+    queue: ReadbackQueue<Buffer>,
+}
+
+impl ReadbackManager {
+    pub fn new(gfx_ctx: GfxContextHandle) -> Self {
+        Self {
+            gfx_ctx,
+            queue: ReadbackQueue::new(DEFAULT_MAX_READBACK_BYTES),
+        }
+    }
+
+    pub fn max_bytes_in_flight(&self) -> BufferAddress {
+        self.queue.max_bytes_in_flight
+    }
+
+    /// Sets the cap. Readbacks already in flight are kept even if they exceed it.
+    pub fn set_max_bytes_in_flight(&mut self, max_bytes_in_flight: BufferAddress) {
+        self.queue.max_bytes_in_flight = max_bytes_in_flight;
+    }
+
+    /// Memory held by the readbacks not delivered yet, canceled ones included until the GPU is done with them.
+    pub fn bytes_in_flight(&self) -> BufferAddress {
+        self.queue.bytes_in_flight
+    }
+
+    pub fn readbacks_in_flight(&self) -> usize {
+        self.queue.pending.len()
+    }
+
+    /// Reads `range` of the buffer back. The buffer must have been created with [`BufferUsages::COPY_SRC`].
+    /// The copy is encoded into `encoder`, which must be submitted with the frame.
+    pub fn request_buffer_readback(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        buffer: &Buffer,
+        range: Range<BufferAddress>,
+    ) -> Result<ReadbackHandle<Vec<u8>>, ReadbackError> {
+        // Copies must be aligned; the bytes around the range are trimmed when decoding.
+        let start = range.start / COPY_BUFFER_ALIGNMENT * COPY_BUFFER_ALIGNMENT;
+        let end = range.end.div_ceil(COPY_BUFFER_ALIGNMENT) * COPY_BUFFER_ALIGNMENT;
+        let size = end - start;
+        self.queue.reserve(size)?;
+
+        let readback_buffer = self.create_buffer("buffer readback", size);
+        encoder.copy_buffer_to_buffer(buffer, start, &readback_buffer, 0, size);
+
+        let trimmed = (range.start - start) as usize..(range.end - start) as usize;
+        Ok(self
+            .queue
+            .push(readback_buffer, size, move |data| data[trimmed].to_vec()))
+    }
+
+    /// Reads a region of the texture back, converted into RGBA8. The texture must have been created with
+    /// [`TextureUsages::COPY_SRC`](wgpu::TextureUsages::COPY_SRC) and have a color format of 8 bits per channel.
+    /// The copy is encoded into `encoder`, which must be submitted with the frame.
+    pub fn request_texture_readback(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+        origin: Origin3d,
+        size: (u32, u32),
+    ) -> Result<ReadbackHandle<ReadbackImage>, ReadbackError> {
+        let format = texture.format();
+        let bytes_per_pixel =
+            bytes_per_pixel(format).ok_or(ReadbackError::UnsupportedFormat(format))?;
+        let padded_bytes_per_row = padded_bytes_per_row(size.0, bytes_per_pixel);
+        let buffer_size = padded_bytes_per_row as BufferAddress * size.1 as BufferAddress;
+        self.queue.reserve(buffer_size)?;
+
+        let readback_buffer = self.create_buffer("texture readback", buffer_size);
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin,
+                aspect: TextureAspect::All,
+            },
+            ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(size.1),
+                },
+            },
+            Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Ok(self.queue.push(readback_buffer, buffer_size, move |data| {
+            decode_image(data, format, size.0, size.1, padded_bytes_per_row)
+        }))
+    }
+
+    /// Ties the copies encoded so far to the frame just submitted, whose fence tells when they can be mapped.
+    pub fn submitted(&mut self, fence: &FrameFence) {
+        self.queue.submitted(fence);
+    }
+
+    /// Maps the copies of the finished frames and delivers the mapped ones, running their callbacks.
+    /// Never blocks; called once per frame after presenting it.
+    pub fn collect(&mut self) {
+        self.queue.collect();
+    }
+
+    fn create_buffer(&self, label: &str, size: BufferAddress) -> Buffer {
+        self.gfx_ctx.device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+}
+
+/// Bytes per row of a texture copy, padded to the alignment required for copying into buffers.
+pub fn padded_bytes_per_row(width: u32, bytes_per_pixel: u32) -> u32 {
+    (width * bytes_per_pixel).div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT
+}
+
+/// Size of a pixel of the formats that can be converted into a [`ReadbackImage`].
+fn bytes_per_pixel(format: TextureFormat) -> Option<u32> {
+    match format {
+        TextureFormat::R8Unorm => Some(1),
+        TextureFormat::Rgba8Unorm
+        | TextureFormat::Rgba8UnormSrgb
+        | TextureFormat::Bgra8Unorm
+        | TextureFormat::Bgra8UnormSrgb => Some(4),
+        _ => None,
+    }
+}
+
+/// Converts the padded rows of a texture copy into an image.
+fn decode_image(
+    data: &[u8],
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+) -> ReadbackImage {
+    let bytes_per_pixel = bytes_per_pixel(format).unwrap() as usize;
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+
+    for row in data
+        .chunks_exact(padded_bytes_per_row as usize)
+        .take(height as usize)
+    {
+        for pixel in row[..width as usize * bytes_per_pixel].chunks_exact(bytes_per_pixel) {
+            match format {
+                TextureFormat::R8Unorm => pixels.extend_from_slice(&[pixel[0], 0, 0, 255]),
+                TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => {
+                    pixels.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]])
+                }
+                _ => pixels.extend_from_slice(pixel),
+            }
+        }
+    }
+
+    ReadbackImage {
+        width,
+        height,
+        pixels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::{atomic::AtomicI32, mpsc},
+        thread,
+        time::Duration,
+    };
+
+    type MapRequest = Box<dyn FnOnce(bool) + Send>;
+
+    /// Mapped by a fake GPU thread, counting the buffers mapped and not unmapped.
+    struct FakeBuffer {
+        data: Vec<u8>,
+        mapped: Arc<AtomicI32>,
+        map_requests: mpsc::Sender<MapRequest>,
+    }
+
+    impl ReadbackBuffer for FakeBuffer {
+        fn map(&self, done: Box<dyn FnOnce(bool) + Send>) {
+            let mapped = self.mapped.clone();
+            self.map_requests
+                .send(Box::new(move |is_mapped| {
+                    if is_mapped {
+                        mapped.fetch_add(1, Ordering::AcqRel);
+                    }
+
+                    done(is_mapped);
+                }))
+                .unwrap();
+        }
+
+        fn read<R>(&self, read: impl FnOnce(&[u8]) -> R) -> R {
+            assert!(0 < self.mapped.load(Ordering::Acquire));
+            read(&self.data)
+        }
+
+        fn unmap(&self) {
+            self.mapped.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+
+    struct FakeGpu {
+        mapped: Arc<AtomicI32>,
+        map_requests: mpsc::Sender<MapRequest>,
+        pending_requests: mpsc::Receiver<MapRequest>,
+    }
+
+    impl FakeGpu {
+        fn new() -> Self {
+            let (map_requests, pending_requests) = mpsc::channel();
+
+            Self {
+                mapped: Arc::new(AtomicI32::new(0)),
+                map_requests,
+                pending_requests,
+            }
+        }
+
+        fn buffer(&self, data: Vec<u8>) -> FakeBuffer {
+            FakeBuffer {
+                data,
+                mapped: self.mapped.clone(),
+                map_requests: self.map_requests.clone(),
+            }
+        }
+
+        fn finish_maps(&self, is_mapped: bool) {
+            for done in self.pending_requests.try_iter() {
+                done(is_mapped);
+            }
+        }
+    }
+
+    #[test]
+    fn check_texture_rows_are_unpadded_and_converted() {
+        let padded = padded_bytes_per_row(2, 4);
+        assert_eq!(padded, COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let mut data = vec![0xEE; (padded * 2) as usize];
+        data[..8].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        data[padded as usize..padded as usize + 8]
+            .copy_from_slice(&[9, 10, 11, 12, 13, 14, 15, 16]);
+
+        let image = decode_image(&data, TextureFormat::Bgra8UnormSrgb, 2, 2, padded);
+        assert_eq!(
+            image.pixels,
+            vec![3, 2, 1, 4, 7, 6, 5, 8, 11, 10, 9, 12, 15, 14, 13, 16]
+        );
+
+        let image = decode_image(&data, TextureFormat::Rgba8Unorm, 2, 2, padded);
+        assert_eq!(image.pixels, (1..=16).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn check_requests_over_quota_fail_fast() {
+        let gpu = FakeGpu::new();
+        let mut queue = ReadbackQueue::new(100);
+
+        queue.reserve(60).unwrap();
+        let handle = queue.push(gpu.buffer(vec![0; 60]), 60, <[u8]>::to_vec);
+
+        assert_eq!(
+            queue.reserve(60),
+            Err(ReadbackError::QuotaExceeded {
+                requested: 60,
+                available: 40,
+            })
+        );
+
+        // The memory is given back once the readback is delivered.
+        let fence = FrameFence::new();
+        queue.submitted(&fence);
+        fence.signal();
+        queue.collect();
+        gpu.finish_maps(true);
+        queue.collect();
+
+        assert_eq!(handle.block_on(|| {}), Ok(vec![0; 60]));
+        assert_eq!(queue.reserve(60), Ok(()));
+    }
+
+    #[test]
+    fn check_readbacks_wait_for_their_frame() {
+        let gpu = FakeGpu::new();
+        let mut queue = ReadbackQueue::new(DEFAULT_MAX_READBACK_BYTES);
+
+        let mut handle = queue.push(gpu.buffer(vec![7; 4]), 4, <[u8]>::to_vec);
+        let fence = FrameFence::new();
+        queue.submitted(&fence);
+
+        queue.collect();
+        gpu.finish_maps(true);
+        queue.collect();
+        assert_eq!(handle.poll(), None);
+
+        fence.signal();
+        queue.collect();
+        assert_eq!(handle.poll(), None);
+        gpu.finish_maps(true);
+        queue.collect();
+
+        assert_eq!(handle.poll(), Some(Ok(vec![7; 4])));
+        assert_eq!(handle.poll(), None);
+    }
+
+    #[test]
+    fn check_callbacks_run_when_collected() {
+        let gpu = FakeGpu::new();
+        let mut queue = ReadbackQueue::new(DEFAULT_MAX_READBACK_BYTES);
+        let (results, received) = mpsc::channel();
+
+        let handle = queue.push(gpu.buffer(vec![1, 2, 3, 4]), 4, |data| data[2]);
+        handle.on_complete(move |result| results.send(result).unwrap());
+
+        let fence = FrameFence::new();
+        queue.submitted(&fence);
+        fence.signal();
+        queue.collect();
+        assert!(received.try_recv().is_err());
+
+        gpu.finish_maps(false);
+        queue.collect();
+        assert_eq!(received.try_recv(), Ok(Err(ReadbackError::MapFailed)));
+        assert_eq!(queue.pending.len(), 0);
+    }
+
+    #[test]
+    fn check_dropped_handles_release_their_buffers() {
+        let gpu = FakeGpu::new();
+        let mut queue = ReadbackQueue::new(DEFAULT_MAX_READBACK_BYTES);
+
+        let dropped = queue.push(gpu.buffer(vec![0; 8]), 8, <[u8]>::to_vec);
+        let canceled = queue.push(gpu.buffer(vec![0; 8]), 8, <[u8]>::to_vec);
+        let fence = FrameFence::new();
+        queue.submitted(&fence);
+        fence.signal();
+        queue.collect();
+        let unsubmitted = queue.push(gpu.buffer(vec![0; 8]), 8, <[u8]>::to_vec);
+
+        drop(dropped);
+        canceled.cancel();
+        unsubmitted.cancel();
+        queue.collect();
+        // The buffers being mapped stay until the mapping ends.
+        assert_eq!(queue.pending.len(), 2);
+
+        gpu.finish_maps(true);
+        assert_eq!(gpu.mapped.load(Ordering::Acquire), 2);
+        queue.collect();
+
+        assert_eq!(queue.pending.len(), 0);
+        assert_eq!(queue.bytes_in_flight, 0);
+        assert_eq!(gpu.mapped.load(Ordering::Acquire), 0);
+    }
+
+    #[test]
+    fn check_dozens_of_concurrent_readbacks_across_resizes() {
+        let gpu = Arc::new(Mutex::new(FakeGpu::new()));
+        let mut queue = ReadbackQueue::new(512 * 1024);
+        let mut handles = Vec::new();
+
+        // A fake GPU finishing the frames and the mappings at its own pace.
+        let (submit, submitted) = mpsc::channel::<FrameFence>();
+        let gpu_thread = {
+            let gpu = gpu.clone();
+            thread::spawn(move || {
+                for (index, fence) in submitted.into_iter().enumerate() {
+                    thread::sleep(Duration::from_micros(40 * (index % 5) as u64));
+                    fence.signal();
+                    gpu.lock().finish_maps(true);
+                }
+            })
+        };
+
+        for frame in 0..60u32 {
+            // The window is resized every few frames; each frame reads back a surface of its size.
+            let width = 64 + (frame / 4) * 12;
+            let height = 32 + (frame / 4) * 5;
+            let padded = padded_bytes_per_row(width, 4);
+            let size = padded as BufferAddress * height as BufferAddress;
+
+            match queue.reserve(size) {
+                Ok(()) => {
+                    let data = vec![frame as u8; size as usize];
+                    let buffer = gpu.lock().buffer(data);
+                    let handle = queue.push(buffer, size, move |data| {
+                        decode_image(data, TextureFormat::Rgba8Unorm, width, height, padded)
+                    });
+                    handles.push((frame, width, height, handle));
+                }
+                // The fake GPU lags behind; the readbacks over the quota are skipped.
+                Err(ReadbackError::QuotaExceeded { .. }) => {}
+                Err(err) => panic!("{}", err),
+            }
+
+            // Some readbacks are abandoned midway.
+            if frame % 7 == 3 && !handles.is_empty() {
+                handles.remove(0);
+            }
+
+            assert!(queue.bytes_in_flight <= queue.max_bytes_in_flight);
+
+            let fence = FrameFence::new();
+            queue.submitted(&fence);
+            submit.send(fence).unwrap();
+            queue.collect();
+        }
+
+        drop(submit);
+        gpu_thread.join().unwrap();
+
+        assert!(!handles.is_empty());
+
+        for (frame, width, height, handle) in handles {
+            let image = handle
+                .block_on(|| {
+                    gpu.lock().finish_maps(true);
+                    queue.collect();
+                })
+                .unwrap();
+
+            assert_eq!((image.width, image.height), (width, height));
+            assert_eq!(image.pixels.len(), (width * height * 4) as usize);
+            assert!(image.pixels.iter().all(|&byte| byte == frame as u8));
+        }
+
+        while !queue.pending.is_empty() {
+            gpu.lock().finish_maps(true);
+            queue.collect();
+        }
+
+        assert_eq!(queue.bytes_in_flight, 0);
+        assert_eq!(gpu.lock().mapped.load(Ordering::Acquire), 0);
+    }
+}
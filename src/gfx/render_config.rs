@@ -0,0 +1,310 @@
+use super::{ColorSpaceMode, DepthStencilMode, QualitySetting};
+use serde::{
+    de::{self, DeserializeSeed, IgnoredAny, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use std::{
+    cell::Cell,
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use thiserror::Error;
+
+/// Data-driven description of the render manager's configurable surface.
+/// Every field has a default, so partial files are accepted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RenderPipelineConfig {
+    pub depth_stencil: DepthStencilMode,
+    pub vsync: bool,
+    /// Overrides the quality preset of the detected render tier.
+    pub quality: QualitySetting,
+    pub color_space: ColorSpaceMode,
+}
+
+impl Default for RenderPipelineConfig {
+    fn default() -> Self {
+        Self {
+            depth_stencil: DepthStencilMode::DepthOnly,
+            vsync: true,
+            quality: QualitySetting::Auto,
+            color_space: ColorSpaceMode::Gamma,
+        }
+    }
+}
+
+impl RenderPipelineConfig {
+    /// Field names known by this version of the engine. Other fields are ignored with a warning.
+    pub const FIELDS: &'static [&'static str] =
+        &["depth_stencil", "vsync", "quality", "color_space"];
+
+    /// Parses a config from JSON. Unknown fields are reported as warnings instead of errors,
+    /// so that older engines can read newer configs partially.
+    pub fn from_json(source: &str) -> Result<(Self, Vec<String>), RenderPipelineConfigError> {
+        let mut deserializer = serde_json::Deserializer::from_str(source);
+        let field = Cell::new(None);
+        let parsed = ConfigSeed { field: &field }
+            .deserialize(&mut deserializer)
+            .and_then(|parsed| deserializer.end().map(|_| parsed));
+        parsed.map_err(|err| parse_error(field.get(), err.to_string()))
+    }
+
+    /// Parses a config from RON. See [`from_json`](Self::from_json) for the handling of unknown fields.
+    pub fn from_ron(source: &str) -> Result<(Self, Vec<String>), RenderPipelineConfigError> {
+        let mut deserializer = ron::Deserializer::from_str(source)
+            .map_err(|err| RenderPipelineConfigError::ParseError(err.to_string()))?;
+        let field = Cell::new(None);
+        let parsed = ConfigSeed { field: &field }
+            .deserialize(&mut deserializer)
+            .and_then(|parsed| deserializer.end().map(|_| parsed));
+        parsed.map_err(|err| parse_error(field.get(), err.to_string()))
+    }
+
+    /// Loads a config from a file, choosing the format by its extension (`ron` or `json`).
+    pub fn load(path: impl AsRef<Path>) -> Result<(Self, Vec<String>), RenderPipelineConfigError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json(&source),
+            _ => Self::from_ron(&source),
+        }
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, Default::default()).unwrap()
+    }
+}
+
+/// Deserializes the fields straight into their types, so that the enums keep their variants in every format,
+/// and records the field being parsed for the error.
+struct ConfigSeed<'a> {
+    field: &'a Cell<Option<&'static str>>,
+}
+
+impl<'de> DeserializeSeed<'de> for ConfigSeed<'_> {
+    type Value = (RenderPipelineConfig, Vec<String>);
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_struct("RenderPipelineConfig", RenderPipelineConfig::FIELDS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for ConfigSeed<'_> {
+    type Value = (RenderPipelineConfig, Vec<String>);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut config = RenderPipelineConfig::default();
+        let mut warnings = Vec::new();
+
+        while let Some(FieldName(key)) = map.next_key()? {
+            let field = RenderPipelineConfig::FIELDS
+                .iter()
+                .find(|&&field| field == key);
+            self.field.set(field.copied());
+
+            match key.as_str() {
+                "depth_stencil" => config.depth_stencil = map.next_value()?,
+                "vsync" => config.vsync = map.next_value()?,
+                "quality" => config.quality = map.next_value()?,
+                "color_space" => config.color_space = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                    warnings.push(format!("unknown render config field `{}` ignored", key));
+                }
+            }
+
+            self.field.set(None);
+        }
+
+        Ok((config, warnings))
+    }
+}
+
+/// Name of a field, read as an identifier: RON only reads the fields of a struct that way.
+struct FieldName(String);
+
+impl<'de> Deserialize<'de> for FieldName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldNameVisitor;
+
+        impl<'de> Visitor<'de> for FieldNameVisitor {
+            type Value = FieldName;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a field name")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                Ok(FieldName(value.to_owned()))
+            }
+        }
+
+        deserializer.deserialize_identifier(FieldNameVisitor)
+    }
+}
+
+fn parse_error(field: Option<&'static str>, message: String) -> RenderPipelineConfigError {
+    match field {
+        Some(field) => RenderPipelineConfigError::InvalidField {
+            path: field.to_owned(),
+            message,
+        },
+        None => RenderPipelineConfigError::ParseError(message),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RenderPipelineConfigError {
+    #[error("failed to read render config: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("failed to parse render config: {0}")]
+    ParseError(String),
+    #[error("invalid render config field `{path}`: {message}")]
+    InvalidField { path: String, message: String },
+}
+
+/// Watches a render config file and reloads it when it changes.
+pub struct RenderConfigWatcher {
+    path: PathBuf,
+    last_modified: Option<SystemTime>,
+    last_checked: Option<Instant>,
+}
+
+impl RenderConfigWatcher {
+    const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            last_modified: None,
+            last_checked: None,
+        }
+    }
+
+    /// Watches `render.ron` placed next to the executable.
+    pub fn next_to_executable() -> Option<Self> {
+        let exe = std::env::current_exe().ok()?;
+        Some(Self::new(exe.parent()?.join("render.ron")))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the newly loaded config if the file has been created or modified since the last call.
+    /// The file system is checked at most once per second.
+    pub fn poll(
+        &mut self,
+    ) -> Option<Result<(RenderPipelineConfig, Vec<String>), RenderPipelineConfigError>> {
+        let now = Instant::now();
+
+        if let Some(last_checked) = self.last_checked {
+            if now - last_checked < Self::CHECK_INTERVAL {
+                return None;
+            }
+        }
+
+        self.last_checked = Some(now);
+
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+
+        if self.last_modified == Some(modified) {
+            return None;
+        }
+
+        self.last_modified = Some(modified);
+        Some(RenderPipelineConfig::load(&self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::{QualityPreset, RenderTier};
+
+    fn configs() -> Vec<RenderPipelineConfig> {
+        vec![
+            RenderPipelineConfig::default(),
+            RenderPipelineConfig {
+                depth_stencil: DepthStencilMode::StencilOnly,
+                vsync: false,
+                quality: QualitySetting::Tier(RenderTier::Ultra),
+                color_space: ColorSpaceMode::Linear,
+            },
+            RenderPipelineConfig {
+                depth_stencil: DepthStencilMode::None,
+                vsync: true,
+                quality: QualitySetting::Custom(QualityPreset::for_tier(RenderTier::Low)),
+                color_space: ColorSpaceMode::Gamma,
+            },
+        ]
+    }
+
+    #[test]
+    fn check_ron_round_trip() {
+        for config in configs() {
+            let (parsed, warnings) = RenderPipelineConfig::from_ron(&config.to_ron()).unwrap();
+            assert_eq!(parsed, config);
+            assert!(warnings.is_empty());
+        }
+    }
+
+    #[test]
+    fn check_json_round_trip() {
+        for config in configs() {
+            let source = serde_json::to_string(&config).unwrap();
+            let (parsed, warnings) = RenderPipelineConfig::from_json(&source).unwrap();
+            assert_eq!(parsed, config);
+            assert!(warnings.is_empty());
+        }
+    }
+
+    #[test]
+    fn check_partial_ron_keeps_the_enum_variants() {
+        let (config, warnings) = RenderPipelineConfig::from_ron(
+            "(depth_stencil: DepthStencil, quality: tier(high), bloom: (intensity: 2.0))",
+        )
+        .unwrap();
+
+        assert_eq!(config.depth_stencil, DepthStencilMode::DepthStencil);
+        assert_eq!(config.quality, QualitySetting::Tier(RenderTier::High));
+        assert_eq!(config.vsync, RenderPipelineConfig::default().vsync);
+        assert_eq!(
+            warnings,
+            vec!["unknown render config field `bloom` ignored".to_owned()]
+        );
+    }
+
+    #[test]
+    fn check_invalid_field_is_named() {
+        let err =
+            RenderPipelineConfig::from_ron("(vsync: true, color_space: Grayscale)").unwrap_err();
+        assert!(
+            matches!(&err, RenderPipelineConfigError::InvalidField { path, .. } if path == "color_space"),
+            "{}",
+            err
+        );
+
+        let err = RenderPipelineConfig::from_json("{\"vsync\": 1}").unwrap_err();
+        assert!(
+            matches!(&err, RenderPipelineConfigError::InvalidField { path, .. } if path == "vsync"),
+            "{}",
+            err
+        );
+
+        let err = RenderPipelineConfig::from_ron("(vsync: true").unwrap_err();
+        assert!(
+            matches!(err, RenderPipelineConfigError::ParseError(_)),
+            "{}",
+            err
+        );
+    }
+}
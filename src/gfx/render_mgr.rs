@@ -1,27 +1,90 @@
 use super::{
-    build_rendering_command, BindGroupLayoutCache, CameraClearMode, DepthStencil, DepthStencilMode,
-    FrameBufferAllocator, GenericBufferAllocation, GfxContextHandle, PipelineCache,
-    PipelineLayoutCache, Renderer, RenderingCommand,
+    build_batched_rendering_command, build_detached_rendering_command, build_rendering_command,
+    create_offscreen_frame_texture, BindGroupLayoutCache, BuiltInShaderManager, BundleTargetFormat,
+    CameraClearMode, CameraStackEntry, CameraStackLayer, CameraStackOutput, CameraStackSlot,
+    CameraStacks, CapturePassTarget, ColorSpaceMode, CubemapHandle, CustomPass, DebugViewMode,
+    DepthStencil, DepthStencilMode, FogView, FogVolume, FrameBufferAllocator, FrameCaptureError,
+    FrameCaptureRecorder, FrameFence, FrameFenceRing, FrameImage, FrameReport, FrameTarget,
+    GenericBufferAllocation, GfxContextHandle, GpuTimer, InputLatencyTracker, OverlayRenderer,
+    OverlayStack, PipelineCache, PipelineLayoutCache, PlanarReflectionPool, PostEffectStack,
+    QualityPreset, QualitySetting, ReadbackManager, RenderPipelineConfig, RenderTarget,
+    RenderTargetHandle, RenderTier, RenderTierReport, Renderer, RenderingCommand, SceneLights,
+    ScreenshotCapture, ScreenshotError, ShaderManager, Skybox, SkyboxSubRenderer, SurfaceOutput,
+    Uploader, ViewportClear, ViewportRect, VolumetricFog, VolumetricFogSettings,
+    POST_PROCESS_FORMAT,
+};
+use crate::{
+    math::Mat4,
+    object::{ObjectHierarchy, ObjectId},
+};
+use asset::AssetKey;
+use std::{
+    mem::{size_of, take},
+    ops::RangeInclusive,
+    path::PathBuf,
+    sync::Arc,
+    time::Instant,
 };
-use crate::object::{ObjectHierarchy, ObjectId};
-use std::mem::size_of;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
     Buffer, BufferSize, BufferUsages, Color, CommandBuffer, CommandEncoder,
-    CommandEncoderDescriptor, LoadOp, Operations, RenderPass, RenderPassColorAttachment,
-    RenderPassDepthStencilAttachment, SurfaceError, TextureView,
+    CommandEncoderDescriptor, LoadOp, Maintain, Operations, RenderPass, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, SubmissionIndex, SurfaceError, Texture, TextureFormat,
+    TextureView,
 };
 use winit::dpi::PhysicalSize;
 use zerocopy::AsBytes;
 
 pub struct RenderManager {
     gfx_ctx: GfxContextHandle,
+    size: PhysicalSize<u32>,
+    /// Scale of the scene drawn by the cameras relative to the surface, see [`set_render_scale`](Self::set_render_scale).
+    render_scale: f32,
     depth_stencil: DepthStencil,
+    /// Stands in for the surface of a headless context.
+    offscreen_frame_texture: Option<Arc<Texture>>,
     bind_group_layout_cache: BindGroupLayoutCache,
+    lights: SceneLights,
     pipeline_layout_cache: PipelineLayoutCache,
     pipeline_cache: PipelineCache,
     frame_buffer_allocator: FrameBufferAllocator,
     standard_ui_vertex_buffer: GenericBufferAllocation<Buffer>,
+    custom_passes: Vec<Box<dyn CustomPass>>,
+    planar_reflections: PlanarReflectionPool,
+    frame_fences: FrameFenceRing<SubmissionIndex>,
+    wait_for_present: bool,
+    input_latency: InputLatencyTracker,
+    frame_wait_ms: f32,
+    frustum_culling: bool,
+    opaque_front_to_back: bool,
+    meshes_culled: u32,
+    light_culling_ms: f32,
+    terrain_chunks: (u32, u32),
+    draw_calls: (u32, u32),
+    material_batches: u32,
+    encoder_threads: usize,
+    encoder_thread_ms: Vec<f32>,
+    last_encoder_thread_ms: Vec<f32>,
+    frame_report: FrameReport,
+    gpu_timer: Option<GpuTimer>,
+    overlays: OverlayStack,
+    overlay_renderer: OverlayRenderer,
+    camera_stacks: CameraStacks,
+    viewport_clear: ViewportClear,
+    skybox: Option<Skybox>,
+    volumetric_fog: Option<VolumetricFog>,
+    post_effects: PostEffectStack,
+    color_space: ColorSpaceMode,
+    /// Format of the targets the cameras drawing into the surface draw in.
+    scene_color_format: TextureFormat,
+    readbacks: ReadbackManager,
+    screenshots: ScreenshotCapture,
+    frame_capture: FrameCaptureRecorder,
+    tier_report: Option<RenderTierReport>,
+    quality: QualitySetting,
+    quality_tier: Option<RenderTier>,
+    quality_preset: QualityPreset,
+    is_quality_preset_pending: bool,
 }
 
 impl RenderManager {
@@ -31,10 +94,28 @@ impl RenderManager {
         depth_stencil_mode: DepthStencilMode,
     ) -> Self {
         let depth_stencil = DepthStencil::new(gfx_ctx.clone(), depth_stencil_mode, size).unwrap();
-        let bind_group_layout_cache = BindGroupLayoutCache::new(gfx_ctx.clone());
+        let offscreen_frame_texture = create_offscreen_frame_texture_if_headless(&gfx_ctx);
+        let mut bind_group_layout_cache = BindGroupLayoutCache::new(gfx_ctx.clone());
+        let lights = SceneLights::new(&gfx_ctx.device, &mut bind_group_layout_cache);
         let pipeline_layout_cache = PipelineLayoutCache::new(gfx_ctx.clone());
-        let pipeline_cache = PipelineCache::new(gfx_ctx.clone());
+        let pipeline_cache = PipelineCache::new(gfx_ctx.clone(), depth_stencil_mode);
         let frame_buffer_allocator = FrameBufferAllocator::new(gfx_ctx.clone());
+        let planar_reflections =
+            PlanarReflectionPool::new(gfx_ctx.clone(), depth_stencil.mode().as_texture_format());
+        let overlay_renderer = OverlayRenderer::new(gfx_ctx.clone());
+        let camera_stacks =
+            CameraStacks::new(gfx_ctx.clone(), depth_stencil.mode().as_texture_format());
+        let scene_color_format = gfx_ctx.surface_config.read().format;
+        let viewport_clear = ViewportClear::new(
+            gfx_ctx.clone(),
+            scene_color_format,
+            depth_stencil.mode().as_texture_format(),
+        );
+        let post_effects = PostEffectStack::new(gfx_ctx.clone());
+        let gpu_timer = GpuTimer::new(&gfx_ctx);
+        let readbacks = ReadbackManager::new(gfx_ctx.clone());
+        let screenshots = ScreenshotCapture::new(gfx_ctx.clone());
+        let frame_capture = FrameCaptureRecorder::new(gfx_ctx.clone());
 
         // Since ui elements are always left-bottom based, positions must in range [0, 1].
         let standard_ui_vertices = vec![
@@ -49,7 +130,7 @@ impl RenderManager {
             gfx_ctx.device.create_buffer_init(&BufferInitDescriptor {
                 label: None,
                 contents: standard_ui_vertices.as_bytes(),
-                usage: BufferUsages::VERTEX,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_SRC,
             }),
             0,
             BufferSize::new((size_of::<f32>() * standard_ui_vertices.len()) as u64).unwrap(),
@@ -57,12 +138,51 @@ impl RenderManager {
 
         Self {
             gfx_ctx,
+            size,
+            render_scale: 1.0,
             depth_stencil,
+            offscreen_frame_texture,
             bind_group_layout_cache,
+            lights,
             pipeline_layout_cache,
             pipeline_cache,
             frame_buffer_allocator,
             standard_ui_vertex_buffer,
+            custom_passes: Vec::new(),
+            planar_reflections,
+            frame_fences: FrameFenceRing::new(2),
+            wait_for_present: false,
+            input_latency: InputLatencyTracker::new(),
+            frame_wait_ms: 0.0,
+            frustum_culling: true,
+            opaque_front_to_back: true,
+            meshes_culled: 0,
+            light_culling_ms: 0.0,
+            terrain_chunks: (0, 0),
+            draw_calls: (0, 0),
+            material_batches: 0,
+            encoder_threads: 1,
+            encoder_thread_ms: Vec::new(),
+            last_encoder_thread_ms: Vec::new(),
+            frame_report: FrameReport::default(),
+            gpu_timer,
+            overlays: OverlayStack::new(),
+            overlay_renderer,
+            camera_stacks,
+            viewport_clear,
+            skybox: None,
+            volumetric_fog: None,
+            post_effects,
+            color_space: ColorSpaceMode::default(),
+            scene_color_format,
+            readbacks,
+            screenshots,
+            frame_capture,
+            tier_report: None,
+            quality: QualitySetting::Auto,
+            quality_tier: Some(RenderTier::Medium),
+            quality_preset: QualityPreset::default(),
+            is_quality_preset_pending: true,
         }
     }
 
@@ -74,6 +194,14 @@ impl RenderManager {
         &mut self.pipeline_layout_cache
     }
 
+    /// Both layout caches at once, e.g. to reload a shader.
+    pub fn layout_caches(&mut self) -> (&mut BindGroupLayoutCache, &mut PipelineLayoutCache) {
+        (
+            &mut self.bind_group_layout_cache,
+            &mut self.pipeline_layout_cache,
+        )
+    }
+
     pub fn pipeline_cache(&mut self) -> &mut PipelineCache {
         &mut self.pipeline_cache
     }
@@ -82,12 +210,740 @@ impl RenderManager {
         (&mut self.bind_group_layout_cache, &mut self.pipeline_cache)
     }
 
+    /// Lights of the scene, as read by the lit shaders.
+    pub fn lights(&self) -> &SceneLights {
+        &self.lights
+    }
+
+    pub fn lights_mut(&mut self) -> &mut SceneLights {
+        &mut self.lights
+    }
+
+    pub fn split_lights(&mut self) -> (&mut SceneLights, &mut Uploader) {
+        (&mut self.lights, self.frame_buffer_allocator.uploader_mut())
+    }
+
     pub fn standard_ui_vertex_buffer(&self) -> &GenericBufferAllocation<Buffer> {
         &self.standard_ui_vertex_buffer
     }
 
+    /// Registers a pass to run after the camera passes every frame.
+    /// Passes sampling resources written by later-registered passes are moved after them.
+    pub fn add_custom_pass(&mut self, pass: Box<dyn CustomPass>) {
+        self.custom_passes.push(pass);
+    }
+
+    pub fn custom_passes(&self) -> &[Box<dyn CustomPass>] {
+        &self.custom_passes
+    }
+
+    pub fn custom_passes_mut(&mut self) -> &mut [Box<dyn CustomPass>] {
+        &mut self.custom_passes
+    }
+
+    pub fn planar_reflections(&self) -> &PlanarReflectionPool {
+        &self.planar_reflections
+    }
+
+    pub fn planar_reflections_mut(&mut self) -> &mut PlanarReflectionPool {
+        &mut self.planar_reflections
+    }
+
+    pub fn split_planar_reflections(
+        &mut self,
+    ) -> (&mut PlanarReflectionPool, &mut BindGroupLayoutCache) {
+        (
+            &mut self.planar_reflections,
+            &mut self.bind_group_layout_cache,
+        )
+    }
+
+    /// Full-screen effects composited over everything at the end of the frame.
+    pub fn overlays(&self) -> &OverlayStack {
+        &self.overlays
+    }
+
+    pub fn overlays_mut(&mut self) -> &mut OverlayStack {
+        &mut self.overlays
+    }
+
+    /// Composites the overlays over the surface, after the camera and custom passes.
+    /// Returns `false` without adding a pass if no overlay is visible.
+    pub fn encode_overlays(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        surface_texture_view: &TextureView,
+    ) -> bool {
+        self.overlay_renderer
+            .encode(&mut self.overlays, encoder, surface_texture_view)
+    }
+
+    /// Creates an offscreen target for cameras to draw into, with a depth buffer of the current depth stencil mode.
+    /// Changing the mode afterwards requires creating the target again.
+    pub fn create_render_target(
+        &self,
+        width: u16,
+        height: u16,
+        format: TextureFormat,
+    ) -> RenderTargetHandle {
+        RenderTargetHandle::new(RenderTarget::new(
+            width,
+            height,
+            format,
+            self.depth_stencil.mode().as_texture_format(),
+            &self.gfx_ctx.device,
+        ))
+    }
+
+    /// Defines the camera stack of the given name, replacing any previous one. It is composited into the surface.
+    /// Cameras draw into its slots through [`Camera::stack`](super::Camera::stack).
+    pub fn define_stack(&mut self, name: impl Into<String>, entries: Vec<CameraStackEntry>) {
+        self.camera_stacks.define_stack(name, entries);
+    }
+
+    /// Composites the stack into the surface or a slot of another stack.
+    /// Returns `false` if there's no stack of the given name.
+    pub fn set_stack_output(&mut self, name: &str, output: CameraStackOutput) -> bool {
+        self.camera_stacks.set_output(name, output)
+    }
+
+    pub fn remove_stack(&mut self, name: &str) -> bool {
+        self.camera_stacks.remove_stack(name).is_some()
+    }
+
+    pub fn camera_stacks(&self) -> &CameraStacks {
+        &self.camera_stacks
+    }
+
+    pub fn camera_stacks_mut(&mut self) -> &mut CameraStacks {
+        &mut self.camera_stacks
+    }
+
+    /// Assigns the intermediate targets of the camera stacks for the frame, after the surface has been acquired.
+    /// Effects applied to the scene before it reaches the surface. An empty stack costs nothing.
+    pub fn post_effects(&self) -> &PostEffectStack {
+        &self.post_effects
+    }
+
+    pub fn post_effects_mut(&mut self) -> &mut PostEffectStack {
+        &mut self.post_effects
+    }
+
+    /// Space the scene is shaded and blended in. Defaults to [`ColorSpaceMode::Gamma`].
+    pub fn color_space(&self) -> ColorSpaceMode {
+        self.color_space
+    }
+
+    /// Shades the scene in the space from the next frame on. The built-in renderers, the clear colors, the fog and
+    /// the post effects convert their colors as they are drawn. Colors handed to shaders otherwise, e.g. of materials
+    /// and lights, must be converted by [`ColorSpaceMode::shader_color`], e.g. through
+    /// [`Material::set_color`](super::Material::set_color).
+    pub fn set_color_space(&mut self, color_space: ColorSpaceMode) {
+        self.color_space = color_space;
+        self.pipeline_cache.set_color_space(color_space);
+    }
+
+    /// What the scene is shown as. Defaults to [`DebugViewMode::None`].
+    pub fn debug_view(&self) -> DebugViewMode {
+        self.pipeline_cache.debug_view()
+    }
+
+    /// Shows the scene as the mode from the next frame on, through pipelines of its own; the materials are left
+    /// untouched, and switching back picks their pipelines up again. Frame captures record the pipelines as the
+    /// materials describe them, so they are best taken with [`DebugViewMode::None`].
+    pub fn set_debug_view(&mut self, debug_view: DebugViewMode) {
+        self.pipeline_cache.set_debug_view(debug_view);
+    }
+
+    /// How the scene of the color space reaches the surface: the format of the view of the surface it is written
+    /// through, and the conversion the post-processing stack applies on the way if the surface has no such view.
+    pub fn surface_output(&self) -> SurfaceOutput {
+        let surface_config = self.gfx_ctx.surface_config.read();
+        super::surface_output(
+            self.color_space,
+            surface_config.format,
+            &surface_config.view_formats,
+        )
+    }
+
+    /// Format the cameras drawing into the surface draw in: the one of [`surface_output`](Self::surface_output), or
+    /// [`POST_PROCESS_FORMAT`] while the post-processing stack is active. Render targets drawn with the built-in
+    /// shaders must be of this format.
+    pub fn scene_color_format(&self) -> TextureFormat {
+        self.scene_color_format
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Draws the scene of the cameras drawing into the surface at the scale of its size, clamped into
+    /// [`RENDER_SCALE_RANGE`]. Any scale but one sends the scene through the post-processing stack, which scales it
+    /// into the surface. Takes effect from the next [`begin_frame`](Self::begin_frame).
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        let render_scale =
+            render_scale.clamp(*RENDER_SCALE_RANGE.start(), *RENDER_SCALE_RANGE.end());
+
+        if render_scale == self.render_scale {
+            return;
+        }
+
+        self.render_scale = render_scale;
+        self.post_effects.set_scaled(render_scale != 1.0);
+        self.depth_stencil.resize(self.scene_size());
+    }
+
+    /// Size of the scene the cameras drawing into the surface draw, i.e. of the surface scaled by the render scale.
+    pub fn scene_size(&self) -> PhysicalSize<u32> {
+        scaled_size(self.size, self.render_scale)
+    }
+
+    /// Returns the target the cameras draw into in place of the surface for the frame, or `None` if they draw into
+    /// the surface directly.
+    pub fn prepare_post_processing(&mut self) -> Option<Arc<TextureView>> {
+        let scene_size = self.scene_size();
+        self.post_effects
+            .prepare(scene_size.width, scene_size.height)
+    }
+
+    /// Applies the post-processing stack to the scene, writing the result into the surface. Runs after the camera
+    /// stacks are composited, and before the custom passes.
+    pub fn encode_post_processing(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        surface_texture_view: &TextureView,
+    ) {
+        self.post_effects.encode(encoder, surface_texture_view);
+    }
+
+    pub fn begin_camera_stacks(&mut self) {
+        let scene_size = self.scene_size();
+        self.camera_stacks
+            .begin_frame(scene_size.width, scene_size.height);
+    }
+
+    /// Returns the target a camera drawing into the slot draws into, or `None` if it draws into the surface.
+    pub fn acquire_stack_layer(&mut self, slot: &CameraStackSlot) -> Option<CameraStackLayer> {
+        self.camera_stacks.acquire_layer(slot)
+    }
+
+    /// Composites the camera stacks, after every camera pass and before the custom passes.
+    pub fn compose_camera_stacks(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        surface_texture_view: &TextureView,
+    ) {
+        self.camera_stacks.compose(encoder, surface_texture_view);
+    }
+
+    /// Cubemap drawn behind the scene, or `None` if there is no sky.
+    pub fn skybox(&self) -> Option<&CubemapHandle> {
+        self.skybox.as_ref().map(Skybox::cubemap)
+    }
+
+    /// Draws the cubemap behind the scene of every camera whose clear mode clears the color, or nothing with `None`.
+    /// Defaults to none.
+    pub fn set_skybox(&mut self, cubemap: Option<CubemapHandle>) {
+        self.skybox = cubemap.map(|cubemap| Skybox::new(cubemap, &self.gfx_ctx.device));
+    }
+
+    /// Returns `None` if there is no sky or its pipeline is not ready.
+    pub fn skybox_sub_renderer(
+        &mut self,
+        built_in_shader_mgr: &BuiltInShaderManager,
+        shader_mgr: &ShaderManager,
+    ) -> Option<SkyboxSubRenderer> {
+        self.skybox.as_mut()?.sub_renderer(
+            built_in_shader_mgr,
+            shader_mgr,
+            &mut self.pipeline_layout_cache,
+            &mut self.pipeline_cache,
+        )
+    }
+
+    /// Settings of the volumetric fog, or `None` if it is off.
+    pub fn volumetric_fog(&self) -> Option<&VolumetricFogSettings> {
+        self.volumetric_fog.as_ref().map(VolumetricFog::settings)
+    }
+
+    /// Turns the volumetric fog on with the given settings, or off with `None`. Defaults to off.
+    /// The froxel grid follows the [`QualityPreset`].
+    pub fn set_volumetric_fog(&mut self, settings: Option<VolumetricFogSettings>) {
+        match (settings, &mut self.volumetric_fog) {
+            (Some(settings), Some(volumetric_fog)) => volumetric_fog.set_settings(settings),
+            (Some(settings), None) => {
+                self.volumetric_fog = Some(VolumetricFog::new(
+                    self.gfx_ctx.clone(),
+                    settings,
+                    self.scene_color_format,
+                ))
+            }
+            (None, _) => self.volumetric_fog = None,
+        }
+    }
+
+    /// Fogs the surface as seen from `view`, after the camera pass that filled the depth.
+    /// Returns `false` without adding a pass if the fog is off or there is no depth to read.
+    pub fn encode_volumetric_fog(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        surface_texture_view: &TextureView,
+        view: &FogView,
+        volumes: &[(Mat4, FogVolume)],
+    ) -> bool {
+        let (volumetric_fog, depth_view) =
+            match (&mut self.volumetric_fog, self.depth_stencil.depth_view()) {
+                (Some(volumetric_fog), Some(depth_view))
+                    if self.depth_stencil.mode().has_depth() =>
+                {
+                    (volumetric_fog, depth_view)
+                }
+                _ => return false,
+            };
+
+        volumetric_fog.apply_quality(&self.quality_preset);
+        volumetric_fog.encode(
+            encoder,
+            self.frame_buffer_allocator.uploader_mut(),
+            surface_texture_view,
+            depth_view,
+            view,
+            volumes,
+            self.color_space,
+        );
+        true
+    }
+
+    /// Reads buffers and textures back from the GPU, see [`ReadbackManager`].
+    pub fn readbacks(&self) -> &ReadbackManager {
+        &self.readbacks
+    }
+
+    pub fn readbacks_mut(&mut self) -> &mut ReadbackManager {
+        &mut self.readbacks
+    }
+
+    /// Saves the next frame into `path` as a PNG image, a few frames later.
+    /// Fails if the surface cannot be copied from on this device.
+    pub fn request_screenshot(&mut self, path: impl Into<PathBuf>) -> Result<(), ScreenshotError> {
+        self.screenshots.request(path)
+    }
+
+    /// Copies the next frame into an image, as presented. Unlike [`capture_next_frame`](Self::capture_next_frame),
+    /// only the pixels are captured. Resolves to an error if the surface cannot be copied from on this device.
+    pub fn capture_frame(&mut self) -> FrameImage {
+        self.screenshots.request_image()
+    }
+
+    /// Copies the surface for the requested screenshots, after the overlays.
+    pub fn encode_screenshots(&mut self, encoder: &mut CommandEncoder, surface_texture: &Texture) {
+        self.screenshots
+            .encode(encoder, surface_texture, &mut self.readbacks);
+    }
+
+    /// Saves the screenshots read back so far, returning where each one went.
+    pub fn collect_screenshots(&mut self) -> Vec<(PathBuf, Result<(), ScreenshotError>)> {
+        self.screenshots.collect()
+    }
+
+    /// Records the command stream of the next frame into `path`, to be replayed by [`FrameReplayer`](super::FrameReplayer).
+    pub fn capture_next_frame(&mut self, path: impl Into<PathBuf>) {
+        self.frame_capture.request(path);
+    }
+
+    /// Identifies the texture by its asset in frame captures.
+    pub fn label_capture_texture(
+        &mut self,
+        view: &Arc<TextureView>,
+        key: AssetKey,
+        width: u32,
+        height: u32,
+    ) {
+        self.frame_capture.label_texture(view, key, width, height);
+    }
+
+    /// Starts capturing the frame if one has been requested. Returns `true` if the passes must be recorded.
+    pub fn begin_frame_capture(&mut self, screen_size: [f32; 4]) -> bool {
+        let scene_size = self.scene_size();
+        // Surface passes draw in the format and at the size of the scene, which are not the surface's under
+        // post-processing.
+        self.frame_capture.begin(
+            scene_size.width,
+            scene_size.height,
+            self.scene_color_format,
+            screen_size,
+        )
+    }
+
+    pub fn is_capturing_frame(&self) -> bool {
+        self.frame_capture.is_active()
+    }
+
+    /// Records a pass of the captured frame, before it is encoded.
+    pub fn record_capture_pass(
+        &mut self,
+        name: impl Into<String>,
+        target: CapturePassTarget,
+        clear_mode: &CameraClearMode,
+        view_projection: &Mat4,
+        commands: &[RenderingCommand],
+        shader_mgr: &ShaderManager,
+    ) {
+        self.frame_capture.record_pass(
+            name,
+            target,
+            clear_mode,
+            view_projection,
+            commands,
+            shader_mgr,
+        );
+    }
+
+    /// Copies the resources of the captured frame, after its last captured pass.
+    pub fn encode_frame_capture(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        surface_texture: &Texture,
+    ) {
+        self.frame_capture.encode(encoder, surface_texture);
+    }
+
+    /// Saves the captured frame once it has been read back, returning where it went.
+    pub fn collect_frame_capture(&mut self) -> Option<(PathBuf, Result<(), FrameCaptureError>)> {
+        self.frame_capture.collect()
+    }
+
     pub fn resize(&mut self, size: PhysicalSize<u32>) {
-        self.depth_stencil.resize(size);
+        self.size = size;
+        self.depth_stencil.resize(self.scene_size());
+        self.offscreen_frame_texture = create_offscreen_frame_texture_if_headless(&self.gfx_ctx);
+    }
+
+    pub fn current_config(&self) -> RenderPipelineConfig {
+        RenderPipelineConfig {
+            depth_stencil: self.depth_stencil.mode(),
+            vsync: self.gfx_ctx.is_vsync(),
+            quality: self.quality,
+            color_space: self.color_space,
+        }
+    }
+
+    /// Applies the given config, touching only what differs from the current state. A new depth stencil mode drops
+    /// the cached pipelines of the previous one. The vsync is left out: it is a display setting, applied at a frame
+    /// boundary along with the others through [`DisplayManager::request_vsync`](super::DisplayManager::request_vsync).
+    pub fn apply_config(&mut self, config: &RenderPipelineConfig) {
+        let current = self.current_config();
+
+        if current.depth_stencil != config.depth_stencil {
+            if let Some(depth_stencil) = DepthStencil::new(
+                self.gfx_ctx.clone(),
+                config.depth_stencil,
+                self.scene_size(),
+            ) {
+                self.viewport_clear = ViewportClear::new(
+                    self.gfx_ctx.clone(),
+                    self.scene_color_format,
+                    depth_stencil.mode().as_texture_format(),
+                );
+                self.camera_stacks
+                    .set_depth_format(depth_stencil.mode().as_texture_format());
+                self.planar_reflections
+                    .set_depth_format(depth_stencil.mode().as_texture_format());
+                self.pipeline_cache
+                    .set_depth_stencil_mode(depth_stencil.mode());
+                self.depth_stencil = depth_stencil;
+            }
+        }
+
+        if current.quality != config.quality {
+            self.quality = config.quality;
+            self.resolve_quality_preset();
+        }
+
+        if current.color_space != config.color_space {
+            self.set_color_space(config.color_space);
+        }
+    }
+
+    /// Tier detected at startup, or `None` if the detection was disabled.
+    pub fn detected_tier(&self) -> Option<RenderTier> {
+        self.tier_report.as_ref().map(|report| report.tier)
+    }
+
+    /// The detected tier with the capabilities and the benchmark it was detected from.
+    pub fn tier_report(&self) -> Option<&RenderTierReport> {
+        self.tier_report.as_ref()
+    }
+
+    /// Sets the detected tier. Under [`QualitySetting::Auto`], its preset is applied.
+    pub fn set_tier_report(&mut self, tier_report: RenderTierReport) {
+        self.tier_report = Some(tier_report);
+        self.resolve_quality_preset();
+    }
+
+    /// Tier of the applied quality preset, or `None` if it is a custom one.
+    pub fn quality_tier(&self) -> Option<RenderTier> {
+        self.quality_tier
+    }
+
+    pub fn quality_preset(&self) -> &QualityPreset {
+        &self.quality_preset
+    }
+
+    /// Returns the quality preset if it has changed since the last call, so that the engine can apply it
+    /// and notify the game code.
+    pub(crate) fn take_changed_quality_preset(
+        &mut self,
+    ) -> Option<(Option<RenderTier>, QualityPreset)> {
+        if !self.is_quality_preset_pending {
+            return None;
+        }
+
+        self.is_quality_preset_pending = false;
+        Some((self.quality_tier, self.quality_preset))
+    }
+
+    fn resolve_quality_preset(&mut self) {
+        let (tier, preset) = self.quality.resolve(self.detected_tier());
+
+        if (tier, preset) != (self.quality_tier, self.quality_preset) {
+            self.quality_tier = tier;
+            self.quality_preset = preset;
+            self.is_quality_preset_pending = true;
+        }
+    }
+
+    pub fn max_frames_in_flight(&self) -> u32 {
+        self.frame_fences.max_frames_in_flight()
+    }
+
+    /// Limits how many frames may be submitted to the GPU without being finished, clamped to `1..=3`.
+    /// A new frame is not encoded until one of them finishes. Defaults to 2.
+    ///
+    /// Lower values reduce input latency at the cost of overlapping less CPU and GPU work.
+    /// With vsync on, the swapchain also blocks in `get_current_texture` once it is full,
+    /// so the effective latency is bounded by both the limit and the swapchain depth;
+    /// a limit of 1 keeps the CPU from running ahead of the display.
+    /// With vsync off, this limit is the only thing that stops the CPU from queueing frames.
+    pub fn set_max_frames_in_flight(&mut self, max_frames_in_flight: u32) {
+        self.frame_fences
+            .set_max_frames_in_flight(max_frames_in_flight);
+    }
+
+    pub fn wait_for_present(&self) -> bool {
+        self.wait_for_present
+    }
+
+    /// If set, every present blocks until the GPU has finished the frame, so that the next frame
+    /// samples input as late as possible. This trades throughput for latency, and combined with vsync
+    /// it may halve the frame rate of frames that miss a vblank.
+    pub fn set_wait_for_present(&mut self, wait_for_present: bool) {
+        self.wait_for_present = wait_for_present;
+    }
+
+    /// Timestamps an input event, to measure the latency of the frame that handles it.
+    pub fn record_input(&mut self, time: Instant) {
+        self.input_latency.record_input(time);
+    }
+
+    /// Timings and counters of the last presented frame.
+    pub fn frame_report(&self) -> FrameReport {
+        self.frame_report
+    }
+
+    /// Returns `true` if whole frames are timed on the GPU, feeding [`FrameReport::gpu_ms`].
+    pub fn is_gpu_timing(&self) -> bool {
+        self.gpu_timer.is_some()
+    }
+
+    /// Turns GPU frame timing on or off. It is on by default where supported, and costs two timestamp queries and
+    /// a readback per frame. Returns `false` if the device does not support timestamp queries.
+    pub fn set_gpu_timing(&mut self, enabled: bool) -> bool {
+        if !enabled {
+            self.gpu_timer = None;
+            return true;
+        }
+
+        if self.gpu_timer.is_none() {
+            self.gpu_timer = GpuTimer::new(&self.gfx_ctx);
+        }
+
+        self.gpu_timer.is_some()
+    }
+
+    /// Batches the small buffer writes of the frame; they land before its passes.
+    pub fn uploader_mut(&mut self) -> &mut Uploader {
+        self.frame_buffer_allocator.uploader_mut()
+    }
+
+    /// Allocates the buffers living for the current frame only, e.g. the segments of a [`LineRenderer`](super::LineRenderer).
+    pub fn frame_buffer_allocator_mut(&mut self) -> &mut FrameBufferAllocator {
+        &mut self.frame_buffer_allocator
+    }
+
+    pub fn encoder_threads(&self) -> usize {
+        self.encoder_threads
+    }
+
+    /// Records the draws of each camera pass on up to `encoder_threads` threads, at least 1. Defaults to 1.
+    ///
+    /// The sorted commands of a camera are split into contiguous ranges, each one recorded into a render bundle
+    /// on its own thread, and the bundles are executed in order in the camera pass. Short command lists are not
+    /// split, as spawning the threads costs more than recording them. Frames whose custom passes must run
+    /// between camera passes are recorded on a single thread.
+    pub fn set_encoder_threads(&mut self, encoder_threads: usize) {
+        self.encoder_threads = encoder_threads.max(1);
+    }
+
+    /// Time each thread spent recording the camera passes of the last presented frame, in milliseconds,
+    /// summed over the cameras.
+    pub fn encoder_thread_ms(&self) -> &[f32] {
+        &self.last_encoder_thread_ms
+    }
+
+    /// Formats of the scene and the depth stencil, which camera passes recorded in bundles must match.
+    pub fn bundle_target_format(&self) -> BundleTargetFormat {
+        BundleTargetFormat {
+            color: self.scene_color_format,
+            depth_stencil: self.depth_stencil.mode().as_texture_format(),
+        }
+    }
+
+    /// Adds the time each thread spent recording a camera pass, for the frame report.
+    pub fn record_encoder_timings(&mut self, thread_ms: &[f32]) {
+        if self.encoder_thread_ms.len() < thread_ms.len() {
+            self.encoder_thread_ms.resize(thread_ms.len(), 0.0);
+        }
+
+        for (total, ms) in self.encoder_thread_ms.iter_mut().zip(thread_ms) {
+            *total += ms;
+        }
+    }
+
+    pub fn is_frustum_culling_enabled(&self) -> bool {
+        self.frustum_culling
+    }
+
+    /// Turns off the culling of mesh renderers outside of the camera frustums, e.g. to debug their bounds.
+    /// Enabled by default.
+    pub fn set_frustum_culling(&mut self, enabled: bool) {
+        self.frustum_culling = enabled;
+    }
+
+    pub fn is_opaque_front_to_back(&self) -> bool {
+        self.opaque_front_to_back
+    }
+
+    /// Turns off the front-to-back sorting of the opaque queue, drawing it in the order the objects are iterated.
+    /// The transparent queue is always sorted back to front. Enabled by default.
+    pub fn set_opaque_front_to_back(&mut self, enabled: bool) {
+        self.opaque_front_to_back = enabled;
+    }
+
+    /// Counts the mesh renderers a camera skipped for being outside of its frustum, for the frame report.
+    pub fn record_culled_meshes(&mut self, culled: u32) {
+        self.meshes_culled += culled;
+    }
+
+    /// Adds the time a camera spent picking the local lights of its mesh renderers, for the frame report.
+    pub fn record_light_culling(&mut self, ms: f32) {
+        self.light_culling_ms += ms;
+    }
+
+    /// Counts the terrain chunks a camera drew and culled, for the frame report.
+    pub fn record_terrain_chunks(&mut self, drawn: u32, culled: u32) {
+        self.terrain_chunks.0 += drawn;
+        self.terrain_chunks.1 += culled;
+    }
+
+    /// Counts the draw commands of a camera pass and the HLOD proxies among them, for the frame report.
+    pub fn record_draw_calls(&mut self, draw_calls: u32, hlod_proxies: u32) {
+        self.draw_calls.0 += draw_calls;
+        self.draw_calls.1 += hlod_proxies;
+    }
+
+    /// Counts the distinct materials the mesh renderers of a camera pass were drawn with, for the frame report.
+    pub fn record_material_batches(&mut self, material_batches: u32) {
+        self.material_batches += material_batches;
+    }
+
+    /// Waits until the frame may start under the frames-in-flight limit. Must be called before encoding.
+    pub fn begin_frame(&mut self) {
+        let device = &self.gfx_ctx.device;
+        let wait_start = Instant::now();
+        self.frame_fences.wait_for_slot(|submission| {
+            device.poll(Maintain::WaitForSubmissionIndex(submission.clone()));
+        });
+        self.frame_wait_ms = wait_start.elapsed().as_secs_f32() * 1000.0;
+        self.input_latency.begin_frame();
+
+        let surface_output = self.surface_output();
+        self.post_effects
+            .set_output(self.color_space, surface_output);
+
+        let scene_color_format = if self.post_effects.is_active() {
+            POST_PROCESS_FORMAT
+        } else {
+            surface_output.format
+        };
+        self.set_scene_color_format(scene_color_format);
+    }
+
+    /// Switches every pass drawing the scene to the format, e.g. as the post-processing stack turns on or off.
+    fn set_scene_color_format(&mut self, color_format: TextureFormat) {
+        if color_format == self.scene_color_format {
+            return;
+        }
+
+        self.scene_color_format = color_format;
+        self.pipeline_cache.set_color_format(color_format);
+        self.camera_stacks.set_color_format(color_format);
+        self.planar_reflections.set_color_format(color_format);
+        self.viewport_clear = ViewportClear::new(
+            self.gfx_ctx.clone(),
+            color_format,
+            self.depth_stencil.mode().as_texture_format(),
+        );
+
+        // The fog is composited with a pipeline of the format.
+        if let Some(settings) = self.volumetric_fog.as_ref().map(|fog| *fog.settings()) {
+            self.volumetric_fog = Some(VolumetricFog::new(
+                self.gfx_ctx.clone(),
+                settings,
+                color_format,
+            ));
+        }
+    }
+
+    /// Abandons the frame begun by [`begin_frame`](Self::begin_frame), e.g. if the surface texture could not be
+    /// acquired. The buffer writes of the frame are still submitted, as later frames build on them.
+    pub fn skip_frame(&mut self) {
+        self.gfx_ctx
+            .queue
+            .submit(std::iter::once(self.frame_buffer_allocator.finish()));
+
+        let fence = FrameFence::new();
+        self.frame_buffer_allocator.recall(&fence);
+        self.gfx_ctx
+            .queue
+            .on_submitted_work_done(move || fence.signal());
+
+        self.meshes_culled = 0;
+        self.light_culling_ms = 0.0;
+        self.terrain_chunks = (0, 0);
+        self.draw_calls = (0, 0);
+        self.material_batches = 0;
+        self.encoder_thread_ms.clear();
+    }
+
+    /// Acquires the texture to render the frame into: the next surface texture, or the offscreen texture if the
+    /// context is headless.
+    pub fn acquire_frame_target(&self) -> Result<FrameTarget, SurfaceError> {
+        match (&self.gfx_ctx.surface, &self.offscreen_frame_texture) {
+            (Some(surface), _) => surface.get_current_texture().map(FrameTarget::Surface),
+            (None, Some(texture)) => Ok(FrameTarget::Offscreen(texture.clone())),
+            (None, None) => Err(SurfaceError::Lost),
+        }
     }
 
     pub fn create_encoder(&self) -> CommandEncoder {
@@ -96,16 +952,77 @@ impl RenderManager {
             .create_command_encoder(&CommandEncoderDescriptor { label: None })
     }
 
+    /// Begins a render pass drawing into the surface.
+    /// With a `viewport`, only it is cleared and drawn into; `None` stands for the whole surface.
     pub fn begin_frame_buffer_render_pass<'e>(
         &'e self,
         encoder: &'e mut CommandEncoder,
         surface_texture_view: &'e TextureView,
         clear_mode: &CameraClearMode,
+        viewport: Option<ViewportRect>,
     ) -> Result<RenderPass<'e>, SurfaceError> {
-        let render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        Ok(self.begin_viewport_render_pass(
+            encoder,
+            surface_texture_view,
+            self.depth_stencil.texture_view(),
+            clear_mode,
+            viewport,
+        ))
+    }
+
+    /// Begins a render pass drawing into targets of the size of the surface, e.g. the layer of a camera stack.
+    /// With a `viewport`, only it is cleared and drawn into; `None` stands for the whole target.
+    pub fn begin_viewport_render_pass<'e>(
+        &'e self,
+        encoder: &'e mut CommandEncoder,
+        color_view: &'e TextureView,
+        depth_stencil_view: Option<&'e TextureView>,
+        clear_mode: &CameraClearMode,
+        viewport: Option<ViewportRect>,
+    ) -> RenderPass<'e> {
+        let viewport = match viewport {
+            Some(viewport) => viewport,
+            None => {
+                return Self::begin_render_target_pass(
+                    encoder,
+                    color_view,
+                    depth_stencil_view,
+                    clear_mode,
+                )
+            }
+        };
+
+        let mut render_pass = Self::begin_render_target_pass(
+            encoder,
+            color_view,
+            depth_stencil_view,
+            &CameraClearMode::Keep,
+        );
+        self.viewport_clear
+            .draw(&mut render_pass, clear_mode, viewport);
+        render_pass.set_viewport(
+            viewport.x as f32,
+            viewport.y as f32,
+            viewport.width as f32,
+            viewport.height as f32,
+            0.0,
+            1.0,
+        );
+        render_pass.set_scissor_rect(viewport.x, viewport.y, viewport.width, viewport.height);
+        render_pass
+    }
+
+    /// Begins a render pass drawing into arbitrary color and depth targets, e.g. an offscreen render texture.
+    pub fn begin_render_target_pass<'e>(
+        encoder: &'e mut CommandEncoder,
+        color_view: &'e TextureView,
+        depth_stencil_view: Option<&'e TextureView>,
+        clear_mode: &CameraClearMode,
+    ) -> RenderPass<'e> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[Some(RenderPassColorAttachment {
-                view: &surface_texture_view,
+                view: color_view,
                 resolve_target: None,
                 ops: Operations {
                     load: match clear_mode {
@@ -121,7 +1038,7 @@ impl RenderManager {
                     store: true,
                 },
             })],
-            depth_stencil_attachment: self.depth_stencil.texture_view().map(|view| {
+            depth_stencil_attachment: depth_stencil_view.map(|view| {
                 RenderPassDepthStencilAttachment {
                     view,
                     depth_ops: Some(Operations {
@@ -142,17 +1059,17 @@ impl RenderManager {
                     }),
                 }
             }),
-        });
-        Ok(render_pass)
+        })
     }
 
     /// Constructs a rendering command for the given object by encoding per-instance data into a buffer.
+    /// Returns `None` if there's nothing to draw.
     pub fn build_rendering_command<'r>(
         &mut self,
         object_id: ObjectId,
         object_hierarchy: &ObjectHierarchy,
         renderer: &'r dyn Renderer,
-    ) -> RenderingCommand<'r> {
+    ) -> Option<RenderingCommand<'r>> {
         build_rendering_command(
             object_id,
             object_hierarchy,
@@ -161,11 +1078,431 @@ impl RenderManager {
         )
     }
 
+    /// Builds one command drawing the renderers of several objects, see [`build_batched_rendering_command`].
+    pub fn build_batched_rendering_command<'r>(
+        &mut self,
+        members: &[(ObjectId, &'r dyn Renderer)],
+        object_hierarchy: &ObjectHierarchy,
+    ) -> Option<RenderingCommand<'r>> {
+        build_batched_rendering_command(members, object_hierarchy, &mut self.frame_buffer_allocator)
+    }
+
+    /// Builds a command drawing a renderer without an object, see [`build_detached_rendering_command`].
+    pub fn build_detached_rendering_command<'r>(
+        &mut self,
+        matrix: &Mat4,
+        renderer: &'r dyn Renderer,
+    ) -> Option<RenderingCommand<'r>> {
+        build_detached_rendering_command(matrix, renderer, &mut self.frame_buffer_allocator)
+    }
+
     pub fn finish_frame(&mut self, command_buffers: Vec<CommandBuffer>) {
-        self.gfx_ctx.queue.submit(
-            std::iter::once(self.frame_buffer_allocator.finish())
-                .chain(command_buffers.into_iter()),
+        // The frame is timed by encoders wrapping everything submitted for it.
+        let mut timed_slot = None;
+        let mut timing_begin = None;
+        let mut timing_end = None;
+
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            let device = &self.gfx_ctx.device;
+            let mut begin_encoder = device.create_command_encoder(&Default::default());
+
+            if let Some(slot) = gpu_timer.begin(&mut begin_encoder) {
+                let mut end_encoder = device.create_command_encoder(&Default::default());
+                gpu_timer.end(&mut end_encoder, slot);
+                timed_slot = Some(slot);
+                timing_begin = Some(begin_encoder.finish());
+                timing_end = Some(end_encoder.finish());
+            }
+        }
+
+        let submission = self.gfx_ctx.queue.submit(
+            timing_begin
+                .into_iter()
+                .chain(std::iter::once(self.frame_buffer_allocator.finish()))
+                .chain(command_buffers)
+                .chain(timing_end),
+        );
+        let fence = self.frame_fences.push(submission);
+        self.frame_buffer_allocator.recall(&fence);
+
+        if let (Some(gpu_timer), Some(slot)) = (&mut self.gpu_timer, timed_slot) {
+            gpu_timer.read_back(slot);
+        }
+
+        if let Some(volumetric_fog) = &mut self.volumetric_fog {
+            volumetric_fog.read_back_timing();
+        }
+
+        self.frame_capture.read_back();
+
+        // Fires once everything submitted so far, this frame included, is done.
+        self.readbacks.submitted(&fence);
+        self.gfx_ctx
+            .queue
+            .on_submitted_work_done(move || fence.signal());
+    }
+
+    /// Presents the frame finished by [`finish_frame`](Self::finish_frame) and updates the frame report.
+    pub fn present(&mut self, frame_target: FrameTarget) {
+        frame_target.present();
+        let presented = Instant::now();
+
+        let frames_in_flight = self.frame_fences.frames_in_flight();
+
+        if self.wait_for_present {
+            let device = &self.gfx_ctx.device;
+            self.frame_fences.wait_until_below(1, |submission| {
+                device.poll(Maintain::WaitForSubmissionIndex(submission.clone()));
+            });
+        }
+
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.collect();
+        }
+
+        if let Some(volumetric_fog) = &mut self.volumetric_fog {
+            volumetric_fog.collect_timing();
+        }
+
+        self.readbacks.collect();
+
+        self.frame_report = FrameReport {
+            frames_in_flight,
+            wait_ms: self.frame_wait_ms,
+            input_latency_ms: self.input_latency.end_frame(presented),
+            gpu_ms: self.gpu_timer.as_ref().and_then(GpuTimer::last_ms),
+            fog_gpu_ms: self.volumetric_fog.as_ref().and_then(VolumetricFog::gpu_ms),
+            meshes_culled: self.meshes_culled,
+            light_culling_ms: self.light_culling_ms,
+            terrain_chunks_drawn: self.terrain_chunks.0,
+            terrain_chunks_culled: self.terrain_chunks.1,
+            draw_calls: self.draw_calls.0,
+            hlod_proxies_drawn: self.draw_calls.1,
+            material_batches: self.material_batches,
+            pipeline_cache: self.pipeline_cache.take_stats(),
+            frame_buffer_bytes: self.frame_buffer_allocator.last_allocated_bytes(),
+            frame_buffers: self.frame_buffer_allocator.stats(),
+            uploads: self.frame_buffer_allocator.uploader().stats(),
+            encoder_threads: self.encoder_thread_ms.len() as u32,
+            encode_ms: self.encoder_thread_ms.iter().copied().fold(0.0, f32::max),
+        };
+        self.meshes_culled = 0;
+        self.light_culling_ms = 0.0;
+        self.terrain_chunks = (0, 0);
+        self.draw_calls = (0, 0);
+        self.material_batches = 0;
+        self.last_encoder_thread_ms = take(&mut self.encoder_thread_ms);
+    }
+}
+
+/// Range the render scale is clamped into.
+pub const RENDER_SCALE_RANGE: RangeInclusive<f32> = 0.25..=2.0;
+
+/// Scales the size, keeping at least one pixel unless the size is empty, e.g. while the window is minimized.
+fn scaled_size(size: PhysicalSize<u32>, scale: f32) -> PhysicalSize<u32> {
+    let scale = |length: u32| match length {
+        0 => 0,
+        length => ((length as f32 * scale).round() as u32).max(1),
+    };
+    PhysicalSize::new(scale(size.width), scale(size.height))
+}
+
+fn create_offscreen_frame_texture_if_headless(gfx_ctx: &GfxContextHandle) -> Option<Arc<Texture>> {
+    gfx_ctx.is_headless().then(|| {
+        Arc::new(create_offscreen_frame_texture(
+            &gfx_ctx.surface_config.read(),
+            &gfx_ctx.device,
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gfx::{
+            create_test_gfx, BindGroupProvider, CachedPipeline, Color, HeadlessGfx,
+            InstanceDataProvider, LineRenderer, LineSubRenderer, Material, MaterialHandle,
+            Renderer, VertexBufferProvider, BUILT_IN_SHADER_LINE,
+        },
+        math::Vec3,
+        object::{ObjectHierarchy, ObjectId},
+        util::FrameArena,
+    };
+    use image::RgbaImage;
+    use parking_lot::RwLockReadGuard;
+    use wgpu::ErrorFilter;
+
+    /// Draws the vertices as they are, without bindings or per-instance inputs.
+    const CLIP_SPACE_LINE_SHADER: &str = r#"
+struct VertexInput {
+  @location(0) position: vec3<f32>,
+  @location(1) vertex_color: vec4<f32>,
+};
+
+struct VertexOutput {
+  @builtin(position) position: vec4<f32>,
+  @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput) -> VertexOutput {
+  var out: VertexOutput;
+  out.position = vec4<f32>(vertex.position, 1.0);
+  out.color = vertex.vertex_color;
+  return out;
+}
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  var out: FragmentOutput;
+  out.color = in.color;
+  return out;
+}
+"#;
+
+    /// A renderer whose vertices are gone, e.g. an empty mesh from a corrupted file.
+    struct WithoutVertices<'r>(&'r dyn Renderer);
+
+    impl Renderer for WithoutVertices<'_> {
+        fn pipeline(&self) -> CachedPipeline {
+            self.0.pipeline()
+        }
+
+        fn material(&self) -> RwLockReadGuard<Material> {
+            self.0.material()
+        }
+
+        fn instance_count(&self) -> u32 {
+            self.0.instance_count()
+        }
+
+        fn vertex_count(&self) -> u32 {
+            0
+        }
+
+        fn bind_group_provider(&self) -> &dyn BindGroupProvider {
+            self.0.bind_group_provider()
+        }
+
+        fn vertex_buffer_provider(&self) -> &dyn VertexBufferProvider {
+            self.0.vertex_buffer_provider()
+        }
+
+        fn instance_data_provider(&self) -> &dyn InstanceDataProvider {
+            self.0.instance_data_provider()
+        }
+    }
+
+    /// A red diagonal across the target, drawn by a shader without per-instance inputs.
+    fn clip_space_line(gfx: &mut HeadlessGfx) -> (LineRenderer, LineSubRenderer) {
+        let (render_mgr, shader_mgr, _) = gfx.split_mut();
+        let shader = shader_mgr
+            .create_shader(render_mgr.bind_group_layout_cache(), CLIP_SPACE_LINE_SHADER)
+            .unwrap();
+        let material = Material::new(shader, render_mgr.pipeline_layout_cache());
+
+        let mut line_renderer = LineRenderer::new();
+        line_renderer.set_material(MaterialHandle::new(material));
+        line_renderer.set_depth_test(false);
+        line_renderer.push_segment(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Color::red(),
+        );
+        line_renderer.upload(
+            render_mgr.frame_buffer_allocator_mut(),
+            &FrameArena::with_capacity(1024),
+            ColorSpaceMode::Gamma,
+        );
+        let sub_renderer = line_renderer
+            .sub_renderer(shader_mgr, render_mgr.pipeline_cache())
+            .unwrap();
+
+        (line_renderer, sub_renderer)
+    }
+
+    /// Renders a frame drawing the commands built by `build`, failing on any validation error.
+    fn render_without_validation_errors<'r>(
+        gfx: &mut HeadlessGfx,
+        build: impl FnOnce(&mut RenderManager) -> Vec<RenderingCommand<'r>>,
+    ) -> RgbaImage {
+        let gfx_ctx = gfx.gfx_ctx().clone();
+        gfx_ctx.device.push_error_scope(ErrorFilter::Validation);
+
+        let image = gfx
+            .render_frame(|render_mgr, encoder, scene_view| {
+                let commands = build(render_mgr);
+                // The shader has no bindings, so none of these are set.
+                let bind_group = render_mgr.lights().bind_group().clone();
+                let clear_mode = CameraClearMode::All {
+                    color: Color::black(),
+                    depth: 1.0,
+                    stencil: 0,
+                };
+                let mut render_pass = render_mgr
+                    .begin_frame_buffer_render_pass(encoder, scene_view, &clear_mode, None)
+                    .unwrap();
+
+                for command in &commands {
+                    command.render_in_pass(&mut render_pass, &bind_group, &bind_group, &bind_group);
+                }
+            })
+            .unwrap();
+
+        let error = pollster::block_on(gfx_ctx.device.pop_error_scope());
+        assert!(error.is_none(), "{}", error.unwrap());
+        image
+    }
+
+    #[test]
+    fn check_scaled_size() {
+        let size = PhysicalSize::new(1920, 1080);
+        assert_eq!(scaled_size(size, 1.0), size);
+        assert_eq!(scaled_size(size, 0.5), PhysicalSize::new(960, 540));
+        assert_eq!(scaled_size(size, 0.67), PhysicalSize::new(1286, 724));
+        assert_eq!(
+            scaled_size(PhysicalSize::new(1, 0), 0.25),
+            PhysicalSize::new(1, 0)
+        );
+    }
+
+    #[test]
+    fn check_scaled_scene_fills_the_surface() {
+        let mut gfx = match create_test_gfx(64, 32, DepthStencilMode::DepthStencil) {
+            Some(gfx) => gfx,
+            None => return,
+        };
+
+        gfx.render_mgr_mut().set_render_scale(0.5);
+        assert_eq!(gfx.render_mgr().scene_size(), PhysicalSize::new(32, 16));
+
+        let image = gfx
+            .render_frame(|render_mgr, encoder, scene_view| {
+                let clear_mode = CameraClearMode::All {
+                    color: Color::red(),
+                    depth: 1.0,
+                    stencil: 0,
+                };
+                // The depth buffer follows the scene, so the pass would fail on a mismatch.
+                render_mgr
+                    .begin_frame_buffer_render_pass(encoder, scene_view, &clear_mode, None)
+                    .unwrap();
+            })
+            .unwrap();
+
+        assert_eq!(image.dimensions(), (64, 32));
+        assert!(image.pixels().all(|pixel| pixel.0 == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn check_zero_vertex_renderers_are_skipped() {
+        let mut gfx = match create_test_gfx(16, 16, DepthStencilMode::DepthOnly) {
+            Some(gfx) => gfx,
+            None => return,
+        };
+        let (_line_renderer, sub_renderer) = clip_space_line(&mut gfx);
+        let empty = WithoutVertices(&sub_renderer);
+        let object_hierarchy = ObjectHierarchy::new();
+
+        let image = render_without_validation_errors(&mut gfx, |render_mgr| {
+            assert!(render_mgr
+                .build_detached_rendering_command(&Mat4::identity(), &empty)
+                .is_none());
+            assert!(render_mgr
+                .build_batched_rendering_command(
+                    &[(ObjectId::from_u32(0), &empty as &dyn Renderer)],
+                    &object_hierarchy,
+                )
+                .is_none());
+
+            Vec::from_iter(
+                render_mgr.build_detached_rendering_command(&Mat4::identity(), &sub_renderer),
+            )
+        });
+
+        assert!(image.pixels().any(|pixel| pixel.0 == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn check_zero_stride_instances_are_drawn_without_instance_data() {
+        let mut gfx = match create_test_gfx(16, 16, DepthStencilMode::DepthOnly) {
+            Some(gfx) => gfx,
+            None => return,
+        };
+        let (_line_renderer, sub_renderer) = clip_space_line(&mut gfx);
+        assert_eq!(
+            sub_renderer
+                .material()
+                .shader
+                .reflected_shader
+                .per_instance_input
+                .stride,
+            0
+        );
+
+        let image = render_without_validation_errors(&mut gfx, |render_mgr| {
+            let command = render_mgr
+                .build_detached_rendering_command(&Mat4::identity(), &sub_renderer)
+                .unwrap();
+            assert!(command.instance_buffer.is_none());
+            vec![command]
+        });
+
+        assert!(image.pixels().any(|pixel| pixel.0 == [255, 0, 0, 255]));
+        assert!(image.pixels().any(|pixel| pixel.0 == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn check_depth_stencil_change_drops_the_pipelines() {
+        let mut gfx = match create_test_gfx(16, 16, DepthStencilMode::DepthOnly) {
+            Some(gfx) => gfx,
+            None => return,
+        };
+        let (render_mgr, shader_mgr, built_in_shader_mgr) = gfx.split_mut();
+        let shader = built_in_shader_mgr
+            .find_shader(BUILT_IN_SHADER_LINE)
+            .unwrap();
+        let material = Material::new(shader, render_mgr.pipeline_layout_cache());
+
+        let mut line_renderer = LineRenderer::new();
+        line_renderer.set_material(MaterialHandle::new(material));
+
+        let mut obtain_pipeline = |render_mgr: &mut RenderManager| {
+            line_renderer.push_segment(
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 1.0),
+                Color::white(),
+            );
+            line_renderer.upload(
+                render_mgr.frame_buffer_allocator_mut(),
+                &FrameArena::with_capacity(1024),
+                ColorSpaceMode::Gamma,
+            );
+            line_renderer
+                .sub_renderer(shader_mgr, render_mgr.pipeline_cache())
+                .unwrap()
+                .pipeline()
+        };
+
+        let before = obtain_pipeline(render_mgr);
+        assert_eq!(render_mgr.pipeline_cache().len(), 1);
+
+        render_mgr.apply_config(&RenderPipelineConfig {
+            depth_stencil: DepthStencilMode::DepthStencil,
+            ..render_mgr.current_config()
+        });
+        assert!(render_mgr.pipeline_cache().is_empty());
+
+        let after = obtain_pipeline(render_mgr);
+        assert_ne!(before, after);
+        assert_eq!(
+            after.key().depth_stencil.as_ref().map(|state| state.format),
+            DepthStencilMode::DepthStencil.as_texture_format()
         );
-        self.frame_buffer_allocator.recall();
     }
 }
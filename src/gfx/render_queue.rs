@@ -0,0 +1,189 @@
+use crate::{
+    math::{Mat4, Vec3},
+    util::FrameArena,
+};
+use std::cmp::Ordering;
+
+/// When a material is drawn within a camera pass. The opaque queue is drawn first, then the transparent one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RenderQueue {
+    /// Drawn front to back with depth writes, so that hidden fragments are rejected early.
+    #[default]
+    Opaque,
+    /// Drawn back to front without depth writes, so that blended surfaces composite over what is behind them.
+    /// The depth test stays on, so opaque surfaces still hide them.
+    Transparent,
+}
+
+impl RenderQueue {
+    /// Returns `false` for the transparent queue, whose pipelines must not occlude each other.
+    pub fn depth_write_enabled(self) -> bool {
+        self == RenderQueue::Opaque
+    }
+}
+
+/// An item to draw, e.g. a rendering command, with where it goes in the queues.
+#[derive(Debug, Clone)]
+pub struct QueuedItem<T> {
+    pub queue: RenderQueue,
+    /// Sub-order of the material within its queue; lower orders are drawn first, whatever their depth.
+    pub order: i32,
+    /// View-space depth of the object, see [`view_depth`].
+    pub depth: f32,
+    pub item: T,
+}
+
+/// Returns how far the position is in front of the camera, along the camera's view direction (-Z).
+pub fn view_depth(camera_transform: &Mat4, position: Vec3) -> f32 {
+    let forward = -Vec3::from(camera_transform.row(2)).normalized();
+    Vec3::dot(position - Vec3::from(camera_transform.row(3)), forward)
+}
+
+/// Sorts the items into drawing order: the opaque queue before the transparent one and lower sub-orders first.
+/// Opaque items of the same sub-order are drawn front to back if `opaque_front_to_back` is set, and transparent
+/// ones always back to front. Items otherwise equal keep their order.
+///
+/// The sort keys are the indices of the items, sorted in `frame_alloc` instead of the heap a stable sort would use.
+pub fn sort_render_queue<T>(
+    items: &mut [QueuedItem<T>],
+    opaque_front_to_back: bool,
+    frame_alloc: &FrameArena,
+) {
+    let mut order = frame_alloc.alloc_vec(items.len());
+    order.extend(0..items.len());
+    order.sort_unstable_by(|&lhs_index, &rhs_index| {
+        let (lhs, rhs) = (&items[lhs_index], &items[rhs_index]);
+        lhs.queue
+            .cmp(&rhs.queue)
+            .then(lhs.order.cmp(&rhs.order))
+            .then_with(|| match lhs.queue {
+                RenderQueue::Opaque if opaque_front_to_back => lhs.depth.total_cmp(&rhs.depth),
+                RenderQueue::Opaque => Ordering::Equal,
+                RenderQueue::Transparent => rhs.depth.total_cmp(&lhs.depth),
+            })
+            .then(lhs_index.cmp(&rhs_index))
+    });
+
+    // Moves the items into place a cycle of the permutation at a time, marking the placed ones.
+    for start in 0..order.len() {
+        let mut current = start;
+
+        loop {
+            let next = order[current];
+            order[current] = current;
+
+            if next == start {
+                break;
+            }
+
+            items.swap(current, next);
+            current = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(
+        queue: RenderQueue,
+        order: i32,
+        depth: f32,
+        item: &'static str,
+    ) -> QueuedItem<&'static str> {
+        QueuedItem {
+            queue,
+            order,
+            depth,
+            item,
+        }
+    }
+
+    fn sorted(
+        mut items: Vec<QueuedItem<&'static str>>,
+        opaque_front_to_back: bool,
+    ) -> Vec<&'static str> {
+        sort_render_queue(
+            &mut items,
+            opaque_front_to_back,
+            &FrameArena::with_capacity(1024),
+        );
+        Vec::from_iter(items.into_iter().map(|item| item.item))
+    }
+
+    #[test]
+    fn check_overlapping_transparent_quads_composite_regardless_of_creation_order() {
+        let near = queued(RenderQueue::Transparent, 0, 2.0, "near");
+        let far = queued(RenderQueue::Transparent, 0, 5.0, "far");
+
+        assert_eq!(
+            sorted(vec![near.clone(), far.clone()], true),
+            vec!["far", "near"]
+        );
+        assert_eq!(sorted(vec![far, near], true), vec!["far", "near"]);
+    }
+
+    #[test]
+    fn check_opaque_queue_is_drawn_before_transparent_queue() {
+        let items = vec![
+            queued(RenderQueue::Transparent, 0, 10.0, "glass"),
+            queued(RenderQueue::Opaque, 0, 1.0, "wall"),
+        ];
+
+        assert_eq!(sorted(items, true), vec!["wall", "glass"]);
+    }
+
+    #[test]
+    fn check_opaque_queue_is_drawn_front_to_back_only_if_asked() {
+        let items = vec![
+            queued(RenderQueue::Opaque, 0, 8.0, "far"),
+            queued(RenderQueue::Opaque, 0, 1.0, "near"),
+        ];
+
+        assert_eq!(sorted(items.clone(), true), vec!["near", "far"]);
+        assert_eq!(sorted(items, false), vec!["far", "near"]);
+    }
+
+    #[test]
+    fn check_sub_order_takes_precedence_over_depth() {
+        let items = vec![
+            queued(RenderQueue::Transparent, 1, 10.0, "overlay"),
+            queued(RenderQueue::Transparent, 0, 1.0, "smoke"),
+            queued(RenderQueue::Transparent, 0, 3.0, "window"),
+        ];
+
+        assert_eq!(sorted(items, true), vec!["window", "smoke", "overlay"]);
+    }
+
+    #[test]
+    fn check_equal_items_keep_their_order() {
+        let items = vec![
+            queued(RenderQueue::Transparent, 0, 1.0, "smoke"),
+            queued(RenderQueue::Opaque, 0, 4.0, "floor"),
+            queued(RenderQueue::Transparent, 0, 1.0, "dust"),
+            queued(RenderQueue::Opaque, 0, 4.0, "wall"),
+            queued(RenderQueue::Opaque, 1, 0.0, "decal"),
+            queued(RenderQueue::Opaque, 0, 2.0, "crate"),
+        ];
+
+        assert_eq!(
+            sorted(items.clone(), false),
+            vec!["floor", "wall", "crate", "decal", "smoke", "dust"]
+        );
+        assert_eq!(
+            sorted(items, true),
+            vec!["crate", "floor", "wall", "decal", "smoke", "dust"]
+        );
+    }
+
+    #[test]
+    fn check_view_depth_is_along_the_view_direction() {
+        let camera = Mat4::look_at(Vec3::new(0.0, 0.0, 10.0), Vec3::ZERO, Vec3::UP);
+
+        assert!((view_depth(&camera, Vec3::ZERO) - 10.0).abs() < 1e-4);
+        // Off to the side at the same distance along the view direction.
+        assert!((view_depth(&camera, Vec3::new(5.0, 0.0, 0.0)) - 10.0).abs() < 1e-4);
+        assert!(view_depth(&camera, Vec3::new(0.0, 0.0, 20.0)) < 0.0);
+    }
+}
@@ -0,0 +1,128 @@
+use super::{BindGroupEntryResource, BindingPropKey, MaterialHandle, Texture};
+use codegen::HandleMut;
+use std::{fmt::Debug, sync::Arc};
+use wgpu::{Device, TextureFormat, TextureView};
+
+/// An offscreen color texture with a matching depth buffer, that cameras draw into instead of the surface,
+/// e.g. for mirrors, portals and minimaps. See [`Camera::target`](super::Camera::target).
+///
+/// The target keeps its size when the window is resized; resize it explicitly with [`RenderTarget::resize`].
+#[derive(HandleMut)]
+pub struct RenderTarget {
+    color: Texture,
+    depth: Option<Texture>,
+    format: TextureFormat,
+    depth_format: Option<TextureFormat>,
+}
+
+impl RenderTarget {
+    /// `format` must match the color targets of the materials drawn into it,
+    /// [`RenderManager::scene_color_format`](super::RenderManager::scene_color_format) for the built-in ones.
+    /// `depth_format` should be the one of the surface passes, so that the same pipelines draw into both.
+    pub fn new(
+        width: u16,
+        height: u16,
+        format: TextureFormat,
+        depth_format: Option<TextureFormat>,
+        device: &Device,
+    ) -> Self {
+        let (color, depth) = create_textures(width, height, format, depth_format, device);
+
+        Self {
+            color,
+            depth,
+            format,
+            depth_format,
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.color.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.color.height
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    pub fn depth_format(&self) -> Option<TextureFormat> {
+        self.depth_format
+    }
+
+    /// The color texture, to be sampled once the cameras drawing into it have been rendered.
+    pub fn color(&self) -> &Texture {
+        &self.color
+    }
+
+    pub fn color_view(&self) -> &Arc<TextureView> {
+        &self.color.view
+    }
+
+    pub fn depth_view(&self) -> Option<&Arc<TextureView>> {
+        self.depth.as_ref().map(|depth| &depth.view)
+    }
+
+    /// Recreates the textures in the new size. Materials sampling the target must be bound again.
+    pub fn resize(&mut self, width: u16, height: u16, device: &Device) {
+        if width == self.width() && height == self.height() {
+            return;
+        }
+
+        (self.color, self.depth) =
+            create_textures(width, height, self.format, self.depth_format, device);
+    }
+
+    /// Binds the color texture and its sampler to the properties of the given names.
+    /// Returns `false` if the material lacks either of them.
+    pub fn bind_to_material(
+        &self,
+        material: &MaterialHandle,
+        texture_name: impl Into<String>,
+        sampler_name: impl Into<String>,
+        device: &Device,
+    ) -> bool {
+        let mut material = material.write();
+        let is_texture_bound = material.set_bind_property(
+            &BindingPropKey::StringKey(texture_name.into()),
+            BindGroupEntryResource::TextureView {
+                texture_view: self.color.view.clone(),
+            },
+        );
+        let is_sampler_bound = material.set_bind_property(
+            &BindingPropKey::StringKey(sampler_name.into()),
+            BindGroupEntryResource::Sampler {
+                sampler: self.color.sampler.clone(),
+            },
+        );
+        material.update_bind_group(device);
+
+        is_texture_bound && is_sampler_bound
+    }
+}
+
+impl Debug for RenderTargetHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let target = self.read();
+        f.debug_struct("RenderTargetHandle")
+            .field("width", &target.width())
+            .field("height", &target.height())
+            .field("format", &target.format)
+            .finish_non_exhaustive()
+    }
+}
+
+fn create_textures(
+    width: u16,
+    height: u16,
+    format: TextureFormat,
+    depth_format: Option<TextureFormat>,
+    device: &Device,
+) -> (Texture, Option<Texture>) {
+    (
+        Texture::create_render_target(width, height, format, device),
+        depth_format.map(|format| Texture::create_render_target(width, height, format, device)),
+    )
+}
@@ -7,7 +7,7 @@ impl GenericBuffer for Buffer {
         Arc::new(device.create_buffer(&BufferDescriptor {
             label: None,
             size: size.get(),
-            usage: BufferUsages::COPY_DST | BufferUsages::VERTEX,
+            usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC | BufferUsages::VERTEX,
             mapped_at_creation: false,
         }))
     }
@@ -1,18 +1,31 @@
-use super::{GenericBufferAllocation, GenericBufferPool, HostBuffer};
-use crate::gfx::GfxContextHandle;
-use std::mem::replace;
-use wgpu::{
-    util::StagingBelt, Buffer, BufferAddress, BufferSize, CommandBuffer, CommandEncoder,
-    CommandEncoderDescriptor, Device,
-};
+use super::{GenericBufferAllocation, GenericBufferPool, GenericBufferPoolStats, HostBuffer};
+use crate::gfx::{FrameFence, GfxContextHandle, Uploader};
+use wgpu::{Buffer, BufferAddress, BufferSize, CommandBuffer};
+
+/// Memory held by a [`FrameBufferAllocator`], for graphing it over time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameBufferAllocatorStats {
+    /// Pages the per-instance data and other frame data are encoded into on the CPU.
+    pub host: GenericBufferPoolStats,
+    /// Pages of the GPU buffers the encoded data are uploaded into.
+    pub device: GenericBufferPoolStats,
+}
+
+impl FrameBufferAllocatorStats {
+    pub fn reserved_bytes(&self) -> u64 {
+        self.host.reserved_bytes + self.device.reserved_bytes
+    }
+}
 
 /// A buffer allocator that can be used to allocate buffers for a single frame.
+/// It also owns the frame's [`Uploader`], through which the committed buffers are written.
 pub struct FrameBufferAllocator {
     gfx_context: GfxContextHandle,
-    staging_belt: StagingBelt,
-    staging_belt_encoder: CommandEncoder,
+    uploader: Uploader,
     host_buffer_list: GenericBufferPool<HostBuffer>,
     device_buffer_list: GenericBufferPool<Buffer>,
+    allocated_bytes: BufferAddress,
+    last_allocated_bytes: BufferAddress,
 }
 
 impl FrameBufferAllocator {
@@ -21,14 +34,52 @@ impl FrameBufferAllocator {
 
     pub fn new(gfx_context: GfxContextHandle) -> FrameBufferAllocator {
         Self {
-            staging_belt: StagingBelt::new(Self::PAGE_SIZE.get()),
-            staging_belt_encoder: create_staging_belt_encoder(&gfx_context.device),
+            uploader: Uploader::new(gfx_context.clone()),
             host_buffer_list: GenericBufferPool::new(Self::PAGE_SIZE),
             device_buffer_list: GenericBufferPool::new(Self::PAGE_SIZE),
+            allocated_bytes: 0,
+            last_allocated_bytes: 0,
             gfx_context,
         }
     }
 
+    pub fn uploader(&self) -> &Uploader {
+        &self.uploader
+    }
+
+    pub fn uploader_mut(&mut self) -> &mut Uploader {
+        &mut self.uploader
+    }
+
+    /// Bytes committed to device buffers in the frame recalled last.
+    pub fn last_allocated_bytes(&self) -> BufferAddress {
+        self.last_allocated_bytes
+    }
+
+    pub fn stats(&self) -> FrameBufferAllocatorStats {
+        FrameBufferAllocatorStats {
+            host: self.host_buffer_list.stats(),
+            device: self.device_buffer_list.stats(),
+        }
+    }
+
+    /// Frames a page is kept for without being allocated from, see [`GenericBufferPool`].
+    pub fn shrink_window(&self) -> u32 {
+        self.device_buffer_list.shrink_window()
+    }
+
+    pub fn set_shrink_window(&mut self, frames: u32) {
+        self.host_buffer_list.set_shrink_window(frames);
+        self.device_buffer_list.set_shrink_window(frames);
+    }
+
+    /// Releases the pages the current frame has not allocated from, e.g. once a loading screen is over. The ones the
+    /// frames in flight may use are dropped as they finish.
+    pub fn trim(&mut self) {
+        self.host_buffer_list.trim();
+        self.device_buffer_list.trim();
+    }
+
     pub fn alloc_staging_buffer(
         &mut self,
         size: BufferAddress,
@@ -54,37 +105,24 @@ impl FrameBufferAllocator {
         let device_allocation = self
             .device_buffer_list
             .allocate(&self.gfx_context.device, allocation.size());
-        let mut view = self.staging_belt.write_buffer(
-            &mut self.staging_belt_encoder,
-            device_allocation.buffer(),
-            device_allocation.offset(),
-            device_allocation.size(),
-            &self.gfx_context.device,
-        );
-
-        allocation.with_data(|data| view.copy_from_slice(data));
+        self.allocated_bytes += allocation.size().get();
+        allocation.with_data(|data| {
+            self.uploader
+                .write(device_allocation.buffer(), device_allocation.offset(), data)
+        });
 
         Some(device_allocation)
     }
 
     pub fn finish(&mut self) -> CommandBuffer {
-        self.staging_belt.finish();
-        replace(
-            &mut self.staging_belt_encoder,
-            create_staging_belt_encoder(&self.gfx_context.device),
-        )
-        .finish()
+        self.uploader.finish()
     }
 
-    pub fn recall(&mut self) {
-        self.staging_belt.recall();
-        self.host_buffer_list.recall();
-        self.device_buffer_list.recall();
+    /// Starts a new frame once the previous one has been submitted, `fence` being signaled when it is done.
+    pub fn recall(&mut self, fence: &FrameFence) {
+        self.last_allocated_bytes = std::mem::take(&mut self.allocated_bytes);
+        self.uploader.recall();
+        self.host_buffer_list.recall(fence);
+        self.device_buffer_list.recall(fence);
     }
 }
-
-fn create_staging_belt_encoder(device: &Device) -> CommandEncoder {
-    device.create_command_encoder(&CommandEncoderDescriptor {
-        label: Some("[frame buffer allocator] staging belt encoder"),
-    })
-}
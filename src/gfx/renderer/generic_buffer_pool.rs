@@ -1,4 +1,5 @@
-use std::{cmp::Ordering, sync::Arc};
+use crate::gfx::FrameFence;
+use std::{cmp::Ordering, collections::VecDeque, sync::Arc};
 use wgpu::{Buffer, BufferAddress, BufferSize, BufferSlice, Device};
 
 /// Represents a buffer that can be used to allocate sub buffers from.
@@ -42,6 +43,15 @@ where
         }
     }
 
+    /// Creates an allocation that refers to a buffer shared with others.
+    pub fn from_shared(buffer: Arc<T>, offset: BufferAddress, size: BufferSize) -> Self {
+        Self {
+            buffer,
+            offset,
+            size,
+        }
+    }
+
     pub fn buffer(&self) -> &Arc<T> {
         &self.buffer
     }
@@ -134,6 +144,8 @@ pub struct GenericBufferPage<T> {
     size: BufferSize,
     /// The amount of bytes that are already allocated in this page.
     allocated: BufferAddress,
+    /// The frame this page was allocated from last, counted by [`GenericBufferPool::recall`].
+    last_used_frame: u64,
 }
 
 impl<T> GenericBufferPage<T>
@@ -145,6 +157,7 @@ where
             buffer: T::allocate(device, size),
             size,
             allocated: 0,
+            last_used_frame: 0,
         }
     }
 
@@ -168,7 +181,82 @@ where
     }
 }
 
+/// Frames a page of a [`GenericBufferPool`] is kept for without being allocated from, by default.
+pub const DEFAULT_SHRINK_WINDOW: u32 = 120;
+
+/// Memory held by a [`GenericBufferPool`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GenericBufferPoolStats {
+    /// Bytes of the pages kept for allocating from.
+    pub reserved_bytes: u64,
+    /// Bytes of the pages released, but still waiting for the frames that may use them to finish.
+    pub retired_bytes: u64,
+    /// Bytes allocated in the frame recalled last.
+    pub used_bytes: u64,
+    /// Most bytes allocated in a frame over the shrink window.
+    pub peak_bytes: u64,
+    /// Pages kept for allocating from.
+    pub page_count: u32,
+}
+
+/// Bytes allocated per frame over a sliding window of frames.
+#[derive(Debug, Clone)]
+pub struct UsageWindow {
+    frames: u32,
+    bytes: VecDeque<u64>,
+}
+
+impl UsageWindow {
+    pub fn new(frames: u32) -> Self {
+        Self {
+            frames: frames.max(1),
+            bytes: VecDeque::new(),
+        }
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Resizes the window, forgetting the frames that no longer fit.
+    pub fn set_frames(&mut self, frames: u32) {
+        self.frames = frames.max(1);
+        self.truncate();
+    }
+
+    /// Records the bytes of a finished frame, pushing the oldest one out of the window if it is full.
+    pub fn push(&mut self, bytes: u64) {
+        self.bytes.push_back(bytes);
+        self.truncate();
+    }
+
+    /// Bytes of the frame recorded last.
+    pub fn last(&self) -> u64 {
+        self.bytes.back().copied().unwrap_or(0)
+    }
+
+    /// Most bytes of a frame in the window.
+    pub fn high_water_mark(&self) -> u64 {
+        self.bytes.iter().copied().max().unwrap_or(0)
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+    }
+
+    fn truncate(&mut self) {
+        while (self.frames as usize) < self.bytes.len() {
+            self.bytes.pop_front();
+        }
+    }
+}
+
 /// Holds a list of buffers of type `T` and allocates sub buffers from them.
+///
+/// Pages are kept across frames, growing to fit the heaviest frame. A page none of the frames of the shrink window
+/// allocated from is released, and so are all the unused ones on [`trim`](Self::trim). Released pages are only
+/// dropped once the fence of the frame that released them is signaled, so that no frame in flight loses a buffer it
+/// reads; holders of allocations keep theirs alive regardless.
 pub struct GenericBufferPool<T>
 where
     T: GenericBuffer,
@@ -177,6 +265,15 @@ where
     page_size: BufferSize,
     /// A list of buffers. It is guaranteed that the buffers are always sorted by size in ascending order.
     pages: Vec<GenericBufferPage<T>>,
+    /// Frames recalled so far.
+    frame: u64,
+    /// Bytes allocated in the current frame.
+    allocated_bytes: u64,
+    usage: UsageWindow,
+    /// Pages released, with the fence of the frame they were released in.
+    retired: Vec<(FrameFence, GenericBufferPage<T>)>,
+    /// Fence of the frame recalled last, signaled once everything that may use the pages so far is done.
+    last_fence: Option<FrameFence>,
 }
 
 impl<T> GenericBufferPool<T>
@@ -187,15 +284,83 @@ where
         Self {
             page_size,
             pages: Vec::new(),
+            frame: 0,
+            allocated_bytes: 0,
+            usage: UsageWindow::new(DEFAULT_SHRINK_WINDOW),
+            retired: Vec::new(),
+            last_fence: None,
         }
     }
 
-    /// Mark all pages as unused.
-    pub fn recall(&mut self) {
-        // TODO: Drop some pages to prevent memory leaks.
+    /// Frames a page is kept for without being allocated from.
+    pub fn shrink_window(&self) -> u32 {
+        self.usage.frames()
+    }
+
+    /// Sets the frames a page is kept for without being allocated from, at least one.
+    pub fn set_shrink_window(&mut self, frames: u32) {
+        self.usage.set_frames(frames);
+    }
+
+    pub fn stats(&self) -> GenericBufferPoolStats {
+        GenericBufferPoolStats {
+            reserved_bytes: self.pages.iter().map(|page| page.size.get()).sum(),
+            retired_bytes: self.retired.iter().map(|(_, page)| page.size.get()).sum(),
+            used_bytes: self.usage.last(),
+            peak_bytes: self.usage.high_water_mark(),
+            page_count: self.pages.len() as u32,
+        }
+    }
+
+    /// Marks all pages as unused once the frame has been submitted, `fence` being signaled when it is done. The
+    /// pages not allocated from over the shrink window are released.
+    pub fn recall(&mut self, fence: &FrameFence) {
+        self.drop_finished_pages();
+        self.usage.push(std::mem::take(&mut self.allocated_bytes));
+
+        let frame = self.frame;
+        let window = self.usage.frames() as u64;
+        let (pages, stale_pages) = std::mem::take(&mut self.pages)
+            .into_iter()
+            .partition::<Vec<_>, _>(|page| frame - page.last_used_frame < window);
+
+        self.pages = pages;
+        self.retire(stale_pages, Some(fence));
+
         for page in &mut self.pages {
             page.allocated = 0;
         }
+
+        // Pages filled unevenly are sorted by their available size, which is now their size.
+        self.pages.sort_by_key(|page| page.size);
+        self.frame += 1;
+        self.last_fence = Some(fence.clone());
+    }
+
+    /// Releases every page nothing has been allocated from since the last recall, e.g. once a loading screen is
+    /// over, and starts the high-water mark over. The pages the last frames may still use are dropped as they finish.
+    pub fn trim(&mut self) {
+        self.drop_finished_pages();
+
+        let (pages, unused_pages) = std::mem::take(&mut self.pages)
+            .into_iter()
+            .partition::<Vec<_>, _>(|page| page.allocated != 0);
+
+        self.pages = pages;
+        self.retire(unused_pages, self.last_fence.clone().as_ref());
+        self.usage.clear();
+    }
+
+    /// Keeps the pages until the fence is signaled, if any.
+    fn retire(&mut self, pages: Vec<GenericBufferPage<T>>, fence: Option<&FrameFence>) {
+        if let Some(fence) = fence.filter(|fence| !fence.is_signaled()) {
+            self.retired
+                .extend(pages.into_iter().map(|page| (fence.clone(), page)));
+        }
+    }
+
+    fn drop_finished_pages(&mut self) {
+        self.retired.retain(|(fence, _)| !fence.is_signaled());
     }
 
     /// Allocates a new buffer with the given size. It may allocate a new page if no page with enough space is available.
@@ -218,6 +383,8 @@ where
 
         let mut updated_page = self.pages.remove(index);
         let allocation = updated_page.allocate(size);
+        updated_page.last_used_frame = self.frame;
+        self.allocated_bytes += size.get();
 
         let new_page_index = self
             .pages
@@ -250,9 +417,108 @@ where
             .err()
             .unwrap();
 
-        let page = GenericBufferPage::new(device, page_size);
+        let mut page = GenericBufferPage::new(device, page_size);
+        page.last_used_frame = self.frame;
         self.pages.insert(index, page);
 
         index
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::{create_test_device, HostBuffer};
+
+    fn size(bytes: u64) -> BufferSize {
+        BufferSize::new(bytes).unwrap()
+    }
+
+    #[test]
+    fn check_high_water_mark_slides_with_the_window() {
+        let mut usage = UsageWindow::new(3);
+        assert_eq!(usage.high_water_mark(), 0);
+
+        for bytes in [100, 4000, 200, 300] {
+            usage.push(bytes);
+        }
+
+        assert_eq!(usage.last(), 300);
+        assert_eq!(usage.high_water_mark(), 4000);
+
+        usage.push(50);
+        assert_eq!(usage.high_water_mark(), 300);
+
+        usage.set_frames(1);
+        assert_eq!(usage.high_water_mark(), 50);
+    }
+
+    #[test]
+    fn check_pages_unused_over_the_window_are_released_after_their_frame() {
+        // Buffers need a device, even the host ones.
+        let device = match create_test_device() {
+            Some(device) => device.device,
+            None => return,
+        };
+        let mut pool = GenericBufferPool::<HostBuffer>::new(size(1024));
+        pool.set_shrink_window(4);
+
+        // A heavy frame, e.g. of a loading screen, then light ones.
+        for _ in 0..8 {
+            pool.allocate(&device, size(1024));
+        }
+
+        let heavy_fence = FrameFence::new();
+        pool.recall(&heavy_fence);
+        assert_eq!(pool.stats().page_count, 8);
+        assert_eq!(pool.stats().used_bytes, 8 * 1024);
+
+        let mut fences = Vec::new();
+
+        for _ in 0..4 {
+            pool.allocate(&device, size(512));
+            fences.push(FrameFence::new());
+            pool.recall(fences.last().unwrap());
+        }
+
+        let stats = pool.stats();
+        assert_eq!(stats.page_count, 1);
+        assert_eq!(stats.reserved_bytes, 1024);
+        assert_eq!(stats.peak_bytes, 512);
+        // The frame that released them has not finished yet.
+        assert_eq!(stats.retired_bytes, 7 * 1024);
+
+        heavy_fence.signal();
+        fences.iter().for_each(FrameFence::signal);
+        pool.recall(&FrameFence::new());
+        assert_eq!(pool.stats().retired_bytes, 0);
+    }
+
+    #[test]
+    fn check_trim_releases_every_unused_page() {
+        let device = match create_test_device() {
+            Some(device) => device.device,
+            None => return,
+        };
+        let mut pool = GenericBufferPool::<HostBuffer>::new(size(1024));
+
+        for _ in 0..4 {
+            pool.allocate(&device, size(1024));
+        }
+
+        let fence = FrameFence::new();
+        pool.recall(&fence);
+        pool.allocate(&device, size(256));
+        pool.trim();
+
+        let stats = pool.stats();
+        assert_eq!(stats.page_count, 1);
+        assert_eq!(stats.retired_bytes, 3 * 1024);
+        assert_eq!(stats.peak_bytes, 0);
+
+        fence.signal();
+        pool.trim();
+        assert_eq!(pool.stats().retired_bytes, 0);
+        assert_eq!(pool.stats().page_count, 1);
+    }
+}
@@ -1,17 +1,28 @@
 use super::{
     semantic_bindings,
     semantic_inputs::{self},
-    CachedPipeline, Material,
+    CachedPipeline, InstancedDraw, Material,
+};
+use crate::{
+    math::Mat4,
+    object::{ObjectHierarchy, ObjectId},
 };
-use crate::object::{ObjectHierarchy, ObjectId};
 use parking_lot::RwLockReadGuard;
-use wgpu::{BindGroup, Buffer, BufferAddress, RenderPass, VertexStepMode};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    hash::Hash,
+    sync::Arc,
+};
+use wgpu::{
+    util::RenderEncoder, BindGroup, Buffer, BufferAddress, IndexFormat, RenderPass, VertexStepMode,
+};
 use zerocopy::AsBytes;
 
 mod device_buffer;
 mod frame_buffer_allocator;
 mod generic_buffer_pool;
 mod host_buffer;
+mod parallel_recording;
 mod pipeline_provider;
 mod renderer;
 mod renderer_impls;
@@ -20,6 +31,7 @@ pub use device_buffer::*;
 pub use frame_buffer_allocator::*;
 pub use generic_buffer_pool::*;
 pub use host_buffer::*;
+pub use parallel_recording::*;
 pub use pipeline_provider::*;
 pub use renderer::*;
 pub use renderer_impls::*;
@@ -32,15 +44,44 @@ pub struct RenderingCommand<'r> {
     pub bind_group_provider: &'r dyn BindGroupProvider,
     pub vertex_buffer_provider: &'r dyn VertexBufferProvider,
     pub instance_buffer: Option<GenericBufferAllocation<Buffer>>,
+    /// If set, the vertices are drawn by `draw_indexed` with this many indices instead of `vertex_count`.
+    pub index_buffer: Option<(GenericBufferAllocation<Buffer>, IndexFormat, u32)>,
+    /// If set, the instance count is read from this buffer by `draw_indirect`, or `draw_indexed_indirect` if
+    /// the vertices are indexed.
+    pub indirect_buffer: Option<Arc<Buffer>>,
 }
 
 impl<'r> RenderingCommand<'r> {
-    /// Records a render pass for this rendering command.
-    pub fn render(
+    /// The stencil reference of the material, see [`MaterialStencil`](crate::gfx::MaterialStencil).
+    pub fn stencil_reference(&self) -> u32 {
+        self.material.stencil.map_or(0, |stencil| stencil.reference)
+    }
+
+    /// Records this rendering command into a render pass, setting its stencil reference first.
+    /// Render bundles cannot set it, so commands recorded into them draw with the one of the pass.
+    pub fn render_in_pass(
         &'r self,
         render_pass: &mut RenderPass<'r>,
         camera_transform_bind_group: &'r BindGroup,
         screen_size_bind_group: &'r BindGroup,
+        lights_bind_group: &'r BindGroup,
+    ) {
+        render_pass.set_stencil_reference(self.stencil_reference());
+        self.render(
+            render_pass,
+            camera_transform_bind_group,
+            screen_size_bind_group,
+            lights_bind_group,
+        );
+    }
+
+    /// Records this rendering command into a render pass or a render bundle.
+    pub fn render(
+        &'r self,
+        render_pass: &mut impl RenderEncoder<'r>,
+        camera_transform_bind_group: &'r BindGroup,
+        screen_size_bind_group: &'r BindGroup,
+        lights_bind_group: &'r BindGroup,
     ) {
         render_pass.set_pipeline(self.pipeline.as_ref());
 
@@ -58,10 +99,13 @@ impl<'r> RenderingCommand<'r> {
                 semantic_bindings::KEY_SCREEN_SIZE => {
                     render_pass.set_bind_group(binding.group, screen_size_bind_group, &[]);
                 }
+                semantic_bindings::KEY_LIGHTS => {
+                    render_pass.set_bind_group(binding.group, lights_bind_group, &[]);
+                }
                 _ => {
                     // TODO: Since this bind group is required, we should notify the user if it's not present.
                     if let Some(bind_group) = self.bind_group_provider.bind_group(0, key) {
-                        render_pass.set_bind_group(binding.group, &bind_group, &[]);
+                        render_pass.set_bind_group(binding.group, bind_group, &[]);
                     }
                 }
             }
@@ -71,7 +115,7 @@ impl<'r> RenderingCommand<'r> {
             let bind_group_holder = &self.material.bind_group_holders[bind_group_index.group_index];
 
             // TODO: Since this bind group is required, we should notify the user if it's not present.
-            if let Some(bind_group) = bind_group_holder.bind_group.as_ref() {
+            if let Some(bind_group) = bind_group_holder.bind_group.as_deref() {
                 render_pass.set_bind_group(bind_group_holder.group, bind_group, &[]);
             }
         }
@@ -115,82 +159,285 @@ impl<'r> RenderingCommand<'r> {
             }
         }
 
-        render_pass.draw(0..self.vertex_count, 0..self.instance_count);
+        if let Some((index_buffer, index_format, _)) = &self.index_buffer {
+            render_pass.set_index_buffer(index_buffer.as_slice(), *index_format);
+        }
+
+        match (&self.indirect_buffer, &self.index_buffer) {
+            (Some(indirect_buffer), Some(_)) => {
+                render_pass.draw_indexed_indirect(indirect_buffer, 0)
+            }
+            (Some(indirect_buffer), None) => render_pass.draw_indirect(indirect_buffer, 0),
+            (None, Some((_, _, index_count))) => {
+                render_pass.draw_indexed(0..*index_count, 0, 0..self.instance_count)
+            }
+            (None, None) => render_pass.draw(0..self.vertex_count, 0..self.instance_count),
+        }
     }
 }
 
 /// Constructs a rendering command for the given object by encoding per-instance data into a buffer.
+/// Returns `None` if there's nothing to draw, i.e. the renderer has no vertices or no instances.
 pub fn build_rendering_command<'r>(
     object_id: ObjectId,
     object_hierarchy: &ObjectHierarchy,
     renderer: &'r dyn Renderer,
     frame_buffer_allocator: &mut FrameBufferAllocator,
-) -> RenderingCommand<'r> {
-    let matrix = object_hierarchy.matrix(object_id);
-    let material = renderer.material();
+) -> Option<RenderingCommand<'r>> {
+    build_batched_rendering_command(
+        &[(object_id, renderer)],
+        object_hierarchy,
+        frame_buffer_allocator,
+    )
+}
 
-    let instance_count = renderer.instance_count();
-    let instance_data_provider = renderer.instance_data_provider();
-    let per_instance_buffer = frame_buffer_allocator.alloc_staging_buffer(
-        material.shader.reflected_shader.per_instance_input.stride
-            * instance_count as BufferAddress,
-    );
-
-    for instance in 0..instance_count {
-        let per_instance_buffer = per_instance_buffer.slice(
-            material.shader.reflected_shader.per_instance_input.stride * instance as BufferAddress,
-            material.shader.reflected_shader.per_instance_input.stride,
-        );
+/// Constructs a single rendering command drawing the renderers of several objects, their instances back to back
+/// in one per-instance buffer. The renderers must share the pipeline, the material and the vertex buffers, e.g.
+/// by [`MeshSubRenderer::batch_key`]; those of the first one are used.
+/// Returns `None` if there's nothing to draw, i.e. the renderers have no vertices or no instances.
+pub fn build_batched_rendering_command<'r>(
+    members: &[(ObjectId, &'r dyn Renderer)],
+    object_hierarchy: &ObjectHierarchy,
+    frame_buffer_allocator: &mut FrameBufferAllocator,
+) -> Option<RenderingCommand<'r>> {
+    let &(_, renderer) = members.first()?;
+    let vertex_count = renderer.vertex_count();
+    let instance_count = members
+        .iter()
+        .map(|(_, member)| member.instance_count())
+        .sum::<u32>();
 
-        for (&key, input_data) in &material.semantic_inputs {
-            if input_data.step_mode != VertexStepMode::Instance {
-                continue;
-            }
+    if vertex_count == 0 || instance_count == 0 {
+        return None;
+    }
 
-            let size = material.shader.reflected_shader.per_instance_input.elements
-                [input_data.index]
-                .attribute
-                .format
-                .size();
-            let allocation = &mut per_instance_buffer.slice(input_data.offset, size);
+    let material = renderer.material();
+    let stride = material.shader.reflected_shader.per_instance_input.stride;
+    let per_instance_buffer =
+        frame_buffer_allocator.alloc_staging_buffer(stride * instance_count as BufferAddress);
 
-            match key {
-                semantic_inputs::KEY_TRANSFORM_ROW_0 => {
-                    allocation.copy_from_slice(matrix.row(0).as_bytes())
-                }
-                semantic_inputs::KEY_TRANSFORM_ROW_1 => {
-                    allocation.copy_from_slice(matrix.row(1).as_bytes())
-                }
-                semantic_inputs::KEY_TRANSFORM_ROW_2 => {
-                    allocation.copy_from_slice(matrix.row(2).as_bytes())
-                }
-                semantic_inputs::KEY_TRANSFORM_ROW_3 => {
-                    allocation.copy_from_slice(matrix.row(3).as_bytes())
-                }
-                _ => {
-                    instance_data_provider.copy_per_instance_data(instance, key, allocation);
-                }
+    // Shaders without per-instance inputs have nothing to encode; the allocation is empty.
+    if stride != 0 {
+        let mut first_instance = 0;
+
+        for &(object_id, member) in members {
+            let matrix = object_hierarchy.matrix(object_id);
+            let instance_data_provider = member.instance_data_provider();
+
+            for instance in 0..member.instance_count() {
+                let per_instance_buffer = per_instance_buffer.slice(
+                    stride * (first_instance + instance) as BufferAddress,
+                    stride,
+                );
+                encode_instance(
+                    &material,
+                    matrix,
+                    instance_data_provider,
+                    instance,
+                    &per_instance_buffer,
+                );
             }
+
+            first_instance += member.instance_count();
         }
+    }
 
-        for property in material.instance_properties.values() {
-            if let Some(value) = &property.value {
-                per_instance_buffer
-                    .slice(property.offset, value.to_vertex_format().size())
-                    .copy_from_slice(value.as_bytes());
-            }
+    let per_instance_buffer = frame_buffer_allocator.commit_staging_buffer(per_instance_buffer);
+
+    Some(RenderingCommand {
+        pipeline: renderer.pipeline(),
+        material,
+        instance_count,
+        vertex_count,
+        bind_group_provider: renderer.bind_group_provider(),
+        vertex_buffer_provider: renderer.vertex_buffer_provider(),
+        instance_buffer: per_instance_buffer,
+        index_buffer: renderer.index_buffer(),
+        indirect_buffer: None,
+    })
+}
+
+/// Constructs a rendering command for a renderer that is not attached to an object, e.g. the debug drawing.
+/// Every instance is drawn with the given matrix.
+/// Returns `None` if there's nothing to draw, i.e. the renderer has no vertices or no instances.
+pub fn build_detached_rendering_command<'r>(
+    matrix: &Mat4,
+    renderer: &'r dyn Renderer,
+    frame_buffer_allocator: &mut FrameBufferAllocator,
+) -> Option<RenderingCommand<'r>> {
+    let vertex_count = renderer.vertex_count();
+    let instance_count = renderer.instance_count();
+
+    if vertex_count == 0 || instance_count == 0 {
+        return None;
+    }
+
+    let material = renderer.material();
+    let stride = material.shader.reflected_shader.per_instance_input.stride;
+    let per_instance_buffer =
+        frame_buffer_allocator.alloc_staging_buffer(stride * instance_count as BufferAddress);
+
+    if stride != 0 {
+        for instance in 0..instance_count {
+            encode_instance(
+                &material,
+                matrix,
+                renderer.instance_data_provider(),
+                instance,
+                &per_instance_buffer.slice(stride * instance as BufferAddress, stride),
+            );
         }
     }
 
     let per_instance_buffer = frame_buffer_allocator.commit_staging_buffer(per_instance_buffer);
 
-    RenderingCommand {
+    Some(RenderingCommand {
         pipeline: renderer.pipeline(),
         material,
         instance_count,
-        vertex_count: renderer.vertex_count(),
+        vertex_count,
         bind_group_provider: renderer.bind_group_provider(),
         vertex_buffer_provider: renderer.vertex_buffer_provider(),
         instance_buffer: per_instance_buffer,
+        index_buffer: renderer.index_buffer(),
+        indirect_buffer: None,
+    })
+}
+
+/// Encodes the per-instance inputs and properties of an instance of a renderer into its slice of the buffer.
+fn encode_instance(
+    material: &Material,
+    matrix: &Mat4,
+    instance_data_provider: &dyn InstanceDataProvider,
+    instance: u32,
+    per_instance_buffer: &GenericBufferAllocation<HostBuffer>,
+) {
+    for (&key, input_data) in &material.semantic_inputs {
+        if input_data.step_mode != VertexStepMode::Instance {
+            continue;
+        }
+
+        let size = material.shader.reflected_shader.per_instance_input.elements[input_data.index]
+            .attribute
+            .format
+            .size();
+        let allocation = &mut per_instance_buffer.slice(input_data.offset, size);
+
+        match key {
+            semantic_inputs::KEY_TRANSFORM_ROW_0 => {
+                allocation.copy_from_slice(matrix.row(0).as_bytes())
+            }
+            semantic_inputs::KEY_TRANSFORM_ROW_1 => {
+                allocation.copy_from_slice(matrix.row(1).as_bytes())
+            }
+            semantic_inputs::KEY_TRANSFORM_ROW_2 => {
+                allocation.copy_from_slice(matrix.row(2).as_bytes())
+            }
+            semantic_inputs::KEY_TRANSFORM_ROW_3 => {
+                allocation.copy_from_slice(matrix.row(3).as_bytes())
+            }
+            _ => {
+                instance_data_provider.copy_per_instance_data(instance, key, allocation);
+            }
+        }
+    }
+
+    for (name, property) in &material.instance_properties {
+        let value = instance_data_provider
+            .instance_property(instance, name)
+            .filter(|value| value.to_vertex_format() == property.format)
+            .or(property.value.as_ref());
+
+        if let Some(value) = value {
+            per_instance_buffer
+                .slice(property.offset, value.to_vertex_format().size())
+                .copy_from_slice(value.as_bytes());
+        }
+    }
+}
+
+/// Groups items sharing a batch key, keeping the groups in the order of their first items and the items in their
+/// order. Items without a key are alone in their group.
+pub fn group_batches<K: Eq + Hash, T>(
+    items: impl IntoIterator<Item = (Option<K>, T)>,
+) -> Vec<Vec<T>> {
+    let mut groups = Vec::<Vec<T>>::new();
+    let mut group_indices = HashMap::<K, usize>::new();
+
+    for (key, item) in items {
+        match key {
+            Some(key) => match group_indices.entry(key) {
+                Entry::Occupied(entry) => groups[*entry.get()].push(item),
+                Entry::Vacant(entry) => {
+                    entry.insert(groups.len());
+                    groups.push(vec![item]);
+                }
+            },
+            None => groups.push(vec![item]),
+        }
+    }
+
+    groups
+}
+
+/// Constructs a rendering command for an instanced group that has already been culled.
+/// Per-instance data are taken from the culling result rather than encoded here.
+/// Returns `None` if the renderer has no vertices.
+pub fn build_instanced_rendering_command<'r>(
+    renderer: &'r dyn Renderer,
+    draw: InstancedDraw,
+) -> Option<RenderingCommand<'r>> {
+    if renderer.vertex_count() == 0 {
+        return None;
+    }
+
+    Some(RenderingCommand {
+        pipeline: renderer.pipeline(),
+        material: renderer.material(),
+        instance_count: draw.instance_count,
+        vertex_count: renderer.vertex_count(),
+        bind_group_provider: renderer.bind_group_provider(),
+        vertex_buffer_provider: renderer.vertex_buffer_provider(),
+        instance_buffer: Some(draw.instance_buffer),
+        index_buffer: renderer.index_buffer(),
+        indirect_buffer: draw.indirect_buffer,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_batches_group_matching_keys_in_order() {
+        let items = [
+            (Some("cube"), 0),
+            (Some("sphere"), 1),
+            (None, 2),
+            (Some("cube"), 3),
+            (None, 4),
+            (Some("sphere"), 5),
+        ];
+
+        assert_eq!(
+            group_batches(items),
+            vec![vec![0, 3], vec![1, 5], vec![2], vec![4]]
+        );
+    }
+
+    #[test]
+    fn check_5000_objects_of_one_mesh_form_one_batch() {
+        let groups = group_batches((0..5000).map(|index| (Some(("cube", "bricks")), index)));
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].len(), 5000);
+        assert!(groups[0].windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn check_opted_out_items_are_drawn_alone() {
+        let groups = group_batches((0..10).map(|index| (None::<&str>, index)));
+
+        assert_eq!(groups.len(), 10);
     }
 }
@@ -0,0 +1,136 @@
+use super::RenderingCommand;
+use std::{ops::Range, thread, time::Instant};
+use wgpu::{
+    BindGroup, Device, RenderBundle, RenderBundleDepthStencil, RenderBundleDescriptor,
+    RenderBundleEncoderDescriptor, TextureFormat,
+};
+
+/// Fewest commands worth a thread of their own; shorter lists are recorded on fewer threads.
+pub const MIN_COMMANDS_PER_RECORDING_THREAD: usize = 256;
+
+/// Attachment formats of the pass the bundles are executed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BundleTargetFormat {
+    pub color: TextureFormat,
+    pub depth_stencil: Option<TextureFormat>,
+}
+
+/// Render bundles recorded from consecutive ranges of a command list, in the order of the list.
+pub struct ParallelRecording {
+    pub bundles: Vec<RenderBundle>,
+    /// Time each thread spent recording its range, in milliseconds.
+    pub thread_ms: Vec<f32>,
+}
+
+/// Records the commands into render bundles on up to `threads` threads, each one taking a contiguous range of them.
+/// Executing the bundles in order in a single pass draws the same as recording the commands into the pass directly.
+///
+/// Bundles inherit the viewport and the scissor of the pass they are executed in, so those are set on the pass.
+pub fn record_in_parallel<'a>(
+    device: &'a Device,
+    format: BundleTargetFormat,
+    commands: &'a [RenderingCommand<'a>],
+    threads: usize,
+    camera_transform_bind_group: &'a BindGroup,
+    screen_size_bind_group: &'a BindGroup,
+    lights_bind_group: &'a BindGroup,
+) -> ParallelRecording {
+    let results = thread::scope(|scope| {
+        let handles = Vec::from_iter(split_ranges(commands.len(), threads).into_iter().map(
+            |range| {
+                let commands = &commands[range];
+                scope.spawn(move || {
+                    let start = Instant::now();
+                    let mut encoder =
+                        device.create_render_bundle_encoder(&RenderBundleEncoderDescriptor {
+                            label: Some("[parallel recording] bundle encoder"),
+                            color_formats: &[Some(format.color)],
+                            depth_stencil: format.depth_stencil.map(|format| {
+                                RenderBundleDepthStencil {
+                                    format,
+                                    depth_read_only: false,
+                                    stencil_read_only: false,
+                                }
+                            }),
+                            sample_count: 1,
+                            multiview: None,
+                        });
+
+                    for cmd in commands {
+                        cmd.render(
+                            &mut encoder,
+                            camera_transform_bind_group,
+                            screen_size_bind_group,
+                            lights_bind_group,
+                        );
+                    }
+
+                    let bundle = encoder.finish(&RenderBundleDescriptor {
+                        label: Some("[parallel recording] bundle"),
+                    });
+                    (bundle, start.elapsed().as_secs_f32() * 1000.0)
+                })
+            },
+        ));
+
+        Vec::from_iter(
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("a recording thread panicked")),
+        )
+    });
+
+    let (bundles, thread_ms) = results.into_iter().unzip();
+    ParallelRecording { bundles, thread_ms }
+}
+
+/// Splits `len` commands into at most `threads` contiguous ranges of nearly equal length,
+/// each one at least [`MIN_COMMANDS_PER_RECORDING_THREAD`] long unless there is a single one.
+fn split_ranges(len: usize, threads: usize) -> Vec<Range<usize>> {
+    let count = (len / MIN_COMMANDS_PER_RECORDING_THREAD).clamp(1, threads.max(1));
+    let base = len / count;
+    let remainder = len % count;
+    let mut ranges = Vec::with_capacity(count);
+    let mut start = 0;
+
+    for index in 0..count {
+        let end = start + base + usize::from(index < remainder);
+        ranges.push(start..end);
+        start = end;
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_split_ranges_cover_commands_in_order() {
+        let ranges = split_ranges(MIN_COMMANDS_PER_RECORDING_THREAD * 4 + 3, 4);
+
+        assert_eq!(ranges.len(), 4);
+        assert_eq!(ranges[0].start, 0);
+        assert_eq!(ranges[3].end, MIN_COMMANDS_PER_RECORDING_THREAD * 4 + 3);
+
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+            assert!(pair[1].len() <= pair[0].len());
+        }
+    }
+
+    #[test]
+    fn check_split_ranges_keep_short_lists_together() {
+        assert_eq!(split_ranges(10, 8), vec![0..10]);
+        assert_eq!(split_ranges(0, 8), vec![0..0]);
+        assert_eq!(
+            split_ranges(MIN_COMMANDS_PER_RECORDING_THREAD * 2, 8).len(),
+            2
+        );
+        assert_eq!(
+            split_ranges(MIN_COMMANDS_PER_RECORDING_THREAD * 2, 0).len(),
+            1
+        );
+    }
+}
@@ -1,6 +1,14 @@
 use super::RendererVertexBufferLayout;
-use crate::gfx::{BufferLayout, CachedPipeline, MaterialHandle, PipelineCache, ShaderManager};
-use wgpu::{DepthStencilState, PrimitiveState, VertexAttribute, VertexStepMode};
+use crate::{
+    gfx::{
+        BufferLayout, CachedPipeline, MaterialHandle, PipelineCache, SemanticInputData,
+        SemanticShaderInputKey, ShaderManager,
+    },
+    use_context,
+};
+use logging::StandardLogLevel;
+use std::collections::HashMap;
+use wgpu::{DepthStencilState, PrimitiveState, PrimitiveTopology, VertexAttribute, VertexStepMode};
 
 // TODO: Should we make buffer layouts and states to be shared across all renderer instances?
 pub struct PipelineProvider {
@@ -9,7 +17,10 @@ pub struct PipelineProvider {
     material: Option<MaterialHandle>,
     buffer_layouts: Vec<RendererVertexBufferLayout>,
     primitive: Option<PrimitiveState>,
+    topology: PrimitiveTopology,
     depth_stencil: Option<DepthStencilState>,
+    /// Per-vertex input of the shader the buffer layouts lack, last reported; reported again only if it changes.
+    missing_input: Option<SemanticShaderInputKey>,
 }
 
 impl PipelineProvider {
@@ -20,7 +31,9 @@ impl PipelineProvider {
             material: None,
             buffer_layouts: Vec::new(),
             primitive: None,
+            topology: PrimitiveTopology::TriangleList,
             depth_stencil: None,
+            missing_input: None,
         }
     }
 
@@ -29,22 +42,33 @@ impl PipelineProvider {
     }
 
     pub fn set_material(&mut self, material: MaterialHandle) {
-        self.is_dirty = true;
+        self.is_dirty |= self.material.as_ref() != Some(&material);
         self.material = Some(material);
     }
 
     pub fn set_buffer_layouts(&mut self, buffer_layouts: Vec<RendererVertexBufferLayout>) {
-        self.is_dirty = true;
+        self.is_dirty |= self.buffer_layouts != buffer_layouts;
         self.buffer_layouts = buffer_layouts;
     }
 
     pub fn set_primitive(&mut self, primitive: PrimitiveState) {
-        self.is_dirty = true;
+        self.is_dirty |= self.primitive != Some(primitive);
         self.primitive = Some(primitive);
     }
 
+    pub fn topology(&self) -> PrimitiveTopology {
+        self.topology
+    }
+
+    /// Sets the topology the vertices are assembled with, overriding the one of the primitive state.
+    /// It defaults to [`PrimitiveTopology::TriangleList`].
+    pub fn set_topology(&mut self, topology: PrimitiveTopology) {
+        self.is_dirty |= self.topology != topology;
+        self.topology = topology;
+    }
+
     pub fn set_depth_stencil(&mut self, depth_stencil: Option<DepthStencilState>) {
-        self.is_dirty = true;
+        self.is_dirty |= self.depth_stencil != depth_stencil;
         self.depth_stencil = depth_stencil;
     }
 
@@ -53,22 +77,83 @@ impl PipelineProvider {
         shader_mgr: &ShaderManager,
         pipeline_cache: &mut PipelineCache,
     ) -> Option<CachedPipeline> {
-        if !self.is_dirty {
-            if let Some(pipeline) = self.pipeline.clone() {
-                return Some(pipeline);
-            }
-        }
-
         let material = if let Some(material) = &self.material {
-            material.read()
+            material
         } else {
             return None;
         };
 
+        if shader_mgr
+            .reloaded_shader(&material.read().shader)
+            .is_some()
+        {
+            let mut material = material.write();
+            let shader = material.shader.clone();
+
+            if shader_mgr.refresh_material(&mut material) {
+                pipeline_cache.invalidate_shader(&shader);
+            }
+        }
+
+        if material.read().needs_prepare() {
+            // A bind group the device rejects would fail the draw; skip it until the bindings are fixed.
+            shader_mgr.prepare_material(&mut material.write()).ok()?;
+        }
+
+        let material = material.read();
+
+        // The transparent queue keeps the depth test but must not write depth.
+        let depth_stencil = self.depth_stencil.clone().map(|mut depth_stencil| {
+            depth_stencil.depth_write_enabled &= material.render_queue.depth_write_enabled();
+
+            if let Some(stencil) = &material.stencil {
+                depth_stencil.stencil = stencil.stencil_state();
+            }
+
+            depth_stencil
+        });
+        let depth_stencil = pipeline_cache.depth_stencil_state(depth_stencil);
+
+        // The material may have been swapped to another shader or queue, or the debug view mode changed, since.
+        if let Some(pipeline) = &self.pipeline {
+            if !self.is_dirty
+                && pipeline.key().shader == material.shader
+                && pipeline.key().depth_stencil == depth_stencil
+                && pipeline.key().color_format == pipeline_cache.color_format()
+                && pipeline.key().variant
+                    == pipeline_cache.debug_view_variant(&material.shader, self.topology)
+            {
+                return Some(pipeline.clone());
+            }
+        }
+
         if self.buffer_layouts.len() == 0 {
             return None;
         }
 
+        // Without a buffer to read it from, the pipeline would be rejected by the device.
+        let missing_input = missing_vertex_input(&self.buffer_layouts, &material.semantic_inputs);
+
+        if let Some(key) = missing_input {
+            if self.missing_input != missing_input {
+                let name = shader_mgr
+                    .get_semantic_input(key)
+                    .map_or("unknown", |input| input.name);
+                use_context().logger().log(
+                    StandardLogLevel::Error,
+                    format!(
+                        "the shader reads the vertex input `{}`, which the mesh does not provide; it is not drawn",
+                        name
+                    ),
+                );
+            }
+
+            self.missing_input = missing_input;
+            return None;
+        }
+
+        self.missing_input = None;
+
         let primitive = if let Some(primitive) = self.primitive.clone() {
             primitive
         } else {
@@ -114,11 +199,14 @@ impl PipelineProvider {
                 .map(|element| element.attribute.clone()),
         );
 
-        buffer_layouts.push(BufferLayout {
-            array_stride: material.shader.reflected_shader.per_instance_input.stride,
-            step_mode: VertexStepMode::Instance,
-            attributes: per_instance_attributes,
-        });
+        // A layout without attributes would still need a buffer bound to its slot.
+        if !per_instance_attributes.is_empty() {
+            buffer_layouts.push(BufferLayout {
+                array_stride: material.shader.reflected_shader.per_instance_input.stride,
+                step_mode: VertexStepMode::Instance,
+                attributes: per_instance_attributes,
+            });
+        }
 
         let pipeline = pipeline_cache.create_pipeline(
             shader_mgr,
@@ -126,7 +214,8 @@ impl PipelineProvider {
             material.shader.clone(),
             buffer_layouts,
             primitive,
-            self.depth_stencil.clone(),
+            self.topology,
+            depth_stencil,
         );
 
         self.is_dirty = false;
@@ -135,3 +224,76 @@ impl PipelineProvider {
         Some(pipeline)
     }
 }
+
+/// Per-vertex input of the material that no attribute of the buffer layouts provides, if any. The one of the lowest
+/// shader location is picked, so that the same one is reported every time.
+fn missing_vertex_input(
+    buffer_layouts: &[RendererVertexBufferLayout],
+    semantic_inputs: &HashMap<SemanticShaderInputKey, SemanticInputData>,
+) -> Option<SemanticShaderInputKey> {
+    semantic_inputs
+        .iter()
+        .filter(|(_, input)| input.step_mode == VertexStepMode::Vertex)
+        .filter(|(&key, _)| {
+            !buffer_layouts.iter().any(|layout| {
+                layout
+                    .attributes
+                    .iter()
+                    .any(|attribute| attribute.key == key)
+            })
+        })
+        .min_by_key(|(_, input)| input.shader_location)
+        .map(|(&key, _)| key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::{semantic_inputs, RendererVertexBufferAttribute};
+
+    fn input(step_mode: VertexStepMode, shader_location: u32) -> SemanticInputData {
+        SemanticInputData {
+            step_mode,
+            offset: 0,
+            shader_location,
+            index: 0,
+        }
+    }
+
+    #[test]
+    fn check_missing_vertex_inputs_are_found() {
+        let buffer_layouts = [RendererVertexBufferLayout {
+            array_stride: 32,
+            attributes: vec![RendererVertexBufferAttribute {
+                key: semantic_inputs::KEY_POSITION,
+                offset: 0,
+            }],
+        }];
+        let mut semantic_inputs = HashMap::from([
+            (
+                semantic_inputs::KEY_POSITION,
+                input(VertexStepMode::Vertex, 0),
+            ),
+            (
+                semantic_inputs::KEY_TRANSFORM_ROW_0,
+                input(VertexStepMode::Instance, 4),
+            ),
+        ]);
+
+        assert_eq!(
+            missing_vertex_input(&buffer_layouts, &semantic_inputs),
+            None
+        );
+
+        semantic_inputs.insert(semantic_inputs::KEY_UV1, input(VertexStepMode::Vertex, 2));
+        semantic_inputs.insert(
+            semantic_inputs::KEY_TANGENT,
+            input(VertexStepMode::Vertex, 1),
+        );
+
+        assert_eq!(
+            missing_vertex_input(&buffer_layouts, &semantic_inputs),
+            Some(semantic_inputs::KEY_TANGENT)
+        );
+    }
+}
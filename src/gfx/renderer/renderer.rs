@@ -1,7 +1,10 @@
 use super::{GenericBufferAllocation, HostBuffer};
-use crate::gfx::{CachedPipeline, Material, SemanticShaderBindingKey, SemanticShaderInputKey};
+use crate::gfx::{
+    CachedPipeline, Material, PerInstancePropertyValue, SemanticShaderBindingKey,
+    SemanticShaderInputKey,
+};
 use parking_lot::RwLockReadGuard;
-use wgpu::{BindGroup, Buffer, BufferAddress};
+use wgpu::{BindGroup, Buffer, BufferAddress, IndexFormat};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct RendererVertexBufferLayout {
@@ -24,6 +27,11 @@ pub trait Renderer {
 
     fn vertex_count(&self) -> u32;
 
+    /// Returns the index buffer, its format and the number of indices to draw, if the vertices are indexed.
+    fn index_buffer(&self) -> Option<(GenericBufferAllocation<Buffer>, IndexFormat, u32)> {
+        None
+    }
+
     fn bind_group_provider(&self) -> &dyn BindGroupProvider;
 
     fn vertex_buffer_provider(&self) -> &dyn VertexBufferProvider;
@@ -31,7 +39,8 @@ pub trait Renderer {
     fn instance_data_provider(&self) -> &dyn InstanceDataProvider;
 }
 
-pub trait BindGroupProvider {
+/// Shared across the threads recording a camera pass, see [`RenderManager::set_encoder_threads`](crate::gfx::RenderManager::set_encoder_threads).
+pub trait BindGroupProvider: Sync {
     fn bind_group(&self, instance: u32, key: SemanticShaderBindingKey) -> Option<&BindGroup>;
 }
 
@@ -40,7 +49,7 @@ pub struct VertexBuffer<'a> {
     pub buffer: &'a GenericBufferAllocation<Buffer>,
 }
 
-pub trait VertexBufferProvider {
+pub trait VertexBufferProvider: Sync {
     fn vertex_buffer_count(&self) -> u32;
     fn vertex_buffer(&self, key: SemanticShaderInputKey) -> Option<VertexBuffer>;
 }
@@ -52,4 +61,9 @@ pub trait InstanceDataProvider {
         key: SemanticShaderInputKey,
         buffer: &mut GenericBufferAllocation<HostBuffer>,
     );
+
+    /// Returns the value overriding the material's per-instance property of the given name for this renderer, if any.
+    fn instance_property(&self, _instance: u32, _name: &str) -> Option<&PerInstancePropertyValue> {
+        None
+    }
 }
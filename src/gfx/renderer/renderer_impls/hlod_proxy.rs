@@ -0,0 +1,272 @@
+use crate::{
+    gfx::{
+        semantic_inputs::{self, KEY_NORMAL, KEY_POSITION, KEY_VERTEX_COLOR},
+        BindGroupProvider, CachedPipeline, GenericBufferAllocation, HlodBakeSettings, HlodCluster,
+        HlodSwitch, HostBuffer, InstanceDataProvider, Material, MaterialHandle,
+        PerInstancePropertyValue, PipelineCache, PipelineProvider, Renderer,
+        RendererVertexBufferAttribute, RendererVertexBufferLayout, SemanticShaderBindingKey,
+        SemanticShaderInputKey, ShaderManager, VertexBuffer, VertexBufferProvider,
+    },
+    math::{Mat4, Vec3},
+    object::ObjectId,
+};
+use parking_lot::RwLockReadGuard;
+use specs::{prelude::*, Component};
+use std::{collections::HashMap, mem::size_of};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, Buffer, BufferAddress, BufferSize, BufferUsages, CompareFunction, DepthStencilState,
+    Device, Face, FrontFace, PolygonMode, PrimitiveState, PrimitiveTopology, TextureFormat,
+};
+use zerocopy::AsBytes;
+
+/// Draws the merged mesh of an [`HlodCluster`] in place of its members while the camera is far from it.
+/// The vertices are in world space, so the object of the proxy should stay at the origin.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct HlodProxy {
+    mask: u32,
+    pipeline_provider: PipelineProvider,
+    members: HashMap<ObjectId, Mat4>,
+    center: Vec3,
+    radius: f32,
+    switch: HlodSwitch,
+    is_invalidated: bool,
+    vertex_count: u32,
+    vertex_buffer: Option<GenericBufferAllocation<Buffer>>,
+}
+
+impl HlodProxy {
+    pub fn new(cluster: &HlodCluster, settings: &HlodBakeSettings, device: &Device) -> Self {
+        let mut pipeline_provider = PipelineProvider::new();
+
+        pipeline_provider.set_buffer_layouts(vec![RendererVertexBufferLayout {
+            array_stride: size_of::<[f32; 10]>() as BufferAddress,
+            attributes: vec![
+                RendererVertexBufferAttribute {
+                    key: KEY_POSITION,
+                    offset: 0,
+                },
+                RendererVertexBufferAttribute {
+                    key: KEY_NORMAL,
+                    offset: size_of::<[f32; 3]>() as BufferAddress,
+                },
+                RendererVertexBufferAttribute {
+                    key: KEY_VERTEX_COLOR,
+                    offset: size_of::<[f32; 6]>() as BufferAddress,
+                },
+            ],
+        }]);
+        pipeline_provider.set_primitive(PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: Some(Face::Back),
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        });
+        pipeline_provider.set_depth_stencil(Some(DepthStencilState {
+            format: TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: CompareFunction::Less,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }));
+
+        let vertex_buffer = if cluster.vertices.is_empty() {
+            None
+        } else {
+            Some(GenericBufferAllocation::new(
+                device.create_buffer_init(&BufferInitDescriptor {
+                    label: None,
+                    contents: cluster.vertices.as_bytes(),
+                    // Frame captures copy the vertices out.
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_SRC,
+                }),
+                0,
+                BufferSize::new((size_of::<[f32; 10]>() * cluster.vertices.len()) as u64).unwrap(),
+            ))
+        };
+
+        Self {
+            mask: 0xFFFF_FFFF,
+            pipeline_provider,
+            members: cluster.members.iter().cloned().collect(),
+            center: cluster.center,
+            radius: cluster.radius,
+            switch: HlodSwitch::new(
+                cluster.center,
+                settings.switch_distance,
+                settings.hysteresis,
+            ),
+            is_invalidated: false,
+            vertex_count: cluster.vertices.len() as u32,
+            vertex_buffer,
+        }
+    }
+
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    pub fn set_mask(&mut self, mask: u32) {
+        self.mask = mask;
+    }
+
+    pub fn material(&self) -> Option<&MaterialHandle> {
+        self.pipeline_provider.material()
+    }
+
+    pub fn set_material(&mut self, material: MaterialHandle) {
+        self.pipeline_provider.set_material(material);
+    }
+
+    pub fn member_count(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = ObjectId> + '_ {
+        self.members.keys().copied()
+    }
+
+    /// World matrix the member has been baked with.
+    pub fn baked_matrix(&self, member: ObjectId) -> Option<&Mat4> {
+        self.members.get(&member)
+    }
+
+    /// Bounding sphere of the proxy, as `(center, radius)`.
+    pub fn bounding_sphere(&self) -> (Vec3, f32) {
+        (self.center, self.radius)
+    }
+
+    /// Returns `true` if the proxy is drawn instead of its members.
+    /// Proxies without a material or whose bake is out of date are never used.
+    pub fn is_using_proxy(&self) -> bool {
+        self.switch.is_using_proxy()
+            && !self.is_invalidated
+            && self.vertex_buffer.is_some()
+            && self.material().is_some()
+    }
+
+    /// Switches between the proxy and the members by the distance of the camera.
+    pub fn update_switch(&mut self, camera_position: Vec3) {
+        self.switch.update(camera_position);
+    }
+
+    pub fn is_invalidated(&self) -> bool {
+        self.is_invalidated
+    }
+
+    /// Marks the bake as out of date, e.g. because a member moved. The members are drawn from then on.
+    /// Returns `true` if it was up to date before.
+    pub fn invalidate(&mut self) -> bool {
+        !std::mem::replace(&mut self.is_invalidated, true)
+    }
+
+    pub fn sub_renderer(
+        &mut self,
+        shader_mgr: &ShaderManager,
+        pipeline_cache: &mut PipelineCache,
+    ) -> Option<HlodProxySubRenderer> {
+        let pipeline = self
+            .pipeline_provider
+            .obtain_pipeline(shader_mgr, pipeline_cache)?;
+        let material = self.pipeline_provider.material().cloned()?;
+        let vertex_buffer = self.vertex_buffer.clone()?;
+
+        Some(HlodProxySubRenderer {
+            pipeline,
+            material,
+            vertex_count: self.vertex_count,
+            bind_group_provider: HlodProxyBindGroupProvider,
+            vertex_buffer_provider: HlodProxyVertexBufferProvider { vertex_buffer },
+            instance_data_provider: HlodProxyInstanceDataProvider,
+        })
+    }
+}
+
+pub struct HlodProxySubRenderer {
+    pipeline: CachedPipeline,
+    material: MaterialHandle,
+    vertex_count: u32,
+    bind_group_provider: HlodProxyBindGroupProvider,
+    vertex_buffer_provider: HlodProxyVertexBufferProvider,
+    instance_data_provider: HlodProxyInstanceDataProvider,
+}
+
+impl Renderer for HlodProxySubRenderer {
+    fn pipeline(&self) -> CachedPipeline {
+        self.pipeline.clone()
+    }
+
+    fn material(&self) -> RwLockReadGuard<Material> {
+        self.material.read()
+    }
+
+    fn instance_count(&self) -> u32 {
+        1
+    }
+
+    fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    fn bind_group_provider(&self) -> &dyn BindGroupProvider {
+        &self.bind_group_provider
+    }
+
+    fn vertex_buffer_provider(&self) -> &dyn VertexBufferProvider {
+        &self.vertex_buffer_provider
+    }
+
+    fn instance_data_provider(&self) -> &dyn InstanceDataProvider {
+        &self.instance_data_provider
+    }
+}
+
+struct HlodProxyBindGroupProvider;
+
+impl BindGroupProvider for HlodProxyBindGroupProvider {
+    fn bind_group(&self, _instance: u32, _key: SemanticShaderBindingKey) -> Option<&BindGroup> {
+        None
+    }
+}
+
+struct HlodProxyVertexBufferProvider {
+    vertex_buffer: GenericBufferAllocation<Buffer>,
+}
+
+impl VertexBufferProvider for HlodProxyVertexBufferProvider {
+    fn vertex_buffer_count(&self) -> u32 {
+        1
+    }
+
+    fn vertex_buffer(&self, key: SemanticShaderInputKey) -> Option<VertexBuffer> {
+        match key {
+            semantic_inputs::KEY_POSITION
+            | semantic_inputs::KEY_NORMAL
+            | semantic_inputs::KEY_VERTEX_COLOR => Some(VertexBuffer {
+                slot: 0,
+                buffer: &self.vertex_buffer,
+            }),
+            _ => None,
+        }
+    }
+}
+
+struct HlodProxyInstanceDataProvider;
+
+impl InstanceDataProvider for HlodProxyInstanceDataProvider {
+    fn copy_per_instance_data(
+        &self,
+        _instance: u32,
+        _key: SemanticShaderInputKey,
+        _buffer: &mut GenericBufferAllocation<HostBuffer>,
+    ) {
+    }
+
+    fn instance_property(&self, _instance: u32, _name: &str) -> Option<&PerInstancePropertyValue> {
+        None
+    }
+}
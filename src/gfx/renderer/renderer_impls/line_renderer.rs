@@ -0,0 +1,311 @@
+use crate::{
+    gfx::{
+        semantic_inputs::{self, KEY_POSITION, KEY_VERTEX_COLOR},
+        BindGroupProvider, CachedPipeline, Color, ColorSpaceMode, FrameBufferAllocator,
+        GenericBufferAllocation, HostBuffer, InstanceDataProvider, Material, MaterialHandle,
+        PerInstancePropertyValue, PipelineCache, PipelineProvider, Renderer,
+        RendererVertexBufferAttribute, RendererVertexBufferLayout, SemanticShaderBindingKey,
+        SemanticShaderInputKey, ShaderManager, VertexBuffer, VertexBufferProvider,
+    },
+    math::Vec3,
+    util::FrameArena,
+};
+use bumpalo::collections::Vec as BumpVec;
+use parking_lot::RwLockReadGuard;
+use specs::{prelude::*, Component};
+use std::mem::size_of;
+use wgpu::{
+    BindGroup, Buffer, BufferAddress, CompareFunction, DepthStencilState, FrontFace, PolygonMode,
+    PrimitiveState, PrimitiveTopology, TextureFormat,
+};
+use zerocopy::AsBytes;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineSegment {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub color: Color,
+}
+
+/// Draws colored line segments in the local space of the object, e.g. for debug drawing.
+///
+/// The segments are submitted every frame: they are uploaded through the frame buffer allocator and cleared
+/// once the frame has been drawn. The built-in [`BUILT_IN_SHADER_LINE`](crate::gfx::BUILT_IN_SHADER_LINE)
+/// draws them with their vertex colors.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct LineRenderer {
+    mask: u32,
+    pipeline_provider: PipelineProvider,
+    segments: Vec<LineSegment>,
+    vertex_count: u32,
+    vertex_buffer: Option<GenericBufferAllocation<Buffer>>,
+}
+
+impl LineRenderer {
+    /// Position and color of a vertex.
+    const VERTEX_STRIDE: BufferAddress = size_of::<[f32; 7]>() as BufferAddress;
+
+    pub fn new() -> Self {
+        let mut pipeline_provider = PipelineProvider::new();
+
+        pipeline_provider.set_buffer_layouts(vec![RendererVertexBufferLayout {
+            array_stride: Self::VERTEX_STRIDE,
+            attributes: vec![
+                RendererVertexBufferAttribute {
+                    key: KEY_POSITION,
+                    offset: 0,
+                },
+                RendererVertexBufferAttribute {
+                    key: KEY_VERTEX_COLOR,
+                    offset: size_of::<[f32; 3]>() as BufferAddress,
+                },
+            ],
+        }]);
+        pipeline_provider.set_primitive(PrimitiveState {
+            topology: PrimitiveTopology::LineList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        });
+        pipeline_provider.set_topology(PrimitiveTopology::LineList);
+        pipeline_provider.set_depth_stencil(Some(line_depth_stencil(true)));
+
+        Self {
+            mask: 0xFFFF_FFFF,
+            pipeline_provider,
+            segments: Vec::new(),
+            vertex_count: 0,
+            vertex_buffer: None,
+        }
+    }
+
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    pub fn set_mask(&mut self, mask: u32) {
+        self.mask = mask;
+    }
+
+    /// Sets whether the segments are hidden behind what has been drawn before them.
+    /// Without the depth test, they are drawn over everything and do not write depth.
+    pub fn set_depth_test(&mut self, depth_test: bool) {
+        self.pipeline_provider
+            .set_depth_stencil(Some(line_depth_stencil(depth_test)));
+    }
+
+    pub fn material(&self) -> Option<&MaterialHandle> {
+        self.pipeline_provider.material()
+    }
+
+    pub fn set_material(&mut self, material: MaterialHandle) {
+        self.pipeline_provider.set_material(material);
+    }
+
+    /// Segments submitted for the current frame.
+    pub fn segments(&self) -> &[LineSegment] {
+        &self.segments
+    }
+
+    pub fn push_segment(&mut self, start: Vec3, end: Vec3, color: Color) {
+        self.segments.push(LineSegment { start, end, color });
+    }
+
+    pub fn extend_segments(&mut self, segments: impl IntoIterator<Item = LineSegment>) {
+        self.segments.extend(segments);
+    }
+
+    pub fn clear_segments(&mut self) {
+        self.segments.clear();
+    }
+
+    /// Uploads the segments of the frame, their colors in the given space, and clears them. Called once per frame,
+    /// before any camera draws it.
+    pub fn upload(
+        &mut self,
+        frame_buffer_allocator: &mut FrameBufferAllocator,
+        frame_alloc: &FrameArena,
+        color_space: ColorSpaceMode,
+    ) {
+        let vertices = encode_segment_vertices(&self.segments, color_space, frame_alloc);
+        let mut staging_buffer = frame_buffer_allocator
+            .alloc_staging_buffer(Self::VERTEX_STRIDE * (self.segments.len() * 2) as BufferAddress);
+
+        if !staging_buffer.is_empty() {
+            staging_buffer.copy_from_slice(vertices.as_bytes());
+        }
+
+        self.vertex_count = (self.segments.len() * 2) as u32;
+        self.vertex_buffer = frame_buffer_allocator.commit_staging_buffer(staging_buffer);
+        self.segments.clear();
+    }
+
+    pub fn sub_renderer(
+        &mut self,
+        shader_mgr: &ShaderManager,
+        pipeline_cache: &mut PipelineCache,
+    ) -> Option<LineSubRenderer> {
+        let vertex_buffer = self.vertex_buffer.clone()?;
+        let pipeline = self
+            .pipeline_provider
+            .obtain_pipeline(shader_mgr, pipeline_cache)?;
+        let material = self.pipeline_provider.material().cloned()?;
+
+        Some(LineSubRenderer {
+            pipeline,
+            material,
+            vertex_count: self.vertex_count,
+            bind_group_provider: LineRendererBindGroupProvider,
+            vertex_buffer_provider: LineRendererVertexBufferProvider { vertex_buffer },
+            instance_data_provider: LineRendererInstanceDataProvider,
+        })
+    }
+}
+
+fn line_depth_stencil(depth_test: bool) -> DepthStencilState {
+    DepthStencilState {
+        format: TextureFormat::Depth32Float,
+        depth_write_enabled: depth_test,
+        depth_compare: if depth_test {
+            CompareFunction::Less
+        } else {
+            CompareFunction::Always
+        },
+        stencil: Default::default(),
+        bias: Default::default(),
+    }
+}
+
+/// Interleaves the position and the color of both ends of each segment.
+fn encode_segment_vertices<'a>(
+    segments: &[LineSegment],
+    color_space: ColorSpaceMode,
+    frame_alloc: &'a FrameArena,
+) -> BumpVec<'a, [f32; 7]> {
+    let mut vertices = frame_alloc.alloc_vec(segments.len() * 2);
+    vertices.extend(segments.iter().flat_map(|segment| {
+        let color = color_space.shader_color(segment.color);
+        [segment.start, segment.end].map(|position| {
+            [
+                position.x, position.y, position.z, color.r, color.g, color.b, color.a,
+            ]
+        })
+    }));
+    vertices
+}
+
+pub struct LineSubRenderer {
+    pipeline: CachedPipeline,
+    material: MaterialHandle,
+    vertex_count: u32,
+    bind_group_provider: LineRendererBindGroupProvider,
+    vertex_buffer_provider: LineRendererVertexBufferProvider,
+    instance_data_provider: LineRendererInstanceDataProvider,
+}
+
+impl Renderer for LineSubRenderer {
+    fn pipeline(&self) -> CachedPipeline {
+        self.pipeline.clone()
+    }
+
+    fn material(&self) -> RwLockReadGuard<Material> {
+        self.material.read()
+    }
+
+    fn instance_count(&self) -> u32 {
+        1
+    }
+
+    fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    fn bind_group_provider(&self) -> &dyn BindGroupProvider {
+        &self.bind_group_provider
+    }
+
+    fn vertex_buffer_provider(&self) -> &dyn VertexBufferProvider {
+        &self.vertex_buffer_provider
+    }
+
+    fn instance_data_provider(&self) -> &dyn InstanceDataProvider {
+        &self.instance_data_provider
+    }
+}
+
+struct LineRendererBindGroupProvider;
+
+impl BindGroupProvider for LineRendererBindGroupProvider {
+    fn bind_group(&self, _instance: u32, _key: SemanticShaderBindingKey) -> Option<&BindGroup> {
+        None
+    }
+}
+
+struct LineRendererVertexBufferProvider {
+    vertex_buffer: GenericBufferAllocation<Buffer>,
+}
+
+impl VertexBufferProvider for LineRendererVertexBufferProvider {
+    fn vertex_buffer_count(&self) -> u32 {
+        1
+    }
+
+    fn vertex_buffer(&self, key: SemanticShaderInputKey) -> Option<VertexBuffer> {
+        match key {
+            semantic_inputs::KEY_POSITION | semantic_inputs::KEY_VERTEX_COLOR => {
+                Some(VertexBuffer {
+                    slot: 0,
+                    buffer: &self.vertex_buffer,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+struct LineRendererInstanceDataProvider;
+
+impl InstanceDataProvider for LineRendererInstanceDataProvider {
+    fn copy_per_instance_data(
+        &self,
+        _instance: u32,
+        _key: SemanticShaderInputKey,
+        _buffer: &mut GenericBufferAllocation<HostBuffer>,
+    ) {
+    }
+
+    fn instance_property(&self, _instance: u32, _name: &str) -> Option<&PerInstancePropertyValue> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_segments_become_vertex_pairs() {
+        let frame_alloc = FrameArena::with_capacity(1024);
+        let vertices = encode_segment_vertices(
+            &[LineSegment {
+                start: Vec3::new(0.0, 1.0, 2.0),
+                end: Vec3::new(3.0, 4.0, 5.0),
+                color: Color::from_rgba(1.0, 0.5, 0.25, 1.0),
+            }],
+            ColorSpaceMode::Gamma,
+            &frame_alloc,
+        );
+
+        assert_eq!(
+            vertices.as_slice(),
+            [
+                [0.0, 1.0, 2.0, 1.0, 0.5, 0.25, 1.0],
+                [3.0, 4.0, 5.0, 1.0, 0.5, 0.25, 1.0],
+            ]
+        );
+    }
+}
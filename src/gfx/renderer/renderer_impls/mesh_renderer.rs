@@ -1,50 +1,109 @@
-use crate::gfx::{
-    semantic_inputs::{self, KEY_NORMAL, KEY_POSITION, KEY_UV},
-    BindGroupProvider, CachedPipeline, GenericBufferAllocation, HostBuffer, InstanceDataProvider,
-    Material, MaterialHandle, MeshHandle, PipelineCache, PipelineProvider, Renderer,
-    RendererVertexBufferAttribute, RendererVertexBufferLayout, SemanticShaderBindingKey,
-    SemanticShaderInputKey, ShaderManager, VertexBuffer, VertexBufferProvider,
+use crate::{
+    animation::BoneHandle,
+    gfx::{
+        pick_local_lights, semantic_bindings,
+        semantic_inputs::{
+            self, KEY_BONE_INDICES, KEY_BONE_WEIGHTS, KEY_NORMAL, KEY_POSITION, KEY_TANGENT,
+            KEY_UV, KEY_UV0, KEY_UV1, KEY_VERTEX_COLOR,
+        },
+        BindGroupProvider, CachedPipeline, GenericBufferAllocation, HostBuffer,
+        InstanceDataProvider, InstancedGroup, LocalLightData, Material, MaterialHandle,
+        MaterialInstance, MeshHandle, MeshSkin, PerInstancePropertyValue, PipelineCache,
+        PipelineProvider, Renderer, RendererVertexBufferAttribute, RendererVertexBufferLayout,
+        SemanticShaderBindingKey, SemanticShaderInputKey, ShaderManager, Uploader, VertexBuffer,
+        VertexBufferProvider, MAX_LIGHTS_PER_OBJECT,
+    },
+    math::{Frustum, Mat4, Vec3},
+    object::{transform_aabb, ObjectHierarchy, ObjectId},
 };
+use asset::assets::{Mesh as ModelMesh, VertexAttributeKind, VertexIndexType};
 use parking_lot::RwLockReadGuard;
 use specs::{prelude::*, Component};
-use std::mem::size_of;
+use std::{collections::HashMap, mem::size_of, sync::Arc};
 use wgpu::{
-    util::{BufferInitDescriptor, DeviceExt},
-    BindGroup, Buffer, BufferAddress, BufferSize, BufferUsages, CompareFunction, DepthStencilState,
-    Device, Face, FrontFace, PolygonMode, PrimitiveState, PrimitiveTopology, TextureFormat,
+    BindGroup, Buffer, BufferAddress, BufferDescriptor, BufferSize, BufferUsages, CompareFunction,
+    DepthStencilState, Device, Face, FrontFace, IndexFormat, PolygonMode, PrimitiveState,
+    PrimitiveTopology, TextureFormat,
 };
 use zerocopy::AsBytes;
 
+/// Layout of the vertices of a [`Mesh`](crate::gfx::Mesh) and of dynamic vertices, as `[position, normal, uv]`.
+fn standard_buffer_layout() -> RendererVertexBufferLayout {
+    RendererVertexBufferLayout {
+        array_stride: size_of::<[f32; 8]>() as BufferAddress,
+        attributes: vec![
+            RendererVertexBufferAttribute {
+                key: KEY_POSITION,
+                offset: 0,
+            },
+            RendererVertexBufferAttribute {
+                key: KEY_NORMAL,
+                offset: size_of::<[f32; 3]>() as BufferAddress,
+            },
+            RendererVertexBufferAttribute {
+                key: KEY_UV,
+                offset: size_of::<[f32; 6]>() as BufferAddress,
+            },
+        ],
+    }
+}
+
+/// Layout of the vertices of a mesh of a model asset, from its attributes. Only the first two UV sets and the first
+/// color set are read.
+fn model_buffer_layout(mesh: &ModelMesh) -> RendererVertexBufferLayout {
+    RendererVertexBufferLayout {
+        array_stride: mesh.vertex_buffer.size() / mesh.vertex_count as BufferAddress,
+        attributes: Vec::from_iter(mesh.vertex_attributes.iter().filter_map(|attribute| {
+            let key = match attribute.kind {
+                VertexAttributeKind::Position => KEY_POSITION,
+                VertexAttributeKind::Normal => KEY_NORMAL,
+                VertexAttributeKind::TexCoord { index: 0 } => KEY_UV0,
+                VertexAttributeKind::TexCoord { index: 1 } => KEY_UV1,
+                VertexAttributeKind::Tangent => KEY_TANGENT,
+                VertexAttributeKind::BoneIndices => KEY_BONE_INDICES,
+                VertexAttributeKind::BoneWeights => KEY_BONE_WEIGHTS,
+                VertexAttributeKind::Color { index: 0 } => KEY_VERTEX_COLOR,
+                _ => return None,
+            };
+
+            Some(RendererVertexBufferAttribute {
+                key,
+                offset: attribute.offset as BufferAddress,
+            })
+        })),
+    }
+}
+
 #[derive(Component)]
 #[storage(HashMapStorage)]
 pub struct MeshRenderer {
     mask: u32,
     pipeline_provider: PipelineProvider,
     mesh: Option<MeshHandle>,
+    /// `true` if the vertices are laid out as the ones of a mesh of a model asset, see
+    /// [`set_model_mesh`](Self::set_model_mesh).
+    is_model_layout: bool,
+    material_slot: u32,
+    local_bounds: Option<(Vec3, Vec3)>,
+    bounds_override: Option<(Vec3, Vec3)>,
+    /// Bounds of the mesh in the current pose of the skin, computed by [`update_skin`](Self::update_skin).
+    skinned_bounds: Option<(Vec3, Vec3)>,
     vertex_buffer: Option<GenericBufferAllocation<Buffer>>,
+    index_buffer: Option<(GenericBufferAllocation<Buffer>, IndexFormat, u32)>,
+    vertex_count: u32,
+    /// `true` if the vertex buffer is written by [`set_dynamic_vertices`](Self::set_dynamic_vertices).
+    is_dynamic: bool,
+    instanced_group: Option<InstancedGroup>,
+    material_instance: MaterialInstance,
+    is_batching: bool,
+    skin: Option<MeshSkin>,
 }
 
 impl MeshRenderer {
     pub fn new() -> Self {
         let mut pipeline_provider = PipelineProvider::new();
 
-        pipeline_provider.set_buffer_layouts(vec![RendererVertexBufferLayout {
-            array_stride: size_of::<[f32; 8]>() as BufferAddress,
-            attributes: vec![
-                RendererVertexBufferAttribute {
-                    key: KEY_POSITION,
-                    offset: 0,
-                },
-                RendererVertexBufferAttribute {
-                    key: KEY_NORMAL,
-                    offset: size_of::<[f32; 3]>() as BufferAddress,
-                },
-                RendererVertexBufferAttribute {
-                    key: KEY_UV,
-                    offset: size_of::<[f32; 6]>() as BufferAddress,
-                },
-            ],
-        }]);
+        pipeline_provider.set_buffer_layouts(vec![standard_buffer_layout()]);
         pipeline_provider.set_primitive(PrimitiveState {
             topology: PrimitiveTopology::TriangleList,
             strip_index_format: None,
@@ -66,7 +125,19 @@ impl MeshRenderer {
             mask: 0xFFFF_FFFF,
             pipeline_provider,
             mesh: None,
+            is_model_layout: false,
+            material_slot: 0,
+            local_bounds: None,
+            bounds_override: None,
+            skinned_bounds: None,
             vertex_buffer: None,
+            index_buffer: None,
+            vertex_count: 0,
+            is_dynamic: false,
+            instanced_group: None,
+            material_instance: MaterialInstance::new(None),
+            is_batching: true,
+            skin: None,
         }
     }
 
@@ -78,49 +149,314 @@ impl MeshRenderer {
         self.mask = mask;
     }
 
+    /// Returns the material shared with the other renderers; writing it changes all of them.
+    /// See [`material_instance`](Self::material_instance) to change this renderer alone.
+    pub fn material(&self) -> Option<&MaterialHandle> {
+        self.material_instance.shared()
+    }
+
+    /// Replaces the shared material, dropping the copy the material instance may have made of the previous one.
     pub fn set_material(&mut self, material: MaterialHandle) {
+        self.material_instance.set_shared(Some(material.clone()));
         self.pipeline_provider.set_material(material);
     }
 
+    /// Returns the material of this renderer alone, copied on write from the shared one.
+    pub fn material_instance(&mut self) -> &mut MaterialInstance {
+        &mut self.material_instance
+    }
+
+    /// Returns the material this renderer draws with: the copy its material instance has made if any, the shared
+    /// material otherwise.
+    pub fn drawn_material(&self) -> Option<&MaterialHandle> {
+        self.material_instance.material()
+    }
+
+    pub fn instance_property(&self, name: &str) -> Option<&PerInstancePropertyValue> {
+        self.material_instance.property_overrides().get(name)
+    }
+
+    /// Overrides the material's per-instance property of the given name for this renderer only.
+    /// Values whose format doesn't match the property are ignored when rendering.
+    pub fn set_instance_property(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<PerInstancePropertyValue>,
+    ) {
+        self.material_instance
+            .set_property_override(name.into(), value.into());
+    }
+
+    pub fn remove_instance_property(&mut self, name: &str) -> Option<PerInstancePropertyValue> {
+        self.material_instance.remove_property_override(name)
+    }
+
+    pub fn is_batching(&self) -> bool {
+        self.is_batching
+    }
+
+    /// Lets the renderer be drawn in one command with the others sharing its mesh and material, as instances.
+    /// Each object still gets its own transform and per-instance properties. Turn it off for a shader that must
+    /// see one object per draw, e.g. one reading the instance index. Enabled by default.
+    pub fn set_batching(&mut self, is_batching: bool) {
+        self.is_batching = is_batching;
+    }
+
+    pub fn skin(&self) -> Option<&MeshSkin> {
+        self.skin.as_ref()
+    }
+
+    pub fn skin_mut(&mut self) -> Option<&mut MeshSkin> {
+        self.skin.as_mut()
+    }
+
+    /// Finds a bone of the skin by name. `None` if the renderer has no skin, or its skin has no such bone.
+    pub fn bone_handle(&self, name: &str) -> Option<BoneHandle> {
+        self.skin.as_ref()?.bone_handle(name)
+    }
+
+    /// Deforms the mesh by the bones of the skin, for shaders reading `bone_matrices`. Skinned renderers are never
+    /// batched, as each one binds its own bone matrices.
+    pub fn set_skin(&mut self, skin: Option<MeshSkin>) {
+        self.skin = skin;
+        self.skinned_bounds = None;
+    }
+
+    /// Uploads the skinning matrices of the skin for the current matrices of the hierarchy, and moves the bounds to
+    /// the current pose, so that a pose leaving the bind pose bounds is neither culled nor missed by picking.
+    pub(crate) fn update_skin(
+        &mut self,
+        hierarchy: &ObjectHierarchy,
+        object_id: ObjectId,
+        uploader: &mut Uploader,
+    ) {
+        let skin = match &self.skin {
+            Some(skin) => skin,
+            None => return,
+        };
+        let matrices = skin.skinning_matrices(hierarchy, object_id);
+        skin.upload(&matrices, uploader);
+        self.skinned_bounds = self
+            .local_bounds
+            .map(|(min, max)| MeshSkin::skinned_bounds(&matrices, min, max));
+    }
+
+    pub fn instanced_group(&self) -> Option<&InstancedGroup> {
+        self.instanced_group.as_ref()
+    }
+
+    pub fn instanced_group_mut(&mut self) -> Option<&mut InstancedGroup> {
+        self.instanced_group.as_mut()
+    }
+
+    /// Renders the mesh once per instance of the group instead of once at the object's transform.
+    /// Instances are culled per camera, on the GPU if supported.
+    pub fn set_instanced_group(&mut self, instanced_group: Option<InstancedGroup>) {
+        self.instanced_group = instanced_group;
+    }
+
+    pub fn mesh(&self) -> Option<&MeshHandle> {
+        self.mesh.as_ref()
+    }
+
+    /// Index of the material slot of the model the mesh came from; see [`spawn_model`](crate::object::spawn_model).
+    pub fn material_slot(&self) -> u32 {
+        self.material_slot
+    }
+
+    pub fn set_material_slot(&mut self, material_slot: u32) {
+        self.material_slot = material_slot;
+    }
+
+    /// Bounding box of the mesh in object space, as `(min, max)`.
+    pub fn local_bounds(&self) -> Option<(Vec3, Vec3)> {
+        self.local_bounds
+    }
+
+    pub fn bounds_override(&self) -> Option<(Vec3, Vec3)> {
+        self.bounds_override
+    }
+
+    /// Replaces the bounding box of the mesh for culling and picking, e.g. for a mesh deformed in the shader
+    /// beyond its vertices. `None` goes back to the bounds of the mesh.
+    pub fn set_bounds_override(&mut self, bounds: Option<(Vec3, Vec3)>) {
+        self.bounds_override = bounds;
+    }
+
+    /// Bounding box culled and picked against in object space: the override if any, the mesh's otherwise. The mesh's
+    /// bounds of a skinned renderer are the ones in the current pose, unknown until the skin is first updated.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        if self.bounds_override.is_some() {
+            return self.bounds_override;
+        }
+
+        match self.skin {
+            Some(_) => self.skinned_bounds,
+            None => self.local_bounds,
+        }
+    }
+
+    /// Returns `false` if the bounds placed by the matrix are outside of the frustum. Renderers without bounds
+    /// and instanced ones, whose instances are culled on their own, are always inside.
+    pub fn is_in_frustum(&self, matrix: &Mat4, frustum: &Frustum) -> bool {
+        if self.instanced_group.is_some() {
+            return true;
+        }
+
+        match self.bounds() {
+            Some((min, max)) => {
+                let (min, max) = transform_aabb(min, max, matrix);
+                frustum.intersects_aabb(min, max)
+            }
+            None => true,
+        }
+    }
+
     pub fn set_mesh(&mut self, mesh: MeshHandle, device: &Device) {
-        if mesh.data.vertices.is_empty() {
+        self.is_dynamic = false;
+        self.use_standard_layout();
+
+        let buffers = if let Some(buffers) = mesh.buffers(device) {
+            buffers.clone()
+        } else {
             self.mesh = None;
+            self.local_bounds = None;
             self.vertex_buffer = None;
+            self.index_buffer = None;
+            self.vertex_count = 0;
             return;
-        }
+        };
 
         self.mesh = Some(mesh.clone());
+        self.local_bounds = mesh.bounds();
+        self.vertex_buffer = Some(buffers.vertex_buffer);
+        self.index_buffer = Some((
+            buffers.index_buffer,
+            buffers.index_format,
+            buffers.index_count,
+        ));
+        self.vertex_count = buffers.vertex_count;
+    }
+
+    /// Replaces the mesh with triangles of `[position, normal, uv]` generated on the CPU, e.g. by a
+    /// [`Cloth`](crate::gfx::Cloth). The buffer is written in place while the vertex count stays the same,
+    /// so it can change every frame.
+    pub fn set_dynamic_vertices(
+        &mut self,
+        vertices: &[[f32; 8]],
+        device: &Device,
+        uploader: &mut Uploader,
+    ) {
+        self.mesh = None;
+        self.index_buffer = None;
+        self.use_standard_layout();
 
-        let mut vertices = Vec::with_capacity(mesh.data.faces.len() * 3 * (3 + 3 + 2));
-        let uvs = mesh.data.texture_coords[0].as_ref().unwrap();
+        if vertices.is_empty() {
+            self.local_bounds = None;
+            self.vertex_buffer = None;
+            self.vertex_count = 0;
+            self.is_dynamic = false;
+            return;
+        }
 
-        for face in &mesh.data.faces {
-            for &face_index in &face.0 {
-                let vertex = &mesh.data.vertices[face_index as usize];
-                vertices.push(vertex.x);
-                vertices.push(vertex.y);
-                vertices.push(vertex.z);
+        self.local_bounds = Some(vertices.iter().fold(
+            (
+                Vec3::new(f32::MAX, f32::MAX, f32::MAX),
+                Vec3::new(f32::MIN, f32::MIN, f32::MIN),
+            ),
+            |(min, max), vertex| {
+                let position = Vec3::new(vertex[0], vertex[1], vertex[2]);
+                (Vec3::min(min, position), Vec3::max(max, position))
+            },
+        ));
 
-                let normal = &mesh.data.normals[face_index as usize];
-                vertices.push(normal.x);
-                vertices.push(normal.y);
-                vertices.push(normal.z);
+        let size = (size_of::<[f32; 8]>() * vertices.len()) as BufferAddress;
+        let is_reusable = self.is_dynamic
+            && self
+                .vertex_buffer
+                .as_ref()
+                .is_some_and(|vertex_buffer| vertex_buffer.size().get() == size);
 
-                let uv = &uvs[face_index as usize];
-                vertices.push(uv.x);
-                vertices.push(uv.y);
-            }
+        if !is_reusable {
+            self.vertex_buffer = Some(GenericBufferAllocation::new(
+                device.create_buffer(&BufferDescriptor {
+                    label: Some("dynamic mesh"),
+                    size,
+                    // Frame captures copy the vertices out.
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                0,
+                BufferSize::new(size).unwrap(),
+            ));
         }
 
-        self.vertex_buffer = Some(GenericBufferAllocation::new(
-            device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: vertices.as_bytes(),
-                usage: BufferUsages::VERTEX,
-            }),
+        uploader.write(
+            self.vertex_buffer.as_ref().unwrap().buffer(),
             0,
-            BufferSize::new((size_of::<f32>() * vertices.len()) as u64).unwrap(),
+            vertices.as_bytes(),
+        );
+        self.vertex_count = vertices.len() as u32;
+        self.is_dynamic = true;
+    }
+
+    /// Draws a mesh of a model asset, sharing the buffers the asset uploaded with every other renderer of it.
+    /// Its vertices are read with their own layout. Meshes with 8-bit indices cannot be drawn and clear the mesh.
+    pub fn set_model_mesh(&mut self, mesh: &ModelMesh) {
+        self.mesh = None;
+        self.is_dynamic = false;
+
+        let index_format = match mesh.index_type {
+            VertexIndexType::U8 => None,
+            VertexIndexType::U16 => Some(IndexFormat::Uint16),
+            VertexIndexType::U32 => Some(IndexFormat::Uint32),
+        };
+        let sizes = BufferSize::new(mesh.vertex_buffer.size())
+            .zip(BufferSize::new(mesh.index_buffer.size()));
+        let (index_format, (vertex_size, index_size)) = match (index_format, sizes) {
+            (Some(index_format), Some(sizes)) if mesh.vertex_count != 0 => (index_format, sizes),
+            _ => {
+                self.use_standard_layout();
+                self.local_bounds = None;
+                self.vertex_buffer = None;
+                self.index_buffer = None;
+                self.vertex_count = 0;
+                return;
+            }
+        };
+        let index_stride = match index_format {
+            IndexFormat::Uint16 => size_of::<u16>(),
+            IndexFormat::Uint32 => size_of::<u32>(),
+        };
+
+        self.pipeline_provider
+            .set_buffer_layouts(vec![model_buffer_layout(mesh)]);
+        self.is_model_layout = true;
+        self.local_bounds = Some((
+            Vec3::new(mesh.aabb.min[0], mesh.aabb.min[1], mesh.aabb.min[2]),
+            Vec3::new(mesh.aabb.max[0], mesh.aabb.max[1], mesh.aabb.max[2]),
+        ));
+        self.vertex_buffer = Some(GenericBufferAllocation::from_shared(
+            mesh.vertex_buffer.clone(),
+            0,
+            vertex_size,
         ));
+        self.index_buffer = Some((
+            GenericBufferAllocation::from_shared(mesh.index_buffer.clone(), 0, index_size),
+            index_format,
+            (index_size.get() / index_stride as BufferAddress) as u32,
+        ));
+        self.vertex_count = mesh.vertex_count;
+    }
+
+    fn use_standard_layout(&mut self) {
+        if !self.is_model_layout {
+            return;
+        }
+
+        self.pipeline_provider
+            .set_buffer_layouts(vec![standard_buffer_layout()]);
+        self.is_model_layout = false;
     }
 
     pub fn sub_renderer(
@@ -128,33 +464,91 @@ impl MeshRenderer {
         shader_mgr: &ShaderManager,
         pipeline_cache: &mut PipelineCache,
     ) -> Option<MeshSubRenderer> {
+        if self.vertex_count == 0 {
+            return None;
+        }
+
+        let material = self.material_instance.material().cloned()?;
+
+        // The material instance may have copied the material, or dropped its copy, since.
+        if self.pipeline_provider.material() != Some(&material) {
+            self.pipeline_provider.set_material(material.clone());
+        }
+
         let pipeline = self
             .pipeline_provider
             .obtain_pipeline(shader_mgr, pipeline_cache)?;
-        let material = self.pipeline_provider.material().cloned()?;
         let vertex_buffer = self.vertex_buffer.clone()?;
-        let mesh = self.mesh.as_ref()?;
 
         Some(MeshSubRenderer {
             pipeline,
             material,
-            vertex_count: mesh.data.faces.len() as u32 * 3,
-            bind_group_provider: MeshRendererBindGroupProvider,
+            vertex_count: self.vertex_count,
+            index_buffer: self.index_buffer.clone(),
+            is_batching: self.is_batching && self.skin.is_none(),
+            bind_group_provider: MeshRendererBindGroupProvider {
+                bone_matrices: self.skin.as_ref().map(|skin| skin.bind_group().clone()),
+            },
+            bounds: self.bounds(),
             vertex_buffer_provider: MeshRendererVertexBufferProvider { vertex_buffer },
-            instance_data_provider: MeshRendererInstanceDataProvider,
+            instance_data_provider: MeshRendererInstanceDataProvider {
+                instance_properties: self.material_instance.property_overrides().clone(),
+                light_indices: [-1.0; MAX_LIGHTS_PER_OBJECT],
+            },
         })
     }
 }
 
+/// Mesh sub renderers of equal keys can be drawn as instances of one command.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct MeshBatchKey {
+    pipeline: CachedPipeline,
+    material: MaterialHandle,
+    vertex_buffer: (*const Buffer, BufferAddress),
+}
+
 pub struct MeshSubRenderer {
     pipeline: CachedPipeline,
     material: MaterialHandle,
     vertex_count: u32,
+    index_buffer: Option<(GenericBufferAllocation<Buffer>, IndexFormat, u32)>,
+    is_batching: bool,
     bind_group_provider: MeshRendererBindGroupProvider,
+    bounds: Option<(Vec3, Vec3)>,
     vertex_buffer_provider: MeshRendererVertexBufferProvider,
     instance_data_provider: MeshRendererInstanceDataProvider,
 }
 
+impl MeshSubRenderer {
+    /// Returns the material drawn with; renderers drawing with the same one can be batched.
+    pub fn material_handle(&self) -> &MaterialHandle {
+        &self.material
+    }
+
+    /// Returns the key of the batch the renderer may be drawn in, or `None` if it opted out of batching.
+    pub fn batch_key(&self) -> Option<MeshBatchKey> {
+        if !self.is_batching {
+            return None;
+        }
+
+        let vertex_buffer = &self.vertex_buffer_provider.vertex_buffer;
+        Some(MeshBatchKey {
+            pipeline: self.pipeline.clone(),
+            material: self.material.clone(),
+            vertex_buffer: (Arc::as_ptr(vertex_buffer.buffer()), vertex_buffer.offset()),
+        })
+    }
+
+    /// Picks the local lights shading the renderer by its bounds placed by the matrix, see [`pick_local_lights`].
+    /// Renderers without bounds are shaded by none.
+    pub fn pick_lights(&mut self, matrix: &Mat4, lights: &[LocalLightData]) {
+        if let Some((min, max)) = self.bounds {
+            let (min, max) = transform_aabb(min, max, matrix);
+            self.instance_data_provider.light_indices = pick_local_lights(min, max, lights);
+        }
+    }
+}
+
 impl Renderer for MeshSubRenderer {
     fn pipeline(&self) -> CachedPipeline {
         self.pipeline.clone()
@@ -172,6 +566,10 @@ impl Renderer for MeshSubRenderer {
         self.vertex_count
     }
 
+    fn index_buffer(&self) -> Option<(GenericBufferAllocation<Buffer>, IndexFormat, u32)> {
+        self.index_buffer.clone()
+    }
+
     fn bind_group_provider(&self) -> &dyn BindGroupProvider {
         &self.bind_group_provider
     }
@@ -185,11 +583,16 @@ impl Renderer for MeshSubRenderer {
     }
 }
 
-struct MeshRendererBindGroupProvider;
+struct MeshRendererBindGroupProvider {
+    bone_matrices: Option<Arc<BindGroup>>,
+}
 
 impl BindGroupProvider for MeshRendererBindGroupProvider {
-    fn bind_group(&self, _instance: u32, _key: SemanticShaderBindingKey) -> Option<&BindGroup> {
-        None
+    fn bind_group(&self, _instance: u32, key: SemanticShaderBindingKey) -> Option<&BindGroup> {
+        match key {
+            semantic_bindings::KEY_BONE_MATRICES => self.bone_matrices.as_deref(),
+            _ => None,
+        }
     }
 }
 
@@ -206,7 +609,12 @@ impl VertexBufferProvider for MeshRendererVertexBufferProvider {
         match key {
             semantic_inputs::KEY_POSITION
             | semantic_inputs::KEY_NORMAL
-            | semantic_inputs::KEY_UV => Some(VertexBuffer {
+            | semantic_inputs::KEY_UV
+            | semantic_inputs::KEY_UV1
+            | semantic_inputs::KEY_TANGENT
+            | semantic_inputs::KEY_BONE_INDICES
+            | semantic_inputs::KEY_BONE_WEIGHTS
+            | semantic_inputs::KEY_VERTEX_COLOR => Some(VertexBuffer {
                 slot: 0,
                 buffer: &self.vertex_buffer,
             }),
@@ -215,14 +623,172 @@ impl VertexBufferProvider for MeshRendererVertexBufferProvider {
     }
 }
 
-struct MeshRendererInstanceDataProvider;
+struct MeshRendererInstanceDataProvider {
+    instance_properties: HashMap<String, PerInstancePropertyValue>,
+    light_indices: [f32; MAX_LIGHTS_PER_OBJECT],
+}
 
 impl InstanceDataProvider for MeshRendererInstanceDataProvider {
     fn copy_per_instance_data(
         &self,
         _instance: u32,
-        _key: SemanticShaderInputKey,
-        _buffer: &mut GenericBufferAllocation<HostBuffer>,
+        key: SemanticShaderInputKey,
+        buffer: &mut GenericBufferAllocation<HostBuffer>,
     ) {
+        if key == semantic_inputs::KEY_LIGHT_INDICES {
+            buffer.copy_from_slice(self.light_indices.as_bytes());
+        }
+    }
+
+    fn instance_property(&self, _instance: u32, name: &str) -> Option<&PerInstancePropertyValue> {
+        self.instance_properties.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gfx::{create_test_gfx, DepthStencilMode, Mesh, BUILT_IN_SHADER_LIT},
+        transform::Transform,
+    };
+    use russimp::{face::Face as RussimpFace, mesh::Mesh as RussimpMesh, Vector3D};
+
+    /// Triangles of a unit cube around the origin as `[position, normal, uv]`; only the bounds matter here.
+    fn cube_vertices() -> Vec<[f32; 8]> {
+        Vec::from_iter([-0.5, 0.5].into_iter().flat_map(|x| {
+            [-0.5, 0.5]
+                .into_iter()
+                .flat_map(move |y| [-0.5, 0.5].map(|z| [x, y, z, 0.0, 1.0, 0.0, 0.0, 0.0]))
+        }))
+    }
+
+    /// A single triangle facing the camera, as a model file would describe it.
+    fn triangle_mesh() -> Mesh {
+        let vector = |x, y, z| Vector3D { x, y, z };
+
+        Mesh::new(RussimpMesh {
+            vertices: vec![
+                vector(-0.5, -0.5, 0.0),
+                vector(0.5, -0.5, 0.0),
+                vector(0.0, 0.5, 0.0),
+            ],
+            normals: vec![vector(0.0, 0.0, 1.0); 3],
+            texture_coords: vec![Some(vec![vector(0.0, 0.0, 0.0); 3])],
+            faces: vec![RussimpFace(vec![0, 1, 2])],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn check_static_meshes_only_add_their_instance_data() {
+        let mut gfx = match create_test_gfx(16, 16, DepthStencilMode::DepthOnly) {
+            Some(gfx) => gfx,
+            None => return,
+        };
+        let gfx_ctx = gfx.gfx_ctx().clone();
+        let (render_mgr, shader_mgr, built_in_shader_mgr) = gfx.split_mut();
+        let shader = built_in_shader_mgr
+            .find_shader(BUILT_IN_SHADER_LIT)
+            .unwrap();
+        let stride = shader.reflected_shader.per_instance_input.stride;
+        assert_ne!(stride, 0);
+
+        let material =
+            MaterialHandle::new(Material::new(shader, render_mgr.pipeline_layout_cache()));
+        let mesh = MeshHandle::new(triangle_mesh());
+        let mut mesh_renderers = Vec::from_iter((0..8).map(|_| {
+            let mut mesh_renderer = MeshRenderer::new();
+            mesh_renderer.set_material(material.clone());
+            mesh_renderer.set_mesh(mesh.clone(), &gfx_ctx.device);
+            mesh_renderer
+        }));
+        let sub_renderers = Vec::from_iter(mesh_renderers.iter_mut().map(|mesh_renderer| {
+            mesh_renderer
+                .sub_renderer(shader_mgr, render_mgr.pipeline_cache())
+                .unwrap()
+        }));
+
+        // The vertices are uploaded once, with the mesh, so each renderer only adds its instance.
+        let mut frame_buffer_bytes = |count: usize| {
+            gfx.render_frame(|render_mgr, _, _| {
+                for sub_renderer in &sub_renderers[..count] {
+                    render_mgr
+                        .build_detached_rendering_command(&Mat4::identity(), sub_renderer)
+                        .unwrap();
+                }
+            })
+            .unwrap();
+            gfx.render_mgr().frame_report().frame_buffer_bytes
+        };
+        let base = frame_buffer_bytes(0);
+
+        for count in [1, 2, 8] {
+            assert_eq!(frame_buffer_bytes(count), base + stride * count as u64);
+        }
+    }
+
+    #[test]
+    fn check_skinned_renderer_is_culled_in_its_pose() {
+        let mut gfx = match create_test_gfx(16, 16, DepthStencilMode::DepthOnly) {
+            Some(gfx) => gfx,
+            None => return,
+        };
+        let gfx_ctx = gfx.gfx_ctx().clone();
+        let device = &gfx_ctx.device;
+        let render_mgr = gfx.render_mgr_mut();
+
+        let mut world = World::new();
+        let mut hierarchy = ObjectHierarchy::new();
+        let [mesh_object, bone] = [0, 1].map(ObjectId::from_u32);
+        hierarchy.add(mesh_object, world.create_entity().build());
+        hierarchy.add(bone, world.create_entity().build());
+
+        // The bone rests at the origin, where the whole cube is bound to it.
+        let mut mesh_renderer = MeshRenderer::new();
+        mesh_renderer.set_dynamic_vertices(&cube_vertices(), device, render_mgr.uploader_mut());
+        mesh_renderer.set_skin(Some(MeshSkin::new(
+            [(Some(bone), Mat4::identity())],
+            device,
+            render_mgr.bind_group_layout_cache(),
+        )));
+
+        // Looks at a 2x2 area around x = 10, away from the bind pose.
+        let frustum = Frustum::from_view_projection(
+            &(Mat4::translation(Vec3::new(-10.0, 0.0, 0.0))
+                * &Mat4::orthographic(-1.0, 1.0, -1.0, 1.0, -10.0, 10.0)),
+        );
+        let mut transforms = HashMap::new();
+        transforms.insert(hierarchy.entity(mesh_object), Transform::new());
+        transforms.insert(hierarchy.entity(bone), Transform::new());
+        hierarchy.update_object_matrices(|entity| transforms.get(&entity));
+
+        // Unknown until the skin is updated, so never culled.
+        assert!(mesh_renderer.is_in_frustum(hierarchy.matrix(mesh_object), &frustum));
+
+        // Each update uploads the matrices, so it runs in a frame of its own.
+        let mut update_skin = |mesh_renderer: &mut MeshRenderer, hierarchy: &ObjectHierarchy| {
+            gfx.render_frame(|render_mgr, _, _| {
+                mesh_renderer.update_skin(hierarchy, mesh_object, render_mgr.uploader_mut())
+            })
+            .unwrap();
+        };
+        update_skin(&mut mesh_renderer, &hierarchy);
+        assert!(!mesh_renderer.is_in_frustum(hierarchy.matrix(mesh_object), &frustum));
+
+        // The bone carries the cube out of its bind pose bounds, into the view.
+        transforms
+            .get_mut(&hierarchy.entity(bone))
+            .unwrap()
+            .position = Vec3::new(10.0, 0.0, 0.0);
+        hierarchy.set_dirty(bone);
+        hierarchy.update_object_matrices(|entity| transforms.get(&entity));
+        update_skin(&mut mesh_renderer, &hierarchy);
+
+        assert_eq!(
+            mesh_renderer.bounds(),
+            Some((Vec3::new(9.5, -0.5, -0.5), Vec3::new(10.5, 0.5, 0.5)))
+        );
+        assert!(mesh_renderer.is_in_frustum(hierarchy.matrix(mesh_object), &frustum));
     }
 }
@@ -0,0 +1,197 @@
+use crate::{
+    animation::{BoneHandle, Skeleton},
+    gfx::{BindGroupLayoutCache, Uploader},
+    math::{Mat4, Vec3},
+    object::{transform_aabb, ObjectHierarchy, ObjectId},
+};
+use std::{mem::size_of, sync::Arc};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutEntry, BindingType, Buffer,
+    BufferAddress, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, Device,
+    ShaderStages,
+};
+use zerocopy::AsBytes;
+
+/// Length of the `bone_matrices` uniform array; the most bones a [`MeshSkin`] moves its mesh with.
+pub const MESH_SKIN_MAX_BONES: usize = 256;
+
+/// Bones deforming the mesh of a [`MeshRenderer`](super::MeshRenderer), each moved by an object of the hierarchy,
+/// e.g. the nodes of a model spawned by [`spawn_model`](crate::object::spawn_model).
+///
+/// Every frame, once the object matrices are updated, a skinning matrix per bone takes the vertices from the bind
+/// pose of the mesh to the current pose of the object of the bone, in the space of the object of the renderer.
+/// They are written into the `bone_matrices` uniform array, indexed by the `bone_indices` of the vertices. Bones
+/// past [`MESH_SKIN_MAX_BONES`] are dropped, and bones without an object keep their vertices in the bind pose.
+///
+/// A skin with a [`Skeleton`] finds its bones by name, see [`bone_handle`](Self::bone_handle).
+pub struct MeshSkin {
+    bones: Vec<Option<ObjectId>>,
+    skeleton: Option<Skeleton>,
+    inverse_bind_matrices: Vec<Mat4>,
+    uniform_buffer: Buffer,
+    bind_group: Arc<BindGroup>,
+}
+
+impl MeshSkin {
+    /// Creates a skin of the bones, given as the objects moving them and their inverse bind matrices in the order of
+    /// the bone indices of the vertices.
+    pub fn new(
+        bones: impl IntoIterator<Item = (Option<ObjectId>, Mat4)>,
+        device: &Device,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) -> Self {
+        let (bones, inverse_bind_matrices): (Vec<_>, Vec<_>) =
+            bones.into_iter().take(MESH_SKIN_MAX_BONES).unzip();
+        let size = size_of::<[Mat4; MESH_SKIN_MAX_BONES]>() as BufferAddress;
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("bone matrices"),
+            size,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = bind_group_layout_cache.create_layout(vec![BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX_FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(size),
+            },
+            count: None,
+        }]);
+        let bind_group = Arc::new(device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: bind_group_layout.as_ref(),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        }));
+
+        Self {
+            bones,
+            skeleton: None,
+            inverse_bind_matrices,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    /// Names the bones by the skeleton, whose bones are in the order of the bone indices.
+    pub fn with_skeleton(mut self, skeleton: Skeleton) -> Self {
+        self.skeleton = Some(skeleton);
+        self
+    }
+
+    /// Objects moving the bones, in the order of the bone indices.
+    pub fn bones(&self) -> &[Option<ObjectId>] {
+        &self.bones
+    }
+
+    pub fn skeleton(&self) -> Option<&Skeleton> {
+        self.skeleton.as_ref()
+    }
+
+    /// Finds a bone of the skeleton by name, e.g. to attach an object to it with
+    /// [`attach_to_bone`](crate::object::ObjectHandle::attach_to_bone).
+    pub fn bone_handle(&self, name: &str) -> Option<BoneHandle> {
+        let index = self.skeleton.as_ref()?.bone_index(name)?;
+        Some(BoneHandle::new(name, index))
+    }
+
+    /// Returns the object moving the bone, if the skeleton still has a bone of its name.
+    pub fn bone_object(&self, bone: &BoneHandle) -> Option<ObjectId> {
+        let index = bone.resolve(self.skeleton.as_ref()?)?;
+        self.bones.get(index).copied().flatten()
+    }
+
+    /// Replaces the object moving a bone, e.g. to drive it from another skeleton. Out of range bones are ignored.
+    pub fn set_bone(&mut self, index: usize, object: Option<ObjectId>) {
+        if let Some(bone) = self.bones.get_mut(index) {
+            *bone = object;
+        }
+    }
+
+    /// Computes the skinning matrices for the current matrices of the hierarchy, for the renderer on `mesh_object`.
+    pub fn skinning_matrices(
+        &self,
+        hierarchy: &ObjectHierarchy,
+        mesh_object: ObjectId,
+    ) -> Vec<Mat4> {
+        let inverse_mesh_matrix = hierarchy.matrix(mesh_object).inversed();
+
+        Vec::from_iter(self.bones.iter().zip(&self.inverse_bind_matrices).map(
+            |(bone, inverse_bind_matrix)| match bone {
+                Some(bone) if hierarchy.contains(*bone) => skinning_matrix(
+                    inverse_bind_matrix,
+                    hierarchy.matrix(*bone),
+                    &inverse_mesh_matrix,
+                ),
+                _ => Mat4::identity(),
+            },
+        ))
+    }
+
+    /// Bounding box of the vertices in the bind pose bounds `(min, max)`, moved by the skinning matrices. Each vertex is
+    /// a weighted average of its positions moved by its bones, which lie in the boxes of their bones, so it is inside
+    /// the union of the boxes of all bones.
+    pub fn skinned_bounds(matrices: &[Mat4], min: Vec3, max: Vec3) -> (Vec3, Vec3) {
+        matrices
+            .iter()
+            .map(|matrix| transform_aabb(min, max, matrix))
+            .reduce(|(min, max), (bone_min, bone_max)| {
+                (Vec3::min(min, bone_min), Vec3::max(max, bone_max))
+            })
+            .unwrap_or((min, max))
+    }
+
+    pub fn upload(&self, matrices: &[Mat4], uploader: &mut Uploader) {
+        let count = matrices.len().min(MESH_SKIN_MAX_BONES);
+
+        if count != 0 {
+            uploader.write(&self.uniform_buffer, 0, matrices[..count].as_bytes());
+        }
+    }
+
+    pub(super) fn bind_group(&self) -> &Arc<BindGroup> {
+        &self.bind_group
+    }
+}
+
+/// Matrix moving a vertex from the bind pose in the space of the mesh to the pose of the bone, in the space of the
+/// object of the mesh. `bone_matrix` is the world matrix of the object of the bone.
+pub fn skinning_matrix(
+    inverse_bind_matrix: &Mat4,
+    bone_matrix: &Mat4,
+    inverse_mesh_matrix: &Mat4,
+) -> Mat4 {
+    inverse_bind_matrix.clone() * bone_matrix * inverse_mesh_matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_bones_in_the_bind_pose_leave_the_vertices() {
+        let bind = Mat4::translation(Vec3::new(0.0, 2.0, 0.0));
+        let mesh = Mat4::translation(Vec3::new(5.0, 0.0, 0.0));
+        let bone = bind.clone() * &mesh;
+
+        assert_eq!(
+            skinning_matrix(&bind.inversed(), &bone, &mesh.inversed()),
+            Mat4::identity()
+        );
+    }
+
+    #[test]
+    fn check_moved_bones_move_the_vertices() {
+        let bind = Mat4::translation(Vec3::new(0.0, 2.0, 0.0));
+        let bone = Mat4::translation(Vec3::new(1.0, 2.0, 0.0));
+
+        assert_eq!(
+            skinning_matrix(&bind.inversed(), &bone, &Mat4::identity()),
+            Mat4::translation(Vec3::new(1.0, 0.0, 0.0))
+        );
+    }
+}
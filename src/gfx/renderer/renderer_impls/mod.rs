@@ -1,7 +1,25 @@
+mod hlod_proxy;
+mod line_renderer;
 mod mesh_renderer;
+mod mesh_skin;
+mod nine_patch_renderer;
+mod particle_system;
+mod sprite_renderer;
+mod terrain;
+mod text_renderer;
 mod ui_element_renderer;
 mod ui_text_renderer;
+mod water_surface;
 
+pub use hlod_proxy::*;
+pub use line_renderer::*;
 pub use mesh_renderer::*;
+pub use mesh_skin::*;
+pub use nine_patch_renderer::*;
+pub use particle_system::*;
+pub use sprite_renderer::*;
+pub use terrain::*;
+pub use text_renderer::*;
 pub use ui_element_renderer::*;
 pub use ui_text_renderer::*;
+pub use water_surface::*;
@@ -0,0 +1,371 @@
+use super::sprite_renderer::{
+    corner_uvs, create_sprite_bind_groups, sprite_pipeline_provider, SpriteQuad,
+    SpriteRendererBindGroupProvider, SpriteRendererInstanceDataProvider,
+    SpriteRendererVertexBufferProvider, SpriteSubRenderer,
+};
+use crate::{
+    gfx::{
+        BindGroupLayoutCache, Color, GenericBufferAllocation, MaterialHandle, NinePatchHandle,
+        PipelineCache, PipelineProvider, ShaderManager, Texture, TextureHandle,
+    },
+    math::Vec2,
+};
+use asset::assets::TextureAsset;
+use specs::{prelude::*, Component};
+use std::sync::Arc;
+use wgpu::{BindGroup, Buffer, Device};
+
+/// Texel edges of the 3 by 3 regions of a nine-patch, as `(x, y)` from the min to the max texel.
+type NinePatchEdges = ([u16; 4], [u16; 4]);
+
+/// Edges of the 3 regions along an axis, from 0 to `length`. The borders keep their lengths and the middle region
+/// stretches over the rest; if the borders don't fit, they shrink proportionally and the middle region is empty.
+fn slice_axis(length: f32, border_start: f32, border_end: f32) -> [f32; 4] {
+    let borders = border_start + border_end;
+    let ratio = if length < borders {
+        length / borders
+    } else {
+        1.0
+    };
+    let start = border_start * ratio;
+    let end = f32::max(start, length - border_end * ratio);
+    [0.0, start, end, length]
+}
+
+/// Quads of the regions that are not empty, in the local space of the object.
+/// The texel rows at the min y are at the top, as for a [`SpriteRenderer`](super::SpriteRenderer).
+fn nine_patch_quads(
+    size: Vec2,
+    pivot: Vec2,
+    pixels_per_unit: f32,
+    texture_size: (u16, u16),
+    (texel_x, texel_y): NinePatchEdges,
+) -> Vec<SpriteQuad> {
+    let border = |min: u16, max: u16| max.saturating_sub(min) as f32 / pixels_per_unit;
+    let x = slice_axis(
+        size.x,
+        border(texel_x[0], texel_x[1]),
+        border(texel_x[2], texel_x[3]),
+    );
+    // From the bottom, whose texels are the ones at the max y.
+    let y = slice_axis(
+        size.y,
+        border(texel_y[2], texel_y[3]),
+        border(texel_y[0], texel_y[1]),
+    );
+    let (texture_width, texture_height) = (texture_size.0 as f32, texture_size.1 as f32);
+    let mut quads = Vec::with_capacity(9);
+
+    for row in 0..3 {
+        for column in 0..3 {
+            let width = x[column + 1] - x[column];
+            let height = y[row + 1] - y[row];
+            let (texel_x_min, texel_x_max) = (texel_x[column], texel_x[column + 1]);
+            let (texel_y_min, texel_y_max) = (texel_y[2 - row], texel_y[3 - row]);
+
+            if width <= 0.0
+                || height <= 0.0
+                || texel_x_max <= texel_x_min
+                || texel_y_max <= texel_y_min
+            {
+                continue;
+            }
+
+            let (uv_min, uv_max) = corner_uvs(
+                [
+                    (texel_x_min as f32 + 0.5) / texture_width,
+                    (texel_y_min as f32 + 0.5) / texture_height,
+                    (texel_x_max as f32 - 0.5) / texture_width,
+                    (texel_y_max as f32 - 0.5) / texture_height,
+                ],
+                false,
+                false,
+            );
+            quads.push(SpriteQuad {
+                size: [width, height],
+                offset: [x[column] - pivot.x * size.x, y[row] - pivot.y * size.y],
+                uv_min,
+                uv_max,
+            });
+        }
+    }
+
+    quads
+}
+
+/// Draws a nine-patch of a texture as a panel of the given size in the XY plane of the object, e.g. for a frame
+/// around UI in the world.
+///
+/// The corners keep their size in texels and the edges and the center stretch in between; if the panel is smaller
+/// than the corners, they shrink proportionally. It is drawn with the same shader and in the same queue as a
+/// [`SpriteRenderer`](super::SpriteRenderer), and batched with the sprites of the same texture.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct NinePatchRenderer {
+    mask: u32,
+    color: Color,
+    size: Vec2,
+    pivot: Vec2,
+    pixels_per_unit: f32,
+    sorting_order: i32,
+    pipeline_provider: PipelineProvider,
+    texture: Option<TextureHandle>,
+    edges: Option<NinePatchEdges>,
+    sprite_texture_bind_group: Option<Arc<BindGroup>>,
+    sprite_sampler_bind_group: Option<Arc<BindGroup>>,
+}
+
+impl NinePatchRenderer {
+    pub fn new(size: Vec2) -> Self {
+        Self {
+            mask: 0xFFFF_FFFF,
+            color: Color::white(),
+            size,
+            pivot: Vec2::new(0.5, 0.5),
+            pixels_per_unit: 1.0,
+            sorting_order: 0,
+            pipeline_provider: sprite_pipeline_provider(),
+            texture: None,
+            edges: None,
+            sprite_texture_bind_group: None,
+            sprite_sampler_bind_group: None,
+        }
+    }
+
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    pub fn set_mask(&mut self, mask: u32) {
+        self.mask = mask;
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Sets the tint the texels are multiplied by.
+    pub fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.size
+    }
+
+    /// Sets the size of the panel in the local space of the object.
+    pub fn set_size(&mut self, size: Vec2) {
+        self.size = size;
+    }
+
+    pub fn pivot(&self) -> Vec2 {
+        self.pivot
+    }
+
+    /// Sets the point of the panel placed at the origin of the object, from `(0, 0)` at the bottom left to `(1, 1)`
+    /// at the top right. Defaults to the center.
+    pub fn set_pivot(&mut self, pivot: Vec2) {
+        self.pivot = pivot;
+    }
+
+    pub fn pixels_per_unit(&self) -> f32 {
+        self.pixels_per_unit
+    }
+
+    /// Sets how many texels of the corners fit in a unit of the size. Defaults to 1, for sizes in pixels.
+    pub fn set_pixels_per_unit(&mut self, pixels_per_unit: f32) {
+        self.pixels_per_unit = pixels_per_unit;
+    }
+
+    pub fn sorting_order(&self) -> i32 {
+        self.sorting_order
+    }
+
+    /// Sets the sub-order of the panel within the transparent queue, as for sprites.
+    pub fn set_sorting_order(&mut self, sorting_order: i32) {
+        self.sorting_order = sorting_order;
+    }
+
+    pub fn material(&self) -> Option<&MaterialHandle> {
+        self.pipeline_provider.material()
+    }
+
+    pub fn set_material(&mut self, material: MaterialHandle) {
+        self.pipeline_provider.set_material(material);
+    }
+
+    pub fn texture(&self) -> Option<&TextureHandle> {
+        self.texture.as_ref()
+    }
+
+    pub fn set_nine_patch(
+        &mut self,
+        nine_patch: NinePatchHandle,
+        device: &Device,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) {
+        let mapping = nine_patch.mapping();
+        let edges = (
+            [
+                mapping.x_min,
+                mapping.x_mid_left,
+                mapping.x_mid_right,
+                mapping.x_max,
+            ],
+            [
+                mapping.y_min,
+                mapping.y_mid_bottom,
+                mapping.y_mid_top,
+                mapping.y_max,
+            ],
+        );
+
+        self.set_edges(
+            nine_patch.texture().clone(),
+            edges,
+            device,
+            bind_group_layout_cache,
+        );
+    }
+
+    /// Draws the nine-patch of the name of a texture asset, with the sampler of the nine-patch.
+    /// Returns `false` if the texture has no such nine-patch, leaving the renderer as it was.
+    pub fn set_texture_asset_nine_patch(
+        &mut self,
+        texture: &dyn TextureAsset,
+        nine_patch_name: &str,
+        device: &Device,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) -> bool {
+        let nine_patch = if let Some(nine_patch) = texture.find_nine_patch(nine_patch_name) {
+            nine_patch
+        } else {
+            return false;
+        };
+
+        let (x, y) = nine_patch.texel_mapping;
+        let edges = (
+            [x.min, x.mid_min, x.mid_max, x.max],
+            [y.min, y.mid_min, y.mid_max, y.max],
+        );
+        let texture = TextureHandle::new(Texture {
+            texture: texture.handle().clone(),
+            view: texture.view_handle().clone(),
+            sampler: nine_patch.sampler_handle.clone(),
+            width: texture.width(),
+            height: texture.height(),
+        });
+
+        self.set_edges(texture, edges, device, bind_group_layout_cache);
+        true
+    }
+
+    fn set_edges(
+        &mut self,
+        texture: TextureHandle,
+        edges: NinePatchEdges,
+        device: &Device,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) {
+        let (sprite_texture_bind_group, sprite_sampler_bind_group) =
+            create_sprite_bind_groups(&texture, device, bind_group_layout_cache);
+        self.sprite_texture_bind_group = Some(sprite_texture_bind_group);
+        self.sprite_sampler_bind_group = Some(sprite_sampler_bind_group);
+        self.texture = Some(texture);
+        self.edges = Some(edges);
+    }
+
+    pub fn sub_renderer(
+        &mut self,
+        standard_ui_vertex_buffer: &GenericBufferAllocation<Buffer>,
+        shader_mgr: &ShaderManager,
+        pipeline_cache: &mut PipelineCache,
+    ) -> Option<SpriteSubRenderer> {
+        let edges = self.edges?;
+        let texture = self.texture.as_ref()?;
+        let quads = nine_patch_quads(
+            self.size,
+            self.pivot,
+            self.pixels_per_unit,
+            (texture.width, texture.height),
+            edges,
+        );
+
+        if quads.is_empty() {
+            return None;
+        }
+
+        let pipeline = self
+            .pipeline_provider
+            .obtain_pipeline(shader_mgr, pipeline_cache)?;
+        let color = pipeline_cache.color_space().shader_color(self.color);
+        let material = self.pipeline_provider.material().cloned()?;
+        let sprite_texture_bind_group = self.sprite_texture_bind_group.clone()?;
+        let sprite_sampler_bind_group = self.sprite_sampler_bind_group.clone()?;
+
+        Some(SpriteSubRenderer {
+            pipeline,
+            material,
+            sorting_order: self.sorting_order,
+            texture: texture.clone(),
+            bind_group_provider: SpriteRendererBindGroupProvider {
+                sprite_texture_bind_group,
+                sprite_sampler_bind_group,
+            },
+            vertex_buffer_provider: SpriteRendererVertexBufferProvider {
+                vertex_buffer: standard_ui_vertex_buffer.clone(),
+            },
+            instance_data_provider: SpriteRendererInstanceDataProvider {
+                quads,
+                color: [color.r, color.g, color.b, color.a],
+                glyph_outline: None,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 12x12 texture whose corners are 4x4 texels and whose middle is 4 texels wide.
+    const EDGES: NinePatchEdges = ([0, 4, 8, 12], [0, 4, 8, 12]);
+
+    #[test]
+    fn check_borders_keep_their_size_and_the_middle_stretches() {
+        assert_eq!(slice_axis(100.0, 4.0, 6.0), [0.0, 4.0, 94.0, 100.0]);
+
+        let quads = nine_patch_quads(Vec2::new(40.0, 20.0), Vec2::ZERO, 1.0, (12, 12), EDGES);
+        assert_eq!(quads.len(), 9);
+        assert_eq!(quads[0].size, [4.0, 4.0]);
+        assert_eq!(quads[4].size, [32.0, 12.0]);
+        assert_eq!(quads[8].offset, [36.0, 16.0]);
+        // The bottom left region samples the texels at the max y.
+        assert_eq!(quads[0].uv_min, [0.5 / 12.0, 11.5 / 12.0]);
+        assert_eq!(quads[0].uv_max, [3.5 / 12.0, 8.5 / 12.0]);
+    }
+
+    #[test]
+    fn check_borders_shrink_instead_of_overlapping() {
+        assert_eq!(slice_axis(10.0, 8.0, 12.0), [0.0, 4.0, 4.0, 10.0]);
+
+        // Too narrow for the corners, so the middle column is left out.
+        let quads = nine_patch_quads(Vec2::new(4.0, 20.0), Vec2::ZERO, 1.0, (12, 12), EDGES);
+        assert_eq!(quads.len(), 6);
+        assert!(quads.iter().all(|quad| quad.size[0] == 2.0));
+    }
+
+    #[test]
+    fn check_empty_middle_regions_are_left_out() {
+        let quads = nine_patch_quads(
+            Vec2::new(40.0, 40.0),
+            Vec2::new(0.5, 0.5),
+            1.0,
+            (8, 8),
+            ([0, 4, 4, 8], [0, 4, 4, 8]),
+        );
+
+        assert_eq!(quads.len(), 4);
+        assert_eq!(quads[0].offset, [-20.0, -20.0]);
+    }
+}
@@ -0,0 +1,525 @@
+use crate::{
+    gfx::{
+        semantic_inputs::{self, KEY_POSITION},
+        simulate_particles, BakedParticleCurves, BindGroupProvider, CachedPipeline,
+        EmissionCounter, GenericBufferAllocation, GfxContextHandle, GpuParticles, HostBuffer,
+        InstanceDataProvider, InstancedDraw, Material, MaterialHandle, Particle, ParticleEmitter,
+        ParticleParams, PerInstancePropertyValue, PipelineCache, PipelineProvider, Renderer,
+        RendererVertexBufferAttribute, RendererVertexBufferLayout, SemanticShaderBindingKey,
+        SemanticShaderInputKey, ShaderManager, Uploader, VertexBuffer, VertexBufferProvider,
+    },
+    math::Mat4,
+};
+use parking_lot::RwLockReadGuard;
+use specs::{prelude::*, Component};
+use std::{mem::size_of, sync::Arc};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, Buffer, BufferAddress, BufferDescriptor,
+    BufferSize, BufferUsages, CommandEncoder, CompareFunction, ComputePassDescriptor,
+    DepthStencilState, FrontFace, PolygonMode, PrimitiveState, PrimitiveTopology, TextureFormat,
+};
+use zerocopy::AsBytes;
+
+/// Where the particles of a [`ParticleSystem`] are simulated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParticleSimulationMode {
+    Cpu,
+    /// Simulates in a compute shader and draws with `draw_indirect`, without reading anything back.
+    /// Falls back to [`Cpu`](Self::Cpu) on devices without compute shaders or indirect draws.
+    Gpu,
+}
+
+struct CpuParticles {
+    particles: Vec<Particle>,
+    buffer: Arc<Buffer>,
+    capacity: u32,
+}
+
+/// Ping-pong pair of particle buffers; each frame reads one side and writes the survivors into the other.
+struct GpuParticleBuffers {
+    params_buffer: Buffer,
+    particle_buffers: [Arc<Buffer>; 2],
+    draw_buffers: [Arc<Buffer>; 2],
+    /// The bind group reading the side of the same index.
+    bind_groups: [BindGroup; 2],
+    source: usize,
+    capacity: u32,
+}
+
+/// Emits particles from the object and draws them as camera-facing quads.
+/// The material must have no per-instance inputs other than the `TRANSFORM_ROW_*` ones, which receive the
+/// particles instead of a transform; see [`BUILT_IN_SHADER_PARTICLE`](crate::gfx::BUILT_IN_SHADER_PARTICLE).
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct ParticleSystem {
+    gfx_ctx: GfxContextHandle,
+    mask: u32,
+    mode: ParticleSimulationMode,
+    emitter: ParticleEmitter,
+    curves: BakedParticleCurves,
+    pipeline_provider: PipelineProvider,
+    emission: EmissionCounter,
+    time: f32,
+    seed: u32,
+    cpu: Option<CpuParticles>,
+    gpu: Option<GpuParticleBuffers>,
+    draw: Option<InstancedDraw>,
+}
+
+impl ParticleSystem {
+    /// The particles are drawn with the standard UI quad.
+    const VERTEX_COUNT: u32 = 6;
+
+    pub fn new(gfx_ctx: GfxContextHandle, emitter: ParticleEmitter) -> Self {
+        let mut pipeline_provider = PipelineProvider::new();
+
+        pipeline_provider.set_buffer_layouts(vec![RendererVertexBufferLayout {
+            array_stride: size_of::<[f32; 3]>() as BufferAddress,
+            attributes: vec![RendererVertexBufferAttribute {
+                key: KEY_POSITION,
+                offset: 0,
+            }],
+        }]);
+        pipeline_provider.set_primitive(PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        });
+        // Particles are blended, so they are depth tested but don't occlude each other.
+        pipeline_provider.set_depth_stencil(Some(DepthStencilState {
+            format: TextureFormat::Depth32Float,
+            depth_write_enabled: false,
+            depth_compare: CompareFunction::Less,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }));
+
+        Self {
+            gfx_ctx,
+            mask: 0xFFFF_FFFF,
+            mode: ParticleSimulationMode::Cpu,
+            curves: BakedParticleCurves::bake(&emitter),
+            emitter,
+            pipeline_provider,
+            emission: EmissionCounter::new(),
+            time: 0.0,
+            seed: 0,
+            cpu: None,
+            gpu: None,
+            draw: None,
+        }
+    }
+
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    pub fn set_mask(&mut self, mask: u32) {
+        self.mask = mask;
+    }
+
+    pub fn mode(&self) -> ParticleSimulationMode {
+        self.mode
+    }
+
+    /// Switches where the particles are simulated. The alive particles are discarded.
+    pub fn set_mode(&mut self, mode: ParticleSimulationMode) {
+        if self.mode != mode {
+            self.mode = mode;
+            self.clear();
+        }
+    }
+
+    pub fn emitter(&self) -> &ParticleEmitter {
+        &self.emitter
+    }
+
+    /// Replaces the emitter; the alive particles keep moving with the new parameters.
+    pub fn set_emitter(&mut self, emitter: ParticleEmitter) {
+        self.curves = BakedParticleCurves::bake(&emitter);
+        self.emitter = emitter;
+    }
+
+    pub fn material(&self) -> Option<&MaterialHandle> {
+        self.pipeline_provider.material()
+    }
+
+    pub fn set_material(&mut self, material: MaterialHandle) {
+        self.pipeline_provider.set_material(material);
+    }
+
+    /// Returns the number of alive particles. It is only tracked for the CPU path, since the GPU path never reads back.
+    pub fn alive_count(&self) -> Option<u32> {
+        self.cpu.as_ref().map(|cpu| cpu.particles.len() as u32)
+    }
+
+    /// Removes every particle.
+    pub fn clear(&mut self) {
+        self.cpu = None;
+        self.gpu = None;
+        self.draw = None;
+        self.emission.reset();
+    }
+
+    /// The draw produced by the last [`simulate`](Self::simulate).
+    pub fn draw(&self) -> Option<&InstancedDraw> {
+        self.draw.as_ref()
+    }
+
+    /// Advances the particles by one frame. The GPU path records a compute pass into the encoder;
+    /// it is taken if the mode is [`Gpu`](ParticleSimulationMode::Gpu) and `gpu_particles` is given.
+    /// At most `particle_budget` particles are alive, however many the emitter allows.
+    pub fn simulate(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        uploader: &mut Uploader,
+        gpu_particles: Option<&GpuParticles>,
+        matrix: &Mat4,
+        delta_time: f32,
+        particle_budget: u32,
+    ) {
+        let capacity = self.emitter.max_particles.min(particle_budget);
+        let spawn_count = self
+            .emission
+            .advance(self.emitter.emission_rate, delta_time);
+        self.time += delta_time;
+        self.seed = self.seed.wrapping_add(1);
+
+        let draw = match (self.mode, gpu_particles) {
+            (ParticleSimulationMode::Gpu, Some(gpu_particles)) => {
+                self.cpu = None;
+                self.simulate_gpu(
+                    encoder,
+                    uploader,
+                    gpu_particles,
+                    matrix,
+                    delta_time,
+                    spawn_count,
+                    capacity,
+                )
+            }
+            _ => {
+                self.gpu = None;
+                self.simulate_cpu(uploader, matrix, delta_time, spawn_count, capacity)
+            }
+        };
+        self.draw = Some(draw);
+    }
+
+    fn simulate_cpu(
+        &mut self,
+        uploader: &mut Uploader,
+        matrix: &Mat4,
+        delta_time: f32,
+        spawn_count: u32,
+        capacity: u32,
+    ) -> InstancedDraw {
+        let params = ParticleParams::new(
+            &self.emitter,
+            &self.curves,
+            matrix,
+            self.time,
+            delta_time,
+            spawn_count,
+            capacity,
+            0,
+            self.seed,
+        );
+        let gfx_ctx = &self.gfx_ctx;
+        let cpu = self.cpu.get_or_insert_with(|| CpuParticles {
+            particles: Vec::new(),
+            buffer: Arc::new(create_particle_buffer(gfx_ctx, 1, BufferUsages::COPY_DST)),
+            capacity: 1,
+        });
+
+        simulate_particles(&mut cpu.particles, &params);
+        cpu.particles.truncate(capacity as usize);
+
+        let count = cpu.particles.len() as u32;
+
+        if cpu.capacity < count {
+            cpu.capacity = count.next_power_of_two();
+            cpu.buffer = Arc::new(create_particle_buffer(
+                gfx_ctx,
+                cpu.capacity,
+                BufferUsages::COPY_DST,
+            ));
+        }
+
+        if count != 0 {
+            uploader.write(&cpu.buffer, 0, cpu.particles.as_bytes());
+        }
+
+        InstancedDraw {
+            instance_buffer: particle_allocation(&cpu.buffer, cpu.capacity),
+            indirect_buffer: None,
+            instance_count: count,
+        }
+    }
+
+    fn simulate_gpu(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        uploader: &mut Uploader,
+        gpu_particles: &GpuParticles,
+        matrix: &Mat4,
+        delta_time: f32,
+        spawn_count: u32,
+        capacity: u32,
+    ) -> InstancedDraw {
+        let capacity = capacity.clamp(1, GpuParticles::MAX_PARTICLES);
+
+        // Resizing drops the particles, since their count is only known by the GPU.
+        if self
+            .gpu
+            .as_ref()
+            .map_or(true, |gpu| gpu.capacity != capacity)
+        {
+            self.gpu = Some(create_gpu_buffers(&self.gfx_ctx, gpu_particles, capacity));
+        }
+
+        let gpu = self.gpu.as_mut().unwrap();
+        let source = gpu.source;
+        let destination = 1 - source;
+        let params = ParticleParams::new(
+            &self.emitter,
+            &self.curves,
+            matrix,
+            self.time,
+            delta_time,
+            spawn_count,
+            capacity,
+            source as u32,
+            self.seed,
+        );
+
+        uploader.write(&gpu.params_buffer, 0, params.as_bytes());
+        uploader.write(
+            &gpu.draw_buffers[destination],
+            0,
+            [Self::VERTEX_COUNT, 0, 0, 0].as_bytes(),
+        );
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("particle simulation pass"),
+            });
+            compute_pass.set_pipeline(gpu_particles.pipeline());
+            compute_pass.set_bind_group(0, &gpu.bind_groups[source], &[]);
+            compute_pass.dispatch_workgroups(
+                (capacity + GpuParticles::WORKGROUP_SIZE - 1) / GpuParticles::WORKGROUP_SIZE,
+                1,
+                1,
+            );
+        }
+
+        gpu.source = destination;
+
+        InstancedDraw {
+            instance_buffer: particle_allocation(&gpu.particle_buffers[destination], capacity),
+            indirect_buffer: Some(gpu.draw_buffers[destination].clone()),
+            instance_count: 0,
+        }
+    }
+
+    pub fn sub_renderer(
+        &mut self,
+        standard_ui_vertex_buffer: &GenericBufferAllocation<Buffer>,
+        shader_mgr: &ShaderManager,
+        pipeline_cache: &mut PipelineCache,
+    ) -> Option<ParticleSubRenderer> {
+        let pipeline = self
+            .pipeline_provider
+            .obtain_pipeline(shader_mgr, pipeline_cache)?;
+        let material = self.pipeline_provider.material().cloned()?;
+
+        Some(ParticleSubRenderer {
+            pipeline,
+            material,
+            bind_group_provider: ParticleSystemBindGroupProvider,
+            vertex_buffer_provider: ParticleSystemVertexBufferProvider {
+                vertex_buffer: standard_ui_vertex_buffer.clone(),
+            },
+            instance_data_provider: ParticleSystemInstanceDataProvider,
+        })
+    }
+}
+
+fn particle_allocation(buffer: &Arc<Buffer>, capacity: u32) -> GenericBufferAllocation<Buffer> {
+    GenericBufferAllocation::from_shared(
+        buffer.clone(),
+        0,
+        BufferSize::new(size_of::<Particle>() as BufferAddress * capacity.max(1) as BufferAddress)
+            .unwrap(),
+    )
+}
+
+fn create_particle_buffer(
+    gfx_ctx: &GfxContextHandle,
+    capacity: u32,
+    usage: BufferUsages,
+) -> Buffer {
+    gfx_ctx.device.create_buffer(&BufferDescriptor {
+        label: Some("particle buffer"),
+        size: size_of::<Particle>() as BufferAddress * capacity.max(1) as BufferAddress,
+        usage: BufferUsages::VERTEX | BufferUsages::COPY_SRC | usage,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_gpu_buffers(
+    gfx_ctx: &GfxContextHandle,
+    gpu_particles: &GpuParticles,
+    capacity: u32,
+) -> GpuParticleBuffers {
+    let device = &gfx_ctx.device;
+    let params_buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("particle simulation params buffer"),
+        size: size_of::<ParticleParams>() as BufferAddress,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let particle_buffers = [(); 2].map(|_| {
+        Arc::new(create_particle_buffer(
+            gfx_ctx,
+            capacity,
+            BufferUsages::STORAGE,
+        ))
+    });
+    let draw_buffers = [(); 2].map(|_| {
+        Arc::new(device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("particle draw buffer"),
+            contents: [ParticleSystem::VERTEX_COUNT, 0, 0, 0].as_bytes(),
+            usage: BufferUsages::STORAGE
+                | BufferUsages::INDIRECT
+                | BufferUsages::COPY_DST
+                | BufferUsages::COPY_SRC,
+        }))
+    });
+    let bind_groups = [0, 1].map(|source| {
+        let destination = 1 - source;
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("particle simulation bind group"),
+            layout: gpu_particles.bind_group_layout(),
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: particle_buffers[source].as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: draw_buffers[source].as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: particle_buffers[destination].as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: draw_buffers[destination].as_entire_binding(),
+                },
+            ],
+        })
+    });
+
+    GpuParticleBuffers {
+        params_buffer,
+        particle_buffers,
+        draw_buffers,
+        bind_groups,
+        source: 0,
+        capacity,
+    }
+}
+
+pub struct ParticleSubRenderer {
+    pipeline: CachedPipeline,
+    material: MaterialHandle,
+    bind_group_provider: ParticleSystemBindGroupProvider,
+    vertex_buffer_provider: ParticleSystemVertexBufferProvider,
+    instance_data_provider: ParticleSystemInstanceDataProvider,
+}
+
+impl Renderer for ParticleSubRenderer {
+    fn pipeline(&self) -> CachedPipeline {
+        self.pipeline.clone()
+    }
+
+    fn material(&self) -> RwLockReadGuard<Material> {
+        self.material.read()
+    }
+
+    fn instance_count(&self) -> u32 {
+        0
+    }
+
+    fn vertex_count(&self) -> u32 {
+        ParticleSystem::VERTEX_COUNT
+    }
+
+    fn bind_group_provider(&self) -> &dyn BindGroupProvider {
+        &self.bind_group_provider
+    }
+
+    fn vertex_buffer_provider(&self) -> &dyn VertexBufferProvider {
+        &self.vertex_buffer_provider
+    }
+
+    fn instance_data_provider(&self) -> &dyn InstanceDataProvider {
+        &self.instance_data_provider
+    }
+}
+
+struct ParticleSystemBindGroupProvider;
+
+impl BindGroupProvider for ParticleSystemBindGroupProvider {
+    fn bind_group(&self, _instance: u32, _key: SemanticShaderBindingKey) -> Option<&BindGroup> {
+        None
+    }
+}
+
+struct ParticleSystemVertexBufferProvider {
+    vertex_buffer: GenericBufferAllocation<Buffer>,
+}
+
+impl VertexBufferProvider for ParticleSystemVertexBufferProvider {
+    fn vertex_buffer_count(&self) -> u32 {
+        1
+    }
+
+    fn vertex_buffer(&self, key: SemanticShaderInputKey) -> Option<VertexBuffer> {
+        match key {
+            semantic_inputs::KEY_POSITION => Some(VertexBuffer {
+                slot: 0,
+                buffer: &self.vertex_buffer,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The per-instance data come from the particle buffer; nothing is encoded on the CPU.
+struct ParticleSystemInstanceDataProvider;
+
+impl InstanceDataProvider for ParticleSystemInstanceDataProvider {
+    fn copy_per_instance_data(
+        &self,
+        _instance: u32,
+        _key: SemanticShaderInputKey,
+        _buffer: &mut GenericBufferAllocation<HostBuffer>,
+    ) {
+    }
+
+    fn instance_property(&self, _instance: u32, _name: &str) -> Option<&PerInstancePropertyValue> {
+        None
+    }
+}
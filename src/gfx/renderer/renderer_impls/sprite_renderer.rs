@@ -0,0 +1,666 @@
+use crate::{
+    gfx::{
+        semantic_bindings,
+        semantic_inputs::{self, KEY_POSITION},
+        BindGroupLayoutCache, BindGroupProvider, CachedPipeline, Color, GenericBufferAllocation,
+        HostBuffer, InstanceDataProvider, Material, MaterialHandle, PipelineCache,
+        PipelineProvider, QueuedItem, RenderQueue, Renderer, RendererVertexBufferAttribute,
+        RendererVertexBufferLayout, SemanticShaderBindingKey, SemanticShaderInputKey,
+        ShaderManager, SpriteHandle, Texture, TextureHandle, VertexBuffer, VertexBufferProvider,
+    },
+    math::Vec2,
+};
+use asset::assets::{TexelInset, TextureAsset};
+use parking_lot::RwLockReadGuard;
+use specs::{prelude::*, Component};
+use std::{mem::size_of, sync::Arc};
+use wgpu::{
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutEntry, BindingResource,
+    BindingType, Buffer, BufferAddress, CompareFunction, DepthStencilState, Device, FrontFace,
+    PolygonMode, PrimitiveState, PrimitiveTopology, SamplerBindingType, ShaderStages,
+    TextureFormat, TextureSampleType, TextureViewDimension,
+};
+use zerocopy::AsBytes;
+
+/// Region of a texture drawn by a [`SpriteRenderer`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SpriteRegion {
+    /// As `[u_min, v_min, u_max, v_max]`.
+    uv_rect: [f32; 4],
+    /// Size in texels.
+    texel_size: (f32, f32),
+}
+
+impl SpriteRegion {
+    fn from_uv_rect(texture: &Texture, uv_rect: [f32; 4]) -> Self {
+        Self {
+            uv_rect,
+            texel_size: (
+                (uv_rect[2] - uv_rect[0]).abs() * texture.width as f32,
+                (uv_rect[3] - uv_rect[1]).abs() * texture.height as f32,
+            ),
+        }
+    }
+}
+
+/// The quads are drawn with the standard UI quad.
+const SPRITE_QUAD_VERTEX_COUNT: u32 = 6;
+
+/// A quad in the local space of the object, scaling and moving the unit quad.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct SpriteQuad {
+    pub(super) size: [f32; 2],
+    pub(super) offset: [f32; 2],
+    /// UV at the bottom left corner.
+    pub(super) uv_min: [f32; 2],
+    /// UV at the top right corner.
+    pub(super) uv_max: [f32; 2],
+}
+
+/// Size of the quad of a sprite of the given size in texels: its longer side is 1 unit long.
+fn quad_size(texel_size: (f32, f32)) -> Vec2 {
+    let (width, height) = texel_size;
+    let longer = f32::max(width, height);
+
+    if longer <= 0.0 {
+        return Vec2::ZERO;
+    }
+
+    Vec2::new(width / longer, height / longer)
+}
+
+/// UVs at the bottom left and the top right corners of the quad. The top of the quad samples `v_min`, the top row
+/// of the texture, so that sprites stand upright.
+pub(super) fn corner_uvs(uv_rect: [f32; 4], flip_x: bool, flip_y: bool) -> ([f32; 2], [f32; 2]) {
+    let [u_min, v_min, u_max, v_max] = uv_rect;
+    let (left, right) = if flip_x {
+        (u_max, u_min)
+    } else {
+        (u_min, u_max)
+    };
+    let (bottom, top) = if flip_y {
+        (v_min, v_max)
+    } else {
+        (v_max, v_min)
+    };
+    ([left, bottom], [right, top])
+}
+
+/// Pipeline states of the sprite quads, shared by [`SpriteRenderer`] and
+/// [`NinePatchRenderer`](super::NinePatchRenderer).
+pub(super) fn sprite_pipeline_provider() -> PipelineProvider {
+    let mut pipeline_provider = PipelineProvider::new();
+
+    pipeline_provider.set_buffer_layouts(vec![RendererVertexBufferLayout {
+        array_stride: size_of::<[f32; 3]>() as BufferAddress,
+        attributes: vec![RendererVertexBufferAttribute {
+            key: KEY_POSITION,
+            offset: 0,
+        }],
+    }]);
+    // Flipped sprites are seen from behind, and sprites can be turned around; both sides are drawn.
+    pipeline_provider.set_primitive(PrimitiveState {
+        topology: PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        front_face: FrontFace::Ccw,
+        cull_mode: None,
+        unclipped_depth: false,
+        polygon_mode: PolygonMode::Fill,
+        conservative: false,
+    });
+    pipeline_provider.set_depth_stencil(Some(DepthStencilState {
+        format: TextureFormat::Depth32Float,
+        depth_write_enabled: false,
+        depth_compare: CompareFunction::Less,
+        stencil: Default::default(),
+        bias: Default::default(),
+    }));
+
+    pipeline_provider
+}
+
+/// Bind groups of the `sprite_texture` and `sprite_sampler` semantic bindings.
+pub(super) fn create_sprite_bind_groups(
+    texture: &Texture,
+    device: &Device,
+    bind_group_layout_cache: &mut BindGroupLayoutCache,
+) -> (Arc<BindGroup>, Arc<BindGroup>) {
+    let sprite_texture_bind_group_layout =
+        bind_group_layout_cache.create_layout(vec![BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX_FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        }]);
+    let sprite_sampler_bind_group_layout =
+        bind_group_layout_cache.create_layout(vec![BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX_FRAGMENT,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        }]);
+
+    (
+        Arc::new(device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: sprite_texture_bind_group_layout.as_ref(),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&texture.view),
+            }],
+        })),
+        Arc::new(device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: sprite_sampler_bind_group_layout.as_ref(),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::Sampler(&texture.sampler),
+            }],
+        })),
+    )
+}
+
+/// Draws a region of a texture, e.g. a sprite of an atlas, as a quad in the XY plane of the object.
+///
+/// The quad keeps the aspect of the region, with its longer side 1 unit long, and the pivot at the origin of the
+/// object. Sprites are blended, so they are drawn in the transparent queue: by their sorting order, which takes the
+/// place of the material's render order, and back to front among the same order. The built-in
+/// [`BUILT_IN_SHADER_SPRITE`](crate::gfx::BUILT_IN_SHADER_SPRITE) draws them tinted by their color.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct SpriteRenderer {
+    mask: u32,
+    color: Color,
+    pivot: Vec2,
+    flip_x: bool,
+    flip_y: bool,
+    sorting_order: i32,
+    pipeline_provider: PipelineProvider,
+    texture: Option<TextureHandle>,
+    region: Option<SpriteRegion>,
+    sprite_texture_bind_group: Option<Arc<BindGroup>>,
+    sprite_sampler_bind_group: Option<Arc<BindGroup>>,
+}
+
+impl SpriteRenderer {
+    pub fn new() -> Self {
+        Self {
+            mask: 0xFFFF_FFFF,
+            color: Color::white(),
+            pivot: Vec2::new(0.5, 0.5),
+            flip_x: false,
+            flip_y: false,
+            sorting_order: 0,
+            pipeline_provider: sprite_pipeline_provider(),
+            texture: None,
+            region: None,
+            sprite_texture_bind_group: None,
+            sprite_sampler_bind_group: None,
+        }
+    }
+
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    pub fn set_mask(&mut self, mask: u32) {
+        self.mask = mask;
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    /// Sets the tint the texels are multiplied by.
+    pub fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    pub fn pivot(&self) -> Vec2 {
+        self.pivot
+    }
+
+    /// Sets the point of the quad placed at the origin of the object, from `(0, 0)` at the bottom left to `(1, 1)`
+    /// at the top right. Defaults to the center.
+    pub fn set_pivot(&mut self, pivot: Vec2) {
+        self.pivot = pivot;
+    }
+
+    pub fn flip_x(&self) -> bool {
+        self.flip_x
+    }
+
+    /// Mirrors the texels horizontally; the pivot stays where it is on the quad.
+    pub fn set_flip_x(&mut self, flip_x: bool) {
+        self.flip_x = flip_x;
+    }
+
+    pub fn flip_y(&self) -> bool {
+        self.flip_y
+    }
+
+    /// Mirrors the texels vertically; the pivot stays where it is on the quad.
+    pub fn set_flip_y(&mut self, flip_y: bool) {
+        self.flip_y = flip_y;
+    }
+
+    pub fn sorting_order(&self) -> i32 {
+        self.sorting_order
+    }
+
+    /// Sets the sub-order of the sprite within the transparent queue; lower orders are drawn first, whatever their
+    /// depth.
+    pub fn set_sorting_order(&mut self, sorting_order: i32) {
+        self.sorting_order = sorting_order;
+    }
+
+    pub fn material(&self) -> Option<&MaterialHandle> {
+        self.pipeline_provider.material()
+    }
+
+    pub fn set_material(&mut self, material: MaterialHandle) {
+        self.pipeline_provider.set_material(material);
+    }
+
+    pub fn texture(&self) -> Option<&TextureHandle> {
+        self.texture.as_ref()
+    }
+
+    /// Size of the quad in the local space of the object, or `None` if there is nothing to draw.
+    pub fn size(&self) -> Option<Vec2> {
+        self.region.map(|region| quad_size(region.texel_size))
+    }
+
+    /// Draws a sprite of a texture atlas.
+    pub fn set_sprite(
+        &mut self,
+        sprite: SpriteHandle,
+        device: &Device,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) {
+        let texture = sprite.texture();
+        let mapping = sprite.mapping();
+        let texel_width_half = 0.5 / texture.width as f32;
+        let texel_height_half = 0.5 / texture.height as f32;
+        let region = SpriteRegion {
+            uv_rect: [
+                mapping.x_min as f32 / texture.width as f32 + texel_width_half,
+                mapping.y_min as f32 / texture.height as f32 + texel_height_half,
+                mapping.x_max as f32 / texture.width as f32 - texel_width_half,
+                mapping.y_max as f32 / texture.height as f32 - texel_height_half,
+            ],
+            texel_size: (sprite.width() as f32, sprite.height() as f32),
+        };
+
+        self.set_region(texture.clone(), region, device, bind_group_layout_cache);
+    }
+
+    /// Draws the sprite of the name of a texture asset, with the sampler of the sprite.
+    /// Returns `false` if the texture has no such sprite, leaving the renderer as it was.
+    pub fn set_texture_asset_sprite(
+        &mut self,
+        texture: &dyn TextureAsset,
+        sprite_name: &str,
+        device: &Device,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) -> bool {
+        let sprite = if let Some(sprite) = texture.find_sprite(sprite_name) {
+            sprite
+        } else {
+            return false;
+        };
+
+        let (x, y) = sprite.texel_mapping;
+        let region = SpriteRegion {
+            uv_rect: sprite.uv_rect(texture.width(), texture.height(), TexelInset::HalfTexel),
+            texel_size: (x.max.abs_diff(x.min) as f32, y.max.abs_diff(y.min) as f32),
+        };
+        let texture = TextureHandle::new(Texture {
+            texture: texture.handle().clone(),
+            view: texture.view_handle().clone(),
+            sampler: sprite.sampler_handle.clone(),
+            width: texture.width(),
+            height: texture.height(),
+        });
+
+        self.set_region(texture, region, device, bind_group_layout_cache);
+        true
+    }
+
+    /// Draws the region of the texture given in UVs, as `[u_min, v_min, u_max, v_max]`.
+    pub fn set_uv_rect(
+        &mut self,
+        texture: TextureHandle,
+        uv_rect: [f32; 4],
+        device: &Device,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) {
+        let region = SpriteRegion::from_uv_rect(&texture, uv_rect);
+        self.set_region(texture, region, device, bind_group_layout_cache);
+    }
+
+    fn set_region(
+        &mut self,
+        texture: TextureHandle,
+        region: SpriteRegion,
+        device: &Device,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) {
+        let (sprite_texture_bind_group, sprite_sampler_bind_group) =
+            create_sprite_bind_groups(&texture, device, bind_group_layout_cache);
+        self.sprite_texture_bind_group = Some(sprite_texture_bind_group);
+        self.sprite_sampler_bind_group = Some(sprite_sampler_bind_group);
+        self.texture = Some(texture);
+        self.region = Some(region);
+    }
+
+    pub fn sub_renderer(
+        &mut self,
+        standard_ui_vertex_buffer: &GenericBufferAllocation<Buffer>,
+        shader_mgr: &ShaderManager,
+        pipeline_cache: &mut PipelineCache,
+    ) -> Option<SpriteSubRenderer> {
+        let region = self.region?;
+        let texture = self.texture.as_ref()?;
+        let pipeline = self
+            .pipeline_provider
+            .obtain_pipeline(shader_mgr, pipeline_cache)?;
+        let color = pipeline_cache.color_space().shader_color(self.color);
+        let material = self.pipeline_provider.material().cloned()?;
+        let sprite_texture_bind_group = self.sprite_texture_bind_group.clone()?;
+        let sprite_sampler_bind_group = self.sprite_sampler_bind_group.clone()?;
+        let size = quad_size(region.texel_size);
+        let (uv_min, uv_max) = corner_uvs(region.uv_rect, self.flip_x, self.flip_y);
+
+        Some(SpriteSubRenderer {
+            pipeline,
+            material,
+            sorting_order: self.sorting_order,
+            texture: texture.clone(),
+            bind_group_provider: SpriteRendererBindGroupProvider {
+                sprite_texture_bind_group,
+                sprite_sampler_bind_group,
+            },
+            vertex_buffer_provider: SpriteRendererVertexBufferProvider {
+                vertex_buffer: standard_ui_vertex_buffer.clone(),
+            },
+            instance_data_provider: SpriteRendererInstanceDataProvider {
+                quads: vec![SpriteQuad {
+                    size: [size.x, size.y],
+                    offset: [-self.pivot.x * size.x, -self.pivot.y * size.y],
+                    uv_min,
+                    uv_max,
+                }],
+                color: [color.r, color.g, color.b, color.a],
+                glyph_outline: None,
+            },
+        })
+    }
+}
+
+/// Sprite sub renderers of equal keys draw the same texture with the same material, and can be drawn as instances
+/// of one command.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct SpriteBatchKey {
+    pipeline: CachedPipeline,
+    material: MaterialHandle,
+    texture: (*const wgpu::TextureView, *const wgpu::Sampler),
+}
+
+/// Draws the quads of a [`SpriteRenderer`], a [`NinePatchRenderer`](super::NinePatchRenderer) or a
+/// [`TextRenderer`](super::TextRenderer), one per instance.
+pub struct SpriteSubRenderer {
+    pub(super) pipeline: CachedPipeline,
+    pub(super) material: MaterialHandle,
+    pub(super) sorting_order: i32,
+    pub(super) texture: TextureHandle,
+    pub(super) bind_group_provider: SpriteRendererBindGroupProvider,
+    pub(super) vertex_buffer_provider: SpriteRendererVertexBufferProvider,
+    pub(super) instance_data_provider: SpriteRendererInstanceDataProvider,
+}
+
+impl SpriteBatchKey {
+    pub(super) fn new(
+        pipeline: CachedPipeline,
+        material: MaterialHandle,
+        texture: &Texture,
+    ) -> Self {
+        Self {
+            pipeline,
+            material,
+            texture: (Arc::as_ptr(&texture.view), Arc::as_ptr(&texture.sampler)),
+        }
+    }
+}
+
+impl SpriteSubRenderer {
+    pub fn sorting_order(&self) -> i32 {
+        self.sorting_order
+    }
+
+    pub fn batch_key(&self) -> SpriteBatchKey {
+        SpriteBatchKey::new(self.pipeline.clone(), self.material.clone(), &self.texture)
+    }
+}
+
+impl Renderer for SpriteSubRenderer {
+    fn pipeline(&self) -> CachedPipeline {
+        self.pipeline.clone()
+    }
+
+    fn material(&self) -> RwLockReadGuard<Material> {
+        self.material.read()
+    }
+
+    fn instance_count(&self) -> u32 {
+        self.instance_data_provider.quads.len() as u32
+    }
+
+    fn vertex_count(&self) -> u32 {
+        SPRITE_QUAD_VERTEX_COUNT
+    }
+
+    fn bind_group_provider(&self) -> &dyn BindGroupProvider {
+        &self.bind_group_provider
+    }
+
+    fn vertex_buffer_provider(&self) -> &dyn VertexBufferProvider {
+        &self.vertex_buffer_provider
+    }
+
+    fn instance_data_provider(&self) -> &dyn InstanceDataProvider {
+        &self.instance_data_provider
+    }
+}
+
+pub(super) struct SpriteRendererBindGroupProvider {
+    pub(super) sprite_texture_bind_group: Arc<BindGroup>,
+    pub(super) sprite_sampler_bind_group: Arc<BindGroup>,
+}
+
+impl BindGroupProvider for SpriteRendererBindGroupProvider {
+    fn bind_group(&self, _instance: u32, key: SemanticShaderBindingKey) -> Option<&BindGroup> {
+        match key {
+            semantic_bindings::KEY_SPRITE_TEXTURE => Some(&self.sprite_texture_bind_group),
+            semantic_bindings::KEY_SPRITE_SAMPLER => Some(&self.sprite_sampler_bind_group),
+            _ => None,
+        }
+    }
+}
+
+pub(super) struct SpriteRendererVertexBufferProvider {
+    pub(super) vertex_buffer: GenericBufferAllocation<Buffer>,
+}
+
+impl VertexBufferProvider for SpriteRendererVertexBufferProvider {
+    fn vertex_buffer_count(&self) -> u32 {
+        1
+    }
+
+    fn vertex_buffer(&self, key: SemanticShaderInputKey) -> Option<VertexBuffer> {
+        match key {
+            semantic_inputs::KEY_POSITION => Some(VertexBuffer {
+                slot: 0,
+                buffer: &self.vertex_buffer,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub(super) struct SpriteRendererInstanceDataProvider {
+    pub(super) quads: Vec<SpriteQuad>,
+    pub(super) color: [f32; 4],
+    /// Thickness and smoothness of the outlines of signed distance field glyphs, for text.
+    pub(super) glyph_outline: Option<(f32, f32)>,
+}
+
+impl InstanceDataProvider for SpriteRendererInstanceDataProvider {
+    fn copy_per_instance_data(
+        &self,
+        instance: u32,
+        key: SemanticShaderInputKey,
+        buffer: &mut GenericBufferAllocation<HostBuffer>,
+    ) {
+        let quad = if let Some(quad) = self.quads.get(instance as usize) {
+            quad
+        } else {
+            return;
+        };
+
+        match key {
+            semantic_inputs::KEY_SPRITE_SIZE => buffer.copy_from_slice(quad.size.as_bytes()),
+            semantic_inputs::KEY_SPRITE_OFFSET => buffer.copy_from_slice(quad.offset.as_bytes()),
+            semantic_inputs::KEY_SPRITE_UV_MIN => buffer.copy_from_slice(quad.uv_min.as_bytes()),
+            semantic_inputs::KEY_SPRITE_UV_MAX => buffer.copy_from_slice(quad.uv_max.as_bytes()),
+            semantic_inputs::KEY_SPRITE_COLOR => buffer.copy_from_slice(self.color.as_bytes()),
+            semantic_inputs::KEY_GLYPH_THICKNESS => {
+                if let Some((thickness, _)) = self.glyph_outline {
+                    buffer.copy_from_slice([thickness].as_bytes());
+                }
+            }
+            semantic_inputs::KEY_GLYPH_SMOOTHNESS => {
+                if let Some((_, smoothness)) = self.glyph_outline {
+                    buffer.copy_from_slice([smoothness].as_bytes());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A sprite to draw, with where it goes in the transparent queue.
+#[derive(Debug, Clone)]
+pub struct QueuedSprite<K, T> {
+    pub sorting_order: i32,
+    /// View-space depth of the object, see [`view_depth`](crate::gfx::view_depth).
+    pub depth: f32,
+    pub batch_key: K,
+    pub item: T,
+}
+
+/// Sorts the sprites by their sorting order and then back to front, and groups the consecutive ones sharing a batch
+/// key so that each group is drawn by one command. The groups are queued at the sorting order and the depth of their
+/// first sprites, which keeps them in this order once the transparent queue is sorted.
+pub fn batch_sprites<K: PartialEq, T>(
+    mut sprites: Vec<QueuedSprite<K, T>>,
+) -> Vec<QueuedItem<Vec<T>>> {
+    sprites.sort_by(|lhs, rhs| {
+        lhs.sorting_order
+            .cmp(&rhs.sorting_order)
+            .then(rhs.depth.total_cmp(&lhs.depth))
+    });
+
+    let mut batches = Vec::<(K, QueuedItem<Vec<T>>)>::new();
+
+    for sprite in sprites {
+        if let Some((batch_key, batch)) = batches.last_mut() {
+            if *batch_key == sprite.batch_key && batch.order == sprite.sorting_order {
+                batch.item.push(sprite.item);
+                continue;
+            }
+        }
+
+        batches.push((
+            sprite.batch_key,
+            QueuedItem {
+                queue: RenderQueue::Transparent,
+                order: sprite.sorting_order,
+                depth: sprite.depth,
+                item: vec![sprite.item],
+            },
+        ));
+    }
+
+    Vec::from_iter(batches.into_iter().map(|(_, batch)| batch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite(
+        sorting_order: i32,
+        depth: f32,
+        batch_key: &'static str,
+        item: &'static str,
+    ) -> QueuedSprite<&'static str, &'static str> {
+        QueuedSprite {
+            sorting_order,
+            depth,
+            batch_key,
+            item,
+        }
+    }
+
+    #[test]
+    fn check_quad_keeps_the_aspect_of_the_sprite() {
+        assert_eq!(quad_size((64.0, 32.0)), Vec2::new(1.0, 0.5));
+        assert_eq!(quad_size((16.0, 48.0)), Vec2::new(1.0 / 3.0, 1.0));
+        assert_eq!(quad_size((0.0, 0.0)), Vec2::ZERO);
+    }
+
+    #[test]
+    fn check_flipping_swaps_the_corner_uvs() {
+        let uv_rect = [0.25, 0.0, 0.5, 0.5];
+
+        assert_eq!(corner_uvs(uv_rect, false, false), ([0.25, 0.5], [0.5, 0.0]));
+        assert_eq!(corner_uvs(uv_rect, true, false), ([0.5, 0.5], [0.25, 0.0]));
+        assert_eq!(corner_uvs(uv_rect, false, true), ([0.25, 0.0], [0.5, 0.5]));
+    }
+
+    #[test]
+    fn check_sprites_are_sorted_by_order_then_back_to_front() {
+        let batches = batch_sprites(vec![
+            sprite(1, 1.0, "ui", "cursor"),
+            sprite(0, 2.0, "atlas", "tree"),
+            sprite(0, 8.0, "atlas", "mountain"),
+            sprite(0, 5.0, "characters", "hero"),
+        ]);
+
+        assert_eq!(
+            Vec::from_iter(batches.iter().map(|batch| batch.item.clone())),
+            vec![vec!["mountain"], vec!["hero"], vec!["tree"], vec!["cursor"]]
+        );
+        assert_eq!(
+            Vec::from_iter(batches.iter().map(|batch| batch.order)),
+            vec![0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn check_consecutive_sprites_of_one_texture_share_a_batch() {
+        let batches = batch_sprites(Vec::from_iter(
+            (0..300).map(|index| sprite(0, index as f32, "atlas", "sprite")),
+        ));
+
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].item.len(), 300);
+        assert_eq!(batches[0].depth, 299.0);
+        assert_eq!(batches[0].queue, RenderQueue::Transparent);
+    }
+}
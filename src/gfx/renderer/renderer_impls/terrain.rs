@@ -0,0 +1,564 @@
+use crate::{
+    gfx::{
+        semantic_bindings,
+        semantic_inputs::{self, KEY_POSITION},
+        BindGroupEntryResource, BindGroupLayoutCache, BindGroupProvider, BindingPropKey,
+        CachedPipeline, GenericBufferAllocation, Heightfield, HeightfieldError, HeightfieldRegion,
+        HostBuffer, InstanceDataProvider, Material, MaterialHandle, PipelineCache,
+        PipelineProvider, Renderer, RendererVertexBufferAttribute, RendererVertexBufferLayout,
+        SemanticShaderBindingKey, SemanticShaderInputKey, ShaderManager, Texture, TextureArray,
+        VertexBuffer, VertexBufferProvider, TERRAIN_CHUNK_QUADS, TERRAIN_MAX_LOD,
+    },
+    math::{Frustum, Mat4, Vec2, Vec3},
+};
+use parking_lot::RwLockReadGuard;
+use specs::{prelude::*, Component};
+use std::{mem::size_of, sync::Arc};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutEntry, BindingResource,
+    BindingType, Buffer, BufferAddress, BufferSize, BufferUsages, CompareFunction,
+    DepthStencilState, Device, Extent3d, FrontFace, ImageCopyTexture, ImageDataLayout, Origin3d,
+    PolygonMode, PrimitiveState, PrimitiveTopology, Queue, ShaderStages, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureViewDimension,
+};
+use zerocopy::AsBytes;
+
+pub const TERRAIN_SPLAT_MAP_NAME: &str = "splat_map";
+pub const TERRAIN_SPLAT_MAP_SAMPLER_NAME: &str = "splat_map_sampler";
+pub const TERRAIN_SPLAT_LAYERS_NAME: &str = "splat_layers";
+pub const TERRAIN_SPLAT_LAYERS_SAMPLER_NAME: &str = "splat_layers_sampler";
+
+/// Binds the splat map and its layers to a material of the built-in terrain shader.
+/// The rgba channels of the splat map weight the first four layers of the array.
+/// Returns `false` if the material lacks any of the bindings.
+pub fn bind_terrain_splatting(
+    material: &mut Material,
+    splat_map: &Texture,
+    splat_layers: &TextureArray,
+) -> bool {
+    let bound = [
+        material.set_bind_property(
+            &BindingPropKey::StringKey(TERRAIN_SPLAT_MAP_NAME.to_owned()),
+            BindGroupEntryResource::TextureView {
+                texture_view: splat_map.view.clone(),
+            },
+        ),
+        material.set_bind_property(
+            &BindingPropKey::StringKey(TERRAIN_SPLAT_MAP_SAMPLER_NAME.to_owned()),
+            BindGroupEntryResource::Sampler {
+                sampler: splat_map.sampler.clone(),
+            },
+        ),
+        material.set_texture_array(TERRAIN_SPLAT_LAYERS_NAME, splat_layers),
+        material.set_bind_property(
+            &BindingPropKey::StringKey(TERRAIN_SPLAT_LAYERS_SAMPLER_NAME.to_owned()),
+            BindGroupEntryResource::Sampler {
+                sampler: splat_layers.sampler.clone(),
+            },
+        ),
+    ];
+
+    bound.iter().all(|&bound| bound)
+}
+
+/// Heightmap terrain drawn in chunks, each at a level of detail picked by its distance to the camera.
+///
+/// All chunks drawn at a level of detail share one grid, displaced by the heights in the vertex shader.
+/// Skirts hang from the chunk edges to hide the cracks between neighbours at different levels of detail.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct Terrain {
+    mask: u32,
+    pipeline_provider: PipelineProvider,
+    heightfield: Heightfield,
+    lod_distance: f32,
+    texture_tiling: f32,
+    dirty_regions: Vec<HeightfieldRegion>,
+    height_texture: wgpu::Texture,
+    height_bind_group: Arc<BindGroup>,
+    grids: Vec<(GenericBufferAllocation<Buffer>, u32)>,
+}
+
+impl Terrain {
+    /// Uploads the heightfield and the grids of every level of detail.
+    /// Fails if the heightfield doesn't fit in a texture of the device.
+    pub fn new(
+        heightfield: Heightfield,
+        device: &Device,
+        queue: &Queue,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) -> Result<Self, HeightfieldError> {
+        let limit = device.limits().max_texture_dimension_2d;
+
+        if limit < heightfield.width() || limit < heightfield.depth() {
+            return Err(HeightfieldError::TooLarge {
+                width: heightfield.width(),
+                depth: heightfield.depth(),
+                limit,
+            });
+        }
+
+        let mut pipeline_provider = PipelineProvider::new();
+
+        pipeline_provider.set_buffer_layouts(vec![RendererVertexBufferLayout {
+            array_stride: size_of::<[f32; 3]>() as BufferAddress,
+            attributes: vec![RendererVertexBufferAttribute {
+                key: KEY_POSITION,
+                offset: 0,
+            }],
+        }]);
+        pipeline_provider.set_primitive(PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            // Skirts are seen from both sides.
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        });
+        pipeline_provider.set_depth_stencil(Some(DepthStencilState {
+            format: TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: CompareFunction::Less,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }));
+
+        let height_texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("terrain heights"),
+                size: Extent3d {
+                    width: heightfield.width(),
+                    height: heightfield.depth(),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rg8Unorm,
+                usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[TextureFormat::Rg8Unorm],
+            },
+            &pack_heights(heightfield.heights()),
+        );
+        let height_bind_group_layout =
+            bind_group_layout_cache.create_layout(vec![BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }]);
+        let height_bind_group = Arc::new(device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: height_bind_group_layout.as_ref(),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(
+                    &height_texture.create_view(&Default::default()),
+                ),
+            }],
+        }));
+
+        let grids = Vec::from_iter((0..=TERRAIN_MAX_LOD).map(terrain_grid_vertices));
+        let grid_buffer = Arc::new(device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("terrain grids"),
+            contents: grids.concat().as_bytes(),
+            // Frame captures copy the vertices out.
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_SRC,
+        }));
+        let mut offset = 0;
+        let grids = Vec::from_iter(grids.iter().map(|vertices| {
+            let size = (size_of::<[f32; 3]>() * vertices.len()) as BufferAddress;
+            let allocation = GenericBufferAllocation::from_shared(
+                grid_buffer.clone(),
+                offset,
+                BufferSize::new(size).unwrap(),
+            );
+            offset += size;
+            (allocation, vertices.len() as u32)
+        }));
+
+        let spacing = heightfield.spacing();
+
+        Ok(Self {
+            mask: 0xFFFF_FFFF,
+            pipeline_provider,
+            // Chunks start losing detail two chunks away.
+            lod_distance: 2.0 * TERRAIN_CHUNK_QUADS as f32 * spacing.x.max(spacing.y),
+            texture_tiling: 1.0,
+            heightfield,
+            dirty_regions: Vec::new(),
+            height_texture,
+            height_bind_group,
+            grids,
+        })
+    }
+
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    pub fn set_mask(&mut self, mask: u32) {
+        self.mask = mask;
+    }
+
+    pub fn material(&self) -> Option<&MaterialHandle> {
+        self.pipeline_provider.material()
+    }
+
+    pub fn set_material(&mut self, material: MaterialHandle) {
+        self.pipeline_provider.set_material(material);
+    }
+
+    pub fn heightfield(&self) -> &Heightfield {
+        &self.heightfield
+    }
+
+    /// Distance from the camera at which chunks start losing detail.
+    /// Each following level of detail starts twice as far as the previous one.
+    pub fn lod_distance(&self) -> f32 {
+        self.lod_distance
+    }
+
+    pub fn set_lod_distance(&mut self, lod_distance: f32) {
+        self.lod_distance = lod_distance;
+    }
+
+    /// Repetitions of the splat layers per unit of local space.
+    pub fn texture_tiling(&self) -> f32 {
+        self.texture_tiling
+    }
+
+    pub fn set_texture_tiling(&mut self, texture_tiling: f32) {
+        self.texture_tiling = texture_tiling;
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.heightfield.chunk_count()
+    }
+
+    /// Height at the position on the xz plane of the terrain's local space. See [`Heightfield::height_at`].
+    pub fn height_at(&self, position: Vec2) -> Option<f32> {
+        self.heightfield.height_at(position)
+    }
+
+    /// Normal at the position on the xz plane of the terrain's local space. See [`Heightfield::normal_at`].
+    pub fn normal_at(&self, position: Vec2) -> Option<Vec3> {
+        self.heightfield.normal_at(position)
+    }
+
+    /// Replaces the heights of the region. See [`Heightfield::set_heights`].
+    /// Only the region is uploaded, the next time the terrain is rendered.
+    pub fn set_heights(
+        &mut self,
+        region: HeightfieldRegion,
+        data: &[f32],
+    ) -> Result<(), HeightfieldError> {
+        self.heightfield.set_heights(region, data)?;
+
+        if region.width != 0 && region.depth != 0 {
+            self.dirty_regions.push(region);
+        }
+
+        Ok(())
+    }
+
+    /// Uploads the regions changed since the last call.
+    pub fn upload_heights(&mut self, queue: &Queue) {
+        let width = self.heightfield.width();
+        let heights = self.heightfield.heights();
+
+        for region in self.dirty_regions.drain(..) {
+            let rows = Vec::from_iter((region.z..region.z + region.depth).flat_map(|z| {
+                let start = (z * width + region.x) as usize;
+                pack_heights(&heights[start..start + region.width as usize])
+            }));
+
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture: &self.height_texture,
+                    mip_level: 0,
+                    origin: Origin3d {
+                        x: region.x,
+                        y: region.z,
+                        z: 0,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                &rows,
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(region.width * 2),
+                    rows_per_image: Some(region.depth),
+                },
+                Extent3d {
+                    width: region.width,
+                    height: region.depth,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    /// Culls the chunks for a camera, returning a renderer for each level of detail that has visible chunks.
+    /// `matrix` is the terrain's transform, and the frustum and camera position are in world space.
+    pub fn sub_renderers(
+        &mut self,
+        matrix: &Mat4,
+        frustum: &Frustum,
+        camera_position: Vec3,
+        shader_mgr: &ShaderManager,
+        pipeline_cache: &mut PipelineCache,
+    ) -> Vec<TerrainSubRenderer> {
+        let pipeline = if let Some(pipeline) = self
+            .pipeline_provider
+            .obtain_pipeline(shader_mgr, pipeline_cache)
+        {
+            pipeline
+        } else {
+            return Vec::new();
+        };
+        let material = if let Some(material) = self.pipeline_provider.material().cloned() {
+            material
+        } else {
+            return Vec::new();
+        };
+
+        let mut chunks = vec![Vec::new(); self.grids.len()];
+
+        for chunk in
+            self.heightfield
+                .visible_chunks(matrix, frustum, camera_position, self.lod_distance)
+        {
+            let (x, z) = self.heightfield.chunk_origin(chunk.index);
+            chunks[chunk.lod as usize].push([
+                x as f32,
+                z as f32,
+                self.heightfield.skirt_depth(chunk.index),
+                0.0,
+            ]);
+        }
+
+        let spacing = self.heightfield.spacing();
+        let params = [
+            spacing.x,
+            spacing.y,
+            self.heightfield.height_scale(),
+            self.texture_tiling,
+        ];
+
+        Vec::from_iter(
+            chunks
+                .into_iter()
+                .zip(&self.grids)
+                .filter(|(chunks, _)| !chunks.is_empty())
+                .map(
+                    |(chunks, (vertex_buffer, vertex_count))| TerrainSubRenderer {
+                        pipeline: pipeline.clone(),
+                        material: material.clone(),
+                        vertex_count: *vertex_count,
+                        bind_group_provider: TerrainBindGroupProvider {
+                            height_bind_group: self.height_bind_group.clone(),
+                        },
+                        vertex_buffer_provider: TerrainVertexBufferProvider {
+                            vertex_buffer: vertex_buffer.clone(),
+                        },
+                        instance_data_provider: TerrainInstanceDataProvider { chunks, params },
+                    },
+                ),
+        )
+    }
+}
+
+/// Vertices of the grid shared by the chunks drawn at the level of detail, as triangles of `[x, skirt, z]`.
+/// `x` and `z` are sample offsets in the chunk, and `skirt` is 1 at the bottom of the skirts and 0 elsewhere.
+pub fn terrain_grid_vertices(lod: u32) -> Vec<[f32; 3]> {
+    let step = 1 << lod;
+    let quads = TERRAIN_CHUNK_QUADS >> lod;
+    let mut vertices = Vec::with_capacity(((quads * quads + 4 * quads) * 6) as usize);
+
+    for z in 0..quads {
+        for x in 0..quads {
+            let (x0, z0) = ((x * step) as f32, (z * step) as f32);
+            let (x1, z1) = (x0 + step as f32, z0 + step as f32);
+            vertices.extend([
+                [x0, 0.0, z0],
+                [x0, 0.0, z1],
+                [x1, 0.0, z1],
+                [x0, 0.0, z0],
+                [x1, 0.0, z1],
+                [x1, 0.0, z0],
+            ]);
+        }
+    }
+
+    let edge = TERRAIN_CHUNK_QUADS as f32;
+
+    for index in 0..quads {
+        let a = (index * step) as f32;
+        let b = a + step as f32;
+
+        for ((x0, z0), (x1, z1)) in [
+            ((a, 0.0), (b, 0.0)),
+            ((edge, a), (edge, b)),
+            ((b, edge), (a, edge)),
+            ((0.0, b), (0.0, a)),
+        ] {
+            vertices.extend([
+                [x0, 0.0, z0],
+                [x1, 0.0, z1],
+                [x1, 1.0, z1],
+                [x0, 0.0, z0],
+                [x1, 1.0, z1],
+                [x0, 1.0, z0],
+            ]);
+        }
+    }
+
+    vertices
+}
+
+/// Splits each height into its high and low bytes, as the two channels of an `Rg8Unorm` texel.
+fn pack_heights(heights: &[u16]) -> Vec<u8> {
+    Vec::from_iter(heights.iter().flat_map(|height| height.to_be_bytes()))
+}
+
+pub struct TerrainSubRenderer {
+    pipeline: CachedPipeline,
+    material: MaterialHandle,
+    vertex_count: u32,
+    bind_group_provider: TerrainBindGroupProvider,
+    vertex_buffer_provider: TerrainVertexBufferProvider,
+    instance_data_provider: TerrainInstanceDataProvider,
+}
+
+impl Renderer for TerrainSubRenderer {
+    fn pipeline(&self) -> CachedPipeline {
+        self.pipeline.clone()
+    }
+
+    fn material(&self) -> RwLockReadGuard<Material> {
+        self.material.read()
+    }
+
+    fn instance_count(&self) -> u32 {
+        self.instance_data_provider.chunks.len() as u32
+    }
+
+    fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    fn bind_group_provider(&self) -> &dyn BindGroupProvider {
+        &self.bind_group_provider
+    }
+
+    fn vertex_buffer_provider(&self) -> &dyn VertexBufferProvider {
+        &self.vertex_buffer_provider
+    }
+
+    fn instance_data_provider(&self) -> &dyn InstanceDataProvider {
+        &self.instance_data_provider
+    }
+}
+
+struct TerrainBindGroupProvider {
+    height_bind_group: Arc<BindGroup>,
+}
+
+impl BindGroupProvider for TerrainBindGroupProvider {
+    fn bind_group(&self, _instance: u32, key: SemanticShaderBindingKey) -> Option<&BindGroup> {
+        match key {
+            semantic_bindings::KEY_TERRAIN_HEIGHTS => Some(&self.height_bind_group),
+            _ => None,
+        }
+    }
+}
+
+struct TerrainVertexBufferProvider {
+    vertex_buffer: GenericBufferAllocation<Buffer>,
+}
+
+impl VertexBufferProvider for TerrainVertexBufferProvider {
+    fn vertex_buffer_count(&self) -> u32 {
+        1
+    }
+
+    fn vertex_buffer(&self, key: SemanticShaderInputKey) -> Option<VertexBuffer> {
+        match key {
+            semantic_inputs::KEY_POSITION => Some(VertexBuffer {
+                slot: 0,
+                buffer: &self.vertex_buffer,
+            }),
+            _ => None,
+        }
+    }
+}
+
+struct TerrainInstanceDataProvider {
+    chunks: Vec<[f32; 4]>,
+    params: [f32; 4],
+}
+
+impl InstanceDataProvider for TerrainInstanceDataProvider {
+    fn copy_per_instance_data(
+        &self,
+        instance: u32,
+        key: SemanticShaderInputKey,
+        buffer: &mut GenericBufferAllocation<HostBuffer>,
+    ) {
+        match key {
+            semantic_inputs::KEY_TERRAIN_CHUNK => {
+                buffer.copy_from_slice(self.chunks[instance as usize].as_bytes());
+            }
+            semantic_inputs::KEY_TERRAIN_PARAMS => {
+                buffer.copy_from_slice(self.params.as_bytes());
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_grid_vertices() {
+        let quads = TERRAIN_CHUNK_QUADS as usize;
+
+        for lod in 0..=TERRAIN_MAX_LOD {
+            let vertices = terrain_grid_vertices(lod);
+            let quads = quads >> lod;
+
+            assert_eq!(vertices.len(), (quads * quads + 4 * quads) * 6);
+            assert!(vertices.iter().all(|vertex| {
+                (0.0..=TERRAIN_CHUNK_QUADS as f32).contains(&vertex[0])
+                    && (0.0..=TERRAIN_CHUNK_QUADS as f32).contains(&vertex[2])
+            }));
+        }
+
+        // The coarsest grid is a single quad and its skirts.
+        let vertices = terrain_grid_vertices(TERRAIN_MAX_LOD);
+        assert_eq!(
+            vertices.iter().filter(|vertex| vertex[1] == 1.0).count(),
+            4 * 3
+        );
+    }
+
+    #[test]
+    fn check_pack_heights() {
+        assert_eq!(
+            pack_heights(&[0x1234, 0xFF00]),
+            vec![0x12, 0x34, 0xFF, 0x00]
+        );
+    }
+}
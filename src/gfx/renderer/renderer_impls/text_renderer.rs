@@ -0,0 +1,302 @@
+use super::sprite_renderer::{
+    corner_uvs, sprite_pipeline_provider, SpriteQuad, SpriteRendererBindGroupProvider,
+    SpriteRendererInstanceDataProvider, SpriteRendererVertexBufferProvider, SpriteSubRenderer,
+};
+use crate::{
+    gfx::{
+        compute_glyph_layout, measure_glyph_layout, BindGroupLayoutCache, Color, FontHandle,
+        GenericBufferAllocation, GlyphLayoutConfig, GlyphManager, GlyphSpriteHandle,
+        MaterialHandle, PipelineCache, PipelineProvider, ShaderManager, TextureHandle,
+    },
+    math::Vec2,
+    ui::UISize,
+};
+use fontdue::layout::{HorizontalAlign, VerticalAlign};
+use specs::{prelude::*, Component};
+use std::sync::Arc;
+use wgpu::{BindGroup, Buffer};
+
+/// Glyphs of a [`TextRenderer`] in one glyph texture, drawn by one sub renderer.
+struct TextPage {
+    texture: TextureHandle,
+    texture_bind_group: Arc<BindGroup>,
+    sampler_bind_group: Arc<BindGroup>,
+    quads: Vec<SpriteQuad>,
+}
+
+/// Quad of a glyph, from a glyph laid out at the SDF font size and scaled by `scale`.
+fn glyph_quad(offset: Vec2, size: Vec2, scale: f32, sprite: &GlyphSpriteHandle) -> SpriteQuad {
+    let texture = sprite.texture();
+    let (width, height) = (texture.width as f32, texture.height as f32);
+    let mapping = sprite.mapping();
+    let (uv_min, uv_max) = corner_uvs(
+        [
+            (mapping.x_min as f32 + 0.5) / width,
+            (mapping.y_min as f32 + 0.5) / height,
+            (mapping.x_max as f32 - 0.5) / width,
+            (mapping.y_max as f32 - 0.5) / height,
+        ],
+        false,
+        false,
+    );
+
+    SpriteQuad {
+        size: [size.x * scale, size.y * scale],
+        offset: [offset.x * scale, offset.y * scale],
+        uv_min,
+        uv_max,
+    }
+}
+
+/// Draws a string in the XY plane of the object, with the glyphs of a font rasterized into the glyph textures of the
+/// [`GlyphManager`] as they are needed.
+///
+/// The text is aligned around the origin of the object: e.g. centered and in the middle, the origin is at the center
+/// of the text. Each line is `font_size` units tall, and lines are broken at `\n`. It is drawn with the sprite
+/// pipeline in the transparent queue, and batched with the other texts of the same glyph texture.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct TextRenderer {
+    mask: u32,
+    color: Color,
+    font_size: f32,
+    thickness: f32,
+    smoothness: f32,
+    sorting_order: i32,
+    pipeline_provider: PipelineProvider,
+    font: Option<FontHandle>,
+    text: String,
+    layout_config: GlyphLayoutConfig,
+    pages: Vec<TextPage>,
+    is_dirty: bool,
+}
+
+impl TextRenderer {
+    pub fn new() -> Self {
+        Self {
+            mask: 0xFFFF_FFFF,
+            color: Color::white(),
+            font_size: 1f32,
+            thickness: 0.5f32,
+            smoothness: 0f32,
+            sorting_order: 0,
+            pipeline_provider: sprite_pipeline_provider(),
+            font: None,
+            text: String::new(),
+            layout_config: Default::default(),
+            pages: Vec::new(),
+            is_dirty: true,
+        }
+    }
+
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    pub fn set_mask(&mut self, mask: u32) {
+        self.mask = mask;
+    }
+
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    pub fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    pub fn font_size(&self) -> f32 {
+        self.font_size
+    }
+
+    /// Sets the height of a line in the local space of the object.
+    pub fn set_font_size(&mut self, font_size: f32) {
+        self.is_dirty |= self.font_size != font_size;
+        self.font_size = font_size;
+    }
+
+    pub fn thickness(&self) -> f32 {
+        self.thickness
+    }
+
+    /// Sets the thickness of the glyph outlines.
+    /// Recommended value is 0.5.
+    pub fn set_thickness(&mut self, thickness: f32) {
+        self.thickness = thickness;
+    }
+
+    pub fn smoothness(&self) -> f32 {
+        self.smoothness
+    }
+
+    /// Sets how much the glyph outlines are blurred, on top of their antialiasing. Defaults to 0.
+    pub fn set_smoothness(&mut self, smoothness: f32) {
+        self.smoothness = smoothness;
+    }
+
+    pub fn sorting_order(&self) -> i32 {
+        self.sorting_order
+    }
+
+    /// Sets the sub-order of the text within the transparent queue, as for sprites.
+    pub fn set_sorting_order(&mut self, sorting_order: i32) {
+        self.sorting_order = sorting_order;
+    }
+
+    pub fn material(&self) -> Option<&MaterialHandle> {
+        self.pipeline_provider.material()
+    }
+
+    pub fn set_material(&mut self, material: MaterialHandle) {
+        self.pipeline_provider.set_material(material);
+    }
+
+    pub fn font(&self) -> Option<&FontHandle> {
+        self.font.as_ref()
+    }
+
+    pub fn set_font(&mut self, font: FontHandle) {
+        self.is_dirty |= self.font.as_ref() != Some(&font);
+        self.font = Some(font);
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Sets the text, laying it out again before the next frame if it changed. The glyphs already in the glyph
+    /// textures are reused.
+    pub fn set_text(&mut self, text: impl AsRef<str>) {
+        let text = text.as_ref();
+
+        if self.text == text {
+            return;
+        }
+
+        self.text.clear();
+        self.text.push_str(text);
+        self.is_dirty = true;
+    }
+
+    pub fn horizontal_align(&self) -> HorizontalAlign {
+        self.layout_config.horizontal_align
+    }
+
+    pub fn set_horizontal_align(&mut self, horizontal_align: HorizontalAlign) {
+        self.is_dirty |= self.layout_config.horizontal_align != horizontal_align;
+        self.layout_config.horizontal_align = horizontal_align;
+    }
+
+    pub fn vertical_align(&self) -> VerticalAlign {
+        self.layout_config.vertical_align
+    }
+
+    pub fn set_vertical_align(&mut self, vertical_align: VerticalAlign) {
+        self.is_dirty |= self.layout_config.vertical_align != vertical_align;
+        self.layout_config.vertical_align = vertical_align;
+    }
+
+    /// Size the given text would take in the local space of the object with the font and the font size of this
+    /// renderer, without rasterizing it. Returns `None` if there is no font.
+    pub fn measure(&self, text: &str) -> Option<UISize> {
+        let font = self.font.as_ref()?;
+        let scale = self.font_size / font.sdf_font_size;
+        let size = measure_glyph_layout(font, font.sdf_font_size, text.chars());
+
+        Some(UISize {
+            width: size.width * scale,
+            height: size.height * scale,
+        })
+    }
+
+    pub fn sub_renderers(
+        &mut self,
+        standard_ui_vertex_buffer: &GenericBufferAllocation<Buffer>,
+        shader_mgr: &ShaderManager,
+        glyph_mgr: &mut GlyphManager,
+        pipeline_cache: &mut PipelineCache,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) -> Option<Vec<SpriteSubRenderer>> {
+        self.update_pages(glyph_mgr, bind_group_layout_cache);
+
+        let pipeline = self
+            .pipeline_provider
+            .obtain_pipeline(shader_mgr, pipeline_cache)?;
+        let material = self.pipeline_provider.material().cloned()?;
+        let color = pipeline_cache.color_space().shader_color(self.color);
+
+        Some(Vec::from_iter(self.pages.iter().map(|page| {
+            SpriteSubRenderer {
+                pipeline: pipeline.clone(),
+                material: material.clone(),
+                sorting_order: self.sorting_order,
+                texture: page.texture.clone(),
+                bind_group_provider: SpriteRendererBindGroupProvider {
+                    sprite_texture_bind_group: page.texture_bind_group.clone(),
+                    sprite_sampler_bind_group: page.sampler_bind_group.clone(),
+                },
+                vertex_buffer_provider: SpriteRendererVertexBufferProvider {
+                    vertex_buffer: standard_ui_vertex_buffer.clone(),
+                },
+                instance_data_provider: SpriteRendererInstanceDataProvider {
+                    quads: page.quads.clone(),
+                    color: [color.r, color.g, color.b, color.a],
+                    glyph_outline: Some((self.thickness, self.smoothness)),
+                },
+            }
+        })))
+    }
+
+    fn update_pages(
+        &mut self,
+        glyph_mgr: &mut GlyphManager,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) {
+        if !self.is_dirty {
+            return;
+        }
+
+        let font = if let Some(font) = &self.font {
+            font
+        } else {
+            return;
+        };
+
+        self.pages.clear();
+
+        // Laid out at the size the glyphs are rasterized at, so that the metrics keep their precision however small
+        // the font size is.
+        let scale = self.font_size / font.sdf_font_size;
+
+        for element in compute_glyph_layout(
+            font,
+            font.sdf_font_size,
+            UISize::new(),
+            &self.layout_config,
+            self.text.chars(),
+        ) {
+            if element.is_blank {
+                continue;
+            }
+
+            let sprite = glyph_mgr.glyph(bind_group_layout_cache, font, element.key);
+            let quad = glyph_quad(element.offset, element.size, scale, &sprite);
+            let page = self
+                .pages
+                .iter_mut()
+                .find(|page| Arc::ptr_eq(&page.texture_bind_group, sprite.texture_bind_group()));
+
+            match page {
+                Some(page) => page.quads.push(quad),
+                None => self.pages.push(TextPage {
+                    texture: sprite.texture().clone(),
+                    texture_bind_group: sprite.texture_bind_group().clone(),
+                    sampler_bind_group: sprite.sampler_bind_group().clone(),
+                    quads: vec![quad],
+                }),
+            }
+        }
+
+        self.is_dirty = false;
+    }
+}
@@ -191,7 +191,7 @@ impl UIElementRenderer {
             instance_data_provider: UIElementRendererInstanceDataProvider {
                 sprite,
                 size,
-                color: self.color,
+                color: pipeline_cache.color_space().shader_color(self.color),
             },
         })
     }
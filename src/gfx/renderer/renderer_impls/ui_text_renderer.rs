@@ -134,16 +134,16 @@ impl UITextRenderer {
     }
 
     pub fn set_font_size(&mut self, font_size: f32) {
+        self.is_dirty |= self.font_size != font_size;
         self.font_size = font_size;
-        self.is_dirty = true;
     }
 
     /// Sets the font size and recommended values for thickness and smoothness.
     pub fn set_font_size_with_recommended_values(&mut self, font_size: f32) {
+        self.is_dirty |= self.font_size != font_size;
         self.font_size = font_size;
         self.thickness = 0.5f32;
         self.smoothness = font_size / 1000f32;
-        self.is_dirty = true;
     }
 
     /// Sets the thickness of the glyph outlines.
@@ -163,13 +163,13 @@ impl UITextRenderer {
     }
 
     pub fn set_font(&mut self, font: FontHandle) {
+        self.is_dirty |= self.font.as_ref() != Some(&font);
         self.font = Some(font);
-        self.is_dirty = true;
     }
 
     pub fn set_text(&mut self, text: String) {
+        self.is_dirty |= self.text.as_ref() != Some(&text);
         self.text = Some(text);
-        self.is_dirty = true;
     }
 
     pub fn sub_renderers<'a>(
@@ -188,6 +188,7 @@ impl UITextRenderer {
             .pipeline_provider
             .obtain_pipeline(shader_mgr, pipeline_cache)?;
         let material = self.pipeline_provider.material().cloned()?;
+        let color = pipeline_cache.color_space().shader_color(self.color);
 
         let groups = self
             .glyphs
@@ -219,7 +220,7 @@ impl UITextRenderer {
                     },
                     instance_data_provider: UITextRendererInstanceDataProvider {
                         glyphs,
-                        color: self.color,
+                        color,
                         thickness: self.thickness,
                         smoothness: self.smoothness,
                     },
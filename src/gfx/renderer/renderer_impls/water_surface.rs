@@ -0,0 +1,408 @@
+use crate::{
+    gfx::{
+        pack_waves, sample_waves_at, semantic_bindings,
+        semantic_inputs::{self, KEY_POSITION},
+        BindGroupLayoutCache, BindGroupProvider, CachedPipeline, Color, ColorSpaceMode,
+        GenericBufferAllocation, GerstnerWave, HostBuffer, InstanceDataProvider, Material,
+        MaterialHandle, PipelineCache, PipelineProvider, Renderer, RendererVertexBufferAttribute,
+        RendererVertexBufferLayout, SemanticShaderBindingKey, SemanticShaderInputKey,
+        ShaderManager, Uploader, VertexBuffer, VertexBufferProvider, WATER_MAX_WAVES,
+    },
+    math::{Vec2, Vec3},
+};
+use parking_lot::RwLockReadGuard;
+use specs::{prelude::*, Component};
+use std::{mem::size_of, sync::Arc};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutEntry, BindingType, Buffer,
+    BufferAddress, BufferBindingType, BufferDescriptor, BufferSize, BufferUsages, CompareFunction,
+    DepthStencilState, Device, FrontFace, PolygonMode, PrimitiveState, PrimitiveTopology,
+    ShaderStages, TextureFormat,
+};
+use zerocopy::AsBytes;
+
+/// Layout of the `water` uniform. See `built_in_shaders/water_waves.wgsl`.
+#[repr(C)]
+#[derive(AsBytes, Debug, Clone)]
+struct WaterUniform {
+    /// `[wave count, time, foam threshold, floor depth]`.
+    params: [f32; 4],
+    shallow_color: [f32; 4],
+    /// The alpha is the absorption.
+    deep_color: [f32; 4],
+    waves: [[f32; 4]; WATER_MAX_WAVES * 2],
+}
+
+/// A square grid of water displaced by a sum of [`GerstnerWave`]s, centered on the object.
+///
+/// The waves are evaluated in world space at the rest position of each vertex, so that moving the object along
+/// with the camera never makes them slide. The object is expected to stay level; its height is the rest plane.
+/// [`height_at`](Self::height_at) and [`normal_at`](Self::normal_at) evaluate the same waves on the CPU.
+///
+/// The built-in water shader samples the reflection of a [`PlanarReflection`](crate::gfx::PlanarReflection)
+/// on the same object, and an `environment_texture` cubemap and `environment_sampler` set on the material.
+#[derive(Component)]
+#[storage(HashMapStorage)]
+pub struct WaterSurface {
+    mask: u32,
+    pipeline_provider: PipelineProvider,
+    waves: Vec<GerstnerWave>,
+    shallow_color: Color,
+    deep_color: Color,
+    absorption: f32,
+    floor_depth: f32,
+    foam_threshold: f32,
+    uniform_buffer: Buffer,
+    bind_group: Arc<BindGroup>,
+    grid: GenericBufferAllocation<Buffer>,
+    vertex_count: u32,
+}
+
+impl WaterSurface {
+    /// Creates a grid `size` units wide, split into `resolution` quads along each side.
+    pub fn new(
+        size: f32,
+        resolution: u32,
+        device: &Device,
+        bind_group_layout_cache: &mut BindGroupLayoutCache,
+    ) -> Self {
+        let mut pipeline_provider = PipelineProvider::new();
+
+        pipeline_provider.set_buffer_layouts(vec![RendererVertexBufferLayout {
+            array_stride: size_of::<[f32; 3]>() as BufferAddress,
+            attributes: vec![RendererVertexBufferAttribute {
+                key: KEY_POSITION,
+                offset: 0,
+            }],
+        }]);
+        pipeline_provider.set_primitive(PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            // Seen from below when the camera dives.
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        });
+        pipeline_provider.set_depth_stencil(Some(DepthStencilState {
+            format: TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: CompareFunction::Less,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }));
+
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("water uniform"),
+            size: size_of::<WaterUniform>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = bind_group_layout_cache.create_layout(vec![BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX_FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(size_of::<WaterUniform>() as u64),
+            },
+            count: None,
+        }]);
+        let bind_group = Arc::new(device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: bind_group_layout.as_ref(),
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        }));
+
+        let vertices = water_grid_vertices(size, resolution);
+        let grid_buffer = Arc::new(device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("water grid"),
+            contents: vertices.as_bytes(),
+            // Frame captures copy the vertices out.
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_SRC,
+        }));
+        let grid = GenericBufferAllocation::from_shared(
+            grid_buffer,
+            0,
+            BufferSize::new((size_of::<[f32; 3]>() * vertices.len().max(1)) as u64).unwrap(),
+        );
+
+        Self {
+            mask: 0xFFFF_FFFF,
+            pipeline_provider,
+            waves: Vec::new(),
+            shallow_color: Color::from_rgb(0.1, 0.45, 0.5),
+            deep_color: Color::from_rgb(0.01, 0.06, 0.12),
+            absorption: 0.15,
+            floor_depth: 20.0,
+            foam_threshold: 0.4,
+            uniform_buffer,
+            bind_group,
+            grid,
+            vertex_count: vertices.len() as u32,
+        }
+    }
+
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    pub fn set_mask(&mut self, mask: u32) {
+        self.mask = mask;
+    }
+
+    pub fn material(&self) -> Option<&MaterialHandle> {
+        self.pipeline_provider.material()
+    }
+
+    pub fn set_material(&mut self, material: MaterialHandle) {
+        self.pipeline_provider.set_material(material);
+    }
+
+    pub fn waves(&self) -> &[GerstnerWave] {
+        &self.waves
+    }
+
+    /// Replaces the waves. Only the first [`WATER_MAX_WAVES`] are kept.
+    pub fn set_waves(&mut self, mut waves: Vec<GerstnerWave>) {
+        waves.truncate(WATER_MAX_WAVES);
+        self.waves = waves;
+    }
+
+    /// Color of the water seen through a thin layer.
+    pub fn shallow_color(&self) -> Color {
+        self.shallow_color
+    }
+
+    pub fn set_shallow_color(&mut self, color: Color) {
+        self.shallow_color = color;
+    }
+
+    /// Color of the water seen through a thick layer.
+    pub fn deep_color(&self) -> Color {
+        self.deep_color
+    }
+
+    pub fn set_deep_color(&mut self, color: Color) {
+        self.deep_color = color;
+    }
+
+    /// Fraction of the light absorbed per unit of distance travelled through the water.
+    pub fn absorption(&self) -> f32 {
+        self.absorption
+    }
+
+    pub fn set_absorption(&mut self, absorption: f32) {
+        self.absorption = absorption;
+    }
+
+    /// Depth of the flat floor the absorption is measured against, below the rest plane.
+    pub fn floor_depth(&self) -> f32 {
+        self.floor_depth
+    }
+
+    pub fn set_floor_depth(&mut self, floor_depth: f32) {
+        self.floor_depth = floor_depth;
+    }
+
+    /// Foam covers the crests where the surface is pinched below this fraction of its rest area.
+    pub fn foam_threshold(&self) -> f32 {
+        self.foam_threshold
+    }
+
+    pub fn set_foam_threshold(&mut self, foam_threshold: f32) {
+        self.foam_threshold = foam_threshold;
+    }
+
+    /// Height of the surface above the rest plane at the point of the world's xz plane, `time` seconds in.
+    /// Matches the rendered surface when `time` is the scaled time of the frame.
+    pub fn height_at(&self, world_xz: Vec2, time: f32) -> f32 {
+        sample_waves_at(&self.waves, world_xz, time).displacement.y
+    }
+
+    /// Normal of the surface at the point of the world's xz plane, `time` seconds in.
+    pub fn normal_at(&self, world_xz: Vec2, time: f32) -> Vec3 {
+        sample_waves_at(&self.waves, world_xz, time).normal
+    }
+
+    /// Uploads the waves and the parameters for the frame at `time` seconds, the colors in the given space.
+    pub fn upload(&self, uploader: &mut Uploader, time: f32, color_space: ColorSpaceMode) {
+        let shallow_color = color_space.shader_color(self.shallow_color);
+        let deep_color = color_space.shader_color(self.deep_color);
+        let uniform = WaterUniform {
+            params: [
+                self.waves.len() as f32,
+                time,
+                self.foam_threshold,
+                self.floor_depth,
+            ],
+            shallow_color: [shallow_color.r, shallow_color.g, shallow_color.b, 1.0],
+            deep_color: [deep_color.r, deep_color.g, deep_color.b, self.absorption],
+            waves: pack_waves(&self.waves),
+        };
+        uploader.write(&self.uniform_buffer, 0, uniform.as_bytes());
+    }
+
+    pub fn sub_renderer(
+        &mut self,
+        shader_mgr: &ShaderManager,
+        pipeline_cache: &mut PipelineCache,
+    ) -> Option<WaterSurfaceSubRenderer> {
+        if self.vertex_count == 0 {
+            return None;
+        }
+
+        let pipeline = self
+            .pipeline_provider
+            .obtain_pipeline(shader_mgr, pipeline_cache)?;
+        let material = self.pipeline_provider.material()?.clone();
+
+        Some(WaterSurfaceSubRenderer {
+            pipeline,
+            material,
+            vertex_count: self.vertex_count,
+            bind_group_provider: WaterSurfaceBindGroupProvider {
+                bind_group: self.bind_group.clone(),
+            },
+            vertex_buffer_provider: WaterSurfaceVertexBufferProvider {
+                vertex_buffer: self.grid.clone(),
+            },
+        })
+    }
+}
+
+/// Vertices of a grid `size` units wide centered on the origin of the xz plane, as triangles facing +Y.
+pub fn water_grid_vertices(size: f32, resolution: u32) -> Vec<[f32; 3]> {
+    let step = size / resolution.max(1) as f32;
+    let origin = -0.5 * size;
+    let mut vertices = Vec::with_capacity((resolution * resolution * 6) as usize);
+
+    for z in 0..resolution {
+        for x in 0..resolution {
+            let (x0, z0) = (origin + x as f32 * step, origin + z as f32 * step);
+            let (x1, z1) = (x0 + step, z0 + step);
+            vertices.extend([
+                [x0, 0.0, z0],
+                [x0, 0.0, z1],
+                [x1, 0.0, z1],
+                [x0, 0.0, z0],
+                [x1, 0.0, z1],
+                [x1, 0.0, z0],
+            ]);
+        }
+    }
+
+    vertices
+}
+
+pub struct WaterSurfaceSubRenderer {
+    pipeline: CachedPipeline,
+    material: MaterialHandle,
+    vertex_count: u32,
+    bind_group_provider: WaterSurfaceBindGroupProvider,
+    vertex_buffer_provider: WaterSurfaceVertexBufferProvider,
+}
+
+impl Renderer for WaterSurfaceSubRenderer {
+    fn pipeline(&self) -> CachedPipeline {
+        self.pipeline.clone()
+    }
+
+    fn material(&self) -> RwLockReadGuard<Material> {
+        self.material.read()
+    }
+
+    fn instance_count(&self) -> u32 {
+        1
+    }
+
+    fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    fn bind_group_provider(&self) -> &dyn BindGroupProvider {
+        &self.bind_group_provider
+    }
+
+    fn vertex_buffer_provider(&self) -> &dyn VertexBufferProvider {
+        &self.vertex_buffer_provider
+    }
+
+    fn instance_data_provider(&self) -> &dyn InstanceDataProvider {
+        &WaterSurfaceInstanceDataProvider
+    }
+}
+
+struct WaterSurfaceBindGroupProvider {
+    bind_group: Arc<BindGroup>,
+}
+
+impl BindGroupProvider for WaterSurfaceBindGroupProvider {
+    fn bind_group(&self, _instance: u32, key: SemanticShaderBindingKey) -> Option<&BindGroup> {
+        match key {
+            semantic_bindings::KEY_WATER => Some(&self.bind_group),
+            _ => None,
+        }
+    }
+}
+
+struct WaterSurfaceVertexBufferProvider {
+    vertex_buffer: GenericBufferAllocation<Buffer>,
+}
+
+impl VertexBufferProvider for WaterSurfaceVertexBufferProvider {
+    fn vertex_buffer_count(&self) -> u32 {
+        1
+    }
+
+    fn vertex_buffer(&self, key: SemanticShaderInputKey) -> Option<VertexBuffer> {
+        match key {
+            semantic_inputs::KEY_POSITION => Some(VertexBuffer {
+                slot: 0,
+                buffer: &self.vertex_buffer,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The transform is the only per-instance input, and it is written by the render system.
+struct WaterSurfaceInstanceDataProvider;
+
+impl InstanceDataProvider for WaterSurfaceInstanceDataProvider {
+    fn copy_per_instance_data(
+        &self,
+        _instance: u32,
+        _key: SemanticShaderInputKey,
+        _buffer: &mut GenericBufferAllocation<HostBuffer>,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_grid_vertices() {
+        let vertices = water_grid_vertices(10.0, 4);
+
+        assert_eq!(vertices.len(), 4 * 4 * 6);
+        assert!(vertices.iter().all(|vertex| {
+            (-5.0..=5.0).contains(&vertex[0])
+                && vertex[1] == 0.0
+                && (-5.0..=5.0).contains(&vertex[2])
+        }));
+    }
+
+    #[test]
+    fn check_uniform_size() {
+        // Must match the size of the `water` semantic binding.
+        assert_eq!(size_of::<WaterUniform>(), 304);
+    }
+}
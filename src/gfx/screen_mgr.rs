@@ -5,6 +5,8 @@ pub struct ScreenManager {
     width: f64,
     height: f64,
     scale_factor: f64,
+    render_scale: f32,
+    is_focused: bool,
     is_dirty: bool,
 }
 
@@ -14,6 +16,8 @@ impl ScreenManager {
             width: width as _,
             height: height as _,
             scale_factor: 1f64,
+            render_scale: 1f32,
+            is_focused: true,
             is_dirty: true,
         }
     }
@@ -30,6 +34,15 @@ impl ScreenManager {
         self.scale_factor
     }
 
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Returns `true` if the window has the keyboard focus.
+    pub fn is_focused(&self) -> bool {
+        self.is_focused
+    }
+
     pub fn is_dirty(&self) -> bool {
         self.is_dirty
     }
@@ -57,6 +70,15 @@ impl ScreenManager {
         self.is_dirty = true;
     }
 
+    pub fn update_render_scale(&mut self, render_scale: f32) {
+        self.render_scale = render_scale;
+        self.is_dirty = true;
+    }
+
+    pub fn update_focus(&mut self, is_focused: bool) {
+        self.is_focused = is_focused;
+    }
+
     pub fn reset_dirty(&mut self) {
         self.is_dirty = false;
     }
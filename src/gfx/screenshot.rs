@@ -0,0 +1,239 @@
+use super::{GfxContextHandle, ReadbackError, ReadbackHandle, ReadbackImage, ReadbackManager};
+use image::RgbaImage;
+use parking_lot::Mutex;
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+use thiserror::Error;
+use wgpu::{CommandEncoder, Origin3d, Texture, TextureUsages};
+
+#[derive(Error, Debug)]
+pub enum ScreenshotError {
+    #[error("the surface cannot be copied from on this device")]
+    Unsupported,
+    #[error("failed to read the surface back: {0}")]
+    ReadBack(#[from] ReadbackError),
+    #[error("failed to save the image: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+#[derive(Default)]
+struct FrameImageSlot {
+    result: Option<Result<RgbaImage, ScreenshotError>>,
+    waker: Option<Waker>,
+}
+
+/// The image of a frame requested by [`ScreenshotCapture::request_image`]. It resolves a few frames after the
+/// frame it shows, as the engine keeps rendering and collecting readbacks.
+pub struct FrameImage {
+    slot: Arc<Mutex<FrameImageSlot>>,
+}
+
+impl FrameImage {
+    fn new() -> Self {
+        Self {
+            slot: Arc::new(Mutex::new(FrameImageSlot::default())),
+        }
+    }
+
+    fn resolved(result: Result<RgbaImage, ScreenshotError>) -> Self {
+        let image = Self::new();
+        deliver(&image.slot, result);
+        image
+    }
+
+    /// Blocks until the image has been read back, without an executor. `wait` is called between polls and must
+    /// make progress, e.g. by polling the device and collecting the readbacks.
+    pub fn block_on(self, mut wait: impl FnMut()) -> Result<RgbaImage, ScreenshotError> {
+        loop {
+            if let Some(result) = self.slot.lock().result.take() {
+                return result;
+            }
+
+            wait();
+        }
+    }
+}
+
+impl Future for FrameImage {
+    type Output = Result<RgbaImage, ScreenshotError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock();
+
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+fn deliver(slot: &Mutex<FrameImageSlot>, result: Result<RgbaImage, ScreenshotError>) {
+    let waker = {
+        let mut slot = slot.lock();
+        slot.result = Some(result);
+        slot.waker.take()
+    };
+
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+struct PendingScreenshot {
+    path: PathBuf,
+    readback: ReadbackHandle<ReadbackImage>,
+}
+
+/// Copies the surface into image files. The copy is read back asynchronously,
+/// so a screenshot is saved a few frames after the frame it shows.
+pub struct ScreenshotCapture {
+    gfx_ctx: GfxContextHandle,
+    requests: Vec<PathBuf>,
+    image_requests: Vec<Arc<Mutex<FrameImageSlot>>>,
+    pending: Vec<PendingScreenshot>,
+    failed: Vec<(PathBuf, ScreenshotError)>,
+}
+
+impl ScreenshotCapture {
+    pub fn new(gfx_ctx: GfxContextHandle) -> Self {
+        Self {
+            gfx_ctx,
+            requests: Vec::new(),
+            image_requests: Vec::new(),
+            pending: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if the surface has been configured to be copied from.
+    pub fn is_supported(&self) -> bool {
+        self.gfx_ctx
+            .surface_config
+            .read()
+            .usage
+            .contains(TextureUsages::COPY_SRC)
+    }
+
+    /// Saves the next frame into `path` as a PNG image.
+    pub fn request(&mut self, path: impl Into<PathBuf>) -> Result<(), ScreenshotError> {
+        if !self.is_supported() {
+            return Err(ScreenshotError::Unsupported);
+        }
+
+        self.requests.push(path.into());
+        Ok(())
+    }
+
+    /// Copies the next frame into an image, e.g. for rendering regression tests. Requested during a frame,
+    /// the copy is made once the frame has been drawn.
+    pub fn request_image(&mut self) -> FrameImage {
+        if !self.is_supported() {
+            return FrameImage::resolved(Err(ScreenshotError::Unsupported));
+        }
+
+        let image = FrameImage::new();
+        self.image_requests.push(image.slot.clone());
+        image
+    }
+
+    /// Copies the surface texture for the requested screenshots. Must be called after everything has been drawn.
+    pub fn encode(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        surface_texture: &Texture,
+        readbacks: &mut ReadbackManager,
+    ) {
+        let size = (surface_texture.width(), surface_texture.height());
+
+        for path in self.requests.drain(..) {
+            match readbacks.request_texture_readback(encoder, surface_texture, Origin3d::ZERO, size)
+            {
+                Ok(readback) => self.pending.push(PendingScreenshot { path, readback }),
+                Err(err) => self.failed.push((path, err.into())),
+            }
+        }
+
+        for slot in self.image_requests.drain(..) {
+            match readbacks.request_texture_readback(encoder, surface_texture, Origin3d::ZERO, size)
+            {
+                Ok(readback) => readback.on_complete(move |result| {
+                    deliver(&slot, result.map(into_rgba_image).map_err(Into::into))
+                }),
+                Err(err) => deliver(&slot, Err(err.into())),
+            }
+        }
+    }
+
+    /// Saves the screenshots that have been read back, returning where each one went.
+    pub fn collect(&mut self) -> Vec<(PathBuf, Result<(), ScreenshotError>)> {
+        let mut results = Vec::from_iter(self.failed.drain(..).map(|(path, err)| (path, Err(err))));
+        let mut index = 0;
+
+        while index < self.pending.len() {
+            let result = match self.pending[index].readback.poll() {
+                Some(Ok(image)) => save(&self.pending[index].path, image),
+                Some(Err(err)) => Err(err.into()),
+                None => {
+                    index += 1;
+                    continue;
+                }
+            };
+
+            let screenshot = self.pending.swap_remove(index);
+            results.push((screenshot.path, result));
+        }
+
+        results
+    }
+}
+
+fn save(path: &Path, image: ReadbackImage) -> Result<(), ScreenshotError> {
+    into_rgba_image(image).save(path)?;
+    Ok(())
+}
+
+fn into_rgba_image(mut image: ReadbackImage) -> RgbaImage {
+    // The surface is opaque, whatever is left in its alpha channel.
+    for pixel in image.pixels.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+
+    RgbaImage::from_raw(image.width, image.height, image.pixels).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_readback_becomes_opaque_image() {
+        let image = into_rgba_image(ReadbackImage {
+            width: 2,
+            height: 1,
+            pixels: vec![10, 20, 30, 0, 40, 50, 60, 128],
+        });
+
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.get_pixel(0, 0).0, [10, 20, 30, 255]);
+        assert_eq!(image.get_pixel(1, 0).0, [40, 50, 60, 255]);
+    }
+
+    #[test]
+    fn check_frame_image_resolves_once_delivered() {
+        let image = FrameImage::new();
+        let slot = image.slot.clone();
+
+        deliver(&slot, Ok(RgbaImage::new(3, 5)));
+
+        let image = pollster::block_on(image).unwrap();
+        assert_eq!(image.dimensions(), (3, 5));
+    }
+}
@@ -0,0 +1,234 @@
+use super::{
+    semantic_inputs::{self, KEY_POSITION},
+    BindGroupEntryResource, BindGroupProvider, BindingPropKey, BuiltInShaderManager,
+    CachedPipeline, CubemapHandle, GenericBufferAllocation, HostBuffer, InstanceDataProvider,
+    Material, MaterialHandle, PipelineCache, PipelineLayoutCache, PipelineProvider, Renderer,
+    RendererVertexBufferAttribute, RendererVertexBufferLayout, SemanticShaderBindingKey,
+    SemanticShaderInputKey, ShaderManager, VertexBuffer, VertexBufferProvider,
+    BUILT_IN_SHADER_SKYBOX,
+};
+use crate::math::Mat4;
+use parking_lot::RwLockReadGuard;
+use std::{mem::size_of, sync::Arc};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, Buffer, BufferAddress, BufferSize, BufferUsages, CompareFunction, DepthStencilState,
+    Device, FrontFace, PolygonMode, PrimitiveState, PrimitiveTopology, TextureFormat,
+};
+use zerocopy::AsBytes;
+
+/// Names of the material bindings of the built-in skybox shader.
+pub const SKYBOX_TEXTURE_NAME: &str = "skybox_texture";
+pub const SKYBOX_SAMPLER_NAME: &str = "skybox_sampler";
+
+/// One triangle covering the whole clip space.
+const FULLSCREEN_TRIANGLE: [[f32; 3]; 3] = [[-1.0, -1.0, 1.0], [3.0, -1.0, 1.0], [-1.0, 3.0, 1.0]];
+
+/// Cubemap drawn behind the scene of every camera clearing its color, see
+/// [`RenderManager::set_skybox`](super::RenderManager::set_skybox).
+///
+/// It is drawn at the far plane after the opaque queue, with the depth test passing only where nothing was drawn,
+/// so that the sky costs nothing behind opaque geometry. Transparent surfaces are blended over it.
+pub struct Skybox {
+    cubemap: CubemapHandle,
+    pipeline_provider: PipelineProvider,
+    triangle: GenericBufferAllocation<Buffer>,
+}
+
+impl Skybox {
+    pub fn new(cubemap: CubemapHandle, device: &Device) -> Self {
+        let mut pipeline_provider = PipelineProvider::new();
+
+        pipeline_provider.set_buffer_layouts(vec![RendererVertexBufferLayout {
+            array_stride: size_of::<[f32; 3]>() as BufferAddress,
+            attributes: vec![RendererVertexBufferAttribute {
+                key: KEY_POSITION,
+                offset: 0,
+            }],
+        }]);
+        pipeline_provider.set_primitive(PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        });
+        // At the maximum depth, it passes only where the depth is still cleared.
+        pipeline_provider.set_depth_stencil(Some(DepthStencilState {
+            format: TextureFormat::Depth32Float,
+            depth_write_enabled: false,
+            depth_compare: CompareFunction::LessEqual,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }));
+
+        let triangle = GenericBufferAllocation::from_shared(
+            Arc::new(device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("skybox triangle"),
+                contents: FULLSCREEN_TRIANGLE.as_bytes(),
+                // Frame captures copy the vertices out.
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_SRC,
+            })),
+            0,
+            BufferSize::new(size_of::<[[f32; 3]; 3]>() as u64).unwrap(),
+        );
+
+        Self {
+            cubemap,
+            pipeline_provider,
+            triangle,
+        }
+    }
+
+    pub fn cubemap(&self) -> &CubemapHandle {
+        &self.cubemap
+    }
+
+    /// Returns `None` until the built-in skybox shader is available.
+    pub fn sub_renderer(
+        &mut self,
+        built_in_shader_mgr: &BuiltInShaderManager,
+        shader_mgr: &ShaderManager,
+        pipeline_layout_cache: &mut PipelineLayoutCache,
+        pipeline_cache: &mut PipelineCache,
+    ) -> Option<SkyboxSubRenderer> {
+        if self.pipeline_provider.material().is_none() {
+            let shader = built_in_shader_mgr.find_shader(BUILT_IN_SHADER_SKYBOX)?;
+            let mut material = Material::new(shader, pipeline_layout_cache);
+            // Last of the opaque queue, whatever the order of the other materials.
+            material.render_order = i32::MAX;
+            material.set_cubemap(SKYBOX_TEXTURE_NAME, &self.cubemap);
+            material.set_bind_property(
+                &BindingPropKey::StringKey(SKYBOX_SAMPLER_NAME.to_owned()),
+                BindGroupEntryResource::Sampler {
+                    sampler: self.cubemap.sampler.clone(),
+                },
+            );
+            self.pipeline_provider
+                .set_material(MaterialHandle::new(material));
+        }
+
+        let pipeline = self
+            .pipeline_provider
+            .obtain_pipeline(shader_mgr, pipeline_cache)?;
+        let material = self.pipeline_provider.material()?.clone();
+
+        Some(SkyboxSubRenderer {
+            pipeline,
+            material,
+            vertex_buffer_provider: SkyboxVertexBufferProvider {
+                vertex_buffer: self.triangle.clone(),
+            },
+        })
+    }
+}
+
+/// Matrix the skybox shader maps clip space back to view directions with: the inverse view-projection of the camera,
+/// as if it stood at the origin. The sky thus turns with the camera but never comes closer.
+pub fn skybox_matrix(camera_transform: &Mat4, projection: &Mat4) -> Mat4 {
+    let (_, rotation, _) = camera_transform.split();
+    (Mat4::rotation(rotation).inversed() * projection).inversed()
+}
+
+pub struct SkyboxSubRenderer {
+    pipeline: CachedPipeline,
+    material: MaterialHandle,
+    vertex_buffer_provider: SkyboxVertexBufferProvider,
+}
+
+impl Renderer for SkyboxSubRenderer {
+    fn pipeline(&self) -> CachedPipeline {
+        self.pipeline.clone()
+    }
+
+    fn material(&self) -> RwLockReadGuard<Material> {
+        self.material.read()
+    }
+
+    fn instance_count(&self) -> u32 {
+        1
+    }
+
+    fn vertex_count(&self) -> u32 {
+        FULLSCREEN_TRIANGLE.len() as u32
+    }
+
+    fn bind_group_provider(&self) -> &dyn BindGroupProvider {
+        &SkyboxBindGroupProvider
+    }
+
+    fn vertex_buffer_provider(&self) -> &dyn VertexBufferProvider {
+        &self.vertex_buffer_provider
+    }
+
+    fn instance_data_provider(&self) -> &dyn InstanceDataProvider {
+        &SkyboxInstanceDataProvider
+    }
+}
+
+/// The cubemap is bound by the material.
+struct SkyboxBindGroupProvider;
+
+impl BindGroupProvider for SkyboxBindGroupProvider {
+    fn bind_group(&self, _instance: u32, _key: SemanticShaderBindingKey) -> Option<&BindGroup> {
+        None
+    }
+}
+
+struct SkyboxVertexBufferProvider {
+    vertex_buffer: GenericBufferAllocation<Buffer>,
+}
+
+impl VertexBufferProvider for SkyboxVertexBufferProvider {
+    fn vertex_buffer_count(&self) -> u32 {
+        1
+    }
+
+    fn vertex_buffer(&self, key: SemanticShaderInputKey) -> Option<VertexBuffer> {
+        match key {
+            semantic_inputs::KEY_POSITION => Some(VertexBuffer {
+                slot: 0,
+                buffer: &self.vertex_buffer,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The matrix of [`skybox_matrix`] is the only per-instance input, and it is written by the render system.
+struct SkyboxInstanceDataProvider;
+
+impl InstanceDataProvider for SkyboxInstanceDataProvider {
+    fn copy_per_instance_data(
+        &self,
+        _instance: u32,
+        _key: SemanticShaderInputKey,
+        _buffer: &mut GenericBufferAllocation<HostBuffer>,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{Quat, Vec3, Vec4};
+
+    #[test]
+    fn check_skybox_matrix_ignores_translation() {
+        let projection = Mat4::perspective(60f32.to_radians(), 1.5, 0.1, 100.0);
+        let rotation = Quat::from_axis_angle(Vec3::UP, 0.5);
+        let camera_transform = Mat4::trs(Vec3::new(1000.0, -20.0, 5.0), rotation, Vec3::ONE);
+        let matrix = skybox_matrix(&camera_transform, &projection);
+
+        let near = Vec4::new(0.0, 0.0, 0.0, 1.0) * &matrix;
+        let far = Vec4::new(0.0, 0.0, 1.0, 1.0) * &matrix;
+        let near = Vec3::from_vec4(near) / near.w;
+        let far = Vec3::from_vec4(far) / far.w;
+
+        // The center of the screen looks forward, turned by the rotation, from the origin.
+        assert!(near.len() < 0.2);
+        assert!(Vec3::distance((far - near).normalized(), rotation * Vec3::FORWARD) < 1e-3);
+    }
+}
@@ -0,0 +1,81 @@
+use super::ScreenManager;
+use wgpu::SurfaceError;
+use winit::dpi::PhysicalSize;
+
+/// How the engine loop recovers from a failure to acquire the surface texture of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SurfaceRecovery {
+    /// The surface no longer matches the window; it is configured again with the current screen size.
+    Reconfigure,
+    /// The frame is dropped, and the next one tries again.
+    SkipFrame,
+    /// The engine loop exits with the error.
+    Exit,
+}
+
+impl SurfaceRecovery {
+    pub fn for_error(err: &SurfaceError) -> Self {
+        match err {
+            SurfaceError::Lost | SurfaceError::Outdated => Self::Reconfigure,
+            SurfaceError::Timeout => Self::SkipFrame,
+            SurfaceError::OutOfMemory => Self::Exit,
+        }
+    }
+}
+
+/// Size to configure the surface with, or `None` while the window is minimized.
+pub fn surface_size(screen_mgr: &ScreenManager) -> Option<PhysicalSize<u32>> {
+    let width = screen_mgr.physical_width().round() as u32;
+    let height = screen_mgr.physical_height().round() as u32;
+
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    Some(PhysicalSize::new(width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_surface_errors_are_recovered() {
+        assert_eq!(
+            SurfaceRecovery::for_error(&SurfaceError::Lost),
+            SurfaceRecovery::Reconfigure
+        );
+        assert_eq!(
+            SurfaceRecovery::for_error(&SurfaceError::Outdated),
+            SurfaceRecovery::Reconfigure
+        );
+        assert_eq!(
+            SurfaceRecovery::for_error(&SurfaceError::Timeout),
+            SurfaceRecovery::SkipFrame
+        );
+        assert_eq!(
+            SurfaceRecovery::for_error(&SurfaceError::OutOfMemory),
+            SurfaceRecovery::Exit
+        );
+    }
+
+    #[test]
+    fn check_surface_size_after_resize_to_zero_and_back() {
+        let mut screen_mgr = ScreenManager::new(800, 600);
+        screen_mgr.update_scale_factor(2.0, PhysicalSize::new(1600, 1200));
+        assert_eq!(
+            surface_size(&screen_mgr),
+            Some(PhysicalSize::new(1600, 1200))
+        );
+
+        // Minimized; the surface is left alone until the window comes back.
+        screen_mgr.update_size(PhysicalSize::new(0, 0));
+        assert_eq!(surface_size(&screen_mgr), None);
+
+        screen_mgr.update_size(PhysicalSize::new(1280, 720));
+        assert_eq!(
+            surface_size(&screen_mgr),
+            Some(PhysicalSize::new(1280, 720))
+        );
+    }
+}
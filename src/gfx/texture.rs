@@ -1,11 +1,90 @@
+use asset::assets::{TextureAddressMode, TextureFilterMode};
 use codegen::Handle;
 use image::{DynamicImage, GenericImageView};
-use std::sync::Arc;
+use std::{borrow::Cow, sync::Arc};
 use wgpu::{
-    util::DeviceExt, AddressMode, Device, Extent3d, FilterMode, Queue, Sampler, SamplerDescriptor,
-    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+    util::DeviceExt, AddressMode, Device, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout,
+    Origin3d, Queue, Sampler, SamplerDescriptor, TextureAspect, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureView, COPY_BYTES_PER_ROW_ALIGNMENT,
 };
 
+/// How [`GfxContext::create_texture_from_image`](crate::gfx::GfxContext::create_texture_from_image) creates a
+/// texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureCreationParams {
+    /// Whether the texels are sRGB-encoded colors, decoded into linear values when sampled. Disable for data such as
+    /// normal maps. Color textures keep their look only under [`ColorSpaceMode::Linear`](super::ColorSpaceMode::Linear)
+    /// with it, and only under `Gamma` without it.
+    pub srgb: bool,
+    pub filter_mode: TextureFilterMode,
+    /// Address modes along u and v.
+    pub address_mode: (TextureAddressMode, TextureAddressMode),
+}
+
+impl Default for TextureCreationParams {
+    fn default() -> Self {
+        Self {
+            srgb: true,
+            filter_mode: TextureFilterMode::Bilinear,
+            address_mode: (TextureAddressMode::Clamp, TextureAddressMode::Clamp),
+        }
+    }
+}
+
+/// The sampler of textures of the filter mode and the address modes along u and v.
+pub fn texture_sampler_descriptor(
+    filter_mode: TextureFilterMode,
+    address_mode: (TextureAddressMode, TextureAddressMode),
+) -> SamplerDescriptor<'static> {
+    let (texel_filter_mode, mipmap_filter_mode) = match filter_mode {
+        TextureFilterMode::Point => (FilterMode::Nearest, FilterMode::Nearest),
+        TextureFilterMode::Bilinear => (FilterMode::Linear, FilterMode::Nearest),
+        TextureFilterMode::Trilinear => (FilterMode::Linear, FilterMode::Linear),
+    };
+    let convert_address_mode = |mode| match mode {
+        TextureAddressMode::Repeat => AddressMode::Repeat,
+        TextureAddressMode::Clamp => AddressMode::ClampToEdge,
+    };
+
+    SamplerDescriptor {
+        label: None,
+        address_mode_u: convert_address_mode(address_mode.0),
+        address_mode_v: convert_address_mode(address_mode.1),
+        address_mode_w: AddressMode::Repeat,
+        mag_filter: texel_filter_mode,
+        min_filter: texel_filter_mode,
+        mipmap_filter: mipmap_filter_mode,
+        lod_min_clamp: 0.0,
+        lod_max_clamp: 32.0,
+        compare: None,
+        anisotropy_clamp: 1,
+        border_color: None,
+    }
+}
+
+/// Pads the rows of the texels to the alignment buffer-to-texture copies require, returning the texels and the
+/// padded bytes per row. Rows that are aligned already are borrowed as they are.
+pub fn pad_texel_rows(texels: &[u8], bytes_per_row: u32, height: u32) -> (Cow<[u8]>, u32) {
+    let padded_bytes_per_row = (bytes_per_row + COPY_BYTES_PER_ROW_ALIGNMENT - 1)
+        / COPY_BYTES_PER_ROW_ALIGNMENT
+        * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    if padded_bytes_per_row == bytes_per_row || height <= 1 {
+        return (Cow::Borrowed(texels), bytes_per_row);
+    }
+
+    let mut padded = vec![0; (padded_bytes_per_row * height) as usize];
+
+    for (src, dst) in texels
+        .chunks_exact(bytes_per_row as usize)
+        .zip(padded.chunks_exact_mut(padded_bytes_per_row as usize))
+    {
+        dst[..bytes_per_row as usize].copy_from_slice(src);
+    }
+
+    (Cow::Owned(padded), padded_bytes_per_row)
+}
+
 #[derive(Handle)]
 pub struct Texture {
     pub texture: Arc<wgpu::Texture>,
@@ -67,7 +146,115 @@ impl Texture {
         }
     }
 
+    /// Uploads the image as an RGBA8 texture, expanding images of other layouts such as RGB8 first.
+    /// Images must be at most 65535 texels wide and high.
+    pub fn from_image_with_params(
+        image: &DynamicImage,
+        params: TextureCreationParams,
+        device: &Device,
+        queue: &Queue,
+    ) -> Self {
+        let (width, height) = image.dimensions();
+        assert!(
+            width <= u16::MAX as u32 && height <= u16::MAX as u32,
+            "image of {}x{} texels is too large for a texture",
+            width,
+            height
+        );
+
+        // wgpu has no 3-channel formats, so anything else than RGBA8 is expanded.
+        let rgba = match image.as_rgba8() {
+            Some(rgba) => Cow::Borrowed(rgba.as_raw().as_slice()),
+            None => Cow::Owned(image.to_rgba8().into_raw()),
+        };
+        let format = if params.srgb {
+            TextureFormat::Rgba8UnormSrgb
+        } else {
+            TextureFormat::Rgba8Unorm
+        };
+        let texture_extent = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&TextureDescriptor {
+            label: None,
+            size: texture_extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[format],
+        });
+
+        if width != 0 && height != 0 {
+            let (texels, bytes_per_row) = pad_texel_rows(&rgba, 4 * width, height);
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                &texels,
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+                texture_extent,
+            );
+        }
+
+        let view = texture.create_view(&Default::default());
+        let sampler = device.create_sampler(&texture_sampler_descriptor(
+            params.filter_mode,
+            params.address_mode,
+        ));
+
+        Self {
+            texture: texture.into(),
+            view: view.into(),
+            sampler: sampler.into(),
+            width: width as u16,
+            height: height as u16,
+        }
+    }
+
     pub fn create_empty(width: u16, height: u16, format: TextureFormat, device: &Device) -> Self {
+        Self::create_with_usage(
+            width,
+            height,
+            format,
+            TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+            device,
+        )
+    }
+
+    /// Creates a texture that can be rendered into and sampled afterwards.
+    pub fn create_render_target(
+        width: u16,
+        height: u16,
+        format: TextureFormat,
+        device: &Device,
+    ) -> Self {
+        Self::create_with_usage(
+            width,
+            height,
+            format,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            device,
+        )
+    }
+
+    fn create_with_usage(
+        width: u16,
+        height: u16,
+        format: TextureFormat,
+        usage: TextureUsages,
+        device: &Device,
+    ) -> Self {
         let texture_extent = Extent3d {
             width: width as _,
             height: height as _,
@@ -80,7 +267,7 @@ impl Texture {
             sample_count: 1,
             dimension: TextureDimension::D2,
             format,
-            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+            usage,
             view_formats: &[format],
         });
         let view = texture.create_view(&Default::default());
@@ -108,3 +295,41 @@ impl Texture {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_rows_are_padded_to_the_copy_alignment() {
+        // A 1-texel-wide RGBA8 image: 4 bytes per row, padded to 256.
+        let texels = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let (padded, bytes_per_row) = pad_texel_rows(&texels, 4, 3);
+
+        assert_eq!(bytes_per_row, COPY_BYTES_PER_ROW_ALIGNMENT);
+        assert_eq!(padded.len(), 3 * COPY_BYTES_PER_ROW_ALIGNMENT as usize);
+        assert_eq!(&padded[0..4], &[1, 2, 3, 4]);
+        assert_eq!(&padded[256..260], &[5, 6, 7, 8]);
+        assert_eq!(&padded[512..516], &[9, 10, 11, 12]);
+        assert!(padded[4..256].iter().all(|&byte| byte == 0));
+
+        // 100 texels wide is not a power of two and not aligned either.
+        let texels = vec![7; 400 * 2];
+        let (padded, bytes_per_row) = pad_texel_rows(&texels, 400, 2);
+        assert_eq!(bytes_per_row, 512);
+        assert_eq!(&padded[512..912], &texels[400..800]);
+    }
+
+    #[test]
+    fn check_aligned_rows_are_borrowed() {
+        let texels = vec![0; 256 * 4];
+        let (padded, bytes_per_row) = pad_texel_rows(&texels, 256, 4);
+        assert!(matches!(padded, Cow::Borrowed(_)));
+        assert_eq!(bytes_per_row, 256);
+
+        // A single row needs no padding.
+        let (padded, bytes_per_row) = pad_texel_rows(&texels[..12], 12, 1);
+        assert!(matches!(padded, Cow::Borrowed(_)));
+        assert_eq!(bytes_per_row, 12);
+    }
+}
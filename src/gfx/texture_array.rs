@@ -0,0 +1,349 @@
+use codegen::Handle;
+use image::{ColorType, DynamicImage, GenericImageView};
+use std::{collections::HashMap, sync::Arc};
+use thiserror::Error;
+use wgpu::{
+    AddressMode, Device, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout, Origin3d, Queue,
+    Sampler, SamplerDescriptor, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TextureArrayError {
+    #[error("texture array has no layers")]
+    Empty,
+    #[error("texture array format {0:?} is not supported; use Rgba8Unorm or Rgba8UnormSrgb")]
+    UnsupportedFormat(TextureFormat),
+    #[error("texture array layer `{0}` is given more than once")]
+    DuplicateLayer(String),
+    #[error(
+        "texture array layers must match `{first}` ({width}x{height}, {color:?}), but these do not: {}",
+        .layers.iter().map(|layer| layer.to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    Mismatch {
+        first: String,
+        width: u32,
+        height: u32,
+        color: ColorType,
+        layers: Vec<TextureArrayLayerInfo>,
+    },
+}
+
+/// Name, size and pixel format of a texture array layer before upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextureArrayLayerInfo {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub color: ColorType,
+}
+
+impl std::fmt::Display for TextureArrayLayerInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` ({}x{}, {:?})",
+            self.name, self.width, self.height, self.color
+        )
+    }
+}
+
+/// Checks that the layers can be packed into one array, returning the size shared by all of them.
+/// Every offending layer is reported at once, so that they can all be fixed in one go.
+pub fn validate_texture_array_layers(
+    layers: &[TextureArrayLayerInfo],
+) -> Result<(u32, u32), TextureArrayError> {
+    let first = layers.first().ok_or(TextureArrayError::Empty)?;
+
+    for (index, layer) in layers.iter().enumerate() {
+        if layers[..index].iter().any(|other| other.name == layer.name) {
+            return Err(TextureArrayError::DuplicateLayer(layer.name.clone()));
+        }
+    }
+
+    let mismatched = Vec::from_iter(
+        layers
+            .iter()
+            .filter(|layer| {
+                layer.width != first.width
+                    || layer.height != first.height
+                    || layer.color != first.color
+            })
+            .cloned(),
+    );
+
+    if !mismatched.is_empty() {
+        return Err(TextureArrayError::Mismatch {
+            first: first.name.clone(),
+            width: first.width,
+            height: first.height,
+            color: first.color,
+            layers: mismatched,
+        });
+    }
+
+    Ok((first.width, first.height))
+}
+
+/// Number of mip levels of a full chain down to 1x1.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Builds the mip chain of an RGBA8 image with a 2x2 box filter. The first level is the image itself.
+/// Odd sizes are handled by clamping to the last row and column.
+pub fn generate_rgba8_mips(width: u32, height: u32, texels: &[u8]) -> Vec<Vec<u8>> {
+    let mut levels = vec![texels.to_vec()];
+    let (mut width, mut height) = (width, height);
+
+    while 1 < width || 1 < height {
+        let src = levels.last().unwrap();
+        let (src_width, src_height) = (width, height);
+        width = (width / 2).max(1);
+        height = (height / 2).max(1);
+
+        let mut dst = vec![0u8; (width * height * 4) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                let xs = [(x * 2).min(src_width - 1), (x * 2 + 1).min(src_width - 1)];
+                let ys = [(y * 2).min(src_height - 1), (y * 2 + 1).min(src_height - 1)];
+
+                for channel in 0..4 {
+                    let mut sum = 0u32;
+                    for sy in ys {
+                        for sx in xs {
+                            sum += src[((sy * src_width + sx) * 4 + channel) as usize] as u32;
+                        }
+                    }
+                    dst[((y * width + x) * 4 + channel) as usize] = ((sum + 2) / 4) as u8;
+                }
+            }
+        }
+
+        levels.push(dst);
+    }
+
+    levels
+}
+
+/// 2D texture array whose layers share one size, format and mip chain, e.g. the ground textures of a terrain.
+/// Layers are addressed by name through [`layer`](Self::layer), so that materials don't hard-code indices.
+#[derive(Handle)]
+pub struct TextureArray {
+    pub texture: Arc<wgpu::Texture>,
+    pub view: Arc<TextureView>,
+    pub sampler: Arc<Sampler>,
+    pub width: u16,
+    pub height: u16,
+    pub mip_level_count: u32,
+    layers: Vec<String>,
+    layer_indices: HashMap<String, u32>,
+}
+
+impl TextureArray {
+    /// Packs the named images into one array in the given order. The images must have the same size and pixel format.
+    /// If `generate_mips` is set, a full mip chain is built for every layer.
+    pub fn from_images(
+        format: TextureFormat,
+        layers: &[(String, DynamicImage)],
+        generate_mips: bool,
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<Self, TextureArrayError> {
+        if !matches!(
+            format,
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+        ) {
+            return Err(TextureArrayError::UnsupportedFormat(format));
+        }
+
+        let (width, height) =
+            validate_texture_array_layers(&Vec::from_iter(layers.iter().map(|(name, image)| {
+                let (width, height) = image.dimensions();
+                TextureArrayLayerInfo {
+                    name: name.clone(),
+                    width,
+                    height,
+                    color: image.color(),
+                }
+            })))?;
+        let mip_level_count = if generate_mips {
+            mip_level_count(width, height)
+        } else {
+            1
+        };
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: layers.len() as u32,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[format],
+        });
+
+        for (layer, (_, image)) in layers.iter().enumerate() {
+            let texels = image.to_rgba8().into_raw();
+            let levels = if generate_mips {
+                generate_rgba8_mips(width, height, &texels)
+            } else {
+                vec![texels]
+            };
+
+            for (level, texels) in levels.iter().enumerate() {
+                let level_width = (width >> level).max(1);
+                let level_height = (height >> level).max(1);
+                queue.write_texture(
+                    ImageCopyTexture {
+                        texture: &texture,
+                        mip_level: level as u32,
+                        origin: Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer as u32,
+                        },
+                        aspect: TextureAspect::All,
+                    },
+                    texels,
+                    ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(4 * level_width),
+                        rows_per_image: Some(level_height),
+                    },
+                    Extent3d {
+                        width: level_width,
+                        height: level_height,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        // Layers of an array are usually tiled over large surfaces, hence the repeating sampler.
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: None,
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 32.0,
+            compare: None,
+            anisotropy_clamp: 1,
+            border_color: None,
+        });
+
+        let layer_names = Vec::from_iter(layers.iter().map(|(name, _)| name.clone()));
+        let layer_indices = HashMap::from_iter(
+            layer_names
+                .iter()
+                .enumerate()
+                .map(|(index, name)| (name.clone(), index as u32)),
+        );
+
+        Ok(Self {
+            texture: texture.into(),
+            view: view.into(),
+            sampler: sampler.into(),
+            width: width as u16,
+            height: height as u16,
+            mip_level_count,
+            layers: layer_names,
+            layer_indices,
+        })
+    }
+
+    /// Index of the named layer, to be passed to `textureSample` as the array index.
+    pub fn layer(&self, name: &str) -> Option<u32> {
+        self.layer_indices.get(name).copied()
+    }
+
+    pub fn layer_names(&self) -> &[String] {
+        &self.layers
+    }
+
+    pub fn layer_count(&self) -> u32 {
+        self.layers.len() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(name: &str, width: u32, height: u32, color: ColorType) -> TextureArrayLayerInfo {
+        TextureArrayLayerInfo {
+            name: name.to_owned(),
+            width,
+            height,
+            color,
+        }
+    }
+
+    #[test]
+    fn check_texture_array_layer_validation() {
+        assert_eq!(
+            validate_texture_array_layers(&[
+                layer("grass", 512, 512, ColorType::Rgba8),
+                layer("rock", 512, 512, ColorType::Rgba8),
+            ]),
+            Ok((512, 512))
+        );
+        assert_eq!(
+            validate_texture_array_layers(&[]),
+            Err(TextureArrayError::Empty)
+        );
+
+        let error = validate_texture_array_layers(&[
+            layer("grass", 512, 512, ColorType::Rgba8),
+            layer("rock", 256, 256, ColorType::Rgba8),
+            layer("sand", 512, 512, ColorType::Rgb8),
+            layer("snow", 512, 512, ColorType::Rgba8),
+        ])
+        .unwrap_err();
+        match &error {
+            TextureArrayError::Mismatch { first, layers, .. } => {
+                assert_eq!(first, "grass");
+                assert_eq!(
+                    Vec::from_iter(layers.iter().map(|layer| layer.name.as_str())),
+                    ["rock", "sand"]
+                );
+            }
+            _ => panic!("unexpected error {:?}", error),
+        }
+        assert!(error.to_string().contains("`rock` (256x256"));
+
+        assert_eq!(
+            validate_texture_array_layers(&[
+                layer("grass", 4, 4, ColorType::Rgba8),
+                layer("grass", 4, 4, ColorType::Rgba8),
+            ]),
+            Err(TextureArrayError::DuplicateLayer("grass".to_owned()))
+        );
+    }
+
+    #[test]
+    fn check_texture_array_mip_chain() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(512, 512), 10);
+        assert_eq!(mip_level_count(5, 3), 3);
+
+        // 3x1: the last level averages the clamped edge texel twice.
+        let texels = [0, 0, 0, 255, 100, 100, 100, 255, 200, 200, 200, 255];
+        let levels = generate_rgba8_mips(3, 1, &texels);
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[1], [50, 50, 50, 255]);
+    }
+}
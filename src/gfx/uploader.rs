@@ -0,0 +1,265 @@
+use super::GfxContextHandle;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    mem::{replace, take},
+    ops::Range,
+    sync::{mpsc, Arc},
+};
+use wgpu::{
+    Buffer, BufferAddress, BufferDescriptor, BufferUsages, CommandBuffer, CommandEncoder,
+    CommandEncoderDescriptor, Device, MapMode, COPY_BUFFER_ALIGNMENT, MAP_ALIGNMENT,
+};
+
+/// Counters of the [`Uploader`] over the last finished frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UploaderStats {
+    /// Writes packed into the staging chunks and copied in a single batch.
+    pub writes_coalesced: u32,
+    /// Writes too large to batch, handed to the queue as they are.
+    pub direct_writes: u32,
+    /// Bytes packed into the staging chunks.
+    pub staging_bytes: u64,
+    /// Staging chunks submitted and not yet mapped again for reuse.
+    pub chunks_in_flight: u32,
+}
+
+struct StagingChunk {
+    buffer: Arc<Buffer>,
+    offset: BufferAddress,
+}
+
+/// Batches the small buffer writes of a frame. Writes are packed into large staging chunks and their copies recorded
+/// into a single command buffer, submitted before the passes of the frame; chunks are reused once the GPU is done
+/// with them. Use it instead of [`Queue::write_buffer`](wgpu::Queue::write_buffer) for per-frame updates such as
+/// uniforms; large one-off uploads gain nothing from it.
+///
+/// Writes to the same range of a buffer within a frame are rejected in debug builds, as their order is ambiguous.
+pub struct Uploader {
+    gfx_ctx: GfxContextHandle,
+    encoder: CommandEncoder,
+    active_chunks: Vec<StagingChunk>,
+    closed_chunks: Vec<StagingChunk>,
+    free_chunks: Vec<StagingChunk>,
+    sender: mpsc::Sender<Option<StagingChunk>>,
+    receiver: mpsc::Receiver<Option<StagingChunk>>,
+    mapping_chunks: u32,
+    written_ranges: WrittenRanges<wgpu::Id<Buffer>>,
+    stats: UploaderStats,
+    last_stats: UploaderStats,
+}
+
+impl Uploader {
+    /// The size of a single staging chunk. It is currently set to 1 MiB.
+    pub const CHUNK_SIZE: BufferAddress = 1024 * 1024;
+    /// Writes larger than this are handed to the queue directly.
+    pub const MAX_BATCHED_SIZE: BufferAddress = 64 * 1024;
+
+    pub fn new(gfx_ctx: GfxContextHandle) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        Self {
+            encoder: create_encoder(&gfx_ctx.device),
+            active_chunks: Vec::new(),
+            closed_chunks: Vec::new(),
+            free_chunks: Vec::new(),
+            sender,
+            receiver,
+            mapping_chunks: 0,
+            written_ranges: WrittenRanges::new(),
+            stats: UploaderStats::default(),
+            last_stats: UploaderStats::default(),
+            gfx_ctx,
+        }
+    }
+
+    /// Counters of the last finished frame.
+    pub fn stats(&self) -> UploaderStats {
+        self.last_stats
+    }
+
+    /// Writes `data` into `target` at `offset` before the passes of the current frame. The target must be created
+    /// with [`BufferUsages::COPY_DST`]; the offset and the size must be multiples of
+    /// [`COPY_BUFFER_ALIGNMENT`], as for [`Queue::write_buffer`](wgpu::Queue::write_buffer).
+    pub fn write(&mut self, target: &Buffer, offset: BufferAddress, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        let size = data.len() as BufferAddress;
+        debug_assert!(
+            offset % COPY_BUFFER_ALIGNMENT == 0 && size % COPY_BUFFER_ALIGNMENT == 0,
+            "uploads must be aligned to {} bytes",
+            COPY_BUFFER_ALIGNMENT
+        );
+
+        if cfg!(debug_assertions) {
+            let is_disjoint = self
+                .written_ranges
+                .insert(target.global_id(), offset..offset + size);
+            debug_assert!(
+                is_disjoint,
+                "overlapping uploads to the same range of a buffer within a frame"
+            );
+        }
+
+        if Self::MAX_BATCHED_SIZE < size {
+            self.gfx_ctx.queue.write_buffer(target, offset, data);
+            self.stats.direct_writes += 1;
+            return;
+        }
+
+        let index = self.obtain_chunk(size);
+        let chunk = &mut self.active_chunks[index];
+        chunk
+            .buffer
+            .slice(chunk.offset..chunk.offset + size)
+            .get_mapped_range_mut()
+            .copy_from_slice(data);
+        self.encoder
+            .copy_buffer_to_buffer(&chunk.buffer, chunk.offset, target, offset, size);
+        chunk.offset = align(chunk.offset + size, MAP_ALIGNMENT);
+
+        self.stats.writes_coalesced += 1;
+        self.stats.staging_bytes += size;
+    }
+
+    /// Closes the frame, returning the copies to submit before its passes.
+    pub fn finish(&mut self) -> CommandBuffer {
+        for chunk in self.active_chunks.drain(..) {
+            chunk.buffer.unmap();
+            self.closed_chunks.push(chunk);
+        }
+
+        self.written_ranges.clear();
+        self.last_stats = UploaderStats {
+            chunks_in_flight: self.mapping_chunks + self.closed_chunks.len() as u32,
+            ..take(&mut self.stats)
+        };
+
+        replace(&mut self.encoder, create_encoder(&self.gfx_ctx.device)).finish()
+    }
+
+    /// Starts mapping the chunks of the finished frame again, so that they are reused once the GPU is done with them.
+    /// Must be called after the command buffer of [`finish`](Self::finish) has been submitted.
+    pub fn recall(&mut self) {
+        self.receive_chunks();
+
+        for chunk in self.closed_chunks.drain(..) {
+            let sender = self.sender.clone();
+            let buffer = chunk.buffer.clone();
+            buffer.slice(..).map_async(MapMode::Write, move |result| {
+                // A chunk failing to map, e.g. on device loss, is dropped.
+                let _ = sender.send(result.ok().map(|_| chunk));
+            });
+            self.mapping_chunks += 1;
+        }
+    }
+
+    fn receive_chunks(&mut self) {
+        while let Ok(chunk) = self.receiver.try_recv() {
+            self.mapping_chunks -= 1;
+
+            if let Some(mut chunk) = chunk {
+                chunk.offset = 0;
+                self.free_chunks.push(chunk);
+            }
+        }
+    }
+
+    fn obtain_chunk(&mut self, size: BufferAddress) -> usize {
+        if let Some(index) = self
+            .active_chunks
+            .iter()
+            .position(|chunk| chunk.offset + size <= Self::CHUNK_SIZE)
+        {
+            return index;
+        }
+
+        self.receive_chunks();
+
+        let chunk = self.free_chunks.pop().unwrap_or_else(|| StagingChunk {
+            buffer: Arc::new(self.gfx_ctx.device.create_buffer(&BufferDescriptor {
+                label: Some("[uploader] staging chunk"),
+                size: Self::CHUNK_SIZE,
+                usage: BufferUsages::MAP_WRITE | BufferUsages::COPY_SRC,
+                mapped_at_creation: true,
+            })),
+            offset: 0,
+        });
+        self.active_chunks.push(chunk);
+        self.active_chunks.len() - 1
+    }
+}
+
+fn create_encoder(device: &Device) -> CommandEncoder {
+    device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("[uploader] upload encoder"),
+    })
+}
+
+fn align(value: BufferAddress, alignment: BufferAddress) -> BufferAddress {
+    (value + alignment - 1) / alignment * alignment
+}
+
+/// Ranges written per buffer within a frame.
+#[derive(Debug)]
+struct WrittenRanges<K> {
+    ranges: HashMap<K, Vec<Range<BufferAddress>>>,
+}
+
+impl<K> WrittenRanges<K>
+where
+    K: Hash + Eq,
+{
+    fn new() -> Self {
+        Self {
+            ranges: HashMap::new(),
+        }
+    }
+
+    /// Records the range, returning `false` if it overlaps a range already written to the same buffer.
+    fn insert(&mut self, key: K, range: Range<BufferAddress>) -> bool {
+        let ranges = self.ranges.entry(key).or_default();
+
+        if ranges
+            .iter()
+            .any(|written| written.start < range.end && range.start < written.end)
+        {
+            return false;
+        }
+
+        ranges.push(range);
+        true
+    }
+
+    fn clear(&mut self) {
+        self.ranges.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_overlapping_ranges_are_rejected() {
+        let mut ranges = WrittenRanges::new();
+
+        assert!(ranges.insert(0, 0..64));
+        assert!(ranges.insert(0, 64..128));
+        assert!(ranges.insert(1, 0..64));
+        assert!(!ranges.insert(0, 60..68));
+        assert!(!ranges.insert(0, 0..256));
+
+        ranges.clear();
+        assert!(ranges.insert(0, 0..64));
+    }
+
+    #[test]
+    fn check_align() {
+        assert_eq!(align(0, MAP_ALIGNMENT), 0);
+        assert_eq!(align(1, MAP_ALIGNMENT), MAP_ALIGNMENT);
+        assert_eq!(align(MAP_ALIGNMENT, MAP_ALIGNMENT), MAP_ALIGNMENT);
+    }
+}
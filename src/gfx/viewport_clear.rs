@@ -0,0 +1,161 @@
+use super::{CameraClearMode, GfxContextHandle, ViewportRect};
+use std::borrow::Cow;
+use wgpu::{
+    BlendComponent, BlendFactor, BlendOperation, BlendState, Color, ColorTargetState, ColorWrites,
+    CompareFunction, DepthStencilState, FragmentState, MultisampleState, PipelineLayoutDescriptor,
+    PrimitiveState, RenderPass, RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor,
+    ShaderSource, StencilFaceState, StencilOperation, StencilState, TextureFormat, VertexState,
+};
+
+/// Clears the viewport of a camera, as load operations can only clear whole attachments.
+pub struct ViewportClear {
+    color_pipeline: RenderPipeline,
+    depth_pipeline: RenderPipeline,
+    has_depth: bool,
+}
+
+impl ViewportClear {
+    /// `color_format` is the one of the targets the cameras draw into.
+    pub fn new(
+        gfx_ctx: GfxContextHandle,
+        color_format: TextureFormat,
+        depth_stencil_format: Option<TextureFormat>,
+    ) -> Self {
+        let device = &gfx_ctx.device;
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("viewport clear shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "./built_in_shaders/viewport_clear.wgsl"
+            ))),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("viewport clear pipeline layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let depth_stencil = depth_stencil_format.map(|format| {
+            let face = StencilFaceState {
+                compare: CompareFunction::Always,
+                fail_op: StencilOperation::Replace,
+                depth_fail_op: StencilOperation::Replace,
+                pass_op: StencilOperation::Replace,
+            };
+            DepthStencilState {
+                format,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Always,
+                stencil: if format.has_stencil_aspect() {
+                    StencilState {
+                        front: face,
+                        back: face,
+                        read_mask: 0xFF,
+                        write_mask: 0xFF,
+                    }
+                } else {
+                    Default::default()
+                },
+                bias: Default::default(),
+            }
+        });
+        let create_pipeline = |label, write_mask| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: PrimitiveState::default(),
+                depth_stencil: depth_stencil.clone(),
+                multisample: MultisampleState::default(),
+                fragment: Some(FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(ColorTargetState {
+                        format: color_format,
+                        // The shader outputs white, so the result is the blend constant.
+                        blend: Some(BlendState {
+                            color: BlendComponent {
+                                src_factor: BlendFactor::Constant,
+                                dst_factor: BlendFactor::Zero,
+                                operation: BlendOperation::Add,
+                            },
+                            alpha: BlendComponent {
+                                src_factor: BlendFactor::Constant,
+                                dst_factor: BlendFactor::Zero,
+                                operation: BlendOperation::Add,
+                            },
+                        }),
+                        write_mask,
+                    })],
+                }),
+                multiview: None,
+            })
+        };
+
+        Self {
+            color_pipeline: create_pipeline("viewport clear pipeline", ColorWrites::ALL),
+            depth_pipeline: create_pipeline("viewport depth clear pipeline", ColorWrites::empty()),
+            has_depth: depth_stencil_format.is_some(),
+        }
+    }
+
+    /// Clears `rect` of the targets of the pass as `clear_mode` says, leaving the viewport at `rect`.
+    /// The pass must have been begun with [`CameraClearMode::Keep`].
+    pub fn draw<'r>(
+        &'r self,
+        render_pass: &mut RenderPass<'r>,
+        clear_mode: &CameraClearMode,
+        rect: ViewportRect,
+    ) {
+        let (pipeline, color, depth, stencil) = match clear_mode {
+            CameraClearMode::Keep => return,
+            CameraClearMode::All {
+                color,
+                depth,
+                stencil,
+            } => (&self.color_pipeline, Some(color), *depth, *stencil),
+            CameraClearMode::DepthOnly { depth, stencil } => {
+                (&self.depth_pipeline, None, *depth, *stencil)
+            }
+        };
+
+        if color.is_none() && !self.has_depth {
+            return;
+        }
+
+        let depth = if depth.is_finite() {
+            depth.clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_scissor_rect(rect.x, rect.y, rect.width, rect.height);
+        // Every fragment lands on `depth` when the depth range is collapsed to it.
+        render_pass.set_viewport(
+            rect.x as f32,
+            rect.y as f32,
+            rect.width as f32,
+            rect.height as f32,
+            depth,
+            depth,
+        );
+
+        if let Some(color) = color {
+            render_pass.set_blend_constant(Color {
+                r: color.r as f64,
+                g: color.g as f64,
+                b: color.b as f64,
+                a: color.a as f64,
+            });
+        }
+
+        if self.has_depth {
+            render_pass.set_stencil_reference(stencil);
+        }
+
+        render_pass.draw(0..3, 0..1);
+    }
+}
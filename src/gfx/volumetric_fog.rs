@@ -0,0 +1,949 @@
+use super::{Color, ColorSpaceMode, GfxContextHandle, GpuTimer, QualityPreset, Uploader};
+use crate::math::{Mat4, Vec3};
+use specs::{prelude::*, Component};
+use std::{borrow::Cow, f32::consts::PI, mem::size_of};
+use wgpu::{
+    AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent, BlendFactor,
+    BlendOperation, BlendState, Buffer, BufferAddress, BufferBindingType, BufferDescriptor,
+    BufferSize, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder, ComputePassDescriptor,
+    ComputePipeline, ComputePipelineDescriptor, DownlevelFlags, Extent3d, FilterMode,
+    FragmentState, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    Sampler, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages, StorageTextureAccess, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsages, TextureView, TextureViewDimension, VertexState,
+};
+use zerocopy::AsBytes;
+
+/// Most [`FogVolume`]s sampled by the fog; the ones nearest to the camera are kept.
+pub const MAX_FOG_VOLUMES: usize = 64;
+
+/// rgb: light scattered towards the camera, a: extinction, or transmittance once integrated.
+const FROXEL_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// Extinction below which a froxel is treated as empty when integrated, to avoid dividing by it.
+const MIN_EXTINCTION: f32 = 1e-5;
+
+/// Adds fog inside a box spanning `-0.5..0.5` on every axis of the object, on top of the global density.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+#[storage(HashMapStorage)]
+pub struct FogVolume {
+    /// Extinction per unit of distance in the middle of the box.
+    pub density: f32,
+    /// Depth of the band inside the faces of the box over which the density fades in, as a fraction of its
+    /// half-size. 0 gives hard edges.
+    pub edge_falloff: f32,
+}
+
+impl FogVolume {
+    pub fn new(density: f32, edge_falloff: f32) -> Self {
+        Self {
+            density,
+            edge_falloff,
+        }
+    }
+
+    /// Density added at the point, given in the space of the object.
+    ///
+    /// This is the density `built_in_shaders/volumetric_fog.wgsl` samples; both must be changed together.
+    pub fn density_at(&self, local_position: Vec3) -> f32 {
+        let Vec3 { x, y, z } = Vec3::abs(local_position);
+        let edge = x.max(y).max(z) * 2.0;
+
+        if 1.0 < edge {
+            return 0.0;
+        }
+
+        let fade = if 0.0 < self.edge_falloff {
+            ((1.0 - edge) / self.edge_falloff).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        self.density * fade
+    }
+}
+
+/// Look of the volumetric fog. The light comes from a single sun and an ambient term.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumetricFogSettings {
+    /// Extinction per unit of distance everywhere, before the [`FogVolume`]s.
+    pub density: f32,
+    /// Fraction of the extinguished light that is scattered rather than absorbed, per channel.
+    pub albedo: Color,
+    /// Asymmetry of the scattering, in `-1..1`. Positive values scatter forwards, so that the fog glows towards the sun.
+    pub anisotropy: f32,
+    /// Direction the sunlight travels in. Normalized when uploaded.
+    pub sun_direction: Vec3,
+    pub sun_color: Color,
+    pub sun_intensity: f32,
+    /// Light scattered towards the camera whatever the direction of the view, e.g. from the sky.
+    pub ambient: Color,
+    /// Distance from the camera where the fog ends. The froxels are spread up to it, so shorter distances give
+    /// finer detail; surfaces further away are fogged as if they were at this distance.
+    pub far: f32,
+}
+
+impl Default for VolumetricFogSettings {
+    fn default() -> Self {
+        Self {
+            density: 0.02,
+            albedo: Color::white(),
+            anisotropy: 0.6,
+            sun_direction: Vec3::new(0.3, -1.0, 0.2),
+            sun_color: Color::white(),
+            sun_intensity: 4.0,
+            ambient: Color::from_rgb(0.1, 0.12, 0.15),
+            far: 128.0,
+        }
+    }
+}
+
+/// The camera the fog is seen from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FogView {
+    pub view_projection: Mat4,
+    pub position: Vec3,
+    /// Distance of the near plane, where the froxel grid starts.
+    pub near: f32,
+}
+
+/// Distance from the camera to the start of `slice` of `slices` slices.
+/// The slices grow exponentially from `near` to `far`, so that froxels stay about as deep as they are wide.
+///
+/// This is the distribution `built_in_shaders/volumetric_fog.wgsl` and `built_in_shaders/volumetric_fog_apply.wgsl`
+/// use; all of them must be changed together.
+pub fn froxel_slice_depth(slice: f32, slices: u32, near: f32, far: f32) -> f32 {
+    near * (far / near).powf(slice / slices as f32)
+}
+
+/// Fractional slice at the distance from the camera, the inverse of [`froxel_slice_depth`].
+pub fn froxel_depth_slice(depth: f32, slices: u32, near: f32, far: f32) -> f32 {
+    (depth / near).ln() / (far / near).ln() * slices as f32
+}
+
+/// Fraction of the light scattered by the angle whose cosine is `cos_theta`, per steradian.
+/// `anisotropy` is the asymmetry of [`VolumetricFogSettings`].
+pub fn henyey_greenstein(cos_theta: f32, anisotropy: f32) -> f32 {
+    let g2 = anisotropy * anisotropy;
+    let denominator = (1.0 + g2 - 2.0 * anisotropy * cos_theta).max(1e-4);
+    (1.0 - g2) / (4.0 * PI * denominator.powf(1.5))
+}
+
+/// Integrates a column of froxels from the camera outwards. Each froxel is `[scattered r, g, b, extinction]`, per unit
+/// of distance. Returns, for each slice, the light scattered towards the camera up to the end of the slice and the
+/// transmittance over the same distance.
+///
+/// The scattering is integrated analytically within each slice, so that dense froxels never add more light than they
+/// let through; `built_in_shaders/volumetric_fog.wgsl` integrates the same way, and both must be changed together.
+pub fn integrate_froxels(froxels: &[[f32; 4]], near: f32, far: f32) -> Vec<[f32; 4]> {
+    let slices = froxels.len() as u32;
+    let mut scattered = [0.0f32; 3];
+    let mut transmittance = 1.0f32;
+
+    Vec::from_iter(froxels.iter().enumerate().map(|(slice, froxel)| {
+        let thickness = froxel_slice_depth(slice as f32 + 1.0, slices, near, far)
+            - froxel_slice_depth(slice as f32, slices, near, far);
+        let extinction = froxel[3];
+        let slice_transmittance = (-extinction * thickness).exp();
+
+        for channel in 0..3 {
+            let integral = if MIN_EXTINCTION < extinction {
+                (froxel[channel] - froxel[channel] * slice_transmittance) / extinction
+            } else {
+                froxel[channel] * thickness
+            };
+            scattered[channel] += transmittance * integral;
+        }
+
+        transmittance *= slice_transmittance;
+        [scattered[0], scattered[1], scattered[2], transmittance]
+    }))
+}
+
+/// Transmittance of a uniform fog over `distance`, which the fog falls back to without compute shaders.
+pub fn analytic_fog_transmittance(density: f32, distance: f32) -> f32 {
+    (-density * distance.max(0.0)).exp()
+}
+
+/// Element `index` of the Halton sequence of the base, in `0..1`.
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+
+    while 0 < index {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+
+    result
+}
+
+/// Layout of the `params` uniform of the fog shaders.
+#[repr(C)]
+#[derive(AsBytes, Debug, Clone)]
+struct FogUniform {
+    inverse_view_projection: [f32; 16],
+    previous_view_projection: [f32; 16],
+    camera_position: [f32; 4],
+    previous_camera_position: [f32; 4],
+    /// `[x, y, z, anisotropy]`.
+    sun_direction: [f32; 4],
+    /// `[r, g, b, intensity]`.
+    sun_color: [f32; 4],
+    ambient: [f32; 4],
+    /// `[r, g, b, density]`.
+    albedo: [f32; 4],
+    /// `[froxels across, down, deep, volume count]`.
+    grid: [u32; 4],
+    /// `[near, far, jitter, history weight]`.
+    depth: [f32; 4],
+    /// `[samples per froxel, 0, 0, 0]`.
+    samples: [u32; 4],
+}
+
+/// Layout of an element of the `volumes` storage buffer.
+#[repr(C)]
+#[derive(AsBytes, Debug, Clone)]
+struct FogVolumeUniform {
+    world_to_local: [f32; 16],
+    /// `[density, edge falloff, 0, 0]`.
+    params: [f32; 4],
+}
+
+struct FroxelPipelines {
+    scatter_layout: BindGroupLayout,
+    integrate_layout: BindGroupLayout,
+    scatter_pipeline: ComputePipeline,
+    integrate_pipeline: ComputePipeline,
+}
+
+struct FroxelGrid {
+    size: [u32; 3],
+    /// Scattering of this frame and of the previous one, swapped every frame.
+    scattering: [TextureView; 2],
+    integrated: TextureView,
+}
+
+/// Where the previous frame saw the fog from, to reproject its froxels.
+struct FogHistory {
+    view_projection: Mat4,
+    camera_position: Vec3,
+}
+
+/// Volumetric fog lit by the sun, computed in a grid of froxels over the view of the camera and composited over its
+/// pass. The density of each froxel is jittered along the view and blended with the previous frames, then the froxels
+/// are integrated from the camera outwards.
+///
+/// Devices without compute shaders fall back to an analytic fog of the global density, which ignores the volumes.
+pub struct VolumetricFog {
+    gfx_ctx: GfxContextHandle,
+    settings: VolumetricFogSettings,
+    pipelines: Option<FroxelPipelines>,
+    apply_layout: BindGroupLayout,
+    apply_pipeline: RenderPipeline,
+    color_format: TextureFormat,
+    sampler: Sampler,
+    uniform_buffer: Buffer,
+    volume_buffer: Buffer,
+    grid: Option<FroxelGrid>,
+    grid_size: [u32; 3],
+    samples: u32,
+    temporal_blend: f32,
+    history: Option<FogHistory>,
+    frame: u32,
+    timer: Option<GpuTimer>,
+    timed_slot: Option<usize>,
+}
+
+impl VolumetricFog {
+    /// Returns `true` if the device can compute the froxels, rather than falling back to the analytic fog.
+    pub fn is_froxel_supported(gfx_ctx: &GfxContextHandle) -> bool {
+        gfx_ctx
+            .downlevel_capabilities
+            .flags
+            .contains(DownlevelFlags::COMPUTE_SHADERS)
+    }
+
+    /// `color_format` is the one of the targets the fog is composited over.
+    pub fn new(
+        gfx_ctx: GfxContextHandle,
+        settings: VolumetricFogSettings,
+        color_format: TextureFormat,
+    ) -> Self {
+        let device = &gfx_ctx.device;
+        let uniform_entry = |visibility| BindGroupLayoutEntry {
+            binding: 0,
+            visibility,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(size_of::<FogUniform>() as u64),
+            },
+            count: None,
+        };
+        let texture_entry =
+            |binding, visibility, sample_type, view_dimension| BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty: BindingType::Texture {
+                    sample_type,
+                    view_dimension,
+                    multisampled: false,
+                },
+                count: None,
+            };
+        let sampler_entry = |binding, visibility| BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: BindingType::Sampler(SamplerBindingType::Filtering),
+            count: None,
+        };
+        let storage_texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::StorageTexture {
+                access: StorageTextureAccess::WriteOnly,
+                format: FROXEL_FORMAT,
+                view_dimension: TextureViewDimension::D3,
+            },
+            count: None,
+        };
+        let froxel_sample_type = TextureSampleType::Float { filterable: true };
+
+        let pipelines = if Self::is_froxel_supported(&gfx_ctx) {
+            let shader = device.create_shader_module(ShaderModuleDescriptor {
+                label: Some("volumetric fog shader"),
+                source: ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                    "./built_in_shaders/volumetric_fog.wgsl"
+                ))),
+            });
+            let scatter_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("volumetric fog scatter bind group layout"),
+                entries: &[
+                    uniform_entry(ShaderStages::COMPUTE),
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    texture_entry(
+                        2,
+                        ShaderStages::COMPUTE,
+                        froxel_sample_type,
+                        TextureViewDimension::D3,
+                    ),
+                    sampler_entry(3, ShaderStages::COMPUTE),
+                    storage_texture_entry(4),
+                ],
+            });
+            let integrate_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("volumetric fog integrate bind group layout"),
+                entries: &[
+                    uniform_entry(ShaderStages::COMPUTE),
+                    texture_entry(
+                        5,
+                        ShaderStages::COMPUTE,
+                        froxel_sample_type,
+                        TextureViewDimension::D3,
+                    ),
+                    storage_texture_entry(6),
+                ],
+            });
+            let create_pipeline = |label, layout: &BindGroupLayout, entry_point| {
+                let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: Some(label),
+                    bind_group_layouts: &[layout],
+                    push_constant_ranges: &[],
+                });
+                device.create_compute_pipeline(&ComputePipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+            };
+            let scatter_pipeline = create_pipeline(
+                "volumetric fog scatter pipeline",
+                &scatter_layout,
+                "cs_scatter",
+            );
+            let integrate_pipeline = create_pipeline(
+                "volumetric fog integrate pipeline",
+                &integrate_layout,
+                "cs_integrate",
+            );
+
+            Some(FroxelPipelines {
+                scatter_layout,
+                integrate_layout,
+                scatter_pipeline,
+                integrate_pipeline,
+            })
+        } else {
+            None
+        };
+
+        let depth_entry = texture_entry(
+            3,
+            ShaderStages::FRAGMENT,
+            TextureSampleType::Depth,
+            TextureViewDimension::D2,
+        );
+        let apply_layout = if pipelines.is_some() {
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("volumetric fog apply bind group layout"),
+                entries: &[
+                    uniform_entry(ShaderStages::FRAGMENT),
+                    texture_entry(
+                        1,
+                        ShaderStages::FRAGMENT,
+                        froxel_sample_type,
+                        TextureViewDimension::D3,
+                    ),
+                    sampler_entry(2, ShaderStages::FRAGMENT),
+                    depth_entry,
+                ],
+            })
+        } else {
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("volumetric fog apply bind group layout"),
+                entries: &[uniform_entry(ShaderStages::FRAGMENT), depth_entry],
+            })
+        };
+        let apply_shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("volumetric fog apply shader"),
+            source: ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "./built_in_shaders/volumetric_fog_apply.wgsl"
+            ))),
+        });
+        let apply_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("volumetric fog apply pipeline layout"),
+            bind_group_layouts: &[&apply_layout],
+            push_constant_ranges: &[],
+        });
+        let apply_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("volumetric fog apply pipeline"),
+            layout: Some(&apply_pipeline_layout),
+            vertex: VertexState {
+                module: &apply_shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &apply_shader,
+                entry_point: if pipelines.is_some() {
+                    "fs_main"
+                } else {
+                    "fs_analytic"
+                },
+                targets: &[Some(ColorTargetState {
+                    format: color_format,
+                    // The shader outputs the scattered light and the transmittance: dst * transmittance + light.
+                    blend: Some(BlendState {
+                        color: BlendComponent {
+                            src_factor: BlendFactor::One,
+                            dst_factor: BlendFactor::SrcAlpha,
+                            operation: BlendOperation::Add,
+                        },
+                        alpha: BlendComponent {
+                            src_factor: BlendFactor::Zero,
+                            dst_factor: BlendFactor::One,
+                            operation: BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("volumetric fog sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("volumetric fog uniform"),
+            size: size_of::<FogUniform>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let volume_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("volumetric fog volumes"),
+            size: (size_of::<FogVolumeUniform>() * MAX_FOG_VOLUMES) as BufferAddress,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let preset = QualityPreset::default();
+        let timer = GpuTimer::with_label(&gfx_ctx, "volumetric fog timer");
+
+        Self {
+            gfx_ctx,
+            settings,
+            pipelines,
+            apply_layout,
+            apply_pipeline,
+            color_format,
+            sampler,
+            uniform_buffer,
+            volume_buffer,
+            grid: None,
+            grid_size: preset.fog_grid,
+            samples: preset.fog_samples,
+            temporal_blend: preset.fog_temporal_blend,
+            history: None,
+            frame: 0,
+            timer,
+            timed_slot: None,
+        }
+    }
+
+    pub fn color_format(&self) -> TextureFormat {
+        self.color_format
+    }
+
+    pub fn settings(&self) -> &VolumetricFogSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: VolumetricFogSettings) {
+        self.settings = settings;
+    }
+
+    /// Froxels across the width, the height and the depth of the view, or `None` under the analytic fallback.
+    pub fn grid_size(&self) -> Option<[u32; 3]> {
+        self.pipelines.as_ref().map(|_| self.grid_size)
+    }
+
+    /// Takes the grid, the samples and the temporal blend from the preset. A new grid drops the history.
+    pub fn apply_quality(&mut self, preset: &QualityPreset) {
+        let grid_size = preset.fog_grid.map(|size| size.max(1));
+
+        if grid_size != self.grid_size {
+            self.grid_size = grid_size;
+            self.grid = None;
+            self.history = None;
+        }
+
+        self.samples = preset.fog_samples.max(1);
+        self.temporal_blend = preset.fog_temporal_blend.clamp(0.0, 0.99);
+    }
+
+    /// Computes the fog seen from `view` and composites it over `target`, whose depth is in `depth_view`.
+    /// Only the [`MAX_FOG_VOLUMES`] volumes nearest to the camera are sampled.
+    pub fn encode(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        uploader: &mut Uploader,
+        target: &TextureView,
+        depth_view: &TextureView,
+        view: &FogView,
+        volumes: &[(Mat4, FogVolume)],
+        color_space: ColorSpaceMode,
+    ) {
+        let near = view.near.max(0.01);
+        let far = self.settings.far.max(near * 2.0);
+
+        let mut nearest_volumes = Vec::from_iter(volumes.iter().map(|(matrix, volume)| {
+            (
+                Vec3::distance(Vec3::from(matrix.row(3)), view.position),
+                FogVolumeUniform {
+                    world_to_local: matrix.inversed().elements,
+                    params: [volume.density, volume.edge_falloff, 0.0, 0.0],
+                },
+            )
+        }));
+        nearest_volumes.sort_unstable_by(|(lhs, _), (rhs, _)| lhs.total_cmp(rhs));
+        nearest_volumes.truncate(MAX_FOG_VOLUMES);
+
+        for (index, (_, volume)) in nearest_volumes.iter().enumerate() {
+            uploader.write(
+                &self.volume_buffer,
+                (size_of::<FogVolumeUniform>() * index) as BufferAddress,
+                volume.as_bytes(),
+            );
+        }
+
+        // Without a history, the previous frame is this one and its weight is zero.
+        let (previous_view_projection, previous_camera_position, history_weight) =
+            match &self.history {
+                Some(history) => (
+                    history.view_projection.clone(),
+                    history.camera_position,
+                    self.temporal_blend,
+                ),
+                None => (view.view_projection.clone(), view.position, 0.0),
+            };
+        let settings = &self.settings;
+        let sun_direction = settings.sun_direction.normalized();
+        let sun_color = color_space.shader_color(settings.sun_color);
+        let ambient = color_space.shader_color(settings.ambient);
+        let albedo = color_space.shader_color(settings.albedo);
+        let uniform = FogUniform {
+            inverse_view_projection: view.view_projection.inversed().elements,
+            previous_view_projection: previous_view_projection.elements,
+            camera_position: [view.position.x, view.position.y, view.position.z, 0.0],
+            previous_camera_position: [
+                previous_camera_position.x,
+                previous_camera_position.y,
+                previous_camera_position.z,
+                0.0,
+            ],
+            sun_direction: [
+                sun_direction.x,
+                sun_direction.y,
+                sun_direction.z,
+                settings.anisotropy.clamp(-0.99, 0.99),
+            ],
+            sun_color: [
+                sun_color.r,
+                sun_color.g,
+                sun_color.b,
+                settings.sun_intensity,
+            ],
+            ambient: [ambient.r, ambient.g, ambient.b, 0.0],
+            albedo: [albedo.r, albedo.g, albedo.b, settings.density.max(0.0)],
+            grid: [
+                self.grid_size[0],
+                self.grid_size[1],
+                self.grid_size[2],
+                nearest_volumes.len() as u32,
+            ],
+            depth: [near, far, halton(self.frame % 16 + 1, 2), history_weight],
+            samples: [self.samples, 0, 0, 0],
+        };
+        uploader.write(&self.uniform_buffer, 0, uniform.as_bytes());
+
+        self.timed_slot = self.timer.as_mut().and_then(|timer| timer.begin(encoder));
+
+        let integrated = self.encode_froxels(encoder);
+        self.encode_apply(encoder, target, depth_view, integrated);
+
+        if let (Some(timer), Some(slot)) = (&mut self.timer, self.timed_slot) {
+            timer.end(encoder, slot);
+        }
+
+        self.history = Some(FogHistory {
+            view_projection: view.view_projection.clone(),
+            camera_position: view.position,
+        });
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    /// Scatters and integrates the froxels of the frame. Returns `false` under the analytic fallback.
+    fn encode_froxels(&mut self, encoder: &mut CommandEncoder) -> bool {
+        let pipelines = match &self.pipelines {
+            Some(pipelines) => pipelines,
+            None => return false,
+        };
+        let grid_size = self.grid_size;
+        let grid = self
+            .grid
+            .get_or_insert_with(|| create_grid(&self.gfx_ctx, grid_size));
+        let device = &self.gfx_ctx.device;
+        let current = (self.frame % 2) as usize;
+
+        let scatter_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("volumetric fog scatter bind group"),
+            layout: &pipelines.scatter_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: self.volume_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&grid.scattering[1 - current]),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(&grid.scattering[current]),
+                },
+            ],
+        });
+        let integrate_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("volumetric fog integrate bind group"),
+            layout: &pipelines.integrate_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: BindingResource::TextureView(&grid.scattering[current]),
+                },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::TextureView(&grid.integrated),
+                },
+            ],
+        });
+
+        let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("volumetric fog"),
+        });
+        compute_pass.set_pipeline(&pipelines.scatter_pipeline);
+        compute_pass.set_bind_group(0, &scatter_bind_group, &[]);
+        compute_pass.dispatch_workgroups(
+            (grid.size[0] + 3) / 4,
+            (grid.size[1] + 3) / 4,
+            (grid.size[2] + 3) / 4,
+        );
+        compute_pass.set_pipeline(&pipelines.integrate_pipeline);
+        compute_pass.set_bind_group(0, &integrate_bind_group, &[]);
+        compute_pass.dispatch_workgroups((grid.size[0] + 7) / 8, (grid.size[1] + 7) / 8, 1);
+
+        true
+    }
+
+    fn encode_apply(
+        &self,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        depth_view: &TextureView,
+        integrated: bool,
+    ) {
+        let device = &self.gfx_ctx.device;
+        let uniform_entry = BindGroupEntry {
+            binding: 0,
+            resource: self.uniform_buffer.as_entire_binding(),
+        };
+        let depth_entry = BindGroupEntry {
+            binding: 3,
+            resource: BindingResource::TextureView(depth_view),
+        };
+        let bind_group = match (&self.grid, integrated) {
+            (Some(grid), true) => device.create_bind_group(&BindGroupDescriptor {
+                label: Some("volumetric fog apply bind group"),
+                layout: &self.apply_layout,
+                entries: &[
+                    uniform_entry,
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&grid.integrated),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Sampler(&self.sampler),
+                    },
+                    depth_entry,
+                ],
+            }),
+            _ => device.create_bind_group(&BindGroupDescriptor {
+                label: Some("volumetric fog apply bind group"),
+                layout: &self.apply_layout,
+                entries: &[uniform_entry, depth_entry],
+            }),
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("volumetric fog apply"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.apply_pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Starts reading back the GPU time of the fog. Must be called after the frame has been submitted.
+    pub fn read_back_timing(&mut self) {
+        if let (Some(timer), Some(slot)) = (&mut self.timer, self.timed_slot.take()) {
+            timer.read_back(slot);
+        }
+    }
+
+    /// Collects the GPU times read back since the last call.
+    pub fn collect_timing(&mut self) {
+        if let Some(timer) = &mut self.timer {
+            timer.collect();
+        }
+    }
+
+    /// GPU time of the fog of a recent frame, in milliseconds. `None` without timestamp queries.
+    pub fn gpu_ms(&self) -> Option<f32> {
+        self.timer.as_ref().and_then(GpuTimer::last_ms)
+    }
+}
+
+fn create_grid(gfx_ctx: &GfxContextHandle, size: [u32; 3]) -> FroxelGrid {
+    let create_view = |label| {
+        gfx_ctx
+            .device
+            .create_texture(&TextureDescriptor {
+                label: Some(label),
+                size: Extent3d {
+                    width: size[0],
+                    height: size[1],
+                    depth_or_array_layers: size[2],
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D3,
+                format: FROXEL_FORMAT,
+                usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&Default::default())
+    };
+
+    FroxelGrid {
+        size,
+        scattering: [
+            create_view("volumetric fog scattering"),
+            create_view("volumetric fog scattering"),
+        ],
+        integrated: create_view("volumetric fog integrated"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_slices_span_the_fog() {
+        let (near, far) = (0.5, 200.0);
+
+        assert!((froxel_slice_depth(0.0, 64, near, far) - near).abs() < 1e-4);
+        assert!((froxel_slice_depth(64.0, 64, near, far) - far).abs() < 1e-2);
+
+        for slice in 0..=64 {
+            let depth = froxel_slice_depth(slice as f32, 64, near, far);
+            assert!((froxel_depth_slice(depth, 64, near, far) - slice as f32).abs() < 1e-3);
+        }
+
+        // Slices deepen with the distance.
+        let thickness = |slice: f32| {
+            froxel_slice_depth(slice + 1.0, 64, near, far)
+                - froxel_slice_depth(slice, 64, near, far)
+        };
+        assert!(thickness(0.0) < thickness(32.0));
+        assert!(thickness(32.0) < thickness(63.0));
+    }
+
+    #[test]
+    fn check_phase_function_is_normalized() {
+        for anisotropy in [-0.8, 0.0, 0.3, 0.8] {
+            // Integrates over the sphere; the phase function only depends on the polar angle.
+            let steps = 4096;
+            let integral = (0..steps)
+                .map(|step| {
+                    let theta = (step as f32 + 0.5) / steps as f32 * PI;
+                    henyey_greenstein(theta.cos(), anisotropy)
+                        * 2.0
+                        * PI
+                        * theta.sin()
+                        * (PI / steps as f32)
+                })
+                .sum::<f32>();
+            assert!(
+                (integral - 1.0).abs() < 1e-2,
+                "{}: {}",
+                anisotropy,
+                integral
+            );
+        }
+
+        assert!((henyey_greenstein(0.3, 0.0) - 1.0 / (4.0 * PI)).abs() < 1e-6);
+        assert!(henyey_greenstein(-1.0, 0.6) < henyey_greenstein(1.0, 0.6));
+    }
+
+    #[test]
+    fn check_uniform_fog_matches_analytic_fog() {
+        let (near, far, density) = (0.5, 100.0, 0.05);
+        let light = [0.2, 0.5, 1.0];
+        let froxels = vec![
+            [
+                light[0] * density,
+                light[1] * density,
+                light[2] * density,
+                density,
+            ];
+            48
+        ];
+        let integrated = integrate_froxels(&froxels, near, far);
+        let last = integrated.last().unwrap();
+        let transmittance = analytic_fog_transmittance(density, far - near);
+
+        assert!((last[3] - transmittance).abs() < 1e-4);
+
+        // Without emission, a uniform medium converges to its light as it becomes opaque.
+        for channel in 0..3 {
+            let expected = light[channel] * (1.0 - transmittance);
+            assert!((last[channel] - expected).abs() < 1e-4);
+        }
+
+        // Transmittance only decreases outwards.
+        assert!(integrated.windows(2).all(|pair| pair[1][3] <= pair[0][3]));
+    }
+
+    #[test]
+    fn check_dense_froxels_do_not_gain_energy() {
+        let froxels = vec![[1000.0, 1000.0, 1000.0, 1000.0]; 8];
+        let integrated = integrate_froxels(&froxels, 0.5, 100.0);
+
+        for froxel in integrated {
+            assert!(froxel[0] <= 1.0 + 1e-4);
+            assert!(froxel[3] >= 0.0);
+        }
+    }
+
+    #[test]
+    fn check_empty_froxels_let_everything_through() {
+        let integrated = integrate_froxels(&[[0.0; 4]; 16], 0.5, 100.0);
+        assert!(integrated
+            .iter()
+            .all(|froxel| *froxel == [0.0, 0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn check_volume_density_fades_at_the_edges() {
+        let volume = FogVolume::new(2.0, 0.5);
+
+        assert_eq!(volume.density_at(Vec3::ZERO), 2.0);
+        assert_eq!(volume.density_at(Vec3::new(0.6, 0.0, 0.0)), 0.0);
+        assert!((volume.density_at(Vec3::new(0.0, 0.375, 0.0)) - 1.0).abs() < 1e-5);
+        assert_eq!(
+            FogVolume::new(2.0, 0.0).density_at(Vec3::new(0.5, 0.5, 0.5)),
+            2.0
+        );
+    }
+
+    #[test]
+    fn check_halton_sequence() {
+        assert_eq!(halton(1, 2), 0.5);
+        assert_eq!(halton(2, 2), 0.25);
+        assert_eq!(halton(3, 2), 0.75);
+        assert!((halton(1, 3) - 1.0 / 3.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,433 @@
+use crate::{
+    math::{Quat, Vec2, Vec3},
+    object::ObjectId,
+    transform::Transform,
+};
+use specs::{prelude::*, Component};
+use std::f32::consts::PI;
+
+/// Maximum number of waves summed by a [`WaterSurface`](super::WaterSurface).
+pub const WATER_MAX_WAVES: usize = 8;
+
+/// Iterations spent looking for the rest position displaced onto a point. Each one divides the error by
+/// at least `1 / steepness`, so a handful is plenty below the sharpest crests.
+const WAVE_INVERSION_ITERATIONS: usize = 8;
+
+/// A trochoidal wave. Points of the surface move in circles, gathering at the crests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GerstnerWave {
+    /// Direction of travel on the xz plane. Normalized when evaluated.
+    pub direction: Vec2,
+    /// Height of the crests above the rest plane.
+    pub amplitude: f32,
+    /// Distance between two crests.
+    pub wavelength: f32,
+    /// Distance the crests travel per second.
+    pub speed: f32,
+    /// Sharpness of the crests, from 0 for a sine wave to 1 for crests pinched to a point.
+    /// It is shared between the waves of a surface, so that their sum never loops over itself.
+    pub steepness: f32,
+}
+
+impl GerstnerWave {
+    pub fn new(
+        direction: Vec2,
+        amplitude: f32,
+        wavelength: f32,
+        speed: f32,
+        steepness: f32,
+    ) -> Self {
+        Self {
+            direction,
+            amplitude,
+            wavelength,
+            speed,
+            steepness,
+        }
+    }
+
+    /// Normalized direction, wave number and steepness of this wave among `count` waves,
+    /// or `None` if the wave is flat.
+    fn terms(&self, count: usize) -> Option<(Vec2, f32, f32)> {
+        if self.wavelength <= 0.0 || self.direction.len_square() == 0.0 {
+            return None;
+        }
+
+        let k = 2.0 * PI / self.wavelength;
+        let q = if k * self.amplitude == 0.0 {
+            0.0
+        } else {
+            self.steepness.clamp(0.0, 1.0) / (k * self.amplitude * count as f32)
+        };
+
+        Some((self.direction.normalized(), k, q))
+    }
+}
+
+/// The water surface above a point of its rest plane.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaveSample {
+    /// Offset of the point from its rest position.
+    pub displacement: Vec3,
+    pub normal: Vec3,
+    /// Area of the surface around the point relative to the rest plane.
+    /// Crests pinch it below 1; foam forms where it falls under the foam threshold.
+    pub jacobian: f32,
+}
+
+/// Sums the waves at the position of the rest plane. Only the first [`WATER_MAX_WAVES`] waves are summed.
+///
+/// This is the function `built_in_shaders/water_waves.wgsl` evaluates on the GPU; both must be changed together.
+pub fn evaluate_waves(waves: &[GerstnerWave], position: Vec2, time: f32) -> WaveSample {
+    let count = waves.len().min(WATER_MAX_WAVES);
+    let mut displacement = Vec3::ZERO;
+    // Derivatives of the displaced point along x and z.
+    let mut tangent_x = Vec3::new(1.0, 0.0, 0.0);
+    let mut tangent_z = Vec3::new(0.0, 0.0, 1.0);
+
+    for wave in &waves[..count] {
+        let (direction, k, q) = if let Some(terms) = wave.terms(count) {
+            terms
+        } else {
+            continue;
+        };
+
+        let theta = k * (Vec2::dot(direction, position) - wave.speed * time);
+        let (sin, cos) = theta.sin_cos();
+        let qa = q * wave.amplitude;
+        let qak = qa * k;
+        let ak = wave.amplitude * k;
+        let (dx, dz) = (direction.x, direction.y);
+
+        displacement += Vec3::new(qa * dx * cos, wave.amplitude * sin, qa * dz * cos);
+        tangent_x += Vec3::new(-qak * dx * dx * sin, ak * dx * cos, -qak * dx * dz * sin);
+        tangent_z += Vec3::new(-qak * dx * dz * sin, ak * dz * cos, -qak * dz * dz * sin);
+    }
+
+    WaveSample {
+        displacement,
+        normal: Vec3::cross(tangent_z, tangent_x).normalized(),
+        jacobian: tangent_x.x * tangent_z.z - tangent_x.z * tangent_z.x,
+    }
+}
+
+/// Packs the waves into the `waves` array of the `Water` uniform, two vectors per wave:
+/// `[direction.x, direction.y, amplitude, wavelength]` and `[speed, steepness, 0, 0]`.
+pub fn pack_waves(waves: &[GerstnerWave]) -> [[f32; 4]; WATER_MAX_WAVES * 2] {
+    let mut packed = [[0.0; 4]; WATER_MAX_WAVES * 2];
+
+    for (index, wave) in waves.iter().take(WATER_MAX_WAVES).enumerate() {
+        packed[index * 2] = [
+            wave.direction.x,
+            wave.direction.y,
+            wave.amplitude,
+            wave.wavelength,
+        ];
+        packed[index * 2 + 1] = [wave.speed, wave.steepness, 0.0, 0.0];
+    }
+
+    packed
+}
+
+/// Samples the surface above the point of the xz plane, i.e. at the rest position the waves displace onto it.
+pub fn sample_waves_at(waves: &[GerstnerWave], position: Vec2, time: f32) -> WaveSample {
+    let mut rest = position;
+
+    for _ in 0..WAVE_INVERSION_ITERATIONS {
+        let displacement = evaluate_waves(waves, rest, time).displacement;
+        rest = Vec2::new(position.x - displacement.x, position.y - displacement.z);
+    }
+
+    evaluate_waves(waves, rest, time)
+}
+
+/// Floats the object on the [`WaterSurface`](super::WaterSurface) of another object, pulling it towards the surface
+/// with a damped spring and tilting it along the normal.
+///
+/// There is no rigid body to push; the spring moves the [`Transform`] directly, which is therefore expected to be
+/// in world space, i.e. the object has no parent.
+#[derive(Debug, Clone, Component)]
+#[storage(HashMapStorage)]
+pub struct Buoyancy {
+    /// The object carrying the water surface.
+    pub water: ObjectId,
+    /// Depth of the object's origin below the surface when floating at rest.
+    pub draft: f32,
+    /// Strength of the spring, per second squared.
+    pub stiffness: f32,
+    /// Damping of the spring, per second.
+    pub damping: f32,
+    /// Rate at which the object tilts towards the normal of the surface, per second. 0 keeps the rotation.
+    pub tilt_rate: f32,
+    vertical_velocity: f32,
+}
+
+impl Buoyancy {
+    pub fn new(water: ObjectId) -> Self {
+        Self {
+            water,
+            draft: 0.0,
+            stiffness: 20.0,
+            damping: 4.0,
+            tilt_rate: 4.0,
+            vertical_velocity: 0.0,
+        }
+    }
+
+    pub fn vertical_velocity(&self) -> f32 {
+        self.vertical_velocity
+    }
+
+    /// Advances the spring by `delta_time` towards the surface at `surface_height` in world space.
+    pub fn step(
+        &mut self,
+        transform: &mut Transform,
+        surface_height: f32,
+        surface_normal: Vec3,
+        delta_time: f32,
+    ) {
+        let offset = surface_height - self.draft - transform.position.y;
+        let acceleration = self.stiffness * offset - self.damping * self.vertical_velocity;
+        self.vertical_velocity += acceleration * delta_time;
+        transform.position.y += self.vertical_velocity * delta_time;
+
+        if 0.0 < self.tilt_rate {
+            let up = transform.rotation * Vec3::UP;
+            let tilt = Quat::from_rotation_arc(up, surface_normal);
+            let t = 1.0 - (-self.tilt_rate * delta_time).exp();
+            transform.rotation =
+                (Quat::slerp(Quat::IDENTITY, tilt, t) * transform.rotation).normalized();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::{create_test_device, HeadlessDevice};
+    use std::sync::mpsc::channel;
+    use wgpu::{
+        util::{BufferInitDescriptor, DeviceExt},
+        BindGroupDescriptor, BindGroupEntry, BufferDescriptor, BufferUsages,
+        CommandEncoderDescriptor, ComputePassDescriptor, ComputePipelineDescriptor, Maintain,
+        MapMode, ShaderModuleDescriptor, ShaderSource,
+    };
+    use zerocopy::AsBytes;
+
+    /// Evaluates the waves of `water_waves.wgsl` at each point, as `[displacement, jacobian]` and `[normal, 0]`.
+    const WAVE_EVALUATION_SHADER: &str = r#"
+@group(0) @binding(0) var<uniform> water: Water;
+@group(0) @binding(1) var<storage, read> points: array<vec2<f32>>;
+@group(0) @binding(2) var<storage, read_write> samples: array<vec4<f32>>;
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+  if (arrayLength(&points) <= id.x) {
+    return;
+  }
+
+  let result = evaluate_waves(points[id.x]);
+  samples[id.x * 2u] = vec4<f32>(result.displacement, result.jacobian);
+  samples[id.x * 2u + 1u] = vec4<f32>(result.normal, 0.0);
+}
+"#;
+
+    /// Evaluates the waves at the points on the GPU, returning `[displacement, jacobian]` and `[normal, 0]` per point.
+    fn evaluate_waves_on_gpu(
+        device: &HeadlessDevice,
+        waves: &[GerstnerWave],
+        points: &[[f32; 2]],
+        time: f32,
+    ) -> Vec<[f32; 4]> {
+        let HeadlessDevice { device, queue } = device;
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: None,
+            source: ShaderSource::Wgsl(
+                (include_str!("./built_in_shaders/water_waves.wgsl").to_owned()
+                    + WAVE_EVALUATION_SHADER)
+                    .into(),
+            ),
+        });
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: None,
+            layout: None,
+            module: &module,
+            entry_point: "cs_main",
+        });
+
+        let mut uniform = vec![[waves.len() as f32, time, 0.0, 0.0], [0.0; 4], [0.0; 4]];
+        uniform.extend(pack_waves(waves));
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: uniform.as_bytes(),
+            usage: BufferUsages::UNIFORM,
+        });
+        let point_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: points.as_bytes(),
+            usage: BufferUsages::STORAGE,
+        });
+        let size = (points.len() * 2 * std::mem::size_of::<[f32; 4]>()) as u64;
+        let sample_buffer = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let read_back_buffer = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: point_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: sample_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups((points.len() as u32 + 63) / 64, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&sample_buffer, 0, &read_back_buffer, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = channel();
+        read_back_buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        device.poll(Maintain::Wait);
+        receiver.recv().unwrap().unwrap();
+
+        let data = read_back_buffer.slice(..).get_mapped_range();
+        Vec::from_iter(data.chunks_exact(16).map(|texel| {
+            let mut sample = [0.0; 4];
+            for (value, bytes) in sample.iter_mut().zip(texel.chunks_exact(4)) {
+                *value = f32::from_le_bytes(bytes.try_into().unwrap());
+            }
+            sample
+        }))
+    }
+
+    fn create_waves() -> Vec<GerstnerWave> {
+        vec![
+            GerstnerWave::new(Vec2::new(1.0, 0.0), 0.5, 12.0, 3.0, 0.7),
+            GerstnerWave::new(Vec2::new(0.6, 0.8), 0.3, 7.0, 2.0, 0.7),
+            GerstnerWave::new(Vec2::new(-0.3, 1.0), 0.1, 3.0, 1.5, 0.7),
+        ]
+    }
+
+    #[test]
+    fn check_flat_water() {
+        let sample = evaluate_waves(&[], Vec2::new(3.0, -2.0), 10.0);
+        assert_eq!(sample.displacement, Vec3::ZERO);
+        assert_eq!(sample.normal, Vec3::UP);
+        assert_eq!(sample.jacobian, 1.0);
+    }
+
+    #[test]
+    fn check_normal_matches_finite_differences() {
+        let waves = create_waves();
+        let position = Vec2::new(4.2, -1.3);
+        let time = 2.5;
+        let epsilon = 1e-3;
+
+        let displaced = |x: f32, z: f32| {
+            let displacement = evaluate_waves(&waves, Vec2::new(x, z), time).displacement;
+            Vec3::new(x, 0.0, z) + displacement
+        };
+        let tangent_x = (displaced(position.x + epsilon, position.y)
+            - displaced(position.x - epsilon, position.y))
+            / (2.0 * epsilon);
+        let tangent_z = (displaced(position.x, position.y + epsilon)
+            - displaced(position.x, position.y - epsilon))
+            / (2.0 * epsilon);
+        let expected = Vec3::cross(tangent_z, tangent_x).normalized();
+
+        let normal = evaluate_waves(&waves, position, time).normal;
+        assert!(Vec3::distance(normal, expected) < 1e-2);
+    }
+
+    #[test]
+    fn check_sample_lands_on_the_point() {
+        let waves = create_waves();
+        let position = Vec2::new(-7.5, 3.0);
+        let sample = sample_waves_at(&waves, position, 1.25);
+
+        // The rest position the sample was taken at is displaced onto the point.
+        let rest = Vec2::new(
+            position.x - sample.displacement.x,
+            position.y - sample.displacement.z,
+        );
+        let displacement = evaluate_waves(&waves, rest, 1.25).displacement;
+        assert!((rest.x + displacement.x - position.x).abs() < 1e-4);
+        assert!((rest.y + displacement.z - position.y).abs() < 1e-4);
+    }
+
+    #[test]
+    fn check_buoyancy_settles_on_the_surface() {
+        let mut buoyancy = Buoyancy::new(ObjectId::from_u32(0));
+        buoyancy.draft = 0.5;
+        let mut transform = Transform::new();
+        transform.position.y = 3.0;
+
+        for _ in 0..600 {
+            buoyancy.step(&mut transform, 1.0, Vec3::UP, 1.0 / 60.0);
+        }
+
+        assert!((transform.position.y - 0.5).abs() < 1e-2);
+        assert!(buoyancy.vertical_velocity().abs() < 1e-2);
+    }
+
+    #[test]
+    fn check_gpu_waves_match_cpu_waves() {
+        let device = if let Some(device) = create_test_device() {
+            device
+        } else {
+            return;
+        };
+
+        let waves = create_waves();
+        let time = 7.75;
+        let points = Vec::from_iter((0..256).map(|index| {
+            let index = index as f32;
+            [(index * 0.37).sin() * 40.0, (index * 0.61).cos() * 40.0]
+        }));
+        let samples = evaluate_waves_on_gpu(&device, &waves, &points, time);
+
+        for (point, gpu) in points.iter().zip(samples.chunks_exact(2)) {
+            let cpu = evaluate_waves(&waves, Vec2::new(point[0], point[1]), time);
+            let displacement = Vec3::new(gpu[0][0], gpu[0][1], gpu[0][2]);
+            let normal = Vec3::new(gpu[1][0], gpu[1][1], gpu[1][2]);
+
+            assert!(
+                Vec3::distance(displacement, cpu.displacement) < 1e-3,
+                "displacement at {:?}: {} on the GPU, {} on the CPU",
+                point,
+                displacement,
+                cpu.displacement
+            );
+            assert!(Vec3::distance(normal, cpu.normal) < 1e-3);
+            assert!((gpu[0][3] - cpu.jacobian).abs() < 1e-3);
+        }
+    }
+}
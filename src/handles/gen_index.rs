@@ -0,0 +1,140 @@
+/// An index into a slot that is reused after removal, paired with the generation of the slot.
+/// The generation changes whenever the slot is freed, so an index outliving its target never matches the next occupant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GenIndex {
+    index: u32,
+    generation: u32,
+}
+
+impl GenIndex {
+    pub fn new(index: u32, generation: u32) -> Self {
+        Self { index, generation }
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GenSlot {
+    generation: u32,
+    is_alive: bool,
+}
+
+/// Allocates [`GenIndex`]es, reusing freed slots.
+#[derive(Debug, Default, Clone)]
+pub struct GenIndexAllocator {
+    slots: Vec<GenSlot>,
+    free_indices: Vec<u32>,
+    alive_count: usize,
+}
+
+impl GenIndexAllocator {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Number of live indices.
+    pub fn len(&self) -> usize {
+        self.alive_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.alive_count == 0
+    }
+
+    /// Number of slots ever allocated, live or not.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn alloc(&mut self) -> GenIndex {
+        self.alive_count += 1;
+
+        if let Some(index) = self.free_indices.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.is_alive = true;
+            return GenIndex::new(index, slot.generation);
+        }
+
+        let index = u32::try_from(self.slots.len()).expect("ran out of generational indices");
+        self.slots.push(GenSlot {
+            generation: 0,
+            is_alive: true,
+        });
+        GenIndex::new(index, 0)
+    }
+
+    /// Frees the index. Returns `false` if it was stale already.
+    pub fn free(&mut self, index: GenIndex) -> bool {
+        if !self.is_alive(index) {
+            return false;
+        }
+
+        let slot = &mut self.slots[index.index as usize];
+        slot.is_alive = false;
+        self.alive_count -= 1;
+
+        // A slot whose generations are used up is retired rather than wrapped around,
+        // since a wrapped generation would make the oldest stale indices valid again.
+        if let Some(generation) = slot.generation.checked_add(1) {
+            slot.generation = generation;
+            self.free_indices.push(index.index);
+        }
+
+        true
+    }
+
+    pub fn is_alive(&self, index: GenIndex) -> bool {
+        self.slots.get(index.index as usize).map_or(false, |slot| {
+            slot.is_alive && slot.generation == index.generation
+        })
+    }
+
+    /// Returns the live index occupying the slot, if any.
+    pub fn alive_at(&self, index: u32) -> Option<GenIndex> {
+        self.slots
+            .get(index as usize)
+            .filter(|slot| slot.is_alive)
+            .map(|slot| GenIndex::new(index, slot.generation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_freed_slots_are_reused_with_a_new_generation() {
+        let mut allocator = GenIndexAllocator::new();
+        let first = allocator.alloc();
+
+        assert!(allocator.free(first));
+        assert!(!allocator.free(first));
+
+        let second = allocator.alloc();
+        assert_eq!(second.index(), first.index());
+        assert_ne!(second, first);
+        assert!(!allocator.is_alive(first));
+        assert!(allocator.is_alive(second));
+        assert_eq!(allocator.capacity(), 1);
+    }
+
+    #[test]
+    fn check_slots_out_of_generations_are_retired() {
+        let mut allocator = GenIndexAllocator::new();
+        allocator.alloc();
+        allocator.slots[0].generation = u32::MAX;
+        let last = GenIndex::new(0, u32::MAX);
+
+        assert!(allocator.free(last));
+        assert_eq!(allocator.alloc().index(), 1);
+        assert!(!allocator.is_alive(last));
+        assert!(!allocator.is_alive(GenIndex::new(0, 0)));
+    }
+}
@@ -0,0 +1,183 @@
+use super::{GenIndex, GenIndexAllocator};
+
+/// Values addressed by [`GenIndex`]es. Slots are reused after removal,
+/// but an index of a removed value never reaches the value that took its slot.
+#[derive(Debug, Clone)]
+pub struct HandleStore<T> {
+    allocator: GenIndexAllocator,
+    values: Vec<Option<T>>,
+}
+
+impl<T> HandleStore<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.allocator.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allocator.is_empty()
+    }
+
+    pub fn insert(&mut self, value: T) -> GenIndex {
+        let index = self.allocator.alloc();
+        let slot = index.index() as usize;
+
+        if self.values.len() <= slot {
+            self.values.resize_with(slot + 1, || None);
+        }
+
+        self.values[slot] = Some(value);
+        index
+    }
+
+    /// Removes the value. Returns `None` if the index is stale.
+    pub fn remove(&mut self, index: GenIndex) -> Option<T> {
+        if !self.allocator.free(index) {
+            return None;
+        }
+
+        self.values[index.index() as usize].take()
+    }
+
+    pub fn is_alive(&self, index: GenIndex) -> bool {
+        self.allocator.is_alive(index)
+    }
+
+    pub fn get(&self, index: GenIndex) -> Option<&T> {
+        if !self.allocator.is_alive(index) {
+            return None;
+        }
+
+        self.values[index.index() as usize].as_ref()
+    }
+
+    pub fn get_mut(&mut self, index: GenIndex) -> Option<&mut T> {
+        if !self.allocator.is_alive(index) {
+            return None;
+        }
+
+        self.values[index.index() as usize].as_mut()
+    }
+
+    /// Iterates the live values in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (GenIndex, &T)> {
+        self.values.iter().enumerate().filter_map(|(slot, value)| {
+            let value = value.as_ref()?;
+            Some((self.allocator.alive_at(slot as u32)?, value))
+        })
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(GenIndex, &mut T) -> bool) {
+        for slot in 0..self.values.len() {
+            let index = match self.allocator.alive_at(slot as u32) {
+                Some(index) => index,
+                None => continue,
+            };
+
+            if let Some(value) = &mut self.values[slot] {
+                if !f(index, value) {
+                    self.remove(index);
+                }
+            }
+        }
+    }
+}
+
+impl<T> Default for HandleStore<T> {
+    fn default() -> Self {
+        Self {
+            allocator: GenIndexAllocator::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Small deterministic generator, so that a failing seed can be replayed.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn new(seed: u64) -> Self {
+            Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+        }
+
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+
+    #[test]
+    fn check_stale_indices_never_reach_new_occupants() {
+        for seed in 0..64 {
+            let mut rng = XorShift::new(seed);
+            let mut store = HandleStore::new();
+            // A vector rather than a map, so that the victims only depend on the seed.
+            let mut alive = Vec::new();
+            let mut stale = Vec::new();
+            let mut next_value = 0u64;
+
+            for _ in 0..2000 {
+                match rng.next(4) {
+                    0 | 1 => {
+                        let index = store.insert(next_value);
+                        assert!(
+                            !stale.contains(&index),
+                            "seed {}: {:?} reissued",
+                            seed,
+                            index
+                        );
+                        assert!(alive.iter().all(|&(alive_index, _)| alive_index != index));
+                        alive.push((index, next_value));
+                        next_value += 1;
+                    }
+                    2 if !alive.is_empty() => {
+                        let (index, value) = alive.swap_remove(rng.next(alive.len()));
+                        assert_eq!(store.remove(index), Some(value));
+                        stale.push(index);
+                    }
+                    _ if !stale.is_empty() => {
+                        let index = stale[rng.next(stale.len())];
+                        assert!(!store.is_alive(index), "seed {}: {:?} alive", seed, index);
+                        assert_eq!(store.get(index), None);
+                        assert_eq!(store.get_mut(index), None);
+                        assert_eq!(store.remove(index), None);
+                    }
+                    _ => {}
+                }
+
+                assert_eq!(store.len(), alive.len());
+            }
+
+            for (index, value) in &alive {
+                assert_eq!(store.get(*index), Some(value));
+            }
+
+            assert_eq!(store.iter().count(), alive.len());
+            assert!(store
+                .iter()
+                .all(|(index, value)| alive.contains(&(index, *value))));
+        }
+    }
+
+    #[test]
+    fn check_retain_keeps_matching_values() {
+        let mut store = HandleStore::new();
+        let indices = Vec::from_iter((0..10).map(|value| store.insert(value)));
+
+        store.retain(|_, value| *value % 2 == 0);
+
+        assert_eq!(store.len(), 5);
+        assert_eq!(store.get(indices[1]), None);
+        assert_eq!(store.get(indices[2]), Some(&2));
+        assert_ne!(store.insert(10), indices[9]);
+    }
+}
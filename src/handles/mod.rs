@@ -0,0 +1,7 @@
+mod gen_index;
+mod handle_store;
+mod stale_handle;
+
+pub use gen_index::*;
+pub use handle_store::*;
+pub use stale_handle::*;
@@ -0,0 +1,22 @@
+use thiserror::Error;
+
+/// Returned by operations on a handle whose target has been removed.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("stale {kind} handle{}", .name.as_ref().map(|name| format!(" `{}`", name)).unwrap_or_default())]
+pub struct StaleHandle {
+    /// Kind of the handle, e.g. `"object"`.
+    pub kind: &'static str,
+    /// Debug name of the target, if it had one.
+    pub name: Option<String>,
+}
+
+impl StaleHandle {
+    pub fn new(kind: &'static str) -> Self {
+        Self { kind, name: None }
+    }
+
+    pub fn with_name(mut self, name: impl Into<Option<String>>) -> Self {
+        self.name = name.into();
+        self
+    }
+}
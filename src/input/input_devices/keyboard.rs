@@ -1,11 +1,19 @@
 use crate::input::{InputDevice, RawInput, RawInputEventDispatcher};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
 
+pub enum KeyboardWindowEvent {
+    KeyboardInput(KeyboardInput),
+    FocusLost,
+}
+
 pub struct Keyboard {
     inputs: Vec<RawInput>,
     input_names: HashMap<String, usize>,
-    window_event_queue: Vec<KeyboardInput>,
+    window_event_queue: Vec<KeyboardWindowEvent>,
+    keys_down: HashSet<VirtualKeyCode>,
+    keys_pressed: HashSet<VirtualKeyCode>,
+    keys_released: HashSet<VirtualKeyCode>,
 }
 
 impl Keyboard {
@@ -140,11 +148,52 @@ impl Keyboard {
             inputs,
             input_names,
             window_event_queue: Vec::new(),
+            keys_down: HashSet::new(),
+            keys_pressed: HashSet::new(),
+            keys_released: HashSet::new(),
         }
     }
 
     pub fn handle_window_event(&mut self, event: KeyboardInput) {
-        self.window_event_queue.push(event);
+        self.window_event_queue
+            .push(KeyboardWindowEvent::KeyboardInput(event));
+    }
+
+    /// Releases all held keys on the next poll, since the window does not receive their release events while unfocused.
+    pub fn handle_focus_lost(&mut self) {
+        self.window_event_queue.push(KeyboardWindowEvent::FocusLost);
+    }
+
+    /// Returns `true` while the key is held.
+    pub fn is_key_down(&self, key: VirtualKeyCode) -> bool {
+        self.keys_down.contains(&key)
+    }
+
+    /// Returns `true` if the key went down this frame. Repeats of a held key do not count.
+    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    /// Returns `true` if the key went up this frame, including keys released by losing the focus.
+    pub fn is_key_released(&self, key: VirtualKeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    /// Forgets the keys pressed and released this frame.
+    pub fn end_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+    }
+
+    fn release_all(&mut self, dispatcher: &mut RawInputEventDispatcher) {
+        self.keys_released.extend(self.keys_down.drain());
+
+        for input in &mut self.inputs {
+            if input.value != 0.0 {
+                input.value = 0.0;
+                dispatcher.dispatch(input);
+            }
+        }
     }
 }
 
@@ -162,11 +211,37 @@ impl InputDevice for Keyboard {
     }
 
     fn poll(&mut self, dispatcher: &mut RawInputEventDispatcher) {
-        for event in self.window_event_queue.drain(..) {
-            let name = if let Some(name) = event
-                .virtual_keycode
-                .and_then(virtual_keycode_into_raw_input_name)
-            {
+        let events = std::mem::take(&mut self.window_event_queue);
+
+        for event in events {
+            let event = match event {
+                KeyboardWindowEvent::KeyboardInput(event) => event,
+                KeyboardWindowEvent::FocusLost => {
+                    self.release_all(dispatcher);
+                    continue;
+                }
+            };
+            let keycode = if let Some(keycode) = event.virtual_keycode {
+                keycode
+            } else {
+                continue;
+            };
+
+            // The OS repeats the pressed events of a held key; only the first one presses it.
+            match event.state {
+                ElementState::Pressed => {
+                    if self.keys_down.insert(keycode) {
+                        self.keys_pressed.insert(keycode);
+                    }
+                }
+                ElementState::Released => {
+                    if self.keys_down.remove(&keycode) {
+                        self.keys_released.insert(keycode);
+                    }
+                }
+            }
+
+            let name = if let Some(name) = virtual_keycode_into_raw_input_name(keycode) {
                 name
             } else {
                 continue;
@@ -357,3 +432,183 @@ fn virtual_keycode_into_raw_input_name(virtual_keycode: VirtualKeyCode) -> Optio
         VirtualKeyCode::Cut => None,
     }
 }
+
+/// Inverse of the raw input names of the keys, e.g. for keys given in configs.
+pub(crate) fn raw_input_name_into_virtual_keycode(name: &str) -> Option<VirtualKeyCode> {
+    match name {
+        "1" => Some(VirtualKeyCode::Key1),
+        "2" => Some(VirtualKeyCode::Key2),
+        "3" => Some(VirtualKeyCode::Key3),
+        "4" => Some(VirtualKeyCode::Key4),
+        "5" => Some(VirtualKeyCode::Key5),
+        "6" => Some(VirtualKeyCode::Key6),
+        "7" => Some(VirtualKeyCode::Key7),
+        "8" => Some(VirtualKeyCode::Key8),
+        "9" => Some(VirtualKeyCode::Key9),
+        "0" => Some(VirtualKeyCode::Key0),
+        "a" => Some(VirtualKeyCode::A),
+        "b" => Some(VirtualKeyCode::B),
+        "c" => Some(VirtualKeyCode::C),
+        "d" => Some(VirtualKeyCode::D),
+        "e" => Some(VirtualKeyCode::E),
+        "f" => Some(VirtualKeyCode::F),
+        "g" => Some(VirtualKeyCode::G),
+        "h" => Some(VirtualKeyCode::H),
+        "i" => Some(VirtualKeyCode::I),
+        "j" => Some(VirtualKeyCode::J),
+        "k" => Some(VirtualKeyCode::K),
+        "l" => Some(VirtualKeyCode::L),
+        "m" => Some(VirtualKeyCode::M),
+        "n" => Some(VirtualKeyCode::N),
+        "o" => Some(VirtualKeyCode::O),
+        "p" => Some(VirtualKeyCode::P),
+        "q" => Some(VirtualKeyCode::Q),
+        "r" => Some(VirtualKeyCode::R),
+        "s" => Some(VirtualKeyCode::S),
+        "t" => Some(VirtualKeyCode::T),
+        "u" => Some(VirtualKeyCode::U),
+        "v" => Some(VirtualKeyCode::V),
+        "w" => Some(VirtualKeyCode::W),
+        "x" => Some(VirtualKeyCode::X),
+        "y" => Some(VirtualKeyCode::Y),
+        "z" => Some(VirtualKeyCode::Z),
+        "escape" => Some(VirtualKeyCode::Escape),
+        "f1" => Some(VirtualKeyCode::F1),
+        "f2" => Some(VirtualKeyCode::F2),
+        "f3" => Some(VirtualKeyCode::F3),
+        "f4" => Some(VirtualKeyCode::F4),
+        "f5" => Some(VirtualKeyCode::F5),
+        "f6" => Some(VirtualKeyCode::F6),
+        "f7" => Some(VirtualKeyCode::F7),
+        "f8" => Some(VirtualKeyCode::F8),
+        "f9" => Some(VirtualKeyCode::F9),
+        "f10" => Some(VirtualKeyCode::F10),
+        "f11" => Some(VirtualKeyCode::F11),
+        "f12" => Some(VirtualKeyCode::F12),
+        "f13" => Some(VirtualKeyCode::F13),
+        "f14" => Some(VirtualKeyCode::F14),
+        "f15" => Some(VirtualKeyCode::F15),
+        "f16" => Some(VirtualKeyCode::F16),
+        "f17" => Some(VirtualKeyCode::F17),
+        "f18" => Some(VirtualKeyCode::F18),
+        "f19" => Some(VirtualKeyCode::F19),
+        "f20" => Some(VirtualKeyCode::F20),
+        "f21" => Some(VirtualKeyCode::F21),
+        "f22" => Some(VirtualKeyCode::F22),
+        "f23" => Some(VirtualKeyCode::F23),
+        "f24" => Some(VirtualKeyCode::F24),
+        "printscreen" => Some(VirtualKeyCode::Snapshot),
+        "scrolllock" => Some(VirtualKeyCode::Scroll),
+        "pause" => Some(VirtualKeyCode::Pause),
+        "insert" => Some(VirtualKeyCode::Insert),
+        "home" => Some(VirtualKeyCode::Home),
+        "delete" => Some(VirtualKeyCode::Delete),
+        "end" => Some(VirtualKeyCode::End),
+        "pagedown" => Some(VirtualKeyCode::PageDown),
+        "pageup" => Some(VirtualKeyCode::PageUp),
+        "left" => Some(VirtualKeyCode::Left),
+        "up" => Some(VirtualKeyCode::Up),
+        "right" => Some(VirtualKeyCode::Right),
+        "down" => Some(VirtualKeyCode::Down),
+        "backspace" => Some(VirtualKeyCode::Back),
+        "enter" => Some(VirtualKeyCode::Return),
+        "space" => Some(VirtualKeyCode::Space),
+        "numlock" => Some(VirtualKeyCode::Numlock),
+        "numpad0" => Some(VirtualKeyCode::Numpad0),
+        "numpad1" => Some(VirtualKeyCode::Numpad1),
+        "numpad2" => Some(VirtualKeyCode::Numpad2),
+        "numpad3" => Some(VirtualKeyCode::Numpad3),
+        "numpad4" => Some(VirtualKeyCode::Numpad4),
+        "numpad5" => Some(VirtualKeyCode::Numpad5),
+        "numpad6" => Some(VirtualKeyCode::Numpad6),
+        "numpad7" => Some(VirtualKeyCode::Numpad7),
+        "numpad8" => Some(VirtualKeyCode::Numpad8),
+        "numpad9" => Some(VirtualKeyCode::Numpad9),
+        "numpadadd" => Some(VirtualKeyCode::NumpadAdd),
+        "numpaddivide" => Some(VirtualKeyCode::NumpadDivide),
+        "numpaddecimal" => Some(VirtualKeyCode::NumpadDecimal),
+        "numpadcomma" => Some(VirtualKeyCode::NumpadComma),
+        "numpadenter" => Some(VirtualKeyCode::NumpadEnter),
+        "numpadequal" => Some(VirtualKeyCode::NumpadEquals),
+        "numpadmultiply" => Some(VirtualKeyCode::NumpadMultiply),
+        "numpadsubtract" => Some(VirtualKeyCode::NumpadSubtract),
+        "asterisk" => Some(VirtualKeyCode::Asterisk),
+        "at" => Some(VirtualKeyCode::At),
+        "backslash" => Some(VirtualKeyCode::Backslash),
+        "colon" => Some(VirtualKeyCode::Colon),
+        "comma" => Some(VirtualKeyCode::Comma),
+        "equal" => Some(VirtualKeyCode::Equals),
+        "grave" => Some(VirtualKeyCode::Grave),
+        "alt:l" => Some(VirtualKeyCode::LAlt),
+        "bracket:l" => Some(VirtualKeyCode::LBracket),
+        "control:l" => Some(VirtualKeyCode::LControl),
+        "shift:l" => Some(VirtualKeyCode::LShift),
+        "os:l" => Some(VirtualKeyCode::LWin),
+        "minus" => Some(VirtualKeyCode::Minus),
+        "plus" => Some(VirtualKeyCode::Plus),
+        "alt:r" => Some(VirtualKeyCode::RAlt),
+        "bracket:r" => Some(VirtualKeyCode::RBracket),
+        "control:r" => Some(VirtualKeyCode::RControl),
+        "shift:r" => Some(VirtualKeyCode::RShift),
+        "os:r" => Some(VirtualKeyCode::RWin),
+        "semicolon" => Some(VirtualKeyCode::Semicolon),
+        "slash" => Some(VirtualKeyCode::Slash),
+        "tab" => Some(VirtualKeyCode::Tab),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(deprecated)]
+    fn key_event(keycode: VirtualKeyCode, state: ElementState) -> KeyboardInput {
+        KeyboardInput {
+            scancode: 0,
+            state,
+            virtual_keycode: Some(keycode),
+            modifiers: Default::default(),
+        }
+    }
+
+    #[test]
+    fn check_pressed_and_released_keys() {
+        let mut dispatcher = RawInputEventDispatcher::new();
+        let mut keyboard = Keyboard::new();
+
+        keyboard.handle_window_event(key_event(VirtualKeyCode::W, ElementState::Pressed));
+        keyboard.poll(&mut dispatcher);
+        assert!(keyboard.is_key_down(VirtualKeyCode::W));
+        assert!(keyboard.is_key_pressed(VirtualKeyCode::W));
+        keyboard.end_frame();
+
+        // Repeats keep the key down without pressing it again.
+        keyboard.handle_window_event(key_event(VirtualKeyCode::W, ElementState::Pressed));
+        keyboard.poll(&mut dispatcher);
+        assert!(keyboard.is_key_down(VirtualKeyCode::W));
+        assert!(!keyboard.is_key_pressed(VirtualKeyCode::W));
+        keyboard.end_frame();
+
+        // A tap within a frame is both pressed and released.
+        keyboard.handle_window_event(key_event(VirtualKeyCode::Space, ElementState::Pressed));
+        keyboard.handle_window_event(key_event(VirtualKeyCode::Space, ElementState::Released));
+        keyboard.poll(&mut dispatcher);
+        assert!(!keyboard.is_key_down(VirtualKeyCode::Space));
+        assert!(keyboard.is_key_pressed(VirtualKeyCode::Space));
+        assert!(keyboard.is_key_released(VirtualKeyCode::Space));
+        keyboard.end_frame();
+
+        keyboard.handle_focus_lost();
+        keyboard.poll(&mut dispatcher);
+        assert!(!keyboard.is_key_down(VirtualKeyCode::W));
+        assert!(keyboard.is_key_released(VirtualKeyCode::W));
+        assert_eq!(keyboard.input("w").unwrap().value, 0.0);
+        keyboard.end_frame();
+
+        // The release after regaining the focus does not release the key again.
+        keyboard.handle_window_event(key_event(VirtualKeyCode::W, ElementState::Released));
+        keyboard.poll(&mut dispatcher);
+        assert!(!keyboard.is_key_released(VirtualKeyCode::W));
+    }
+}
@@ -1,10 +1,21 @@
-use crate::input::{InputDevice, RawInput, RawInputEventDispatcher};
-use std::collections::HashMap;
+use crate::{
+    input::{InputDevice, RawInput, RawInputEventDispatcher},
+    math::Vec2,
+};
+use std::collections::{HashMap, HashSet};
 use winit::{
     dpi::PhysicalPosition,
     event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
 };
 
+/// Position of the cursor relative to the top-left corner of the window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CursorPosition {
+    pub physical: Vec2,
+    /// The physical position divided by the scale factor of the window.
+    pub logical: Vec2,
+}
+
 pub enum MouseWindowEvent {
     CursorMoved {
         position: PhysicalPosition<f64>,
@@ -16,15 +27,30 @@ pub enum MouseWindowEvent {
         state: ElementState,
         button: MouseButton,
     },
+    CursorEntered,
+    CursorLeft,
+    FocusLost,
 }
 
 pub struct Mouse {
     inputs: Vec<RawInput>,
     input_names: HashMap<String, usize>,
     window_event_queue: Vec<MouseWindowEvent>,
+    buttons_down: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    cursor_position: Option<PhysicalPosition<f64>>,
+    is_cursor_inside_window: bool,
+    scale_factor: f64,
+    scroll_delta: Vec2,
+    last_scroll_delta: Option<MouseScrollDelta>,
+    lines_to_pixels: f32,
 }
 
 impl Mouse {
+    /// Pixels a line of a wheel scrolls by default.
+    pub const DEFAULT_LINES_TO_PIXELS: f32 = 20.0;
+
     pub fn new() -> Self {
         let inputs = vec![
             RawInput::new("x"),
@@ -47,6 +73,15 @@ impl Mouse {
             inputs,
             input_names,
             window_event_queue: Vec::new(),
+            buttons_down: HashSet::new(),
+            buttons_pressed: HashSet::new(),
+            buttons_released: HashSet::new(),
+            cursor_position: None,
+            is_cursor_inside_window: false,
+            scale_factor: 1.0,
+            scroll_delta: Vec2::ZERO,
+            last_scroll_delta: None,
+            lines_to_pixels: Self::DEFAULT_LINES_TO_PIXELS,
         }
     }
 
@@ -67,9 +102,85 @@ impl Mouse {
                     button: *button,
                 });
             }
+            WindowEvent::CursorEntered { .. } => {
+                self.window_event_queue
+                    .push(MouseWindowEvent::CursorEntered);
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.window_event_queue.push(MouseWindowEvent::CursorLeft);
+            }
             _ => {}
         }
     }
+
+    /// Releases all held buttons on the next poll, since the window does not receive their release events while unfocused.
+    pub fn handle_focus_lost(&mut self) {
+        self.window_event_queue.push(MouseWindowEvent::FocusLost);
+    }
+
+    /// Sets the scale factor converting the physical cursor position into the logical one.
+    /// Takes effect immediately, so the logical position is right before the cursor moves again.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+
+    /// Position of the cursor, or `None` if it has not moved over the window yet.
+    /// The last position is kept after the cursor leaves the window.
+    pub fn cursor_position(&self) -> Option<CursorPosition> {
+        self.cursor_position.map(|position| {
+            let logical = position.to_logical::<f32>(self.scale_factor);
+            CursorPosition {
+                physical: Vec2::new(position.x as f32, position.y as f32),
+                logical: Vec2::new(logical.x, logical.y),
+            }
+        })
+    }
+
+    pub fn is_cursor_inside_window(&self) -> bool {
+        self.is_cursor_inside_window
+    }
+
+    /// Returns `true` while the button is held.
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        self.buttons_down.contains(&button)
+    }
+
+    /// Returns `true` if the button went down this frame.
+    pub fn is_button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    /// Returns `true` if the button went up this frame, including buttons released by losing the focus.
+    pub fn is_button_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    /// Sum of the scroll deltas this frame in pixels, with lines converted by [`lines_to_pixels`](Self::lines_to_pixels).
+    pub fn scroll_delta(&self) -> Vec2 {
+        self.scroll_delta
+    }
+
+    /// The last scroll delta this frame as the window reported it. Wheels usually scroll by lines and touchpads by pixels.
+    pub fn last_scroll_delta(&self) -> Option<MouseScrollDelta> {
+        self.last_scroll_delta
+    }
+
+    pub fn lines_to_pixels(&self) -> f32 {
+        self.lines_to_pixels
+    }
+
+    /// Sets how many pixels a line of [`MouseScrollDelta::LineDelta`] counts as in [`scroll_delta`](Self::scroll_delta).
+    pub fn set_lines_to_pixels(&mut self, lines_to_pixels: f32) {
+        self.lines_to_pixels = lines_to_pixels;
+    }
+
+    /// Forgets the buttons pressed and released and the scrolling this frame.
+    pub fn end_frame(&mut self) {
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.scroll_delta = Vec2::ZERO;
+        self.last_scroll_delta = None;
+    }
 }
 
 impl InputDevice for Mouse {
@@ -111,6 +222,7 @@ impl InputDevice for Mouse {
 
                     self.inputs[x_index].value = position.x as f32;
                     self.inputs[y_index].value = position.y as f32;
+                    self.cursor_position = Some(position);
 
                     dispatcher.dispatch(&self.inputs[x_index]);
                     dispatcher.dispatch(&self.inputs[y_index]);
@@ -128,21 +240,38 @@ impl InputDevice for Mouse {
                         MouseScrollDelta::LineDelta(x, y) => {
                             self.inputs[scroll_x_index].value += x;
                             self.inputs[scroll_y_index].value += y;
+                            self.scroll_delta += Vec2::new(x, y) * self.lines_to_pixels;
                         }
                         MouseScrollDelta::PixelDelta(position) => {
                             self.inputs[scroll_x_index].value += position.x as f32;
                             self.inputs[scroll_y_index].value += position.y as f32;
+                            self.scroll_delta += Vec2::new(position.x as f32, position.y as f32);
                         }
                     }
 
+                    self.last_scroll_delta = Some(delta);
+
                     is_scroll_changed = true;
                 }
                 MouseWindowEvent::MouseInput { state, button } => {
+                    match state {
+                        ElementState::Pressed => {
+                            if self.buttons_down.insert(button) {
+                                self.buttons_pressed.insert(button);
+                            }
+                        }
+                        ElementState::Released => {
+                            if self.buttons_down.remove(&button) {
+                                self.buttons_released.insert(button);
+                            }
+                        }
+                    }
+
                     let button_index = match button {
                         MouseButton::Left => self.input_names["button:left"],
                         MouseButton::Right => self.input_names["button:right"],
                         MouseButton::Middle => self.input_names["button:middle"],
-                        _ => return,
+                        _ => continue,
                     };
 
                     match state {
@@ -152,6 +281,24 @@ impl InputDevice for Mouse {
 
                     dispatcher.dispatch(&self.inputs[button_index]);
                 }
+                MouseWindowEvent::CursorEntered => {
+                    self.is_cursor_inside_window = true;
+                }
+                MouseWindowEvent::CursorLeft => {
+                    self.is_cursor_inside_window = false;
+                }
+                MouseWindowEvent::FocusLost => {
+                    self.buttons_released.extend(self.buttons_down.drain());
+
+                    for name in ["button:left", "button:right", "button:middle"] {
+                        let input = &mut self.inputs[self.input_names[name]];
+
+                        if input.value != 0.0 {
+                            input.value = 0.0;
+                            dispatcher.dispatch(input);
+                        }
+                    }
+                }
             }
         }
 
@@ -166,3 +313,85 @@ impl InputDevice for Mouse {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_buttons_and_cursor_position() {
+        let mut dispatcher = RawInputEventDispatcher::new();
+        let mut mouse = Mouse::new();
+        assert_eq!(mouse.cursor_position(), None);
+
+        mouse.window_event_queue.extend([
+            MouseWindowEvent::CursorEntered,
+            MouseWindowEvent::CursorMoved {
+                position: PhysicalPosition::new(200.0, 100.0),
+            },
+            MouseWindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Other(4),
+            },
+            MouseWindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+            },
+        ]);
+        mouse.poll(&mut dispatcher);
+        assert!(mouse.is_cursor_inside_window());
+        assert!(mouse.is_button_pressed(MouseButton::Other(4)));
+        assert!(mouse.is_button_down(MouseButton::Left));
+        assert!(mouse.is_button_pressed(MouseButton::Left));
+        assert_eq!(mouse.input("button:left").unwrap().value, 1.0);
+        mouse.end_frame();
+        assert!(!mouse.is_button_pressed(MouseButton::Left));
+
+        // The logical position follows the scale factor without waiting for the cursor to move.
+        mouse.set_scale_factor(2.0);
+        let position = mouse.cursor_position().unwrap();
+        assert_eq!(position.physical, Vec2::new(200.0, 100.0));
+        assert_eq!(position.logical, Vec2::new(100.0, 50.0));
+
+        mouse
+            .window_event_queue
+            .extend([MouseWindowEvent::CursorLeft, MouseWindowEvent::FocusLost]);
+        mouse.poll(&mut dispatcher);
+        assert!(!mouse.is_cursor_inside_window());
+        assert!(!mouse.is_button_down(MouseButton::Left));
+        assert!(mouse.is_button_released(MouseButton::Left));
+        assert_eq!(mouse.input("button:left").unwrap().value, 0.0);
+    }
+
+    #[test]
+    fn check_scroll_delta_accumulates_per_frame() {
+        let mut dispatcher = RawInputEventDispatcher::new();
+        let mut mouse = Mouse::new();
+        mouse.set_lines_to_pixels(10.0);
+
+        mouse.window_event_queue.extend([
+            MouseWindowEvent::MouseWheel {
+                delta: MouseScrollDelta::LineDelta(0.0, 1.0),
+            },
+            MouseWindowEvent::MouseWheel {
+                delta: MouseScrollDelta::LineDelta(0.0, 2.0),
+            },
+            MouseWindowEvent::MouseWheel {
+                delta: MouseScrollDelta::PixelDelta(PhysicalPosition::new(4.0, -5.0)),
+            },
+        ]);
+        mouse.poll(&mut dispatcher);
+        assert_eq!(mouse.scroll_delta(), Vec2::new(4.0, 25.0));
+        assert_eq!(
+            mouse.last_scroll_delta(),
+            Some(MouseScrollDelta::PixelDelta(PhysicalPosition::new(
+                4.0, -5.0
+            )))
+        );
+
+        mouse.end_frame();
+        mouse.poll(&mut dispatcher);
+        assert_eq!(mouse.scroll_delta(), Vec2::ZERO);
+        assert_eq!(mouse.last_scroll_delta(), None);
+    }
+}
@@ -10,6 +10,9 @@ pub use raw_input::*;
 pub use raw_input_event::*;
 pub use raw_input_event_dispatcher::*;
 
+use crate::math::Vec2;
+use winit::event::{MouseButton, VirtualKeyCode};
+
 pub struct InputManager {
     keyboard: Keyboard,
     mouse: Mouse,
@@ -41,8 +44,53 @@ impl InputManager {
         &mut self.mouse
     }
 
+    pub fn is_key_down(&self, key: VirtualKeyCode) -> bool {
+        self.keyboard.is_key_down(key)
+    }
+
+    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keyboard.is_key_pressed(key)
+    }
+
+    pub fn is_key_released(&self, key: VirtualKeyCode) -> bool {
+        self.keyboard.is_key_released(key)
+    }
+
+    /// Position of the cursor, or `None` if it has not moved over the window yet.
+    pub fn cursor_position(&self) -> Option<CursorPosition> {
+        self.mouse.cursor_position()
+    }
+
+    pub fn cursor_inside_window(&self) -> bool {
+        self.mouse.is_cursor_inside_window()
+    }
+
+    pub fn is_mouse_button_down(&self, button: MouseButton) -> bool {
+        self.mouse.is_button_down(button)
+    }
+
+    pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse.is_button_pressed(button)
+    }
+
+    pub fn is_mouse_button_released(&self, button: MouseButton) -> bool {
+        self.mouse.is_button_released(button)
+    }
+
+    /// Scrolling this frame in pixels. See [`Mouse::scroll_delta`].
+    pub fn scroll_delta(&self) -> Vec2 {
+        self.mouse.scroll_delta()
+    }
+
+    /// Feeds the window events queued since the last frame into the devices. Called at the start of each frame.
     pub fn poll(&mut self) {
         self.keyboard.poll(&mut self.dispatcher);
         self.mouse.poll(&mut self.dispatcher);
     }
+
+    /// Resets the per-frame state of the devices. Called at the end of each frame.
+    pub fn end_frame(&mut self) {
+        self.keyboard.end_frame();
+        self.mouse.end_frame();
+    }
 }
@@ -1,56 +1,119 @@
 use self::{
+    animation::{
+        AnimationManager, AnimationPlayer, BoneAttachment, IkConstraint, PathFollower,
+        PropertyAnimator,
+    },
     ecs_system::{
-        render::RenderSystem, update_camera_transform_buffer::UpdateCameraTransformBufferSystem,
+        render::RenderSystem, system_registry::SystemRegistry,
+        update_animation_players::UpdateAnimationPlayersSystem,
+        update_bone_attachments::UpdateBoneAttachmentsSystem,
+        update_buoyancy::UpdateBuoyancySystem,
+        update_camera_transform_buffer::UpdateCameraTransformBufferSystem,
+        update_cloth_meshes::UpdateClothMeshesSystem, update_cloths::UpdateClothsSystem,
+        update_ik_constraints::UpdateIkConstraintsSystem, update_lights::UpdateLightsSystem,
+        update_nav_agents::UpdateNavAgentsSystem, update_path_followers::UpdatePathFollowersSystem,
+        update_property_animators::UpdatePropertyAnimatorsSystem,
+        update_skinned_meshes::UpdateSkinnedMeshesSystem,
     },
     gfx::{
-        Camera, DepthStencilMode, GfxContext, GfxContextCreationError, GfxContextHandle,
-        RenderManager, ScreenManager, ShaderManager,
+        surface_size, timestamped_capture_path, Camera, DepthStencilMode, DirectionalLight,
+        DisplayManager, DisplaySettings, FrameCapture, FrameReplayError, FrameReplayer, GfxContext,
+        GfxContextCreationError, GfxContextHandle, HeadlessDevice, PointLight, RenderConfigWatcher,
+        RenderManager, RenderTierReport, ScreenManager, ShaderManager, SpotLight, SurfaceRecovery,
     },
-    time::TimeManager,
-    vsync::TargetFrameInterval,
+    time::{AnimationBurst, TimeManager},
+    vsync::{advance_frame_time, frame_pacing_wake_time, TargetFrameInterval},
+    world_streaming::WorldStreamingManager,
 };
+use ::asset::AssetKey;
+use ::image::RgbaImage;
+use audio::{AudioManager, AudioSettings};
 use codegen::Handle;
+use console::{
+    parse_command_line, register_built_in_commands, ConsoleCommandError, ConsoleDrawList,
+    ConsoleManager, ConsoleOverlay, CONSOLE_OVERLAY_PRIORITY,
+};
+#[cfg(feature = "egui")]
+use debug_ui::{winit_cursor_icon, EguiIntegration, EguiUiId};
+use deferred::{DeferredMutations, FramePhase, MutationKind};
 use ecs_system::{
     make_ui_scaler_dirty::MakeUIScalerDirty, update_ui_element::UpdateUIElement,
     update_ui_raycast_grid::UpdateUIRaycastGrid, update_ui_scaler::UpdateUIScaler,
 };
 use event::{event_types, EventManager};
-use gfx::{BuiltInShaderManager, GlyphManager, MeshRenderer, UIElementRenderer, UITextRenderer};
+use gfx::{
+    BindGroupEntryResource, BindingPropKey, BuiltInShaderManager, Buoyancy, Cloth, ClothCollider,
+    DebugDrawManager, FogVolume, GlyphManager, HlodBake, HlodBakeSettings, HlodBakeTask, HlodProxy,
+    HlodStatic, Layers, LineRenderer, Material, MaterialHandle, MeshRenderer, NinePatchRenderer,
+    OverlayContent, ParticleSystem, PlanarReflection, ShaderFileWatcher, ShaderHandle,
+    SpriteRenderer, Terrain, TextRenderer, UIElementRenderer, UITextRenderer, WaterSurface,
+    BUILT_IN_SHADER_HLOD_PROXY, BUILT_IN_SHADER_LINE,
+};
 use input::InputManager;
-use math::Vec2;
-use object::{Object, ObjectManager};
+use logging::{transports::ConsoleTransport, Logger, StandardLogLevel};
+use math::{Vec2, Vec3};
+use navigation::{
+    NavAgent, NavMesh, NavMeshBakeSettings, NavMeshBakeTask, NavMeshSource, NavigationManager,
+};
+use object::{Object, ObjectHandle, ObjectManager};
 use object_event::ObjectEventManager;
-use specs::prelude::*;
+use platform::PlatformManager;
+use prefab::{Prefab, PrefabError, PrefabManager};
+use specs::{prelude::*, storage::Tracked};
 use std::{
-    cell::{Ref, RefCell, RefMut},
-    mem::MaybeUninit,
+    cell::{Cell, Ref, RefCell, RefMut},
+    mem::{take, MaybeUninit},
     num::NonZeroU32,
-    time::Instant,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
 };
+use task::{TaskHandle, TaskScheduler};
 use thiserror::Error;
 use transform::Transform;
 use ui::{UIElement, UIEventManager, UIRaycastManager, UIScaler, UISize};
-use wgpu::MaintainBase;
+use util::{FrameArena, FrameArenaStats, FrameArenas, RateLimiter};
+use wgpu::{Backends, MaintainBase, SurfaceError};
 use winit::{
     dpi::{LogicalSize, PhysicalSize},
-    event::{Event, WindowEvent},
+    event::{ElementState, Event, VirtualKeyCode, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    platform::run_return::EventLoopExtRunReturn,
+    window::{Fullscreen, Window, WindowBuilder},
 };
+use world_ext::ChangeReader;
 
+pub mod animation;
 pub mod asset;
+pub mod audio;
+pub mod console;
+#[cfg(feature = "egui")]
+pub mod debug_ui;
+pub mod deferred;
 pub mod ecs_system;
 pub mod event;
 pub mod gfx;
+pub mod handles;
 pub mod input;
 pub mod math;
+pub mod navigation;
 pub mod object;
 pub mod object_event;
+pub mod platform;
+pub mod prefab;
+pub mod rollback;
+pub mod task;
 pub mod time;
 pub mod transform;
 pub mod ui;
 pub mod util;
 pub mod vsync;
+pub mod world_ext;
+pub mod world_streaming;
+
+mod engine_config;
+
+pub use engine_config::*;
 
 // re-exports.
 pub use fontdue;
@@ -72,14 +135,19 @@ pub fn use_context() -> &'static ContextHandle {
 // How about to make managers smaller?
 #[derive(Handle)]
 pub struct Context {
-    window: Window,
+    /// `None` only for the headless contexts of tests.
+    window: Option<Window>,
+    logger: Logger<StandardLogLevel>,
     gfx_ctx: GfxContextHandle,
     world: RefCell<World>,
     object_mgr: RefCell<ObjectManager>,
     screen_mgr: RefCell<ScreenManager>,
+    display_mgr: RefCell<DisplayManager>,
     render_mgr: RefCell<RenderManager>,
+    debug_draw_mgr: RefCell<DebugDrawManager>,
     glyph_mgr: RefCell<GlyphManager>,
     shader_mgr: ShaderManager,
+    shader_watcher: RefCell<ShaderFileWatcher>,
     built_in_shader_mgr: BuiltInShaderManager,
     ui_raycast_mgr: RefCell<UIRaycastManager>,
     ui_event_mgr: RefCell<UIEventManager>,
@@ -87,14 +155,61 @@ pub struct Context {
     input_mgr: RefCell<InputManager>,
     event_mgr: EventManager,
     object_event_mgr: ObjectEventManager,
+    platform_mgr: RefCell<PlatformManager>,
+    audio_mgr: RefCell<AudioManager>,
+    task_scheduler: RefCell<TaskScheduler>,
+    system_registry: RefCell<SystemRegistry>,
+    fixed_system_registry: RefCell<SystemRegistry>,
+    prefab_mgr: RefCell<PrefabManager>,
+    animation_burst: RefCell<AnimationBurst>,
+    world_streaming_mgr: RefCell<WorldStreamingManager>,
+    animation_mgr: RefCell<AnimationManager>,
+    navigation_mgr: RefCell<NavigationManager>,
+    pending_hlod_bake: RefCell<Option<TaskHandle<HlodBake>>>,
+    console_mgr: RefCell<ConsoleManager>,
+    deferred_mutations: DeferredMutations,
+    frame_arenas: RefCell<FrameArenas>,
+    global_wind: Cell<Vec3>,
+    exit_requested: Cell<bool>,
+    is_shut_down: Cell<bool>,
+    target_fps: Cell<EngineTargetFps>,
+    pending_target_fps: Cell<Option<EngineTargetFps>>,
+    exit_callbacks: RefCell<Vec<Box<dyn FnOnce()>>>,
+    close_request_callbacks: RefCell<Vec<Box<dyn FnMut() -> bool>>>,
+    frame_capture_key: Option<VirtualKeyCode>,
+    throttle_when_unfocused: Option<NonZeroU32>,
+    #[cfg(feature = "egui")]
+    egui_integration: RefCell<EguiIntegration>,
 }
 
 impl Context {
-    pub fn new(window: Window, gfx_ctx: GfxContext, screen_width: u32, screen_height: u32) -> Self {
+    pub fn new(window: Window, gfx_ctx: GfxContext, config: &EngineConfig) -> Self {
+        Self::with_window(Some(window), gfx_ctx, config)
+    }
+
+    fn with_window(window: Option<Window>, gfx_ctx: GfxContext, config: &EngineConfig) -> Self {
+        let screen_width = config.width;
+        let screen_height = config.height;
+        let mut logger = Logger::new();
+        logger.wire(Arc::new(ConsoleTransport::new()));
+        let mut console_mgr = ConsoleManager::new(config.console.as_ref());
+        register_built_in_commands(console_mgr.commands_mut());
+
+        if let Some(transport) = console_mgr.log_transport() {
+            logger.wire(Arc::new(transport));
+        }
+
         let gfx_ctx = GfxContextHandle::new(gfx_ctx);
         let world = World::new().into();
         let object_mgr = ObjectManager::new().into();
         let screen_mgr = ScreenManager::new(screen_width, screen_height).into();
+        let display_mgr = DisplayManager::new(DisplaySettings {
+            size: LogicalSize::new(screen_width, screen_height),
+            fullscreen: false,
+            vsync: config.vsync,
+            render_scale: config.render_scale,
+        })
+        .into();
         let render_mgr: RefCell<RenderManager> = RenderManager::new(
             gfx_ctx.clone(),
             PhysicalSize::new(screen_width, screen_height),
@@ -102,40 +217,92 @@ impl Context {
         )
         .into();
         let glyph_mgr = GlyphManager::new(gfx_ctx.clone()).into();
-        let shader_mgr = ShaderManager::new(gfx_ctx.clone());
+        let shader_mgr = ShaderManager::new(gfx_ctx.clone(), config.shader_cache.clone());
         let mut built_in_shader_mgr = BuiltInShaderManager::new();
         built_in_shader_mgr.init(
             &shader_mgr,
             render_mgr.borrow_mut().bind_group_layout_cache(),
         );
+        let debug_draw_mgr = DebugDrawManager::new(MaterialHandle::new(Material::new(
+            built_in_shader_mgr
+                .find_shader(BUILT_IN_SHADER_LINE)
+                .unwrap(),
+            render_mgr.borrow_mut().pipeline_layout_cache(),
+        )))
+        .into();
         let ui_raycast_mgr = UIRaycastManager::new().into();
         let ui_event_mgr = UIEventManager::new().into();
-        let time_mgr = TimeManager::new().into();
+        let mut time_mgr = TimeManager::new();
+        time_mgr.set_max_delta_time(config.max_delta_time);
+        time_mgr.set_fixed_update_rate(config.fixed_update_rate);
         let input_mgr = InputManager::new().into();
         let event_mgr = EventManager::new();
         let object_event_mgr = ObjectEventManager::new();
+        let platform_mgr = PlatformManager::new().into();
+        let audio_mgr = AudioManager::new().into();
+        let task_scheduler = TaskScheduler::new().into();
+        let system_registry = SystemRegistry::new().into();
+        let fixed_system_registry = SystemRegistry::new().into();
+        let prefab_mgr = PrefabManager::new().into();
+        let animation_burst = AnimationBurst::new(Duration::from_millis(16)).into();
+        let world_streaming_mgr = WorldStreamingManager::new().into();
+        let animation_mgr = AnimationManager::new().into();
+        let navigation_mgr = NavigationManager::new().into();
 
         Self {
             window,
+            logger,
             gfx_ctx,
             world,
             object_mgr,
             screen_mgr,
+            display_mgr,
             render_mgr,
+            debug_draw_mgr,
             glyph_mgr,
             shader_mgr,
+            shader_watcher: ShaderFileWatcher::new().into(),
             built_in_shader_mgr: built_in_shader_mgr.into(),
             ui_raycast_mgr,
             ui_event_mgr,
-            time_mgr,
+            time_mgr: time_mgr.into(),
             input_mgr,
             event_mgr,
             object_event_mgr,
+            platform_mgr,
+            audio_mgr,
+            task_scheduler,
+            system_registry,
+            fixed_system_registry,
+            prefab_mgr,
+            animation_burst,
+            world_streaming_mgr,
+            animation_mgr,
+            navigation_mgr,
+            pending_hlod_bake: RefCell::new(None),
+            console_mgr: console_mgr.into(),
+            deferred_mutations: DeferredMutations::new(),
+            frame_arenas: FrameArenas::new(config.frame_arena_bytes).into(),
+            global_wind: Cell::new(Vec3::ZERO),
+            exit_requested: Cell::new(false),
+            is_shut_down: Cell::new(false),
+            target_fps: Cell::new(EngineTargetFps::default()),
+            pending_target_fps: Cell::new(None),
+            exit_callbacks: RefCell::new(Vec::new()),
+            close_request_callbacks: RefCell::new(Vec::new()),
+            frame_capture_key: config.frame_capture_key,
+            throttle_when_unfocused: config.throttle_when_unfocused,
+            #[cfg(feature = "egui")]
+            egui_integration: EguiIntegration::new(gfx_ctx.clone()).into(),
         }
     }
 
     pub fn window(&self) -> &Window {
-        &self.window
+        self.window.as_ref().expect("the context has no window")
+    }
+
+    pub fn logger(&self) -> &Logger<StandardLogLevel> {
+        &self.logger
     }
 
     pub fn gfx_ctx(&self) -> &GfxContextHandle {
@@ -166,6 +333,163 @@ impl Context {
         self.screen_mgr.borrow_mut()
     }
 
+    pub fn display_mgr(&self) -> Ref<DisplayManager> {
+        self.display_mgr.borrow()
+    }
+
+    pub fn display_mgr_mut(&self) -> RefMut<DisplayManager> {
+        self.display_mgr.borrow_mut()
+    }
+
+    /// Requests a display change. See [`DisplayManager::apply`].
+    pub fn apply_display_settings(&self, settings: DisplaySettings) {
+        self.display_mgr_mut().apply(settings);
+    }
+
+    /// Applies the pending display change in one go: window, surface, render targets and screen manager.
+    /// Returns `true` if a change has been applied; the caller should skip rendering for this frame.
+    fn apply_pending_display_settings(&self) -> bool {
+        let settings = match self.display_mgr_mut().take_pending(Instant::now()) {
+            Some(settings) => settings,
+            None => return false,
+        };
+        let previous = *self.display_mgr().current();
+
+        self.event_mgr
+            .dispatch(&event_types::DisplaySettingsChanging {
+                from: previous,
+                to: settings,
+            });
+
+        let physical_size = if settings.fullscreen {
+            self.window()
+                .set_fullscreen(Some(Fullscreen::Borderless(None)));
+            self.window()
+                .current_monitor()
+                .map(|monitor| monitor.size())
+                .unwrap_or_else(|| self.window().inner_size())
+        } else {
+            self.window().set_fullscreen(None);
+            self.window().set_inner_size(settings.size);
+            settings.size.to_physical(self.window().scale_factor())
+        };
+
+        if physical_size.width != 0 && physical_size.height != 0 {
+            self.gfx_ctx.device.poll(MaintainBase::Wait);
+            self.gfx_ctx.set_vsync(settings.vsync);
+            self.gfx_ctx.resize(physical_size);
+            self.render_mgr_mut().resize(physical_size);
+        }
+
+        {
+            let mut screen_mgr = self.screen_mgr_mut();
+            screen_mgr.update_size(physical_size);
+            screen_mgr.update_render_scale(settings.render_scale);
+        }
+
+        self.display_mgr_mut().mark_applied(settings, physical_size);
+        self.event_mgr
+            .dispatch(&event_types::DisplaySettingsChanged {
+                from: previous,
+                to: settings,
+            });
+
+        true
+    }
+
+    /// Applies the quality preset if it has changed: clamps the render scale into its bounds and notifies the game code.
+    fn apply_quality_preset(&self) {
+        let (tier, preset) = match self.render_mgr_mut().take_changed_quality_preset() {
+            Some(changed) => changed,
+            None => return,
+        };
+
+        {
+            let mut screen_mgr = self.screen_mgr_mut();
+            let render_scale = preset.clamp_render_scale(screen_mgr.render_scale());
+            screen_mgr.update_render_scale(render_scale);
+        }
+
+        self.logger.log(
+            StandardLogLevel::Info,
+            match tier {
+                Some(tier) => format!("quality preset applied: {}", tier),
+                None => "quality preset applied: custom".to_owned(),
+            },
+        );
+        self.event_mgr
+            .dispatch(&event_types::QualityPresetApplied { tier, preset });
+    }
+
+    /// Warns about objects whose world matrices were not finite and had to be reset, at most once per second.
+    fn report_non_finite_matrices(&self, count: usize, limiter: &mut RateLimiter) {
+        if count == 0 {
+            return;
+        }
+
+        if let Some(suppressed) = limiter.allow(Instant::now()) {
+            self.logger.log(
+                StandardLogLevel::Warning,
+                format!(
+                    "{} object(s) had non-finite world matrices and were reset to identity ({} similar warning(s) suppressed)",
+                    count, suppressed
+                ),
+            );
+        }
+    }
+
+    /// Reloads the render config if the watched file has changed, and applies it.
+    fn reload_render_config(&self, watcher: &mut RenderConfigWatcher) {
+        match watcher.poll() {
+            Some(Ok((config, warnings))) => {
+                for warning in warnings {
+                    self.logger.log(StandardLogLevel::Warning, warning);
+                }
+
+                self.render_mgr_mut().apply_config(&config);
+                self.display_mgr_mut().request_vsync(config.vsync);
+                self.apply_quality_preset();
+            }
+            Some(Err(err)) => {
+                self.logger.log(
+                    StandardLogLevel::Error,
+                    format!("{}: {}", watcher.path().display(), err),
+                );
+            }
+            None => {}
+        }
+    }
+
+    /// Loads the audio settings saved next to the executable, if any.
+    fn load_audio_settings(&self) {
+        let path = match AudioSettings::path_next_to_executable() {
+            Some(path) => path,
+            None => return,
+        };
+
+        if let Err(err) = self.audio_mgr_mut().load_settings(&path) {
+            self.logger.log(
+                StandardLogLevel::Error,
+                format!("{}: {}", path.display(), err),
+            );
+        }
+    }
+
+    /// Saves the audio settings next to the executable, if they changed.
+    fn save_audio_settings(&self) {
+        let path = match AudioSettings::path_next_to_executable() {
+            Some(path) => path,
+            None => return,
+        };
+
+        if let Err(err) = self.audio_mgr_mut().save_settings(&path) {
+            self.logger.log(
+                StandardLogLevel::Error,
+                format!("{}: {}", path.display(), err),
+            );
+        }
+    }
+
     pub fn render_mgr(&self) -> Ref<RenderManager> {
         self.render_mgr.borrow()
     }
@@ -174,6 +498,14 @@ impl Context {
         self.render_mgr.borrow_mut()
     }
 
+    pub fn debug_draw_mgr(&self) -> Ref<DebugDrawManager> {
+        self.debug_draw_mgr.borrow()
+    }
+
+    pub fn debug_draw_mgr_mut(&self) -> RefMut<DebugDrawManager> {
+        self.debug_draw_mgr.borrow_mut()
+    }
+
     pub fn glyph_mgr(&self) -> Ref<GlyphManager> {
         self.glyph_mgr.borrow()
     }
@@ -186,6 +518,37 @@ impl Context {
         &self.shader_mgr
     }
 
+    /// Reloads the shader whenever its WGSL file changes, while the engine runs. Materials of the shader move to
+    /// the new version; a failed reload is logged and keeps the previous one.
+    pub fn watch_shader(&self, path: impl Into<PathBuf>, shader: ShaderHandle) {
+        self.shader_watcher.borrow_mut().watch(path, shader);
+    }
+
+    fn reload_watched_shaders(&self) {
+        let results = {
+            let mut render_mgr = self.render_mgr_mut();
+            let (bind_group_layout_cache, pipeline_layout_cache) = render_mgr.layout_caches();
+            self.shader_watcher.borrow_mut().poll(
+                &self.shader_mgr,
+                bind_group_layout_cache,
+                pipeline_layout_cache,
+            )
+        };
+
+        for (path, result) in results {
+            match result {
+                Ok(()) => self.logger.log(
+                    StandardLogLevel::Info,
+                    format!("shader reloaded from {}", path.display()),
+                ),
+                Err(err) => self.logger.log(
+                    StandardLogLevel::Error,
+                    format!("{}: {}", path.display(), err),
+                ),
+            }
+        }
+    }
+
     pub fn built_in_shader_mgr(&self) -> &BuiltInShaderManager {
         &self.built_in_shader_mgr
     }
@@ -229,6 +592,720 @@ impl Context {
     pub fn object_event_mgr(&self) -> &ObjectEventManager {
         &self.object_event_mgr
     }
+
+    pub fn platform_mgr(&self) -> Ref<PlatformManager> {
+        self.platform_mgr.borrow()
+    }
+
+    pub fn platform_mgr_mut(&self) -> RefMut<PlatformManager> {
+        self.platform_mgr.borrow_mut()
+    }
+
+    pub fn audio_mgr(&self) -> Ref<AudioManager> {
+        self.audio_mgr.borrow()
+    }
+
+    pub fn audio_mgr_mut(&self) -> RefMut<AudioManager> {
+        self.audio_mgr.borrow_mut()
+    }
+
+    pub fn task_scheduler(&self) -> Ref<TaskScheduler> {
+        self.task_scheduler.borrow()
+    }
+
+    pub fn task_scheduler_mut(&self) -> RefMut<TaskScheduler> {
+        self.task_scheduler.borrow_mut()
+    }
+
+    pub fn system_registry(&self) -> Ref<SystemRegistry> {
+        self.system_registry.borrow()
+    }
+
+    pub fn system_registry_mut(&self) -> RefMut<SystemRegistry> {
+        self.system_registry.borrow_mut()
+    }
+
+    /// Systems run on each fixed update, at [`TimeManager::fixed_delta_time`] intervals, before the `Update` event.
+    pub fn fixed_system_registry(&self) -> Ref<SystemRegistry> {
+        self.fixed_system_registry.borrow()
+    }
+
+    pub fn fixed_system_registry_mut(&self) -> RefMut<SystemRegistry> {
+        self.fixed_system_registry.borrow_mut()
+    }
+
+    pub fn prefab_mgr(&self) -> Ref<PrefabManager> {
+        self.prefab_mgr.borrow()
+    }
+
+    pub fn prefab_mgr_mut(&self) -> RefMut<PrefabManager> {
+        self.prefab_mgr.borrow_mut()
+    }
+
+    pub fn animation_burst(&self) -> Ref<AnimationBurst> {
+        self.animation_burst.borrow()
+    }
+
+    pub fn animation_burst_mut(&self) -> RefMut<AnimationBurst> {
+        self.animation_burst.borrow_mut()
+    }
+
+    pub fn world_streaming_mgr(&self) -> Ref<WorldStreamingManager> {
+        self.world_streaming_mgr.borrow()
+    }
+
+    pub fn world_streaming_mgr_mut(&self) -> RefMut<WorldStreamingManager> {
+        self.world_streaming_mgr.borrow_mut()
+    }
+
+    pub fn animation_mgr(&self) -> Ref<AnimationManager> {
+        self.animation_mgr.borrow()
+    }
+
+    pub fn animation_mgr_mut(&self) -> RefMut<AnimationManager> {
+        self.animation_mgr.borrow_mut()
+    }
+
+    pub fn navigation_mgr(&self) -> Ref<NavigationManager> {
+        self.navigation_mgr.borrow()
+    }
+
+    pub fn navigation_mgr_mut(&self) -> RefMut<NavigationManager> {
+        self.navigation_mgr.borrow_mut()
+    }
+
+    /// Bakes the geometry of the active objects with a [`NavMeshSource`] into a navmesh on the background thread.
+    /// Pass the result to [`NavigationManager::set_navmesh`] when it is done.
+    pub fn bake_navmesh(&self, settings: NavMeshBakeSettings) -> TaskHandle<NavMesh> {
+        let geometry = navigation::collect_navmesh_geometry(self);
+        self.task_scheduler_mut()
+            .schedule_background_task(NavMeshBakeTask::new(Arc::new(geometry), settings))
+    }
+
+    /// Bakes the active objects with a [`HlodStatic`] into HLOD proxies on the background thread.
+    /// Pass the result to [`apply_hlod_bake`](Self::apply_hlod_bake) when it is done.
+    pub fn bake_hlod(&self, settings: HlodBakeSettings) -> TaskHandle<HlodBake> {
+        let sources = gfx::collect_hlod_sources(self);
+        self.task_scheduler_mut()
+            .schedule_background_task(HlodBakeTask::new(Arc::new(sources), settings))
+    }
+
+    /// Bakes as [`bake_hlod`](Self::bake_hlod) does, and applies the result once it is done.
+    /// A bake still pending is cancelled.
+    pub fn bake_and_apply_hlod(&self, settings: HlodBakeSettings) {
+        let handle = self.bake_hlod(settings);
+
+        if let Some(pending) = self.pending_hlod_bake.replace(Some(handle)) {
+            pending.cancel();
+        }
+    }
+
+    /// Replaces the HLOD proxies in the world by the ones of the bake, drawn with the built-in proxy shader.
+    /// Returns the objects of the new proxies.
+    pub fn apply_hlod_bake(&self, bake: &HlodBake) -> Vec<ObjectHandle> {
+        let previous_proxies = {
+            let world = self.world();
+            let objects = world.read_storage::<Object>();
+            let hlod_proxies = world.read_storage::<HlodProxy>();
+            Vec::from_iter(
+                (&objects, &hlod_proxies)
+                    .join()
+                    .map(|(object, _)| object.object_id()),
+            )
+        };
+
+        for object_id in previous_proxies {
+            let handle = self.object_mgr().get(object_id);
+
+            if let Some(handle) = handle {
+                handle.remove().unwrap();
+            }
+        }
+
+        let material = MaterialHandle::new(Material::new(
+            self.built_in_shader_mgr
+                .find_shader(BUILT_IN_SHADER_HLOD_PROXY)
+                .unwrap(),
+            self.render_mgr_mut().pipeline_layout_cache(),
+        ));
+        let mut object_mgr = self.object_mgr_mut();
+        let mut world = self.world_mut();
+
+        Vec::from_iter(bake.clusters.iter().enumerate().map(|(index, cluster)| {
+            let mut hlod_proxy = HlodProxy::new(cluster, &bake.settings, &self.gfx_ctx.device);
+            hlod_proxy.set_material(material.clone());
+
+            let (handle, builder) = object_mgr.create_object_builder(
+                &mut world,
+                Some(format!("hlod proxy #{}", index)),
+                None,
+            );
+            builder.with(hlod_proxy).build();
+            handle
+        }))
+    }
+
+    /// Applies the bake started by [`bake_and_apply_hlod`](Self::bake_and_apply_hlod) once it is done.
+    fn update_hlod_bake(&self) {
+        let result = match self.pending_hlod_bake.borrow().as_ref() {
+            Some(handle) => match handle.poll() {
+                Some(result) => result,
+                None => return,
+            },
+            None => return,
+        };
+        self.pending_hlod_bake.replace(None);
+
+        match result {
+            Ok(bake) => {
+                let proxies = self.apply_hlod_bake(&bake);
+                let members = bake
+                    .clusters
+                    .iter()
+                    .map(|cluster| cluster.members.len())
+                    .sum::<usize>();
+                self.logger.log(
+                    StandardLogLevel::Info,
+                    format!(
+                        "HLOD bake applied: {} proxies standing in for {} object(s)",
+                        proxies.len(),
+                        members
+                    ),
+                );
+            }
+            Err(err) => self.logger.log(
+                StandardLogLevel::Error,
+                format!("HLOD bake failed: {}", err),
+            ),
+        }
+    }
+
+    pub fn console_mgr(&self) -> Ref<ConsoleManager> {
+        self.console_mgr.borrow()
+    }
+
+    pub fn console_mgr_mut(&self) -> RefMut<ConsoleManager> {
+        self.console_mgr.borrow_mut()
+    }
+
+    pub fn deferred_mutations(&self) -> &DeferredMutations {
+        &self.deferred_mutations
+    }
+
+    /// Wind blowing on every [`Cloth`], in world space, on top of the wind of each cloth.
+    pub fn global_wind(&self) -> Vec3 {
+        self.global_wind.get()
+    }
+
+    pub fn set_global_wind(&self, wind: Vec3) {
+        self.global_wind.set(wind);
+    }
+
+    /// Applies a structural change at once, or defers it to the start of the next frame during a restricted phase.
+    /// See [`deferred`] for which changes are structural.
+    pub fn mutate_structurally(&self, kind: MutationKind, mutation: impl FnOnce() + 'static) {
+        if self.deferred_mutations.run_or_defer(kind, mutation) {
+            return;
+        }
+
+        #[cfg(debug_assertions)]
+        self.logger.log(
+            StandardLogLevel::Debug,
+            format!(
+                "{} requested during the {} phase; deferred to the next frame",
+                kind,
+                self.deferred_mutations.phase()
+            ),
+        );
+    }
+
+    /// Swaps the shader of a material, keeping the resources the new shader still declares.
+    /// Deferred to the next frame during a restricted phase.
+    pub fn set_material_shader(&self, material: &MaterialHandle, shader: ShaderHandle) {
+        let material = material.clone();
+        self.mutate_structurally(MutationKind::MaterialShader, move || {
+            let swapped = material.read().with_shader(
+                shader,
+                use_context().render_mgr_mut().pipeline_layout_cache(),
+            );
+            *material.write() = swapped;
+        });
+    }
+
+    /// Binds a resource to a material, e.g. a texture. Deferred to the next frame during a restricted phase.
+    /// A resource the material does not declare with a matching type is logged and ignored.
+    pub fn set_material_bind_property(
+        &self,
+        material: &MaterialHandle,
+        key: BindingPropKey,
+        resource: impl Into<BindGroupEntryResource>,
+    ) {
+        let material = material.clone();
+        let resource = resource.into();
+        self.mutate_structurally(MutationKind::MaterialBinding, move || {
+            if !material.write().set_bind_property(&key, resource) {
+                use_context().logger().log(
+                    StandardLogLevel::Warning,
+                    format!("material has no binding {:?} of the given type", key),
+                );
+            }
+        });
+    }
+
+    /// Replaces a prefab, e.g. after a hot reload, and re-instantiates the instances depending on it.
+    /// Both are deferred to the next frame during a restricted phase.
+    /// Fails without changing anything if the prefab would contain itself.
+    pub fn replace_prefab(&self, key: AssetKey, prefab: Prefab) -> Result<(), PrefabError> {
+        self.prefab_mgr().registry().check_insert(&key, &prefab)?;
+        self.mutate_structurally(MutationKind::AssetReplacement, move || {
+            let mut prefab_mgr = use_context().prefab_mgr_mut();
+            // Prefabs replaced in the meantime may have made it contain itself after all.
+            let result = prefab_mgr
+                .registry_mut()
+                .insert(key.clone(), prefab)
+                .and_then(|_| prefab_mgr.apply_prefab_changes(&key));
+
+            if let Err(err) = result {
+                use_context().logger().log(
+                    StandardLogLevel::Error,
+                    format!("failed to apply the changes of prefab {}: {}", key, err),
+                );
+            }
+        });
+        Ok(())
+    }
+
+    /// Returns the scratch arena of this frame, for temporary data that would otherwise be allocated every frame.
+    /// Allocations stay valid until the frame after next begins. The borrow must not be held across frames.
+    pub fn frame_alloc(&self) -> Ref<FrameArena> {
+        Ref::map(self.frame_arenas.borrow(), FrameArenas::current)
+    }
+
+    /// Usage of the frame arenas, for tuning [`EngineConfig::frame_arena_bytes`].
+    pub fn frame_arena_stats(&self) -> FrameArenaStats {
+        self.frame_arenas.borrow().stats()
+    }
+
+    /// Applies the structural changes deferred during the last frame.
+    fn apply_deferred_mutations(&self) {
+        self.deferred_mutations.enter_phase(FramePhase::Idle);
+        self.deferred_mutations.flush_now();
+    }
+
+    #[cfg(feature = "egui")]
+    pub fn egui_integration(&self) -> Ref<EguiIntegration> {
+        self.egui_integration.borrow()
+    }
+
+    #[cfg(feature = "egui")]
+    pub fn egui_integration_mut(&self) -> RefMut<EguiIntegration> {
+        self.egui_integration.borrow_mut()
+    }
+
+    /// Registers a closure building a debug UI with egui, run every frame before rendering.
+    #[cfg(feature = "egui")]
+    pub fn egui(&self, ui: impl FnMut(&egui::Context) + 'static) -> EguiUiId {
+        self.egui_integration_mut().add_ui(ui)
+    }
+
+    #[cfg(feature = "egui")]
+    pub fn remove_egui(&self, id: EguiUiId) {
+        self.egui_integration_mut().remove_ui(id);
+    }
+
+    /// Runs the debug UI closures and applies what egui asks of the platform.
+    #[cfg(feature = "egui")]
+    fn run_egui(&self) {
+        let clipboard_text = if self.egui_integration().is_paste_requested() {
+            self.platform_mgr_mut().get_clipboard_text().ok().flatten()
+        } else {
+            None
+        };
+        let (width, height) = {
+            let surface_config = self.gfx_ctx.surface_config.read();
+            (surface_config.width, surface_config.height)
+        };
+        let scale_factor = self.screen_mgr().scale_factor();
+        let frame =
+            self.egui_integration_mut()
+                .begin_frame(width, height, scale_factor, clipboard_text);
+        // The integration is not borrowed while the closures run, so that they can use it.
+        let frame = frame.run();
+        let platform_output = self.egui_integration_mut().end_frame(frame);
+
+        if !platform_output.copied_text.is_empty() {
+            if let Err(err) = self
+                .platform_mgr_mut()
+                .set_clipboard_text(&platform_output.copied_text)
+            {
+                self.logger.log(
+                    StandardLogLevel::Warning,
+                    format!("failed to copy the debug UI text: {}", err),
+                );
+            }
+        }
+
+        let cursor_icon = self
+            .egui_integration_mut()
+            .update_cursor_icon(platform_output.cursor_icon);
+
+        if let Some(cursor_icon) = cursor_icon {
+            match winit_cursor_icon(cursor_icon) {
+                Some(cursor_icon) => {
+                    self.window().set_cursor_visible(true);
+                    self.window().set_cursor_icon(cursor_icon);
+                }
+                None => self.window().set_cursor_visible(false),
+            }
+        }
+    }
+
+    /// Returns `true` if the debug UI has to be drawn again. Always `false` without the `egui` feature.
+    fn debug_ui_needs_repaint(&self) -> bool {
+        #[cfg(feature = "egui")]
+        return self.egui_integration().needs_repaint();
+        #[cfg(not(feature = "egui"))]
+        false
+    }
+
+    /// Exits the engine loop at the next event, firing the callbacks registered with [`on_exit`](Self::on_exit).
+    pub fn request_exit(&self) {
+        self.exit_requested.set(true);
+    }
+
+    pub fn is_exit_requested(&self) -> bool {
+        self.exit_requested.get()
+    }
+
+    /// The frame rate the engine loop targets, including a change not yet taken effect.
+    pub fn target_fps(&self) -> EngineTargetFps {
+        self.target_fps.get()
+    }
+
+    /// Changes the frame rate the running engine loop targets, from the next frame on, e.g. from a settings menu.
+    /// The present mode is left alone; see [`GfxContext::set_present_mode`] and [`DisplaySettings::vsync`].
+    pub fn set_target_fps(&self, target_fps: EngineTargetFps) {
+        self.target_fps.set(target_fps);
+        self.pending_target_fps.set(Some(target_fps));
+    }
+
+    /// Registers a callback fired once when the engine loop exits, after the last frame and once the GPU has
+    /// finished all of its work, e.g. to save the game or release external resources.
+    pub fn on_exit(&self, callback: impl FnOnce() + 'static) {
+        self.exit_callbacks.borrow_mut().push(Box::new(callback));
+    }
+
+    /// Registers a callback fired when the user asks to close the window. Returning `false` vetoes the close,
+    /// e.g. to ask about unsaved changes first and call [`request_exit`](Self::request_exit) afterwards.
+    pub fn on_close_requested(&self, callback: impl FnMut() -> bool + 'static) {
+        self.close_request_callbacks
+            .borrow_mut()
+            .push(Box::new(callback));
+    }
+
+    /// Asks every close request callback, returning `true` if none of them vetoes the close.
+    fn accept_close_request(&self) -> bool {
+        // Taken out, so that the callbacks may register new ones.
+        let mut callbacks = take(&mut *self.close_request_callbacks.borrow_mut());
+        // Stops at the first veto, so that a single dialog shows up.
+        let is_accepted = callbacks.iter_mut().all(|callback| callback());
+
+        let mut registered = self.close_request_callbacks.borrow_mut();
+        callbacks.append(&mut registered);
+        *registered = callbacks;
+
+        is_accepted
+    }
+
+    /// Saves the audio settings, waits for the GPU to finish and fires the exit callbacks.
+    /// Runs only once, even though the loop keeps delivering events after it has been asked to exit.
+    fn shutdown(&self) {
+        if self.is_shut_down.replace(true) {
+            return;
+        }
+
+        self.save_audio_settings();
+
+        let callbacks = take(&mut *self.exit_callbacks.borrow_mut());
+
+        if callbacks.is_empty() {
+            return;
+        }
+
+        self.gfx_ctx.device.poll(MaintainBase::Wait);
+
+        for callback in callbacks {
+            callback();
+        }
+    }
+
+    /// Recovers from a failure to acquire the surface texture, or returns the error if the engine must exit.
+    fn recover_surface(&self, err: SurfaceError) -> Result<(), EngineExecError> {
+        match SurfaceRecovery::for_error(&err) {
+            SurfaceRecovery::Reconfigure => {
+                // Left alone while minimized; the resize on restore configures it again.
+                if let Some(size) = surface_size(&self.screen_mgr()) {
+                    self.logger.log(
+                        StandardLogLevel::Debug,
+                        format!("surface {}; reconfiguring it", err),
+                    );
+                    self.gfx_ctx.device.poll(MaintainBase::Wait);
+                    self.gfx_ctx.resize(size);
+                    self.render_mgr_mut().resize(size);
+                }
+
+                Ok(())
+            }
+            SurfaceRecovery::SkipFrame => Ok(()),
+            SurfaceRecovery::Exit => Err(EngineExecError::SurfaceError(err)),
+        }
+    }
+
+    /// Captures the next frame into a timestamped file in the working directory.
+    /// See [`RenderManager::capture_next_frame`].
+    pub fn capture_next_frame(&self) -> PathBuf {
+        let path = timestamped_capture_path();
+        self.render_mgr_mut().capture_next_frame(&path);
+        path
+    }
+
+    /// Parses and executes a console command line on the spot. See [`ConsoleManager`].
+    pub fn execute_console_command(
+        &self,
+        line: &str,
+    ) -> Result<Option<String>, ConsoleCommandError> {
+        let args = parse_command_line(line)?;
+        let (name, args) = match args.split_first() {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+        let mut command = self
+            .console_mgr_mut()
+            .commands_mut()
+            .take(name)
+            .ok_or_else(|| ConsoleCommandError::UnknownCommand(name.clone()))?;
+
+        // The console is not borrowed while the command runs, so that the command can use it.
+        let result = command.execute(args);
+        self.console_mgr_mut().commands_mut().restore(command);
+        result.map_err(ConsoleCommandError::Failed)
+    }
+
+    /// Runs the systems of the registry for this frame.
+    fn run_registered_systems(&self) {
+        let mut frame = {
+            let mut world = self.world_mut();
+            self.system_registry_mut().begin_frame(&mut world)
+        };
+
+        // The registry is not borrowed while the systems run, so that they can register and remove systems.
+        frame.run(&self.world());
+        self.system_registry_mut().end_frame(frame);
+    }
+
+    /// Runs the fixed updates due this frame: each dispatches the `FixedUpdate` event, runs the fixed systems,
+    /// then simulates the cloth.
+    fn run_fixed_updates(&self, update_cloths: &mut UpdateClothsSystem) {
+        let fixed_steps = self.time_mgr().fixed_steps();
+
+        if fixed_steps == 0 {
+            return;
+        }
+
+        let mut frame = {
+            let mut world = self.world_mut();
+            self.fixed_system_registry_mut().begin_frame(&mut world)
+        };
+
+        for _ in 0..fixed_steps {
+            self.event_mgr().dispatch(&event_types::FixedUpdate);
+            frame.run(&self.world());
+            update_cloths.run_now(&self.world());
+        }
+
+        self.fixed_system_registry_mut().end_frame(frame);
+    }
+
+    /// Executes the submitted console commands, takes in the new logs and lays the console out.
+    fn update_console(&self) {
+        loop {
+            let line = match self.console_mgr_mut().take_submitted() {
+                Some(line) => line,
+                None => break,
+            };
+
+            self.logger
+                .log_target(StandardLogLevel::Info, "console", format!("> {}", line));
+
+            match self.execute_console_command(&line) {
+                Ok(Some(output)) => {
+                    self.logger
+                        .log_target(StandardLogLevel::Info, "console", output)
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    self.logger
+                        .log_target(StandardLogLevel::Error, "console", err.to_string())
+                }
+            }
+        }
+
+        let frame = self.time_mgr().frame_index();
+        let mut console_mgr = self.console_mgr_mut();
+        console_mgr.update(frame);
+
+        let is_open = console_mgr.is_open();
+        let fade = Duration::from_millis(100);
+        let (overlay, is_shown) = match console_mgr.overlay() {
+            Some(overlay) => overlay,
+            None if is_open => {
+                let mut render_mgr = self.render_mgr_mut();
+                let console_overlay = ConsoleOverlay::new(
+                    self.gfx_ctx.clone(),
+                    render_mgr.bind_group_layout_cache(),
+                    console_mgr.draw_list().clone(),
+                );
+                let overlay = render_mgr.overlays_mut().add_faded_in(
+                    CONSOLE_OVERLAY_PRIORITY,
+                    OverlayContent::Shader(Box::new(console_overlay)),
+                    fade,
+                );
+                console_mgr.set_overlay(overlay, true);
+                (overlay, true)
+            }
+            None => return,
+        };
+
+        // Fades only start when the console is toggled; restarting them every frame would never finish them.
+        if is_open != is_shown {
+            let mut render_mgr = self.render_mgr_mut();
+
+            if is_open {
+                render_mgr.overlays_mut().fade_in(overlay, fade);
+            } else {
+                render_mgr.overlays_mut().fade_out(overlay, fade);
+            }
+
+            console_mgr.set_overlay(overlay, is_open);
+        }
+
+        let is_visible = is_open
+            || self
+                .render_mgr()
+                .overlays()
+                .opacity(overlay)
+                .is_some_and(|opacity| 0.0 < opacity);
+
+        if !is_visible {
+            return;
+        }
+
+        let (width, height) = {
+            let surface_config = self.gfx_ctx.surface_config.read();
+            (surface_config.width as f32, surface_config.height as f32)
+        };
+        let scale_factor = self.screen_mgr().scale_factor() as f32;
+        let draw_list = ConsoleDrawList::build(
+            &console_mgr,
+            width,
+            height,
+            scale_factor,
+            &mut self.glyph_mgr_mut(),
+            self.render_mgr_mut().bind_group_layout_cache(),
+        );
+        *console_mgr.draw_list().lock() = draw_list;
+    }
+
+    /// Starts observing the changes of a component registered through [`world_ext::register_tracked`].
+    pub fn changes<T>(&self) -> ChangeReader<T>
+    where
+        T: Component,
+        T::Storage: Tracked,
+    {
+        ChangeReader::new(use_context().clone())
+    }
+}
+
+/// Registers the components of the engine.
+fn register_components(world: &mut World) {
+    world.register::<Object>();
+    world_ext::register_tracked::<Transform>(world);
+
+    world.register::<Camera>();
+    world.register::<DirectionalLight>();
+    world.register::<PointLight>();
+    world.register::<SpotLight>();
+    world.register::<Layers>();
+    world.register::<MeshRenderer>();
+    world.register::<ParticleSystem>();
+    world.register::<Terrain>();
+    world.register::<PlanarReflection>();
+    world.register::<WaterSurface>();
+    world.register::<FogVolume>();
+    world.register::<Buoyancy>();
+    world.register::<Cloth>();
+    world.register::<ClothCollider>();
+    world.register::<PathFollower>();
+    world.register::<NavAgent>();
+    world.register::<NavMeshSource>();
+    world.register::<HlodStatic>();
+    world.register::<HlodProxy>();
+    world.register::<LineRenderer>();
+    world.register::<SpriteRenderer>();
+    world.register::<NinePatchRenderer>();
+    world.register::<TextRenderer>();
+    world.register::<PropertyAnimator>();
+    world.register::<AnimationPlayer>();
+    world.register::<IkConstraint>();
+    world.register::<BoneAttachment>();
+    world.register::<UIElementRenderer>();
+    world.register::<UITextRenderer>();
+
+    world.register::<UISize>();
+    world.register::<UIScaler>();
+    world.register::<UIElement>();
+}
+
+/// A windowless context made the current one by [`create_test_context`]. Only one exists at a time, since
+/// [`use_context`] is shared by all the tests.
+#[cfg(test)]
+pub(crate) struct TestContext {
+    ctx: ContextHandle,
+    _lock: parking_lot::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestContext {
+    type Target = ContextHandle;
+
+    fn deref(&self) -> &Self::Target {
+        &self.ctx
+    }
+}
+
+/// Creates a context without a window for tests and makes it the current one, waiting for the tests holding the
+/// previous one to end. Returns `None` if there is nothing to render on.
+#[cfg(test)]
+pub(crate) fn create_test_context() -> Option<TestContext> {
+    static LOCK: parking_lot::Mutex<()> = parking_lot::const_mutex(());
+
+    let lock = LOCK.lock();
+    let config = EngineConfig::default();
+    let size = PhysicalSize::new(config.width, config.height);
+    let gfx_ctx = match pollster::block_on(GfxContext::new_headless(&config.gfx, size)) {
+        Ok(gfx_ctx) => gfx_ctx,
+        Err(GfxContextCreationError::AdapterNotFound) => return None,
+        Err(err) => panic!("{}", err),
+    };
+    let ctx = ContextHandle::new(Context::with_window(None, gfx_ctx, &config));
+    register_components(&mut ctx.world_mut());
+
+    // The previous context is leaked; its handles may outlive the test that created it.
+    unsafe {
+        CONTEXT.write(ctx.clone());
+    }
+
+    Some(TestContext { ctx, _lock: lock })
 }
 
 pub struct Engine {
@@ -241,42 +1318,56 @@ impl Engine {
         let event_loop = EventLoop::new();
         let window = WindowBuilder::new()
             .with_visible(false)
-            .with_title(config.title)
+            .with_title(&config.title)
             .with_resizable(config.resizable)
             .with_inner_size(LogicalSize::new(config.width, config.height))
             .build(&event_loop)
             .unwrap();
-        let gfx_ctx = GfxContext::new(&window).await?;
-        let ctx = ContextHandle::new(Context::new(window, gfx_ctx, config.width, config.height));
+        let gfx_ctx = GfxContext::new(&window, &config.gfx).await?;
+        let ctx = ContextHandle::new(Context::new(window, gfx_ctx, &config));
 
         unsafe {
             CONTEXT.write(ctx.clone());
         }
 
-        {
-            let mut world = ctx.world_mut();
-            world.register::<Object>();
-            world.register::<Transform>();
-
-            world.register::<Camera>();
-            world.register::<MeshRenderer>();
-            world.register::<UIElementRenderer>();
-            world.register::<UITextRenderer>();
-
-            world.register::<UISize>();
-            world.register::<UIScaler>();
-            world.register::<UIElement>();
-        }
+        register_components(&mut ctx.world_mut());
 
         {
-            let scale_factor = ctx.window.scale_factor();
+            let scale_factor = ctx.window().scale_factor();
             let physical_size =
                 LogicalSize::new(config.width, config.height).to_physical(scale_factor);
             let mut screen_mgr = ctx.screen_mgr_mut();
             screen_mgr.update_scale_factor(scale_factor, physical_size);
+            screen_mgr.update_render_scale(config.render_scale);
+            ctx.input_mgr_mut()
+                .mouse_mut()
+                .set_scale_factor(scale_factor);
+            ctx.gfx_ctx().set_vsync(config.vsync);
             ctx.gfx_ctx().resize(physical_size);
         }
 
+        {
+            let tier_report = RenderTierReport::detect(ctx.gfx_ctx(), config.render_tier_benchmark);
+            ctx.logger()
+                .log(StandardLogLevel::Info, tier_report.to_string());
+            ctx.render_mgr_mut().set_tier_report(tier_report);
+        }
+
+        if let Some(path) = config
+            .console
+            .as_ref()
+            .and_then(|console| console.crash_report_path.clone())
+        {
+            install_crash_report_hook(path);
+        }
+
+        for override_ in &config.overrides {
+            ctx.logger().log(
+                StandardLogLevel::Info,
+                format!("engine config overridden: {}", override_),
+            );
+        }
+
         Ok(Self { event_loop, ctx })
     }
 
@@ -284,6 +1375,20 @@ impl Engine {
         self.ctx.clone()
     }
 
+    /// Replays a frame capture on a headless device, returning the rendered frame.
+    /// Only the first `command_limit` draws are rendered if given. Asset textures are replaced by placeholders;
+    /// use [`FrameReplayer`] directly to provide them.
+    pub async fn replay_capture(
+        path: impl AsRef<Path>,
+        command_limit: Option<usize>,
+    ) -> Result<RgbaImage, FrameReplayError> {
+        let capture = FrameCapture::load(path)?;
+        let device = HeadlessDevice::new(Backends::all()).await?;
+        FrameReplayer::new(&device.device, &device.queue).render(&capture, command_limit)
+    }
+
+    /// Runs the engine loop until it exits. Fails if the surface can no longer be drawn to, e.g. out of memory;
+    /// a lost or outdated surface is configured again instead.
     pub fn run(
         self,
         loop_mode: EngineLoopMode,
@@ -293,58 +1398,203 @@ impl Engine {
         let mut update_ui_scaler = UpdateUIScaler::new(self.ctx.clone());
         let mut update_ui_element = UpdateUIElement::new(self.ctx.clone());
         let mut update_ui_raycast_grid = UpdateUIRaycastGrid::new(self.ctx.clone());
+        let mut update_path_followers = UpdatePathFollowersSystem::new(self.ctx.clone());
+        let mut update_nav_agents = UpdateNavAgentsSystem::new(self.ctx.clone());
+        let mut update_buoyancy = UpdateBuoyancySystem::new(self.ctx.clone());
+        let mut update_animation_players = UpdateAnimationPlayersSystem::new(self.ctx.clone());
+        let mut update_bone_attachments = UpdateBoneAttachmentsSystem::new(self.ctx.clone());
+        let mut update_property_animators = UpdatePropertyAnimatorsSystem::new(self.ctx.clone());
+        let mut update_ik_constraints = UpdateIkConstraintsSystem::new(self.ctx.clone());
+        let mut update_cloths = UpdateClothsSystem::new(self.ctx.clone());
+        let mut update_cloth_meshes = UpdateClothMeshesSystem::new(self.ctx.clone());
+        let mut update_skinned_meshes = UpdateSkinnedMeshesSystem::new(self.ctx.clone());
+        let mut update_lights = UpdateLightsSystem::new(self.ctx.clone());
         let mut update_camera_transform_buffer_system =
             UpdateCameraTransformBufferSystem::new(self.ctx.clone());
         let mut render_system = RenderSystem::new(
-            &self.ctx.gfx_ctx.device,
+            &self.ctx.gfx_ctx,
             self.ctx.render_mgr_mut().bind_group_layout_cache(),
         );
 
-        self.ctx.window.set_visible(true);
+        self.ctx.window().set_visible(true);
 
-        let window_id = self.ctx.window.id();
+        let window_id = self.ctx.window().id();
         let mut window_occluded = false;
-        let mut target_frame_interval = TargetFrameInterval::new(
-            match target_fps {
-                EngineTargetFps::VSync => None,
-                EngineTargetFps::MilliHertz(millihertz) => Some(millihertz),
-                EngineTargetFps::Unlimited => None,
-            },
-            self.ctx.window(),
-        );
+        self.ctx.target_fps.set(target_fps);
+        self.ctx.pending_target_fps.set(None);
+        let mut focused_frame_millihertz = target_fps.frame_millihertz();
+        let mut target_frame_interval =
+            TargetFrameInterval::new(focused_frame_millihertz, self.ctx.window());
+        let mut is_throttled = false;
         let mut last_frame_time = Instant::now();
+        self.ctx
+            .animation_burst_mut()
+            .set_frame_interval(target_frame_interval.interval());
+        let mut render_config_watcher = RenderConfigWatcher::next_to_executable();
+        let mut non_finite_matrix_warning = RateLimiter::new(Duration::from_secs(1));
+        // Catches refresh rate changes no window event reports, e.g. a display mode switch.
+        let mut refresh_rate_check = RateLimiter::new(Duration::from_secs(1));
+
+        if let Some(watcher) = &mut render_config_watcher {
+            self.ctx.reload_render_config(watcher);
+        }
+
+        self.ctx.apply_quality_preset();
+        self.ctx.load_audio_settings();
+
+        let mut exec_error = None;
+        let exec_error_slot = &mut exec_error;
+        let mut event_loop = self.event_loop;
 
-        self.event_loop.run(move |event, _, control_flow| {
+        event_loop.run_return(move |event, _, control_flow| {
             *control_flow = match loop_mode {
-                EngineLoopMode::Wait => ControlFlow::Wait,
+                // Animations in progress wake the loop up at the next frame of the burst.
+                EngineLoopMode::Wait => match self.ctx.animation_burst().next_frame() {
+                    Some(next_frame) => ControlFlow::WaitUntil(next_frame),
+                    None => ControlFlow::Wait,
+                },
+                // Sleeps between the frames instead of spinning, while throttled.
+                EngineLoopMode::Poll if is_throttled => {
+                    ControlFlow::WaitUntil(last_frame_time + target_frame_interval.interval())
+                }
                 EngineLoopMode::Poll => ControlFlow::Poll,
             };
 
+            if self.ctx.is_exit_requested() {
+                self.ctx.shutdown();
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+
+            // The events consumed by the debug UI do not reach the game input.
+            #[cfg(feature = "egui")]
+            if let Event::WindowEvent {
+                event,
+                window_id: id,
+            } = &event
+            {
+                if *id == window_id && self.ctx.egui_integration_mut().handle_window_event(event) {
+                    return;
+                }
+            }
+
+            if let Some(target_fps) = self.ctx.pending_target_fps.take() {
+                focused_frame_millihertz = target_fps.frame_millihertz();
+
+                // A throttled loop picks the new target up once the focus comes back.
+                if !is_throttled {
+                    target_frame_interval =
+                        TargetFrameInterval::new(focused_frame_millihertz, self.ctx.window());
+                    self.ctx
+                        .animation_burst_mut()
+                        .set_frame_interval(target_frame_interval.interval());
+                }
+            }
+
+            let is_moved = matches!(
+                event,
+                Event::WindowEvent {
+                    event: WindowEvent::Moved(_),
+                    window_id: id,
+                } if id == window_id
+            );
+
+            if (is_moved || refresh_rate_check.allow(Instant::now()).is_some())
+                && target_frame_interval.update_window(self.ctx.window())
+            {
+                self.ctx
+                    .animation_burst_mut()
+                    .set_frame_interval(target_frame_interval.interval());
+            }
+
             match event {
                 Event::MainEventsCleared => {
                     if loop_mode == EngineLoopMode::Wait {
+                        let other_active = self.ctx.task_scheduler().is_active()
+                            || self.ctx.render_mgr().overlays().is_animating()
+                            || self.ctx.console_mgr().is_open()
+                            || self.ctx.debug_ui_needs_repaint()
+                            || update_path_followers.is_animating()
+                            || update_nav_agents.is_animating()
+                            || update_buoyancy.is_animating()
+                            || update_animation_players.is_animating()
+                            || update_property_animators.is_animating()
+                            || update_cloths.is_animating();
+
+                        if self
+                            .ctx
+                            .animation_burst()
+                            .should_wake(Instant::now(), other_active)
+                        {
+                            self.ctx.window().request_redraw();
+                        }
+
                         return;
                     }
 
                     let now = Instant::now();
+                    let next_frame_time = last_frame_time + target_frame_interval.interval();
+
+                    if now < next_frame_time {
+                        // Sleeps through most of the wait, then spins the rest of it for accuracy.
+                        if let Some(wake_time) = frame_pacing_wake_time(now, next_frame_time) {
+                            *control_flow = ControlFlow::WaitUntil(wake_time);
+                        }
 
-                    if now - last_frame_time < target_frame_interval.interval() {
                         return;
                     }
 
-                    last_frame_time = now;
+                    last_frame_time =
+                        advance_frame_time(last_frame_time, target_frame_interval.interval(), now);
+
+                    if self.ctx.apply_pending_display_settings() {
+                        return;
+                    }
+
+                    self.ctx.frame_arenas.borrow_mut().begin_frame();
+
+                    if let Some(watcher) = &mut render_config_watcher {
+                        self.ctx.reload_render_config(watcher);
+                    }
+
+                    self.ctx.reload_watched_shaders();
 
                     {
                         let mut time_mgr = self.ctx.time_mgr_mut();
                         time_mgr.update();
                     }
 
+                    {
+                        let unscaled_delta_time = self.ctx.time_mgr().unscaled_delta_duration();
+                        self.ctx
+                            .render_mgr_mut()
+                            .overlays_mut()
+                            .update(unscaled_delta_time);
+                    }
+
+                    self.ctx.update_console();
+                    self.ctx.update_hlod_bake();
+                    self.ctx.apply_deferred_mutations();
+                    self.ctx.deferred_mutations().enter_phase(FramePhase::Input);
+
                     {
                         let mut input_mgr = self.ctx.input_mgr_mut();
                         input_mgr.poll();
                     }
 
+                    self.ctx
+                        .deferred_mutations()
+                        .enter_phase(FramePhase::Update);
+                    self.ctx.run_fixed_updates(&mut update_cloths);
                     self.ctx.event_mgr().dispatch(&event_types::Update);
+                    self.ctx.run_registered_systems();
+
+                    update_path_followers.run_now(&self.ctx.world());
+                    update_path_followers.dispatch_reached_markers();
+                    update_nav_agents.run_now(&self.ctx.world());
+                    update_buoyancy.run_now(&self.ctx.world());
+                    update_animation_players.run_now(&self.ctx.world());
+                    update_bone_attachments.run_now(&self.ctx.world());
 
                     make_ui_scaler_dirty.run_now(&self.ctx.world());
                     update_ui_scaler.run_now(&self.ctx.world());
@@ -353,25 +1603,72 @@ impl Engine {
 
                     self.ctx.ui_event_mgr_mut().handle_mouse_move();
 
-                    {
+                    let non_finite_matrices = {
                         let world = self.ctx.world();
                         let mut object_mgr = self.ctx.object_mgr_mut();
+                        object_mgr.compact_incremental();
+
                         let object_hierarchy = object_mgr.object_hierarchy_mut();
 
                         object_hierarchy.copy_dirty_to_current_frame();
 
                         let transforms = world.read_component::<Transform>();
-                        object_hierarchy.update_object_matrices(|entity| transforms.get(entity));
+                        object_hierarchy.update_object_matrices(|entity| transforms.get(entity))
+                    };
+                    self.ctx.report_non_finite_matrices(
+                        non_finite_matrices,
+                        &mut non_finite_matrix_warning,
+                    );
+
+                    {
+                        let unscaled_delta_time = self.ctx.time_mgr().unscaled_delta_duration();
+                        self.ctx
+                            .world_streaming_mgr_mut()
+                            .update(unscaled_delta_time);
                     }
 
                     self.ctx.event_mgr().dispatch(&event_types::LateUpdate);
 
-                    if window_occluded {
-                        return;
+                    update_property_animators.run_now(&self.ctx.world());
+                    update_property_animators.dispatch_fired_events();
+                    update_ik_constraints.run_now(&self.ctx.world());
+
+                    {
+                        let delta_time = self.ctx.time_mgr().delta_time();
+                        self.ctx.audio_mgr_mut().update_spatial(delta_time);
                     }
 
-                    update_camera_transform_buffer_system.run_now(&self.ctx.world());
-                    render_system.run_now(&self.ctx.world());
+                    #[cfg(feature = "egui")]
+                    self.ctx.run_egui();
+
+                    self.ctx
+                        .deferred_mutations()
+                        .enter_phase(FramePhase::Render);
+
+                    if !window_occluded {
+                        update_cloth_meshes.run_now(&self.ctx.world());
+                        update_skinned_meshes.run_now(&self.ctx.world());
+                        update_lights.run_now(&self.ctx.world());
+                        update_camera_transform_buffer_system.run_now(&self.ctx.world());
+                        render_system.run_now(&self.ctx.world());
+                    }
+
+                    self.ctx.deferred_mutations().enter_phase(FramePhase::Idle);
+
+                    if let Some(err) = render_system.take_surface_error() {
+                        if let Err(err) = self.ctx.recover_surface(err) {
+                            *exec_error_slot = Some(err);
+                            self.ctx.shutdown();
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                    }
+
+                    self.ctx
+                        .task_scheduler_mut()
+                        .run_frame(target_frame_interval.interval(), now.elapsed());
+
+                    self.ctx.input_mgr_mut().end_frame();
 
                     return;
                 }
@@ -380,17 +1677,57 @@ impl Engine {
                         return;
                     }
 
+                    if self.ctx.apply_pending_display_settings() {
+                        self.ctx.window().request_redraw();
+                        return;
+                    }
+
+                    let frame_start = Instant::now();
+                    self.ctx.frame_arenas.borrow_mut().begin_frame();
+
+                    if let Some(watcher) = &mut render_config_watcher {
+                        self.ctx.reload_render_config(watcher);
+                    }
+
+                    self.ctx.reload_watched_shaders();
+
                     {
+                        let max_delta_time = self.ctx.animation_burst().max_delta_time();
                         let mut time_mgr = self.ctx.time_mgr_mut();
-                        time_mgr.update();
+                        time_mgr.update_clamped(max_delta_time);
                     }
 
+                    {
+                        let unscaled_delta_time = self.ctx.time_mgr().unscaled_delta_duration();
+                        self.ctx
+                            .render_mgr_mut()
+                            .overlays_mut()
+                            .update(unscaled_delta_time);
+                    }
+
+                    self.ctx.update_console();
+                    self.ctx.update_hlod_bake();
+                    self.ctx.apply_deferred_mutations();
+                    self.ctx.deferred_mutations().enter_phase(FramePhase::Input);
+
                     {
                         let mut input_mgr = self.ctx.input_mgr_mut();
                         input_mgr.poll();
                     }
 
+                    self.ctx
+                        .deferred_mutations()
+                        .enter_phase(FramePhase::Update);
+                    self.ctx.run_fixed_updates(&mut update_cloths);
                     self.ctx.event_mgr().dispatch(&event_types::Update);
+                    self.ctx.run_registered_systems();
+
+                    update_path_followers.run_now(&self.ctx.world());
+                    update_path_followers.dispatch_reached_markers();
+                    update_nav_agents.run_now(&self.ctx.world());
+                    update_buoyancy.run_now(&self.ctx.world());
+                    update_animation_players.run_now(&self.ctx.world());
+                    update_bone_attachments.run_now(&self.ctx.world());
 
                     make_ui_scaler_dirty.run_now(&self.ctx.world());
                     update_ui_scaler.run_now(&self.ctx.world());
@@ -399,21 +1736,96 @@ impl Engine {
 
                     self.ctx.ui_event_mgr_mut().handle_mouse_move();
 
-                    {
+                    let non_finite_matrices = {
                         let world = self.ctx.world();
                         let mut object_mgr = self.ctx.object_mgr_mut();
+                        object_mgr.compact_incremental();
+
                         let object_hierarchy = object_mgr.object_hierarchy_mut();
 
                         object_hierarchy.copy_dirty_to_current_frame();
 
                         let transforms = world.read_component::<Transform>();
-                        object_hierarchy.update_object_matrices(|entity| transforms.get(entity));
+                        object_hierarchy.update_object_matrices(|entity| transforms.get(entity))
+                    };
+                    self.ctx.report_non_finite_matrices(
+                        non_finite_matrices,
+                        &mut non_finite_matrix_warning,
+                    );
+
+                    {
+                        let unscaled_delta_time = self.ctx.time_mgr().unscaled_delta_duration();
+                        self.ctx
+                            .world_streaming_mgr_mut()
+                            .update(unscaled_delta_time);
                     }
 
                     self.ctx.event_mgr().dispatch(&event_types::LateUpdate);
 
+                    update_property_animators.run_now(&self.ctx.world());
+                    update_property_animators.dispatch_fired_events();
+                    update_ik_constraints.run_now(&self.ctx.world());
+
+                    {
+                        let delta_time = self.ctx.time_mgr().delta_time();
+                        self.ctx.audio_mgr_mut().update_spatial(delta_time);
+                    }
+
+                    #[cfg(feature = "egui")]
+                    self.ctx.run_egui();
+
+                    self.ctx
+                        .deferred_mutations()
+                        .enter_phase(FramePhase::Render);
+
+                    update_cloth_meshes.run_now(&self.ctx.world());
+                    update_skinned_meshes.run_now(&self.ctx.world());
+                    update_lights.run_now(&self.ctx.world());
                     update_camera_transform_buffer_system.run_now(&self.ctx.world());
                     render_system.run_now(&self.ctx.world());
+                    self.ctx.deferred_mutations().enter_phase(FramePhase::Idle);
+
+                    if let Some(err) = render_system.take_surface_error() {
+                        if let Err(err) = self.ctx.recover_surface(err) {
+                            *exec_error_slot = Some(err);
+                            self.ctx.shutdown();
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                    }
+
+                    self.ctx
+                        .task_scheduler_mut()
+                        .run_frame(target_frame_interval.interval(), frame_start.elapsed());
+
+                    self.ctx.input_mgr_mut().end_frame();
+
+                    {
+                        let other_active = self.ctx.task_scheduler().is_active()
+                            || self.ctx.render_mgr().overlays().is_animating()
+                            || self.ctx.console_mgr().is_open()
+                            || self.ctx.debug_ui_needs_repaint()
+                            || update_path_followers.is_animating()
+                            || update_nav_agents.is_animating()
+                            || update_buoyancy.is_animating()
+                            || update_animation_players.is_animating()
+                            || update_property_animators.is_animating()
+                            || update_cloths.is_animating();
+                        let mut animation_burst = self.ctx.animation_burst_mut();
+                        let was_bursting = animation_burst.is_bursting();
+                        animation_burst.end_frame(Instant::now(), other_active);
+
+                        if was_bursting != animation_burst.is_bursting() {
+                            self.ctx.logger().log(
+                                StandardLogLevel::Debug,
+                                if was_bursting {
+                                    "animation burst ended; waiting for events"
+                                } else {
+                                    "animation burst started; drawing continuously"
+                                },
+                            );
+                        }
+                    }
 
                     return;
                 }
@@ -425,10 +1837,60 @@ impl Engine {
 
                     return;
                 }
+                Event::WindowEvent {
+                    event: WindowEvent::Focused(is_focused),
+                    window_id: id,
+                } if id == window_id => {
+                    if !is_focused {
+                        let mut input_mgr = self.ctx.input_mgr_mut();
+                        input_mgr.keyboard_mut().handle_focus_lost();
+                        input_mgr.mouse_mut().handle_focus_lost();
+                    }
+
+                    self.ctx.screen_mgr_mut().update_focus(is_focused);
+
+                    if let Some(throttled_millihertz) = self.ctx.throttle_when_unfocused {
+                        let focused =
+                            TargetFrameInterval::new(focused_frame_millihertz, self.ctx.window());
+                        let throttled =
+                            TargetFrameInterval::new(Some(throttled_millihertz), self.ctx.window());
+
+                        // Throttling never speeds up a target that is already slower.
+                        is_throttled = !is_focused && focused.interval() < throttled.interval();
+                        target_frame_interval = if is_throttled { throttled } else { focused };
+                        self.ctx
+                            .animation_burst_mut()
+                            .set_frame_interval(target_frame_interval.interval());
+                    }
+
+                    self.ctx
+                        .event_mgr()
+                        .dispatch(&event_types::FocusChanged { is_focused });
+
+                    return;
+                }
                 Event::WindowEvent {
                     event: WindowEvent::KeyboardInput { input, .. },
                     window_id: id,
                 } if id == window_id => {
+                    self.ctx.render_mgr_mut().record_input(Instant::now());
+
+                    if self.ctx.console_mgr_mut().handle_keyboard_input(&input) {
+                        return;
+                    }
+
+                    if input.state == ElementState::Pressed
+                        && input.virtual_keycode.is_some()
+                        && input.virtual_keycode == self.ctx.frame_capture_key
+                    {
+                        let path = self.ctx.capture_next_frame();
+                        self.ctx.logger().log(
+                            StandardLogLevel::Info,
+                            format!("capturing the next frame into {}", path.display()),
+                        );
+                    }
+
+                    self.ctx.platform_mgr_mut().handle_keyboard_input(&input);
                     self.ctx
                         .input_mgr_mut()
                         .keyboard_mut()
@@ -437,17 +1899,44 @@ impl Engine {
                     return;
                 }
                 Event::WindowEvent {
-                    event: WindowEvent::CursorEntered { .. },
+                    event: WindowEvent::ReceivedCharacter(character),
                     window_id: id,
                 } if id == window_id => {
-                    // TODO: Handle cursor entered event here.
+                    self.ctx
+                        .console_mgr_mut()
+                        .handle_received_character(character);
 
                     return;
                 }
                 Event::WindowEvent {
-                    event: WindowEvent::CursorLeft { .. },
+                    event: WindowEvent::ModifiersChanged(modifiers),
                     window_id: id,
                 } if id == window_id => {
+                    self.ctx
+                        .platform_mgr_mut()
+                        .handle_modifiers_changed(modifiers);
+
+                    return;
+                }
+                Event::WindowEvent {
+                    event: event @ WindowEvent::CursorEntered { .. },
+                    window_id: id,
+                } if id == window_id => {
+                    self.ctx
+                        .input_mgr_mut()
+                        .mouse_mut()
+                        .handle_window_event(&event);
+
+                    return;
+                }
+                Event::WindowEvent {
+                    event: event @ WindowEvent::CursorLeft { .. },
+                    window_id: id,
+                } if id == window_id => {
+                    self.ctx
+                        .input_mgr_mut()
+                        .mouse_mut()
+                        .handle_window_event(&event);
                     self.ctx.ui_event_mgr_mut().handle_mouse_leave();
 
                     return;
@@ -456,6 +1945,7 @@ impl Engine {
                     event: event @ WindowEvent::CursorMoved { .. },
                     window_id: id,
                 } if id == window_id => {
+                    self.ctx.render_mgr_mut().record_input(Instant::now());
                     self.ctx
                         .input_mgr_mut()
                         .mouse_mut()
@@ -475,6 +1965,7 @@ impl Engine {
                     event: event @ WindowEvent::MouseInput { .. },
                     window_id: id,
                 } if id == window_id => {
+                    self.ctx.render_mgr_mut().record_input(Instant::now());
                     self.ctx
                         .input_mgr_mut()
                         .mouse_mut()
@@ -486,6 +1977,7 @@ impl Engine {
                     event: event @ WindowEvent::MouseWheel { .. },
                     window_id: id,
                 } if id == window_id => {
+                    self.ctx.render_mgr_mut().record_input(Instant::now());
                     self.ctx
                         .input_mgr_mut()
                         .mouse_mut()
@@ -497,6 +1989,10 @@ impl Engine {
                     event: WindowEvent::Resized(inner_size),
                     window_id: id,
                 } if id == window_id => {
+                    if self.ctx.display_mgr_mut().consume_expected_size(inner_size) {
+                        return;
+                    }
+
                     self.ctx.screen_mgr_mut().update_size(inner_size);
 
                     if inner_size.width == 0 || inner_size.height == 0 {
@@ -520,10 +2016,17 @@ impl Engine {
                         },
                     window_id: id,
                 } if id == window_id => {
-                    target_frame_interval.update_window(&self.ctx.window);
+                    target_frame_interval.update_window(self.ctx.window());
+                    self.ctx
+                        .animation_burst_mut()
+                        .set_frame_interval(target_frame_interval.interval());
                     self.ctx
                         .screen_mgr_mut()
                         .update_scale_factor(scale_factor, *new_inner_size);
+                    self.ctx
+                        .input_mgr_mut()
+                        .mouse_mut()
+                        .set_scale_factor(scale_factor);
 
                     if new_inner_size.width == 0 || new_inner_size.height == 0 {
                         window_occluded = true;
@@ -541,21 +2044,47 @@ impl Engine {
                     event: WindowEvent::CloseRequested,
                     window_id: id,
                 } if id == window_id => {
-                    *control_flow = ControlFlow::Exit;
+                    if self.ctx.accept_close_request() {
+                        self.ctx.shutdown();
+                        *control_flow = ControlFlow::Exit;
+                    }
 
                     return;
                 }
                 _ => return,
             }
-        })
+        });
+
+        match exec_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
     }
 }
 
-pub struct EngineConfig {
-    pub title: String,
-    pub resizable: bool,
-    pub width: u32,
-    pub height: u32,
+/// Writes the captured logs into `path` when the main thread panics, then runs the previous hook.
+fn install_crash_report_hook(path: PathBuf) {
+    let main_thread = std::thread::current().id();
+    let previous_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        // The context is only usable from the main thread, and may be borrowed by the panicking code.
+        if std::thread::current().id() == main_thread {
+            let mut header = info.to_string();
+
+            if let Ok(render_mgr) = use_context().render_mgr.try_borrow() {
+                if let Some(tier_report) = render_mgr.tier_report() {
+                    header += &format!("\n{}", tier_report);
+                }
+            }
+
+            if let Ok(mut console_mgr) = use_context().console_mgr.try_borrow_mut() {
+                let _ = console_mgr.write_crash_report(&path, &header);
+            }
+        }
+
+        previous_hook(info);
+    }));
 }
 
 #[derive(Error, Debug)]
@@ -595,6 +2124,17 @@ pub enum EngineTargetFps {
     Unlimited,
 }
 
+impl EngineTargetFps {
+    /// The fixed frame rate, or `None` to follow the refresh rate of the monitor.
+    pub fn frame_millihertz(self) -> Option<NonZeroU32> {
+        match self {
+            Self::VSync => None,
+            Self::MilliHertz(millihertz) => Some(millihertz),
+            Self::Unlimited => None,
+        }
+    }
+}
+
 impl Default for EngineTargetFps {
     fn default() -> Self {
         Self::VSync
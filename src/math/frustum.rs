@@ -0,0 +1,137 @@
+use super::{Mat4, Vec3, Vec4};
+
+/// View frustum represented as 6 inward-facing planes (left, right, bottom, top, near, far).
+/// Each plane is stored as `(normal, distance)` packed into a [`Vec4`]; a point `p` is inside if `dot(normal, p) + distance >= 0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the frustum planes from a view-projection matrix. Since this engine uses row vectors
+    /// (`clip = position * matrix`), the planes are derived from the columns of the matrix.
+    /// The near plane is taken from `-w <= z`, which is conservative for both `[-1, 1]` and `[0, 1]` depth ranges.
+    pub fn from_view_projection(matrix: &Mat4) -> Self {
+        let c0 = matrix.column(0);
+        let c1 = matrix.column(1);
+        let c2 = matrix.column(2);
+        let c3 = matrix.column(3);
+
+        Self {
+            planes: [
+                normalize_plane(c3 + c0),
+                normalize_plane(c3 - c0),
+                normalize_plane(c3 + c1),
+                normalize_plane(c3 - c1),
+                normalize_plane(c3 + c2),
+                normalize_plane(c3 - c2),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| distance_to_plane(*plane, point) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| distance_to_plane(*plane, center) >= -radius)
+    }
+
+    pub fn intersects_aabb(&self, min: Vec3, max: Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            // Test the corner that is farthest along the plane normal.
+            let positive = Vec3::new(
+                if plane.x >= 0.0 { max.x } else { min.x },
+                if plane.y >= 0.0 { max.y } else { min.y },
+                if plane.z >= 0.0 { max.z } else { min.z },
+            );
+            distance_to_plane(*plane, positive) >= 0.0
+        })
+    }
+}
+
+fn normalize_plane(plane: Vec4) -> Vec4 {
+    let len = Vec3::new(plane.x, plane.y, plane.z).len();
+
+    if len <= f32::EPSILON {
+        return plane;
+    }
+
+    plane / len
+}
+
+fn distance_to_plane(plane: Vec4, point: Vec3) -> f32 {
+    plane.x * point.x + plane.y * point.y + plane.z * point.z + plane.w
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check_orthographic_frustum() {
+        let frustum =
+            Frustum::from_view_projection(&Mat4::orthographic(-1.0, 1.0, -1.0, 1.0, 0.0, 10.0));
+
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -5.0)));
+        assert!(!frustum.contains_point(Vec3::new(2.0, 0.0, -5.0)));
+        assert!(frustum.intersects_sphere(Vec3::new(1.5, 0.0, -5.0), 1.0));
+        assert!(!frustum.intersects_sphere(Vec3::new(3.0, 0.0, -5.0), 1.0));
+        assert!(frustum.intersects_aabb(Vec3::new(0.5, 0.5, -6.0), Vec3::new(1.5, 1.5, -4.0)));
+        assert!(!frustum.intersects_aabb(Vec3::new(1.5, 1.5, -6.0), Vec3::new(2.5, 2.5, -4.0)));
+    }
+
+    #[test]
+    fn check_perspective_frustum_planes() {
+        let frustum = Frustum::from_view_projection(&Mat4::perspective(
+            std::f32::consts::FRAC_PI_2,
+            1.0,
+            0.1,
+            100.0,
+        ));
+        let [left, _, _, _, near, far] = frustum.planes;
+
+        // The side planes of a 90 degree field of view lean 45 degrees inwards.
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert!(
+            (left.x - half).abs() < 1e-5 && left.y.abs() < 1e-5 && (left.z + half).abs() < 1e-5
+        );
+        assert!(left.w.abs() < 1e-5);
+        assert!(near.z < 0.0 && 0.0 < far.z);
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -50.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -150.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 1.0)));
+        assert!(!frustum.contains_point(Vec3::new(-3.0, 0.0, -2.0)));
+    }
+
+    #[test]
+    fn check_aabb_frustum_intersection() {
+        // A camera at z = 5, looking down -z.
+        let view = Mat4::translation(Vec3::new(0.0, 0.0, 5.0)).inversed();
+        let projection = Mat4::perspective(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let frustum = Frustum::from_view_projection(&(view * projection));
+
+        assert!(frustum.intersects_aabb(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)));
+        // Behind the camera.
+        assert!(!frustum.intersects_aabb(Vec3::new(-1.0, -1.0, 6.0), Vec3::new(1.0, 1.0, 8.0)));
+        // Beside the view.
+        assert!(!frustum.intersects_aabb(Vec3::new(20.0, -1.0, -1.0), Vec3::new(22.0, 1.0, 1.0)));
+        // Beyond the far plane.
+        assert!(
+            !frustum.intersects_aabb(Vec3::new(-1.0, -1.0, -200.0), Vec3::new(1.0, 1.0, -150.0))
+        );
+        // Straddling a side plane.
+        assert!(frustum.intersects_aabb(Vec3::new(4.0, -1.0, -1.0), Vec3::new(8.0, 1.0, 1.0)));
+        // The camera inside the box, whose corners are all outside of the frustum.
+        assert!(frustum.intersects_aabb(Vec3::new(-2.0, -2.0, 3.0), Vec3::new(2.0, 2.0, 7.0)));
+        // A box enclosing the whole frustum.
+        assert!(frustum.intersects_aabb(
+            Vec3::new(-500.0, -500.0, -500.0),
+            Vec3::new(500.0, 500.0, 500.0)
+        ));
+    }
+}
@@ -87,11 +87,43 @@ impl Mat4 {
             0.0, //
             0.0,
             0.0,
-            (far + near) / (near - far),
+            far / (near - far),
             -1.0, //
             0.0,
             0.0,
-            (2.0 * far * near) / (near - far),
+            (far * near) / (near - far),
+            0.0, //
+        ])
+    }
+
+    /// Asymmetric perspective projection. `left`, `right`, `bottom` and `top` are the extents of the near plane in view space.
+    pub fn perspective_off_center(
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        near: f32,
+        far: f32,
+    ) -> Self {
+        let width_inv = (right - left).recip();
+        let height_inv = (top - bottom).recip();
+
+        Self::new([
+            2.0 * near * width_inv,
+            0.0,
+            0.0,
+            0.0, //
+            0.0,
+            2.0 * near * height_inv,
+            0.0,
+            0.0, //
+            (right + left) * width_inv,
+            (top + bottom) * height_inv,
+            far / (near - far),
+            -1.0, //
+            0.0,
+            0.0,
+            (far * near) / (near - far),
             0.0, //
         ])
     }
@@ -206,6 +238,11 @@ impl Mat4 {
             - d * (e * (j * o - k * n) - f * (i * o - k * m) + g * (i * n - j * m))
     }
 
+    /// Returns `true` if no element is NaN or infinite.
+    pub fn is_finite(&self) -> bool {
+        self.elements.iter().all(|element| element.is_finite())
+    }
+
     pub fn inverse(&mut self) -> &mut Self {
         let a = self.elements[0 * 4 + 0];
         let b = self.elements[0 * 4 + 1];
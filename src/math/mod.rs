@@ -1,11 +1,15 @@
+mod frustum;
 mod mat4;
 mod quat;
+mod spline;
 mod vec2;
 mod vec3;
 mod vec4;
 
+pub use frustum::*;
 pub use mat4::*;
 pub use quat::*;
+pub use spline::*;
 pub use vec2::*;
 pub use vec3::*;
 pub use vec4::*;
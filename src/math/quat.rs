@@ -54,6 +54,32 @@ impl Quat {
         }
     }
 
+    /// The shortest rotation turning the direction `from` into the direction `to`.
+    pub fn from_rotation_arc(from: Vec3, to: Vec3) -> Self {
+        let from = from.normalized();
+        let to = to.normalized();
+        let dot = Vec3::dot(from, to);
+
+        if dot < -1.0 + 1e-6 {
+            // Opposite directions: half a turn around any perpendicular axis.
+            let axis = if Vec3::cross(Vec3::RIGHT, from).len_square() < 1e-6 {
+                Vec3::cross(Vec3::UP, from)
+            } else {
+                Vec3::cross(Vec3::RIGHT, from)
+            };
+            return Self::from_axis_angle(axis.normalized(), std::f32::consts::PI);
+        }
+
+        let axis = Vec3::cross(from, to);
+        Self {
+            x: axis.x,
+            y: axis.y,
+            z: axis.z,
+            w: 1.0 + dot,
+        }
+        .normalized()
+    }
+
     pub fn from_mat4(mat: &Mat4) -> Self {
         let elements = &mat.elements;
         let trace = elements[0] + elements[5] + elements[10];
@@ -139,6 +165,43 @@ impl Quat {
         result
     }
 
+    pub fn dot(lhs: Self, rhs: Self) -> f32 {
+        lhs.x * rhs.x + lhs.y * rhs.y + lhs.z * rhs.z + lhs.w * rhs.w
+    }
+
+    /// Spherical interpolation along the shortest path.
+    pub fn slerp(from: Self, to: Self, t: f32) -> Self {
+        let mut dot = Self::dot(from, to);
+        let mut to = to;
+
+        if dot < 0.0 {
+            dot = -dot;
+            to = Self {
+                x: -to.x,
+                y: -to.y,
+                z: -to.z,
+                w: -to.w,
+            };
+        }
+
+        let (from_weight, to_weight) = if 0.9995 < dot {
+            // Nearly the same rotation; the linear interpolation is precise enough and stable.
+            (1.0 - t, t)
+        } else {
+            let angle = dot.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+
+        Self {
+            x: from.x * from_weight + to.x * to_weight,
+            y: from.y * from_weight + to.y * to_weight,
+            z: from.z * from_weight + to.z * to_weight,
+            w: from.w * from_weight + to.w * to_weight,
+        }
+        .normalized()
+    }
+
     pub fn into_eular(self) -> Vec3 {
         let sinr_cosp = 2.0 * (self.w * self.x + self.y * self.z);
         let cosr_cosp = 1.0 - 2.0 * (self.x * self.x + self.y * self.y);
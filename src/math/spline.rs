@@ -0,0 +1,530 @@
+use super::Vec3;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use thiserror::Error;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SplineKind {
+    /// Passes through every point, with the tangents taken from the neighbouring points.
+    CatmullRom,
+    /// Cubic Bézier segments given as `p0, c0, c1, p1, c2, c3, p2, ...`; passes through every third point.
+    Bezier,
+}
+
+impl Display for SplineKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SplineKind::CatmullRom => write!(f, "Catmull-Rom"),
+            SplineKind::Bezier => write!(f, "Bézier"),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SplineError {
+    #[error("a {kind} spline needs at least {required} points, but {count} were given")]
+    NotEnoughPoints {
+        kind: SplineKind,
+        required: usize,
+        count: usize,
+    },
+    #[error("a Bézier spline needs 3n + 1 points if open or 3n points if closed, but {count} were given")]
+    BezierPointCount { closed: bool, count: usize },
+    #[error("the spline needs at least 1 sample per segment")]
+    NoSamples,
+}
+
+/// A point on a spline, found by [`Spline::closest_point`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SplinePoint {
+    /// Distance along the spline from its start.
+    pub distance: f32,
+    pub position: Vec3,
+}
+
+/// A curve through space, made of cubic segments and parameterized by arc length,
+/// so that advancing the distance at a constant rate moves along it at a constant speed.
+///
+/// The arc length is approximated by a table of `samples_per_segment` chords per segment;
+/// more samples are more accurate on tightly bent segments. Segments of zero length are allowed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(into = "SplineData", try_from = "SplineData")]
+pub struct Spline {
+    kind: SplineKind,
+    points: Vec<Vec3>,
+    closed: bool,
+    samples_per_segment: usize,
+    /// Bézier control points of every segment.
+    segments: Vec<[Vec3; 4]>,
+    /// Distance along the spline at every sample; `segments.len() * samples_per_segment + 1` entries.
+    distances: Vec<f32>,
+    positions: Vec<Vec3>,
+}
+
+impl Spline {
+    pub const DEFAULT_SAMPLES_PER_SEGMENT: usize = 32;
+
+    pub fn new(kind: SplineKind, points: Vec<Vec3>, closed: bool) -> Result<Self, SplineError> {
+        Self::with_samples(kind, points, closed, Self::DEFAULT_SAMPLES_PER_SEGMENT)
+    }
+
+    pub fn catmull_rom(points: Vec<Vec3>, closed: bool) -> Result<Self, SplineError> {
+        Self::new(SplineKind::CatmullRom, points, closed)
+    }
+
+    pub fn bezier(points: Vec<Vec3>, closed: bool) -> Result<Self, SplineError> {
+        Self::new(SplineKind::Bezier, points, closed)
+    }
+
+    pub fn with_samples(
+        kind: SplineKind,
+        points: Vec<Vec3>,
+        closed: bool,
+        samples_per_segment: usize,
+    ) -> Result<Self, SplineError> {
+        if samples_per_segment == 0 {
+            return Err(SplineError::NoSamples);
+        }
+
+        let segments = match kind {
+            SplineKind::CatmullRom => catmull_rom_segments(&points, closed)?,
+            SplineKind::Bezier => bezier_segments(&points, closed)?,
+        };
+
+        let mut distances = Vec::with_capacity(segments.len() * samples_per_segment + 1);
+        let mut positions = Vec::with_capacity(segments.len() * samples_per_segment + 1);
+        distances.push(0.0);
+        positions.push(segments[0][0]);
+
+        for segment in &segments {
+            for sample in 1..=samples_per_segment {
+                let position = evaluate(segment, sample as f32 / samples_per_segment as f32);
+                let distance = distances.last().unwrap()
+                    + Vec3::distance(*positions.last().unwrap(), position);
+                distances.push(distance);
+                positions.push(position);
+            }
+        }
+
+        Ok(Self {
+            kind,
+            points,
+            closed,
+            samples_per_segment,
+            segments,
+            distances,
+            positions,
+        })
+    }
+
+    pub fn kind(&self) -> SplineKind {
+        self.kind
+    }
+
+    pub fn points(&self) -> &[Vec3] {
+        &self.points
+    }
+
+    /// Returns `true` if the end of the spline connects back to its start.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn samples_per_segment(&self) -> usize {
+        self.samples_per_segment
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn length(&self) -> f32 {
+        *self.distances.last().unwrap()
+    }
+
+    /// Wraps the distance around a closed spline, or clamps it to an open one.
+    pub fn normalize_distance(&self, distance: f32) -> f32 {
+        let length = self.length();
+
+        if length <= 0.0 || !distance.is_finite() {
+            0.0
+        } else if self.closed {
+            distance.rem_euclid(length)
+        } else {
+            distance.clamp(0.0, length)
+        }
+    }
+
+    pub fn point_at_distance(&self, distance: f32) -> Vec3 {
+        let (segment, t) = self.parameter_at(distance);
+        evaluate(&self.segments[segment], t)
+    }
+
+    /// Normalized direction of the spline at the distance, or zero if the whole spline is a single point.
+    pub fn tangent_at(&self, distance: f32) -> Vec3 {
+        let (segment, t) = self.parameter_at(distance);
+        let derivative = evaluate_derivative(&self.segments[segment], t);
+
+        if f32::EPSILON < derivative.len_square() {
+            return derivative.normalized();
+        }
+
+        // The derivative vanishes where control points coincide; the nearest chord gives the direction instead.
+        let index = segment * self.samples_per_segment
+            + ((t * self.samples_per_segment as f32) as usize).min(self.samples_per_segment - 1);
+        let forward = self.positions[index + 1..]
+            .iter()
+            .find(|position| f32::EPSILON < (**position - self.positions[index]).len_square())
+            .map(|position| *position - self.positions[index]);
+        let backward = self.positions[..=index]
+            .iter()
+            .rev()
+            .find(|position| f32::EPSILON < (self.positions[index + 1] - **position).len_square())
+            .map(|position| self.positions[index + 1] - *position);
+
+        match forward.or(backward) {
+            Some(direction) => direction.normalized(),
+            None => Vec3::ZERO,
+        }
+    }
+
+    /// Finds the point of the spline nearest to `point`.
+    pub fn closest_point(&self, point: Vec3) -> SplinePoint {
+        let nearest = (0..self.positions.len())
+            .min_by(|&a, &b| {
+                let a = Vec3::distance_square(self.positions[a], point);
+                let b = Vec3::distance_square(self.positions[b], point);
+                a.total_cmp(&b)
+            })
+            .unwrap();
+
+        // The nearest sample brackets the nearest point between its neighbours; refine it by ternary search.
+        let mut low = self.distances[nearest.saturating_sub(1)];
+        let mut high = self.distances[(nearest + 1).min(self.distances.len() - 1)];
+
+        for _ in 0..24 {
+            let a = low + (high - low) / 3.0;
+            let b = high - (high - low) / 3.0;
+
+            if Vec3::distance_square(self.point_at_distance(a), point)
+                <= Vec3::distance_square(self.point_at_distance(b), point)
+            {
+                high = b;
+            } else {
+                low = a;
+            }
+        }
+
+        let distance = (low + high) * 0.5;
+        SplinePoint {
+            distance,
+            position: self.point_at_distance(distance),
+        }
+    }
+
+    /// Line strip through the arc length samples, for drawing the spline.
+    pub fn polyline(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    /// Segment index and segment parameter at the distance.
+    fn parameter_at(&self, distance: f32) -> (usize, f32) {
+        let distance = self.normalize_distance(distance);
+        let index = self
+            .distances
+            .partition_point(|sample| *sample <= distance)
+            .clamp(1, self.distances.len() - 1)
+            - 1;
+        let span = self.distances[index + 1] - self.distances[index];
+        let fraction = if 0.0 < span {
+            ((distance - self.distances[index]) / span).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let segment = index / self.samples_per_segment;
+        let sample = index % self.samples_per_segment;
+        (
+            segment,
+            (sample as f32 + fraction) / self.samples_per_segment as f32,
+        )
+    }
+}
+
+/// The serialized form of a [`Spline`]; the arc length table is rebuilt when it is read.
+#[derive(Serialize, Deserialize)]
+struct SplineData {
+    kind: SplineKind,
+    points: Vec<[f32; 3]>,
+    #[serde(default)]
+    closed: bool,
+    #[serde(default = "default_samples_per_segment")]
+    samples_per_segment: usize,
+}
+
+fn default_samples_per_segment() -> usize {
+    Spline::DEFAULT_SAMPLES_PER_SEGMENT
+}
+
+impl From<Spline> for SplineData {
+    fn from(spline: Spline) -> Self {
+        Self {
+            kind: spline.kind,
+            points: spline
+                .points
+                .iter()
+                .map(|point| [point.x, point.y, point.z])
+                .collect(),
+            closed: spline.closed,
+            samples_per_segment: spline.samples_per_segment,
+        }
+    }
+}
+
+impl TryFrom<SplineData> for Spline {
+    type Error = SplineError;
+
+    fn try_from(data: SplineData) -> Result<Self, Self::Error> {
+        Self::with_samples(
+            data.kind,
+            data.points
+                .into_iter()
+                .map(|[x, y, z]| Vec3::new(x, y, z))
+                .collect(),
+            data.closed,
+            data.samples_per_segment,
+        )
+    }
+}
+
+fn catmull_rom_segments(points: &[Vec3], closed: bool) -> Result<Vec<[Vec3; 4]>, SplineError> {
+    if points.len() < 2 {
+        return Err(SplineError::NotEnoughPoints {
+            kind: SplineKind::CatmullRom,
+            required: 2,
+            count: points.len(),
+        });
+    }
+
+    let count = points.len();
+    let point = |index: isize| -> Vec3 {
+        if closed {
+            points[index.rem_euclid(count as isize) as usize]
+        } else if index < 0 {
+            // Mirrors the second point over the first one, so that the ends are not bent.
+            points[0] * 2.0 - points[1]
+        } else if count as isize <= index {
+            points[count - 1] * 2.0 - points[count - 2]
+        } else {
+            points[index as usize]
+        }
+    };
+    let segment_count = if closed { count } else { count - 1 };
+
+    Ok((0..segment_count as isize)
+        .map(|index| {
+            let p0 = point(index - 1);
+            let p1 = point(index);
+            let p2 = point(index + 1);
+            let p3 = point(index + 2);
+            [p1, p1 + (p2 - p0) / 6.0, p2 - (p3 - p1) / 6.0, p2]
+        })
+        .collect())
+}
+
+fn bezier_segments(points: &[Vec3], closed: bool) -> Result<Vec<[Vec3; 4]>, SplineError> {
+    let is_valid = if closed {
+        3 <= points.len() && points.len() % 3 == 0
+    } else {
+        4 <= points.len() && points.len() % 3 == 1
+    };
+
+    if !is_valid {
+        return Err(SplineError::BezierPointCount {
+            closed,
+            count: points.len(),
+        });
+    }
+
+    let count = points.len();
+    Ok((0..count / 3)
+        .map(|segment| {
+            let index = segment * 3;
+            [
+                points[index],
+                points[index + 1],
+                points[index + 2],
+                points[(index + 3) % count],
+            ]
+        })
+        .collect())
+}
+
+fn evaluate(segment: &[Vec3; 4], t: f32) -> Vec3 {
+    let u = 1.0 - t;
+    segment[0] * (u * u * u)
+        + segment[1] * (3.0 * u * u * t)
+        + segment[2] * (3.0 * u * t * t)
+        + segment[3] * (t * t * t)
+}
+
+fn evaluate_derivative(segment: &[Vec3; 4], t: f32) -> Vec3 {
+    let u = 1.0 - t;
+    (segment[1] - segment[0]) * (3.0 * u * u)
+        + (segment[2] - segment[1]) * (6.0 * u * t)
+        + (segment[3] - segment[2]) * (3.0 * t * t)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_near(a: f32, b: f32, tolerance: f32) {
+        assert!((a - b).abs() <= tolerance, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn check_arc_length_of_known_curves() {
+        // Collinear control points spaced evenly make a straight line.
+        let line = Spline::bezier(
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(3.0, 0.0, 0.0),
+            ],
+            false,
+        )
+        .unwrap();
+        assert_near(line.length(), 3.0, 1e-5);
+        assert_near(line.point_at_distance(1.25).x, 1.25, 1e-4);
+        assert_near(line.tangent_at(2.0).x, 1.0, 1e-5);
+
+        // The usual Bézier approximation of a quarter circle is within 0.03% of its radius.
+        let k = 0.552_284_8;
+        let quarter = Spline::with_samples(
+            SplineKind::Bezier,
+            vec![
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, k, 0.0),
+                Vec3::new(k, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            false,
+            256,
+        )
+        .unwrap();
+        assert_near(quarter.length(), std::f32::consts::FRAC_PI_2, 1e-3);
+
+        // Equal distances cover equal angles, i.e. the spline is walked at a constant speed.
+        for step in 0..=8 {
+            let distance = quarter.length() * step as f32 / 8.0;
+            let point = quarter.point_at_distance(distance);
+            let angle = point.y.atan2(point.x);
+            assert_near(angle, std::f32::consts::FRAC_PI_2 * step as f32 / 8.0, 2e-3);
+        }
+
+        // A closed Catmull-Rom spline through the corners of a square.
+        let square = Spline::catmull_rom(
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            true,
+        )
+        .unwrap();
+        assert_eq!(square.segment_count(), 4);
+        let start = square.point_at_distance(0.0);
+        let wrapped = square.point_at_distance(square.length());
+        assert_near(Vec3::distance(start, wrapped), 0.0, 1e-5);
+        let wrapped = square.point_at_distance(-square.length() * 0.25);
+        assert_near(Vec3::distance(wrapped, Vec3::new(0.0, 1.0, 0.0)), 0.0, 1e-5);
+    }
+
+    #[test]
+    fn check_degenerate_splines() {
+        // The middle segment has all its control points at the same place.
+        let spline = Spline::bezier(
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(3.0, 0.0, 0.0),
+                Vec3::new(3.0, 0.0, 0.0),
+                Vec3::new(3.0, 0.0, 0.0),
+                Vec3::new(3.0, 0.0, 0.0),
+                Vec3::new(4.0, 0.0, 0.0),
+                Vec3::new(5.0, 0.0, 0.0),
+                Vec3::new(6.0, 0.0, 0.0),
+            ],
+            false,
+        )
+        .unwrap();
+        assert_near(spline.length(), 6.0, 1e-4);
+        assert_near(spline.point_at_distance(3.0).x, 3.0, 1e-4);
+        assert_near(spline.point_at_distance(4.5).x, 4.5, 1e-4);
+        assert_near(spline.tangent_at(3.0).x, 1.0, 1e-4);
+        assert_near(spline.point_at_distance(100.0).x, 6.0, 1e-5);
+        assert_near(spline.point_at_distance(-1.0).x, 0.0, 1e-5);
+        assert_near(
+            spline.closest_point(Vec3::new(4.5, 1.0, 0.0)).distance,
+            4.5,
+            1e-3,
+        );
+
+        // The derivative vanishes where a control point sits on its end point.
+        let spline = Spline::bezier(
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 2.0),
+                Vec3::new(0.0, 0.0, 3.0),
+            ],
+            false,
+        )
+        .unwrap();
+        assert_near(spline.tangent_at(0.0).z, 1.0, 1e-4);
+
+        // A spline collapsed into a single point.
+        let point = Spline::catmull_rom(vec![Vec3::ONE, Vec3::ONE], true).unwrap();
+        assert_eq!(point.length(), 0.0);
+        assert_eq!(point.point_at_distance(1.0), Vec3::ONE);
+        assert_eq!(point.tangent_at(1.0), Vec3::ZERO);
+        assert_eq!(point.closest_point(Vec3::ZERO).position, Vec3::ONE);
+
+        assert_eq!(
+            Spline::catmull_rom(vec![Vec3::ONE], false),
+            Err(SplineError::NotEnoughPoints {
+                kind: SplineKind::CatmullRom,
+                required: 2,
+                count: 1
+            })
+        );
+        assert_eq!(
+            Spline::bezier(vec![Vec3::ONE; 5], false),
+            Err(SplineError::BezierPointCount {
+                closed: false,
+                count: 5
+            })
+        );
+        assert!(Spline::bezier(vec![Vec3::ONE; 6], true).is_ok());
+    }
+
+    #[test]
+    fn check_serialization_rebuilds_the_table() {
+        let spline = Spline::catmull_rom(
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 2.0, 0.0),
+                Vec3::new(3.0, 0.0, 1.0),
+            ],
+            false,
+        )
+        .unwrap();
+        let json = serde_json::to_string(&spline).unwrap();
+        assert_eq!(serde_json::from_str::<Spline>(&json).unwrap(), spline);
+        assert!(serde_json::from_str::<Spline>(r#"{"kind":"Bezier","points":[[0,0,0]]}"#).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+mod nav_agent;
+mod nav_mesh;
+mod nav_mesh_bake;
+
+pub use nav_agent::*;
+pub use nav_mesh::*;
+pub use nav_mesh_bake::*;
+
+use crate::{object::Object, Context};
+use specs::{prelude::*, Component};
+use std::sync::Arc;
+
+/// Marks the static geometry of the object as part of the navmesh bake.
+/// The geometry is in the local space of the object; the bake places it with the world matrix of the object.
+#[derive(Component, Debug, Clone)]
+#[storage(HashMapStorage)]
+pub struct NavMeshSource {
+    pub geometry: Arc<NavMeshGeometry>,
+}
+
+impl NavMeshSource {
+    pub fn new(geometry: Arc<NavMeshGeometry>) -> Self {
+        Self { geometry }
+    }
+}
+
+/// Holds the navmesh the agents walk on.
+#[derive(Debug, Default)]
+pub struct NavigationManager {
+    navmesh: Option<Arc<NavMesh>>,
+}
+
+impl NavigationManager {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn navmesh(&self) -> Option<&Arc<NavMesh>> {
+        self.navmesh.as_ref()
+    }
+
+    /// Replaces the navmesh. The agents search their paths again on the next update.
+    pub fn set_navmesh(&mut self, navmesh: Option<Arc<NavMesh>>) -> Option<Arc<NavMesh>> {
+        std::mem::replace(&mut self.navmesh, navmesh)
+    }
+}
+
+/// Gathers the geometry of the active objects with a [`NavMeshSource`] into world space,
+/// using the world matrices of the last frame.
+pub fn collect_navmesh_geometry(ctx: &Context) -> NavMeshGeometry {
+    let world = ctx.world();
+    let objects = world.read_storage::<Object>();
+    let sources = world.read_storage::<NavMeshSource>();
+    let object_mgr = ctx.object_mgr();
+    let object_hierarchy = object_mgr.object_hierarchy();
+    let mut geometry = NavMeshGeometry::new();
+
+    for (object, source) in (&objects, &sources).join() {
+        let object_id = object.object_id();
+
+        if !object_hierarchy.is_active(object_id) {
+            continue;
+        }
+
+        geometry.add_triangles(
+            &source.geometry.vertices,
+            &source.geometry.indices,
+            object_hierarchy.matrix(object_id),
+        );
+    }
+
+    geometry
+}
@@ -0,0 +1,261 @@
+use super::NavPath;
+use crate::math::Vec3;
+use specs::{prelude::*, Component};
+use std::time::Duration;
+
+/// Another agent nearby, as seen by [`NavAgent::avoidance`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavNeighbor {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub radius: f32,
+}
+
+/// Walks the object to a destination on the navmesh of the [`NavigationManager`](super::NavigationManager),
+/// steering around the other agents.
+///
+/// The agent moves the position of the object, which is taken as being in world space.
+#[derive(Component, Debug, Clone)]
+#[storage(HashMapStorage)]
+pub struct NavAgent {
+    /// Top speed, in units per second.
+    pub speed: f32,
+    /// How fast the agent speeds up, slows down and turns, in units per second squared.
+    pub acceleration: f32,
+    pub radius: f32,
+    /// The path is searched again after this long, so that the agent adapts to a changing navmesh or destination.
+    pub repath_interval: Duration,
+    /// Distance to the destination at which the agent has arrived.
+    pub stopping_distance: f32,
+    /// Whether the agent steers around the other agents. They share the effort of avoiding each other.
+    pub avoids_agents: bool,
+    destination: Option<Vec3>,
+    path: Option<NavPath>,
+    corner: usize,
+    velocity: Vec3,
+    since_repath: Duration,
+}
+
+impl NavAgent {
+    /// How far ahead, in seconds, the agents look for collisions with each other.
+    const AVOIDANCE_HORIZON: f32 = 1.5;
+
+    pub fn new(speed: f32, acceleration: f32, radius: f32) -> Self {
+        Self {
+            speed,
+            acceleration,
+            radius,
+            repath_interval: Duration::from_secs(1),
+            stopping_distance: 0.1,
+            avoids_agents: true,
+            destination: None,
+            path: None,
+            corner: 0,
+            velocity: Vec3::ZERO,
+            since_repath: Duration::ZERO,
+        }
+    }
+
+    pub fn destination(&self) -> Option<Vec3> {
+        self.destination
+    }
+
+    /// Heads for the destination; the path is searched on the next update.
+    pub fn set_destination(&mut self, destination: Vec3) {
+        self.destination = Some(destination);
+        self.path = None;
+    }
+
+    /// Forgets the destination; the agent slows down to a halt.
+    pub fn stop(&mut self) {
+        self.destination = None;
+        self.path = None;
+    }
+
+    pub fn path(&self) -> Option<&NavPath> {
+        self.path.as_ref()
+    }
+
+    pub fn velocity(&self) -> Vec3 {
+        self.velocity
+    }
+
+    /// Returns `true` if the path has to be searched, because there is none yet or it timed out.
+    pub fn needs_path(&self) -> bool {
+        self.destination.is_some()
+            && (self.path.is_none() || self.repath_interval <= self.since_repath)
+    }
+
+    /// Follows the path from its first corner; `None` means no path was found, and the agent waits for the next search.
+    pub fn set_path(&mut self, path: Option<NavPath>) {
+        self.path = path;
+        self.corner = 1;
+        self.since_repath = Duration::ZERO;
+    }
+
+    /// Returns `true` if the agent has a destination and is within the stopping distance of it.
+    pub fn has_arrived(&self, position: Vec3) -> bool {
+        match (&self.path, self.destination) {
+            (Some(path), Some(_)) => {
+                Vec3::distance(horizontal(position), horizontal(path.end()))
+                    <= self.stopping_distance
+            }
+            _ => false,
+        }
+    }
+
+    /// Velocity towards the next corner of the path, slowing down to stop at the end.
+    pub fn desired_velocity(&mut self, position: Vec3) -> Vec3 {
+        let path = match (&self.path, self.destination) {
+            (Some(path), Some(_)) => path,
+            _ => return Vec3::ZERO,
+        };
+
+        // Corners within the stopping distance are passed, so that the agent does not have to hit each one exactly.
+        while self.corner + 1 < path.points.len()
+            && Vec3::distance(horizontal(position), horizontal(path.points[self.corner]))
+                <= self.stopping_distance
+        {
+            self.corner += 1;
+        }
+
+        let corner = match path.points.get(self.corner) {
+            Some(&corner) => corner,
+            None => return Vec3::ZERO,
+        };
+        let to_corner = horizontal(corner - position);
+        let remaining = to_corner.len()
+            + path.points[self.corner..]
+                .windows(2)
+                .map(|segment| Vec3::distance(segment[0], segment[1]))
+                .sum::<f32>();
+
+        if remaining <= self.stopping_distance {
+            return Vec3::ZERO;
+        }
+
+        // Slow enough to stop in the remaining distance.
+        let speed = self.speed.min((2.0 * self.acceleration * remaining).sqrt());
+        to_corner.normalized() * speed
+    }
+
+    /// Change of velocity that steers clear of the neighbours within the avoidance horizon.
+    /// Each of two agents heading for each other takes half of the correction.
+    pub fn avoidance(&self, position: Vec3, neighbors: &[NavNeighbor]) -> Vec3 {
+        let mut correction = Vec3::ZERO;
+
+        for neighbor in neighbors {
+            let offset = horizontal(neighbor.position - position);
+            let relative_velocity = horizontal(self.velocity - neighbor.velocity);
+            let radius = self.radius + neighbor.radius;
+
+            let reach = radius + relative_velocity.len() * Self::AVOIDANCE_HORIZON;
+
+            if reach * reach < offset.len_square() {
+                continue;
+            }
+
+            // Time and offset at the closest approach, if both keep their velocities.
+            let time = if relative_velocity.len_square() <= f32::EPSILON {
+                0.0
+            } else {
+                (Vec3::dot(offset, relative_velocity) / relative_velocity.len_square())
+                    .clamp(0.0, Self::AVOIDANCE_HORIZON)
+            };
+            let closest = offset - relative_velocity * time;
+            let distance = closest.len();
+
+            if radius <= distance {
+                continue;
+            }
+
+            let away = if distance <= f32::EPSILON {
+                // Head-on or on top of each other: sidestep to the right of the line between them.
+                Vec3::cross(offset, Vec3::UP).normalized()
+            } else {
+                -closest / distance
+            };
+            correction += away * ((radius - distance) / time.max(0.1) * 0.5);
+        }
+
+        correction
+    }
+
+    /// Accelerates towards the desired velocity over the time step, limited to the top speed, and returns the step to move.
+    pub fn steer(&mut self, desired_velocity: Vec3, delta_time: f32) -> Vec3 {
+        let mut desired_velocity = desired_velocity;
+
+        if self.speed * self.speed < desired_velocity.len_square() {
+            desired_velocity = desired_velocity.normalized() * self.speed;
+        }
+
+        let change = desired_velocity - self.velocity;
+        let max_change = self.acceleration * delta_time;
+
+        self.velocity = if change.len_square() <= max_change * max_change {
+            desired_velocity
+        } else {
+            self.velocity + change.normalized() * max_change
+        };
+        self.since_repath += Duration::from_secs_f32(delta_time);
+        self.velocity * delta_time
+    }
+
+    /// Keeps the velocity along the navmesh after the position was pulled back onto it.
+    pub fn set_velocity(&mut self, velocity: Vec3) {
+        self.velocity = velocity;
+    }
+}
+
+fn horizontal(vec: Vec3) -> Vec3 {
+    Vec3::new(vec.x, 0.0, vec.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_agents_avoid_each_other() {
+        let mut left = NavAgent::new(2.0, 8.0, 0.5);
+        let mut right = NavAgent::new(2.0, 8.0, 0.5);
+        left.velocity = Vec3::new(2.0, 0.0, 0.0);
+        right.velocity = Vec3::new(-2.0, 0.0, 0.0);
+        let left_position = Vec3::new(-2.0, 0.0, 0.0);
+        let right_position = Vec3::new(2.0, 0.0, 0.1);
+
+        let left_correction = left.avoidance(
+            left_position,
+            &[NavNeighbor {
+                position: right_position,
+                velocity: right.velocity,
+                radius: right.radius,
+            }],
+        );
+        let right_correction = right.avoidance(
+            right_position,
+            &[NavNeighbor {
+                position: left_position,
+                velocity: left.velocity,
+                radius: left.radius,
+            }],
+        );
+
+        // They sidestep in opposite directions, sharing the correction.
+        assert!(left_correction.z < 0.0 && 0.0 < right_correction.z);
+        assert!((left_correction.z + right_correction.z).abs() < 1e-4);
+
+        // Agents passing far apart do not react.
+        let far = NavNeighbor {
+            position: Vec3::new(2.0, 0.0, 3.0),
+            velocity: right.velocity,
+            radius: right.radius,
+        };
+        assert_eq!(left.avoidance(left_position, &[far]), Vec3::ZERO);
+
+        left.velocity = Vec3::ZERO;
+        let step = left.steer(Vec3::new(10.0, 0.0, 0.0), 0.1);
+        assert!((left.velocity().x - 0.8).abs() < 1e-5);
+        assert!((step.x - 0.08).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,542 @@
+use crate::math::Vec3;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    ops::{BitAnd, BitOr},
+};
+
+/// Area flags of a polygon. Queries skip the polygons their [`NavQueryFilter`] rejects,
+/// so that areas can be closed at runtime without rebaking, e.g. by clearing [`WALK`](Self::WALK) behind a door.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NavPolyFlags(pub u16);
+
+impl NavPolyFlags {
+    pub const NONE: Self = Self(0);
+    pub const WALK: Self = Self(1 << 0);
+    pub const ALL: Self = Self(u16::MAX);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl Default for NavPolyFlags {
+    fn default() -> Self {
+        Self::WALK
+    }
+}
+
+impl BitOr for NavPolyFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for NavPolyFlags {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self(self.0 & rhs.0)
+    }
+}
+
+/// Selects the polygons a query may use: those with any of the included flags and none of the excluded ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NavQueryFilter {
+    pub include: NavPolyFlags,
+    pub exclude: NavPolyFlags,
+}
+
+impl NavQueryFilter {
+    pub fn passes(&self, flags: NavPolyFlags) -> bool {
+        flags.intersects(self.include) && !flags.intersects(self.exclude)
+    }
+}
+
+impl Default for NavQueryFilter {
+    fn default() -> Self {
+        Self {
+            include: NavPolyFlags::ALL,
+            exclude: NavPolyFlags::NONE,
+        }
+    }
+}
+
+/// A connection to a neighbouring polygon through a portal on the shared boundary.
+/// The portal ends are named as seen when leaving the polygon that owns the link.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavLink {
+    pub polygon: u32,
+    pub left: Vec3,
+    pub right: Vec3,
+}
+
+/// A convex polygon of a [`NavMesh`], walkable as seen from above.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavPolygon {
+    /// Indices of the vertices, in order along the boundary.
+    pub vertices: Vec<u32>,
+    pub links: Vec<NavLink>,
+    pub flags: NavPolyFlags,
+}
+
+/// A point on a [`NavMesh`] and the polygon it lies on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavMeshPoint {
+    pub polygon: usize,
+    pub position: Vec3,
+}
+
+/// A path found on a [`NavMesh`]: the string-pulled corners, and the corridor of polygons they run through.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavPath {
+    /// Corners from the start to the end, both snapped onto the mesh.
+    pub points: Vec<Vec3>,
+    pub polygons: Vec<usize>,
+}
+
+impl NavPath {
+    pub fn start(&self) -> Vec3 {
+        self.points[0]
+    }
+
+    pub fn end(&self) -> Vec3 {
+        *self.points.last().unwrap()
+    }
+
+    pub fn length(&self) -> f32 {
+        self.points
+            .windows(2)
+            .map(|segment| Vec3::distance(segment[0], segment[1]))
+            .sum()
+    }
+}
+
+/// A polygon mesh of the walkable surfaces of a level, searched for paths by the agents.
+/// Baked by [`bake_navmesh`](super::bake_navmesh); serializable, so that it can be baked ahead of time and shipped as an asset.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(into = "NavMeshData", from = "NavMeshData")]
+pub struct NavMesh {
+    vertices: Vec<Vec3>,
+    polygons: Vec<NavPolygon>,
+}
+
+impl NavMesh {
+    pub fn new(vertices: Vec<Vec3>, polygons: Vec<NavPolygon>) -> Self {
+        Self { vertices, polygons }
+    }
+
+    pub fn vertices(&self) -> &[Vec3] {
+        &self.vertices
+    }
+
+    pub fn polygons(&self) -> &[NavPolygon] {
+        &self.polygons
+    }
+
+    pub fn polygon(&self, index: usize) -> Option<&NavPolygon> {
+        self.polygons.get(index)
+    }
+
+    pub fn set_polygon_flags(&mut self, index: usize, flags: NavPolyFlags) {
+        self.polygons[index].flags = flags;
+    }
+
+    pub fn polygon_vertices(&self, index: usize) -> impl Iterator<Item = Vec3> + '_ {
+        self.polygons[index]
+            .vertices
+            .iter()
+            .map(|&vertex| self.vertices[vertex as usize])
+    }
+
+    pub fn polygon_centroid(&self, index: usize) -> Vec3 {
+        let count = self.polygons[index].vertices.len() as f32;
+        self.polygon_vertices(index)
+            .fold(Vec3::ZERO, |sum, vertex| sum + vertex)
+            / count
+    }
+
+    /// Edges of every polygon, for drawing the mesh while debugging. Shared edges appear once per polygon.
+    pub fn debug_lines(&self) -> Vec<(Vec3, Vec3)> {
+        let mut lines = Vec::new();
+
+        for index in 0..self.polygons.len() {
+            let vertices = self.polygon_vertices(index).collect::<Vec<_>>();
+
+            for (i, &vertex) in vertices.iter().enumerate() {
+                lines.push((vertex, vertices[(i + 1) % vertices.len()]));
+            }
+        }
+
+        lines
+    }
+
+    /// Returns the point of the mesh closest to the point, or `None` if the mesh has no polygon.
+    pub fn closest_point_on_navmesh(&self, point: Vec3) -> Option<NavMeshPoint> {
+        self.closest_point_filtered(point, &NavQueryFilter::default())
+    }
+
+    pub fn closest_point_filtered(
+        &self,
+        point: Vec3,
+        filter: &NavQueryFilter,
+    ) -> Option<NavMeshPoint> {
+        let mut closest: Option<(f32, NavMeshPoint)> = None;
+
+        for (index, polygon) in self.polygons.iter().enumerate() {
+            if !filter.passes(polygon.flags) {
+                continue;
+            }
+
+            let position = self.closest_point_on_polygon(index, point);
+            let distance = Vec3::distance_square(point, position);
+
+            if closest.map_or(true, |(closest, _)| distance < closest) {
+                closest = Some((
+                    distance,
+                    NavMeshPoint {
+                        polygon: index,
+                        position,
+                    },
+                ));
+            }
+        }
+
+        closest.map(|(_, point)| point)
+    }
+
+    /// Finds the shortest path through the polygons with A*, then pulls it tight along the corridor.
+    /// Both ends are snapped onto the mesh first. Returns `None` if they are not connected.
+    pub fn find_path(&self, start: Vec3, end: Vec3) -> Option<NavPath> {
+        self.find_path_filtered(start, end, &NavQueryFilter::default())
+    }
+
+    pub fn find_path_filtered(
+        &self,
+        start: Vec3,
+        end: Vec3,
+        filter: &NavQueryFilter,
+    ) -> Option<NavPath> {
+        let start = self.closest_point_filtered(start, filter)?;
+        let end = self.closest_point_filtered(end, filter)?;
+        let corridor = self.find_corridor(start, end, filter)?;
+
+        // Each portal is crossed in order; the ends are zero-width portals, so that the funnel starts and stops on them.
+        let mut portals = Vec::with_capacity(corridor.len() + 1);
+        portals.push((start.position, start.position));
+
+        for pair in corridor.windows(2) {
+            let link = self.polygons[pair[0]]
+                .links
+                .iter()
+                .find(|link| link.polygon as usize == pair[1])
+                .unwrap();
+            portals.push((link.left, link.right));
+        }
+
+        portals.push((end.position, end.position));
+
+        Some(NavPath {
+            points: string_pull(&portals),
+            polygons: corridor,
+        })
+    }
+
+    fn find_corridor(
+        &self,
+        start: NavMeshPoint,
+        end: NavMeshPoint,
+        filter: &NavQueryFilter,
+    ) -> Option<Vec<usize>> {
+        // Polygons are entered at the middle of the portal, which is where their cost is measured from.
+        let mut entries = HashMap::from([(start.polygon, (0.0, start.position, usize::MAX))]);
+        let mut open = BinaryHeap::new();
+        open.push(OpenNode {
+            cost: Vec3::distance(start.position, end.position),
+            polygon: start.polygon,
+        });
+
+        while let Some(OpenNode { cost, polygon }) = open.pop() {
+            let (distance, position, _) = entries[&polygon];
+
+            if polygon == end.polygon {
+                let mut corridor = vec![polygon];
+
+                while let Some(&(_, _, parent)) = entries.get(corridor.last().unwrap()) {
+                    if parent == usize::MAX {
+                        break;
+                    }
+
+                    corridor.push(parent);
+                }
+
+                corridor.reverse();
+                return Some(corridor);
+            }
+
+            if distance + Vec3::distance(position, end.position) < cost - 1e-4 {
+                // A shorter way to the polygon was found after this node was queued.
+                continue;
+            }
+
+            for link in &self.polygons[polygon].links {
+                let neighbor = link.polygon as usize;
+
+                if !filter.passes(self.polygons[neighbor].flags) {
+                    continue;
+                }
+
+                let entry = if neighbor == end.polygon {
+                    end.position
+                } else {
+                    (link.left + link.right) * 0.5
+                };
+                let distance = distance + Vec3::distance(position, entry);
+
+                if entries
+                    .get(&neighbor)
+                    .map_or(true, |&(known, _, _)| distance < known)
+                {
+                    entries.insert(neighbor, (distance, entry, polygon));
+                    open.push(OpenNode {
+                        cost: distance + Vec3::distance(entry, end.position),
+                        polygon: neighbor,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
+    fn closest_point_on_polygon(&self, index: usize, point: Vec3) -> Vec3 {
+        let vertices = self.polygon_vertices(index).collect::<Vec<_>>();
+
+        // Inside the polygon seen from above, the point drops onto the surface of the fan triangle below it.
+        for i in 1..vertices.len() - 1 {
+            let (a, b, c) = (vertices[0], vertices[i], vertices[i + 1]);
+
+            if let Some(height) = height_on_triangle(point, a, b, c) {
+                return Vec3::new(point.x, height, point.z);
+            }
+        }
+
+        let mut closest = vertices[0];
+        let mut closest_distance = f32::MAX;
+
+        for (i, &a) in vertices.iter().enumerate() {
+            let b = vertices[(i + 1) % vertices.len()];
+            let candidate = closest_point_on_segment(point, a, b);
+            let distance = Vec3::distance_square(point, candidate);
+
+            if distance < closest_distance {
+                closest = candidate;
+                closest_distance = distance;
+            }
+        }
+
+        closest
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct OpenNode {
+    cost: f32,
+    polygon: usize,
+}
+
+impl Eq for OpenNode {}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed, so that the heap pops the cheapest node first.
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| other.polygon.cmp(&self.polygon))
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Twice the signed area of the triangle seen from above, with the sign such that
+/// a portal crossed from `a` gives a positive area from its `left` end to its `right` end.
+pub(crate) fn triangle_area_2d(a: Vec3, b: Vec3, c: Vec3) -> f32 {
+    (c.x - a.x) * (b.z - a.z) - (b.x - a.x) * (c.z - a.z)
+}
+
+fn height_on_triangle(point: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+    let v0 = (c.x - a.x, c.z - a.z);
+    let v1 = (b.x - a.x, b.z - a.z);
+    let v2 = (point.x - a.x, point.z - a.z);
+    let denominator = v0.0 * v1.1 - v1.0 * v0.1;
+
+    if denominator.abs() <= f32::EPSILON {
+        return None;
+    }
+
+    let u = (v2.0 * v1.1 - v1.0 * v2.1) / denominator;
+    let v = (v0.0 * v2.1 - v2.0 * v0.1) / denominator;
+    const EPSILON: f32 = 1e-4;
+
+    if u < -EPSILON || v < -EPSILON || 1.0 + EPSILON < u + v {
+        return None;
+    }
+
+    Some(a.y + (c.y - a.y) * u + (b.y - a.y) * v)
+}
+
+fn closest_point_on_segment(point: Vec3, a: Vec3, b: Vec3) -> Vec3 {
+    let ab = b - a;
+    let len_square = ab.len_square();
+
+    if len_square <= f32::EPSILON {
+        return a;
+    }
+
+    let t = (Vec3::dot(point - a, ab) / len_square).clamp(0.0, 1.0);
+    a + ab * t
+}
+
+/// The funnel algorithm: walks the portals keeping the funnel of visible space from the last corner,
+/// adding a corner whenever one side of the funnel crosses the other.
+fn string_pull(portals: &[(Vec3, Vec3)]) -> Vec<Vec3> {
+    fn is_same(a: Vec3, b: Vec3) -> bool {
+        Vec3::distance_square(a, b) < 1e-6
+    }
+
+    let mut points = vec![portals[0].0];
+    let mut apex = portals[0].0;
+    let (mut left, mut right) = portals[0];
+    let (mut left_index, mut right_index) = (0, 0);
+    let mut i = 1;
+
+    while i < portals.len() {
+        let (next_left, next_right) = portals[i];
+
+        if triangle_area_2d(apex, right, next_right) <= 0.0 {
+            if is_same(apex, right) || 0.0 < triangle_area_2d(apex, left, next_right) {
+                right = next_right;
+                right_index = i;
+            } else {
+                // The right side crossed the left one, so the left end is a corner of the path.
+                apex = left;
+                let apex_index = left_index;
+                points.push(apex);
+                left = apex;
+                right = apex;
+                left_index = apex_index;
+                right_index = apex_index;
+                i = apex_index + 1;
+                continue;
+            }
+        }
+
+        if 0.0 <= triangle_area_2d(apex, left, next_left) {
+            if is_same(apex, left) || triangle_area_2d(apex, right, next_left) < 0.0 {
+                left = next_left;
+                left_index = i;
+            } else {
+                apex = right;
+                let apex_index = right_index;
+                points.push(apex);
+                left = apex;
+                right = apex;
+                left_index = apex_index;
+                right_index = apex_index;
+                i = apex_index + 1;
+                continue;
+            }
+        }
+
+        i += 1;
+    }
+
+    let end = portals.last().unwrap().0;
+
+    if !is_same(*points.last().unwrap(), end) {
+        points.push(end);
+    }
+
+    points
+}
+
+/// The serialized form of a [`NavMesh`].
+#[derive(Serialize, Deserialize)]
+struct NavMeshData {
+    vertices: Vec<[f32; 3]>,
+    polygons: Vec<NavPolygonData>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NavPolygonData {
+    vertices: Vec<u32>,
+    links: Vec<(u32, [f32; 3], [f32; 3])>,
+    #[serde(default)]
+    flags: NavPolyFlags,
+}
+
+fn to_array(vec: Vec3) -> [f32; 3] {
+    [vec.x, vec.y, vec.z]
+}
+
+fn from_array(array: [f32; 3]) -> Vec3 {
+    Vec3::new(array[0], array[1], array[2])
+}
+
+impl From<NavMesh> for NavMeshData {
+    fn from(navmesh: NavMesh) -> Self {
+        Self {
+            vertices: navmesh.vertices.into_iter().map(to_array).collect(),
+            polygons: navmesh
+                .polygons
+                .into_iter()
+                .map(|polygon| NavPolygonData {
+                    vertices: polygon.vertices,
+                    links: polygon
+                        .links
+                        .into_iter()
+                        .map(|link| (link.polygon, to_array(link.left), to_array(link.right)))
+                        .collect(),
+                    flags: polygon.flags,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<NavMeshData> for NavMesh {
+    fn from(data: NavMeshData) -> Self {
+        Self {
+            vertices: data.vertices.into_iter().map(from_array).collect(),
+            polygons: data
+                .polygons
+                .into_iter()
+                .map(|polygon| NavPolygon {
+                    vertices: polygon.vertices,
+                    links: polygon
+                        .links
+                        .into_iter()
+                        .map(|(polygon, left, right)| NavLink {
+                            polygon,
+                            left: from_array(left),
+                            right: from_array(right),
+                        })
+                        .collect(),
+                    flags: polygon.flags,
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,845 @@
+use super::{triangle_area_2d, NavLink, NavMesh, NavPolyFlags, NavPolygon};
+use crate::{
+    math::{Mat4, Vec3, Vec4},
+    task::{Task, TaskContext, TaskStatus},
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    f32::consts::FRAC_PI_4,
+    sync::Arc,
+    time::Instant,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum NavMeshBakeError {
+    #[error("no geometry to bake")]
+    EmptyGeometry,
+    #[error("invalid bake settings: {0}")]
+    InvalidSettings(&'static str),
+    #[error("the geometry spans {width}x{depth} cells, more than the {max} cells a side can have")]
+    TooLarge {
+        width: usize,
+        depth: usize,
+        max: usize,
+    },
+}
+
+/// Parameters of a navmesh bake. Distances are in world units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavMeshBakeSettings {
+    /// Size of the voxels on the horizontal plane. Smaller cells follow the obstacles more tightly but bake slower.
+    pub cell_size: f32,
+    /// Height of the voxels.
+    pub cell_height: f32,
+    /// The walkable area is shrunk by the radius, so that agents walking on the mesh do not clip into walls.
+    pub agent_radius: f32,
+    /// Clearance required above a surface to stand on it.
+    pub agent_height: f32,
+    /// Steepest walkable slope, in radians.
+    pub max_slope: f32,
+    /// Highest ledge an agent can step up or down.
+    pub max_step: f32,
+}
+
+impl NavMeshBakeSettings {
+    fn validate(&self) -> Result<(), NavMeshBakeError> {
+        if !(0.0 < self.cell_size && 0.0 < self.cell_height) {
+            return Err(NavMeshBakeError::InvalidSettings(
+                "cell sizes must be positive",
+            ));
+        }
+
+        if !(0.0 <= self.agent_radius && 0.0 < self.agent_height && 0.0 <= self.max_step) {
+            return Err(NavMeshBakeError::InvalidSettings(
+                "agent dimensions must not be negative",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for NavMeshBakeSettings {
+    fn default() -> Self {
+        Self {
+            cell_size: 0.25,
+            cell_height: 0.1,
+            agent_radius: 0.5,
+            agent_height: 2.0,
+            max_slope: FRAC_PI_4,
+            max_step: 0.4,
+        }
+    }
+}
+
+/// Triangles of the static geometry to bake, in world space.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NavMeshGeometry {
+    pub vertices: Vec<Vec3>,
+    /// Three per triangle, counter-clockwise as seen from the side agents walk on.
+    pub indices: Vec<u32>,
+}
+
+impl NavMeshGeometry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn triangle(&self, index: usize) -> [Vec3; 3] {
+        let indices = &self.indices[index * 3..index * 3 + 3];
+        [
+            self.vertices[indices[0] as usize],
+            self.vertices[indices[1] as usize],
+            self.vertices[indices[2] as usize],
+        ]
+    }
+
+    /// Adds triangles transformed by the matrix, e.g. the world matrix of the object they belong to.
+    pub fn add_triangles(&mut self, vertices: &[Vec3], indices: &[u32], matrix: &Mat4) {
+        let base = self.vertices.len() as u32;
+        self.vertices.extend(
+            vertices
+                .iter()
+                .map(|&vertex| Vec3::from(Vec4::from_vec3(vertex, 1.0) * matrix)),
+        );
+        self.indices
+            .extend(indices.iter().map(|&index| base + index));
+    }
+
+    /// Adds an axis-aligned box, e.g. for a floor or an obstacle.
+    pub fn add_box(&mut self, min: Vec3, max: Vec3) {
+        let base = self.vertices.len() as u32;
+
+        for i in 0..8 {
+            self.vertices.push(Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            ));
+        }
+
+        #[rustfmt::skip]
+        let faces: [u32; 36] = [
+            2, 6, 7, 2, 7, 3, // top
+            0, 1, 5, 0, 5, 4, // bottom
+            0, 2, 3, 0, 3, 1, // -z
+            4, 5, 7, 4, 7, 6, // +z
+            0, 4, 6, 0, 6, 2, // -x
+            1, 3, 7, 1, 7, 5, // +x
+        ];
+        self.indices.extend(faces.iter().map(|&index| base + index));
+    }
+
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        let mut vertices = self
+            .indices
+            .iter()
+            .map(|&index| self.vertices[index as usize]);
+        let first = vertices.next()?;
+        Some(vertices.fold((first, first), |(min, max), vertex| {
+            (Vec3::min(min, vertex), Vec3::max(max, vertex))
+        }))
+    }
+}
+
+/// A solid run of voxels in a column of the heightfield, in cell heights from the bottom of the bounds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Span {
+    min: i32,
+    max: i32,
+    /// Whether the top of the span is a walkable surface.
+    walkable: bool,
+}
+
+/// The voxelized geometry: spans per column of cells over the horizontal bounds.
+struct Heightfield {
+    origin: Vec3,
+    width: usize,
+    depth: usize,
+    columns: Vec<Vec<Span>>,
+    /// Heights of the surfaces facing down and up in each column, which bound the insides of closed meshes.
+    bottoms: Vec<Vec<i32>>,
+    tops: Vec<Vec<i32>>,
+}
+
+/// A walkable floor cell of the heightfield, with its links to the floors next to it.
+#[derive(Debug, Clone, Copy)]
+struct FloorCell {
+    x: usize,
+    z: usize,
+    /// Height of the floor in cell heights.
+    y: i32,
+    /// Neighbouring cells in the -x, +z, +x and -z directions.
+    neighbors: [Option<usize>; 4],
+}
+
+const DIRECTIONS: [(isize, isize); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+
+/// Sides of the heightfield are capped, so that a stray vertex far away cannot exhaust the memory.
+const MAX_CELLS_PER_SIDE: usize = 4096;
+
+/// Bakes the walkable surfaces of the geometry into a navmesh, all at once.
+/// Use [`NavMeshBakeTask`] to bake over several frames or on the background thread.
+pub fn bake_navmesh(
+    geometry: &NavMeshGeometry,
+    settings: &NavMeshBakeSettings,
+) -> Result<NavMesh, NavMeshBakeError> {
+    let mut task = NavMeshBakeTask::new(Arc::new(geometry.clone()), *settings);
+    Ok(task.bake_step(None)?.unwrap())
+}
+
+/// Bakes a navmesh in steps: the triangles are voxelized a batch at a time, then the mesh is built.
+/// It only uses the CPU, so it can run on the background thread of the task scheduler.
+pub struct NavMeshBakeTask {
+    geometry: Arc<NavMeshGeometry>,
+    settings: NavMeshBakeSettings,
+    heightfield: Option<Heightfield>,
+    next_triangle: usize,
+}
+
+impl NavMeshBakeTask {
+    /// Triangles voxelized between deadline checks.
+    const TRIANGLE_BATCH: usize = 64;
+
+    pub fn new(geometry: Arc<NavMeshGeometry>, settings: NavMeshBakeSettings) -> Self {
+        Self {
+            geometry,
+            settings,
+            heightfield: None,
+            next_triangle: 0,
+        }
+    }
+
+    fn progress(&self) -> f32 {
+        // Voxelizing takes most of the time; building the mesh is the last step.
+        0.9 * self.next_triangle as f32 / self.geometry.triangle_count().max(1) as f32
+    }
+
+    /// Returns `None` if the deadline passed before the bake finished.
+    fn bake_step(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<Option<NavMesh>, NavMeshBakeError> {
+        let settings = self.settings;
+
+        if self.heightfield.is_none() {
+            settings.validate()?;
+            let (min, max) = self
+                .geometry
+                .bounds()
+                .ok_or(NavMeshBakeError::EmptyGeometry)?;
+            let width = ((max.x - min.x) / settings.cell_size).ceil() as usize + 1;
+            let depth = ((max.z - min.z) / settings.cell_size).ceil() as usize + 1;
+
+            if MAX_CELLS_PER_SIDE < width || MAX_CELLS_PER_SIDE < depth {
+                return Err(NavMeshBakeError::TooLarge {
+                    width,
+                    depth,
+                    max: MAX_CELLS_PER_SIDE,
+                });
+            }
+
+            self.heightfield = Some(Heightfield {
+                origin: min,
+                width,
+                depth,
+                columns: vec![Vec::new(); width * depth],
+                bottoms: vec![Vec::new(); width * depth],
+                tops: vec![Vec::new(); width * depth],
+            });
+        }
+
+        let heightfield = self.heightfield.as_mut().unwrap();
+        let walkable_normal_y = settings.max_slope.cos();
+        let step_cells = (settings.max_step / settings.cell_height).floor() as i32;
+
+        while self.next_triangle < self.geometry.triangle_count() {
+            let end =
+                (self.next_triangle + Self::TRIANGLE_BATCH).min(self.geometry.triangle_count());
+
+            for index in self.next_triangle..end {
+                let triangle = self.geometry.triangle(index);
+                let normal =
+                    Vec3::cross(triangle[1] - triangle[0], triangle[2] - triangle[0]).normalized();
+                rasterize_triangle(
+                    heightfield,
+                    &settings,
+                    &triangle,
+                    normal.y,
+                    walkable_normal_y,
+                    step_cells,
+                );
+            }
+
+            self.next_triangle = end;
+
+            if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+                return Ok(None);
+            }
+        }
+
+        let mut heightfield = self.heightfield.take().unwrap();
+        fill_solids(&mut heightfield, step_cells);
+        let cells = build_floor_cells(&heightfield, &settings);
+        let cells = erode(cells, settings.agent_radius / settings.cell_size);
+        Ok(Some(build_polygons(&heightfield, &settings, &cells)))
+    }
+}
+
+impl Task for NavMeshBakeTask {
+    type Output = NavMesh;
+
+    fn step(&mut self, _ctx: &mut TaskContext, deadline: Instant) -> TaskStatus<Self::Output> {
+        match self.bake_step(Some(deadline)) {
+            Ok(Some(navmesh)) => TaskStatus::Done(navmesh),
+            Ok(None) => TaskStatus::InProgress(self.progress()),
+            Err(err) => TaskStatus::Failed(err.to_string()),
+        }
+    }
+}
+
+/// Splits a convex polygon by the plane `axis = offset`, into the parts below and above it.
+/// Points on the plane go to both parts, so that geometry lying on a cell boundary covers the cells on both sides.
+fn split_polygon(polygon: &[Vec3], offset: f32, axis: fn(Vec3) -> f32) -> (Vec<Vec3>, Vec<Vec3>) {
+    let mut below = Vec::new();
+    let mut above = Vec::new();
+
+    for (i, &a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        let da = axis(a) - offset;
+        let db = axis(b) - offset;
+
+        if da <= 0.0 {
+            below.push(a);
+        }
+
+        if 0.0 <= da {
+            above.push(a);
+        }
+
+        if (da < 0.0 && 0.0 < db) || (0.0 < da && db < 0.0) {
+            let point = Vec3::lerp_unclamped(a, b, da / (da - db));
+            below.push(point);
+            above.push(point);
+        }
+    }
+
+    (below, above)
+}
+
+/// Voxelizes a triangle: clips it to every cell it covers, and adds the height range of each clipped part as a span.
+fn rasterize_triangle(
+    heightfield: &mut Heightfield,
+    settings: &NavMeshBakeSettings,
+    triangle: &[Vec3; 3],
+    normal_y: f32,
+    walkable_normal_y: f32,
+    merge_threshold: i32,
+) {
+    let walkable = walkable_normal_y <= normal_y;
+    let origin = heightfield.origin;
+    let cell_size = settings.cell_size;
+    let min_z = triangle.iter().map(|v| v.z).fold(f32::MAX, f32::min);
+    let max_z = triangle.iter().map(|v| v.z).fold(f32::MIN, f32::max);
+    let z0 = (((min_z - origin.z) / cell_size).floor() as isize).max(0) as usize;
+    let z1 = (((max_z - origin.z) / cell_size).floor() as usize).min(heightfield.depth - 1);
+    let mut rest = triangle.to_vec();
+
+    for z in z0..=z1 {
+        let (row, above) = split_polygon(&rest, origin.z + (z + 1) as f32 * cell_size, |v| v.z);
+        rest = above;
+
+        if row.len() < 3 {
+            continue;
+        }
+
+        let min_x = row.iter().map(|v| v.x).fold(f32::MAX, f32::min);
+        let max_x = row.iter().map(|v| v.x).fold(f32::MIN, f32::max);
+        let x0 = (((min_x - origin.x) / cell_size).floor() as isize).max(0) as usize;
+        let x1 = (((max_x - origin.x) / cell_size).floor() as usize).min(heightfield.width - 1);
+        let mut row_rest = row;
+
+        for x in x0..=x1 {
+            let (cell, right) =
+                split_polygon(&row_rest, origin.x + (x + 1) as f32 * cell_size, |v| v.x);
+            row_rest = right;
+
+            if cell.len() < 3 {
+                continue;
+            }
+
+            let min_y = cell.iter().map(|v| v.y).fold(f32::MAX, f32::min) - origin.y;
+            let max_y = cell.iter().map(|v| v.y).fold(f32::MIN, f32::max) - origin.y;
+            // The tolerance keeps rounding errors from lifting a surface on a cell boundary by a whole cell.
+            let max = (max_y / settings.cell_height - 1e-3).ceil() as i32;
+            let min = ((min_y / settings.cell_height + 1e-3).floor() as i32).min(max - 1);
+            let column = x + z * heightfield.width;
+            add_span(
+                &mut heightfield.columns[column],
+                Span { min, max, walkable },
+                merge_threshold,
+            );
+
+            if normal_y < -f32::EPSILON {
+                heightfield.bottoms[column].push(min);
+            } else if f32::EPSILON < normal_y {
+                heightfield.tops[column].push(max);
+            }
+        }
+    }
+}
+
+/// Fills the insides of closed meshes, so that the floor inside an obstacle is not taken for a walkable area.
+/// Going up a column, a surface facing down enters a mesh and one facing up leaves it; the solid is where
+/// the column is inside any mesh, which also covers meshes overlapping each other.
+fn fill_solids(heightfield: &mut Heightfield, merge_threshold: i32) {
+    for column in 0..heightfield.columns.len() {
+        // Entries sort before exits at the same height, so that stacked meshes fill as one.
+        let mut crossings = heightfield.bottoms[column]
+            .iter()
+            .map(|&height| (height, 0, 1))
+            .chain(
+                heightfield.tops[column]
+                    .iter()
+                    .map(|&height| (height, 1, -1)),
+            )
+            .collect::<Vec<_>>();
+        crossings.sort_unstable();
+
+        let mut depth = 0;
+        let mut solid_min = 0;
+
+        for (height, _, change) in crossings {
+            if depth == 0 && change < 0 {
+                // Leaving a mesh that was never entered, e.g. a lone plane.
+                continue;
+            }
+
+            if depth == 0 {
+                solid_min = height;
+            }
+
+            depth += change;
+
+            if depth == 0 {
+                let span = Span {
+                    min: solid_min,
+                    max: height,
+                    walkable: false,
+                };
+                add_span(&mut heightfield.columns[column], span, merge_threshold);
+            }
+        }
+    }
+}
+
+/// Adds a span to a column, merging it with the spans it overlaps.
+/// The merged top is walkable if the highest top is, or if a walkable top is within the threshold below it.
+fn add_span(column: &mut Vec<Span>, mut span: Span, merge_threshold: i32) {
+    let mut index = 0;
+
+    while index < column.len() {
+        let other = column[index];
+
+        if other.max < span.min || span.max < other.min {
+            index += 1;
+            continue;
+        }
+
+        if (other.max - span.max).abs() <= merge_threshold {
+            span.walkable |= other.walkable;
+        } else if span.max < other.max {
+            span.walkable = other.walkable;
+        }
+
+        span.min = span.min.min(other.min);
+        span.max = span.max.max(other.max);
+        column.remove(index);
+    }
+
+    let index = column.partition_point(|other| other.min < span.min);
+    column.insert(index, span);
+}
+
+/// Finds the walkable tops with room for an agent above them, and links the ones an agent can step between.
+fn build_floor_cells(heightfield: &Heightfield, settings: &NavMeshBakeSettings) -> Vec<FloorCell> {
+    let agent_height = (settings.agent_height / settings.cell_height).ceil() as i32;
+    let max_step = (settings.max_step / settings.cell_height).floor() as i32;
+    let mut cells = Vec::new();
+    // Index of the first cell of each column, and the clearance above each cell.
+    let mut column_starts = Vec::with_capacity(heightfield.columns.len() + 1);
+    let mut ceilings = Vec::new();
+
+    for (column_index, column) in heightfield.columns.iter().enumerate() {
+        column_starts.push(cells.len());
+
+        for (index, span) in column.iter().enumerate() {
+            let ceiling = column.get(index + 1).map_or(i32::MAX, |above| above.min);
+
+            if span.walkable && agent_height <= ceiling.saturating_sub(span.max) {
+                cells.push(FloorCell {
+                    x: column_index % heightfield.width,
+                    z: column_index / heightfield.width,
+                    y: span.max,
+                    neighbors: [None; 4],
+                });
+                ceilings.push(ceiling);
+            }
+        }
+    }
+
+    column_starts.push(cells.len());
+
+    for index in 0..cells.len() {
+        let cell = cells[index];
+
+        for (direction, (dx, dz)) in DIRECTIONS.iter().enumerate() {
+            let x = cell.x as isize + dx;
+            let z = cell.z as isize + dz;
+
+            if x < 0 || z < 0 || heightfield.width as isize <= x || heightfield.depth as isize <= z
+            {
+                continue;
+            }
+
+            let column = x as usize + z as usize * heightfield.width;
+            cells[index].neighbors[direction] = (column_starts[column]..column_starts[column + 1])
+                .find(|&other| {
+                    let gap = ceilings[index].min(ceilings[other]) - cell.y.max(cells[other].y);
+                    (cell.y - cells[other].y).abs() <= max_step && agent_height <= gap
+                });
+        }
+    }
+
+    cells
+}
+
+/// Removes the cells closer to the edge of the walkable area than the radius, in cells.
+fn erode(cells: Vec<FloorCell>, radius: f32) -> Vec<FloorCell> {
+    if radius <= 0.0 {
+        return cells;
+    }
+
+    // Distances from the edges, spread over straight and diagonal steps, starting half a cell in from the edge.
+    let mut distances = vec![f32::MAX; cells.len()];
+    let mut queue = VecDeque::new();
+
+    for (index, cell) in cells.iter().enumerate() {
+        if cell.neighbors.iter().any(|neighbor| neighbor.is_none()) {
+            distances[index] = 0.5;
+            queue.push_back(index);
+        }
+    }
+
+    while let Some(index) = queue.pop_front() {
+        let cell = cells[index];
+
+        for direction in 0..4 {
+            let Some(neighbor) = cell.neighbors[direction] else {
+                continue;
+            };
+            let mut steps = vec![(neighbor, 1.0)];
+
+            // Diagonal steps go through a straight neighbour, so that they cannot cut through a corner.
+            if let Some(diagonal) = cells[neighbor].neighbors[(direction + 1) % 4] {
+                steps.push((diagonal, std::f32::consts::SQRT_2));
+            }
+
+            for (other, step) in steps {
+                let distance = distances[index] + step;
+
+                if distance + 1e-4 < distances[other] {
+                    distances[other] = distance;
+                    queue.push_back(other);
+                }
+            }
+        }
+    }
+
+    let mut remap = vec![None; cells.len()];
+    let mut kept = Vec::new();
+
+    for (index, cell) in cells.iter().enumerate() {
+        if radius <= distances[index] {
+            remap[index] = Some(kept.len());
+            kept.push(*cell);
+        }
+    }
+
+    for cell in &mut kept {
+        for neighbor in &mut cell.neighbors {
+            *neighbor = neighbor.and_then(|neighbor| remap[neighbor]);
+        }
+    }
+
+    kept
+}
+
+/// Merges the cells greedily into rectangles of similar height, which become the polygons,
+/// and links the rectangles through the parts of their sides they share.
+fn build_polygons(
+    heightfield: &Heightfield,
+    settings: &NavMeshBakeSettings,
+    cells: &[FloorCell],
+) -> NavMesh {
+    let max_step = (settings.max_step / settings.cell_height).floor() as i32;
+    let mut owners = vec![usize::MAX; cells.len()];
+    let mut rectangles = Vec::new();
+
+    for seed in 0..cells.len() {
+        if owners[seed] != usize::MAX {
+            continue;
+        }
+
+        let rectangle = rectangles.len();
+        let base = cells[seed].y;
+        let fits = |cell: usize, owners: &[usize]| {
+            owners[cell] == usize::MAX && (cells[cell].y - base).abs() <= max_step
+        };
+
+        // Grow along +x, then add rows along +z while the whole row fits.
+        let mut row = vec![seed];
+        owners[seed] = rectangle;
+
+        while let Some(next) = cells[*row.last().unwrap()].neighbors[2] {
+            if !fits(next, &owners) {
+                break;
+            }
+
+            owners[next] = rectangle;
+            row.push(next);
+        }
+
+        let mut rows = vec![row];
+
+        loop {
+            let next_row = rows
+                .last()
+                .unwrap()
+                .iter()
+                .map(|&cell| cells[cell].neighbors[1].filter(|&next| fits(next, &owners)))
+                .collect::<Option<Vec<_>>>();
+            let next_row = match next_row {
+                // The row must stay connected along x too, or the rectangle would span a wall.
+                Some(next_row)
+                    if next_row
+                        .windows(2)
+                        .all(|pair| cells[pair[0]].neighbors[2] == Some(pair[1])) =>
+                {
+                    next_row
+                }
+                _ => break,
+            };
+
+            for &cell in &next_row {
+                owners[cell] = rectangle;
+            }
+
+            rows.push(next_row);
+        }
+
+        rectangles.push(rows);
+    }
+
+    let origin = heightfield.origin;
+    let cell_size = settings.cell_size;
+    let world = |x: usize, z: usize, y: i32| {
+        Vec3::new(
+            origin.x + x as f32 * cell_size,
+            origin.y + y as f32 * settings.cell_height,
+            origin.z + z as f32 * cell_size,
+        )
+    };
+    let mut vertices = Vec::new();
+    let mut vertex_indices = HashMap::new();
+    let mut polygons = Vec::with_capacity(rectangles.len());
+
+    for rows in &rectangles {
+        let first_row = rows.first().unwrap();
+        let last_row = rows.last().unwrap();
+        let corners = [
+            (first_row[0], 0, 0),
+            (*first_row.last().unwrap(), 1, 0),
+            (*last_row.last().unwrap(), 1, 1),
+            (last_row[0], 0, 1),
+        ];
+        let polygon_vertices = corners
+            .iter()
+            .map(|&(cell, dx, dz)| {
+                let cell = cells[cell];
+                let key = (cell.x + dx, cell.z + dz, cell.y);
+                *vertex_indices.entry(key).or_insert_with(|| {
+                    vertices.push(world(key.0, key.1, key.2));
+                    vertices.len() as u32 - 1
+                })
+            })
+            .collect();
+
+        polygons.push(NavPolygon {
+            vertices: polygon_vertices,
+            links: Vec::new(),
+            flags: NavPolyFlags::WALK,
+        });
+    }
+
+    for (rectangle, rows) in rectangles.iter().enumerate() {
+        let centroid = {
+            let first = cells[rows[0][0]];
+            let last = cells[*rows.last().unwrap().last().unwrap()];
+            (world(first.x, first.z, first.y) + world(last.x + 1, last.z + 1, last.y)) * 0.5
+        };
+        // The cells along each side, in order, with the direction leaving the rectangle there.
+        let sides = [
+            (rows.iter().map(|row| row[0]).collect::<Vec<_>>(), 0),
+            (rows.last().unwrap().clone(), 1),
+            (rows.iter().map(|row| *row.last().unwrap()).collect(), 2),
+            (rows[0].clone(), 3),
+        ];
+
+        for (side, direction) in sides {
+            let mut start = 0;
+
+            while start < side.len() {
+                let neighbor_of =
+                    |cell: usize| cells[cell].neighbors[direction].map(|neighbor| owners[neighbor]);
+                let neighbor = neighbor_of(side[start]);
+                let mut end = start + 1;
+
+                while end < side.len() && neighbor_of(side[end]) == neighbor {
+                    end += 1;
+                }
+
+                if let Some(neighbor) = neighbor {
+                    let a = portal_end(cells[side[start]], direction, false, &world);
+                    let b = portal_end(cells[side[end - 1]], direction, true, &world);
+                    let (left, right) = if 0.0 < triangle_area_2d(centroid, a, b) {
+                        (a, b)
+                    } else {
+                        (b, a)
+                    };
+                    polygons[rectangle].links.push(NavLink {
+                        polygon: neighbor as u32,
+                        left,
+                        right,
+                    });
+                }
+
+                start = end;
+            }
+        }
+    }
+
+    NavMesh::new(vertices, polygons)
+}
+
+/// One end of the edge of a cell on the side in the direction; the end towards -x or -z, or the other one.
+fn portal_end(
+    cell: FloorCell,
+    direction: usize,
+    is_far_end: bool,
+    world: &impl Fn(usize, usize, i32) -> Vec3,
+) -> Vec3 {
+    let along = is_far_end as usize;
+    let (x, z) = match direction {
+        0 => (cell.x, cell.z + along),
+        1 => (cell.x + along, cell.z + 1),
+        2 => (cell.x + 1, cell.z + along),
+        _ => (cell.x + along, cell.z),
+    };
+    world(x, z, cell.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shortest way from `start` on the -x side of a box to `end` on the +x side, around its -z or +z end.
+    fn around_box(start: Vec3, end: Vec3, min: Vec3, max: Vec3) -> f32 {
+        [min.z, max.z]
+            .into_iter()
+            .map(|z| {
+                let a = Vec3::new(min.x, start.y, z);
+                let b = Vec3::new(max.x, end.y, z);
+                Vec3::distance(start, a) + Vec3::distance(a, b) + Vec3::distance(b, end)
+            })
+            .fold(f32::MAX, f32::min)
+    }
+
+    fn segment_hits_square(a: Vec3, b: Vec3, min: Vec3, max: Vec3) -> bool {
+        (0..=100).any(|i| {
+            let point = Vec3::lerp(a, b, i as f32 / 100.0);
+            min.x < point.x && point.x < max.x && min.z < point.z && point.z < max.z
+        })
+    }
+
+    #[test]
+    fn check_paths_avoid_pillars() {
+        let settings = NavMeshBakeSettings::default();
+        let mut geometry = NavMeshGeometry::new();
+        geometry.add_box(Vec3::new(0.0, -0.5, 0.0), Vec3::new(20.0, 0.0, 20.0));
+        let pillars = [
+            (Vec3::new(9.0, 0.0, 4.0), Vec3::new(11.0, 3.0, 16.0)),
+            (Vec3::new(3.0, 0.0, 14.0), Vec3::new(5.0, 3.0, 16.0)),
+        ];
+
+        for (min, max) in pillars {
+            geometry.add_box(min, max);
+        }
+
+        let navmesh = bake_navmesh(&geometry, &settings).unwrap();
+
+        // Walking around the long pillar in the middle.
+        let start = Vec3::new(5.0, 0.0, 10.0);
+        let end = Vec3::new(15.0, 0.0, 10.0);
+        let path = navmesh.find_path(start, end).unwrap();
+        assert!(Vec3::distance(path.start(), start) < 0.01);
+        assert!(Vec3::distance(path.end(), end) < 0.01);
+
+        let radius = settings.agent_radius;
+        for (min, max) in pillars {
+            // The path keeps the agent radius away from the pillars, give or take a cell.
+            let clearance = Vec3::new(
+                radius - settings.cell_size,
+                0.0,
+                radius - settings.cell_size,
+            );
+            for segment in path.points.windows(2) {
+                assert!(!segment_hits_square(
+                    segment[0],
+                    segment[1],
+                    min - clearance,
+                    max + clearance
+                ));
+            }
+        }
+
+        let inflated = Vec3::new(radius, 0.0, radius);
+        let optimal = around_box(start, end, pillars[0].0 - inflated, pillars[0].1 + inflated);
+        let length = path.length();
+        assert!(
+            optimal * 0.98 < length && length < optimal * 1.05,
+            "{} vs {}",
+            length,
+            optimal
+        );
+
+        // In the open, the path is a straight line.
+        let start = Vec3::new(2.0, 0.0, 2.0);
+        let end = Vec3::new(18.0, 0.0, 3.0);
+        let path = navmesh.find_path(start, end).unwrap();
+        assert_eq!(path.points.len(), 2);
+        assert!((path.length() - Vec3::distance(start, end)).abs() < 0.01);
+
+        // Points inside a pillar snap to its side.
+        let snapped = navmesh
+            .closest_point_on_navmesh(Vec3::new(4.0, 0.0, 15.0))
+            .unwrap()
+            .position;
+        assert!(!(3.0 < snapped.x && snapped.x < 5.0 && 14.0 < snapped.z && snapped.z < 16.0));
+    }
+}
@@ -2,6 +2,7 @@ use specs::{prelude::*, Component};
 
 mod component_storage;
 mod handle;
+mod model_spawning;
 mod object_component;
 mod object_handle;
 mod object_hierarchy;
@@ -9,10 +10,12 @@ mod object_id;
 mod object_id_allocator;
 mod object_manager;
 mod object_name_registry;
+mod object_picking;
 mod object_storage;
 
 pub use component_storage::*;
 pub use handle::*;
+pub use model_spawning::*;
 pub use object_component::*;
 pub use object_handle::*;
 pub use object_hierarchy::*;
@@ -20,6 +23,7 @@ pub use object_id::*;
 pub use object_id_allocator::*;
 pub use object_manager::*;
 pub use object_name_registry::*;
+pub use object_picking::*;
 pub use object_storage::*;
 
 #[derive(Debug, Clone, Copy, Component)]
@@ -157,6 +161,25 @@ pub mod new {
             self.remove_component_at::<T>(storage, index);
         }
 
+        pub fn remove_component_untyped(
+            &mut self,
+            storage: &mut ComponentStorage,
+            id: ComponentId,
+        ) {
+            let index = if let Some(index) = self
+                .component_ids
+                .iter()
+                .position(|component| *component == id)
+            {
+                index
+            } else {
+                return;
+            };
+
+            storage.remove_component_untyped(id);
+            self.component_ids.swap_remove(index);
+        }
+
         pub fn remove_component_at<T: Component>(
             &mut self,
             storage: &mut ComponentStorage,
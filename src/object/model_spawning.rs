@@ -0,0 +1,329 @@
+use super::ObjectHandle;
+use crate::{
+    animation::{AnimationPlayer, SkeletalAnimation, Skeleton},
+    gfx::{
+        MaterialHandle, MeshRenderer, MeshSkin, PbrFallbacks, PbrLighting, PbrMaterial,
+        BUILT_IN_SHADER_STANDARD_PBR, BUILT_IN_SHADER_STANDARD_PBR_SKINNED,
+    },
+    math::Mat4,
+    transform::Transform,
+    use_context,
+};
+use asset::assets::{ModelAsset, ModelSkeleton, VertexAttributeKind};
+use logging::StandardLogLevel;
+use specs::{Builder, Entity, WorldExt};
+use std::collections::HashMap;
+
+/// Spawns the node hierarchy of a model as objects, under the parent if any, and returns the object of its root node.
+/// A model without a root node gets an unnamed root object holding its top-level nodes.
+///
+/// Each object is named after its node and placed at the node's local transform. A node with one mesh gets a
+/// [`MeshRenderer`] of it; a node with several meshes gets a child object per mesh, named `<node>#<slot>`. The mesh
+/// renderers share the buffers of the model, and are given the index of their mesh in the model as their material
+/// slot. The renderers of a slot share a material of the built-in standard PBR shader, of the glTF defaults and lit
+/// by the default [`PbrLighting`]: change it with [`set_model_pbr_material`], or replace it with
+/// [`set_model_material`].
+///
+/// The mesh renderers of skinned meshes get a [`MeshSkin`] moved by the objects of the nodes of the bones, and the
+/// root object gets an [`AnimationPlayer`] holding the animations of the model by name, if it has any. The player
+/// does not start on its own: call [`play`](AnimationPlayer::play).
+pub fn spawn_model(model: &dyn ModelAsset, parent: Option<&ObjectHandle>) -> ObjectHandle {
+    let node_count = model.nodes().len();
+    let mut nodes = vec![None; node_count];
+    let mut skinned = Vec::new();
+    let mut materials = PbrMaterials::new(PbrMaterial::default());
+
+    let root = match model
+        .root_node_index()
+        .filter(|&index| (index as usize) < node_count)
+    {
+        Some(root) => spawn_node(
+            model,
+            root as usize,
+            parent,
+            &mut nodes,
+            &mut skinned,
+            &mut materials,
+        ),
+        None => {
+            let root = create_object(None, Transform::new(), parent, None);
+
+            for (index, node) in model.nodes().iter().enumerate() {
+                if node.parent_index.is_none() && nodes[index].is_none() {
+                    spawn_node(
+                        model,
+                        index,
+                        Some(&root),
+                        &mut nodes,
+                        &mut skinned,
+                        &mut materials,
+                    );
+                }
+            }
+
+            root
+        }
+    };
+
+    if let Some(skeleton) = model.skeleton() {
+        attach_skins(skeleton, &nodes, &skinned);
+    }
+
+    if !model.animations().is_empty() {
+        let player = model
+            .animations()
+            .iter()
+            .fold(AnimationPlayer::new(), |player, animation| {
+                player.with_clip(
+                    animation.name.clone(),
+                    SkeletalAnimation::from_model_animation(animation),
+                )
+            });
+        let ctx = use_context();
+        let world = ctx.world();
+        world
+            .write_storage::<AnimationPlayer>()
+            .insert(root.entity, player)
+            .unwrap();
+    }
+
+    root
+}
+
+/// Sets the material of every mesh renderer of the material slot under the object, as spawned by [`spawn_model`].
+pub fn set_model_material(root: &ObjectHandle, slot: u32, material: MaterialHandle) {
+    set_slot_materials(root, slot, |_| Some(material.clone()));
+}
+
+/// Gives every mesh renderer of the material slot under the object, as spawned by [`spawn_model`], a standard PBR
+/// material of the properties, lit by the default [`PbrLighting`]. Skinned renderers get the skinned variant.
+pub fn set_model_pbr_material(root: &ObjectHandle, slot: u32, properties: PbrMaterial) {
+    let mut materials = PbrMaterials::new(properties);
+    set_slot_materials(root, slot, |mesh_renderer| {
+        materials.material(slot, mesh_renderer.skin().is_some())
+    });
+}
+
+fn set_slot_materials(
+    root: &ObjectHandle,
+    slot: u32,
+    mut material: impl FnMut(&MeshRenderer) -> Option<MaterialHandle>,
+) {
+    let ctx = use_context();
+    let object_mgr = ctx.object_mgr();
+    let world = ctx.world();
+    let mut mesh_renderers = world.write_storage::<MeshRenderer>();
+    let hierarchy = object_mgr.object_hierarchy();
+
+    if !hierarchy.contains(root.object_id) {
+        return;
+    }
+
+    for &object_id in hierarchy.object_and_children(root.object_id) {
+        if let Some(mesh_renderer) = mesh_renderers.get_mut(hierarchy.entity(object_id)) {
+            if mesh_renderer.material_slot() != slot {
+                continue;
+            }
+
+            if let Some(material) = material(mesh_renderer) {
+                mesh_renderer.set_material(material);
+            }
+        }
+    }
+}
+
+/// Spawns the node and its children, recording the objects of the nodes and the entities of the skinned meshes.
+fn spawn_node(
+    model: &dyn ModelAsset,
+    index: usize,
+    parent: Option<&ObjectHandle>,
+    nodes: &mut [Option<ObjectHandle>],
+    skinned: &mut Vec<Entity>,
+    materials: &mut PbrMaterials,
+) -> ObjectHandle {
+    let node = &model.nodes()[index];
+    let name = if node.name.is_empty() {
+        None
+    } else {
+        Some(node.name.clone())
+    };
+    let transform = Transform::from_mat4(&Mat4::new(node.transform.matrix));
+    let mesh_renderers = Vec::from_iter(node.mesh_indices.iter().filter_map(|&slot| {
+        let mesh = model.meshes().get(slot as usize)?;
+        let mut mesh_renderer = MeshRenderer::new();
+        mesh_renderer.set_model_mesh(mesh);
+        mesh_renderer.set_material_slot(slot);
+        let is_skinned = mesh
+            .vertex_attributes
+            .iter()
+            .any(|attribute| attribute.kind == VertexAttributeKind::BoneIndices);
+
+        if let Some(material) = materials.material(slot, is_skinned) {
+            mesh_renderer.set_material(material);
+        }
+
+        Some((mesh_renderer, is_skinned))
+    }));
+
+    let handle = if mesh_renderers.len() == 1 {
+        let (mesh_renderer, is_skinned) = mesh_renderers.into_iter().next().unwrap();
+        let handle = create_object(name, transform, parent, Some(mesh_renderer));
+
+        if is_skinned {
+            skinned.push(handle.entity);
+        }
+
+        handle
+    } else {
+        let handle = create_object(name, transform, parent, None);
+
+        for (mesh_renderer, is_skinned) in mesh_renderers {
+            let name = format!("{}#{}", node.name, mesh_renderer.material_slot());
+            let child = create_object(
+                Some(name),
+                Transform::new(),
+                Some(&handle),
+                Some(mesh_renderer),
+            );
+
+            if is_skinned {
+                skinned.push(child.entity);
+            }
+        }
+
+        handle
+    };
+
+    // Guards against nodes reached twice, as in malformed models.
+    nodes[index] = Some(handle.clone());
+
+    for &child in &node.children_indices {
+        if matches!(nodes.get(child as usize), Some(None)) {
+            spawn_node(
+                model,
+                child as usize,
+                Some(&handle),
+                nodes,
+                skinned,
+                materials,
+            );
+        }
+    }
+
+    handle
+}
+
+/// Gives the skinned mesh renderers a skin of the bones of the model, each moved by the object of its node.
+fn attach_skins(skeleton: &ModelSkeleton, nodes: &[Option<ObjectHandle>], skinned: &[Entity]) {
+    if skinned.is_empty() {
+        return;
+    }
+
+    let ctx = use_context();
+    let world = ctx.world();
+    let mut mesh_renderers = world.write_storage::<MeshRenderer>();
+    let mut render_mgr = ctx.render_mgr_mut();
+    let device = &ctx.gfx_ctx().device;
+    // Names the bones of the skins, for the objects attached to them.
+    let bone_skeleton = match Skeleton::from_model_skeleton(skeleton) {
+        Ok(bone_skeleton) => Some(bone_skeleton),
+        Err(err) => {
+            ctx.logger().log(
+                StandardLogLevel::Warning,
+                format!("skinned meshes have no bone names: {}", err),
+            );
+            None
+        }
+    };
+
+    for &entity in skinned {
+        let mesh_renderer = match mesh_renderers.get_mut(entity) {
+            Some(mesh_renderer) => mesh_renderer,
+            None => continue,
+        };
+        let bones = skeleton.bones.iter().map(|bone| {
+            let object = bone
+                .node_index
+                .and_then(|index| nodes.get(index as usize)?.as_ref())
+                .map(|handle| handle.object_id);
+            (object, Mat4::new(bone.inverse_bind_matrix))
+        });
+
+        let skin = MeshSkin::new(bones, device, render_mgr.bind_group_layout_cache());
+        mesh_renderer.set_skin(Some(match &bone_skeleton {
+            Some(bone_skeleton) => skin.with_skeleton(bone_skeleton.clone()),
+            None => skin,
+        }));
+    }
+}
+
+/// Standard PBR materials of the same properties, created on first use and shared by the renderers of a slot.
+struct PbrMaterials {
+    properties: PbrMaterial,
+    lighting: PbrLighting,
+    fallbacks: Option<PbrFallbacks>,
+    materials: HashMap<(u32, bool), MaterialHandle>,
+}
+
+impl PbrMaterials {
+    fn new(properties: PbrMaterial) -> Self {
+        Self {
+            properties,
+            lighting: PbrLighting::default(),
+            fallbacks: None,
+            materials: HashMap::new(),
+        }
+    }
+
+    /// Returns the material of the slot, of the skinned variant of the shader if `is_skinned`.
+    fn material(&mut self, slot: u32, is_skinned: bool) -> Option<MaterialHandle> {
+        if let Some(material) = self.materials.get(&(slot, is_skinned)) {
+            return Some(material.clone());
+        }
+
+        let ctx = use_context();
+        let key = if is_skinned {
+            BUILT_IN_SHADER_STANDARD_PBR_SKINNED
+        } else {
+            BUILT_IN_SHADER_STANDARD_PBR
+        };
+        let shader = ctx.built_in_shader_mgr().find_shader(key)?;
+        let device = &ctx.gfx_ctx().device;
+        let fallbacks = self
+            .fallbacks
+            .get_or_insert_with(|| PbrFallbacks::new(device));
+        let material = MaterialHandle::new(self.properties.create_material(
+            shader,
+            &self.lighting,
+            fallbacks,
+            device,
+            ctx.render_mgr_mut().pipeline_layout_cache(),
+        ));
+        self.materials.insert((slot, is_skinned), material.clone());
+        Some(material)
+    }
+}
+
+fn create_object(
+    name: Option<String>,
+    transform: Transform,
+    parent: Option<&ObjectHandle>,
+    mesh_renderer: Option<MeshRenderer>,
+) -> ObjectHandle {
+    let ctx = use_context();
+    let mut world = ctx.world_mut();
+    let mut object_mgr = ctx.object_mgr_mut();
+    let (handle, builder) = object_mgr.create_object_builder(&mut world, name, Some(transform));
+
+    match mesh_renderer {
+        Some(mesh_renderer) => builder.with(mesh_renderer).build(),
+        None => builder.build(),
+    };
+
+    // A freshly created object has no children, so it can't form a cycle.
+    object_mgr
+        .object_hierarchy_mut()
+        .set_parent(handle.object_id, parent.map(|parent| parent.object_id))
+        .unwrap();
+    handle
+}
@@ -1,8 +1,18 @@
-use super::{ObjectComponent, ObjectId};
-use crate::ContextHandle;
-use specs::Entity;
+use super::{HierarchyError, ObjectComponent, ObjectId};
+use crate::{
+    animation::{bone_matrix, BoneAttachment, BoneHandle},
+    deferred::MutationKind,
+    gfx::{Layers, MeshRenderer},
+    handles::StaleHandle,
+    math::Mat4,
+    transform::Transform,
+    ContextHandle,
+};
+use specs::{Entity, WorldExt};
 use std::hash::{Hash, Hasher};
 
+/// A handle of an object. It outlives the object: once the object has been removed, queries return nothing
+/// and changes fail with [`StaleHandle`], also after the id has been reused by another object.
 #[derive(Clone)]
 pub struct ObjectHandle {
     pub ctx: ContextHandle,
@@ -23,18 +33,35 @@ impl ObjectHandle {
         T::new(self.clone())
     }
 
+    /// Returns `false` once the object has been removed.
+    pub fn is_alive(&self) -> bool {
+        self.ctx.object_mgr().is_alive(self.object_id)
+    }
+
+    fn ensure_alive(&self) -> Result<(), StaleHandle> {
+        if self.is_alive() {
+            Ok(())
+        } else {
+            Err(StaleHandle::new("object"))
+        }
+    }
+
     pub fn is_active(&self) -> bool {
-        self.ctx
-            .object_mgr()
-            .object_hierarchy()
-            .is_active(self.object_id)
+        self.is_alive()
+            && self
+                .ctx
+                .object_mgr()
+                .object_hierarchy()
+                .is_active(self.object_id)
     }
 
     pub fn is_active_self(&self) -> bool {
-        self.ctx
-            .object_mgr()
-            .object_hierarchy()
-            .is_active_self(self.object_id)
+        self.is_alive()
+            && self
+                .ctx
+                .object_mgr()
+                .object_hierarchy()
+                .is_active_self(self.object_id)
     }
 
     pub fn name(&self) -> Option<String> {
@@ -45,14 +72,24 @@ impl ObjectHandle {
             .cloned()
     }
 
-    pub fn index(&self) -> u32 {
-        self.ctx
-            .object_mgr()
-            .object_hierarchy()
-            .index(self.object_id)
+    pub fn index(&self) -> Option<u32> {
+        if !self.is_alive() {
+            return None;
+        }
+
+        Some(
+            self.ctx
+                .object_mgr()
+                .object_hierarchy()
+                .index(self.object_id),
+        )
     }
 
     pub fn parent(&self) -> Option<Self> {
+        if !self.is_alive() {
+            return None;
+        }
+
         self.ctx
             .object_mgr()
             .object_hierarchy()
@@ -61,6 +98,10 @@ impl ObjectHandle {
     }
 
     pub fn parents(&self) -> Vec<Self> {
+        if !self.is_alive() {
+            return Vec::new();
+        }
+
         self.ctx
             .object_mgr()
             .object_hierarchy()
@@ -71,6 +112,10 @@ impl ObjectHandle {
     }
 
     pub fn children(&self) -> Vec<Self> {
+        if !self.is_alive() {
+            return Vec::new();
+        }
+
         self.ctx
             .object_mgr()
             .object_hierarchy()
@@ -81,6 +126,10 @@ impl ObjectHandle {
     }
 
     pub fn direct_children(&self) -> Vec<Self> {
+        if !self.is_alive() {
+            return Vec::new();
+        }
+
         match self
             .ctx
             .object_mgr()
@@ -94,29 +143,162 @@ impl ObjectHandle {
         }
     }
 
-    pub fn set_active(&self, active: bool) {
+    pub fn set_active(&self, active: bool) -> Result<(), StaleHandle> {
+        self.ensure_alive()?;
         self.ctx
             .object_mgr_mut()
             .object_hierarchy_mut()
             .set_active(self.object_id, active);
+        Ok(())
     }
 
-    pub fn set_name(&self, name: impl Into<Option<String>>) {
+    pub fn set_name(&self, name: impl Into<Option<String>>) -> Result<(), StaleHandle> {
+        self.ensure_alive()?;
         self.ctx
             .object_mgr_mut()
             .object_name_registry_mut()
             .set_name(self.object_id, name.into());
+        Ok(())
     }
 
-    pub fn set_parent<'a>(&self, parent: impl Into<Option<&'a Self>>) {
+    pub fn set_parent<'a>(
+        &self,
+        parent: impl Into<Option<&'a Self>>,
+    ) -> Result<(), HierarchyError> {
         self.ctx
             .object_mgr_mut()
             .object_hierarchy_mut()
-            .set_parent(self.object_id, parent.into().map(|parent| parent.object_id));
+            .set_parent(self.object_id, parent.into().map(|parent| parent.object_id))
+    }
+
+    /// Layers of the object as a bitmask, see [`Layers`]. Objects default to the first layer.
+    pub fn layer(&self) -> u32 {
+        Layers::of(self.ctx.world().read_storage::<Layers>().get(self.entity)).0
     }
 
-    pub fn remove(&self) {
-        self.ctx.object_mgr_mut().remove_object(self);
+    /// Moves the object to the layers of the bitmask. Cameras whose culling mask shares no bit with it skip it.
+    pub fn set_layer(&self, layer: u32) -> Result<(), StaleHandle> {
+        self.ensure_alive()?;
+        self.ctx
+            .world()
+            .write_storage::<Layers>()
+            .insert(self.entity, Layers(layer))
+            .map_err(|_| StaleHandle::new("object"))?;
+        Ok(())
+    }
+
+    /// Places the object relative to an externally driven matrix, such as an animated joint, in its parent's space.
+    /// Passing `None` detaches it again.
+    pub fn set_attachment(&self, attachment: Option<Mat4>) -> Result<(), StaleHandle> {
+        self.ensure_alive()?;
+        self.ctx
+            .object_mgr_mut()
+            .object_hierarchy_mut()
+            .set_attachment(self.object_id, attachment);
+        Ok(())
+    }
+
+    /// Attaches the object to a bone of the skinned mesh renderer on `renderer`, see [`BoneAttachment`]: it becomes a
+    /// child of `renderer` that follows the animated bone, and its transform is then relative to the bone.
+    /// With `keep_world`, the transform is changed so that the object stays where it is; otherwise it is kept as is.
+    pub fn attach_to_bone(
+        &self,
+        renderer: &Self,
+        bone: BoneHandle,
+        keep_world: bool,
+    ) -> Result<(), HierarchyError> {
+        self.ensure_alive()?;
+        renderer.ensure_alive()?;
+
+        let world = self.ctx.world();
+        let mut attachments = world.write_storage::<BoneAttachment>();
+        let mut transforms = world.write_storage::<Transform>();
+        let mut object_mgr = self.ctx.object_mgr_mut();
+        let object_hierarchy = object_mgr.object_hierarchy_mut();
+
+        // Moving to another bone keeps the parent from before the first one.
+        let previous_parent = match attachments.get(self.entity) {
+            Some(attachment) => attachment.previous_parent(),
+            None => object_hierarchy.parent(self.object_id),
+        };
+        let bone_object = world
+            .read_storage::<MeshRenderer>()
+            .get(renderer.entity)
+            .and_then(|mesh_renderer| mesh_renderer.skin()?.bone_object(&bone))
+            .filter(|&bone_object| object_hierarchy.contains(bone_object));
+        let world_matrix =
+            object_hierarchy.compute_matrix(self.object_id, |entity| transforms.get(entity));
+
+        object_hierarchy.set_parent(self.object_id, Some(renderer.object_id))?;
+        let attachment = bone_object.map(|bone_object| {
+            bone_matrix(
+                object_hierarchy,
+                renderer.object_id,
+                bone_object,
+                |entity| transforms.get(entity),
+            )
+        });
+        object_hierarchy.set_attachment(self.object_id, attachment);
+
+        if keep_world {
+            let parent_matrix = object_hierarchy
+                .compute_matrix(renderer.object_id, |entity| transforms.get(entity));
+            let parent_matrix = match object_hierarchy.attachment(self.object_id) {
+                Some(attachment) => attachment.clone() * &parent_matrix,
+                None => parent_matrix,
+            };
+
+            if let Some(transform) = transforms.get_mut(self.entity) {
+                *transform = Transform::from_mat4(&(world_matrix * &parent_matrix.inversed()));
+            }
+        }
+
+        attachments
+            .insert(
+                self.entity,
+                BoneAttachment::new(renderer.object_id, bone, previous_parent),
+            )
+            .map_err(|_| StaleHandle::new("object"))?;
+        Ok(())
+    }
+
+    /// Detaches the object from its bone, back to the parent it had before [`attach_to_bone`](Self::attach_to_bone).
+    /// Its transform is kept, now relative to that parent again. Does nothing if it is not attached.
+    pub fn detach_from_bone(&self) -> Result<(), HierarchyError> {
+        self.ensure_alive()?;
+
+        let attachment = match self
+            .ctx
+            .world()
+            .write_storage::<BoneAttachment>()
+            .remove(self.entity)
+        {
+            Some(attachment) => attachment,
+            None => return Ok(()),
+        };
+        let mut object_mgr = self.ctx.object_mgr_mut();
+        let object_hierarchy = object_mgr.object_hierarchy_mut();
+        let previous_parent = attachment
+            .previous_parent()
+            .filter(|&parent| object_hierarchy.contains(parent));
+
+        object_hierarchy.set_attachment(self.object_id, None);
+        object_hierarchy.set_parent(self.object_id, previous_parent)
+    }
+
+    /// Removes the object and its children. Fails if it has been removed already.
+    /// During a restricted [`FramePhase`](crate::deferred::FramePhase), the object stays alive until the start of
+    /// the next frame.
+    pub fn remove(&self) -> Result<(), StaleHandle> {
+        self.ensure_alive()?;
+
+        let handle = self.clone();
+        self.ctx
+            .mutate_structurally(MutationKind::ObjectRemoval, move || {
+                // Removing it twice in the same frame queues it twice; the second one finds it gone.
+                handle.ctx.object_mgr_mut().remove_object(&handle).ok();
+            });
+        Ok(())
     }
 }
 
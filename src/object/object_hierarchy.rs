@@ -1,8 +1,19 @@
 use super::ObjectId;
-use crate::{math::Mat4, transform::Transform};
+use crate::{handles::StaleHandle, math::Mat4, transform::Transform};
 use bitvec::prelude::*;
 use specs::prelude::*;
-use std::{cmp::Ordering, ops::Range};
+use std::{cmp::Ordering, collections::BTreeSet, ops::Range};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum HierarchyError {
+    #[error("object {0:?} can't be its own parent")]
+    SelfParent(ObjectId),
+    #[error("object {parent:?} is a descendant of object {object:?}, so it can't be its parent")]
+    Cycle { object: ObjectId, parent: ObjectId },
+    #[error(transparent)]
+    Stale(#[from] StaleHandle),
+}
 
 #[derive(Debug, Clone, Copy, Eq, Ord, Hash)]
 pub struct ObjectSpan {
@@ -28,6 +39,61 @@ impl PartialOrd for ObjectSpan {
     }
 }
 
+/// Maps object ids to the slots of the unordered per-object data.
+/// Slots are compacted independently of ids, so ids held by handles stay valid.
+/// The generation of the id is kept along, so that a removed id never maps to the object reusing its index.
+#[derive(Debug, Default)]
+pub(crate) struct ObjectSlotTable {
+    slots: Vec<(u32, u32)>,
+}
+
+impl ObjectSlotTable {
+    const INVALID_SLOT: (u32, u32) = (u32::MAX, u32::MAX);
+
+    /// Returns `None` if the object has been removed, or never added.
+    pub fn get(&self, object: ObjectId) -> Option<usize> {
+        match self.slots.get(object.get() as usize) {
+            Some(&(generation, slot))
+                if slot != Self::INVALID_SLOT.1 && generation == object.generation() =>
+            {
+                Some(slot as usize)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the slot of an object the hierarchy holds. Panics if the object has been removed.
+    pub fn slot(&self, object: ObjectId) -> usize {
+        self.get(object)
+            .unwrap_or_else(|| panic!("object {:?} has been removed", object))
+    }
+
+    pub fn set(&mut self, object: ObjectId, slot: usize) {
+        let index = object.get() as usize;
+
+        if self.slots.len() <= index {
+            self.slots.resize(index + 1, Self::INVALID_SLOT);
+        }
+
+        self.slots[index] = (object.generation(), slot as u32);
+    }
+
+    pub fn remove(&mut self, object: ObjectId) -> usize {
+        let slot = self.slot(object);
+        self.slots[object.get() as usize] = Self::INVALID_SLOT;
+
+        while self.slots.last() == Some(&Self::INVALID_SLOT) {
+            self.slots.pop();
+        }
+
+        slot
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.slots.shrink_to_fit();
+    }
+}
+
 impl From<ObjectSpan> for Range<u32> {
     fn from(span: ObjectSpan) -> Self {
         span.index..span.index + span.count
@@ -45,14 +111,16 @@ pub struct ObjectSiblingIter<'a> {
     parent: ObjectId,
     object: ObjectId,
     sibling_index: usize,
+    object_slots: &'a ObjectSlotTable,
     object_spans: &'a [ObjectSpan],
     objects: &'a [ObjectId],
 }
 
 impl<'a> ObjectSiblingIter<'a> {
-    pub fn new(
+    pub(crate) fn new(
         parent: Option<ObjectId>,
         object: ObjectId,
+        object_slots: &'a ObjectSlotTable,
         object_spans: &'a [ObjectSpan],
         objects: &'a [ObjectId],
     ) -> Self {
@@ -60,6 +128,7 @@ impl<'a> ObjectSiblingIter<'a> {
             parent: parent.unwrap_or(object),
             object,
             sibling_index: 0,
+            object_slots,
             object_spans,
             objects,
         }
@@ -79,14 +148,14 @@ impl<'a> Iterator for ObjectSiblingIter<'a> {
             };
         }
 
-        let parent_span = self.object_spans[self.parent.get() as usize];
+        let parent_span = self.object_spans[self.object_slots.slot(self.parent)];
         let parent_span_index = parent_span.index as usize;
         let parent_span_count = parent_span.count as usize;
         let index = parent_span_index + 1 + self.sibling_index;
 
         if index < parent_span_index + parent_span_count {
             let object = self.objects[index];
-            self.sibling_index += self.object_spans[object.get() as usize].count as usize;
+            self.sibling_index += self.object_spans[self.object_slots.slot(object)].count as usize;
             Some(object)
         } else {
             None
@@ -104,10 +173,14 @@ pub struct ObjectHierarchy {
     object_current_frame_dirties: BitVec,
     object_actives: BitVec,
     object_active_selfs: BitVec,
-    // unordered
+    // unordered, indexed by slots
+    object_slots: ObjectSlotTable,
+    free_slots: BTreeSet<u32>,
+    slot_objects: Vec<Option<ObjectId>>,
     object_spans: Vec<ObjectSpan>,
     object_parents: Vec<Vec<ObjectId>>,
     object_matrices: Vec<Mat4>,
+    object_attachments: Vec<Option<Mat4>>,
 }
 
 impl ObjectHierarchy {
@@ -123,53 +196,75 @@ impl ObjectHierarchy {
         &self.object_entities
     }
 
+    /// Returns `false` if the object has been removed, or never added.
+    pub fn contains(&self, object: ObjectId) -> bool {
+        self.object_slots.get(object).is_some()
+    }
+
+    /// Position of the object among the ordered objects, or `None` if it has been removed.
+    fn object_index(&self, object: ObjectId) -> Option<usize> {
+        let slot = self.object_slots.get(object)?;
+        Some(self.object_spans[slot].index as usize)
+    }
+
     pub fn index(&self, object: ObjectId) -> u32 {
-        self.object_spans[object.get() as usize].index
+        self.object_spans[self.object_slots.slot(object)].index
     }
 
     pub fn entity(&self, object: ObjectId) -> Entity {
-        self.object_entities[self.object_spans[object.get() as usize].index as usize]
+        self.object_entities[self.object_spans[self.object_slots.slot(object)].index as usize]
     }
 
+    /// The flags of a removed object are all `false`.
     pub fn is_dirty(&self, object: ObjectId) -> bool {
-        self.object_dirties[self.object_spans[object.get() as usize].index as usize]
+        self.object_index(object)
+            .map_or(false, |index| self.object_dirties[index])
     }
 
     pub fn is_current_frame_dirty(&self, object: ObjectId) -> bool {
-        self.object_current_frame_dirties[self.object_spans[object.get() as usize].index as usize]
+        self.object_index(object)
+            .map_or(false, |index| self.object_current_frame_dirties[index])
     }
 
     pub fn is_active(&self, object: ObjectId) -> bool {
-        self.object_actives[self.object_spans[object.get() as usize].index as usize]
+        self.object_index(object)
+            .map_or(false, |index| self.object_actives[index])
     }
 
     pub fn is_active_self(&self, object: ObjectId) -> bool {
-        self.object_active_selfs[self.object_spans[object.get() as usize].index as usize]
+        self.object_index(object)
+            .map_or(false, |index| self.object_active_selfs[index])
     }
 
     pub fn parent(&self, object: ObjectId) -> Option<ObjectId> {
-        self.object_parents[object.get() as usize].first().copied()
+        self.object_parents[self.object_slots.slot(object)]
+            .first()
+            .copied()
     }
 
     pub fn parents(&self, object: ObjectId) -> &[ObjectId] {
-        &self.object_parents[object.get() as usize]
+        &self.object_parents[self.object_slots.slot(object)]
     }
 
     pub fn children(&self, object: ObjectId) -> &[ObjectId] {
-        let span = self.object_spans[object.get() as usize];
+        let span = self.object_spans[self.object_slots.slot(object)];
         &self.objects[(span.index + 1) as usize..(span.index + span.count) as usize]
     }
 
     pub fn matrix(&self, object: ObjectId) -> &Mat4 {
-        &self.object_matrices[object.get() as usize]
+        &self.object_matrices[self.object_slots.slot(object)]
     }
 
     pub fn matrix_mut(&mut self, object: ObjectId) -> &mut Mat4 {
-        &mut self.object_matrices[object.get() as usize]
+        &mut self.object_matrices[self.object_slots.slot(object)]
+    }
+
+    pub fn attachment(&self, object: ObjectId) -> Option<&Mat4> {
+        self.object_attachments[self.object_slots.slot(object)].as_ref()
     }
 
     pub fn object_and_children(&self, object: ObjectId) -> &[ObjectId] {
-        let span = self.object_spans[object.get() as usize];
+        let span = self.object_spans[self.object_slots.slot(object)];
         &self.objects[span.index as usize..(span.index + span.count) as usize]
     }
 
@@ -177,19 +272,21 @@ impl ObjectHierarchy {
         ObjectSiblingIter::new(
             self.parent(object),
             object,
+            &self.object_slots,
             &self.object_spans,
             &self.objects,
         )
     }
 
     pub fn direct_children_iter(&self, object: ObjectId) -> Option<ObjectSiblingIter> {
-        let span = self.object_spans[object.get() as usize];
+        let span = self.object_spans[self.object_slots.slot(object)];
         if span.count < 2 {
             None
         } else {
             Some(ObjectSiblingIter::new(
                 Some(object),
                 self.objects[span.index as usize + 1],
+                &self.object_slots,
                 &self.object_spans,
                 &self.objects,
             ))
@@ -197,8 +294,16 @@ impl ObjectHierarchy {
     }
 
     pub fn set_dirty(&mut self, object: ObjectId) {
-        self.object_dirties.as_mut_bitslice()[self.object_spans[object.get() as usize].to_range()]
-            .fill(true);
+        self.object_dirties.as_mut_bitslice()
+            [self.object_spans[self.object_slots.slot(object)].to_range()]
+        .fill(true);
+    }
+
+    /// Sets the attachment matrix of the given object, e.g. the model space matrix of a bone it is attached to.
+    /// It is applied between the object's own transform and its parent's matrix.
+    pub fn set_attachment(&mut self, object: ObjectId, attachment: Option<Mat4>) {
+        self.object_attachments[self.object_slots.slot(object)] = attachment;
+        self.set_dirty(object);
     }
 
     pub fn copy_dirty_to_current_frame(&mut self) {
@@ -207,8 +312,8 @@ impl ObjectHierarchy {
     }
 
     pub fn set_active(&mut self, object: ObjectId, is_active: bool) {
-        self.object_active_selfs
-            .set(object.get() as usize, is_active);
+        let index = self.object_spans[self.object_slots.slot(object)].index as usize;
+        self.object_active_selfs.set(index, is_active);
 
         let is_parent_active = match self.parent(object) {
             Some(parent) => self.is_active(parent),
@@ -221,12 +326,12 @@ impl ObjectHierarchy {
 
             flags.push(true);
 
-            let base_index = self.object_spans[object.get() as usize].index;
+            let base_index = self.object_spans[self.object_slots.slot(object)].index;
 
             for &child in children {
                 let is_parent_active = match self.parent(child) {
                     Some(parent) => {
-                        let parent_index = self.object_spans[parent.get() as usize].index;
+                        let parent_index = self.object_spans[self.object_slots.slot(parent)].index;
                         let index = parent_index - base_index;
                         flags[index as usize]
                     }
@@ -236,11 +341,11 @@ impl ObjectHierarchy {
             }
 
             self.object_actives.as_mut_bitslice()
-                [self.object_spans[object.get() as usize].to_range()]
+                [self.object_spans[self.object_slots.slot(object)].to_range()]
             .copy_from_bitslice(&flags);
         } else {
             self.object_actives.as_mut_bitslice()
-                [self.object_spans[object.get() as usize].to_range()]
+                [self.object_spans[self.object_slots.slot(object)].to_range()]
             .fill(false);
         }
     }
@@ -251,22 +356,29 @@ impl ObjectHierarchy {
 
     /// Adds the given object to the hierarchy.
     pub fn add(&mut self, object: ObjectId, entity: Entity) {
-        let object_usize = object.get() as usize;
+        let span = ObjectSpan {
+            index: self.objects.len() as u32,
+            count: 1,
+        };
 
-        if object_usize < self.object_spans.len() {
-            self.object_spans[object_usize] = ObjectSpan {
-                index: self.objects.len() as u32,
-                count: 1,
-            };
-            self.object_parents[object_usize].clear();
-        } else {
-            debug_assert!(object_usize == self.object_spans.len());
-            self.object_spans.push(ObjectSpan {
-                index: self.objects.len() as u32,
-                count: 1,
-            });
-            self.object_parents.push(Vec::with_capacity(4));
-            self.object_matrices.push(Mat4::identity());
+        match self.free_slots.pop_first() {
+            Some(slot) => {
+                let slot = slot as usize;
+                self.object_spans[slot] = span;
+                self.object_parents[slot].clear();
+                self.object_matrices[slot] = Mat4::identity();
+                self.object_attachments[slot] = None;
+                self.slot_objects[slot] = Some(object);
+                self.object_slots.set(object, slot);
+            }
+            None => {
+                self.object_slots.set(object, self.object_spans.len());
+                self.object_spans.push(span);
+                self.object_parents.push(Vec::with_capacity(4));
+                self.object_matrices.push(Mat4::identity());
+                self.object_attachments.push(None);
+                self.slot_objects.push(Some(object));
+            }
         }
 
         self.objects.push(object);
@@ -279,22 +391,28 @@ impl ObjectHierarchy {
 
     /// Removes the given object and its children. Returns the removed objects in the order of hierarchy.
     pub fn remove(&mut self, object: ObjectId) -> Vec<Entity> {
-        let object_usize = object.get() as usize;
-        let span = self.object_spans[object_usize];
+        let object_slot = self.object_slots.slot(object);
+        let span = self.object_spans[object_slot];
         let to_be_removed = self.object_entities[span.to_range()].to_vec();
 
         // Remove the object and its children from its parents.
-        for &parent in &self.object_parents[object_usize] {
-            let parent_usize = parent.get() as usize;
-            self.object_spans[parent_usize].count -= span.count;
+        for &parent in &self.object_parents[object_slot] {
+            self.object_spans[self.object_slots.slot(parent)].count -= span.count;
         }
 
         let span_index = span.index as usize;
         let span_count = span.count as usize;
 
+        // Release the slots of the object and its children. They are reused or compacted later.
+        for &object in &self.objects[span.to_range()] {
+            let slot = self.object_slots.remove(object);
+            self.slot_objects[slot] = None;
+            self.free_slots.insert(slot as u32);
+        }
+
         // Remove the object and its children from the ordered objects.
         for &object in &self.objects[span_index + span_count..] {
-            self.object_spans[object.get() as usize].index -= span.count;
+            self.object_spans[self.object_slots.slot(object)].index -= span.count;
         }
 
         if span_index + span_count < self.objects.len() {
@@ -348,51 +466,69 @@ impl ObjectHierarchy {
     }
 
     /// Sets the parent of the given object and re-order all objects.
-    pub fn set_parent(&mut self, object: ObjectId, parent: Option<ObjectId>) {
+    /// Fails without changing anything if the parent is the object itself or one of its descendants.
+    pub fn set_parent(
+        &mut self,
+        object: ObjectId,
+        parent: Option<ObjectId>,
+    ) -> Result<(), HierarchyError> {
+        if !self.contains(object) || parent.map_or(false, |parent| !self.contains(parent)) {
+            return Err(StaleHandle::new("object").into());
+        }
+
+        if let Some(parent) = parent {
+            if parent == object {
+                return Err(HierarchyError::SelfParent(object));
+            }
+
+            if self.object_and_children(object).contains(&parent) {
+                return Err(HierarchyError::Cycle { object, parent });
+            }
+        }
+
         self.set_dirty(object);
 
-        let object_usize = object.get() as usize;
-        let span = self.object_spans[object_usize];
+        let object_slot = self.object_slots.slot(object);
+        let span = self.object_spans[object_slot];
 
         // Remove the object and its children from its parents.
-        for &parent in &self.object_parents[object_usize] {
-            let parent_usize = parent.get() as usize;
-            self.object_spans[parent_usize].count -= span.count;
+        for &parent in &self.object_parents[object_slot] {
+            self.object_spans[self.object_slots.slot(parent)].count -= span.count;
         }
 
-        let parent_count = self.object_parents[object_usize].len();
+        let parent_count = self.object_parents[object_slot].len();
 
         // Remove the parents of the object and its children.
         for &object in &self.objects[span.to_range()] {
-            let parents = &mut self.object_parents[object.get() as usize];
+            let parents = &mut self.object_parents[self.object_slots.slot(object)];
             parents.truncate(parents.len() - parent_count);
         }
 
         let destination_index = if let Some(parent) = parent {
-            let parent_usize = parent.get() as usize;
-            let (left, right) = self.object_parents.split_at_mut(parent_usize);
+            let parent_slot = self.object_slots.slot(parent);
+            let (left, right) = self.object_parents.split_at_mut(parent_slot);
             let (high_parents, right) = right.split_first_mut().unwrap();
 
             // Assign a new parent and its parents.
             for &object in &self.objects[span.to_range()] {
-                let parents = if object < parent {
-                    &mut left[object.get() as usize]
+                let object_slot = self.object_slots.slot(object);
+                let parents = if object_slot < parent_slot {
+                    &mut left[object_slot]
                 } else {
-                    &mut right[object.get() as usize - parent_usize - 1]
+                    &mut right[object_slot - parent_slot - 1]
                 };
                 parents.reserve(high_parents.len() + 1);
                 parents.push(parent);
                 parents.extend_from_slice(high_parents);
             }
 
-            let prev_parent_span = self.object_spans[parent_usize];
+            let prev_parent_span = self.object_spans[parent_slot];
 
             // Add the object and its children to its new parent.
-            self.object_spans[parent_usize].count += span.count;
+            self.object_spans[parent_slot].count += span.count;
 
             for &high_parent in high_parents.iter() {
-                let high_parent_usize = high_parent.get() as usize;
-                self.object_spans[high_parent_usize].count += span.count;
+                self.object_spans[self.object_slots.slot(high_parent)].count += span.count;
             }
 
             (prev_parent_span.index + prev_parent_span.count) as usize
@@ -408,13 +544,19 @@ impl ObjectHierarchy {
 
         // Update active flags.
         self.set_active(object, self.is_active_self(object));
+
+        Ok(())
     }
 
-    /// Updates the object matrices.
+    /// Updates the object matrices. Matrices that are not finite, e.g. from NaN positions, are replaced
+    /// by the identity so that they don't spread to the children and the GPU.
+    /// Returns the number of replaced matrices.
     pub fn update_object_matrices<'a>(
         &mut self,
         transforms: impl Fn(Entity) -> Option<&'a Transform>,
-    ) {
+    ) -> usize {
+        let mut non_finite_count = 0;
+
         for (&object, &entity) in self.objects.iter().zip(self.object_entities.iter()) {
             if !self.is_dirty(object) {
                 continue;
@@ -426,20 +568,113 @@ impl ObjectHierarchy {
                 Mat4::identity()
             };
 
+            if let Some(attachment) = self.attachment(object) {
+                matrix *= attachment;
+            }
+
             if let Some(parent) = self.parent(object) {
                 matrix *= self.matrix(parent);
             }
 
-            self.object_matrices[object.get() as usize] = matrix;
+            if !matrix.is_finite() {
+                matrix = Mat4::identity();
+                non_finite_count += 1;
+            }
+
+            self.object_matrices[self.object_slots.slot(object)] = matrix;
         }
 
         self.reset_dirties();
+        non_finite_count
+    }
+
+    /// Computes the matrix of the object from the current transforms and attachments of it and its parents, as
+    /// [`update_object_matrices`](Self::update_object_matrices) would, without waiting for the next update.
+    pub fn compute_matrix<'a>(
+        &self,
+        object: ObjectId,
+        transforms: impl Fn(Entity) -> Option<&'a Transform>,
+    ) -> Mat4 {
+        let mut matrix = Mat4::identity();
+        let mut current = Some(object);
+
+        while let Some(object) = current {
+            if let Some(transform) = transforms(self.entity(object)) {
+                matrix *= transform.matrix();
+            }
+
+            if let Some(attachment) = self.attachment(object) {
+                matrix *= attachment;
+            }
+
+            current = self.parent(object);
+        }
+
+        matrix
+    }
+
+    /// Returns the ratio of unused slots in the per-object data, in range [0, 1].
+    pub fn fragmentation(&self) -> f32 {
+        if self.object_spans.is_empty() {
+            0.0
+        } else {
+            self.free_slots.len() as f32 / self.object_spans.len() as f32
+        }
+    }
+
+    /// Moves up to `max_steps` objects from the tail of the per-object data into unused slots.
+    /// Object ids are not changed. Returns `true` if no unused slot remains.
+    pub fn compact(&mut self, max_steps: usize) -> bool {
+        for _ in 0..max_steps {
+            if self.free_slots.is_empty() {
+                break;
+            }
+
+            let last = self.object_spans.len() - 1;
+
+            if self.free_slots.last() == Some(&(last as u32)) {
+                self.free_slots.pop_last();
+            } else {
+                let slot = self.free_slots.pop_first().unwrap() as usize;
+                let object = self.slot_objects[last].unwrap();
+
+                self.object_spans.swap(slot, last);
+                self.object_parents.swap(slot, last);
+                self.object_matrices.swap(slot, last);
+                self.object_attachments.swap(slot, last);
+                self.slot_objects.swap(slot, last);
+                self.object_slots.set(object, slot);
+            }
+
+            self.object_spans.pop();
+            self.object_parents.pop();
+            self.object_matrices.pop();
+            self.object_attachments.pop();
+            self.slot_objects.pop();
+        }
+
+        self.free_slots.is_empty()
+    }
+
+    /// Releases the memory that is no longer used after removals and compaction.
+    pub fn shrink_to_fit(&mut self) {
+        self.objects.shrink_to_fit();
+        self.object_entities.shrink_to_fit();
+        self.object_dirties.shrink_to_fit();
+        self.object_current_frame_dirties.shrink_to_fit();
+        self.object_actives.shrink_to_fit();
+        self.object_active_selfs.shrink_to_fit();
+        self.object_slots.shrink_to_fit();
+        self.slot_objects.shrink_to_fit();
+        self.object_spans.shrink_to_fit();
+        self.object_parents.shrink_to_fit();
+        self.object_matrices.shrink_to_fit();
+        self.object_attachments.shrink_to_fit();
     }
 
     /// Moves the given object and its children to the destination index.
     fn move_objects(&mut self, object: ObjectId, destination_index: usize) {
-        let object = object.get() as usize;
-        let span = self.object_spans[object];
+        let span = self.object_spans[self.object_slots.slot(object)];
         let span_index = span.index as usize;
         let span_count = span.count as usize;
         let span_index_end = span_index + span_count;
@@ -452,11 +687,11 @@ impl ObjectHierarchy {
             let offset = (span_index - destination_index) as u32;
 
             for &object in &self.objects[span_index..span_index_end] {
-                self.object_spans[object.get() as usize].index -= offset;
+                self.object_spans[self.object_slots.slot(object)].index -= offset;
             }
 
             for &object in &self.objects[destination_index..span_index] {
-                self.object_spans[object.get() as usize].index += span.count;
+                self.object_spans[self.object_slots.slot(object)].index += span.count;
             }
 
             self.swap_range(destination_index, span_index, span_index_end);
@@ -464,11 +699,11 @@ impl ObjectHierarchy {
             let offset = (destination_index - span_index - span_count) as u32;
 
             for &object in &self.objects[span_index..span_index_end] {
-                self.object_spans[object.get() as usize].index += offset;
+                self.object_spans[self.object_slots.slot(object)].index += offset;
             }
 
             for &object in &self.objects[span_index_end..destination_index] {
-                self.object_spans[object.get() as usize].index -= span.count;
+                self.object_spans[self.object_slots.slot(object)].index -= span.count;
             }
 
             self.swap_range(span_index, span_index_end, destination_index);
@@ -539,9 +774,13 @@ impl Default for ObjectHierarchy {
             object_actives: BitVec::with_capacity(1024),
             object_active_selfs: BitVec::with_capacity(1024),
 
+            object_slots: ObjectSlotTable::default(),
+            free_slots: BTreeSet::new(),
+            slot_objects: Vec::with_capacity(1024),
             object_spans: Vec::with_capacity(1024),
             object_parents: Vec::with_capacity(1024),
             object_matrices: Vec::with_capacity(1024),
+            object_attachments: Vec::with_capacity(1024),
         }
     }
 }
@@ -549,7 +788,7 @@ impl Default for ObjectHierarchy {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::math::Vec3;
+    use crate::{math::Vec3, object::ObjectIdAllocator};
     use std::collections::HashMap;
 
     fn equals_float(a: f32, b: f32) -> bool {
@@ -591,8 +830,12 @@ mod test {
             ]
         );
 
-        hierarchy.set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(0)));
-        hierarchy.set_parent(ObjectId::from_u32(3), Some(ObjectId::from_u32(0)));
+        hierarchy
+            .set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(0)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(3), Some(ObjectId::from_u32(0)))
+            .unwrap();
 
         assert_eq!(
             hierarchy.objects(),
@@ -604,8 +847,12 @@ mod test {
             ]
         );
 
-        hierarchy.set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(1)));
-        hierarchy.set_parent(ObjectId::from_u32(3), Some(ObjectId::from_u32(1)));
+        hierarchy
+            .set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(1)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(3), Some(ObjectId::from_u32(1)))
+            .unwrap();
 
         assert_eq!(
             hierarchy.objects(),
@@ -617,7 +864,9 @@ mod test {
             ]
         );
 
-        hierarchy.set_parent(ObjectId::from_u32(0), Some(ObjectId::from_u32(1)));
+        hierarchy
+            .set_parent(ObjectId::from_u32(0), Some(ObjectId::from_u32(1)))
+            .unwrap();
 
         assert_eq!(
             hierarchy.objects(),
@@ -639,8 +888,12 @@ mod test {
         hierarchy.matrix_mut(ObjectId::from_u32(2)).elements[0] = 300.0;
         hierarchy.matrix_mut(ObjectId::from_u32(3)).elements[0] = 400.0;
 
-        hierarchy.set_parent(ObjectId::from_u32(0), Some(ObjectId::from_u32(3)));
-        hierarchy.set_parent(ObjectId::from_u32(1), Some(ObjectId::from_u32(2)));
+        hierarchy
+            .set_parent(ObjectId::from_u32(0), Some(ObjectId::from_u32(3)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(1), Some(ObjectId::from_u32(2)))
+            .unwrap();
 
         assert_eq!(
             hierarchy.objects(),
@@ -698,7 +951,9 @@ mod test {
             ]
         );
 
-        hierarchy.set_parent(ObjectId::from_u32(0), Some(ObjectId::from_u32(5)));
+        hierarchy
+            .set_parent(ObjectId::from_u32(0), Some(ObjectId::from_u32(5)))
+            .unwrap();
         let to_be_removed = vec![
             hierarchy.entity(ObjectId::from_u32(5)),
             hierarchy.entity(ObjectId::from_u32(0)),
@@ -716,14 +971,30 @@ mod test {
     fn check_hierarchy_object_active_flag() {
         let mut hierarchy = create_hierarchy(10);
 
-        hierarchy.set_parent(ObjectId::from_u32(1), Some(ObjectId::from_u32(0)));
-        hierarchy.set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(0)));
-        hierarchy.set_parent(ObjectId::from_u32(3), Some(ObjectId::from_u32(0)));
-        hierarchy.set_parent(ObjectId::from_u32(4), Some(ObjectId::from_u32(0)));
-        hierarchy.set_parent(ObjectId::from_u32(5), Some(ObjectId::from_u32(1)));
-        hierarchy.set_parent(ObjectId::from_u32(6), Some(ObjectId::from_u32(2)));
-        hierarchy.set_parent(ObjectId::from_u32(7), Some(ObjectId::from_u32(3)));
-        hierarchy.set_parent(ObjectId::from_u32(8), Some(ObjectId::from_u32(4)));
+        hierarchy
+            .set_parent(ObjectId::from_u32(1), Some(ObjectId::from_u32(0)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(0)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(3), Some(ObjectId::from_u32(0)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(4), Some(ObjectId::from_u32(0)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(5), Some(ObjectId::from_u32(1)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(6), Some(ObjectId::from_u32(2)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(7), Some(ObjectId::from_u32(3)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(8), Some(ObjectId::from_u32(4)))
+            .unwrap();
 
         hierarchy.set_active(ObjectId::from_u32(1), false);
         assert_eq!(hierarchy.is_active(ObjectId::from_u32(0)), true);
@@ -781,22 +1052,28 @@ mod test {
         hierarchy.set_active(ObjectId::from_u32(0), true);
         hierarchy.set_active(ObjectId::from_u32(1), false);
 
-        hierarchy.set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(0)));
-        hierarchy.set_parent(ObjectId::from_u32(3), Some(ObjectId::from_u32(2)));
+        hierarchy
+            .set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(0)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(3), Some(ObjectId::from_u32(2)))
+            .unwrap();
 
         assert_eq!(hierarchy.is_active(ObjectId::from_u32(0)), true);
         assert_eq!(hierarchy.is_active(ObjectId::from_u32(1)), false);
         assert_eq!(hierarchy.is_active(ObjectId::from_u32(2)), true);
         assert_eq!(hierarchy.is_active(ObjectId::from_u32(3)), true);
 
-        hierarchy.set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(1)));
+        hierarchy
+            .set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(1)))
+            .unwrap();
 
         assert_eq!(hierarchy.is_active(ObjectId::from_u32(0)), true);
         assert_eq!(hierarchy.is_active(ObjectId::from_u32(1)), false);
         assert_eq!(hierarchy.is_active(ObjectId::from_u32(2)), false);
         assert_eq!(hierarchy.is_active(ObjectId::from_u32(3)), false);
 
-        hierarchy.set_parent(ObjectId::from_u32(2), None);
+        hierarchy.set_parent(ObjectId::from_u32(2), None).unwrap();
 
         assert_eq!(hierarchy.is_active(ObjectId::from_u32(0)), true);
         assert_eq!(hierarchy.is_active(ObjectId::from_u32(1)), false);
@@ -808,9 +1085,15 @@ mod test {
     fn check_hierarchy_object_matrix_update_uniform_scales() {
         let mut hierarchy = create_hierarchy(4);
 
-        hierarchy.set_parent(ObjectId::from_u32(1), Some(ObjectId::from_u32(0)));
-        hierarchy.set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(1)));
-        hierarchy.set_parent(ObjectId::from_u32(3), Some(ObjectId::from_u32(2)));
+        hierarchy
+            .set_parent(ObjectId::from_u32(1), Some(ObjectId::from_u32(0)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(1)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(3), Some(ObjectId::from_u32(2)))
+            .unwrap();
 
         let mut transforms = HashMap::new();
         transforms.insert(hierarchy.entity(ObjectId::from_u32(0)), {
@@ -865,4 +1148,218 @@ mod test {
             true
         );
     }
+
+    #[test]
+    fn check_hierarchy_object_matrix_update_attachment() {
+        let mut hierarchy = create_hierarchy(3);
+
+        hierarchy
+            .set_parent(ObjectId::from_u32(1), Some(ObjectId::from_u32(0)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(1)))
+            .unwrap();
+
+        let mut transforms = HashMap::new();
+        for (id, position) in [
+            (0, Vec3::new(10.0, 0.0, 0.0)),
+            (1, Vec3::new(0.0, 0.0, 1.0)),
+            (2, Vec3::new(0.0, 0.0, 2.0)),
+        ] {
+            transforms.insert(hierarchy.entity(ObjectId::from_u32(id)), {
+                let mut transform = Transform::new();
+                transform.position = position;
+                transform
+            });
+        }
+
+        // Object 1 is attached to a joint that moves between updates.
+        for joint in [Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 2.0, 0.0)] {
+            hierarchy.set_attachment(ObjectId::from_u32(1), Some(Mat4::translation(joint)));
+            hierarchy.update_object_matrices(|entity| transforms.get(&entity));
+
+            assert_eq!(
+                equals_mat4(
+                    hierarchy.matrix(ObjectId::from_u32(1)),
+                    &Mat4::translation(Vec3::new(10.0, joint.y, 1.0))
+                ),
+                true
+            );
+            assert_eq!(
+                equals_mat4(
+                    hierarchy.matrix(ObjectId::from_u32(2)),
+                    &Mat4::translation(Vec3::new(10.0, joint.y, 3.0))
+                ),
+                true
+            );
+        }
+
+        // Detaching restores the plain parenting.
+        hierarchy.set_attachment(ObjectId::from_u32(1), None);
+        hierarchy.update_object_matrices(|entity| transforms.get(&entity));
+
+        assert_eq!(
+            equals_mat4(
+                hierarchy.matrix(ObjectId::from_u32(2)),
+                &Mat4::translation(Vec3::new(10.0, 0.0, 3.0))
+            ),
+            true
+        );
+    }
+
+    #[test]
+    fn check_hierarchy_compaction() {
+        let mut hierarchy = ObjectHierarchy::new();
+        let mut world = World::new();
+
+        for id in 0..1000 {
+            hierarchy.add(ObjectId::from_u32(id), world.create_entity().build());
+        }
+
+        for id in (1..1000).step_by(2) {
+            hierarchy
+                .set_parent(ObjectId::from_u32(id), Some(ObjectId::from_u32(id - 1)))
+                .unwrap();
+        }
+
+        // Keep the last 10 pairs alive, so that they must be moved into the freed slots.
+        for id in (0..980).step_by(2) {
+            hierarchy.remove(ObjectId::from_u32(id));
+        }
+
+        assert!(0.9 < hierarchy.fragmentation());
+        assert_eq!(hierarchy.compact(usize::MAX), true);
+        assert_eq!(hierarchy.fragmentation(), 0.0);
+        assert_eq!(hierarchy.object_spans.len(), 20);
+
+        for id in (980..1000).step_by(2) {
+            let parent = ObjectId::from_u32(id);
+            let child = ObjectId::from_u32(id + 1);
+
+            assert_eq!(hierarchy.children(parent), &[child]);
+            assert_eq!(hierarchy.parent(child), Some(parent));
+        }
+
+        // Reused ids take the lowest slots again.
+        hierarchy.add(ObjectId::from_u32(0), world.create_entity().build());
+        hierarchy
+            .set_parent(ObjectId::from_u32(0), Some(ObjectId::from_u32(998)))
+            .unwrap();
+
+        assert_eq!(
+            hierarchy.children(ObjectId::from_u32(998)),
+            &[ObjectId::from_u32(999), ObjectId::from_u32(0)]
+        );
+    }
+
+    #[test]
+    fn check_hierarchy_rejects_self_parent_and_cycles() {
+        let mut hierarchy = create_hierarchy(3);
+
+        hierarchy
+            .set_parent(ObjectId::from_u32(1), Some(ObjectId::from_u32(0)))
+            .unwrap();
+        hierarchy
+            .set_parent(ObjectId::from_u32(2), Some(ObjectId::from_u32(1)))
+            .unwrap();
+
+        assert_eq!(
+            hierarchy.set_parent(ObjectId::from_u32(1), Some(ObjectId::from_u32(1))),
+            Err(HierarchyError::SelfParent(ObjectId::from_u32(1)))
+        );
+        assert_eq!(
+            hierarchy.set_parent(ObjectId::from_u32(0), Some(ObjectId::from_u32(2))),
+            Err(HierarchyError::Cycle {
+                object: ObjectId::from_u32(0),
+                parent: ObjectId::from_u32(2),
+            })
+        );
+
+        // The rejected changes left the hierarchy intact, so removal and updates still terminate.
+        assert_eq!(hierarchy.parent(ObjectId::from_u32(0)), None);
+        assert_eq!(
+            hierarchy.parent(ObjectId::from_u32(2)),
+            Some(ObjectId::from_u32(1))
+        );
+        assert_eq!(hierarchy.update_object_matrices(|_| None), 0);
+        assert_eq!(hierarchy.remove(ObjectId::from_u32(0)).len(), 3);
+        assert!(hierarchy.objects().is_empty());
+    }
+
+    #[test]
+    fn check_hierarchy_rejects_stale_ids() {
+        let mut allocator = ObjectIdAllocator::new();
+        let mut hierarchy = ObjectHierarchy::new();
+        let mut world = World::new();
+
+        let removed = allocator.alloc();
+        let parent = allocator.alloc();
+        hierarchy.add(removed, world.create_entity().build());
+        hierarchy.add(parent, world.create_entity().build());
+        hierarchy.remove(removed);
+        assert!(allocator.dealloc(removed));
+
+        // The reused index doesn't make the removed id valid again.
+        let reused = allocator.alloc();
+        assert_eq!(reused.get(), removed.get());
+        hierarchy.add(reused, world.create_entity().build());
+
+        assert!(!hierarchy.contains(removed));
+        assert!(hierarchy.contains(reused));
+        assert_eq!(
+            hierarchy.set_parent(removed, Some(parent)),
+            Err(HierarchyError::Stale(StaleHandle::new("object")))
+        );
+        assert_eq!(
+            hierarchy.set_parent(parent, Some(removed)),
+            Err(HierarchyError::Stale(StaleHandle::new("object")))
+        );
+        hierarchy.set_parent(reused, Some(parent)).unwrap();
+        assert_eq!(hierarchy.parent(reused), Some(parent));
+    }
+
+    #[test]
+    fn check_hierarchy_non_finite_matrices_are_reset() {
+        let mut hierarchy = create_hierarchy(3);
+
+        hierarchy
+            .set_parent(ObjectId::from_u32(1), Some(ObjectId::from_u32(0)))
+            .unwrap();
+
+        let mut transforms = HashMap::new();
+        transforms.insert(hierarchy.entity(ObjectId::from_u32(0)), {
+            let mut transform = Transform::new();
+            transform.position = Vec3::new(f32::NAN, 0.0, 0.0);
+            transform
+        });
+        transforms.insert(hierarchy.entity(ObjectId::from_u32(1)), {
+            let mut transform = Transform::new();
+            transform.position = Vec3::new(1.0, 2.0, 3.0);
+            transform
+        });
+        transforms.insert(hierarchy.entity(ObjectId::from_u32(2)), {
+            // Zero scale is degenerate but finite, so it is kept.
+            let mut transform = Transform::new();
+            transform.scale = Vec3::ZERO;
+            transform
+        });
+
+        assert_eq!(
+            hierarchy.update_object_matrices(|entity| transforms.get(&entity)),
+            1
+        );
+        assert!(equals_mat4(
+            hierarchy.matrix(ObjectId::from_u32(0)),
+            &Mat4::identity()
+        ));
+        // The child is not poisoned by its parent.
+        assert!(equals_mat4(
+            hierarchy.matrix(ObjectId::from_u32(1)),
+            &Mat4::translation(Vec3::new(1.0, 2.0, 3.0))
+        ));
+        assert!(equals_mat4(
+            hierarchy.matrix(ObjectId::from_u32(2)),
+            &Mat4::scale(Vec3::ZERO)
+        ));
+    }
 }
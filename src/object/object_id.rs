@@ -1,24 +1,45 @@
+use crate::handles::GenIndex;
 use std::num::NonZeroU32;
 
+/// Identifies an object. Indices are reused after removal; the generation tells the objects sharing one apart.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct ObjectId(NonZeroU32);
+pub struct ObjectId {
+    id: NonZeroU32,
+    generation: u32,
+}
 
 impl ObjectId {
     pub fn new(id: NonZeroU32) -> Self {
-        Self(id)
+        Self { id, generation: 0 }
     }
 
     pub fn from_u32(id: u32) -> Self {
-        Self(NonZeroU32::new(id + 1).unwrap())
+        Self::new(NonZeroU32::new(id + 1).unwrap())
+    }
+
+    pub fn from_gen_index(index: GenIndex) -> Self {
+        Self {
+            id: NonZeroU32::new(index.index() + 1).unwrap(),
+            generation: index.generation(),
+        }
     }
 
+    /// Returns the index of the object, which is shared with the removed objects that had it before.
     pub fn get(&self) -> u32 {
-        self.0.get() - 1
+        self.id.get() - 1
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn gen_index(&self) -> GenIndex {
+        GenIndex::new(self.get(), self.generation)
     }
 }
 
 impl From<ObjectId> for u32 {
     fn from(id: ObjectId) -> Self {
-        id.0.get() - 1
+        id.get()
     }
 }
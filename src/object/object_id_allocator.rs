@@ -1,10 +1,9 @@
 use super::ObjectId;
-use std::num::NonZeroU32;
+use crate::handles::GenIndexAllocator;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct ObjectIdAllocator {
-    next_id: NonZeroU32,
-    freed_ids: Vec<ObjectId>,
+    allocator: GenIndexAllocator,
 }
 
 impl ObjectIdAllocator {
@@ -13,29 +12,15 @@ impl ObjectIdAllocator {
     }
 
     pub fn alloc(&mut self) -> ObjectId {
-        match self.freed_ids.pop() {
-            Some(id) => id,
-            None => {
-                let id = ObjectId::new(self.next_id);
-                self.next_id = self
-                    .next_id
-                    .checked_add(1)
-                    .unwrap_or_else(|| NonZeroU32::new(1).unwrap());
-                id
-            }
-        }
+        ObjectId::from_gen_index(self.allocator.alloc())
     }
 
-    pub fn dealloc(&mut self, id: ObjectId) {
-        self.freed_ids.push(id);
+    /// Releases the id. Returns `false` if it was released already.
+    pub fn dealloc(&mut self, id: ObjectId) -> bool {
+        self.allocator.free(id.gen_index())
     }
-}
 
-impl Default for ObjectIdAllocator {
-    fn default() -> Self {
-        Self {
-            next_id: NonZeroU32::new(1).unwrap(),
-            freed_ids: Vec::with_capacity(1024),
-        }
+    pub fn is_alive(&self, id: ObjectId) -> bool {
+        self.allocator.is_alive(id.gen_index())
     }
 }
@@ -1,21 +1,91 @@
 use super::{
-    Object, ObjectHandle, ObjectHierarchy, ObjectId, ObjectIdAllocator, ObjectNameRegistry,
+    pick_point, pick_rect, transform_aabb, Object, ObjectHandle, ObjectHierarchy, ObjectId,
+    ObjectIdAllocator, ObjectNameRegistry, PickCandidate, PickRectMode, PickShape, PickView,
+};
+use crate::{
+    gfx::{Camera, Layers, MeshRenderer, UIElementRenderer},
+    handles::StaleHandle,
+    math::{Vec2, Vec3, Vec4},
+    transform::Transform,
+    ui::UISize,
+    use_context,
 };
-use crate::{transform::Transform, use_context};
 use specs::prelude::*;
+use std::time::{Duration, Instant};
 
 pub struct ObjectManager {
     object_hierarchy: ObjectHierarchy,
     object_name_registry: ObjectNameRegistry,
     object_id_allocator: ObjectIdAllocator,
+    compaction_threshold: f32,
+    compaction_budget: Duration,
+    is_compacting: bool,
 }
 
 impl ObjectManager {
+    /// Number of slots compacted between budget checks.
+    const COMPACTION_STEPS: usize = 64;
+
     pub fn new() -> Self {
         Self {
             object_hierarchy: ObjectHierarchy::new(),
             object_name_registry: ObjectNameRegistry::new(),
             object_id_allocator: ObjectIdAllocator::new(),
+            compaction_threshold: 0.25,
+            compaction_budget: Duration::from_micros(200),
+            is_compacting: false,
+        }
+    }
+
+    /// Returns the ratio of unused per-object storage left behind by removed objects.
+    pub fn fragmentation(&self) -> f32 {
+        self.object_hierarchy.fragmentation()
+    }
+
+    pub fn compaction_threshold(&self) -> f32 {
+        self.compaction_threshold
+    }
+
+    /// Sets the fragmentation above which the storage is compacted incrementally.
+    pub fn set_compaction_threshold(&mut self, threshold: f32) {
+        self.compaction_threshold = threshold;
+    }
+
+    pub fn compaction_budget(&self) -> Duration {
+        self.compaction_budget
+    }
+
+    /// Sets the time a single frame may spend on incremental compaction.
+    pub fn set_compaction_budget(&mut self, budget: Duration) {
+        self.compaction_budget = budget;
+    }
+
+    /// Compacts the storage fully and releases unused memory. Intended for loading screens.
+    pub fn compact_now(&mut self) {
+        self.object_hierarchy.compact(usize::MAX);
+        self.object_hierarchy.shrink_to_fit();
+        self.object_name_registry.shrink_to_fit();
+        self.is_compacting = false;
+    }
+
+    /// Compacts a part of the storage within the budget, once the fragmentation exceeds the threshold.
+    /// Called by the engine every frame.
+    pub fn compact_incremental(&mut self) {
+        if !self.is_compacting {
+            if self.fragmentation() <= self.compaction_threshold {
+                return;
+            }
+
+            self.is_compacting = true;
+        }
+
+        let now = Instant::now();
+
+        while now.elapsed() < self.compaction_budget {
+            if self.object_hierarchy.compact(Self::COMPACTION_STEPS) {
+                self.is_compacting = false;
+                break;
+            }
         }
     }
 
@@ -35,6 +105,21 @@ impl ObjectManager {
         &mut self.object_hierarchy
     }
 
+    /// Returns `false` once the object has been removed, even if its index has been reused since.
+    pub fn is_alive(&self, object_id: ObjectId) -> bool {
+        self.object_id_allocator.is_alive(object_id)
+    }
+
+    /// Returns a handle of the object, or `None` if it has been removed.
+    pub fn get(&self, object_id: ObjectId) -> Option<ObjectHandle> {
+        if !self.is_alive(object_id) {
+            return None;
+        }
+
+        Some(self.object_handle(object_id))
+    }
+
+    /// Returns a handle of the object. The object must be alive; see [`ObjectManager::get`] otherwise.
     pub fn object_handle(&self, object_id: ObjectId) -> ObjectHandle {
         ObjectHandle::new(
             use_context().clone(),
@@ -57,12 +142,138 @@ impl ObjectManager {
             .unwrap_or_default()
     }
 
+    /// Picks the objects inside the rectangle given in window coordinates, as seen through the camera.
+    /// Meshes are picked by their bounding boxes and UI elements by their quads. Only objects rendered by the camera
+    /// and matching `layer_mask` are considered. The result is ordered nearest first and holds at most `max_results` objects.
+    pub fn pick_rect(
+        &self,
+        min: Vec2,
+        max: Vec2,
+        camera: &ObjectHandle,
+        mode: PickRectMode,
+        layer_mask: u32,
+        max_results: usize,
+    ) -> Vec<ObjectHandle> {
+        let (candidates, view) = match self.pick_candidates(camera, layer_mask) {
+            Some(candidates) => candidates,
+            None => return Vec::new(),
+        };
+
+        pick_rect(candidates, &view, min, max, mode, max_results)
+            .into_iter()
+            .map(|object_id| self.object_handle(object_id))
+            .collect()
+    }
+
+    /// Picks the frontmost object under the point given in window coordinates, as seen through the camera.
+    pub fn pick_point(
+        &self,
+        point: Vec2,
+        camera: &ObjectHandle,
+        layer_mask: u32,
+    ) -> Option<ObjectHandle> {
+        let (candidates, view) = self.pick_candidates(camera, layer_mask)?;
+        pick_point(candidates, &view, point).map(|object_id| self.object_handle(object_id))
+    }
+
+    fn pick_candidates(
+        &self,
+        camera: &ObjectHandle,
+        layer_mask: u32,
+    ) -> Option<(Vec<PickCandidate>, PickView)> {
+        let ctx = use_context();
+        let world = ctx.world();
+        let screen_mgr = ctx.screen_mgr();
+        let cameras = world.read_storage::<Camera>();
+        let camera_component = cameras.get(camera.entity)?;
+        let camera_matrix = self.object_hierarchy.matrix(camera.object_id);
+        let view = PickView {
+            view_projection: camera_component.view_projection_matrix(&screen_mgr, camera_matrix),
+            camera_position: Vec3::from_vec4(camera_matrix.row(3)),
+            screen_size: Vec2::new(screen_mgr.width() as f32, screen_mgr.height() as f32),
+        };
+        let mask = layer_mask & camera_component.mask;
+
+        let objects = world.read_storage::<Object>();
+        let layers = world.read_storage::<Layers>();
+        let mesh_renderers = world.read_storage::<MeshRenderer>();
+        let ui_element_renderers = world.read_storage::<UIElementRenderer>();
+        let ui_sizes = world.read_storage::<UISize>();
+        let mut candidates = Vec::new();
+
+        for (object, mesh_renderer, object_layers) in
+            (&objects, &mesh_renderers, layers.maybe()).join()
+        {
+            // Instanced renderers draw elsewhere than at the object, so their bounds are unknown here.
+            if mesh_renderer.mask() & mask == 0
+                || !Layers::of(object_layers).intersects(camera_component.culling_mask)
+                || mesh_renderer.instanced_group().is_some()
+                || !self.object_hierarchy.is_active(object.object_id())
+            {
+                continue;
+            }
+
+            let (min, max) = match mesh_renderer.bounds() {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+            let (min, max) =
+                transform_aabb(min, max, self.object_hierarchy.matrix(object.object_id()));
+
+            candidates.push(PickCandidate {
+                object: object.object_id(),
+                order: self.object_hierarchy.index(object.object_id()),
+                shape: PickShape::Box { min, max },
+            });
+        }
+
+        for (object, ui_element_renderer, ui_size, object_layers) in
+            (&objects, &ui_element_renderers, &ui_sizes, layers.maybe()).join()
+        {
+            if ui_element_renderer.mask() & mask == 0
+                || !Layers::of(object_layers).intersects(camera_component.culling_mask)
+                || !self.object_hierarchy.is_active(object.object_id())
+            {
+                continue;
+            }
+
+            let matrix = self.object_hierarchy.matrix(object.object_id());
+            let corner = |x: f32, y: f32| Vec2::from_vec4(Vec4::new(x, y, 0.0, 1.0) * matrix);
+
+            candidates.push(PickCandidate {
+                object: object.object_id(),
+                order: self.object_hierarchy.index(object.object_id()),
+                shape: PickShape::Quad([
+                    corner(0.0, 0.0),
+                    corner(ui_size.width, 0.0),
+                    corner(ui_size.width, ui_size.height),
+                    corner(0.0, ui_size.height),
+                ]),
+            });
+        }
+
+        Some((candidates, view))
+    }
+
     pub fn create_object_builder<'w>(
         &mut self,
         world: &'w mut World,
         name: impl Into<Option<String>>,
         transform: Option<Transform>,
     ) -> (ObjectHandle, EntityBuilder<'w>) {
+        let (object_id, builder) = self.create_entity_builder(world, name, transform);
+        let object_handle = ObjectHandle::new(use_context().clone(), builder.entity, object_id);
+
+        (object_handle, builder)
+    }
+
+    /// Same as [`create_object_builder`](Self::create_object_builder), without creating a handle.
+    pub(crate) fn create_entity_builder<'w>(
+        &mut self,
+        world: &'w mut World,
+        name: impl Into<Option<String>>,
+        transform: Option<Transform>,
+    ) -> (ObjectId, EntityBuilder<'w>) {
         let object_id = self.object_id_allocator.alloc();
         let builder = world.create_entity();
         let entity = builder.entity;
@@ -70,29 +281,143 @@ impl ObjectManager {
         self.object_hierarchy.add(object_id, entity);
         self.object_name_registry.set_name(object_id, name.into());
 
-        let object_handle = ObjectHandle::new(use_context().clone(), entity, object_id);
-
         (
-            object_handle,
+            object_id,
             builder
                 .with(Object::new(entity, object_id))
                 .with(transform.unwrap_or_default()),
         )
     }
 
-    pub fn remove_object(&mut self, handle: &ObjectHandle) {
-        use_context()
-            .world_mut()
-            .delete_entity(handle.entity)
+    /// Removes the object and its children. Fails if the object has been removed already.
+    pub fn remove_object(&mut self, handle: &ObjectHandle) -> Result<(), StaleHandle> {
+        if !self.is_alive(handle.object_id) {
+            return Err(StaleHandle::new("object"));
+        }
+
+        let removed = self.remove_from_world(&mut use_context().world_mut(), handle.object_id);
+        Self::forget_removed(removed);
+        Ok(())
+    }
+
+    /// Removes the UI and event state of objects removed by [`remove_from_world`](Self::remove_from_world).
+    pub(crate) fn forget_removed(removed: Vec<(ObjectId, Entity)>) {
+        for (object_id, entity) in removed {
+            let handle = ObjectHandle::new(use_context().clone(), entity, object_id);
+            use_context().ui_raycast_mgr_mut().remove_object(&handle);
+            use_context()
+                .object_event_mgr()
+                .remove_handler_for(object_id);
+            use_context().ui_event_mgr_mut().remove_object(&handle);
+        }
+    }
+
+    /// Removes the object and its children from the hierarchy and the world, and releases their ids.
+    /// Returns the removed objects along with their entities.
+    pub(crate) fn remove_from_world(
+        &mut self,
+        world: &mut World,
+        object_id: ObjectId,
+    ) -> Vec<(ObjectId, Entity)> {
+        // The children are removed along with the object, in the same order as their entities.
+        let object_ids = self
+            .object_hierarchy
+            .object_and_children(object_id)
+            .to_vec();
+        let entities = self.object_hierarchy.remove(object_id);
+        world.delete_entities(&entities).unwrap();
+
+        for &object_id in &object_ids {
+            self.object_id_allocator.dealloc(object_id);
+            self.object_name_registry.set_name(object_id, None);
+        }
+
+        object_ids.into_iter().zip(entities).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(
+        object_mgr: &mut ObjectManager,
+        world: &mut World,
+        parent: Option<ObjectId>,
+    ) -> ObjectId {
+        let (object_id, builder) = object_mgr.create_entity_builder(world, None, None);
+        builder.build();
+        object_mgr
+            .object_hierarchy
+            .set_parent(object_id, parent)
             .unwrap();
-        self.object_hierarchy.remove(handle.object_id);
-        self.object_id_allocator.dealloc(handle.object_id);
-        self.object_name_registry.set_name(handle.object_id, None);
-
-        use_context().ui_raycast_mgr_mut().remove_object(handle);
-        use_context()
-            .object_event_mgr()
-            .remove_handler_for(handle.object_id);
-        use_context().ui_event_mgr_mut().remove_object(handle);
+        object_id
+    }
+
+    #[test]
+    fn check_spawn_remove_soak() {
+        let mut world = World::new();
+        world.register::<Object>();
+        world.register::<Transform>();
+
+        let mut object_mgr = ObjectManager::new();
+        let mut alive = Vec::<ObjectId>::new();
+        let mut removed = Vec::<ObjectId>::new();
+        let mut seed = 0x2545_f491_u32;
+        let mut next = move |bound: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as usize % bound
+        };
+
+        for step in 0..4000 {
+            if alive.is_empty() || next(3) != 0 {
+                let parent = match next(4) {
+                    0 => None,
+                    _ if alive.is_empty() => None,
+                    _ => Some(alive[next(alive.len())]),
+                };
+                alive.push(spawn(&mut object_mgr, &mut world, parent));
+            } else {
+                let object_id = alive[next(alive.len())];
+                let gone = object_mgr.remove_from_world(&mut world, object_id);
+                world.maintain();
+
+                alive.retain(|id| gone.iter().all(|(gone_id, _)| gone_id != id));
+                removed.extend(gone.into_iter().map(|(object_id, _)| object_id));
+            }
+
+            if step % 64 == 0 {
+                object_mgr.object_hierarchy.compact(16);
+            }
+
+            let objects = world.read_storage::<Object>();
+            let entities = world.entities();
+            let mut count = 0;
+
+            for (entity, object) in (&entities, &objects).join() {
+                // No orphan is left behind in the world.
+                assert!(object_mgr.object_hierarchy.contains(object.object_id()));
+                assert_eq!(
+                    object_mgr.object_hierarchy.entity(object.object_id()),
+                    entity
+                );
+                count += 1;
+            }
+
+            assert_eq!(count, alive.len());
+            assert_eq!(object_mgr.object_hierarchy.objects().len(), alive.len());
+        }
+
+        for &object_id in &removed {
+            assert!(!object_mgr.is_alive(object_id));
+            assert!(!object_mgr.object_hierarchy.contains(object_id));
+            assert!(!object_mgr.object_hierarchy.is_active(object_id));
+            assert!(!object_mgr.object_hierarchy.is_dirty(object_id));
+        }
+
+        object_mgr.compact_now();
+        assert_eq!(object_mgr.fragmentation(), 0.0);
     }
 }
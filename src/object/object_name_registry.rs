@@ -34,10 +34,19 @@ impl ObjectNameRegistry {
         }
     }
 
+    pub fn shrink_to_fit(&mut self) {
+        self.object_names.shrink_to_fit();
+        self.object_ids.shrink_to_fit();
+    }
+
     fn decouple(&mut self, object: ObjectId) {
         if let Some(name) = self.object_names.remove(&object) {
             if let Some(object_ids) = self.object_ids.get_mut(&name) {
                 object_ids.remove(&object);
+
+                if object_ids.is_empty() {
+                    self.object_ids.remove(&name);
+                }
             }
         }
     }
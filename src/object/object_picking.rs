@@ -0,0 +1,541 @@
+use super::ObjectId;
+use crate::math::{Frustum, Mat4, Vec2, Vec3, Vec4};
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PickRectMode {
+    /// Picks objects lying entirely inside the rectangle.
+    Contain,
+    /// Picks objects overlapping the rectangle at all.
+    Intersect,
+}
+
+/// The shape an object is picked by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PickShape {
+    /// World space bounding box, seen through the camera.
+    Box { min: Vec3, max: Vec3 },
+    /// Corners of a quad drawn in screen space, e.g. a UI sprite.
+    /// The origin is at the center of the screen and y points up.
+    Quad([Vec2; 4]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickCandidate {
+    pub object: ObjectId,
+    /// Order of the object in the hierarchy, used to break distance ties.
+    pub order: u32,
+    pub shape: PickShape,
+}
+
+/// The camera and the screen a pick is made through.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickView {
+    pub view_projection: Mat4,
+    pub camera_position: Vec3,
+    /// Size of the screen in logical pixels.
+    pub screen_size: Vec2,
+}
+
+impl PickView {
+    /// Converts a point in window coordinates (origin at the top left, y down) to normalized device coordinates.
+    pub fn to_ndc(&self, point: Vec2) -> Vec2 {
+        Vec2::new(
+            point.x / self.screen_size.x * 2.0 - 1.0,
+            1.0 - point.y / self.screen_size.y * 2.0,
+        )
+    }
+
+    /// Converts a point in window coordinates to the screen space of quads.
+    pub fn to_screen_space(&self, point: Vec2) -> Vec2 {
+        Vec2::new(
+            point.x - self.screen_size.x * 0.5,
+            self.screen_size.y * 0.5 - point.y,
+        )
+    }
+
+    /// Returns the frustum of the part of the view inside the rectangle given in normalized device coordinates.
+    pub fn sub_frustum(&self, ndc_min: Vec2, ndc_max: Vec2) -> Frustum {
+        // Remaps the rectangle to the whole clip space, so that the regular plane extraction applies.
+        let half = (ndc_max - ndc_min) * 0.5;
+        let center = (ndc_min + ndc_max) * 0.5;
+        let remap = Mat4::new([
+            1.0 / half.x,
+            0.0,
+            0.0,
+            0.0, //
+            0.0,
+            1.0 / half.y,
+            0.0,
+            0.0, //
+            0.0,
+            0.0,
+            1.0,
+            0.0, //
+            -center.x / half.x,
+            -center.y / half.y,
+            0.0,
+            1.0, //
+        ]);
+        Frustum::from_view_projection(&(&self.view_projection * remap))
+    }
+
+    /// Returns the origin and the direction of the ray going through the point given in window coordinates.
+    pub fn ray(&self, point: Vec2) -> (Vec3, Vec3) {
+        let ndc = self.to_ndc(point);
+        let inverse = self.view_projection.inversed();
+        let near = Vec4::new(ndc.x, ndc.y, 0.0, 1.0) * &inverse;
+        let far = Vec4::new(ndc.x, ndc.y, 1.0, 1.0) * &inverse;
+        let near = Vec3::from_vec4(near / near.w);
+        let far = Vec3::from_vec4(far / far.w);
+        (near, (far - near).normalized())
+    }
+}
+
+/// Picks the candidates inside the rectangle given in window coordinates, nearest first, then in hierarchy order.
+/// At most `max_results` objects are returned. A rectangle without area picks the object under it, if any.
+pub fn pick_rect(
+    candidates: impl IntoIterator<Item = PickCandidate>,
+    view: &PickView,
+    min: Vec2,
+    max: Vec2,
+    mode: PickRectMode,
+    max_results: usize,
+) -> Vec<ObjectId> {
+    let (min, max) = (Vec2::min(min, max), Vec2::max(min, max));
+
+    if max.x - min.x <= f32::EPSILON || max.y - min.y <= f32::EPSILON {
+        return Vec::from_iter(
+            pick_point(candidates, view, min)
+                .into_iter()
+                .take(max_results),
+        );
+    }
+
+    if max_results == 0 {
+        return Vec::new();
+    }
+
+    // Window coordinates have y pointing down, so the corners swap vertically.
+    let ndc_min = view.to_ndc(Vec2::new(min.x, max.y));
+    let ndc_max = view.to_ndc(Vec2::new(max.x, min.y));
+    let screen_min = view.to_screen_space(Vec2::new(min.x, max.y));
+    let screen_max = view.to_screen_space(Vec2::new(max.x, min.y));
+    let frustum = view.sub_frustum(ndc_min, ndc_max);
+
+    // Keeps the nearest hits only, so that huge selections don't hold every object.
+    let mut hits = BinaryHeap::new();
+
+    for candidate in candidates {
+        let distance = match candidate.shape {
+            PickShape::Box { min, max } => {
+                if !frustum.intersects_aabb(min, max) {
+                    continue;
+                }
+
+                if mode == PickRectMode::Contain
+                    && !box_corners(min, max).iter().all(|&corner| {
+                        let clip = Vec4::from_vec3(corner, 1.0) * &view.view_projection;
+                        let ndc = Vec2::new(clip.x / clip.w, clip.y / clip.w);
+                        0.0 < clip.w && is_inside(ndc, ndc_min, ndc_max)
+                    })
+                {
+                    continue;
+                }
+
+                Vec3::distance(view.camera_position, (min + max) * 0.5)
+            }
+            PickShape::Quad(corners) => {
+                let is_hit = match mode {
+                    PickRectMode::Contain => corners
+                        .iter()
+                        .all(|&corner| is_inside(corner, screen_min, screen_max)),
+                    PickRectMode::Intersect => {
+                        quad_intersects_rect(&corners, screen_min, screen_max)
+                    }
+                };
+
+                if !is_hit {
+                    continue;
+                }
+
+                // Quads are drawn over the scene.
+                0.0
+            }
+        };
+
+        hits.push(PickHit {
+            distance,
+            order: candidate.order,
+            object: candidate.object,
+        });
+
+        if max_results < hits.len() {
+            hits.pop();
+        }
+    }
+
+    Vec::from_iter(hits.into_sorted_vec().into_iter().map(|hit| hit.object))
+}
+
+/// Picks the frontmost candidate under the point given in window coordinates.
+/// Quads are drawn over the scene, so they win over boxes; among quads, the last one drawn wins.
+pub fn pick_point(
+    candidates: impl IntoIterator<Item = PickCandidate>,
+    view: &PickView,
+    point: Vec2,
+) -> Option<ObjectId> {
+    let screen_point = view.to_screen_space(point);
+    let (origin, direction) = view.ray(point);
+    let mut quad_hit: Option<PickCandidate> = None;
+    let mut box_hit: Option<(PickHit, PickCandidate)> = None;
+
+    for candidate in candidates {
+        match candidate.shape {
+            PickShape::Box { min, max } => {
+                let distance = match ray_intersects_aabb(origin, direction, min, max) {
+                    Some(distance) => distance,
+                    None => continue,
+                };
+                let hit = PickHit {
+                    distance,
+                    order: candidate.order,
+                    object: candidate.object,
+                };
+
+                if box_hit.as_ref().map_or(true, |(nearest, _)| hit < *nearest) {
+                    box_hit = Some((hit, candidate));
+                }
+            }
+            PickShape::Quad(corners) => {
+                if !quad_intersects_rect(&corners, screen_point, screen_point) {
+                    continue;
+                }
+
+                if quad_hit.map_or(true, |top| top.order < candidate.order) {
+                    quad_hit = Some(candidate);
+                }
+            }
+        }
+    }
+
+    quad_hit
+        .or(box_hit.map(|(_, candidate)| candidate))
+        .map(|candidate| candidate.object)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PickHit {
+    distance: f32,
+    order: u32,
+    object: ObjectId,
+}
+
+impl PartialEq for PickHit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PickHit {}
+
+impl PartialOrd for PickHit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PickHit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.order.cmp(&other.order))
+    }
+}
+
+fn box_corners(min: Vec3, max: Vec3) -> [Vec3; 8] {
+    [
+        Vec3::new(min.x, min.y, min.z),
+        Vec3::new(max.x, min.y, min.z),
+        Vec3::new(min.x, max.y, min.z),
+        Vec3::new(max.x, max.y, min.z),
+        Vec3::new(min.x, min.y, max.z),
+        Vec3::new(max.x, min.y, max.z),
+        Vec3::new(min.x, max.y, max.z),
+        Vec3::new(max.x, max.y, max.z),
+    ]
+}
+
+/// Returns the world space bounding box of a local bounding box transformed by the matrix.
+pub fn transform_aabb(min: Vec3, max: Vec3, matrix: &Mat4) -> (Vec3, Vec3) {
+    box_corners(min, max).iter().fold(
+        (
+            Vec3::new(f32::MAX, f32::MAX, f32::MAX),
+            Vec3::new(f32::MIN, f32::MIN, f32::MIN),
+        ),
+        |(min, max), &corner| {
+            let corner = Vec3::from_vec4(Vec4::from_vec3(corner, 1.0) * matrix);
+            (Vec3::min(min, corner), Vec3::max(max, corner))
+        },
+    )
+}
+
+fn is_inside(point: Vec2, min: Vec2, max: Vec2) -> bool {
+    min.x <= point.x && point.x <= max.x && min.y <= point.y && point.y <= max.y
+}
+
+/// Separating axis test between a convex quad and an axis-aligned rectangle.
+fn quad_intersects_rect(corners: &[Vec2; 4], min: Vec2, max: Vec2) -> bool {
+    let rect = [
+        Vec2::new(min.x, min.y),
+        Vec2::new(max.x, min.y),
+        Vec2::new(max.x, max.y),
+        Vec2::new(min.x, max.y),
+    ];
+    let edge_normals = (0..4).map(|index| {
+        let edge = corners[(index + 1) % 4] - corners[index];
+        Vec2::new(-edge.y, edge.x)
+    });
+
+    [Vec2::new(1.0, 0.0), Vec2::new(0.0, 1.0)]
+        .into_iter()
+        .chain(edge_normals)
+        .all(|axis| {
+            let project = |points: &[Vec2; 4]| {
+                points
+                    .iter()
+                    .fold((f32::MAX, f32::MIN), |(min, max), &point| {
+                        let distance = Vec2::dot(point, axis);
+                        (min.min(distance), max.max(distance))
+                    })
+            };
+            let (quad_min, quad_max) = project(corners);
+            let (rect_min, rect_max) = project(&rect);
+            quad_min <= rect_max && rect_min <= quad_max
+        })
+}
+
+/// Returns the distance along the ray to the box, zero if the ray starts inside it.
+fn ray_intersects_aabb(origin: Vec3, direction: Vec3, min: Vec3, max: Vec3) -> Option<f32> {
+    let mut near = 0.0f32;
+    let mut far = f32::MAX;
+
+    for (origin, direction, min, max) in [
+        (origin.x, direction.x, min.x, max.x),
+        (origin.y, direction.y, min.y, max.y),
+        (origin.z, direction.z, min.z, max.z),
+    ] {
+        if direction.abs() <= f32::EPSILON {
+            if origin < min || max < origin {
+                return None;
+            }
+
+            continue;
+        }
+
+        let t0 = (min - origin) / direction;
+        let t1 = (max - origin) / direction;
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+
+        if far < near {
+            return None;
+        }
+    }
+
+    Some(near)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An orthographic camera showing x in [-10, 10] and y in [-10, 10] on a 200x200 screen, with depth in [0, 10].
+    fn view() -> PickView {
+        PickView {
+            view_projection: Mat4::orthographic(-10.0, 10.0, -10.0, 10.0, 0.0, 10.0),
+            camera_position: Vec3::ZERO,
+            screen_size: Vec2::new(200.0, 200.0),
+        }
+    }
+
+    /// A 5x5 grid of unit boxes centered at x, y in {-8, -4, 0, 4, 8}, ids in row-major order from the top left.
+    fn grid() -> Vec<PickCandidate> {
+        let mut candidates = Vec::new();
+
+        for row in 0..5 {
+            for column in 0..5 {
+                let index = row * 5 + column;
+                let center = Vec3::new(column as f32 * 4.0 - 8.0, 8.0 - row as f32 * 4.0, 5.0);
+                candidates.push(PickCandidate {
+                    object: ObjectId::from_u32(index),
+                    order: index,
+                    shape: PickShape::Box {
+                        min: center - Vec3::ONE * 0.5,
+                        max: center + Vec3::ONE * 0.5,
+                    },
+                });
+            }
+        }
+
+        candidates
+    }
+
+    fn ids(objects: Vec<ObjectId>) -> Vec<u32> {
+        Vec::from_iter(objects.into_iter().map(|object| object.get()))
+    }
+
+    #[test]
+    fn check_pick_rect_grid() {
+        let view = view();
+
+        // World x in [-2.5, 5], y in [-2.5, 5]: contains (0, 0), (4, 4), (0, 4), (4, 0).
+        let contained = pick_rect(
+            grid(),
+            &view,
+            Vec2::new(75.0, 50.0),
+            Vec2::new(150.0, 125.0),
+            PickRectMode::Contain,
+            usize::MAX,
+        );
+        let mut contained = ids(contained);
+        contained.sort();
+        assert_eq!(contained, vec![7, 8, 12, 13]);
+
+        // World x in [-4.2, 4.2], y in [3.8, 8.2]: cuts the boxes at x = -4 and x = 4 and y = 8.
+        let rect = (Vec2::new(58.0, 18.0), Vec2::new(142.0, 62.0));
+        let mut contained = ids(pick_rect(
+            grid(),
+            &view,
+            rect.0,
+            rect.1,
+            PickRectMode::Contain,
+            usize::MAX,
+        ));
+        contained.sort();
+        assert_eq!(contained, Vec::<u32>::new());
+
+        let mut intersected = ids(pick_rect(
+            grid(),
+            &view,
+            rect.1,
+            rect.0,
+            PickRectMode::Intersect,
+            usize::MAX,
+        ));
+        intersected.sort();
+        assert_eq!(intersected, vec![1, 2, 3, 6, 7, 8]);
+
+        // A rectangle between the boxes picks nothing.
+        assert!(pick_rect(
+            grid(),
+            &view,
+            Vec2::new(106.0, 106.0),
+            Vec2::new(114.0, 114.0),
+            PickRectMode::Intersect,
+            usize::MAX,
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn check_pick_rect_order_and_limit() {
+        let mut view = view();
+        view.camera_position = Vec3::new(8.0, -8.0, 0.0);
+
+        // Everything, nearest to the bottom right corner first, ties in hierarchy order.
+        let all = ids(pick_rect(
+            grid(),
+            &view,
+            Vec2::new(0.0, 0.0),
+            Vec2::new(200.0, 200.0),
+            PickRectMode::Contain,
+            usize::MAX,
+        ));
+        assert_eq!(all.len(), 25);
+        assert_eq!(&all[..3], &[24, 19, 23]);
+        assert_eq!(all[24], 0);
+
+        let limited = ids(pick_rect(
+            grid(),
+            &view,
+            Vec2::new(0.0, 0.0),
+            Vec2::new(200.0, 200.0),
+            PickRectMode::Intersect,
+            3,
+        ));
+        assert_eq!(limited, vec![24, 19, 23]);
+    }
+
+    #[test]
+    fn check_pick_rect_quads_and_clicks() {
+        let view = view();
+        // A quad rotated by 45 degrees around (50, 50) in screen space, i.e. around (150, 50) in window coordinates.
+        let diamond = PickCandidate {
+            object: ObjectId::from_u32(100),
+            order: 100,
+            shape: PickShape::Quad([
+                Vec2::new(50.0, 40.0),
+                Vec2::new(60.0, 50.0),
+                Vec2::new(50.0, 60.0),
+                Vec2::new(40.0, 50.0),
+            ]),
+        };
+        let candidates = || grid().into_iter().chain(std::iter::once(diamond));
+
+        // Overlaps the bounding box of the diamond but not the diamond itself.
+        assert!(ids(pick_rect(
+            candidates(),
+            &view,
+            Vec2::new(156.0, 40.0),
+            Vec2::new(165.0, 44.0),
+            PickRectMode::Intersect,
+            usize::MAX,
+        ))
+        .is_empty());
+        assert_eq!(
+            ids(pick_rect(
+                candidates(),
+                &view,
+                Vec2::new(152.0, 44.0),
+                Vec2::new(165.0, 48.0),
+                PickRectMode::Intersect,
+                usize::MAX,
+            )),
+            vec![100]
+        );
+        // The diamond is contained, and is ordered before the box at (4, 4) since it is drawn over the scene.
+        assert_eq!(
+            ids(pick_rect(
+                candidates(),
+                &view,
+                Vec2::new(130.0, 30.0),
+                Vec2::new(170.0, 70.0),
+                PickRectMode::Contain,
+                usize::MAX,
+            )),
+            vec![100, 8]
+        );
+
+        // Clicks without dragging pick the object under the cursor.
+        assert_eq!(
+            ids(pick_rect(
+                candidates(),
+                &view,
+                Vec2::new(100.0, 100.0),
+                Vec2::new(100.0, 100.0),
+                PickRectMode::Contain,
+                usize::MAX,
+            )),
+            vec![12]
+        );
+        assert_eq!(
+            pick_point(candidates(), &view, Vec2::new(150.0, 50.0)),
+            Some(ObjectId::from_u32(100))
+        );
+        assert_eq!(
+            pick_point(candidates(), &view, Vec2::new(110.0, 110.0)),
+            None
+        );
+    }
+}
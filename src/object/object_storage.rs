@@ -63,7 +63,7 @@ impl ObjectStorage {
         if let Some(object) = self.objects.get_mut(id) {
             // TODO: we need a method that only removes the component from the object,
             // but not from the component storage
-            object.remove_component(&mut self.component_storage, component_id);
+            object.remove_component_untyped(&mut self.component_storage, component_id);
         }
     }
 }
@@ -0,0 +1,65 @@
+use super::{PlatformCapability, PlatformError};
+
+/// Thin abstraction over the system clipboard. It is backed by `arboard` when the `clipboard`
+/// feature is enabled on a native target, and is a stub otherwise.
+pub struct Clipboard {
+    #[cfg(all(feature = "clipboard", not(target_arch = "wasm32")))]
+    inner: Option<arboard::Clipboard>,
+}
+
+#[cfg(all(feature = "clipboard", not(target_arch = "wasm32")))]
+impl Clipboard {
+    pub fn new() -> Self {
+        Self {
+            // The clipboard may be unavailable (e.g. no display server); we report it lazily.
+            inner: arboard::Clipboard::new().ok(),
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    pub fn get_text(&mut self) -> Result<Option<String>, PlatformError> {
+        let inner = self
+            .inner
+            .as_mut()
+            .ok_or(PlatformError::Unsupported(PlatformCapability::Clipboard))?;
+
+        match inner.get_text() {
+            Ok(text) => Ok(Some(text)),
+            Err(arboard::Error::ContentNotAvailable) => Ok(None),
+            Err(err) => Err(PlatformError::ClipboardError(err.to_string())),
+        }
+    }
+
+    pub fn set_text(&mut self, text: &str) -> Result<(), PlatformError> {
+        let inner = self
+            .inner
+            .as_mut()
+            .ok_or(PlatformError::Unsupported(PlatformCapability::Clipboard))?;
+
+        inner
+            .set_text(text)
+            .map_err(|err| PlatformError::ClipboardError(err.to_string()))
+    }
+}
+
+#[cfg(not(all(feature = "clipboard", not(target_arch = "wasm32"))))]
+impl Clipboard {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn is_supported(&self) -> bool {
+        false
+    }
+
+    pub fn get_text(&mut self) -> Result<Option<String>, PlatformError> {
+        Err(PlatformError::Unsupported(PlatformCapability::Clipboard))
+    }
+
+    pub fn set_text(&mut self, _text: &str) -> Result<(), PlatformError> {
+        Err(PlatformError::Unsupported(PlatformCapability::Clipboard))
+    }
+}
@@ -0,0 +1,252 @@
+use super::PlatformError;
+use std::{
+    cell::RefCell,
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileDialogFilter {
+    pub name: String,
+    pub extensions: Vec<String>,
+}
+
+impl FileDialogFilter {
+    pub fn new(name: impl Into<String>, extensions: &[&str]) -> Self {
+        Self {
+            name: name.into(),
+            extensions: extensions.iter().map(|ext| ext.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageBoxKind {
+    Info,
+    Warning,
+    Error,
+    /// Shows OK and Cancel buttons.
+    Confirm,
+}
+
+enum DialogState<T> {
+    Open(Pin<Box<dyn Future<Output = T>>>),
+    Closed(T),
+    Taken,
+}
+
+/// Pollable handle of a dialog shown without blocking. The main loop never blocks on it.
+///
+/// The dialog is driven by polling the handle, on the thread that opened it. Native dialogs must run on the
+/// event-loop thread on some platforms (e.g. macOS), so open and poll them from the main loop.
+pub struct DialogHandle<T> {
+    state: RefCell<DialogState<T>>,
+}
+
+impl<T> DialogHandle<T> {
+    #[cfg_attr(
+        not(all(feature = "dialog", not(target_arch = "wasm32"))),
+        allow(dead_code)
+    )]
+    fn from_future(future: impl Future<Output = T> + 'static) -> Self {
+        Self {
+            state: RefCell::new(DialogState::Open(Box::pin(future))),
+        }
+    }
+
+    /// Returns `true` if the dialog has been closed and its result has not been taken yet.
+    pub fn is_done(&self) -> bool {
+        self.advance();
+        matches!(*self.state.borrow(), DialogState::Closed(_))
+    }
+
+    /// Takes the result if the dialog has been closed. Returns `None` while it is still open,
+    /// and after the result has been taken once.
+    pub fn poll(&self) -> Option<T> {
+        self.advance();
+
+        let mut state = self.state.borrow_mut();
+        match std::mem::replace(&mut *state, DialogState::Taken) {
+            DialogState::Closed(result) => Some(result),
+            open_or_taken => {
+                *state = open_or_taken;
+                None
+            }
+        }
+    }
+
+    fn advance(&self) {
+        let mut state = self.state.borrow_mut();
+        let future = match &mut *state {
+            DialogState::Open(future) => future,
+            _ => return,
+        };
+
+        // Nothing waits on the waker; the main loop polls again on the next frame instead.
+        let waker = noop_waker();
+        if let Poll::Ready(result) = future.as_mut().poll(&mut Context::from_waker(&waker)) {
+            *state = DialogState::Closed(result);
+        }
+    }
+}
+
+fn noop_waker() -> Waker {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(
+        |_| RawWaker::new(std::ptr::null(), &VTABLE),
+        |_| {},
+        |_| {},
+        |_| {},
+    );
+
+    // SAFETY: the vtable never dereferences the data pointer.
+    unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
+}
+
+#[cfg(all(feature = "dialog", not(target_arch = "wasm32")))]
+mod native {
+    use super::*;
+    use rfd::{
+        AsyncFileDialog, AsyncMessageDialog, MessageButtons, MessageDialogResult, MessageLevel,
+    };
+
+    fn file_dialog(filters: &[FileDialogFilter]) -> AsyncFileDialog {
+        filters
+            .iter()
+            .fold(AsyncFileDialog::new(), |dialog, filter| {
+                dialog.add_filter(&filter.name, &filter.extensions)
+            })
+    }
+
+    pub fn is_dialog_supported() -> bool {
+        true
+    }
+
+    pub fn open_file_dialog(
+        filters: &[FileDialogFilter],
+    ) -> Result<DialogHandle<Option<PathBuf>>, PlatformError> {
+        let file = file_dialog(filters).pick_file();
+        Ok(DialogHandle::from_future(async move {
+            file.await.map(PathBuf::from)
+        }))
+    }
+
+    pub fn save_file_dialog(
+        filters: &[FileDialogFilter],
+    ) -> Result<DialogHandle<Option<PathBuf>>, PlatformError> {
+        let file = file_dialog(filters).save_file();
+        Ok(DialogHandle::from_future(async move {
+            file.await.map(PathBuf::from)
+        }))
+    }
+
+    pub fn message_box(
+        kind: MessageBoxKind,
+        text: &str,
+    ) -> Result<DialogHandle<bool>, PlatformError> {
+        let (level, buttons) = match kind {
+            MessageBoxKind::Info => (MessageLevel::Info, MessageButtons::Ok),
+            MessageBoxKind::Warning => (MessageLevel::Warning, MessageButtons::Ok),
+            MessageBoxKind::Error => (MessageLevel::Error, MessageButtons::Ok),
+            MessageBoxKind::Confirm => (MessageLevel::Info, MessageButtons::OkCancel),
+        };
+        let result = AsyncMessageDialog::new()
+            .set_level(level)
+            .set_buttons(buttons)
+            .set_description(text)
+            .show();
+        Ok(DialogHandle::from_future(async move {
+            matches!(
+                result.await,
+                MessageDialogResult::Ok | MessageDialogResult::Yes
+            )
+        }))
+    }
+}
+
+#[cfg(not(all(feature = "dialog", not(target_arch = "wasm32"))))]
+mod native {
+    use super::*;
+    use crate::platform::PlatformCapability;
+
+    pub fn is_dialog_supported() -> bool {
+        false
+    }
+
+    pub fn open_file_dialog(
+        _filters: &[FileDialogFilter],
+    ) -> Result<DialogHandle<Option<PathBuf>>, PlatformError> {
+        Err(PlatformError::Unsupported(PlatformCapability::FileDialog))
+    }
+
+    pub fn save_file_dialog(
+        _filters: &[FileDialogFilter],
+    ) -> Result<DialogHandle<Option<PathBuf>>, PlatformError> {
+        Err(PlatformError::Unsupported(PlatformCapability::FileDialog))
+    }
+
+    pub fn message_box(
+        _kind: MessageBoxKind,
+        _text: &str,
+    ) -> Result<DialogHandle<bool>, PlatformError> {
+        Err(PlatformError::Unsupported(PlatformCapability::MessageBox))
+    }
+}
+
+pub(super) use native::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+
+    /// Stands in for a native dialog, closed with the result once `close` is set.
+    fn dialog(close: &Rc<Cell<Option<bool>>>) -> DialogHandle<bool> {
+        let close = close.clone();
+        DialogHandle::from_future(std::future::poll_fn(move |_| match close.get() {
+            Some(result) => Poll::Ready(result),
+            None => Poll::Pending,
+        }))
+    }
+
+    #[test]
+    fn check_dialog_result_is_taken_once() {
+        let close = Rc::new(Cell::new(None));
+        let handle = dialog(&close);
+
+        assert!(!handle.is_done());
+        assert_eq!(handle.poll(), None);
+
+        close.set(Some(true));
+        assert!(handle.is_done());
+        assert!(handle.is_done());
+        assert_eq!(handle.poll(), Some(true));
+
+        assert!(!handle.is_done());
+        assert_eq!(handle.poll(), None);
+    }
+
+    #[test]
+    fn check_dialog_is_driven_by_polling() {
+        let close = Rc::new(Cell::new(None));
+        let handle = dialog(&close);
+        let polls = Rc::new(Cell::new(0));
+        let counted = {
+            let polls = polls.clone();
+            DialogHandle::from_future(std::future::poll_fn(move |_| {
+                polls.set(polls.get() + 1);
+                Poll::<()>::Pending
+            }))
+        };
+
+        // Nothing runs in the background: the dialog only advances on the thread polling it.
+        assert_eq!(polls.get(), 0);
+        counted.poll();
+        counted.is_done();
+        assert_eq!(polls.get(), 2);
+
+        close.set(Some(false));
+        assert_eq!(handle.poll(), Some(false));
+    }
+}
@@ -0,0 +1,268 @@
+mod clipboard;
+mod dialog;
+mod text_clipboard_target;
+
+pub use clipboard::*;
+pub use dialog::*;
+pub use text_clipboard_target::*;
+
+use std::cell::RefCell;
+use thiserror::Error;
+use winit::event::{ElementState, KeyboardInput, ModifiersState, VirtualKeyCode};
+
+#[derive(Error, Debug)]
+pub enum PlatformError {
+    #[error("{0} is not supported on this platform")]
+    Unsupported(PlatformCapability),
+    #[error("clipboard error: {0}")]
+    ClipboardError(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlatformCapability {
+    Clipboard,
+    FileDialog,
+    MessageBox,
+}
+
+impl std::fmt::Display for PlatformCapability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlatformCapability::Clipboard => write!(f, "clipboard"),
+            PlatformCapability::FileDialog => write!(f, "file dialog"),
+            PlatformCapability::MessageBox => write!(f, "message box"),
+        }
+    }
+}
+
+/// Provides access to platform services such as the clipboard and native dialogs.
+/// Every API returns [`PlatformError::Unsupported`] when the service is not available,
+/// either because the platform lacks it or because the corresponding feature is disabled.
+pub struct PlatformManager {
+    clipboard: Clipboard,
+    modifiers: ModifiersState,
+    text_target: Option<TextClipboardTargetHandle>,
+}
+
+impl PlatformManager {
+    pub fn new() -> Self {
+        Self {
+            clipboard: Clipboard::new(),
+            modifiers: ModifiersState::empty(),
+            text_target: None,
+        }
+    }
+
+    pub fn is_supported(&self, capability: PlatformCapability) -> bool {
+        match capability {
+            PlatformCapability::Clipboard => self.clipboard.is_supported(),
+            PlatformCapability::FileDialog | PlatformCapability::MessageBox => {
+                dialog::is_dialog_supported()
+            }
+        }
+    }
+
+    pub fn get_clipboard_text(&mut self) -> Result<Option<String>, PlatformError> {
+        self.clipboard.get_text()
+    }
+
+    pub fn set_clipboard_text(&mut self, text: &str) -> Result<(), PlatformError> {
+        self.clipboard.set_text(text)
+    }
+
+    /// Opens a native file dialog without blocking. The result can be polled from the returned handle.
+    pub fn open_file_dialog(
+        &self,
+        filters: &[FileDialogFilter],
+    ) -> Result<DialogHandle<Option<std::path::PathBuf>>, PlatformError> {
+        dialog::open_file_dialog(filters)
+    }
+
+    /// Opens a native save dialog without blocking. The result can be polled from the returned handle.
+    pub fn save_file_dialog(
+        &self,
+        filters: &[FileDialogFilter],
+    ) -> Result<DialogHandle<Option<std::path::PathBuf>>, PlatformError> {
+        dialog::save_file_dialog(filters)
+    }
+
+    /// Shows a native message box without blocking. The handle yields `true` if the user confirmed it.
+    pub fn message_box(
+        &self,
+        kind: MessageBoxKind,
+        text: &str,
+    ) -> Result<DialogHandle<bool>, PlatformError> {
+        dialog::message_box(kind, text)
+    }
+
+    /// Sets the text field that receives clipboard shortcuts (Ctrl+C/V/X). Pass `None` when no text field is active.
+    pub fn set_active_text_target(&mut self, target: Option<TextClipboardTargetHandle>) {
+        self.text_target = target;
+    }
+
+    pub fn handle_modifiers_changed(&mut self, modifiers: ModifiersState) {
+        self.modifiers = modifiers;
+    }
+
+    pub fn handle_keyboard_input(&mut self, input: &KeyboardInput) {
+        if input.state != ElementState::Pressed {
+            return;
+        }
+
+        let shortcut_modifier = if cfg!(target_os = "macos") {
+            self.modifiers.logo()
+        } else {
+            self.modifiers.ctrl()
+        };
+
+        if !shortcut_modifier {
+            return;
+        }
+
+        let target = match self
+            .text_target
+            .as_ref()
+            .and_then(|target| target.upgrade())
+        {
+            Some(target) => target,
+            None => return,
+        };
+
+        if let Some(key) = input.virtual_keycode {
+            apply_clipboard_shortcut(key, &target, &mut self.clipboard);
+        }
+    }
+}
+
+/// Text access to a clipboard, so that the shortcuts do not depend on the system clipboard.
+trait TextClipboard {
+    fn get_text(&mut self) -> Result<Option<String>, PlatformError>;
+    fn set_text(&mut self, text: &str) -> Result<(), PlatformError>;
+}
+
+impl TextClipboard for Clipboard {
+    fn get_text(&mut self) -> Result<Option<String>, PlatformError> {
+        Clipboard::get_text(self)
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<(), PlatformError> {
+        Clipboard::set_text(self, text)
+    }
+}
+
+fn apply_clipboard_shortcut(
+    key: VirtualKeyCode,
+    target: &RefCell<dyn TextClipboardTarget>,
+    clipboard: &mut impl TextClipboard,
+) {
+    // Failures are not fatal here; an unsupported clipboard simply makes the shortcut a no-op.
+    match key {
+        VirtualKeyCode::C => {
+            if let Some(text) = target.borrow().copy_selection() {
+                let _ = clipboard.set_text(&text);
+            }
+        }
+        VirtualKeyCode::X => {
+            // The selection is only removed once it is on the clipboard, so a failed cut loses nothing.
+            let text = target.borrow().copy_selection();
+
+            if let Some(text) = text {
+                if clipboard.set_text(&text).is_ok() {
+                    target.borrow_mut().cut_selection();
+                }
+            }
+        }
+        VirtualKeyCode::V => {
+            if let Ok(Some(text)) = clipboard.get_text() {
+                target.borrow_mut().paste(&text);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A clipboard that holds its text in memory, or fails every access when unavailable.
+    struct MemoryClipboard {
+        text: Option<String>,
+        is_available: bool,
+    }
+
+    impl TextClipboard for MemoryClipboard {
+        fn get_text(&mut self) -> Result<Option<String>, PlatformError> {
+            if self.is_available {
+                Ok(self.text.clone())
+            } else {
+                Err(PlatformError::ClipboardError("unavailable".to_owned()))
+            }
+        }
+
+        fn set_text(&mut self, text: &str) -> Result<(), PlatformError> {
+            if self.is_available {
+                self.text = Some(text.to_owned());
+                Ok(())
+            } else {
+                Err(PlatformError::ClipboardError("unavailable".to_owned()))
+            }
+        }
+    }
+
+    /// A text field whose selection is the whole text.
+    struct TextField {
+        text: String,
+    }
+
+    impl TextClipboardTarget for TextField {
+        fn copy_selection(&self) -> Option<String> {
+            Some(self.text.clone()).filter(|text| !text.is_empty())
+        }
+
+        fn cut_selection(&mut self) -> Option<String> {
+            Some(std::mem::take(&mut self.text)).filter(|text| !text.is_empty())
+        }
+
+        fn paste(&mut self, text: &str) {
+            self.text = text.to_owned();
+        }
+    }
+
+    fn text_field(text: &str) -> RefCell<TextField> {
+        RefCell::new(TextField {
+            text: text.to_owned(),
+        })
+    }
+
+    #[test]
+    fn check_cut_moves_the_selection_to_the_clipboard() {
+        let field = text_field("hello");
+        let mut clipboard = MemoryClipboard {
+            text: None,
+            is_available: true,
+        };
+
+        apply_clipboard_shortcut(VirtualKeyCode::X, &field, &mut clipboard);
+        assert_eq!(field.borrow().text, "");
+        assert_eq!(clipboard.text.as_deref(), Some("hello"));
+
+        apply_clipboard_shortcut(VirtualKeyCode::V, &field, &mut clipboard);
+        assert_eq!(field.borrow().text, "hello");
+    }
+
+    #[test]
+    fn check_failed_cut_keeps_the_selection() {
+        let field = text_field("hello");
+        let mut clipboard = MemoryClipboard {
+            text: None,
+            is_available: false,
+        };
+
+        apply_clipboard_shortcut(VirtualKeyCode::X, &field, &mut clipboard);
+        assert_eq!(field.borrow().text, "hello");
+
+        apply_clipboard_shortcut(VirtualKeyCode::V, &field, &mut clipboard);
+        assert_eq!(field.borrow().text, "hello");
+    }
+}
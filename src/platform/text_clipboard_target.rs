@@ -0,0 +1,13 @@
+use std::{cell::RefCell, rc::Weak};
+
+pub type TextClipboardTargetHandle = Weak<RefCell<dyn TextClipboardTarget>>;
+
+/// Implemented by text fields that want to receive clipboard shortcuts.
+pub trait TextClipboardTarget {
+    /// Returns the selected text, if any.
+    fn copy_selection(&self) -> Option<String>;
+    /// Removes the selected text and returns it, if any.
+    fn cut_selection(&mut self) -> Option<String>;
+    /// Replaces the selection (or inserts at the cursor) with the given text.
+    fn paste(&mut self, text: &str);
+}
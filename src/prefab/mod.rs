@@ -0,0 +1,7 @@
+mod prefab;
+mod prefab_manager;
+mod prefab_registry;
+
+pub use prefab::*;
+pub use prefab_manager::*;
+pub use prefab_registry::*;
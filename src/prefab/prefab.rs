@@ -0,0 +1,357 @@
+use crate::{
+    math::{Quat, Vec3},
+    transform::Transform,
+};
+use asset::AssetKey;
+use serde::{Deserialize, Serialize};
+
+/// Serialized hierarchy of objects that can be instantiated many times.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Prefab {
+    pub root: PrefabNode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum PrefabNode {
+    Object(PrefabObject),
+    /// An instance of another prefab, with the changes made on top of it.
+    Instance(PrefabInstanceNode),
+}
+
+impl PrefabNode {
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            PrefabNode::Object(object) => object.name.as_deref(),
+            PrefabNode::Instance(instance) => instance.name(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PrefabObject {
+    pub name: Option<String>,
+    pub active: bool,
+    pub transform: PrefabTransform,
+    pub children: Vec<PrefabNode>,
+}
+
+impl PrefabObject {
+    pub fn new(name: impl Into<Option<String>>) -> Self {
+        Self {
+            name: name.into(),
+            active: true,
+            transform: PrefabTransform::default(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_transform(mut self, transform: PrefabTransform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_child(mut self, child: impl Into<PrefabNode>) -> Self {
+        self.children.push(child.into());
+        self
+    }
+
+    /// Finds a descendant by the names along the path. Children that are not resolved yet can't be found.
+    pub fn find(&self, path: &PrefabNodePath) -> Option<&PrefabObject> {
+        path.0.iter().try_fold(self, |object, name| {
+            object.children.iter().find_map(|child| match child {
+                PrefabNode::Object(child) if child.name.as_deref() == Some(name) => Some(child),
+                _ => None,
+            })
+        })
+    }
+
+    pub fn find_mut(&mut self, path: &PrefabNodePath) -> Option<&mut PrefabObject> {
+        path.0.iter().try_fold(self, |object, name| {
+            object.children.iter_mut().find_map(|child| match child {
+                PrefabNode::Object(child) if child.name.as_deref() == Some(name) => Some(child),
+                _ => None,
+            })
+        })
+    }
+
+    pub fn property(&self, kind: PrefabPropertyKind) -> PrefabProperty {
+        match kind {
+            PrefabPropertyKind::Name => PrefabProperty::Name(self.name.clone()),
+            PrefabPropertyKind::Active => PrefabProperty::Active(self.active),
+            PrefabPropertyKind::Position => PrefabProperty::Position(self.transform.position),
+            PrefabPropertyKind::Rotation => PrefabProperty::Rotation(self.transform.rotation),
+            PrefabPropertyKind::Scale => PrefabProperty::Scale(self.transform.scale),
+        }
+    }
+
+    pub fn set_property(&mut self, property: &PrefabProperty) {
+        match property {
+            PrefabProperty::Name(name) => self.name = name.clone(),
+            PrefabProperty::Active(active) => self.active = *active,
+            PrefabProperty::Position(position) => self.transform.position = *position,
+            PrefabProperty::Rotation(rotation) => self.transform.rotation = *rotation,
+            PrefabProperty::Scale(scale) => self.transform.scale = *scale,
+        }
+    }
+}
+
+impl From<PrefabObject> for PrefabNode {
+    fn from(object: PrefabObject) -> Self {
+        PrefabNode::Object(object)
+    }
+}
+
+impl From<PrefabInstanceNode> for PrefabNode {
+    fn from(instance: PrefabInstanceNode) -> Self {
+        PrefabNode::Instance(instance)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PrefabTransform {
+    pub position: [f32; 3],
+    /// `[x, y, z, w]`
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Default for PrefabTransform {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: [1.0, 1.0, 1.0],
+        }
+    }
+}
+
+impl From<&Transform> for PrefabTransform {
+    fn from(transform: &Transform) -> Self {
+        Self {
+            position: [
+                transform.position.x,
+                transform.position.y,
+                transform.position.z,
+            ],
+            rotation: [
+                transform.rotation.x,
+                transform.rotation.y,
+                transform.rotation.z,
+                transform.rotation.w,
+            ],
+            scale: [transform.scale.x, transform.scale.y, transform.scale.z],
+        }
+    }
+}
+
+impl From<PrefabTransform> for Transform {
+    fn from(transform: PrefabTransform) -> Self {
+        let [px, py, pz] = transform.position;
+        let [rx, ry, rz, rw] = transform.rotation;
+        let [sx, sy, sz] = transform.scale;
+        Self {
+            position: Vec3::new(px, py, pz),
+            rotation: Quat {
+                x: rx,
+                y: ry,
+                z: rz,
+                w: rw,
+            },
+            scale: Vec3::new(sx, sy, sz),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PrefabInstanceNode {
+    pub prefab: AssetKey,
+    pub overrides: PrefabOverrides,
+}
+
+impl PrefabInstanceNode {
+    pub fn new(prefab: AssetKey) -> Self {
+        Self {
+            prefab,
+            overrides: PrefabOverrides::default(),
+        }
+    }
+
+    pub fn with_overrides(mut self, overrides: PrefabOverrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// The overridden name of the instance root, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.overrides
+            .properties
+            .iter()
+            .find_map(|override_| match override_ {
+                PrefabPropertyOverride {
+                    path,
+                    property: PrefabProperty::Name(name),
+                } if path.is_root() => name.as_deref(),
+                _ => None,
+            })
+    }
+}
+
+/// Path of an object inside a prefab instance, given as the names of the objects below the instance root.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct PrefabNodePath(pub Vec<String>);
+
+impl PrefabNodePath {
+    pub fn root() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn child(&self, name: impl Into<String>) -> Self {
+        let mut path = self.0.clone();
+        path.push(name.into());
+        Self(path)
+    }
+
+    /// Splits the path into the path of the parent and the name of the last object.
+    pub fn split_last(&self) -> Option<(PrefabNodePath, &str)> {
+        let (last, parent) = self.0.split_last()?;
+        Some((Self(parent.to_vec()), last))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrefabPropertyKind {
+    Name,
+    Active,
+    Position,
+    Rotation,
+    Scale,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum PrefabProperty {
+    Name(Option<String>),
+    Active(bool),
+    Position([f32; 3]),
+    Rotation([f32; 4]),
+    Scale([f32; 3]),
+}
+
+impl PrefabProperty {
+    pub fn kind(&self) -> PrefabPropertyKind {
+        match self {
+            PrefabProperty::Name(_) => PrefabPropertyKind::Name,
+            PrefabProperty::Active(_) => PrefabPropertyKind::Active,
+            PrefabProperty::Position(_) => PrefabPropertyKind::Position,
+            PrefabProperty::Rotation(_) => PrefabPropertyKind::Rotation,
+            PrefabProperty::Scale(_) => PrefabPropertyKind::Scale,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PrefabPropertyOverride {
+    pub path: PrefabNodePath,
+    pub property: PrefabProperty,
+}
+
+/// Changes made on an instance relative to its source prefab. Everything not recorded here follows the source.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct PrefabOverrides {
+    pub properties: Vec<PrefabPropertyOverride>,
+    /// Children added under the object at the given path.
+    pub added_children: Vec<(PrefabNodePath, PrefabNode)>,
+    /// Paths of the source objects removed from the instance.
+    pub removed_children: Vec<PrefabNodePath>,
+}
+
+impl PrefabOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+            && self.added_children.is_empty()
+            && self.removed_children.is_empty()
+    }
+
+    /// Records a property change, replacing the previous override of the same property.
+    pub fn set_property(&mut self, path: PrefabNodePath, property: PrefabProperty) {
+        let kind = property.kind();
+
+        match self
+            .properties
+            .iter_mut()
+            .find(|override_| override_.path == path && override_.property.kind() == kind)
+        {
+            Some(override_) => override_.property = property,
+            None => self
+                .properties
+                .push(PrefabPropertyOverride { path, property }),
+        }
+    }
+
+    /// Reverts a property to the value of the source prefab.
+    pub fn revert_property(&mut self, path: &PrefabNodePath, kind: PrefabPropertyKind) {
+        self.properties
+            .retain(|override_| &override_.path != path || override_.property.kind() != kind);
+    }
+
+    pub fn add_child(&mut self, parent: PrefabNodePath, child: PrefabNode) {
+        self.added_children.push((parent, child));
+    }
+
+    pub fn remove_child(&mut self, path: PrefabNodePath) {
+        // Removing an added child simply forgets it.
+        if let Some((parent, name)) = path.split_last() {
+            let count = self.added_children.len();
+            self.added_children.retain(|(added_parent, child)| {
+                added_parent != &parent || child.name() != Some(name)
+            });
+
+            if self.added_children.len() != count {
+                return;
+            }
+        }
+
+        if !self.removed_children.contains(&path) {
+            self.removed_children.push(path);
+        }
+    }
+
+    /// Applies the overrides to a resolved copy of the source prefab.
+    /// `resolve` expands the nested instances among the added children.
+    pub(crate) fn apply<E>(
+        &self,
+        root: &mut PrefabObject,
+        mut resolve: impl FnMut(&PrefabNode) -> Result<PrefabObject, E>,
+    ) -> Result<(), E> {
+        for path in &self.removed_children {
+            if let Some((parent, name)) = path.split_last() {
+                if let Some(parent) = root.find_mut(&parent) {
+                    if let Some(index) = parent
+                        .children
+                        .iter()
+                        .position(|child| child.name() == Some(name))
+                    {
+                        parent.children.remove(index);
+                    }
+                }
+            }
+        }
+
+        for (path, child) in &self.added_children {
+            if let Some(parent) = root.find_mut(path) {
+                parent.children.push(PrefabNode::Object(resolve(child)?));
+            }
+        }
+
+        for override_ in &self.properties {
+            if let Some(object) = root.find_mut(&override_.path) {
+                object.set_property(&override_.property);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,386 @@
+use super::{
+    PrefabError, PrefabInstanceNode, PrefabNode, PrefabNodePath, PrefabObject, PrefabOverrides,
+    PrefabProperty, PrefabRegistry,
+};
+use crate::{
+    handles::StaleHandle,
+    math::{Quat, Vec3},
+    object::{ObjectHandle, ObjectId, ObjectManager},
+    transform::Transform,
+    use_context,
+};
+use asset::AssetKey;
+use specs::{Builder, Entity, World, WorldExt};
+
+/// An instantiated prefab and the changes made on it.
+#[derive(Clone)]
+pub struct PrefabInstance {
+    pub prefab: AssetKey,
+    pub root: ObjectHandle,
+    pub overrides: PrefabOverrides,
+    pub record_overrides: bool,
+}
+
+/// Instantiates prefabs into objects and keeps the instances in sync with their prefabs.
+#[derive(Default)]
+pub struct PrefabManager {
+    registry: PrefabRegistry,
+    instances: Vec<PrefabInstance>,
+}
+
+impl PrefabManager {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn registry(&self) -> &PrefabRegistry {
+        &self.registry
+    }
+
+    pub fn registry_mut(&mut self) -> &mut PrefabRegistry {
+        &mut self.registry
+    }
+
+    pub fn instances(&self) -> &[PrefabInstance] {
+        &self.instances
+    }
+
+    pub fn instance(&self, root: &ObjectHandle) -> Option<&PrefabInstance> {
+        self.instances
+            .iter()
+            .find(|instance| &instance.root == root)
+    }
+
+    pub fn instantiate(
+        &mut self,
+        prefab: AssetKey,
+        parent: Option<&ObjectHandle>,
+    ) -> Result<ObjectHandle, PrefabError> {
+        self.instantiate_with_overrides(prefab, PrefabOverrides::default(), parent)
+    }
+
+    /// Instantiates a prefab with previously recorded overrides applied.
+    pub fn instantiate_with_overrides(
+        &mut self,
+        prefab: AssetKey,
+        overrides: PrefabOverrides,
+        parent: Option<&ObjectHandle>,
+    ) -> Result<ObjectHandle, PrefabError> {
+        let node = PrefabNode::Instance(PrefabInstanceNode { prefab, overrides });
+        let object = self.registry.resolve_node(&node)?;
+        let ctx = use_context();
+        let (entity, object_id) = spawn(
+            &mut ctx.world_mut(),
+            &mut ctx.object_mgr_mut(),
+            &object,
+            parent.map(|parent| parent.object_id),
+        );
+        let root = ObjectHandle::new(ctx.clone(), entity, object_id);
+
+        let instance = match node {
+            PrefabNode::Instance(instance) => instance,
+            PrefabNode::Object(_) => unreachable!(),
+        };
+        self.instances.push(PrefabInstance {
+            prefab: instance.prefab,
+            root: root.clone(),
+            overrides: instance.overrides,
+            record_overrides: false,
+        });
+
+        Ok(root)
+    }
+
+    /// Enables or disables recording the changes made through [`set_property`](Self::set_property)
+    /// and [`remove_object`](Self::remove_object) as overrides of the instance.
+    pub fn record_overrides(&mut self, root: &ObjectHandle, record: bool) {
+        self.prune();
+
+        if let Some(instance) = self
+            .instances
+            .iter_mut()
+            .find(|instance| &instance.root == root)
+        {
+            instance.record_overrides = record;
+        }
+    }
+
+    /// Changes a property of an object. If it belongs to an instance recording overrides, the change is recorded.
+    /// Fails without recording anything if the object has been removed.
+    pub fn set_property(
+        &mut self,
+        object: &ObjectHandle,
+        property: PrefabProperty,
+    ) -> Result<(), StaleHandle> {
+        self.prune();
+
+        if !object.is_alive() {
+            return Err(StaleHandle::new("object"));
+        }
+
+        if let Some((instance, path)) = self.recording_instance(object) {
+            self.instances[instance]
+                .overrides
+                .set_property(path, property.clone());
+        }
+
+        apply_property(object, &property)
+    }
+
+    /// Removes an object. If it belongs to an instance recording overrides, the removal is recorded.
+    /// Fails without recording anything if the object has been removed already.
+    pub fn remove_object(&mut self, object: &ObjectHandle) -> Result<(), StaleHandle> {
+        self.prune();
+
+        if !object.is_alive() {
+            return Err(StaleHandle::new("object"));
+        }
+
+        if let Some((instance, path)) = self.recording_instance(object) {
+            if path.is_root() {
+                self.instances.remove(instance);
+            } else {
+                self.instances[instance].overrides.remove_child(path);
+            }
+        }
+
+        object.remove()
+    }
+
+    /// Re-instantiates every instance affected by a change of the given prefab, keeping their overrides.
+    /// The new roots keep the parents of the old ones.
+    pub fn apply_prefab_changes(&mut self, prefab: &AssetKey) -> Result<(), PrefabError> {
+        self.prune();
+
+        for index in 0..self.instances.len() {
+            let instance = &self.instances[index];
+
+            if !self.registry.depends_on(&instance.prefab, prefab) {
+                continue;
+            }
+
+            // Resolve first, so that a failure leaves the old instance intact.
+            let object = self
+                .registry
+                .resolve_node(&PrefabNode::Instance(PrefabInstanceNode {
+                    prefab: instance.prefab.clone(),
+                    overrides: instance.overrides.clone(),
+                }))?;
+            let ctx = use_context();
+            let (removed, (entity, object_id)) = respawn(
+                &mut ctx.world_mut(),
+                &mut ctx.object_mgr_mut(),
+                instance.root.object_id,
+                &object,
+            );
+            ObjectManager::forget_removed(removed);
+            self.instances[index].root = ObjectHandle::new(ctx.clone(), entity, object_id);
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the instances whose roots have been removed.
+    fn prune(&mut self) {
+        self.instances.retain(|instance| instance.root.is_alive());
+    }
+
+    /// Finds the outermost instance recording overrides that contains the object,
+    /// and the path of the object in it. Unnamed objects can't be addressed, so they are never recorded.
+    fn recording_instance(&self, object: &ObjectHandle) -> Option<(usize, PrefabNodePath)> {
+        let parents = object.parents();
+
+        self.instances
+            .iter()
+            .enumerate()
+            .filter(|(_, instance)| instance.record_overrides)
+            .filter_map(|(index, instance)| {
+                if &instance.root == object {
+                    return Some((parents.len(), index, PrefabNodePath::root()));
+                }
+
+                let depth = parents.iter().position(|parent| parent == &instance.root)?;
+                let mut names = Vec::with_capacity(depth + 1);
+                names.push(object.name()?);
+
+                for parent in parents[..depth].iter() {
+                    names.push(parent.name()?);
+                }
+
+                names.reverse();
+                Some((parents.len() - depth - 1, index, PrefabNodePath(names)))
+            })
+            .min_by_key(|(root_depth, _, _)| *root_depth)
+            .map(|(_, index, path)| (index, path))
+    }
+}
+
+/// Spawns the object and its children, returning the entity and id of the object.
+fn spawn(
+    world: &mut World,
+    object_mgr: &mut ObjectManager,
+    object: &PrefabObject,
+    parent: Option<ObjectId>,
+) -> (Entity, ObjectId) {
+    let (object_id, builder) = object_mgr.create_entity_builder(
+        world,
+        object.name.clone(),
+        Some(Transform::from(object.transform)),
+    );
+    let entity = builder.build();
+
+    let hierarchy = object_mgr.object_hierarchy_mut();
+    // A freshly created object has no children, so it can't form a cycle.
+    hierarchy.set_parent(object_id, parent).unwrap();
+    hierarchy.set_active(object_id, object.active);
+
+    for child in &object.children {
+        if let PrefabNode::Object(child) = child {
+            spawn(world, object_mgr, child, Some(object_id));
+        }
+    }
+
+    (entity, object_id)
+}
+
+/// Replaces the instance rooted at `root` with the object, under the parent of the old root.
+/// The children of the old root are removed along with it. Returns the removed objects and the new root.
+fn respawn(
+    world: &mut World,
+    object_mgr: &mut ObjectManager,
+    root: ObjectId,
+    object: &PrefabObject,
+) -> (Vec<(ObjectId, Entity)>, (Entity, ObjectId)) {
+    // The root may have been removed by hand; the instance is respawned all the same.
+    let (parent, removed) = if object_mgr.is_alive(root) {
+        let parent = object_mgr.object_hierarchy().parent(root);
+        (parent, object_mgr.remove_from_world(world, root))
+    } else {
+        (None, Vec::new())
+    };
+
+    (removed, spawn(world, object_mgr, object, parent))
+}
+
+fn apply_property(object: &ObjectHandle, property: &PrefabProperty) -> Result<(), StaleHandle> {
+    match property {
+        PrefabProperty::Name(name) => object.set_name(name.clone()),
+        PrefabProperty::Active(active) => object.set_active(*active),
+        PrefabProperty::Position(_) | PrefabProperty::Rotation(_) | PrefabProperty::Scale(_) => {
+            if !object.is_alive() {
+                return Err(StaleHandle::new("object"));
+            }
+
+            object
+                .ctx
+                .object_mgr_mut()
+                .object_hierarchy_mut()
+                .set_dirty(object.object_id);
+
+            let world = object.ctx.world();
+            let mut transforms = world.write_component::<Transform>();
+            let transform = match transforms.get_mut(object.entity) {
+                Some(transform) => transform,
+                None => return Ok(()),
+            };
+
+            match property {
+                PrefabProperty::Position([x, y, z]) => transform.position = Vec3::new(*x, *y, *z),
+                PrefabProperty::Rotation([x, y, z, w]) => {
+                    transform.rotation = Quat {
+                        x: *x,
+                        y: *y,
+                        z: *z,
+                        w: *w,
+                    }
+                }
+                PrefabProperty::Scale([x, y, z]) => transform.scale = Vec3::new(*x, *y, *z),
+                _ => unreachable!(),
+            }
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Object;
+    use specs::Join;
+
+    fn names(object_mgr: &ObjectManager, objects: &[ObjectId]) -> Vec<Option<String>> {
+        objects
+            .iter()
+            .map(|&object| object_mgr.object_name_registry().name(object).cloned())
+            .collect()
+    }
+
+    #[test]
+    fn check_respawn_replaces_the_children() {
+        let mut world = World::new();
+        world.register::<Object>();
+        world.register::<Transform>();
+        let mut object_mgr = ObjectManager::new();
+
+        let (_, scene) = object_mgr.create_entity_builder(&mut world, "scene".to_owned(), None);
+        let scene = scene.build();
+        let scene = world
+            .read_storage::<Object>()
+            .get(scene)
+            .unwrap()
+            .object_id();
+
+        let enemy = PrefabObject::new("enemy".to_owned())
+            .with_child(
+                PrefabObject::new("body".to_owned())
+                    .with_child(PrefabObject::new("weapon".to_owned())),
+            )
+            .with_child(PrefabObject::new("health bar".to_owned()));
+        let (_, root) = spawn(&mut world, &mut object_mgr, &enemy, Some(scene));
+        let old_objects = object_mgr
+            .object_hierarchy()
+            .object_and_children(root)
+            .to_vec();
+
+        // The edited prefab drops the weapon and gains a shadow.
+        let edited = PrefabObject::new("enemy".to_owned())
+            .with_child(PrefabObject::new("body".to_owned()))
+            .with_child(PrefabObject::new("health bar".to_owned()))
+            .with_child(PrefabObject::new("shadow".to_owned()));
+        let (removed, (_, root)) = respawn(&mut world, &mut object_mgr, root, &edited);
+        world.maintain();
+
+        let removed_objects = removed
+            .iter()
+            .map(|(object, _)| *object)
+            .collect::<Vec<_>>();
+        assert_eq!(removed_objects, old_objects);
+
+        for (object, entity) in removed {
+            assert!(!object_mgr.is_alive(object));
+            assert!(!world.is_alive(entity));
+        }
+
+        let hierarchy = object_mgr.object_hierarchy();
+        assert_eq!(hierarchy.parent(root), Some(scene));
+        assert_eq!(
+            names(&object_mgr, hierarchy.object_and_children(scene)),
+            [
+                Some("scene".to_owned()),
+                Some("enemy".to_owned()),
+                Some("body".to_owned()),
+                Some("health bar".to_owned()),
+                Some("shadow".to_owned()),
+            ]
+        );
+
+        // No object of the old instance is left in the world.
+        let objects = world.read_storage::<Object>();
+        assert_eq!((&objects).join().count(), 5);
+
+        for object in (&objects).join() {
+            assert!(hierarchy.contains(object.object_id()));
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use super::{Prefab, PrefabNode, PrefabObject};
+use asset::AssetKey;
+use itertools::Itertools;
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PrefabError {
+    #[error("prefab {0} not found")]
+    NotFound(AssetKey),
+    #[error("prefab contains itself: {}", .0.iter().join(" -> "))]
+    Cycle(Vec<AssetKey>),
+}
+
+/// Holds the prefabs by their asset keys, and expands the nested prefab instances.
+#[derive(Debug, Default)]
+pub struct PrefabRegistry {
+    prefabs: HashMap<AssetKey, Prefab>,
+}
+
+impl PrefabRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn get(&self, key: &AssetKey) -> Option<&Prefab> {
+        self.prefabs.get(key)
+    }
+
+    /// Adds or replaces a prefab. Fails without changing anything if the prefab would contain itself.
+    /// References to prefabs that are not registered yet are allowed.
+    pub fn insert(&mut self, key: AssetKey, prefab: Prefab) -> Result<Option<Prefab>, PrefabError> {
+        self.check_insert(&key, &prefab)?;
+        Ok(self.prefabs.insert(key, prefab))
+    }
+
+    /// Fails the way [`insert`](Self::insert) would, without inserting anything.
+    pub fn check_insert(&self, key: &AssetKey, prefab: &Prefab) -> Result<(), PrefabError> {
+        let mut chain = vec![key.clone()];
+
+        if self.find_cycle(key, &prefab.root, &mut chain) {
+            return Err(PrefabError::Cycle(chain));
+        }
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &AssetKey) -> Option<Prefab> {
+        self.prefabs.remove(key)
+    }
+
+    /// Returns `true` if instances of `key` have to be rebuilt when `changed` changes.
+    pub fn depends_on(&self, key: &AssetKey, changed: &AssetKey) -> bool {
+        if key == changed {
+            return true;
+        }
+
+        match self.prefabs.get(key) {
+            Some(prefab) => references(&prefab.root)
+                .iter()
+                .any(|reference| self.depends_on(reference, changed)),
+            None => false,
+        }
+    }
+
+    /// Expands the prefab into plain objects, applying the overrides of every nested instance.
+    pub fn resolve(&self, key: &AssetKey) -> Result<PrefabObject, PrefabError> {
+        let prefab = self
+            .prefabs
+            .get(key)
+            .ok_or_else(|| PrefabError::NotFound(key.clone()))?;
+        self.resolve_node(&prefab.root)
+    }
+
+    /// Expands the given node into plain objects.
+    pub fn resolve_node(&self, node: &PrefabNode) -> Result<PrefabObject, PrefabError> {
+        match node {
+            PrefabNode::Object(object) => Ok(PrefabObject {
+                name: object.name.clone(),
+                active: object.active,
+                transform: object.transform,
+                children: object
+                    .children
+                    .iter()
+                    .map(|child| self.resolve_node(child).map(PrefabNode::Object))
+                    .collect::<Result<_, _>>()?,
+            }),
+            PrefabNode::Instance(instance) => {
+                let mut object = self.resolve(&instance.prefab)?;
+                instance
+                    .overrides
+                    .apply(&mut object, |child| self.resolve_node(child))?;
+                Ok(object)
+            }
+        }
+    }
+
+    /// Returns `true` if `node` reaches `key`, leaving the path to it in `chain`.
+    fn find_cycle(&self, key: &AssetKey, node: &PrefabNode, chain: &mut Vec<AssetKey>) -> bool {
+        for reference in references(node) {
+            chain.push(reference.clone());
+
+            if &reference == key {
+                return true;
+            }
+
+            // Guards against cycles among the registered prefabs, which `insert` never lets in.
+            if chain[..chain.len() - 1].contains(&reference) {
+                chain.pop();
+                continue;
+            }
+
+            if let Some(prefab) = self.prefabs.get(&reference) {
+                if self.find_cycle(key, &prefab.root, chain) {
+                    return true;
+                }
+            }
+
+            chain.pop();
+        }
+
+        false
+    }
+}
+
+/// Collects the prefabs directly instantiated by the node or its descendants.
+fn references(node: &PrefabNode) -> Vec<AssetKey> {
+    let mut references = Vec::new();
+    collect_references(node, &mut references);
+    references
+}
+
+fn collect_references(node: &PrefabNode, references: &mut Vec<AssetKey>) {
+    match node {
+        PrefabNode::Object(object) => {
+            for child in &object.children {
+                collect_references(child, references);
+            }
+        }
+        PrefabNode::Instance(instance) => {
+            if !references.contains(&instance.prefab) {
+                references.push(instance.prefab.clone());
+            }
+
+            for (_, child) in &instance.overrides.added_children {
+                collect_references(child, references);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prefab::{
+        PrefabInstanceNode, PrefabNodePath, PrefabOverrides, PrefabProperty, PrefabTransform,
+    };
+
+    fn key(path: &str) -> AssetKey {
+        AssetKey::Path(path.to_owned())
+    }
+
+    fn enemy(health_bar_position: [f32; 3]) -> Prefab {
+        Prefab {
+            root: PrefabObject::new("enemy".to_owned())
+                .with_child(PrefabObject::new("body".to_owned()))
+                .with_child(PrefabObject::new("health bar".to_owned()).with_transform(
+                    PrefabTransform {
+                        position: health_bar_position,
+                        ..Default::default()
+                    },
+                ))
+                .into(),
+        }
+    }
+
+    fn camp() -> Prefab {
+        let mut overrides = PrefabOverrides::default();
+        overrides.set_property(
+            PrefabNodePath::root(),
+            PrefabProperty::Name(Some("boss".to_owned())),
+        );
+        overrides.set_property(
+            PrefabNodePath::root().child("body"),
+            PrefabProperty::Scale([2.0, 2.0, 2.0]),
+        );
+        overrides.remove_child(PrefabNodePath::root().child("health bar"));
+
+        Prefab {
+            root: PrefabObject::new("camp".to_owned())
+                .with_child(PrefabInstanceNode::new(key("enemy")))
+                .with_child(PrefabInstanceNode::new(key("enemy")).with_overrides(overrides))
+                .into(),
+        }
+    }
+
+    fn child<'a>(object: &'a PrefabObject, index: usize) -> &'a PrefabObject {
+        match &object.children[index] {
+            PrefabNode::Object(object) => object,
+            PrefabNode::Instance(_) => panic!("resolved prefabs must not contain instances"),
+        }
+    }
+
+    #[test]
+    fn check_nested_prefab_changes_keep_overrides() {
+        let mut registry = PrefabRegistry::new();
+        registry
+            .insert(key("enemy"), enemy([0.0, 1.0, 0.0]))
+            .unwrap();
+        registry.insert(key("camp"), camp()).unwrap();
+
+        // Edit the base prefab: the body moves and the health bar is raised.
+        let mut edited = enemy([0.0, 2.0, 0.0]);
+        if let PrefabNode::Object(root) = &mut edited.root {
+            root.find_mut(&PrefabNodePath::root().child("body"))
+                .unwrap()
+                .transform
+                .position = [0.0, 0.5, 0.0];
+        }
+        registry.insert(key("enemy"), edited).unwrap();
+        assert!(registry.depends_on(&key("camp"), &key("enemy")));
+
+        let camp = registry.resolve(&key("camp")).unwrap();
+        let plain = child(&camp, 0);
+        let boss = child(&camp, 1);
+
+        assert_eq!(plain.name.as_deref(), Some("enemy"));
+        assert_eq!(child(plain, 0).transform.position, [0.0, 0.5, 0.0]);
+        assert_eq!(child(plain, 1).transform.position, [0.0, 2.0, 0.0]);
+
+        // Overridden fields survive, the others follow the base prefab.
+        assert_eq!(boss.name.as_deref(), Some("boss"));
+        assert_eq!(boss.children.len(), 1);
+        assert_eq!(child(boss, 0).transform.scale, [2.0, 2.0, 2.0]);
+        assert_eq!(child(boss, 0).transform.position, [0.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn check_prefab_cycle_detection() {
+        let mut registry = PrefabRegistry::new();
+        registry
+            .insert(key("enemy"), enemy([0.0, 1.0, 0.0]))
+            .unwrap();
+        registry.insert(key("camp"), camp()).unwrap();
+
+        let looped = Prefab {
+            root: PrefabObject::new("enemy".to_owned())
+                .with_child(PrefabInstanceNode::new(key("camp")))
+                .into(),
+        };
+
+        assert_eq!(
+            registry.insert(key("enemy"), looped),
+            Err(PrefabError::Cycle(vec![
+                key("enemy"),
+                key("camp"),
+                key("enemy")
+            ]))
+        );
+        // The rejected prefab is not registered.
+        assert_eq!(registry.resolve(&key("camp")).unwrap().children.len(), 2);
+
+        let recursive = Prefab {
+            root: PrefabInstanceNode::new(key("self")).into(),
+        };
+        assert!(matches!(
+            registry.insert(key("self"), recursive),
+            Err(PrefabError::Cycle(_))
+        ));
+    }
+}
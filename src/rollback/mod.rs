@@ -0,0 +1,153 @@
+//! Rollback of the simulation state for networked prediction.
+//!
+//! Components opt in by being registered through [`RollbackRegistry::register`]. Every tick, the
+//! [`RollbackSession`] captures a compact copy of them, so that it can restore a past tick when a late input
+//! arrives and simulate forward again with the corrected inputs.
+//!
+//! Only the components in the world are captured. GPU-side state, e.g. particle buffers, is never part of a
+//! snapshot; it follows the restored components on the next frame.
+
+mod rollback_session;
+
+pub use rollback_session::*;
+
+use specs::prelude::*;
+use std::{any::Any, marker::PhantomData, mem::size_of};
+
+/// Copy of one component type in all entities, taken by a [`RollbackRegistry`].
+struct ComponentSnapshot {
+    data: Box<dyn Any + Send + Sync>,
+    bytes: usize,
+}
+
+/// Copy of the rollback-relevant components of a world at one tick.
+pub struct WorldSnapshot {
+    components: Vec<ComponentSnapshot>,
+    bytes: usize,
+}
+
+impl WorldSnapshot {
+    /// Shallow size of the copied components and their entities; heap memory owned by the components is not counted.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+trait RollbackComponent: Send + Sync {
+    fn capture(&self, world: &World) -> ComponentSnapshot;
+    fn restore(&self, world: &World, snapshot: &ComponentSnapshot, restored: &mut Vec<Entity>);
+}
+
+struct TypedRollbackComponent<T>(PhantomData<fn() -> T>);
+
+impl<T> RollbackComponent for TypedRollbackComponent<T>
+where
+    T: Component + Clone + Send + Sync,
+{
+    fn capture(&self, world: &World) -> ComponentSnapshot {
+        let entities = world.entities();
+        let storage = world.read_storage::<T>();
+        let components = (&entities, &storage)
+            .join()
+            .map(|(entity, component)| (entity, component.clone()))
+            .collect::<Vec<_>>();
+        let bytes = components.len() * size_of::<(Entity, T)>();
+
+        ComponentSnapshot {
+            data: Box::new(components),
+            bytes,
+        }
+    }
+
+    fn restore(&self, world: &World, snapshot: &ComponentSnapshot, restored: &mut Vec<Entity>) {
+        let components = snapshot
+            .data
+            .downcast_ref::<Vec<(Entity, T)>>()
+            .expect("snapshot of another component type");
+        let entities = world.entities();
+        let mut storage = world.write_storage::<T>();
+
+        // Components added since the snapshot are removed; the snapshot is sorted by entity, as joins are.
+        let added = (&entities, &storage)
+            .join()
+            .map(|(entity, _)| entity)
+            .filter(|entity| {
+                components
+                    .binary_search_by_key(&entity.id(), |(entity, _)| entity.id())
+                    .map_or(true, |index| components[index].0 != *entity)
+            })
+            .collect::<Vec<_>>();
+
+        for entity in added {
+            storage.remove(entity);
+            restored.push(entity);
+        }
+
+        // Entities deleted since the snapshot cannot be revived; their components are lost.
+        for (entity, component) in components {
+            if entities.is_alive(*entity) {
+                storage.insert(*entity, component.clone()).unwrap();
+                restored.push(*entity);
+            }
+        }
+    }
+}
+
+/// The component types whose state is rolled back.
+#[derive(Default)]
+pub struct RollbackRegistry {
+    components: Vec<Box<dyn RollbackComponent>>,
+}
+
+impl RollbackRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers `T` in the world and marks it as rollback-relevant.
+    /// It is copied with [`Clone`], so keep it small and free of shared state such as GPU resources.
+    pub fn register<T>(&mut self, world: &mut World)
+    where
+        T: Component + Clone + Send + Sync,
+        T::Storage: Default,
+    {
+        world.register::<T>();
+        self.components
+            .push(Box::new(TypedRollbackComponent::<T>(PhantomData)));
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// Copies the registered components out of the world.
+    pub fn capture(&self, world: &World) -> WorldSnapshot {
+        let components = self
+            .components
+            .iter()
+            .map(|component| component.capture(world))
+            .collect::<Vec<_>>();
+        let bytes = components.iter().map(|component| component.bytes).sum();
+
+        WorldSnapshot { components, bytes }
+    }
+
+    /// Writes the snapshot back into the world and returns the entities whose components were touched,
+    /// e.g. to mark their objects dirty.
+    /// The snapshot must have been captured by this registry.
+    pub fn restore(&self, world: &World, snapshot: &WorldSnapshot) -> Vec<Entity> {
+        let mut restored = Vec::new();
+
+        for (component, snapshot) in self.components.iter().zip(&snapshot.components) {
+            component.restore(world, snapshot, &mut restored);
+        }
+
+        restored.sort_unstable();
+        restored.dedup();
+        restored
+    }
+}
@@ -0,0 +1,370 @@
+use super::{RollbackRegistry, WorldSnapshot};
+use specs::prelude::*;
+use std::collections::VecDeque;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollbackError {
+    #[error("tick {tick} is no longer buffered; the oldest one is {oldest}")]
+    TickTooOld { tick: u64, oldest: u64 },
+    #[error("tick {tick} has not been simulated yet; the current one is {current}")]
+    TickInFuture { tick: u64, current: u64 },
+}
+
+/// Cost of the snapshots, to keep the per-tick capture within budget.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct RollbackStats {
+    /// Size of the latest snapshot, i.e. the bytes copied per tick.
+    pub last_snapshot_bytes: usize,
+    pub max_snapshot_bytes: usize,
+    /// Size of all buffered snapshots.
+    pub buffered_bytes: usize,
+    pub rollbacks: u64,
+    pub resimulated_ticks: u64,
+}
+
+/// What [`RollbackSession::rollback_to`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollbackOutcome {
+    pub resimulated_ticks: u64,
+    /// Entities whose components were restored. Objects among them may need to be marked dirty,
+    /// if the simulation did not touch them again.
+    pub restored_entities: Vec<Entity>,
+}
+
+struct TickRecord<I> {
+    tick: u64,
+    /// State before the tick was simulated.
+    snapshot: WorldSnapshot,
+    input: I,
+}
+
+/// Ring of the last ticks with their snapshots and inputs, able to rewind and simulate again.
+///
+/// Each tick is run by [`advance`](Self::advance), which captures the state before simulating it.
+/// When the input of a past tick turns out to be mispredicted, replace it with [`set_input`](Self::set_input)
+/// and call [`rollback_to`](Self::rollback_to); the world is restored and simulated forward to the present
+/// within the call, so rendering only ever sees the latest predicted tick.
+///
+/// The simulation must be deterministic, and should neither create nor delete entities,
+/// since deleted entities cannot be revived by a restore.
+pub struct RollbackSession<I> {
+    registry: RollbackRegistry,
+    capacity: usize,
+    records: VecDeque<TickRecord<I>>,
+    tick: u64,
+    stats: RollbackStats,
+}
+
+impl<I> RollbackSession<I>
+where
+    I: Clone + PartialEq,
+{
+    /// Keeps the last `capacity` ticks, which bounds how late an input may arrive.
+    pub fn new(registry: RollbackRegistry, capacity: usize) -> Self {
+        Self {
+            registry,
+            capacity: capacity.max(1),
+            records: VecDeque::with_capacity(capacity.max(1)),
+            tick: 0,
+            stats: RollbackStats::default(),
+        }
+    }
+
+    pub fn registry(&self) -> &RollbackRegistry {
+        &self.registry
+    }
+
+    /// The next tick to simulate.
+    pub fn current_tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// The oldest tick that can be rolled back to.
+    pub fn oldest_tick(&self) -> u64 {
+        self.records.front().map_or(self.tick, |record| record.tick)
+    }
+
+    pub fn stats(&self) -> RollbackStats {
+        self.stats
+    }
+
+    /// The input the tick has been simulated with.
+    pub fn input(&self, tick: u64) -> Option<&I> {
+        self.record_index(tick)
+            .ok()
+            .map(|index| &self.records[index].input)
+    }
+
+    /// Captures the state, then simulates the current tick with the input.
+    pub fn advance(
+        &mut self,
+        world: &mut World,
+        input: I,
+        mut simulate: impl FnMut(&mut World, u64, &I),
+    ) {
+        if self.records.len() == self.capacity {
+            let record = self.records.pop_front().unwrap();
+            self.stats.buffered_bytes -= record.snapshot.bytes();
+        }
+
+        let snapshot = self.capture(world);
+        simulate(world, self.tick, &input);
+        self.records.push_back(TickRecord {
+            tick: self.tick,
+            snapshot,
+            input,
+        });
+        self.tick += 1;
+    }
+
+    /// Replaces the input of a past tick. Returns `true` if it differs from the one the tick was simulated with,
+    /// in which case the tick has to be rolled back to.
+    pub fn set_input(&mut self, tick: u64, input: I) -> Result<bool, RollbackError> {
+        let index = self.record_index(tick)?;
+        let record = &mut self.records[index];
+
+        if record.input == input {
+            return Ok(false);
+        }
+
+        record.input = input;
+        Ok(true)
+    }
+
+    /// Restores the state before `tick` and simulates forward to the current tick with the recorded inputs.
+    pub fn rollback_to(
+        &mut self,
+        world: &mut World,
+        tick: u64,
+        mut simulate: impl FnMut(&mut World, u64, &I),
+    ) -> Result<RollbackOutcome, RollbackError> {
+        let index = self.record_index(tick)?;
+        let restored_entities = self.registry.restore(world, &self.records[index].snapshot);
+
+        for index in index..self.records.len() {
+            // The later snapshots were taken from the mispredicted states.
+            if self.records[index].tick != tick {
+                let snapshot = self.capture(world);
+                let record = &mut self.records[index];
+                self.stats.buffered_bytes -= record.snapshot.bytes();
+                record.snapshot = snapshot;
+            }
+
+            let record = &self.records[index];
+            simulate(world, record.tick, &record.input);
+        }
+
+        let resimulated_ticks = self.tick - tick;
+        self.stats.rollbacks += 1;
+        self.stats.resimulated_ticks += resimulated_ticks;
+
+        Ok(RollbackOutcome {
+            resimulated_ticks,
+            restored_entities,
+        })
+    }
+
+    fn capture(&mut self, world: &World) -> WorldSnapshot {
+        let snapshot = self.registry.capture(world);
+        self.stats.last_snapshot_bytes = snapshot.bytes();
+        self.stats.max_snapshot_bytes = self.stats.max_snapshot_bytes.max(snapshot.bytes());
+        self.stats.buffered_bytes += snapshot.bytes();
+        snapshot
+    }
+
+    fn record_index(&self, tick: u64) -> Result<usize, RollbackError> {
+        if self.tick <= tick {
+            return Err(RollbackError::TickInFuture {
+                tick,
+                current: self.tick,
+            });
+        }
+
+        let oldest = self.oldest_tick();
+
+        if tick < oldest {
+            return Err(RollbackError::TickTooOld { tick, oldest });
+        }
+
+        Ok((tick - oldest) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+    };
+
+    #[derive(Debug, Clone, Hash)]
+    struct Fighter {
+        x: i32,
+        velocity: i32,
+        health: i32,
+    }
+
+    impl Component for Fighter {
+        type Storage = VecStorage<Self>;
+    }
+
+    /// Inputs of both players at a tick.
+    type Inputs = [i32; 2];
+
+    const PLAYERS: usize = 2;
+    const TICKS: u64 = 120;
+    const DELAY: u64 = 4;
+
+    fn create_world() -> (World, RollbackRegistry) {
+        let mut world = World::new();
+        let mut registry = RollbackRegistry::new();
+        registry.register::<Fighter>(&mut world);
+
+        for player in 0..PLAYERS {
+            world
+                .create_entity()
+                .with(Fighter {
+                    x: player as i32 * 100,
+                    velocity: 0,
+                    health: 100,
+                })
+                .build();
+        }
+
+        (world, registry)
+    }
+
+    fn simulate(world: &mut World, _tick: u64, inputs: &Inputs) {
+        let mut fighters = world.write_storage::<Fighter>();
+        let mut fighters = (&mut fighters).join().collect::<Vec<_>>();
+
+        for (fighter, input) in fighters.iter_mut().zip(inputs) {
+            fighter.velocity = (fighter.velocity + input).clamp(-5, 5);
+            fighter.x += fighter.velocity;
+        }
+
+        // Fighters close enough hit each other; the faster one deals more damage.
+        if (fighters[0].x - fighters[1].x).abs() < 10 {
+            let (left, right) = fighters.split_at_mut(1);
+            left[0].health -= 1 + right[0].velocity.abs();
+            right[0].health -= 1 + left[0].velocity.abs();
+        }
+    }
+
+    fn state_hash(world: &World) -> u64 {
+        let fighters = world.read_storage::<Fighter>();
+        let mut hasher = DefaultHasher::new();
+
+        for fighter in (&fighters).join() {
+            fighter.hash(&mut hasher);
+        }
+
+        hasher.finish()
+    }
+
+    /// Deterministic but irregular input of a player.
+    fn local_input(player: usize, tick: u64) -> i32 {
+        let value = (tick * 7 + player as u64 * 13) ^ (tick >> 2);
+        (value % 3) as i32 - 1
+    }
+
+    struct Peer {
+        player: usize,
+        world: World,
+        session: RollbackSession<Inputs>,
+        last_remote_input: i32,
+    }
+
+    impl Peer {
+        fn new(player: usize) -> Self {
+            let (world, registry) = create_world();
+
+            Self {
+                player,
+                world,
+                session: RollbackSession::new(registry, 8),
+                last_remote_input: 0,
+            }
+        }
+
+        fn advance(&mut self) {
+            let tick = self.session.current_tick();
+            let mut inputs = [0; PLAYERS];
+            inputs[self.player] = local_input(self.player, tick);
+            // The remote input is predicted to repeat.
+            inputs[1 - self.player] = self.last_remote_input;
+            self.session.advance(&mut self.world, inputs, simulate);
+        }
+
+        fn receive(&mut self, tick: u64, remote_input: i32) {
+            let mut inputs = *self.session.input(tick).unwrap();
+            inputs[1 - self.player] = remote_input;
+            self.last_remote_input = remote_input;
+
+            if self.session.set_input(tick, inputs).unwrap() {
+                let outcome = self
+                    .session
+                    .rollback_to(&mut self.world, tick, simulate)
+                    .unwrap();
+                assert_eq!(
+                    outcome.resimulated_ticks,
+                    self.session.current_tick() - tick
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn check_peers_converge_after_rollbacks() {
+        let mut peers = [Peer::new(0), Peer::new(1)];
+
+        for tick in 0..TICKS + DELAY {
+            if tick < TICKS {
+                for peer in &mut peers {
+                    peer.advance();
+                }
+            }
+
+            // Each peer receives the input the other sent `DELAY` ticks ago.
+            if DELAY <= tick {
+                let sent = tick - DELAY;
+
+                for peer in &mut peers {
+                    let remote = 1 - peer.player;
+                    peer.receive(sent, local_input(remote, sent));
+                }
+            }
+        }
+
+        let (mut reference, _) = create_world();
+
+        for tick in 0..TICKS {
+            simulate(
+                &mut reference,
+                tick,
+                &[local_input(0, tick), local_input(1, tick)],
+            );
+        }
+
+        assert_eq!(state_hash(&peers[0].world), state_hash(&peers[1].world));
+        assert_eq!(state_hash(&peers[0].world), state_hash(&reference));
+
+        let [peer, _] = &mut peers;
+        let stats = peer.session.stats();
+        assert!(0 < stats.rollbacks);
+        assert_eq!(
+            stats.last_snapshot_bytes,
+            PLAYERS * std::mem::size_of::<(Entity, Fighter)>()
+        );
+        assert_eq!(stats.buffered_bytes, 8 * stats.last_snapshot_bytes);
+        assert_eq!(
+            peer.session.rollback_to(&mut peer.world, 0, simulate),
+            Err(RollbackError::TickTooOld {
+                tick: 0,
+                oldest: TICKS - 8
+            })
+        );
+    }
+}
@@ -0,0 +1,318 @@
+mod task;
+mod task_handle;
+
+pub use task::*;
+pub use task_handle::*;
+
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Spreads long-running work across frames. Main-thread tasks are stepped at the end of each frame
+/// within the headroom left by the frame; background tasks run on a worker thread in parallel.
+pub struct TaskScheduler {
+    tasks: Vec<(Duration, Box<dyn ErasedTask>)>,
+    min_budget: Duration,
+    background: Option<BackgroundWorker>,
+}
+
+impl TaskScheduler {
+    /// Time slice of a background task before the worker moves on to the next one.
+    const BACKGROUND_SLICE: Duration = Duration::from_millis(4);
+
+    pub fn new() -> Self {
+        Self {
+            tasks: Vec::new(),
+            min_budget: Duration::from_millis(1),
+            background: None,
+        }
+    }
+
+    pub fn task_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Returns `true` if main-thread tasks are pending, which need frames to progress.
+    pub fn is_active(&self) -> bool {
+        !self.tasks.is_empty()
+    }
+
+    pub fn min_budget(&self) -> Duration {
+        self.min_budget
+    }
+
+    /// Sets the time given to tasks even if the frame has no headroom, so that they keep progressing.
+    pub fn set_min_budget(&mut self, min_budget: Duration) {
+        self.min_budget = min_budget;
+    }
+
+    /// Schedules a task stepped on the main thread, which is required for GPU work.
+    /// The task is given at most `budget_hint` per frame, within the frame's headroom.
+    pub fn schedule_task<T>(&mut self, budget_hint: Duration, task: T) -> TaskHandle<T::Output>
+    where
+        T: Task + 'static,
+    {
+        let control = Arc::new(TaskControl::new(0));
+        let handle = TaskHandle::new(control.clone());
+        self.tasks.push((
+            budget_hint,
+            Box::new(ScheduledTask {
+                task,
+                step_index: 0,
+                control,
+                result: handle.result_slot(),
+            }),
+        ));
+        handle
+    }
+
+    /// Schedules a CPU-only task on the background thread. It overlaps with frames and main-thread tasks.
+    pub fn schedule_background_task<T>(&mut self, task: T) -> TaskHandle<T::Output>
+    where
+        T: Task + Send + 'static,
+        T::Output: Send,
+    {
+        let control = Arc::new(TaskControl::new(0));
+        let handle = TaskHandle::new(control.clone());
+        let task = Box::new(ScheduledTask {
+            task,
+            step_index: 0,
+            control,
+            result: handle.result_slot(),
+        });
+
+        self.background
+            .get_or_insert_with(BackgroundWorker::spawn)
+            .sender
+            .send(task)
+            .unwrap();
+        handle
+    }
+
+    /// Steps the main-thread tasks within the headroom of the frame: the target frame time minus
+    /// the time the frame has already taken, but at least the minimum budget.
+    pub fn run_frame(&mut self, target_frame_time: Duration, frame_cost: Duration) {
+        let budget = target_frame_time
+            .saturating_sub(frame_cost)
+            .max(self.min_budget);
+        self.run_until(Instant::now() + budget);
+    }
+
+    /// Steps the main-thread tasks in order of priority until the deadline passes.
+    /// A step is never interrupted, so the deadline can be overshot by at most one step.
+    pub fn run_until(&mut self, deadline: Instant) {
+        self.run_until_with_clock(deadline, Instant::now);
+    }
+
+    /// [`run_until`](Self::run_until) with the time read from the given clock, e.g. a simulated one in tests.
+    fn run_until_with_clock(&mut self, deadline: Instant, clock: impl Fn() -> Instant) {
+        self.tasks
+            .retain(|(_, task)| !task.control().is_cancelled());
+        // Stable, so tasks of the same priority run in the order they were scheduled.
+        self.tasks
+            .sort_by_key(|(_, task)| std::cmp::Reverse(task.control().priority()));
+
+        let mut index = 0;
+
+        while index < self.tasks.len() {
+            let now = clock();
+
+            if deadline <= now {
+                break;
+            }
+
+            let (budget_hint, task) = &mut self.tasks[index];
+
+            if task.control().is_paused() {
+                index += 1;
+                continue;
+            }
+
+            let task_deadline = now
+                .checked_add(*budget_hint)
+                .map_or(deadline, |task_deadline| deadline.min(task_deadline));
+            let mut is_finished = false;
+
+            while !is_finished && clock() < task_deadline {
+                is_finished = task.step(false, task_deadline);
+            }
+
+            if is_finished {
+                self.tasks.remove(index);
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
+
+/// Worker thread of background tasks. It exits once the scheduler is dropped and no task is left.
+struct BackgroundWorker {
+    sender: Sender<Box<dyn ErasedTask + Send>>,
+}
+
+impl BackgroundWorker {
+    fn spawn() -> Self {
+        let (sender, receiver) = channel();
+        std::thread::Builder::new()
+            .name("task scheduler".to_owned())
+            .spawn(move || Self::run(receiver))
+            .unwrap();
+
+        Self { sender }
+    }
+
+    fn run(receiver: Receiver<Box<dyn ErasedTask + Send>>) {
+        let mut tasks: Vec<Box<dyn ErasedTask + Send>> = Vec::new();
+
+        loop {
+            if tasks.is_empty() {
+                match receiver.recv() {
+                    Ok(task) => tasks.push(task),
+                    Err(_) => return,
+                }
+            }
+
+            tasks.extend(receiver.try_iter());
+            tasks.retain(|task| !task.control().is_cancelled());
+            tasks.sort_by_key(|task| std::cmp::Reverse(task.control().priority()));
+
+            let mut is_idle = true;
+
+            tasks.retain_mut(|task| {
+                if task.control().is_paused() {
+                    return true;
+                }
+
+                is_idle = false;
+                !task.step(true, Instant::now() + TaskScheduler::BACKGROUND_SLICE)
+            });
+
+            if is_idle {
+                // Every task is paused; avoid spinning.
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+
+    struct SlowTask {
+        steps: u32,
+        step_duration: Duration,
+    }
+
+    impl Task for SlowTask {
+        type Output = u32;
+
+        fn step(&mut self, ctx: &mut TaskContext, _deadline: Instant) -> TaskStatus<u32> {
+            std::thread::sleep(self.step_duration);
+
+            if ctx.step_index + 1 == self.steps as u64 {
+                TaskStatus::Done(self.steps)
+            } else {
+                TaskStatus::InProgress((ctx.step_index + 1) as f32 / self.steps as f32)
+            }
+        }
+    }
+
+    /// A task whose steps take time on a simulated clock.
+    struct ClockedTask {
+        steps: u32,
+        step_duration: Duration,
+        clock: Rc<Cell<Instant>>,
+    }
+
+    impl Task for ClockedTask {
+        type Output = u32;
+
+        fn step(&mut self, ctx: &mut TaskContext, _deadline: Instant) -> TaskStatus<u32> {
+            self.clock.set(self.clock.get() + self.step_duration);
+
+            if ctx.step_index + 1 == self.steps as u64 {
+                TaskStatus::Done(self.steps)
+            } else {
+                TaskStatus::InProgress((ctx.step_index + 1) as f32 / self.steps as f32)
+            }
+        }
+    }
+
+    #[test]
+    fn check_slow_task_overshoots_by_at_most_one_step() {
+        let step_duration = Duration::from_millis(3);
+        let budget = Duration::from_millis(8);
+        let clock = Rc::new(Cell::new(Instant::now()));
+        let mut scheduler = TaskScheduler::new();
+        let handle = scheduler.schedule_task(
+            Duration::MAX,
+            ClockedTask {
+                steps: 100,
+                step_duration,
+                clock: clock.clone(),
+            },
+        );
+
+        for _ in 0..5 {
+            let start = clock.get();
+            scheduler.run_until_with_clock(start + budget, || clock.get());
+
+            // Steps at 0, 3 and 6 ms; the last one ends past the deadline, and no step starts after it.
+            assert_eq!(clock.get() - start, step_duration * 3);
+        }
+
+        assert_eq!(handle.progress(), 15.0 / 100.0);
+        assert!(!handle.is_done());
+    }
+
+    #[test]
+    fn check_paused_and_cancelled_tasks_are_not_stepped() {
+        let mut scheduler = TaskScheduler::new();
+        let task = || SlowTask {
+            steps: 1,
+            step_duration: Duration::ZERO,
+        };
+        let paused = scheduler.schedule_task(Duration::MAX, task());
+        let cancelled = scheduler.schedule_task(Duration::MAX, task());
+        paused.pause();
+        cancelled.cancel();
+
+        // Time stands still, so every task that is not paused finishes within the deadline.
+        let now = Instant::now();
+        let deadline = now + Duration::from_millis(5);
+        scheduler.run_until_with_clock(deadline, || now);
+        assert_eq!(scheduler.task_count(), 1);
+        assert_eq!(paused.poll(), None);
+        assert_eq!(cancelled.poll(), None);
+
+        paused.resume();
+        scheduler.run_until_with_clock(deadline, || now);
+        assert_eq!(paused.poll(), Some(Ok(1)));
+        assert_eq!(scheduler.task_count(), 0);
+    }
+
+    #[test]
+    fn check_background_task_delivers_result() {
+        let mut scheduler = TaskScheduler::new();
+        let handle = scheduler.schedule_background_task(SlowTask {
+            steps: 3,
+            step_duration: Duration::from_millis(1),
+        });
+
+        let start = Instant::now();
+
+        while !handle.is_done() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(handle.poll(), Some(Ok(3)));
+    }
+}
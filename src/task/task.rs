@@ -0,0 +1,27 @@
+use std::time::Instant;
+
+/// Result of a single step of a [`Task`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskStatus<T> {
+    /// The task needs more steps. Holds the progress in range [0, 1].
+    InProgress(f32),
+    Done(T),
+    Failed(String),
+}
+
+/// Information given to a task on each step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskContext {
+    /// Number of times the task has been stepped before this step.
+    pub step_index: u64,
+    /// Whether the task runs on the background thread.
+    pub is_background: bool,
+}
+
+/// A long-running job split into steps. Each step should return soon after the deadline passes;
+/// the scheduler never interrupts a step, so a step overshooting the deadline delays the frame.
+pub trait Task {
+    type Output: 'static;
+
+    fn step(&mut self, ctx: &mut TaskContext, deadline: Instant) -> TaskStatus<Self::Output>;
+}
@@ -0,0 +1,153 @@
+use super::TaskStatus;
+use parking_lot::Mutex;
+use std::sync::{
+    atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering},
+    Arc,
+};
+
+/// State of a task shared between the scheduler and its handles.
+#[derive(Debug, Default)]
+pub(crate) struct TaskControl {
+    priority: AtomicI32,
+    progress: AtomicU32,
+    is_paused: AtomicBool,
+    is_cancelled: AtomicBool,
+}
+
+impl TaskControl {
+    pub fn new(priority: i32) -> Self {
+        Self {
+            priority: AtomicI32::new(priority),
+            ..Default::default()
+        }
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority.load(Ordering::Relaxed)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused.load(Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_progress(&self, progress: f32) {
+        self.progress.store(progress.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Handle to a scheduled task. The result can be polled once the task finishes.
+pub struct TaskHandle<T> {
+    control: Arc<TaskControl>,
+    result: Arc<Mutex<Option<Result<T, String>>>>,
+}
+
+impl<T> TaskHandle<T> {
+    pub(crate) fn new(control: Arc<TaskControl>) -> Self {
+        Self {
+            control,
+            result: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub(crate) fn result_slot(&self) -> Arc<Mutex<Option<Result<T, String>>>> {
+        self.result.clone()
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.control.priority()
+    }
+
+    /// Tasks with higher priority are stepped first in each frame.
+    pub fn set_priority(&self, priority: i32) {
+        self.control.priority.store(priority, Ordering::Relaxed);
+    }
+
+    pub fn progress(&self) -> f32 {
+        f32::from_bits(self.control.progress.load(Ordering::Relaxed))
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.control.is_paused()
+    }
+
+    pub fn pause(&self) {
+        self.control.is_paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.control.is_paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Cancels the task. It is dropped before its next step.
+    pub fn cancel(&self) {
+        self.control.is_cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.control.is_cancelled()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.result.lock().is_some()
+    }
+
+    /// Takes the result of the task if it has finished.
+    pub fn poll(&self) -> Option<Result<T, String>> {
+        self.result.lock().take()
+    }
+}
+
+impl<T> Clone for TaskHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            control: self.control.clone(),
+            result: self.result.clone(),
+        }
+    }
+}
+
+/// Type-erased task, stepped by the scheduler.
+pub(crate) trait ErasedTask {
+    fn control(&self) -> &TaskControl;
+
+    /// Steps the task once. Returns `true` if it has finished.
+    fn step(&mut self, is_background: bool, deadline: std::time::Instant) -> bool;
+}
+
+pub(crate) struct ScheduledTask<T: super::Task> {
+    pub task: T,
+    pub step_index: u64,
+    pub control: Arc<TaskControl>,
+    pub result: Arc<Mutex<Option<Result<T::Output, String>>>>,
+}
+
+impl<T: super::Task> ErasedTask for ScheduledTask<T> {
+    fn control(&self) -> &TaskControl {
+        &self.control
+    }
+
+    fn step(&mut self, is_background: bool, deadline: std::time::Instant) -> bool {
+        let mut ctx = super::TaskContext {
+            step_index: self.step_index,
+            is_background,
+        };
+        self.step_index += 1;
+
+        let result = match self.task.step(&mut ctx, deadline) {
+            TaskStatus::InProgress(progress) => {
+                self.control.set_progress(progress);
+                return false;
+            }
+            TaskStatus::Done(output) => Ok(output),
+            TaskStatus::Failed(err) => Err(err),
+        };
+
+        self.control.set_progress(1.0);
+        *self.result.lock() = Some(result);
+        true
+    }
+}
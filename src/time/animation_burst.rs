@@ -0,0 +1,179 @@
+use std::time::{Duration, Instant};
+
+/// Something that needs continuous frames while it is active, e.g. a running tween.
+pub trait AnimationSource {
+    fn is_active(&self) -> bool;
+}
+
+impl<F> AnimationSource for F
+where
+    F: Fn() -> bool,
+{
+    fn is_active(&self) -> bool {
+        self()
+    }
+}
+
+/// Drives continuous frames in the `Wait` loop mode while any animation source is active,
+/// and lets the loop go back to waiting for events once the last one finishes.
+pub struct AnimationBurst {
+    sources: Vec<Box<dyn AnimationSource>>,
+    frame_interval: Duration,
+    max_delta_time: Duration,
+    keep_alive_until: Option<Instant>,
+    next_frame: Option<Instant>,
+}
+
+impl AnimationBurst {
+    pub fn new(frame_interval: Duration) -> Self {
+        Self {
+            sources: Vec::new(),
+            frame_interval,
+            max_delta_time: Duration::from_millis(100),
+            keep_alive_until: None,
+            next_frame: None,
+        }
+    }
+
+    pub fn frame_interval(&self) -> Duration {
+        self.frame_interval
+    }
+
+    /// Sets the interval between the frames of a burst, usually the target frame interval.
+    pub fn set_frame_interval(&mut self, frame_interval: Duration) {
+        self.frame_interval = frame_interval;
+    }
+
+    pub fn max_delta_time(&self) -> Duration {
+        self.max_delta_time
+    }
+
+    /// Sets the upper bound of the delta time of burst frames. The first frame of a burst comes
+    /// after an arbitrarily long wait, which must not make the animations jump.
+    pub fn set_max_delta_time(&mut self, max_delta_time: Duration) {
+        self.max_delta_time = max_delta_time;
+    }
+
+    pub fn add_source(&mut self, source: impl AnimationSource + 'static) {
+        self.sources.push(Box::new(source));
+    }
+
+    /// Requests continuous frames until the deadline, for animations that are not tracked by a source.
+    pub fn keep_alive_until(&mut self, deadline: Instant) {
+        self.keep_alive_until = Some(match self.keep_alive_until {
+            Some(current) => current.max(deadline),
+            None => deadline,
+        });
+    }
+
+    /// Returns `true` while the engine drives frames on its own.
+    pub fn is_bursting(&self) -> bool {
+        self.next_frame.is_some()
+    }
+
+    /// Time at which the next frame of the burst is due.
+    pub fn next_frame(&self) -> Option<Instant> {
+        self.next_frame
+    }
+
+    pub fn is_active(&self, now: Instant) -> bool {
+        self.keep_alive_until.is_some_and(|deadline| now < deadline)
+            || self.sources.iter().any(|source| source.is_active())
+    }
+
+    /// Returns `true` if a frame should be drawn now without waiting for an event.
+    pub fn should_wake(&self, now: Instant, other_active: bool) -> bool {
+        match self.next_frame {
+            Some(next_frame) => next_frame <= now,
+            None => other_active || self.is_active(now),
+        }
+    }
+
+    /// Updates the burst after a frame, returning when the next frame is due.
+    /// `other_active` tells whether sources owned elsewhere, e.g. the task scheduler, are active.
+    /// Returns `None` once nothing is active, which ends the burst.
+    pub fn end_frame(&mut self, now: Instant, other_active: bool) -> Option<Instant> {
+        if self
+            .keep_alive_until
+            .is_some_and(|deadline| deadline <= now)
+        {
+            self.keep_alive_until = None;
+        }
+
+        self.next_frame = if other_active || self.is_active(now) {
+            Some(now + self.frame_interval)
+        } else {
+            None
+        };
+        self.next_frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+
+    #[test]
+    fn check_burst_stops_within_one_frame() {
+        let frame_interval = Duration::from_millis(16);
+        let mut burst = AnimationBurst::new(frame_interval);
+
+        // A 200 ms tween on a simulated clock.
+        let start = Instant::now();
+        let clock = Rc::new(Cell::new(start));
+        let tween_end = start + Duration::from_millis(200);
+        burst.add_source({
+            let clock = clock.clone();
+            move || clock.get() < tween_end
+        });
+
+        // Idle before: nothing wakes the loop.
+        assert!(!burst.is_bursting());
+
+        let mut frames = 0;
+        let mut now = start;
+        assert!(burst.should_wake(now, false));
+
+        while let Some(next_frame) = burst.end_frame(now, false) {
+            frames += 1;
+            assert!(frames < 100, "the burst never ended");
+            assert!(burst.is_bursting());
+            assert_eq!(next_frame, now + frame_interval);
+
+            now = next_frame;
+            clock.set(now);
+        }
+
+        // The first frame at or after the end of the tween ends the burst.
+        assert!(tween_end <= now);
+        assert!(now < tween_end + frame_interval);
+        assert!(!burst.is_bursting());
+        assert!(!burst.should_wake(now + Duration::from_secs(10), false));
+    }
+
+    #[test]
+    fn check_burst_keep_alive_and_other_sources() {
+        let start = Instant::now();
+        let mut burst = AnimationBurst::new(Duration::from_millis(10));
+
+        burst.keep_alive_until(start + Duration::from_millis(25));
+        assert!(burst.end_frame(start, false).is_some());
+        assert!(!burst.should_wake(start + Duration::from_millis(5), false));
+        assert!(burst.should_wake(start + Duration::from_millis(10), false));
+        assert!(burst
+            .end_frame(start + Duration::from_millis(20), false)
+            .is_some());
+        assert!(burst
+            .end_frame(start + Duration::from_millis(30), false)
+            .is_none());
+
+        // Sources owned elsewhere keep the burst going.
+        assert!(burst
+            .end_frame(start + Duration::from_millis(40), true)
+            .is_some());
+        assert!(burst
+            .end_frame(start + Duration::from_millis(50), false)
+            .is_none());
+    }
+}
@@ -0,0 +1,120 @@
+use std::{collections::VecDeque, time::Duration};
+
+/// Frames the minimum and maximum frame times are taken over.
+pub const FRAME_STATS_WINDOW: usize = 120;
+
+/// Weight of the newest frame in the smoothed frame rate.
+const FPS_SMOOTHING: f32 = 0.1;
+
+/// Counters the render system reports for a frame. See [`TimeManager::report_render_counters`](super::TimeManager::report_render_counters).
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FrameRenderCounters {
+    pub draw_calls: u32,
+    pub pipeline_cache_hits: u32,
+    pub pipeline_cache_misses: u32,
+    pub frame_buffer_bytes: u64,
+    pub gpu_ms: Option<f32>,
+}
+
+/// Timings of the recent frames and counters of the last rendered one.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    /// Unscaled time since the previous frame.
+    pub frame_ms: f32,
+    /// Frame rate smoothed with an exponential moving average.
+    pub fps: f32,
+    /// Shortest frame time over the last [`FRAME_STATS_WINDOW`] frames.
+    pub min_frame_ms: f32,
+    /// Longest frame time over the last [`FRAME_STATS_WINDOW`] frames.
+    pub max_frame_ms: f32,
+    /// Draw commands recorded by the camera passes.
+    pub draw_calls: u32,
+    /// Pipelines found in the pipeline cache.
+    pub pipeline_cache_hits: u32,
+    /// Pipelines created because the cache had none matching.
+    pub pipeline_cache_misses: u32,
+    /// Bytes allocated from the [`FrameBufferAllocator`](crate::gfx::FrameBufferAllocator).
+    pub frame_buffer_bytes: u64,
+    /// GPU time of a recent frame, lagging a few frames behind. `None` unless GPU timing is on and supported;
+    /// see [`RenderManager::set_gpu_timing`](crate::gfx::RenderManager::set_gpu_timing).
+    pub gpu_ms: Option<f32>,
+}
+
+#[derive(Debug, Default)]
+pub struct FrameStatsRecorder {
+    stats: FrameStats,
+    frame_times: VecDeque<f32>,
+}
+
+impl FrameStatsRecorder {
+    pub fn new() -> Self {
+        Self {
+            stats: FrameStats::default(),
+            frame_times: VecDeque::with_capacity(FRAME_STATS_WINDOW),
+        }
+    }
+
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    pub fn record_frame(&mut self, frame_time: Duration) {
+        let frame_ms = frame_time.as_secs_f32() * 1000.0;
+
+        if self.frame_times.len() == FRAME_STATS_WINDOW {
+            self.frame_times.pop_front();
+        }
+
+        self.frame_times.push_back(frame_ms);
+
+        let stats = &mut self.stats;
+        stats.frame_ms = frame_ms;
+
+        if 0.0 < frame_ms {
+            let fps = 1000.0 / frame_ms;
+            stats.fps = if stats.fps == 0.0 {
+                fps
+            } else {
+                stats.fps + (fps - stats.fps) * FPS_SMOOTHING
+            };
+        }
+
+        stats.min_frame_ms = self.frame_times.iter().copied().fold(f32::MAX, f32::min);
+        stats.max_frame_ms = self.frame_times.iter().copied().fold(0.0, f32::max);
+    }
+
+    pub fn record_render(&mut self, counters: FrameRenderCounters) {
+        let stats = &mut self.stats;
+        stats.draw_calls = counters.draw_calls;
+        stats.pipeline_cache_hits = counters.pipeline_cache_hits;
+        stats.pipeline_cache_misses = counters.pipeline_cache_misses;
+        stats.frame_buffer_bytes = counters.frame_buffer_bytes;
+        stats.gpu_ms = counters.gpu_ms;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_fps_is_smoothed_and_extremes_are_windowed() {
+        let mut recorder = FrameStatsRecorder::new();
+        recorder.record_frame(Duration::from_millis(10));
+        assert_eq!(recorder.stats().fps, 100.0);
+
+        // A single hitch moves the smoothed rate only by a fraction.
+        recorder.record_frame(Duration::from_millis(50));
+        assert_eq!(recorder.stats().fps, 100.0 - 80.0 * FPS_SMOOTHING);
+        assert_eq!(recorder.stats().max_frame_ms, 50.0);
+        assert_eq!(recorder.stats().min_frame_ms, 10.0);
+
+        for _ in 0..FRAME_STATS_WINDOW {
+            recorder.record_frame(Duration::from_millis(20));
+        }
+
+        assert_eq!(recorder.stats().max_frame_ms, 20.0);
+        assert_eq!(recorder.stats().min_frame_ms, 20.0);
+        assert!((recorder.stats().fps - 50.0).abs() < 0.01);
+    }
+}
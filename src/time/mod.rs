@@ -1,3 +1,9 @@
+mod animation_burst;
+mod frame_stats;
+
+pub use animation_burst::*;
+pub use frame_stats::*;
+
 use std::time::{Duration, Instant};
 
 pub struct TimeManager {
@@ -6,9 +12,16 @@ pub struct TimeManager {
     base_time: Duration,
     delta_time: Duration,
     unscaled_delta_time: Duration,
+    max_delta_time: Duration,
+    frame_index: u64,
     initial_time: Instant,
     last_frame_time: Instant,
     last_scale_updated_time: Instant,
+    fixed_delta_time: Duration,
+    max_fixed_steps: u32,
+    fixed_steps: u32,
+    fixed_accumulator: Duration,
+    frame_stats: FrameStatsRecorder,
 }
 
 impl TimeManager {
@@ -20,9 +33,16 @@ impl TimeManager {
             base_time: Duration::from_secs(0),
             delta_time: Duration::from_secs(0),
             unscaled_delta_time: Duration::from_secs(0),
+            max_delta_time: Duration::from_millis(250),
+            frame_index: 0,
             initial_time: now,
             last_frame_time: now,
             last_scale_updated_time: now,
+            fixed_delta_time: Duration::from_millis(20),
+            max_fixed_steps: 5,
+            fixed_steps: 0,
+            fixed_accumulator: Duration::from_secs(0),
+            frame_stats: FrameStatsRecorder::new(),
         }
     }
 
@@ -30,23 +50,104 @@ impl TimeManager {
         self.time_scale
     }
 
+    /// Scaled time since the engine started.
     pub fn time(&self) -> Duration {
         self.time + self.base_time
     }
 
-    pub fn unscaled_time(&self) -> Duration {
+    /// Unscaled time since the engine started, as of the current frame.
+    pub fn elapsed(&self) -> Duration {
         self.last_frame_time.duration_since(self.initial_time)
     }
 
-    pub fn delta_time(&self) -> Duration {
+    /// Scaled time since the last frame, in seconds. Clamped to [`max_delta_time`](Self::max_delta_time).
+    pub fn delta_time(&self) -> f32 {
+        self.delta_time.as_secs_f32()
+    }
+
+    pub fn delta_time_f64(&self) -> f64 {
+        self.delta_time.as_secs_f64()
+    }
+
+    pub fn delta_duration(&self) -> Duration {
         self.delta_time
     }
 
-    pub fn unscaled_delta_time(&self) -> Duration {
+    /// Time since the last frame, in seconds, regardless of the time scale and the clamp.
+    pub fn unscaled_delta_time(&self) -> f32 {
+        self.unscaled_delta_time.as_secs_f32()
+    }
+
+    pub fn unscaled_delta_time_f64(&self) -> f64 {
+        self.unscaled_delta_time.as_secs_f64()
+    }
+
+    pub fn unscaled_delta_duration(&self) -> Duration {
         self.unscaled_delta_time
     }
 
+    /// Upper bound of the scaled delta time, so that a long hitch does not make gameplay code jump. 250ms by default.
+    pub fn max_delta_time(&self) -> Duration {
+        self.max_delta_time
+    }
+
+    /// Number of frames advanced so far.
+    pub fn frame_index(&self) -> u64 {
+        self.frame_index
+    }
+
+    /// Timings of the recent frames and the counters the render system reported for the last one.
+    pub fn frame_stats(&self) -> &FrameStats {
+        self.frame_stats.stats()
+    }
+
+    /// Called by the render system once a frame has been presented.
+    pub fn report_render_counters(&mut self, counters: FrameRenderCounters) {
+        self.frame_stats.record_render(counters);
+    }
+
+    pub fn set_max_delta_time(&mut self, max_delta_time: Duration) {
+        self.max_delta_time = max_delta_time;
+    }
+
+    /// Scaled time between two fixed updates. 20ms by default.
+    pub fn fixed_delta_time(&self) -> Duration {
+        self.fixed_delta_time
+    }
+
+    /// Number of fixed updates to run this frame.
+    pub fn fixed_steps(&self) -> u32 {
+        self.fixed_steps
+    }
+
+    /// How far the time is between the last fixed update and the next one, in range [0, 1).
+    /// Render code blends the last two fixed states by it.
+    pub fn fixed_alpha(&self) -> f32 {
+        (self.fixed_accumulator.as_secs_f64() / self.fixed_delta_time.as_secs_f64()) as f32
+    }
+
+    /// Sets how many fixed updates run per second of scaled time.
+    pub fn set_fixed_update_rate(&mut self, rate: f64) {
+        assert!(
+            0.0 < rate && rate.is_finite(),
+            "the fixed update rate must be positive"
+        );
+        self.fixed_delta_time = Duration::from_secs_f64(1.0 / rate);
+        self.fixed_accumulator = Duration::from_secs(0);
+    }
+
+    /// Caps the fixed updates per frame; the time they could not catch up on is dropped,
+    /// so that a long frame, e.g. while the window is dragged, does not make the next frames longer. 5 by default.
+    pub fn set_max_fixed_steps(&mut self, max_fixed_steps: u32) {
+        self.max_fixed_steps = max_fixed_steps;
+    }
+
+    /// Multiplies the scaled times, e.g. 0.5 for slow motion or 0 to pause.
     pub fn set_time_scale(&mut self, time_scale: f64) {
+        assert!(
+            0.0 <= time_scale && time_scale.is_finite(),
+            "the time scale must not be negative"
+        );
         self.time_scale = time_scale;
         self.base_time += self.time;
         self.time = Duration::from_secs(0);
@@ -54,14 +155,148 @@ impl TimeManager {
     }
 
     pub fn update(&mut self) {
-        let now = Instant::now();
+        self.advance(None);
+    }
+
+    /// Same as [`update`](Self::update), but the delta time is clamped, e.g. after the loop waited for events.
+    pub fn update_clamped(&mut self, max_delta_time: Duration) {
+        self.advance(Some(max_delta_time));
+    }
+
+    fn advance(&mut self, max_delta_time: Option<Duration>) {
+        self.advance_to(Instant::now(), max_delta_time);
+    }
+
+    fn advance_to(&mut self, now: Instant, max_delta_time: Option<Duration>) {
+        let mut unscaled_delta_time = now.duration_since(self.last_frame_time);
+
+        if let Some(max_delta_time) = max_delta_time {
+            unscaled_delta_time = unscaled_delta_time.min(max_delta_time);
+        }
+
         self.time = now
             .duration_since(self.last_scale_updated_time)
             .mul_f64(self.time_scale);
-        self.delta_time = now
-            .duration_since(self.last_frame_time)
-            .mul_f64(self.time_scale);
-        self.unscaled_delta_time = now.duration_since(self.last_frame_time);
+        self.delta_time = unscaled_delta_time
+            .mul_f64(self.time_scale)
+            .min(self.max_delta_time);
+        self.unscaled_delta_time = unscaled_delta_time;
         self.last_frame_time = now;
+        self.frame_index += 1;
+        self.frame_stats.record_frame(unscaled_delta_time);
+        self.accumulate_fixed(self.delta_time);
+    }
+
+    fn accumulate_fixed(&mut self, delta_time: Duration) {
+        self.fixed_accumulator += delta_time;
+
+        let due = self.fixed_accumulator.as_nanos() / self.fixed_delta_time.as_nanos();
+        self.fixed_steps = due.min(self.max_fixed_steps as u128) as u32;
+        self.fixed_accumulator -= self.fixed_delta_time * self.fixed_steps;
+
+        if self.fixed_delta_time <= self.fixed_accumulator {
+            self.fixed_accumulator = Duration::from_nanos(
+                (self.fixed_accumulator.as_nanos() % self.fixed_delta_time.as_nanos()) as u64,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs_system::system_registry::SystemRegistry;
+    use specs::{System, World, WorldExt, Write};
+
+    #[derive(Default)]
+    struct Ticks(u32);
+
+    struct Tick;
+
+    impl<'a> System<'a> for Tick {
+        type SystemData = Write<'a, Ticks>;
+
+        fn run(&mut self, mut ticks: Self::SystemData) {
+            ticks.0 += 1;
+        }
+    }
+
+    /// Runs a second of frames at `fps` and returns how many times the fixed system ticked.
+    fn ticks_in_a_second(fps: u32) -> u32 {
+        let mut time_mgr = TimeManager::new();
+        time_mgr.set_fixed_update_rate(50.0);
+        let mut world = World::new();
+        let mut registry = SystemRegistry::new();
+        registry.register(0, Tick);
+
+        for _ in 0..fps {
+            time_mgr.accumulate_fixed(Duration::from_secs(1) / fps);
+            assert!(time_mgr.fixed_steps() <= 5);
+            assert!((0.0..1.0).contains(&time_mgr.fixed_alpha()));
+
+            let mut frame = registry.begin_frame(&mut world);
+
+            for _ in 0..time_mgr.fixed_steps() {
+                frame.run(&world);
+            }
+
+            registry.end_frame(frame);
+        }
+
+        let ticks = world.read_resource::<Ticks>();
+        ticks.0
+    }
+
+    #[test]
+    fn check_fixed_updates_tick_regardless_of_frame_rate() {
+        assert_eq!(
+            TimeManager::new().fixed_delta_time(),
+            Duration::from_millis(20)
+        );
+
+        for fps in [30, 60, 144, 240] {
+            let ticks = ticks_in_a_second(fps);
+            assert!((49..=50).contains(&ticks), "{} ticks at {} fps", ticks, fps);
+        }
+    }
+
+    #[test]
+    fn check_delta_time_is_scaled_and_clamped() {
+        let mut time_mgr = TimeManager::new();
+        let start = time_mgr.last_frame_time;
+
+        // A hitch of a second.
+        time_mgr.advance_to(start + Duration::from_secs(1), None);
+        assert_eq!(time_mgr.delta_time(), 0.25);
+        assert_eq!(time_mgr.unscaled_delta_time(), 1.0);
+        assert_eq!(time_mgr.elapsed(), Duration::from_secs(1));
+        assert_eq!(time_mgr.frame_index(), 1);
+
+        time_mgr.set_time_scale(0.5);
+        time_mgr.advance_to(start + Duration::from_millis(1100), None);
+        assert_eq!(time_mgr.delta_duration(), Duration::from_millis(50));
+        assert_eq!(
+            time_mgr.unscaled_delta_duration(),
+            Duration::from_millis(100)
+        );
+
+        time_mgr.set_time_scale(0.0);
+        time_mgr.advance_to(start + Duration::from_millis(1200), None);
+        assert_eq!(time_mgr.delta_time(), 0.0);
+        assert_eq!(time_mgr.frame_index(), 3);
+    }
+
+    #[test]
+    fn check_fixed_steps_are_capped() {
+        let mut time_mgr = TimeManager::new();
+        time_mgr.set_max_fixed_steps(3);
+
+        // A frame of a second, e.g. after the window was dragged.
+        time_mgr.accumulate_fixed(Duration::from_secs(1));
+        assert_eq!(time_mgr.fixed_steps(), 3);
+        assert!(time_mgr.fixed_alpha() < 1.0);
+
+        time_mgr.accumulate_fixed(Duration::from_millis(10));
+        assert!(time_mgr.fixed_steps() <= 1);
     }
 }
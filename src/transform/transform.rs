@@ -2,16 +2,20 @@ use crate::{
     math::{Mat4, Quat, Vec3, Vec4},
     object::{ObjectComponent, ObjectHandle, ObjectHierarchy, ObjectId},
 };
-use specs::{prelude::*, Component};
+use specs::prelude::*;
 
-#[derive(Debug, Clone, Component)]
-#[storage(VecStorage)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Transform {
     pub position: Vec3,
     pub rotation: Quat,
     pub scale: Vec3,
 }
 
+/// Flagged, so that changes can be observed through [`ChangeReader`](crate::world_ext::ChangeReader).
+impl Component for Transform {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
+
 impl Transform {
     pub fn new() -> Self {
         Default::default()
@@ -0,0 +1,191 @@
+use bumpalo::{collections::Vec as BumpVec, Bump};
+
+/// Bytes each frame arena reserves up front. See [`FrameArenaStats::high_water_bytes`] for tuning it.
+pub const DEFAULT_FRAME_ARENA_BYTES: usize = 1024 * 1024;
+
+/// Written over the memory of an arena when it is reset in debug builds, so that data read past its frame stands out.
+pub const FRAME_ARENA_POISON: u8 = 0xDD;
+
+/// A bump allocator for temporary data of one frame.
+///
+/// Allocating is a pointer bump, and the memory is only given back when the whole arena is reset. Vectors drop their
+/// elements as usual, but values allocated directly are never dropped. When a chunk is exhausted, a larger one is
+/// added instead of failing.
+pub struct FrameArena {
+    bump: Bump,
+}
+
+impl FrameArena {
+    pub fn with_capacity(bytes: usize) -> Self {
+        Self {
+            bump: Bump::with_capacity(bytes),
+        }
+    }
+
+    /// Returns an empty vector in the arena. Growing it past the capacity leaves the old memory behind until the reset.
+    pub fn alloc_vec<T>(&self, capacity: usize) -> BumpVec<'_, T> {
+        BumpVec::with_capacity_in(capacity, &self.bump)
+    }
+
+    pub fn alloc_str(&self, s: &str) -> &str {
+        self.bump.alloc_str(s)
+    }
+
+    pub fn alloc_slice_copy<T: Copy>(&self, src: &[T]) -> &[T] {
+        self.bump.alloc_slice_copy(src)
+    }
+
+    /// Bytes of the chunks the arena holds, used or not.
+    pub fn reserved_bytes(&self) -> usize {
+        self.bump.allocated_bytes()
+    }
+
+    /// Bytes allocated since the last reset.
+    pub fn used_bytes(&mut self) -> usize {
+        self.bump
+            .iter_allocated_chunks()
+            .map(|chunk| chunk.len())
+            .sum()
+    }
+
+    /// Frees every allocation at once, keeping the largest chunk for the next frame.
+    fn reset(&mut self) {
+        #[cfg(debug_assertions)]
+        // SAFETY: No allocation can be borrowed while the arena is borrowed mutably, and the chunks are writable.
+        unsafe {
+            for (ptr, len) in self.bump.iter_allocated_chunks_raw() {
+                ptr.write_bytes(FRAME_ARENA_POISON, len);
+            }
+        }
+
+        self.bump.reset();
+    }
+}
+
+/// Usage of the frame arenas.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameArenaStats {
+    /// Bytes allocated during the last finished frame.
+    pub last_frame_bytes: usize,
+    /// Most bytes allocated during a frame so far. Reserving this much avoids growing the arenas.
+    pub high_water_bytes: usize,
+    /// Bytes held by both arenas.
+    pub reserved_bytes: usize,
+}
+
+/// Two [`FrameArena`]s used in turn, so that the data of frame N-1 stays valid during frame N, e.g. for readback
+/// callbacks that complete a frame late. Each arena is reset when the frame after next begins.
+pub struct FrameArenas {
+    arenas: [FrameArena; 2],
+    current: usize,
+    last_frame_bytes: usize,
+    high_water_bytes: usize,
+}
+
+impl FrameArenas {
+    /// Creates the arenas, each reserving `bytes` up front.
+    pub fn new(bytes: usize) -> Self {
+        Self {
+            arenas: [
+                FrameArena::with_capacity(bytes),
+                FrameArena::with_capacity(bytes),
+            ],
+            current: 0,
+            last_frame_bytes: 0,
+            high_water_bytes: 0,
+        }
+    }
+
+    /// The arena of the frame in progress.
+    pub fn current(&self) -> &FrameArena {
+        &self.arenas[self.current]
+    }
+
+    /// The arena of the frame before, still holding its data.
+    pub fn previous(&self) -> &FrameArena {
+        &self.arenas[self.current ^ 1]
+    }
+
+    pub fn stats(&self) -> FrameArenaStats {
+        FrameArenaStats {
+            last_frame_bytes: self.last_frame_bytes,
+            high_water_bytes: self.high_water_bytes,
+            reserved_bytes: self.arenas.iter().map(FrameArena::reserved_bytes).sum(),
+        }
+    }
+
+    /// Finishes the frame in progress and starts the next one in the other arena, resetting the data it held.
+    pub fn begin_frame(&mut self) {
+        self.last_frame_bytes = self.arenas[self.current].used_bytes();
+        self.high_water_bytes = usize::max(self.high_water_bytes, self.last_frame_bytes);
+
+        self.current ^= 1;
+        self.arenas[self.current].reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_previous_frame_data_survives_one_frame() {
+        let mut arenas = FrameArenas::new(1024);
+
+        let ptr = arenas.current().alloc_slice_copy(&[7u32; 4]).as_ptr();
+        arenas.begin_frame();
+
+        // SAFETY: The slice lives in the previous arena, which is only reset by the next frame.
+        assert_eq!(unsafe { std::slice::from_raw_parts(ptr, 4) }, &[7u32; 4]);
+        assert!(arenas.previous().reserved_bytes() != 0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn check_reset_memory_is_poisoned() {
+        let mut arenas = FrameArenas::new(1024);
+
+        let ptr = arenas.current().alloc_slice_copy(&[0u8; 16]).as_ptr();
+        arenas.begin_frame();
+        arenas.begin_frame();
+
+        // SAFETY: The chunk is kept by the reset, so the memory is still owned by the arena.
+        assert_eq!(
+            unsafe { std::slice::from_raw_parts(ptr, 16) },
+            &[FRAME_ARENA_POISON; 16]
+        );
+    }
+
+    #[test]
+    fn check_arena_grows_past_its_reservation() {
+        let mut arenas = FrameArenas::new(64);
+
+        let mut values = arenas.current().alloc_vec(0);
+        values.extend(0..10_000u32);
+        // Dropping the vector would give its memory back, since it is the last allocation.
+        let values = values.into_bump_slice();
+        assert_eq!(values.iter().sum::<u32>(), (0..10_000).sum::<u32>());
+
+        let reserved = arenas.stats().reserved_bytes;
+        assert!(64 * 2 < reserved);
+
+        arenas.begin_frame();
+        let stats = arenas.stats();
+        assert!(10_000 * 4 <= stats.last_frame_bytes);
+        assert_eq!(stats.high_water_bytes, stats.last_frame_bytes);
+    }
+
+    #[test]
+    fn check_high_water_mark_keeps_the_largest_frame() {
+        let mut arenas = FrameArenas::new(1024);
+
+        arenas.current().alloc_str(&"x".repeat(512));
+        arenas.begin_frame();
+        arenas.current().alloc_str("x");
+        arenas.begin_frame();
+
+        let stats = arenas.stats();
+        assert_eq!(stats.last_frame_bytes, 1);
+        assert_eq!(stats.high_water_bytes, 512);
+    }
+}
@@ -1,3 +1,7 @@
+mod frame_arena;
+mod rate_limiter;
 mod slot_map;
 
+pub use frame_arena::*;
+pub use rate_limiter::*;
 pub use slot_map::*;
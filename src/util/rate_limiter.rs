@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+
+/// Lets an event through at most once per interval, counting the ones suppressed in between.
+/// Useful for warnings that could otherwise flood the log every frame.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    interval: Duration,
+    last: Option<Instant>,
+    suppressed: u32,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: None,
+            suppressed: 0,
+        }
+    }
+
+    /// Returns the number of events suppressed since the last one let through, or `None` if this one is suppressed.
+    pub fn allow(&mut self, now: Instant) -> Option<u32> {
+        match self.last {
+            Some(last) if now.saturating_duration_since(last) < self.interval => {
+                self.suppressed += 1;
+                None
+            }
+            _ => {
+                self.last = Some(now);
+                Some(std::mem::take(&mut self.suppressed))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_rate_limiter_counts_suppressed() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(Duration::from_secs(1));
+
+        assert_eq!(limiter.allow(start), Some(0));
+        assert_eq!(limiter.allow(start + Duration::from_millis(100)), None);
+        assert_eq!(limiter.allow(start + Duration::from_millis(900)), None);
+        assert_eq!(limiter.allow(start + Duration::from_secs(1)), Some(2));
+        assert_eq!(limiter.allow(start + Duration::from_millis(1500)), None);
+    }
+}
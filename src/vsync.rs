@@ -1,20 +1,28 @@
-use std::{num::NonZeroU32, time::Duration};
+use std::{
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
 use winit::window::Window;
 
+/// How long before a frame the loop stops sleeping and spins instead, as sleeps overshoot by about a millisecond.
+pub const FRAME_PACING_SPIN: Duration = Duration::from_millis(2);
+
 pub struct TargetFrameInterval {
     target_frame_millihertz: Option<NonZeroU32>,
+    frame_millihertz: u32,
     interval: Duration,
 }
 
 impl TargetFrameInterval {
     pub fn new(target_frame_millihertz: Option<NonZeroU32>, window: &Window) -> Self {
+        let frame_millihertz = target_frame_millihertz
+            .map(|n| n.get())
+            .unwrap_or_else(|| get_window_refresh_rate_millihertz(window));
+
         Self {
             target_frame_millihertz,
-            interval: compute_target_frame_interval(
-                target_frame_millihertz
-                    .map(|n| n.get())
-                    .unwrap_or_else(|| get_window_refresh_rate_millihertz(window)),
-            ),
+            frame_millihertz,
+            interval: compute_target_frame_interval(frame_millihertz),
         }
     }
 
@@ -22,19 +30,54 @@ impl TargetFrameInterval {
         self.target_frame_millihertz
     }
 
+    /// The frame rate the interval is computed from: the target, or the refresh rate of the monitor.
+    pub fn frame_millihertz(&self) -> u32 {
+        self.frame_millihertz
+    }
+
     pub fn interval(&self) -> Duration {
         self.interval
     }
 
-    pub fn update_window(&mut self, window: &Window) {
+    /// Follows the refresh rate of the monitor the window is on, e.g. after it has been moved to another one.
+    /// Returns `true` if the interval has changed.
+    pub fn update_window(&mut self, window: &Window) -> bool {
         if self.target_frame_millihertz.is_some() {
-            return;
+            return false;
+        }
+
+        let frame_millihertz = get_window_refresh_rate_millihertz(window);
+
+        if frame_millihertz == self.frame_millihertz {
+            return false;
         }
 
-        self.interval = compute_target_frame_interval(get_window_refresh_rate_millihertz(window));
+        self.frame_millihertz = frame_millihertz;
+        self.interval = compute_target_frame_interval(frame_millihertz);
+        true
     }
 }
 
+/// The time the frame after the one due at `last_frame_time` is due. Frames are scheduled from the previous
+/// deadline rather than from when they started, so that the rate does not drift; a loop that has fallen more than
+/// a frame behind starts over from `now` instead of catching up.
+pub fn advance_frame_time(last_frame_time: Instant, interval: Duration, now: Instant) -> Instant {
+    let frame_time = last_frame_time + interval;
+
+    if interval <= now.saturating_duration_since(frame_time) {
+        now
+    } else {
+        frame_time
+    }
+}
+
+/// When a loop waiting for the frame due at `next_frame_time` should wake up to spin the rest of the wait,
+/// or `None` if it is too close to sleep.
+pub fn frame_pacing_wake_time(now: Instant, next_frame_time: Instant) -> Option<Instant> {
+    (FRAME_PACING_SPIN < next_frame_time.saturating_duration_since(now))
+        .then(|| next_frame_time - FRAME_PACING_SPIN)
+}
+
 fn get_window_refresh_rate_millihertz(window: &Window) -> u32 {
     window
         .current_monitor()
@@ -43,5 +86,54 @@ fn get_window_refresh_rate_millihertz(window: &Window) -> u32 {
 }
 
 fn compute_target_frame_interval(target_frame_millihertz: impl Into<u64>) -> Duration {
-    Duration::from_millis(1000_000 / target_frame_millihertz.into())
+    Duration::from_nanos(1_000_000_000_000 / target_frame_millihertz.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_fractional_rates_keep_precision() {
+        assert_eq!(
+            compute_target_frame_interval(59_940u32),
+            Duration::from_nanos(16_683_350)
+        );
+        assert_eq!(
+            compute_target_frame_interval(144_000u32),
+            Duration::from_nanos(6_944_444)
+        );
+    }
+
+    #[test]
+    fn check_frame_time_does_not_drift() {
+        let interval = compute_target_frame_interval(59_940u32);
+        let start = Instant::now();
+        let mut frame_time = start;
+
+        // Each frame starts a little late, which must not push the following ones back.
+        for frame in 1..=600u32 {
+            let now = start + interval * frame + Duration::from_micros(300);
+            frame_time = advance_frame_time(frame_time, interval, now);
+        }
+
+        assert_eq!(frame_time, start + interval * 600);
+
+        let stalled = frame_time + interval * 5;
+        assert_eq!(advance_frame_time(frame_time, interval, stalled), stalled);
+    }
+
+    #[test]
+    fn check_pacing_sleeps_only_far_from_the_frame() {
+        let now = Instant::now();
+
+        assert_eq!(
+            frame_pacing_wake_time(now, now + Duration::from_millis(10)),
+            Some(now + Duration::from_millis(8))
+        );
+        assert_eq!(
+            frame_pacing_wake_time(now, now + Duration::from_millis(1)),
+            None
+        );
+    }
 }
@@ -0,0 +1,276 @@
+//! Change detection for components stored in a [`FlaggedStorage`].
+//!
+//! A component opts in by using a flagged storage, e.g. `type Storage = FlaggedStorage<Self, VecStorage<Self>>`,
+//! and being registered through [`register_tracked`]. Every [`ChangeReader`] has its own cursor,
+//! so any number of consumers can observe the same changes.
+//!
+//! Any mutable access raises a [`ChangeKind::Modified`] event, whether or not the value actually changes.
+//! Code mutating tracked components must therefore take them mutably only when it writes, e.g. through
+//! [`write_if_changed`], and must not hide state behind interior mutability.
+//!
+//! # Replicating changes over the network
+//!
+//! Keep one reader per replication layer, and drain it once per tick after the systems ran.
+//! Coalesce the events per object, since an object may be modified several times in one tick:
+//!
+//! ```ignore
+//! let mut reader = ctx.changes::<Transform>();
+//!
+//! // Every tick:
+//! let mut dirty = HashMap::new();
+//! for (object, kind) in reader.read() {
+//!     // A removal wins over the earlier events, an insertion over the later modifications.
+//!     let entry = dirty.entry(object).or_insert(kind);
+//!     if kind == ChangeKind::Removed || *entry != ChangeKind::Inserted {
+//!         *entry = kind;
+//!     }
+//! }
+//! for (object, kind) in dirty {
+//!     match kind {
+//!         ChangeKind::Removed => send_despawn(&object),
+//!         _ => send_snapshot(&object, &object.component::<TransformComponent>()),
+//!     }
+//! }
+//! ```
+//!
+//! Readers that are never drained keep the events alive, so drop the readers that are no longer used.
+
+use crate::{
+    object::{Object, ObjectHandle},
+    ContextHandle,
+};
+use specs::{
+    prelude::*,
+    shrev::ReaderId,
+    storage::{ComponentEvent, Tracked},
+};
+use std::{collections::HashMap, marker::PhantomData};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    Inserted,
+    Modified,
+    Removed,
+}
+
+/// Registers a component whose changes can be observed through [`ChangeReader`].
+pub fn register_tracked<T>(world: &mut World)
+where
+    T: Component,
+    T::Storage: Default + Tracked,
+{
+    world.register::<T>();
+}
+
+/// Applies `update` to a copy of the component of the entity, and writes the copy back only if it differs, so that
+/// an update leaving the value as it is raises no [`ChangeKind::Modified`] event. Returns `true` if it was written,
+/// `false` if it did not change or the entity has no such component.
+pub fn write_if_changed<T>(
+    storage: &mut WriteStorage<T>,
+    entity: Entity,
+    update: impl FnOnce(&mut T),
+) -> bool
+where
+    T: Component + Clone + PartialEq,
+{
+    let mut value = match storage.get(entity) {
+        Some(value) => value.clone(),
+        None => return false,
+    };
+    update(&mut value);
+
+    if storage.get(entity) == Some(&value) {
+        return false;
+    }
+
+    *storage.get_mut(entity).unwrap() = value;
+    true
+}
+
+/// Reads the changes of `T` made since the last read, by entity. Holds its own cursor.
+pub struct ComponentChanges<T> {
+    reader: ReaderId<ComponentEvent>,
+    _component: PhantomData<fn() -> T>,
+}
+
+impl<T> ComponentChanges<T>
+where
+    T: Component,
+    T::Storage: Tracked,
+{
+    /// Starts observing the changes made from now on.
+    pub fn new(world: &World) -> Self {
+        Self {
+            reader: world.write_storage::<T>().register_reader(),
+            _component: PhantomData,
+        }
+    }
+
+    pub fn read(&mut self, world: &World) -> Vec<(u32, ChangeKind)> {
+        let storage = world.read_storage::<T>();
+        Vec::from_iter(
+            storage
+                .channel()
+                .read(&mut self.reader)
+                .map(|event| match *event {
+                    ComponentEvent::Inserted(index) => (index, ChangeKind::Inserted),
+                    ComponentEvent::Modified(index) => (index, ChangeKind::Modified),
+                    ComponentEvent::Removed(index) => (index, ChangeKind::Removed),
+                }),
+        )
+    }
+}
+
+/// Yields the changes of `T` per object. Created by [`Context::changes`](crate::Context::changes).
+pub struct ChangeReader<T> {
+    ctx: ContextHandle,
+    changes: ComponentChanges<T>,
+    /// Handles of the objects having `T`, so that removed ones can still be reported.
+    objects: HashMap<u32, ObjectHandle>,
+}
+
+impl<T> ChangeReader<T>
+where
+    T: Component,
+    T::Storage: Tracked,
+{
+    pub fn new(ctx: ContextHandle) -> Self {
+        let (changes, objects) = {
+            let world = ctx.world();
+            let changes = ComponentChanges::new(&world);
+            let objects = HashMap::from_iter(
+                (&world.read_storage::<Object>(), &world.read_storage::<T>())
+                    .join()
+                    .map(|(object, _)| {
+                        (
+                            object.entity().id(),
+                            ObjectHandle::new(ctx.clone(), object.entity(), object.object_id()),
+                        )
+                    }),
+            );
+            (changes, objects)
+        };
+
+        Self {
+            ctx,
+            changes,
+            objects,
+        }
+    }
+
+    /// Returns the changes made since the last read, in order.
+    /// Changes of entities that are not objects are skipped.
+    pub fn read(&mut self) -> Vec<(ObjectHandle, ChangeKind)> {
+        let world = self.ctx.world();
+        let objects = world.read_storage::<Object>();
+        let mut changes = Vec::new();
+
+        for (index, kind) in self.changes.read(&world) {
+            let handle = match kind {
+                ChangeKind::Inserted | ChangeKind::Modified => {
+                    let entity = world.entities().entity(index);
+                    match objects.get(entity) {
+                        Some(object) => self
+                            .objects
+                            .entry(index)
+                            .or_insert_with(|| {
+                                ObjectHandle::new(
+                                    self.ctx.clone(),
+                                    object.entity(),
+                                    object.object_id(),
+                                )
+                            })
+                            .clone(),
+                        None => continue,
+                    }
+                }
+                ChangeKind::Removed => match self.objects.remove(&index) {
+                    Some(handle) => handle,
+                    None => continue,
+                },
+            };
+
+            changes.push((handle, kind));
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::Transform;
+
+    #[test]
+    fn check_one_modified_event_per_mutation() {
+        let mut world = World::new();
+        register_tracked::<Transform>(&mut world);
+
+        let entity = world.create_entity().with(Transform::new()).build();
+        let mut first = ComponentChanges::<Transform>::new(&world);
+        let mut second = ComponentChanges::<Transform>::new(&world);
+
+        // Read-only access raises nothing.
+        {
+            let transforms = world.read_storage::<Transform>();
+            let _ = transforms.get(entity).unwrap().position;
+            let _ = (&transforms).join().count();
+        }
+        assert_eq!(first.read(&world), []);
+
+        {
+            let mut transforms = world.write_storage::<Transform>();
+            transforms.get_mut(entity).unwrap().position.x = 1.0;
+            transforms.get_mut(entity).unwrap().position.y = 2.0;
+        }
+        assert_eq!(
+            first.read(&world),
+            [
+                (entity.id(), ChangeKind::Modified),
+                (entity.id(), ChangeKind::Modified)
+            ]
+        );
+        // Already read events are not returned again.
+        assert_eq!(first.read(&world), []);
+
+        world.delete_entity(entity).unwrap();
+        assert_eq!(first.read(&world), [(entity.id(), ChangeKind::Removed)]);
+
+        // Each reader has its own cursor.
+        assert_eq!(
+            second.read(&world),
+            [
+                (entity.id(), ChangeKind::Modified),
+                (entity.id(), ChangeKind::Modified),
+                (entity.id(), ChangeKind::Removed)
+            ]
+        );
+    }
+
+    #[test]
+    fn check_unchanged_write_raises_nothing() {
+        let mut world = World::new();
+        register_tracked::<Transform>(&mut world);
+
+        let entity = world.create_entity().with(Transform::new()).build();
+        let mut changes = ComponentChanges::<Transform>::new(&world);
+
+        {
+            let mut transforms = world.write_storage::<Transform>();
+            assert!(!write_if_changed(&mut transforms, entity, |transform| {
+                transform.position.x = 0.0;
+            }));
+        }
+        assert_eq!(changes.read(&world), []);
+
+        {
+            let mut transforms = world.write_storage::<Transform>();
+            assert!(write_if_changed(&mut transforms, entity, |transform| {
+                transform.position.x = 1.0;
+            }));
+            assert_eq!(transforms.get(entity).unwrap().position.x, 1.0);
+        }
+        assert_eq!(changes.read(&world), [(entity.id(), ChangeKind::Modified)]);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::math::Vec3;
+use asset::AssetKey;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkId(pub u32);
+
+/// Axis-aligned bounds of a chunk in world space. Unbounded axes use infinite extents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl ChunkBounds {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// A cell of a grid on the XZ plane, unbounded along Y.
+    pub fn grid(x: i32, z: i32, cell_size: f32) -> Self {
+        Self {
+            min: Vec3::new(
+                x as f32 * cell_size,
+                f32::NEG_INFINITY,
+                z as f32 * cell_size,
+            ),
+            max: Vec3::new(
+                (x + 1) as f32 * cell_size,
+                f32::INFINITY,
+                (z + 1) as f32 * cell_size,
+            ),
+        }
+    }
+
+    /// Distance from the point to the bounds, zero inside them.
+    pub fn distance(&self, point: Vec3) -> f32 {
+        let axis = |min: f32, max: f32, p: f32| (min - p).max(p - max).max(0.0);
+        let dx = axis(self.min.x, self.max.x, point.x);
+        let dy = axis(self.min.y, self.max.y, point.y);
+        let dz = axis(self.min.z, self.max.z, point.z);
+        (dx * dx + dy * dy + dz * dz).sqrt()
+    }
+}
+
+/// A section of the world, streamed in as a scene when an anchor comes close.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkDescriptor {
+    pub bounds: ChunkBounds,
+    /// The prefab holding the objects of the chunk.
+    pub scene: AssetKey,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChunkState {
+    Unloaded,
+    /// Loading the scene, then spawning its objects. Holds the progress in range [0, 1].
+    Loading(f32),
+    Active,
+    /// Out of range and about to be unloaded once the grace period ends, unless an anchor comes back.
+    Unloading,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChunkEventKind {
+    LoadStarted,
+    /// An anchor moved away before the chunk finished loading.
+    LoadCancelled,
+    LoadFailed(String),
+    Activated,
+    UnloadPending,
+    UnloadCancelled,
+    Unloaded,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkEvent {
+    pub chunk: ChunkId,
+    pub kind: ChunkEventKind,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_grid_bounds_distance() {
+        let bounds = ChunkBounds::grid(1, -1, 10.0);
+        assert_eq!(bounds.distance(Vec3::new(15.0, 100.0, -5.0)), 0.0);
+        assert_eq!(bounds.distance(Vec3::new(25.0, -40.0, -5.0)), 5.0);
+        assert_eq!(bounds.distance(Vec3::new(23.0, 0.0, 4.0)), 5.0);
+    }
+}
@@ -0,0 +1,201 @@
+use super::ChunkId;
+use crate::{
+    object::ObjectHandle,
+    prefab::{PrefabNode, PrefabObject, PrefabTransform},
+    task::{Task, TaskContext, TaskHandle, TaskStatus},
+    transform::Transform,
+    use_context,
+};
+use asset::AssetKey;
+use specs::Builder;
+use std::{collections::HashMap, time::Duration, time::Instant};
+
+/// Status of a chunk load started by [`ChunkBackend::begin_load`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChunkLoadStatus {
+    /// Holds the progress in range [0, 1].
+    Loading(f32),
+    /// The scene is loaded and its objects can be spawned.
+    Ready {
+        object_count: usize,
+    },
+    Failed(String),
+}
+
+/// Loads, spawns and unloads the scenes of chunks for the [`WorldStreamingManager`](super::WorldStreamingManager).
+pub trait ChunkBackend {
+    fn begin_load(&mut self, chunk: ChunkId, scene: &AssetKey);
+
+    fn poll_load(&mut self, chunk: ChunkId) -> ChunkLoadStatus;
+
+    /// Spawns at most `budget` objects of a ready chunk, returning how many were spawned.
+    fn spawn(&mut self, chunk: ChunkId, budget: usize) -> usize;
+
+    /// Cancels the load in flight, if any, and removes everything spawned for the chunk.
+    fn unload(&mut self, chunk: ChunkId);
+}
+
+/// An object of a scene, with its parent given as an index into the flattened scene.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatSceneObject {
+    pub name: Option<String>,
+    pub active: bool,
+    pub transform: PrefabTransform,
+    pub parent: Option<usize>,
+}
+
+/// Flattens a resolved prefab in pre-order, so that parents always come before their children.
+pub fn flatten_scene(root: &PrefabObject) -> Vec<FlatSceneObject> {
+    fn visit(object: &PrefabObject, parent: Option<usize>, objects: &mut Vec<FlatSceneObject>) {
+        let index = objects.len();
+        objects.push(FlatSceneObject {
+            name: object.name.clone(),
+            active: object.active,
+            transform: object.transform,
+            parent,
+        });
+
+        for child in &object.children {
+            if let PrefabNode::Object(child) = child {
+                visit(child, Some(index), objects);
+            }
+        }
+    }
+
+    let mut objects = Vec::new();
+    visit(root, None, &mut objects);
+    objects
+}
+
+/// Resolves the prefab of a chunk on the task scheduler.
+struct ResolveSceneTask {
+    scene: AssetKey,
+}
+
+impl Task for ResolveSceneTask {
+    type Output = Vec<FlatSceneObject>;
+
+    fn step(&mut self, _ctx: &mut TaskContext, _deadline: Instant) -> TaskStatus<Self::Output> {
+        match use_context().prefab_mgr().registry().resolve(&self.scene) {
+            Ok(root) => TaskStatus::Done(flatten_scene(&root)),
+            Err(err) => TaskStatus::Failed(err.to_string()),
+        }
+    }
+}
+
+struct SceneLoad {
+    task: TaskHandle<Vec<FlatSceneObject>>,
+    is_resolved: bool,
+    objects: Vec<FlatSceneObject>,
+    spawned: Vec<ObjectHandle>,
+}
+
+/// Streams chunks as prefabs of the [`PrefabRegistry`](crate::prefab::PrefabRegistry).
+/// Each chunk becomes a hierarchy of objects rooted at the root of its prefab.
+#[derive(Default)]
+pub struct SceneChunkBackend {
+    loads: HashMap<ChunkId, SceneLoad>,
+}
+
+impl SceneChunkBackend {
+    const RESOLVE_BUDGET_HINT: Duration = Duration::from_millis(2);
+
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The root object of a chunk, once spawned.
+    pub fn root(&self, chunk: ChunkId) -> Option<&ObjectHandle> {
+        self.loads.get(&chunk).and_then(|load| load.spawned.first())
+    }
+}
+
+impl ChunkBackend for SceneChunkBackend {
+    fn begin_load(&mut self, chunk: ChunkId, scene: &AssetKey) {
+        let task = use_context().task_scheduler_mut().schedule_task(
+            Self::RESOLVE_BUDGET_HINT,
+            ResolveSceneTask {
+                scene: scene.clone(),
+            },
+        );
+        self.loads.insert(
+            chunk,
+            SceneLoad {
+                task,
+                is_resolved: false,
+                objects: Vec::new(),
+                spawned: Vec::new(),
+            },
+        );
+    }
+
+    fn poll_load(&mut self, chunk: ChunkId) -> ChunkLoadStatus {
+        let load = match self.loads.get_mut(&chunk) {
+            Some(load) => load,
+            None => return ChunkLoadStatus::Failed(format!("chunk {} is not loading", chunk.0)),
+        };
+
+        if !load.is_resolved {
+            match load.task.poll() {
+                Some(Ok(objects)) => {
+                    load.objects = objects;
+                    load.is_resolved = true;
+                }
+                Some(Err(err)) => return ChunkLoadStatus::Failed(err),
+                None => return ChunkLoadStatus::Loading(load.task.progress()),
+            }
+        }
+
+        ChunkLoadStatus::Ready {
+            object_count: load.objects.len(),
+        }
+    }
+
+    fn spawn(&mut self, chunk: ChunkId, budget: usize) -> usize {
+        let load = match self.loads.get_mut(&chunk) {
+            Some(load) => load,
+            None => return 0,
+        };
+        let ctx = use_context();
+        let mut count = 0;
+
+        while count < budget && load.spawned.len() < load.objects.len() {
+            let object = &load.objects[load.spawned.len()];
+            let parent = object.parent.map(|index| load.spawned[index].object_id);
+
+            let mut world = ctx.world_mut();
+            let mut object_mgr = ctx.object_mgr_mut();
+            let (handle, builder) = object_mgr.create_object_builder(
+                &mut world,
+                object.name.clone(),
+                Some(Transform::from(object.transform)),
+            );
+            builder.build();
+
+            let hierarchy = object_mgr.object_hierarchy_mut();
+            // Parents are spawned before their children, so the new object can't form a cycle.
+            hierarchy.set_parent(handle.object_id, parent).unwrap();
+            hierarchy.set_active(handle.object_id, object.active);
+
+            load.spawned.push(handle);
+            count += 1;
+        }
+
+        count
+    }
+
+    fn unload(&mut self, chunk: ChunkId) {
+        let load = match self.loads.remove(&chunk) {
+            Some(load) => load,
+            None => return,
+        };
+
+        load.task.cancel();
+
+        // The children are removed along with the root.
+        // It may have been removed by hand already.
+        if let Some(root) = load.spawned.first() {
+            root.remove().ok();
+        }
+    }
+}
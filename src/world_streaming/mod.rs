@@ -0,0 +1,466 @@
+mod chunk;
+mod chunk_backend;
+
+pub use chunk::*;
+pub use chunk_backend::*;
+
+use crate::{math::Vec3, object::ObjectHandle, use_context};
+use specs::WorldExt;
+use std::time::Duration;
+
+struct Chunk {
+    descriptor: ChunkDescriptor,
+    state: ChunkState,
+    /// Objects spawned and the total, once the scene is loaded.
+    spawn: Option<(usize, usize)>,
+    /// Time spent out of range while unloading.
+    outside_for: Duration,
+    /// Set when the load fails, so that it is not retried until every anchor has left.
+    has_failed: bool,
+}
+
+/// Loads the chunks of the world near the streaming anchors and unloads the ones left behind.
+///
+/// A chunk starts loading once an anchor is within the load radius, and is unloaded once every anchor
+/// has stayed beyond the unload radius for the grace period. The unload radius is larger than the load
+/// radius, so that chunks don't thrash while an anchor moves along the boundary.
+/// Loads still in flight are cancelled as soon as every anchor is beyond the unload radius.
+pub struct WorldStreamingManager {
+    backend: Box<dyn ChunkBackend>,
+    chunks: Vec<Chunk>,
+    anchors: Vec<ObjectHandle>,
+    load_radius: f32,
+    unload_radius: f32,
+    unload_grace: Duration,
+    objects_per_frame: usize,
+    events: Vec<ChunkEvent>,
+}
+
+impl WorldStreamingManager {
+    pub fn new() -> Self {
+        Self::with_backend(Box::new(SceneChunkBackend::new()))
+    }
+
+    pub fn with_backend(backend: Box<dyn ChunkBackend>) -> Self {
+        Self {
+            backend,
+            chunks: Vec::new(),
+            anchors: Vec::new(),
+            load_radius: 100.0,
+            unload_radius: 120.0,
+            unload_grace: Duration::from_secs(2),
+            objects_per_frame: 64,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn backend(&self) -> &dyn ChunkBackend {
+        self.backend.as_ref()
+    }
+
+    pub fn load_radius(&self) -> f32 {
+        self.load_radius
+    }
+
+    pub fn unload_radius(&self) -> f32 {
+        self.unload_radius
+    }
+
+    /// Sets the radii. The unload radius is raised to the load radius if it is smaller.
+    pub fn set_radii(&mut self, load_radius: f32, unload_radius: f32) {
+        self.load_radius = load_radius;
+        self.unload_radius = unload_radius.max(load_radius);
+    }
+
+    pub fn unload_grace(&self) -> Duration {
+        self.unload_grace
+    }
+
+    pub fn set_unload_grace(&mut self, unload_grace: Duration) {
+        self.unload_grace = unload_grace;
+    }
+
+    pub fn objects_per_frame(&self) -> usize {
+        self.objects_per_frame
+    }
+
+    /// Limits the objects spawned per frame across all chunks; the nearest chunks are spawned first.
+    pub fn set_objects_per_frame(&mut self, objects_per_frame: usize) {
+        self.objects_per_frame = objects_per_frame.max(1);
+    }
+
+    pub fn add_chunk(&mut self, descriptor: ChunkDescriptor) -> ChunkId {
+        self.chunks.push(Chunk {
+            descriptor,
+            state: ChunkState::Unloaded,
+            spawn: None,
+            outside_for: Duration::ZERO,
+            has_failed: false,
+        });
+        ChunkId(self.chunks.len() as u32 - 1)
+    }
+
+    pub fn chunk(&self, chunk: ChunkId) -> Option<&ChunkDescriptor> {
+        self.chunks
+            .get(chunk.0 as usize)
+            .map(|chunk| &chunk.descriptor)
+    }
+
+    pub fn chunk_state(&self, chunk: ChunkId) -> Option<ChunkState> {
+        self.chunks.get(chunk.0 as usize).map(|chunk| chunk.state)
+    }
+
+    pub fn chunk_states(&self) -> impl Iterator<Item = (ChunkId, ChunkState)> + '_ {
+        self.chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| (ChunkId(index as u32), chunk.state))
+    }
+
+    pub fn anchors(&self) -> &[ObjectHandle] {
+        &self.anchors
+    }
+
+    /// Adds an object around which chunks are loaded, usually the camera or the player.
+    pub fn add_anchor(&mut self, anchor: ObjectHandle) {
+        if !self.anchors.contains(&anchor) {
+            self.anchors.push(anchor);
+        }
+    }
+
+    pub fn remove_anchor(&mut self, anchor: &ObjectHandle) {
+        self.anchors.retain(|other| other != anchor);
+    }
+
+    /// Takes the events raised since the last call.
+    pub fn drain_events(&mut self) -> Vec<ChunkEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Updates the chunks around the world positions of the anchors. Removed anchors are forgotten.
+    pub fn update(&mut self, unscaled_delta_time: Duration) {
+        let positions = {
+            let ctx = use_context();
+            let world = ctx.world();
+            self.anchors.retain(|anchor| world.is_alive(anchor.entity));
+
+            let object_mgr = ctx.object_mgr();
+            let hierarchy = object_mgr.object_hierarchy();
+            Vec::from_iter(
+                self.anchors
+                    .iter()
+                    .map(|anchor| Vec3::from(hierarchy.matrix(anchor.object_id).row(3))),
+            )
+        };
+
+        self.update_with_anchors(&positions, unscaled_delta_time);
+    }
+
+    /// Updates the chunks around the given anchor positions.
+    pub fn update_with_anchors(&mut self, anchors: &[Vec3], unscaled_delta_time: Duration) {
+        let mut spawning = Vec::new();
+
+        for (index, chunk) in self.chunks.iter_mut().enumerate() {
+            let id = ChunkId(index as u32);
+            let distance = anchors
+                .iter()
+                .map(|anchor| chunk.descriptor.bounds.distance(*anchor))
+                .fold(f32::INFINITY, f32::min);
+            let is_out_of_range = self.unload_radius < distance;
+
+            if is_out_of_range {
+                chunk.has_failed = false;
+            }
+
+            match chunk.state {
+                ChunkState::Unloaded => {
+                    if distance <= self.load_radius && !chunk.has_failed {
+                        self.backend.begin_load(id, &chunk.descriptor.scene);
+                        chunk.state = ChunkState::Loading(0.0);
+                        push_event(&mut self.events, id, ChunkEventKind::LoadStarted);
+                    }
+                }
+                ChunkState::Loading(_) if is_out_of_range => {
+                    self.backend.unload(id);
+                    chunk.state = ChunkState::Unloaded;
+                    chunk.spawn = None;
+                    push_event(&mut self.events, id, ChunkEventKind::LoadCancelled);
+                }
+                ChunkState::Loading(_) => {
+                    if chunk.spawn.is_none() {
+                        match self.backend.poll_load(id) {
+                            ChunkLoadStatus::Loading(progress) => {
+                                chunk.state = ChunkState::Loading(progress.clamp(0.0, 1.0) * 0.5);
+                            }
+                            ChunkLoadStatus::Ready { object_count } => {
+                                chunk.spawn = Some((0, object_count));
+                                chunk.state = ChunkState::Loading(0.5);
+                            }
+                            ChunkLoadStatus::Failed(err) => {
+                                self.backend.unload(id);
+                                chunk.state = ChunkState::Unloaded;
+                                chunk.has_failed = true;
+                                push_event(&mut self.events, id, ChunkEventKind::LoadFailed(err));
+                            }
+                        }
+                    }
+
+                    if chunk.spawn.is_some() {
+                        spawning.push((distance, index));
+                    }
+                }
+                ChunkState::Active => {
+                    if is_out_of_range {
+                        chunk.state = ChunkState::Unloading;
+                        chunk.outside_for = Duration::ZERO;
+                        push_event(&mut self.events, id, ChunkEventKind::UnloadPending);
+                    }
+                }
+                ChunkState::Unloading => {
+                    if !is_out_of_range {
+                        chunk.state = ChunkState::Active;
+                        push_event(&mut self.events, id, ChunkEventKind::UnloadCancelled);
+                        continue;
+                    }
+
+                    chunk.outside_for += unscaled_delta_time;
+
+                    if self.unload_grace <= chunk.outside_for {
+                        self.backend.unload(id);
+                        chunk.state = ChunkState::Unloaded;
+                        chunk.spawn = None;
+                        push_event(&mut self.events, id, ChunkEventKind::Unloaded);
+                    }
+                }
+            }
+        }
+
+        // The nearest chunks get the spawn budget first.
+        spawning.sort_by(|(lhs, _), (rhs, _)| lhs.total_cmp(rhs));
+        let mut budget = self.objects_per_frame;
+
+        for (_, index) in spawning {
+            let id = ChunkId(index as u32);
+            let chunk = &mut self.chunks[index];
+            let (spawned, total) = chunk.spawn.as_mut().unwrap();
+
+            if budget != 0 && *spawned < *total {
+                let count = self.backend.spawn(id, budget.min(*total - *spawned));
+                *spawned += count;
+                budget -= count.min(budget);
+            }
+
+            if *spawned < *total {
+                chunk.state = ChunkState::Loading(0.5 + 0.5 * *spawned as f32 / *total as f32);
+            } else {
+                chunk.state = ChunkState::Active;
+                push_event(&mut self.events, id, ChunkEventKind::Activated);
+            }
+        }
+    }
+}
+
+fn push_event(events: &mut Vec<ChunkEvent>, chunk: ChunkId, kind: ChunkEventKind) {
+    events.push(ChunkEvent { chunk, kind });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asset::AssetKey;
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    const OBJECTS_PER_CHUNK: usize = 10;
+    const LOAD_FRAMES: u32 = 3;
+
+    /// Counts the objects alive and the scenes held in memory.
+    #[derive(Default)]
+    struct FakeMemory {
+        objects: usize,
+        scenes: usize,
+        spawned_this_frame: usize,
+    }
+
+    /// Loads every scene in a few frames and spawns the same number of objects for each.
+    struct FakeBackend {
+        memory: Rc<RefCell<FakeMemory>>,
+        loads: HashMap<ChunkId, (u32, usize)>,
+    }
+
+    impl ChunkBackend for FakeBackend {
+        fn begin_load(&mut self, chunk: ChunkId, _scene: &AssetKey) {
+            assert!(self.loads.insert(chunk, (LOAD_FRAMES, 0)).is_none());
+            self.memory.borrow_mut().scenes += 1;
+        }
+
+        fn poll_load(&mut self, chunk: ChunkId) -> ChunkLoadStatus {
+            let (frames_left, _) = self.loads.get_mut(&chunk).unwrap();
+            *frames_left -= 1;
+
+            if *frames_left == 0 {
+                ChunkLoadStatus::Ready {
+                    object_count: OBJECTS_PER_CHUNK,
+                }
+            } else {
+                ChunkLoadStatus::Loading(1.0 - *frames_left as f32 / LOAD_FRAMES as f32)
+            }
+        }
+
+        fn spawn(&mut self, chunk: ChunkId, budget: usize) -> usize {
+            let (_, spawned) = self.loads.get_mut(&chunk).unwrap();
+            let count = budget.min(OBJECTS_PER_CHUNK - *spawned);
+            *spawned += count;
+
+            let mut memory = self.memory.borrow_mut();
+            memory.objects += count;
+            memory.spawned_this_frame += count;
+            count
+        }
+
+        fn unload(&mut self, chunk: ChunkId) {
+            let (_, spawned) = self.loads.remove(&chunk).unwrap();
+            let mut memory = self.memory.borrow_mut();
+            memory.objects -= spawned;
+            memory.scenes -= 1;
+        }
+    }
+
+    fn grid_manager(memory: &Rc<RefCell<FakeMemory>>) -> WorldStreamingManager {
+        let mut manager = WorldStreamingManager::with_backend(Box::new(FakeBackend {
+            memory: memory.clone(),
+            loads: HashMap::new(),
+        }));
+        manager.set_radii(15.0, 25.0);
+        manager.set_unload_grace(Duration::from_millis(500));
+        manager.set_objects_per_frame(8);
+
+        for x in 0..10 {
+            for z in 0..10 {
+                manager.add_chunk(ChunkDescriptor {
+                    bounds: ChunkBounds::grid(x, z, 10.0),
+                    scene: AssetKey::Path(format!("chunks/{}_{}", x, z)),
+                });
+            }
+        }
+
+        manager
+    }
+
+    /// Moves the anchor along the path at the given speed in units per frame, returning the events.
+    fn traverse(
+        manager: &mut WorldStreamingManager,
+        memory: &Rc<RefCell<FakeMemory>>,
+        path: &[Vec3],
+        speed: f32,
+    ) -> Vec<ChunkEvent> {
+        let frame = Duration::from_millis(16);
+        let mut events = Vec::new();
+
+        for segment in path.windows(2) {
+            let (from, to) = (segment[0], segment[1]);
+            let length = Vec3::distance(from, to);
+            let steps = (length / speed).ceil().max(1.0) as u32;
+
+            for step in 0..=steps {
+                let t = step as f32 / steps as f32;
+                let anchor = from + (to - from) * t;
+
+                memory.borrow_mut().spawned_this_frame = 0;
+                manager.update_with_anchors(&[anchor], frame);
+                assert!(memory.borrow().spawned_this_frame <= manager.objects_per_frame());
+                events.extend(manager.drain_events());
+            }
+        }
+
+        events
+    }
+
+    fn count(events: &[ChunkEvent], kind: ChunkEventKind) -> usize {
+        events.iter().filter(|event| event.kind == kind).count()
+    }
+
+    #[test]
+    fn check_memory_returns_to_baseline_after_traversal() {
+        let memory = Rc::new(RefCell::new(FakeMemory::default()));
+        let mut manager = grid_manager(&memory);
+
+        // A winding path across the grid, then far away from it.
+        let path = [
+            Vec3::new(-30.0, 0.0, 5.0),
+            Vec3::new(95.0, 0.0, 5.0),
+            Vec3::new(95.0, 0.0, 50.0),
+            Vec3::new(5.0, 0.0, 50.0),
+            Vec3::new(5.0, 0.0, 95.0),
+            Vec3::new(95.0, 0.0, 95.0),
+            Vec3::new(300.0, 0.0, 300.0),
+        ];
+        let mut events = traverse(&mut manager, &memory, &path, 1.0);
+
+        // Wait past the grace period.
+        events.extend(traverse(
+            &mut manager,
+            &memory,
+            &[Vec3::new(300.0, 0.0, 300.0), Vec3::new(300.0, 0.0, 340.0)],
+            1.0,
+        ));
+
+        let activated = count(&events, ChunkEventKind::Activated);
+        assert!(30 <= activated);
+        assert_eq!(
+            count(&events, ChunkEventKind::LoadStarted),
+            activated + count(&events, ChunkEventKind::LoadCancelled)
+        );
+        assert_eq!(count(&events, ChunkEventKind::Unloaded), activated);
+
+        assert_eq!(memory.borrow().objects, 0);
+        assert_eq!(memory.borrow().scenes, 0);
+        assert!(manager
+            .chunk_states()
+            .all(|(_, state)| state == ChunkState::Unloaded));
+    }
+
+    #[test]
+    fn check_fast_anchor_cancels_passed_loads() {
+        let memory = Rc::new(RefCell::new(FakeMemory::default()));
+        let mut manager = grid_manager(&memory);
+
+        // Faster than the chunks can load and spawn.
+        let events = traverse(
+            &mut manager,
+            &memory,
+            &[Vec3::new(-30.0, 0.0, 45.0), Vec3::new(130.0, 0.0, 45.0)],
+            20.0,
+        );
+
+        assert!(0 < count(&events, ChunkEventKind::LoadCancelled));
+        assert_eq!(memory.borrow().objects, 0);
+        assert_eq!(memory.borrow().scenes, 0);
+    }
+
+    #[test]
+    fn check_boundary_does_not_thrash() {
+        let memory = Rc::new(RefCell::new(FakeMemory::default()));
+        let mut manager = grid_manager(&memory);
+        let chunk = ChunkId(0);
+
+        // Oscillating across the load radius of the first chunk, within its unload radius.
+        let mut path = Vec::new();
+        for _ in 0..20 {
+            path.push(Vec3::new(-14.0, 0.0, 5.0));
+            path.push(Vec3::new(-18.0, 0.0, 5.0));
+        }
+        let events = traverse(&mut manager, &memory, &path, 1.0);
+
+        let chunk_events = Vec::from_iter(
+            events
+                .iter()
+                .filter(|event| event.chunk == chunk)
+                .map(|event| event.kind.clone()),
+        );
+        assert_eq!(
+            chunk_events,
+            [ChunkEventKind::LoadStarted, ChunkEventKind::Activated]
+        );
+        assert_eq!(manager.chunk_state(chunk), Some(ChunkState::Active));
+    }
+}